@@ -40,3 +40,53 @@ where
 {
     start + (end - start) * t
 }
+
+/// Computes the eigenvalues and eigenvectors of the symmetric 3x3 matrix `m`
+/// with the Jacobi eigenvalue algorithm. Returns the eigenvalues, and a matrix
+/// with the matching unit eigenvectors as its columns. The eigenvalues are not
+/// sorted.
+pub fn symmetric_eigen3(m: glam::Mat3) -> (glam::Vec3, glam::Mat3) {
+    // Row-major copies. `m` is symmetric, so transposing it changes nothing.
+    let mut a = m.to_cols_array_2d();
+    let mut v = glam::Mat3::IDENTITY.to_cols_array_2d();
+    let scale = a.iter().flatten().map(|x| x * x).sum::<f32>();
+
+    // Each sweep zeroes the off-diagonal entries one at a time. The matrix
+    // converges to a diagonal one very quickly, so a few sweeps are enough.
+    for _ in 0..32 {
+        let off_diagonal = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off_diagonal <= scale * 1e-14 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in &mut v {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    let values = glam::Vec3::new(a[0][0], a[1][1], a[2][2]);
+    // `v` holds the eigenvectors as columns of a row-major matrix, so reading
+    // it as column-major gives them as rows.
+    let vectors = glam::Mat3::from_cols_array_2d(&v).transpose();
+    (values, vectors)
+}
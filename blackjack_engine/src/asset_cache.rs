@@ -0,0 +1,460 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    any::{Any, TypeId},
+    collections::hash_map::DefaultHasher,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use mlua::Lua;
+
+use crate::prelude::*;
+use crate::sync::{MaybeSync, RefCounted};
+
+/// The name of the Lua registry value with the cache used by a runtime.
+const ASSET_CACHE_KEY: &str = "__blackjack_asset_cache";
+
+/// Settings for an [`AssetCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetCacheConfig {
+    /// The memory the cached assets may use, in bytes. Past it, the assets
+    /// used least recently are dropped.
+    pub budget_bytes: usize,
+    /// When set, files are recognized by a hash of their contents instead of
+    /// their modification time. Files are read again on every access, but
+    /// saving a file without changes doesn't parse it again.
+    pub hash_contents: bool,
+}
+
+impl Default for AssetCacheConfig {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 512 * 1024 * 1024,
+            hash_contents: false,
+        }
+    }
+}
+
+/// Something read from a file that can be stored in an [`AssetCache`].
+pub trait Asset: Any + MaybeSync {
+    /// An estimate of the memory used by this asset, in bytes.
+    fn memory_size(&self) -> usize;
+}
+
+#[cfg(feature = "sync")]
+type AnyAsset = dyn Any + Send + Sync;
+
+#[cfg(not(feature = "sync"))]
+type AnyAsset = dyn Any;
+
+/// Identifies the version of a file an asset was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    size: u64,
+    modified: Option<SystemTime>,
+    hash: Option<u64>,
+}
+
+impl Fingerprint {
+    /// Returns `None` when the file can't be read, so it is never cached.
+    fn read(path: &Path, hash_contents: bool) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let hash = if hash_contents {
+            let mut hasher = DefaultHasher::new();
+            hasher.write(&std::fs::read(path).ok()?);
+            Some(hasher.finish())
+        } else {
+            None
+        };
+        Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            hash,
+        })
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        self.size == other.size
+            && match (self.hash, other.hash) {
+                (Some(a), Some(b)) => a == b,
+                // A missing modification time only disables the cache
+                _ => self.modified.is_some() && self.modified == other.modified,
+            }
+    }
+}
+
+struct CacheEntry {
+    fingerprint: Fingerprint,
+    asset: RefCounted<AnyAsset>,
+    size: usize,
+    /// The value of the cache's clock the last time this entry was used.
+    last_used: u64,
+}
+
+/// Counters describing the contents of an [`AssetCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetCacheStats {
+    pub entries: usize,
+    pub used_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct AssetCacheInner {
+    config: AssetCacheConfig,
+    /// Files can be cached as different kinds of assets, so entries are
+    /// keyed by the type of the asset as well.
+    entries: HashMap<(PathBuf, TypeId), CacheEntry>,
+    clock: u64,
+    stats: AssetCacheStats,
+}
+
+impl AssetCacheInner {
+    fn remove(&mut self, key: &(PathBuf, TypeId)) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.stats.used_bytes -= entry.size;
+        }
+    }
+
+    fn insert(&mut self, key: (PathBuf, TypeId), entry: CacheEntry) {
+        self.remove(&key);
+        // Assets larger than the whole budget would evict everything else
+        if entry.size > self.config.budget_bytes {
+            return;
+        }
+        self.stats.used_bytes += entry.size;
+        self.entries.insert(key, entry);
+        self.evict();
+    }
+
+    /// Drops the least recently used entries until the budget is met.
+    fn evict(&mut self) {
+        while self.stats.used_bytes > self.config.budget_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.remove(&key),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Keeps the assets read by import nodes, like meshes and images, so running
+/// a graph again doesn't parse the same files, and nodes importing the same
+/// file share a single copy. Assets are reused until their file changes:
+/// files are recognized by their size and modification time, or by a hash of
+/// their contents, see [`AssetCacheConfig::hash_contents`].
+///
+/// Cloning an `AssetCache` returns a handle to the same cache. With the
+/// `sync` feature, handles can be sent to other threads, so the runtime of a
+/// [`GraphWorker`](crate::graph_worker::GraphWorker) can share the cache of
+/// the main one.
+#[derive(Clone, Default)]
+pub struct AssetCache {
+    inner: RefCounted<Mutex<AssetCacheInner>>,
+}
+
+impl mlua::UserData for AssetCache {}
+
+/// Paths are compared in their canonical form when the file exists, so the
+/// same file imported through different paths is recognized.
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+impl AssetCache {
+    pub fn new(config: AssetCacheConfig) -> Self {
+        let cache = Self::default();
+        cache.set_config(config);
+        cache
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AssetCacheInner> {
+        // Loaders run without the lock, so a panic can't leave the cache in
+        // an inconsistent state.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn config(&self) -> AssetCacheConfig {
+        self.lock().config
+    }
+
+    /// Changes the settings of the cache, dropping assets if the new budget
+    /// is smaller.
+    pub fn set_config(&self, config: AssetCacheConfig) {
+        let mut inner = self.lock();
+        inner.config = config;
+        inner.evict();
+    }
+
+    pub fn stats(&self) -> AssetCacheStats {
+        let inner = self.lock();
+        AssetCacheStats {
+            entries: inner.entries.len(),
+            ..inner.stats
+        }
+    }
+
+    /// Returns the asset read from the file at `path`. When the cache doesn't
+    /// have it, or the file changed since it was read, it is read again by
+    /// calling `load` with the path.
+    pub fn get_or_load<T: Asset>(
+        &self,
+        path: &Path,
+        load: impl FnOnce(&Path) -> Result<T>,
+    ) -> Result<RefCounted<T>> {
+        let key = (normalize(path), TypeId::of::<T>());
+        let hash_contents = self.lock().config.hash_contents;
+        // Hashing reads the whole file, so the cache is not locked meanwhile
+        let fingerprint = Fingerprint::read(path, hash_contents);
+
+        if let Some(fingerprint) = &fingerprint {
+            let mut guard = self.lock();
+            let inner = &mut *guard;
+            inner.clock += 1;
+            if let Some(entry) = inner
+                .entries
+                .get_mut(&key)
+                .filter(|entry| entry.fingerprint.matches(fingerprint))
+            {
+                entry.last_used = inner.clock;
+                if let Ok(asset) = RefCounted::clone(&entry.asset).downcast::<T>() {
+                    inner.stats.hits += 1;
+                    return Ok(asset);
+                }
+            }
+        }
+
+        let asset = RefCounted::new(load(path)?);
+        let mut inner = self.lock();
+        inner.stats.misses += 1;
+        match fingerprint {
+            Some(fingerprint) => {
+                inner.clock += 1;
+                let entry = CacheEntry {
+                    fingerprint,
+                    asset: RefCounted::clone(&asset) as RefCounted<AnyAsset>,
+                    size: asset.memory_size(),
+                    last_used: inner.clock,
+                };
+                inner.insert(key, entry);
+            }
+            None => inner.remove(&key),
+        }
+        Ok(asset)
+    }
+
+    /// Drops every asset read from the file at `path`. Integrations call this
+    /// when a file watcher reports a change, so the memory is freed right
+    /// away instead of on the next access.
+    pub fn invalidate(&self, path: &Path) {
+        let path = normalize(path);
+        let mut inner = self.lock();
+        let keys = inner
+            .entries
+            .keys()
+            .filter(|(p, _)| *p == path)
+            .cloned()
+            .collect_vec();
+        for key in keys {
+            inner.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.entries.clear();
+        inner.stats.used_bytes = 0;
+    }
+
+    /// Returns the files with cached assets, to watch them for changes.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock()
+            .entries
+            .keys()
+            .map(|(path, _)| path.clone())
+            .unique()
+            .collect()
+    }
+}
+
+/// Makes the import nodes run in `lua` use `cache`.
+pub fn set_lua_asset_cache(lua: &Lua, cache: AssetCache) -> Result<()> {
+    lua.set_named_registry_value(ASSET_CACHE_KEY, cache)?;
+    Ok(())
+}
+
+/// Returns the cache used by the import nodes run in `lua`. A new one is
+/// created the first time when none was set.
+pub fn lua_asset_cache(lua: &Lua) -> Result<AssetCache> {
+    match lua.named_registry_value::<_, Option<mlua::AnyUserData>>(ASSET_CACHE_KEY)? {
+        Some(cache) => Ok(cache.borrow::<AssetCache>()?.clone()),
+        None => {
+            let cache = AssetCache::default();
+            set_lua_asset_cache(lua, cache.clone())?;
+            Ok(cache)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// An asset that claims to use as many bytes as its contents say.
+    struct Blob(usize);
+
+    impl MaybeSync for Blob {}
+
+    impl Asset for Blob {
+        fn memory_size(&self) -> usize {
+            self.0
+        }
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Rewrites the file at `path` with contents of the same length, until
+    /// its modification time changes.
+    fn touch(path: &Path, contents: &str) {
+        let modified = || std::fs::metadata(path).unwrap().modified().unwrap();
+        let before = modified();
+        loop {
+            std::fs::write(path, contents).unwrap();
+            if modified() != before {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    /// Counts the calls to the loader, which reads the length of the file.
+    fn loader(loads: &Cell<usize>) -> impl FnOnce(&Path) -> Result<Blob> + '_ {
+        move |path| {
+            loads.set(loads.get() + 1);
+            Ok(Blob(std::fs::read(path)?.len()))
+        }
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let path = temp_file("blackjack_asset_cache_hits.txt", "0123456789");
+        let cache = AssetCache::default();
+        let loads = Cell::new(0);
+
+        let a = cache.get_or_load(&path, loader(&loads)).unwrap();
+        let b = cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert!(RefCounted::ptr_eq(&a, &b));
+        assert_eq!(loads.get(), 1);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!((stats.entries, stats.used_bytes), (1, 10));
+
+        // Load errors are returned, and not cached
+        let missing = std::env::temp_dir().join("blackjack_asset_cache_missing.txt");
+        assert!(cache.get_or_load(&missing, loader(&loads)).is_err());
+        assert!(cache.get_or_load(&missing, loader(&loads)).is_err());
+        assert_eq!(loads.get(), 3);
+
+        cache.invalidate(&path);
+        assert_eq!(cache.stats().entries, 0);
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 4);
+    }
+
+    #[test]
+    fn test_mtime_invalidation() {
+        let path = temp_file("blackjack_asset_cache_mtime.txt", "aaaa");
+        let cache = AssetCache::default();
+        let loads = Cell::new(0);
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+
+        // Same size, newer modification time
+        touch(&path, "bbbb");
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 2);
+        // Different size
+        std::fs::write(&path, "cccccc").unwrap();
+        let blob = cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 3);
+        assert_eq!(blob.0, 6);
+        // The old versions are replaced
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let path = temp_file("blackjack_asset_cache_hash.txt", "aaaa");
+        let cache = AssetCache::new(AssetCacheConfig {
+            hash_contents: true,
+            ..Default::default()
+        });
+        let loads = Cell::new(0);
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+
+        // Saving the same contents again is still a hit
+        touch(&path, "aaaa");
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 1);
+        touch(&path, "bbbb");
+        cache.get_or_load(&path, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 2);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let a = temp_file("blackjack_asset_cache_lru_a.txt", &"a".repeat(40));
+        let b = temp_file("blackjack_asset_cache_lru_b.txt", &"b".repeat(40));
+        let c = temp_file("blackjack_asset_cache_lru_c.txt", &"c".repeat(40));
+        let huge = temp_file("blackjack_asset_cache_lru_huge.txt", &"h".repeat(200));
+        let cache = AssetCache::new(AssetCacheConfig {
+            budget_bytes: 100,
+            ..Default::default()
+        });
+        let loads = Cell::new(0);
+
+        cache.get_or_load(&a, loader(&loads)).unwrap();
+        cache.get_or_load(&b, loader(&loads)).unwrap();
+        // Using `a` again makes `b` the least recently used one
+        cache.get_or_load(&a, loader(&loads)).unwrap();
+        cache.get_or_load(&c, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 3);
+        assert_eq!(cache.stats().used_bytes, 80);
+
+        cache.get_or_load(&a, loader(&loads)).unwrap();
+        cache.get_or_load(&c, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 3);
+        cache.get_or_load(&b, loader(&loads)).unwrap();
+        assert_eq!(loads.get(), 4);
+
+        // Assets over the whole budget are returned, but never kept
+        cache.get_or_load(&huge, loader(&loads)).unwrap();
+        assert_eq!(cache.stats().entries, 2);
+        assert!(cache.paths().iter().all(|p| *p != normalize(&huge)));
+
+        // Shrinking the budget evicts right away
+        cache.set_config(AssetCacheConfig {
+            budget_bytes: 50,
+            ..Default::default()
+        });
+        assert_eq!(cache.stats().entries, 1);
+    }
+}
@@ -4,11 +4,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::graph::serialization::SerializedBjkGraph;
-use crate::graph::{BjkGraph, BjkNodeId};
-use crate::graph_interpreter::run_graph;
-use crate::lua_engine::{LuaRuntime, ProgramResult, RenderableThing};
+use crate::gizmos::{BlackjackGizmo, HandleGizmo};
+use crate::graph::expressions::ExpressionTime;
+use crate::graph::serialization::{RuntimeData, SerializedBjkGraph};
+use crate::graph::{BjkGraph, BjkNodeId, BlackjackValue, DataType, PickedSelection};
+use crate::graph_interpreter::{
+    run_graph, ExternalParameter, ExternalParameterValues, GizmoState, GraphInterpreter,
+    MemoryBudgetExceeded, MeshSummary, NodePanicked, RunOptions, StepValue,
+};
+use crate::lua_engine::{LuaRuntime, LuaRuntimeConfig, ProgramResult, RenderableThing};
+use crate::prelude::selection::{SelectionExpression, SelectionKind};
 use crate::prelude::*;
+use mlua::{FromLua, ToLua};
+use slotmap::SecondaryMap;
 
 /// Looks for the first node with no outgoing parameters and assumes it to be
 /// the graph's final node. Comment nodes are ignored because examples typically
@@ -41,7 +49,11 @@ struct Example {
 
 fn run_example(example: &Example, rt: &LuaRuntime) -> ProgramResult {
     let bjk_data = std::fs::read_to_string(example.path).unwrap();
-    let (rt_data, _, _) = SerializedBjkGraph::load_from_string(&bjk_data)
+    run_bjk_string(&bjk_data, rt)
+}
+
+fn run_bjk_string(bjk_data: &str, rt: &LuaRuntime) -> ProgramResult {
+    let (rt_data, _, _) = SerializedBjkGraph::load_from_string(bjk_data)
         .unwrap()
         .into_runtime()
         .unwrap();
@@ -93,3 +105,1485 @@ pub fn test_examples_folder() {
         }
     }
 }
+
+#[test]
+pub fn test_canonical_format_examples() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let mesh_positions = |result: ProgramResult| match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(h)) => {
+            let positions = h.read_positions();
+            h.read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| positions[v])
+                .collect_vec()
+        }
+        _ => panic!("Expected a mesh"),
+    };
+
+    for path in [
+        "../examples/box.bjk",
+        "../examples/tp_cutter.bjk",
+        "../examples/stylised_sword.bjk",
+    ] {
+        println!("Converting example at {path}");
+        let original = std::fs::read_to_string(path).unwrap();
+        let canonical = SerializedBjkGraph::load_from_string(&original)
+            .unwrap()
+            .to_canonical_string()
+            .unwrap();
+        assert_eq!(
+            mesh_positions(run_bjk_string(&original, &lua_runtime)),
+            mesh_positions(run_bjk_string(&canonical, &lua_runtime)),
+        );
+    }
+}
+
+#[test]
+pub fn test_bundled_templates() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+
+    for path in [
+        "../templates/box_modeling.bjk",
+        "../templates/terrain.bjk",
+        "../templates/scatter.bjk",
+    ] {
+        println!("Loading template at {path}");
+        let bytes = std::fs::read(path).unwrap();
+        let (rt_data, ui_data, _) = SerializedBjkGraph::load_from_bytes(&bytes)
+            .unwrap()
+            .into_runtime()
+            .unwrap();
+        assert!(ui_data.is_some(), "{path} can't be opened in the editor");
+        let target = rt_data
+            .graph
+            .default_node
+            .unwrap_or_else(|| infer_target_node(&rt_data.graph));
+        let result = run_graph(
+            &lua_runtime.lua,
+            &rt_data.graph,
+            target,
+            rt_data.external_parameters.unwrap(),
+            &lua_runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        match result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(h)) => {
+                assert!(h.read_connectivity().num_faces() > 0, "{path} is empty")
+            }
+            Some(RenderableThing::HeightMap(_)) => {}
+            _ => panic!("{path} doesn't produce a mesh"),
+        }
+    }
+}
+
+/// The example graphs opened from the docs of a node
+#[test]
+pub fn test_node_examples() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+
+    for (op_name, path) in [
+        ("ExtrudeFaces", "../examples/nodes/extrude_faces.bjk"),
+        ("BevelEdges", "../examples/nodes/bevel_edges.bjk"),
+    ] {
+        println!("Running node example at {path}");
+        let (rt_data, _, _) = SerializedBjkGraph::load_from_file(path)
+            .unwrap()
+            .into_runtime()
+            .unwrap();
+        let target = rt_data.graph.default_node.unwrap();
+        assert_eq!(rt_data.graph.nodes[target].op_name, op_name);
+        let result = run_graph(
+            &lua_runtime.lua,
+            &rt_data.graph,
+            target,
+            rt_data.external_parameters.unwrap(),
+            &lua_runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        match result.renderable {
+            // The box the node edits has 6 faces
+            Some(RenderableThing::HalfEdgeMesh(h)) => {
+                assert!(
+                    h.read_connectivity().num_faces() > 6,
+                    "{path} changed nothing"
+                )
+            }
+            _ => panic!("{path} doesn't produce a mesh"),
+        }
+    }
+}
+
+/// Builds a graph extruding the face with id 2 of a box, picked in the
+/// viewport against the output of the box node. Optionally, a subdivide node
+/// is inserted between the box and the extrusion.
+fn picked_extrude_graph(subdivide: bool) -> (BjkGraph, BjkNodeId, ExternalParameterValues) {
+    let mut graph = BjkGraph::new();
+    let mut params = ExternalParameterValues::default();
+    let mut param = |node, name: &str, value| {
+        params
+            .0
+            .insert(ExternalParameter::new(node, name.into()), value);
+    };
+
+    let cube = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_input(cube, "origin", DataType::Vector, None)
+        .unwrap();
+    graph
+        .add_input(cube, "size", DataType::Vector, None)
+        .unwrap();
+    graph.add_output(cube, "out_mesh", DataType::Mesh).unwrap();
+    param(cube, "origin", BlackjackValue::Vector(Vec3::ZERO));
+    param(cube, "size", BlackjackValue::Vector(Vec3::ONE));
+
+    let mut last = cube;
+    if subdivide {
+        let subd = graph.add_node("Subdivide", Some("out_mesh".into()));
+        graph.add_input(subd, "mesh", DataType::Mesh, None).unwrap();
+        graph
+            .add_input(subd, "technique", DataType::String, None)
+            .unwrap();
+        graph
+            .add_input(subd, "iterations", DataType::Scalar, None)
+            .unwrap();
+        graph.add_output(subd, "out_mesh", DataType::Mesh).unwrap();
+        graph
+            .add_connection(cube, "out_mesh", subd, "mesh")
+            .unwrap();
+        param(subd, "technique", BlackjackValue::String("linear".into()));
+        param(subd, "iterations", BlackjackValue::Scalar(2.0));
+        last = subd;
+    }
+
+    let extrude = graph.add_node("ExtrudeFaces", Some("out_mesh".into()));
+    graph
+        .add_input(extrude, "in_mesh", DataType::Mesh, None)
+        .unwrap();
+    graph
+        .add_input(extrude, "faces", DataType::Selection, None)
+        .unwrap();
+    graph
+        .add_input(extrude, "amount", DataType::Scalar, None)
+        .unwrap();
+    graph
+        .add_output(extrude, "out_mesh", DataType::Mesh)
+        .unwrap();
+    graph
+        .add_connection(last, "out_mesh", extrude, "in_mesh")
+        .unwrap();
+    let faces = SelectionExpression::from_ids([2]);
+    param(
+        extrude,
+        "faces",
+        BlackjackValue::Selection(faces.unparse(), Some(faces)),
+    );
+    param(extrude, "amount", BlackjackValue::Scalar(1.0));
+    graph
+        .set_picked_from(
+            extrude,
+            "faces",
+            Some(PickedSelection {
+                node: cube,
+                kind: SelectionKind::Faces,
+            }),
+        )
+        .unwrap();
+
+    (graph, extrude, params)
+}
+
+fn bounding_box(mesh: &HalfEdgeMesh) -> (Vec3, Vec3) {
+    let positions = mesh.read_positions();
+    mesh.read_connectivity().iter_vertices().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), (v, _)| (min.min(positions[v]), max.max(positions[v])),
+    )
+}
+
+#[test]
+pub fn test_mesh_stats_counts_connect_to_scalars() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (mut graph, extrude, params) = picked_extrude_graph(false);
+    let cube = match &graph.nodes[extrude].inputs[0].kind {
+        crate::graph::DependencyKind::Connection { node, .. } => *node,
+        _ => unreachable!(),
+    };
+    let stats_outputs = lua_runtime
+        .node_definitions
+        .node_def("MeshStats")
+        .unwrap()
+        .outputs
+        .clone();
+    let stats = graph.add_node("MeshStats", None);
+    graph
+        .add_input(stats, "mesh", DataType::Mesh, None)
+        .unwrap();
+    for output in &stats_outputs {
+        graph
+            .add_output(stats, &output.name, output.data_type)
+            .unwrap();
+    }
+    graph
+        .add_connection(cube, "out_mesh", stats, "mesh")
+        .unwrap();
+
+    // All the counts are integers, and any of them can drive a scalar input
+    let counts = [
+        "vertices",
+        "edges",
+        "faces",
+        "triangles",
+        "boundary_loops",
+        "components",
+    ];
+    for count in counts {
+        let output = stats_outputs.iter().find(|o| o.name == count).unwrap();
+        assert_eq!(output.data_type, DataType::Int, "{count}");
+    }
+    // Extruding the box by its 6 faces makes it 7 units tall
+    graph
+        .add_connection(stats, "faces", extrude, "amount")
+        .unwrap();
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        extrude,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+            let (min, max) = bounding_box(&mesh);
+            assert!(((max - min).max_element() - 7.0).abs() < 1e-4);
+        }
+        _ => panic!("Expected a mesh"),
+    }
+}
+
+#[test]
+pub fn test_picked_selection_survives_upstream_subdivide() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let run = |subdivide| {
+        let (graph, target, params) = picked_extrude_graph(subdivide);
+        match run_graph(
+            &lua_runtime.lua,
+            &graph,
+            target,
+            params,
+            &lua_runtime.node_definitions,
+            None,
+        )
+        .unwrap()
+        .renderable
+        {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh,
+            _ => panic!("Expected a mesh"),
+        }
+    };
+
+    let plain = run(false);
+    let subdivided = run(true);
+
+    // The extrusion happens on the same side of the box, so the result covers
+    // the same region of space.
+    let (min_a, max_a) = bounding_box(&plain);
+    let (min_b, max_b) = bounding_box(&subdivided);
+    assert!(min_a.abs_diff_eq(min_b, 1e-5) && max_a.abs_diff_eq(max_b, 1e-5));
+    assert!((max_a - min_a).max_element() > 1.99);
+
+    // The 16 faces that replaced the original one are extruded as a single
+    // region, adding a strip of 4 side faces along each of its 4 edges.
+    assert_eq!(subdivided.read_connectivity().num_faces(), 6 * 16 + 16);
+}
+
+#[test]
+pub fn test_step_through_graph() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (graph, target, params) = picked_extrude_graph(true);
+    let mut interpreter = GraphInterpreter::run_iter(
+        &lua_runtime.lua,
+        &graph,
+        target,
+        params,
+        &lua_runtime.node_definitions,
+        RunOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(interpreter.pending_nodes().len(), 3);
+
+    let summary = |num_vertices, num_faces, num_halfedges| {
+        StepValue::Mesh(MeshSummary {
+            num_vertices,
+            num_faces,
+            num_halfedges,
+        })
+    };
+    let cube = interpreter.next().unwrap().unwrap();
+    assert_eq!(cube.op_name, "MakeBox");
+    assert!(cube.inputs.contains(&(
+        "size".into(),
+        StepValue::Value(BlackjackValue::Vector(Vec3::ONE))
+    )));
+    assert_eq!(
+        cube.outputs,
+        vec![("out_mesh".to_string(), summary(8, 6, 24))]
+    );
+    // The full meshes are only copied on request
+    let mesh = cube.output_mesh("out_mesh").unwrap().unwrap();
+    assert_eq!(mesh.read_connectivity().num_faces(), 6);
+    assert!(cube.output_mesh("not_an_output").unwrap().is_none());
+
+    let subdivide = interpreter.next().unwrap().unwrap();
+    assert_eq!(subdivide.op_name, "Subdivide");
+    assert_eq!(subdivide.inputs[0], ("mesh".into(), summary(8, 6, 24)));
+    match &subdivide.outputs[0].1 {
+        StepValue::Mesh(summary) => assert_eq!(summary.num_faces, 6 * 16),
+        other => panic!("Expected a mesh, got {other:?}"),
+    }
+
+    // Stopping here, the extrusion never runs
+    assert_eq!(interpreter.pending_nodes(), &[target]);
+    assert_eq!(interpreter.stats().nodes_executed, 2);
+    drop(interpreter);
+
+    // Finishing an execution that was stepped through runs the rest of it
+    let (graph, target, params) = picked_extrude_graph(true);
+    let mut interpreter = GraphInterpreter::run_iter(
+        &lua_runtime.lua,
+        &graph,
+        target,
+        params,
+        &lua_runtime.node_definitions,
+        RunOptions::default(),
+    )
+    .unwrap();
+    interpreter.next().unwrap().unwrap();
+    let result = interpreter.finish().unwrap();
+    assert_eq!(result.stats.nodes_executed, 3);
+    match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+            assert_eq!(mesh.read_connectivity().num_faces(), 6 * 16 + 16)
+        }
+        _ => panic!("Expected a mesh"),
+    }
+}
+
+/// A box with two identical jitter nodes downstream of it, as if one was a
+/// duplicate of the other.
+fn duplicated_jitter_graph(seed: u64) -> (BjkGraph, [BjkNodeId; 2], ExternalParameterValues) {
+    let mut graph = BjkGraph::new();
+    graph.seed = seed;
+    let (jitters, params) = add_duplicated_jitters(&mut graph);
+    (graph, jitters, params)
+}
+
+/// Adds the nodes of [`duplicated_jitter_graph`] to an existing `graph`.
+fn add_duplicated_jitters(graph: &mut BjkGraph) -> ([BjkNodeId; 2], ExternalParameterValues) {
+    let mut params = ExternalParameterValues::default();
+    let mut param = |node, name: &str, value| {
+        params
+            .0
+            .insert(ExternalParameter::new(node, name.into()), value);
+    };
+
+    let cube = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_input(cube, "origin", DataType::Vector, None)
+        .unwrap();
+    graph
+        .add_input(cube, "size", DataType::Vector, None)
+        .unwrap();
+    graph.add_output(cube, "out_mesh", DataType::Mesh).unwrap();
+    param(cube, "origin", BlackjackValue::Vector(Vec3::ZERO));
+    param(cube, "size", BlackjackValue::Vector(Vec3::ONE));
+
+    let mut add_jitter = || {
+        let jitter = graph.add_node("Jitter", Some("out_mesh".into()));
+        graph
+            .add_input(jitter, "mesh", DataType::Mesh, None)
+            .unwrap();
+        for (name, data_type) in [
+            ("selection", DataType::Selection),
+            ("amount", DataType::Vector),
+            ("seed", DataType::Scalar),
+            ("mode", DataType::String),
+            ("mask_channel", DataType::String),
+        ] {
+            graph.add_input(jitter, name, data_type, None).unwrap();
+        }
+        graph
+            .add_output(jitter, "out_mesh", DataType::Mesh)
+            .unwrap();
+        graph
+            .add_connection(cube, "out_mesh", jitter, "mesh")
+            .unwrap();
+        let all = SelectionExpression::All;
+        param(
+            jitter,
+            "selection",
+            BlackjackValue::Selection(all.unparse(), Some(all)),
+        );
+        param(jitter, "amount", BlackjackValue::Vector(Vec3::splat(0.1)));
+        param(jitter, "seed", BlackjackValue::Scalar(0.0));
+        param(jitter, "mode", BlackjackValue::String("World".into()));
+        param(jitter, "mask_channel", BlackjackValue::String("".into()));
+        jitter
+    };
+    let jitters = [add_jitter(), add_jitter()];
+
+    (jitters, params)
+}
+
+fn output_positions(
+    lua_runtime: &LuaRuntime,
+    graph: &BjkGraph,
+    target: BjkNodeId,
+    params: ExternalParameterValues,
+) -> Vec<Vec3> {
+    let result = run_graph(
+        &lua_runtime.lua,
+        graph,
+        target,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+            let positions = mesh.read_positions();
+            mesh.read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| positions[v])
+                .collect()
+        }
+        _ => panic!("Expected a mesh"),
+    }
+}
+
+#[test]
+pub fn test_node_seeds() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+
+    // Duplicated nodes get different seeds
+    let (graph, [a, b], params) = duplicated_jitter_graph(7);
+    let positions_a = output_positions(&lua_runtime, &graph, a, params.clone());
+    let positions_b = output_positions(&lua_runtime, &graph, b, params.clone());
+    assert_ne!(positions_a, positions_b);
+    // But the results are the same on every run
+    assert_eq!(
+        positions_a,
+        output_positions(&lua_runtime, &graph, a, params.clone())
+    );
+
+    // Changing the graph seed re-rolls every node
+    let (rerolled, [a2, _], rerolled_params) = duplicated_jitter_graph(8);
+    assert_ne!(
+        positions_a,
+        output_positions(&lua_runtime, &rerolled, a2, rerolled_params)
+    );
+
+    // Saving and loading the graph keeps the seeds of all nodes
+    let (serialized, mappings) = SerializedBjkGraph::from_runtime(RuntimeData {
+        graph,
+        external_parameters: Some(params),
+    })
+    .unwrap();
+    let (idx_a, idx_b) = (mappings.get_idx(a).unwrap(), mappings.get_idx(b).unwrap());
+    let contents = serialized.to_canonical_string().unwrap();
+    let (loaded, _, loaded_mappings) = SerializedBjkGraph::load_from_string(&contents)
+        .unwrap()
+        .into_runtime()
+        .unwrap();
+    assert_eq!(loaded.graph.seed, 7);
+    let loaded_params = loaded.external_parameters.unwrap();
+    for (idx, expected) in [(idx_a, &positions_a), (idx_b, &positions_b)] {
+        let node = loaded_mappings.get_id(idx).unwrap();
+        assert_eq!(
+            &output_positions(&lua_runtime, &loaded.graph, node, loaded_params.clone()),
+            expected
+        );
+    }
+}
+
+#[test]
+pub fn test_node_seeds_survive_deleting_other_nodes() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let mut graph = BjkGraph::new();
+    graph.seed = 7;
+    let unrelated = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_output(unrelated, "out_mesh", DataType::Mesh)
+        .unwrap();
+    let ([a, b], params) = add_duplicated_jitters(&mut graph);
+
+    let fingerprint = |graph: &BjkGraph, node, params: &ExternalParameterValues| {
+        crate::graph_interpreter::frozen_cache::upstream_fingerprint(
+            graph,
+            params,
+            &lua_runtime.node_definitions,
+            &HashSet::new(),
+            node,
+        )
+        .unwrap()
+    };
+    let before = [a, b].map(|node| {
+        (
+            output_positions(&lua_runtime, &graph, node, params.clone()),
+            fingerprint(&graph, node, &params),
+        )
+    });
+
+    // The editor builds a new graph on every run, so deleting a node shifts
+    // the ids of the nodes that were added after it. Saving and loading the
+    // graph does the same.
+    graph.nodes.remove(unrelated);
+    let (serialized, mappings) = SerializedBjkGraph::from_runtime(RuntimeData {
+        graph,
+        external_parameters: Some(params),
+    })
+    .unwrap();
+    let (rebuilt, _, rebuilt_mappings) = serialized.into_runtime().unwrap();
+    let rebuilt_params = rebuilt.external_parameters.unwrap();
+    for (node, (positions, node_fingerprint)) in [a, b].into_iter().zip(before) {
+        let new_id = rebuilt_mappings
+            .get_id(mappings.get_idx(node).unwrap())
+            .unwrap();
+        assert_ne!(new_id, node);
+        assert_eq!(
+            output_positions(&lua_runtime, &rebuilt.graph, new_id, rebuilt_params.clone()),
+            positions
+        );
+        assert_eq!(
+            fingerprint(&rebuilt.graph, new_id, &rebuilt_params),
+            node_fingerprint
+        );
+    }
+}
+
+#[test]
+pub fn test_expression_parameters() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let mut graph = BjkGraph::new();
+    let cube = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_input(cube, "size", DataType::Vector, Some("box_size".into()))
+        .unwrap();
+    graph
+        .add_input(cube, "origin", DataType::Vector, None)
+        .unwrap();
+    graph.add_output(cube, "out_mesh", DataType::Mesh).unwrap();
+    let origin = ExternalParameter::new(cube, "origin".into());
+    let expr = BlackjackValue::Expression("vec(0, box_size.y / 2 + t, frame)".into());
+    let mut params = ExternalParameterValues::default();
+    params.0.insert(
+        ExternalParameter::new(cube, "size".into()),
+        BlackjackValue::Vector(Vec3::splat(2.0)),
+    );
+    params.0.insert(origin.clone(), expr.clone());
+
+    let run = |time: f32, frame: u32| {
+        GraphInterpreter::run_iter(
+            &lua_runtime.lua,
+            &graph,
+            cube,
+            params.clone(),
+            &lua_runtime.node_definitions,
+            RunOptions {
+                time: ExpressionTime { time, frame },
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .finish()
+        .unwrap()
+    };
+
+    // Expressions are evaluated again on every run
+    for (time, frame) in [(0.0, 0), (1.5, 3)] {
+        let result = run(time, frame);
+        let mesh = match &result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh,
+            _ => panic!("Expected a mesh"),
+        };
+        let (min, max) = bounding_box(mesh);
+        let center = Vec3::new(0.0, 1.0 + time, frame as f32);
+        assert!(min.abs_diff_eq(center - Vec3::ONE, 1e-5));
+        assert!(max.abs_diff_eq(center + Vec3::ONE, 1e-5));
+        // The parameter still holds the expression, not its result
+        assert_eq!(result.updated_values.0[&origin], expr);
+    }
+}
+
+#[test]
+pub fn test_warnings_are_attributed_to_nodes() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (mut graph, [jitter, _], mut params) = duplicated_jitter_graph(0);
+    let decimate = graph.add_node("Decimate", Some("out_mesh".into()));
+    graph
+        .add_input(decimate, "mesh", DataType::Mesh, None)
+        .unwrap();
+    graph
+        .add_input(decimate, "ratio", DataType::Scalar, None)
+        .unwrap();
+    graph
+        .add_output(decimate, "out_mesh", DataType::Mesh)
+        .unwrap();
+    graph
+        .add_connection(jitter, "out_mesh", decimate, "mesh")
+        .unwrap();
+    // A box can't be decimated down to no faces at all
+    params.0.insert(
+        ExternalParameter::new(decimate, "ratio".into()),
+        BlackjackValue::Scalar(0.0),
+    );
+
+    let mut interpreter = GraphInterpreter::run_iter(
+        &lua_runtime.lua,
+        &graph,
+        decimate,
+        params,
+        &lua_runtime.node_definitions,
+        RunOptions::default(),
+    )
+    .unwrap();
+    let steps = (&mut interpreter)
+        .map(|step| step.unwrap().warnings)
+        .collect_vec();
+    assert_eq!(steps.len(), 3);
+    assert!(steps[0].is_empty() && steps[1].is_empty());
+    assert_eq!(steps[2].len(), 1);
+
+    // Warnings don't stop the execution, and end up in the stats
+    let result = interpreter.finish().unwrap();
+    assert!(result.renderable.is_some());
+    let warnings = &result.stats.warnings;
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].node_id, decimate);
+    assert_eq!(warnings[0].op_name, "Decimate");
+    assert_eq!(warnings[0].warning, steps[2][0]);
+    assert!(warnings[0].describe().starts_with("Decimate (node "));
+    assert_eq!(result.stats.node_warnings(decimate).count(), 1);
+    assert_eq!(result.stats.node_warnings(jitter).count(), 0);
+}
+
+#[test]
+pub fn test_soft_errors_use_default_outputs() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (mut graph, [jitter, _], mut params) = duplicated_jitter_graph(0);
+    let probe = graph.add_node("Probe", None);
+    graph
+        .add_input(probe, "mesh", DataType::Mesh, None)
+        .unwrap();
+    for (name, data_type) in [
+        ("mode", DataType::String),
+        ("id", DataType::Int),
+        ("channel_key", DataType::String),
+        ("channel_name", DataType::String),
+    ] {
+        graph.add_input(probe, name, data_type, None).unwrap();
+    }
+    graph.add_output(probe, "vector", DataType::Vector).unwrap();
+    graph.add_output(probe, "x", DataType::Scalar).unwrap();
+    graph
+        .add_connection(jitter, "out_mesh", probe, "mesh")
+        .unwrap();
+    let mut param = |name: &str, value| {
+        params
+            .0
+            .insert(ExternalParameter::new(probe, name.into()), value);
+    };
+    param("mode", BlackjackValue::String("VertexPosition".into()));
+    // The box only has 8 vertices
+    param("id", BlackjackValue::Int(50));
+    param("channel_key", BlackjackValue::String("Vertex".into()));
+    param("channel_name", BlackjackValue::String("".into()));
+
+    let mut interpreter = GraphInterpreter::run_iter(
+        &lua_runtime.lua,
+        &graph,
+        probe,
+        params.clone(),
+        &lua_runtime.node_definitions,
+        RunOptions::default(),
+    )
+    .unwrap();
+    let steps = (&mut interpreter).map(|step| step.unwrap()).collect_vec();
+    let probe_step = steps.last().unwrap();
+    assert_eq!(probe_step.node_id, probe);
+    assert_eq!(
+        probe_step.outputs,
+        vec![
+            (
+                "vector".to_string(),
+                StepValue::Value(BlackjackValue::Vector(Vec3::ZERO))
+            ),
+            (
+                "x".to_string(),
+                StepValue::Value(BlackjackValue::Scalar(0.0))
+            ),
+        ]
+    );
+    let result = interpreter.finish().unwrap();
+    let error = result.stats.node_error(probe).unwrap();
+    assert!(error.message.contains("out of range"), "{}", error.message);
+    assert!(result.stats.node_error(jitter).is_none());
+
+    // With a valid id, there's no error
+    params.0.insert(
+        ExternalParameter::new(probe, "id".into()),
+        BlackjackValue::Int(0),
+    );
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        probe,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    assert!(result.stats.errors.is_empty());
+}
+
+#[test]
+pub fn test_deform_only_nodes_keep_upstream_meshes() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (mut graph, [jitter, _], params) = duplicated_jitter_graph(0);
+    let cube = match &graph.nodes[jitter].inputs[0].kind {
+        crate::graph::DependencyKind::Connection { node, .. } => *node,
+        _ => unreachable!(),
+    };
+
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        jitter,
+        params.clone(),
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    assert!(result.positions_only);
+
+    // The cube is merged after the jitter ran on it, and its vertices must
+    // still be at the corners.
+    let merge = graph.add_node("MergeMeshes", Some("out_mesh".into()));
+    graph
+        .add_input(merge, "mesh_a", DataType::Mesh, None)
+        .unwrap();
+    graph
+        .add_input(merge, "mesh_b", DataType::Mesh, None)
+        .unwrap();
+    graph.add_output(merge, "out_mesh", DataType::Mesh).unwrap();
+    graph
+        .add_connection(jitter, "out_mesh", merge, "mesh_a")
+        .unwrap();
+    graph
+        .add_connection(cube, "out_mesh", merge, "mesh_b")
+        .unwrap();
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        merge,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    assert!(!result.positions_only);
+    let corners = match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+            let positions = mesh.read_positions();
+            mesh.read_connectivity()
+                .iter_vertices()
+                .filter(|(v, _)| positions[*v].abs() == Vec3::splat(0.5))
+                .count()
+        }
+        _ => panic!("Expected a mesh"),
+    };
+    assert_eq!(corners, 8);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+pub fn test_deform_only_nodes_that_change_topology_run_again() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let node_library = lua_runtime
+        .lua
+        .load(
+            r#"
+            local NodeLibrary = require("node_library")
+            NodeLibrary:addNodes({
+                NotADeform = {
+                    label = "Not a deform",
+                    inputs = { { name = "mesh", type = "mesh" } },
+                    outputs = { { name = "out_mesh", type = "mesh" } },
+                    returns = "out_mesh",
+                    deform_only = true,
+                    op = function(inputs)
+                        Ops.extrude(SelectionExpression.new("0"), 0.5, inputs.mesh)
+                        return { out_mesh = inputs.mesh }
+                    end,
+                },
+            })
+            return NodeLibrary.nodes
+            "#,
+        )
+        .eval::<mlua::Table>()
+        .unwrap();
+    lua_runtime
+        .node_definitions
+        .update(crate::graph::NodeDefinition::load_nodes_from_table(node_library).unwrap());
+
+    let (mut graph, [jitter, _], params) = duplicated_jitter_graph(0);
+    let node = graph.add_node("NotADeform", Some("out_mesh".into()));
+    graph.add_input(node, "mesh", DataType::Mesh, None).unwrap();
+    graph.add_output(node, "out_mesh", DataType::Mesh).unwrap();
+    graph
+        .add_connection(jitter, "out_mesh", node, "mesh")
+        .unwrap();
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        node,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    assert!(!result.positions_only);
+    let warnings = result.stats.node_warnings(node).collect_vec();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].message.contains("deform-only"));
+    match result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+            assert_eq!(mesh.read_connectivity().num_faces(), 10);
+            assert!(edit_ops::channels_in_sync(&mesh));
+        }
+        _ => panic!("Expected a mesh"),
+    }
+}
+
+#[test]
+pub fn test_handle_gizmos_round_trip() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let lua = &lua_runtime.lua;
+    let handles = lua
+        .load(
+            r#"
+            local Gz = require("gizmo_helpers")
+            return {
+                Gz.point("center", vector(1, 2, 3)),
+                Gz.arrow("normal", vector(0, 0, 0), vector(0, 2, 0)),
+                Gz.slider("radius", vector(1, 0, 0), vector(0, 0, 1), 0.5),
+            }
+            "#,
+        )
+        .eval::<BlackjackGizmo>()
+        .unwrap();
+    let expected = vec![
+        HandleGizmo::Point {
+            param: "center".into(),
+            position: Vec3::new(1.0, 2.0, 3.0),
+        },
+        HandleGizmo::Arrow {
+            param: "normal".into(),
+            origin: Vec3::ZERO,
+            direction: Vec3::new(0.0, 2.0, 0.0),
+        },
+        HandleGizmo::Slider {
+            param: "radius".into(),
+            origin: Vec3::X,
+            axis: Vec3::Z,
+            value: 0.5,
+        },
+    ];
+    match &handles {
+        BlackjackGizmo::Handles(h) => assert_eq!(h, &expected),
+        _ => panic!("Expected handles"),
+    }
+    match BlackjackGizmo::from_lua(handles.to_lua(lua).unwrap(), lua).unwrap() {
+        BlackjackGizmo::Handles(h) => assert_eq!(h, expected),
+        _ => panic!("Expected handles"),
+    }
+
+    let bad_kind = lua
+        .load(r#"return { { kind = "dial", param = "radius" } }"#)
+        .eval::<BlackjackGizmo>();
+    assert!(bad_kind.is_err());
+}
+
+#[test]
+pub fn test_dragging_handle_gizmos_updates_params() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let mut graph = BjkGraph::new();
+    let circle = graph.add_node("MakeCircle", Some("out_mesh".into()));
+    let mut params = ExternalParameterValues::default();
+    for (name, data_type, value) in [
+        ("center", DataType::Vector, BlackjackValue::Vector(Vec3::Y)),
+        ("radius", DataType::Scalar, BlackjackValue::Scalar(1.0)),
+        ("num_vertices", DataType::Int, BlackjackValue::Int(8)),
+        (
+            "fill",
+            DataType::String,
+            BlackjackValue::String("None".into()),
+        ),
+    ] {
+        graph.add_input(circle, name, data_type, None).unwrap();
+        params
+            .0
+            .insert(ExternalParameter::new(circle, name.into()), value);
+    }
+    graph
+        .add_output(circle, "out_mesh", DataType::Mesh)
+        .unwrap();
+
+    let run = |params: ExternalParameterValues, gizmo_state: GizmoState| {
+        let mut gizmos_state = SecondaryMap::new();
+        gizmos_state.insert(circle, gizmo_state);
+        let mut result = run_graph(
+            &lua_runtime.lua,
+            &graph,
+            circle,
+            params,
+            &lua_runtime.node_definitions,
+            Some(gizmos_state),
+        )
+        .unwrap();
+        let mut gizmos = result
+            .updated_gizmos
+            .take()
+            .unwrap()
+            .remove(circle)
+            .unwrap();
+        assert_eq!(gizmos.len(), 1);
+        match gizmos.remove(0) {
+            BlackjackGizmo::Handles(handles) => (result, handles),
+            _ => panic!("Expected handles"),
+        }
+    };
+
+    let (_, mut handles) = run(params.clone(), GizmoState::default());
+    assert_eq!(handles.len(), 2);
+    assert_eq!(handles[1].handle_position(), Vec3::new(1.0, 1.0, 0.0));
+
+    // Dragging the slider sets the radius to its distance from the center
+    handles[1].drag_to(Vec3::new(2.5, 3.0, 0.0));
+    let (result, handles) = run(
+        params,
+        GizmoState {
+            active_gizmos: Some(vec![BlackjackGizmo::Handles(handles)]),
+            gizmos_changed: true,
+        },
+    );
+    let radius = &result.updated_values.0[&ExternalParameter::new(circle, "radius".into())];
+    assert_eq!(radius, &BlackjackValue::Scalar(2.5));
+    assert_eq!(handles[1].bound_value(), BlackjackValue::Scalar(2.5));
+    let center = &result.updated_values.0[&ExternalParameter::new(circle, "center".into())];
+    assert_eq!(center, &BlackjackValue::Vector(Vec3::Y));
+}
+
+#[test]
+pub fn test_panicking_ops_fail_their_node() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let node_library = lua_runtime
+        .lua
+        .load(
+            r#"
+            local NodeLibrary = require("node_library")
+            NodeLibrary:addNodes({
+                Crash = {
+                    label = "Crash",
+                    inputs = { { name = "mesh", type = "mesh" } },
+                    outputs = { { name = "out_mesh", type = "mesh" } },
+                    returns = "out_mesh",
+                    op = function(inputs)
+                        Ops.test_panic("Unexpected topology")
+                        return { out_mesh = inputs.mesh }
+                    end,
+                },
+            })
+            return NodeLibrary.nodes
+            "#,
+        )
+        .eval::<mlua::Table>()
+        .unwrap();
+    lua_runtime
+        .node_definitions
+        .update(crate::graph::NodeDefinition::load_nodes_from_table(node_library).unwrap());
+
+    let (mut graph, [jitter, _], params) = duplicated_jitter_graph(0);
+    let crash = graph.add_node("Crash", Some("out_mesh".into()));
+    graph
+        .add_input(crash, "mesh", DataType::Mesh, None)
+        .unwrap();
+    graph.add_output(crash, "out_mesh", DataType::Mesh).unwrap();
+    graph
+        .add_connection(jitter, "out_mesh", crash, "mesh")
+        .unwrap();
+
+    let err = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        crash,
+        params.clone(),
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .err()
+    .expect("The run should fail");
+    let panicked = err
+        .downcast_ref::<NodePanicked>()
+        .expect("The error should point to the node");
+    assert_eq!(panicked.node_id, crash);
+    assert!(panicked.message.contains("Unexpected topology"));
+
+    // The runtime keeps working after the panic
+    let before = output_positions(&lua_runtime, &graph, jitter, params.clone());
+    let after = output_positions(&lua_runtime, &graph, jitter, params);
+    assert_eq!(before.len(), 8);
+    assert_eq!(before, after);
+}
+
+#[test]
+pub fn test_curve_inputs_are_checked() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let node_library = lua_runtime
+        .lua
+        .load(
+            r#"
+            local NodeLibrary = require("node_library")
+            NodeLibrary:addNodes({
+                KeepCurve = {
+                    label = "Keep Curve",
+                    inputs = { { name = "curve", type = "curve" } },
+                    outputs = { { name = "out_curve", type = "curve" } },
+                    returns = "out_curve",
+                    op = function(inputs)
+                        return { out_curve = inputs.curve }
+                    end,
+                },
+            })
+            return NodeLibrary.nodes
+            "#,
+        )
+        .eval::<mlua::Table>()
+        .unwrap();
+    lua_runtime
+        .node_definitions
+        .update(crate::graph::NodeDefinition::load_nodes_from_table(node_library).unwrap());
+
+    // The graph says the box is a curve, like a file edited by hand would
+    let mut graph = BjkGraph::new();
+    let cube = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_input(cube, "origin", DataType::Vector, None)
+        .unwrap();
+    graph
+        .add_input(cube, "size", DataType::Vector, None)
+        .unwrap();
+    graph.add_output(cube, "out_mesh", DataType::Curve).unwrap();
+    let keep = graph.add_node("KeepCurve", Some("out_curve".into()));
+    graph
+        .add_input(keep, "curve", DataType::Curve, None)
+        .unwrap();
+    graph
+        .add_output(keep, "out_curve", DataType::Curve)
+        .unwrap();
+    graph
+        .add_connection(cube, "out_mesh", keep, "curve")
+        .unwrap();
+    let mut params = ExternalParameterValues::default();
+    for (name, value) in [("origin", Vec3::ZERO), ("size", Vec3::ONE)] {
+        params.0.insert(
+            ExternalParameter::new(cube, name.into()),
+            BlackjackValue::Vector(value),
+        );
+    }
+
+    let err = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        keep,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .err()
+    .expect("The run should fail");
+    let message = format!("{err:#}");
+    assert!(message.contains("Invalid input 'curve'"), "{message}");
+    assert!(message.contains("The mesh is not a curve"), "{message}");
+}
+
+#[test]
+pub fn test_streamed_grid_node() {
+    // The whole grid is way over the instruction limit, but each chunk of
+    // rows is well within it.
+    let lua_runtime = LuaRuntime::initialize_with_std_and_config(
+        "../blackjack_lua".into(),
+        crate::lua_engine::LuaRuntimeConfig {
+            instruction_limit: Some(200_000),
+            ..crate::lua_engine::LuaRuntimeConfig::sandboxed()
+        },
+    )
+    .unwrap();
+    let mut graph = BjkGraph::new();
+    let grid = graph.add_node("MakeStreamedGrid", Some("out_mesh".into()));
+    let mut params = ExternalParameterValues::default();
+    for (name, data_type, value) in [
+        (
+            "size",
+            DataType::Vector,
+            BlackjackValue::Vector(Vec3::new(100.0, 0.0, 10.0)),
+        ),
+        ("columns", DataType::Int, BlackjackValue::Int(1000)),
+        ("rows", DataType::Int, BlackjackValue::Int(100)),
+        ("chunk_rows", DataType::Int, BlackjackValue::Int(1)),
+    ] {
+        graph.add_input(grid, name, data_type, None).unwrap();
+        params
+            .0
+            .insert(ExternalParameter::new(grid, name.into()), value);
+    }
+    graph.add_output(grid, "out_mesh", DataType::Mesh).unwrap();
+
+    let result = run_graph(
+        &lua_runtime.lua,
+        &graph,
+        grid,
+        params,
+        &lua_runtime.node_definitions,
+        None,
+    )
+    .unwrap();
+    let mesh = match &result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh,
+        _ => panic!("Expected a mesh"),
+    };
+    assert_eq!(mesh.read_connectivity().num_faces(), 100_000);
+    let (min, max) = bounding_box(mesh);
+    assert!(min.abs_diff_eq(Vec3::new(-50.0, 0.0, -5.0), 1e-3));
+    assert!(max.abs_diff_eq(Vec3::new(50.0, 0.0, 5.0), 1e-3));
+}
+
+#[test]
+pub fn test_import_nodes_share_asset_cache() {
+    let mut graph = BjkGraph::new();
+    let import = graph.add_node("ImportObj", Some("out_mesh".into()));
+    graph
+        .add_input(import, "path", DataType::String, None)
+        .unwrap();
+    graph
+        .add_output(import, "out_mesh", DataType::Mesh)
+        .unwrap();
+    let mut params = ExternalParameterValues::default();
+    params.0.insert(
+        ExternalParameter::new(import, "path".into()),
+        BlackjackValue::String("../test/test_mesh.obj".into()),
+    );
+
+    let a = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let mut b = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    b.set_asset_cache(a.asset_cache().clone()).unwrap();
+    let faces = |lua_runtime: &LuaRuntime| {
+        let result = run_graph(
+            &lua_runtime.lua,
+            &graph,
+            import,
+            params.clone(),
+            &lua_runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        match &result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh.read_connectivity().num_faces(),
+            _ => panic!("Expected a mesh"),
+        }
+    };
+
+    // The file is only parsed the first time, even from another runtime
+    let expected = faces(&a);
+    assert!(expected > 0);
+    assert_eq!(faces(&a), expected);
+    assert_eq!(faces(&b), expected);
+    let stats = a.asset_cache().stats();
+    assert_eq!((stats.misses, stats.hits), (1, 2));
+    assert_eq!(stats.entries, 1);
+}
+
+#[test]
+pub fn test_alternative_run_reuses_upstream_outputs() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (graph, extrude, params) = picked_extrude_graph(true);
+    let subd = match &graph.nodes[extrude].inputs[0].kind {
+        crate::graph::DependencyKind::Connection { node, .. } => *node,
+        _ => unreachable!(),
+    };
+    let faces = |result: &ProgramResult| match &result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh.read_connectivity().num_faces(),
+        _ => panic!("Expected a mesh"),
+    };
+    let run = |muted: HashSet<BjkNodeId>| {
+        GraphInterpreter::run_iter(
+            &lua_runtime.lua,
+            &graph,
+            extrude,
+            params.clone(),
+            &lua_runtime.node_definitions,
+            RunOptions {
+                muted,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let group: HashSet<_> = [subd].into_iter().collect();
+    let (primary, alternative) = run(HashSet::new()).finish_with_alternative(&group).unwrap();
+    let alternative = alternative.unwrap();
+    assert_eq!(primary.stats.nodes_executed, 3);
+    assert_eq!(primary.stats.nodes_reused, 0);
+    // The cube is shared by both variants, and only the extrude runs again
+    assert_eq!(alternative.stats.nodes_reused, 1);
+    assert_eq!(alternative.stats.nodes_bypassed, 1);
+    assert_eq!(alternative.stats.nodes_executed, 1);
+
+    // Without the subdivision, the picked face is still found in the cube,
+    // and only gets 4 side faces.
+    assert_eq!(faces(&primary), 6 * 16 + 16);
+    assert_eq!(faces(&alternative), 10);
+
+    // Running with the group bypassed gives the same result as the
+    // alternative, and its alternative is the original graph.
+    let (bypassed, restored) = run(group.clone()).finish_with_alternative(&group).unwrap();
+    assert_eq!(bypassed.stats.nodes_bypassed, 1);
+    assert_eq!(faces(&bypassed), 10);
+    assert_eq!(faces(&restored.unwrap()), 6 * 16 + 16);
+}
+
+#[test]
+pub fn test_frozen_cache_skips_upstream_nodes() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let dir = std::env::temp_dir().join("blackjack_frozen_cache_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    let cached_file = dir.join("graph.box.bjkmesh");
+    let _ = std::fs::remove_file(&cached_file);
+
+    let mut graph = BjkGraph::new();
+    graph.file_path = Some(dir.join("graph.bjk"));
+    let cube = graph.add_node("MakeBox", Some("out_mesh".into()));
+    graph
+        .add_input(cube, "origin", DataType::Vector, None)
+        .unwrap();
+    graph
+        .add_input(cube, "size", DataType::Vector, None)
+        .unwrap();
+    graph.add_output(cube, "out_mesh", DataType::Mesh).unwrap();
+    let cache = graph.add_node("Cache", Some("out_mesh".into()));
+    graph
+        .add_input(cache, "mesh", DataType::Mesh, None)
+        .unwrap();
+    for (name, data_type) in [
+        ("frozen", DataType::Bool),
+        ("name", DataType::String),
+        ("fingerprint", DataType::String),
+    ] {
+        graph.add_input(cache, name, data_type, None).unwrap();
+    }
+    graph.add_output(cache, "out_mesh", DataType::Mesh).unwrap();
+    graph
+        .add_connection(cube, "out_mesh", cache, "mesh")
+        .unwrap();
+
+    let mut params = ExternalParameterValues::default();
+    let set = |params: &mut ExternalParameterValues, node, name: &str, value| {
+        params
+            .0
+            .insert(ExternalParameter::new(node, name.into()), value);
+    };
+    set(
+        &mut params,
+        cube,
+        "origin",
+        BlackjackValue::Vector(Vec3::ZERO),
+    );
+    set(&mut params, cube, "size", BlackjackValue::Vector(Vec3::ONE));
+    set(&mut params, cache, "frozen", BlackjackValue::Bool(false));
+    set(
+        &mut params,
+        cache,
+        "name",
+        BlackjackValue::String("box".into()),
+    );
+    set(
+        &mut params,
+        cache,
+        "fingerprint",
+        BlackjackValue::String("".into()),
+    );
+
+    let run = |params: &ExternalParameterValues| {
+        let result = run_graph(
+            &lua_runtime.lua,
+            &graph,
+            cache,
+            params.clone(),
+            &lua_runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        let size = match &result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+                let (min, max) = bounding_box(mesh);
+                max - min
+            }
+            _ => panic!("Expected a mesh"),
+        };
+        (result, size)
+    };
+    let fingerprint = |params: &ExternalParameterValues| match &params.0
+        [&ExternalParameter::new(cache, "fingerprint".into())]
+    {
+        BlackjackValue::String(fingerprint) => fingerprint.clone(),
+        other => panic!("Unexpected fingerprint {other:?}"),
+    };
+
+    // Unfrozen caches just pass the mesh through
+    let (result, _) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 2);
+    assert_eq!(fingerprint(&result.updated_values), "");
+    assert!(!cached_file.exists());
+
+    // Freezing stores the mesh, and the box stops running
+    set(&mut params, cache, "frozen", BlackjackValue::Bool(true));
+    let (result, _) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 2);
+    assert!(cached_file.exists());
+    assert_ne!(fingerprint(&result.updated_values), "");
+    let mut params = result.updated_values;
+    let (result, size) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 1);
+    assert!(result.stats.warnings.is_empty());
+    assert!(size.abs_diff_eq(Vec3::ONE, 1e-5));
+
+    // Changing the box makes the cache stale, but it keeps its mesh
+    set(
+        &mut params,
+        cube,
+        "size",
+        BlackjackValue::Vector(Vec3::splat(2.0)),
+    );
+    let (result, size) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 1);
+    assert_eq!(result.stats.warnings.len(), 1);
+    assert_eq!(result.stats.warnings[0].node_id, cache);
+    assert!(result.stats.warnings[0].warning.message.contains("stale"));
+    assert!(size.abs_diff_eq(Vec3::ONE, 1e-5));
+
+    // Refreshing runs the box again
+    set(
+        &mut params,
+        cache,
+        "fingerprint",
+        BlackjackValue::String("".into()),
+    );
+    let (result, size) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 2);
+    assert!(size.abs_diff_eq(Vec3::splat(2.0), 1e-5));
+    let mut params = result.updated_values;
+    let (result, size) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 1);
+    assert!(result.stats.warnings.is_empty());
+    assert!(size.abs_diff_eq(Vec3::splat(2.0), 1e-5));
+
+    // Thawing forgets the fingerprint, so freezing again stores a new mesh
+    set(&mut params, cache, "frozen", BlackjackValue::Bool(false));
+    let (result, _) = run(&params);
+    assert_eq!(result.stats.nodes_executed, 2);
+    assert_eq!(fingerprint(&result.updated_values), "");
+
+    // The cache writes and reads its file through the engine, which
+    // sandboxed graphs can do too
+    std::fs::remove_file(&cached_file).unwrap();
+    let sandboxed = LuaRuntime::initialize_with_std_and_config(
+        "../blackjack_lua".into(),
+        LuaRuntimeConfig::sandboxed(),
+    )
+    .unwrap();
+    let run_sandboxed = |params: ExternalParameterValues| {
+        run_graph(
+            &sandboxed.lua,
+            &graph,
+            cache,
+            params,
+            &sandboxed.node_definitions,
+            None,
+        )
+        .unwrap()
+    };
+    set(&mut params, cache, "frozen", BlackjackValue::Bool(true));
+    let result = run_sandboxed(params);
+    assert!(cached_file.exists());
+    let result = run_sandboxed(result.updated_values);
+    assert_eq!(result.stats.nodes_executed, 1);
+    assert!(matches!(
+        result.renderable,
+        Some(RenderableThing::HalfEdgeMesh(_))
+    ));
+
+    std::fs::remove_file(&cached_file).unwrap();
+}
+
+#[test]
+pub fn test_memory_budget_stops_large_nodes() {
+    let lua_runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+    let (graph, extrude, params) = picked_extrude_graph(true);
+    let subd = match &graph.nodes[extrude].inputs[0].kind {
+        crate::graph::DependencyKind::Connection { node, .. } => *node,
+        _ => unreachable!(),
+    };
+    let cube = match &graph.nodes[subd].inputs[0].kind {
+        crate::graph::DependencyKind::Connection { node, .. } => *node,
+        _ => unreachable!(),
+    };
+    let run = |target, memory_budget| {
+        GraphInterpreter::run_iter(
+            &lua_runtime.lua,
+            &graph,
+            target,
+            params.clone(),
+            &lua_runtime.node_definitions,
+            RunOptions {
+                memory_budget,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .finish()
+    };
+
+    let cube_bytes = run(cube, None).unwrap().stats.mesh_bytes;
+    assert!(cube_bytes > 0);
+    let result = run(subd, None).unwrap();
+    let subd_bytes = match &result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh.estimated_bytes(),
+        _ => panic!("Expected a mesh"),
+    };
+    assert_eq!(result.stats.mesh_bytes, cube_bytes + subd_bytes);
+
+    // Two iterations make the cube 16 times larger
+    let err = run(subd, Some(cube_bytes * 10)).unwrap_err();
+    let exceeded = err.downcast_ref::<MemoryBudgetExceeded>().unwrap();
+    assert_eq!(exceeded.node_id, subd);
+    assert_eq!(exceeded.predicted_bytes, cube_bytes * 16);
+    assert_eq!(exceeded.budget, cube_bytes * 10);
+    run(subd, Some(cube_bytes * 16)).unwrap();
+    // Nodes without a cost hint always run
+    run(cube, Some(1)).unwrap();
+}
@@ -6,6 +6,8 @@
 
 use mlua::{FromLua, Lua, ToLua};
 
+use crate::graph::BlackjackValue;
+use crate::lua_engine::lua_stdlib::LVec3;
 use crate::prelude::*;
 
 #[derive(Debug, Copy, Clone)]
@@ -193,9 +195,164 @@ mod tr_gizmo {
     }
 }
 
+/// A handle that can be dragged in the viewport, bound to a parameter of the
+/// node that returned it. Handles are described by Lua nodes that define
+/// `gizmos` as a function, as tables with a `kind` and a `param` key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandleGizmo {
+    /// A point that can be moved freely, bound to a vector parameter.
+    Point { param: String, position: Vec3 },
+    /// An arrow starting at `origin` whose tip can be rotated around it, bound
+    /// to a direction parameter. Dragging the tip keeps the direction's length.
+    Arrow {
+        param: String,
+        origin: Vec3,
+        direction: Vec3,
+    },
+    /// A handle at `origin + axis * value` that slides along `axis`, bound to
+    /// a scalar parameter.
+    Slider {
+        param: String,
+        origin: Vec3,
+        axis: Vec3,
+        value: f32,
+    },
+}
+
+impl HandleGizmo {
+    /// The name of the parameter this handle is bound to.
+    pub fn param(&self) -> &str {
+        match self {
+            HandleGizmo::Point { param, .. }
+            | HandleGizmo::Arrow { param, .. }
+            | HandleGizmo::Slider { param, .. } => param,
+        }
+    }
+
+    /// The value of the bound parameter, according to this handle.
+    pub fn bound_value(&self) -> BlackjackValue {
+        match self {
+            HandleGizmo::Point { position, .. } => BlackjackValue::Vector(*position),
+            HandleGizmo::Arrow { direction, .. } => BlackjackValue::Vector(*direction),
+            HandleGizmo::Slider { value, .. } => BlackjackValue::Scalar(*value),
+        }
+    }
+
+    /// The point in world space that is dragged to move this handle.
+    pub fn handle_position(&self) -> Vec3 {
+        match self {
+            HandleGizmo::Point { position, .. } => *position,
+            HandleGizmo::Arrow {
+                origin, direction, ..
+            } => *origin + *direction,
+            HandleGizmo::Slider {
+                origin,
+                axis,
+                value,
+                ..
+            } => *origin + axis.normalize_or_zero() * *value,
+        }
+    }
+
+    /// Moves this handle so that it's as close as it can be to `target`.
+    /// Arrows and sliders project the target onto the space they can move in.
+    pub fn drag_to(&mut self, target: Vec3) {
+        match self {
+            HandleGizmo::Point { position, .. } => *position = target,
+            HandleGizmo::Arrow {
+                origin, direction, ..
+            } => {
+                let new_direction = (target - *origin).normalize_or_zero();
+                if new_direction != Vec3::ZERO {
+                    *direction = new_direction * direction.length();
+                }
+            }
+            HandleGizmo::Slider {
+                origin,
+                axis,
+                value,
+                ..
+            } => *value = (target - *origin).dot(axis.normalize_or_zero()),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for HandleGizmo {
+    fn from_lua(lua_value: mlua::Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        let table = mlua::Table::from_lua(lua_value, lua)?;
+        let vector = |key: &str| -> mlua::Result<Vec3> {
+            Ok(table
+                .get::<_, LVec3>(key)
+                .map_err(|err| {
+                    mlua::Error::RuntimeError(format!("Handle gizmos need a vector '{key}'. {err}"))
+                })?
+                .0)
+        };
+        let param: String = table.get("param")?;
+        let kind: String = table.get("kind")?;
+        Ok(match kind.as_str() {
+            "point" => HandleGizmo::Point {
+                param,
+                position: vector("position")?,
+            },
+            "arrow" => HandleGizmo::Arrow {
+                param,
+                origin: vector("origin")?,
+                direction: vector("direction")?,
+            },
+            "slider" => HandleGizmo::Slider {
+                param,
+                origin: vector("origin")?,
+                axis: vector("axis")?,
+                value: table.get("value")?,
+            },
+            _ => {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "Invalid handle gizmo kind '{kind}'. Expected 'point', 'arrow' or 'slider'."
+                )))
+            }
+        })
+    }
+}
+
+impl<'lua> ToLua<'lua> for HandleGizmo {
+    fn to_lua(self, lua: &'lua Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("param", self.param())?;
+        match self {
+            HandleGizmo::Point { position, .. } => {
+                table.set("kind", "point")?;
+                table.set("position", LVec3(position))?;
+            }
+            HandleGizmo::Arrow {
+                origin, direction, ..
+            } => {
+                table.set("kind", "arrow")?;
+                table.set("origin", LVec3(origin))?;
+                table.set("direction", LVec3(direction))?;
+            }
+            HandleGizmo::Slider {
+                origin,
+                axis,
+                value,
+                ..
+            } => {
+                table.set("kind", "slider")?;
+                table.set("origin", LVec3(origin))?;
+                table.set("axis", LVec3(axis))?;
+                table.set("value", value)?;
+            }
+        }
+        Ok(mlua::Value::Table(table))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum BlackjackGizmo {
     Transform(TransformGizmo),
+    /// The handles returned by a node's `gizmos` function, as a list of
+    /// descriptor tables.
+    Handles(Vec<HandleGizmo>),
     // This special value is sometimes returned by the UI to indicate a gizmo
     // wasn't initialized. No gizmo should be rendered for this value.
     None,
@@ -215,6 +372,10 @@ impl<'lua> FromLua<'lua> for BlackjackGizmo {
         if let mlua::Value::UserData(x) = lua_value {
             // NOTE: Add more cases here:
             gizmo_type!(x, TransformGizmo, Transform);
+        } else if let mlua::Value::Table(handles) = lua_value {
+            return Ok(BlackjackGizmo::Handles(
+                handles.sequence_values().collect::<mlua::Result<_>>()?,
+            ));
         }
         mlua::Result::Err(mlua::Error::FromLuaConversionError {
             from: "Value",
@@ -230,6 +391,7 @@ impl<'lua> ToLua<'lua> for BlackjackGizmo {
     fn to_lua(self, lua: &'lua Lua) -> mlua::Result<mlua::Value<'lua>> {
         match self {
             BlackjackGizmo::Transform(t) => t.to_lua(lua),
+            BlackjackGizmo::Handles(handles) => handles.to_lua(lua),
             // The special gizmo value "None" is encoded as nil. Lua functions
             // know that the nil value represents an uninitialized gizmo.
             BlackjackGizmo::None => Ok(mlua::Value::Nil),
@@ -7,10 +7,22 @@
 use std::cell::{Ref, RefCell};
 use std::collections::BTreeMap;
 use std::ops::Deref;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 use crate::prelude::*;
-use crate::{lua_engine::lua_stdlib::LVec3, mesh::halfedge::selection::SelectionExpression};
+use crate::{
+    lua_engine::lua_stdlib::LVec3,
+    mesh::{
+        halfedge::{
+            edit_ops::{VertexDeltas, VertexWeights},
+            selection::{SelectionExpression, SelectionKind},
+        },
+        material::MaterialTable,
+    },
+    progress::{current_sink, report_warning, Warning},
+    units::{LengthUnit, ParamUnit},
+};
 use anyhow::{anyhow, Result};
 use mlua::{FromLua, Table, ToLua};
 use slotmap::SlotMap;
@@ -18,6 +30,24 @@ use slotmap::SlotMap;
 /// The core `bjk` file format
 pub mod serialization;
 
+/// Semantic differences between two serialized graphs
+pub mod diff;
+
+/// Upgrades nodes saved with older versions of their node definitions
+pub mod node_migration;
+
+/// Named sets of parameter values that can be applied to nodes
+pub mod node_presets;
+
+/// Storing file path parameters relative to the graph file
+pub mod project_paths;
+
+/// Parameters computed from formulas over other parameters
+pub mod expressions;
+
+/// The documentation of nodes, shown in the editor
+pub mod node_docs;
+
 pub struct LuaExpression(pub String);
 
 /// A node has inputs (dependencies) that need to be met. A dependency can be
@@ -39,50 +69,147 @@ pub enum DependencyKind {
 pub enum DataType {
     Vector,
     Scalar,
+    Int,
+    Bool,
     Selection,
     Mesh,
+    /// A mesh made of polylines: it has no faces, and no vertex has more
+    /// than two edges.
+    Curve,
+    /// A mesh made of isolated vertices, without edges or faces.
+    Points,
     String,
     HeightMap,
+    Scene,
+    /// Offsets for individual vertices. Only used as a parameter of nodes
+    /// that record edits made in the viewport.
+    VertexDeltas,
+    /// Values for individual vertices, painted in the viewport. Only used as
+    /// a parameter of nodes that record painting.
+    VertexWeights,
 }
 
 impl DataType {
     /// Returns whether this datatype can be rendered into a final artifact
     pub fn can_be_enabled(&self) -> bool {
         match self {
-            DataType::Mesh | DataType::HeightMap => true,
-            DataType::Vector | DataType::Scalar | DataType::Selection | DataType::String => false,
+            DataType::Mesh
+            | DataType::Curve
+            | DataType::Points
+            | DataType::HeightMap
+            | DataType::Scene => true,
+            DataType::Vector
+            | DataType::Scalar
+            | DataType::Int
+            | DataType::Bool
+            | DataType::Selection
+            | DataType::String
+            | DataType::VertexDeltas
+            | DataType::VertexWeights => false,
         }
     }
 
     /// Returns whether the given value is valid for this data type
     pub fn is_valid_value(&self, value: &BlackjackValue) -> bool {
         match self {
-            DataType::Vector => matches!(value, BlackjackValue::Vector(_)),
-            DataType::Scalar => matches!(value, BlackjackValue::Scalar(_)),
+            DataType::Vector => matches!(
+                value,
+                BlackjackValue::Vector(_) | BlackjackValue::Expression(_)
+            ),
+            DataType::Scalar => matches!(
+                value,
+                BlackjackValue::Scalar(_) | BlackjackValue::Expression(_)
+            ),
+            DataType::Int => matches!(
+                value,
+                BlackjackValue::Int(_) | BlackjackValue::Expression(_)
+            ),
+            DataType::Bool => matches!(value, BlackjackValue::Bool(_)),
             DataType::Selection => matches!(value, BlackjackValue::Selection(_, _)),
             DataType::String => matches!(value, BlackjackValue::String(_)),
             DataType::Mesh => matches!(value, BlackjackValue::None),
+            DataType::Curve => matches!(value, BlackjackValue::None),
+            DataType::Points => matches!(value, BlackjackValue::None),
             DataType::HeightMap => matches!(value, BlackjackValue::None),
+            DataType::Scene => matches!(value, BlackjackValue::None),
+            DataType::VertexDeltas => matches!(value, BlackjackValue::VertexDeltas(_)),
+            DataType::VertexWeights => matches!(value, BlackjackValue::VertexWeights(_)),
         }
     }
+
+    /// Returns whether values of this type are meshes. Curves and points are
+    /// meshes too, with a more restricted structure.
+    pub fn is_mesh(&self) -> bool {
+        matches!(self, DataType::Mesh | DataType::Curve | DataType::Points)
+    }
+
+    /// Returns whether an output of type `from` can be connected to an input
+    /// of this type. Besides the same type, integers can go into scalars and
+    /// curves or points can go into meshes. The other way around needs a
+    /// conversion node, which checks the structure of the mesh.
+    pub fn accepts(&self, from: DataType) -> bool {
+        *self == from
+            || (*self == DataType::Scalar && from == DataType::Int)
+            || (*self == DataType::Mesh && from.is_mesh())
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BlackjackValue {
     Vector(glam::Vec3),
     Scalar(f32),
+    Int(i32),
+    Bool(bool),
     String(String),
+    /// The text of a selection, along with its parsed expression, or `None`
+    /// when the text doesn't parse. The expression is parsed when the text
+    /// changes, so graph runs don't parse it again.
     Selection(String, Option<SelectionExpression>),
+    VertexDeltas(VertexDeltas),
+    VertexWeights(VertexWeights),
+    /// A number or vector computed from other parameters, see
+    /// [`expressions`]. Replaced by its result before running the node.
+    Expression(String),
     None,
 }
 
+impl BlackjackValue {
+    /// Converts this value to one of the given data type. Integers become
+    /// scalars, and scalars become integers by dropping their fractional
+    /// part, which reports a warning when there was one. Other values can
+    /// only be converted to their own type.
+    pub fn convert_to(self, data_type: DataType) -> Result<Self> {
+        match (self, data_type) {
+            (BlackjackValue::Int(i), DataType::Scalar) => Ok(BlackjackValue::Scalar(i as f32)),
+            (BlackjackValue::Scalar(x), DataType::Int) => {
+                if x.fract() != 0.0 {
+                    report_warning(
+                        current_sink().as_deref(),
+                        Warning::new(format!("Truncating {x} to an integer")),
+                    );
+                }
+                Ok(BlackjackValue::Int(x as i32))
+            }
+            (value, data_type) if data_type.is_valid_value(&value) => Ok(value),
+            (value, data_type) => bail!("Cannot convert {value:?} to {data_type:?}"),
+        }
+    }
+}
+
 impl<'lua> ToLua<'lua> for BlackjackValue {
     fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
         match self {
             BlackjackValue::Vector(v) => Ok(v.cast_to_lua(lua)),
             BlackjackValue::Scalar(s) => Ok(s.cast_to_lua(lua)),
+            BlackjackValue::Int(i) => i.to_lua(lua),
+            BlackjackValue::Bool(b) => b.to_lua(lua),
             BlackjackValue::String(s) => s.to_lua(lua),
             BlackjackValue::Selection(_, sel) => sel.to_lua(lua),
+            BlackjackValue::VertexDeltas(deltas) => deltas.to_lua(lua),
+            BlackjackValue::VertexWeights(weights) => weights.to_lua(lua),
+            BlackjackValue::Expression(expr) => Err(mlua::Error::RuntimeError(format!(
+                "The expression '{expr}' was not evaluated"
+            ))),
             BlackjackValue::None => Ok(mlua::Value::Nil),
         }
     }
@@ -93,6 +220,7 @@ impl<'lua> FromLua<'lua> for BlackjackValue {
         let type_name = lua_value.type_name();
         match lua_value {
             mlua::Value::Nil => return Ok(BlackjackValue::None),
+            mlua::Value::Boolean(b) => return Ok(BlackjackValue::Bool(b)),
             mlua::Value::Integer(i) => return Ok(BlackjackValue::Scalar(i as f32)),
             mlua::Value::Number(n) => return Ok(BlackjackValue::Scalar(n as f32)),
             mlua::Value::Vector(x, y, z) => {
@@ -104,6 +232,14 @@ impl<'lua> FromLua<'lua> for BlackjackValue {
                     let sel = u.borrow::<SelectionExpression>()?.clone();
                     return Ok(BlackjackValue::Selection(sel.unparse(), Some(sel)));
                 }
+                if u.is::<VertexDeltas>() {
+                    let deltas = u.borrow::<VertexDeltas>()?.clone();
+                    return Ok(BlackjackValue::VertexDeltas(deltas));
+                }
+                if u.is::<VertexWeights>() {
+                    let weights = u.borrow::<VertexWeights>()?.clone();
+                    return Ok(BlackjackValue::VertexWeights(weights));
+                }
             }
             _ => {}
         }
@@ -122,6 +258,18 @@ pub struct InputParameter {
     pub name: String,
     pub data_type: DataType,
     pub kind: DependencyKind,
+    /// For selection parameters picked in the viewport, the mesh they were
+    /// picked against. Used to keep the selection on the same elements when
+    /// upstream ops change the ids.
+    pub picked_from: Option<PickedSelection>,
+}
+
+/// Records that a selection was picked on the output mesh of `node`, and
+/// which kind of elements it refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickedSelection {
+    pub node: BjkNodeId,
+    pub kind: SelectionKind,
 }
 
 /// An output parameter. Outputs are pieces of data produced by a node, which
@@ -141,6 +289,13 @@ pub struct BjkNode {
     pub return_value: Option<String>,
     pub inputs: Vec<InputParameter>,
     pub outputs: Vec<Output>,
+    /// The version of the node definition this node was created with. See
+    /// [`NodeDefinition::version`].
+    pub version: u32,
+    /// Identifies the node across runs and file saves, unlike its
+    /// [`BjkNodeId`], which changes every time the graph is rebuilt. Random
+    /// ops derive their seed from it, see [`crate::random::node_seed`].
+    pub seed: u64,
 }
 
 slotmap::new_key_type! { pub struct BjkNodeId; }
@@ -159,6 +314,19 @@ pub struct BjkGraph {
     pub nodes: SlotMap<BjkNodeId, BjkNode>,
     /// When the graph is run, this is the node that will be executed by default.
     pub default_node: Option<BjkNodeId>,
+    /// The graph-level seed. Each node gets a different seed derived from
+    /// this one, see [`crate::random::node_seed`].
+    pub seed: u64,
+    /// The materials faces can refer to, see `Ops.set_material`. They are
+    /// written by the exporters.
+    pub materials: MaterialTable,
+    /// The unit lengths are shown in. Lengths are stored in meters, so this
+    /// only changes how they are displayed and how some exporters scale them.
+    pub units: LengthUnit,
+    /// The `bjk` file this graph was loaded from or saved to, if any. File
+    /// parameters starting with `//` are relative to its folder, see
+    /// [`project_paths`].
+    pub file_path: Option<PathBuf>,
 }
 
 /// Represents a fragment of a `BjkGraph`. Snippets can be taken out of a graph
@@ -206,6 +374,19 @@ pub enum InputValueConfig {
         soft_min: Option<f32>,
         soft_max: Option<f32>,
         num_decimals: Option<u32>,
+        /// What the value measures. Lengths are shown in the unit of the
+        /// project, but always stored in meters.
+        unit: ParamUnit,
+    },
+    Int {
+        default: i32,
+        min: Option<i32>,
+        max: Option<i32>,
+        soft_min: Option<i32>,
+        soft_max: Option<i32>,
+    },
+    Bool {
+        default: bool,
     },
     Selection {
         default_selection: SelectionExpression,
@@ -242,12 +423,19 @@ impl DataType {
         match self {
             DataType::Vector => BlackjackValue::Vector(Vec3::default()),
             DataType::Scalar => BlackjackValue::Scalar(0.0),
+            DataType::Int => BlackjackValue::Int(0),
+            DataType::Bool => BlackjackValue::Bool(false),
             DataType::Selection => {
                 BlackjackValue::Selection("".into(), Some(SelectionExpression::None))
             }
             DataType::String => BlackjackValue::String("".into()),
             DataType::Mesh => BlackjackValue::None,
+            DataType::Curve => BlackjackValue::None,
+            DataType::Points => BlackjackValue::None,
             DataType::HeightMap => BlackjackValue::None,
+            DataType::Scene => BlackjackValue::None,
+            DataType::VertexDeltas => BlackjackValue::VertexDeltas(VertexDeltas::default()),
+            DataType::VertexWeights => BlackjackValue::VertexWeights(VertexWeights::default()),
         }
     }
 }
@@ -263,6 +451,8 @@ impl InputDefinition {
             (DataType::Scalar, InputValueConfig::Scalar { default, .. }) => {
                 BlackjackValue::Scalar(*default)
             }
+            (DataType::Int, InputValueConfig::Int { default, .. }) => BlackjackValue::Int(*default),
+            (DataType::Bool, InputValueConfig::Bool { default }) => BlackjackValue::Bool(*default),
             (DataType::Selection, InputValueConfig::Selection { default_selection }) => {
                 BlackjackValue::Selection(
                     default_selection.unparse(),
@@ -270,6 +460,8 @@ impl InputDefinition {
                 )
             }
             (DataType::Mesh, InputValueConfig::None) => BlackjackValue::None,
+            (DataType::Curve, InputValueConfig::None) => BlackjackValue::None,
+            (DataType::Points, InputValueConfig::None) => BlackjackValue::None,
             (
                 DataType::String,
                 InputValueConfig::Enum {
@@ -297,6 +489,7 @@ impl InputDefinition {
             }
             (DataType::String, InputValueConfig::LuaString {}) => default_string(),
             (DataType::HeightMap, InputValueConfig::None) => BlackjackValue::None,
+            (DataType::Scene, InputValueConfig::None) => BlackjackValue::None,
 
             // Fallback: When config is not valud, return some valid value
             (data_type, _) => data_type.default_value(),
@@ -332,11 +525,113 @@ pub struct NodeDefinition {
     pub executable: bool,
     /// This node has an available interactive gizmo.
     pub has_gizmo: bool,
+    /// The version of this definition. Node libraries increase it when they
+    /// change the inputs of a node, so that graphs saved with older versions
+    /// can be migrated. See [`node_migration`].
+    pub version: u32,
+    /// The channels this node reads from its input meshes and adds to its
+    /// output meshes. When `None`, the node doesn't declare them, and the
+    /// channels of its output meshes can't be known without running it.
+    pub channels: Option<ChannelDeclarations>,
+    /// How much larger this node makes its input meshes, if it declares it.
+    pub cost: Option<CostHint>,
+    /// Hidden nodes are not listed in the node finder. They are only created
+    /// by the editor itself, like the reroutes inserted on wires.
+    pub hidden: bool,
+    /// When the op of a node with soft errors fails, the graph keeps running:
+    /// Its outputs get the default value of their type, and the error is
+    /// reported for that node alone.
+    pub soft_errors: bool,
+    /// Deform-only nodes just move the vertices of their first mesh input,
+    /// and write the channels they declare as produced. They get a copy of
+    /// that mesh sharing its connectivity and its other channels, which they
+    /// modify in place, instead of having to clone it.
+    pub deform_only: bool,
+    /// Cache nodes store their input mesh in a file next to the graph once
+    /// frozen, and the nodes upstream of them stop running until they are
+    /// refreshed. See [`crate::graph_interpreter::frozen_cache`].
+    pub cache: bool,
+    /// The description of the node, and of its inputs.
+    pub docs: node_docs::NodeDocs,
+    /// Where this definition was loaded from.
+    pub provenance: NodeProvenance,
+}
+
+/// Where a node definition was loaded from. Graphs only need to bring along
+/// the sources of the nodes that are not bundled with blackjack, see
+/// [`crate::lua_engine::graph_libraries`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NodeProvenance {
+    /// One of the node libraries that ship with blackjack.
+    Bundled,
+    /// A node library the user added to the `run` folder, at `file`.
+    User { file: String },
+    /// A library embedded in the file of the open graph.
+    Embedded { library: String },
+    /// A library in the `node_libraries` folder next to the open graph.
+    GraphFolder { file: String },
+}
+
+impl std::fmt::Display for NodeProvenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeProvenance::Bundled => write!(f, "bundled"),
+            NodeProvenance::User { file } => write!(f, "user library {file}"),
+            NodeProvenance::Embedded { library } => write!(f, "embedded library {library}"),
+            NodeProvenance::GraphFolder { file } => write!(f, "graph library {file}"),
+        }
+    }
+}
+
+/// Where the name of a channel declared by a node definition comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelName {
+    /// A fixed name, like `uv`.
+    Literal(String),
+    /// The value of one of the string parameters of the node. An empty value
+    /// means the node doesn't use the channel.
+    Param(String),
+}
+
+/// A channel read or written by a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelDeclaration {
+    /// The element the channel is keyed by. `None` when it can be any.
+    pub key: Option<ChannelKeyType>,
+    pub name: ChannelName,
+}
+
+/// The channels a node definition declares, so graphs can be checked
+/// without running them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelDeclarations {
+    /// The channels the node reads from its input meshes.
+    pub requires: Vec<ChannelDeclaration>,
+    /// The channels the node adds to the channels of its input meshes. Nodes
+    /// without mesh inputs produce only these, and the vertex positions.
+    pub produces: Vec<ChannelDeclaration>,
+}
+
+/// The factor by which a node multiplies the size of its input meshes: The
+/// `base` raised to the value of the `exponent` parameter, or just the `base`
+/// when there's no exponent. Subdivision, for instance, declares a base of 4
+/// and its number of iterations as the exponent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostHint {
+    pub base: f32,
+    pub exponent: Option<String>,
 }
 
 #[derive(Default)]
 pub struct NodeDefinitionsInner(BTreeMap<String, NodeDefinition>);
 
+impl NodeDefinitionsInner {
+    /// Iterates over the definitions, to modify them.
+    pub fn definitions_mut(&mut self) -> impl Iterator<Item = &mut NodeDefinition> {
+        self.0.values_mut()
+    }
+}
+
 /// A collection of node definitions. This struct is the Rust counterpart to the
 /// node library in Lua.
 ///
@@ -362,6 +657,19 @@ impl NodeDefinitions {
     pub fn node_names(&self) -> Vec<String> {
         self.inner.borrow().0.keys().cloned().collect()
     }
+    /// Returns the names of the nodes listed in the node finder. When
+    /// inserting a node on a wire of type `wire_type`, only the nodes that can
+    /// be inserted there are listed.
+    pub fn finder_node_names(&self, wire_type: Option<DataType>) -> Vec<String> {
+        self.inner
+            .borrow()
+            .0
+            .values()
+            .filter(|def| !def.hidden)
+            .filter(|def| wire_type.map_or(true, |ty| def.can_be_inserted_on(ty)))
+            .map(|def| def.op_name.clone())
+            .collect()
+    }
     pub fn node_def(&self, op_name: &str) -> Option<impl Deref<Target = NodeDefinition> + '_> {
         let guard = self.inner.borrow();
         if guard.0.contains_key(op_name) {
@@ -373,6 +681,14 @@ impl NodeDefinitions {
     pub fn update(&self, new_data: NodeDefinitionsInner) {
         *self.inner.borrow_mut() = new_data;
     }
+    /// Adds a definition, replacing any other with the same op name.
+    pub fn insert(&self, def: NodeDefinition) {
+        self.inner.borrow_mut().0.insert(def.op_name.clone(), def);
+    }
+    /// Removes the definition for `op_name`, if any.
+    pub fn remove(&self, op_name: &str) -> Option<NodeDefinition> {
+        self.inner.borrow_mut().0.remove(op_name)
+    }
 }
 
 /// Given a string representing an input definition type (taken from a Lua
@@ -381,9 +697,16 @@ fn data_type_from_str(s: &str) -> Result<DataType> {
     match s {
         "vec3" => Ok(DataType::Vector),
         "scalar" => Ok(DataType::Scalar),
+        "int" => Ok(DataType::Int),
+        "bool" => Ok(DataType::Bool),
         "selection" => Ok(DataType::Selection),
         "mesh" => Ok(DataType::Mesh),
+        "curve" => Ok(DataType::Curve),
+        "points" => Ok(DataType::Points),
         "heightmap" => Ok(DataType::HeightMap),
+        "scene" => Ok(DataType::Scene),
+        "vertex_deltas" => Ok(DataType::VertexDeltas),
+        "vertex_weights" => Ok(DataType::VertexWeights),
         "enum" => Ok(DataType::String),
         "file" => Ok(DataType::String),
         "string" => Ok(DataType::String),
@@ -408,12 +731,34 @@ impl InputDefinition {
                 soft_min: table.get::<_, Option<f32>>("soft_min")?,
                 soft_max: table.get::<_, Option<f32>>("soft_max")?,
                 num_decimals: table.get::<_, Option<u32>>("num_decimals")?,
+                unit: match table.get::<_, Option<String>>("unit")? {
+                    Some(unit) => unit.parse()?,
+                    None => ParamUnit::None,
+                },
+            },
+            DataType::Int => InputValueConfig::Int {
+                default: table.get::<_, Option<i32>>("default")?.unwrap_or(0),
+                min: table.get::<_, Option<i32>>("min")?,
+                max: table.get::<_, Option<i32>>("max")?,
+                soft_min: table.get::<_, Option<i32>>("soft_min")?,
+                soft_max: table.get::<_, Option<i32>>("soft_max")?,
+            },
+            DataType::Bool => InputValueConfig::Bool {
+                default: table.get::<_, Option<bool>>("default")?.unwrap_or(false),
             },
             DataType::Selection => InputValueConfig::Selection {
-                default_selection: SelectionExpression::None,
+                default_selection: match table.get::<_, Option<String>>("default")? {
+                    Some(default) => SelectionExpression::parse(&default)?,
+                    None => SelectionExpression::None,
+                },
             },
             DataType::Mesh => InputValueConfig::None,
+            DataType::Curve => InputValueConfig::None,
+            DataType::Points => InputValueConfig::None,
             DataType::HeightMap => InputValueConfig::None,
+            DataType::Scene => InputValueConfig::None,
+            DataType::VertexDeltas => InputValueConfig::None,
+            DataType::VertexWeights => InputValueConfig::None,
             DataType::String if type_str == "enum" => InputValueConfig::Enum {
                 values: table
                     .get::<_, Table>("values")?
@@ -449,6 +794,69 @@ impl InputDefinition {
     }
 }
 
+impl ChannelDeclaration {
+    /// Parses from a Lua table with an optional `key`, one of `"vertex"`,
+    /// `"face"` or `"halfedge"`, and either a `name` or a `param`.
+    pub fn from_lua(table: Table) -> Result<Self> {
+        let key = match table.get::<_, Option<String>>("key")?.as_deref() {
+            None => None,
+            Some("vertex") => Some(ChannelKeyType::VertexId),
+            Some("face") => Some(ChannelKeyType::FaceId),
+            Some("halfedge") => Some(ChannelKeyType::HalfEdgeId),
+            Some(other) => bail!("Invalid channel key in node definition {other:?}"),
+        };
+        let name = match (
+            table.get::<_, Option<String>>("name")?,
+            table.get::<_, Option<String>>("param")?,
+        ) {
+            (Some(name), None) => ChannelName::Literal(name),
+            (None, Some(param)) => ChannelName::Param(param),
+            _ => bail!("Channel declarations need either a 'name' or a 'param'"),
+        };
+        Ok(Self { key, name })
+    }
+}
+
+impl ChannelDeclarations {
+    /// Parses from a Lua table with optional `requires` and `produces` lists
+    /// of [`ChannelDeclaration`]s.
+    pub fn from_lua(table: Table) -> Result<Self> {
+        let list = |field: &str| -> Result<Vec<ChannelDeclaration>> {
+            match table.get::<_, Option<Table>>(field)? {
+                Some(list) => list
+                    .sequence_values()
+                    .map(|x| ChannelDeclaration::from_lua(x?))
+                    .collect(),
+                None => Ok(vec![]),
+            }
+        };
+        Ok(Self {
+            requires: list("requires")?,
+            produces: list("produces")?,
+        })
+    }
+}
+
+impl CostHint {
+    /// Parses from a Lua table with a `base` and an optional `exponent`.
+    pub fn from_lua(table: Table) -> Result<Self> {
+        Ok(Self {
+            base: table.get("base")?,
+            exponent: table.get("exponent")?,
+        })
+    }
+
+    /// Returns the factor for the given value of the `exponent` parameter.
+    /// Returns `None` when the hint has an exponent but its value is unknown.
+    pub fn factor(&self, exponent: Option<f32>) -> Option<f32> {
+        match (&self.exponent, exponent) {
+            (None, _) => Some(self.base),
+            (Some(_), Some(exponent)) => Some(self.base.powf(exponent)),
+            (Some(_), None) => None,
+        }
+    }
+}
+
 impl OutputDefinition {
     /// Parses from a Lua table describing this [`OutputDefinition`]
     pub fn from_lua(table: Table) -> Result<Self> {
@@ -460,6 +868,22 @@ impl OutputDefinition {
 }
 
 impl NodeDefinition {
+    /// Returns whether this node can be inserted on a wire carrying values of
+    /// `data_type`: It needs an input that accepts them, and an output of the
+    /// same type.
+    pub fn can_be_inserted_on(&self, data_type: DataType) -> bool {
+        self.inputs.iter().any(|i| i.data_type.accepts(data_type))
+            && self.outputs.iter().any(|o| data_type.accepts(o.data_type))
+    }
+
+    /// Returns whether the input called `name` is a file path, which may be
+    /// relative to the folder of the graph.
+    pub fn is_file_path(&self, name: &str) -> bool {
+        self.inputs.iter().any(|input| {
+            input.name == name && matches!(input.config, InputValueConfig::FilePath { .. })
+        })
+    }
+
     /// Parses from a Lua table describing this [`NodeDefinition`]
     pub fn from_lua(name: String, table: Table) -> Result<Self> {
         let inputs = table
@@ -474,6 +898,9 @@ impl NodeDefinition {
             .map(|x| OutputDefinition::from_lua(x?))
             .collect::<Result<Vec<_>>>()?;
 
+        let docs = node_docs::NodeDocs::from_lua(&table, &inputs)
+            .with_context(|| format!("Invalid documentation for node {name}"))?;
+
         Ok(NodeDefinition {
             op_name: name,
             inputs,
@@ -482,6 +909,26 @@ impl NodeDefinition {
             returns: table.get::<_, Option<String>>("returns")?,
             executable: table.get::<_, Option<bool>>("executable")?.unwrap_or(false),
             has_gizmo: table.get::<_, mlua::Value>("gizmos")? != mlua::Value::Nil,
+            version: table.get::<_, Option<u32>>("version")?.unwrap_or(1),
+            channels: table
+                .get::<_, Option<Table>>("channels")?
+                .map(ChannelDeclarations::from_lua)
+                .transpose()?,
+            cost: table
+                .get::<_, Option<Table>>("cost")?
+                .map(CostHint::from_lua)
+                .transpose()?,
+            hidden: table.get::<_, Option<bool>>("hidden")?.unwrap_or(false),
+            soft_errors: table
+                .get::<_, Option<bool>>("soft_errors")?
+                .unwrap_or(false),
+            deform_only: table
+                .get::<_, Option<bool>>("deform_only")?
+                .unwrap_or(false),
+            cache: table.get::<_, Option<bool>>("cache")?.unwrap_or(false),
+            docs,
+            // Set by the caller, which knows what file the table comes from
+            provenance: NodeProvenance::Bundled,
         })
     }
 
@@ -505,18 +952,34 @@ impl BjkGraph {
         Self {
             nodes: Default::default(),
             default_node: None,
+            seed: 0,
+            materials: MaterialTable::default(),
+            units: LengthUnit::default(),
+            file_path: None,
         }
     }
-    /// Adds a new empty node to the graph
+    /// Adds a new empty node to the graph, with a seed no other node has
     pub fn add_node(&mut self, op_name: impl ToString, return_value: Option<String>) -> BjkNodeId {
+        let seed = self.unused_node_seed();
         self.nodes.insert(BjkNode {
             op_name: op_name.to_string(),
             return_value,
             inputs: vec![],
             outputs: vec![],
+            version: 1,
+            seed,
         })
     }
 
+    /// Returns a [`BjkNode::seed`] that none of the nodes in the graph use.
+    pub fn unused_node_seed(&self) -> u64 {
+        self.nodes
+            .values()
+            .map(|node| node.seed.saturating_add(1))
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Registers a new input for `node_id`
     pub fn add_input(
         &mut self,
@@ -534,11 +997,34 @@ impl BjkGraph {
                 name,
                 data_type,
                 kind: DependencyKind::External { promoted },
+                picked_from: None,
             });
         }
         Ok(())
     }
 
+    /// Sets where the selection for the `name` input of `node_id` was picked.
+    pub fn set_picked_from(
+        &mut self,
+        node_id: BjkNodeId,
+        name: &str,
+        picked_from: Option<PickedSelection>,
+    ) -> Result<()> {
+        let input = self.nodes[node_id]
+            .inputs
+            .iter_mut()
+            .find(|input| input.name == name)
+            .ok_or_else(|| anyhow!("Input parameter {name} does not exist for node {node_id:?}"))?;
+        if input.data_type != DataType::Selection {
+            bail!(
+                "Only selection parameters can be picked, but {name} is {:?}",
+                input.data_type
+            );
+        }
+        input.picked_from = picked_from;
+        Ok(())
+    }
+
     /// Registers a new input for `node_id`
     pub fn add_output(
         &mut self,
@@ -579,7 +1065,7 @@ impl BjkGraph {
             .iter_mut()
             .find(|input| input.name == dst_param)
         {
-            if input.data_type != src_data_type {
+            if !input.data_type.accepts(src_data_type) {
                 bail!(
                     "Incompatible types. Input is {:?}, but its corresponding output is {:?}",
                     input.data_type,
@@ -597,3 +1083,143 @@ impl BjkGraph {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lua_engine::LuaRuntime;
+
+    #[test]
+    fn test_value_conversions() {
+        let convert = |value: BlackjackValue, data_type| value.convert_to(data_type);
+        assert_eq!(
+            convert(BlackjackValue::Int(3), DataType::Scalar).unwrap(),
+            BlackjackValue::Scalar(3.0)
+        );
+        assert_eq!(
+            convert(BlackjackValue::Scalar(3.0), DataType::Int).unwrap(),
+            BlackjackValue::Int(3)
+        );
+        // Fractional parts are dropped
+        assert_eq!(
+            convert(BlackjackValue::Scalar(2.7), DataType::Int).unwrap(),
+            BlackjackValue::Int(2)
+        );
+        assert_eq!(
+            convert(BlackjackValue::Scalar(-2.7), DataType::Int).unwrap(),
+            BlackjackValue::Int(-2)
+        );
+        assert_eq!(
+            convert(BlackjackValue::Bool(true), DataType::Bool).unwrap(),
+            BlackjackValue::Bool(true)
+        );
+        assert!(convert(BlackjackValue::Bool(true), DataType::Scalar).is_err());
+        assert!(convert(BlackjackValue::Scalar(1.0), DataType::Bool).is_err());
+        assert!(convert(BlackjackValue::String("1".into()), DataType::Int).is_err());
+
+        // Only integers go into scalars implicitly
+        assert!(DataType::Scalar.accepts(DataType::Int));
+        assert!(DataType::Int.accepts(DataType::Int));
+        assert!(!DataType::Int.accepts(DataType::Scalar));
+        assert!(!DataType::Bool.accepts(DataType::Int));
+
+        // Curves and points are meshes, but a mesh needs a conversion node
+        // to become one of them
+        assert!(DataType::Mesh.accepts(DataType::Curve));
+        assert!(DataType::Mesh.accepts(DataType::Points));
+        assert!(DataType::Curve.accepts(DataType::Curve));
+        assert!(!DataType::Curve.accepts(DataType::Mesh));
+        assert!(!DataType::Points.accepts(DataType::Mesh));
+        assert!(!DataType::Curve.accepts(DataType::Points));
+        assert!(!DataType::Points.accepts(DataType::Curve));
+        assert!(!DataType::HeightMap.accepts(DataType::Curve));
+    }
+
+    #[test]
+    fn test_lua_boundary() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let lua = &runtime.lua;
+        let table = lua.create_table().unwrap();
+        table.set("i", BlackjackValue::Int(7)).unwrap();
+        table.set("b", BlackjackValue::Bool(true)).unwrap();
+        let (i, is_integral): (f64, bool) = lua
+            .load("return ..., ... == math.floor(...)")
+            .call(table.get::<_, mlua::Value>("i").unwrap())
+            .unwrap();
+        assert_eq!((i, is_integral), (7.0, true));
+        // Booleans come back as they are, but Lua numbers need a type to
+        // become integers again.
+        assert_eq!(
+            table.get::<_, BlackjackValue>("b").unwrap(),
+            BlackjackValue::Bool(true)
+        );
+        let i = table.get::<_, BlackjackValue>("i").unwrap();
+        assert_eq!(i.convert_to(DataType::Int).unwrap(), BlackjackValue::Int(7));
+
+        let input_def = |code: &str| {
+            InputDefinition::from_lua(lua.load(code).eval::<Table>().unwrap()).unwrap()
+        };
+        let segments = input_def("return require('params').int('segments', 8, { min = 1 })");
+        assert_eq!(segments.data_type, DataType::Int);
+        assert_eq!(segments.default_value(), BlackjackValue::Int(8));
+        assert!(matches!(
+            segments.config,
+            InputValueConfig::Int {
+                min: Some(1),
+                max: None,
+                ..
+            }
+        ));
+        let cap = input_def("return require('params').bool('cap', true)");
+        assert_eq!(cap.data_type, DataType::Bool);
+        assert_eq!(cap.default_value(), BlackjackValue::Bool(true));
+        let cap = input_def("return require('params').bool('cap')");
+        assert_eq!(cap.default_value(), BlackjackValue::Bool(false));
+    }
+
+    #[test]
+    fn test_cost_hint_factor() {
+        let subdivide = CostHint {
+            base: 4.0,
+            exponent: Some("iterations".into()),
+        };
+        assert_eq!(subdivide.factor(Some(3.0)), Some(64.0));
+        assert_eq!(subdivide.factor(None), None);
+        let fixed = CostHint {
+            base: 2.0,
+            exponent: None,
+        };
+        assert_eq!(fixed.factor(Some(3.0)), Some(2.0));
+    }
+
+    #[test]
+    fn test_finder_node_names() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &runtime.node_definitions;
+        let all = defs.finder_node_names(None);
+        assert!(all.iter().any(|name| name == "MakeBox"));
+        assert!(defs.node_def("RerouteMesh").unwrap().hidden);
+        assert!(!all.iter().any(|name| name.starts_with("Reroute")));
+
+        // Only nodes with a mesh input and a mesh output go on mesh wires
+        let mesh_nodes = defs.finder_node_names(Some(DataType::Mesh));
+        assert!(mesh_nodes.iter().any(|name| name == "BevelEdges"));
+        assert!(!mesh_nodes.iter().any(|name| name == "MakeBox"));
+        assert!(!mesh_nodes.iter().any(|name| name == "ExportObj"));
+        for name in &mesh_nodes {
+            assert!(defs
+                .node_def(name)
+                .unwrap()
+                .can_be_inserted_on(DataType::Mesh));
+        }
+
+        // Integers go into scalar inputs, but scalar outputs can't go back
+        // into the integer input at the end of the wire.
+        let scalar_nodes = defs.finder_node_names(Some(DataType::Scalar));
+        assert!(scalar_nodes.iter().any(|name| name == "MakeScalar"));
+        assert!(!defs
+            .finder_node_names(Some(DataType::Int))
+            .iter()
+            .any(|name| name == "MakeScalar"));
+    }
+}
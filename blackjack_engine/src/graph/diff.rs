@@ -0,0 +1,722 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compares two serialized graphs, and reports their differences in terms of
+//! nodes, parameters and connections instead of lines of RON.
+//!
+//! Nodes don't have a stable identity in the `bjk` format, only their index
+//! in the node list. Nodes are first matched by index when both graphs have a
+//! node with the same op at that index. The remaining nodes are then matched
+//! by op name, pairing the nodes that are closest to each other in the graph
+//! editor. A node matched at a different index is reported as renamed.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::units::LengthUnit;
+
+use super::serialization::{
+    SerializedBjkGraph, SerializedBlackjackValue, SerializedDependencyKind, SerializedInput,
+};
+
+/// Positions closer than this are considered equal.
+const LAYOUT_EPSILON: f32 = 1e-3;
+
+/// A node of one of the compared graphs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeRef {
+    /// The index of the node in its graph
+    pub idx: usize,
+    pub op_name: String,
+}
+
+/// A node that exists in both graphs, but at a different index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenamedNode {
+    pub op_name: String,
+    pub before_idx: usize,
+    pub after_idx: usize,
+}
+
+/// A change in the value of a node parameter. The value is `None` when the
+/// parameter doesn't have one in that graph.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamChange {
+    /// The node, in the second graph
+    pub node: NodeRef,
+    pub param_name: String,
+    pub before: Option<SerializedBlackjackValue>,
+    pub after: Option<SerializedBlackjackValue>,
+}
+
+/// One end of a connection: An output of some node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Endpoint {
+    pub node: NodeRef,
+    pub output: String,
+}
+
+/// A change in what an input of a node is connected to. The endpoints are
+/// `None` when the input is not connected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionChange {
+    /// The node, in the second graph
+    pub node: NodeRef,
+    pub input: String,
+    /// The connected output, in the first graph
+    pub before: Option<Endpoint>,
+    /// The connected output, in the second graph
+    pub after: Option<Endpoint>,
+}
+
+/// A node that was moved in the graph editor.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayoutChange {
+    /// The node, in the second graph
+    pub node: NodeRef,
+    pub before: glam::Vec2,
+    pub after: glam::Vec2,
+}
+
+/// The differences between two graphs, as returned by [`graph_diff`]. Nodes
+/// in `removed_nodes` refer to the first graph, and all other nodes refer to
+/// the second graph, unless noted otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GraphDiff {
+    pub added_nodes: Vec<NodeRef>,
+    pub removed_nodes: Vec<NodeRef>,
+    pub renamed_nodes: Vec<RenamedNode>,
+    pub param_changes: Vec<ParamChange>,
+    pub connection_changes: Vec<ConnectionChange>,
+    /// Position changes are kept apart from the rest, because they don't
+    /// change the result of the graph.
+    pub layout_changes: Vec<LayoutChange>,
+    /// The output node before and after, when it changed.
+    pub default_node_change: Option<(Option<NodeRef>, Option<NodeRef>)>,
+    /// The random seed before and after, when it changed.
+    pub seed_change: Option<(u64, u64)>,
+    /// Whether the materials of the graph changed.
+    pub materials_changed: bool,
+    /// The length unit before and after, when it changed.
+    pub units_change: Option<(LengthUnit, LengthUnit)>,
+}
+
+impl GraphDiff {
+    /// Returns whether the graphs are the same, except maybe for the layout.
+    pub fn is_semantically_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.renamed_nodes.is_empty()
+            && self.param_changes.is_empty()
+            && self.connection_changes.is_empty()
+            && self.default_node_change.is_none()
+            && self.seed_change.is_none()
+            && !self.materials_changed
+            && self.units_change.is_none()
+    }
+
+    /// Returns whether the graphs are the same.
+    pub fn is_empty(&self) -> bool {
+        self.is_semantically_empty() && self.layout_changes.is_empty()
+    }
+
+    /// Drops the position-only changes from this diff.
+    pub fn without_layout(self) -> Self {
+        Self {
+            layout_changes: vec![],
+            ..self
+        }
+    }
+
+    /// Formats this diff as a human-readable report.
+    pub fn to_report(&self) -> String {
+        if self.is_empty() {
+            return "No differences\n".into();
+        }
+        let mut out = String::new();
+        // Writing to a string can't fail
+        macro_rules! put {
+            ($($arg:tt)*) => { writeln!(out, $($arg)*).unwrap() };
+        }
+
+        if !self.added_nodes.is_empty() {
+            put!("Added nodes:");
+            for node in &self.added_nodes {
+                put!("  + {}", fmt_node(node));
+            }
+        }
+        if !self.removed_nodes.is_empty() {
+            put!("Removed nodes:");
+            for node in &self.removed_nodes {
+                put!("  - {}", fmt_node(node));
+            }
+        }
+        if !self.renamed_nodes.is_empty() {
+            put!("Renamed nodes:");
+            for node in &self.renamed_nodes {
+                put!(
+                    "  ~ {} #{} -> #{}",
+                    node.op_name,
+                    node.before_idx,
+                    node.after_idx
+                );
+            }
+        }
+        if !self.param_changes.is_empty() {
+            put!("Parameter changes:");
+            for change in &self.param_changes {
+                put!(
+                    "  {}.{}: {} -> {}",
+                    fmt_node(&change.node),
+                    change.param_name,
+                    fmt_value(change.before.as_ref()),
+                    fmt_value(change.after.as_ref()),
+                );
+            }
+        }
+        if !self.connection_changes.is_empty() {
+            put!("Connection changes:");
+            for change in &self.connection_changes {
+                put!(
+                    "  {}.{}: {} -> {}",
+                    fmt_node(&change.node),
+                    change.input,
+                    fmt_endpoint(change.before.as_ref()),
+                    fmt_endpoint(change.after.as_ref()),
+                );
+            }
+        }
+        if let Some((before, after)) = &self.default_node_change {
+            let fmt_opt = |node: &Option<NodeRef>| match node {
+                Some(node) => fmt_node(node),
+                None => "(none)".into(),
+            };
+            put!("Output node: {} -> {}", fmt_opt(before), fmt_opt(after));
+        }
+        if let Some((before, after)) = self.seed_change {
+            put!("Seed: {before} -> {after}");
+        }
+        if self.materials_changed {
+            put!("Materials changed");
+        }
+        if let Some((before, after)) = self.units_change {
+            put!("Units: {before} -> {after}");
+        }
+        if !self.layout_changes.is_empty() {
+            put!("Layout changes:");
+            for change in &self.layout_changes {
+                put!(
+                    "  {} moved from ({}, {}) to ({}, {})",
+                    fmt_node(&change.node),
+                    change.before.x,
+                    change.before.y,
+                    change.after.x,
+                    change.after.y,
+                );
+            }
+        }
+        out
+    }
+}
+
+fn fmt_node(node: &NodeRef) -> String {
+    format!("#{} {}", node.idx, node.op_name)
+}
+
+fn fmt_value(value: Option<&SerializedBlackjackValue>) -> String {
+    match value {
+        Some(SerializedBlackjackValue::Vector(v)) => format!("({}, {}, {})", v.x, v.y, v.z),
+        Some(SerializedBlackjackValue::Scalar(s)) => s.to_string(),
+        Some(SerializedBlackjackValue::Int(i)) => i.to_string(),
+        Some(SerializedBlackjackValue::Bool(b)) => b.to_string(),
+        Some(SerializedBlackjackValue::String(s)) => format!("{s:?}"),
+        Some(SerializedBlackjackValue::Selection(s)) => format!("selection {s:?}"),
+        Some(SerializedBlackjackValue::VertexDeltas(d)) => format!("{} vertex offsets", d.len()),
+        Some(SerializedBlackjackValue::VertexWeights(w)) => format!("{} painted vertices", w.len()),
+        Some(SerializedBlackjackValue::Expression(e)) => format!("= {e}"),
+        None => "(none)".into(),
+    }
+}
+
+fn fmt_endpoint(endpoint: Option<&Endpoint>) -> String {
+    match endpoint {
+        Some(endpoint) => format!("{}.{}", fmt_node(&endpoint.node), endpoint.output),
+        None => "(not connected)".into(),
+    }
+}
+
+fn node_ref(graph: &SerializedBjkGraph, idx: usize) -> NodeRef {
+    NodeRef {
+        idx,
+        op_name: graph
+            .nodes
+            .get(idx)
+            .map(|node| node.op_name.clone())
+            .unwrap_or_else(|| "<missing>".into()),
+    }
+}
+
+fn node_position(graph: &SerializedBjkGraph, idx: usize) -> Option<glam::Vec2> {
+    graph
+        .ui_data
+        .as_ref()
+        .and_then(|ui_data| ui_data.node_positions.get(idx).copied())
+}
+
+/// Returns, for each node of `a`, the index of the matching node in `b`.
+fn match_nodes(a: &SerializedBjkGraph, b: &SerializedBjkGraph) -> Vec<Option<usize>> {
+    let mut a_to_b = vec![None; a.nodes.len()];
+    let mut b_matched = vec![false; b.nodes.len()];
+
+    // Nodes with the same op at the same index are the same node
+    for (idx, (node_a, node_b)) in a.nodes.iter().zip(&b.nodes).enumerate() {
+        if node_a.op_name == node_b.op_name {
+            a_to_b[idx] = Some(idx);
+            b_matched[idx] = true;
+        }
+    }
+
+    // The rest are matched by op name, closest pairs first. The distance
+    // between indices is used when the graphs have no positions.
+    let mut candidates = vec![];
+    for (i, node_a) in a.nodes.iter().enumerate() {
+        if a_to_b[i].is_some() {
+            continue;
+        }
+        for (j, node_b) in b.nodes.iter().enumerate() {
+            if b_matched[j] || node_a.op_name != node_b.op_name {
+                continue;
+            }
+            let distance = match (node_position(a, i), node_position(b, j)) {
+                (Some(pos_a), Some(pos_b)) => pos_a.distance(pos_b),
+                _ => (i as f32 - j as f32).abs(),
+            };
+            candidates.push((distance, i, j));
+        }
+    }
+    candidates.sort_by(|x, y| x.0.total_cmp(&y.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+    for (_, i, j) in candidates {
+        if a_to_b[i].is_none() && !b_matched[j] {
+            a_to_b[i] = Some(j);
+            b_matched[j] = true;
+        }
+    }
+    a_to_b
+}
+
+/// Returns the node index and output that the input called `name` is
+/// connected to, if any.
+fn find_connection<'a>(inputs: &'a [SerializedInput], name: &str) -> Option<(usize, &'a str)> {
+    let input = inputs.iter().find(|input| input.name == name)?;
+    match &input.kind {
+        SerializedDependencyKind::Conection {
+            node_idx,
+            param_name,
+        } => Some((*node_idx, param_name.as_str())),
+        SerializedDependencyKind::External { .. } => None,
+    }
+}
+
+fn param_values(graph: &SerializedBjkGraph) -> HashMap<(usize, &str), &SerializedBlackjackValue> {
+    graph
+        .external_parameters
+        .iter()
+        .flat_map(|params| &params.param_values)
+        .map(|(loc, value)| ((loc.node_idx, loc.param_name.as_str()), value))
+        .collect()
+}
+
+/// Computes the differences between graphs `a` and `b`, going from `a` to
+/// `b`.
+pub fn graph_diff(a: &SerializedBjkGraph, b: &SerializedBjkGraph) -> GraphDiff {
+    let a_to_b = match_nodes(a, b);
+    let mut b_to_a = vec![None; b.nodes.len()];
+    for (i, j) in a_to_b.iter().enumerate() {
+        if let Some(j) = j {
+            b_to_a[*j] = Some(i);
+        }
+    }
+    let matched = a_to_b
+        .iter()
+        .enumerate()
+        .filter_map(|(i, j)| Some((i, (*j)?)))
+        .collect::<Vec<_>>();
+
+    let mut diff = GraphDiff {
+        added_nodes: (0..b.nodes.len())
+            .filter(|j| b_to_a[*j].is_none())
+            .map(|j| node_ref(b, j))
+            .collect(),
+        removed_nodes: (0..a.nodes.len())
+            .filter(|i| a_to_b[*i].is_none())
+            .map(|i| node_ref(a, i))
+            .collect(),
+        renamed_nodes: matched
+            .iter()
+            .filter(|(i, j)| i != j)
+            .map(|&(i, j)| RenamedNode {
+                op_name: b.nodes[j].op_name.clone(),
+                before_idx: i,
+                after_idx: j,
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let (params_a, params_b) = (param_values(a), param_values(b));
+    for &(i, j) in &matched {
+        let (node_a, node_b) = (&a.nodes[i], &b.nodes[j]);
+
+        // Parameters, in the order of the inputs of the node
+        let mut names = node_b
+            .inputs
+            .iter()
+            .map(|input| input.name.as_str())
+            .collect::<Vec<_>>();
+        let mut seen = names.iter().copied().collect::<BTreeSet<_>>();
+        let extra = params_a
+            .keys()
+            .filter(|(idx, _)| *idx == i)
+            .chain(params_b.keys().filter(|(idx, _)| *idx == j))
+            .map(|(_, name)| *name)
+            .collect::<BTreeSet<_>>();
+        for name in extra {
+            if seen.insert(name) {
+                names.push(name);
+            }
+        }
+        for name in &names {
+            let before = params_a.get(&(i, *name)).copied();
+            let after = params_b.get(&(j, *name)).copied();
+            if before != after {
+                diff.param_changes.push(ParamChange {
+                    node: node_ref(b, j),
+                    param_name: name.to_string(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                });
+            }
+        }
+
+        // Connections, in the order of the inputs of the node
+        let mut inputs = node_b.inputs.iter().collect::<Vec<_>>();
+        inputs.extend(
+            node_a
+                .inputs
+                .iter()
+                .filter(|input| !node_b.inputs.iter().any(|other| other.name == input.name)),
+        );
+        for input in inputs {
+            let before = find_connection(&node_a.inputs, &input.name);
+            let after = find_connection(&node_b.inputs, &input.name);
+            let same = match (before, after) {
+                (Some((src_a, out_a)), Some((src_b, out_b))) => {
+                    a_to_b.get(src_a).copied().flatten() == Some(src_b) && out_a == out_b
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            if !same {
+                let endpoint = |graph, (idx, output): (usize, &str)| Endpoint {
+                    node: node_ref(graph, idx),
+                    output: output.into(),
+                };
+                diff.connection_changes.push(ConnectionChange {
+                    node: node_ref(b, j),
+                    input: input.name.clone(),
+                    before: before.map(|x| endpoint(a, x)),
+                    after: after.map(|x| endpoint(b, x)),
+                });
+            }
+        }
+
+        if let (Some(before), Some(after)) = (node_position(a, i), node_position(b, j)) {
+            if before.distance(after) > LAYOUT_EPSILON {
+                diff.layout_changes.push(LayoutChange {
+                    node: node_ref(b, j),
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    let default_a = a.default_node.filter(|i| *i < a.nodes.len());
+    let default_b = b.default_node.filter(|j| *j < b.nodes.len());
+    if default_a.and_then(|i| a_to_b[i]) != default_b || default_a.is_some() != default_b.is_some()
+    {
+        diff.default_node_change = Some((
+            default_a.map(|i| node_ref(a, i)),
+            default_b.map(|j| node_ref(b, j)),
+        ));
+    }
+    if a.seed != b.seed {
+        diff.seed_change = Some((a.seed, b.seed));
+    }
+    diff.materials_changed = a.materials != b.materials;
+    if a.units != b.units {
+        diff.units_change = Some((a.units, b.units));
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::serialization::{
+        SerializedBjkNode, SerializedExternalParameters, SerializedOutput, SerializedParamLocation,
+        SerializedUiData,
+    };
+
+    fn node(op_name: &str, connections: &[(&str, usize)]) -> SerializedBjkNode {
+        SerializedBjkNode {
+            op_name: op_name.into(),
+            return_value: Some("out_mesh".into()),
+            inputs: connections
+                .iter()
+                .map(|(name, src)| SerializedInput {
+                    name: name.to_string(),
+                    data_type: "BJK_MESH".into(),
+                    kind: SerializedDependencyKind::Conection {
+                        node_idx: *src,
+                        param_name: "out_mesh".into(),
+                    },
+                    picked_from: None,
+                })
+                .chain(std::iter::once(SerializedInput {
+                    name: "size".into(),
+                    data_type: "BJK_SCALAR".into(),
+                    kind: SerializedDependencyKind::External { promoted: None },
+                    picked_from: None,
+                }))
+                .collect(),
+            outputs: vec![SerializedOutput {
+                name: "out_mesh".into(),
+                data_type: "BJK_MESH".into(),
+            }],
+            version: 1,
+            seed: None,
+        }
+    }
+
+    /// A graph with the given nodes, a position for each node and a `size`
+    /// parameter on each node.
+    fn graph(
+        nodes: Vec<SerializedBjkNode>,
+        positions: &[(f32, f32)],
+        sizes: &[f32],
+    ) -> SerializedBjkGraph {
+        SerializedBjkGraph {
+            external_parameters: Some(SerializedExternalParameters {
+                param_values: sizes
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, size)| {
+                        (
+                            SerializedParamLocation {
+                                node_idx: idx,
+                                param_name: "size".into(),
+                            },
+                            SerializedBlackjackValue::Scalar(*size),
+                        )
+                    })
+                    .collect(),
+            }),
+            default_node: Some(nodes.len() - 1),
+            ui_data: Some(SerializedUiData {
+                node_positions: positions
+                    .iter()
+                    .map(|(x, y)| glam::Vec2::new(*x, *y))
+                    .collect(),
+                node_order: (0..nodes.len()).collect(),
+                pan: glam::Vec2::ZERO,
+                zoom: 1.0,
+                locked_gizmo_nodes: vec![],
+                viewport_lighting: None,
+            }),
+            nodes,
+            seed: 0,
+            materials: Default::default(),
+            node_libraries: vec![],
+            thumbnail_png: None,
+            units: Default::default(),
+            file_path: None,
+        }
+    }
+
+    /// A box, that gets beveled and then subdivided.
+    fn base() -> SerializedBjkGraph {
+        graph(
+            vec![
+                node("MakeBox", &[]),
+                node("BevelEdges", &[("in_mesh", 0)]),
+                node("Subdivide", &[("in_mesh", 1)]),
+            ],
+            &[(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)],
+            &[1.0, 0.1, 2.0],
+        )
+    }
+
+    #[test]
+    fn test_identical_graphs() {
+        assert!(graph_diff(&base(), &base()).is_empty());
+        assert_eq!(graph_diff(&base(), &base()).to_report(), "No differences\n");
+    }
+
+    #[test]
+    fn test_identical_files() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../all_nodes_test.bjk");
+        let a = SerializedBjkGraph::load_from_file(path).unwrap();
+        let b = SerializedBjkGraph::load_from_file(path).unwrap();
+        assert!(graph_diff(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_added_node() {
+        let mut b = base();
+        b.nodes.push(node("MakeBox", &[]));
+        b.ui_data
+            .as_mut()
+            .unwrap()
+            .node_positions
+            .push(glam::Vec2::new(0.0, 100.0));
+        let diff = graph_diff(&base(), &b);
+        assert_eq!(
+            diff.added_nodes,
+            vec![NodeRef {
+                idx: 3,
+                op_name: "MakeBox".into()
+            }]
+        );
+        assert!(diff.removed_nodes.is_empty() && diff.renamed_nodes.is_empty());
+        assert!(diff.default_node_change.is_some());
+        assert!(diff.to_report().contains("+ #3 MakeBox"));
+    }
+
+    #[test]
+    fn test_removed_node_and_rename() {
+        // The bevel is removed, so the subdivide node moves from index 2 to 1
+        let b = graph(
+            vec![node("MakeBox", &[]), node("Subdivide", &[("in_mesh", 0)])],
+            &[(0.0, 0.0), (200.0, 0.0)],
+            &[1.0, 2.0],
+        );
+        let diff = graph_diff(&base(), &b);
+        assert_eq!(diff.removed_nodes.len(), 1);
+        assert_eq!(diff.removed_nodes[0].op_name, "BevelEdges");
+        assert!(diff.added_nodes.is_empty());
+        assert_eq!(
+            diff.renamed_nodes,
+            vec![RenamedNode {
+                op_name: "Subdivide".into(),
+                before_idx: 2,
+                after_idx: 1
+            }]
+        );
+        // The subdivide now takes the box directly
+        assert_eq!(diff.connection_changes.len(), 1);
+        let change = &diff.connection_changes[0];
+        assert_eq!(change.before.as_ref().unwrap().node.op_name, "BevelEdges");
+        assert_eq!(change.after.as_ref().unwrap().node.op_name, "MakeBox");
+        // The size parameter moved along with the node, so it didn't change
+        assert!(diff.param_changes.is_empty());
+        assert!(diff.layout_changes.is_empty());
+        assert!(diff.default_node_change.is_none());
+    }
+
+    #[test]
+    fn test_rename_uses_positions() {
+        // Two boxes swap indices, but keep their positions
+        let a = graph(
+            vec![
+                node("MakeBox", &[]),
+                node("MakeBox", &[]),
+                node("Join", &[]),
+            ],
+            &[(0.0, 0.0), (0.0, 100.0), (100.0, 0.0)],
+            &[1.0, 2.0, 0.0],
+        );
+        let b = graph(
+            vec![
+                node("Join", &[]),
+                node("MakeBox", &[]),
+                node("MakeBox", &[]),
+            ],
+            &[(100.0, 0.0), (0.0, 100.0), (0.0, 0.0)],
+            &[0.0, 2.0, 1.0],
+        );
+        let diff = graph_diff(&a, &b);
+        assert!(diff.added_nodes.is_empty() && diff.removed_nodes.is_empty());
+        // The box at index 1 keeps its index, the other two swap
+        assert_eq!(diff.renamed_nodes.len(), 2);
+        assert!(diff.renamed_nodes.contains(&RenamedNode {
+            op_name: "MakeBox".into(),
+            before_idx: 0,
+            after_idx: 2
+        }));
+        assert!(diff.param_changes.is_empty());
+        assert!(diff.layout_changes.is_empty());
+    }
+
+    #[test]
+    fn test_param_change() {
+        let mut b = base();
+        let params = &mut b.external_parameters.as_mut().unwrap().param_values;
+        params.insert(
+            SerializedParamLocation {
+                node_idx: 1,
+                param_name: "size".into(),
+            },
+            SerializedBlackjackValue::Scalar(0.5),
+        );
+        let diff = graph_diff(&base(), &b);
+        assert_eq!(
+            diff.param_changes,
+            vec![ParamChange {
+                node: NodeRef {
+                    idx: 1,
+                    op_name: "BevelEdges".into()
+                },
+                param_name: "size".into(),
+                before: Some(SerializedBlackjackValue::Scalar(0.1)),
+                after: Some(SerializedBlackjackValue::Scalar(0.5)),
+            }]
+        );
+        assert!(diff.to_report().contains("#1 BevelEdges.size: 0.1 -> 0.5"));
+    }
+
+    #[test]
+    fn test_connection_change() {
+        let mut b = base();
+        b.nodes[2] = node("Subdivide", &[("in_mesh", 0)]);
+        let diff = graph_diff(&base(), &b);
+        assert!(diff.added_nodes.is_empty() && diff.removed_nodes.is_empty());
+        assert_eq!(diff.connection_changes.len(), 1);
+        assert!(diff
+            .to_report()
+            .contains("#2 Subdivide.in_mesh: #1 BevelEdges.out_mesh -> #0 MakeBox.out_mesh"));
+
+        b.nodes[2] = node("Subdivide", &[]);
+        let diff = graph_diff(&base(), &b);
+        assert_eq!(diff.connection_changes[0].after, None);
+    }
+
+    #[test]
+    fn test_layout_change() {
+        let mut b = base();
+        b.ui_data.as_mut().unwrap().node_positions[0] = glam::Vec2::new(-50.0, 0.0);
+        let diff = graph_diff(&base(), &b);
+        assert!(diff.is_semantically_empty());
+        assert_eq!(diff.layout_changes.len(), 1);
+        assert!(diff
+            .to_report()
+            .contains("#0 MakeBox moved from (0, 0) to (-50, 0)"));
+        assert!(diff.without_layout().is_empty());
+    }
+}
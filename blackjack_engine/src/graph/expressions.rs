@@ -0,0 +1,697 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Scalar and vector parameters can be bound to an expression instead of a
+//! literal value, like `base_size * 0.05`. Expressions can refer to the
+//! promoted parameters of the graph by their name, and to the special
+//! variables `t` and `frame`. They are evaluated every time the graph runs,
+//! right before the node that uses them.
+//!
+//! The language is small: numbers, the usual arithmetic operators including
+//! `%` and `^`, parentheses, component access like `offset.y`, and a few
+//! functions, see [`call_function`]. Vectors are built with `vec(x, y, z)`,
+//! and operations between a vector and a scalar apply to each component.
+
+use super::{BjkGraph, BlackjackValue, DataType, DependencyKind};
+use crate::graph_interpreter::{ExternalParameter, ExternalParameterValues};
+use crate::prelude::*;
+
+/// A binary operator in an [`Expr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Variable(String),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// Accessing the `x`, `y` or `z` component of a vector.
+    Component(Box<Expr>, String),
+}
+
+/// The result of evaluating an [`Expr`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExprValue {
+    Scalar(f32),
+    Vector(Vec3),
+}
+
+/// The values of the special variables `t`, the time in seconds, and `frame`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExpressionTime {
+    pub time: f32,
+    pub frame: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    Symbol(char),
+}
+
+/// Splits `src` into tokens, along with the position they start at.
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>> {
+    let chars = src.char_indices().collect_vec();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            // Numbers like `.5`, but not components like `offset.x`
+            || (c == '.' && chars.get(i + 1).map_or(false, |(_, c)| c.is_ascii_digit()))
+        {
+            while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                i += 1;
+            }
+            // Exponents, like `1e-3`
+            if i < chars.len() && (chars[i].1 == 'e' || chars[i].1 == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j].1 == '+' || chars[j].1 == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].1.is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].1.is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let end = chars.get(i).map_or(src.len(), |(pos, _)| *pos);
+            let text = &src[pos..end];
+            let number = text
+                .parse::<f32>()
+                .map_err(|_| anyhow!("Invalid number '{text}' at position {}", pos + 1))?;
+            tokens.push((pos, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            let end = chars.get(i).map_or(src.len(), |(pos, _)| *pos);
+            tokens.push((pos, Token::Ident(src[pos..end].to_owned())));
+        } else if "+-*/%^(),.".contains(c) {
+            tokens.push((pos, Token::Symbol(c)));
+            i += 1;
+        } else {
+            bail!("Unexpected '{c}' at position {}", pos + 1);
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    src_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, t)| t)
+    }
+
+    fn peek_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&Token::Symbol(symbol))
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.src_len, |(pos, _)| *pos)
+            + 1
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<()> {
+        if self.peek_symbol(symbol) {
+            self.next += 1;
+            Ok(())
+        } else {
+            bail!("Expected '{symbol}' at position {}", self.position())
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('+')) => BinOp::Add,
+                Some(Token::Symbol('-')) => BinOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.next += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('*')) => BinOp::Mul,
+                Some(Token::Symbol('/')) => BinOp::Div,
+                Some(Token::Symbol('%')) => BinOp::Rem,
+                _ => return Ok(lhs),
+            };
+            self.next += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek_symbol('-') {
+            self.next += 1;
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    /// Powers bind tighter than negation, and group to the right, so `-2^2`
+    /// is `-4` and `2^3^2` is `2^9`.
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.postfix()?;
+        if self.peek_symbol('^') {
+            self.next += 1;
+            let exponent = self.unary()?;
+            Ok(Expr::Binary(BinOp::Pow, Box::new(base), Box::new(exponent)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        while self.peek_symbol('.') {
+            self.next += 1;
+            match self.peek().cloned() {
+                Some(Token::Ident(component)) => {
+                    self.next += 1;
+                    expr = Expr::Component(Box::new(expr), component);
+                }
+                _ => bail!("Expected a component name at position {}", self.position()),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let position = self.position();
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.next += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::Ident(name)) => {
+                self.next += 1;
+                if !self.peek_symbol('(') {
+                    return Ok(Expr::Variable(name));
+                }
+                self.next += 1;
+                let mut args = vec![];
+                if !self.peek_symbol(')') {
+                    args.push(self.additive()?);
+                    while self.peek_symbol(',') {
+                        self.next += 1;
+                        args.push(self.additive()?);
+                    }
+                }
+                self.expect_symbol(')')?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Symbol('(')) => {
+                self.next += 1;
+                let expr = self.additive()?;
+                self.expect_symbol(')')?;
+                Ok(expr)
+            }
+            Some(Token::Symbol(c)) => bail!("Unexpected '{c}' at position {position}"),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+/// Parses an expression, like `base_size * 0.05`.
+pub fn parse(src: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        next: 0,
+        src_len: src.len(),
+    };
+    let expr = parser.additive()?;
+    if parser.next < parser.tokens.len() {
+        bail!("Unexpected input at position {}", parser.position());
+    }
+    Ok(expr)
+}
+
+impl ExprValue {
+    fn map(self, f: impl Fn(f32) -> f32) -> ExprValue {
+        match self {
+            ExprValue::Scalar(x) => ExprValue::Scalar(f(x)),
+            ExprValue::Vector(v) => ExprValue::Vector(Vec3::new(f(v.x), f(v.y), f(v.z))),
+        }
+    }
+
+    /// Applies `f` to each pair of components. Scalars are used for all three
+    /// components when the other value is a vector.
+    fn zip(self, other: ExprValue, f: impl Fn(f32, f32) -> f32) -> ExprValue {
+        match (self, other) {
+            (ExprValue::Scalar(a), ExprValue::Scalar(b)) => ExprValue::Scalar(f(a, b)),
+            (a, b) => {
+                let (a, b) = (a.to_vector(), b.to_vector());
+                ExprValue::Vector(Vec3::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z)))
+            }
+        }
+    }
+
+    fn to_vector(self) -> Vec3 {
+        match self {
+            ExprValue::Scalar(x) => Vec3::splat(x),
+            ExprValue::Vector(v) => v,
+        }
+    }
+
+    fn scalar(self, what: &str) -> Result<f32> {
+        match self {
+            ExprValue::Scalar(x) => Ok(x),
+            ExprValue::Vector(_) => bail!("{what} should be a number, not a vector"),
+        }
+    }
+}
+
+/// Calls the function `name` of the expression language.
+fn call_function(name: &str, args: &[ExprValue]) -> Result<ExprValue> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Function '{name}' takes {n} arguments, but got {}",
+                args.len()
+            ))
+        }
+    };
+    let unary = |f: fn(f32) -> f32| -> Result<ExprValue> {
+        arity(1)?;
+        Ok(args[0].map(f))
+    };
+    let binary = |f: fn(f32, f32) -> f32| -> Result<ExprValue> {
+        arity(2)?;
+        Ok(args[0].zip(args[1], f))
+    };
+    match name {
+        "sin" => unary(f32::sin),
+        "cos" => unary(f32::cos),
+        "tan" => unary(f32::tan),
+        "asin" => unary(f32::asin),
+        "acos" => unary(f32::acos),
+        "atan" => unary(f32::atan),
+        "sqrt" => unary(f32::sqrt),
+        "abs" => unary(f32::abs),
+        "floor" => unary(f32::floor),
+        "ceil" => unary(f32::ceil),
+        "round" => unary(f32::round),
+        "exp" => unary(f32::exp),
+        "ln" => unary(f32::ln),
+        "radians" => unary(f32::to_radians),
+        "degrees" => unary(f32::to_degrees),
+        "atan2" => binary(f32::atan2),
+        "min" => binary(f32::min),
+        "max" => binary(f32::max),
+        "pow" => binary(f32::powf),
+        "clamp" => {
+            arity(3)?;
+            Ok(args[0].zip(args[1], f32::max).zip(args[2], f32::min))
+        }
+        "lerp" => {
+            arity(3)?;
+            let t = args[2];
+            Ok(args[0]
+                .zip(t, |a, t| a * (1.0 - t))
+                .zip(args[1].zip(t, |b, t| b * t), |a, b| a + b))
+        }
+        "length" => {
+            arity(1)?;
+            Ok(ExprValue::Scalar(args[0].to_vector().length()))
+        }
+        "vec" => {
+            arity(3)?;
+            Ok(ExprValue::Vector(Vec3::new(
+                args[0].scalar("The x component")?,
+                args[1].scalar("The y component")?,
+                args[2].scalar("The z component")?,
+            )))
+        }
+        _ => bail!("Unknown function '{name}'"),
+    }
+}
+
+impl Expr {
+    /// Returns the names of the variables this expression refers to.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = vec![];
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Variable(name) => {
+                if !out.contains(&name.as_str()) {
+                    out.push(name)
+                }
+            }
+            Expr::Neg(e) | Expr::Component(e, _) => e.collect_variables(out),
+            Expr::Binary(_, a, b) => {
+                a.collect_variables(out);
+                b.collect_variables(out);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.collect_variables(out)),
+        }
+    }
+
+    /// Evaluates the expression. The values of variables are looked up with
+    /// `variable`, except for `pi`.
+    pub fn eval(&self, variable: &mut dyn FnMut(&str) -> Result<ExprValue>) -> Result<ExprValue> {
+        Ok(match self {
+            Expr::Number(n) => ExprValue::Scalar(*n),
+            Expr::Variable(name) if name == "pi" => ExprValue::Scalar(std::f32::consts::PI),
+            Expr::Variable(name) => variable(name)?,
+            Expr::Neg(e) => e.eval(variable)?.map(|x| -x),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(variable)?, b.eval(variable)?);
+                match op {
+                    BinOp::Add => a.zip(b, |a, b| a + b),
+                    BinOp::Sub => a.zip(b, |a, b| a - b),
+                    BinOp::Mul => a.zip(b, |a, b| a * b),
+                    BinOp::Div => a.zip(b, |a, b| a / b),
+                    BinOp::Rem => a.zip(b, f32::rem_euclid),
+                    BinOp::Pow => a.zip(b, f32::powf),
+                }
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(variable))
+                    .collect::<Result<Vec<_>>>()?;
+                call_function(name, &args)?
+            }
+            Expr::Component(e, component) => {
+                let v = match e.eval(variable)? {
+                    ExprValue::Vector(v) => v,
+                    ExprValue::Scalar(_) => bail!("Can't take '.{component}' of a number"),
+                };
+                match component.as_str() {
+                    "x" => ExprValue::Scalar(v.x),
+                    "y" => ExprValue::Scalar(v.y),
+                    "z" => ExprValue::Scalar(v.z),
+                    _ => bail!("Vectors have no component '{component}'"),
+                }
+            }
+        })
+    }
+}
+
+/// Converts the result of an expression to a value for a parameter of the
+/// given data type. Numbers can be used as vectors with the same value on all
+/// three components.
+pub fn expr_value_to_blackjack(value: ExprValue, data_type: DataType) -> Result<BlackjackValue> {
+    match (value, data_type) {
+        (ExprValue::Scalar(x), DataType::Scalar) => Ok(BlackjackValue::Scalar(x)),
+        (ExprValue::Scalar(x), DataType::Int) => Ok(BlackjackValue::Int(x.round() as i32)),
+        (value, DataType::Vector) => Ok(BlackjackValue::Vector(value.to_vector())),
+        (ExprValue::Vector(_), DataType::Scalar | DataType::Int) => {
+            bail!("The expression is a vector, but the parameter is a number")
+        }
+        (_, data_type) => bail!("Parameters of type {data_type:?} can't use expressions"),
+    }
+}
+
+/// Evaluates the expression parameters of a graph, with the values of the
+/// other parameters.
+pub struct ExpressionScope<'a> {
+    graph: &'a BjkGraph,
+    params: &'a ExternalParameterValues,
+    time: ExpressionTime,
+}
+
+impl<'a> ExpressionScope<'a> {
+    pub fn new(
+        graph: &'a BjkGraph,
+        params: &'a ExternalParameterValues,
+        time: ExpressionTime,
+    ) -> Self {
+        Self {
+            graph,
+            params,
+            time,
+        }
+    }
+
+    /// Returns the parameter promoted with the given `name`, and its type.
+    fn promoted_param(&self, name: &str) -> Option<(ExternalParameter, DataType)> {
+        self.graph.nodes.iter().find_map(|(node_id, node)| {
+            node.inputs.iter().find_map(|input| match &input.kind {
+                DependencyKind::External {
+                    promoted: Some(promoted),
+                } if promoted == name => Some((
+                    ExternalParameter::new(node_id, input.name.clone()),
+                    input.data_type,
+                )),
+                _ => None,
+            })
+        })
+    }
+
+    /// A readable name for `param`, for error messages.
+    fn param_name(&self, param: &ExternalParameter) -> String {
+        let promoted = self.graph.nodes.get(param.node_id).and_then(|node| {
+            node.inputs
+                .iter()
+                .find(|input| input.name == param.param_name)
+                .and_then(|input| match &input.kind {
+                    DependencyKind::External { promoted } => promoted.clone(),
+                    DependencyKind::Connection { .. } => None,
+                })
+        });
+        promoted.unwrap_or_else(|| {
+            let op_name = self
+                .graph
+                .nodes
+                .get(param.node_id)
+                .map_or("?", |node| node.op_name.as_str());
+            format!("{op_name}.{}", param.param_name)
+        })
+    }
+
+    /// Returns the value of `param`, as a value of `data_type`. Expressions
+    /// are evaluated, along with the expressions of any parameters they
+    /// refer to. Circular references are an error.
+    pub fn resolve(
+        &self,
+        param: &ExternalParameter,
+        data_type: DataType,
+    ) -> Result<BlackjackValue> {
+        match self.params.0.get(param) {
+            Some(BlackjackValue::Expression(_)) => {
+                let value = self.eval_param(param, &mut vec![])?;
+                expr_value_to_blackjack(value, data_type)
+            }
+            Some(value) => Ok(value.clone()),
+            None => bail!("The parameter {} has no value", self.param_name(param)),
+        }
+    }
+
+    /// Evaluates `param`, which is being referenced by the parameters in
+    /// `stack`.
+    fn eval_param(
+        &self,
+        param: &ExternalParameter,
+        stack: &mut Vec<ExternalParameter>,
+    ) -> Result<ExprValue> {
+        if let Some(idx) = stack.iter().position(|p| p == param) {
+            let chain = stack[idx..]
+                .iter()
+                .chain(std::iter::once(param))
+                .map(|p| self.param_name(p))
+                .join(" -> ");
+            bail!("Circular reference between expressions: {chain}");
+        }
+
+        match self.params.0.get(param) {
+            Some(BlackjackValue::Expression(src)) => {
+                let expr = parse(src).map_err(|err| {
+                    anyhow!("Invalid expression for {}: {err}", self.param_name(param))
+                })?;
+                stack.push(param.clone());
+                let value = expr.eval(&mut |name| match name {
+                    "t" => Ok(ExprValue::Scalar(self.time.time)),
+                    "frame" => Ok(ExprValue::Scalar(self.time.frame as f32)),
+                    _ => match self.promoted_param(name) {
+                        Some((referenced, _)) => self.eval_param(&referenced, stack),
+                        None => bail!("There is no parameter named '{name}'"),
+                    },
+                });
+                stack.pop();
+                value
+            }
+            Some(BlackjackValue::Scalar(x)) => Ok(ExprValue::Scalar(*x)),
+            Some(BlackjackValue::Int(i)) => Ok(ExprValue::Scalar(*i as f32)),
+            Some(BlackjackValue::Vector(v)) => Ok(ExprValue::Vector(*v)),
+            Some(_) => bail!("The parameter {} is not a number", self.param_name(param)),
+            None => bail!("The parameter {} has no value", self.param_name(param)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::BjkNodeId;
+
+    fn eval(src: &str) -> Result<ExprValue> {
+        parse(src)?.eval(&mut |name| match name {
+            "size" => Ok(ExprValue::Scalar(2.0)),
+            "offset" => Ok(ExprValue::Vector(Vec3::new(1.0, 2.0, 3.0))),
+            _ => bail!("Unknown variable '{name}'"),
+        })
+    }
+
+    #[test]
+    fn test_parse_and_eval() {
+        let scalar = |src| match eval(src).unwrap() {
+            ExprValue::Scalar(x) => x,
+            other => panic!("Expected a scalar, got {other:?}"),
+        };
+        assert_eq!(scalar("1 + 2 * 3"), 7.0);
+        assert_eq!(scalar("(1 + 2) * 3"), 9.0);
+        assert_eq!(scalar("size * 0.05"), 0.1);
+        assert_eq!(scalar("-2^2"), -4.0);
+        assert_eq!(scalar("2^3^2"), 512.0);
+        assert_eq!(scalar("-7 % 3"), 2.0);
+        assert_eq!(scalar("1.5e2 - 50"), 100.0);
+        assert_eq!(scalar("max(size, 3) + min(1, -1)"), 2.0);
+        assert_eq!(scalar("offset.y * size"), 4.0);
+        assert_eq!(scalar("clamp(5, 0, 1)"), 1.0);
+        assert_eq!(scalar("lerp(2, 4, 0.5)"), 3.0);
+
+        assert_eq!(
+            eval("offset * size + vec(0, 0, 1)").unwrap(),
+            ExprValue::Vector(Vec3::new(2.0, 4.0, 7.0))
+        );
+
+        assert_eq!(parse("a + b * a").unwrap().variables(), vec!["a", "b"]);
+        for invalid in ["", "1 +", "(1", "1 2", "size $ 2", "offset.", "f(1,"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+        assert!(eval("nope + 1").is_err());
+        assert!(eval("size.x").is_err());
+        assert!(eval("vec(1, 2)").is_err());
+        assert!(eval("frobnicate(1)").is_err());
+    }
+
+    /// A graph with one node, whose inputs are promoted with the same names
+    /// and have the given values.
+    fn graph_with_params(
+        values: &[(&str, BlackjackValue)],
+    ) -> (BjkGraph, BjkNodeId, ExternalParameterValues) {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("Test", None);
+        let mut params = ExternalParameterValues::default();
+        for (name, value) in values {
+            let data_type = match value {
+                BlackjackValue::Vector(_) => DataType::Vector,
+                _ => DataType::Scalar,
+            };
+            graph
+                .add_input(node, name, data_type, Some(name.to_string()))
+                .unwrap();
+            params.0.insert(
+                ExternalParameter::new(node, name.to_string()),
+                value.clone(),
+            );
+        }
+        (graph, node, params)
+    }
+
+    #[test]
+    fn test_evaluation_order() {
+        let expr = |src: &str| BlackjackValue::Expression(src.into());
+        // Expressions refer to parameters declared after them, which are
+        // expressions themselves
+        let (graph, node, params) = graph_with_params(&[
+            ("wall", expr("thickness * 2")),
+            ("thickness", expr("base_size * 0.05 + t")),
+            ("base_size", BlackjackValue::Scalar(10.0)),
+            ("offset", expr("vec(wall, 0, frame)")),
+        ]);
+        let time = ExpressionTime {
+            time: 0.5,
+            frame: 12,
+        };
+        let scope = ExpressionScope::new(&graph, &params, time);
+        let param = |name: &str| ExternalParameter::new(node, name.into());
+        assert_eq!(
+            scope.resolve(&param("wall"), DataType::Scalar).unwrap(),
+            BlackjackValue::Scalar(2.0)
+        );
+        assert_eq!(
+            scope.resolve(&param("offset"), DataType::Vector).unwrap(),
+            BlackjackValue::Vector(Vec3::new(2.0, 0.0, 12.0))
+        );
+        // Literals are returned as they are
+        assert_eq!(
+            scope
+                .resolve(&param("base_size"), DataType::Scalar)
+                .unwrap(),
+            BlackjackValue::Scalar(10.0)
+        );
+        // Vector results don't fit in numbers
+        assert!(scope.resolve(&param("offset"), DataType::Scalar).is_err());
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let expr = |src: &str| BlackjackValue::Expression(src.into());
+        let (graph, node, params) = graph_with_params(&[
+            ("a", expr("b + 1")),
+            ("b", expr("c * 2")),
+            ("c", expr("a")),
+            ("d", expr("d + 1")),
+            ("e", expr("a + 1")),
+        ]);
+        let scope = ExpressionScope::new(&graph, &params, ExpressionTime::default());
+        let error = |name: &str| {
+            scope
+                .resolve(&ExternalParameter::new(node, name.into()), DataType::Scalar)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("a"),
+            "Circular reference between expressions: a -> b -> c -> a"
+        );
+        assert_eq!(error("d"), "Circular reference between expressions: d -> d");
+        // Parameters that refer to a cycle report just the cycle
+        assert_eq!(
+            error("e"),
+            "Circular reference between expressions: a -> b -> c -> a"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The documentation of nodes, shown in the node finder and in the docs
+//! panel of the editor. Node definitions document themselves in Lua:
+//!
+//! - `description`: What the node does. The older `doc` field is read when
+//!   it's missing.
+//! - `input_docs`: A table from the name of an input to what it does.
+//! - `example`: The name of a small bundled graph that shows the node in use.
+//!
+//! Descriptions can mention the functions of the Lua API the node is built
+//! on between backticks, like `Ops.extrude`. The docs panel shows the
+//! documentation of those as well, which is the same that the
+//! `blackjack_lua_module` macro collects to generate the Lua API reference.
+
+use mlua::Table;
+use once_cell::sync::Lazy;
+
+use super::InputDefinition;
+use crate::lua_engine::lua_stdlib::LuaDocstringData;
+use crate::prelude::*;
+
+/// The documentation of a node definition.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeDocs {
+    pub description: Option<String>,
+    /// The documentation of the inputs, in the order of the inputs.
+    pub input_docs: Vec<(String, String)>,
+    /// The name of the bundled example graph for the node.
+    pub example: Option<String>,
+}
+
+impl NodeDocs {
+    /// Parses the documentation fields of the Lua table of a node definition.
+    /// The `inputs` are the ones of the node, which the input docs must refer
+    /// to.
+    pub fn from_lua(table: &Table, inputs: &[InputDefinition]) -> Result<Self> {
+        let description = match table.get::<_, Option<String>>("description")? {
+            Some(description) => Some(description),
+            None => table.get::<_, Option<String>>("doc")?,
+        }
+        .map(|text| normalize_doc_text(&text))
+        .filter(|text| !text.is_empty());
+
+        let mut documented = match table.get::<_, Option<Table>>("input_docs")? {
+            Some(docs) => docs
+                .pairs::<String, String>()
+                .collect::<mlua::Result<HashMap<_, _>>>()?,
+            None => HashMap::new(),
+        };
+        let input_docs = inputs
+            .iter()
+            .filter_map(|input| {
+                let doc = documented.remove(&input.name)?;
+                Some((input.name.clone(), normalize_doc_text(&doc)))
+            })
+            .collect();
+        if let Some(name) = documented.keys().sorted().next() {
+            bail!("The input docs mention an input called '{name}', which the node doesn't have");
+        }
+
+        Ok(Self {
+            description,
+            input_docs,
+            example: table.get::<_, Option<String>>("example")?,
+        })
+    }
+
+    /// Returns the documentation of the input called `name`, if any.
+    pub fn input_doc(&self, name: &str) -> Option<&str> {
+        self.input_docs
+            .iter()
+            .find(|(input, _)| input == name)
+            .map(|(_, doc)| doc.as_str())
+    }
+
+    /// Returns the first paragraph of the description, as a short summary of
+    /// the node for the node finder.
+    pub fn summary(&self) -> Option<&str> {
+        self.description
+            .as_deref()
+            .and_then(|description| description.split("\n\n").next())
+    }
+
+    /// Returns the documentation of the Lua API functions mentioned between
+    /// backticks in the description or the input docs, in the order they are
+    /// mentioned first.
+    pub fn referenced_api(&self) -> Vec<&'static ApiDoc> {
+        let texts = self
+            .description
+            .iter()
+            .chain(self.input_docs.iter().map(|(_, doc)| doc));
+        let mut referenced: Vec<&'static ApiDoc> = vec![];
+        for text in texts {
+            // Every odd piece is between backticks
+            for name in text.split('`').skip(1).step_by(2) {
+                if let Some(api) = api_doc(name.trim_end_matches("()")) {
+                    if !referenced.iter().any(|other| other.name == api.name) {
+                        referenced.push(api);
+                    }
+                }
+            }
+        }
+        referenced
+    }
+}
+
+/// Turns the text of a doc string written in a Lua long string into plain
+/// paragraphs: The indentation and line breaks of each paragraph are
+/// removed, and paragraphs are separated by an empty line.
+pub fn normalize_doc_text(text: &str) -> String {
+    let mut paragraphs: Vec<String> = vec![];
+    let mut current: Vec<&str> = vec![];
+    for line in text.lines().map(str::trim).chain([""]) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join(" "));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    paragraphs.join("\n\n")
+}
+
+/// The documentation of a function of the Lua API that is implemented in
+/// Rust.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiDoc {
+    /// The name of the function, with the table or class it's under, like
+    /// `Ops.extrude`.
+    pub name: String,
+    pub description: String,
+    /// The names and types of the parameters.
+    pub params: Vec<(String, String)>,
+}
+
+impl ApiDoc {
+    /// Parses one of the LuaDoc stubs generated for the functions under the
+    /// table or class called `under`. Returns `None` for anything that's not
+    /// a function, like constants.
+    pub fn parse(under: &str, stub: &str) -> Option<Self> {
+        let mut description = vec![];
+        let mut params = vec![];
+        let mut fn_name = None;
+        for line in stub.lines() {
+            if let Some(signature) = line.strip_prefix("function ") {
+                fn_name = signature.split('(').next().map(str::trim);
+                break;
+            }
+            let comment = line.trim_start_matches('-').trim();
+            if let Some(param) = comment.strip_prefix("@param ") {
+                let (name, typ) = param.split_once(' ').unwrap_or((param, ""));
+                if name != "self" {
+                    params.push((name.to_string(), simplify_rust_type(typ)));
+                }
+            } else {
+                description.push(comment);
+            }
+        }
+        Some(Self {
+            name: format!("{under}.{}", fn_name?),
+            description: normalize_doc_text(&description.join("\n")),
+            params,
+        })
+    }
+}
+
+/// Makes the type of a parameter of an API function, as written in Rust,
+/// easier to read: `& mut HalfEdgeMesh` becomes `HalfEdgeMesh`, and
+/// `Option < f32 >` becomes `Option<f32>`.
+fn simplify_rust_type(typ: &str) -> String {
+    typ.replace("& mut ", "").replace("& ", "").replace(' ', "")
+}
+
+/// The documentation of all the Lua API functions implemented in Rust.
+pub fn api_docs() -> &'static [ApiDoc] {
+    static API_DOCS: Lazy<Vec<ApiDoc>> = Lazy::new(|| {
+        inventory::iter::<LuaDocstringData>()
+            .flat_map(|data| data.data.iter())
+            .filter_map(|(_, under, stub)| ApiDoc::parse(under, stub))
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    });
+    &API_DOCS
+}
+
+/// Returns the documentation of the Lua API function called `name`, like
+/// `Ops.extrude`.
+pub fn api_doc(name: &str) -> Option<&'static ApiDoc> {
+    api_docs().iter().find(|api| api.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lua_engine::LuaRuntime;
+
+    #[test]
+    fn test_normalize_doc_text() {
+        let text = "
+            Extrudes the faces,
+            along their normals.
+
+            Another paragraph.
+        ";
+        assert_eq!(
+            normalize_doc_text(text),
+            "Extrudes the faces, along their normals.\n\nAnother paragraph."
+        );
+        assert_eq!(normalize_doc_text("  \n  "), "");
+    }
+
+    #[test]
+    fn test_parse_node_docs() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let lua = &runtime.lua;
+        let inputs = [
+            InputDefinition::from_lua(
+                lua.load("return require('params').mesh('mesh')")
+                    .eval()
+                    .unwrap(),
+            )
+            .unwrap(),
+            InputDefinition::from_lua(
+                lua.load("return require('params').scalar('amount', { default = 1 })")
+                    .eval()
+                    .unwrap(),
+            )
+            .unwrap(),
+        ];
+        let docs =
+            |code: &str| NodeDocs::from_lua(&lua.load(code).eval::<Table>().unwrap(), &inputs);
+
+        let parsed = docs(
+            "return {
+                description = [[
+                    Moves the faces with `Ops.extrude`.
+
+                    More details.
+                ]],
+                input_docs = { amount = 'How far', mesh = 'The mesh' },
+                example = 'extrude_faces',
+            }",
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.description.as_deref(),
+            Some("Moves the faces with `Ops.extrude`.\n\nMore details.")
+        );
+        assert_eq!(
+            parsed.summary(),
+            Some("Moves the faces with `Ops.extrude`.")
+        );
+        // Input docs follow the order of the inputs
+        assert_eq!(
+            parsed.input_docs,
+            vec![
+                ("mesh".to_string(), "The mesh".to_string()),
+                ("amount".to_string(), "How far".to_string())
+            ]
+        );
+        assert_eq!(parsed.input_doc("amount"), Some("How far"));
+        assert_eq!(parsed.example.as_deref(), Some("extrude_faces"));
+        let referenced = parsed.referenced_api();
+        assert_eq!(referenced.len(), 1);
+        assert_eq!(referenced[0].name, "Ops.extrude");
+        assert!(referenced[0]
+            .params
+            .iter()
+            .any(|(name, typ)| name == "mesh" && typ == "HalfEdgeMesh"));
+
+        // The older `doc` field is the description of nodes without one
+        let legacy = docs("return { doc = 'Old docs' }").unwrap();
+        assert_eq!(legacy.description.as_deref(), Some("Old docs"));
+        assert_eq!(docs("return {}").unwrap(), NodeDocs::default());
+
+        let err = docs("return { input_docs = { size = 'Not an input' } }").unwrap_err();
+        assert!(err.to_string().contains("'size'"));
+    }
+
+    #[test]
+    fn test_parse_api_doc() {
+        let stub = "--- Extrudes the given `faces`.\n\
+                    -- Second line.\n\
+                    --\n\
+                    -- @param faces SelectionExpression\n\
+                    -- @param mesh & mut HalfEdgeMesh\n\
+                    -- @param even_offset Option < bool >\n\
+                    function extrude(faces, mesh, even_offset)\n    \
+                    error('Documentation stub only')\nend\n";
+        let api = ApiDoc::parse("Ops", stub).unwrap();
+        assert_eq!(api.name, "Ops.extrude");
+        assert_eq!(api.description, "Extrudes the given `faces`. Second line.");
+        assert_eq!(
+            api.params,
+            vec![
+                ("faces".to_string(), "SelectionExpression".to_string()),
+                ("mesh".to_string(), "HalfEdgeMesh".to_string()),
+                ("even_offset".to_string(), "Option<bool>".to_string()),
+            ]
+        );
+        assert!(ApiDoc::parse("Types", "--- A constant\nlocal VEC3 = null\n").is_none());
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Node definitions declare a `version` in their Lua table, 1 by default,
+//! which is stored with every node in `bjk` files. When a file was saved with
+//! an older version of a node than the one in the node library, the
+//! definition's `migrate(params, from_version)` function is called to rewrite
+//! the parameter values of the old node, and its inputs and outputs are
+//! rebuilt from the current definition.
+//!
+//! Nodes that can't be migrated are loaded as they were saved, and reported
+//! with a [`NodeVersionWarning`].
+
+use mlua::{Function, Lua, Table};
+
+use super::serialization::{
+    serialize_data_type, SerializedBjkGraph, SerializedBlackjackValue, SerializedDependencyKind,
+    SerializedExternalParameters, SerializedInput, SerializedOutput, SerializedParamLocation,
+};
+use super::{BlackjackValue, NodeDefinition, NodeDefinitions};
+use crate::prelude::*;
+
+/// A node that was saved with a different version of its node definition, and
+/// couldn't be migrated to the current one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeVersionWarning {
+    /// The index of the node in the serialized graph
+    pub node_idx: usize,
+    pub op_name: String,
+    /// The version of the node definition the node was saved with
+    pub saved_version: u32,
+    /// The version of the node definition in the node library
+    pub library_version: u32,
+    /// Inputs of the node definition that the saved node doesn't have
+    pub missing_inputs: Vec<String>,
+    /// Inputs of the saved node that the node definition doesn't have. These
+    /// were typically renamed or removed.
+    pub unknown_inputs: Vec<String>,
+}
+
+impl NodeVersionWarning {
+    /// Returns a description of the problem, to be shown to users.
+    pub fn describe(&self) -> String {
+        let mut description = if self.saved_version < self.library_version {
+            format!(
+                "This node was saved with version {} of '{}', but the node library has \
+                 version {} and doesn't know how to migrate it.",
+                self.saved_version, self.op_name, self.library_version
+            )
+        } else {
+            format!(
+                "This node was saved with version {} of '{}', which is newer than version {} \
+                 in the node library.",
+                self.saved_version, self.op_name, self.library_version
+            )
+        };
+        if !self.missing_inputs.is_empty() {
+            description += &format!("\nMissing inputs: {}", self.missing_inputs.join(", "));
+        }
+        if !self.unknown_inputs.is_empty() {
+            description += &format!("\nUnknown inputs: {}", self.unknown_inputs.join(", "));
+        }
+        description
+    }
+}
+
+/// Returns the `migrate` function of the node definition for `op_name`, in
+/// the node library loaded in `lua`.
+fn find_migration<'lua>(lua: &'lua Lua, op_name: &str) -> Result<Option<Function<'lua>>> {
+    let nodes = lua
+        .load("require('node_library')")
+        .eval::<Table>()?
+        .get::<_, Table>("nodes")?;
+    match nodes.get::<_, Option<Table>>(op_name)? {
+        Some(node) => Ok(node.get::<_, Option<Function>>("migrate")?),
+        None => Ok(None),
+    }
+}
+
+/// Migrates the nodes of `graph` that were saved with an older version of
+/// their node definition, using the `migrate` functions of the node library
+/// loaded in `lua`. Returns a warning for each node with a different version
+/// than its definition that was left as it is.
+///
+/// This needs to run before the graph is converted with
+/// [`SerializedBjkGraph::into_runtime`].
+pub fn migrate_graph(
+    graph: &mut SerializedBjkGraph,
+    lua: &Lua,
+    node_definitions: &NodeDefinitions,
+) -> Result<Vec<NodeVersionWarning>> {
+    let mut warnings = vec![];
+    let mut any_migrated = false;
+    for node_idx in 0..graph.nodes.len() {
+        let op_name = graph.nodes[node_idx].op_name.clone();
+        let saved_version = graph.nodes[node_idx].version;
+        // Nodes without a definition are reported when the graph is loaded
+        let node_def = match node_definitions.node_def(&op_name) {
+            Some(node_def) if node_def.version != saved_version => node_def.clone(),
+            _ => continue,
+        };
+
+        let migration = if saved_version < node_def.version {
+            find_migration(lua, &op_name)?
+        } else {
+            None
+        };
+        match migration {
+            Some(migrate) => {
+                migrate_node(graph, node_idx, &node_def, lua, migrate).with_context(|| {
+                    format!("Could not migrate node {op_name} from version {saved_version}")
+                })?;
+                any_migrated = true;
+            }
+            None => {
+                let saved_inputs = graph.nodes[node_idx]
+                    .inputs
+                    .iter()
+                    .map(|input| input.name.clone())
+                    .collect_vec();
+                let def_inputs = node_def
+                    .inputs
+                    .iter()
+                    .map(|input| input.name.clone())
+                    .collect_vec();
+                warnings.push(NodeVersionWarning {
+                    node_idx,
+                    op_name,
+                    saved_version,
+                    library_version: node_def.version,
+                    missing_inputs: def_inputs
+                        .iter()
+                        .filter(|name| !saved_inputs.contains(name))
+                        .cloned()
+                        .collect(),
+                    unknown_inputs: saved_inputs
+                        .iter()
+                        .filter(|name| !def_inputs.contains(name))
+                        .cloned()
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    if any_migrated {
+        disconnect_missing_outputs(graph);
+    }
+    Ok(warnings)
+}
+
+/// Calls `migrate` on the parameters of the node at `node_idx`, and replaces
+/// its inputs and outputs with the ones in `node_def`. Inputs that keep their
+/// name and type also keep their connections.
+fn migrate_node(
+    graph: &mut SerializedBjkGraph,
+    node_idx: usize,
+    node_def: &NodeDefinition,
+    lua: &Lua,
+    migrate: Function,
+) -> Result<()> {
+    let param_values = &mut graph
+        .external_parameters
+        .get_or_insert_with(|| SerializedExternalParameters {
+            param_values: HashMap::new(),
+        })
+        .param_values;
+    let (node_params, other_params): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(param_values)
+        .into_iter()
+        .partition(|(loc, _)| loc.node_idx == node_idx);
+    *param_values = other_params;
+
+    let params = lua.create_table()?;
+    for (loc, value) in node_params {
+        params.set(loc.param_name, value.into_runtime())?;
+    }
+    let saved_version = graph.nodes[node_idx].version;
+    // The function can either return new parameters or modify the given ones
+    let migrated = migrate
+        .call::<_, Option<Table>>((params.clone(), saved_version))?
+        .unwrap_or(params);
+    for pair in migrated.pairs::<String, BlackjackValue>() {
+        let (param_name, value) = pair?;
+        let input_def = match node_def
+            .inputs
+            .iter()
+            .find(|input| input.name == param_name)
+        {
+            Some(input_def) => input_def,
+            None => {
+                println!(
+                    "[WARNING] Migration of {} returned unknown parameter '{param_name}'",
+                    node_def.op_name
+                );
+                continue;
+            }
+        };
+        // Lua numbers come back as scalars, even for integer parameters
+        let value = value
+            .convert_to(input_def.data_type)
+            .with_context(|| format!("Invalid value for parameter '{param_name}'"))?;
+        if let Some(value) = SerializedBlackjackValue::from_runtime(value) {
+            param_values.insert(
+                SerializedParamLocation {
+                    node_idx,
+                    param_name,
+                },
+                value,
+            );
+        }
+    }
+
+    let node = &mut graph.nodes[node_idx];
+    let mut saved_inputs = std::mem::take(&mut node.inputs);
+    for input_def in &node_def.inputs {
+        let data_type = serialize_data_type(input_def.data_type);
+        let existing = saved_inputs
+            .iter()
+            .position(|input| input.name == input_def.name && input.data_type == data_type);
+        node.inputs.push(match existing {
+            Some(pos) => saved_inputs.swap_remove(pos),
+            None => SerializedInput {
+                name: input_def.name.clone(),
+                data_type,
+                kind: SerializedDependencyKind::External { promoted: None },
+                picked_from: None,
+            },
+        });
+
+        // New inputs the migration didn't set start with their default value
+        let loc = SerializedParamLocation {
+            node_idx,
+            param_name: input_def.name.clone(),
+        };
+        if !param_values.contains_key(&loc) {
+            if let Some(value) = SerializedBlackjackValue::from_runtime(input_def.default_value()) {
+                param_values.insert(loc, value);
+            }
+        }
+    }
+    node.outputs = node_def
+        .outputs
+        .iter()
+        .map(|output_def| SerializedOutput {
+            name: output_def.name.clone(),
+            data_type: serialize_data_type(output_def.data_type),
+        })
+        .collect();
+    node.return_value = node_def.returns.clone();
+    node.version = node_def.version;
+    Ok(())
+}
+
+/// Replaces the connections to outputs that no longer exist after a
+/// migration with external parameters.
+fn disconnect_missing_outputs(graph: &mut SerializedBjkGraph) {
+    let outputs = graph
+        .nodes
+        .iter()
+        .map(|node| node.outputs.iter().map(|o| o.name.clone()).collect_vec())
+        .collect_vec();
+    for node in &mut graph.nodes {
+        for input in &mut node.inputs {
+            if let SerializedDependencyKind::Conection {
+                node_idx,
+                param_name,
+            } = &input.kind
+            {
+                let exists = outputs
+                    .get(*node_idx)
+                    .map(|outputs| outputs.contains(param_name))
+                    .unwrap_or(false);
+                if !exists {
+                    println!(
+                        "[WARNING] Removing connection from missing output '{param_name}' of \
+                         node {node_idx} to '{}' of {}",
+                        input.name, node.op_name
+                    );
+                    input.kind = SerializedDependencyKind::External { promoted: None };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph_interpreter::run_graph;
+    use crate::lua_engine::{LuaRuntime, RenderableThing};
+
+    /// A runtime with the node library plus the nodes in the
+    /// `versioned_nodes.lua` fixture.
+    fn runtime_with_fixtures() -> LuaRuntime {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let fixture = std::fs::read_to_string("../test/fixtures/versioned_nodes.lua").unwrap();
+        runtime.lua.load(&fixture).exec().unwrap();
+        let nodes = runtime
+            .lua
+            .load("require('node_library').nodes")
+            .eval::<Table>()
+            .unwrap();
+        runtime
+            .node_definitions
+            .update(NodeDefinition::load_nodes_from_table(nodes).unwrap());
+        runtime
+    }
+
+    fn loc(node_idx: usize, param_name: &str) -> SerializedParamLocation {
+        SerializedParamLocation {
+            node_idx,
+            param_name: param_name.into(),
+        }
+    }
+
+    #[test]
+    fn test_migrate_split_param() {
+        let runtime = runtime_with_fixtures();
+        let mut graph =
+            SerializedBjkGraph::load_from_file("../test/fixtures/versioned_box_v1.bjk").unwrap();
+        let warnings = migrate_graph(&mut graph, &runtime.lua, &runtime.node_definitions).unwrap();
+
+        let node = &graph.nodes[0];
+        assert_eq!(node.version, 2);
+        assert_eq!(
+            node.inputs.iter().map(|i| i.name.as_str()).collect_vec(),
+            ["width", "height"]
+        );
+        let params = &graph.external_parameters.as_ref().unwrap().param_values;
+        assert_eq!(
+            params[&loc(0, "width")],
+            SerializedBlackjackValue::Scalar(3.0)
+        );
+        assert_eq!(
+            params[&loc(0, "height")],
+            SerializedBlackjackValue::Scalar(3.0)
+        );
+        assert!(!params.contains_key(&loc(0, "size")));
+
+        // Without a migration, the node is left as it was
+        assert_eq!(
+            warnings,
+            vec![NodeVersionWarning {
+                node_idx: 2,
+                op_name: "UnmigratedBox".into(),
+                saved_version: 1,
+                library_version: 2,
+                missing_inputs: vec!["width".into(), "height".into()],
+                unknown_inputs: vec!["size".into()],
+            }]
+        );
+        assert_eq!(graph.nodes[2].version, 1);
+        assert!(params.contains_key(&loc(2, "size")));
+
+        // The connections to the migrated node are kept, and the result is
+        // the same as for a graph made with the current version.
+        let (rt_data, _, mappings) = graph.into_runtime().unwrap();
+        let result = run_graph(
+            &runtime.lua,
+            &rt_data.graph,
+            mappings.get_id(1).unwrap(),
+            rt_data.external_parameters.unwrap(),
+            &runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        match result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+                let positions = mesh.read_positions();
+                let conn = mesh.read_connectivity();
+                assert_eq!(conn.num_vertices(), 16);
+                let max = conn
+                    .iter_vertices()
+                    .map(|(v, _)| positions[v])
+                    .fold(Vec3::splat(f32::MIN), Vec3::max);
+                assert!(max.abs_diff_eq(Vec3::new(1.5, 1.5, 0.5), 1e-5), "{max}");
+            }
+            _ => panic!("Expected a mesh"),
+        }
+    }
+
+    #[test]
+    fn test_migrate_counts_to_ints() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let mut graph =
+            SerializedBjkGraph::load_from_file("../examples/stylised_sword.bjk").unwrap();
+        let warnings = migrate_graph(&mut graph, &runtime.lua, &runtime.node_definitions).unwrap();
+        assert!(warnings.is_empty());
+
+        let params = &graph.external_parameters.as_ref().unwrap().param_values;
+        let mut migrated = 0;
+        for (idx, node) in graph.nodes.iter().enumerate() {
+            if node.op_name != "MakeCylinder" {
+                continue;
+            }
+            assert_eq!(node.version, 2);
+            let input = node.inputs.iter().find(|i| i.name == "num_vertices");
+            assert_eq!(input.unwrap().data_type, "BJK_INT");
+            assert!(matches!(
+                params[&loc(idx, "num_vertices")],
+                SerializedBlackjackValue::Int(n) if n >= 3
+            ));
+            migrated += 1;
+        }
+        assert!(migrated > 0);
+        assert!(graph.into_runtime().is_ok());
+    }
+
+    #[test]
+    fn test_current_versions_are_untouched() {
+        let runtime = runtime_with_fixtures();
+        let mut graph = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        let before = graph.to_canonical_string().unwrap();
+        let warnings = migrate_graph(&mut graph, &runtime.lua, &runtime.node_definitions).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(graph.to_canonical_string().unwrap(), before);
+
+        // Nodes saved by a newer node library are reported
+        graph.nodes[0].version = 3;
+        let warnings = migrate_graph(&mut graph, &runtime.lua, &runtime.node_definitions).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].saved_version, 3);
+        assert!(warnings[0].missing_inputs.is_empty());
+        assert!(warnings[0].describe().contains("newer"));
+    }
+}
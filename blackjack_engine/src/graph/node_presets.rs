@@ -0,0 +1,291 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Presets are named sets of parameter values for a node type, which can be
+//! applied to any node of that type. They are stored in a [`NodePresetLibrary`]
+//! file outside of any graph, so they are shared across projects.
+//!
+//! Node definitions change over time, so applying a preset only sets the
+//! parameters that the current definition still has, with the same type.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::serialization::SerializedBlackjackValue;
+use super::{BlackjackValue, NodeDefinition};
+use crate::prelude::*;
+
+/// A named set of parameter values for nodes of type `op_name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodePreset {
+    pub name: String,
+    pub op_name: String,
+    /// The value of each input parameter, by name.
+    pub values: BTreeMap<String, SerializedBlackjackValue>,
+}
+
+/// The values to set on a node when applying a [`NodePreset`] to it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PresetApplication {
+    /// The input parameters to set, and their values.
+    pub values: Vec<(String, BlackjackValue)>,
+    /// Parameters of the preset that the node definition doesn't have
+    /// anymore, or that changed their type.
+    pub skipped: Vec<String>,
+}
+
+impl PresetApplication {
+    /// Returns a description of the skipped parameters, if any, to be shown
+    /// to users.
+    pub fn warning(&self) -> Option<String> {
+        (!self.skipped.is_empty()).then(|| {
+            format!(
+                "Some parameters of the preset don't exist on this node anymore: {}",
+                self.skipped.join(", ")
+            )
+        })
+    }
+}
+
+impl NodePreset {
+    /// Creates a preset from the current `values` of a node's input
+    /// parameters. Parameters that are not in `node_def`, or have no value
+    /// such as meshes, are not stored.
+    pub fn from_values<'a>(
+        name: impl Into<String>,
+        node_def: &NodeDefinition,
+        values: impl IntoIterator<Item = (&'a str, &'a BlackjackValue)>,
+    ) -> Self {
+        let values = values
+            .into_iter()
+            .filter(|(input, _)| node_def.inputs.iter().any(|def| def.name == *input))
+            .filter_map(|(input, value)| {
+                SerializedBlackjackValue::from_runtime(value.clone())
+                    .map(|value| (input.to_owned(), value))
+            })
+            .collect();
+        Self {
+            name: name.into(),
+            op_name: node_def.op_name.clone(),
+            values,
+        }
+    }
+
+    /// Returns the values this preset sets on a node defined by `node_def`.
+    pub fn apply(&self, node_def: &NodeDefinition) -> Result<PresetApplication> {
+        if self.op_name != node_def.op_name {
+            bail!(
+                "The preset '{}' is for '{}' nodes, and can't be applied to a '{}' node",
+                self.name,
+                self.op_name,
+                node_def.op_name
+            );
+        }
+        let mut application = PresetApplication::default();
+        for (input, value) in &self.values {
+            let value = value.clone().into_runtime();
+            match node_def.inputs.iter().find(|def| def.name == *input) {
+                Some(def) if def.data_type.is_valid_value(&value) => {
+                    application.values.push((input.clone(), value));
+                }
+                _ => application.skipped.push(input.clone()),
+            }
+        }
+        Ok(application)
+    }
+}
+
+/// All the presets of a user, and which of them are applied to new nodes.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodePresetLibrary {
+    pub presets: Vec<NodePreset>,
+    /// The name of the preset applied to newly created nodes, by op name.
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+}
+
+impl NodePresetLibrary {
+    /// Loads the presets stored in the JSON file at `path`. A missing file is
+    /// an empty library.
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Could not read node presets from {}", path.display()))
+    }
+
+    /// Stores the presets as a JSON file at `path`.
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Could not save node presets to {}", path.display()))
+    }
+
+    /// Returns the presets for nodes of type `op_name`.
+    pub fn presets_for<'a>(&'a self, op_name: &'a str) -> impl Iterator<Item = &'a NodePreset> {
+        self.presets.iter().filter(move |p| p.op_name == op_name)
+    }
+
+    pub fn get(&self, op_name: &str, name: &str) -> Option<&NodePreset> {
+        self.presets_for(op_name).find(|p| p.name == name)
+    }
+
+    /// Adds `preset` to the library, replacing any preset with the same name
+    /// for the same node type.
+    pub fn insert(&mut self, preset: NodePreset) {
+        match self
+            .presets
+            .iter_mut()
+            .find(|p| p.op_name == preset.op_name && p.name == preset.name)
+        {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    /// Removes a preset. If it was the default for its node type, new nodes
+    /// go back to the defaults of the node definition.
+    pub fn remove(&mut self, op_name: &str, name: &str) {
+        self.presets
+            .retain(|p| !(p.op_name == op_name && p.name == name));
+        if self.defaults.get(op_name).map(String::as_str) == Some(name) {
+            self.defaults.remove(op_name);
+        }
+    }
+
+    /// Sets the preset applied to new nodes of type `op_name`, or clears it
+    /// when `name` is `None`.
+    pub fn set_default(&mut self, op_name: &str, name: Option<&str>) -> Result<()> {
+        match name {
+            Some(name) => {
+                if self.get(op_name, name).is_none() {
+                    bail!("There is no preset named '{name}' for '{op_name}' nodes");
+                }
+                self.defaults.insert(op_name.into(), name.into());
+            }
+            None => {
+                self.defaults.remove(op_name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the preset to apply to new nodes of type `op_name`, if any.
+    pub fn default_for(&self, op_name: &str) -> Option<&NodePreset> {
+        self.defaults
+            .get(op_name)
+            .and_then(|name| self.get(op_name, name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bevel_definition(lua: &mlua::Lua, inputs: &str) -> NodeDefinition {
+        let table = lua
+            .load(&format!(
+                "return {{ label = 'Bevel', inputs = {{ {inputs} }}, outputs = {{}} }}"
+            ))
+            .eval()
+            .unwrap();
+        NodeDefinition::from_lua("Bevel".into(), table).unwrap()
+    }
+
+    #[test]
+    fn test_apply_to_changed_definition() {
+        let lua = mlua::Lua::new();
+        let old_def = bevel_definition(
+            &lua,
+            "{ name = 'amount', type = 'scalar', default = 0.1 },
+             { name = 'segments', type = 'int', default = 1 },
+             { name = 'in_mesh', type = 'mesh' }",
+        );
+        let preset = NodePreset::from_values(
+            "Soft",
+            &old_def,
+            [
+                ("amount", &BlackjackValue::Scalar(0.3)),
+                ("segments", &BlackjackValue::Int(4)),
+                // Meshes have no value to store
+                ("in_mesh", &BlackjackValue::None),
+                ("not_an_input", &BlackjackValue::Bool(true)),
+            ],
+        );
+        assert_eq!(preset.values.len(), 2);
+
+        // `segments` was turned into a scalar, and `amount` was renamed
+        let new_def = bevel_definition(
+            &lua,
+            "{ name = 'width', type = 'scalar', default = 0.1 },
+             { name = 'segments', type = 'scalar', default = 1 }",
+        );
+        let application = preset.apply(&new_def).unwrap();
+        assert!(application.values.is_empty());
+        assert_eq!(application.skipped, vec!["amount", "segments"]);
+        assert!(application.warning().unwrap().contains("amount, segments"));
+
+        let application = preset.apply(&old_def).unwrap();
+        assert_eq!(
+            application.values,
+            vec![
+                ("amount".to_owned(), BlackjackValue::Scalar(0.3)),
+                ("segments".to_owned(), BlackjackValue::Int(4)),
+            ]
+        );
+        assert_eq!(application.warning(), None);
+
+        let mut other_def = old_def;
+        other_def.op_name = "Chamfer".into();
+        assert!(preset.apply(&other_def).is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let lua = mlua::Lua::new();
+        let def = bevel_definition(&lua, "{ name = 'amount', type = 'scalar', default = 0.1 }");
+        let preset = |name, amount| {
+            NodePreset::from_values(name, &def, [("amount", &BlackjackValue::Scalar(amount))])
+        };
+
+        let mut library = NodePresetLibrary::default();
+        library.insert(preset("Soft", 0.3));
+        library.insert(preset("Sharp", 0.01));
+        assert!(library.default_for("Bevel").is_none());
+        assert!(library.set_default("Bevel", Some("Missing")).is_err());
+
+        library.set_default("Bevel", Some("Soft")).unwrap();
+        assert_eq!(library.default_for("Bevel").unwrap().name, "Soft");
+        assert!(library.default_for("Chamfer").is_none());
+
+        // Saving a preset with the same name replaces it
+        library.insert(preset("Soft", 0.5));
+        assert_eq!(library.presets_for("Bevel").count(), 2);
+        assert_eq!(
+            library.default_for("Bevel").unwrap().values["amount"],
+            SerializedBlackjackValue::Scalar(0.5)
+        );
+
+        let path = std::env::temp_dir().join("blackjack_test_node_presets.json");
+        library.save_to_file(&path).unwrap();
+        assert_eq!(NodePresetLibrary::load_from_file(&path).unwrap(), library);
+        std::fs::remove_file(&path).unwrap();
+
+        // Removing the default preset clears it
+        library.remove("Bevel", "Soft");
+        assert!(library.default_for("Bevel").is_none());
+        library.set_default("Bevel", None).unwrap();
+        assert_eq!(library.presets_for("Bevel").count(), 1);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! File path parameters are stored relative to the folder of the graph when
+//! the files are inside it, so a project can be moved to another folder or
+//! machine as a whole. Relative paths start with `//`, and are resolved when
+//! the graph runs, see [`ProjectContext`].
+
+use super::{BjkGraph, BlackjackValue, NodeDefinitions};
+use crate::graph_interpreter::ExternalParameterValues;
+use crate::lua_engine::lua_stdlib::lua_path::{self, ProjectContext};
+
+/// Returns the form of `path` to store in a graph saved to `new_dir`, that was
+/// previously saved to `old_dir`, if anywhere. Files inside `new_dir` are
+/// stored relative to it. Paths that were relative stay relative when they
+/// can, and keep pointing to the same files.
+pub fn rebase_path(path: &str, old_dir: Option<&str>, new_dir: &str, separator: char) -> String {
+    let was_relative = lua_path::is_project_relative(path);
+    let absolute = match lua_path::resolve_with(path, old_dir, separator) {
+        Ok(absolute) => absolute,
+        // The graph was never saved, so the path is taken as relative to the
+        // folder it's being saved to.
+        Err(_) => return path.into(),
+    };
+    match lua_path::make_relative(&absolute, new_dir) {
+        Some(relative) if was_relative || lua_path::is_inside(&absolute, new_dir) => relative,
+        _ => absolute,
+    }
+}
+
+/// Rewrites the file path parameters in `params` for `graph` being saved from
+/// the `old` project to the `new` one, see [`rebase_path`]. The graph must be
+/// saved somewhere, so parameters without a folder in `new` are left
+/// untouched.
+pub fn rebase_file_paths(
+    graph: &BjkGraph,
+    params: &mut ExternalParameterValues,
+    node_definitions: &NodeDefinitions,
+    old: &ProjectContext,
+    new: &ProjectContext,
+) {
+    let dir_str = |project: &ProjectContext| {
+        project
+            .graph_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned())
+    };
+    let (old_dir, new_dir) = match (dir_str(old), dir_str(new)) {
+        (old_dir, Some(new_dir)) => (old_dir, new_dir),
+        (_, None) => return,
+    };
+    for (param, value) in params.0.iter_mut() {
+        let is_file_path = graph.nodes.get(param.node_id).map_or(false, |node| {
+            node_definitions
+                .node_def(&node.op_name)
+                .map_or(false, |def| def.is_file_path(&param.param_name))
+        });
+        if let (true, BlackjackValue::String(path)) = (is_file_path, value) {
+            *path = rebase_path(
+                path,
+                old_dir.as_deref(),
+                &new_dir,
+                std::path::MAIN_SEPARATOR,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use crate::graph::{DataType, NodeDefinition, NodeDefinitionsInner};
+    use crate::graph_interpreter::ExternalParameter;
+
+    #[test]
+    fn test_rebase_path() {
+        let rebase = |path, old_dir| rebase_path(path, old_dir, "/home/me/jacks", '/');
+
+        // Files inside the new folder become relative, others stay absolute
+        assert_eq!(
+            rebase("/home/me/jacks/models/sword.obj", None),
+            "//models/sword.obj"
+        );
+        assert_eq!(
+            rebase("/home/me/textures/rock.png", None),
+            "/home/me/textures/rock.png"
+        );
+        // Relative paths keep pointing to the same file
+        assert_eq!(
+            rebase("//models/sword.obj", Some("/home/me/jacks")),
+            "//models/sword.obj"
+        );
+        assert_eq!(
+            rebase("//textures/rock.png", Some("/home/me")),
+            "//../textures/rock.png"
+        );
+        assert_eq!(
+            rebase("//rock.png", Some("D:\\textures")),
+            "D:/textures/rock.png"
+        );
+        // Graphs saved for the first time
+        assert_eq!(rebase("//sword.obj", None), "//sword.obj");
+        assert_eq!(rebase("", None), "");
+    }
+
+    #[test]
+    fn test_rebase_file_paths() {
+        let lua = mlua::Lua::new();
+        let table = lua
+            .load(
+                "return { label = 'Import OBJ', outputs = {}, inputs = {
+                     { name = 'path', type = 'file', mode = 'open' },
+                     { name = 'label', type = 'string', default = '', multiline = false },
+                 } }",
+            )
+            .eval()
+            .unwrap();
+        let def = NodeDefinition::from_lua("ImportObj".into(), table).unwrap();
+        let node_definitions = NodeDefinitions::new(NodeDefinitionsInner(
+            [("ImportObj".to_string(), def)].into_iter().collect(),
+        ));
+
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("ImportObj", None);
+        graph
+            .add_input(node, "path", DataType::String, None)
+            .unwrap();
+        graph
+            .add_input(node, "label", DataType::String, None)
+            .unwrap();
+        let absolute = "/home/me/jacks/sword.obj".to_string();
+        let mut params = ExternalParameterValues::default();
+        for name in ["path", "label"] {
+            params.0.insert(
+                ExternalParameter::new(node, name.into()),
+                BlackjackValue::String(absolute.clone()),
+            );
+        }
+
+        let value = |params: &ExternalParameterValues, name: &str| {
+            params.0[&ExternalParameter::new(node, name.into())].clone()
+        };
+        let project = ProjectContext::for_graph_file(Some(Path::new("/home/me/jacks/sword.bjk")));
+        rebase_file_paths(
+            &graph,
+            &mut params,
+            &node_definitions,
+            &ProjectContext::default(),
+            &project,
+        );
+        assert_eq!(
+            value(&params, "path"),
+            BlackjackValue::String("//sword.obj".into())
+        );
+        // Only file parameters are paths
+        assert_eq!(
+            value(&params, "label"),
+            BlackjackValue::String(absolute.clone())
+        );
+
+        // The saved path resolves back to the file
+        let resolved = match value(&params, "path") {
+            BlackjackValue::String(path) => {
+                lua_path::resolve_with(&path, Some("/home/me/jacks"), '/')
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(resolved.unwrap(), absolute);
+    }
+}
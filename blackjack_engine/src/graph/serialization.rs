@@ -7,7 +7,8 @@
 use std::{
     collections::HashMap,
     io::{BufRead, BufReader, BufWriter, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use anyhow::{anyhow, bail, Result};
@@ -18,15 +19,20 @@ use slotmap::{SecondaryMap, SlotMap};
 
 use crate::{
     graph_interpreter::{ExternalParameter, ExternalParameterValues},
-    prelude::selection::SelectionExpression,
+    mesh::{
+        halfedge::edit_ops::{VertexDeltas, VertexWeights},
+        material::MaterialTable,
+    },
+    prelude::selection::{SelectionExpression, SelectionKind},
+    units::LengthUnit,
 };
 
 use super::{
     BjkGraph, BjkNode, BjkNodeId, BjkSnippet, BlackjackValue, DataType, DependencyKind,
-    InputParameter, Output,
+    InputParameter, Output, PickedSelection,
 };
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SerializationVersion {
     pub major: u32,
     pub minor: u32,
@@ -42,7 +48,13 @@ impl SerializationVersion {
         }
     }
 
-    pub fn to_writer(&self, mut w: impl Write) -> Result<()> {
+    pub fn to_writer(&self, w: impl Write) -> Result<()> {
+        self.to_writer_with_format(w, BjkFileFormat::Ron)
+    }
+
+    /// Writes the version header for a file in the given `format`. The header
+    /// of canonical files ends with an additional `CANONICAL` tag.
+    pub fn to_writer_with_format(&self, mut w: impl Write, format: BjkFileFormat) -> Result<()> {
         // Serde made it very inconvenient to deserialize the version field
         // before attempting to deserialize the whole RON file. A pragmatic
         // solution was to (ab)use RON's comment support to encode version
@@ -51,25 +63,42 @@ impl SerializationVersion {
         // This "comment" is just as part of the BJK file format as the
         // subsequent RON data, so if a user tampers with it they will corrupt
         // the file, same as if they arbitrarily removed parts of the RON data.
+        let tag = match format {
+            BjkFileFormat::Ron => "",
+            BjkFileFormat::Canonical => " CANONICAL",
+        };
         writeln!(
             w,
-            "// BLACKJACK_VERSION_HEADER {} {} {}",
+            "// BLACKJACK_VERSION_HEADER {} {} {}{tag}",
             self.major, self.minor, self.patch
         )?;
         Ok(())
     }
 
-    pub fn from_reader(mut r: impl BufRead) -> Result<Self, anyhow::Error> {
+    pub fn from_reader(r: impl BufRead) -> Result<Self, anyhow::Error> {
+        Ok(Self::from_reader_with_format(r)?.0)
+    }
+
+    /// Reads the version header, and the format of the file it belongs to.
+    pub fn from_reader_with_format(mut r: impl BufRead) -> Result<(Self, BjkFileFormat)> {
         let mut header_line = String::new();
         r.read_line(&mut header_line)?;
+        Self::parse_header(&header_line)
+    }
 
-        let header = header_line.trim_end_matches('\n').split(' ').collect_vec();
+    fn parse_header(header_line: &str) -> Result<(Self, BjkFileFormat)> {
+        let header = header_line.trim_end().split(' ').collect_vec();
         match header.as_slice() {
-            &[_, header_str, major_str, minor_str, patch_str] => {
+            &[_, header_str, major_str, minor_str, patch_str, ref tag @ ..] => {
                 if header_str != "BLACKJACK_VERSION_HEADER" {
                     bail!("Blackjack files should start with a version header.");
                 }
-                Ok(Self {
+                let format = match tag {
+                    [] => BjkFileFormat::Ron,
+                    ["CANONICAL"] => BjkFileFormat::Canonical,
+                    _ => bail!("Invalid blackjack version header."),
+                };
+                let version = Self {
                     major: major_str.parse().map_err(|err| {
                         anyhow!("Could not parse version major '{major_str}'. {err}")
                     })?,
@@ -79,7 +108,8 @@ impl SerializationVersion {
                     patch: patch_str.parse().map_err(|err| {
                         anyhow!("Could not parse version patch '{patch_str}'. {err}")
                     })?,
-                })
+                };
+                Ok((version, format))
             }
             _ => {
                 bail!("Invalid blackjack version header.")
@@ -88,20 +118,120 @@ impl SerializationVersion {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The start of the metadata lines, which follow the version header. They are
+/// comments as well, so the RON parser skips them.
+const METADATA_PREFIX: &str = "// BLACKJACK_METADATA ";
+
+/// Information about a `bjk` file that's stored in its header. The header can
+/// be read on its own, without parsing the rest of the file, e.g. to show
+/// previews of many files at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BjkMetadata {
+    pub version: SerializationVersion,
+    pub format: BjkFileFormat,
+    /// A PNG image of the result of the graph, taken when the file was saved.
+    pub thumbnail_png: Option<Vec<u8>>,
+    /// The unit lengths are shown in. Only written when it's not meters, so
+    /// files from before units existed read as meters.
+    pub units: LengthUnit,
+}
+
+impl BjkMetadata {
+    pub fn to_writer(&self, mut w: impl Write) -> Result<()> {
+        self.version.to_writer_with_format(&mut w, self.format)?;
+        if let Some(png) = &self.thumbnail_png {
+            writeln!(w, "{METADATA_PREFIX}thumbnail_png {}", base64::encode(png))?;
+        }
+        if self.units != LengthUnit::Meters {
+            writeln!(w, "{METADATA_PREFIX}units {}", self.units)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the metadata from the header of a file, stopping at the first
+    /// line that is not part of it. Unknown metadata keys are ignored, so
+    /// newer files can be read by older versions.
+    pub fn from_reader(mut r: impl BufRead) -> Result<Self> {
+        let (version, format) = SerializationVersion::from_reader_with_format(&mut r)?;
+        let mut metadata = Self {
+            version,
+            format,
+            thumbnail_png: None,
+            units: LengthUnit::Meters,
+        };
+        let mut line = String::new();
+        loop {
+            line.clear();
+            r.read_line(&mut line)?;
+            let entry = match line.trim_end().strip_prefix(METADATA_PREFIX) {
+                Some(entry) => entry,
+                None => break,
+            };
+            let (key, value) = entry.split_once(' ').unwrap_or((entry, ""));
+            match key {
+                "thumbnail_png" => {
+                    metadata.thumbnail_png = Some(
+                        base64::decode(value).map_err(|err| anyhow!("Invalid thumbnail. {err}"))?,
+                    );
+                }
+                "units" => metadata.units = value.parse()?,
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Reads the metadata of the `bjk` file at `path`. Only the header is
+    /// read from disk.
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_reader(BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+/// The text formats a `bjk` file can be saved in. Both are written as RON,
+/// and are told apart by the version header when loading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BjkFileFormat {
+    /// The serialized data structures, as written by RON.
+    #[default]
+    Ron,
+    /// A format meant to be reviewed and merged under version control.
+    /// Saving the same graph always produces the same file:
+    ///
+    /// - Each node lists its own parameter values, sorted by name, instead of
+    ///   storing them in a map with no fixed order.
+    /// - Node positions and the editor pan are rounded to whole units, so
+    ///   nudging a node by a fraction of a pixel doesn't change the file.
+    /// - Floats are written with the shortest representation that reads back
+    ///   as the same value.
+    ///
+    /// Nodes keep their order, and inputs keep the order of the node
+    /// definition.
+    Canonical,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum SerializedDependencyKind {
     External { promoted: Option<String> },
     Conection { node_idx: usize, param_name: String },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SerializedInput {
     pub name: String,
     pub data_type: String,
     pub kind: SerializedDependencyKind,
+    #[serde(default)]
+    pub picked_from: Option<SerializedPickedSelection>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SerializedPickedSelection {
+    pub node_idx: usize,
+    pub kind: SelectionKind,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SerializedOutput {
     pub name: String,
     pub data_type: String,
@@ -113,6 +243,18 @@ pub struct SerializedBjkNode {
     pub return_value: Option<String>,
     pub inputs: Vec<SerializedInput>,
     pub outputs: Vec<SerializedOutput>,
+    /// The version of the node definition. Files from before node versions
+    /// existed were saved with the first one.
+    #[serde(default = "first_node_version")]
+    pub version: u32,
+    /// See [`BjkNode::seed`]. Nodes in files from before node seeds existed
+    /// use their position in the file.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn first_node_version() -> u32 {
+    1
 }
 
 #[derive(Serialize, Deserialize)]
@@ -123,6 +265,23 @@ pub struct SerializedUiData {
     pub zoom: f32,
     #[serde(default)]
     pub locked_gizmo_nodes: Vec<usize>,
+    /// How the viewport lights the mesh. Files from before lighting settings
+    /// existed use the plain matcap shading.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport_lighting: Option<SerializedViewportLighting>,
+}
+
+/// The lighting settings of the 3d viewport, stored along with the graph so
+/// each project keeps its own. Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SerializedViewportLighting {
+    pub key_azimuth: f32,
+    pub key_elevation: f32,
+    pub key_intensity: f32,
+    pub ambient: f32,
+    pub three_point: bool,
+    pub ssao: bool,
+    pub ssao_radius: f32,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -131,12 +290,20 @@ pub struct SerializedParamLocation {
     pub param_name: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SerializedBlackjackValue {
     Vector(glam::Vec3),
     Scalar(f32),
     String(String),
     Selection(String),
+    Int(i32),
+    Bool(bool),
+    /// Vertex indices and their offsets, sorted by index.
+    VertexDeltas(Vec<(u32, glam::Vec3)>),
+    /// Vertex indices and their painted values, sorted by index.
+    VertexWeights(Vec<(u32, f32)>),
+    /// The source of an expression, stored instead of a literal value.
+    Expression(String),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -144,12 +311,84 @@ pub struct SerializedExternalParameters {
     pub param_values: HashMap<SerializedParamLocation, SerializedBlackjackValue>,
 }
 
+/// The sources of a node library embedded in a graph file, so the graph can
+/// be opened where the library is not installed. See
+/// [`crate::lua_engine::graph_libraries`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EmbeddedNodeLibrary {
+    /// The file name of the library.
+    pub name: String,
+    /// The Lua source of the library.
+    pub source: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SerializedBjkGraph {
     pub nodes: Vec<SerializedBjkNode>,
     pub default_node: Option<usize>,
     pub ui_data: Option<SerializedUiData>,
     pub external_parameters: Option<SerializedExternalParameters>,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub materials: MaterialTable,
+    /// The node libraries embedded in the file, when the graph was saved with
+    /// the user libraries it uses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub node_libraries: Vec<EmbeddedNodeLibrary>,
+    /// A PNG preview of the graph. Stored in the metadata header of the file.
+    #[serde(skip)]
+    pub thumbnail_png: Option<Vec<u8>>,
+    /// The unit lengths are shown in. Stored in the metadata header too.
+    #[serde(skip)]
+    pub units: LengthUnit,
+    /// The file this graph was loaded from. Not stored in the file itself.
+    #[serde(skip)]
+    pub file_path: Option<PathBuf>,
+}
+
+/// The layout of a graph in the [`BjkFileFormat::Canonical`] format.
+#[derive(Serialize, Deserialize)]
+struct CanonicalBjkGraph {
+    seed: u64,
+    #[serde(default)]
+    materials: MaterialTable,
+    default_node: Option<usize>,
+    nodes: Vec<CanonicalBjkNode>,
+    ui_data: Option<CanonicalUiData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    node_libraries: Vec<EmbeddedNodeLibrary>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CanonicalBjkNode {
+    op_name: String,
+    return_value: Option<String>,
+    /// The position in the graph editor, rounded to whole units.
+    position: Option<(i32, i32)>,
+    inputs: Vec<SerializedInput>,
+    outputs: Vec<SerializedOutput>,
+    /// The values of the node parameters, sorted by name.
+    params: Vec<(String, SerializedBlackjackValue)>,
+    #[serde(default = "first_node_version")]
+    version: u32,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CanonicalUiData {
+    node_order: Vec<usize>,
+    pan: (i32, i32),
+    zoom: f32,
+    /// Sorted, because the editor doesn't keep these in any particular order.
+    locked_gizmo_nodes: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    viewport_lighting: Option<SerializedViewportLighting>,
+}
+
+fn quantize(v: glam::Vec2) -> (i32, i32) {
+    (v.x.round() as i32, v.y.round() as i32)
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -216,13 +455,151 @@ impl IdMappings {
 
 impl SerializedBjkGraph {
     pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
-        let version = SerializationVersion::latest();
+        self.write_to_file_with_format(path, BjkFileFormat::Ron)
+    }
+
+    pub fn write_to_file_with_format(
+        &self,
+        path: impl AsRef<Path>,
+        format: BjkFileFormat,
+    ) -> Result<()> {
         let mut writer = BufWriter::new(std::fs::File::create(path)?);
-        version.to_writer(&mut writer)?;
-        ron::ser::to_writer_pretty(&mut writer, &self, PrettyConfig::default())?;
+        match format {
+            BjkFileFormat::Ron => {
+                self.metadata(format).to_writer(&mut writer)?;
+                ron::ser::to_writer_pretty(&mut writer, &self, PrettyConfig::default())?;
+            }
+            BjkFileFormat::Canonical => writer.write_all(self.to_canonical_string()?.as_bytes())?,
+        }
         Ok(())
     }
 
+    fn metadata(&self, format: BjkFileFormat) -> BjkMetadata {
+        BjkMetadata {
+            version: SerializationVersion::latest(),
+            format,
+            thumbnail_png: self.thumbnail_png.clone(),
+            units: self.units,
+        }
+    }
+
+    /// Returns the contents of a `bjk` file for this graph, in the
+    /// [`BjkFileFormat::Canonical`] format.
+    pub fn to_canonical_string(&self) -> Result<String> {
+        let mut params = vec![vec![]; self.nodes.len()];
+        for (loc, value) in self
+            .external_parameters
+            .iter()
+            .flat_map(|params| &params.param_values)
+        {
+            match params.get_mut(loc.node_idx) {
+                Some(node_params) => node_params.push((loc.param_name.clone(), value.clone())),
+                None => bail!(
+                    "Cannot write the canonical format: Parameter '{}' belongs to node {}, \
+                     which is not in the graph",
+                    loc.param_name,
+                    loc.node_idx
+                ),
+            }
+        }
+        let positions = self.ui_data.as_ref().map(|ui_data| &ui_data.node_positions);
+
+        let canonical = CanonicalBjkGraph {
+            seed: self.seed,
+            materials: self.materials.clone(),
+            default_node: self.default_node,
+            nodes: self
+                .nodes
+                .iter()
+                .zip(params)
+                .enumerate()
+                .map(|(idx, (node, mut params))| {
+                    params.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    CanonicalBjkNode {
+                        op_name: node.op_name.clone(),
+                        return_value: node.return_value.clone(),
+                        position: positions.and_then(|p| p.get(idx)).copied().map(quantize),
+                        inputs: node.inputs.clone(),
+                        outputs: node.outputs.clone(),
+                        params,
+                        version: node.version,
+                        seed: node.seed,
+                    }
+                })
+                .collect(),
+            ui_data: self.ui_data.as_ref().map(|ui_data| CanonicalUiData {
+                node_order: ui_data.node_order.clone(),
+                pan: quantize(ui_data.pan),
+                zoom: ui_data.zoom,
+                locked_gizmo_nodes: ui_data
+                    .locked_gizmo_nodes
+                    .iter()
+                    .copied()
+                    .sorted()
+                    .collect(),
+                viewport_lighting: ui_data.viewport_lighting,
+            }),
+            node_libraries: self.node_libraries.clone(),
+        };
+
+        // The config is spelled out so the output doesn't depend on the
+        // platform or on the defaults of the RON version.
+        let config = PrettyConfig::new()
+            .new_line("\n".into())
+            .indentor("    ".into());
+        let mut w = Vec::<u8>::new();
+        self.metadata(BjkFileFormat::Canonical).to_writer(&mut w)?;
+        ron::ser::to_writer_pretty(&mut w, &canonical, config)?;
+        w.push(b'\n');
+        Ok(String::from_utf8(w)?)
+    }
+
+    fn from_canonical(canonical: CanonicalBjkGraph) -> Self {
+        let mut param_values = HashMap::new();
+        let mut node_positions = vec![];
+        let mut nodes = vec![];
+        for (idx, node) in canonical.nodes.into_iter().enumerate() {
+            for (param_name, value) in node.params {
+                param_values.insert(
+                    SerializedParamLocation {
+                        node_idx: idx,
+                        param_name,
+                    },
+                    value,
+                );
+            }
+            let (x, y) = node.position.unwrap_or_default();
+            node_positions.push(glam::Vec2::new(x as f32, y as f32));
+            nodes.push(SerializedBjkNode {
+                op_name: node.op_name,
+                return_value: node.return_value,
+                inputs: node.inputs,
+                outputs: node.outputs,
+                version: node.version,
+                seed: node.seed,
+            });
+        }
+        Self {
+            nodes,
+            default_node: canonical.default_node,
+            ui_data: canonical.ui_data.map(|ui_data| SerializedUiData {
+                node_positions,
+                node_order: ui_data.node_order,
+                pan: glam::Vec2::new(ui_data.pan.0 as f32, ui_data.pan.1 as f32),
+                zoom: ui_data.zoom,
+                locked_gizmo_nodes: ui_data.locked_gizmo_nodes,
+                viewport_lighting: ui_data.viewport_lighting,
+            }),
+            external_parameters: Some(SerializedExternalParameters { param_values }),
+            seed: canonical.seed,
+            materials: canonical.materials,
+            node_libraries: canonical.node_libraries,
+            thumbnail_png: None,
+            units: LengthUnit::Meters,
+            file_path: None,
+        }
+    }
+
     pub fn from_runtime(runtime_data: RuntimeData) -> Result<(Self, IdMappings)> {
         let RuntimeData {
             graph,
@@ -234,6 +611,10 @@ impl SerializedBjkGraph {
         let BjkGraph {
             nodes,
             default_node,
+            seed,
+            materials,
+            units,
+            file_path,
         } = graph;
 
         let mut serialized_nodes = vec![];
@@ -251,6 +632,12 @@ impl SerializedBjkGraph {
                     None
                 },
                 ui_data: None,
+                seed,
+                materials,
+                node_libraries: vec![],
+                thumbnail_png: None,
+                units,
+                file_path,
             },
             mappings,
         ))
@@ -353,8 +740,15 @@ impl SerializedBlackjackValue {
         match val {
             BlackjackValue::Vector(v) => Some(Self::Vector(v)),
             BlackjackValue::Scalar(s) => Some(Self::Scalar(s)),
+            BlackjackValue::Int(i) => Some(Self::Int(i)),
+            BlackjackValue::Bool(b) => Some(Self::Bool(b)),
             BlackjackValue::String(s) => Some(Self::String(s)),
             BlackjackValue::Selection(s, _) => Some(Self::Selection(s)),
+            BlackjackValue::VertexDeltas(d) => Some(Self::VertexDeltas(d.0.into_iter().collect())),
+            BlackjackValue::VertexWeights(w) => {
+                Some(Self::VertexWeights(w.0.into_iter().collect()))
+            }
+            BlackjackValue::Expression(e) => Some(Self::Expression(e)),
             BlackjackValue::None => None,
         }
     }
@@ -367,6 +761,8 @@ impl SerializedBjkNode {
             return_value,
             inputs,
             outputs,
+            version,
+            seed,
         } = node;
 
         let inputs = inputs
@@ -383,18 +779,27 @@ impl SerializedBjkNode {
             return_value: return_value.clone(),
             inputs,
             outputs,
+            version: *version,
+            seed: Some(*seed),
         })
     }
 }
 
-fn serialize_data_type(data_type: DataType) -> String {
+pub(crate) fn serialize_data_type(data_type: DataType) -> String {
     match data_type {
         super::DataType::Vector => "BJK_VECTOR",
         super::DataType::Scalar => "BJK_SCALAR",
+        super::DataType::Int => "BJK_INT",
+        super::DataType::Bool => "BJK_BOOL",
         super::DataType::Selection => "BJK_SELECTION",
         super::DataType::Mesh => "BJK_MESH",
+        super::DataType::Curve => "BJK_CURVE",
+        super::DataType::Points => "BJK_POINTS",
         super::DataType::String => "BJK_STRING",
         super::DataType::HeightMap => "BJK_HEIGHTMAP",
+        super::DataType::Scene => "BJK_SCENE",
+        super::DataType::VertexDeltas => "BJK_VERTEX_DELTAS",
+        super::DataType::VertexWeights => "BJK_VERTEX_WEIGHTS",
     }
     .to_owned()
 }
@@ -405,6 +810,7 @@ impl SerializedInput {
             name,
             data_type,
             kind,
+            picked_from,
         } = input;
 
         let dependency_kind = SerializedDependencyKind::from_runtime_data(kind, mappings)?;
@@ -413,6 +819,14 @@ impl SerializedInput {
             name: name.clone(),
             data_type: serialize_data_type(*data_type),
             kind: dependency_kind,
+            // When serializing a snippet, the node the selection was picked
+            // against may not be part of it. The provenance is dropped then.
+            picked_from: picked_from.and_then(|picked| {
+                Some(SerializedPickedSelection {
+                    node_idx: mappings.get_idx(picked.node).ok()?,
+                    kind: picked.kind,
+                })
+            }),
         })
     }
 }
@@ -444,6 +858,29 @@ impl SerializedDependencyKind {
 // ==== RUNTIME DATA GENERATION FROM STORED VALUES ====
 // ====================================================
 
+impl SerializedBlackjackValue {
+    pub fn into_runtime(self) -> BlackjackValue {
+        match self {
+            SerializedBlackjackValue::Vector(x) => BlackjackValue::Vector(x),
+            SerializedBlackjackValue::Scalar(x) => BlackjackValue::Scalar(x),
+            SerializedBlackjackValue::Int(x) => BlackjackValue::Int(x),
+            SerializedBlackjackValue::Bool(x) => BlackjackValue::Bool(x),
+            SerializedBlackjackValue::String(x) => BlackjackValue::String(x),
+            SerializedBlackjackValue::Selection(x) => {
+                let expr = SelectionExpression::parse(&x).ok();
+                BlackjackValue::Selection(x, expr)
+            }
+            SerializedBlackjackValue::VertexDeltas(x) => {
+                BlackjackValue::VertexDeltas(VertexDeltas(x.into_iter().collect()))
+            }
+            SerializedBlackjackValue::VertexWeights(x) => {
+                BlackjackValue::VertexWeights(VertexWeights(x.into_iter().collect()))
+            }
+            SerializedBlackjackValue::Expression(x) => BlackjackValue::Expression(x),
+        }
+    }
+}
+
 impl IdMappings {
     pub fn from_serialized_graph(
         nodes: &[SerializedBjkNode],
@@ -456,6 +893,8 @@ impl IdMappings {
                 return_value: node.return_value.clone(),
                 inputs: vec![],
                 outputs: vec![],
+                version: node.version,
+                seed: node.seed.unwrap_or(idx as u64),
             });
 
             mappings.idx_to_id.push(node_id);
@@ -466,13 +905,35 @@ impl IdMappings {
 }
 
 impl SerializedBjkGraph {
+    /// Loads a graph from a file in any of the [`BjkFileFormat`]s.
     pub fn load_from_file(path: impl AsRef<Path>) -> Result<SerializedBjkGraph> {
-        let reader = BufReader::new(std::fs::File::open(path)?);
-        Ok(ron::de::from_reader(reader)?)
+        let mut graph = Self::load_from_string(&std::fs::read_to_string(path.as_ref())?)?;
+        graph.file_path = Some(path.as_ref().to_owned());
+        Ok(graph)
     }
 
+    /// Loads a graph from the contents of a file in any of the
+    /// [`BjkFileFormat`]s.
     pub fn load_from_string(s: &str) -> Result<SerializedBjkGraph> {
-        Ok(ron::de::from_str(s)?)
+        s.parse()
+    }
+
+    /// Loads a graph from the bytes of a file in any of the
+    /// [`BjkFileFormat`]s, like the ones embedded in the binary. The graph
+    /// has no file path.
+    pub fn load_from_bytes(bytes: &[u8]) -> Result<SerializedBjkGraph> {
+        let s = std::str::from_utf8(bytes).map_err(|err| anyhow!("Invalid graph file. {err}"))?;
+        Self::load_from_string(s)
+    }
+
+    /// Returns the format of the given file contents. Files without a valid
+    /// version header are assumed to be RON, like files from before the
+    /// header existed.
+    pub fn detect_format(s: &str) -> BjkFileFormat {
+        let first_line = s.lines().next().unwrap_or_default();
+        SerializationVersion::parse_header(first_line)
+            .map(|(_, format)| format)
+            .unwrap_or_default()
     }
 
     pub fn into_runtime(self) -> Result<(RuntimeData, Option<SerializedUiData>, IdMappings)> {
@@ -489,6 +950,10 @@ impl SerializedBjkGraph {
                 graph: BjkGraph {
                     nodes: rt_nodes,
                     default_node: self.default_node.and_then(|x| mappings.get_id(x).ok()),
+                    seed: self.seed,
+                    materials: self.materials,
+                    units: self.units,
+                    file_path: self.file_path,
                 },
                 external_parameters: if let Some(e) = self.external_parameters {
                     Some(e.into_runtime(&mappings)?)
@@ -502,6 +967,26 @@ impl SerializedBjkGraph {
     }
 }
 
+impl FromStr for SerializedBjkGraph {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // The header is made of comments in both formats, so it's skipped by
+        // RON. Files from before the header existed are RON.
+        let metadata = BjkMetadata::from_reader(s.as_bytes()).ok();
+        let format = metadata.as_ref().map(|m| m.format).unwrap_or_default();
+        let mut graph: Self = match format {
+            BjkFileFormat::Ron => ron::de::from_str(s)?,
+            BjkFileFormat::Canonical => Self::from_canonical(ron::de::from_str(s)?),
+        };
+        if let Some(metadata) = metadata {
+            graph.thumbnail_png = metadata.thumbnail_png;
+            graph.units = metadata.units;
+        }
+        Ok(graph)
+    }
+}
+
 impl SerializedBjkNode {
     pub fn fill_runtime(self, rt_node: &mut BjkNode, mappings: &IdMappings) -> Result<()> {
         for input in self.inputs {
@@ -521,6 +1006,12 @@ impl SerializedBjkNode {
                             param_name,
                         },
                     },
+                    picked_from: input.picked_from.and_then(|picked| {
+                        Some(PickedSelection {
+                            node: *mappings.idx_to_id.get(picked.node_idx)?,
+                            kind: picked.kind,
+                        })
+                    }),
                 })
             } else {
                 println!("[WARNING] Unkown data type: {}", &input.data_type)
@@ -574,10 +1065,17 @@ fn deserialize_data_type(data_type_str: &str) -> Option<DataType> {
     match data_type_str {
         "BJK_VECTOR" => Some(super::DataType::Vector),
         "BJK_SCALAR" => Some(super::DataType::Scalar),
+        "BJK_INT" => Some(super::DataType::Int),
+        "BJK_BOOL" => Some(super::DataType::Bool),
         "BJK_SELECTION" => Some(super::DataType::Selection),
         "BJK_MESH" => Some(super::DataType::Mesh),
+        "BJK_CURVE" => Some(super::DataType::Curve),
+        "BJK_POINTS" => Some(super::DataType::Points),
         "BJK_STRING" => Some(super::DataType::String),
         "BJK_HEIGHTMAP" => Some(super::DataType::HeightMap),
+        "BJK_SCENE" => Some(super::DataType::Scene),
+        "BJK_VERTEX_DELTAS" => Some(super::DataType::VertexDeltas),
+        "BJK_VERTEX_WEIGHTS" => Some(super::DataType::VertexWeights),
         _ => None,
     }
     .to_owned()
@@ -594,15 +1092,7 @@ impl SerializedExternalParameters {
                             node_id: mappings.get_id(param.node_idx)?,
                             param_name: param.param_name,
                         },
-                        match value {
-                            SerializedBlackjackValue::Vector(x) => BlackjackValue::Vector(x),
-                            SerializedBlackjackValue::Scalar(x) => BlackjackValue::Scalar(x),
-                            SerializedBlackjackValue::String(x) => BlackjackValue::String(x),
-                            SerializedBlackjackValue::Selection(x) => {
-                                let expr = SelectionExpression::parse(&x).ok();
-                                BlackjackValue::Selection(x, expr)
-                            }
-                        },
+                        value.into_runtime(),
                     ))
                 })
                 .collect::<Result<HashMap<_, _>>>()?,
@@ -645,4 +1135,378 @@ mod tests {
         assert_eq!(version, new_version);
         assert_eq!(data, new_data);
     }
+
+    #[test]
+    pub fn test_header_formats() {
+        let parse = |s: &str| SerializationVersion::parse_header(s).unwrap();
+        let version = SerializationVersion::latest();
+        let mut header = vec![];
+        version
+            .to_writer_with_format(&mut header, BjkFileFormat::Canonical)
+            .unwrap();
+        let header = String::from_utf8(header).unwrap();
+        assert_eq!(header, "// BLACKJACK_VERSION_HEADER 0 1 0 CANONICAL\n");
+        assert_eq!(parse(&header), (version, BjkFileFormat::Canonical));
+        assert_eq!(
+            parse("// BLACKJACK_VERSION_HEADER 0 1 0\r\n").1,
+            BjkFileFormat::Ron
+        );
+        assert!(SerializationVersion::parse_header("// BLACKJACK_VERSION_HEADER 0 1 0 X").is_err());
+        // Files without a header are loaded as RON
+        assert_eq!(
+            SerializedBjkGraph::detect_format("(nodes: [])"),
+            BjkFileFormat::Ron
+        );
+    }
+
+    /// Saving a graph in the canonical format, loading it and saving it again
+    /// produces exactly the same file, starting from either format.
+    #[test]
+    pub fn test_canonical_idempotence() {
+        for path in [
+            "../examples/box.bjk",
+            "../examples/tp_cutter.bjk",
+            "../examples/stylised_sword.bjk",
+            "../all_nodes_test.bjk",
+        ] {
+            let mut graph = SerializedBjkGraph::load_from_file(path).unwrap();
+            // Fractional positions are rounded on the first save
+            if let Some(ui_data) = &mut graph.ui_data {
+                for pos in &mut ui_data.node_positions {
+                    *pos += glam::Vec2::new(0.3, -0.4);
+                }
+            }
+            let first = graph.to_canonical_string().unwrap();
+            assert_eq!(
+                SerializedBjkGraph::detect_format(&first),
+                BjkFileFormat::Canonical
+            );
+            let second = SerializedBjkGraph::load_from_string(&first)
+                .unwrap()
+                .to_canonical_string()
+                .unwrap();
+            assert_eq!(first, second, "{path} is not stable");
+
+            // A trip through the RON format doesn't change the canonical form
+            let ron_path = std::env::temp_dir().join("blackjack_canonical_test.bjk");
+            SerializedBjkGraph::load_from_string(&first)
+                .unwrap()
+                .write_to_file(&ron_path)
+                .unwrap();
+            let third = SerializedBjkGraph::load_from_file(&ron_path)
+                .unwrap()
+                .to_canonical_string()
+                .unwrap();
+            assert_eq!(first, third, "{path} changed through RON");
+        }
+    }
+
+    #[test]
+    pub fn test_metadata_only_parse() {
+        let metadata = BjkMetadata {
+            version: SerializationVersion::latest(),
+            format: BjkFileFormat::Canonical,
+            thumbnail_png: Some((0..=255).collect()),
+            units: LengthUnit::Custom(0.25),
+        };
+        let mut contents = vec![];
+        metadata.to_writer(&mut contents).unwrap();
+        // The graph itself is never parsed, so it can be anything
+        contents.extend_from_slice(b"// BLACKJACK_METADATA some_future_key 42\n");
+        contents.extend_from_slice(b"(this is { not a graph");
+        assert_eq!(BjkMetadata::from_reader(&contents[..]).unwrap(), metadata);
+
+        let path = std::env::temp_dir().join("blackjack_metadata_test.bjk");
+        std::fs::write(&path, &contents).unwrap();
+        assert_eq!(BjkMetadata::load_from_file(&path).unwrap(), metadata);
+
+        // Existing files have a header without metadata
+        let metadata = BjkMetadata::load_from_file("../examples/box.bjk").unwrap();
+        assert_eq!(metadata.format, BjkFileFormat::Ron);
+        assert_eq!(metadata.thumbnail_png, None);
+        assert_eq!(metadata.units, LengthUnit::Meters);
+    }
+
+    #[test]
+    pub fn test_thumbnail_survives_serialization() {
+        let thumbnail = (0..1000).map(|i| (i * 7 % 256) as u8).collect_vec();
+        let mut graph = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        graph.thumbnail_png = Some(thumbnail.clone());
+
+        let canonical = graph.to_canonical_string().unwrap();
+        let loaded = SerializedBjkGraph::load_from_string(&canonical).unwrap();
+        assert_eq!(loaded.thumbnail_png.as_ref(), Some(&thumbnail));
+        assert_eq!(loaded.to_canonical_string().unwrap(), canonical);
+
+        let path = std::env::temp_dir().join("blackjack_thumbnail_test.bjk");
+        loaded.write_to_file(&path).unwrap();
+        let loaded = SerializedBjkGraph::load_from_file(&path).unwrap();
+        assert_eq!(loaded.thumbnail_png, Some(thumbnail));
+        assert!(loaded.into_runtime().is_ok());
+    }
+
+    #[test]
+    pub fn test_load_from_bytes() {
+        let bytes = std::fs::read("../examples/box.bjk").unwrap();
+        let from_bytes = SerializedBjkGraph::load_from_bytes(&bytes).unwrap();
+        let from_file = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        assert_eq!(from_bytes.file_path, None);
+        assert_eq!(
+            from_bytes.to_canonical_string().unwrap(),
+            from_file.to_canonical_string().unwrap()
+        );
+        assert!(SerializedBjkGraph::load_from_bytes(&[0xff, 0xfe, 0x00]).is_err());
+    }
+
+    #[test]
+    pub fn test_units_survive_serialization() {
+        let graph = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        assert_eq!(graph.units, LengthUnit::Meters);
+        let meters = graph.to_canonical_string().unwrap();
+        assert!(!meters.contains("BLACKJACK_METADATA units"));
+
+        let mut graph = SerializedBjkGraph::load_from_string(&meters).unwrap();
+        graph.units = LengthUnit::Centimeters;
+        let centimeters = graph.to_canonical_string().unwrap();
+        let loaded = SerializedBjkGraph::load_from_string(&centimeters).unwrap();
+        assert_eq!(loaded.units, LengthUnit::Centimeters);
+
+        let path = std::env::temp_dir().join("blackjack_units_test.bjk");
+        loaded.write_to_file(&path).unwrap();
+        let loaded = SerializedBjkGraph::load_from_file(&path).unwrap();
+        assert_eq!(loaded.units, LengthUnit::Centimeters);
+        // Only the unit changes, values are still stored in meters
+        assert_eq!(
+            centimeters.replace("// BLACKJACK_METADATA units centimeters\n", ""),
+            meters
+        );
+        let (runtime, _, _) = loaded.into_runtime().unwrap();
+        assert_eq!(runtime.graph.units, LengthUnit::Centimeters);
+    }
+
+    #[test]
+    pub fn test_viewport_lighting_survives_serialization() {
+        let mut graph = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        let lighting = SerializedViewportLighting {
+            key_azimuth: -30.0,
+            key_elevation: 40.0,
+            key_intensity: 0.8,
+            ambient: 0.25,
+            three_point: true,
+            ssao: true,
+            ssao_radius: 0.5,
+        };
+        let without_lighting = graph.to_canonical_string().unwrap();
+        assert!(!without_lighting.contains("viewport_lighting"));
+        graph.ui_data.as_mut().unwrap().viewport_lighting = Some(lighting);
+
+        let canonical = graph.to_canonical_string().unwrap();
+        let loaded = SerializedBjkGraph::load_from_string(&canonical).unwrap();
+        assert_eq!(loaded.ui_data.unwrap().viewport_lighting, Some(lighting));
+
+        let path = std::env::temp_dir().join("blackjack_lighting_test.bjk");
+        graph.write_to_file(&path).unwrap();
+        let loaded = SerializedBjkGraph::load_from_file(&path).unwrap();
+        let (_, ui_data, _) = loaded.into_runtime().unwrap();
+        assert_eq!(ui_data.unwrap().viewport_lighting, Some(lighting));
+    }
+
+    #[test]
+    pub fn test_int_and_bool_values() {
+        for value in [
+            BlackjackValue::Int(-3),
+            BlackjackValue::Bool(true),
+            BlackjackValue::Bool(false),
+        ] {
+            let serialized = SerializedBlackjackValue::from_runtime(value.clone()).unwrap();
+            let text = ron::to_string(&serialized).unwrap();
+            let parsed: SerializedBlackjackValue = ron::from_str(&text).unwrap();
+            assert_eq!(parsed.into_runtime(), value);
+        }
+        for data_type in [DataType::Int, DataType::Bool] {
+            assert_eq!(
+                deserialize_data_type(&serialize_data_type(data_type)),
+                Some(data_type)
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_vertex_deltas_values() {
+        let mut deltas = VertexDeltas::default();
+        deltas.add(7, glam::Vec3::new(0.5, -1.0, 0.0));
+        deltas.add(2, glam::Vec3::Z);
+        let value = BlackjackValue::VertexDeltas(deltas);
+
+        let serialized = SerializedBlackjackValue::from_runtime(value.clone()).unwrap();
+        assert_eq!(
+            serialized,
+            SerializedBlackjackValue::VertexDeltas(vec![
+                (2, glam::Vec3::Z),
+                (7, glam::Vec3::new(0.5, -1.0, 0.0))
+            ])
+        );
+        let text = ron::to_string(&serialized).unwrap();
+        let parsed: SerializedBlackjackValue = ron::from_str(&text).unwrap();
+        assert_eq!(parsed.into_runtime(), value);
+        assert_eq!(
+            deserialize_data_type(&serialize_data_type(DataType::VertexDeltas)),
+            Some(DataType::VertexDeltas)
+        );
+    }
+
+    #[test]
+    pub fn test_vertex_weights_values() {
+        let mut weights = VertexWeights::default();
+        weights.set(5, 0.25);
+        weights.set(1, 1.0);
+        let value = BlackjackValue::VertexWeights(weights);
+
+        let serialized = SerializedBlackjackValue::from_runtime(value.clone()).unwrap();
+        assert_eq!(
+            serialized,
+            SerializedBlackjackValue::VertexWeights(vec![(1, 1.0), (5, 0.25)])
+        );
+        let text = ron::to_string(&serialized).unwrap();
+        let parsed: SerializedBlackjackValue = ron::from_str(&text).unwrap();
+        assert_eq!(parsed.into_runtime(), value);
+        assert_eq!(
+            deserialize_data_type(&serialize_data_type(DataType::VertexWeights)),
+            Some(DataType::VertexWeights)
+        );
+    }
+
+    #[test]
+    pub fn test_curve_and_points_types() {
+        let mut graph = BjkGraph::new();
+        let line = graph.add_node("MakeLine", None);
+        graph.add_output(line, "out_mesh", DataType::Curve).unwrap();
+        let scatter = graph.add_node("PointCloud", None);
+        graph
+            .add_output(scatter, "out_mesh", DataType::Points)
+            .unwrap();
+        let copy = graph.add_node("CopyToPoints", Some("out_mesh".into()));
+        graph
+            .add_input(copy, "points", DataType::Points, None)
+            .unwrap();
+        graph.add_input(copy, "mesh", DataType::Mesh, None).unwrap();
+        graph.add_output(copy, "out_mesh", DataType::Mesh).unwrap();
+        graph
+            .add_connection(scatter, "out_mesh", copy, "points")
+            .unwrap();
+        graph
+            .add_connection(line, "out_mesh", copy, "mesh")
+            .unwrap();
+        // A plain mesh can't go into a points input
+        assert!(graph
+            .add_connection(copy, "out_mesh", copy, "points")
+            .is_err());
+
+        let (serialized, _) = SerializedBjkGraph::from_runtime(RuntimeData {
+            graph,
+            external_parameters: None,
+        })
+        .unwrap();
+        let text = serialized.to_canonical_string().unwrap();
+        assert!(text.contains("BJK_CURVE") && text.contains("BJK_POINTS"));
+
+        let (runtime, _, mappings) = SerializedBjkGraph::load_from_string(&text)
+            .unwrap()
+            .into_runtime()
+            .unwrap();
+        let node = |idx| &runtime.graph.nodes[mappings.get_id(idx).unwrap()];
+        assert_eq!(node(0).outputs[0].data_type, DataType::Curve);
+        assert_eq!(node(1).outputs[0].data_type, DataType::Points);
+        let copy = node(2);
+        assert_eq!(copy.inputs[0].data_type, DataType::Points);
+        assert!(matches!(
+            &copy.inputs[1].kind,
+            DependencyKind::Connection { param_name, .. } if param_name == "out_mesh"
+        ));
+    }
+
+    #[test]
+    pub fn test_expression_values() {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("MakeBox", Some("out_mesh".into()));
+        graph
+            .add_input(node, "size", DataType::Vector, Some("box_size".into()))
+            .unwrap();
+        graph
+            .add_input(node, "origin", DataType::Vector, None)
+            .unwrap();
+        let expr = BlackjackValue::Expression("vec(0, box_size.y / 2, 0)".into());
+        let mut params = ExternalParameterValues::default();
+        params.0.insert(
+            ExternalParameter::new(node, "size".into()),
+            BlackjackValue::Vector(glam::Vec3::ONE),
+        );
+        params
+            .0
+            .insert(ExternalParameter::new(node, "origin".into()), expr.clone());
+
+        let (serialized, _) = SerializedBjkGraph::from_runtime(RuntimeData {
+            graph,
+            external_parameters: Some(params),
+        })
+        .unwrap();
+        let text = serialized.to_canonical_string().unwrap();
+        assert!(text.contains("Expression(\"vec(0, box_size.y / 2, 0)\")"));
+
+        let (runtime, _, mappings) = SerializedBjkGraph::load_from_string(&text)
+            .unwrap()
+            .into_runtime()
+            .unwrap();
+        let node = mappings.get_id(0).unwrap();
+        let params = runtime.external_parameters.unwrap();
+        assert_eq!(
+            params.0[&ExternalParameter::new(node, "origin".into())],
+            expr
+        );
+        assert_eq!(
+            params.0[&ExternalParameter::new(node, "size".into())],
+            BlackjackValue::Vector(glam::Vec3::ONE)
+        );
+    }
+
+    #[test]
+    pub fn test_canonical_params_are_sorted() {
+        let mut contents = SerializedBjkGraph::load_from_file("../examples/box.bjk")
+            .unwrap()
+            .to_canonical_string()
+            .unwrap();
+        assert!(contents.contains("position: Some(("));
+        let graph = SerializedBjkGraph::load_from_string(&contents).unwrap();
+        let params = graph.external_parameters.unwrap().param_values;
+        assert!(!params.is_empty());
+        // The map is rebuilt in a different order on each load, but the
+        // output stays the same.
+        for _ in 0..4 {
+            let again = SerializedBjkGraph::load_from_string(&contents)
+                .unwrap()
+                .to_canonical_string()
+                .unwrap();
+            assert_eq!(contents, again);
+            contents = again;
+        }
+    }
+
+    #[test]
+    pub fn test_canonical_rejects_params_of_missing_nodes() {
+        let mut graph = SerializedBjkGraph::load_from_file("../examples/box.bjk").unwrap();
+        let missing_node = graph.nodes.len();
+        let values = &mut graph.external_parameters.as_mut().unwrap().param_values;
+        let (_, value) = values.iter().next().unwrap();
+        let value = value.clone();
+        values.insert(
+            SerializedParamLocation {
+                node_idx: missing_node,
+                param_name: "size".into(),
+            },
+            value,
+        );
+        let err = graph.to_canonical_string().unwrap_err().to_string();
+        assert!(err.contains("'size'"), "{err}");
+        assert!(err.contains(&format!("node {missing_node}")), "{err}");
+    }
 }
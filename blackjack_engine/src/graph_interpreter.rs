@@ -4,13 +4,37 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use mlua::{Table, ToLua};
+use std::cell::RefCell;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mlua::{FromLua, Table, ToLua};
 use slotmap::SecondaryMap;
 
-use crate::gizmos::BlackjackGizmo;
-use crate::graph::{BjkGraph, BjkNodeId, BlackjackValue, NodeDefinitions};
-use crate::lua_engine::{ProgramResult, RenderableThing};
+use crate::gizmos::{BlackjackGizmo, HandleGizmo};
+use crate::graph::expressions::{ExpressionScope, ExpressionTime};
+use crate::graph::{
+    BjkGraph, BjkNode, BjkNodeId, BlackjackValue, DataType, DependencyKind, NodeDefinition,
+    NodeDefinitions, PickedSelection,
+};
+use crate::lua_engine::{
+    lua_stdlib::lua_path::{self, ProjectContext},
+    mesh_generators, sandbox, ProgramResult, RenderableThing,
+};
+use crate::mesh::halfedge::selection::SelectionExpression;
 use crate::prelude::*;
+use crate::progress::{with_progress_sink, ProgressSink, Warning};
+
+/// Checks graphs for problems without running their ops
+pub mod dry_run;
+/// Cache nodes, which stop the evaluation of the graph once frozen
+pub mod frozen_cache;
+/// Stopping nodes predicted to allocate too much memory
+pub mod memory_budget;
+pub use memory_budget::MemoryBudgetExceeded;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct ExternalParameter {
@@ -30,17 +54,238 @@ impl ExternalParameter {
 #[derive(Debug, Default, Clone)]
 pub struct ExternalParameterValues(pub HashMap<ExternalParameter, BlackjackValue>);
 
+/// A flag that can be shared with a running graph execution to abort it. The
+/// interpreter checks the flag before running every node, and Lua code is
+/// interrupted as soon as it is set.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Signals the execution holding this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The error returned by `run_graph` when the execution is aborted through its
+/// [`CancellationToken`].
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionCancelled;
+
+impl std::fmt::Display for ExecutionCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Graph execution was cancelled")
+    }
+}
+impl std::error::Error for ExecutionCancelled {}
+
+/// The error returned by `run_graph` when the op of a node panics. Panics are
+/// bugs, but they only stop the execution: The Lua runtime can keep running
+/// graphs afterwards.
+#[derive(Debug, Clone)]
+pub struct NodePanicked {
+    pub node_id: BjkNodeId,
+    pub op_name: String,
+    /// The message the op panicked with.
+    pub message: String,
+}
+
+impl std::fmt::Display for NodePanicked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (node {}) crashed: {}\nThis is a bug in Blackjack, please report it.",
+            self.op_name,
+            self.node_id.display_id(),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for NodePanicked {}
+
+/// Returns the message of a panic, from its payload.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic".into()
+    }
+}
+
+/// Shares the progress of a running graph execution with other threads. Stores
+/// the node currently running, and its reported progress.
+#[derive(Debug, Default, Clone)]
+pub struct ExecutionProgress(Arc<Mutex<Option<(BjkNodeId, f32)>>>);
+
+impl ExecutionProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the node currently running and its progress, between 0 and 1.
+    /// Nodes that don't report any progress stay at zero.
+    pub fn current(&self) -> Option<(BjkNodeId, f32)> {
+        *self.0.lock().unwrap()
+    }
+
+    fn set(&self, value: Option<(BjkNodeId, f32)>) {
+        *self.0.lock().unwrap() = value;
+    }
+}
+
+/// The [`ProgressSink`] passed to the ops of every node execution. Forwards
+/// the reports to the [`ExecutionProgress`] and the cancellation requests from
+/// the [`CancellationToken`], when the execution has them.
+struct NodeProgress {
+    node_id: BjkNodeId,
+    progress: Option<ExecutionProgress>,
+    cancellation: Option<CancellationToken>,
+    warnings: RefCell<Vec<Warning>>,
+}
+
+impl ProgressSink for NodeProgress {
+    fn report(&self, progress: f32) {
+        if let Some(p) = &self.progress {
+            p.set(Some((self.node_id, progress)));
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .map_or(false, |c| c.is_cancelled())
+    }
+
+    fn warn(&self, warning: Warning) {
+        self.warnings.borrow_mut().push(warning);
+    }
+}
+
+/// Some statistics about a graph execution.
+#[derive(Debug, Default, Clone)]
+pub struct RunStats {
+    /// The number of nodes that had their `op` executed.
+    pub nodes_executed: usize,
+    /// The number of nodes that were bypassed instead, see
+    /// [`RunOptions::muted`].
+    pub nodes_bypassed: usize,
+    /// The number of nodes whose outputs were reused from an earlier run of
+    /// the same interpreter, see [`GraphInterpreter::finish_with_alternative`].
+    pub nodes_reused: usize,
+    /// The total wall time spent running the graph. Always zero on platforms
+    /// without a clock, like wasm32-unknown-unknown.
+    pub elapsed: Duration,
+    /// The warnings reported by the ops of the nodes, in the order they ran.
+    pub warnings: Vec<NodeWarning>,
+    /// The errors of the nodes with soft errors, which got default outputs
+    /// instead of stopping the execution. See
+    /// [`NodeDefinition::soft_errors`](crate::graph::NodeDefinition::soft_errors).
+    pub errors: Vec<NodeWarning>,
+    /// The estimated memory taken by the meshes the nodes returned, in bytes.
+    /// See [`HalfEdgeMesh::estimated_bytes`].
+    pub mesh_bytes: usize,
+}
+
+impl RunStats {
+    /// Returns the warnings reported by the given node.
+    pub fn node_warnings(&self, node_id: BjkNodeId) -> impl Iterator<Item = &Warning> {
+        self.warnings
+            .iter()
+            .filter(move |w| w.node_id == node_id)
+            .map(|w| &w.warning)
+    }
+
+    /// Returns the error of the given node, if it failed with a soft error.
+    pub fn node_error(&self, node_id: BjkNodeId) -> Option<&Warning> {
+        self.errors
+            .iter()
+            .find(|e| e.node_id == node_id)
+            .map(|e| &e.warning)
+    }
+}
+
+/// A [`Warning`] reported while running the op of a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeWarning {
+    pub node_id: BjkNodeId,
+    pub op_name: String,
+    pub warning: Warning,
+}
+
+impl NodeWarning {
+    /// Returns a line describing the warning and the node that reported it.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} (node {}): {}",
+            self.op_name,
+            self.node_id.display_id(),
+            self.warning.message
+        )
+    }
+}
+
+/// A minimal stopwatch that degrades to a no-op on wasm, where
+/// `std::time::Instant` is not available.
+struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        return Duration::ZERO;
+    }
+}
+
 pub struct InterpreterContext<'a, 'lua> {
     outputs_cache: HashMap<BjkNodeId, mlua::Table<'lua>>,
-    /// The values for all the external parameters. Mutable reference because
-    /// node gizmos may modify these values.
-    external_param_values: &'a mut ExternalParameterValues,
+    /// The values for all the external parameters. Node gizmos may modify
+    /// these values.
+    external_param_values: ExternalParameterValues,
     node_definitions: &'a NodeDefinitions,
     /// If not present, means all gizmo computations are skipped
     gizmo_state: Option<SecondaryMap<BjkNodeId, GizmoState>>,
     /// Stores the gizmo outputs for each node. This is not filled if
     /// gizmo_state is None.
-    gizmo_outputs: &'a mut SecondaryMap<BjkNodeId, Vec<BlackjackGizmo>>,
+    gizmo_outputs: SecondaryMap<BjkNodeId, Vec<BlackjackGizmo>>,
+    /// When set, the execution is aborted as soon as the token is cancelled.
+    cancellation: Option<&'a CancellationToken>,
+    /// When set, nodes report their progress here while running.
+    progress: Option<&'a ExecutionProgress>,
+    stats: RunStats,
+    /// The values of `t` and `frame` in parameter expressions.
+    time: ExpressionTime,
+    /// The mesh outputs that deform-only nodes produced by moving the
+    /// vertices of their input, without changing its topology.
+    deformed_outputs: HashSet<(BjkNodeId, String)>,
+    /// The nodes that are bypassed instead of running their op.
+    muted: HashSet<BjkNodeId>,
+    /// The frozen cache nodes that load their mesh from a file. The nodes
+    /// upstream of them don't run, see [`frozen_cache`].
+    frozen_caches: HashSet<BjkNodeId>,
+    /// See [`RunOptions::memory_budget`].
+    memory_budget: Option<usize>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -49,57 +294,620 @@ pub struct GizmoState {
     pub gizmos_changed: bool,
 }
 
+/// Runs `graph` up to `target_node`, and returns its result. To run a graph
+/// one node at a time, use a [`GraphInterpreter`].
 pub fn run_graph(
     lua: &mlua::Lua,
     graph: &BjkGraph,
     target_node: BjkNodeId,
-    mut external_param_values: ExternalParameterValues,
+    external_param_values: ExternalParameterValues,
     node_definitions: &NodeDefinitions,
     gizmos_state: Option<SecondaryMap<BjkNodeId, GizmoState>>,
 ) -> Result<ProgramResult> {
-    let gizmos_enabled = gizmos_state.is_some();
+    run_graph_cancellable(
+        lua,
+        graph,
+        target_node,
+        external_param_values,
+        node_definitions,
+        gizmos_state,
+        None,
+    )
+}
 
-    let mut gizmo_outputs = Default::default();
-    let mut context = InterpreterContext {
-        outputs_cache: Default::default(),
-        external_param_values: &mut external_param_values,
+/// Same as [`run_graph`], but the execution can be stopped early by cancelling
+/// the given `cancellation` token. A cancelled execution returns an
+/// [`ExecutionCancelled`] error.
+pub fn run_graph_cancellable(
+    lua: &mlua::Lua,
+    graph: &BjkGraph,
+    target_node: BjkNodeId,
+    external_param_values: ExternalParameterValues,
+    node_definitions: &NodeDefinitions,
+    gizmos_state: Option<SecondaryMap<BjkNodeId, GizmoState>>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<ProgramResult> {
+    run_graph_with_progress(
+        lua,
+        graph,
+        target_node,
+        external_param_values,
         node_definitions,
-        gizmo_state: gizmos_state,
-        gizmo_outputs: &mut gizmo_outputs,
-    };
+        gizmos_state,
+        cancellation,
+        None,
+    )
+}
+
+/// Same as [`run_graph_cancellable`], but the running node and its progress
+/// are published to the given `progress` while the graph runs. Ops that
+/// support it report their progress and stop early when cancelled.
+#[allow(clippy::too_many_arguments)]
+pub fn run_graph_with_progress(
+    lua: &mlua::Lua,
+    graph: &BjkGraph,
+    target_node: BjkNodeId,
+    external_param_values: ExternalParameterValues,
+    node_definitions: &NodeDefinitions,
+    gizmos_state: Option<SecondaryMap<BjkNodeId, GizmoState>>,
+    cancellation: Option<&CancellationToken>,
+    progress: Option<&ExecutionProgress>,
+) -> Result<ProgramResult> {
+    GraphInterpreter::run_iter(
+        lua,
+        graph,
+        target_node,
+        external_param_values,
+        node_definitions,
+        RunOptions {
+            gizmos_state,
+            cancellation,
+            progress,
+            ..Default::default()
+        },
+    )?
+    .finish()
+}
+
+/// The optional settings of a [`GraphInterpreter`].
+#[derive(Default)]
+pub struct RunOptions<'a> {
+    /// The state of the gizmos of each node. Gizmos only run when this is set.
+    pub gizmos_state: Option<SecondaryMap<BjkNodeId, GizmoState>>,
+    /// When set, the execution is aborted as soon as the token is cancelled.
+    pub cancellation: Option<&'a CancellationToken>,
+    /// When set, nodes report their progress here while running.
+    pub progress: Option<&'a ExecutionProgress>,
+    /// The values of `t` and `frame` in parameter expressions.
+    pub time: ExpressionTime,
+    /// Nodes to bypass in this run, without changing the graph. A bypassed
+    /// node doesn't run its op: Each of its outputs takes the value of its
+    /// first input of the same type, so meshes flow through it unchanged.
+    /// Outputs without such an input get their default value.
+    pub muted: HashSet<BjkNodeId>,
+    /// When set, the nodes that declare a cost hint don't run if their outputs
+    /// are predicted to take more than this many bytes. The execution fails
+    /// with a [`MemoryBudgetExceeded`] error instead. See [`memory_budget`].
+    pub memory_budget: Option<usize>,
+}
+
+/// A value passed to or returned by a node, as reported by a
+/// [`NodeStepResult`]. Meshes are summarized, since copying them at every step
+/// would be too slow. The full mesh can be requested from the step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepValue {
+    Value(BlackjackValue),
+    Mesh(MeshSummary),
+    /// Values with no [`BlackjackValue`] counterpart, like scenes, by the name
+    /// of their Lua type.
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshSummary {
+    pub num_vertices: usize,
+    pub num_faces: usize,
+    pub num_halfedges: usize,
+}
+
+impl StepValue {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> Self {
+        if let mlua::Value::UserData(ud) = &value {
+            if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+                let conn = mesh.read_connectivity();
+                return StepValue::Mesh(MeshSummary {
+                    num_vertices: conn.num_vertices(),
+                    num_faces: conn.num_faces(),
+                    num_halfedges: conn.num_halfedges(),
+                });
+            }
+        }
+        let type_name = value.type_name();
+        match BlackjackValue::from_lua(value, lua) {
+            Ok(value) => StepValue::Value(value),
+            Err(_) => StepValue::Other(type_name.into()),
+        }
+    }
+}
+
+/// The result of running a single node, as yielded by a [`GraphInterpreter`].
+pub struct NodeStepResult<'lua> {
+    pub node_id: BjkNodeId,
+    pub op_name: String,
+    /// The value of each input the op was called with, by name, after
+    /// connections, gizmos and picked selections were applied.
+    pub inputs: Vec<(String, StepValue)>,
+    /// The value of each output of the node, by name.
+    pub outputs: Vec<(String, StepValue)>,
+    /// The time spent running the node. Always zero on platforms without a
+    /// clock.
+    pub elapsed: Duration,
+    /// The warnings reported by the op of the node.
+    pub warnings: Vec<Warning>,
+    input_table: Table<'lua>,
+    output_table: Table<'lua>,
+}
+
+impl<'lua> NodeStepResult<'lua> {
+    /// Returns a copy of the mesh passed to the input called `name`, or `None`
+    /// when it is not a mesh.
+    pub fn input_mesh(&self, name: &str) -> Result<Option<HalfEdgeMesh>> {
+        mesh_in_table(&self.input_table, name)
+    }
+
+    /// Returns a copy of the mesh in the output called `name`, or `None` when
+    /// it is not a mesh.
+    pub fn output_mesh(&self, name: &str) -> Result<Option<HalfEdgeMesh>> {
+        mesh_in_table(&self.output_table, name)
+    }
+}
+
+fn mesh_in_table(table: &Table, name: &str) -> Result<Option<HalfEdgeMesh>> {
+    if let mlua::Value::UserData(ud) = table.get::<_, mlua::Value>(name)? {
+        if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+            return Ok(Some(mesh.clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// Runs a graph one node at a time. Each step runs the next node that the
+/// target node depends on, after all of its own dependencies, and yields its
+/// inputs and outputs. The target node runs last. After a step fails, the
+/// iterator yields nothing more.
+///
+/// Callers can stop at any step, and the remaining nodes never run. To get
+/// the result of the whole execution, use [`GraphInterpreter::finish`].
+///
+/// The steps hold values from the Lua runtime, so neither the interpreter nor
+/// its steps can outlive the borrow of the runtime. While the interpreter is
+/// alive, the runtime has the interrupt used for cancellation and the
+/// instruction limit installed, so any other Lua code run between steps
+/// counts towards the limit.
+pub struct GraphInterpreter<'a, 'lua> {
+    lua: &'lua mlua::Lua,
+    graph: &'a BjkGraph,
+    target_node: BjkNodeId,
+    ctx: InterpreterContext<'a, 'lua>,
+    project: ProjectContext,
+    /// The nodes left to run, in order.
+    schedule: std::vec::IntoIter<BjkNodeId>,
+    gizmos_enabled: bool,
+    failed: bool,
+    stopwatch: Stopwatch,
+}
+
+impl<'a, 'lua> GraphInterpreter<'a, 'lua> {
+    /// Prepares the execution of `graph` up to `target_node`. No node runs
+    /// until the first step.
+    pub fn run_iter(
+        lua: &'lua mlua::Lua,
+        graph: &'a BjkGraph,
+        target_node: BjkNodeId,
+        external_param_values: ExternalParameterValues,
+        node_definitions: &'a NodeDefinitions,
+        options: RunOptions<'a>,
+    ) -> Result<Self> {
+        let frozen_caches =
+            frozen_cache::frozen_caches(graph, &external_param_values, node_definitions);
+        let schedule = execution_order(graph, target_node, &frozen_caches)?;
+
+        // Exporters write the materials of the graph along with the meshes
+        crate::mesh::material::set_active_materials(lua, &graph.materials)?;
+        crate::units::set_active_unit(lua, graph.units)?;
+
+        // Interrupt any long-running Lua code when the execution gets
+        // cancelled, or when it runs out of instructions.
+        sandbox::begin_execution(lua, options.cancellation);
 
-    // Ensure the outputs cache is populated.
-    run_node(lua, graph, &mut context, target_node)?;
+        Ok(Self {
+            lua,
+            graph,
+            target_node,
+            gizmos_enabled: options.gizmos_state.is_some(),
+            ctx: InterpreterContext {
+                outputs_cache: Default::default(),
+                external_param_values,
+                node_definitions,
+                gizmo_state: options.gizmos_state,
+                gizmo_outputs: Default::default(),
+                cancellation: options.cancellation,
+                progress: options.progress,
+                stats: RunStats::default(),
+                time: options.time,
+                deformed_outputs: Default::default(),
+                muted: options.muted,
+                frozen_caches,
+                memory_budget: options.memory_budget,
+            },
+            // File parameters relative to the folder of the graph are resolved
+            // against it, and nodes can do the same with `Path.project_dir`.
+            project: ProjectContext::for_graph_file(graph.file_path.as_deref()),
+            schedule: schedule.into_iter(),
+            failed: false,
+            stopwatch: Stopwatch::start(),
+        })
+    }
+
+    /// The nodes that have not run yet, in the order they will run.
+    pub fn pending_nodes(&self) -> &[BjkNodeId] {
+        self.schedule.as_slice()
+    }
+
+    /// The statistics of the nodes run so far.
+    pub fn stats(&self) -> &RunStats {
+        &self.ctx.stats
+    }
+
+    /// Runs the remaining nodes, and returns the result of the execution.
+    pub fn finish(mut self) -> Result<ProgramResult> {
+        for step in &mut self {
+            step?;
+        }
+        self.result()
+    }
 
-    let renderable = if let Some(return_value) = &graph.nodes[target_node].return_value {
-        let output = context
+    /// Runs the remaining nodes like [`GraphInterpreter::finish`], and then
+    /// runs the graph again with every node in `group` toggled between
+    /// bypassed and not, to compare both variants of the graph. The second
+    /// run only runs the nodes of the group and the ones that depend on them,
+    /// the rest reuse their outputs from the first run. Gizmos only run in
+    /// the first one.
+    ///
+    /// Returns the results of both runs. When only the second run fails, the
+    /// first result is still returned.
+    pub fn finish_with_alternative(
+        mut self,
+        group: &HashSet<BjkNodeId>,
+    ) -> Result<(ProgramResult, Result<ProgramResult>)> {
+        for step in &mut self {
+            step?;
+        }
+        let primary = self.result()?;
+        let values = primary.updated_values.clone();
+        let alternative = self
+            .restart_toggling(group, values)
+            .and_then(|()| self.finish());
+        Ok((primary, alternative))
+    }
+
+    /// Prepares the interpreter to run the graph again, with the nodes in
+    /// `group` toggled between bypassed and not. Outputs of nodes that don't
+    /// depend on the group are kept, and only the rest are scheduled.
+    fn restart_toggling(
+        &mut self,
+        group: &HashSet<BjkNodeId>,
+        values: ExternalParameterValues,
+    ) -> Result<()> {
+        let order = execution_order(self.graph, self.target_node, &self.ctx.frozen_caches)?;
+        // Nodes run after their dependencies, so one pass finds everything
+        // downstream of the group.
+        let mut stale = HashSet::new();
+        for node_id in &order {
+            let node = &self.graph.nodes[*node_id];
+            let depends_on_stale = node.inputs.iter().any(|input| match &input.kind {
+                DependencyKind::Connection { node, .. } => stale.contains(node),
+                DependencyKind::External { .. } => false,
+            });
+            if group.contains(node_id) || depends_on_stale {
+                stale.insert(*node_id);
+            }
+        }
+        self.ctx
             .outputs_cache
-            .get(&target_node)
-            .expect("Final node should be in the outputs cache");
-        Some(RenderableThing::from_lua_value(
-            output.get(return_value.as_str())?,
-        )?)
-    } else {
-        None
-    };
+            .retain(|node, _| !stale.contains(node));
+        self.ctx
+            .deformed_outputs
+            .retain(|(node, _)| !stale.contains(node));
+        self.ctx.muted = self
+            .ctx
+            .muted
+            .symmetric_difference(group)
+            .copied()
+            .collect();
+        self.ctx.external_param_values = values;
+        self.ctx.gizmo_state = None;
+        self.gizmos_enabled = false;
+
+        let schedule = order
+            .iter()
+            .copied()
+            .filter(|node_id| stale.contains(node_id))
+            .collect_vec();
+        self.ctx.stats = RunStats {
+            nodes_reused: order.len() - schedule.len(),
+            ..Default::default()
+        };
+        self.schedule = schedule.into_iter();
+        self.failed = false;
+        self.stopwatch = Stopwatch::start();
+        Ok(())
+    }
 
-    Ok(ProgramResult {
-        renderable,
-        updated_gizmos: if gizmos_enabled {
-            Some(gizmo_outputs)
+    /// Returns the result of the execution, once every node has run.
+    fn result(&mut self) -> Result<ProgramResult> {
+        let target_outputs = self
+            .ctx
+            .outputs_cache
+            .get(&self.target_node)
+            .ok_or_else(|| anyhow!("The graph execution stopped before the final node"))?;
+
+        let renderable = match &self.graph.nodes[self.target_node].return_value {
+            Some(return_value) => Some(RenderableThing::from_lua_value(
+                target_outputs.get(return_value.as_str())?,
+            )?),
+            None => None,
+        };
+        let positions_only = match &self.graph.nodes[self.target_node].return_value {
+            Some(return_value) => self
+                .ctx
+                .deformed_outputs
+                .contains(&(self.target_node, return_value.clone())),
+            None => false,
+        };
+
+        let mut stats = std::mem::take(&mut self.ctx.stats);
+        stats.elapsed = self.stopwatch.elapsed();
+
+        Ok(ProgramResult {
+            renderable,
+            positions_only,
+            updated_gizmos: if self.gizmos_enabled {
+                Some(std::mem::take(&mut self.ctx.gizmo_outputs))
+            } else {
+                None
+            },
+            updated_values: std::mem::take(&mut self.ctx.external_param_values),
+            stats,
+            mesh_stats: None,
+        })
+    }
+}
+
+impl<'a, 'lua> Iterator for GraphInterpreter<'a, 'lua> {
+    type Item = Result<NodeStepResult<'lua>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let node_id = self.schedule.next()?;
+        let cancellation = self.ctx.cancellation;
+        let is_cancelled = || cancellation.map_or(false, |c| c.is_cancelled());
+
+        let result = if is_cancelled() {
+            Err(ExecutionCancelled.into())
         } else {
-            None
-        },
-        updated_values: external_param_values,
-    })
+            let (lua, graph, ctx) = (self.lua, self.graph, &mut self.ctx);
+            // A panicking op can leave the meshes it was editing half-done,
+            // but nothing else: Borrows of meshes and channels are released
+            // while unwinding, mlua restores the Lua stack, and the thread
+            // locals set for the node are restored too. So the context stays
+            // usable once the outputs the node could have touched are gone.
+            let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                lua_path::with_project(&self.project, || run_node(lua, graph, ctx, node_id))
+            }));
+            match run {
+                Ok(result) => result,
+                Err(payload) => {
+                    forget_outputs_up_to(ctx, graph, node_id);
+                    Err(NodePanicked {
+                        node_id,
+                        op_name: graph.nodes[node_id].op_name.clone(),
+                        message: panic_message(payload.as_ref()),
+                    }
+                    .into())
+                }
+            }
+        };
+        Some(result.map_err(|err| {
+            self.failed = true;
+            // Ops stopped by a cancelled progress sink fail with an error like
+            // any other, but the execution was simply aborted.
+            if is_cancelled() || err.is::<crate::progress::Cancelled>() {
+                ExecutionCancelled.into()
+            } else {
+                err
+            }
+        }))
+    }
+}
+
+impl<'a, 'lua> Drop for GraphInterpreter<'a, 'lua> {
+    fn drop(&mut self) {
+        sandbox::end_execution(self.lua);
+        if let Some(progress) = self.ctx.progress {
+            progress.set(None);
+        }
+    }
+}
+
+/// Drops the cached outputs of `node_id` and of all the nodes it depends on.
+/// Nodes edit their input meshes in place, so after a panic these can't be
+/// trusted anymore.
+fn forget_outputs_up_to(ctx: &mut InterpreterContext, graph: &BjkGraph, node_id: BjkNodeId) {
+    let upstream: HashSet<BjkNodeId> = execution_order(graph, node_id, &ctx.frozen_caches)
+        .unwrap_or_else(|_| vec![node_id])
+        .into_iter()
+        .collect();
+    ctx.outputs_cache.retain(|node, _| !upstream.contains(node));
+    ctx.deformed_outputs
+        .retain(|(node, _)| !upstream.contains(node));
+}
+
+/// Returns the nodes that `target_node` depends on, followed by itself, in
+/// the order they run: Depth first, following the inputs of each node in
+/// order, and with every node after its dependencies. The dependencies of
+/// the `frozen_caches` are left out, since they don't need to run.
+fn execution_order(
+    graph: &BjkGraph,
+    target_node: BjkNodeId,
+    frozen_caches: &HashSet<BjkNodeId>,
+) -> Result<Vec<BjkNodeId>> {
+    fn visit(
+        graph: &BjkGraph,
+        node_id: BjkNodeId,
+        frozen_caches: &HashSet<BjkNodeId>,
+        visiting: &mut HashSet<BjkNodeId>,
+        order: &mut Vec<BjkNodeId>,
+    ) -> Result<()> {
+        if order.contains(&node_id) {
+            return Ok(());
+        }
+        if !visiting.insert(node_id) {
+            bail!(
+                "The graph has a cycle going through node {}",
+                node_id.display_id()
+            );
+        }
+        let node = graph
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| anyhow!("Node {} is not in the graph", node_id.display_id()))?;
+        if !frozen_caches.contains(&node_id) {
+            for input in &node.inputs {
+                if let DependencyKind::Connection { node, .. } = &input.kind {
+                    visit(graph, *node, frozen_caches, visiting, order)?;
+                }
+            }
+        }
+        order.push(node_id);
+        Ok(())
+    }
+
+    let mut order = vec![];
+    visit(
+        graph,
+        target_node,
+        frozen_caches,
+        &mut HashSet::new(),
+        &mut order,
+    )?;
+    Ok(order)
+}
+
+/// The mesh input of a deform-only node, replaced by a copy made with
+/// [`HalfEdgeMesh::clone_for_deform`]. See
+/// [`NodeDefinition::deform_only`](crate::graph::NodeDefinition::deform_only).
+struct DeformedInput<'lua> {
+    /// The name of the input.
+    name: String,
+    /// The mesh the input had before it was replaced.
+    upstream: mlua::AnyUserData<'lua>,
+    /// The element counts of the upstream mesh.
+    counts: (usize, usize, usize),
+}
+
+impl<'lua> DeformedInput<'lua> {
+    /// Replaces the first mesh input of `node` in the `input_map` with a copy
+    /// for its op to deform. The copy gets its own `position` channel, and the
+    /// channels the node declares as produced. Returns `None` when the input
+    /// has no mesh.
+    fn prepare(
+        node: &BjkNode,
+        node_def: &NodeDefinition,
+        input_map: &Table<'lua>,
+    ) -> Result<Option<Self>> {
+        let name = match node.inputs.iter().find(|i| i.data_type.is_mesh()) {
+            Some(input) => input.name.clone(),
+            None => return Ok(None),
+        };
+        let upstream = match input_map.get::<_, mlua::Value>(name.as_str())? {
+            mlua::Value::UserData(ud) if ud.is::<HalfEdgeMesh>() => ud,
+            _ => return Ok(None),
+        };
+
+        let mut written = vec![];
+        for declaration in node_def.channels.iter().flat_map(|c| &c.produces) {
+            match &declaration.name {
+                crate::graph::ChannelName::Literal(channel) => {
+                    written.push(ChannelName::new(channel));
+                }
+                crate::graph::ChannelName::Param(param) => {
+                    if let Some(channel) = input_map.get::<_, Option<String>>(param.as_str())? {
+                        if !channel.is_empty() {
+                            written.push(ChannelName::new(&channel));
+                        }
+                    }
+                }
+            }
+        }
+
+        let (copy, counts) = {
+            let mesh = upstream.borrow::<HalfEdgeMesh>()?;
+            let counts = mesh.read_connectivity().element_counts();
+            (mesh.clone_for_deform(&written), counts)
+        };
+        input_map.set(name.as_str(), copy)?;
+        Ok(Some(Self {
+            name,
+            upstream,
+            counts,
+        }))
+    }
+
+    /// Returns whether any of the mesh outputs of `node` has a different
+    /// number of elements than the upstream mesh.
+    fn changed_topology(&self, node: &BjkNode, outputs: &Table<'lua>) -> Result<bool> {
+        for output in node.outputs.iter().filter(|o| o.data_type.is_mesh()) {
+            if let mlua::Value::UserData(ud) =
+                outputs.get::<_, mlua::Value>(output.name.as_str())?
+            {
+                if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+                    if mesh.read_connectivity().element_counts() != self.counts {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns a regular clone of the upstream mesh.
+    fn full_copy(&self) -> Result<HalfEdgeMesh> {
+        Ok(self.upstream.borrow::<HalfEdgeMesh>()?.clone())
+    }
+
+    /// Returns whether `mesh` still shares the connectivity of the upstream
+    /// mesh, so it has the same topology.
+    fn kept_topology(&self, mesh: &HalfEdgeMesh) -> bool {
+        // The borrow fails when `mesh` is the upstream mesh itself
+        self.upstream
+            .borrow::<HalfEdgeMesh>()
+            .map(|upstream| mesh.shares_connectivity(&upstream))
+            .unwrap_or(false)
+    }
 }
 
+/// Runs the op of `node_id`, along with its gizmos, and stores its outputs
+/// in the cache of `ctx`. The nodes it depends on must have run already.
 pub fn run_node<'lua>(
     lua: &'lua mlua::Lua,
     graph: &BjkGraph,
     ctx: &mut InterpreterContext<'_, 'lua>,
     node_id: BjkNodeId,
-) -> Result<()> {
+) -> Result<NodeStepResult<'lua>> {
+    let stopwatch = Stopwatch::start();
     let node = &graph.nodes[node_id];
     let op_name = &node.op_name;
     let node_def = ctx
@@ -118,19 +926,20 @@ pub fn run_node<'lua>(
         None
     };
 
-    // Compute the values for dependent nodes and populate the output cache.
+    // Take the values of connected inputs from the outputs cache. Frozen
+    // caches get no values from the nodes upstream of them, which didn't run.
+    let is_frozen_cache = ctx.frozen_caches.contains(&node_id);
     for input in &node.inputs {
         match &input.kind {
+            crate::graph::DependencyKind::Connection { .. } if is_frozen_cache => {}
             crate::graph::DependencyKind::Connection { node, param_name } => {
-                // Make sure the value is there by running the node.
-                let cached_output_map = if let Some(cached) = ctx.outputs_cache.get(node) {
-                    cached
-                } else {
-                    run_node(lua, graph, ctx, *node)?;
-                    ctx.outputs_cache
-                        .get(node)
-                        .expect("Cache should be populated after calling run_node.")
-                };
+                let cached_output_map = ctx.outputs_cache.get(node).ok_or_else(|| {
+                    anyhow!(
+                        "Node {} should run before node {}",
+                        node.display_id(),
+                        node_id.display_id()
+                    )
+                })?;
 
                 input_map.set(
                     input.name.as_str(),
@@ -146,14 +955,89 @@ pub fn run_node<'lua>(
                         node_id.display_id(),
                     )
                 })?;
-                input_map.set(input.name.as_str(), val.clone().to_lua(lua)?)?;
-                if let Some(m) = &mut referenced_external_params {
+                let is_expression = matches!(val, BlackjackValue::Expression(_));
+                let val = match val {
+                    BlackjackValue::String(path) if node_def.is_file_path(&input.name) => {
+                        BlackjackValue::String(lua_path::resolve_project_path(path)?)
+                    }
+                    // Evaluated on every run, leaving the expression in place
+                    BlackjackValue::Expression(_) => {
+                        ExpressionScope::new(graph, &ctx.external_param_values, ctx.time)
+                            .resolve(&ext, input.data_type)?
+                    }
+                    val => val.clone(),
+                };
+                input_map.set(input.name.as_str(), val.to_lua(lua)?)?;
+                // Picked selections may be remapped below. Gizmos don't edit
+                // selections, so they are left out to avoid writing the
+                // remapped value back to the parameter. The same goes for
+                // expressions, which would be replaced by their result.
+                if let (Some(m), None, false) = (
+                    &mut referenced_external_params,
+                    &input.picked_from,
+                    is_expression,
+                ) {
                     m.push(ext);
                 }
             }
         }
     }
 
+    // Selections picked in the viewport refer to the ids of the mesh returned
+    // by some upstream node. When the ops in between reported how they changed
+    // those ids, the selection is translated so it keeps pointing to the same
+    // elements.
+    for input in &node.inputs {
+        if let Some(picked) = &input.picked_from {
+            let ext = ExternalParameter::new(node_id, input.name.clone());
+            if let Some(BlackjackValue::Selection(_, Some(selection))) =
+                ctx.external_param_values.0.get(&ext)
+            {
+                if let Some(remapped) = remap_picked_selection(&input_map, node, picked, selection)?
+                {
+                    input_map.set(input.name.as_str(), remapped)?;
+                }
+            }
+        }
+    }
+
+    if ctx.muted.contains(&node_id) {
+        let outputs = bypass_outputs(lua, node, &input_map)?;
+        ctx.outputs_cache.insert(node_id, outputs.clone());
+        ctx.stats.nodes_bypassed += 1;
+        return Ok(NodeStepResult {
+            node_id,
+            op_name: op_name.clone(),
+            inputs: step_values(lua, &input_map, node.inputs.iter().map(|i| &i.name))?,
+            outputs: step_values(lua, &outputs, node.outputs.iter().map(|o| &o.name))?,
+            elapsed: stopwatch.elapsed(),
+            warnings: vec![],
+            input_table: input_map,
+            output_table: outputs,
+        });
+    }
+
+    // Curves and point clouds are meshes with a restricted structure. It's
+    // checked here, so the ops declaring them don't find out halfway through.
+    for input in &node.inputs {
+        let check = match input.data_type {
+            DataType::Curve => edit_ops::check_curve,
+            DataType::Points => edit_ops::check_points,
+            _ => continue,
+        };
+        if let mlua::Value::UserData(ud) = input_map.get::<_, mlua::Value>(input.name.as_str())? {
+            if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+                check(&mesh).with_context(|| {
+                    format!(
+                        "Invalid input '{}' of node {}",
+                        input.name,
+                        node_id.display_id()
+                    )
+                })?;
+            }
+        }
+    }
+
     // This special value is injected into the inputs to signal nodes that the
     // gizmos are being processed. This is useful to let nodes optimize out
     // parts of the computation when they're running on a game engine.
@@ -161,14 +1045,52 @@ pub fn run_node<'lua>(
         input_map.set("__gizmos_enabled", true)?;
     }
 
+    // Each node gets its own seed, to be used as the stream of its random
+    // number generators. Only the top 53 bits are kept, so the value survives
+    // the conversion to a Lua number. The `inputs:rng()` helper, defined in
+    // the node library, builds a generator from it.
+    input_map.set(
+        "__node_seed",
+        (crate::random::node_seed(graph.seed, node) >> 11) as f64,
+    )?;
+
+    // Cache nodes get the file they store their mesh in, and functions to
+    // write and read it. They're missing when the graph was never saved, or
+    // the node has an invalid name.
+    if node_def.cache {
+        if let Ok(file) = frozen_cache::node_cache_file(graph, &ctx.external_param_values, node_id)
+        {
+            input_map.set("__cache_file", file.to_string_lossy().as_ref())?;
+            input_map.set(
+                "__load_cache",
+                frozen_cache::load_function(lua, file.clone())?,
+            )?;
+            input_map.set("__store_cache", frozen_cache::store_function(lua, file)?)?;
+        }
+    }
+
+    input_map.set_metatable(
+        lua.load("require('node_library').inputs_metatable")
+            .eval::<Option<mlua::Table>>()?,
+    );
+
     let node_table = lua
         .load(&(format!("require('node_library'):getNode('{op_name}')")))
         .eval::<mlua::Table>()?;
 
-    struct GizmoFns<'lua> {
-        update_params_fn: mlua::Function<'lua>,
-        update_gizmos_fn: mlua::Function<'lua>,
-        affected_params_fn: mlua::Function<'lua>,
+    enum GizmoFns<'lua> {
+        /// An entry of a node's `gizmos` table
+        Table {
+            update_params_fn: mlua::Function<'lua>,
+            update_gizmos_fn: mlua::Function<'lua>,
+            affected_params_fn: mlua::Function<'lua>,
+        },
+        /// A node's `gizmos` function, returning handle descriptors, and its
+        /// optional `on_gizmo_changed` function.
+        Handles {
+            gizmos_fn: mlua::Function<'lua>,
+            on_gizmo_changed_fn: Option<mlua::Function<'lua>>,
+        },
     }
 
     struct GizmoDescriptor<'lua> {
@@ -185,6 +1107,19 @@ pub fn run_node<'lua>(
                 // needs the data from its own gizmos and all nodes are run
                 // exactly once.
                 if let Some(gizmo_data) = gizmos_state.remove(node_id) {
+                    // Nodes defining `gizmos` as a function show a single
+                    // gizmo, with all the handles that function returns.
+                    if let mlua::Value::Function(gizmos_fn) = node_table.get("gizmos")? {
+                        return Ok(vec![GizmoDescriptor {
+                            data: gizmo_data.active_gizmos.and_then(|v| v.into_iter().next()),
+                            gizmos_changed: gizmo_data.gizmos_changed,
+                            fns: GizmoFns::Handles {
+                                gizmos_fn,
+                                on_gizmo_changed_fn: node_table.get("on_gizmo_changed")?,
+                            },
+                        }]);
+                    }
+
                     let gizmos_table: mlua::Table = node_table
                         .get("gizmos")
                         .map_err(|err| anyhow!("Expected node to have gizmos table. {err}"))?;
@@ -205,7 +1140,7 @@ pub fn run_node<'lua>(
                         gizmo_descriptors.push(GizmoDescriptor {
                             data: gizmo_data.active_gizmos.as_ref().map(|v| v[i].clone()),
                             gizmos_changed: gizmo_data.gizmos_changed,
-                            fns: GizmoFns {
+                            fns: GizmoFns::Table {
                                 update_params_fn: get_fn!("update_params"),
                                 update_gizmos_fn: get_fn!("update_gizmos"),
                                 affected_params_fn: get_fn!("affected_params"),
@@ -221,15 +1156,18 @@ pub fn run_node<'lua>(
 
     // When a gizmo affects parameters, the gizmo will be disabled if all the
     // parameters are connected. Nodes can return nil from the `affected_params`
-    // function to disable this behavior.
+    // function to disable this behavior. Handles bound to a connected parameter
+    // are left out after running the node instead.
     let enabled_gizmos = gizmo_descriptors
         .iter()
         .map(|descr| {
-            if let Some(affected_params) = descr
-                .fns
-                .affected_params_fn
-                .call::<_, Option<Vec<String>>>(())?
-            {
+            let affected_params_fn = match &descr.fns {
+                GizmoFns::Table {
+                    affected_params_fn, ..
+                } => affected_params_fn,
+                GizmoFns::Handles { .. } => return Ok(true),
+            };
+            if let Some(affected_params) = affected_params_fn.call::<_, Option<Vec<String>>>(())? {
                 for input in node.inputs.iter() {
                     if affected_params.contains(&input.name) {
                         match &input.kind {
@@ -251,9 +1189,7 @@ pub fn run_node<'lua>(
             GizmoDescriptor {
                 gizmos_changed: true,
                 data: Some(gizmo_in),
-                fns: GizmoFns {
-                    update_params_fn, ..
-                },
+                fns,
             },
             true,
         ) = it
@@ -261,14 +1197,49 @@ pub fn run_node<'lua>(
             // Update params
             // Patch the input map, running the gizmo function
             let input_gizmo = gizmo_in.clone().to_lua(lua)?;
-            let new_input_map = update_params_fn
-                .call::<_, Table>((input_map, input_gizmo))
-                .map_err(|err| {
-                    anyhow!(
-                        "A node's update_params gizmo callback should return an updated parameter list as a table. {err}"
-                    )
-                })?;
-            input_map = new_input_map;
+            match fns {
+                GizmoFns::Table {
+                    update_params_fn, ..
+                } => {
+                    let new_input_map = update_params_fn
+                        .call::<_, Table>((input_map, input_gizmo))
+                        .map_err(|err| {
+                            anyhow!(
+                                "A node's update_params gizmo callback should return an updated parameter list as a table. {err}"
+                            )
+                        })?;
+                    input_map = new_input_map;
+                }
+                GizmoFns::Handles {
+                    on_gizmo_changed_fn,
+                    ..
+                } => {
+                    // Without an `on_gizmo_changed` function, each handle
+                    // sets the parameter it's bound to.
+                    let updates = match on_gizmo_changed_fn {
+                        Some(on_gizmo_changed_fn) => on_gizmo_changed_fn
+                            .call::<_, Table>((input_map.clone(), input_gizmo))
+                            .map_err(|err| {
+                                anyhow!(
+                                    "A node's on_gizmo_changed function should return a table with the updated parameters. {err}"
+                                )
+                            })?,
+                        None => {
+                            let updates = lua.create_table()?;
+                            if let BlackjackGizmo::Handles(handles) = gizmo_in {
+                                for handle in handles {
+                                    updates.set(handle.param(), handle.bound_value())?;
+                                }
+                            }
+                            updates
+                        }
+                    };
+                    for pair in updates.pairs::<mlua::Value, mlua::Value>() {
+                        let (param, value) = pair?;
+                        input_map.set(param, value)?;
+                    }
+                }
+            }
 
             // Write the inputs that were returned to lua back to the
             // external_parameter_values in the context. This will then be sent
@@ -278,8 +1249,16 @@ pub fn run_node<'lua>(
                 .as_ref()
                 .expect("When gizmos run, this should be defined");
             for param in referenced_external_params.iter() {
+                let data_type = node
+                    .inputs
+                    .iter()
+                    .find(|input| input.name == param.param_name)
+                    .map(|input| input.data_type)
+                    .expect("Referenced params are inputs of the node");
                 let new_val = input_map
                     .get::<_, BlackjackValue>(param.param_name.clone())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|value| value.convert_to(data_type))
                     .map_err(|err| {
                         anyhow!(
                         "The gizmos input function modified a parameter in an illegal way: {err}"
@@ -293,18 +1272,139 @@ pub fn run_node<'lua>(
         }
     }
 
+    // Deform-only nodes move the vertices of a copy of their mesh that shares
+    // everything else with the upstream mesh.
+    let mut deformed_input = if node_def.deform_only {
+        DeformedInput::prepare(node, &node_def, &input_map)?
+    } else {
+        None
+    };
+
+    // Nodes that make their meshes larger check that their outputs fit in
+    // the budget, after the gizmos had the chance to change their parameters.
+    if let (Some(budget), Some(hint)) = (ctx.memory_budget, &node_def.cost) {
+        memory_budget::check_budget(node, node_id, hint, &input_map, budget)?;
+    }
+
     // Run node 'op'
     let op_fn: mlua::Function = node_table
         .get("op")
         .map_err(|err| anyhow!("Node should always have an 'op'. {err}"))?;
-    let outputs = match op_fn.call(input_map.clone())? {
-        mlua::Value::Table(t) => t,
-        other => {
+    if let Some(progress) = ctx.progress {
+        progress.set(Some((node_id, 0.0)));
+    }
+    let (progress, cancellation) = (ctx.progress, ctx.cancellation);
+    let call_op = |input_map: &Table<'lua>| {
+        let sink = Rc::new(NodeProgress {
+            node_id,
+            progress: progress.cloned(),
+            cancellation: cancellation.cloned(),
+            warnings: RefCell::new(vec![]),
+        });
+        // Mesh outputs can be coroutines that generate the mesh in chunks.
+        // They run here so they can report progress and warnings too.
+        let op_result = with_progress_sink(sink.clone(), || {
+            let outputs = op_fn.call::<_, mlua::Value>(input_map.clone())?;
+            if let mlua::Value::Table(outputs) = &outputs {
+                mesh_generators::drive_mesh_outputs(lua, node, outputs)?;
+            }
+            Ok(outputs)
+        });
+        (op_result, sink.warnings.take())
+    };
+    let (mut op_result, mut warnings) = call_op(&input_map);
+    if node_def.cache && op_result.is_ok() {
+        warnings.extend(update_cache_fingerprint(graph, ctx, node_id)?);
+    }
+
+    // Debug builds check that deform-only nodes kept the topology of their
+    // mesh. When they didn't, they run again on a full copy of the mesh.
+    let changed_topology = match (&deformed_input, &op_result) {
+        (Some(deformed), Ok(mlua::Value::Table(outputs))) if cfg!(debug_assertions) => {
+            deformed.changed_topology(node, outputs)?
+        }
+        _ => false,
+    };
+    if changed_topology {
+        if let Some(deformed) = deformed_input.take() {
+            input_map.set(deformed.name.as_str(), deformed.full_copy()?)?;
+            let (rerun_result, rerun_warnings) = call_op(&input_map);
+            op_result = rerun_result;
+            warnings = std::iter::once(Warning::new(format!(
+                "{op_name} is declared as deform-only, but it changed the topology of its \
+                 mesh. It had to run again on a full copy of the mesh."
+            )))
+            .chain(rerun_warnings)
+            .collect();
+        }
+    }
+    ctx.stats
+        .warnings
+        .extend(warnings.iter().map(|warning| NodeWarning {
+            node_id,
+            op_name: op_name.clone(),
+            warning: warning.clone(),
+        }));
+    let is_cancelled = ctx.cancellation.map_or(false, |c| c.is_cancelled());
+    let outputs = match op_result {
+        Ok(mlua::Value::Table(t)) => t,
+        Ok(other) => {
             bail!("A node's `op` function should always return a table, got {other:?}");
         }
+        Err(err) if node_def.soft_errors && !is_cancelled && !aborts_execution(&err) => {
+            ctx.stats.errors.push(NodeWarning {
+                node_id,
+                op_name: op_name.clone(),
+                warning: Warning::new(err.to_string()),
+            });
+            default_outputs(lua, node)?
+        }
+        Err(err) => return Err(err.into()),
     };
 
+    // Record that the output meshes come from this node, so selections picked
+    // against them can be remapped further down the graph. Their channels are
+    // also synced, so the elements the op created get the default values.
+    // Meshes that kept the topology of the upstream mesh are synced already.
+    for output in node.outputs.iter().filter(|o| o.data_type.is_mesh()) {
+        if let mlua::Value::UserData(ud) = outputs.get::<_, mlua::Value>(output.name.as_str())? {
+            if let Ok(mut mesh) = ud.borrow_mut::<HalfEdgeMesh>() {
+                mesh.lineage_mut().push_output(node_id);
+                let deformed = deformed_input
+                    .as_ref()
+                    .map(|deformed| deformed.kept_topology(&mesh))
+                    .unwrap_or(false);
+                if deformed {
+                    ctx.deformed_outputs.insert((node_id, output.name.clone()));
+                } else {
+                    edit_ops::sync_channels(&mesh).with_context(|| {
+                        format!("Syncing the channels of output '{}'", output.name)
+                    })?;
+                }
+                debug_assert!(
+                    edit_ops::channels_in_sync(&mesh),
+                    "The channels of output '{}' of {op_name} are out of sync",
+                    output.name
+                );
+                ctx.stats.mesh_bytes += mesh.estimated_bytes();
+            }
+        }
+    }
+
+    // Lua only has one kind of number, so integer outputs are truncated here
+    // before other nodes see them.
+    for output in node.outputs.iter().filter(|o| o.data_type == DataType::Int) {
+        let value = outputs.get::<_, BlackjackValue>(output.name.as_str())?;
+        if let BlackjackValue::Scalar(_) = value {
+            let converted = value.convert_to(DataType::Int).with_context(|| {
+                format!("Invalid value for output '{}' of {op_name}", output.name)
+            })?;
+            outputs.set(output.name.as_str(), converted)?;
+        }
+    }
+
     ctx.outputs_cache.insert(node_id, outputs.clone());
+    ctx.stats.nodes_executed += 1;
 
     // Run post-gizmo
     for (gz_descr, enabled) in gizmo_descriptors.iter_mut().zip(&enabled_gizmos) {
@@ -317,13 +1417,50 @@ pub fn run_node<'lua>(
                     .transpose()?
                     .unwrap_or(mlua::Value::Nil);
 
-                gz_descr
-                    .fns
-                    .update_gizmos_fn
-                    .call::<_, BlackjackGizmo>((input_map.clone(), gizmo, outputs.clone()))
-                    .map_err(|err| {
-                        anyhow!("A node's gizmo outputs function should return a new gizmo. {err}")
-                    })
+                match &gz_descr.fns {
+                    GizmoFns::Table {
+                        update_gizmos_fn, ..
+                    } => update_gizmos_fn
+                        .call::<_, BlackjackGizmo>((input_map.clone(), gizmo, outputs.clone()))
+                        .map_err(|err| {
+                            anyhow!(
+                                "A node's gizmo outputs function should return a new gizmo. {err}"
+                            )
+                        }),
+                    GizmoFns::Handles { gizmos_fn, .. } => {
+                        // The handles are computed from the first mesh the node returned
+                        let out_mesh = node
+                            .outputs
+                            .iter()
+                            .find(|output| output.data_type.is_mesh())
+                            .map(|output| outputs.get::<_, mlua::Value>(output.name.as_str()))
+                            .transpose()?
+                            .unwrap_or(mlua::Value::Nil);
+                        let handles = gizmos_fn
+                            .call::<_, Vec<HandleGizmo>>((input_map.clone(), out_mesh))
+                            .map_err(|err| {
+                                anyhow!(
+                                    "A node's gizmos function should return a list of handles. {err}"
+                                )
+                            })?;
+                        // Dragging a handle can't change a connected parameter
+                        let is_external = |param: &str| {
+                            node.inputs.iter().any(|input| {
+                                input.name == param
+                                    && matches!(
+                                        input.kind,
+                                        crate::graph::DependencyKind::External { .. }
+                                    )
+                            })
+                        };
+                        Ok(BlackjackGizmo::Handles(
+                            handles
+                                .into_iter()
+                                .filter(|handle| is_external(handle.param()))
+                                .collect(),
+                        ))
+                    }
+                }
             })
             .transpose()?;
         ctx.gizmo_outputs
@@ -336,5 +1473,160 @@ pub fn run_node<'lua>(
             .push(updated_gizmo.unwrap_or(BlackjackGizmo::None));
     }
 
-    Ok(())
+    Ok(NodeStepResult {
+        node_id,
+        op_name: op_name.clone(),
+        inputs: step_values(lua, &input_map, node.inputs.iter().map(|i| &i.name))?,
+        outputs: step_values(lua, &outputs, node.outputs.iter().map(|o| &o.name))?,
+        elapsed: stopwatch.elapsed(),
+        warnings,
+        input_table: input_map,
+        output_table: outputs,
+    })
+}
+
+/// Updates the fingerprint stored in the cache node `node_id` after its op
+/// ran: A frozen cache that just stored its mesh remembers the fingerprint of
+/// the nodes upstream of it, and an unfrozen one forgets it. Returns a
+/// warning when a cache that loaded its mesh is stale.
+fn update_cache_fingerprint(
+    graph: &BjkGraph,
+    ctx: &mut InterpreterContext,
+    node_id: BjkNodeId,
+) -> Result<Option<Warning>> {
+    let values = &ctx.external_param_values;
+    let stored = frozen_cache::stored_fingerprint(values, node_id).map(|f| f.to_owned());
+    let updated = if !frozen_cache::is_frozen(values, node_id) {
+        String::new()
+    } else {
+        let current = frozen_cache::upstream_fingerprint(
+            graph,
+            values,
+            ctx.node_definitions,
+            &ctx.muted,
+            node_id,
+        )?;
+        if ctx.frozen_caches.contains(&node_id) {
+            return Ok((stored.as_deref() != Some(current.as_str())).then(|| {
+                Warning::new(
+                    "The cached mesh is stale: The nodes upstream of this cache changed \
+                     since it was frozen. Refresh it to run them again.",
+                )
+            }));
+        }
+        current
+    };
+    if stored.unwrap_or_default() != updated {
+        let fingerprint = ExternalParameter::new(node_id, frozen_cache::FINGERPRINT_PARAM.into());
+        if let Some(value) = ctx.external_param_values.0.get_mut(&fingerprint) {
+            *value = BlackjackValue::String(updated);
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the values in `table` for each of the `names`, to report them in a
+/// [`NodeStepResult`].
+fn step_values<'a, 'lua>(
+    lua: &'lua mlua::Lua,
+    table: &Table<'lua>,
+    names: impl Iterator<Item = &'a String>,
+) -> Result<Vec<(String, StepValue)>> {
+    names
+        .map(|name| {
+            let value = table.get::<_, mlua::Value>(name.as_str())?;
+            Ok((name.clone(), StepValue::from_lua(value, lua)))
+        })
+        .collect()
+}
+
+/// Returns the outputs of a bypassed `node`, given the values of its inputs.
+/// Each output takes the value of the first input with the same type, and
+/// the rest get their default value, see [`RunOptions::muted`].
+fn bypass_outputs<'lua>(
+    lua: &'lua mlua::Lua,
+    node: &BjkNode,
+    input_map: &Table<'lua>,
+) -> Result<Table<'lua>> {
+    let outputs = default_outputs(lua, node)?;
+    for output in &node.outputs {
+        if let Some(input) = node
+            .inputs
+            .iter()
+            .find(|input| input.data_type == output.data_type)
+        {
+            let value = input_map.get::<_, mlua::Value>(input.name.as_str())?;
+            if !matches!(value, mlua::Value::Nil) {
+                outputs.set(output.name.as_str(), value)?;
+            }
+        }
+    }
+    Ok(outputs)
+}
+
+/// Returns whether `err` stops the whole execution, even for nodes with soft
+/// errors: Cancellations, and running out of instructions or memory.
+fn aborts_execution(err: &mlua::Error) -> bool {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => aborts_execution(cause),
+        mlua::Error::ExternalError(err) => {
+            err.is::<ExecutionCancelled>()
+                || err.is::<sandbox::InstructionLimitExceeded>()
+                || err.is::<crate::progress::Cancelled>()
+        }
+        mlua::Error::MemoryError(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns a table with the default value for each output of `node`, used
+/// instead of the outputs of an op that failed with a soft error. Mesh
+/// outputs get an empty mesh, so the nodes after it can still run.
+fn default_outputs<'lua>(lua: &'lua mlua::Lua, node: &BjkNode) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    for output in &node.outputs {
+        let value = match output.data_type {
+            data_type if data_type.is_mesh() => HalfEdgeMesh::new().to_lua(lua)?,
+            data_type => data_type.default_value().to_lua(lua)?,
+        };
+        table.set(output.name.as_str(), value)?;
+    }
+    Ok(table)
+}
+
+/// Translates a `selection` picked against the output of an upstream node into
+/// the ids of the first mesh input of `node` that comes from that output.
+/// Returns `None` when none of the mesh inputs do.
+fn remap_picked_selection(
+    input_map: &Table,
+    node: &BjkNode,
+    picked: &PickedSelection,
+    selection: &SelectionExpression,
+) -> Result<Option<SelectionExpression>> {
+    for input in node.inputs.iter().filter(|i| i.data_type.is_mesh()) {
+        if let mlua::Value::UserData(ud) = input_map.get::<_, mlua::Value>(input.name.as_str())? {
+            if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+                let remapped = mesh
+                    .lineage()
+                    .remap_selection(picked.node, selection, picked.kind);
+                if remapped.is_some() {
+                    return Ok(remapped);
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Ops that misbehave on purpose, to test how the interpreter copes with them.
+#[cfg(any(test, feature = "test_ops"))]
+#[blackjack_macros::blackjack_lua_module]
+mod test_ops {
+    use crate::prelude::*;
+
+    /// Panics with the given `message`.
+    #[lua(under = "Ops")]
+    pub fn test_panic(message: String) -> Result<()> {
+        panic!("{message}")
+    }
 }
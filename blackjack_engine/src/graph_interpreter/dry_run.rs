@@ -0,0 +1,600 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks a graph without running any of its ops. Walks every node of the
+//! graph and reports its unconnected inputs, mismatched data types and, when
+//! the node definitions declare them, the channels that won't be there when
+//! the graph runs. Along the way, it estimates how large the meshes flowing
+//! through each node get, from the cost hints of the node definitions.
+//!
+//! A connection is only valid when the input accepts the type of the output,
+//! see [`DataType::accepts`].
+
+use std::collections::BTreeSet;
+
+use slotmap::SecondaryMap;
+
+use crate::graph::expressions::{ExpressionScope, ExpressionTime};
+use crate::graph::{
+    BjkGraph, BjkNodeId, BlackjackValue, ChannelDeclaration, ChannelName, DataType, DependencyKind,
+    NodeDefinition, NodeDefinitions,
+};
+use crate::prelude::*;
+
+use super::{ExternalParameter, ExternalParameterValues};
+
+/// Nodes whose meshes grow this many times larger than the ones at the start
+/// of the graph get a warning.
+pub const SLOW_NODE_RELATIVE_SIZE: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The node may not produce what's expected, or may be slow.
+    Warning,
+    /// The node will fail to run.
+    Error,
+}
+
+/// A problem found in one of the nodes of the graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRunProblem {
+    pub node: BjkNodeId,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// A value that goes from the output of a node to the input of another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataFlow {
+    pub src_node: BjkNodeId,
+    pub src_param: String,
+    pub dst_node: BjkNodeId,
+    pub dst_param: String,
+    pub data_type: DataType,
+}
+
+/// The estimated cost of running a node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostEstimate {
+    /// How many times larger the node makes its input meshes, according to
+    /// its cost hint. `None` when the hint depends on a parameter that is
+    /// connected to another node, so its value is not known.
+    pub factor: Option<f32>,
+    /// The size of the meshes produced by the node, relative to the size of
+    /// the meshes created at the start of the graph. Unknown factors count as
+    /// 1, and nodes with several mesh inputs add up their sizes.
+    pub relative_size: f32,
+}
+
+/// The result of a [`dry_run`].
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    pub problems: Vec<DryRunProblem>,
+    /// All the connections in the graph with valid data types.
+    pub flows: Vec<DataFlow>,
+    pub costs: SecondaryMap<BjkNodeId, CostEstimate>,
+}
+
+impl DryRunReport {
+    pub fn has_errors(&self) -> bool {
+        self.problems.iter().any(|p| p.severity == Severity::Error)
+    }
+
+    /// Returns the problems found in `node`.
+    pub fn problems_for(&self, node: BjkNodeId) -> impl Iterator<Item = &DryRunProblem> {
+        self.problems.iter().filter(move |p| p.node == node)
+    }
+}
+
+/// The channels known to exist in a mesh. Entries without a key come from
+/// nodes that can write to channels of any key type.
+type KnownChannels = BTreeSet<(Option<ChannelKeyType>, String)>;
+
+/// What the dry run knows about the meshes produced by a node.
+#[derive(Clone)]
+struct MeshFlow {
+    /// `None` when the channels can't be known without running the graph.
+    channels: Option<KnownChannels>,
+    relative_size: f32,
+}
+
+struct DryRun<'a> {
+    graph: &'a BjkGraph,
+    node_definitions: &'a NodeDefinitions,
+    params: &'a ExternalParameterValues,
+    report: DryRunReport,
+    visited: SecondaryMap<BjkNodeId, MeshFlow>,
+    in_progress: HashSet<BjkNodeId>,
+}
+
+/// Checks all the nodes of `graph`, with the parameter values in `params`,
+/// without running their ops.
+pub fn dry_run(
+    graph: &BjkGraph,
+    node_definitions: &NodeDefinitions,
+    params: &ExternalParameterValues,
+) -> DryRunReport {
+    let mut dry_run = DryRun {
+        graph,
+        node_definitions,
+        params,
+        report: DryRunReport::default(),
+        visited: SecondaryMap::new(),
+        in_progress: HashSet::new(),
+    };
+    for node_id in graph.nodes.keys() {
+        dry_run.visit(node_id);
+    }
+    dry_run.report
+}
+
+fn key_name(key: ChannelKeyType) -> &'static str {
+    match key {
+        ChannelKeyType::VertexId => "vertex",
+        ChannelKeyType::FaceId => "face",
+        ChannelKeyType::HalfEdgeId => "halfedge",
+    }
+}
+
+impl<'a> DryRun<'a> {
+    fn problem(&mut self, node: BjkNodeId, severity: Severity, message: String) {
+        self.report.problems.push(DryRunProblem {
+            node,
+            severity,
+            message,
+        });
+    }
+
+    /// Returns the value of the `param` of `node`, when it's not connected to
+    /// another node.
+    fn param_value(&self, node: BjkNodeId, param: &str) -> Option<&'a BlackjackValue> {
+        self.params
+            .0
+            .get(&ExternalParameter::new(node, param.to_owned()))
+    }
+
+    /// Returns the name of a declared channel, or `None` if it isn't known.
+    /// Names taken from empty parameters are `Some("")`.
+    fn channel_name(&self, node: BjkNodeId, decl: &ChannelDeclaration) -> Option<String> {
+        match &decl.name {
+            ChannelName::Literal(name) => Some(name.clone()),
+            ChannelName::Param(param) => match self.param_value(node, param) {
+                Some(BlackjackValue::String(name)) => Some(name.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    fn visit(&mut self, node_id: BjkNodeId) -> MeshFlow {
+        let unknown = MeshFlow {
+            channels: None,
+            relative_size: 1.0,
+        };
+        if let Some(flow) = self.visited.get(node_id) {
+            return flow.clone();
+        }
+        if !self.in_progress.insert(node_id) {
+            self.problem(
+                node_id,
+                Severity::Error,
+                "The node depends on its own output".into(),
+            );
+            return unknown;
+        }
+
+        let graph = self.graph;
+        let node = &graph.nodes[node_id];
+        let node_def = match self.node_definitions.node_def(&node.op_name) {
+            Some(def) => NodeDefinition::clone(&def),
+            None => {
+                let message = format!("Node definition not found for {}", node.op_name);
+                self.problem(node_id, Severity::Error, message);
+                self.in_progress.remove(&node_id);
+                self.visited.insert(node_id, unknown.clone());
+                return unknown;
+            }
+        };
+
+        let mut upstream_meshes = vec![];
+        for input_def in &node_def.inputs {
+            let name = &input_def.name;
+            let input = match node.inputs.iter().find(|i| &i.name == name) {
+                Some(input) => input,
+                None => {
+                    self.problem(node_id, Severity::Error, format!("Missing input '{name}'"));
+                    continue;
+                }
+            };
+            if input.data_type != input_def.data_type {
+                let message = format!(
+                    "Input '{name}' is {:?}, but the node definition expects {:?}",
+                    input.data_type, input_def.data_type
+                );
+                self.problem(node_id, Severity::Error, message);
+                continue;
+            }
+
+            match &input.kind {
+                DependencyKind::Connection {
+                    node: src,
+                    param_name,
+                } => {
+                    let output = graph.nodes.get(*src).and_then(|src_node| {
+                        src_node.outputs.iter().find(|o| &o.name == param_name)
+                    });
+                    let output = match output {
+                        Some(output) => output,
+                        None => {
+                            let message = format!(
+                                "Input '{name}' is connected to an output that doesn't exist"
+                            );
+                            self.problem(node_id, Severity::Error, message);
+                            continue;
+                        }
+                    };
+                    if !input.data_type.accepts(output.data_type) {
+                        let message = format!(
+                            "Input '{name}' is {:?}, but it's connected to '{param_name}' of {}, \
+                             which is {:?}",
+                            input.data_type, graph.nodes[*src].op_name, output.data_type
+                        );
+                        self.problem(node_id, Severity::Error, message);
+                        continue;
+                    }
+                    self.report.flows.push(DataFlow {
+                        src_node: *src,
+                        src_param: param_name.clone(),
+                        dst_node: node_id,
+                        dst_param: name.clone(),
+                        data_type: input.data_type,
+                    });
+                    let flow = self.visit(*src);
+                    if input.data_type.is_mesh() {
+                        upstream_meshes.push(flow);
+                    }
+                }
+                DependencyKind::External { .. } => {
+                    // Only data types that hold no value are valid with `None`
+                    if input.data_type.is_valid_value(&BlackjackValue::None) {
+                        let message = format!("Input '{name}' is not connected");
+                        self.problem(node_id, Severity::Error, message);
+                        continue;
+                    }
+                    match self.param_value(node_id, name) {
+                        Some(BlackjackValue::Expression(_)) => {
+                            let param = ExternalParameter::new(node_id, name.clone());
+                            let scope = ExpressionScope::new(
+                                self.graph,
+                                self.params,
+                                ExpressionTime::default(),
+                            );
+                            if let Err(err) = scope.resolve(&param, input.data_type) {
+                                let message = format!("Input '{name}': {err}");
+                                self.problem(node_id, Severity::Error, message);
+                            }
+                        }
+                        Some(value) if input.data_type.is_valid_value(value) => {}
+                        Some(_) => {
+                            let message = format!("Input '{name}' has an invalid value");
+                            self.problem(node_id, Severity::Error, message);
+                        }
+                        None => {
+                            let message = format!("Input '{name}' has no value");
+                            self.problem(node_id, Severity::Error, message);
+                        }
+                    }
+                }
+            }
+        }
+
+        let flow = MeshFlow {
+            channels: self.output_channels(node_id, &node_def, &upstream_meshes),
+            relative_size: self.estimate_cost(node_id, &node_def, &upstream_meshes),
+        };
+        self.in_progress.remove(&node_id);
+        self.visited.insert(node_id, flow.clone());
+        flow
+    }
+
+    /// Checks the channels required by the node, and returns the ones its
+    /// output meshes will have.
+    fn output_channels(
+        &mut self,
+        node_id: BjkNodeId,
+        node_def: &NodeDefinition,
+        upstream_meshes: &[MeshFlow],
+    ) -> Option<KnownChannels> {
+        let declarations = node_def.channels.as_ref()?;
+
+        // The channels of all the input meshes, when they can be known
+        let mut channels = KnownChannels::new();
+        channels.insert((Some(ChannelKeyType::VertexId), "position".into()));
+        for upstream in upstream_meshes {
+            channels.extend(upstream.channels.as_ref()?.iter().cloned());
+        }
+
+        for decl in &declarations.requires {
+            let name = match self.channel_name(node_id, decl) {
+                Some(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+            // Channels written by nodes that don't know their key may have
+            // any of them.
+            let found = channels.iter().any(|(key, n)| {
+                n == &name && (decl.key.is_none() || key.is_none() || *key == decl.key)
+            });
+            if !found {
+                let message = match decl.key {
+                    Some(key) => format!(
+                        "The input mesh has no {} channel named '{name}'",
+                        key_name(key)
+                    ),
+                    None => format!("The input mesh has no channel named '{name}'"),
+                };
+                self.problem(node_id, Severity::Warning, message);
+            }
+        }
+
+        for decl in &declarations.produces {
+            match self.channel_name(node_id, decl) {
+                Some(name) if name.is_empty() => {}
+                Some(name) => {
+                    channels.insert((decl.key, name));
+                }
+                // Any downstream channel could be this one
+                None => return None,
+            }
+        }
+        Some(channels)
+    }
+
+    /// Stores the cost estimate for the node, and returns its relative size.
+    fn estimate_cost(
+        &mut self,
+        node_id: BjkNodeId,
+        node_def: &NodeDefinition,
+        upstream_meshes: &[MeshFlow],
+    ) -> f32 {
+        let factor = match &node_def.cost {
+            Some(hint) => {
+                let exponent = hint.exponent.as_ref().and_then(|param| {
+                    match self.param_value(node_id, param) {
+                        Some(BlackjackValue::Scalar(exponent)) => Some(*exponent),
+                        Some(BlackjackValue::Int(exponent)) => Some(*exponent as f32),
+                        _ => None,
+                    }
+                });
+                hint.factor(exponent)
+            }
+            None => Some(1.0),
+        };
+        let input_size = if upstream_meshes.is_empty() {
+            1.0
+        } else {
+            upstream_meshes.iter().map(|m| m.relative_size).sum()
+        };
+        let relative_size = input_size * factor.unwrap_or(1.0);
+        if relative_size >= SLOW_NODE_RELATIVE_SIZE && node_def.cost.is_some() {
+            let message = format!(
+                "This node may be slow: Its output is about {relative_size:.0} times larger \
+                 than the meshes at the start of the graph"
+            );
+            self.problem(node_id, Severity::Warning, message);
+        }
+        self.report.costs.insert(
+            node_id,
+            CostEstimate {
+                factor,
+                relative_size,
+            },
+        );
+        relative_size
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lua_engine::LuaRuntime;
+
+    /// Adds a node for `op_name`, with the inputs and outputs of its
+    /// definition, and the default value for all of its parameters.
+    fn add_node(
+        graph: &mut BjkGraph,
+        params: &mut ExternalParameterValues,
+        defs: &NodeDefinitions,
+        op_name: &str,
+    ) -> BjkNodeId {
+        let def = defs.node_def(op_name).unwrap();
+        let node = graph.add_node(op_name, def.returns.clone());
+        for input in &def.inputs {
+            graph
+                .add_input(node, &input.name, input.data_type, None)
+                .unwrap();
+            params.0.insert(
+                ExternalParameter::new(node, input.name.clone()),
+                input.default_value(),
+            );
+        }
+        for output in &def.outputs {
+            graph
+                .add_output(node, &output.name, output.data_type)
+                .unwrap();
+        }
+        node
+    }
+
+    fn set_param(
+        params: &mut ExternalParameterValues,
+        node: BjkNodeId,
+        name: &str,
+        value: BlackjackValue,
+    ) {
+        params
+            .0
+            .insert(ExternalParameter::new(node, name.into()), value);
+    }
+
+    fn messages(report: &DryRunReport, node: BjkNodeId) -> Vec<(Severity, String)> {
+        report
+            .problems_for(node)
+            .map(|p| (p.severity, p.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_missing_connection() {
+        let rt = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &rt.node_definitions;
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let subdivide = add_node(&mut graph, &mut params, defs, "Subdivide");
+
+        let report = dry_run(&graph, defs, &params);
+        assert!(report.has_errors());
+        assert_eq!(
+            messages(&report, subdivide),
+            vec![(Severity::Error, "Input 'mesh' is not connected".into())]
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let rt = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &rt.node_definitions;
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let cube = add_node(&mut graph, &mut params, defs, "MakeBox");
+        let expr = |src: &str| BlackjackValue::Expression(src.into());
+
+        set_param(&mut params, cube, "origin", expr("vec(1, 2, 3) * 2"));
+        assert!(!dry_run(&graph, defs, &params).has_errors());
+
+        set_param(&mut params, cube, "origin", expr("vec(1, 2"));
+        set_param(&mut params, cube, "size", expr("missing * 2"));
+        let report = dry_run(&graph, defs, &params);
+        let mut problems = messages(&report, cube);
+        problems.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    Severity::Error,
+                    "Input 'origin': Invalid expression for MakeBox.origin: Expected ')' at \
+                     position 9"
+                        .into()
+                ),
+                (
+                    Severity::Error,
+                    "Input 'size': There is no parameter named 'missing'".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let rt = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &rt.node_definitions;
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let cube = add_node(&mut graph, &mut params, defs, "MakeBox");
+        let subdivide = add_node(&mut graph, &mut params, defs, "Subdivide");
+        graph
+            .add_connection(cube, "out_mesh", subdivide, "mesh")
+            .unwrap();
+        // `add_connection` rejects this, but graphs loaded from older files
+        // can still have it.
+        let iterations = graph.nodes[subdivide]
+            .inputs
+            .iter_mut()
+            .find(|i| i.name == "iterations")
+            .unwrap();
+        iterations.kind = DependencyKind::Connection {
+            node: cube,
+            param_name: "out_mesh".into(),
+        };
+
+        let report = dry_run(&graph, defs, &params);
+        let problems = messages(&report, subdivide);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, Severity::Error);
+        assert!(problems[0].1.contains("which is Mesh"), "{}", problems[0].1);
+        // Only the valid connection is reported as a flow
+        assert_eq!(report.flows.len(), 1);
+        assert_eq!(report.flows[0].dst_param, "mesh");
+    }
+
+    #[test]
+    fn test_missing_channel() {
+        let rt = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &rt.node_definitions;
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let cube = add_node(&mut graph, &mut params, defs, "MakeBox");
+        let sample = add_node(&mut graph, &mut params, defs, "SampleImage");
+        graph
+            .add_connection(cube, "out_mesh", sample, "mesh")
+            .unwrap();
+        set_param(
+            &mut params,
+            sample,
+            "image_path",
+            BlackjackValue::String("image.png".into()),
+        );
+
+        let report = dry_run(&graph, defs, &params);
+        assert!(!report.has_errors());
+        assert_eq!(
+            messages(&report, sample),
+            vec![(
+                Severity::Warning,
+                "The input mesh has no channel named 'uv'".into()
+            )]
+        );
+
+        // Once the mesh has UVs, the warning goes away
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let cube = add_node(&mut graph, &mut params, defs, "MakeBox");
+        let uvs = add_node(&mut graph, &mut params, defs, "SetFullRangeUVs");
+        let sample = add_node(&mut graph, &mut params, defs, "SampleImage");
+        graph.add_connection(cube, "out_mesh", uvs, "mesh").unwrap();
+        graph
+            .add_connection(uvs, "out_mesh", sample, "mesh")
+            .unwrap();
+        let report = dry_run(&graph, defs, &params);
+        assert_eq!(messages(&report, sample), vec![]);
+    }
+
+    #[test]
+    fn test_cost_estimates() {
+        let rt = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &rt.node_definitions;
+        let (mut graph, mut params) = (BjkGraph::new(), ExternalParameterValues::default());
+        let cube = add_node(&mut graph, &mut params, defs, "MakeBox");
+        let subdivide = add_node(&mut graph, &mut params, defs, "Subdivide");
+        graph
+            .add_connection(cube, "out_mesh", subdivide, "mesh")
+            .unwrap();
+        set_param(
+            &mut params,
+            subdivide,
+            "iterations",
+            BlackjackValue::Scalar(3.0),
+        );
+
+        let report = dry_run(&graph, defs, &params);
+        assert_eq!(report.costs[cube].relative_size, 1.0);
+        assert_eq!(report.costs[subdivide].factor, Some(64.0));
+        assert_eq!(report.costs[subdivide].relative_size, 64.0);
+        assert!(report.problems.is_empty());
+
+        set_param(
+            &mut params,
+            subdivide,
+            "iterations",
+            BlackjackValue::Scalar(6.0),
+        );
+        let report = dry_run(&graph, defs, &params);
+        assert_eq!(messages(&report, subdivide)[0].0, Severity::Warning);
+    }
+}
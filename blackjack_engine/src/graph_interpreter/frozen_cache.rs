@@ -0,0 +1,250 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cache nodes, which store the mesh flowing through them so the nodes
+//! upstream of them don't need to run again.
+//!
+//! A frozen cache node writes its input mesh to a snapshot file next to the
+//! graph, see [`crate::mesh::halfedge::snapshot`], and remembers the
+//! fingerprint of the nodes upstream of it in its `fingerprint` parameter.
+//! From then on, the evaluation of the graph stops at the cache node, which
+//! loads the mesh from the file instead. When the upstream nodes change, the
+//! stored fingerprint doesn't match anymore and the node warns that its cache
+//! is stale. Clearing the fingerprint, or unfreezing the node, makes the
+//! upstream nodes run again.
+//!
+//! The snapshot is written and read by the engine rather than by Lua file
+//! ops, so cache nodes keep working in sandboxed mode. The node only gets
+//! functions that store and load its mesh in its own file, see
+//! [`store_function`] and [`load_function`].
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use mlua::{AnyUserData, Function, Lua};
+
+use crate::graph::serialization::SerializedBlackjackValue;
+use crate::graph::{BjkGraph, BjkNodeId, BlackjackValue, DependencyKind, NodeDefinitions};
+use crate::lua_engine::ToLuaError;
+use crate::mesh::halfedge::snapshot::{load_mesh_snapshot, save_mesh_snapshot};
+use crate::prelude::*;
+
+use super::{ExternalParameter, ExternalParameterValues};
+
+/// The boolean parameter of cache nodes that freezes them.
+pub const FROZEN_PARAM: &str = "frozen";
+/// The string parameter naming the file of a cache node, see [`cache_file`].
+pub const NAME_PARAM: &str = "name";
+/// The string parameter where cache nodes store the fingerprint of the nodes
+/// upstream of them, in hexadecimal. Empty while nothing is cached.
+pub const FINGERPRINT_PARAM: &str = "fingerprint";
+/// The extension of the files cache nodes store their mesh in.
+pub const CACHE_FILE_EXTENSION: &str = "bjkmesh";
+
+/// Returns the file where the cache node with the given `name` stores its
+/// mesh, next to the `graph_file`: `<graph>.<name>.bjkmesh`. Graphs that were
+/// never saved have nowhere to store it.
+pub fn cache_file(graph_file: Option<&Path>, name: &str) -> Result<PathBuf> {
+    let graph_file = graph_file
+        .ok_or_else(|| anyhow!("The graph must be saved before freezing a cache node"))?;
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid cache name '{name}', it should be a plain file name");
+    }
+    let stem = graph_file
+        .file_stem()
+        .ok_or_else(|| anyhow!("Invalid graph file {}", graph_file.display()))?;
+    let mut file_name = stem.to_os_string();
+    file_name.push(format!(".{name}.{CACHE_FILE_EXTENSION}"));
+    Ok(graph_file.with_file_name(file_name))
+}
+
+/// Returns the value of the parameter `param` of `node_id`, when it's set.
+fn param<'a>(
+    values: &'a ExternalParameterValues,
+    node_id: BjkNodeId,
+    param: &str,
+) -> Option<&'a BlackjackValue> {
+    values.0.get(&ExternalParameter::new(node_id, param.into()))
+}
+
+/// Returns whether the cache node `node_id` is frozen.
+pub fn is_frozen(values: &ExternalParameterValues, node_id: BjkNodeId) -> bool {
+    matches!(
+        param(values, node_id, FROZEN_PARAM),
+        Some(BlackjackValue::Bool(true))
+    )
+}
+
+/// Returns the fingerprint stored in the cache node `node_id`, if any.
+pub fn stored_fingerprint(values: &ExternalParameterValues, node_id: BjkNodeId) -> Option<&str> {
+    match param(values, node_id, FINGERPRINT_PARAM) {
+        Some(BlackjackValue::String(fingerprint)) if !fingerprint.is_empty() => {
+            Some(fingerprint.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the file of the cache node `node_id` in `graph`, see
+/// [`cache_file`].
+pub fn node_cache_file(
+    graph: &BjkGraph,
+    values: &ExternalParameterValues,
+    node_id: BjkNodeId,
+) -> Result<PathBuf> {
+    match param(values, node_id, NAME_PARAM) {
+        Some(BlackjackValue::String(name)) => cache_file(graph.file_path.as_deref(), name),
+        _ => bail!("Cache node {} has no name", node_id.display_id()),
+    }
+}
+
+/// Returns a Lua function that saves the mesh it is called with to the cache
+/// `file`. This is the only way cache nodes can write their file: the path is
+/// fixed by the engine, so it can be given to sandboxed code.
+pub fn store_function(lua: &Lua, file: PathBuf) -> mlua::Result<Function<'_>> {
+    lua.create_function(move |_, mesh: AnyUserData| {
+        let mesh = mesh.borrow::<HalfEdgeMesh>()?;
+        save_mesh_snapshot(&mesh, &file).map_lua_err()
+    })
+}
+
+/// Returns a Lua function that loads the mesh stored in the cache `file`, see
+/// [`store_function`].
+pub fn load_function(lua: &Lua, file: PathBuf) -> mlua::Result<Function<'_>> {
+    lua.create_function(move |_, ()| load_mesh_snapshot(&file).map_lua_err())
+}
+
+/// Returns the cache nodes of `graph` that can provide their mesh without
+/// running the nodes upstream of them: They are frozen, they stored a
+/// fingerprint, and their file is there.
+pub fn frozen_caches(
+    graph: &BjkGraph,
+    values: &ExternalParameterValues,
+    node_definitions: &NodeDefinitions,
+) -> HashSet<BjkNodeId> {
+    graph
+        .nodes
+        .iter()
+        .filter(
+            |(_, node)| matches!(node_definitions.node_def(&node.op_name), Some(def) if def.cache),
+        )
+        .map(|(node_id, _)| node_id)
+        .filter(|node_id| {
+            is_frozen(values, *node_id)
+                && stored_fingerprint(values, *node_id).is_some()
+                && matches!(node_cache_file(graph, values, *node_id), Ok(file) if file.is_file())
+        })
+        .collect()
+}
+
+/// Returns the fingerprint of the nodes upstream of `node_id`, which changes
+/// when any of them would produce a different result: When their parameters,
+/// connections, versions or seeds change, or when they get bypassed. Files
+/// read by the upstream nodes are not looked at.
+pub fn upstream_fingerprint(
+    graph: &BjkGraph,
+    values: &ExternalParameterValues,
+    node_definitions: &NodeDefinitions,
+    muted: &HashSet<BjkNodeId>,
+    node_id: BjkNodeId,
+) -> Result<String> {
+    let mut fingerprinter = Fingerprinter {
+        graph,
+        values,
+        node_definitions,
+        muted,
+        visited: HashMap::new(),
+    };
+    let mut hasher = DefaultHasher::new();
+    fingerprinter.hash_inputs(node_id, &mut hasher)?;
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+struct Fingerprinter<'a> {
+    graph: &'a BjkGraph,
+    values: &'a ExternalParameterValues,
+    node_definitions: &'a NodeDefinitions,
+    muted: &'a HashSet<BjkNodeId>,
+    /// The fingerprints of the nodes hashed so far, or `None` while they are
+    /// being hashed.
+    visited: HashMap<BjkNodeId, Option<u64>>,
+}
+
+impl<'a> Fingerprinter<'a> {
+    fn node_fingerprint(&mut self, node_id: BjkNodeId) -> Result<u64> {
+        match self.visited.get(&node_id) {
+            Some(Some(fingerprint)) => return Ok(*fingerprint),
+            Some(None) => bail!(
+                "The graph has a cycle going through node {}",
+                node_id.display_id()
+            ),
+            None => {}
+        }
+        self.visited.insert(node_id, None);
+
+        let node = self
+            .graph
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| anyhow!("Node {} is not in the graph", node_id.display_id()))?;
+        let mut hasher = DefaultHasher::new();
+        node.op_name.hash(&mut hasher);
+        node.version.hash(&mut hasher);
+        self.muted.contains(&node_id).hash(&mut hasher);
+        crate::random::node_seed(self.graph.seed, node).hash(&mut hasher);
+        self.hash_inputs(node_id, &mut hasher)?;
+        let fingerprint = hasher.finish();
+
+        self.visited.insert(node_id, Some(fingerprint));
+        Ok(fingerprint)
+    }
+
+    fn hash_inputs(&mut self, node_id: BjkNodeId, hasher: &mut DefaultHasher) -> Result<()> {
+        let node = &self.graph.nodes[node_id];
+        // Cache nodes write their fingerprint themselves, which doesn't
+        // change the mesh they produce.
+        let is_cache = matches!(
+            self.node_definitions.node_def(&node.op_name),
+            Some(def) if def.cache
+        );
+        for input in &node.inputs {
+            if is_cache && input.name == FINGERPRINT_PARAM {
+                continue;
+            }
+            input.name.hash(hasher);
+            match &input.kind {
+                DependencyKind::Connection { node, param_name } => {
+                    self.node_fingerprint(*node)?.hash(hasher);
+                    param_name.hash(hasher);
+                }
+                DependencyKind::External { .. } => {
+                    // The serialized values have a stable representation
+                    let value = param(self.values, node_id, &input.name)
+                        .cloned()
+                        .and_then(SerializedBlackjackValue::from_runtime);
+                    format!("{value:?}").hash(hasher);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_file_next_to_graph() {
+        let file = cache_file(Some(Path::new("/projects/tree.bjk")), "trunk").unwrap();
+        assert_eq!(file, Path::new("/projects/tree.trunk.bjkmesh"));
+
+        assert!(cache_file(None, "trunk").is_err());
+        assert!(cache_file(Some(Path::new("/projects/tree.bjk")), "../trunk").is_err());
+        assert!(cache_file(Some(Path::new("/projects/tree.bjk")), "").is_err());
+    }
+}
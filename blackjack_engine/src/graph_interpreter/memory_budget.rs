@@ -0,0 +1,161 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A soft limit on the memory taken by the meshes of a graph execution.
+//!
+//! Nodes that make their meshes much larger, like subdivision, declare by how
+//! much with a [`CostHint`]. Before running one of them, the interpreter
+//! predicts the size of its outputs from the size of its input meshes, see
+//! [`HalfEdgeMesh::estimated_bytes`]. When the prediction goes over the
+//! budget of the execution, the node doesn't run and the execution fails with
+//! a [`MemoryBudgetExceeded`] error. Integrations can ask the user and run the
+//! graph again with a larger budget.
+
+use mlua::Table;
+
+use crate::graph::{BjkNode, BjkNodeId, CostHint};
+use crate::prelude::*;
+
+/// The error returned by `run_graph` when a node is predicted to output meshes
+/// larger than the memory budget, see [`RunOptions::memory_budget`](super::RunOptions::memory_budget).
+#[derive(Debug, Clone)]
+pub struct MemoryBudgetExceeded {
+    pub node_id: BjkNodeId,
+    pub op_name: String,
+    /// The predicted size of the outputs of the node, in bytes.
+    pub predicted_bytes: usize,
+    /// The budget of the execution, in bytes.
+    pub budget: usize,
+}
+
+impl std::fmt::Display for MemoryBudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (node {}) would allocate about {}, over the memory budget of {}",
+            self.op_name,
+            self.node_id.display_id(),
+            format_bytes(self.predicted_bytes),
+            format_bytes(self.budget)
+        )
+    }
+}
+
+impl std::error::Error for MemoryBudgetExceeded {}
+
+/// Formats an amount of bytes for display, like `3.2 GB`.
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut amount = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while amount >= 1024.0 && unit < UNITS.len() - 1 {
+        amount /= 1024.0;
+        unit += 1;
+    }
+    format!("{amount:.1} {}", UNITS[unit])
+}
+
+/// Returns the predicted size of the outputs of a node, from the size of its
+/// input meshes and its cost `hint`. The `exponent` is the value of the
+/// parameter the hint names, if any. Returns `None` when the hint needs an
+/// exponent that is unknown.
+pub fn predicted_bytes(
+    hint: &CostHint,
+    exponent: Option<f32>,
+    input_bytes: usize,
+) -> Option<usize> {
+    let factor = hint.factor(exponent)?;
+    // Saturates for predictions too large to count
+    Some((input_bytes as f64 * factor as f64) as usize)
+}
+
+/// Returns whether a node predicted to output `predicted_bytes` goes over the
+/// `budget`, and needs to be confirmed before it runs. Nodes are never stopped
+/// without a prediction or a budget.
+pub fn exceeds_budget(predicted_bytes: Option<usize>, budget: Option<usize>) -> bool {
+    matches!((predicted_bytes, budget), (Some(predicted), Some(budget)) if predicted > budget)
+}
+
+/// Fails with a [`MemoryBudgetExceeded`] error when the outputs of `node`,
+/// called with the inputs in `input_map`, are predicted to go over the
+/// `budget`.
+pub(super) fn check_budget(
+    node: &BjkNode,
+    node_id: BjkNodeId,
+    hint: &CostHint,
+    input_map: &Table,
+    budget: usize,
+) -> Result<()> {
+    let exponent = match &hint.exponent {
+        Some(param) => input_map.get::<_, Option<f32>>(param.as_str())?,
+        None => None,
+    };
+    let mut input_bytes = 0;
+    for input in node.inputs.iter().filter(|i| i.data_type.is_mesh()) {
+        if let mlua::Value::UserData(ud) = input_map.get::<_, mlua::Value>(input.name.as_str())? {
+            if let Ok(mesh) = ud.borrow::<HalfEdgeMesh>() {
+                input_bytes += mesh.estimated_bytes();
+            }
+        }
+    }
+    match predicted_bytes(hint, exponent, input_bytes) {
+        Some(predicted) if exceeds_budget(Some(predicted), Some(budget)) => {
+            Err(MemoryBudgetExceeded {
+                node_id,
+                op_name: node.op_name.clone(),
+                predicted_bytes: predicted,
+                budget,
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_predicted_bytes() {
+        let subdivide = CostHint {
+            base: 4.0,
+            exponent: Some("iterations".into()),
+        };
+        assert_eq!(predicted_bytes(&subdivide, Some(2.0), 1000), Some(16000));
+        assert_eq!(predicted_bytes(&subdivide, Some(0.0), 1000), Some(1000));
+        assert_eq!(predicted_bytes(&subdivide, None, 1000), None);
+        let double = CostHint {
+            base: 2.0,
+            exponent: None,
+        };
+        assert_eq!(predicted_bytes(&double, None, 1000), Some(2000));
+        assert_eq!(
+            predicted_bytes(&subdivide, Some(100.0), 1000),
+            Some(usize::MAX)
+        );
+    }
+
+    #[test]
+    fn test_budget_decision() {
+        assert!(exceeds_budget(Some(2000), Some(1000)));
+        assert!(!exceeds_budget(Some(1000), Some(1000)));
+        assert!(!exceeds_budget(Some(500), Some(1000)));
+        // Without a prediction or a budget, nodes always run
+        assert!(!exceeds_budget(None, Some(1000)));
+        assert!(!exceeds_budget(Some(usize::MAX), None));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(format_bytes(3_435_973_837), "3.2 GB");
+    }
+}
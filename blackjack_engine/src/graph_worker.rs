@@ -0,0 +1,727 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+use slotmap::SecondaryMap;
+
+use crate::graph::{BjkGraph, BjkNodeId};
+use crate::graph_interpreter::{
+    CancellationToken, ExecutionProgress, ExternalParameterValues, GizmoState, GraphInterpreter,
+    RunOptions,
+};
+use crate::lua_engine::graph_libraries::GraphLibraries;
+use crate::lua_engine::{LuaRuntime, LuaRuntimeConfig, ProgramResult, RenderableThing};
+use crate::mesh::halfedge::analysis;
+use crate::prelude::*;
+
+/// Identifies one of the graphs run by a [`GraphWorker`]. Integrations that
+/// edit several graphs at once, like the tabs of the editor, get a handle for
+/// each with [`GraphWorker::open_graph`]. Requests of different graphs don't
+/// replace each other, and each graph runs with its own node libraries.
+///
+/// Requests sent with [`GraphWorker::submit`] belong to the default handle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GraphHandle(u64);
+
+/// Everything needed to run a graph in the background. Integrations build a
+/// snapshot of their graph and send it to the worker in one of these.
+pub struct ExecutionRequest {
+    pub graph: BjkGraph,
+    pub target_node: BjkNodeId,
+    pub params: ExternalParameterValues,
+    pub gizmos: Option<SecondaryMap<BjkNodeId, GizmoState>>,
+    /// The nodes bypassed in this execution, see [`RunOptions::muted`].
+    pub muted: HashSet<BjkNodeId>,
+    /// When set, the graph runs a second time with these nodes toggled
+    /// between bypassed and not, to compare the results. See
+    /// [`GraphInterpreter::finish_with_alternative`].
+    pub compare_group: Option<HashSet<BjkNodeId>>,
+    /// The memory the meshes of this execution can take, see
+    /// [`RunOptions::memory_budget`].
+    pub memory_budget: Option<usize>,
+}
+
+/// The result of running an [`ExecutionRequest`].
+pub struct ExecutionResponse {
+    /// The id returned by [`GraphWorker::submit`] for this request.
+    pub request_id: u64,
+    /// The graph the request was submitted for.
+    pub graph: GraphHandle,
+    /// The result of the execution. Cancelled executions return an
+    /// [`ExecutionCancelled`](crate::graph_interpreter::ExecutionCancelled)
+    /// error.
+    pub result: Result<ProgramResult>,
+    /// The result of the second run, for requests with a `compare_group`
+    /// whose first run succeeded.
+    pub alternative: Option<Result<ProgramResult>>,
+}
+
+struct PendingRequest {
+    id: u64,
+    graph: GraphHandle,
+    request: ExecutionRequest,
+}
+
+/// Stores the requests waiting to be picked by the worker thread. Only the
+/// most recent request of each graph is kept: submitting a new request while
+/// another one of the same graph is pending discards the older one. Graphs
+/// take turns in the order their requests arrived.
+#[derive(Default)]
+struct RequestQueue {
+    next_id: u64,
+    pending: Vec<PendingRequest>,
+    /// The graph and the token for the execution currently running, if any.
+    running: Option<(GraphHandle, CancellationToken)>,
+    reload_runtime: bool,
+    /// The config requested with [`GraphWorker::set_runtime_config`], if any.
+    /// It is also applied to runtimes re-created after a reload.
+    runtime_config: Option<LuaRuntimeConfig>,
+    config_changed: bool,
+    /// The libraries requested with [`GraphWorker::set_graph_libraries_for`]
+    /// for each graph, with an id that changes every time they are set. They
+    /// are loaded before running a request of a graph whose libraries are not
+    /// the ones loaded.
+    graph_libraries: HashMap<GraphHandle, (u64, GraphLibraries)>,
+    shutdown: bool,
+}
+
+impl RequestQueue {
+    fn submit(&mut self, graph: GraphHandle, request: ExecutionRequest) -> u64 {
+        self.next_id += 1;
+        self.pending.retain(|pending| pending.graph != graph);
+        self.pending.push(PendingRequest {
+            id: self.next_id,
+            graph,
+            request,
+        });
+        self.next_id
+    }
+
+    fn take_next(&mut self) -> Option<PendingRequest> {
+        (!self.pending.is_empty()).then(|| self.pending.remove(0))
+    }
+
+    fn cancel(&mut self) {
+        self.pending.clear();
+        if let Some((_, token)) = &self.running {
+            token.cancel();
+        }
+    }
+
+    fn cancel_graph(&mut self, graph: GraphHandle) {
+        self.pending.retain(|pending| pending.graph != graph);
+        match &self.running {
+            Some((running, token)) if *running == graph => token.cancel(),
+            _ => {}
+        }
+    }
+
+    fn set_graph_libraries(&mut self, graph: GraphHandle, libraries: GraphLibraries) {
+        self.next_id += 1;
+        self.graph_libraries
+            .insert(graph, (self.next_id, libraries));
+    }
+
+    /// Returns the libraries to load before running a request of `graph`,
+    /// with their id, when they are not the `loaded` ones. Graphs without
+    /// libraries get the empty ones, which unload the loaded ones.
+    fn libraries_to_load(
+        &self,
+        graph: GraphHandle,
+        loaded: Option<u64>,
+    ) -> Option<(Option<u64>, GraphLibraries)> {
+        let wanted = self.graph_libraries.get(&graph);
+        let wanted_id = wanted.map(|(id, _)| *id);
+        (wanted_id != loaded).then(|| {
+            let libraries = wanted.map(|(_, libraries)| libraries.clone());
+            (wanted_id, libraries.unwrap_or_default())
+        })
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.pending.is_empty() || self.running.is_some()
+    }
+}
+
+struct SharedState {
+    queue: Mutex<RequestQueue>,
+    wakeup: Condvar,
+}
+
+/// Runs graphs in a background thread, so integrations with a UI never block
+/// while a graph is being executed.
+///
+/// The [`LuaRuntime`] is not `Send`, so the worker thread creates its own
+/// runtime using the `init_runtime` function passed to [`GraphWorker::spawn`]
+/// and keeps it confined to that thread.
+pub struct GraphWorker {
+    shared: Arc<SharedState>,
+    responses: Receiver<ExecutionResponse>,
+    progress: ExecutionProgress,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl GraphWorker {
+    pub fn spawn(init_runtime: impl Fn() -> Result<LuaRuntime> + Send + 'static) -> Self {
+        let shared = Arc::new(SharedState {
+            queue: Mutex::new(RequestQueue::default()),
+            wakeup: Condvar::new(),
+        });
+        let (tx, rx) = mpsc::channel();
+        let progress = ExecutionProgress::new();
+        let thread = {
+            let shared = Arc::clone(&shared);
+            let progress = progress.clone();
+            std::thread::Builder::new()
+                .name("blackjack_graph_worker".into())
+                .spawn(move || Self::worker_loop(&shared, tx, &progress, init_runtime))
+                .expect("Could not spawn graph worker thread")
+        };
+        Self {
+            shared,
+            responses: rx,
+            progress,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns a handle for a new graph, see [`GraphHandle`].
+    pub fn open_graph(&self) -> GraphHandle {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.next_id += 1;
+        GraphHandle(queue.next_id)
+    }
+
+    /// Forgets the graph of a handle returned by [`GraphWorker::open_graph`]:
+    /// Its requests are dropped, and its libraries are no longer loaded for
+    /// it.
+    pub fn close_graph(&self, graph: GraphHandle) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.cancel_graph(graph);
+        queue.graph_libraries.remove(&graph);
+    }
+
+    /// Queues a new request for the default graph and returns its id, see
+    /// [`GraphWorker::submit_to`].
+    pub fn submit(&self, request: ExecutionRequest) -> u64 {
+        self.submit_to(GraphHandle::default(), request)
+    }
+
+    /// Queues a new request for `graph` and returns its id. Any request of
+    /// the same graph that was still waiting to run is discarded, but a
+    /// request that is already running will run to completion unless it's
+    /// cancelled.
+    pub fn submit_to(&self, graph: GraphHandle, request: ExecutionRequest) -> u64 {
+        let id = self.shared.queue.lock().unwrap().submit(graph, request);
+        self.shared.wakeup.notify_one();
+        id
+    }
+
+    /// Discards any pending request, and aborts the running one, if any.
+    pub fn cancel(&self) {
+        self.shared.queue.lock().unwrap().cancel();
+    }
+
+    /// Discards the pending request of `graph`, and aborts its running one,
+    /// if any. The requests of other graphs are left alone.
+    pub fn cancel_graph(&self, graph: GraphHandle) {
+        self.shared.queue.lock().unwrap().cancel_graph(graph);
+    }
+
+    /// Asks the worker to re-create its Lua runtime before running the next
+    /// request. Used to pick up changes after reloading Lua code.
+    pub fn reload_runtime(&self) {
+        self.shared.queue.lock().unwrap().reload_runtime = true;
+        self.shared.wakeup.notify_one();
+    }
+
+    /// Changes the configuration of the worker's Lua runtime, e.g. to enable
+    /// the sandbox. The change applies from the next request on, for all the
+    /// graphs.
+    pub fn set_runtime_config(&self, config: LuaRuntimeConfig) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.runtime_config = Some(config);
+        queue.config_changed = true;
+    }
+
+    /// Sets the node libraries of the default graph, see
+    /// [`GraphWorker::set_graph_libraries_for`].
+    pub fn set_graph_libraries(&self, libraries: GraphLibraries) {
+        self.set_graph_libraries_for(GraphHandle::default(), libraries);
+    }
+
+    /// Sets the node libraries that come with `graph`. They are loaded into
+    /// the worker's Lua runtime before running the requests of that graph,
+    /// replacing the ones of other graphs, see
+    /// [`LuaRuntime::load_graph_libraries`]. The change applies from the next
+    /// request on.
+    pub fn set_graph_libraries_for(&self, graph: GraphHandle, libraries: GraphLibraries) {
+        self.shared
+            .queue
+            .lock()
+            .unwrap()
+            .set_graph_libraries(graph, libraries);
+    }
+
+    /// Returns true while there are requests pending or running.
+    pub fn is_busy(&self) -> bool {
+        self.shared.queue.lock().unwrap().is_busy()
+    }
+
+    /// Returns the node of the running request that is currently executing,
+    /// and its progress between 0 and 1, if any.
+    pub fn progress(&self) -> Option<(BjkNodeId, f32)> {
+        self.progress.current()
+    }
+
+    /// Returns the next available response, without blocking.
+    pub fn try_recv(&self) -> Option<ExecutionResponse> {
+        self.responses.try_recv().ok()
+    }
+
+    /// Blocks until the next response is available.
+    pub fn recv(&self) -> Option<ExecutionResponse> {
+        self.responses.recv().ok()
+    }
+
+    /// Runs a `request`, along with its alternative when it has a compare
+    /// group.
+    fn execute(
+        runtime: &LuaRuntime,
+        request: ExecutionRequest,
+        token: &CancellationToken,
+        progress: &ExecutionProgress,
+    ) -> (Result<ProgramResult>, Option<Result<ProgramResult>>) {
+        let interpreter = GraphInterpreter::run_iter(
+            &runtime.lua,
+            &request.graph,
+            request.target_node,
+            request.params,
+            &runtime.node_definitions,
+            RunOptions {
+                gizmos_state: request.gizmos,
+                cancellation: Some(token),
+                progress: Some(progress),
+                muted: request.muted,
+                memory_budget: request.memory_budget,
+                ..Default::default()
+            },
+        );
+        let interpreter = match interpreter {
+            Ok(interpreter) => interpreter,
+            Err(err) => return (Err(err), None),
+        };
+        match &request.compare_group {
+            Some(group) => match interpreter.finish_with_alternative(group) {
+                Ok((result, alternative)) => (Ok(result), Some(alternative)),
+                Err(err) => (Err(err), None),
+            },
+            None => (interpreter.finish(), None),
+        }
+    }
+
+    /// Loads the node libraries of the graph about to run into `runtime`.
+    /// The config is applied again afterwards, since loading untrusted
+    /// libraries enables the sandbox.
+    fn load_libraries(
+        runtime: &mut LuaRuntime,
+        libraries: GraphLibraries,
+        config: Option<LuaRuntimeConfig>,
+    ) -> Result<()> {
+        // The graph just misses the nodes of a library that fails to load,
+        // which the runs report.
+        if let Err(err) = runtime.load_graph_libraries(libraries) {
+            println!("[WARNING] Could not load the graph libraries: {err:?}");
+        }
+        match config {
+            Some(config) if config != *runtime.config() => runtime.set_config(config),
+            _ => Ok(()),
+        }
+    }
+
+    fn worker_loop(
+        shared: &SharedState,
+        responses: Sender<ExecutionResponse>,
+        progress: &ExecutionProgress,
+        init_runtime: impl Fn() -> Result<LuaRuntime>,
+    ) {
+        let mut runtime = init_runtime();
+        // The id of the graph libraries loaded into the runtime, if any.
+        let mut loaded_libraries = None;
+        loop {
+            let (next, libraries, config, token) = {
+                let mut queue = shared.queue.lock().unwrap();
+                loop {
+                    if queue.shutdown {
+                        return;
+                    }
+                    if std::mem::take(&mut queue.reload_runtime) {
+                        drop(queue);
+                        runtime = init_runtime();
+                        loaded_libraries = None;
+                        queue = shared.queue.lock().unwrap();
+                        queue.config_changed |= queue.runtime_config.is_some();
+                        continue;
+                    }
+                    if std::mem::take(&mut queue.config_changed) {
+                        if let (Ok(rt), Some(config)) = (&mut runtime, queue.runtime_config) {
+                            if let Err(err) = rt.set_config(config) {
+                                runtime = Err(err);
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(next) = queue.take_next() {
+                        let libraries = queue.libraries_to_load(next.graph, loaded_libraries);
+                        let token = CancellationToken::new();
+                        queue.running = Some((next.graph, token.clone()));
+                        break (next, libraries, queue.runtime_config, token);
+                    }
+                    queue = shared.wakeup.wait(queue).unwrap();
+                }
+            };
+
+            if let (Ok(rt), Some((id, libraries))) = (&mut runtime, libraries) {
+                loaded_libraries = id;
+                if let Err(err) = Self::load_libraries(rt, libraries, config) {
+                    runtime = Err(err);
+                }
+            }
+
+            let (mut result, mut alternative) = match &runtime {
+                Ok(runtime) => Self::execute(runtime, next.request, &token, progress),
+                Err(err) => (
+                    Err(anyhow!("The Lua runtime failed to initialize: {err}")),
+                    None,
+                ),
+            };
+            let results = std::iter::once(&mut result).chain(alternative.as_mut());
+            for program_result in results.filter_map(|r| r.as_mut().ok()) {
+                compute_mesh_stats(program_result);
+            }
+
+            shared.queue.lock().unwrap().running = None;
+            if responses
+                .send(ExecutionResponse {
+                    request_id: next.id,
+                    graph: next.graph,
+                    result,
+                    alternative,
+                })
+                .is_err()
+            {
+                // The receiving end was dropped, nobody is listening anymore.
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for GraphWorker {
+    fn drop(&mut self) {
+        self.cancel();
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Fills the [`ProgramResult::mesh_stats`] of `result`.
+fn compute_mesh_stats(result: &mut ProgramResult) {
+    result.mesh_stats = match &result.renderable {
+        Some(RenderableThing::HalfEdgeMesh(mesh)) => analysis::mesh_stats(mesh).ok(),
+        Some(RenderableThing::Scene(scene)) => analysis::mesh_stats(&scene.to_merged_mesh()).ok(),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::serialization::EmbeddedNodeLibrary;
+    use crate::graph::{BlackjackValue, DataType};
+    use crate::graph_interpreter::{ExecutionCancelled, ExternalParameter};
+
+    fn test_runtime() -> Result<LuaRuntime> {
+        LuaRuntime::initialize_with_std("../blackjack_lua".into())
+    }
+
+    fn box_request(size: f32) -> ExecutionRequest {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("MakeBox", Some("out_mesh".into()));
+        graph
+            .add_input(node, "origin", DataType::Vector, None)
+            .unwrap();
+        graph
+            .add_input(node, "size", DataType::Vector, None)
+            .unwrap();
+        graph.add_output(node, "out_mesh", DataType::Mesh).unwrap();
+        let mut params = ExternalParameterValues::default();
+        params.0.insert(
+            ExternalParameter::new(node, "origin".into()),
+            BlackjackValue::Vector(Vec3::ZERO),
+        );
+        params.0.insert(
+            ExternalParameter::new(node, "size".into()),
+            BlackjackValue::Vector(Vec3::splat(size)),
+        );
+        ExecutionRequest {
+            graph,
+            target_node: node,
+            params,
+            gizmos: None,
+            muted: HashSet::new(),
+            compare_group: None,
+            memory_budget: None,
+        }
+    }
+
+    /// A request whose Lua code never terminates on its own.
+    fn infinite_loop_request() -> ExecutionRequest {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("MakeTerrain", Some("out_heightmap".into()));
+        graph
+            .add_input(node, "width", DataType::Scalar, None)
+            .unwrap();
+        graph
+            .add_input(node, "height", DataType::Scalar, None)
+            .unwrap();
+        graph
+            .add_input(node, "code", DataType::String, None)
+            .unwrap();
+        graph
+            .add_output(node, "out_heightmap", DataType::HeightMap)
+            .unwrap();
+        let mut params = ExternalParameterValues::default();
+        for (name, value) in [
+            ("width", BlackjackValue::Scalar(2.0)),
+            ("height", BlackjackValue::Scalar(2.0)),
+            (
+                "code",
+                BlackjackValue::String("function(x, y) while true do end end".into()),
+            ),
+        ] {
+            params
+                .0
+                .insert(ExternalParameter::new(node, name.into()), value);
+        }
+        ExecutionRequest {
+            graph,
+            target_node: node,
+            params,
+            gizmos: None,
+            muted: HashSet::new(),
+            compare_group: None,
+            memory_budget: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_coalescing() {
+        let mut queue = RequestQueue::default();
+        let graph = GraphHandle::default();
+        queue.submit(graph, box_request(1.0));
+        queue.submit(graph, box_request(2.0));
+        let last = queue.submit(graph, box_request(3.0));
+
+        let next = queue.take_next().unwrap();
+        assert_eq!(next.id, last);
+        assert!(queue.take_next().is_none());
+    }
+
+    #[test]
+    fn test_queue_coalescing_per_graph() {
+        let mut queue = RequestQueue::default();
+        let (a, b) = (GraphHandle(1), GraphHandle(2));
+        queue.submit(a, box_request(1.0));
+        let last_b = queue.submit(b, box_request(2.0));
+        let last_a = queue.submit(a, box_request(3.0));
+
+        // The graphs take turns, each with its latest request
+        let next = queue.take_next().unwrap();
+        assert_eq!((next.graph, next.id), (b, last_b));
+        queue.cancel_graph(b);
+        let next = queue.take_next().unwrap();
+        assert_eq!((next.graph, next.id), (a, last_a));
+        assert!(queue.take_next().is_none());
+
+        queue.submit(a, box_request(1.0));
+        queue.submit(b, box_request(1.0));
+        queue.cancel_graph(a);
+        assert_eq!(queue.take_next().unwrap().graph, b);
+        assert!(queue.take_next().is_none());
+    }
+
+    #[test]
+    fn test_worker_runs_latest_request() {
+        let worker = GraphWorker::spawn(test_runtime);
+        let mut last = 0;
+        for i in 0..10 {
+            last = worker.submit(box_request(i as f32));
+        }
+
+        // Some of the requests may be skipped, but the last one always runs.
+        let mut num_responses = 0;
+        loop {
+            let response = worker.recv().unwrap();
+            num_responses += 1;
+            assert!(response.result.is_ok());
+            if response.request_id == last {
+                break;
+            }
+        }
+        assert!(num_responses <= 10);
+        assert!(!worker.is_busy());
+    }
+
+    #[test]
+    fn test_worker_cancellation() {
+        let worker = GraphWorker::spawn(test_runtime);
+        let id = worker.submit(infinite_loop_request());
+        // Wait until the worker has picked up the request before cancelling.
+        while !worker.shared.queue.lock().unwrap().pending.is_empty() {
+            std::thread::yield_now();
+        }
+        worker.cancel();
+
+        let response = worker.recv().unwrap();
+        assert_eq!(response.request_id, id);
+        let err = response
+            .result
+            .err()
+            .expect("Execution should be cancelled");
+        assert!(err.downcast_ref::<ExecutionCancelled>().is_some());
+
+        // The worker is still usable after cancelling.
+        let id = worker.submit(box_request(1.0));
+        let response = worker.recv().unwrap();
+        assert_eq!(response.request_id, id);
+        assert!(response.result.is_ok());
+    }
+
+    #[test]
+    fn test_worker_computes_mesh_stats() {
+        let worker = GraphWorker::spawn(test_runtime);
+        worker.submit(box_request(2.0));
+        let stats = worker.recv().unwrap().result.unwrap().mesh_stats.unwrap();
+        assert_eq!((stats.num_vertices, stats.num_faces), (8, 6));
+        assert!(stats.is_closed);
+        assert!((stats.volume - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_worker_runs_alternative() {
+        let worker = GraphWorker::spawn(test_runtime);
+        let mut request = box_request(1.0);
+        request.compare_group = Some([request.target_node].into_iter().collect());
+        worker.submit(request);
+
+        let response = worker.recv().unwrap();
+        let faces = |result: ProgramResult| match result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => mesh.read_connectivity().num_faces(),
+            _ => panic!("Expected a mesh"),
+        };
+        assert_eq!(faces(response.result.unwrap()), 6);
+        // The bypassed box has no mesh input to pass through
+        assert_eq!(faces(response.alternative.unwrap().unwrap()), 0);
+    }
+
+    /// Libraries that define the same node, which makes a box of a different
+    /// size in each one.
+    fn sized_box_library(size: f32) -> GraphLibraries {
+        let source = format!(
+            r#"
+local P = require("params")
+local NodeLibrary = require("node_library")
+NodeLibrary:addNodes({{
+    MakeTestBox = {{
+        label = "Test box",
+        op = function(inputs)
+            return {{ out_mesh = Primitives.cube(vector(0, 0, 0), vector({size}, 1, 1)) }}
+        end,
+        inputs = {{}},
+        outputs = {{ P.mesh("out_mesh") }},
+        returns = "out_mesh",
+    }},
+}})
+"#
+        );
+        GraphLibraries {
+            embedded: vec![EmbeddedNodeLibrary {
+                name: "sized_box.lua".into(),
+                source,
+            }],
+            trusted: true,
+            ..Default::default()
+        }
+    }
+
+    fn test_box_request() -> ExecutionRequest {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("MakeTestBox", Some("out_mesh".into()));
+        graph.add_output(node, "out_mesh", DataType::Mesh).unwrap();
+        ExecutionRequest {
+            graph,
+            target_node: node,
+            params: ExternalParameterValues::default(),
+            gizmos: None,
+            muted: HashSet::new(),
+            compare_group: None,
+            memory_budget: None,
+        }
+    }
+
+    /// Returns the width of the box made by the response.
+    fn box_width(response: ExecutionResponse) -> f32 {
+        match response.result.unwrap().renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+                let positions = mesh.read_positions();
+                let xs = positions.iter().map(|(_, pos)| pos.x).collect_vec();
+                xs.iter().copied().fold(f32::MIN, f32::max)
+                    - xs.iter().copied().fold(f32::MAX, f32::min)
+            }
+            _ => panic!("Expected a mesh"),
+        }
+    }
+
+    #[test]
+    fn test_worker_runs_graphs_interleaved() {
+        let worker = GraphWorker::spawn(test_runtime);
+        let (a, b) = (worker.open_graph(), worker.open_graph());
+        assert_ne!(a, b);
+        worker.set_graph_libraries_for(a, sized_box_library(1.0));
+        worker.set_graph_libraries_for(b, sized_box_library(3.0));
+
+        for _ in 0..3 {
+            let id_a = worker.submit_to(a, test_box_request());
+            let id_b = worker.submit_to(b, test_box_request());
+            // A request of one graph never replaces the one of another
+            let first = worker.recv().unwrap();
+            assert_eq!((first.request_id, first.graph), (id_a, a));
+            assert_eq!(box_width(first), 1.0);
+            let second = worker.recv().unwrap();
+            assert_eq!((second.request_id, second.graph), (id_b, b));
+            assert_eq!(box_width(second), 3.0);
+        }
+
+        // The nodes of a graph's libraries are gone for the graphs without
+        // them
+        let id = worker.submit(test_box_request());
+        let response = worker.recv().unwrap();
+        assert_eq!(response.request_id, id);
+        assert!(response.result.is_err());
+
+        worker.close_graph(a);
+        worker.set_graph_libraries_for(b, sized_box_library(2.0));
+        worker.submit_to(b, test_box_request());
+        assert_eq!(box_width(worker.recv().unwrap()), 2.0);
+    }
+}
@@ -22,6 +22,25 @@ pub mod graph;
 /// High level interpreter of blackjack graphs.
 pub mod graph_interpreter;
 
+/// Runs graphs in a background thread. Requires the `sync` feature, so the
+/// results can be sent across threads.
+#[cfg(feature = "sync")]
+pub mod graph_worker;
+
+/// Progress reporting and cancellation for long-running operations.
+pub mod progress;
+
+/// Deterministic random number generation for ops and nodes.
+pub mod random;
+
+/// Units of length, and the conversions between them.
+pub mod units;
+
+pub mod resources;
+
+/// Keeps the meshes and images read by import nodes between graph runs.
+pub mod asset_cache;
+
 /// Gizmos allow visual modifications of a node's parameters.
 pub mod gizmos;
 
@@ -4,22 +4,24 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::sync::Arc;
+#[cfg(feature = "hot_reload")]
 use std::{
-    sync::{
-        mpsc::{self, Receiver},
-        Arc,
-    },
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
     time::Duration,
 };
 
 use crate::{
+    asset_cache::{set_lua_asset_cache, AssetCache},
     gizmos::BlackjackGizmo,
     graph::{BjkNodeId, NodeDefinitions},
-    graph_interpreter::ExternalParameterValues,
-    mesh::heightmap::HeightMap,
+    graph_interpreter::{ExternalParameterValues, RunStats},
+    mesh::{halfedge::analysis::MeshStats, heightmap::HeightMap, scene::Scene},
     prelude::*,
 };
 use mlua::Lua;
+#[cfg(feature = "hot_reload")]
 use notify::{DebouncedEvent, Watcher};
 use slotmap::SecondaryMap;
 
@@ -27,6 +29,20 @@ use self::lua_stdlib::{load_node_definitions, LuaFileIo, StdLuaFileIo};
 
 pub mod lua_stdlib;
 
+/// Restrictions and resource limits for running untrusted Lua code.
+pub mod sandbox;
+pub use sandbox::LuaRuntimeConfig;
+
+/// Node libraries that come along with a graph, embedded in its file or in a
+/// folder next to it.
+pub mod graph_libraries;
+
+/// Building meshes from the chunks of geometry yielded by a coroutine.
+pub mod mesh_generators;
+
+/// Runs the `*_test.lua` files that test the Lua API.
+pub mod lua_test_harness;
+
 pub trait ToLuaError<T> {
     fn map_lua_err(self) -> mlua::Result<T>;
 }
@@ -47,6 +63,7 @@ impl<T> ToLuaError<T> for Result<T, TraversalError> {
 pub enum RenderableThing {
     HalfEdgeMesh(HalfEdgeMesh),
     HeightMap(HeightMap),
+    Scene(Scene),
 }
 
 impl RenderableThing {
@@ -58,6 +75,9 @@ impl RenderableThing {
             mlua::Value::UserData(renderable) if renderable.is::<HeightMap>() => {
                 Ok(RenderableThing::HeightMap(renderable.take()?))
             }
+            mlua::Value::UserData(renderable) if renderable.is::<Scene>() => {
+                Ok(RenderableThing::Scene(renderable.take()?))
+            }
             _ => {
                 bail!("Object {renderable:?} is not a thing we can render.")
             }
@@ -69,6 +89,10 @@ impl RenderableThing {
 pub struct ProgramResult {
     /// The renderable thing produced by this program to be shown on-screen.
     pub renderable: Option<RenderableThing>,
+    /// Set when the renderable mesh comes from a deform-only node that kept
+    /// the topology of its input. When the input didn't change either, only
+    /// the vertex positions of the previous result need updating.
+    pub positions_only: bool,
     /// The gizmos requested by graph nodes after an execution of this program.
     /// If you are implementing an integration, you can ignore this field. This
     /// field will be returned as None will be none when gizmos aren't run.
@@ -76,18 +100,42 @@ pub struct ProgramResult {
     /// The updated external parameters. Any node may modify its own parameters
     /// when running its gizmo function.
     pub updated_values: ExternalParameterValues,
+    /// Statistics about the execution that produced this result, along with
+    /// the warnings reported by nodes while they ran, see `Blackjack.warn`.
+    pub stats: RunStats,
+    /// Statistics about the renderable mesh, or about all the objects of a
+    /// renderable scene merged together. Only the [`GraphWorker`] computes
+    /// them, so integrations don't need to on their own thread. `None` for
+    /// other results.
+    ///
+    /// [`GraphWorker`]: crate::graph_worker::GraphWorker
+    pub mesh_stats: Option<MeshStats>,
 }
 
+#[cfg(feature = "hot_reload")]
 pub struct LuaFileWatcher {
     pub watcher: notify::RecommendedWatcher,
     pub watcher_channel: Receiver<notify::DebouncedEvent>,
+    /// The files of the cached assets that are being watched, so they're
+    /// dropped from the cache as soon as they change.
+    pub watched_assets: HashSet<PathBuf>,
 }
 
 pub struct LuaRuntime {
     pub lua: Lua,
     pub node_definitions: NodeDefinitions,
+    #[cfg(feature = "hot_reload")]
     pub file_watcher: Option<LuaFileWatcher>,
     pub lua_io: Arc<dyn LuaFileIo + 'static>,
+    /// The configuration currently applied to the `lua` state. Use
+    /// [`LuaRuntime::set_config`] to change it.
+    config: LuaRuntimeConfig,
+    /// The cache used by the import nodes. Use
+    /// [`LuaRuntime::set_asset_cache`] to share one between runtimes.
+    asset_cache: AssetCache,
+    /// The node libraries of the open graph, see
+    /// [`LuaRuntime::load_graph_libraries`].
+    graph_libraries: Option<graph_libraries::LoadedGraphLibraries>,
 }
 
 impl LuaRuntime {
@@ -101,19 +149,77 @@ impl LuaRuntime {
     }
 
     pub fn initialize_custom(lua_io: impl LuaFileIo + 'static) -> anyhow::Result<LuaRuntime> {
+        Self::initialize_custom_with_config(lua_io, LuaRuntimeConfig::default())
+    }
+
+    /// Same as [`LuaRuntime::initialize_with_std`], but the runtime uses the
+    /// given `config`.
+    pub fn initialize_with_std_and_config(
+        node_libraries_path: String,
+        config: LuaRuntimeConfig,
+    ) -> anyhow::Result<LuaRuntime> {
+        Self::initialize_custom_with_config(
+            StdLuaFileIo {
+                base_folder: node_libraries_path,
+            },
+            config,
+        )
+    }
+
+    /// Same as [`LuaRuntime::initialize_custom`], but the runtime uses the
+    /// given `config`. The node libraries are loaded before the config is
+    /// applied, so they are always trusted.
+    pub fn initialize_custom_with_config(
+        lua_io: impl LuaFileIo + 'static,
+        config: LuaRuntimeConfig,
+    ) -> anyhow::Result<LuaRuntime> {
         let lua = Lua::new();
         let lua_io = Arc::new(lua_io);
         lua_stdlib::load_lua_bindings(&lua, lua_io.clone())?;
         let node_definitions = NodeDefinitions::new(load_node_definitions(&lua, lua_io.as_ref())?);
+        sandbox::apply_config(&lua, &config)?;
+        let asset_cache = AssetCache::default();
+        set_lua_asset_cache(&lua, asset_cache.clone())?;
 
         Ok(LuaRuntime {
             lua,
             node_definitions,
+            #[cfg(feature = "hot_reload")]
             file_watcher: None,
             lua_io,
+            config,
+            asset_cache,
+            graph_libraries: None,
         })
     }
 
+    pub fn config(&self) -> &LuaRuntimeConfig {
+        &self.config
+    }
+
+    /// Changes the configuration of this runtime. This can be used to enable
+    /// the sandbox before running a graph from an untrusted source, and to
+    /// disable it again afterwards.
+    pub fn set_config(&mut self, config: LuaRuntimeConfig) -> Result<()> {
+        sandbox::apply_config(&self.lua, &config)?;
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn asset_cache(&self) -> &AssetCache {
+        &self.asset_cache
+    }
+
+    /// Makes the import nodes of this runtime use `cache`. Passing a handle
+    /// to the same cache to several runtimes, like the one of a graph worker,
+    /// lets them share the imported assets.
+    pub fn set_asset_cache(&mut self, cache: AssetCache) -> Result<()> {
+        set_lua_asset_cache(&self.lua, cache.clone())?;
+        self.asset_cache = cache;
+        Ok(())
+    }
+
+    #[cfg(feature = "hot_reload")]
     pub fn start_file_watcher(&mut self) -> Result<()> {
         let (tx, rx) = mpsc::channel();
         let mut watcher = notify::watcher(tx, Duration::from_secs(1))?;
@@ -121,23 +227,58 @@ impl LuaRuntime {
         self.file_watcher = Some(LuaFileWatcher {
             watcher,
             watcher_channel: rx,
+            watched_assets: HashSet::new(),
         });
         Ok(())
     }
 
     /// Watches the lua source folders for changes. Returns true when a change
     /// was detected and the `NodeDefinitions` were successfully updated.
+    ///
+    /// The files of the cached assets are watched too, and dropped from the
+    /// cache when they change. Those changes alone don't reload the scripts.
+    #[cfg(feature = "hot_reload")]
     pub fn watch_for_changes(&mut self) -> anyhow::Result<bool> {
         let file_watcher = self
             .file_watcher
-            .as_ref()
+            .as_mut()
             .ok_or_else(|| anyhow!("File watcher was not set up."))?;
+        for path in self.asset_cache.paths() {
+            if !file_watcher.watched_assets.contains(&path)
+                && file_watcher
+                    .watcher
+                    .watch(&path, notify::RecursiveMode::NonRecursive)
+                    .is_ok()
+            {
+                file_watcher.watched_assets.insert(path);
+            }
+        }
         if let Ok(msg) = file_watcher.watcher_channel.try_recv() {
             match msg {
                 DebouncedEvent::Create(_)
                 | DebouncedEvent::Write(_)
                 | DebouncedEvent::Remove(_)
                 | DebouncedEvent::Rename(_, _) => {
+                    let paths = match &msg {
+                        DebouncedEvent::Rename(from, to) => vec![from, to],
+                        DebouncedEvent::Create(path)
+                        | DebouncedEvent::Write(path)
+                        | DebouncedEvent::Remove(path) => vec![path],
+                        _ => vec![],
+                    };
+                    let only_assets = paths
+                        .iter()
+                        .all(|path| file_watcher.watched_assets.contains(*path));
+                    for path in paths {
+                        self.asset_cache.invalidate(path);
+                        // The file is watched again the next time it's
+                        // imported. Removed files lose their watch anyway.
+                        file_watcher.watched_assets.remove(path);
+                    }
+                    if only_assets {
+                        return Ok(false);
+                    }
+
                     println!("Reloading Lua scripts...");
                     // Reset the _LOADED table to clear any required libraries
                     // from the cache. This will trigger reloading of libraries
@@ -152,6 +293,7 @@ impl LuaRuntime {
                     // be executed and the node definitions will be reloaded.
                     self.node_definitions
                         .update(load_node_definitions(&self.lua, self.lua_io.as_ref())?);
+                    self.reload_graph_libraries()?;
                 }
                 _ => {}
             }
@@ -0,0 +1,696 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Graphs can use nodes from libraries that are not installed where they are
+//! opened. Those libraries can come along with the graph in two ways:
+//!
+//! - Their sources can be embedded in the `bjk` file. This is opt-in when
+//!   saving, see [`embed_node_libraries`].
+//! - They can be put in a [`GRAPH_LIBRARIES_FOLDER`] next to the `bjk` file.
+//!   The other files in that folder can be loaded with `require`.
+//!
+//! [`LuaRuntime::load_graph_libraries`] runs them for the open graph alone,
+//! and [`LuaRuntime::unload_graph_libraries`] removes their nodes again. They
+//! never replace the definitions that were already loaded, and they run in
+//! the sandbox unless the graph is trusted.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use mlua::{Lua, Table, Value};
+
+use super::lua_stdlib::LuaSourceFile;
+use super::{sandbox, LuaRuntime, LuaRuntimeConfig};
+use crate::graph::serialization::{EmbeddedNodeLibrary, SerializedBjkGraph};
+use crate::graph::{BjkGraph, NodeDefinition, NodeProvenance};
+use crate::prelude::*;
+
+/// The folder next to a graph file with the node libraries of that graph.
+pub const GRAPH_LIBRARIES_FOLDER: &str = "node_libraries";
+
+/// The largest total size, in bytes, of the libraries a graph file can embed.
+pub const MAX_EMBEDDED_SIZE: usize = 1 << 20;
+
+/// Registry key for the graph libraries folder of the open graph, where
+/// `require` looks for the files it can't find in $BLACKJACK_LUA/lib.
+const FOLDER_KEY: &str = "__blackjack_graph_libraries_folder";
+
+/// The node libraries that come with a graph.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphLibraries {
+    /// The libraries embedded in the graph file.
+    pub embedded: Vec<EmbeddedNodeLibrary>,
+    /// The [`GRAPH_LIBRARIES_FOLDER`] next to the graph file, if there's one.
+    pub folder: Option<PathBuf>,
+    /// When set, the libraries run outside of the sandbox. Only for graphs
+    /// the user trusts.
+    pub trusted: bool,
+}
+
+impl GraphLibraries {
+    /// Returns the libraries that come with `graph`: The ones embedded in it,
+    /// and the ones in the folder next to the file it was loaded from.
+    pub fn of_graph(graph: &SerializedBjkGraph, trusted: bool) -> Self {
+        let folder = graph
+            .file_path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map(|parent| parent.join(GRAPH_LIBRARIES_FOLDER))
+            .filter(|folder| folder.is_dir());
+        Self {
+            embedded: graph.node_libraries.clone(),
+            folder,
+            trusted,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.embedded.is_empty() && self.folder.is_none()
+    }
+
+    /// Returns the libraries to run, with the provenance of their nodes. The
+    /// files in the folder come first, so they win over an older copy of the
+    /// same nodes embedded in the graph.
+    fn sources(&self) -> Result<Vec<(LuaSourceFile, NodeProvenance)>> {
+        let mut sources = vec![];
+        if let Some(folder) = &self.folder {
+            let mut paths = std::fs::read_dir(folder)
+                .with_context(|| format!("Could not read {}", folder.display()))?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension() == Some("lua".as_ref()))
+                .collect_vec();
+            paths.sort();
+            for path in paths {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Could not read {}", path.display()))?;
+                let file = file_name(&path.display().to_string());
+                sources.push((
+                    LuaSourceFile {
+                        contents,
+                        name: path.display().to_string(),
+                    },
+                    NodeProvenance::GraphFolder { file },
+                ));
+            }
+        }
+        check_embedded_size(
+            self.embedded
+                .iter()
+                .map(|library| library.source.len())
+                .sum(),
+        )?;
+        for library in &self.embedded {
+            sources.push((
+                LuaSourceFile {
+                    contents: library.source.clone(),
+                    name: format!("embedded:{}", library.name),
+                },
+                NodeProvenance::Embedded {
+                    library: library.name.clone(),
+                },
+            ));
+        }
+        Ok(sources)
+    }
+}
+
+/// What [`LuaRuntime::load_graph_libraries`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphLibrariesReport {
+    /// The nodes defined by the graph libraries, and where each comes from.
+    pub loaded: Vec<(String, NodeProvenance)>,
+    /// The nodes the graph libraries defined again. These keep the definition
+    /// that was already loaded.
+    pub skipped: Vec<(String, NodeProvenance)>,
+    /// Whether the libraries ran in the sandbox.
+    pub sandboxed: bool,
+}
+
+/// The graph libraries loaded into a runtime, so they can be unloaded.
+pub(super) struct LoadedGraphLibraries {
+    libraries: GraphLibraries,
+    /// The nodes added by the libraries.
+    ops: Vec<String>,
+    /// The source of each library by name, to embed it again when saving.
+    sources: HashMap<String, String>,
+}
+
+impl LuaRuntime {
+    /// Runs the node libraries that come with a graph, and adds their nodes
+    /// to the node definitions. The libraries of the previous graph, if any,
+    /// are unloaded first.
+    ///
+    /// Unless the libraries are trusted, this enables the sandbox. It stays
+    /// enabled after the libraries are unloaded, like any other config set on
+    /// the runtime.
+    pub fn load_graph_libraries(
+        &mut self,
+        libraries: GraphLibraries,
+    ) -> Result<GraphLibrariesReport> {
+        self.unload_graph_libraries()?;
+        let mut report = GraphLibrariesReport::default();
+        if libraries.is_empty() {
+            return Ok(report);
+        }
+        if !libraries.trusted && !self.config.sandboxed {
+            self.set_config(LuaRuntimeConfig::sandboxed())?;
+        }
+        report.sandboxed = self.config.sandboxed;
+
+        let sources = libraries.sources()?;
+        if let Some(folder) = &libraries.folder {
+            self.lua
+                .set_named_registry_value(FOLDER_KEY, folder.display().to_string())?;
+        }
+        self.graph_libraries = Some(LoadedGraphLibraries {
+            libraries,
+            ops: vec![],
+            sources: HashMap::new(),
+        });
+        for (file, provenance) in sources {
+            if let Err(err) = self.load_graph_library(file, provenance, &mut report) {
+                self.unload_graph_libraries()?;
+                return Err(err);
+            }
+        }
+        Ok(report)
+    }
+
+    fn load_graph_library(
+        &mut self,
+        file: LuaSourceFile,
+        provenance: NodeProvenance,
+        report: &mut GraphLibrariesReport,
+    ) -> Result<()> {
+        let (added, redefined) = run_library(&self.lua, &file)?;
+        let loaded = self
+            .graph_libraries
+            .as_mut()
+            .expect("The graph libraries are being loaded");
+        if let Some(name) = library_name(&provenance) {
+            loaded.sources.entry(name).or_insert(file.contents);
+        }
+        loaded
+            .ops
+            .extend(added.iter().map(|(op_name, _)| op_name.clone()));
+        for (op_name, table) in added {
+            let mut def = NodeDefinition::from_lua(op_name.clone(), table)?;
+            def.provenance = provenance.clone();
+            self.node_definitions.insert(def);
+            report.loaded.push((op_name, provenance.clone()));
+        }
+        report.skipped.extend(
+            redefined
+                .into_iter()
+                .map(|op_name| (op_name, provenance.clone())),
+        );
+        Ok(())
+    }
+
+    /// Removes the nodes added by [`LuaRuntime::load_graph_libraries`].
+    pub fn unload_graph_libraries(&mut self) -> Result<()> {
+        let loaded = match self.graph_libraries.take() {
+            Some(loaded) => loaded,
+            None => return Ok(()),
+        };
+        let nodes = self
+            .lua
+            .load("require('node_library').nodes")
+            .eval::<Table>()?;
+        for op_name in &loaded.ops {
+            nodes.set(op_name.as_str(), Value::Nil)?;
+            self.node_definitions.remove(op_name);
+        }
+        self.forget_graph_folder()
+    }
+
+    /// Stops `require` from looking into the graph libraries folder. The
+    /// files it loaded from there are dropped from `_LOADED` too, or the next
+    /// graph would get them.
+    fn forget_graph_folder(&self) -> Result<()> {
+        let loaded = self.lua.globals().get::<_, Table>("_LOADED")?;
+        let from_folder = loaded
+            .clone()
+            .pairs::<String, Value>()
+            .filter_map(|entry| entry.ok())
+            .map(|(file, _)| file)
+            .filter(|file| {
+                self.lua_io.load_file_require(file).is_err()
+                    && load_from_graph_folder(&self.lua, file).is_some()
+            })
+            .collect_vec();
+        for file in from_folder {
+            loaded.set(file, Value::Nil)?;
+        }
+        self.lua.unset_named_registry_value(FOLDER_KEY)?;
+        Ok(())
+    }
+
+    /// Runs the loaded graph libraries again. Used after reloading the node
+    /// libraries, which drops their nodes.
+    pub(super) fn reload_graph_libraries(&mut self) -> Result<()> {
+        if let Some(loaded) = self.graph_libraries.take() {
+            // The reloaded node library doesn't have the nodes to remove.
+            for op_name in &loaded.ops {
+                self.node_definitions.remove(op_name);
+            }
+            self.forget_graph_folder()?;
+            self.load_graph_libraries(loaded.libraries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a library, and returns the nodes it added and the names of the
+/// existing nodes it tried to redefine. Those keep their old definition.
+fn run_library<'lua>(
+    lua: &'lua Lua,
+    file: &LuaSourceFile,
+) -> Result<(Vec<(String, Table<'lua>)>, Vec<String>)> {
+    let library = lua.load("require('node_library')").eval::<Table>()?;
+    let nodes = library.get::<_, Table>("nodes")?;
+    let sources = library.get::<_, Table>("sources")?;
+    let nodes_before = nodes
+        .clone()
+        .pairs::<String, Value>()
+        .collect::<mlua::Result<Vec<_>>>()?;
+    let sources_before = sources
+        .clone()
+        .pairs::<String, Value>()
+        .collect::<mlua::Result<Vec<_>>>()?;
+
+    library.set("current_source", file.name.as_str())?;
+    // Libraries run under the same limits as the nodes.
+    sandbox::begin_execution(lua, None);
+    let result = lua.load(file).exec();
+    sandbox::end_execution(lua);
+    library.set("current_source", Value::Nil)?;
+
+    let known: HashSet<&str> = nodes_before.iter().map(|(name, _)| name.as_str()).collect();
+    let mut added = vec![];
+    let mut redefined = vec![];
+    for entry in sources.clone().pairs::<String, Value>() {
+        let (op_name, source) = entry?;
+        let from_file = matches!(&source, Value::String(s) if s.as_bytes() == file.name.as_bytes());
+        if from_file {
+            if known.contains(op_name.as_str()) {
+                redefined.push(op_name);
+            } else if result.is_ok() {
+                added.push((op_name.clone(), nodes.get::<_, Table>(op_name.as_str())?));
+            } else {
+                nodes.set(op_name.as_str(), Value::Nil)?;
+                sources.set(op_name.as_str(), Value::Nil)?;
+            }
+        }
+    }
+    for (op_name, node) in nodes_before {
+        nodes.set(op_name, node)?;
+    }
+    for (op_name, source) in sources_before {
+        sources.set(op_name, source)?;
+    }
+    result.with_context(|| format!("Could not load the node library {}", file.name))?;
+    added.sort_by(|(a, _), (b, _)| a.cmp(b));
+    redefined.sort();
+    Ok((added, redefined))
+}
+
+/// Loads a file `require`d by a graph library from the graph libraries folder
+/// of the open graph, if there's one.
+pub(crate) fn load_from_graph_folder(lua: &Lua, file: &str) -> Option<LuaSourceFile> {
+    let folder = lua.named_registry_value::<_, String>(FOLDER_KEY).ok()?;
+    let mut path = Path::new(&folder).join(file);
+    path.set_extension("lua");
+    let contents = std::fs::read_to_string(&path).ok()?;
+    Some(LuaSourceFile {
+        contents,
+        name: path.display().to_string(),
+    })
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// The name a library with definitions of the given provenance is embedded
+/// with. Bundled libraries are never embedded.
+fn library_name(provenance: &NodeProvenance) -> Option<String> {
+    match provenance {
+        NodeProvenance::Bundled => None,
+        NodeProvenance::User { file } | NodeProvenance::GraphFolder { file } => {
+            Some(file_name(file))
+        }
+        NodeProvenance::Embedded { library } => Some(library.clone()),
+    }
+}
+
+fn check_embedded_size(size: usize) -> Result<()> {
+    if size > MAX_EMBEDDED_SIZE {
+        bail!(
+            "The node libraries take {size} bytes, more than the \
+             {MAX_EMBEDDED_SIZE} bytes a graph file can embed"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the sources of the libraries defining the nodes used by `graph`
+/// which don't ship with blackjack, to embed them in the graph file. Each
+/// library is embedded whole, once, and only if it has a node in the graph.
+pub fn embed_node_libraries(
+    runtime: &LuaRuntime,
+    graph: &BjkGraph,
+) -> Result<Vec<EmbeddedNodeLibrary>> {
+    let mut libraries = BTreeMap::<String, String>::new();
+    for node in graph.nodes.values() {
+        let provenance = match runtime.node_definitions.node_def(&node.op_name) {
+            Some(def) => def.provenance.clone(),
+            None => continue,
+        };
+        let name = match library_name(&provenance) {
+            Some(name) if !libraries.contains_key(&name) => name,
+            _ => continue,
+        };
+        let source = match &provenance {
+            NodeProvenance::User { file } => runtime.lua_io.load_file_absolute(file)?.contents,
+            _ => runtime
+                .graph_libraries
+                .as_ref()
+                .and_then(|loaded| loaded.sources.get(&name))
+                .cloned()
+                .ok_or_else(|| anyhow!("The source of the {provenance} is not loaded"))?,
+        };
+        libraries.insert(name, source);
+    }
+    check_embedded_size(libraries.values().map(String::len).sum())?;
+    Ok(libraries
+        .into_iter()
+        .map(|(name, source)| EmbeddedNodeLibrary { name, source })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::{BlackjackValue, DataType};
+    use crate::graph_interpreter::{run_graph, ExternalParameter, ExternalParameterValues};
+    use crate::lua_engine::lua_stdlib::{LuaFileIo, StdLuaFileIo};
+    use crate::lua_engine::RenderableThing;
+
+    const LIBRARY: &str = r#"
+local P = require("params")
+local NodeLibrary = require("node_library")
+NodeLibrary:addNodes({
+    MakeTestBox = {
+        label = "Test box",
+        op = function(inputs)
+            return { out_mesh = Primitives.cube(vector(0, 0, 0), inputs.size) }
+        end,
+        inputs = { P.v3("size", vector(1, 1, 1)) },
+        outputs = { P.mesh("out_mesh") },
+        returns = "out_mesh",
+    },
+})
+"#;
+
+    /// The std file io, with one more file in the `run` folder.
+    struct WithUserLibrary {
+        std: StdLuaFileIo,
+        path: String,
+    }
+
+    impl LuaFileIo for WithUserLibrary {
+        fn base_folder(&self) -> &str {
+            self.std.base_folder()
+        }
+
+        fn find_run_files(&self) -> Box<dyn Iterator<Item = String>> {
+            Box::new(
+                self.std
+                    .find_run_files()
+                    .chain(std::iter::once(self.path.clone())),
+            )
+        }
+
+        fn load_file_absolute(&self, path: &str) -> Result<LuaSourceFile> {
+            self.std.load_file_absolute(path)
+        }
+
+        fn load_file_require(&self, path: &str) -> Result<LuaSourceFile> {
+            self.std.load_file_require(path)
+        }
+    }
+
+    fn test_box_graph() -> (BjkGraph, crate::graph::BjkNodeId, ExternalParameterValues) {
+        let mut graph = BjkGraph::new();
+        let node = graph.add_node("MakeTestBox", Some("out_mesh".into()));
+        graph
+            .add_input(node, "size", DataType::Vector, None)
+            .unwrap();
+        graph.add_output(node, "out_mesh", DataType::Mesh).unwrap();
+        let mut params = ExternalParameterValues::default();
+        params.0.insert(
+            ExternalParameter::new(node, "size".into()),
+            BlackjackValue::Vector(Vec3::ONE),
+        );
+        (graph, node, params)
+    }
+
+    fn embedded(name: &str, source: &str) -> GraphLibraries {
+        GraphLibraries {
+            embedded: vec![EmbeddedNodeLibrary {
+                name: name.into(),
+                source: source.into(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_embed_user_libraries() {
+        let path = std::env::temp_dir().join("blackjack_embed_test_nodes.lua");
+        std::fs::write(&path, LIBRARY).unwrap();
+        let runtime = LuaRuntime::initialize_custom(WithUserLibrary {
+            std: StdLuaFileIo {
+                base_folder: "../blackjack_lua".into(),
+            },
+            path: path.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            runtime
+                .node_definitions
+                .node_def("MakeTestBox")
+                .unwrap()
+                .provenance,
+            NodeProvenance::User {
+                file: path.display().to_string()
+            }
+        );
+        assert_eq!(
+            runtime
+                .node_definitions
+                .node_def("MakeBox")
+                .unwrap()
+                .provenance,
+            NodeProvenance::Bundled
+        );
+
+        // Bundled libraries are never embedded
+        let (mut graph, _, _) = test_box_graph();
+        let mut bundled_only = BjkGraph::new();
+        bundled_only.add_node("MakeBox", Some("out_mesh".into()));
+        assert!(embed_node_libraries(&runtime, &bundled_only)
+            .unwrap()
+            .is_empty());
+
+        // A library used by several nodes is embedded once
+        graph.add_node("MakeTestBox", Some("out_mesh".into()));
+        graph.add_node("MakeBox", Some("out_mesh".into()));
+        assert_eq!(
+            embed_node_libraries(&runtime, &graph).unwrap(),
+            vec![EmbeddedNodeLibrary {
+                name: "blackjack_embed_test_nodes.lua".into(),
+                source: LIBRARY.into(),
+            }]
+        );
+
+        let huge = format!("{LIBRARY}\n--{}", "x".repeat(MAX_EMBEDDED_SIZE));
+        std::fs::write(&path, huge).unwrap();
+        let err = embed_node_libraries(&runtime, &graph).unwrap_err();
+        assert!(err.to_string().contains("bytes"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_load_embedded_libraries() {
+        let mut runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        assert!(runtime.node_definitions.node_def("MakeTestBox").is_none());
+
+        let library = NodeProvenance::Embedded {
+            library: "test_nodes.lua".into(),
+        };
+        let report = runtime
+            .load_graph_libraries(embedded("test_nodes.lua", LIBRARY))
+            .unwrap();
+        assert_eq!(
+            report.loaded,
+            vec![("MakeTestBox".to_owned(), library.clone())]
+        );
+        assert!(report.skipped.is_empty());
+        assert!(report.sandboxed);
+        assert!(runtime.config().sandboxed);
+        assert_eq!(
+            runtime
+                .node_definitions
+                .node_def("MakeTestBox")
+                .unwrap()
+                .provenance,
+            library
+        );
+
+        let (graph, node, params) = test_box_graph();
+        let result = run_graph(
+            &runtime.lua,
+            &graph,
+            node,
+            params,
+            &runtime.node_definitions,
+            None,
+        )
+        .unwrap();
+        match &result.renderable {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => {
+                assert_eq!(mesh.read_connectivity().num_vertices(), 8)
+            }
+            _ => panic!("Expected a mesh"),
+        }
+
+        // Saving the graph again keeps the library
+        assert_eq!(
+            embed_node_libraries(&runtime, &graph).unwrap(),
+            embedded("test_nodes.lua", LIBRARY).embedded
+        );
+
+        runtime.unload_graph_libraries().unwrap();
+        assert!(runtime.node_definitions.node_def("MakeTestBox").is_none());
+        let node = runtime
+            .lua
+            .load("return require('node_library').nodes.MakeTestBox")
+            .eval::<Value>()
+            .unwrap();
+        assert!(matches!(node, Value::Nil));
+
+        let huge = format!("--{}", "x".repeat(MAX_EMBEDDED_SIZE));
+        let err = runtime
+            .load_graph_libraries(embedded("huge.lua", &huge))
+            .unwrap_err();
+        assert!(err.to_string().contains("bytes"));
+    }
+
+    #[test]
+    fn test_load_graph_folder_libraries() {
+        let folder = std::env::temp_dir().join("blackjack_graph_folder_test");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(
+            folder.join("box_helpers.lua"),
+            "return { size = function() return vector(2, 2, 2) end }",
+        )
+        .unwrap();
+        std::fs::write(
+            folder.join("boxes.lua"),
+            LIBRARY.replace(
+                "local P",
+                "local Helpers = require('box_helpers')\nassert(Helpers.size())\nlocal P",
+            ),
+        )
+        .unwrap();
+
+        let mut runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let report = runtime
+            .load_graph_libraries(GraphLibraries {
+                folder: Some(folder.clone()),
+                trusted: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(!report.sandboxed);
+        assert!(!runtime.config().sandboxed);
+        // The helpers define no nodes
+        assert_eq!(
+            report.loaded,
+            vec![(
+                "MakeTestBox".to_owned(),
+                NodeProvenance::GraphFolder {
+                    file: "boxes.lua".into()
+                }
+            )]
+        );
+
+        // The folder is only searched while the libraries are loaded
+        runtime.unload_graph_libraries().unwrap();
+        assert!(load_from_graph_folder(&runtime.lua, "box_helpers").is_none());
+        assert!(runtime
+            .lua
+            .load("return require('box_helpers')")
+            .eval::<Value>()
+            .is_err());
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn test_graph_libraries_are_sandboxed() {
+        let path = std::env::temp_dir().join("blackjack_graph_library_write.txt");
+        let _ = std::fs::remove_file(&path);
+        let writer = format!("Io.write({:?}, 'contents')", path.display().to_string());
+
+        let mut runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let err = runtime
+            .load_graph_libraries(embedded("writer.lua", &writer))
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("sandboxed mode"));
+        assert!(!path.exists());
+
+        // Existing nodes keep their definitions
+        let label = |runtime: &LuaRuntime| {
+            runtime
+                .node_definitions
+                .node_def("MakeBox")
+                .unwrap()
+                .label
+                .clone()
+        };
+        let before = label(&runtime);
+        let report = runtime
+            .load_graph_libraries(embedded(
+                "boxes.lua",
+                &LIBRARY.replace("MakeTestBox", "MakeBox"),
+            ))
+            .unwrap();
+        assert!(report.loaded.is_empty());
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "MakeBox");
+        assert_eq!(label(&runtime), before);
+        assert_eq!(
+            runtime
+                .node_definitions
+                .node_def("MakeBox")
+                .unwrap()
+                .provenance,
+            NodeProvenance::Bundled
+        );
+        let label_in_lua = runtime
+            .lua
+            .load("return require('node_library').nodes.MakeBox.label")
+            .eval::<String>()
+            .unwrap();
+        assert_eq!(label_in_lua, before);
+    }
+}
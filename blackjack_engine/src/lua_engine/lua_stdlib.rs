@@ -24,6 +24,14 @@ pub use lua_require_io::*;
 
 mod lua_core_library;
 
+mod lua_vector;
+
+mod lua_format;
+
+pub mod lua_path;
+
+pub mod lua_coercion;
+
 pub mod lua_documentation;
 
 /// A function pointer to register global lua functions. Stored globally using
@@ -50,6 +58,7 @@ pub fn load_lua_bindings(lua: &Lua, lua_io: Arc<dyn LuaFileIo + 'static>) -> any
     for register_fn in inventory::iter::<LuaRegisterFn>() {
         (register_fn.f)(lua).expect("Failed to register Lua API");
     }
+    lua_vector::init_vector_metatable(lua)?;
 
     Ok(())
 }
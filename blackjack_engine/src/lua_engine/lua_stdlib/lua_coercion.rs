@@ -0,0 +1,93 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! All Lua numbers arrive to Rust as floats. Functions exported with `#[lua]`
+//! that take integer arguments receive them as an `mlua::Number`, and convert
+//! them with [`coerce_integer_arg`], unless marked as `#[lua(strict)]`.
+
+/// How far from an integer a Lua number can be and still be accepted as one.
+/// Leaves room for rounding errors like `0.1 * 30`.
+pub const INTEGER_EPSILON: f64 = 1e-6;
+
+/// Converts the Lua number `value`, passed as the argument `arg_name` of the
+/// function `fn_name`, to the integer type `T`. Errors when the number is not
+/// integral, or doesn't fit in `T`.
+pub fn coerce_integer_arg<T: TryFrom<i64>>(
+    value: mlua::Number,
+    fn_name: &str,
+    arg_name: &str,
+) -> mlua::Result<T> {
+    let type_name = std::any::type_name::<T>();
+    let error = |reason: &str| {
+        mlua::Error::RuntimeError(format!(
+            "Invalid argument '{arg_name}' for '{fn_name}': Expected {type_name}, got {value}, which {reason}"
+        ))
+    };
+
+    let rounded = value.round();
+    if !value.is_finite() || (value - rounded).abs() > INTEGER_EPSILON {
+        return Err(error("is not an integer"));
+    }
+    // Floats outside of this range would saturate when cast to i64. The upper
+    // bound is 2^63, which is already out of range.
+    if rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+        return Err(error("is out of range"));
+    }
+    T::try_from(rounded as i64).map_err(|_| error("is out of range"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn coerce<T: TryFrom<i64>>(value: f64) -> Result<T, String> {
+        coerce_integer_arg::<T>(value, "Ops.test", "amount").map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_coerce_u32() {
+        assert_eq!(coerce::<u32>(3.0), Ok(3));
+        assert_eq!(coerce::<u32>(0.1 * 30.0), Ok(3));
+        assert_eq!(coerce::<u32>(4294967295.0), Ok(u32::MAX));
+
+        let err = coerce::<u32>(3.5).unwrap_err();
+        assert!(err.contains("'amount'"), "{err}");
+        assert!(err.contains("'Ops.test'"), "{err}");
+        assert!(err.contains("3.5"), "{err}");
+        assert!(err.contains("not an integer"), "{err}");
+
+        assert!(coerce::<u32>(-1.0).unwrap_err().contains("out of range"));
+        assert!(coerce::<u32>(4294967296.0)
+            .unwrap_err()
+            .contains("out of range"));
+        assert!(coerce::<u32>(f64::NAN).is_err());
+        assert!(coerce::<u32>(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_coerce_i64() {
+        assert_eq!(coerce::<i64>(-42.0), Ok(-42));
+        assert_eq!(coerce::<i64>(1e15), Ok(1_000_000_000_000_000));
+        assert_eq!(coerce::<i64>(i64::MIN as f64), Ok(i64::MIN));
+
+        assert!(coerce::<i64>(-0.25).unwrap_err().contains("not an integer"));
+        assert!(coerce::<i64>(9.3e18).unwrap_err().contains("out of range"));
+        assert!(coerce::<i64>(-1e19).unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn test_coerce_usize() {
+        assert_eq!(coerce::<usize>(0.0), Ok(0));
+        assert_eq!(coerce::<usize>(12.0), Ok(12));
+        assert_eq!(coerce::<usize>(-0.0), Ok(0));
+
+        assert!(coerce::<usize>(7.999)
+            .unwrap_err()
+            .contains("not an integer"));
+        assert!(coerce::<usize>(-3.0).unwrap_err().contains("out of range"));
+        assert!(coerce::<usize>(1e20).unwrap_err().contains("out of range"));
+    }
+}
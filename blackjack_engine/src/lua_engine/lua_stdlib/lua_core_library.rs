@@ -44,7 +44,17 @@ pub fn load(lua: &Lua, lua_io: Arc<dyn LuaFileIo + 'static>) -> anyhow::Result<(
                     // The `def_lib!` calls above return. If none did, then we
                     // know this is a regular lua file from the filesystem.
                     {
-                        let file_chunk = lua_io.load_file_require(&file).map_lua_err()?;
+                        crate::lua_engine::sandbox::check_require_path(lua, &file).map_lua_err()?;
+                        let file_chunk = match lua_io.load_file_require(&file) {
+                            Ok(file_chunk) => file_chunk,
+                            // Graph libraries can require the other files in
+                            // their folder.
+                            Err(err) => crate::lua_engine::graph_libraries::load_from_graph_folder(
+                                lua, &file,
+                            )
+                            .ok_or(err)
+                            .map_lua_err()?,
+                        };
                         let value = lua.load(&file_chunk).eval::<mlua::Value>()?;
                         loaded.set(file, value.clone())?;
                         Ok(value)
@@ -74,19 +84,24 @@ pub fn load(lua: &Lua, lua_io: Arc<dyn LuaFileIo + 'static>) -> anyhow::Result<(
 #[blackjack_macros::blackjack_lua_module]
 mod lua_module {
     use anyhow::Result;
+    use mlua::Lua;
+
+    use crate::lua_engine::sandbox::ensure_filesystem_allowed;
 
     /// Read the contents of the file at `path` and return as a string. Will
     /// fail if the path does not exist, or the user does not have correct
     /// access permissions.
     #[lua(under = "Io")]
-    pub fn read_to_string(path: String) -> Result<String> {
+    pub fn read_to_string(lua: &Lua, path: String) -> Result<String> {
+        ensure_filesystem_allowed(lua, "Io.read_to_string")?;
         Ok(std::fs::read_to_string(path)?)
     }
 
     /// Write the given string `contents` as a file to the given `path`. Will
     /// overwrite any previous existing file with that name.
     #[lua(under = "Io")]
-    pub fn write(path: String, contents: String) -> Result<()> {
+    pub fn write(lua: &Lua, path: String, contents: String) -> Result<()> {
+        ensure_filesystem_allowed(lua, "Io.write")?;
         std::fs::write(path, contents)?;
         Ok(())
     }
@@ -0,0 +1,198 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! String formatting for Lua, using a subset of Rust's `format!` syntax.
+
+use crate::prelude::*;
+
+/// A value to be formatted by [`format_values`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatArg {
+    Integer(i64),
+    Number(f64),
+    Text(String),
+}
+
+/// How a single `{}` placeholder is formatted: `{:[0][width][.precision]}`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct FormatSpec {
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid format specifier '{{:{spec}}}'");
+        let (width, precision) = match spec.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (spec, None),
+        };
+        let zero_pad = width.len() > 1 && width.starts_with('0');
+        let parse_num = |s: &str| -> Result<usize> {
+            if s.chars().all(|c| c.is_ascii_digit()) {
+                s.parse().map_err(|_| invalid())
+            } else {
+                Err(invalid())
+            }
+        };
+        Ok(Self {
+            zero_pad,
+            width: if width.is_empty() {
+                0
+            } else {
+                parse_num(width)?
+            },
+            precision: precision.map(parse_num).transpose()?,
+        })
+    }
+
+    fn apply(&self, arg: &FormatArg) -> String {
+        let w = self.width;
+        match (arg, self.precision) {
+            (FormatArg::Text(s), None) => format!("{s:w$}"),
+            (FormatArg::Text(s), Some(p)) => format!("{s:w$.p$}"),
+            (FormatArg::Integer(i), None) if self.zero_pad => format!("{i:0w$}"),
+            (FormatArg::Integer(i), None) => format!("{i:>w$}"),
+            // Integers with a precision are printed as numbers
+            (FormatArg::Integer(i), Some(_)) => self.apply(&FormatArg::Number(*i as f64)),
+            (FormatArg::Number(n), None) if self.zero_pad => format!("{n:0w$}"),
+            (FormatArg::Number(n), None) => format!("{n:>w$}"),
+            (FormatArg::Number(n), Some(p)) if self.zero_pad => format!("{n:0w$.p$}"),
+            (FormatArg::Number(n), Some(p)) => format!("{n:>w$.p$}"),
+        }
+    }
+}
+
+/// Replaces each `{}` placeholder in `fmt` with the next of the `args`. Use
+/// `{{` and `}}` for literal braces. Fails when the number of placeholders
+/// and arguments doesn't match.
+pub fn format_values(fmt: &str, args: &[FormatArg]) -> Result<String> {
+    let mut result = String::with_capacity(fmt.len());
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+    let mut placeholders = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            bail!("Unclosed '{{' in format string. Use '{{{{' for a literal brace")
+                        }
+                    }
+                }
+                let spec = match placeholder.strip_prefix(':') {
+                    Some(spec) => FormatSpec::parse(spec)?,
+                    None if placeholder.is_empty() => FormatSpec::default(),
+                    None => bail!(
+                        "Invalid placeholder '{{{placeholder}}}'. Only '{{}}' and \
+                         '{{:spec}}' are supported"
+                    ),
+                };
+                placeholders += 1;
+                let arg = args.next().ok_or_else(|| {
+                    anyhow!("Missing argument for placeholder number {placeholders}")
+                })?;
+                result.push_str(&spec.apply(arg));
+            }
+            '}' => bail!("Unmatched '}}' in format string. Use '}}}}' for a literal brace"),
+            c => result.push(c),
+        }
+    }
+    let extra = args.count();
+    if extra > 0 {
+        bail!(
+            "The format string has {placeholders} placeholders, but {extra} more \
+             arguments were given"
+        );
+    }
+    Ok(result)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Formats the given values into the `fmt` string, replacing each `{}`.
+    /// Placeholders can specify a width, zero padding and a precision, as in
+    /// `{:04}` for frame numbers or `{:.2}` for decimals.
+    #[lua(under = "Str")]
+    pub fn format(fmt: String, args: mlua::Variadic<mlua::Value>) -> Result<String> {
+        let args = args
+            .into_iter()
+            .map(|value| {
+                Ok(match value {
+                    mlua::Value::Integer(i) => FormatArg::Integer(i),
+                    mlua::Value::Number(n) => FormatArg::Number(n),
+                    mlua::Value::String(s) => FormatArg::Text(s.to_str()?.into()),
+                    mlua::Value::Boolean(b) => FormatArg::Text(b.to_string()),
+                    mlua::Value::Nil => FormatArg::Text("nil".into()),
+                    mlua::Value::Vector(x, y, z) => {
+                        FormatArg::Text(format!("vector({x}, {y}, {z})"))
+                    }
+                    value => bail!("Cannot format a value of type {}", value.type_name()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        format_values(&fmt, &args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use FormatArg::*;
+
+    #[test]
+    fn test_format() {
+        let f = |fmt, args: &[FormatArg]| format_values(fmt, args).unwrap();
+        assert_eq!(f("frame_{:04}.obj", &[Integer(7)]), "frame_0007.obj");
+        assert_eq!(f("frame_{:04}.obj", &[Number(7.0)]), "frame_0007.obj");
+        assert_eq!(f("{}-{}", &[Text("a".into()), Integer(-3)]), "a--3");
+        assert_eq!(f("{:.2}", &[Number(1.0 / 3.0)]), "0.33");
+        assert_eq!(f("{:06.2}", &[Integer(3)]), "003.00");
+        assert_eq!(f("[{:4}]", &[Integer(12)]), "[  12]");
+        assert_eq!(f("[{:4}]", &[Text("ab".into())]), "[ab  ]");
+        assert_eq!(f("{{{}}}", &[Integer(1)]), "{1}");
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let err = |fmt, args: &[FormatArg]| format_values(fmt, args).unwrap_err().to_string();
+        assert!(err("{} {}", &[Integer(1)]).contains("Missing argument"));
+        assert!(err("{}", &[Integer(1), Integer(2)]).contains("more arguments"));
+        assert!(err("{:x}", &[Integer(1)]).contains("Invalid format specifier"));
+        assert!(err("{name}", &[Integer(1)]).contains("Invalid placeholder"));
+        assert!(err("a } b", &[]).contains("Unmatched"));
+        assert!(err("frame_{:04", &[Integer(1)]).contains("Unclosed"));
+    }
+
+    #[test]
+    fn test_format_from_lua() {
+        let lua = mlua::Lua::new();
+        for register_fn in inventory::iter::<super::super::LuaRegisterFn>() {
+            (register_fn.f)(&lua).unwrap();
+        }
+        let formatted: String = lua
+            .load(r#"return Str.format("{}_{:03}.{}", "sword", 12, true)"#)
+            .eval()
+            .unwrap();
+        assert_eq!(formatted, "sword_012.true");
+    }
+}
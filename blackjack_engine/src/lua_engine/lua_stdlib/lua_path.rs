@@ -0,0 +1,511 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Helpers to manipulate file paths from Lua. Paths are plain strings, and
+//! both `/` and `\` are accepted as separators regardless of the platform, so
+//! graphs saved on one system keep working on the others.
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+use crate::prelude::*;
+
+/// Paths starting with this prefix are relative to the folder of the graph
+/// file, as in Blender. They always use `/` as the separator, so graphs can be
+/// moved between systems along with the files they use.
+pub const PROJECT_RELATIVE_PREFIX: &str = "//";
+
+/// What the ops running in a graph need to know about the project it belongs
+/// to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectContext {
+    /// The folder containing the `bjk` file of the graph, or `None` for graphs
+    /// that have never been saved.
+    pub graph_dir: Option<PathBuf>,
+}
+
+impl ProjectContext {
+    /// Returns the context for a graph saved at `file`, if any.
+    pub fn for_graph_file(file: Option<&Path>) -> Self {
+        Self {
+            graph_dir: file.map(|file| {
+                let dir = parent(&file.to_string_lossy());
+                PathBuf::from(if dir.is_empty() { ".".into() } else { dir })
+            }),
+        }
+    }
+
+    fn graph_dir_str(&self) -> Option<String> {
+        self.graph_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned())
+    }
+
+    /// Returns the path to open for `path`, which is only different for paths
+    /// relative to the project. Fails for those when the graph has not been
+    /// saved.
+    pub fn resolve(&self, path: &str) -> Result<String> {
+        resolve_with(
+            path,
+            self.graph_dir_str().as_deref(),
+            std::path::MAIN_SEPARATOR,
+        )
+    }
+
+    /// Returns `path` relative to the folder of the graph, see
+    /// [`make_relative`].
+    pub fn make_relative(&self, path: &str) -> Option<String> {
+        make_relative(path, &self.graph_dir_str()?)
+    }
+
+    /// Returns whether `path` is inside the folder of the graph.
+    pub fn contains(&self, path: &str) -> bool {
+        self.graph_dir_str()
+            .map_or(false, |dir| is_inside(path, &dir))
+    }
+}
+
+thread_local! {
+    /// The project of the graph currently running on this thread. Ops are
+    /// called from Lua, so there is no other way to pass it around.
+    static CURRENT_PROJECT: RefCell<ProjectContext> = RefCell::new(ProjectContext::default());
+}
+
+/// Runs `f` with `project` as the current project for this thread. The
+/// previous project is restored afterwards, even when `f` panics.
+pub fn with_project<T>(project: &ProjectContext, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<ProjectContext>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                CURRENT_PROJECT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+    }
+    let _restore = Restore(Some(
+        CURRENT_PROJECT.with(|current| current.replace(project.clone())),
+    ));
+    f()
+}
+
+/// Runs `f` with the project of the graph saved at `path`, if any.
+pub fn with_project_file<T>(path: Option<&Path>, f: impl FnOnce() -> T) -> T {
+    with_project(&ProjectContext::for_graph_file(path), f)
+}
+
+/// Returns the folder containing the `bjk` file of the graph currently
+/// running. Fails for graphs that have never been saved.
+pub fn project_dir() -> Result<String> {
+    match CURRENT_PROJECT.with(|current| current.borrow().graph_dir_str()) {
+        Some(dir) => Ok(dir),
+        None => bail!("The graph has not been saved yet, so it has no project directory"),
+    }
+}
+
+/// Resolves `path` against the project of the graph currently running, see
+/// [`ProjectContext::resolve`].
+pub fn resolve_project_path(path: &str) -> Result<String> {
+    CURRENT_PROJECT.with(|current| current.borrow().resolve(path))
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Returns whether `path` starts at a root, either with a separator or with a
+/// Windows drive letter like `C:`.
+pub fn is_absolute(path: &str) -> bool {
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), _) if is_separator(c) => true,
+        (Some(drive), Some(':')) => drive.is_ascii_alphabetic(),
+        _ => false,
+    }
+}
+
+/// Joins `parts` with the given `separator`. Separators already at the end of
+/// a part are kept, and an absolute part discards everything before it.
+pub fn join_with(parts: &[&str], separator: char) -> String {
+    let mut result = String::new();
+    for part in parts.iter().filter(|p| !p.is_empty()) {
+        if is_absolute(part) {
+            result.clear();
+        } else if !result.is_empty() && !result.ends_with(is_separator) {
+            result.push(separator);
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// Splits `path` at its last separator, ignoring trailing ones. Returns the
+/// folder, with the root kept for top-level paths, and the file name.
+fn split_last(path: &str) -> (&str, &str) {
+    let trimmed = path.trim_end_matches(is_separator);
+    if trimmed.is_empty() {
+        // Only separators, or an empty path
+        return (&path[..path.len().min(1)], "");
+    }
+    match trimmed.rfind(is_separator) {
+        Some(idx) => {
+            let dir = trimmed[..idx].trim_end_matches(is_separator);
+            let dir = if dir.is_empty() || (dir.len() == 2 && is_absolute(dir)) {
+                &trimmed[..=idx]
+            } else {
+                dir
+            };
+            (dir, &trimmed[idx + 1..])
+        }
+        None => ("", trimmed),
+    }
+}
+
+/// Returns the folder containing `path`, or an empty string when `path` is
+/// just a file name.
+pub fn parent(path: &str) -> String {
+    split_last(path).0.into()
+}
+
+/// Returns the last component of `path`.
+pub fn filename(path: &str) -> String {
+    split_last(path).1.into()
+}
+
+/// Returns whether `path` is relative to the folder of the graph, that is,
+/// whether it starts with [`PROJECT_RELATIVE_PREFIX`].
+pub fn is_project_relative(path: &str) -> bool {
+    path.starts_with(PROJECT_RELATIVE_PREFIX)
+}
+
+/// Splits `path` into its root and its components. The root is empty for
+/// relative paths, `/` for paths starting with a separator of either kind, or
+/// a drive like `C:`. Empty and `.` components are dropped, and `..` removes
+/// the component before it, if any.
+fn components(path: &str) -> (String, Vec<&str>) {
+    let (root, rest) = if path.starts_with(is_separator) {
+        ("/".to_string(), path)
+    } else if is_absolute(path) {
+        (path[..2].to_ascii_uppercase(), &path[2..])
+    } else {
+        (String::new(), path)
+    };
+    let mut components: Vec<&str> = vec![];
+    for component in rest.split(is_separator) {
+        match component {
+            "" | "." => {}
+            ".." if components.last().map_or(false, |last| *last != "..") => {
+                components.pop();
+            }
+            // There is nothing above the root
+            ".." if !root.is_empty() => {}
+            component => components.push(component),
+        }
+    }
+    (root, components)
+}
+
+/// Returns the path with the given root and components, as returned by
+/// [`components`], using `separator`.
+fn assemble(root: &str, components: &[&str], separator: char) -> String {
+    let joined = components.join(&separator.to_string());
+    match root {
+        "" if joined.is_empty() => ".".into(),
+        "" => joined,
+        "/" => format!("{separator}{joined}"),
+        drive => format!("{drive}{separator}{joined}"),
+    }
+}
+
+/// Returns the absolute `path` relative to `dir`, with the
+/// [`PROJECT_RELATIVE_PREFIX`], going up with `..` for files outside `dir`.
+/// Returns `None` when `path` is not absolute, or is on another drive. Paths
+/// already relative to the project are returned as they are.
+pub fn make_relative(path: &str, dir: &str) -> Option<String> {
+    if is_project_relative(path) {
+        return Some(path.into());
+    }
+    let (root, parts) = components(path);
+    let (dir_root, dir_parts) = components(dir);
+    if root.is_empty() || root != dir_root {
+        return None;
+    }
+    let common = parts
+        .iter()
+        .zip(&dir_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let relative = std::iter::repeat("..")
+        .take(dir_parts.len() - common)
+        .chain(parts[common..].iter_cpy())
+        .join("/");
+    Some(format!("{PROJECT_RELATIVE_PREFIX}{relative}"))
+}
+
+/// Returns whether the absolute `path` is inside `dir`.
+pub fn is_inside(path: &str, dir: &str) -> bool {
+    !is_project_relative(path)
+        && make_relative(path, dir).map_or(false, |relative| {
+            relative[PROJECT_RELATIVE_PREFIX.len()..].split('/').next() != Some("..")
+        })
+}
+
+/// Resolves a `path` relative to the project against the folder of the graph,
+/// `dir`, using `separator`. Other paths are returned as they are. Fails when
+/// there is no `dir`, because the graph has not been saved.
+pub fn resolve_with(path: &str, dir: Option<&str>, separator: char) -> Result<String> {
+    let relative = match path.strip_prefix(PROJECT_RELATIVE_PREFIX) {
+        Some(relative) => relative,
+        None => return Ok(path.into()),
+    };
+    let dir = dir.ok_or_else(|| {
+        anyhow!(
+            "The path '{path}' is relative to the folder of the graph, but the graph has \
+             not been saved yet. Save the graph, or use an absolute path."
+        )
+    })?;
+    let joined = format!("{dir}/{relative}");
+    let (root, parts) = components(&joined);
+    Ok(assemble(&root, &parts, separator))
+}
+
+/// Replaces the extension of the file name in `path` with `extension`, or
+/// adds it when there is none. An empty `extension` removes it.
+pub fn with_extension(path: &str, extension: &str) -> String {
+    let name_start = path.rfind(is_separator).map(|idx| idx + 1).unwrap_or(0);
+    let stem_end = match path[name_start..].rfind('.') {
+        // A leading dot is part of the name, as in `.hidden`
+        Some(idx) if idx > 0 => name_start + idx,
+        _ => path.len(),
+    };
+    let extension = extension.trim_start_matches('.');
+    if extension.is_empty() {
+        path[..stem_end].into()
+    } else {
+        format!("{}.{extension}", &path[..stem_end])
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Joins the given path components with the separator of the current
+    /// platform. A component that is an absolute path replaces the ones
+    /// before it.
+    #[lua(under = "Path")]
+    pub fn join(parts: mlua::Variadic<String>) -> Result<String> {
+        let parts = parts.iter().map(|p| p.as_str()).collect_vec();
+        Ok(join_with(&parts, std::path::MAIN_SEPARATOR))
+    }
+
+    /// Returns `path` with its extension replaced by `extension`. An empty
+    /// `extension` removes it.
+    #[lua(under = "Path")]
+    pub fn with_extension(path: String, extension: String) -> Result<String> {
+        Ok(super::with_extension(&path, &extension))
+    }
+
+    /// Returns the folder containing `path`.
+    #[lua(under = "Path")]
+    pub fn parent(path: String) -> Result<String> {
+        Ok(super::parent(&path))
+    }
+
+    /// Returns the file name of `path`, without its folder.
+    #[lua(under = "Path")]
+    pub fn filename(path: String) -> Result<String> {
+        Ok(super::filename(&path))
+    }
+
+    /// Returns whether `path` is absolute.
+    #[lua(under = "Path")]
+    pub fn is_absolute(path: String) -> Result<bool> {
+        Ok(super::is_absolute(&path))
+    }
+
+    /// Returns the home folder of the current user.
+    #[lua(under = "Path")]
+    pub fn home() -> Result<String> {
+        std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .map_err(|_| anyhow!("Could not find the home folder of the current user"))
+    }
+
+    /// Returns the folder containing the file of the current graph. Raises
+    /// an error if the graph has not been saved yet.
+    #[lua(under = "Path")]
+    pub fn project_dir() -> Result<String> {
+        super::project_dir()
+    }
+
+    /// Returns the file a `path` starting with `//` refers to, in the folder
+    /// of the current graph. Other paths are returned as they are. File
+    /// parameters are already resolved before they reach the nodes.
+    #[lua(under = "Path")]
+    pub fn resolve(path: String) -> Result<String> {
+        resolve_project_path(&path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        graph::{serialization::SerializedBjkGraph, BjkGraph},
+        lua_engine::LuaRuntime,
+    };
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join_with(&["a", "b", "c.obj"], '/'), "a/b/c.obj");
+        assert_eq!(join_with(&["a", "b", "c.obj"], '\\'), "a\\b\\c.obj");
+        // Existing separators of either kind are not repeated
+        assert_eq!(join_with(&["a/", "b"], '\\'), "a/b");
+        assert_eq!(
+            join_with(&["C:\\models\\", "out.obj"], '/'),
+            "C:\\models\\out.obj"
+        );
+        assert_eq!(join_with(&["a", "", "b"], '/'), "a/b");
+        // Absolute parts reset the path
+        assert_eq!(join_with(&["a", "/tmp", "b"], '/'), "/tmp/b");
+        assert_eq!(join_with(&["a", "D:\\out", "b"], '\\'), "D:\\out\\b");
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(parent("models/out.obj"), "models");
+        assert_eq!(parent("models\\sword\\out.obj"), "models\\sword");
+        assert_eq!(parent("models/sword/"), "models");
+        assert_eq!(parent("/out.obj"), "/");
+        assert_eq!(parent("C:\\out.obj"), "C:\\");
+        assert_eq!(parent("out.obj"), "");
+        assert_eq!(filename("models\\sword/out.obj"), "out.obj");
+        assert_eq!(filename("models/sword/"), "sword");
+        assert_eq!(filename("out.obj"), "out.obj");
+    }
+
+    #[test]
+    fn test_with_extension() {
+        assert_eq!(with_extension("out", "obj"), "out.obj");
+        assert_eq!(with_extension("out.gltf", ".obj"), "out.obj");
+        assert_eq!(with_extension("a.b\\out", "obj"), "a.b\\out.obj");
+        assert_eq!(
+            with_extension("models/.hidden", "obj"),
+            "models/.hidden.obj"
+        );
+        assert_eq!(with_extension("out.obj", ""), "out");
+    }
+
+    #[test]
+    fn test_make_relative() {
+        assert_eq!(
+            make_relative("/home/me/jacks/models/sword.obj", "/home/me/jacks").unwrap(),
+            "//models/sword.obj"
+        );
+        // Relative paths always use `/`, and drives are compared ignoring case
+        assert_eq!(
+            make_relative("c:\\jacks\\models\\sword.obj", "C:\\jacks\\").unwrap(),
+            "//models/sword.obj"
+        );
+        assert_eq!(
+            make_relative("/home/me/textures/rock.png", "/home/me/jacks/").unwrap(),
+            "//../textures/rock.png"
+        );
+        assert_eq!(make_relative("D:\\rock.png", "C:\\jacks"), None);
+        assert_eq!(make_relative("rock.png", "/home/me/jacks"), None);
+        assert_eq!(
+            make_relative("//rock.png", "/home/me/jacks").unwrap(),
+            "//rock.png"
+        );
+
+        assert!(is_inside("/home/me/jacks/./sword.obj", "/home/me/jacks"));
+        assert!(!is_inside("/home/me/jacks2/sword.obj", "/home/me/jacks"));
+        assert!(!is_inside("/home/me/jacks/../sword.obj", "/home/me/jacks"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve_with("//models/sword.obj", Some("/home/me/jacks"), '/').unwrap(),
+            "/home/me/jacks/models/sword.obj"
+        );
+        // Separators of both kinds are replaced by the given one
+        assert_eq!(
+            resolve_with("//models\\sword.obj", Some("C:\\jacks"), '\\').unwrap(),
+            "C:\\jacks\\models\\sword.obj"
+        );
+        assert_eq!(
+            resolve_with("//../textures/rock.png", Some("/home/me/jacks/"), '/').unwrap(),
+            "/home/me/textures/rock.png"
+        );
+        assert_eq!(
+            resolve_with("//sword.obj", Some("../examples"), '/').unwrap(),
+            "../examples/sword.obj"
+        );
+        // Other paths are left alone, even without a folder
+        assert_eq!(
+            resolve_with("/tmp/sword.obj", None, '\\').unwrap(),
+            "/tmp/sword.obj"
+        );
+        assert_eq!(resolve_with("sword.obj", None, '/').unwrap(), "sword.obj");
+
+        let err = resolve_with("//sword.obj", None, '/')
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not been saved"), "{err}");
+    }
+
+    #[test]
+    fn test_relative_round_trip() {
+        let project = ProjectContext::for_graph_file(Some(Path::new("/home/me/jacks/sword.bjk")));
+        for path in [
+            "/home/me/jacks/sword.obj",
+            "/home/me/jacks/models/hilt/hilt.obj",
+            "/home/me/textures/rock.png",
+        ] {
+            let relative = project.make_relative(path).unwrap();
+            assert!(is_project_relative(&relative));
+            assert_eq!(
+                resolve_with(&relative, Some("/home/me/jacks"), '/').unwrap(),
+                path
+            );
+        }
+        assert!(project.contains("/home/me/jacks/models/hilt/hilt.obj"));
+        assert!(!project.contains("/home/me/textures/rock.png"));
+
+        let unsaved = ProjectContext::for_graph_file(None);
+        assert_eq!(unsaved.make_relative("/home/me/jacks/sword.obj"), None);
+        assert!(unsaved.resolve("//sword.obj").is_err());
+        assert_eq!(unsaved.resolve("/tmp/sword.obj").unwrap(), "/tmp/sword.obj");
+    }
+
+    #[test]
+    fn test_project_dir() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let eval = |graph: &BjkGraph| {
+            with_project_file(graph.file_path.as_deref(), || {
+                runtime
+                    .lua
+                    .load("return Path.project_dir()")
+                    .eval::<String>()
+            })
+        };
+
+        let (loaded, _, _) = SerializedBjkGraph::load_from_file("../examples/box.bjk")
+            .unwrap()
+            .into_runtime()
+            .unwrap();
+        assert_eq!(eval(&loaded.graph).unwrap(), "../examples");
+
+        let err = eval(&BjkGraph::new()).unwrap_err().to_string();
+        assert!(err.contains("has not been saved"), "{err}");
+        // The project file only lasts for the duration of the call
+        assert!(project_dir().is_err());
+    }
+}
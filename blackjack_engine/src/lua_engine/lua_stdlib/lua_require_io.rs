@@ -4,9 +4,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{borrow::Cow, path::PathBuf};
-
-use crate::graph::{NodeDefinition, NodeDefinitionsInner};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use crate::graph::{NodeDefinition, NodeDefinitionsInner, NodeProvenance};
+
+/// The files under $BLACKJACK_LUA/run that ship with blackjack. Nodes defined
+/// anywhere else come from user libraries.
+pub const BUNDLED_NODE_LIBRARIES: &[&str] = &["core_nodes.lua"];
+
+/// Returns whether the run file at `path` is one of the
+/// [`BUNDLED_NODE_LIBRARIES`].
+pub fn is_bundled_library(path: &str) -> bool {
+    let name = Path::new(path).file_name().and_then(|name| name.to_str());
+    matches!(name, Some(name) if BUNDLED_NODE_LIBRARIES.contains(&name))
+}
 
 pub struct LuaSourceFile {
     pub contents: String,
@@ -102,19 +116,27 @@ impl LuaFileIo for StdLuaFileIo {
 }
 
 /// Scans and runs all files inside $BLACKJACK_LUA/run. Then, parses every
-/// registered node and returns a `NodeDefinitions` object with the nodes.
+/// registered node and returns a `NodeDefinitions` object with the nodes. Each
+/// definition records the file it comes from, see [`NodeProvenance`].
 pub fn load_node_definitions(
     lua: &mlua::Lua,
     lua_io: &dyn LuaFileIo,
 ) -> anyhow::Result<NodeDefinitionsInner> {
+    let library = lua.load("require('node_library')").eval::<mlua::Table>()?;
     for path in lua_io.find_run_files() {
         let file = lua_io.load_file_absolute(&path)?;
+        library.set("current_source", path.as_str())?;
         lua.load(&file).exec()?;
     }
-
-    let table = lua
-        .load("require('node_library')")
-        .eval::<mlua::Table>()?
-        .get::<_, mlua::Table>("nodes")?;
-    NodeDefinition::load_nodes_from_table(table)
+    library.set("current_source", mlua::Value::Nil)?;
+
+    let mut definitions = NodeDefinition::load_nodes_from_table(library.get("nodes")?)?;
+    let sources = library.get::<_, mlua::Table>("sources")?;
+    for def in definitions.definitions_mut() {
+        def.provenance = match sources.get::<_, Option<String>>(def.op_name.as_str())? {
+            Some(file) if !is_bundled_library(&file) => NodeProvenance::User { file },
+            _ => NodeProvenance::Bundled,
+        };
+    }
+    Ok(definitions)
 }
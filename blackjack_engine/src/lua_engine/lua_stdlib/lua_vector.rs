@@ -0,0 +1,257 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Vectors are a native type in Luau, with built-in arithmetic operators and
+//! `x`, `y`, `z` fields. This module completes them with the functions in the
+//! `V3` table, which are also available as methods on any vector (e.g.
+//! `v:length()`), and with swizzles like `v.xz` or `v.zyx`.
+
+use std::os::raw::c_int;
+
+use glam::Vec3;
+use mlua::Value;
+
+use super::*;
+
+#[blackjack_macros::blackjack_lua_module]
+#[allow(non_upper_case_globals)]
+mod lua_api {
+    use super::*;
+
+    /// The vector `(0, 0, 0)`.
+    #[lua(under = "V3")]
+    const zero: LVec3 = LVec3(Vec3::ZERO);
+
+    /// The vector `(1, 1, 1)`.
+    #[lua(under = "V3")]
+    const one: LVec3 = LVec3(Vec3::ONE);
+
+    /// The unit vector along the X axis.
+    #[lua(under = "V3")]
+    const unit_x: LVec3 = LVec3(Vec3::X);
+
+    /// The unit vector along the Y axis.
+    #[lua(under = "V3")]
+    const unit_y: LVec3 = LVec3(Vec3::Y);
+
+    /// The unit vector along the Z axis.
+    #[lua(under = "V3")]
+    const unit_z: LVec3 = LVec3(Vec3::Z);
+
+    /// Returns a vector with all its components set to `s`.
+    #[lua(under = "V3")]
+    pub fn splat(s: f32) -> LVec3 {
+        LVec3(Vec3::splat(s))
+    }
+
+    /// Returns the length of `v`.
+    #[lua(under = "V3")]
+    pub fn length(v: LVec3) -> f32 {
+        v.0.length()
+    }
+
+    /// Returns `v` scaled to a length of 1. A zero vector stays zero.
+    #[lua(under = "V3")]
+    pub fn normalized(v: LVec3) -> LVec3 {
+        LVec3(v.0.normalize_or_zero())
+    }
+
+    /// Returns the dot product of `a` and `b`.
+    #[lua(under = "V3")]
+    pub fn dot(a: LVec3, b: LVec3) -> f32 {
+        a.0.dot(b.0)
+    }
+
+    /// Returns the cross product of `a` and `b`.
+    #[lua(under = "V3")]
+    pub fn cross(a: LVec3, b: LVec3) -> LVec3 {
+        LVec3(a.0.cross(b.0))
+    }
+
+    /// Interpolates linearly between `a` and `b`, by factor `t`.
+    #[lua(under = "V3")]
+    pub fn lerp(a: LVec3, b: LVec3, t: f32) -> LVec3 {
+        LVec3(a.0.lerp(b.0, t))
+    }
+
+    /// Returns the component-wise minimum of `a` and `b`.
+    #[lua(under = "V3")]
+    pub fn min(a: LVec3, b: LVec3) -> LVec3 {
+        LVec3(a.0.min(b.0))
+    }
+
+    /// Returns the component-wise maximum of `a` and `b`.
+    #[lua(under = "V3")]
+    pub fn max(a: LVec3, b: LVec3) -> LVec3 {
+        LVec3(a.0.max(b.0))
+    }
+
+    /// Returns the absolute value of each component of `v`.
+    #[lua(under = "V3")]
+    pub fn abs(v: LVec3) -> LVec3 {
+        LVec3(v.0.abs())
+    }
+
+    /// Returns whether every component of `a` and `b` differs by at most
+    /// `epsilon`, which defaults to `1e-5`. Use this instead of `==` to
+    /// compare vectors that are the result of computations.
+    #[lua(under = "V3")]
+    pub fn approx_eq(a: LVec3, b: LVec3, epsilon: Option<f32>) -> bool {
+        a.0.abs_diff_eq(b.0, epsilon.unwrap_or(1e-5))
+    }
+}
+
+/// Reads a swizzle like `xy` or `zyx` from vector `v`. Two-component swizzles
+/// return a vector with a zero Z component.
+fn swizzle(v: Vec3, key: &str) -> Option<Vec3> {
+    let mut components = [0.0; 3];
+    if !(2..=3).contains(&key.len()) {
+        return None;
+    }
+    for (i, c) in key.chars().enumerate() {
+        components[i] = match c {
+            'x' => v.x,
+            'y' => v.y,
+            'z' => v.z,
+            _ => return None,
+        };
+    }
+    Some(Vec3::from(components))
+}
+
+/// Sets the metatable shared by all the vector values of a Lua state. Takes
+/// any vector as the first argument, and the metatable as the second one.
+///
+/// Luau doesn't allow setting the metatable of a vector from Lua code, so
+/// this needs to go through the C API.
+unsafe extern "C" fn set_vector_metatable(state: *mut mlua::ffi::lua_State) -> c_int {
+    mlua::ffi::lua_setmetatable(state, 1);
+    0
+}
+
+/// Sets up the metatable for vector values, so the functions in the `V3`
+/// table can be called as methods, and makes the `V3` table callable as a
+/// constructor: `V3(x, y, z)`. Must run after the `V3` table is registered.
+pub fn init_vector_metatable(lua: &Lua) -> anyhow::Result<()> {
+    let v3: Table = lua.globals().get("V3")?;
+
+    let metatable = lua.create_table()?;
+    metatable.set(
+        "__index",
+        lua.create_function(|lua, (v, key): (LVec3, String)| {
+            let v3: Table = lua.globals().get("V3")?;
+            match v3.raw_get::<_, Value>(key.as_str())? {
+                Value::Function(f) => Ok(Value::Function(f)),
+                _ => match swizzle(v.0, &key) {
+                    Some(swizzled) => LVec3(swizzled).to_lua(lua),
+                    None => Err(mlua::Error::RuntimeError(format!(
+                        "Vectors have no field or method '{key}'"
+                    ))),
+                },
+            }
+        })?,
+    )?;
+    metatable.set(
+        "__tostring",
+        lua.create_function(|_, v: LVec3| Ok(format!("vector({}, {}, {})", v.0.x, v.0.y, v.0.z)))?,
+    )?;
+    // SAFETY: The function only manipulates the Lua stack, with the arguments
+    // passed below.
+    let set_metatable = unsafe { lua.create_c_function(set_vector_metatable)? };
+    set_metatable.call::<_, ()>((LVec3(Vec3::ZERO), metatable))?;
+
+    let v3_metatable = lua.create_table()?;
+    v3_metatable.set(
+        "__call",
+        lua.create_function(|_, (_, x, y, z): (Value, f32, f32, f32)| {
+            Ok(LVec3(Vec3::new(x, y, z)))
+        })?,
+    )?;
+    v3.set_metatable(Some(v3_metatable));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn init_lua() -> Lua {
+        let lua = Lua::new();
+        for register_fn in inventory::iter::<LuaRegisterFn>() {
+            (register_fn.f)(&lua).unwrap();
+        }
+        init_vector_metatable(&lua).unwrap();
+        lua
+    }
+
+    #[test]
+    fn test_vector_operators() {
+        init_lua()
+            .load(
+                r#"
+                local a = V3(1, 2, 3)
+                local b = vector(4, 5, 6)
+                assert(a + b == vector(5, 7, 9))
+                assert(b - a == V3.splat(3))
+                assert(a * 2 == vector(2, 4, 6))
+                assert(2 * a == vector(2, 4, 6))
+                assert(a * b == vector(4, 10, 18))
+                assert(b / 2 == vector(2, 2.5, 3))
+                assert(b / a == vector(4, 2.5, 2))
+                assert(-a == vector(-1, -2, -3))
+                assert(a ~= b)
+                assert(a.x == 1 and a.y == 2 and a.z == 3)
+                assert(tostring(a) == "vector(1, 2, 3)")
+                "#,
+            )
+            .exec()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_vector_methods() {
+        init_lua()
+            .load(
+                r#"
+                local a = V3(3, 0, 4)
+                assert(a:length() == 5)
+                assert(V3.length(a) == 5)
+                assert(a:normalized():approx_eq(vector(0.6, 0, 0.8)))
+                assert(V3.zero:normalized() == V3.zero)
+                assert(a:dot(V3.unit_z) == 4)
+                assert(V3.unit_x:cross(V3.unit_y) == V3.unit_z)
+                assert(V3.zero:lerp(a, 0.5) == vector(1.5, 0, 2))
+                assert(a:min(V3.one) == vector(1, 0, 1))
+                assert(a:max(V3.one) == vector(3, 1, 4))
+                assert(vector(-1, 2, -3):abs() == vector(1, 2, 3))
+                assert(a:approx_eq(a + V3.splat(1e-7)))
+                assert(not a:approx_eq(a + V3.unit_y))
+                assert(a:approx_eq(a + V3.unit_y, 2))
+                "#,
+            )
+            .exec()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_vector_swizzles() {
+        let lua = init_lua();
+        lua.load(
+            r#"
+            local a = vector(1, 2, 3)
+            assert(a.xy == vector(1, 2, 0))
+            assert(a.xz == vector(1, 3, 0))
+            assert(a.yz == vector(2, 3, 0))
+            assert(a.zyx == vector(3, 2, 1))
+            assert(a.xxx == V3.splat(1))
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let err = lua.load("return vector(1, 2, 3).foo").exec().unwrap_err();
+        assert!(err.to_string().contains("no field or method 'foo'"));
+    }
+}
@@ -88,8 +88,29 @@ fn ligthdata_to_keydata(d: mlua::LightUserData) -> slotmap::KeyData {
     slotmap::KeyData::from_ffi(d.0 as u64)
 }
 
-impl UserData for ChannelKeyType {}
-impl UserData for ChannelValueType {}
+/// Channel types can be compared, so Lua code can check the types of the
+/// channels listed by `mesh:channels()`, and printed by their name.
+macro_rules! channel_type_userdata {
+    ($t:ty) => {
+        impl UserData for $t {
+            fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                methods.add_meta_method(
+                    mlua::MetaMethod::Eq,
+                    |_, this, other: mlua::AnyUserData| {
+                        Ok(other
+                            .borrow::<Self>()
+                            .map_or(false, |other| *other == *this))
+                    },
+                );
+                methods.add_meta_method(mlua::MetaMethod::ToString, |_, this, ()| {
+                    Ok(format!("{this:?}"))
+                });
+            }
+        }
+    };
+}
+channel_type_userdata!(ChannelKeyType);
+channel_type_userdata!(ChannelValueType);
 
 pub struct PerlinNoise(pub noise::Perlin);
 
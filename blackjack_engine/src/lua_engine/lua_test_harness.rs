@@ -0,0 +1,306 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runs tests written in Lua against a fully initialized [`LuaRuntime`].
+//!
+//! Test files are named `*_test.lua`, and declare their tests by calling the
+//! global `test(name, fn)` function. Each test fails when its function raises
+//! an error, and the following assertion functions are available to raise
+//! errors with helpful messages:
+//!
+//! - `assert_eq(a, b, message?)`: Checks that `a == b`.
+//! - `assert_close(a, b, epsilon?)`: Checks that two numbers or vectors are
+//!   within `epsilon` of each other, `1e-5` by default.
+//! - `assert_error(fn, expected?)`: Checks that calling `fn` raises an error,
+//!   whose message contains `expected` when given.
+//! - `expect_mesh_counts(mesh, v, e, f)`: Checks the number of vertices,
+//!   edges and faces in a mesh.
+//!
+//! The test files are read with `std::fs` from a directory given by the
+//! caller, so the same tests can run from `cargo test` or against a packaged
+//! build of the node libraries.
+
+use std::path::{Path, PathBuf};
+
+use mlua::{AnyUserData, FromLua, Function, Lua, Table, Value};
+
+use super::lua_stdlib::{LVec3, LuaSourceFile};
+use super::LuaRuntime;
+use crate::prelude::*;
+
+/// The registry key of the table where the `test` function collects tests.
+const TESTS_KEY: &str = "__blackjack_lua_tests";
+/// The registry key of a function comparing two values with `==`.
+const EQUALS_KEY: &str = "__blackjack_lua_tests_equals";
+
+/// The outcome of a single Lua test.
+#[derive(Debug, Clone)]
+pub struct LuaTestResult {
+    /// The test name, prefixed by the name of its file
+    pub name: String,
+    /// The error message, when the test failed
+    pub error: Option<String>,
+}
+
+/// Returns the paths of all the `*_test.lua` files under `dir`, sorted.
+pub fn find_test_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = walkdir::WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && e.file_name()
+                    .to_str()
+                    .map(|name| name.ends_with("_test.lua"))
+                    .unwrap_or(false)
+        })
+        .map(|e| e.into_path())
+        .collect_vec();
+    files.sort();
+    files
+}
+
+fn display(lua: &Lua, value: Value) -> mlua::Result<String> {
+    lua.globals().get::<_, Function>("tostring")?.call(value)
+}
+
+fn fail<T>(message: String) -> mlua::Result<T> {
+    Err(mlua::Error::RuntimeError(message))
+}
+
+/// Adds the `test` function and the assertion functions to the globals of
+/// `lua`.
+pub fn load_test_api(lua: &Lua) -> Result<()> {
+    let globals = lua.globals();
+    lua.set_named_registry_value(TESTS_KEY, lua.create_table()?)?;
+    lua.set_named_registry_value(
+        EQUALS_KEY,
+        lua.load("return function(a, b) return a == b end")
+            .eval::<Function>()?,
+    )?;
+
+    globals.set(
+        "test",
+        lua.create_function(|lua, (name, f): (String, Function)| {
+            let tests: Table = lua.named_registry_value(TESTS_KEY)?;
+            let test = lua.create_table()?;
+            test.set("name", name)?;
+            test.set("f", f)?;
+            tests.raw_set(tests.raw_len() + 1, test)
+        })?,
+    )?;
+
+    globals.set(
+        "assert_eq",
+        lua.create_function(|lua, (a, b, message): (Value, Value, Option<String>)| {
+            let equals: Function = lua.named_registry_value(EQUALS_KEY)?;
+            if equals.call((a.clone(), b.clone()))? {
+                return Ok(());
+            }
+            let (a, b) = (display(lua, a)?, display(lua, b)?);
+            match message {
+                Some(message) => fail(format!("{message}: {a} ~= {b}")),
+                None => fail(format!("assert_eq failed: {a} ~= {b}")),
+            }
+        })?,
+    )?;
+
+    globals.set(
+        "assert_close",
+        lua.create_function(|lua, (a, b, epsilon): (Value, Value, Option<f32>)| {
+            let epsilon = epsilon.unwrap_or(1e-5);
+            let close = match (&a, &b) {
+                (Value::Vector(..), Value::Vector(..)) => {
+                    let a = LVec3::from_lua(a.clone(), lua)?.0;
+                    let b = LVec3::from_lua(b.clone(), lua)?.0;
+                    a.abs_diff_eq(b, epsilon)
+                }
+                _ => {
+                    let a = f32::from_lua(a.clone(), lua)?;
+                    let b = f32::from_lua(b.clone(), lua)?;
+                    (a - b).abs() <= epsilon
+                }
+            };
+            if close {
+                Ok(())
+            } else {
+                let (a, b) = (display(lua, a)?, display(lua, b)?);
+                fail(format!(
+                    "assert_close failed: {a} and {b} differ by more than {epsilon}"
+                ))
+            }
+        })?,
+    )?;
+
+    globals.set(
+        "assert_error",
+        lua.create_function(|_, (f, expected): (Function, Option<String>)| {
+            match (f.call::<_, ()>(()), expected) {
+                (Ok(()), _) => fail("assert_error failed: The function did not fail".into()),
+                (Err(err), Some(expected)) if !err.to_string().contains(&expected) => fail(
+                    format!("assert_error failed: The error doesn't contain '{expected}': {err}"),
+                ),
+                (Err(_), _) => Ok(()),
+            }
+        })?,
+    )?;
+
+    globals.set(
+        "expect_mesh_counts",
+        lua.create_function(|_, (mesh, v, e, f): (AnyUserData, usize, usize, usize)| {
+            let mesh = mesh.borrow::<HalfEdgeMesh>()?;
+            let conn = mesh.read_connectivity();
+            // Each edge is a pair of twin halfedges, except for halfedges
+            // without a twin.
+            let edges = conn
+                .iter_halfedges()
+                .filter(|(h, _)| match conn.at_halfedge(*h).twin().try_end() {
+                    Ok(twin) => *h < twin,
+                    Err(_) => true,
+                })
+                .count();
+            let counts = (conn.num_vertices(), edges, conn.num_faces());
+            if counts == (v, e, f) {
+                Ok(())
+            } else {
+                fail(format!(
+                    "expect_mesh_counts failed: Expected {v} vertices, {e} edges and {f} \
+                         faces, but the mesh has {} vertices, {} edges and {} faces",
+                    counts.0, counts.1, counts.2
+                ))
+            }
+        })?,
+    )?;
+
+    Ok(())
+}
+
+/// Runs all the tests declared in the Lua file at `path`. The test API must
+/// have been loaded with [`load_test_api`]. Returns an error when the file
+/// itself fails to run.
+pub fn run_test_file(lua: &Lua, path: &Path) -> Result<Vec<LuaTestResult>> {
+    let file_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let source = LuaSourceFile {
+        contents: std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read test file {}", path.display()))?,
+        name: path.display().to_string(),
+    };
+    lua.set_named_registry_value(TESTS_KEY, lua.create_table()?)?;
+    lua.load(&source).exec()?;
+
+    let tests: Table = lua.named_registry_value(TESTS_KEY)?;
+    let mut results = vec![];
+    for test in tests.sequence_values::<Table>() {
+        let test = test?;
+        let name: String = test.get("name")?;
+        let f: Function = test.get("f")?;
+        results.push(LuaTestResult {
+            name: format!("{file_name}::{name}"),
+            error: f.call::<_, ()>(()).err().map(|err| err.to_string()),
+        });
+    }
+    Ok(results)
+}
+
+/// Initializes a [`LuaRuntime`] with the node libraries at `lua_path`, and
+/// runs all the test files under `tests_path` on it.
+pub fn run_all_tests(lua_path: &Path, tests_path: &Path) -> Result<Vec<LuaTestResult>> {
+    let runtime = LuaRuntime::initialize_with_std(lua_path.display().to_string())?;
+    load_test_api(&runtime.lua)?;
+    let mut results = vec![];
+    for file in find_test_files(tests_path) {
+        match run_test_file(&runtime.lua, &file) {
+            Ok(file_results) => results.extend(file_results),
+            Err(err) => results.push(LuaTestResult {
+                name: file.display().to_string(),
+                error: Some(format!("{err:?}")),
+            }),
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("..")
+    }
+
+    fn tests_path() -> PathBuf {
+        root().join("test").join("lua")
+    }
+
+    /// Runs the tests in `test/lua/<name>.lua`, and fails with the errors of
+    /// all the Lua tests that failed.
+    fn run_lua_test_file(name: &str) {
+        let runtime =
+            LuaRuntime::initialize_with_std(root().join("blackjack_lua").display().to_string())
+                .unwrap();
+        load_test_api(&runtime.lua).unwrap();
+        let path = tests_path().join(format!("{name}.lua"));
+        let results = run_test_file(&runtime.lua, &path)
+            .unwrap_or_else(|err| panic!("Could not run {}: {err:?}", path.display()));
+        assert!(
+            !results.is_empty(),
+            "No Lua tests found in {}",
+            path.display()
+        );
+
+        let mut failures = vec![];
+        for result in &results {
+            match &result.error {
+                None => println!("lua test {} ... ok", result.name),
+                Some(err) => {
+                    println!("lua test {} ... FAILED", result.name);
+                    failures.push(format!("---- {} ----\n{err}", result.name));
+                }
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{} of {} Lua tests failed:\n\n{}",
+            failures.len(),
+            results.len(),
+            failures.join("\n\n")
+        );
+    }
+
+    /// Declares a test for each of the given files in `test/lua`, so they are
+    /// reported, filtered and run in parallel like the Rust tests.
+    macro_rules! lua_test_files {
+        ($($name:ident),* $(,)?) => {
+            const LUA_TEST_FILES: &[&str] = &[$(stringify!($name)),*];
+            $(
+                #[test]
+                fn $name() {
+                    run_lua_test_file(stringify!($name));
+                }
+            )*
+        };
+    }
+
+    lua_test_files!(channels_test, edit_ops_test, primitives_test, scene_test);
+
+    #[test]
+    fn test_all_lua_files_are_listed() {
+        let found = find_test_files(&tests_path())
+            .iter()
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+            .sorted()
+            .collect_vec();
+        let listed = LUA_TEST_FILES.iter().copied().sorted().collect_vec();
+        assert_eq!(
+            found, listed,
+            "Every file in test/lua should be listed in `lua_test_files!`"
+        );
+    }
+}
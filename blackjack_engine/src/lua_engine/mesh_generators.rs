@@ -0,0 +1,231 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Nodes that make very large meshes can build them from a coroutine instead
+//! of all at once. When a mesh output of an op is a coroutine, the engine
+//! resumes it until it finishes, and every time it yields it takes one chunk
+//! of geometry:
+//!
+//! ```lua
+//! coroutine.yield(positions, faces, progress)
+//! ```
+//!
+//! Where `positions` is a list of vectors, added after the ones of earlier
+//! chunks, and `faces` is a list of faces, each one a list of vertex indices.
+//! Indices are 0-based, like the vertex indices of selections, and count the
+//! positions of all the chunks so far, so faces can use vertices from earlier
+//! chunks. The optional `progress` goes from 0 to 1 and is shown on the node.
+//!
+//! Each time the coroutine is resumed it gets the whole instruction budget of
+//! the sandbox, so generators are only limited by the size of their chunks.
+
+use mlua::{FromLua, Lua, MultiValue, Table, Thread, ThreadStatus, Value};
+
+use super::lua_stdlib::LVec3;
+use super::sandbox;
+use crate::graph::BjkNode;
+use crate::mesh::halfedge::mesh_builder::{MeshBuildError, MeshBuilder};
+use crate::prelude::*;
+use crate::progress::{current_sink, Cancelled};
+
+fn chunk_error(chunk: usize, message: impl std::fmt::Display) -> mlua::Error {
+    mlua::Error::RuntimeError(format!("Chunk {chunk} of the mesh generator: {message}"))
+}
+
+/// Resumes the `generator` coroutine until it finishes, and builds a mesh
+/// with the chunks of geometry it yields. Chunks are checked as they arrive,
+/// and errors mention the index of the chunk that caused them.
+pub fn drive_mesh_generator<'lua>(
+    lua: &'lua Lua,
+    generator: Thread<'lua>,
+) -> mlua::Result<HalfEdgeMesh> {
+    let mut builder = MeshBuilder::new(&[]);
+    // The number of faces given before each chunk, to find the chunk of
+    // the faces rejected when building.
+    let mut chunk_starts = vec![];
+    let mut faces_given = 0;
+    let mut progress = 0.0;
+    let sink = current_sink();
+
+    for chunk in 0.. {
+        let values = sandbox::with_fresh_budget(|| generator.resume::<_, MultiValue>(()))?;
+        if generator.status() != ThreadStatus::Resumable {
+            // The coroutine returned, so there are no more chunks.
+            break;
+        }
+        let mut values = values.into_iter();
+        let mut next_value = || values.next().unwrap_or(Value::Nil);
+
+        let positions = Vec::<LVec3>::from_lua(next_value(), lua)
+            .map_err(|err| chunk_error(chunk, format!("Invalid positions. {err}")))?;
+        let faces = Vec::<Vec<i64>>::from_lua(next_value(), lua)
+            .map_err(|err| chunk_error(chunk, format!("Invalid faces. {err}")))?;
+        let chunk_progress = Option::<f32>::from_lua(next_value(), lua)
+            .map_err(|err| chunk_error(chunk, format!("Invalid progress. {err}")))?;
+
+        chunk_starts.push(faces_given);
+        builder.add_positions(&LVec3::cast_vector(positions));
+        for face in faces {
+            let polygon = face
+                .iter()
+                .map(|i| usize::try_from(*i))
+                .collect::<Result<SVec<usize>, _>>()
+                .map_err(|_| {
+                    chunk_error(
+                        chunk,
+                        format!("Face {faces_given} has negative vertex indices"),
+                    )
+                })?;
+            faces_given += 1;
+            builder
+                .add_face(&polygon)
+                .map_err(|err| chunk_error(chunk, err))?;
+        }
+
+        if let Some(p) = chunk_progress {
+            progress = p.clamp(progress, 1.0);
+        }
+        if let Some(sink) = &sink {
+            sink.report(progress);
+            if sink.is_cancelled() {
+                return Err(mlua::Error::external(Cancelled));
+            }
+        }
+    }
+
+    builder.build().map_err(|err: MeshBuildError| {
+        let chunk = chunk_starts.partition_point(|start| *start <= err.face) - 1;
+        chunk_error(chunk, err)
+    })
+}
+
+/// Replaces the mesh outputs of `node` that are coroutines with the meshes
+/// they generate.
+pub(crate) fn drive_mesh_outputs<'lua>(
+    lua: &'lua Lua,
+    node: &BjkNode,
+    outputs: &Table<'lua>,
+) -> mlua::Result<()> {
+    for output in node.outputs.iter().filter(|o| o.data_type.is_mesh()) {
+        if let Value::Thread(generator) = outputs.get::<_, Value>(output.name.as_str())? {
+            let mesh = drive_mesh_generator(lua, generator)?;
+            outputs.set(output.name.as_str(), mesh)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lua_engine::{LuaRuntime, LuaRuntimeConfig};
+
+    fn runtime(config: LuaRuntimeConfig) -> LuaRuntime {
+        LuaRuntime::initialize_with_std_and_config("../blackjack_lua".into(), config).unwrap()
+    }
+
+    fn generate(lua: &Lua, code: &str) -> mlua::Result<HalfEdgeMesh> {
+        sandbox::begin_execution(lua, None);
+        let result = lua
+            .load(code)
+            .eval::<Thread>()
+            .and_then(|generator| drive_mesh_generator(lua, generator));
+        sandbox::end_execution(lua);
+        result
+    }
+
+    /// A generator for a grid of `rows` by 1000 quads, one row per chunk.
+    fn grid_generator(rows: usize) -> String {
+        format!(
+            "return coroutine.create(function()
+                local width = 1000
+                for row = 0, {rows} - 1 do
+                    local positions = {{}}
+                    local faces = {{}}
+                    if row == 0 then
+                        for i = 0, width do
+                            table.insert(positions, vector(i, 0, 0))
+                        end
+                    end
+                    for i = 0, width do
+                        table.insert(positions, vector(i, 0, row + 1))
+                    end
+                    for i = 0, width - 1 do
+                        local bottom = row * (width + 1) + i
+                        local top = bottom + width + 1
+                        table.insert(faces, {{ bottom, top, top + 1, bottom + 1 }})
+                    end
+                    coroutine.yield(positions, faces, (row + 1) / {rows})
+                end
+            end)"
+        )
+    }
+
+    #[test]
+    fn test_generate_in_chunks() {
+        // Each chunk fits in the instruction limit, but all of them together
+        // don't.
+        let rt = runtime(LuaRuntimeConfig {
+            instruction_limit: Some(50_000),
+            ..LuaRuntimeConfig::sandboxed()
+        });
+        let mesh = generate(&rt.lua, &grid_generator(100)).unwrap();
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_faces(), 100_000);
+        assert_eq!(conn.num_vertices(), 1001 * 101);
+
+        // The budget of each resume is still enforced
+        let err = generate(
+            &rt.lua,
+            "return coroutine.create(function()
+                coroutine.yield({vector(0, 0, 0)}, {})
+                while true do end
+            end)",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("instruction limit"));
+    }
+
+    #[test]
+    fn test_invalid_chunks() {
+        let rt = runtime(LuaRuntimeConfig::default());
+        let err = generate(
+            &rt.lua,
+            "return coroutine.create(function()
+                coroutine.yield({vector(0, 0, 0), vector(1, 0, 0), vector(0, 1, 0)}, {{0, 1, 2}})
+                coroutine.yield({vector(1, 1, 0)}, {{1, 3, 5}})
+            end)",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Chunk 1"), "{err}");
+        assert!(err.contains("vertex 5 is out of bounds"), "{err}");
+
+        let err = generate(
+            &rt.lua,
+            "return coroutine.create(function()
+                coroutine.yield({vector(0, 0, 0)}, 'not faces')
+            end)",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("Chunk 0") && err.contains("Invalid faces"),
+            "{err}"
+        );
+
+        let err = generate(
+            &rt.lua,
+            "return coroutine.create(function()
+                coroutine.yield({}, {})
+                error('Something went wrong')
+            end)",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Something went wrong"), "{err}");
+    }
+}
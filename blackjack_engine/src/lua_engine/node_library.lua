@@ -5,7 +5,24 @@
 -- file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 local NodeLibrary = {
-    nodes = {}
+    nodes = {},
+    -- The file each node was defined in. The engine sets `current_source`
+    -- while it runs each node library.
+    sources = {},
+    current_source = nil,
+}
+
+-- The metatable of the `inputs` table passed to the functions of each node,
+-- with helper methods for node authors.
+NodeLibrary.inputs_metatable = {
+    __index = {
+        -- Returns a random number generator for this node. Each node gets
+        -- different numbers, even for the same `seed`, and they only change
+        -- along with the seed of the graph.
+        rng = function(inputs, seed)
+            return Rng.new(seed or 0, inputs.__node_seed)
+        end,
+    },
 }
 
 function NodeLibrary:addNodes(nodes)
@@ -18,6 +35,7 @@ function NodeLibrary:addNodes(nodes)
             print("[Engine] Loading new node definition for "..k)
         end
         self.nodes[k] = v
+        self.sources[k] = self.current_source
     end
 end
 
@@ -6,7 +6,9 @@
 
 local Params = {}
 
---- A scalar parameter, with given `default`, `min` and `max` value
+--- A scalar parameter, with given `default`, `min` and `max` value. Setting
+--- `unit = "length"` in the config shows the value in the unit of the
+--- project, while still storing it in meters.
 Params.scalar = function(name, config)
     if config ~= nil then
         assert(type(config) == 'table', "config should be table")
@@ -17,6 +19,7 @@ Params.scalar = function(name, config)
             max = config.max,
             soft_min = config.soft_min,
             soft_max = config.soft_max,
+            unit = config.unit,
             type = "scalar",
         }
     else
@@ -30,6 +33,27 @@ Params.scalar_int = function(name, config)
     return s
 end
 
+--- An integer parameter, with given `default` value. The optional `config`
+--- table can set `min`, `max`, `soft_min` and `soft_max`.
+Params.int = function(name, default, config)
+    config = config or {}
+    assert(type(config) == 'table', "config should be table")
+    return {
+        name = name,
+        default = default or 0,
+        min = config.min,
+        max = config.max,
+        soft_min = config.soft_min,
+        soft_max = config.soft_max,
+        type = "int",
+    }
+end
+
+--- A boolean parameter, with given `default` value. Shown as a checkbox.
+Params.bool = function(name, default)
+    return { name = name, default = default or false, type = "bool" }
+end
+
 --- A vector parameter, with given `default` value
 Params.v3 = function(name, default)
     return { name = name, default = default, type = "vec3" }
@@ -41,10 +65,23 @@ Params.mesh = function(name)
     return { name = name, type = "mesh" }
 end
 
+--- A curve parameter: a mesh made of polylines, without faces. Curves can go
+--- into mesh parameters, but meshes need a `To Curve` node to become one.
+Params.curve = function(name)
+    return { name = name, type = "curve" }
+end
+
+--- A point cloud parameter: a mesh made of vertices only. Like curves, point
+--- clouds can go into mesh parameters, but not the other way around.
+Params.points = function(name)
+    return { name = name, type = "points" }
+end
+
 --- A selection parameter. Lets user specify a group of vertices, halfedges or
---- faces. The selected element is context-dependent.
-Params.selection = function(name)
-    return { name = name, type = "selection" }
+--- faces. The selected element is context-dependent. The optional `default`
+--- is a selection string, like `"*"`. When not set, nothing is selected.
+Params.selection = function(name, default)
+    return { name = name, default = default, type = "selection" }
 end
 
 --- A string parameter, with a given `default` value. If `multiline` is set, the
@@ -87,4 +124,22 @@ Params.heightmap = function(name)
     return { name = name, type = "heightmap" }
 end
 
+--- A scene parameter, holding several named objects. Like a mesh, it can't be
+--- set by the user so it has no widget.
+Params.scene = function(name)
+    return { name = name, type = "scene" }
+end
+
+--- Per-vertex offsets, recorded by dragging vertices in the viewport's edit
+--- mode. It can't be connected, and is only edited from the viewport.
+Params.vertex_deltas = function(name)
+    return { name = name, type = "vertex_deltas" }
+end
+
+--- Per-vertex values, painted with the brush of the viewport's paint mode. It
+--- can't be connected, and is only edited from the viewport.
+Params.vertex_weights = function(name)
+    return { name = name, type = "vertex_weights" }
+end
+
 return Params
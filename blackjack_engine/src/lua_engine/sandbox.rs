@@ -0,0 +1,341 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Graph files reference Lua code, so opening a graph someone else made means
+//! running their scripts. The sandbox restricts what those scripts can do:
+//! When enabled, Lua code can't touch the filesystem or the OS, and can't load
+//! libraries from outside the node libraries folder.
+//!
+//! Independently of the sandbox, graph executions can be given a budget of
+//! instructions and the Lua state can be given a memory cap, so runaway
+//! scripts are stopped instead of freezing or exhausting the host.
+
+use std::cell::Cell;
+use std::path::{Component, Path};
+
+use mlua::{Lua, Table, Value};
+
+use crate::graph_interpreter::{CancellationToken, ExecutionCancelled};
+use crate::prelude::*;
+
+/// Settings for the Lua runtime. The default configuration is unrestricted,
+/// which is what trusted code should use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LuaRuntimeConfig {
+    /// When set, Lua code can't access the filesystem or the OS: The `io`
+    /// library is removed, `os` only keeps `time` and `clock`, the ops that
+    /// read or write files fail with an error, see
+    /// [`ensure_filesystem_allowed`], and `require` only accepts relative
+    /// paths inside the node libraries folder.
+    pub sandboxed: bool,
+    /// The maximum number of instructions a single graph execution can run
+    /// before it is aborted. Luau doesn't count individual instructions, so
+    /// this counts its interrupt checks instead, which happen on every
+    /// function call and loop iteration. Generator ops get the full budget
+    /// for each time they're resumed, see [`with_fresh_budget`].
+    pub instruction_limit: Option<u64>,
+    /// The maximum number of bytes the Lua state can allocate. Allocations
+    /// above this limit fail with a memory error.
+    pub memory_limit: Option<usize>,
+}
+
+impl LuaRuntimeConfig {
+    /// The default instruction budget for sandboxed graph executions.
+    pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 500_000_000;
+    /// The default memory cap for sandboxed runtimes: 2GiB.
+    pub const DEFAULT_MEMORY_LIMIT: usize = 2 << 30;
+
+    /// A sandboxed configuration with the default resource limits. Use this
+    /// for graphs coming from untrusted sources.
+    pub fn sandboxed() -> Self {
+        Self {
+            sandboxed: true,
+            instruction_limit: Some(Self::DEFAULT_INSTRUCTION_LIMIT),
+            memory_limit: Some(Self::DEFAULT_MEMORY_LIMIT),
+        }
+    }
+}
+
+/// The error returned when a graph execution runs out of instructions.
+#[derive(Debug, Clone, Copy)]
+pub struct InstructionLimitExceeded(pub u64);
+
+impl std::fmt::Display for InstructionLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The Lua code exceeded the instruction limit of {} and was aborted",
+            self.0
+        )
+    }
+}
+impl std::error::Error for InstructionLimitExceeded {}
+
+/// Registry keys for the sandbox state. The registry lets functions which only
+/// get a `&Lua`, like the graph interpreter and `require`, read the config.
+const SANDBOXED_KEY: &str = "__blackjack_sandboxed";
+const INSTRUCTION_LIMIT_KEY: &str = "__blackjack_instruction_limit";
+/// Stores the globals replaced by the sandbox, to restore them when it is
+/// disabled again.
+const ORIGINALS_KEY: &str = "__blackjack_sandbox_originals";
+
+/// Applies `config` to the `lua` state. The sandbox can be enabled and
+/// disabled any number of times.
+pub fn apply_config(lua: &Lua, config: &LuaRuntimeConfig) -> Result<()> {
+    if config.sandboxed != is_sandboxed(lua) {
+        if config.sandboxed {
+            enable_sandbox(lua)?;
+        } else {
+            disable_sandbox(lua)?;
+        }
+    }
+    lua.set_named_registry_value(SANDBOXED_KEY, config.sandboxed)?;
+    lua.set_named_registry_value(INSTRUCTION_LIMIT_KEY, config.instruction_limit)?;
+    // A limit of zero means no limit
+    lua.set_memory_limit(config.memory_limit.unwrap_or(0))?;
+    Ok(())
+}
+
+/// Returns whether the sandbox is enabled for the `lua` state.
+pub fn is_sandboxed(lua: &Lua) -> bool {
+    lua.named_registry_value::<_, bool>(SANDBOXED_KEY)
+        .unwrap_or(false)
+}
+
+fn enable_sandbox(lua: &Lua) -> Result<()> {
+    let globals = lua.globals();
+    let originals = lua.create_table()?;
+
+    originals.set("io", globals.get::<_, Value>("io")?)?;
+    globals.set("io", Value::Nil)?;
+
+    let os = globals.get::<_, Value>("os")?;
+    if let Value::Table(os) = &os {
+        let safe_os = lua.create_table()?;
+        for name in ["time", "clock"] {
+            safe_os.set(name, os.get::<_, Value>(name)?)?;
+        }
+        globals.set("os", safe_os)?;
+    }
+    originals.set("os", os)?;
+
+    lua.set_named_registry_value(ORIGINALS_KEY, originals)?;
+    Ok(())
+}
+
+fn disable_sandbox(lua: &Lua) -> Result<()> {
+    let globals = lua.globals();
+    let originals: Table = lua.named_registry_value(ORIGINALS_KEY)?;
+    globals.set("io", originals.get::<_, Value>("io")?)?;
+    globals.set("os", originals.get::<_, Value>("os")?)?;
+    lua.unset_named_registry_value(ORIGINALS_KEY)?;
+    Ok(())
+}
+
+/// Fails when the sandbox is enabled for the `lua` state. Every op that reads
+/// or writes files calls this before touching them, with its own Lua `name`
+/// for the error message.
+pub fn ensure_filesystem_allowed(lua: &Lua, name: &str) -> Result<()> {
+    if is_sandboxed(lua) {
+        bail!("{name} accesses the filesystem, which is not allowed in sandboxed mode");
+    }
+    Ok(())
+}
+
+/// Checks whether `require` can load the library at `path`. In sandboxed
+/// mode, libraries can only be loaded from inside the node libraries folder.
+pub fn check_require_path(lua: &Lua, path: &str) -> Result<()> {
+    if !is_sandboxed(lua) {
+        return Ok(());
+    }
+    let escapes_folder = Path::new(path).components().any(|c| {
+        matches!(
+            c,
+            Component::RootDir | Component::Prefix(_) | Component::ParentDir
+        )
+    });
+    if escapes_folder {
+        bail!("Cannot require '{path}': Only relative paths inside the library folder are allowed in sandboxed mode")
+    }
+    Ok(())
+}
+
+thread_local! {
+    /// The interrupt checks counted so far against the instruction limit.
+    /// Graph executions and their interrupts stay on one thread.
+    static INSTRUCTION_COUNT: Cell<u64> = Cell::new(0);
+}
+
+/// Installs the interrupt used during a graph execution. The execution is
+/// aborted when the `cancellation` token is cancelled, or when it exceeds the
+/// instruction limit of the runtime.
+pub(crate) fn begin_execution(lua: &Lua, cancellation: Option<&CancellationToken>) {
+    let limit = lua
+        .named_registry_value::<_, Option<u64>>(INSTRUCTION_LIMIT_KEY)
+        .ok()
+        .flatten();
+    if limit.is_none() && cancellation.is_none() {
+        return;
+    }
+    let cancellation = cancellation.cloned();
+    INSTRUCTION_COUNT.with(|count| count.set(0));
+    lua.set_interrupt(move || {
+        if cancellation.as_ref().map_or(false, |c| c.is_cancelled()) {
+            return Err(mlua::Error::external(ExecutionCancelled));
+        }
+        if let Some(limit) = limit {
+            let count = INSTRUCTION_COUNT.with(|count| count.replace(count.get() + 1));
+            if count >= limit {
+                return Err(mlua::Error::external(InstructionLimitExceeded(limit)));
+            }
+        }
+        Ok(mlua::VmState::Continue)
+    });
+}
+
+/// Runs `f` with a whole instruction budget of its own. The count of the
+/// current execution is restored afterwards, so what `f` runs doesn't count
+/// against it. Generators are resumed this way, which lets them produce any
+/// amount of geometry as long as each step stays within the limit.
+pub(crate) fn with_fresh_budget<T>(f: impl FnOnce() -> T) -> T {
+    let before = INSTRUCTION_COUNT.with(|count| count.replace(0));
+    let result = f();
+    INSTRUCTION_COUNT.with(|count| count.set(before));
+    result
+}
+
+/// Removes the interrupt installed by [`begin_execution`].
+pub(crate) fn end_execution(lua: &Lua) {
+    lua.remove_interrupt();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lua_engine::LuaRuntime;
+
+    fn runtime(config: LuaRuntimeConfig) -> LuaRuntime {
+        LuaRuntime::initialize_with_std_and_config("../blackjack_lua".into(), config).unwrap()
+    }
+
+    fn run(lua: &Lua, code: &str) -> mlua::Result<Value<'_>> {
+        begin_execution(lua, None);
+        let result = lua.load(code).eval::<Value>();
+        end_execution(lua);
+        result
+    }
+
+    #[test]
+    fn test_sandbox_blocks_file_writes() {
+        let path = std::env::temp_dir().join("blackjack_sandbox_test.obj");
+        let _ = std::fs::remove_file(&path);
+        let code = format!(
+            "HalfEdgeMesh.to_wavefront_obj(Primitives.cube(vector(0, 0, 0), vector(1, 1, 1)), {:?})",
+            path.to_str().unwrap()
+        );
+
+        let rt = runtime(LuaRuntimeConfig::sandboxed());
+        let err = run(&rt.lua, &code).unwrap_err();
+        assert!(err.to_string().contains("sandboxed mode"));
+        assert!(!path.exists());
+        assert!(run(&rt.lua, "Io.write('out.txt', 'contents')").is_err());
+        assert!(matches!(run(&rt.lua, "return io").unwrap(), Value::Nil));
+        assert!(matches!(
+            run(&rt.lua, "return os.getenv").unwrap(),
+            Value::Nil
+        ));
+        assert!(run(&rt.lua, "return os.time()").is_ok());
+        assert!(run(&rt.lua, "require('/etc/passwd')").is_err());
+        assert!(run(&rt.lua, "require('../run/core_nodes')").is_err());
+
+        // Disabling the sandbox restores the original functions
+        apply_config(&rt.lua, &LuaRuntimeConfig::default()).unwrap();
+        run(&rt.lua, &code).unwrap();
+        assert!(path.exists());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sandbox_blocks_filesystem_ops() {
+        // Each op is called with a `PATH` and the Lua values below. Writers
+        // create the file, readers fail on it when it's missing.
+        let setup = "local cube = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+                     local scene = Scene.new()
+                     Ops.scene_add(scene, Ops.make_object(cube, 'cube'))";
+        let writers = [
+            ("Io.write(PATH, 'contents')", "txt"),
+            ("HalfEdgeMesh.to_wavefront_obj(cube, PATH)", "obj"),
+            ("HalfEdgeMesh.to_ply(cube, PATH)", "ply"),
+            ("Ops.save_mesh_snapshot(cube, PATH)", "bjkmesh"),
+            ("Scene.to_gltf(scene, PATH)", "gltf"),
+            ("Scene.to_wavefront_obj(scene, PATH)", "obj"),
+        ];
+        let readers = [
+            "Io.read_to_string(PATH)",
+            "HalfEdgeMesh.from_wavefront_obj(PATH)",
+            "HalfEdgeMesh.from_ply(PATH)",
+            "Ops.load_mesh_snapshot(PATH)",
+            "Primitives.heightmap_image(PATH, vector(1, 1, 1), 1.0, 64)",
+            "Ops.sample_image(cube, PATH, 'uv', 'color', Types.VEC3, 'Repeat', 'Nearest')",
+        ];
+        let code = |call: &str, path: &Path| {
+            let call = call.replace("PATH", &format!("{:?}", path.to_str().unwrap()));
+            format!("{setup}\n{call}")
+        };
+        let sandboxed = runtime(LuaRuntimeConfig::sandboxed());
+        let trusted = runtime(LuaRuntimeConfig::default());
+
+        for (idx, (call, extension)) in writers.iter().enumerate() {
+            let path =
+                std::env::temp_dir().join(format!("blackjack_sandbox_test_{idx}.{extension}"));
+            let _ = std::fs::remove_file(&path);
+            let err = run(&sandboxed.lua, &code(call, &path)).unwrap_err();
+            assert!(err.to_string().contains("sandboxed mode"), "{call}: {err}");
+            assert!(!path.exists(), "{call}");
+            run(&trusted.lua, &code(call, &path)).unwrap();
+            assert!(path.exists(), "{call}");
+            let _ = std::fs::remove_file(&path);
+        }
+
+        let missing = std::env::temp_dir().join("blackjack_sandbox_test_missing_file");
+        for call in readers {
+            let err = run(&sandboxed.lua, &code(call, &missing)).unwrap_err();
+            assert!(err.to_string().contains("sandboxed mode"), "{call}: {err}");
+            // Trusted code gets to the filesystem, which has no such file
+            let err = run(&trusted.lua, &code(call, &missing)).unwrap_err();
+            assert!(!err.to_string().contains("sandboxed mode"), "{call}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_infinite_loop_is_interrupted() {
+        let rt = runtime(LuaRuntimeConfig {
+            instruction_limit: Some(100_000),
+            ..LuaRuntimeConfig::sandboxed()
+        });
+        let err = run(&rt.lua, "while true do end").unwrap_err();
+        assert!(err.to_string().contains("instruction limit"));
+        // The budget is per execution
+        assert!(run(&rt.lua, "local x = 0; for i = 1, 100 do x = x + i end").is_ok());
+    }
+
+    #[test]
+    fn test_geometry_ops_work_in_sandbox() {
+        let rt = runtime(LuaRuntimeConfig::sandboxed());
+        begin_execution(&rt.lua, None);
+        let obj = rt
+            .lua
+            .load(
+                "local mesh = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+                 Ops.extrude(SelectionExpression.new('0'), 1.0, mesh)
+                 return HalfEdgeMesh.to_wavefront_obj_string(mesh)",
+            )
+            .eval::<String>()
+            .unwrap();
+        end_execution(&rt.lua);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 10);
+    }
+}
@@ -10,3 +10,10 @@ pub mod halfedge;
 /// A heightmap data structure. A different mesh representation based on
 /// heightmaps. Supports different operations.
 pub mod heightmap;
+
+/// Materials, and the table of materials of a graph.
+pub mod material;
+
+/// Scenes made of several named objects, each with its own transform, and
+/// their exporters.
+pub mod scene;
@@ -37,12 +37,51 @@ pub mod edit_ops;
 /// Import / Export of HalfEdgeMesh data structure to Wavefront OBJ files
 pub mod wavefront_obj;
 
+/// Splitting vertices along hard edges, shared by the exporters
+pub mod export;
+
+/// Converting meshes between the up axes and handedness files use
+pub mod conventions;
+
+/// Pages of per-element values of a mesh, for the geometry spreadsheet
+pub mod spreadsheet;
+
+/// Import of HalfEdgeMesh data structure from PLY files
+pub mod ply;
+
+/// Building meshes from lists of faces, rejecting the ones that would make
+/// them non-manifold
+pub mod mesh_builder;
+pub use mesh_builder::{FaceErrorReason, MeshBuildError, MeshBuilder};
+
 /// A compact halfedge graph specifically optimized for some operations
 pub mod compact_mesh;
 
 /// Types to represent a selection of a subset of faces, vertices or edges.
 pub mod selection;
 
+/// Reports of how ops change the ids of mesh elements, used to keep
+/// selections pointing to the same elements.
+pub mod id_remap;
+
+/// Computing statistics about meshes, like surface area or volume
+pub mod analysis;
+
+/// Bounding boxes and spheres around meshes
+pub mod bounding_volumes;
+
+/// Walking edge loops and edge rings across quads
+pub mod edge_loops;
+
+/// Comparing meshes regardless of the ids of their elements
+pub mod compare;
+
+/// Storing meshes in files, as the expected results of tests
+pub mod snapshot;
+
+/// Closest point and raycast queries against the surface of a mesh
+pub mod bvh;
+
 /// Generate vertex and index buffers suitable to be uploaded to the GPU for rendering
 pub mod gpu_buffer_generation;
 pub use gpu_buffer_generation::*;
@@ -52,6 +91,14 @@ pub mod halfedge_lua_api;
 pub mod channels;
 pub use channels::*;
 
+/// Interned channel names, cheap to compare and hash
+pub mod channel_name;
+pub use channel_name::ChannelName;
+
+/// String tags on mesh elements, stored as interned channel values
+pub mod tags;
+pub use tags::Tag;
+
 use self::mappings::MeshMapping;
 
 /// HalfEdge meshes are a type of linked list. This means it is sometimes
@@ -143,38 +190,109 @@ pub struct MeshConnectivity {
     debug_vertices: HashMap<VertexId, DebugMark>,
 }
 
+/// How the faces of a mesh are shaded. Used by the viewport and by exporters
+/// to decide which normals to use.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ShadingMode {
+    /// Flat (i.e. per-face) normals.
+    #[default]
+    Flat,
+    /// Smooth (i.e. per-vertex) normals.
+    Smooth,
+    /// Smooth normals, with hard edges wherever faces meet at a sharper angle
+    /// than this one, in radians.
+    AutoSmooth(f32),
+}
+
+impl ShadingMode {
+    /// Parses one of the shading mode names used in Lua: `"flat"`, `"smooth"`
+    /// or `"auto smooth"`. The `angle`, in degrees, is only used by the
+    /// latter, and defaults to 30 degrees.
+    pub fn from_name(name: &str, angle: Option<f32>) -> Result<Self> {
+        match name {
+            "flat" => Ok(ShadingMode::Flat),
+            "smooth" => Ok(ShadingMode::Smooth),
+            "auto smooth" | "auto_smooth" => {
+                Ok(ShadingMode::AutoSmooth(angle.unwrap_or(30.0).to_radians()))
+            }
+            _ => bail!("Unknown shading mode '{name}'"),
+        }
+    }
+
+    /// The inverse of [`ShadingMode::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShadingMode::Flat => "flat",
+            ShadingMode::Smooth => "smooth",
+            ShadingMode::AutoSmooth(_) => "auto smooth",
+        }
+    }
+}
+
 /// This struct contains some parameters that allow configuring the way in which
 /// a mesh is generated.
+///
+/// When two meshes are merged, the result keeps the configuration of the first
+/// one, unless it had no faces, in which case it takes the one of the second.
+/// This way, merging meshes into an empty one keeps the shading of the first
+/// mesh that was merged.
 #[derive(Default, Debug, Clone)]
 pub struct MeshGenerationConfig {
+    /// How the faces of this mesh are shaded.
+    pub shading: ShadingMode,
+}
+
+impl MeshGenerationConfig {
     /// Should this mesh be generated using smooth (i.e. per-vertex) normals? Or
     /// flat (i.e. per-face) normals?
-    pub smooth_normals: bool,
+    pub fn smooth_normals(&self) -> bool {
+        !matches!(self.shading, ShadingMode::Flat)
+    }
+
+    /// When set, smooth meshes are exported with hard edges wherever faces
+    /// meet at a sharper angle than this, in radians.
+    pub fn auto_smooth_angle(&self) -> Option<f32> {
+        match self.shading {
+            ShadingMode::AutoSmooth(angle) => Some(angle),
+            ShadingMode::Flat | ShadingMode::Smooth => None,
+        }
+    }
 }
 
+/// Cloning a mesh is cheap for its connectivity: Clones share it until one of
+/// them writes to it, which makes a copy. Channels are always copied, except
+/// by [`HalfEdgeMesh::clone_for_deform`].
 #[derive(Debug)]
 #[cfg_attr(not(feature = "sync"), derive(Clone))]
 pub struct HalfEdgeMesh {
-    connectivity: InteriorMutable<MeshConnectivity>,
+    connectivity: InteriorMutable<RefCounted<MeshConnectivity>>,
     pub channels: MeshChannels,
     default_channels: DefaultChannels,
     pub gen_config: MeshGenerationConfig,
+    lineage: id_remap::IdLineage,
 }
 
 #[cfg(feature = "sync")]
 impl Clone for HalfEdgeMesh {
     fn clone(&self) -> Self {
         HalfEdgeMesh {
-            connectivity: InteriorMutable::new(self.connectivity.borrow().clone()),
+            connectivity: InteriorMutable::new(RefCounted::clone(&self.connectivity.borrow())),
             channels: self.channels.clone(),
             default_channels: self.default_channels.clone(),
             gen_config: self.gen_config.clone(),
+            lineage: self.lineage.clone(),
         }
     }
 }
 
 pub type Positions = Channel<VertexId, Vec3>;
 
+/// The memory taken by `len` values of type `T` in a slotmap, or in one of
+/// its secondary maps, which store a version along with each value.
+fn slot_bytes<T>(len: usize) -> usize {
+    len * (std::mem::size_of::<T>() + std::mem::size_of::<u32>())
+}
+
 impl MeshConnectivity {
     pub fn new() -> Self {
         Self::default()
@@ -515,6 +633,44 @@ impl MeshConnectivity {
     pub fn num_faces(&self) -> usize {
         self.faces.len()
     }
+
+    /// Returns the number of vertices, halfedges and faces, in that order.
+    pub fn element_counts(&self) -> (usize, usize, usize) {
+        (self.num_vertices(), self.num_halfedges(), self.num_faces())
+    }
+
+    /// Returns an estimate of the memory taken by the elements of this mesh,
+    /// in bytes. See [`HalfEdgeMesh::estimated_bytes`].
+    pub fn estimated_bytes(&self) -> usize {
+        slot_bytes::<Vertex>(self.num_vertices())
+            + slot_bytes::<HalfEdge>(self.num_halfedges())
+            + slot_bytes::<Face>(self.num_faces())
+    }
+
+    /// Returns a hash of the elements of this mesh and the way they connect.
+    /// Two meshes with the same fingerprint have, with very high probability,
+    /// the same topology, no matter where their vertices are.
+    pub fn topology_fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for (v, vertex) in self.vertices.iter() {
+            (v, vertex.halfedge).hash(&mut hasher);
+        }
+        for (f, face) in self.faces.iter() {
+            (f, face.halfedge).hash(&mut hasher);
+        }
+        for (h, halfedge) in self.halfedges.iter() {
+            (
+                h,
+                halfedge.twin,
+                halfedge.next,
+                halfedge.vertex,
+                halfedge.face,
+            )
+                .hash(&mut hasher);
+        }
+        hasher.finish()
+    }
 }
 
 impl HalfEdgeMesh {
@@ -524,13 +680,55 @@ impl HalfEdgeMesh {
         Self {
             channels,
             default_channels,
-            connectivity: InteriorMutable::new(MeshConnectivity::new()),
+            connectivity: InteriorMutable::new(RefCounted::new(MeshConnectivity::new())),
             gen_config: MeshGenerationConfig::default(),
+            lineage: Default::default(),
         }
     }
 
+    /// The nodes this mesh went through, and the id remaps reported by the ops
+    /// that modified it. See [`id_remap`].
+    pub fn lineage(&self) -> &id_remap::IdLineage {
+        &self.lineage
+    }
+
+    pub fn lineage_mut(&mut self) -> &mut id_remap::IdLineage {
+        &mut self.lineage
+    }
+
     pub fn read_connectivity(&self) -> BorrowedRef<'_, MeshConnectivity> {
-        self.connectivity.borrow()
+        BorrowedRef::map(self.connectivity.borrow(), |conn| &**conn)
+    }
+
+    /// Returns whether this mesh and `other` share the same connectivity,
+    /// because one is a clone of the other and neither changed it since.
+    pub fn shares_connectivity(&self, other: &HalfEdgeMesh) -> bool {
+        RefCounted::ptr_eq(&self.connectivity.borrow(), &other.connectivity.borrow())
+    }
+
+    /// Returns an estimate of the memory taken by this mesh, in bytes: The
+    /// elements of its connectivity and the values stored in its channels.
+    /// Spare capacity and the channels shared with other meshes are not told
+    /// apart, so it's only good for telling how large a mesh is.
+    pub fn estimated_bytes(&self) -> usize {
+        self.read_connectivity().estimated_bytes() + self.channels.estimated_bytes()
+    }
+
+    /// Returns a copy of this mesh for ops that only move its vertices. The
+    /// copy shares the connectivity with this mesh, like any clone, but it
+    /// also shares all of its channels except `position` and the ones in
+    /// `written`. The op must not write to the shared channels, since that
+    /// would change this mesh too.
+    pub fn clone_for_deform(&self, written: &[ChannelName]) -> HalfEdgeMesh {
+        let mut private = written.to_vec();
+        private.extend(self.channels.channel_name(self.default_channels.position));
+        HalfEdgeMesh {
+            connectivity: InteriorMutable::new(RefCounted::clone(&self.connectivity.borrow())),
+            channels: self.channels.clone_sharing(&private),
+            default_channels: self.default_channels.clone(),
+            gen_config: self.gen_config.clone(),
+            lineage: self.lineage.clone(),
+        }
     }
 
     /// Generates a lambda suitable for calling the `introspect` method on this
@@ -548,8 +746,32 @@ impl HalfEdgeMesh {
         }
     }
 
+    /// Returns a description of every channel in this mesh, sorted by key
+    /// type, value type and name.
+    pub fn channel_infos(&self) -> Vec<ChannelInfo> {
+        let conn = self.read_connectivity();
+        self.channels
+            .iter_channels_dyn()
+            .map(|(key_type, value_type, name)| ChannelInfo {
+                name,
+                key_type,
+                value_type,
+                len: match key_type {
+                    ChannelKeyType::VertexId => conn.num_vertices(),
+                    ChannelKeyType::FaceId => conn.num_faces(),
+                    ChannelKeyType::HalfEdgeId => conn.num_halfedges(),
+                },
+            })
+            .sorted_by(|a, b| {
+                (a.key_type, a.value_type, &a.name).cmp(&(b.key_type, b.value_type, &b.name))
+            })
+            .collect()
+    }
+
+    /// Borrows the connectivity for writing. When it is shared with other
+    /// meshes, this mesh gets its own copy first.
     pub fn write_connectivity(&self) -> MutableRef<'_, MeshConnectivity> {
-        self.connectivity.borrow_mut()
+        MutableRef::map(self.connectivity.borrow_mut(), RefCounted::make_mut)
     }
 
     pub fn read_positions(&self) -> BorrowedRef<'_, Positions> {
@@ -588,6 +810,41 @@ impl HalfEdgeMesh {
             .expect("Could not write positions")
     }
 
+    /// Applies an affine `transform` to the positions of this mesh. The normal
+    /// channels, when present, are transformed as well so shading stays
+    /// correct.
+    pub fn apply_transform(&self, transform: Mat4) {
+        let conn = self.read_connectivity();
+        let mut positions = self.write_positions();
+        for (v, _) in conn.iter_vertices() {
+            positions[v] = transform.transform_point3(positions[v]);
+        }
+
+        let normal_matrix = transform.inverse().transpose();
+        if let Some(ch_id) = self.default_channels.vertex_normals {
+            let mut normals = self
+                .channels
+                .write_channel(ch_id)
+                .expect("Could not write vertex normals");
+            for (v, _) in conn.iter_vertices() {
+                normals[v] = normal_matrix
+                    .transform_vector3(normals[v])
+                    .normalize_or_zero();
+            }
+        }
+        if let Some(ch_id) = self.default_channels.face_normals {
+            let mut normals = self
+                .channels
+                .write_channel(ch_id)
+                .expect("Could not write face normals");
+            for (f, _) in conn.iter_faces() {
+                normals[f] = normal_matrix
+                    .transform_vector3(normals[f])
+                    .normalize_or_zero();
+            }
+        }
+    }
+
     /// Builds this mesh from a list of vertices, and a list of polygons,
     /// containing indices that reference those vertices.
     ///
@@ -596,6 +853,9 @@ impl HalfEdgeMesh {
     ///
     /// If unsure, you can pass `Vec<Vec<u32>>` as `polygons`. You can also use
     /// `[[u32;3]]` or `&[&[u32]]`. Same for `u8`, `u16` or `usize` indices.
+    ///
+    /// Fails with a [`MeshBuildError`] for the first polygon that would make
+    /// the mesh non-manifold, see [`MeshBuilder`].
     pub fn build_from_polygons<Index, Polygon>(
         positions: &[Vec3],
         polygons: &[Polygon],
@@ -604,140 +864,49 @@ impl HalfEdgeMesh {
         Index: num_traits::AsPrimitive<usize> + 'static + Eq + PartialEq + core::hash::Hash + Copy,
         Polygon: AsRef<[Index]>,
     {
-        let mesh = Self::new();
-        let mut conn = mesh.write_connectivity();
-        let mut positions_ch = mesh.write_positions();
-
-        // Maps indices from the `polygons` array to the allocated vertices in
-        // the newly created halfedge mesh.
-        let mut index_to_vertex = HashMap::<Index, VertexId>::new();
-
-        // Used to compute the degree of a vertex. Useful to do some sanity
-        // checks.
-        let mut vertex_degree = HashMap::<VertexId, u32>::new();
-
-        // First pass over polygon data to determine some initial properties
-        for polygon in polygons.iter().map(|p| p.as_ref()) {
-            // Some sanity checks
-            if polygon.len() < 3 {
-                bail!("Cannot build meshes where polygons have less than three vertices.")
-            }
-            if polygon.iter().duplicates().next().is_some() {
-                bail!("Cannot not build meshes where a polygon has duplicate vertices")
-            }
-
-            // Compute correspondence between vertices and indices. Also fill in vertex degree data.
-            for index in polygon {
-                // Create the vertex if it doesn't exist
-                let position = positions.get(index.as_()).ok_or_else(|| {
-                    anyhow!("Out-of-bounds index in the polygon array {}", index.as_())
-                })?;
-                let v_id = index_to_vertex
-                    .entry(*index)
-                    .or_insert_with(|| conn.alloc_vertex(&mut positions_ch, *position, None));
-
-                // Increment the vertex degree counter for that vertex.
-                *vertex_degree.entry(*v_id).or_insert(0) += 1;
-            }
-        }
-
-        // After the sanity checks, we know the amount of vertices and faces.
-        let _num_vertices = index_to_vertex.len();
-        let _num_faces = polygons.len();
-
-        // Maps pairs of indices to mesh halfedges
-        let mut pair_to_halfedge = HashMap::<(Index, Index), HalfEdgeId>::new();
-
-        // We can now start building connectivity information by doing a second
-        // pass over the polygon list
-        for polygon in polygons.iter().map(|p| p.as_ref()) {
-            // Cyclically ordered list of the half edge ids of this face.
-            let mut half_edges_in_face = SVec::new();
-
-            let face = conn.alloc_face(None);
-
-            for (&a, &b) in polygon.iter().circular_tuple_windows() {
-                if pair_to_halfedge.get(&(a, b)).is_some() {
-                    bail!(
-                        "Found multiple oriented edges with the same indices.\
-                         This means either (i) surface is non-manifold or (ii) faces \
-                         are not oriented in the same direction"
-                    )
-                }
-
-                let h = conn.alloc_halfedge(HalfEdge::default());
-                // Link halfedge to face
-                conn[h].face = Some(face);
-                conn[face].halfedge = Some(h);
-
-                // Link halfedge to source vertex
-                let v_a = index_to_vertex[&a];
-                conn[h].vertex = Some(v_a);
-                conn[v_a].halfedge = Some(h);
-
-                half_edges_in_face.push(h);
-
-                pair_to_halfedge.insert((a, b), h);
-
-                if let Some(&other) = pair_to_halfedge.get(&(b, a)) {
-                    conn[h].twin = Some(other);
-                    conn[other].twin = Some(h);
-                }
-            }
-
-            for (&h1, &h2) in half_edges_in_face.iter().circular_tuple_windows() {
-                conn[h1].next = Some(h2);
-            }
+        let mut builder = MeshBuilder::new(positions);
+        for polygon in polygons {
+            let polygon: SVec<usize> = polygon.as_ref().iter().map(|i| i.as_()).collect();
+            builder.add_face(&polygon)?;
         }
+        Ok(builder.build()?)
+    }
 
-        // Construct the boundary halfedges. Right now, the boundary consists of
-        // incomplete edges, i.e. half edges that do not have a twin. Leaving it
-        // like this would complicate some kinds of traversal because we can't
-        // rely on halfedges always having a twin. We will instead create
-        // boundary half edges: That is, twin halfedges that do not point to any
-        // face. The boundary halfedges are linked following a circle around the
-        // closed boundary. It's easier to imagine this by thinking of a hole in
-        // the mesh, but it works just as well if you think about the "outside"
-        // of a quad grid as a hole, as the loop would go all around the quad
-        conn.add_boundary_halfedges();
-
-        // Do some final manifoldness checks
-        for (v, vertex) in conn.iter_vertices() {
-            if vertex.halfedge.is_none() {
-                bail!("There is at least a single vertex that's disconnected from any polygon");
-            }
-
-            // Check that the number of halfedges emanating from this vertex
-            // equal the number of polygons containing this vertex. If this
-            // doesn't check out, it means our vertex is not a polygon "fan",
-            // but some other (thus, non-manifold) structure
-            let h0 = conn.at_vertex(v).halfedge().end();
-            let mut h = h0;
-            let mut count = 0;
-            loop {
-                if !conn.at_halfedge(h).is_boundary().unwrap() {
-                    count += 1;
-                }
-                h = conn.at_halfedge(h).twin().next().end();
-
-                if h == h0 {
-                    break;
-                }
-            }
-
-            if count != vertex_degree[&v] {
-                bail!("At least one of the vertices is not a polygon fan, but some other nonmanifold structure instead.")
+    /// Same as [`HalfEdgeMesh::build_from_polygons`], but the polygons that
+    /// can't be added are left out instead. Returns the errors for those
+    /// polygons, along with the mesh.
+    pub fn build_from_polygons_lenient<Index, Polygon>(
+        positions: &[Vec3],
+        polygons: &[Polygon],
+    ) -> (Self, Vec<MeshBuildError>)
+    where
+        Index: num_traits::AsPrimitive<usize> + 'static + Eq + PartialEq + core::hash::Hash + Copy,
+        Polygon: AsRef<[Index]>,
+    {
+        let mut builder = MeshBuilder::new(positions);
+        let mut skipped = vec![];
+        for polygon in polygons {
+            let polygon: SVec<usize> = polygon.as_ref().iter().map(|i| i.as_()).collect();
+            if let Err(err) = builder.add_face(&polygon) {
+                skipped.push(err);
             }
         }
-
-        drop(conn);
-        drop(positions_ch);
-        Ok(mesh)
+        let (mesh, non_manifold) = builder.build_lenient();
+        skipped.extend(non_manifold);
+        skipped.sort_by_key(|err| err.face);
+        (mesh, skipped)
     }
 
     /// Merges this halfedge mesh with another one. No additional connectivity
     /// data is generated between the two.
+    ///
+    /// The merged mesh keeps its [`MeshGenerationConfig`], unless it had no
+    /// faces before merging. Then it takes the one of `mesh_b`.
     pub fn merge_with(&mut self, mesh_b: &HalfEdgeMesh) {
+        if self.read_connectivity().num_faces() == 0 {
+            self.gen_config = mesh_b.gen_config.clone();
+        }
+
         let mut vmap = SecondaryMap::<VertexId, VertexId>::new();
         let mut hmap = SecondaryMap::<HalfEdgeId, HalfEdgeId>::new();
         let mut fmap = SecondaryMap::<FaceId, FaceId>::new();
@@ -925,3 +1094,107 @@ impl MeshConnectivity {
         mappings::MeshMapping::new(&self.halfedges)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_connectivity_until_written() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let copy = cube.clone();
+        assert!(copy.shares_connectivity(&cube));
+        let fingerprint = cube.read_connectivity().topology_fingerprint();
+
+        let face = copy.read_connectivity().iter_faces().next().unwrap().0;
+        edit_ops::extrude_faces(
+            &mut copy.write_connectivity(),
+            &mut copy.write_positions(),
+            &[face],
+            1.0,
+        )
+        .unwrap();
+        assert!(!copy.shares_connectivity(&cube));
+        assert_eq!(cube.read_connectivity().num_faces(), 6);
+        assert_eq!(copy.read_connectivity().num_faces(), 10);
+        assert_eq!(cube.read_connectivity().topology_fingerprint(), fingerprint);
+        assert_ne!(copy.read_connectivity().topology_fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_estimated_bytes() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let conn_bytes = cube.read_connectivity().estimated_bytes();
+        assert_eq!(
+            conn_bytes,
+            slot_bytes::<Vertex>(8) + slot_bytes::<HalfEdge>(24) + slot_bytes::<Face>(6)
+        );
+        let channel_bytes = cube.channels.estimated_bytes();
+        assert_eq!(cube.estimated_bytes(), conn_bytes + channel_bytes);
+
+        // Each value takes its size and the version of its slot
+        let ch_id = cube.channels.ensure_channel::<VertexId, f32>("weight");
+        assert_eq!(cube.channels.estimated_bytes(), channel_bytes);
+        let vertices = cube
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .collect_vec();
+        let mut weight = cube.channels.write_channel(ch_id).unwrap();
+        for v in vertices {
+            weight[v] = 1.0;
+        }
+        drop(weight);
+        assert_eq!(cube.channels.estimated_bytes(), channel_bytes + 8 * (4 + 4));
+        let ch_id = cube.channels.ensure_channel::<FaceId, Vec3>("color");
+        let face = cube.read_connectivity().iter_faces().next().unwrap().0;
+        cube.channels.write_channel(ch_id).unwrap()[face] = Vec3::ONE;
+        assert_eq!(
+            cube.channels.estimated_bytes(),
+            channel_bytes + 8 * (4 + 4) + (12 + 4)
+        );
+    }
+
+    #[test]
+    fn test_clone_for_deform() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let weight = cube.channels.ensure_channel::<VertexId, f32>("weight");
+        let other = cube.channels.ensure_channel::<VertexId, f32>("other");
+        let v = cube.read_connectivity().iter_vertices().next().unwrap().0;
+        let original = cube.read_positions()[v];
+
+        let copy = cube.clone_for_deform(&[ChannelName::new("weight")]);
+        assert!(copy.shares_connectivity(&cube));
+        copy.write_positions()[v] = Vec3::splat(5.0);
+        copy.channels.write_channel(weight).unwrap()[v] = 1.0;
+        assert_eq!(cube.read_positions()[v], original);
+        assert_eq!(cube.channels.read_channel(weight).unwrap()[v], 0.0);
+
+        // The channels that weren't declared as written are shared
+        copy.channels.write_channel(other).unwrap()[v] = 2.0;
+        assert_eq!(cube.channels.read_channel(other).unwrap()[v], 2.0);
+    }
+
+    #[test]
+    fn test_merge_shading() {
+        let cube = || primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let mut smooth = cube();
+        edit_ops::set_smooth_normals(&mut smooth).unwrap();
+        let mut auto_smooth = cube();
+        edit_ops::set_shading(&mut auto_smooth, ShadingMode::AutoSmooth(0.5)).unwrap();
+
+        // The first mesh keeps its shading
+        let mut merged = cube();
+        merged.merge_with(&smooth);
+        assert_eq!(merged.gen_config.shading, ShadingMode::Flat);
+        let mut merged = auto_smooth.clone();
+        merged.merge_with(&smooth);
+        assert_eq!(merged.gen_config.shading, ShadingMode::AutoSmooth(0.5));
+
+        // Unless it has no faces
+        let mut merged = HalfEdgeMesh::new();
+        merged.merge_with(&auto_smooth);
+        merged.merge_with(&smooth);
+        assert_eq!(merged.gen_config.shading, ShadingMode::AutoSmooth(0.5));
+    }
+}
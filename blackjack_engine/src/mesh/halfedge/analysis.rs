@@ -0,0 +1,916 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use mlua::ToLua;
+
+use crate::lua_engine::lua_stdlib::LVec3;
+
+use super::*;
+
+/// Summary statistics about a mesh, as computed by [`mesh_stats`].
+#[derive(Debug, Clone, Default)]
+pub struct MeshStats {
+    pub num_vertices: usize,
+    /// Number of edges, that is, pairs of twin halfedges.
+    pub num_edges: usize,
+    pub num_faces: usize,
+    /// Number of triangles the mesh would have if all its faces were
+    /// triangulated.
+    pub num_triangles: usize,
+    pub surface_area: f32,
+    /// Signed volume enclosed by the mesh. This is only meaningful when the
+    /// mesh is closed. Negative when the faces point inwards.
+    pub volume: f32,
+    /// A mesh is closed when it has faces and no boundary edges.
+    pub is_closed: bool,
+    pub bounds_min: Vec3,
+    pub bounds_max: Vec3,
+    /// Number of closed loops formed by the boundary halfedges.
+    pub num_boundary_loops: usize,
+    /// Number of connected components. Isolated vertices count as their own
+    /// component.
+    pub num_components: usize,
+}
+
+impl MeshStats {
+    /// Returns a short, single-line, human-readable description of these stats.
+    pub fn summary(&self) -> String {
+        let closed = if self.is_closed {
+            String::new()
+        } else {
+            format!(" (open, {} boundary loops)", self.num_boundary_loops)
+        };
+        format!(
+            "Verts: {} | Edges: {} | Faces: {} | Tris: {} | Components: {} | Area: {:.3} \
+             | Volume: {:.3}{closed}",
+            self.num_vertices,
+            self.num_edges,
+            self.num_faces,
+            self.num_triangles,
+            self.num_components,
+            self.surface_area,
+            self.volume,
+        )
+    }
+}
+
+/// Iterates the triangles in a fan triangulation of `face`, as triples of
+/// vertex positions. This is exact for convex planar faces and a reasonable
+/// approximation for everything else.
+fn face_fan(
+    conn: &MeshConnectivity,
+    positions: &Positions,
+    face: FaceId,
+) -> impl Iterator<Item = (Vec3, Vec3, Vec3)> {
+    let verts = conn
+        .face_vertices(face)
+        .iter()
+        .map(|v| positions[*v])
+        .collect::<SVec<_>>();
+    let v0 = verts.first().copied().unwrap_or(Vec3::ZERO);
+    (1..verts.len().saturating_sub(1)).map(move |i| (v0, verts[i], verts[i + 1]))
+}
+
+/// Returns the area of a single `face`.
+pub(crate) fn face_area(conn: &MeshConnectivity, positions: &Positions, face: FaceId) -> f32 {
+    face_fan(conn, positions, face)
+        .map(|(a, b, c)| 0.5 * (b - a).cross(c - a).length())
+        .sum()
+}
+
+/// Returns the total area of the faces of this mesh.
+pub fn surface_area(mesh: &HalfEdgeMesh) -> f32 {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    conn.iter_faces()
+        .map(|(f, _)| face_area(&conn, &positions, f))
+        .sum()
+}
+
+/// Returns the signed volume enclosed by this mesh, computed as the sum of the
+/// signed volumes of the tetrahedra formed by the origin and each triangle.
+/// The result is only meaningful for closed meshes.
+pub fn signed_volume(mesh: &HalfEdgeMesh) -> f32 {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    conn.iter_faces()
+        .flat_map(|(f, _)| face_fan(&conn, &positions, f))
+        .map(|(a, b, c)| a.dot(b.cross(c)) / 6.0)
+        .sum()
+}
+
+/// Returns the (min, max) corners of the axis-aligned bounding box of this
+/// mesh. Empty meshes return a zero-sized box at the origin.
+pub fn bounds(mesh: &HalfEdgeMesh) -> (Vec3, Vec3) {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    conn.iter_vertices_with_channel(&positions)
+        .map(|(_, _, pos)| (pos, pos))
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        .unwrap_or((Vec3::ZERO, Vec3::ZERO))
+}
+
+/// Returns the number of closed loops formed by the boundary halfedges of
+/// this mesh, that is, the number of holes.
+pub fn num_boundary_loops(mesh: &HalfEdgeMesh) -> Result<usize> {
+    let conn = mesh.read_connectivity();
+    let mut visited = HashSet::new();
+    let mut count = 0;
+    for (h, halfedge) in conn.iter_halfedges() {
+        if halfedge.face.is_some() || visited.contains(&h) {
+            continue;
+        }
+        let mut h2 = h;
+        let mut iterations = 0;
+        loop {
+            visited.insert(h2);
+            h2 = conn.at_halfedge(h2).next().try_end()?;
+            if h2 == h {
+                break;
+            }
+            iterations += 1;
+            if iterations > MAX_LOOP_ITERATIONS {
+                bail!("Max number of iterations reached. Is the mesh malformed?");
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Returns the number of connected components in this mesh. Two vertices are
+/// connected when there is a path of edges between them.
+pub fn num_connected_components(mesh: &HalfEdgeMesh) -> Result<usize> {
+    let conn = mesh.read_connectivity();
+    // A simple union-find over the vertex ids.
+    let mut parent = HashMap::<VertexId, VertexId>::new();
+    fn find(parent: &mut HashMap<VertexId, VertexId>, v: VertexId) -> VertexId {
+        let mut root = v;
+        while let Some(p) = parent.get(&root).copied().filter(|p| *p != root) {
+            root = p;
+        }
+        // Path compression: point everything we visited straight to the root.
+        let mut v = v;
+        while v != root {
+            let next = parent[&v];
+            parent.insert(v, root);
+            v = next;
+        }
+        root
+    }
+
+    for (v, _) in conn.iter_vertices() {
+        parent.insert(v, v);
+    }
+    for (h, _) in conn.iter_halfedges() {
+        let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+        let (root_a, root_b) = (find(&mut parent, src), find(&mut parent, dst));
+        if root_a != root_b {
+            parent.insert(root_a, root_b);
+        }
+    }
+
+    let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+    Ok(vertices
+        .into_iter()
+        .filter(|v| find(&mut parent, *v) == *v)
+        .count())
+}
+
+/// Computes all the [`MeshStats`] for a given mesh.
+pub fn mesh_stats(mesh: &HalfEdgeMesh) -> Result<MeshStats> {
+    let (num_vertices, num_edges, num_faces, num_triangles, has_boundary) = {
+        let conn = mesh.read_connectivity();
+        let num_edges = conn
+            .iter_halfedges()
+            .filter(|(h, halfedge)| halfedge.twin.map_or(true, |tw| *h < tw))
+            .count();
+        let num_triangles = conn
+            .iter_faces()
+            .map(|(f, _)| conn.face_edges(f).len().saturating_sub(2))
+            .sum();
+        let has_boundary = conn.iter_halfedges().any(|(_, h)| h.face.is_none());
+        (
+            conn.num_vertices(),
+            num_edges,
+            conn.num_faces(),
+            num_triangles,
+            has_boundary,
+        )
+    };
+    let (bounds_min, bounds_max) = bounds(mesh);
+
+    Ok(MeshStats {
+        num_vertices,
+        num_edges,
+        num_faces,
+        num_triangles,
+        surface_area: surface_area(mesh),
+        volume: signed_volume(mesh),
+        is_closed: num_faces > 0 && !has_boundary,
+        bounds_min,
+        bounds_max,
+        num_boundary_loops: num_boundary_loops(mesh)?,
+        num_components: num_connected_components(mesh)?,
+    })
+}
+
+/// Returns one halfedge for each edge of this mesh with a length between `min`
+/// and `max`, both included.
+pub fn edges_by_length(mesh: &HalfEdgeMesh, min: f32, max: f32) -> Result<Vec<HalfEdgeId>> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let mut edges = vec![];
+    for (h, halfedge) in conn.iter_halfedges() {
+        if halfedge.twin.map_or(false, |tw| tw < h) {
+            continue;
+        }
+        let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+        if (min..=max).contains(&positions[src].distance(positions[dst])) {
+            edges.push(h);
+        }
+    }
+    Ok(edges)
+}
+
+/// Returns the faces of this mesh with an area between `min` and `max`, both
+/// included.
+pub fn faces_by_area(mesh: &HalfEdgeMesh, min: f32, max: f32) -> Vec<FaceId> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    conn.iter_faces()
+        .map(|(f, _)| f)
+        .filter(|f| (min..=max).contains(&face_area(&conn, &positions, *f)))
+        .collect()
+}
+
+/// Returns the faces of this mesh that are not planar. The deviation of a
+/// vertex is the angle between the plane of the face and the line from the
+/// face centroid to the vertex. Faces where any vertex deviates more than
+/// `max_deviation_deg` degrees are returned. Triangles are always planar.
+pub fn non_planar_faces(mesh: &HalfEdgeMesh, max_deviation_deg: f32) -> Vec<FaceId> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let max_sin = max_deviation_deg.to_radians().sin();
+    conn.iter_faces()
+        .map(|(f, _)| f)
+        .filter(|f| {
+            let points = conn
+                .face_vertices(*f)
+                .iter()
+                .map(|v| positions[*v])
+                .collect::<SVec<_>>();
+            if points.len() <= 3 {
+                return false;
+            }
+            // Newell's method, which works for concave and non-planar faces
+            let normal = points
+                .iter()
+                .circular_tuple_windows()
+                .map(|(a, b)| (*a - *b).cross(*a + *b))
+                .sum::<Vec3>()
+                .normalize_or_zero();
+            let centroid = points.iter().sum::<Vec3>() / points.len() as f32;
+            points.iter().any(|p| {
+                let rel = *p - centroid;
+                normal.dot(rel).abs() > max_sin * rel.length()
+            })
+        })
+        .collect()
+}
+
+/// Returns one halfedge for each of the non-manifold edges of this mesh. An
+/// edge is non-manifold when the number of faces around the pair of vertices
+/// it connects is neither 1, for boundary edges, nor 2. Edges shared by more
+/// than two faces are stored as several halfedge pairs between the same two
+/// vertices, and all of them are returned.
+pub fn non_manifold_edges(mesh: &HalfEdgeMesh) -> Result<Vec<HalfEdgeId>> {
+    let conn = mesh.read_connectivity();
+    let mut edges = HashMap::<(VertexId, VertexId), (usize, SVec<HalfEdgeId>)>::new();
+    let mut order = vec![];
+    for (h, halfedge) in conn.iter_halfedges() {
+        let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+        let key = (src.min(dst), src.max(dst));
+        let (num_faces, halfedges) = edges.entry(key).or_insert_with(|| {
+            order.push(key);
+            Default::default()
+        });
+        *num_faces += halfedge.face.is_some() as usize;
+        if halfedge.twin.map_or(true, |tw| h < tw) {
+            halfedges.push(h);
+        }
+    }
+    Ok(order
+        .into_iter()
+        .filter_map(|key| {
+            let (num_faces, halfedges) = edges.remove(&key)?;
+            (num_faces != 1 && num_faces != 2).then_some(halfedges)
+        })
+        .flatten()
+        .collect())
+}
+
+/// Returns the non-manifold vertices of this mesh, the ones where the faces
+/// around them don't form a single fan. This is detected when going around
+/// the vertex doesn't visit all of its outgoing halfedges, or when the fan
+/// has more than one gap at the boundary.
+pub fn non_manifold_vertices(mesh: &HalfEdgeMesh) -> Result<Vec<VertexId>> {
+    let conn = mesh.read_connectivity();
+    let mut outgoing = HashMap::<VertexId, usize>::new();
+    for (h, _) in conn.iter_halfedges() {
+        *outgoing
+            .entry(conn.at_halfedge(h).vertex().try_end()?)
+            .or_default() += 1;
+    }
+    let mut vertices = vec![];
+    for (v, _) in conn.iter_vertices() {
+        let manifold = match conn.at_vertex(v).outgoing_halfedges() {
+            Ok(fan) => {
+                let num_gaps = fan.iter().filter(|h| conn[**h].face.is_none()).count();
+                fan.len() == outgoing.get(&v).copied().unwrap_or(0) && num_gaps <= 1
+            }
+            Err(_) => false,
+        };
+        if !manifold {
+            vertices.push(v);
+        }
+    }
+    Ok(vertices)
+}
+
+/// Two uvs closer than this are considered the same corner value.
+const UV_EPSILON: f32 = 1e-5;
+
+/// Returns whether the edge of `h` is a uv seam, that is, whether the faces at
+/// both sides of the edge disagree on the uvs of its endpoints. Uvs are stored
+/// at the source vertex of each halfedge. Boundary edges are always seams.
+pub(crate) fn is_uv_seam(
+    conn: &MeshConnectivity,
+    uvs: &Channel<HalfEdgeId, Vec3>,
+    h: HalfEdgeId,
+) -> Result<bool> {
+    let t = conn.at_halfedge(h).twin().try_end()?;
+    if conn[h].face.is_none() || conn[t].face.is_none() {
+        return Ok(true);
+    }
+    let h_next = conn.at_halfedge(h).next().try_end()?;
+    let t_next = conn.at_halfedge(t).next().try_end()?;
+    Ok(uvs[h].distance(uvs[t_next]) > UV_EPSILON || uvs[t].distance(uvs[h_next]) > UV_EPSILON)
+}
+
+/// A face of the mesh laid out in uv space, as returned by [`uv_islands`].
+#[derive(Debug, Clone)]
+pub struct UvPolygon {
+    pub face: FaceId,
+    /// The uvs at the corners of the face, in the order of its halfedges.
+    pub uvs: SVec<Vec2>,
+    /// The stretch of the edge from each corner to the next one: The ratio
+    /// between its length in 3d and its length in uv space. Edges collapsed
+    /// to a point in uv space have an infinite stretch.
+    pub stretch: SVec<f32>,
+}
+
+impl UvPolygon {
+    /// Returns the average stretch of the edges of this face.
+    pub fn mean_stretch(&self) -> f32 {
+        self.stretch.iter().sum::<f32>() / self.stretch.len().max(1) as f32
+    }
+}
+
+/// A group of faces that are connected in uv space, through edges that are
+/// not uv seams.
+#[derive(Debug, Clone, Default)]
+pub struct UvIsland {
+    pub polygons: Vec<UvPolygon>,
+}
+
+/// Lays out the faces of this mesh in uv space, using its `uv` channel, and
+/// groups them into islands. Returns an error when the mesh has no uvs.
+pub fn uv_islands(mesh: &HalfEdgeMesh) -> Result<Vec<UvIsland>> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let uvs = mesh
+        .read_uvs()
+        .ok_or_else(|| anyhow!("The mesh has no uv channel"))?;
+
+    let mut visited = HashSet::new();
+    let mut islands = vec![];
+    for (start, _) in conn.iter_faces() {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut island = UvIsland::default();
+        let mut stack = vec![start];
+        while let Some(face) = stack.pop() {
+            let mut polygon = UvPolygon {
+                face,
+                uvs: SVec::new(),
+                stretch: SVec::new(),
+            };
+            for h in conn.face_edges(face) {
+                let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+                let h_next = conn.at_halfedge(h).next().try_end()?;
+                let (uv, uv_next) = (uvs[h].truncate(), uvs[h_next].truncate());
+                let uv_length = uv.distance(uv_next);
+                polygon.uvs.push(uv);
+                polygon.stretch.push(if uv_length > 1e-12 {
+                    positions[src].distance(positions[dst]) / uv_length
+                } else {
+                    f32::INFINITY
+                });
+                if !is_uv_seam(&conn, &uvs, h)? {
+                    let neighbor = conn.at_halfedge(h).twin().face().try_end()?;
+                    if visited.insert(neighbor) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            island.polygons.push(polygon);
+        }
+        islands.push(island);
+    }
+    Ok(islands)
+}
+
+/// A measurement of a mesh, as taken by [`probe`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeMode {
+    BoundsMin,
+    BoundsMax,
+    BoundsSize,
+    /// The average position of the vertices.
+    Centroid,
+    /// The position of the vertex with the given index.
+    VertexPosition(u32),
+    /// The average position of the vertices of the face with the given index.
+    FaceCenter(u32),
+    /// The value of a channel for the element with the given index. Boolean
+    /// channels give 1 or 0.
+    ChannelValue {
+        key_type: ChannelKeyType,
+        name: String,
+        id: u32,
+    },
+    SurfaceArea,
+    VertexCount,
+}
+
+/// The result of a [`probe`], either a scalar or a vector depending on the
+/// mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeValue {
+    Scalar(f32),
+    Vector(Vec3),
+}
+
+/// Takes the measurement of `mesh` described by `mode`. Indices refer to the
+/// elements in the same order as selections do. Fails when an index is out of
+/// range, or when the channel doesn't exist.
+pub fn probe(mesh: &HalfEdgeMesh, mode: &ProbeMode) -> Result<ProbeValue> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let vertex_at = |id: u32| {
+        conn.iter_vertices()
+            .nth(id as usize)
+            .map(|(v, _)| v)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Vertex index {id} is out of range. The mesh has {} vertices",
+                    conn.num_vertices()
+                )
+            })
+    };
+    let face_at = |id: u32| {
+        conn.iter_faces()
+            .nth(id as usize)
+            .map(|(f, _)| f)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Face index {id} is out of range. The mesh has {} faces",
+                    conn.num_faces()
+                )
+            })
+    };
+
+    Ok(match mode {
+        ProbeMode::BoundsMin => ProbeValue::Vector(bounds(mesh).0),
+        ProbeMode::BoundsMax => ProbeValue::Vector(bounds(mesh).1),
+        ProbeMode::BoundsSize => {
+            let (min, max) = bounds(mesh);
+            ProbeValue::Vector(max - min)
+        }
+        ProbeMode::Centroid => {
+            let sum = conn
+                .iter_vertices()
+                .fold(Vec3::ZERO, |sum, (v, _)| sum + positions[v]);
+            ProbeValue::Vector(sum / conn.num_vertices().max(1) as f32)
+        }
+        ProbeMode::VertexPosition(id) => ProbeValue::Vector(positions[vertex_at(*id)?]),
+        ProbeMode::FaceCenter(id) => {
+            let vertices = conn.face_vertices(face_at(*id)?);
+            let sum = vertices
+                .iter()
+                .fold(Vec3::ZERO, |sum, v| sum + positions[*v]);
+            ProbeValue::Vector(sum / vertices.len().max(1) as f32)
+        }
+        ProbeMode::ChannelValue { key_type, name, id } => {
+            macro_rules! read_value {
+                ($key:ty, $element:expr) => {{
+                    let element = $element;
+                    if let Ok(ch) = mesh.channels.read_channel_by_name::<$key, f32>(name) {
+                        ProbeValue::Scalar(ch[element])
+                    } else if let Ok(ch) = mesh.channels.read_channel_by_name::<$key, Vec3>(name) {
+                        ProbeValue::Vector(ch[element])
+                    } else if let Ok(ch) = mesh.channels.read_channel_by_name::<$key, bool>(name) {
+                        ProbeValue::Scalar(if ch[element] { 1.0 } else { 0.0 })
+                    } else {
+                        bail!("The mesh has no {key_type:?} channel named '{name}'")
+                    }
+                }};
+            }
+            match key_type {
+                ChannelKeyType::VertexId => read_value!(VertexId, vertex_at(*id)?),
+                ChannelKeyType::FaceId => read_value!(FaceId, face_at(*id)?),
+                ChannelKeyType::HalfEdgeId => read_value!(
+                    HalfEdgeId,
+                    conn.iter_halfedges()
+                        .nth(*id as usize)
+                        .map(|(h, _)| h)
+                        .ok_or_else(|| anyhow!(
+                            "Halfedge index {id} is out of range. The mesh has {} halfedges",
+                            conn.num_halfedges()
+                        ))?
+                ),
+            }
+        }
+        ProbeMode::SurfaceArea => ProbeValue::Scalar(surface_area(mesh)),
+        ProbeMode::VertexCount => ProbeValue::Scalar(conn.num_vertices() as f32),
+    })
+}
+
+impl<'lua> ToLua<'lua> for ProbeValue {
+    fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
+        match self {
+            ProbeValue::Scalar(value) => value.to_lua(lua),
+            ProbeValue::Vector(value) => LVec3(value).to_lua(lua),
+        }
+    }
+}
+
+impl<'lua> ToLua<'lua> for MeshStats {
+    fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("num_vertices", self.num_vertices)?;
+        table.set("num_edges", self.num_edges)?;
+        table.set("num_faces", self.num_faces)?;
+        table.set("num_triangles", self.num_triangles)?;
+        table.set("surface_area", self.surface_area)?;
+        table.set("volume", self.volume)?;
+        table.set("is_closed", self.is_closed)?;
+        table.set("bounds_min", LVec3(self.bounds_min))?;
+        table.set("bounds_max", LVec3(self.bounds_max))?;
+        table.set("num_boundary_loops", self.num_boundary_loops)?;
+        table.set("num_components", self.num_components)?;
+        table.set("summary", self.summary())?;
+        table.to_lua(lua)
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Computes statistics about the given `mesh`. Returns a table with the
+    /// vertex, edge, face and triangle counts, the surface area, the signed
+    /// volume and whether the mesh is closed, the `bounds_min` and
+    /// `bounds_max` corners of its bounding box, the number of boundary loops
+    /// and connected components, plus a human-readable `summary` string.
+    #[lua(under = "Ops")]
+    pub fn mesh_stats(mesh: &HalfEdgeMesh) -> Result<MeshStats> {
+        super::mesh_stats(mesh)
+    }
+
+    /// Measures `mesh`, returning a number or a vector depending on the
+    /// `mode`. The `id` is the index of the vertex, face or channel element
+    /// for the modes that need one, and `key_type` and `channel_name` pick
+    /// the channel for the `ChannelValue` mode.
+    #[lua(under = "Ops")]
+    pub fn probe(
+        mesh: &HalfEdgeMesh,
+        mode: String,
+        id: u32,
+        key_type: ChannelKeyType,
+        channel_name: String,
+    ) -> Result<ProbeValue> {
+        let mode = match mode.as_str() {
+            "BoundsMin" => ProbeMode::BoundsMin,
+            "BoundsMax" => ProbeMode::BoundsMax,
+            "BoundsSize" => ProbeMode::BoundsSize,
+            "Centroid" => ProbeMode::Centroid,
+            "VertexPosition" => ProbeMode::VertexPosition(id),
+            "FaceCenter" => ProbeMode::FaceCenter(id),
+            "ChannelValue" => ProbeMode::ChannelValue {
+                key_type,
+                name: channel_name,
+                id,
+            },
+            "SurfaceArea" => ProbeMode::SurfaceArea,
+            "VertexCount" => ProbeMode::VertexCount,
+            _ => bail!("Invalid probe mode '{mode}'"),
+        };
+        super::probe(mesh, &mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cube_stats() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::splat(2.0)).unwrap();
+        let stats = mesh_stats(&cube).unwrap();
+        assert_eq!(stats.num_vertices, 8);
+        assert_eq!(stats.num_edges, 12);
+        assert_eq!(stats.num_faces, 6);
+        assert_eq!(stats.num_triangles, 12);
+        assert!((stats.surface_area - 24.0).abs() < 1e-4);
+        assert!((stats.volume.abs() - 8.0).abs() < 1e-4);
+        assert!(stats.is_closed);
+        assert!(stats.bounds_min.abs_diff_eq(Vec3::splat(-1.0), 1e-5));
+        assert!(stats.bounds_max.abs_diff_eq(Vec3::splat(1.0), 1e-5));
+        assert_eq!(stats.num_boundary_loops, 0);
+        assert_eq!(stats.num_components, 1);
+    }
+
+    #[test]
+    fn test_sphere_volume() {
+        let radius = 1.5;
+        let sphere = primitives::UVSphere::build(Vec3::ZERO, 64, 32, radius).unwrap();
+        let stats = mesh_stats(&sphere).unwrap();
+        let expected_volume = 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3);
+        let expected_area = 4.0 * std::f32::consts::PI * radius.powi(2);
+        assert!(stats.is_closed);
+        // The polygonal approximation is always a bit smaller than the sphere.
+        assert!((stats.volume.abs() - expected_volume).abs() / expected_volume < 0.01);
+        assert!((stats.surface_area - expected_area).abs() / expected_area < 0.01);
+    }
+
+    #[test]
+    fn test_open_mesh_stats() {
+        let mut quad = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        let stats = mesh_stats(&quad).unwrap();
+        assert!(!stats.is_closed);
+        assert_eq!(stats.num_boundary_loops, 1);
+        assert_eq!(stats.num_edges, 4);
+        assert!((stats.surface_area - 1.0).abs() < 1e-5);
+
+        let other = primitives::Quad::build(Vec3::X * 5.0, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        quad.merge_with(&other);
+        let stats = mesh_stats(&quad).unwrap();
+        assert_eq!(stats.num_components, 2);
+        assert_eq!(stats.num_boundary_loops, 2);
+        let summary = stats.summary();
+        assert!(summary.contains("| Components: 2 |"), "{summary}");
+        assert!(summary.ends_with("(open, 2 boundary loops)"), "{summary}");
+    }
+
+    /// Two quads sharing an edge, with a triangle fin on that same edge
+    /// making it non-manifold. The fin is connected by hand, because
+    /// `build_from_polygons` rejects non-manifold meshes.
+    fn mesh_with_fin() -> HalfEdgeMesh {
+        let mesh = HalfEdgeMesh::build_from_polygons(
+            &[
+                Vec3::ZERO,
+                Vec3::X,
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::Z,
+                Vec3::new(1.0, 0.0, -1.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            ],
+            &[[0, 1, 2, 3], [1, 0, 5, 4]],
+        )
+        .unwrap();
+        {
+            let mut conn = mesh.write_connectivity();
+            let mut positions = mesh.write_positions();
+            let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+            let (v0, v1) = (vertices[0], vertices[1]);
+            let tip = conn.alloc_vertex(&mut positions, Vec3::new(0.5, 1.0, 0.0), None);
+
+            let face = conn.alloc_face(None);
+            // Halfedges v0 -> v1 -> tip, and their twins going the other way
+            let inner = [v0, v1, tip].map(|src| {
+                conn.alloc_halfedge(HalfEdge {
+                    vertex: Some(src),
+                    face: Some(face),
+                    ..Default::default()
+                })
+            });
+            let outer = [v1, tip, v0].map(|src| {
+                conn.alloc_halfedge(HalfEdge {
+                    vertex: Some(src),
+                    ..Default::default()
+                })
+            });
+            conn[face].halfedge = Some(inner[0]);
+            conn[tip].halfedge = Some(inner[2]);
+            for i in 0..3 {
+                conn[inner[i]].next = Some(inner[(i + 1) % 3]);
+                conn[inner[i]].twin = Some(outer[i]);
+                conn[outer[i]].twin = Some(inner[i]);
+            }
+            // The boundary goes around the fin the other way
+            conn[outer[0]].next = Some(outer[2]);
+            conn[outer[2]].next = Some(outer[1]);
+            conn[outer[1]].next = Some(outer[0]);
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_quality_selections() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0)).unwrap();
+        assert_eq!(edges_by_length(&cube, 1.5, 2.5).unwrap().len(), 4);
+        assert_eq!(edges_by_length(&cube, 0.0, 10.0).unwrap().len(), 12);
+        // The two faces of size 1x2
+        assert_eq!(faces_by_area(&cube, 1.9, 2.1).len(), 2);
+        assert!(non_planar_faces(&cube, 0.1).is_empty());
+
+        // Lifting a corner of a quad makes it non-planar
+        let quad = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        let v = quad.read_connectivity().iter_vertices().next().unwrap().0;
+        quad.write_positions()[v].y += 0.5;
+        assert_eq!(non_planar_faces(&quad, 5.0).len(), 1);
+        assert!(non_planar_faces(&quad, 60.0).is_empty());
+    }
+
+    #[test]
+    fn test_non_manifold() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert!(non_manifold_edges(&cube).unwrap().is_empty());
+        assert!(non_manifold_vertices(&cube).unwrap().is_empty());
+        // Boundary edges are fine
+        let quad = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        assert!(non_manifold_edges(&quad).unwrap().is_empty());
+
+        let mesh = mesh_with_fin();
+        let conn = mesh.read_connectivity();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        // The shared edge is made of two halfedge pairs, one for the quads
+        // and one for the fin.
+        let edges = non_manifold_edges(&mesh).unwrap();
+        assert_eq!(edges.len(), 2);
+        for h in edges {
+            let (src, dst) = conn.at_halfedge(h).src_dst_pair().unwrap();
+            let mut pair = [src, dst];
+            pair.sort();
+            assert_eq!(pair, [vertices[0], vertices[1]]);
+        }
+        // Only the two vertices of the shared edge have two fans
+        assert_eq!(
+            non_manifold_vertices(&mesh).unwrap(),
+            vec![vertices[0], vertices[1]]
+        );
+    }
+
+    #[test]
+    fn test_uv_islands() {
+        // Each side of the cube is its own island in the uv layout
+        let sphere = primitives::QuadSphere::build(Vec3::ZERO, 1.0, 3).unwrap();
+        let islands = uv_islands(&sphere).unwrap();
+        assert_eq!(islands.len(), 6);
+        for island in &islands {
+            assert_eq!(island.polygons.len(), 9);
+        }
+
+        // With every face mapped to the full uv range, no edge is shared
+        let mut sphere = sphere;
+        edit_ops::set_full_range_uvs(&mut sphere).unwrap();
+        assert_eq!(uv_islands(&sphere).unwrap().len(), 6 * 9);
+
+        let no_uvs = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert!(uv_islands(&no_uvs).is_err());
+    }
+
+    #[test]
+    fn test_uv_stretch() {
+        let mut quad =
+            primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::new(2.0, 4.0)).unwrap();
+        edit_ops::set_full_range_uvs(&mut quad).unwrap();
+        let islands = uv_islands(&quad).unwrap();
+        assert_eq!(islands.len(), 1);
+        let polygon = &islands[0].polygons[0];
+        assert_eq!(polygon.uvs.len(), 4);
+        // The unit uv square is stretched to 2 units along one side and 4
+        // along the other.
+        let mut stretch = polygon.stretch.to_vec();
+        stretch.sort_by(f32::total_cmp);
+        for (s, expected) in stretch.iter().zip([2.0, 2.0, 4.0, 4.0]) {
+            assert!((s - expected).abs() < 1e-5, "{stretch:?}");
+        }
+        assert!((polygon.mean_stretch() - 3.0).abs() < 1e-5);
+
+        // Collapsing a corner onto the next one in uv space
+        let conn = quad.read_connectivity();
+        let h = conn
+            .iter_halfedges()
+            .find(|(_, h)| h.face.is_some())
+            .unwrap()
+            .0;
+        let h_next = conn.at_halfedge(h).next().try_end().unwrap();
+        let uvs_ch_id = quad.default_channels.uvs.unwrap();
+        let mut uvs = quad.channels.write_channel(uvs_ch_id).unwrap();
+        let uv = uvs[h_next];
+        uvs[h] = uv;
+        drop(uvs);
+        drop(conn);
+        let islands = uv_islands(&quad).unwrap();
+        assert!(islands[0].polygons[0]
+            .stretch
+            .iter()
+            .any(|s| s.is_infinite()));
+    }
+
+    #[test]
+    fn test_probe_box() {
+        let cube =
+            primitives::Box::build(Vec3::new(1.0, 2.0, 3.0), Vec3::new(2.0, 4.0, 6.0)).unwrap();
+        let vector = |mode: ProbeMode| match probe(&cube, &mode).unwrap() {
+            ProbeValue::Vector(v) => v,
+            other => panic!("Expected a vector for {mode:?}, got {other:?}"),
+        };
+        let scalar = |mode: ProbeMode| match probe(&cube, &mode).unwrap() {
+            ProbeValue::Scalar(s) => s,
+            other => panic!("Expected a scalar for {mode:?}, got {other:?}"),
+        };
+        let (min, max) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 6.0));
+        let center = Vec3::new(1.0, 2.0, 3.0);
+
+        assert!(vector(ProbeMode::BoundsMin).abs_diff_eq(min, 1e-5));
+        assert!(vector(ProbeMode::BoundsMax).abs_diff_eq(max, 1e-5));
+        assert!(vector(ProbeMode::BoundsSize).abs_diff_eq(max - min, 1e-5));
+        assert!(vector(ProbeMode::Centroid).abs_diff_eq(center, 1e-5));
+        assert_eq!(scalar(ProbeMode::VertexCount), 8.0);
+        // 2 * (2*4 + 2*6 + 4*6)
+        assert!((scalar(ProbeMode::SurfaceArea) - 88.0).abs() < 1e-3);
+
+        for id in 0..8 {
+            // Every vertex is a corner of the box
+            let offset = (vector(ProbeMode::VertexPosition(id)) - center).abs();
+            assert!(
+                offset.abs_diff_eq(Vec3::new(1.0, 2.0, 3.0), 1e-5),
+                "{offset}"
+            );
+        }
+        for id in 0..6 {
+            // Face centers are at the middle of a side, along a single axis
+            let offset = (vector(ProbeMode::FaceCenter(id)) - center).abs();
+            let on_axis = [Vec3::X, Vec3::Y * 2.0, Vec3::Z * 3.0]
+                .iter()
+                .any(|axis| offset.abs_diff_eq(*axis, 1e-5));
+            assert!(on_axis, "{offset}");
+        }
+        assert!(probe(&cube, &ProbeMode::VertexPosition(8)).is_err());
+        assert!(probe(&cube, &ProbeMode::FaceCenter(6)).is_err());
+    }
+
+    #[test]
+    fn test_probe_channels() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let weight_id = cube
+            .channels
+            .create_channel::<VertexId, f32>("weight")
+            .unwrap();
+        {
+            let conn = cube.read_connectivity();
+            let mut weight = cube.channels.write_channel(weight_id).unwrap();
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                weight[v] = i as f32 * 0.5;
+            }
+        }
+        let channel = |key_type, name: &str, id| ProbeMode::ChannelValue {
+            key_type,
+            name: name.into(),
+            id,
+        };
+        assert_eq!(
+            probe(&cube, &channel(ChannelKeyType::VertexId, "weight", 3)).unwrap(),
+            ProbeValue::Scalar(1.5)
+        );
+        // Positions are a vector channel
+        assert_eq!(
+            probe(&cube, &channel(ChannelKeyType::VertexId, "position", 0)).unwrap(),
+            probe(&cube, &ProbeMode::VertexPosition(0)).unwrap()
+        );
+        let err = probe(&cube, &channel(ChannelKeyType::FaceId, "weight", 0)).unwrap_err();
+        assert!(
+            err.to_string().contains("no FaceId channel named 'weight'"),
+            "{err}"
+        );
+        assert!(probe(&cube, &channel(ChannelKeyType::VertexId, "weight", 100)).is_err());
+    }
+}
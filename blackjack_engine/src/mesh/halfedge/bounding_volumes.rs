@@ -0,0 +1,326 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use glam::{EulerRot, Mat3};
+use mlua::ToLua;
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::*;
+
+/// A box around a mesh, possibly rotated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub center: Vec3,
+    /// The full length of the box along each of its local axes.
+    pub size: Vec3,
+    /// The rotation from the local axes of the box to world space. The
+    /// identity for axis-aligned boxes.
+    pub rotation: Quat,
+}
+
+/// A sphere around a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+fn vertex_positions(mesh: &HalfEdgeMesh) -> Result<Vec<Vec3>> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let points = conn
+        .iter_vertices_with_channel(&positions)
+        .map(|(_, _, pos)| pos)
+        .collect_vec();
+    if points.is_empty() {
+        bail!("The mesh has no vertices to compute its bounds");
+    }
+    Ok(points)
+}
+
+/// Returns the axis-aligned bounding box of `mesh`.
+pub fn axis_aligned_bounding_box(mesh: &HalfEdgeMesh) -> Result<BoundingBox> {
+    vertex_positions(mesh)?;
+    let (min, max) = super::analysis::bounds(mesh);
+    Ok(BoundingBox {
+        center: (min + max) * 0.5,
+        size: max - min,
+        rotation: Quat::IDENTITY,
+    })
+}
+
+/// Returns an oriented bounding box of `mesh`. The axes of the box are the
+/// principal components of the vertex positions, from the direction they are
+/// most spread along to the one they are least spread along. This fits
+/// elongated shapes well, but isn't always the smallest box possible.
+pub fn oriented_bounding_box(mesh: &HalfEdgeMesh) -> Result<BoundingBox> {
+    let points = vertex_positions(mesh)?;
+    let mean = points.iter().copied().sum::<Vec3>() / points.len() as f32;
+    let mut covariance = Mat3::ZERO;
+    for p in &points {
+        let r = *p - mean;
+        covariance += Mat3::from_cols(r * r.x, r * r.y, r * r.z);
+    }
+    let (values, vectors) = symmetric_eigen3(covariance * (1.0 / points.len() as f32));
+
+    let mut axes = [
+        (values.x, vectors.x_axis),
+        (values.y, vectors.y_axis),
+        (values.z, vectors.z_axis),
+    ];
+    axes.sort_by(|a, b| b.0.total_cmp(&a.0));
+    // Eigenvectors have no preferred sign. Pointing them towards positive
+    // coordinates keeps the rotation small for boxes that are almost aligned.
+    let mut axes = axes.map(|(_, axis)| {
+        let largest = (0..3)
+            .max_by(|a, b| axis[*a].abs().total_cmp(&axis[*b].abs()))
+            .expect("Vectors have three components");
+        if axis[largest] < 0.0 {
+            -axis
+        } else {
+            axis
+        }
+    });
+    // Keep the axes right-handed, so they form a rotation
+    if axes[0].cross(axes[1]).dot(axes[2]) < 0.0 {
+        axes[2] = -axes[2];
+    }
+    let rotation = Quat::from_mat3(&Mat3::from_cols(axes[0], axes[1], axes[2])).normalize();
+
+    let (min, max) = points
+        .iter()
+        .map(|p| rotation.inverse() * *p)
+        .map(|p| (p, p))
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        .expect("There is at least one vertex");
+    Ok(BoundingBox {
+        center: rotation * ((min + max) * 0.5),
+        size: max - min,
+        rotation,
+    })
+}
+
+impl BoundingBox {
+    /// Builds a box mesh with six quads matching this box.
+    pub fn to_mesh(&self) -> Result<HalfEdgeMesh> {
+        let mesh = primitives::Box::build(Vec3::ZERO, self.size)?;
+        {
+            let conn = mesh.read_connectivity();
+            let mut positions = mesh.write_positions();
+            for (v, _) in conn.iter_vertices() {
+                positions[v] = self.center + self.rotation * positions[v];
+            }
+        }
+        Ok(mesh)
+    }
+}
+
+/// Returns a sphere containing all the vertices of `mesh`, using Ritter's
+/// algorithm. The sphere is close to the smallest one, usually a few percent
+/// larger.
+pub fn bounding_sphere(mesh: &HalfEdgeMesh) -> Result<BoundingSphere> {
+    let points = vertex_positions(mesh)?;
+    let farthest_from = |from: Vec3| {
+        points
+            .iter()
+            .copied()
+            .max_by(|a, b| {
+                a.distance_squared(from)
+                    .total_cmp(&b.distance_squared(from))
+            })
+            .expect("There is at least one vertex")
+    };
+
+    // Start with the sphere around two points that are far apart
+    let a = farthest_from(points[0]);
+    let b = farthest_from(a);
+    let mut center = (a + b) * 0.5;
+    let mut radius = a.distance(b) * 0.5;
+
+    // Then grow it to contain the points that are left out
+    for p in &points {
+        let distance = p.distance(center);
+        if distance > radius {
+            let new_radius = (radius + distance) * 0.5;
+            center += (*p - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    }
+    Ok(BoundingSphere { center, radius })
+}
+
+impl BoundingSphere {
+    /// Builds a UV sphere matching this sphere, with the given number of
+    /// `segments` around it.
+    pub fn to_mesh(&self, segments: u32) -> Result<HalfEdgeMesh> {
+        if segments < 3 {
+            bail!("A sphere needs at least 3 segments, got {segments}");
+        }
+        primitives::UVSphere::build(self.center, segments, (segments / 2).max(2), self.radius)
+    }
+}
+
+impl<'lua> ToLua<'lua> for BoundingBox {
+    fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("center", LVec3(self.center))?;
+        table.set("size", LVec3(self.size))?;
+        let rotation: Vec3 = self.rotation.to_euler(EulerRot::XYZ).into();
+        table.set("rotation", LVec3(rotation))?;
+        table.to_lua(lua)
+    }
+}
+
+impl<'lua> ToLua<'lua> for BoundingSphere {
+    fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("center", LVec3(self.center))?;
+        table.set("radius", self.radius)?;
+        table.to_lua(lua)
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    fn compute_box(mesh: &HalfEdgeMesh, oriented: bool) -> Result<BoundingBox> {
+        if oriented {
+            oriented_bounding_box(mesh)
+        } else {
+            axis_aligned_bounding_box(mesh)
+        }
+    }
+
+    /// Returns a box mesh around `mesh`. When `oriented` is true, the box is
+    /// rotated to follow the principal axes of the mesh, otherwise it is
+    /// aligned with the world axes.
+    #[lua(under = "Ops")]
+    pub fn bounding_box(mesh: &HalfEdgeMesh, oriented: bool) -> Result<HalfEdgeMesh> {
+        compute_box(mesh, oriented)?.to_mesh()
+    }
+
+    /// Measures the box returned by `bounding_box`. Returns a table with its
+    /// `center`, its `size` and its `rotation`, as XYZ euler angles.
+    #[lua(under = "Ops")]
+    pub fn measure_bounding_box(mesh: &HalfEdgeMesh, oriented: bool) -> Result<BoundingBox> {
+        compute_box(mesh, oriented)
+    }
+
+    /// Returns a UV sphere mesh with the given number of `segments` that
+    /// contains all the vertices of `mesh`.
+    #[lua(under = "Ops")]
+    pub fn bounding_sphere(mesh: &HalfEdgeMesh, segments: u32) -> Result<HalfEdgeMesh> {
+        super::bounding_sphere(mesh)?.to_mesh(segments)
+    }
+
+    /// Measures the sphere returned by `bounding_sphere`. Returns a table
+    /// with its `center` and its `radius`.
+    #[lua(under = "Ops")]
+    pub fn measure_bounding_sphere(mesh: &HalfEdgeMesh) -> Result<BoundingSphere> {
+        super::bounding_sphere(mesh)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rotated_box(size: Vec3, rotation: Quat, center: Vec3) -> HalfEdgeMesh {
+        BoundingBox {
+            center,
+            size,
+            rotation,
+        }
+        .to_mesh()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_axis_aligned_box() {
+        let mesh = primitives::UVSphere::build(Vec3::new(1.0, 2.0, 3.0), 8, 4, 2.0).unwrap();
+        let bbox = axis_aligned_bounding_box(&mesh).unwrap();
+        assert!(bbox.center.abs_diff_eq(Vec3::new(1.0, 2.0, 3.0), 1e-5));
+        assert!((bbox.size.y - 4.0).abs() < 1e-5);
+        assert_eq!(bbox.rotation, Quat::IDENTITY);
+
+        let box_mesh = bbox.to_mesh().unwrap();
+        assert_eq!(box_mesh.read_connectivity().num_faces(), 6);
+        let (min, max) = crate::mesh::halfedge::analysis::bounds(&mesh);
+        let (box_min, box_max) = crate::mesh::halfedge::analysis::bounds(&box_mesh);
+        assert!(box_min.abs_diff_eq(min, 1e-5) && box_max.abs_diff_eq(max, 1e-5));
+    }
+
+    #[test]
+    fn test_oriented_box_of_rotated_box() {
+        let size = Vec3::new(4.0, 2.0, 1.0);
+        let rotation = Quat::from_euler(EulerRot::XYZ, 0.4, -0.9, 1.3);
+        let center = Vec3::new(-1.0, 0.5, 2.0);
+        let mesh = rotated_box(size, rotation, center);
+
+        let obb = oriented_bounding_box(&mesh).unwrap();
+        assert!(obb.size.abs_diff_eq(size, 1e-3), "{:?}", obb.size);
+        assert!(obb.center.abs_diff_eq(center, 1e-3));
+        // The axes match up to their sign
+        for (axis, expected) in [
+            (obb.rotation * Vec3::X, rotation * Vec3::X),
+            (obb.rotation * Vec3::Y, rotation * Vec3::Y),
+            (obb.rotation * Vec3::Z, rotation * Vec3::Z),
+        ] {
+            assert!((axis.dot(expected).abs() - 1.0).abs() < 1e-3);
+        }
+
+        // The box mesh has the same corners as the original box
+        let obb_mesh = obb.to_mesh().unwrap();
+        let corners = |mesh: &HalfEdgeMesh| {
+            let positions = mesh.read_positions();
+            mesh.read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| positions[v])
+                .collect_vec()
+        };
+        let expected = corners(&mesh);
+        for corner in corners(&obb_mesh) {
+            assert!(expected.iter().any(|c| c.abs_diff_eq(corner, 1e-3)));
+        }
+    }
+
+    #[test]
+    fn test_bounding_sphere_contains_vertices() {
+        let mut mesh = rotated_box(
+            Vec3::new(3.0, 1.0, 0.5),
+            Quat::from_rotation_y(0.7),
+            Vec3::X,
+        );
+        mesh.merge_with(&primitives::UVSphere::build(Vec3::new(0.0, 3.0, 1.0), 8, 4, 0.5).unwrap());
+        let sphere = bounding_sphere(&mesh).unwrap();
+        for p in vertex_positions(&mesh).unwrap() {
+            assert!(p.distance(sphere.center) <= sphere.radius + 1e-4);
+        }
+
+        let sphere_mesh = sphere.to_mesh(16).unwrap();
+        for p in vertex_positions(&sphere_mesh).unwrap() {
+            assert!((p.distance(sphere.center) - sphere.radius).abs() < 1e-4);
+        }
+        assert!(sphere.to_mesh(2).is_err());
+        assert!(bounding_sphere(&HalfEdgeMesh::new()).is_err());
+    }
+
+    #[test]
+    fn test_symmetric_eigen3() {
+        let rotation = Mat3::from_quat(Quat::from_euler(EulerRot::XYZ, 0.3, 1.1, -0.5));
+        let m = rotation * Mat3::from_diagonal(Vec3::new(5.0, 2.0, 0.5)) * rotation.transpose();
+        let (values, vectors) = symmetric_eigen3(m);
+        for i in 0..3 {
+            let v = vectors.col(i);
+            assert!((v.length() - 1.0).abs() < 1e-4);
+            assert!((m * v).abs_diff_eq(v * values[i], 1e-4));
+        }
+        let mut sorted = values.to_array();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        assert!(Vec3::from(sorted).abs_diff_eq(Vec3::new(0.5, 2.0, 5.0), 1e-4));
+    }
+}
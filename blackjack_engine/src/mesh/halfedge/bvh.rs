@@ -0,0 +1,326 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A bounding volume hierarchy over the faces of a mesh, for closest point
+//! and raycast queries against its surface. Faces are split into triangles
+//! with a fan around their first vertex, so non-planar faces are supported but
+//! their surface may not match the one used for rendering exactly.
+
+use rstar::{PointDistance, RTree, RTreeObject, SelectionFunction, AABB};
+
+use crate::prelude::*;
+
+/// One of the triangles of a face.
+#[derive(Debug, Clone)]
+struct BvhTriangle {
+    face: FaceId,
+    vertices: [VertexId; 3],
+    points: [Vec3; 3],
+}
+
+impl BvhTriangle {
+    /// Returns the closest point in this triangle to `p`, as barycentric
+    /// coordinates.
+    ///
+    /// From "Real-Time Collision Detection" (Ericson 2004), section 5.1.5.
+    fn closest_point_barycentric(&self, p: Vec3) -> Vec3 {
+        let [a, b, c] = self.points;
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return Vec3::X;
+        }
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return Vec3::Y;
+        }
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            let v = d1 / (d1 - d3);
+            return Vec3::new(1.0 - v, v, 0.0);
+        }
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return Vec3::Z;
+        }
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            let w = d2 / (d2 - d6);
+            return Vec3::new(1.0 - w, 0.0, w);
+        }
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+            return Vec3::new(0.0, 1.0 - w, w);
+        }
+        let denom = 1.0 / (va + vb + vc);
+        let v = vb * denom;
+        let w = vc * denom;
+        Vec3::new(1.0 - v - w, v, w)
+    }
+
+    fn point_at(&self, barycentric: Vec3) -> Vec3 {
+        self.points[0] * barycentric.x
+            + self.points[1] * barycentric.y
+            + self.points[2] * barycentric.z
+    }
+
+    /// Intersects the ray with this triangle. Returns the distance along the
+    /// ray, and the barycentric coordinates of the hit.
+    ///
+    /// Uses the Möller–Trumbore algorithm. Both sides of the triangle count.
+    fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<(f32, Vec3)> {
+        let [a, b, c] = self.points;
+        let ab = b - a;
+        let ac = c - a;
+        let p = direction.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let ao = origin - a;
+        let u = ao.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = ao.cross(ab);
+        let v = direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = ac.dot(q) * inv_det;
+        (t >= 0.0).then(|| (t, Vec3::new(1.0 - u - v, u, v)))
+    }
+}
+
+impl RTreeObject for BvhTriangle {
+    type Envelope = AABB<[f32; 3]>;
+    fn envelope(&self) -> Self::Envelope {
+        let [a, b, c] = self.points;
+        AABB::from_corners(a.min(b).min(c).to_array(), a.max(b).max(c).to_array())
+    }
+}
+
+impl PointDistance for BvhTriangle {
+    fn distance_2(&self, point: &[f32; 3]) -> f32 {
+        let p = Vec3::from_slice(point);
+        self.point_at(self.closest_point_barycentric(p))
+            .distance_squared(p)
+    }
+}
+
+/// Selects the triangles whose bounding box is crossed by a ray.
+struct RaySelection {
+    origin: Vec3,
+    inv_direction: Vec3,
+}
+
+impl SelectionFunction<BvhTriangle> for RaySelection {
+    fn should_unpack_parent(&self, envelope: &AABB<[f32; 3]>) -> bool {
+        // Slab test
+        let t0 = (Vec3::from_slice(&envelope.lower()) - self.origin) * self.inv_direction;
+        let t1 = (Vec3::from_slice(&envelope.upper()) - self.origin) * self.inv_direction;
+        let t_min = t0.min(t1).max_element();
+        let t_max = t0.max(t1).min_element();
+        t_max >= t_min.max(0.0)
+    }
+}
+
+/// A point on the surface of a mesh, found by a [`MeshBvh`] query.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceHit {
+    pub point: Vec3,
+    /// The face the point belongs to
+    pub face: FaceId,
+    /// The vertices of the triangle of `face` containing the point
+    pub vertices: [VertexId; 3],
+    /// The barycentric coordinates of the point, relative to `vertices`
+    pub barycentric: Vec3,
+    /// The distance from the query point, or along the ray
+    pub distance: f32,
+}
+
+impl SurfaceHit {
+    /// Interpolates the values of a vertex channel at the hit point.
+    pub fn interpolate<V>(&self, values: impl Fn(VertexId) -> V) -> V
+    where
+        V: std::ops::Mul<f32, Output = V> + std::ops::Add<Output = V>,
+    {
+        values(self.vertices[0]) * self.barycentric.x
+            + values(self.vertices[1]) * self.barycentric.y
+            + values(self.vertices[2]) * self.barycentric.z
+    }
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh.
+pub struct MeshBvh {
+    tree: RTree<BvhTriangle>,
+}
+
+impl MeshBvh {
+    pub fn build(mesh: &HalfEdgeMesh) -> Self {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let mut triangles = vec![];
+        for (face, _) in conn.iter_faces() {
+            let verts = conn.face_vertices(face);
+            for i in 1..verts.len().saturating_sub(1) {
+                let vertices = [verts[0], verts[i], verts[i + 1]];
+                triangles.push(BvhTriangle {
+                    face,
+                    vertices,
+                    points: vertices.map(|v| positions[v]),
+                });
+            }
+        }
+        Self {
+            tree: RTree::bulk_load(triangles),
+        }
+    }
+
+    /// Returns the closest point to `p` on the surface of the mesh. Returns
+    /// `None` when the mesh has no faces.
+    pub fn closest_point(&self, p: Vec3) -> Option<SurfaceHit> {
+        let triangle = self.tree.nearest_neighbor(&p.to_array())?;
+        let barycentric = triangle.closest_point_barycentric(p);
+        let point = triangle.point_at(barycentric);
+        Some(SurfaceHit {
+            point,
+            face: triangle.face,
+            vertices: triangle.vertices,
+            barycentric,
+            distance: point.distance(p),
+        })
+    }
+
+    /// Returns the first point on the surface of the mesh hit by a ray from
+    /// `origin` towards `direction`. The `direction` doesn't need to be
+    /// normalized, but the distance of the hit is measured in multiples of
+    /// its length.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<SurfaceHit> {
+        self.ray_hits(origin, direction)
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Same as [`MeshBvh::raycast`], but returns all the points where the ray
+    /// crosses the surface, sorted by distance. A ray going exactly through
+    /// an edge or a vertex hits every triangle around it.
+    pub fn raycast_all(&self, origin: Vec3, direction: Vec3) -> Vec<SurfaceHit> {
+        let mut hits = self.ray_hits(origin, direction).collect_vec();
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    /// Returns whether a ray from `origin` towards `direction` hits the
+    /// surface at a distance between `min_distance` and `max_distance`. This
+    /// stops at the first hit found, so it's cheaper than a full raycast.
+    pub fn ray_occluded(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        min_distance: f32,
+        max_distance: f32,
+    ) -> bool {
+        self.ray_hits(origin, direction)
+            .any(|hit| hit.distance >= min_distance && hit.distance <= max_distance)
+    }
+
+    /// Returns the vertices of the faces of the mesh that are within
+    /// `radius` of `center`, in no particular order. Vertices with no faces
+    /// are not in the hierarchy, so they are never returned.
+    pub fn vertices_within(&self, center: Vec3, radius: f32) -> Vec<VertexId> {
+        let mut found = HashSet::new();
+        for triangle in self
+            .tree
+            .locate_within_distance(center.to_array(), radius * radius)
+        {
+            for (v, point) in triangle.vertices.iter().zip(&triangle.points) {
+                if point.distance(center) <= radius {
+                    found.insert(*v);
+                }
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    fn ray_hits(&self, origin: Vec3, direction: Vec3) -> impl Iterator<Item = SurfaceHit> + '_ {
+        let selection = RaySelection {
+            origin,
+            inv_direction: Vec3::ONE / direction,
+        };
+        self.tree
+            .locate_with_selection_function(selection)
+            .filter_map(move |triangle| {
+                let (t, barycentric) = triangle.raycast(origin, direction)?;
+                Some(SurfaceHit {
+                    point: origin + direction * t,
+                    face: triangle.face,
+                    vertices: triangle.vertices,
+                    barycentric,
+                    distance: t,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_closest_point_and_raycast() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::splat(2.0)).unwrap();
+        let bvh = MeshBvh::build(&mesh);
+
+        let hit = bvh.closest_point(Vec3::new(0.2, 5.0, -0.3)).unwrap();
+        assert!((hit.point - Vec3::new(0.2, 1.0, -0.3)).length() < 1e-5);
+        assert!((hit.distance - 4.0).abs() < 1e-5);
+        let b = hit.barycentric;
+        assert!((b.x + b.y + b.z - 1.0).abs() < 1e-5);
+
+        // Outside the box, close to a corner
+        let hit = bvh.closest_point(Vec3::splat(3.0)).unwrap();
+        assert!((hit.point - Vec3::ONE).length() < 1e-5);
+
+        let hit = bvh.raycast(Vec3::new(0.5, 0.5, 10.0), -Vec3::Z).unwrap();
+        assert!((hit.point - Vec3::new(0.5, 0.5, 1.0)).length() < 1e-5);
+        assert!((hit.distance - 9.0).abs() < 1e-5);
+        assert!(bvh.raycast(Vec3::new(0.5, 0.5, 10.0), Vec3::Z).is_none());
+        assert!(bvh.raycast(Vec3::new(5.0, 0.5, 10.0), -Vec3::Z).is_none());
+
+        // Through the two faces perpendicular to the ray
+        let hits = bvh.raycast_all(Vec3::new(0.5, 0.25, 10.0), -Vec3::Z);
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].distance - 9.0).abs() < 1e-5);
+        assert!((hits[1].distance - 11.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_vertices_within() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::splat(2.0)).unwrap();
+        let bvh = MeshBvh::build(&mesh);
+        let positions = mesh.read_positions();
+
+        let found = bvh.vertices_within(Vec3::new(1.0, 1.0, 0.0), 1.5);
+        assert_eq!(found.len(), 2);
+        for v in found {
+            assert!(
+                positions[v].abs_diff_eq(Vec3::new(1.0, 1.0, 1.0), 1e-5)
+                    || positions[v].abs_diff_eq(Vec3::new(1.0, 1.0, -1.0), 1e-5)
+            );
+        }
+        assert!(bvh.vertices_within(Vec3::splat(5.0), 1.0).is_empty());
+        assert_eq!(bvh.vertices_within(Vec3::ZERO, 2.0).len(), 8);
+    }
+}
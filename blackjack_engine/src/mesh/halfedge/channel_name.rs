@@ -0,0 +1,214 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::{Debug, Display};
+use std::sync::RwLock;
+
+use mlua::{FromLua, Lua, ToLua};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// The interned strings, shared by all the threads. Interned strings are
+/// never freed, which is fine since there's only a handful of different
+/// channel names in a session.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, u32>,
+    names: Vec<&'static str>,
+}
+
+static INTERNER: Lazy<RwLock<Interner>> = Lazy::new(Default::default);
+
+/// The name of a channel. Names are interned: Each different string gets a
+/// small integer, so comparing or hashing names doesn't need to look at the
+/// string. This matters for ops that go over every channel of a mesh for
+/// each element they create.
+///
+/// The string is only hashed when the name is created, usually once at the
+/// API boundary, from Lua or from Rust code using `&str` names. Names are
+/// ordered by their string, and serialized as strings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChannelName(u32);
+
+impl ChannelName {
+    /// Returns the name for `name`, interning it if this is the first time it
+    /// is seen.
+    pub fn new(name: &str) -> Self {
+        if let Some(id) = INTERNER.read().unwrap().ids.get(name) {
+            return Self(*id);
+        }
+        let mut interner = INTERNER.write().unwrap();
+        // Another thread may have interned it after releasing the read lock
+        if let Some(id) = interner.ids.get(name) {
+            return Self(*id);
+        }
+        let id = interner.names.len() as u32;
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        interner.names.push(name);
+        interner.ids.insert(name, id);
+        Self(id)
+    }
+
+    /// Returns the string this name was created from.
+    pub fn as_str(self) -> &'static str {
+        INTERNER.read().unwrap().names[self.0 as usize]
+    }
+}
+
+impl From<&str> for ChannelName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<&ChannelName> for ChannelName {
+    fn from(name: &ChannelName) -> Self {
+        *name
+    }
+}
+
+impl From<&String> for ChannelName {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for ChannelName {
+    fn from(name: String) -> Self {
+        Self::new(&name)
+    }
+}
+
+impl PartialEq<str> for ChannelName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ChannelName {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for ChannelName {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ChannelName {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self.0 == other.0 {
+            std::cmp::Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl Display for ChannelName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for ChannelName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for ChannelName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ChannelName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(&String::deserialize(deserializer)?))
+    }
+}
+
+impl<'lua> FromLua<'lua> for ChannelName {
+    fn from_lua(value: mlua::Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        Ok(Self::new(mlua::String::from_lua(value, lua)?.to_str()?))
+    }
+}
+
+impl<'lua> ToLua<'lua> for ChannelName {
+    fn to_lua(self, lua: &'lua Lua) -> mlua::Result<mlua::Value<'lua>> {
+        self.as_str().to_lua(lua)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let name = ChannelName::new("test_round_trip");
+        assert_eq!(name.as_str(), "test_round_trip");
+        assert_eq!(name.to_string(), "test_round_trip");
+        assert_eq!(format!("{name:?}"), "\"test_round_trip\"");
+        assert_eq!(name, ChannelName::from("test_round_trip".to_string()));
+        assert_ne!(name, ChannelName::new("test_round_trip_2"));
+        assert!(name == "test_round_trip");
+
+        let serialized = ron::to_string(&name).unwrap();
+        assert_eq!(serialized, "\"test_round_trip\"");
+        assert_eq!(ron::from_str::<ChannelName>(&serialized).unwrap(), name);
+
+        let lua = Lua::new();
+        let value = name.to_lua(&lua).unwrap();
+        assert!(matches!(value, mlua::Value::String(_)));
+        assert_eq!(ChannelName::from_lua(value, &lua).unwrap(), name);
+    }
+
+    #[test]
+    fn test_ordered_by_string() {
+        // Interned in the opposite order
+        let b = ChannelName::new("test_ordered_b");
+        let a = ChannelName::new("test_ordered_a");
+        assert!(a < b);
+        assert_eq!(vec![b, a].into_iter().sorted().collect_vec(), vec![a, b]);
+    }
+
+    #[test]
+    fn test_concurrent_interning() {
+        let names = (0..50)
+            .map(|i| format!("test_concurrent_{i}"))
+            .collect_vec();
+        let threads = (0..8)
+            .map(|_| {
+                let names = names.clone();
+                std::thread::spawn(move || {
+                    names
+                        .iter()
+                        .map(|name| ChannelName::new(name))
+                        .collect_vec()
+                })
+            })
+            .collect_vec();
+        let interned = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect_vec();
+        // Every thread got the same name for each string, and different
+        // strings got different names.
+        for other in &interned[1..] {
+            assert_eq!(other, &interned[0]);
+        }
+        assert_eq!(interned[0].iter().unique().count(), names.len());
+        for (name, interned) in names.iter().zip(&interned[0]) {
+            assert_eq!(interned.as_str(), name);
+        }
+    }
+}
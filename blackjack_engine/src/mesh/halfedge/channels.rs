@@ -69,10 +69,100 @@ impl Introspect for bool {
     }
 }
 
+impl Introspect for Tag {
+    fn introspect(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+/// Used by mesh operations that create new elements in between existing ones,
+/// like splitting an edge, to compute the channel values of the new elements.
+pub trait Interpolate: Sized {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+    /// Returns the average of the given `(value, weight)` pairs. The weights
+    /// don't need to add up to one, but their sum must be positive.
+    fn weighted_average(values: &[(Self, f32)]) -> Self;
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+
+    fn weighted_average(values: &[(Self, f32)]) -> Self {
+        let total: f32 = values.iter().map(|(_, w)| w).sum();
+        values
+            .iter()
+            .fold(Vec3::ZERO, |acc, (v, w)| acc + *v * (*w / total))
+    }
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+
+    fn weighted_average(values: &[(Self, f32)]) -> Self {
+        let total: f32 = values.iter().map(|(_, w)| w).sum();
+        values.iter().map(|(v, w)| v * (w / total)).sum()
+    }
+}
+
+impl Interpolate for bool {
+    /// Booleans can't be interpolated, so the closest value is picked.
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        if t < 0.5 {
+            self
+        } else {
+            other
+        }
+    }
+
+    /// The value with the largest total weight is picked. Ties are `false`.
+    fn weighted_average(values: &[(Self, f32)]) -> Self {
+        values
+            .iter()
+            .map(|(v, w)| if *v { *w } else { -*w })
+            .sum::<f32>()
+            > 0.0
+    }
+}
+
+impl Interpolate for Tag {
+    /// Tags can't be interpolated either, so the closest one is picked.
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        if t < 0.5 {
+            self
+        } else {
+            other
+        }
+    }
+
+    /// The tag with the largest total weight is picked. Ties go to the one
+    /// that comes first.
+    fn weighted_average(values: &[(Self, f32)]) -> Self {
+        let mut totals: SVec<(Tag, f32)> = SVec::new();
+        for (tag, w) in values {
+            match totals.iter_mut().find(|(t, _)| t == tag) {
+                Some((_, total)) => *total += w,
+                None => totals.push((*tag, *w)),
+            }
+        }
+        totals
+            .iter()
+            .fold(None, |best: Option<(Tag, f32)>, (tag, total)| match best {
+                Some((_, best_total)) if best_total >= *total => best,
+                _ => Some((*tag, *total)),
+            })
+            .map(|(tag, _)| tag)
+            .unwrap_or_default()
+    }
+}
+
 /// The value of a channel is the data that is associated to a specific key.
-/// Values can be scalars (f32) or vectors (Vec3).
+/// Values can be scalars (f32), vectors (Vec3), booleans or string tags.
 pub trait ChannelValue:
-    Default + Debug + Clone + Copy + Sized + FromToLua + Introspect + MaybeSync + 'static
+    Default + Debug + Clone + Copy + Sized + FromToLua + Introspect + Interpolate + MaybeSync + 'static
 {
     fn value_type() -> ChannelValueType;
     fn name() -> &'static str;
@@ -94,6 +184,7 @@ macro_rules! impl_channel_value {
 impl_channel_value!(Vec3);
 impl_channel_value!(f32);
 impl_channel_value!(bool);
+impl_channel_value!(Tag);
 
 /// The `FromLua` and `ToLua` traits have a lifetime parameter which is
 /// unnecessary for the channel keys and values. We introduce this new trait
@@ -138,6 +229,18 @@ impl_from_to_lua!(flat VertexId);
 impl_from_to_lua!(flat FaceId);
 impl_from_to_lua!(flat HalfEdgeId);
 
+/// Tags are strings in Lua.
+impl FromToLua for Tag {
+    fn cast_to_lua(self, lua: &Lua) -> mlua::Value {
+        self.as_str().to_lua(lua).unwrap()
+    }
+
+    fn cast_from_lua(value: mlua::Value, lua: &Lua) -> Result<Self> {
+        let value = mlua::String::from_lua(value, lua)?;
+        Ok(Tag::new(value.to_str()?))
+    }
+}
+
 /// An enum representing all the types that implement the [`ChannelKey`] type as
 /// variants. The values from this enum are used when dynamic behaviour is
 /// required. This can be seen as an ad-hoc replacement for `TypeId`.
@@ -149,7 +252,7 @@ pub enum ChannelKeyType { VertexId, FaceId, HalfEdgeId }
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 #[rustfmt::skip]
 #[allow(non_camel_case_types)]
-pub enum ChannelValueType { Vec3, f32, bool }
+pub enum ChannelValueType { Vec3, f32, bool, Tag }
 
 /// A channel represents a set of data that is associated over all the elements
 /// of a mesh. For instance, the well-known `position` channel of a mesh, is a
@@ -212,7 +315,7 @@ impl<K: ChannelKey, V: ChannelValue> ChannelId<K, V> {
 /// ownership of a channel to the Lua runtime.
 #[derive(Debug)]
 pub struct ChannelGroup<K: ChannelKey, V: ChannelValue> {
-    channel_names: bimap::BiMap<String, ChannelId<K, V>>,
+    channel_names: bimap::BiMap<ChannelName, ChannelId<K, V>>,
     channels: SlotMap<RawChannelId, RefCounted<InteriorMutable<Channel<K, V>>>>,
 }
 
@@ -224,6 +327,14 @@ impl<K: ChannelKey, V: ChannelValue> MaybeSync for ChannelGroup<K, V> {}
 ///
 /// The methods in this struct mirror the [`ChannelGroup`] API by providing
 /// typed and untyped variants for static and dynamic access.
+///
+/// Every channel has a value for each mesh element of its key type. When ops
+/// create new elements, they either give them a value computed from existing
+/// elements with a [`ChannelWriteBatch`], or leave them with the default
+/// value of the channel. After each node runs, the channels of its output
+/// meshes are synced with [`sync_channels`](super::edit_ops::sync_channels):
+/// The default value is stored for elements without a value, and the values of
+/// removed elements are dropped.
 #[derive(Default, Debug, Clone)]
 pub struct MeshChannels {
     channels: HashMap<(ChannelKeyType, ChannelValueType), Box<dyn DynChannelGroup>>,
@@ -244,6 +355,29 @@ pub struct DefaultChannels {
     pub uvs: Option<ChannelId<HalfEdgeId, Vec3>>,
 }
 
+/// A description of one of the channels of a mesh, as returned by
+/// [`HalfEdgeMesh::channel_infos`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelInfo {
+    pub name: ChannelName,
+    pub key_type: ChannelKeyType,
+    pub value_type: ChannelValueType,
+    /// The number of values in the channel, one for each element of the mesh
+    /// with the channel's key type.
+    pub len: usize,
+}
+
+impl<'lua> ToLua<'lua> for ChannelInfo {
+    fn to_lua(self, lua: &'lua Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("name", self.name)?;
+        table.set("key_type", self.key_type)?;
+        table.set("value_type", self.value_type)?;
+        table.set("len", self.len)?;
+        Ok(mlua::Value::Table(table))
+    }
+}
+
 impl<K: ChannelKey, V: ChannelValue> std::ops::Index<K> for Channel<K, V> {
     type Output = V;
 
@@ -260,7 +394,7 @@ impl<K: ChannelKey, V: ChannelValue> std::ops::IndexMut<K> for Channel<K, V> {
             // key was removed from the originating slot map.
             .expect("Error indexing channel. Key was removed from the originating slotmap.")
             // Will insert the default value for never-accessed keys.
-            .or_default()
+            .or_insert(self.default)
     }
 }
 impl<K: ChannelKey, V: ChannelValue> Channel<K, V> {
@@ -281,6 +415,11 @@ impl<K: ChannelKey, V: ChannelValue> Channel<K, V> {
         }
     }
 
+    /// Returns the value for the keys that were never set.
+    pub fn default_value(&self) -> V {
+        self.default
+    }
+
     /// Iterates the inner slotmap, returning an iterator of keys and values
     pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
         self.inner.iter()
@@ -357,6 +496,41 @@ pub trait DynChannel: Any + Debug {
         table: mlua::Table<'lua>,
     ) -> Result<()>;
 
+    /// Sets the value of every element in `keys` to `value`.
+    fn fill_lua<'lua>(
+        &mut self,
+        keys: &[u64],
+        lua: &'lua mlua::Lua,
+        value: mlua::Value<'lua>,
+    ) -> Result<()>;
+
+    /// Sets the value returned for the keys that were never set to `value`.
+    fn set_default_lua<'lua>(
+        &mut self,
+        lua: &'lua mlua::Lua,
+        value: mlua::Value<'lua>,
+    ) -> Result<()>;
+
+    /// Calls `f` with the value and the key of every element in `keys`, and
+    /// replaces the value with the one returned by `f`. This keeps the channel
+    /// borrowed during the whole iteration instead of once per element.
+    fn map_lua<'lua>(
+        &mut self,
+        keys: &[u64],
+        lua: &'lua mlua::Lua,
+        f: &mlua::Function<'lua>,
+    ) -> Result<()>;
+
+    /// Copies the values of `other` into this channel. The value for each of
+    /// the `keys` is taken from the key at the same position in `other_keys`.
+    /// Fails if both channels are not of the same type.
+    fn copy_from_dyn(
+        &mut self,
+        keys: &[u64],
+        other: &dyn DynChannel,
+        other_keys: &[u64],
+    ) -> Result<()>;
+
     /// Merges this channel with another channel. This method will panic if both
     /// channels are not of the same type.
     ///
@@ -369,6 +543,58 @@ pub trait DynChannel: Any + Debug {
         get_ids: &dyn Fn(ChannelKeyType) -> Rc<Vec<slotmap::KeyData>>,
         id_map: &dyn Fn(ChannelKeyType, slotmap::KeyData) -> slotmap::KeyData,
     );
+
+    /// Returns the keys of every element with a value explicitly stored in
+    /// this channel. Note that this may include keys for elements that have
+    /// since been removed from the mesh.
+    fn stored_keys(&self) -> Vec<slotmap::KeyData>;
+
+    /// Removes the value stored for `key`, if any. The channel will return
+    /// the default value for that key afterwards.
+    fn remove_stored(&mut self, key: slotmap::KeyData);
+
+    /// Returns the number of stored values, the same as the length of
+    /// `stored_keys`.
+    fn stored_len(&self) -> usize;
+
+    /// Returns an estimate of the memory taken by the stored values, in
+    /// bytes.
+    fn estimated_bytes(&self) -> usize;
+
+    /// Stores the default value of the channel for every key in `keys` that
+    /// doesn't have a value yet.
+    fn backfill_dyn(&mut self, keys: &[slotmap::KeyData]);
+
+    /// Sets the value at `dst` to the default value of the channel.
+    fn reset_dyn(&mut self, dst: slotmap::KeyData);
+
+    /// Sets the value at `dst` to the interpolation between the values at `a`
+    /// and `b`, with factor `t`.
+    fn interpolate_dyn(
+        &mut self,
+        dst: slotmap::KeyData,
+        a: slotmap::KeyData,
+        b: slotmap::KeyData,
+        t: f32,
+    );
+
+    /// Sets the value at `dst` to the weighted average of the values at the
+    /// `(key, weight)` pairs in `sources`.
+    fn interpolate_weighted_dyn(
+        &mut self,
+        dst: slotmap::KeyData,
+        sources: &[(slotmap::KeyData, f32)],
+    );
+
+    /// Sets the value for each of the `keys` to the weighted average of the
+    /// values of `other` at the `(key, weight)` pairs in the same position of
+    /// `sources`. Fails if both channels are not of the same type.
+    fn interpolate_from_dyn(
+        &mut self,
+        keys: &[slotmap::KeyData],
+        other: &dyn DynChannel,
+        sources: &[SVec<(slotmap::KeyData, f32)>],
+    ) -> Result<()>;
 }
 impl<K: ChannelKey, V: ChannelValue> DynChannel for Channel<K, V> {
     fn as_any(&self) -> &dyn Any {
@@ -455,6 +681,68 @@ impl<K: ChannelKey, V: ChannelValue> DynChannel for Channel<K, V> {
         })
     }
 
+    fn fill_lua<'lua>(
+        &mut self,
+        keys: &[u64],
+        lua: &'lua mlua::Lua,
+        value: mlua::Value<'lua>,
+    ) -> Result<()> {
+        let value: V = FromToLua::cast_from_lua(value, lua)?;
+        for k in keys.iter_cpy() {
+            self[K::cast_from_ffi(k)] = value;
+        }
+        Ok(())
+    }
+
+    fn set_default_lua<'lua>(
+        &mut self,
+        lua: &'lua mlua::Lua,
+        value: mlua::Value<'lua>,
+    ) -> Result<()> {
+        self.default = FromToLua::cast_from_lua(value, lua)?;
+        Ok(())
+    }
+
+    fn map_lua<'lua>(
+        &mut self,
+        keys: &[u64],
+        lua: &'lua mlua::Lua,
+        f: &mlua::Function<'lua>,
+    ) -> Result<()> {
+        for k in keys.iter_cpy().map(K::cast_from_ffi) {
+            let new_value: mlua::Value = f.call((self[k].cast_to_lua(lua), k.cast_to_lua(lua)))?;
+            self[k] = FromToLua::cast_from_lua(new_value, lua)?;
+        }
+        Ok(())
+    }
+
+    fn copy_from_dyn(
+        &mut self,
+        keys: &[u64],
+        other: &dyn DynChannel,
+        other_keys: &[u64],
+    ) -> Result<()> {
+        let other = other.as_any().downcast_ref::<Self>().ok_or_else(|| {
+            anyhow!(
+                "Can't copy from a channel of a different type. Expected {} -> {}",
+                K::name(),
+                V::name()
+            )
+        })?;
+        if keys.len() != other_keys.len() {
+            bail!(
+                "Can't copy between channels of meshes with a different number of elements: \
+                 {} and {}",
+                keys.len(),
+                other_keys.len()
+            );
+        }
+        for (k, other_k) in keys.iter_cpy().zip(other_keys.iter_cpy()) {
+            self[K::cast_from_ffi(k)] = other[K::cast_from_ffi(other_k)];
+        }
+        Ok(())
+    }
+
     fn merge_with_dyn(
         &mut self,
         other: &dyn DynChannel,
@@ -474,17 +762,121 @@ impl<K: ChannelKey, V: ChannelValue> DynChannel for Channel<K, V> {
             )
         }
     }
+
+    fn stored_keys(&self) -> Vec<slotmap::KeyData> {
+        self.inner.keys().map(|k| k.data()).collect()
+    }
+
+    fn remove_stored(&mut self, key: slotmap::KeyData) {
+        self.inner.remove(K::from(key));
+    }
+
+    fn stored_len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        super::slot_bytes::<V>(self.inner.len())
+    }
+
+    fn backfill_dyn(&mut self, keys: &[slotmap::KeyData]) {
+        for k in keys.iter_cpy().map(K::from) {
+            if !self.inner.contains_key(k) {
+                self.inner.insert(k, self.default);
+            }
+        }
+    }
+
+    fn reset_dyn(&mut self, dst: slotmap::KeyData) {
+        self[K::from(dst)] = self.default;
+    }
+
+    fn interpolate_dyn(
+        &mut self,
+        dst: slotmap::KeyData,
+        a: slotmap::KeyData,
+        b: slotmap::KeyData,
+        t: f32,
+    ) {
+        self[K::from(dst)] = self[K::from(a)].interpolate(self[K::from(b)], t);
+    }
+
+    fn interpolate_weighted_dyn(
+        &mut self,
+        dst: slotmap::KeyData,
+        sources: &[(slotmap::KeyData, f32)],
+    ) {
+        let values: SVec<(V, f32)> = sources
+            .iter()
+            .map(|(k, w)| (self[K::from(*k)], *w))
+            .collect();
+        self[K::from(dst)] = V::weighted_average(&values);
+    }
+
+    fn interpolate_from_dyn(
+        &mut self,
+        keys: &[slotmap::KeyData],
+        other: &dyn DynChannel,
+        sources: &[SVec<(slotmap::KeyData, f32)>],
+    ) -> Result<()> {
+        let other = other.as_any().downcast_ref::<Self>().ok_or_else(|| {
+            anyhow!(
+                "Can't interpolate from a channel of a different type. Expected {} -> {}",
+                K::name(),
+                V::name()
+            )
+        })?;
+        if keys.len() != sources.len() {
+            bail!(
+                "Got {} keys to interpolate, but {} lists of sources",
+                keys.len(),
+                sources.len()
+            );
+        }
+        for (k, sources) in keys.iter_cpy().zip(sources) {
+            let values: SVec<(V, f32)> = sources
+                .iter()
+                .map(|(other_k, w)| (other[K::from(*other_k)], *w))
+                .collect();
+            self[K::from(k)] = V::weighted_average(&values);
+        }
+        Ok(())
+    }
 }
 
 impl<K: ChannelKey, V: ChannelValue> ChannelGroup<K, V> {
     /// Creates a new channel with a given `name`. If the channel with `name`
     /// already exists in the group, this operation is ignored.
-    pub fn ensure_channel(&mut self, name: &str) -> ChannelId<K, V> {
-        match self.channel_names.get_by_left(name) {
+    pub fn ensure_channel(&mut self, name: impl Into<ChannelName>) -> ChannelId<K, V> {
+        let name = name.into();
+        match self.channel_names.get_by_left(&name) {
             Some(id) => *id,
             None => {
                 let ch_id = ChannelId::new(self.channels.insert(Default::default()));
-                self.channel_names.insert(name.into(), ch_id);
+                self.channel_names.insert(name, ch_id);
+                ch_id
+            }
+        }
+    }
+
+    /// Same as `ensure_channel`, but when the channel is created, it returns
+    /// `default` for the keys that were never set. This includes the elements
+    /// added to the mesh later on. An existing channel is left as is.
+    pub fn ensure_channel_with_default(
+        &mut self,
+        name: impl Into<ChannelName>,
+        default: V,
+    ) -> ChannelId<K, V> {
+        let name = name.into();
+        match self.channel_names.get_by_left(&name) {
+            Some(id) => *id,
+            None => {
+                let channel = Channel::new_with_default(default);
+                let ch_id = ChannelId::new(
+                    self.channels
+                        .insert(RefCounted::new(InteriorMutable::new(channel))),
+                );
+                self.channel_names.insert(name, ch_id);
                 ch_id
             }
         }
@@ -492,8 +884,9 @@ impl<K: ChannelKey, V: ChannelValue> ChannelGroup<K, V> {
 
     /// Creates a new channel with a given `name`. If the channel with `name`
     /// already exists, returns an error.
-    pub fn create_channel(&mut self, name: &str) -> Result<ChannelId<K, V>> {
-        if self.channel_names.contains_left(name) {
+    pub fn create_channel(&mut self, name: impl Into<ChannelName>) -> Result<ChannelId<K, V>> {
+        let name = name.into();
+        if self.channel_names.contains_left(&name) {
             bail!("The channel named {name} already exists in mesh");
         } else {
             Ok(self.ensure_channel(name))
@@ -518,14 +911,14 @@ impl<K: ChannelKey, V: ChannelValue> ChannelGroup<K, V> {
 
     /// Returns the channel id for a channel with given `name`, or `None` if it
     /// doesn't exist.
-    pub fn channel_id(&self, name: &str) -> Option<ChannelId<K, V>> {
-        self.channel_names.get_by_left(name).copied()
+    pub fn channel_id(&self, name: impl Into<ChannelName>) -> Option<ChannelId<K, V>> {
+        self.channel_names.get_by_left(&name.into()).copied()
     }
 
     /// Returns the channel name for a given channel `id`, or `None` if it
     /// doesn't exist
-    pub fn channel_name(&self, id: ChannelId<K, V>) -> Option<&str> {
-        self.channel_names.get_by_right(&id).map(|x| x.as_str())
+    pub fn channel_name(&self, id: ChannelId<K, V>) -> Option<ChannelName> {
+        self.channel_names.get_by_right(&id).copied()
     }
 
     /// Accesses a channel immutably. The operation may fail if that channel is
@@ -559,19 +952,26 @@ pub trait DynChannelGroup: Any + Debug + dyn_clone::DynClone + MaybeSync {
     /// Same as `as_any`, but for a mutable reference instead.
     fn as_any_mut(&mut self) -> &mut dyn Any;
     /// Same as `ensure_channel`, but with erased types.
-    fn ensure_channel_dyn(&mut self, name: &str) -> RawChannelId;
+    fn ensure_channel_dyn(&mut self, name: ChannelName) -> RawChannelId;
     /// Same as `read_channel`, but with erased types.
     fn read_channel_dyn(&self, raw_id: RawChannelId) -> BorrowedRef<dyn DynChannel>;
     /// Same as `write_channel`, but with erased types.
     fn write_channel_dyn(&self, raw_id: RawChannelId) -> MutableRef<dyn DynChannel>;
     /// Same as `channel_id`, but with erased types.
-    fn channel_id_dyn(&self, name: &str) -> Option<RawChannelId>;
+    fn channel_id_dyn(&self, name: ChannelName) -> Option<RawChannelId>;
     /// Returns a shared ownership borrow of the channel. This uses reference
     /// counting and allows storing the channel as a long-lived value. This can
     /// be used to hand channels over to the Lua runtime.
     fn channel_rc_dyn(&self, raw_id: RawChannelId) -> RefCounted<InteriorMutable<dyn DynChannel>>;
     /// Returns the names of the channels present in this group
-    fn channel_names(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+    fn channel_names(&self) -> Box<dyn Iterator<Item = ChannelName> + '_>;
+    /// Returns the names and ids of the channels present in this group. Used
+    /// to go over all the channels without looking up their ids.
+    fn channel_ids_dyn(&self) -> Box<dyn Iterator<Item = (ChannelName, RawChannelId)> + '_>;
+    /// Same as `clone`, but only the channels named in `private` get their
+    /// contents copied. The other channels are shared with this group, so
+    /// writing to them in either group changes both.
+    fn clone_sharing(&self, private: &[ChannelName]) -> Box<dyn DynChannelGroup>;
 }
 
 impl<K: ChannelKey, V: ChannelValue> Clone for ChannelGroup<K, V> {
@@ -605,7 +1005,7 @@ impl<K: ChannelKey, V: ChannelValue> DynChannelGroup for ChannelGroup<K, V> {
         for (name, id) in self.channel_names.iter() {
             let ch = self.read_channel(*id).unwrap();
             result.insert(
-                name.into(),
+                name.to_string(),
                 keys.iter()
                     .map(|k| ch[K::from(*k)])
                     .map(|x| x.introspect())
@@ -621,7 +1021,7 @@ impl<K: ChannelKey, V: ChannelValue> DynChannelGroup for ChannelGroup<K, V> {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
-    fn ensure_channel_dyn(&mut self, name: &str) -> RawChannelId {
+    fn ensure_channel_dyn(&mut self, name: ChannelName) -> RawChannelId {
         self.ensure_channel(name).raw
     }
     fn read_channel_dyn(&self, raw_id: RawChannelId) -> BorrowedRef<dyn DynChannel> {
@@ -642,12 +1042,31 @@ impl<K: ChannelKey, V: ChannelValue> DynChannelGroup for ChannelGroup<K, V> {
         }
         convert_channel(RefCounted::clone(&self.channels[raw_id]))
     }
-    fn channel_id_dyn(&self, name: &str) -> Option<RawChannelId> {
-        self.channel_names.get_by_left(name).map(|x| x.raw)
+    fn channel_id_dyn(&self, name: ChannelName) -> Option<RawChannelId> {
+        self.channel_names.get_by_left(&name).map(|x| x.raw)
+    }
+
+    fn channel_names(&self) -> Box<dyn Iterator<Item = ChannelName> + '_> {
+        Box::new(self.channel_names.left_values().copied())
     }
 
-    fn channel_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
-        Box::new(self.channel_names.iter().map(|(l, _)| l.as_str()))
+    fn channel_ids_dyn(&self) -> Box<dyn Iterator<Item = (ChannelName, RawChannelId)> + '_> {
+        Box::new(self.channel_names.iter().map(|(name, id)| (*name, id.raw)))
+    }
+
+    fn clone_sharing(&self, private: &[ChannelName]) -> Box<dyn DynChannelGroup> {
+        // Cloning the slotmap only clones the Rcs
+        let mut channels = self.channels.clone();
+        for name in private {
+            if let Some(id) = self.channel_names.get_by_left(name) {
+                let ch_inner: Channel<K, V> = self.channels[id.raw].borrow().clone();
+                channels[id.raw] = RefCounted::new(InteriorMutable::new(ch_inner));
+            }
+        }
+        Box::new(Self {
+            channel_names: self.channel_names.clone(),
+            channels,
+        })
     }
 }
 
@@ -701,15 +1120,26 @@ impl MeshChannels {
     /// Calls `ensure_channel` for the channel group with key and value type
     pub fn ensure_channel<K: ChannelKey, V: ChannelValue>(
         &mut self,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> ChannelId<K, V> {
         self.group_or_default().ensure_channel(name)
     }
 
+    /// Calls `ensure_channel_with_default` for the channel group with key and
+    /// value type
+    pub fn ensure_channel_with_default<K: ChannelKey, V: ChannelValue>(
+        &mut self,
+        name: impl Into<ChannelName>,
+        default: V,
+    ) -> ChannelId<K, V> {
+        self.group_or_default()
+            .ensure_channel_with_default(name, default)
+    }
+
     /// Calls `create_channel` for the channel group with key and value type
     pub fn create_channel<K: ChannelKey, V: ChannelValue>(
         &mut self,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<ChannelId<K, V>> {
         self.group_or_default().create_channel(name)
     }
@@ -734,8 +1164,9 @@ impl MeshChannels {
     /// the channel name instead of its id.
     pub fn read_channel_by_name<K: ChannelKey, V: ChannelValue>(
         &self,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<BorrowedRef<Channel<K, V>>> {
+        let name = name.into();
         let group = self.group()?;
         group.read_channel(
             group
@@ -756,8 +1187,9 @@ impl MeshChannels {
     /// the channel name instead of its id.
     pub fn write_channel_by_name<K: ChannelKey, V: ChannelValue>(
         &self,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<MutableRef<Channel<K, V>>> {
+        let name = name.into();
         let group = self.group()?;
         group.write_channel(
             group
@@ -792,12 +1224,15 @@ impl MeshChannels {
             VertexId, Vec3;
             VertexId, f32;
             VertexId, bool;
+            VertexId, Tag;
             FaceId, Vec3;
             FaceId, f32;
             FaceId, bool;
+            FaceId, Tag;
             HalfEdgeId, Vec3;
             HalfEdgeId, f32;
-            HalfEdgeId, bool
+            HalfEdgeId, bool;
+            HalfEdgeId, Tag
         }
     }
 
@@ -807,10 +1242,10 @@ impl MeshChannels {
         &mut self,
         kty: ChannelKeyType,
         vty: ChannelValueType,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> RawChannelId {
         let group = self.ensure_group_dyn(kty, vty);
-        group.ensure_channel_dyn(name)
+        group.ensure_channel_dyn(name.into())
     }
 
     /// Calls `read_channel` for a group with dynamic key and value
@@ -849,14 +1284,14 @@ impl MeshChannels {
         &self,
         kty: ChannelKeyType,
         vty: ChannelValueType,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<BorrowedRef<dyn DynChannel>> {
         let group = self
             .channels
             .get(&(kty, vty))
             .ok_or_else(|| anyhow!("Channel type does not exist"))?;
         let raw_id = group
-            .channel_id_dyn(name)
+            .channel_id_dyn(name.into())
             .ok_or_else(|| anyhow!("Channel value does not exist"))?;
         Ok(group.read_channel_dyn(raw_id))
     }
@@ -867,14 +1302,14 @@ impl MeshChannels {
         &self,
         kty: ChannelKeyType,
         vty: ChannelValueType,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<MutableRef<dyn DynChannel>> {
         let group = self
             .channels
             .get(&(kty, vty))
             .ok_or_else(|| anyhow!("Channel type does not exist"))?;
         let raw_id = group
-            .channel_id_dyn(name)
+            .channel_id_dyn(name.into())
             .ok_or_else(|| anyhow!("Channel value does not exist"))?;
         Ok(group.write_channel_dyn(raw_id))
     }
@@ -885,14 +1320,14 @@ impl MeshChannels {
         &self,
         kty: ChannelKeyType,
         vty: ChannelValueType,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Result<RefCounted<InteriorMutable<dyn DynChannel>>> {
         let group = self
             .channels
             .get(&(kty, vty))
             .ok_or_else(|| anyhow!("Channel type does not exist"))?;
         let raw_id = group
-            .channel_id_dyn(name)
+            .channel_id_dyn(name.into())
             .ok_or_else(|| anyhow!("Channel value does not exist"))?;
         Ok(group.channel_rc_dyn(raw_id))
     }
@@ -900,7 +1335,7 @@ impl MeshChannels {
     /// Calls `channel_id` for the channel group with key and value type
     pub fn channel_id<K: ChannelKey, V: ChannelValue>(
         &self,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Option<ChannelId<K, V>> {
         self.group().ok()?.channel_id(name)
     }
@@ -910,19 +1345,148 @@ impl MeshChannels {
         &self,
         kty: ChannelKeyType,
         vty: ChannelValueType,
-        name: &str,
+        name: impl Into<ChannelName>,
     ) -> Option<RawChannelId> {
-        self.channels.get(&(kty, vty))?.channel_id_dyn(name)
+        self.channels.get(&(kty, vty))?.channel_id_dyn(name.into())
     }
 
     /// Calls `channel_name` for the channel group with key and value type
     pub fn channel_name<K: ChannelKey, V: ChannelValue>(
         &self,
         ch_id: ChannelId<K, V>,
-    ) -> Option<&str> {
+    ) -> Option<ChannelName> {
         self.group().ok()?.channel_name(ch_id)
     }
 
+    /// Returns a copy of these channels where only the channels named in
+    /// `private` have their contents copied, and the rest are shared with
+    /// `self`. See [`HalfEdgeMesh::clone_for_deform`].
+    pub fn clone_sharing(&self, private: &[ChannelName]) -> MeshChannels {
+        MeshChannels {
+            channels: self
+                .channels
+                .iter()
+                .map(|(key, group)| (*key, group.clone_sharing(private)))
+                .collect(),
+        }
+    }
+
+    /// Returns an estimate of the memory taken by the values stored in all
+    /// the channels, in bytes.
+    pub fn estimated_bytes(&self) -> usize {
+        self.iter_channels_dyn()
+            .filter_map(|(kty, vty, name)| {
+                self.dyn_read_channel_by_name(kty, vty, name)
+                    .ok()
+                    .map(|ch| ch.estimated_bytes())
+            })
+            .sum()
+    }
+
+    /// Iterates the key type, value type and name of every channel in this
+    /// `MeshChannels`.
+    pub fn iter_channels_dyn(
+        &self,
+    ) -> impl Iterator<Item = (ChannelKeyType, ChannelValueType, ChannelName)> + '_ {
+        self.channels.iter().flat_map(|((kty, vty), group)| {
+            group.channel_names().map(move |name| (*kty, *vty, name))
+        })
+    }
+
+    /// For every channel with key type `K`, sets the value at `dst` to the
+    /// interpolation between the values at `a` and `b`, with factor `t`.
+    ///
+    /// This will panic if any of the channels is currently borrowed.
+    pub fn interpolate_values<K: ChannelKey>(&self, dst: K, a: K, b: K, t: f32) {
+        for ((kty, _), group) in self.channels.iter() {
+            if *kty != K::key_type() {
+                continue;
+            }
+            for (_, id) in group.channel_ids_dyn() {
+                group
+                    .write_channel_dyn(id)
+                    .interpolate_dyn(dst.data(), a.data(), b.data(), t);
+            }
+        }
+    }
+
+    /// For every channel with key type `K`, copies the value at `src` to
+    /// `dst`.
+    ///
+    /// This will panic if any of the channels is currently borrowed.
+    pub fn copy_values<K: ChannelKey>(&self, dst: K, src: K) {
+        self.interpolate_values(dst, src, src, 0.0)
+    }
+
+    /// For every channel with key type `K`, sets the value at `dst` to the
+    /// weighted average of the values at the `(key, weight)` pairs in
+    /// `sources`.
+    ///
+    /// This will panic if any of the channels is currently borrowed.
+    pub fn interpolate_weighted<K: ChannelKey>(&self, dst: K, sources: &[(K, f32)]) {
+        let sources: SVec<_> = sources.iter().map(|(k, w)| (k.data(), *w)).collect();
+        for ((kty, _), group) in self.channels.iter() {
+            if *kty != K::key_type() {
+                continue;
+            }
+            for (_, id) in group.channel_ids_dyn() {
+                group
+                    .write_channel_dyn(id)
+                    .interpolate_weighted_dyn(dst.data(), &sources);
+            }
+        }
+    }
+
+    /// For every channel with key type `K` in `other`, sets the value for each
+    /// of the `keys` to the weighted average of the values of `other` at the
+    /// `(key, weight)` pairs in the same position of `sources`. This is used
+    /// when the elements of a mesh are built from the ones of `other`, and
+    /// creates the channels that are missing.
+    pub fn interpolate_from<K: ChannelKey>(
+        &mut self,
+        other: &Self,
+        keys: &[K],
+        sources: &[SVec<(K, f32)>],
+    ) -> Result<()> {
+        let keys = keys.iter().map(|k| k.data()).collect_vec();
+        let sources = sources
+            .iter()
+            .map(|s| s.iter().map(|(k, w)| (k.data(), *w)).collect())
+            .collect_vec();
+        for ((kty, vty), other_group) in other.channels.iter() {
+            if *kty != K::key_type() {
+                continue;
+            }
+            let self_group = self.ensure_group_dyn(*kty, *vty);
+            for (ch_name, other_id) in other_group.channel_ids_dyn() {
+                let self_id = self_group.ensure_channel_dyn(ch_name);
+                let other_ch = other_group.read_channel_dyn(other_id);
+                self_group.write_channel_dyn(self_id).interpolate_from_dyn(
+                    &keys,
+                    other_ch.deref(),
+                    &sources,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether there is a channel with the given key type and `name`,
+    /// for any value type.
+    pub fn has_channel(&self, kty: ChannelKeyType, name: impl Into<ChannelName>) -> bool {
+        let name = name.into();
+        self.channels
+            .iter()
+            .any(|((k, _), group)| *k == kty && group.channel_id_dyn(name).is_some())
+    }
+
+    /// Returns whether there is any channel with the given key type.
+    pub fn has_channels(&self, kty: ChannelKeyType) -> bool {
+        self.channels
+            .iter()
+            .any(|((k, _), group)| *k == kty && group.channel_names().next().is_some())
+    }
+
     /// Used to inspect the contents of this `MeshChannels`, for UI display
     pub fn introspect(
         &self,
@@ -944,10 +1508,7 @@ impl MeshChannels {
         // - Any channels present in B, but not present in A will need to be copied.
         for ((kty, vty), other_group) in other.channels.iter() {
             let self_group = self.ensure_group_dyn(*kty, *vty);
-            for ch_name in other_group.channel_names() {
-                let other_id = other_group
-                    .channel_id_dyn(ch_name)
-                    .expect("We know it exists because we're iterating the channel names");
+            for (ch_name, other_id) in other_group.channel_ids_dyn() {
                 let self_id = self_group.ensure_channel_dyn(ch_name);
 
                 let other_ch = other_group.read_channel_dyn(other_id);
@@ -963,7 +1524,7 @@ impl MeshChannels {
     /// used. Returns the id of the channel that was created.
     pub fn replace_or_create_channel<K: ChannelKey, V: ChannelValue>(
         &mut self,
-        name: &str,
+        name: impl Into<ChannelName>,
         ch: Channel<K, V>,
     ) -> ChannelId<K, V> {
         let ch_id = self.group_or_default().ensure_channel(name);
@@ -974,6 +1535,98 @@ impl MeshChannels {
     }
 }
 
+/// How a [`ChannelWriteBatch`] computes the value of an element.
+#[derive(Clone, Debug)]
+enum BatchSource {
+    Default,
+    Interpolate(slotmap::KeyData, slotmap::KeyData, f32),
+    Weighted(SVec<(slotmap::KeyData, f32)>),
+}
+
+/// A list of values to set on every channel with key type `K`, usually for
+/// the elements created by an op. Each value is computed from the values of
+/// other elements, or is the default value of the channel.
+///
+/// Values are set in the order they were added, so a value can be computed
+/// from the values set before it. Applying the batch borrows each channel once,
+/// which is much faster than setting the values one at a time with methods
+/// like [`MeshChannels::interpolate_values`].
+#[derive(Clone, Debug)]
+pub struct ChannelWriteBatch<K: ChannelKey> {
+    entries: Vec<(slotmap::KeyData, BatchSource)>,
+    _phantom: PhantomData<K>,
+}
+
+impl<K: ChannelKey> Default for ChannelWriteBatch<K> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<K: ChannelKey> ChannelWriteBatch<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value at `dst` to the value at `src`.
+    pub fn copy(&mut self, dst: K, src: K) {
+        self.interpolate(dst, src, src, 0.0)
+    }
+
+    /// Sets the value at `dst` to the interpolation between the values at `a`
+    /// and `b`, with factor `t`.
+    pub fn interpolate(&mut self, dst: K, a: K, b: K, t: f32) {
+        self.entries
+            .push((dst.data(), BatchSource::Interpolate(a.data(), b.data(), t)));
+    }
+
+    /// Sets the value at `dst` to the weighted average of the values at the
+    /// `(key, weight)` pairs in `sources`.
+    pub fn interpolate_weighted(&mut self, dst: K, sources: &[(K, f32)]) {
+        let sources = sources.iter().map(|(k, w)| (k.data(), *w)).collect();
+        self.entries
+            .push((dst.data(), BatchSource::Weighted(sources)));
+    }
+
+    /// Sets the value at `dst` to the default value of each channel.
+    pub fn reset(&mut self, dst: K) {
+        self.entries.push((dst.data(), BatchSource::Default));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sets the values on every channel of `channels` with key type `K`.
+    ///
+    /// This will panic if any of the channels is currently borrowed.
+    pub fn apply(self, channels: &MeshChannels) {
+        if self.entries.is_empty() {
+            return;
+        }
+        for ((kty, _), group) in channels.channels.iter() {
+            if *kty != K::key_type() {
+                continue;
+            }
+            for (_, id) in group.channel_ids_dyn() {
+                let mut ch = group.write_channel_dyn(id);
+                for (dst, source) in &self.entries {
+                    match source {
+                        BatchSource::Default => ch.reset_dyn(*dst),
+                        BatchSource::Interpolate(a, b, t) => ch.interpolate_dyn(*dst, *a, *b, *t),
+                        BatchSource::Weighted(sources) => {
+                            ch.interpolate_weighted_dyn(*dst, sources)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl DefaultChannels {
     pub fn with_position(channels: &mut MeshChannels) -> Self {
         let position = channels.ensure_channel::<VertexId, Vec3>("position");
@@ -1104,6 +1757,59 @@ mod test {
         drop(dyn_pos);
     }
 
+    #[test]
+    pub fn test_interpolate_weighted() {
+        let mut halfedges: slotmap::SlotMap<HalfEdgeId, ()> = slotmap::SlotMap::with_key();
+        let (h1, h2, h3, h4) = (
+            halfedges.insert(()),
+            halfedges.insert(()),
+            halfedges.insert(()),
+            halfedges.insert(()),
+        );
+        let mut mesh_channels = MeshChannels::default();
+        let uv = mesh_channels.ensure_channel::<HalfEdgeId, Vec3>("uv");
+        let seam = mesh_channels.ensure_channel::<HalfEdgeId, bool>("seam");
+        {
+            let mut uvs = mesh_channels.write_channel(uv).unwrap();
+            uvs[h1] = Vec3::ZERO;
+            uvs[h2] = Vec3::X;
+            uvs[h3] = Vec3::Y;
+            let mut seams = mesh_channels.write_channel(seam).unwrap();
+            seams[h2] = true;
+            seams[h3] = true;
+        }
+
+        mesh_channels.interpolate_weighted(h4, &[(h1, 2.0), (h2, 1.0), (h3, 1.0)]);
+        assert_eq!(
+            mesh_channels.read_channel(uv).unwrap()[h4],
+            Vec3::new(0.25, 0.25, 0.0)
+        );
+        // A tie between both values
+        assert!(!mesh_channels.read_channel(seam).unwrap()[h4]);
+
+        // Interpolating from other channels creates the missing ones
+        let mut other = MeshChannels::default();
+        other
+            .interpolate_from(
+                &mesh_channels,
+                &[h1],
+                &[smallvec::smallvec![(h2, 1.0), (h3, 3.0)]],
+            )
+            .unwrap();
+        assert_eq!(
+            other
+                .read_channel_by_name::<HalfEdgeId, Vec3>("uv")
+                .unwrap()[h1],
+            Vec3::new(0.25, 0.75, 0.0)
+        );
+        assert!(
+            other
+                .read_channel_by_name::<HalfEdgeId, bool>("seam")
+                .unwrap()[h1]
+        );
+        assert!(!other.has_channels(ChannelKeyType::VertexId));
+    }
+
     #[test]
     pub fn test_ensure_channel() {
         let mut mesh_channels = MeshChannels::default();
@@ -1116,6 +1822,102 @@ mod test {
             mesh_channels.ensure_channel::<VertexId, Vec3>("position")
         );
     }
+
+    /// Creates a channel named "test" with key type `K` in a box mesh, with a
+    /// `default` value, sets a single value to `other` and then grows the
+    /// mesh. Every other element, old or new, must have the default value.
+    fn check_ensure_with_default<K: ChannelKey, V: ChannelValue + PartialEq>(
+        default: V,
+        other: V,
+        elements: impl Fn(&MeshConnectivity) -> Vec<K>,
+    ) {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert!(!mesh.channels.has_channel(K::key_type(), "test"));
+        let ch_id = mesh
+            .channels
+            .ensure_channel_with_default::<K, V>("test", default);
+        assert!(mesh.channels.has_channel(K::key_type(), "test"));
+        // Ensuring an existing channel doesn't change its default
+        assert_eq!(
+            ch_id,
+            mesh.channels
+                .ensure_channel_with_default::<K, V>("test", other)
+        );
+
+        let first = elements(&mesh.read_connectivity())[0];
+        mesh.channels.write_channel(ch_id).unwrap()[first] = other;
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+
+        let elements = elements(&mesh.read_connectivity());
+        let channel = mesh.channels.read_channel(ch_id).unwrap();
+        for k in elements.iter_cpy() {
+            let expected = if k == first { other } else { default };
+            assert_eq!(channel[k], expected);
+        }
+        let info = mesh
+            .channel_infos()
+            .into_iter()
+            .find(|info| info.name == "test")
+            .unwrap();
+        assert_eq!(
+            info,
+            ChannelInfo {
+                name: "test".into(),
+                key_type: K::key_type(),
+                value_type: V::value_type(),
+                len: elements.len(),
+            }
+        );
+    }
+
+    fn check_all_values<K: ChannelKey>(elements: impl Fn(&MeshConnectivity) -> Vec<K> + Copy) {
+        check_ensure_with_default::<K, f32>(0.5, 2.0, elements);
+        check_ensure_with_default::<K, Vec3>(Vec3::ONE, Vec3::Z, elements);
+        check_ensure_with_default::<K, bool>(true, false, elements);
+        check_ensure_with_default::<K, Tag>(Tag::new("a"), Tag::new("b"), elements);
+    }
+
+    #[test]
+    pub fn test_ensure_channel_with_default() {
+        check_all_values(|conn| conn.iter_vertices().map(|(v, _)| v).collect());
+        check_all_values(|conn| conn.iter_faces().map(|(f, _)| f).collect());
+        check_all_values(|conn| conn.iter_halfedges().map(|(h, _)| h).collect());
+    }
+
+    #[test]
+    pub fn test_channel_infos() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        mesh.channels.ensure_channel::<FaceId, bool>("top");
+        mesh.channels.ensure_channel::<HalfEdgeId, f32>("crease");
+        let infos = mesh
+            .channel_infos()
+            .into_iter()
+            .map(|info| (info.key_type, info.value_type, info.name, info.len))
+            .collect_vec();
+        assert!(infos.contains(&(
+            ChannelKeyType::VertexId,
+            ChannelValueType::Vec3,
+            "position".into(),
+            8
+        )));
+        assert!(infos.contains(&(
+            ChannelKeyType::FaceId,
+            ChannelValueType::bool,
+            "top".into(),
+            6
+        )));
+        assert!(infos.contains(&(
+            ChannelKeyType::HalfEdgeId,
+            ChannelValueType::f32,
+            "crease".into(),
+            24
+        )));
+        // Sorted by key type, then value type and name
+        assert!(infos.windows(2).all(|w| w[0] <= w[1]));
+
+        assert!(mesh.channels.has_channel(ChannelKeyType::FaceId, "top"));
+        assert!(!mesh.channels.has_channel(ChannelKeyType::VertexId, "top"));
+    }
 }
 
 // ------------- Boilerplate zone ------------
@@ -8,7 +8,9 @@ use atomic_float::AtomicF32;
 use nonmax::NonMaxU32;
 use std::sync::atomic::Ordering;
 
+use crate::mesh::halfedge::id_remap::{ElementRemap, IdRemap};
 use crate::prelude::*;
+use crate::progress::{report_progress, ProgressSink};
 
 /// A HalfEdge representation storing the halfedge pointers in contiguous
 /// arrays. For each of the main arrays, at position `h` there is the data for
@@ -346,6 +348,9 @@ impl<const Subdivided: bool> CompactMesh<Subdivided> {
     /// Clark algorithm is performed, otherwise linear subdivision is performed.
     #[profiling::function]
     pub fn subdivide(&self, catmull_clark: bool) -> CompactMesh<true> {
+        #[cfg(not(feature = "parallel"))]
+        use self::serial_prelude::*;
+        #[cfg(feature = "parallel")]
         use rayon::prelude::*;
 
         // Compute the counts for the new mesh
@@ -513,14 +518,239 @@ impl<const Subdivided: bool> CompactMesh<Subdivided> {
 
     #[profiling::function]
     pub fn subdivide_multi(&self, iterations: usize, catmull_clark: bool) -> CompactMesh<true> {
+        self.subdivide_multi_with_progress(iterations, catmull_clark, None)
+            .expect("Subdivision can only fail when cancelled")
+    }
+
+    /// Same as [`CompactMesh::subdivide_multi`], but reports progress to the
+    /// given `sink` after every iteration, and stops early with a
+    /// [`Cancelled`](crate::progress::Cancelled) error when it is cancelled.
+    #[profiling::function]
+    pub fn subdivide_multi_with_progress(
+        &self,
+        iterations: usize,
+        catmull_clark: bool,
+        sink: Option<&dyn ProgressSink>,
+    ) -> Result<CompactMesh<true>> {
+        // Every iteration quadruples the number of faces, and so does the cost
+        // of the next iteration. Progress is weighted accordingly.
+        let total_cost: f32 = (0..iterations).map(|i| 4f32.powi(i as i32)).sum();
+        let mut done_cost = 1.0;
+
         let mut mesh = self.subdivide(catmull_clark);
-        for _ in 0..(iterations - 1) {
+        report_progress(sink, done_cost / total_cost)?;
+        for i in 1..iterations {
             mesh = mesh.subdivide(catmull_clark);
+            done_cost += 4f32.powi(i as i32);
+            report_progress(sink, done_cost / total_cost)?;
         }
-        mesh
+        Ok(mesh)
+    }
+
+    /// Returns how the ids of `conn`, the mesh this compact mesh was built
+    /// from, map to the ids of the mesh returned after subdividing it
+    /// `iterations` times and converting the result back with
+    /// [`CompactMesh::to_halfedge`].
+    ///
+    /// Vertices keep their ids, each face is split into one quad per corner,
+    /// and each halfedge is split in two halves. Boundary halfedges are
+    /// reported as removed, since they don't exist in the compact mesh.
+    pub fn subdivision_remap(&self, conn: &MeshConnectivity, iterations: usize) -> IdRemap {
+        let mut compact_idx = 0;
+        let to_compact = IdRemap {
+            vertices: ElementRemap::identity(self.counts.num_vertices),
+            faces: ElementRemap::identity(self.counts.num_faces),
+            halfedges: ElementRemap::new(
+                conn.iter_halfedges()
+                    .map(|(_, h)| {
+                        if h.face.is_some() {
+                            compact_idx += 1;
+                            smallvec::smallvec![compact_idx - 1]
+                        } else {
+                            SVec::new()
+                        }
+                    })
+                    .collect(),
+            ),
+        };
+
+        let mut remap = to_compact.then(&refinement_remap(
+            self.counts,
+            |h| self.get_next(h),
+            |h| self.get_face(h),
+        ));
+        let mut counts = self.counts.subdiv();
+        for _ in 1..iterations {
+            // After the first iteration, next and face pointers follow the
+            // same analytical expressions as `get_next` and `get_face`.
+            remap = remap.then(&refinement_remap(
+                counts,
+                |h| if h % 4 == 3 { h - 3 } else { h + 1 },
+                |h| h / 4,
+            ));
+            counts = counts.subdiv();
+        }
+        remap
+    }
+
+    /// Returns, for each halfedge of the mesh returned after subdividing this
+    /// one `iterations` times, the weights of the halfedges of this mesh that
+    /// its corner values are interpolated from.
+    ///
+    /// Corner values can change across edges, like UVs at a seam, so they
+    /// always follow the linear rule, using the same weights as positions in
+    /// linear subdivision: Original corners keep their value, corners at edge
+    /// points are the average of the corners at both ends of the edge, and
+    /// corners at face points are the average of all the corners of the face.
+    pub fn subdivision_corner_weights(&self, iterations: usize) -> Vec<SVec<(u32, f32)>> {
+        let mut weights =
+            refinement_corner_weights(self.counts, |h| self.get_next(h), |h| self.get_prev(h));
+        let mut counts = self.counts.subdiv();
+        for _ in 1..iterations {
+            let step = refinement_corner_weights(
+                counts,
+                |h| if h % 4 == 3 { h - 3 } else { h + 1 },
+                |h| if h % 4 == 0 { h + 3 } else { h - 1 },
+            );
+            weights = step
+                .iter()
+                .map(|sources| {
+                    let mut composed = SVec::<(u32, f32)>::new();
+                    for (h, w) in sources {
+                        for (h_0, w_0) in &weights[*h as usize] {
+                            match composed.iter_mut().find(|(c, _)| c == h_0) {
+                                Some((_, acc)) => *acc += w * w_0,
+                                None => composed.push((*h_0, w * w_0)),
+                            }
+                        }
+                    }
+                    composed
+                })
+                .collect();
+            counts = counts.subdiv();
+        }
+        weights
     }
 }
 
+/// Returns the corner weights for one iteration of the halfedge refinement
+/// rule on a mesh with the given `counts`. See
+/// [`CompactMesh::subdivision_corner_weights`].
+fn refinement_corner_weights(
+    counts: MeshCounts,
+    next: impl Fn(usize) -> usize,
+    prev: impl Fn(usize) -> usize,
+) -> Vec<SVec<(u32, f32)>> {
+    let mut weights = Vec::with_capacity(4 * counts.num_halfedges);
+    for h in 0..counts.num_halfedges {
+        let mut face = SVec::<u32>::new();
+        let mut hh = h;
+        loop {
+            face.push(hh as u32);
+            hh = next(hh);
+            if hh == h || face.len() > MAX_LOOP_ITERATIONS {
+                break;
+            }
+        }
+        let m = face.len() as f32;
+        let h32 = h as u32;
+        // The corners of the new face `h`, in the same order as the vertex
+        // refinement rule: The original vertex, the edge point of `h`, the
+        // face point and the edge point of the previous halfedge.
+        weights.push(smallvec::smallvec![(h32, 1.0)]);
+        weights.push(smallvec::smallvec![(h32, 0.5), (next(h) as u32, 0.5)]);
+        weights.push(face.iter().map(|f| (*f, 1.0 / m)).collect());
+        weights.push(smallvec::smallvec![(prev(h) as u32, 0.5), (h32, 0.5)]);
+    }
+    weights
+}
+
+/// Returns the id remap for one iteration of the halfedge refinement rule on
+/// a mesh with the given `counts`, using the paper nomenclature: Halfedge `h`
+/// spawns halfedges 4h+0..4h+3, which form the new face `h`.
+fn refinement_remap(
+    counts: MeshCounts,
+    next: impl Fn(usize) -> usize,
+    face: impl Fn(usize) -> usize,
+) -> IdRemap {
+    let mut faces = vec![SVec::new(); counts.num_faces];
+    for h in 0..counts.num_halfedges {
+        faces[face(h)].push(h as u32);
+    }
+    IdRemap {
+        vertices: ElementRemap::identity(counts.num_vertices),
+        faces: ElementRemap::new(faces),
+        // The first half of `h` is 4h+0, going from its source vertex to the
+        // edge point. The second half goes from the edge point to the next
+        // vertex, which is halfedge 4h'+3 of the next halfedge h'.
+        halfedges: ElementRemap::new(
+            (0..counts.num_halfedges)
+                .map(|h| smallvec::smallvec![4 * h as u32, 4 * next(h) as u32 + 3])
+                .collect(),
+        ),
+    }
+}
+
+/// A sequential stand-in for the subset of `rayon::prelude` used by
+/// [`CompactMesh::subdivide`]. Used when the `parallel` feature is disabled,
+/// e.g. on platforms without thread support like wasm32-unknown-unknown.
+#[cfg(not(feature = "parallel"))]
+mod serial_prelude {
+    pub trait ParChunksMut<T> {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> std::slice::ChunksMut<'_, T>;
+    }
+
+    impl<T> ParChunksMut<T> for [T] {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> std::slice::ChunksMut<'_, T> {
+            self.chunks_mut(chunk_size)
+        }
+    }
+
+    pub trait IntoParIter {
+        type Iter: Iterator;
+        fn into_par_iter(self) -> Self::Iter;
+    }
+
+    impl IntoParIter for std::ops::Range<usize> {
+        type Iter = Self;
+        fn into_par_iter(self) -> Self::Iter {
+            self
+        }
+    }
+
+    type Zip3<A, B, C> = std::iter::Map<
+        std::iter::Zip<std::iter::Zip<A, B>, C>,
+        fn(
+            (
+                (<A as Iterator>::Item, <B as Iterator>::Item),
+                <C as Iterator>::Item,
+            ),
+        ) -> (
+            <A as Iterator>::Item,
+            <B as Iterator>::Item,
+            <C as Iterator>::Item,
+        ),
+    >;
+
+    impl<A: Iterator, B: Iterator, C: Iterator> IntoParIter for (A, B, C) {
+        type Iter = Zip3<A, B, C>;
+        fn into_par_iter(self) -> Self::Iter {
+            let flatten: fn(((A::Item, B::Item), C::Item)) -> (A::Item, B::Item, C::Item) =
+                |((a, b), c)| (a, b, c);
+            self.0.zip(self.1).zip(self.2).map(flatten)
+        }
+    }
+
+    pub trait CollectIntoVec<T>: Iterator<Item = T> + Sized {
+        fn collect_into_vec(self, target: &mut Vec<T>) {
+            target.clear();
+            target.extend(self);
+        }
+    }
+
+    impl<T, I: Iterator<Item = T>> CollectIntoVec<T> for I {}
+}
+
 /// A counterpart to `glam::Vec3` with atomics in its `x`, `y`, `z` fields.
 #[repr(C)]
 struct AtomicVec3 {
@@ -554,6 +784,122 @@ impl AtomicVec3 {
 #[cfg(test)]
 pub mod test {
     use super::*;
+
+    #[test]
+    pub fn subdivision_progress_test() {
+        use crate::progress::{Cancelled, MockSink};
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let compact = CompactMesh::<false>::from_halfedge(&mesh).unwrap();
+
+        let sink = MockSink::new(None);
+        compact
+            .subdivide_multi_with_progress(3, true, Some(&sink))
+            .unwrap();
+        sink.assert_monotonic();
+        assert_eq!(sink.reports.borrow().len(), 3);
+        assert_eq!(*sink.reports.borrow().last().unwrap(), 1.0);
+
+        // Cancelling stops right after the first report
+        let sink = MockSink::new(Some(1));
+        let err = compact
+            .subdivide_multi_with_progress(3, true, Some(&sink))
+            .err()
+            .unwrap();
+        assert!(err.is::<Cancelled>());
+        assert_eq!(sink.reports.borrow().len(), 1);
+    }
+
+    #[test]
+    pub fn subdivision_remap_test() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let compact = CompactMesh::<false>::from_halfedge(&mesh).unwrap();
+        let remap = compact.subdivision_remap(&mesh.read_connectivity(), 2);
+        let subdivided = compact.subdivide_multi(2, false).to_halfedge();
+
+        let (conn, positions) = (mesh.read_connectivity(), mesh.read_positions());
+        let (new_conn, new_positions) =
+            (subdivided.read_connectivity(), subdivided.read_positions());
+        let new_faces = new_conn.iter_faces().map(|(f, _)| f).collect_vec();
+        let new_vertices = new_conn.iter_vertices().map(|(v, _)| v).collect_vec();
+
+        for (i, (face, _)) in conn.iter_faces().enumerate() {
+            let targets = remap.faces.get(i as u32);
+            assert_eq!(targets.len(), 16);
+            // Linear subdivision keeps all the new faces on the original one
+            let verts = conn.face_vertices(face);
+            let normal = (positions[verts[1]] - positions[verts[0]])
+                .cross(positions[verts[2]] - positions[verts[0]])
+                .normalize();
+            for t in targets {
+                for v in new_conn.face_vertices(new_faces[*t as usize]) {
+                    let dist = (new_positions[v] - positions[verts[0]]).dot(normal);
+                    assert!(dist.abs() < 1e-5);
+                }
+            }
+        }
+        for (i, (v, _)) in conn.iter_vertices().enumerate() {
+            assert_eq!(remap.vertices.get(i as u32), &[i as u32]);
+            assert_eq!(positions[v], new_positions[new_vertices[i]]);
+        }
+    }
+
+    #[test]
+    pub fn subdivision_corner_channels_test() {
+        use crate::mesh::halfedge::edit_ops;
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        edit_ops::set_full_range_uvs(&mut mesh).unwrap();
+
+        let conn = mesh.read_connectivity();
+        let uvs = mesh
+            .channels
+            .read_channel_by_name::<HalfEdgeId, Vec3>("uv")
+            .unwrap();
+        let faces = conn.iter_faces().map(|(f, _)| f).collect_vec();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        let remap = CompactMesh::<false>::from_halfedge(&mesh)
+            .unwrap()
+            .subdivision_remap(&conn, 2);
+
+        for catmull_clark in [false, true] {
+            let subdivided = edit_ops::lua_fns::subdivide(&mesh, 2, catmull_clark).unwrap();
+            let new_conn = subdivided.read_connectivity();
+            let new_uvs = subdivided
+                .channels
+                .read_channel_by_name::<HalfEdgeId, Vec3>("uv")
+                .unwrap();
+            let new_faces = new_conn.iter_faces().map(|(f, _)| f).collect_vec();
+            let new_vertices = new_conn.iter_vertices().map(|(v, _)| v).collect_vec();
+
+            for (i, face) in faces.iter_cpy().enumerate() {
+                // The UVs of the faces coming from the same original face are
+                // continuous: All the corners at the same vertex agree.
+                let mut corner_uvs = HashMap::<VertexId, Vec3>::new();
+                for t in remap.faces.get(i as u32) {
+                    for h in new_conn.face_edges(new_faces[*t as usize]) {
+                        let v = new_conn.at_halfedge(h).vertex().try_end().unwrap();
+                        if let Some(other) = corner_uvs.insert(v, new_uvs[h]) {
+                            assert!((other - new_uvs[h]).length() < 1e-5);
+                        }
+                    }
+                }
+                // Two iterations split each face in a 4x4 grid, so all the
+                // UVs are at multiples of a quarter.
+                assert_eq!(corner_uvs.len(), 25);
+                for uv in corner_uvs.values() {
+                    let quarters = *uv * 4.0;
+                    assert!((quarters - quarters.round()).length() < 1e-5, "{uv}");
+                    assert!(uv.min_element() >= 0.0 && uv.max_element() <= 1.0);
+                }
+                // The original corners keep their exact values
+                for h in conn.face_edges(face) {
+                    let v = conn.at_halfedge(h).vertex().try_end().unwrap();
+                    let idx = vertices.iter().position(|x| *x == v).unwrap();
+                    assert_eq!(corner_uvs[&new_vertices[idx]], uvs[h]);
+                }
+            }
+        }
+    }
+
     #[test]
     pub fn mesh_counts_test() {
         // Results empirically validated by subdividing several meshes in
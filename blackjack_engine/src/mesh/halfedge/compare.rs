@@ -0,0 +1,549 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+
+use slotmap::SecondaryMap;
+
+use super::*;
+
+/// The elements of a mesh in canonical order. The order only depends on the
+/// connectivity and vertex positions of the mesh, not on the ids of its
+/// elements, so equal meshes get the same order no matter how they were
+/// built.
+///
+/// Each connected component is traversed breadth-first, starting from the
+/// halfedge whose vertices have the smallest positions. The components come
+/// in the order of their starting halfedges, followed by the isolated
+/// vertices, sorted by position.
+pub struct CanonicalOrder {
+    pub vertices: Vec<VertexId>,
+    pub faces: Vec<FaceId>,
+    pub halfedges: Vec<HalfEdgeId>,
+}
+
+/// Orders positions lexicographically by their x, y and z coordinates.
+fn cmp_positions(a: Vec3, b: Vec3) -> Ordering {
+    a.x.total_cmp(&b.x)
+        .then_with(|| a.y.total_cmp(&b.y))
+        .then_with(|| a.z.total_cmp(&b.z))
+}
+
+/// Maps each key to its position in `keys`.
+fn index_map<K: slotmap::Key>(keys: &[K]) -> SecondaryMap<K, usize> {
+    keys.iter_cpy().enumerate().map(|(i, k)| (k, i)).collect()
+}
+
+impl CanonicalOrder {
+    pub fn new(conn: &MeshConnectivity, positions: &Positions) -> Self {
+        let position = |v: Option<VertexId>| v.map_or(Vec3::ZERO, |v| positions[v]);
+        let next = |h: Option<HalfEdgeId>| h.and_then(|h| conn.halfedges.get(h));
+        // The positions of the first three vertices reached by following the
+        // halfedge. Two vertices are seldom enough when some of them are
+        // duplicated, like at the seams of a mesh.
+        let start_key = |h: &HalfEdge| {
+            let second = next(h.next);
+            let third = second.and_then(|s| next(s.next));
+            [
+                position(h.vertex),
+                position(second.and_then(|s| s.vertex)),
+                position(third.and_then(|t| t.vertex)),
+            ]
+        };
+        let mut starts = conn
+            .iter_halfedges()
+            .map(|(h, halfedge)| (start_key(halfedge), h))
+            .collect_vec();
+        starts.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| cmp_positions(*a, *b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut order = CanonicalOrder {
+            vertices: Vec::with_capacity(conn.num_vertices()),
+            faces: Vec::with_capacity(conn.num_faces()),
+            halfedges: Vec::with_capacity(conn.num_halfedges()),
+        };
+        let mut seen_halfedges = SecondaryMap::<HalfEdgeId, ()>::new();
+        let mut seen_vertices = SecondaryMap::<VertexId, ()>::new();
+        let mut seen_faces = SecondaryMap::<FaceId, ()>::new();
+        let mut queue = VecDeque::new();
+        for (_, start) in starts {
+            queue.push_back(start);
+            while let Some(h) = queue.pop_front() {
+                if seen_halfedges.insert(h, ()).is_some() {
+                    continue;
+                }
+                let halfedge = match conn.halfedges.get(h) {
+                    Some(halfedge) => halfedge,
+                    None => continue,
+                };
+                order.halfedges.push(h);
+                if let Some(v) = halfedge.vertex {
+                    if seen_vertices.insert(v, ()).is_none() {
+                        order.vertices.push(v);
+                    }
+                }
+                if let Some(f) = halfedge.face {
+                    if seen_faces.insert(f, ()).is_none() {
+                        order.faces.push(f);
+                    }
+                }
+                queue.extend(halfedge.next);
+                queue.extend(halfedge.twin);
+            }
+        }
+
+        let mut isolated = conn
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .filter(|v| !seen_vertices.contains_key(*v))
+            .collect_vec();
+        isolated.sort_by(|a, b| cmp_positions(positions[*a], positions[*b]));
+        order.vertices.extend(isolated);
+        // Faces with no halfedges only appear in malformed meshes
+        order.faces.extend(
+            conn.iter_faces()
+                .map(|(f, _)| f)
+                .filter(|f| !seen_faces.contains_key(*f)),
+        );
+        order
+    }
+
+    /// Describes the connectivity of each halfedge, in canonical order,
+    /// through the canonical indices of its twin, next, vertex and face.
+    fn halfedge_signature(&self, conn: &MeshConnectivity) -> Vec<[Option<usize>; 4]> {
+        let vertices = index_map(&self.vertices);
+        let faces = index_map(&self.faces);
+        let halfedges = index_map(&self.halfedges);
+        self.halfedges
+            .iter()
+            .map(|h| {
+                let halfedge = &conn[*h];
+                [
+                    halfedge.twin.and_then(|h| halfedges.get(h).copied()),
+                    halfedge.next.and_then(|h| halfedges.get(h).copied()),
+                    halfedge.vertex.and_then(|v| vertices.get(v).copied()),
+                    halfedge.face.and_then(|f| faces.get(f).copied()),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// The number of elements of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementCounts {
+    pub vertices: usize,
+    pub faces: usize,
+    pub halfedges: usize,
+}
+
+impl ElementCounts {
+    fn of(conn: &MeshConnectivity) -> Self {
+        Self {
+            vertices: conn.num_vertices(),
+            faces: conn.num_faces(),
+            halfedges: conn.num_halfedges(),
+        }
+    }
+}
+
+impl fmt::Display for ElementCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vertices, {} faces and {} halfedges",
+            self.vertices, self.faces, self.halfedges
+        )
+    }
+}
+
+/// How two meshes differ, as found by [`compare_meshes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshDiff {
+    /// The element counts of the first and second meshes.
+    pub counts: [ElementCounts; 2],
+    /// A description of the first difference found. Elements are referred to
+    /// by their index in the [`CanonicalOrder`] of the meshes.
+    pub first_difference: String,
+    /// How many elements differ in the same way as the first one.
+    pub num_differences: usize,
+}
+
+impl fmt::Display for MeshDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first_difference)?;
+        if self.num_differences > 1 {
+            write!(f, " ({} differences in total)", self.num_differences)?;
+        }
+        write!(
+            f,
+            ". The first mesh has {}, and the second one has {}",
+            self.counts[0], self.counts[1]
+        )
+    }
+}
+
+/// Channel values that can be compared with a tolerance.
+trait CompareValue: ChannelValue {
+    fn approx_eq(&self, other: &Self, tolerance: f32) -> bool;
+}
+
+impl CompareValue for Vec3 {
+    fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        self.distance(*other) <= tolerance
+    }
+}
+
+impl CompareValue for f32 {
+    fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        (self - other).abs() <= tolerance
+    }
+}
+
+impl CompareValue for bool {
+    fn approx_eq(&self, other: &Self, _tolerance: f32) -> bool {
+        self == other
+    }
+}
+
+impl CompareValue for Tag {
+    fn approx_eq(&self, other: &Self, _tolerance: f32) -> bool {
+        self == other
+    }
+}
+
+/// The key type, value type and name of a channel.
+type ChannelDesc = (ChannelKeyType, ChannelValueType, ChannelName);
+
+/// Returns the first of `a` and `b`'s values that are not equal, by their
+/// index, along with how many pairs are not equal.
+fn first_difference<V: CompareValue>(
+    a: impl Iterator<Item = V>,
+    b: impl Iterator<Item = V>,
+    tolerance: f32,
+) -> Option<(usize, V, V, usize)> {
+    let mut first = None;
+    let mut count = 0;
+    for (i, (a, b)) in a.zip(b).enumerate() {
+        if !a.approx_eq(&b, tolerance) {
+            count += 1;
+            first = first.or(Some((i, a, b)));
+        }
+    }
+    first.map(|(i, a, b)| (i, a, b, count))
+}
+
+/// Compares the values of the channel `name` at the elements `keys_a` of `a`,
+/// and `keys_b` of `b`.
+fn channel_difference<K: ChannelKey, V: CompareValue>(
+    a: &HalfEdgeMesh,
+    b: &HalfEdgeMesh,
+    name: ChannelName,
+    keys_a: &[K],
+    keys_b: &[K],
+    tolerance: f32,
+) -> Result<Option<(String, usize)>> {
+    let ch_a = a.channels.read_channel_by_name::<K, V>(name)?;
+    let ch_b = b.channels.read_channel_by_name::<K, V>(name)?;
+    let values_a = keys_a.iter().map(|k| ch_a[*k]);
+    let values_b = keys_b.iter().map(|k| ch_b[*k]);
+    Ok(
+        first_difference(values_a, values_b, tolerance).map(|(i, value_a, value_b, count)| {
+            let element = match K::key_type() {
+                ChannelKeyType::VertexId => "vertex",
+                ChannelKeyType::FaceId => "face",
+                ChannelKeyType::HalfEdgeId => "halfedge",
+            };
+            let message = format!(
+                "The channel '{name}' has the value {value_a:?} at the {element} {i} of the \
+                 first mesh, but {value_b:?} in the second"
+            );
+            (message, count)
+        }),
+    )
+}
+
+/// Compares meshes `a` and `b`, and returns how they differ, if they do.
+///
+/// The meshes are equal when they have the same topology under their
+/// [`CanonicalOrder`], and their vertices are at most `position_tolerance`
+/// apart. With `check_channels`, both meshes must also have the same
+/// channels, with the same values. Numbers and vectors in channels are
+/// compared with the same tolerance as positions.
+pub fn compare_meshes(
+    a: &HalfEdgeMesh,
+    b: &HalfEdgeMesh,
+    position_tolerance: f32,
+    check_channels: bool,
+) -> Result<Option<MeshDiff>> {
+    let conn_a = a.read_connectivity();
+    let conn_b = b.read_connectivity();
+    let positions_a = a.read_positions();
+    let positions_b = b.read_positions();
+    let counts = [ElementCounts::of(&conn_a), ElementCounts::of(&conn_b)];
+    let diff = |first_difference: String, num_differences: usize| {
+        Ok(Some(MeshDiff {
+            counts,
+            first_difference,
+            num_differences,
+        }))
+    };
+
+    if counts[0] != counts[1] {
+        return diff("The meshes have a different number of elements".into(), 1);
+    }
+
+    let order_a = CanonicalOrder::new(&conn_a, &positions_a);
+    let order_b = CanonicalOrder::new(&conn_b, &positions_b);
+    let signature_a = order_a.halfedge_signature(&conn_a);
+    let signature_b = order_b.halfedge_signature(&conn_b);
+    let differing = (0..signature_a.len())
+        .filter(|i| signature_a[*i] != signature_b[*i])
+        .collect_vec();
+    if let Some(first) = differing.first() {
+        let message = format!(
+            "The meshes have different topology: The halfedge {first} is connected to \
+             different elements in each mesh"
+        );
+        return diff(message, differing.len());
+    }
+
+    let vertex_positions = |order: &CanonicalOrder, positions: &Positions| {
+        order.vertices.iter().map(|v| positions[*v]).collect_vec()
+    };
+    if let Some((i, pos_a, pos_b, count)) = first_difference(
+        vertex_positions(&order_a, &positions_a).into_iter(),
+        vertex_positions(&order_b, &positions_b).into_iter(),
+        position_tolerance,
+    ) {
+        let message = format!(
+            "The vertex {i} is at {pos_a:?} in the first mesh, but at {pos_b:?} in the second, \
+             {} apart",
+            pos_a.distance(pos_b)
+        );
+        return diff(message, count);
+    }
+    if !check_channels {
+        return Ok(None);
+    }
+
+    let channels = |mesh: &HalfEdgeMesh| {
+        let position_name = mesh.channels.channel_name(mesh.default_channels.position);
+        mesh.channels
+            .iter_channels_dyn()
+            .filter(|(_, _, name)| Some(*name) != position_name)
+            .sorted()
+            .collect_vec()
+    };
+    let (channels_a, channels_b) = (channels(a), channels(b));
+    let only_in = |these: &[ChannelDesc], others: &[ChannelDesc]| {
+        these
+            .iter()
+            .filter(|ch| !others.contains(*ch))
+            .cloned()
+            .collect_vec()
+    };
+    for (missing, which) in [
+        (only_in(&channels_a, &channels_b), "first"),
+        (only_in(&channels_b, &channels_a), "second"),
+    ] {
+        if let Some((kty, vty, name)) = missing.first() {
+            let message = format!(
+                "The channel '{name}', of {vty:?} values for each {kty:?}, is only in the \
+                 {which} mesh"
+            );
+            return diff(message, missing.len());
+        }
+    }
+
+    for (kty, vty, name) in &channels_a {
+        let tolerance = position_tolerance;
+        macro_rules! compare {
+            ($k:ty, $v:ty, $keys:ident) => {
+                channel_difference::<$k, $v>(
+                    a,
+                    b,
+                    *name,
+                    &order_a.$keys,
+                    &order_b.$keys,
+                    tolerance,
+                )?
+            };
+        }
+        let difference = match (kty, vty) {
+            (ChannelKeyType::VertexId, ChannelValueType::Vec3) => {
+                compare!(VertexId, Vec3, vertices)
+            }
+            (ChannelKeyType::VertexId, ChannelValueType::f32) => compare!(VertexId, f32, vertices),
+            (ChannelKeyType::VertexId, ChannelValueType::bool) => {
+                compare!(VertexId, bool, vertices)
+            }
+            (ChannelKeyType::VertexId, ChannelValueType::Tag) => compare!(VertexId, Tag, vertices),
+            (ChannelKeyType::FaceId, ChannelValueType::Vec3) => compare!(FaceId, Vec3, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::f32) => compare!(FaceId, f32, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::bool) => compare!(FaceId, bool, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::Tag) => compare!(FaceId, Tag, faces),
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::Vec3) => {
+                compare!(HalfEdgeId, Vec3, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::f32) => {
+                compare!(HalfEdgeId, f32, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::bool) => {
+                compare!(HalfEdgeId, bool, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::Tag) => {
+                compare!(HalfEdgeId, Tag, halfedges)
+            }
+        };
+        if let Some((message, count)) = difference {
+            return diff(message, count);
+        }
+    }
+
+    Ok(None)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Fails with a description of the differences between meshes `a` and
+    /// `b`, unless they are equal. Meshes are equal when they have the same
+    /// topology, no matter the order of their elements, and their vertices
+    /// are at most `position_tolerance` apart. When `check_channels` is set,
+    /// their channels must also be equal.
+    #[lua(under = "Ops")]
+    pub fn assert_mesh_equals(
+        a: &HalfEdgeMesh,
+        b: &HalfEdgeMesh,
+        position_tolerance: f32,
+        check_channels: bool,
+    ) -> Result<()> {
+        match compare_meshes(a, b, position_tolerance, check_channels)? {
+            Some(diff) => bail!("The meshes are not equal: {diff}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unit_box() -> HalfEdgeMesh {
+        primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap()
+    }
+
+    /// The same box as [`unit_box`], with its vertices and faces listed in
+    /// a different order, so all of its elements get different ids.
+    fn reindexed_box() -> HalfEdgeMesh {
+        let mesh = unit_box();
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        // Vertices in reverse order
+        let new_positions = vertices.iter().rev().map(|v| positions[*v]).collect_vec();
+        let new_index =
+            |v: VertexId| vertices.len() - 1 - vertices.iter().position(|w| *w == v).unwrap();
+        // Faces in reverse order, each one starting at a different vertex
+        let polygons = conn
+            .iter_faces()
+            .map(|(f, _)| {
+                let mut polygon = conn
+                    .face_vertices(f)
+                    .iter()
+                    .map(|v| new_index(*v))
+                    .collect_vec();
+                polygon.rotate_left(1);
+                polygon
+            })
+            .collect_vec();
+        let polygons = polygons.into_iter().rev().collect_vec();
+        HalfEdgeMesh::build_from_polygons(&new_positions, &polygons).unwrap()
+    }
+
+    #[test]
+    fn test_equal_meshes() {
+        let a = unit_box();
+        assert_eq!(compare_meshes(&a, &a.clone(), 0.0, true).unwrap(), None);
+        assert_eq!(compare_meshes(&a, &unit_box(), 0.0, true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reindexed_meshes() {
+        let mut a = unit_box();
+        let mut b = reindexed_box();
+        assert_eq!(compare_meshes(&a, &b, 0.0, false).unwrap(), None);
+
+        // Channels are compared at the matching elements
+        let set_marks = |mesh: &HalfEdgeMesh| {
+            let mut ch = mesh
+                .channels
+                .write_channel_by_name::<VertexId, f32>("mark")
+                .unwrap();
+            let positions = mesh.read_positions();
+            for (v, _) in mesh.read_connectivity().iter_vertices() {
+                ch[v] = positions[v].x + 2.0 * positions[v].y;
+            }
+        };
+        for mesh in [&mut a, &mut b] {
+            mesh.channels.ensure_channel::<VertexId, f32>("mark");
+            set_marks(mesh);
+        }
+        assert_eq!(compare_meshes(&a, &b, 0.0, true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_perturbed_meshes() {
+        let mut a = unit_box();
+        let b = unit_box();
+        let moved = {
+            let order = CanonicalOrder::new(&b.read_connectivity(), &b.read_positions());
+            order.vertices[3]
+        };
+        b.write_positions()[moved].y += 0.01;
+
+        // Within the tolerance
+        assert_eq!(compare_meshes(&a, &b, 0.02, false).unwrap(), None);
+
+        let diff = compare_meshes(&a, &b, 0.001, false).unwrap().unwrap();
+        assert_eq!(diff.num_differences, 1);
+        assert!(diff.first_difference.starts_with("The vertex 3 is at"));
+        assert!(diff
+            .to_string()
+            .contains("8 vertices, 6 faces and 24 halfedges"));
+
+        // Different topology
+        let mut c = unit_box();
+        c.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+        let diff = compare_meshes(&a, &c, 0.0, false).unwrap().unwrap();
+        assert_eq!(diff.counts[1].vertices, 16);
+        assert_eq!(
+            diff.first_difference,
+            "The meshes have a different number of elements"
+        );
+
+        // Missing and different channels
+        a.channels.ensure_channel::<FaceId, bool>("selected");
+        let diff = compare_meshes(&a, &a.clone(), 0.0, true).unwrap();
+        assert_eq!(diff, None);
+        let diff = compare_meshes(&a, &unit_box(), 0.0, true).unwrap().unwrap();
+        assert_eq!(
+            diff.first_difference,
+            "The channel 'selected', of bool values for each FaceId, is only in the first mesh"
+        );
+        // But channels are only checked on request
+        assert_eq!(compare_meshes(&a, &unit_box(), 0.0, false).unwrap(), None);
+    }
+}
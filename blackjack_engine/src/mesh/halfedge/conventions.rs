@@ -0,0 +1,347 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::str::FromStr;
+
+use slotmap::SecondaryMap;
+
+use super::export::CREASE_CHANNEL;
+use crate::prelude::*;
+use crate::units::ExportFormat;
+
+/// The directions the axes of a file point to. Meshes in blackjack are always
+/// Y-up and right-handed, and importers and exporters convert from and to the
+/// convention of the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AxisConvention {
+    /// The native convention, also used by glTF and most game engines.
+    #[default]
+    YUpRightHanded,
+    /// Used by Blender and 3ds Max, where Y points forward.
+    ZUpRightHanded,
+    /// Used by Unity, where Z points forward.
+    YUpLeftHanded,
+}
+
+impl AxisConvention {
+    pub const ALL: [AxisConvention; 3] = [
+        Self::YUpRightHanded,
+        Self::ZUpRightHanded,
+        Self::YUpLeftHanded,
+    ];
+
+    /// The matrix taking coordinates in this convention to the native one.
+    pub fn to_native(self) -> Mat3 {
+        match self {
+            AxisConvention::YUpRightHanded => Mat3::IDENTITY,
+            AxisConvention::ZUpRightHanded => Mat3::from_cols(Vec3::X, -Vec3::Z, Vec3::Y),
+            AxisConvention::YUpLeftHanded => Mat3::from_cols(Vec3::X, Vec3::Y, -Vec3::Z),
+        }
+    }
+
+    /// The matrix taking coordinates in `from` to coordinates in `to`.
+    pub fn conversion(from: Self, to: Self) -> Mat3 {
+        // The matrices are rotations or reflections, so their inverse is
+        // their transpose.
+        to.to_native().transpose() * from.to_native()
+    }
+
+    /// Whether the axes of this convention are right-handed.
+    pub fn is_right_handed(self) -> bool {
+        self != AxisConvention::YUpLeftHanded
+    }
+
+    /// The convention exporters write files in when none is given. glTF files
+    /// are always written in the one required by the spec.
+    pub fn export_default(format: ExportFormat) -> Self {
+        match format {
+            // The glTF spec mandates Y-up, right-handed coordinates
+            ExportFormat::Gltf => AxisConvention::YUpRightHanded,
+            // OBJ has no convention, but most tools read it as Y-up
+            ExportFormat::Obj => AxisConvention::YUpRightHanded,
+        }
+    }
+}
+
+impl FromStr for AxisConvention {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "YUpRightHanded" => Ok(AxisConvention::YUpRightHanded),
+            "ZUpRightHanded" => Ok(AxisConvention::ZUpRightHanded),
+            "YUpLeftHanded" => Ok(AxisConvention::YUpLeftHanded),
+            _ => bail!("Invalid axis convention '{s}'"),
+        }
+    }
+}
+
+/// Parses an optional axis convention given from Lua, taking `default` when
+/// it's missing.
+pub fn parse_convention(s: Option<String>, default: AxisConvention) -> Result<AxisConvention> {
+    match s {
+        Some(s) => s.parse(),
+        None => Ok(default),
+    }
+}
+
+/// For every halfedge channel of `mesh` with values of type `V`, moves the
+/// value of each halfedge in `sources` to its key.
+fn move_corner_values<V: ChannelValue>(
+    mesh: &HalfEdgeMesh,
+    name: ChannelName,
+    sources: &SecondaryMap<HalfEdgeId, HalfEdgeId>,
+) -> Result<()> {
+    let ch_id = mesh
+        .channels
+        .channel_id::<HalfEdgeId, V>(name)
+        .ok_or_else(|| anyhow!("No halfedge channel named {name}"))?;
+    let mut channel = mesh.channels.write_channel(ch_id)?;
+    let old = channel.clone();
+    for (h, src) in sources.iter() {
+        channel[h] = old[*src];
+    }
+    Ok(())
+}
+
+/// Reverses the winding of every face of `mesh`, so their normals point the
+/// other way. Values in halfedge channels belong to the corner at the start
+/// of the halfedge, and are moved with the corners, except for creases,
+/// which belong to the edge.
+pub fn reverse_winding(mesh: &HalfEdgeMesh) -> Result<()> {
+    let halfedges = mesh
+        .read_connectivity()
+        .iter_halfedges()
+        .map(|(h, _)| h)
+        .collect();
+    reverse_winding_of(mesh, &halfedges)
+}
+
+/// Like [`reverse_winding`], but only for the faces of the given `halfedges`.
+/// They must be all the halfedges of some connected components of `mesh`,
+/// so that no reversed halfedge is connected to one that isn't.
+pub fn reverse_winding_of(mesh: &HalfEdgeMesh, halfedges: &HashSet<HalfEdgeId>) -> Result<()> {
+    // The corner the values of each halfedge come from after reversing
+    let mut sources = SecondaryMap::<HalfEdgeId, HalfEdgeId>::new();
+    {
+        let mut conn = mesh.write_connectivity();
+        let mut new_next = SecondaryMap::<HalfEdgeId, HalfEdgeId>::new();
+        let mut new_vertex = SecondaryMap::<HalfEdgeId, VertexId>::new();
+        for h in halfedges.iter().copied() {
+            let halfedge = &conn[h];
+            if let Some(next) = halfedge.next {
+                new_next.insert(next, h);
+                if halfedge.face.is_some() {
+                    sources.insert(h, next);
+                }
+            }
+            // Each halfedge now starts where it used to end
+            new_vertex.insert(h, conn.at_halfedge(h).twin().vertex().try_end()?);
+        }
+        // The outgoing halfedges of each vertex are the twins of the ones
+        // that were outgoing before.
+        let mut vertex_halfedges = vec![];
+        for (v, vertex) in conn.iter_vertices() {
+            if let Some(h) = vertex.halfedge.filter(|h| halfedges.contains(h)) {
+                vertex_halfedges.push((v, conn.at_halfedge(h).twin().try_end()?));
+            }
+        }
+
+        for h in halfedges.iter().copied() {
+            conn[h].next = new_next.get(h).copied();
+            conn[h].vertex = Some(new_vertex[h]);
+        }
+        for (v, h) in vertex_halfedges {
+            conn[v].halfedge = Some(h);
+        }
+    }
+
+    let corner_channels = mesh
+        .channels
+        .iter_channels_dyn()
+        .filter(|(kty, _, name)| *kty == ChannelKeyType::HalfEdgeId && *name != CREASE_CHANNEL)
+        .collect_vec();
+    for (_, vty, name) in corner_channels {
+        match vty {
+            ChannelValueType::Vec3 => move_corner_values::<Vec3>(mesh, name, &sources)?,
+            ChannelValueType::f32 => move_corner_values::<f32>(mesh, name, &sources)?,
+            ChannelValueType::bool => move_corner_values::<bool>(mesh, name, &sources)?,
+            ChannelValueType::Tag => move_corner_values::<Tag>(mesh, name, &sources)?,
+        }
+    }
+    Ok(())
+}
+
+/// Converts the coordinates of `mesh` from the `from` axis convention to the
+/// `to` one. Positions and normals are rotated or mirrored, and when the
+/// handedness changes, the winding of the faces is reversed so they keep
+/// pointing outwards. The uvs stay on the same corners.
+pub fn convert_mesh_in_place(
+    mesh: &HalfEdgeMesh,
+    from: AxisConvention,
+    to: AxisConvention,
+) -> Result<()> {
+    if from == to {
+        return Ok(());
+    }
+    let conversion = AxisConvention::conversion(from, to);
+    mesh.apply_transform(Mat4::from_mat3(conversion));
+    if from.is_right_handed() != to.is_right_handed() {
+        reverse_winding(mesh)?;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Converts the coordinates of the mesh from the `from` axis convention to
+    /// the `to` one, each one of `"YUpRightHanded"`, the one used by
+    /// blackjack, `"ZUpRightHanded"` or `"YUpLeftHanded"`. Faces are flipped
+    /// when the handedness changes, so they still point outwards.
+    #[lua(under = "Ops")]
+    pub fn convert_axes(mesh: &mut HalfEdgeMesh, from: String, to: String) -> Result<()> {
+        convert_mesh_in_place(mesh, from.parse()?, to.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validation;
+
+    /// A tetrahedron with edges of different lengths along each axis, so any
+    /// swapped or mirrored axis shows. Its uvs are the positions of the
+    /// vertex at the start of each halfedge.
+    fn marker_tetrahedron() -> HalfEdgeMesh {
+        let positions = [
+            Vec3::ZERO,
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 3.0),
+        ];
+        let faces = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        let mesh = HalfEdgeMesh::build_from_polygons(&positions, &faces).unwrap();
+        let mut uvs = Channel::<HalfEdgeId, Vec3>::new();
+        {
+            let conn = mesh.read_connectivity();
+            let positions = mesh.read_positions();
+            for (h, _) in conn.iter_halfedges() {
+                let v = conn.at_halfedge(h).vertex().end();
+                uvs[h] = positions[v];
+            }
+        }
+        let mut mesh = mesh;
+        mesh.default_channels.uvs = Some(mesh.channels.replace_or_create_channel("uv", uvs));
+        mesh
+    }
+
+    /// Checks that every face of the closed `mesh` points away from its
+    /// center.
+    fn assert_outward(mesh: &HalfEdgeMesh) {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let center = positions.iter().map(|(_, p)| *p).sum::<Vec3>() / 4.0;
+        for (f, _) in conn.iter_faces() {
+            let normal = conn.face_normal(&positions, f).unwrap();
+            let face_center = conn.face_vertex_average(&positions, f);
+            assert!(normal.dot(face_center - center) > 0.0, "{normal:?}");
+        }
+    }
+
+    /// Checks that the uvs of `mesh` are still on the same corners, after
+    /// converting it with `conversion`.
+    fn assert_uvs_follow(mesh: &HalfEdgeMesh, conversion: Mat3) {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let uvs = mesh.read_uvs().unwrap();
+        for (h, halfedge) in conn.iter_halfedges() {
+            if halfedge.face.is_none() {
+                continue;
+            }
+            let v = conn.at_halfedge(h).vertex().end();
+            assert!((conversion * uvs[h]).abs_diff_eq(positions[v], 1e-5));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = marker_tetrahedron();
+        assert_outward(&original);
+        let original_positions = original.read_positions().clone();
+
+        for (from, to) in AxisConvention::ALL
+            .into_iter()
+            .cartesian_product(AxisConvention::ALL)
+        {
+            let mesh = original.clone();
+            convert_mesh_in_place(&mesh, from, to).unwrap();
+            assert!(validation::validate(&mesh).is_valid(), "{from:?} {to:?}");
+            assert_outward(&mesh);
+            let conversion = AxisConvention::conversion(from, to);
+            assert_uvs_follow(&mesh, conversion);
+            for (v, p) in mesh.read_positions().iter() {
+                assert!(p.abs_diff_eq(conversion * original_positions[v], 1e-5));
+            }
+
+            convert_mesh_in_place(&mesh, to, from).unwrap();
+            assert_outward(&mesh);
+            assert_uvs_follow(&mesh, Mat3::IDENTITY);
+            for (v, p) in mesh.read_positions().iter() {
+                assert!(p.abs_diff_eq(original_positions[v], 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        // Blender's up axis is our Y, and its forward axis is our -Z
+        let to_native =
+            AxisConvention::conversion(AxisConvention::ZUpRightHanded, AxisConvention::default());
+        assert!((to_native * Vec3::Z).abs_diff_eq(Vec3::Y, 1e-6));
+        assert!((to_native * Vec3::Y).abs_diff_eq(-Vec3::Z, 1e-6));
+
+        let to_unity =
+            AxisConvention::conversion(AxisConvention::default(), AxisConvention::YUpLeftHanded);
+        assert!((to_unity * Vec3::new(1.0, 2.0, 3.0)).abs_diff_eq(Vec3::new(1.0, 2.0, -3.0), 1e-6));
+        assert!(to_unity.determinant() < 0.0);
+
+        assert!("ZUpLeftHanded".parse::<AxisConvention>().is_err());
+    }
+
+    #[test]
+    fn test_creases_stay_on_edges() {
+        let mut mesh = marker_tetrahedron();
+        let ch_id = mesh
+            .channels
+            .ensure_channel::<HalfEdgeId, f32>(CREASE_CHANNEL);
+        let creased_edge = {
+            let conn = mesh.read_connectivity();
+            let (h, _) = conn.iter_halfedges().next().unwrap();
+            mesh.channels.write_channel(ch_id).unwrap()[h] = 1.0;
+            conn.at_halfedge(h).src_dst_pair().unwrap()
+        };
+
+        convert_mesh_in_place(
+            &mesh,
+            AxisConvention::YUpRightHanded,
+            AxisConvention::YUpLeftHanded,
+        )
+        .unwrap();
+        let conn = mesh.read_connectivity();
+        let creases = mesh.channels.read_channel(ch_id).unwrap();
+        for (h, _) in conn.iter_halfedges() {
+            let (src, dst) = conn.at_halfedge(h).src_dst_pair().unwrap();
+            let creased = creases[h] >= 1.0;
+            assert_eq!(
+                creased,
+                (dst, src) == creased_edge,
+                "{src:?} {dst:?} {creased}"
+            );
+        }
+    }
+}
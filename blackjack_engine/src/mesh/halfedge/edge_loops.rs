@@ -0,0 +1,244 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Edge loops and edge rings are walked across regions of quads. A loop goes
+//! from edge to edge through vertices with four edges, always taking the edge
+//! straight ahead. A ring goes from edge to edge through quads, always taking
+//! the edge at the opposite side of the quad. Loops stop at boundaries and
+//! irregular vertices, rings stop at boundaries and faces that aren't quads.
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// Returns whether `v` is an interior vertex with four edges. The edges around
+/// these vertices come in two pairs of opposite edges, so loops can go
+/// through them.
+pub fn is_regular_vertex(conn: &MeshConnectivity, v: VertexId) -> Result<bool> {
+    let outgoing = conn.at_vertex(v).outgoing_halfedges()?;
+    if outgoing.len() != 4 {
+        return Ok(false);
+    }
+    for h in outgoing {
+        if conn.at_halfedge(h).is_boundary()? || conn.at_halfedge(h).twin().is_boundary()? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the halfedge that continues the edge loop of `h` past its
+/// destination vertex, pointing in the same direction as `h`. Returns `None`
+/// when the loop stops at that vertex.
+pub fn next_in_loop(conn: &MeshConnectivity, h: HalfEdgeId) -> Result<Option<HalfEdgeId>> {
+    let dst = conn.at_halfedge(h).dst_vertex().try_end()?;
+    if !is_regular_vertex(conn, dst)? {
+        return Ok(None);
+    }
+    Ok(Some(conn.at_halfedge(h).next().twin().next().try_end()?))
+}
+
+/// Returns the halfedge that continues the edge ring of `h` past its face,
+/// pointing in the same direction as `h`. Returns `None` when the ring stops
+/// at that face, or when `h` is a boundary.
+pub fn next_in_ring(conn: &MeshConnectivity, h: HalfEdgeId) -> Result<Option<HalfEdgeId>> {
+    let face = match conn.at_halfedge(h).face_or_boundary()? {
+        Some(face) => face,
+        None => return Ok(None),
+    };
+    if conn.at_face(face).halfedges()?.len() != 4 {
+        return Ok(None);
+    }
+    Ok(Some(conn.at_halfedge(h).next().next().twin().try_end()?))
+}
+
+/// Walks from `h` in both directions with the `step` function, returning all
+/// the halfedges found in order, pointing in the same direction as `h`. The
+/// walk backwards starts from the twin of `h`.
+fn walk_both_ways(
+    conn: &MeshConnectivity,
+    h: HalfEdgeId,
+    step: impl Fn(&MeshConnectivity, HalfEdgeId) -> Result<Option<HalfEdgeId>>,
+) -> Result<Vec<HalfEdgeId>> {
+    let mut forward = vec![h];
+    let mut current = h;
+    while let Some(next) = step(conn, current)? {
+        if next == h {
+            // Closed, there's nothing else to find backwards
+            return Ok(forward);
+        }
+        if forward.len() > MAX_LOOP_ITERATIONS {
+            bail!("Maximum number of iterations reached walking from {h:?}");
+        }
+        forward.push(next);
+        current = next;
+    }
+
+    let mut backward = vec![];
+    let mut current = conn.at_halfedge(h).twin().try_end()?;
+    while let Some(next) = step(conn, current)? {
+        if backward.len() > MAX_LOOP_ITERATIONS {
+            bail!("Maximum number of iterations reached walking from {h:?}");
+        }
+        backward.push(conn.at_halfedge(next).twin().try_end()?);
+        current = next;
+    }
+    backward.reverse();
+    backward.extend(forward);
+    Ok(backward)
+}
+
+/// Returns the edge loop going through `h`, as a sequence of halfedges
+/// pointing in the same direction. When the loop is closed, it starts at `h`.
+pub fn edge_loop(conn: &MeshConnectivity, h: HalfEdgeId) -> Result<Vec<HalfEdgeId>> {
+    walk_both_ways(conn, h, next_in_loop)
+}
+
+/// Returns the edge ring going through `h`, as a sequence of parallel
+/// halfedges. When the ring is closed, it starts at `h`.
+pub fn edge_ring(conn: &MeshConnectivity, h: HalfEdgeId) -> Result<Vec<HalfEdgeId>> {
+    walk_both_ways(conn, h, next_in_ring)
+}
+
+/// Returns the halfedges of the loops, or rings, going through each of the
+/// `edges`. Each edge is only returned once.
+fn walk_selection(
+    mesh: &HalfEdgeMesh,
+    edges: &SelectionExpression,
+    walk: impl Fn(&MeshConnectivity, HalfEdgeId) -> Result<Vec<HalfEdgeId>>,
+) -> Result<Vec<HalfEdgeId>> {
+    let conn = mesh.read_connectivity();
+    let mut seen = HashSet::new();
+    let mut result = vec![];
+    for h in mesh.resolve_halfedge_selection_full(edges)? {
+        if seen.contains(&h) {
+            continue;
+        }
+        for h in walk(&conn, h)? {
+            let twin = conn.at_halfedge(h).twin().try_end()?;
+            if seen.insert(h) && seen.insert(twin) {
+                result.push(h);
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Selects the edge loops going through the selected `edges` of `mesh`.
+    #[lua(under = "Select")]
+    fn edge_loops(mesh: &HalfEdgeMesh, edges: SelectionExpression) -> Result<SelectionExpression> {
+        let loops = walk_selection(mesh, &edges, edge_loop)?;
+        let mapping = mesh.read_connectivity().halfedge_mapping();
+        Ok(SelectionExpression::from_ids(mapping.map_seq(&loops)))
+    }
+
+    /// Selects the edge rings going through the selected `edges` of `mesh`.
+    #[lua(under = "Select")]
+    fn edge_rings(mesh: &HalfEdgeMesh, edges: SelectionExpression) -> Result<SelectionExpression> {
+        let rings = walk_selection(mesh, &edges, edge_ring)?;
+        let mapping = mesh.read_connectivity().halfedge_mapping();
+        Ok(SelectionExpression::from_ids(mapping.map_seq(&rings)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A grid of 4x3 quads on the XZ plane, with vertex (x, z) at the integer
+    /// coordinates.
+    fn grid() -> HalfEdgeMesh {
+        let (w, h) = (4, 3);
+        let positions = (0..=h)
+            .flat_map(|z| (0..=w).map(move |x| Vec3::new(x as f32, 0.0, z as f32)))
+            .collect_vec();
+        let idx = |x: usize, z: usize| z * (w + 1) + x;
+        let polygons = (0..h)
+            .flat_map(|z| {
+                (0..w).map(move |x| [idx(x, z), idx(x, z + 1), idx(x + 1, z + 1), idx(x + 1, z)])
+            })
+            .collect_vec();
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    fn halfedge_between(mesh: &HalfEdgeMesh, a: Vec3, b: Vec3) -> HalfEdgeId {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        conn.iter_halfedges()
+            .map(|(h, _)| h)
+            .find(|h| {
+                let (src, dst) = conn.at_halfedge(*h).src_dst_pair().unwrap();
+                positions[src] == a && positions[dst] == b
+            })
+            .unwrap()
+    }
+
+    fn endpoints(mesh: &HalfEdgeMesh, halfedges: &[HalfEdgeId]) -> Vec<(Vec3, Vec3)> {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        halfedges
+            .iter()
+            .map(|h| {
+                let (src, dst) = conn.at_halfedge(*h).src_dst_pair().unwrap();
+                (positions[src], positions[dst])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_edge_loop() {
+        let mesh = grid();
+        let h = halfedge_between(&mesh, Vec3::new(1.0, 0.0, 1.0), Vec3::new(2.0, 0.0, 1.0));
+        let edge_loop = edge_loop(&mesh.read_connectivity(), h).unwrap();
+        let expected = (0..4)
+            .map(|x| {
+                (
+                    Vec3::new(x as f32, 0.0, 1.0),
+                    Vec3::new(x as f32 + 1.0, 0.0, 1.0),
+                )
+            })
+            .collect_vec();
+        assert_eq!(endpoints(&mesh, &edge_loop), expected);
+
+        // Loops stop at boundary vertices
+        let h = halfedge_between(&mesh, Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(edge_loop(&mesh.read_connectivity(), h).unwrap(), vec![h]);
+    }
+
+    #[test]
+    fn test_edge_ring() {
+        let mesh = grid();
+        let h = halfedge_between(&mesh, Vec3::new(2.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 1.0));
+        let ring = edge_ring(&mesh.read_connectivity(), h).unwrap();
+        let mut xs = endpoints(&mesh, &ring)
+            .into_iter()
+            .map(|(src, dst)| {
+                // All edges are parallel
+                assert_eq!(dst - src, Vec3::Z);
+                src.x
+            })
+            .collect_vec();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_closed_loop() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let conn = mesh.read_connectivity();
+        let (h, _) = conn.iter_halfedges().next().unwrap();
+        // The vertices of a box have three edges, so loops don't go anywhere,
+        // but rings go around the box.
+        assert_eq!(edge_loop(&conn, h).unwrap(), vec![h]);
+        let ring = edge_ring(&conn, h).unwrap();
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring[0], h);
+        assert_eq!(next_in_ring(&conn, ring[3]).unwrap(), Some(h));
+    }
+}
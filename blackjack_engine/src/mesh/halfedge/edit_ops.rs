@@ -15,12 +15,155 @@ use glam::EulerRot;
 use smallvec::SmallVec;
 
 use crate::prelude::*;
+use crate::progress::{report_warning, ProgressSink, Warning, WarningElements};
 
 use super::selection::SelectionExpression;
 
 /// Just a place where commented-out code goes to die
 pub mod deprecated;
 
+/// Checking and fixing the halfedge invariants of a mesh
+pub mod validation;
+pub use validation::{
+    channels_in_sync, remove_stale_channel_entries, repair, sync_channels, validate,
+    StaleChannelEntries, ValidationReport,
+};
+
+/// Checking that meshes are curves or point clouds
+pub mod mesh_kinds;
+pub use mesh_kinds::{check_curve, check_points};
+
+/// Splitting meshes into several parts
+pub mod separate;
+pub use separate::{find_islands, separate_components, split_by_selection, MeshIsland};
+
+/// Transforms with pivot, orientation and selection options
+pub mod transform;
+pub use transform::{transform_with_options, TransformOptions, TransformPivot, TransformRotation};
+
+/// Snapping vertices to a grid or a plane
+pub mod snap;
+pub use snap::{flatten, quantize, FlattenPlane};
+
+/// Random offsets for vertex positions
+pub mod jitter;
+pub use jitter::{jitter, JitterMode};
+
+/// Random transforms for each connected component of a mesh
+pub mod islands;
+pub use islands::{transform_islands, IslandPivot};
+
+/// Proportional editing with a falloff around a selection
+pub mod falloff;
+pub use falloff::{falloff_weights, proportional_translate, FalloffDistance, FalloffKind};
+
+/// Blending between meshes with the same topology
+pub mod blend;
+pub use blend::{blend_meshes, check_same_topology};
+
+/// Projecting a mesh onto the surface of another
+pub mod shrinkwrap;
+pub use shrinkwrap::{shrinkwrap, ShrinkwrapMode};
+
+/// Free-form deformation of a mesh with a lattice of control points
+pub mod lattice;
+pub use lattice::{lattice_deform, LatticeInterpolation};
+
+/// Twisting, bending and tapering a mesh along an axis
+pub mod simple_deform;
+pub use simple_deform::{simple_deform, DeformKind};
+
+/// Splitting, connecting and sliding edges
+pub mod edge_ops;
+pub use edge_ops::{connect_vertices, slide_edges, split_edges};
+
+/// Multi-segment bevels with a profile curve
+pub mod bevel;
+pub use bevel::{bevel_edges_profile, bevel_profile_point};
+
+/// Mesh simplification by collapsing edges
+pub mod decimate;
+pub use decimate::decimate;
+
+/// Deleting faces and the elements left unused
+pub mod delete;
+pub use delete::{delete, delete_faces, DeleteMode};
+
+/// Sampling images at the UVs of a mesh
+pub mod sample_image;
+pub use sample_image::sample_image;
+
+/// Rebuilding the surface of a mesh from a voxel grid
+pub mod voxel_remesh;
+pub use voxel_remesh::{voxel_remesh, DEFAULT_MAX_VOXEL_CELLS};
+
+/// Rebuilding a surface with triangles of a uniform size
+pub mod remesh;
+pub use remesh::isotropic_remesh;
+
+/// Convex hulls of point sets
+pub mod convex_hull;
+pub use convex_hull::convex_hull;
+
+/// Per-vertex offsets recorded by dragging vertices in the viewport
+pub mod vertex_deltas;
+pub use vertex_deltas::{apply_vertex_deltas, VertexDeltas};
+
+/// Per-vertex values painted in the viewport, and the brush that paints them
+pub mod vertex_weights;
+pub use vertex_weights::{apply_vertex_weights, VertexWeights};
+
+/// Giving values to the corners created by topology changes
+pub mod corners;
+
+/// Clipping meshes against a plane
+pub mod plane_clip;
+pub use plane_clip::{ClippedMesh, Plane};
+
+/// Slicing meshes with a plane
+pub mod bisect;
+pub use bisect::{bisect, BisectKeep};
+
+/// Enforcing mirror symmetry on a mesh
+pub mod symmetrize;
+pub use symmetrize::{symmetrize, SymmetrizeDirection};
+
+/// Filling holes with a grid of quads
+pub mod grid_fill;
+pub use grid_fill::grid_fill;
+
+/// Smoothing edge loops to follow the curvature of the surface
+pub mod edge_flow;
+pub use edge_flow::set_edge_flow;
+
+/// Merging pairs of triangles into quads
+pub mod tris_to_quads;
+pub use tris_to_quads::tris_to_quads;
+
+/// Packing uv islands into the unit square
+pub mod pack_uvs;
+pub use pack_uvs::pack_uv_islands;
+
+/// Unwrapping meshes into flattened uv islands
+pub mod unwrap;
+pub use unwrap::{unwrap_uvs, UnwrapMethod};
+
+/// Baking ambient occlusion into a vertex channel
+pub mod ambient_occlusion;
+pub use ambient_occlusion::bake_ao;
+
+/// Estimating the curvature of the surface at each vertex
+pub mod curvature;
+pub use curvature::{compute_curvature, CurvatureKind};
+
+/// Extruding boundary edges along a rail polyline
+pub mod rail_extrude;
+pub use rail_extrude::{parallel_transport, rail_extrude};
+
+/// Making the winding of faces consistent
+pub mod winding;
+pub use winding::recalculate_winding;
+
 /// Removes `h_l` and its twin `h_r`, merging their respective faces together.
 /// The face on the L side will be kept, and the R side removed. Both sides of
 /// the edge that will be dissolved need to be on a face. Boundary halfedges are
@@ -53,10 +196,10 @@ pub fn dissolve_edge(mesh: &mut MeshConnectivity, h_l: HalfEdgeId) -> Result<()>
         mesh[f_l].halfedge = Some(h_l_prv);
     }
     if mesh[v].halfedge == Some(h_l) {
-        mesh[v].halfedge = Some(h_l_nxt);
+        mesh[v].halfedge = Some(h_r_nxt);
     }
     if mesh[w].halfedge == Some(h_r) {
-        mesh[w].halfedge = Some(h_r_nxt);
+        mesh[w].halfedge = Some(h_l_nxt);
     }
 
     // --- Remove elements ---
@@ -647,7 +790,18 @@ pub fn bevel_edges(
     amount: f32,
 ) -> Result<()> {
     let beveled_edges = bevel_edges_connectivity(mesh, positions, halfedges)?;
+    apply_bevel_pulls(mesh, positions, &beveled_edges, amount)
+}
 
+/// Moves the vertices of the edges returned by `bevel_edges_connectivity` to
+/// their final position, as if they were pulled a distance of `amount` away
+/// from the original edge.
+fn apply_bevel_pulls(
+    mesh: &mut MeshConnectivity,
+    positions: &mut Positions,
+    beveled_edges: &BTreeSet<HalfEdgeId>,
+    amount: f32,
+) -> Result<()> {
     // --- Adjust vertex positions ---
 
     // Movement of vertices in a bevel can be modelled as a set of pulls. For
@@ -656,7 +810,7 @@ pub fn bevel_edges(
     // depending on their location of the halfedge (head, tail resp.). The final
     // move direction of a vertice is the sum of all its pulls.
     let mut move_ops = HashMap::<VertexId, HashSet<Vec3Ord>>::new();
-    for h in beveled_edges {
+    for h in beveled_edges.iter().copied() {
         mesh.add_debug_halfedge(h, DebugMark::green("bvl"));
 
         if mesh.at_halfedge(h).is_boundary()? {
@@ -690,12 +844,65 @@ pub fn bevel_edges(
 
 /// Extrudes the given set of faces. Faces that are connected by at least one
 /// edge will be connected after the extrude.
+///
+/// Returns the halfedges on the boundary of the extruded region. They stay on
+/// the extruded faces, and their twins are on the new side walls.
 pub fn extrude_faces(
     mesh: &mut MeshConnectivity,
     positions: &mut Positions,
     faces: &[FaceId],
     amount: f32,
-) -> Result<()> {
+) -> Result<Vec<HalfEdgeId>> {
+    extrude_faces_with_offset(mesh, positions, faces, amount, None)
+}
+
+/// The largest factor that vertices of an even offset extrude get their
+/// offset scaled by, unless told otherwise. See [`even_offset_factor`].
+pub const DEFAULT_EVEN_OFFSET_MAX_FACTOR: f32 = 3.0;
+
+/// Returns how much farther a vertex has to move along its averaged
+/// `direction` so it ends up at the same distance from the planes of all of
+/// its faces, with the given `normals`: 1/cos(θ), where θ is the average
+/// angle between the direction and each normal. The factor is clamped to
+/// `max_factor`, since it grows without bound as the faces get close to
+/// being folded onto each other.
+pub fn even_offset_factor(
+    direction: Vec3,
+    normals: impl Iterator<Item = Vec3>,
+    max_factor: f32,
+) -> f32 {
+    let (angles, count) = normals.fold((0.0, 0), |(angles, count), normal| {
+        (
+            angles + direction.dot(normal).clamp(-1.0, 1.0).acos(),
+            count + 1,
+        )
+    });
+    if count == 0 {
+        return 1.0;
+    }
+    let cos = (angles / count as f32).cos();
+    if cos * max_factor <= 1.0 {
+        max_factor
+    } else {
+        1.0 / cos
+    }
+}
+
+/// Same as [`extrude_faces`], but when `even_offset` is set, the vertices
+/// where faces with different normals meet move farther along their averaged
+/// normal, so the extruded faces end up at the same distance `amount` from
+/// the original ones. Its value is the largest factor the offset of a vertex
+/// gets scaled by, see [`even_offset_factor`].
+pub fn extrude_faces_with_offset(
+    mesh: &mut MeshConnectivity,
+    positions: &mut Positions,
+    faces: &[FaceId],
+    amount: f32,
+    even_offset: Option<f32>,
+) -> Result<Vec<HalfEdgeId>> {
+    if matches!(even_offset, Some(max_factor) if max_factor < 1.0) {
+        bail!("The maximum factor of an even offset can't be less than 1");
+    }
     let face_set: HashSet<FaceId> = faces.iter().cloned().collect();
 
     // Find the set of all halfedges not adjacent to another extruded face.
@@ -732,14 +939,20 @@ pub fn extrude_faces(
     }
 
     for (v_id, ops) in move_ops {
-        positions[v_id] += ops
+        let direction = ops
             .iter()
             .fold(Vec3::ZERO, |x, y| x + y.to_vec())
-            .normalize()
-            * amount;
+            .normalize();
+        let factor = match even_offset {
+            Some(max_factor) => {
+                even_offset_factor(direction, ops.iter().map(|n| n.to_vec()), max_factor)
+            }
+            None => 1.0,
+        };
+        positions[v_id] += direction * amount * factor;
     }
 
-    Ok(())
+    Ok(halfedges)
 }
 
 /// Generates the flat normals channel for this mesh
@@ -765,7 +978,7 @@ pub fn set_flat_normals(mesh: &mut HalfEdgeMesh) -> Result<()> {
         .replace_or_create_channel("face_normal", normals);
 
     mesh.default_channels.face_normals = Some(normals_ch_id);
-    mesh.gen_config.smooth_normals = false;
+    mesh.gen_config.shading = ShadingMode::Flat;
 
     Ok(())
 }
@@ -788,19 +1001,34 @@ pub fn generate_smooth_normals_channel(mesh: &HalfEdgeMesh) -> Result<Channel<Ve
     Ok(normals)
 }
 
-/// Computes "flat" normals for this mesh. Flat normals are attached to faces.
+/// Computes the smooth normals channel for this mesh and configures the mesh
+/// to generate smooth normals. Meshes that were already shaded with auto
+/// smooth keep their angle.
 pub fn set_smooth_normals(mesh: &mut HalfEdgeMesh) -> Result<()> {
     let normals = generate_smooth_normals_channel(mesh)?;
     let normals_ch_id = mesh
         .channels
         .replace_or_create_channel("vertex_normal", normals);
 
-    mesh.gen_config.smooth_normals = true;
+    if !mesh.gen_config.smooth_normals() {
+        mesh.gen_config.shading = ShadingMode::Smooth;
+    }
     mesh.default_channels.vertex_normals = Some(normals_ch_id);
 
     Ok(())
 }
 
+/// Sets the [`ShadingMode`] of this mesh, and computes the normals channel it
+/// uses.
+pub fn set_shading(mesh: &mut HalfEdgeMesh, shading: ShadingMode) -> Result<()> {
+    match shading {
+        ShadingMode::Flat => set_flat_normals(mesh)?,
+        ShadingMode::Smooth | ShadingMode::AutoSmooth(_) => set_smooth_normals(mesh)?,
+    }
+    mesh.gen_config.shading = shading;
+    Ok(())
+}
+
 /// Generates an UV channel for the mesh where ever polygon is mapped to the
 /// full UV range. Triangles will take half the UV space, quads will take the
 /// full space, and n-gons will take as much space as possible, being centered
@@ -1177,11 +1405,16 @@ pub fn sort_bag_of_edges(
 ///
 /// The extra flip parameter lets you select all permutations of flipping either
 /// the first or second chain, leading to different winding orders.
+///
+/// Open chains of different lengths are bridged up to the end of the shortest
+/// one, and the vertices left out of the longest one are reported to the
+/// `sink` as a warning. Closed chains need to be of the same length.
 pub fn bridge_chains_ui(
     mesh: &mut HalfEdgeMesh,
     bag_1: &[HalfEdgeId],
     bag_2: &[HalfEdgeId],
     flip: usize,
+    sink: Option<&dyn ProgressSink>,
 ) -> Result<()> {
     if bag_1.is_empty() || bag_2.is_empty() {
         bail!("Loops cannot be empty")
@@ -1213,6 +1446,24 @@ pub fn bridge_chains_ui(
         _ => unreachable!(),
     }
 
+    if !is_closed && chain_1.len() != chain_2.len() {
+        let len = chain_1.len().min(chain_2.len());
+        let longest = if chain_1.len() > len {
+            &mut chain_1
+        } else {
+            &mut chain_2
+        };
+        let skipped = longest.drain(len..).collect_vec();
+        report_warning(
+            sink,
+            Warning::new(format!(
+                "The chains have a different number of vertices, {} were left out",
+                skipped.len()
+            ))
+            .with_elements(WarningElements::Vertices(skipped)),
+        );
+    }
+
     bridge_chains(mesh, &chain_1, &chain_2, is_closed)?;
 
     Ok(())
@@ -1435,17 +1686,22 @@ pub fn vertex_attribute_transfer<V: ChannelValue>(
     Ok(())
 }
 
+/// Sets the material index of the faces in `selection`. Indices refer to the
+/// materials of the graph, see [`crate::mesh::material::MaterialTable`].
 pub fn set_material(
     mesh: &mut HalfEdgeMesh,
     selection: &SelectionExpression,
-    material: f32,
+    material: u32,
 ) -> Result<()> {
     // TODO: Use default channels?
-    let ch_id = mesh.channels.ensure_channel::<FaceId, f32>("material");
+    // NOTE: The channel stores floats, as that's what engine integrations read.
+    let ch_id = mesh
+        .channels
+        .ensure_channel::<FaceId, f32>(crate::mesh::material::MATERIAL_CHANNEL);
     let mut material_ch = mesh.channels.write_channel(ch_id)?;
     let ids = mesh.resolve_face_selection_full(selection)?;
     for id in ids {
-        material_ch[id] = material;
+        material_ch[id] = material as f32;
     }
     Ok(())
 }
@@ -1948,6 +2204,7 @@ pub mod lua_fns {
 
     use crate::lua_engine::lua_stdlib::LVec3;
     use halfedge::compact_mesh::CompactMesh;
+    use halfedge::id_remap::with_id_remap;
 
     use super::*;
 
@@ -1961,40 +2218,85 @@ pub mod lua_fns {
     ) -> Result<()> {
         mesh.write_connectivity().clear_debug();
         let verts = mesh.resolve_vertex_selection_full(&vertices)?;
-        for v in verts {
-            crate::mesh::halfedge::edit_ops::chamfer_vertex(
-                &mut mesh.write_connectivity(),
-                &mut mesh.write_positions(),
-                v,
-                amount,
-            )?;
-        }
+        with_id_remap(mesh, |mesh| {
+            let mut known = corners::corners(mesh);
+            for v in verts {
+                corners::chamfer_vertex_with_corners(mesh, v, amount, &mut known)?;
+            }
+            corners::fill_new_corners(mesh, known)
+        })?;
         Ok(())
     }
 
     /// Bevels the given `edges`, replacing each edge with a face and indenting
-    /// it by a given `amount` distance.
+    /// it by a given `amount` distance. The optional `segments` (1 by default)
+    /// splits each bevel in several faces following a profile curve with the
+    /// given `shape` (0.5 by default, a flat profile).
     #[lua(under = "Ops")]
-    pub fn bevel(edges: SelectionExpression, amount: f32, mesh: &HalfEdgeMesh) -> Result<()> {
+    pub fn bevel(
+        edges: SelectionExpression,
+        amount: f32,
+        mesh: &HalfEdgeMesh,
+        segments: Option<u32>,
+        shape: Option<f32>,
+    ) -> Result<()> {
         let edges = mesh.resolve_halfedge_selection_full(&edges)?;
-        crate::mesh::halfedge::edit_ops::bevel_edges(
+        let mut known = corners::corners(mesh);
+        let beveled = {
+            let conn = mesh.read_connectivity();
+            let mut beveled = BTreeSet::new();
+            for h in edges.iter_cpy() {
+                beveled.insert(h);
+                beveled.insert(conn.at_halfedge(h).twin().try_end()?);
+            }
+            beveled
+        };
+        crate::mesh::halfedge::edit_ops::bevel_edges_profile(
             &mut mesh.write_connectivity(),
             &mut mesh.write_positions(),
             &edges,
             amount,
-        )
+            segments.unwrap_or(1),
+            shape.unwrap_or(0.5),
+        )?;
+        corners::copy_beveled_corners(mesh, &beveled, &mut known)?;
+        corners::fill_new_corners(mesh, known)
     }
 
-    /// Extrudes the given `faces` by a given `amount` distance.
+    /// Extrudes the given `faces` by a given `amount` distance. With
+    /// `even_offset`, the extruded faces keep the same distance to the
+    /// original ones where they meet at an angle, scaling the offset of
+    /// those vertices by up to `max_factor`, 3 by default.
     #[lua(under = "Ops")]
-    pub fn extrude(faces: SelectionExpression, amount: f32, mesh: &HalfEdgeMesh) -> Result<()> {
+    pub fn extrude(
+        faces: SelectionExpression,
+        amount: f32,
+        mesh: &mut HalfEdgeMesh,
+        even_offset: Option<bool>,
+        max_factor: Option<f32>,
+    ) -> Result<()> {
         let faces = mesh.resolve_face_selection_full(&faces)?;
-        crate::mesh::halfedge::edit_ops::extrude_faces(
-            &mut mesh.write_connectivity(),
-            &mut mesh.write_positions(),
-            &faces,
-            amount,
-        )?;
+        let even_offset = even_offset
+            .unwrap_or(false)
+            .then(|| max_factor.unwrap_or(DEFAULT_EVEN_OFFSET_MAX_FACTOR));
+        with_id_remap(mesh, |mesh| {
+            let mut known = corners::corners(mesh);
+            let old_vertices = mesh
+                .read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| v)
+                .collect();
+            let boundary = crate::mesh::halfedge::edit_ops::extrude_faces_with_offset(
+                &mut mesh.write_connectivity(),
+                &mut mesh.write_positions(),
+                &faces,
+                amount,
+                even_offset,
+            )?;
+            halfedge::tags::copy_extruded_tags(mesh, &boundary, &old_vertices)?;
+            corners::copy_extruded_corners(mesh, &boundary, &mut known)?;
+            corners::fill_new_corners(mesh, known)
+        })?;
         Ok(())
     }
 
@@ -2002,7 +2304,10 @@ pub mod lua_fns {
     /// unmodified.
     #[lua(under = "Ops")]
     pub fn merge(a: &mut HalfEdgeMesh, b: &HalfEdgeMesh) -> Result<()> {
-        a.merge_with(b);
+        with_id_remap(a, |a| {
+            a.merge_with(b);
+            Ok(())
+        })?;
         Ok(())
     }
 
@@ -2016,9 +2321,41 @@ pub mod lua_fns {
         catmull_clark: bool,
     ) -> Result<HalfEdgeMesh> {
         let new_mesh = CompactMesh::<false>::from_halfedge(mesh)?;
-        Ok(new_mesh
-            .subdivide_multi(iterations, catmull_clark)
-            .to_halfedge())
+        let sink = crate::progress::current_sink();
+        let mut result = new_mesh
+            .subdivide_multi_with_progress(iterations, catmull_clark, sink.as_deref())?
+            .to_halfedge();
+        *result.lineage_mut() = mesh.lineage().clone();
+        let remap = new_mesh.subdivision_remap(&mesh.read_connectivity(), iterations);
+        halfedge::tags::copy_tags_through_remap(mesh, &mut result, &remap)?;
+        result.lineage_mut().push_remap(remap);
+
+        if mesh.channels.has_channels(ChannelKeyType::HalfEdgeId) {
+            // The compact mesh has no boundary halfedges, so its indices only
+            // count the halfedges on a face.
+            let corners = |mesh: &HalfEdgeMesh| {
+                mesh.read_connectivity()
+                    .iter_halfedges()
+                    .filter(|(_, h)| h.face.is_some())
+                    .map(|(h, _)| h)
+                    .collect_vec()
+            };
+            let (old_corners, new_corners) = (corners(mesh), corners(&result));
+            let sources = new_mesh
+                .subdivision_corner_weights(iterations)
+                .iter()
+                .map(|weights| {
+                    weights
+                        .iter()
+                        .map(|(h, w)| (old_corners[*h as usize], *w))
+                        .collect::<SVec<_>>()
+                })
+                .collect_vec();
+            result
+                .channels
+                .interpolate_from(&mesh.channels, &new_corners, &sources)?;
+        }
+        Ok(result)
     }
 
     /// Computes the smooth normals channel for the given `mesh` and sets the
@@ -2037,6 +2374,38 @@ pub mod lua_fns {
         Ok(())
     }
 
+    /// Sets the auto smooth `angle` of the given `mesh`, in degrees. The mesh
+    /// is then shaded smooth, but the viewport and exporters make the edges
+    /// where faces meet at a sharper angle hard. A `nil` angle turns auto
+    /// smooth off, leaving the mesh shaded smooth.
+    #[lua(under = "Ops")]
+    pub fn set_auto_smooth(mesh: &mut HalfEdgeMesh, angle: Option<f32>) {
+        mesh.gen_config.shading = match (angle, mesh.gen_config.shading) {
+            (Some(angle), _) => ShadingMode::AutoSmooth(angle.to_radians()),
+            (None, ShadingMode::AutoSmooth(_)) => ShadingMode::Smooth,
+            (None, shading) => shading,
+        };
+    }
+
+    /// Sets how the given `mesh` is shaded, and computes the normals for it.
+    /// The `mode` is one of `"flat"`, `"smooth"` or `"auto smooth"`. With auto
+    /// smooth, the edges where faces meet at a sharper `angle` than the given
+    /// one, in degrees, are hard. The angle defaults to 30 degrees.
+    #[lua(under = "Ops")]
+    pub fn set_shading(mesh: &mut HalfEdgeMesh, mode: String, angle: Option<f32>) -> Result<()> {
+        super::set_shading(mesh, ShadingMode::from_name(&mode, angle)?)
+    }
+
+    /// Returns the name of the shading mode of the given `mesh`, and its auto
+    /// smooth angle in degrees, if any. See `Ops.set_shading`.
+    #[lua(under = "Ops")]
+    pub fn get_shading(mesh: &HalfEdgeMesh) -> (String, Option<f32>) {
+        (
+            mesh.gen_config.shading.name().to_string(),
+            mesh.gen_config.auto_smooth_angle().map(f32::to_degrees),
+        )
+    }
+
     /// Given a mesh representing a polyline, resamples it using Catmull-Rom
     /// interpolation to create a smooth path that passes through all the points
     /// of the original curve.
@@ -2092,7 +2461,8 @@ pub mod lua_fns {
     ) -> Result<()> {
         let bag_1 = mesh.resolve_halfedge_selection_full(&loop_1)?;
         let bag_2 = mesh.resolve_halfedge_selection_full(&loop_2)?;
-        super::bridge_chains_ui(mesh, &bag_1, &bag_2, flip)
+        let sink = crate::progress::current_sink();
+        super::bridge_chains_ui(mesh, &bag_1, &bag_2, flip, sink.as_deref())
     }
 
     /// Given four vertices `a`, `b`, `c` and `d`, creates a quad face between
@@ -2156,13 +2526,14 @@ pub mod lua_fns {
     /// Sets the `material` channel for all faces in `selection` to use the
     /// given `material_index`.
     ///
-    /// Material indices are currently not interpreted by blackjack, but game
-    /// engine integrations may use this channel in different ways.
+    /// Indices refer to the materials of the graph, which are written by the
+    /// OBJ and glTF exporters. Faces with an index that has no material use a
+    /// default material. Game engine integrations may also read this channel.
     #[lua(under = "Ops")]
     pub fn set_material(
         mesh: &mut HalfEdgeMesh,
         selection: SelectionExpression,
-        material_index: f32,
+        material_index: u32,
     ) -> Result<()> {
         super::set_material(mesh, &selection, material_index)
     }
@@ -2190,6 +2561,9 @@ pub mod lua_fns {
             ChannelValueType::bool => {
                 super::vertex_attribute_transfer::<bool>(src_mesh, dst_mesh, &channel_name)
             }
+            ChannelValueType::Tag => {
+                super::vertex_attribute_transfer::<Tag>(src_mesh, dst_mesh, &channel_name)
+            }
         }
     }
 
@@ -2328,6 +2702,38 @@ pub mod lua_fns {
         Ok(())
     }
 
+    /// Splits the `mesh` into its connected components, returning a list with
+    /// one mesh for each component.
+    #[lua(under = "Ops")]
+    pub fn separate_components(mesh: &HalfEdgeMesh) -> Result<Vec<HalfEdgeMesh>> {
+        super::separate_components(mesh)
+    }
+
+    /// Splits the `mesh` in two, returning a first mesh with the selected
+    /// `faces` and a second mesh with the remaining ones.
+    #[lua(under = "Ops")]
+    pub fn split_by_selection(
+        mesh: &HalfEdgeMesh,
+        faces: SelectionExpression,
+    ) -> Result<(HalfEdgeMesh, HalfEdgeMesh)> {
+        super::split_by_selection(mesh, &faces)
+    }
+
+    /// Checks the halfedge invariants of the `mesh`, returning a report that
+    /// lists the ids of all the offending elements.
+    #[lua(under = "Ops")]
+    pub fn validate(mesh: &HalfEdgeMesh) -> ValidationReport {
+        super::validate(mesh)
+    }
+
+    /// Fixes the problems listed in the `report` returned by `Ops.validate`,
+    /// as long as they can be fixed safely. Some problems, like broken halfedge
+    /// cycles, can't be repaired and will remain in the mesh.
+    #[lua(under = "Ops")]
+    pub fn repair(mesh: &mut HalfEdgeMesh, report: &ValidationReport) -> Result<()> {
+        super::repair(mesh, report)
+    }
+
     #[lua(under = "Ops")]
     pub fn cut_face(
         mesh: &mut HalfEdgeMesh,
@@ -2351,3 +2757,69 @@ pub mod lua_fns {
         Ok(h)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::primitives;
+
+    /// Extrudes two faces of a box meeting at a 90° corner, and returns the
+    /// distances from the vertices of each extruded face to its original
+    /// plane.
+    fn corner_extrude_thickness(even_offset: Option<f32>) -> Vec<f32> {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let mut conn = cube.write_connectivity();
+        let mut positions = cube.write_positions();
+        let a = conn.iter_faces().next().unwrap().0;
+        let h = conn.at_face(a).halfedge().try_end().unwrap();
+        let b = conn.at_halfedge(h).twin().face().try_end().unwrap();
+        let planes = [a, b].map(|face| {
+            let normal = conn.face_normal(&positions, face).unwrap();
+            let v = conn.at_face(face).vertices().unwrap()[0];
+            (face, normal, normal.dot(positions[v]))
+        });
+
+        extrude_faces_with_offset(&mut conn, &mut positions, &[a, b], 0.1, even_offset).unwrap();
+        planes
+            .iter()
+            .flat_map(|(face, normal, offset)| {
+                conn.at_face(*face)
+                    .vertices()
+                    .unwrap()
+                    .iter()
+                    .map(|v| normal.dot(positions[*v]) - offset)
+                    .collect_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_even_offset_extrude_keeps_thickness() {
+        let even = corner_extrude_thickness(Some(DEFAULT_EVEN_OFFSET_MAX_FACTOR));
+        for thickness in &even {
+            assert!((thickness - 0.1).abs() < 0.1 * 0.02, "{even:?}");
+        }
+
+        // Without it, the vertices on the corner only move 0.1 * cos(45°)
+        // away from each face.
+        let uneven = corner_extrude_thickness(None);
+        let (min, max) = uneven.iter().fold((f32::MAX, f32::MIN), |(min, max), t| {
+            (min.min(*t), max.max(*t))
+        });
+        assert!((max - 0.1).abs() < 1e-5);
+        assert!((min - 0.1 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_even_offset_factor_is_clamped() {
+        let normals = [Vec3::X, Vec3::Y, Vec3::Z];
+        let direction = Vec3::ONE.normalize();
+        let factor = even_offset_factor(direction, normals.into_iter(), 3.0);
+        assert!((factor - 3f32.sqrt()).abs() < 1e-4);
+        // Faces folded onto each other would need an infinite offset
+        let folded = [Vec3::X, -Vec3::X + Vec3::Y * 1e-3];
+        let factor = even_offset_factor(Vec3::Y, folded.into_iter(), 3.0);
+        assert_eq!(factor, 3.0);
+        assert_eq!(even_offset_factor(Vec3::Y, std::iter::empty(), 3.0), 1.0);
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::mesh::halfedge::analysis::bounds;
+use crate::mesh::halfedge::bvh::MeshBvh;
+use crate::prelude::*;
+
+/// Returns the `i`-th point of the van der Corput sequence in base 2, by
+/// mirroring the bits of `i` around the binary point.
+fn radical_inverse(i: u32) -> f32 {
+    (i.reverse_bits() as f64 / (1u64 << 32) as f64) as f32
+}
+
+/// Returns `samples` directions over the hemisphere around +Z, distributed
+/// by the cosine of their angle with it. Points come from a Hammersley set,
+/// so the directions are evenly spread and the same on every call.
+fn cosine_hemisphere_directions(samples: u32) -> Vec<Vec3> {
+    (0..samples)
+        .map(|i| {
+            let u = (i as f32 + 0.5) / samples as f32;
+            let phi = std::f32::consts::TAU * radical_inverse(i);
+            let r = u.sqrt();
+            Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u).sqrt())
+        })
+        .collect()
+}
+
+/// Bakes ambient occlusion into the f32 vertex channel named `out_channel`.
+/// For each vertex, `samples` rays are cast over the hemisphere around its
+/// normal, and the fraction of them that don't hit the mesh is stored: 1 for
+/// fully exposed vertices, and lower values for vertices in creases and
+/// cavities. Only hits closer than `max_distance` count as occluded.
+///
+/// Rays are distributed by the cosine of their angle with the normal, like
+/// light over a diffuse surface. Their directions follow a low-discrepancy
+/// sequence, so baking the same mesh always gives the same result.
+pub fn bake_ao(
+    mesh: &mut HalfEdgeMesh,
+    samples: u32,
+    max_distance: f32,
+    out_channel: &str,
+) -> Result<()> {
+    if samples == 0 {
+        bail!("Baking ambient occlusion needs at least one sample");
+    }
+    let normals = generate_smooth_normals_channel(mesh)?;
+    let (min, max) = bounds(mesh);
+    // Rays start slightly above the surface, and ignore hits right at their
+    // origin, so they don't hit the faces around the vertex.
+    let epsilon = 1e-4 * min.distance(max).max(1e-3);
+
+    let vertices = {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        conn.iter_vertices()
+            .map(|(v, _)| (v, positions[v], normals[v]))
+            .collect_vec()
+    };
+    let bvh = MeshBvh::build(mesh);
+    let directions = cosine_hemisphere_directions(samples);
+
+    let exposure = |(_, pos, normal): &(VertexId, Vec3, Vec3)| -> f32 {
+        if *normal == Vec3::ZERO {
+            return 1.0;
+        }
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+        let origin = *pos + *normal * epsilon;
+        let unoccluded = directions
+            .iter()
+            .filter(|d| {
+                let direction = tangent * d.x + bitangent * d.y + *normal * d.z;
+                !bvh.ray_occluded(origin, direction, epsilon, max_distance)
+            })
+            .count();
+        unoccluded as f32 / samples as f32
+    };
+    #[cfg(feature = "parallel")]
+    let values: Vec<f32> = {
+        use rayon::prelude::*;
+        vertices.par_iter().map(exposure).collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let values: Vec<f32> = vertices.iter().map(exposure).collect();
+
+    let mut ao = Channel::<VertexId, f32>::new();
+    for ((v, _, _), value) in vertices.iter().zip(values) {
+        ao[*v] = value;
+    }
+    mesh.channels.replace_or_create_channel(out_channel, ao);
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Bakes ambient occlusion into the f32 vertex channel `out_channel` of
+    /// `mesh`, casting `samples` rays per vertex. Only hits closer than
+    /// `max_distance` occlude.
+    #[lua(under = "Ops")]
+    pub fn bake_ao(
+        mesh: &mut HalfEdgeMesh,
+        samples: u32,
+        max_distance: f32,
+        out_channel: String,
+    ) -> Result<()> {
+        super::bake_ao(mesh, samples, max_distance, &out_channel)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A prism with an L-shaped section, like a floor and a wall, extruded
+    /// along Z in two segments. The inside corner, where the floor meets the
+    /// wall, runs along the line x = 1, y = 1.
+    fn l_prism() -> HalfEdgeMesh {
+        let profile = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(4.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 4.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        let n = profile.len();
+        let rings = 3;
+        let positions = (0..rings)
+            .flat_map(|k| profile.iter().map(move |p| p.extend(k as f32 * 2.0)))
+            .collect_vec();
+        let idx = |i: usize, k: usize| k * n + i % n;
+        let mut polygons = vec![];
+        for k in 0..rings - 1 {
+            for i in 0..n {
+                polygons.push(vec![
+                    idx(i, k),
+                    idx(i + 1, k),
+                    idx(i + 1, k + 1),
+                    idx(i, k + 1),
+                ]);
+            }
+        }
+        polygons.push((0..n).rev().map(|i| idx(i, 0)).collect());
+        polygons.push((0..n).map(|i| idx(i, rings - 1)).collect());
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    fn ao_at(mesh: &HalfEdgeMesh, pos: Vec3) -> f32 {
+        let positions = mesh.read_positions();
+        let ao = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("ao")
+            .unwrap();
+        let (v, _) = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .find(|(v, _)| positions[*v].distance(pos) < 1e-5)
+            .unwrap();
+        ao[v]
+    }
+
+    #[test]
+    fn test_flat_plane_is_unoccluded() {
+        let mut quad =
+            primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::splat(2.0)).unwrap();
+        bake_ao(&mut quad, 64, 10.0, "ao").unwrap();
+        let ao = quad
+            .channels
+            .read_channel_by_name::<VertexId, f32>("ao")
+            .unwrap();
+        for (v, _) in quad.read_connectivity().iter_vertices() {
+            assert!((ao[v] - 1.0).abs() < 1e-5, "{}", ao[v]);
+        }
+    }
+
+    #[test]
+    fn test_inside_corner_is_occluded() {
+        let mut mesh = l_prism();
+        bake_ao(&mut mesh, 256, 100.0, "ao").unwrap();
+        let corner = ao_at(&mesh, Vec3::new(1.0, 1.0, 2.0));
+        // An outside corner on the same segment, only blocked by the end caps
+        let outside = ao_at(&mesh, Vec3::new(0.0, 0.0, 2.0));
+        assert!(corner < 0.85, "{corner}");
+        assert!(outside > 0.95, "{outside}");
+
+        // The sequence of directions is fixed, so baking is reproducible
+        let mut again = l_prism();
+        bake_ao(&mut again, 256, 100.0, "ao").unwrap();
+        assert_eq!(ao_at(&again, Vec3::new(1.0, 1.0, 2.0)), corner);
+    }
+
+    #[test]
+    fn test_max_distance() {
+        // A floor under a wide roof, 2 units above it
+        let mut mesh =
+            primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::splat(2.0)).unwrap();
+        let roof = primitives::Quad::build(Vec3::Y * 2.0, Vec3::NEG_Y, Vec3::X, Vec2::splat(20.0))
+            .unwrap();
+        mesh.merge_with(&roof);
+        let floor_corner = Vec3::new(1.0, 0.0, 1.0);
+
+        bake_ao(&mut mesh, 128, 10.0, "ao").unwrap();
+        let covered = ao_at(&mesh, floor_corner);
+        assert!(covered < 0.2, "{covered}");
+        // The roof is further than the max distance, so it doesn't count
+        bake_ao(&mut mesh, 128, 1.0, "ao").unwrap();
+        assert!((ao_at(&mesh, floor_corner) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_directions_are_cosine_weighted() {
+        let directions = cosine_hemisphere_directions(1024);
+        for d in &directions {
+            assert!((d.length() - 1.0).abs() < 1e-4);
+            assert!(d.z >= 0.0);
+        }
+        // The average of cos(theta) under a cosine distribution is 2/3
+        let mean_cos = directions.iter().map(|d| d.z).sum::<f32>() / directions.len() as f32;
+        assert!((mean_cos - 2.0 / 3.0).abs() < 1e-2, "{mean_cos}");
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::f32::consts::FRAC_PI_2;
+
+use crate::prelude::*;
+
+use super::{apply_bevel_pulls, bevel_edges, bevel_edges_connectivity, cut_face, divide_edge};
+
+/// Returns the point at parameter `u` (in the [0, 1] range) of the bevel
+/// profile going from `a` to `b`, where `corner` is the position of the
+/// original, sharp, edge.
+///
+/// The profile is a quarter of a superellipse. The `shape` sets the position
+/// of the midpoint of the profile along the diagonal that goes from the point
+/// opposite to the corner (0) to the corner itself (1). This way, a shape of
+/// 0.5 gives a flat profile, values around 0.7 give a round profile and larger
+/// values approach the original sharp corner. Values below 0.5 give a concave
+/// profile.
+pub fn bevel_profile_point(a: Vec3, b: Vec3, corner: Vec3, shape: f32, u: f32) -> Vec3 {
+    let shape = shape.clamp(1e-3, 1.0 - 1e-3);
+    // The superellipse |x|^r + |y|^r = 1 passes through (m, m), with
+    // m = 2^(-1/r). Solving for r gives us the exponent for our shape.
+    let r = -std::f32::consts::LN_2 / shape.ln();
+    let theta = u.clamp(0.0, 1.0) * FRAC_PI_2;
+    let x = theta.cos().powf(2.0 / r);
+    let y = theta.sin().powf(2.0 / r);
+    let opposite = a + b - corner;
+    opposite + (a - opposite) * x + (b - opposite) * y
+}
+
+/// Same as [`bevel_edges`], but each bevel is made of several `segments`
+/// following a profile curve of the given `shape`. See
+/// [`bevel_profile_point`] for the meaning of `shape`.
+///
+/// With a single segment, this is exactly the same as [`bevel_edges`]. Where
+/// three or more beveled edges meet, the resulting corner is filled with a
+/// fan of triangles.
+pub fn bevel_edges_profile(
+    mesh: &mut MeshConnectivity,
+    positions: &mut Positions,
+    halfedges: &[HalfEdgeId],
+    amount: f32,
+    segments: u32,
+    shape: f32,
+) -> Result<()> {
+    if segments == 0 {
+        bail!("A bevel needs at least one segment");
+    }
+    if segments == 1 {
+        return bevel_edges(mesh, positions, halfedges, amount);
+    }
+
+    let beveled_edges = bevel_edges_connectivity(mesh, positions, halfedges)?;
+    // At this point, the new vertices are still at the position of the vertex
+    // they were created from, the original corner.
+    let corners = positions.clone();
+    apply_bevel_pulls(mesh, positions, &beveled_edges, amount)?;
+
+    // Each beveled edge is now a quad with two long edges, the twins of the
+    // beveled halfedges, and two short ones, the rails, going across the bevel.
+    // The strip goes p -> q -> q' -> p' -> p, so the rails are q -> q' and
+    // p' -> p.
+    struct Strip {
+        q_rail: HalfEdgeId,
+        p_rail: HalfEdgeId,
+    }
+    let mut strips = vec![];
+    let mut strip_faces = HashSet::new();
+    let mut rails = HashSet::new();
+    for h in beveled_edges.iter().copied() {
+        let s0 = mesh.at_halfedge(h).twin().try_end()?;
+        let strip = match mesh.at_halfedge(s0).face().try_end() {
+            Ok(strip) => strip,
+            Err(_) => continue,
+        };
+        // Both beveled halfedges of the strip point to it. Only handle it once.
+        if mesh.at_face(strip).halfedges()?.len() != 4 || !strip_faces.insert(strip) {
+            continue;
+        }
+        let q_rail = mesh.at_halfedge(s0).next().try_end()?;
+        let p_rail = mesh.at_halfedge(s0).previous().try_end()?;
+        rails.insert(q_rail);
+        rails.insert(p_rail);
+        strips.push(Strip { q_rail, p_rail });
+    }
+
+    // Faces fully surrounded by rails are the corners where several beveled
+    // edges meet. They will be filled once the rails are subdivided.
+    let mut corner_faces = vec![];
+    for rail in rails.iter().copied() {
+        let face = match mesh.at_halfedge(rail).twin().face().try_end() {
+            Ok(face) => face,
+            Err(_) => continue,
+        };
+        if corner_faces.contains(&face) || strip_faces.contains(&face) {
+            continue;
+        }
+        let is_corner = mesh.at_face(face).halfedges()?.iter().all(|h| {
+            mesh.at_halfedge(*h)
+                .twin()
+                .try_end()
+                .map_or(false, |tw| rails.contains(&tw))
+        });
+        if is_corner {
+            corner_faces.push(face);
+        }
+    }
+
+    // The rail endpoints need to be known upfront: Splitting a rail shared by
+    // two strips changes the endpoints of its twin.
+    let strips = strips
+        .into_iter()
+        .map(|strip| {
+            let q_ends = mesh.at_halfedge(strip.q_rail).src_dst_pair()?;
+            let p_ends = mesh.at_halfedge(strip.p_rail).src_dst_pair()?;
+            Ok((strip, q_ends, p_ends))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Splits the `rail` going from `a` to `b` in segments, following the bevel
+    // profile. Returns the new vertices, in order from `a` to `b`. Rails
+    // shared by two strips are only split once.
+    let mut split_rails = HashMap::<(VertexId, VertexId), SVec<VertexId>>::new();
+    let mut split_rail = |mesh: &mut MeshConnectivity,
+                          positions: &mut Positions,
+                          rail: HalfEdgeId,
+                          (a, b): (VertexId, VertexId)|
+     -> Result<SVec<VertexId>> {
+        if let Some(vs) = split_rails.get(&(b, a)) {
+            return Ok(vs.iter().rev().copied().collect());
+        }
+        let (a_pos, b_pos, corner) = (positions[a], positions[b], corners[a]);
+        let mut vs = SVec::new();
+        for i in 1..segments {
+            // The rail starts at the last vertex we inserted, so the
+            // remaining length shrinks every iteration.
+            let t = 1.0 / (segments - i + 1) as f32;
+            let x = divide_edge(mesh, positions, rail, t)?;
+            let u = i as f32 / segments as f32;
+            positions[x] = bevel_profile_point(a_pos, b_pos, corner, shape, u);
+            vs.push(x);
+        }
+        split_rails.insert((a, b), vs.clone());
+        Ok(vs)
+    };
+
+    for (strip, q_ends, p_ends) in strips {
+        let q_side = split_rail(mesh, positions, strip.q_rail, q_ends)?;
+        let p_side = split_rail(mesh, positions, strip.p_rail, p_ends)?;
+        // The p rail goes from p' to p, opposite to the q rail
+        for (p, q) in p_side.iter().rev().copied().zip(q_side) {
+            cut_face(mesh, p, q)?;
+        }
+    }
+
+    for face in corner_faces {
+        let verts = mesh.face_vertices(face);
+        for v in verts.iter_cpy().take(verts.len() - 1).skip(2) {
+            cut_face(mesh, verts[0], v)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    fn bevel_cube_edge(segments: u32, shape: f32) -> HalfEdgeMesh {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        {
+            let mut conn = mesh.write_connectivity();
+            let mut positions = mesh.write_positions();
+            let (_, face) = conn.iter_faces().next().unwrap();
+            let edge = face.halfedge.unwrap();
+            bevel_edges_profile(&mut conn, &mut positions, &[edge], 0.2, segments, shape).unwrap();
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_single_segment_matches_bevel() {
+        let a = bevel_cube_edge(1, 0.8);
+        let b = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        {
+            let mut conn = b.write_connectivity();
+            let mut positions = b.write_positions();
+            let (_, face) = conn.iter_faces().next().unwrap();
+            let edge = face.halfedge.unwrap();
+            bevel_edges(&mut conn, &mut positions, &[edge], 0.2).unwrap();
+        }
+        let (conn_a, conn_b) = (a.read_connectivity(), b.read_connectivity());
+        assert_eq!(conn_a.num_vertices(), conn_b.num_vertices());
+        assert_eq!(conn_a.num_faces(), conn_b.num_faces());
+        let (pos_a, pos_b) = (a.read_positions(), b.read_positions());
+        for ((_, _, pa), (_, _, pb)) in conn_a
+            .iter_vertices_with_channel(&pos_a)
+            .zip(conn_b.iter_vertices_with_channel(&pos_b))
+        {
+            assert_eq!(pa, pb);
+        }
+    }
+
+    #[test]
+    fn test_multi_segment_cube_bevel() {
+        let single = bevel_cube_edge(1, 0.5);
+        let multi = bevel_cube_edge(4, 0.75);
+        assert!(validate(&multi).is_valid());
+
+        let (conn_1, conn_4) = (single.read_connectivity(), multi.read_connectivity());
+        // Each of the two rails gets 3 new vertices, and the strip is split in
+        // 4 faces. This adds three new loops along the edge.
+        assert_eq!(conn_4.num_vertices(), conn_1.num_vertices() + 6);
+        assert_eq!(conn_4.num_faces(), conn_1.num_faces() + 3);
+
+        // Nothing gets out of the original cube
+        let positions = multi.read_positions();
+        for (_, _, pos) in conn_4.iter_vertices_with_channel(&positions) {
+            assert!(pos.abs().max_element() <= 0.5 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_profile_is_monotone() {
+        let corner = Vec3::new(1.0, 1.0, 0.0);
+        let (a, b) = (Vec3::new(0.8, 1.0, 0.0), Vec3::new(1.0, 0.8, 0.0));
+        let chord_mid = (a + b) * 0.5;
+        let to_corner = (corner - chord_mid).normalize();
+
+        for shape in [0.25, 0.5, 0.75] {
+            let points = (0..=4)
+                .map(|i| bevel_profile_point(a, b, corner, shape, i as f32 / 4.0))
+                .collect_vec();
+            assert!(points[0].abs_diff_eq(a, 1e-5));
+            assert!(points[4].abs_diff_eq(b, 1e-5));
+            // Points advance steadily from a to b
+            for (p, q) in points.iter().tuple_windows() {
+                assert!((*q - *p).dot(b - a) > 0.0);
+            }
+            // Convex profiles bulge towards the corner, concave ones away
+            // from it, and flat ones stay on the chord.
+            for p in &points[1..4] {
+                let side = (*p - a).dot(to_corner);
+                if shape > 0.5 {
+                    assert!(side > 1e-4);
+                } else if shape < 0.5 {
+                    assert!(side < -1e-4);
+                } else {
+                    assert!(side.abs() < 1e-4);
+                }
+            }
+        }
+    }
+}
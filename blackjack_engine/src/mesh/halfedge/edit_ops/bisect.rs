@@ -0,0 +1,171 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::*;
+
+use super::plane_clip::{ClippedMesh, Plane};
+
+/// Vertices closer than this to the cutting plane are considered to be on it.
+const ON_PLANE_EPSILON: f32 = 1e-5;
+
+/// Which sides of the plane [`bisect`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BisectKeep {
+    Both,
+    /// The side the plane normal points to.
+    Above,
+    Below,
+}
+
+/// Slices `mesh` with `plane`. Edges crossing the plane are split at the
+/// intersection, and faces are split along the cut. Depending on `keep`, the
+/// part on one side of the plane is removed.
+///
+/// Vertices on the plane belong to both sides. Faces lying on the plane are
+/// kept on the side opposite to their normal, so cutting a box along one of
+/// its faces keeps that face with the rest of the box.
+///
+/// With `cap`, the loops of the cut are filled with a face each, leaving the
+/// parts closed when the mesh was. When keeping both sides, `cap` also
+/// separates them, so each part gets its own cap.
+pub fn bisect(
+    mesh: &HalfEdgeMesh,
+    plane: Plane,
+    keep: BisectKeep,
+    cap: bool,
+) -> Result<HalfEdgeMesh> {
+    let mut clipped = ClippedMesh::default();
+    match keep {
+        BisectKeep::Above => clipped.add_clipped_faces(mesh, &plane, true, ON_PLANE_EPSILON)?,
+        BisectKeep::Below => clipped.add_clipped_faces(mesh, &plane, false, ON_PLANE_EPSILON)?,
+        BisectKeep::Both => {
+            clipped.add_clipped_faces(mesh, &plane, true, ON_PLANE_EPSILON)?;
+            if cap {
+                clipped.start_new_part();
+            }
+            clipped.add_clipped_faces(mesh, &plane, false, ON_PLANE_EPSILON)?;
+        }
+    }
+    if cap {
+        clipped.add_caps(&plane, ON_PLANE_EPSILON);
+    }
+    clipped.build(mesh)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Slices the mesh with the plane through `plane_origin` with the given
+    /// `plane_normal`. The `keep` parameter is one of `"Both"`, the default,
+    /// `"Above"` or `"Below"`, where above is the side the normal points to.
+    /// When `cap` is set, the cut is filled with faces.
+    #[lua(under = "Ops")]
+    pub fn bisect(
+        mesh: &HalfEdgeMesh,
+        plane_origin: LVec3,
+        plane_normal: LVec3,
+        keep: Option<String>,
+        cap: bool,
+    ) -> Result<HalfEdgeMesh> {
+        let keep = match keep.as_deref() {
+            None | Some("Both") => BisectKeep::Both,
+            Some("Above") => BisectKeep::Above,
+            Some("Below") => BisectKeep::Below,
+            Some(keep) => bail!("Invalid bisect side '{keep}'"),
+        };
+        let plane = Plane::new(plane_origin.0, plane_normal.0)?;
+        super::bisect(mesh, plane, keep, cap)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    /// A UV sphere with a ring of vertices on the XZ plane, 86 vertices and 96
+    /// faces.
+    fn sphere(center: Vec3) -> HalfEdgeMesh {
+        primitives::UVSphere::build(center, 12, 8, 1.0).unwrap()
+    }
+
+    fn assert_closed(mesh: &HalfEdgeMesh) {
+        assert!(validate(mesh).is_valid());
+        let conn = mesh.read_connectivity();
+        assert!(conn.iter_halfedges().all(|(_, h)| h.face.is_some()));
+    }
+
+    #[test]
+    fn test_bisect_sphere_at_equator() {
+        let mesh = sphere(Vec3::ZERO);
+        let plane = Plane::new(Vec3::ZERO, Vec3::Y).unwrap();
+
+        let mut clipped = ClippedMesh::default();
+        clipped
+            .add_clipped_faces(&mesh, &plane, true, ON_PLANE_EPSILON)
+            .unwrap();
+        assert_eq!(clipped.add_caps(&plane, ON_PLANE_EPSILON), 1);
+        let top = clipped.build(&mesh).unwrap();
+        assert_closed(&top);
+        // The upper half has 48 faces, plus the cap
+        assert_eq!(top.read_connectivity().num_faces(), 49);
+        assert_eq!(top.read_connectivity().num_vertices(), 1 + 12 * 4);
+
+        let bottom = bisect(&mesh, plane, BisectKeep::Below, true).unwrap();
+        assert_closed(&bottom);
+        let positions = bottom.read_positions();
+        let conn = bottom.read_connectivity();
+        assert!(conn
+            .iter_vertices()
+            .all(|(v, _)| positions[v].y <= ON_PLANE_EPSILON));
+
+        // Without a cap, the cut is left open
+        let open = bisect(&mesh, plane, BisectKeep::Above, false).unwrap();
+        assert_eq!(open.read_connectivity().num_faces(), 48);
+        assert!(validate(&open).is_valid());
+    }
+
+    #[test]
+    fn test_bisect_both_sides() {
+        let mesh = sphere(Vec3::ZERO);
+        // Between two rings of vertices, so the faces in between are split
+        let plane = Plane::new(Vec3::new(0.0, 0.3, 0.0), Vec3::Y).unwrap();
+
+        let both = bisect(&mesh, plane, BisectKeep::Both, false).unwrap();
+        assert_closed(&both);
+        let conn = both.read_connectivity();
+        assert_eq!(conn.num_faces(), 96 + 12);
+        assert_eq!(conn.num_vertices(), 86 + 12);
+        drop(conn);
+
+        // With caps, the two parts are closed on their own, and don't share
+        // the vertices of the cut
+        let parts = bisect(&mesh, plane, BisectKeep::Both, true).unwrap();
+        assert_closed(&parts);
+        let conn = parts.read_connectivity();
+        assert_eq!(conn.num_faces(), 96 + 12 + 2);
+        assert_eq!(conn.num_vertices(), 86 + 12 * 2);
+        drop(conn);
+        assert_eq!(super::super::separate_components(&parts).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_one_cap_per_loop() {
+        // Two spheres side by side leave two loops
+        let mut mesh = sphere(Vec3::new(-3.0, 0.0, 0.0));
+        mesh.merge_with(&sphere(Vec3::new(3.0, 0.0, 0.0)));
+        let plane = Plane::new(Vec3::new(0.0, -0.3, 0.0), Vec3::Y).unwrap();
+
+        let mut clipped = ClippedMesh::default();
+        clipped
+            .add_clipped_faces(&mesh, &plane, false, ON_PLANE_EPSILON)
+            .unwrap();
+        assert_eq!(clipped.add_caps(&plane, ON_PLANE_EPSILON), 2);
+        assert_closed(&clipped.build(&mesh).unwrap());
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use slotmap::SecondaryMap;
+
+use crate::prelude::*;
+
+/// The elements of a mesh, in iteration order. Two meshes have the same
+/// topology when their elements at the same position are connected in the
+/// same way.
+struct ElementOrder {
+    vertices: Vec<VertexId>,
+    faces: Vec<FaceId>,
+    halfedges: Vec<HalfEdgeId>,
+}
+
+impl ElementOrder {
+    fn new(conn: &MeshConnectivity) -> Self {
+        Self {
+            vertices: conn.iter_vertices().map(|(v, _)| v).collect(),
+            faces: conn.iter_faces().map(|(f, _)| f).collect(),
+            halfedges: conn.iter_halfedges().map(|(h, _)| h).collect(),
+        }
+    }
+}
+
+/// Describes the connectivity of each halfedge through the positions of its
+/// twin, next, vertex and face in the iteration order of the mesh.
+fn halfedge_signature(conn: &MeshConnectivity) -> Vec<[Option<usize>; 4]> {
+    fn positions<K: slotmap::Key>(keys: impl Iterator<Item = K>) -> SecondaryMap<K, usize> {
+        keys.enumerate().map(|(i, k)| (k, i)).collect()
+    }
+    let vertices = positions(conn.iter_vertices().map(|(v, _)| v));
+    let faces = positions(conn.iter_faces().map(|(f, _)| f));
+    let halfedges = positions(conn.iter_halfedges().map(|(h, _)| h));
+    conn.iter_halfedges()
+        .map(|(_, halfedge)| {
+            [
+                halfedge.twin.and_then(|h| halfedges.get(h).copied()),
+                halfedge.next.and_then(|h| halfedges.get(h).copied()),
+                halfedge.vertex.and_then(|v| vertices.get(v).copied()),
+                halfedge.face.and_then(|f| faces.get(f).copied()),
+            ]
+        })
+        .collect()
+}
+
+/// Checks that meshes `a` and `b` have the same topology. This means they
+/// have the same number of vertices, faces and halfedges, and each halfedge
+/// is connected to the elements at the same positions in the iteration order
+/// of both meshes. This is the case for meshes that were built by the same
+/// operations, changing only their parameters, or by moving vertices around.
+pub fn check_same_topology(a: &MeshConnectivity, b: &MeshConnectivity) -> Result<()> {
+    let counts = [
+        ("vertices", a.num_vertices(), b.num_vertices()),
+        ("faces", a.num_faces(), b.num_faces()),
+        ("halfedges", a.num_halfedges(), b.num_halfedges()),
+    ];
+    for (element, count_a, count_b) in counts {
+        if count_a != count_b {
+            bail!(
+                "The meshes have different topology: The first mesh has {count_a} {element}, \
+                 but the second one has {count_b}"
+            );
+        }
+    }
+    let signature_b = halfedge_signature(b);
+    for (i, (sig_a, sig_b)) in halfedge_signature(a).iter().zip(signature_b).enumerate() {
+        if *sig_a != sig_b {
+            bail!(
+                "The meshes have different topology: The halfedge {i} is connected to different \
+                 elements in each mesh"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Blends the values of channel `name` of mesh `b` into `out`.
+fn blend_channel<K: ChannelKey, V: ChannelValue>(
+    out: &HalfEdgeMesh,
+    b: &HalfEdgeMesh,
+    name: ChannelName,
+    keys_out: &[K],
+    keys_b: &[K],
+    t: f32,
+) -> Result<()> {
+    let ch_b = b.channels.read_channel_by_name::<K, V>(name)?;
+    let mut ch_out = out.channels.write_channel_by_name::<K, V>(name)?;
+    for (k_out, k_b) in keys_out.iter_cpy().zip(keys_b.iter_cpy()) {
+        ch_out[k_out] = ch_out[k_out].interpolate(ch_b[k_b], t);
+    }
+    Ok(())
+}
+
+/// Returns a mesh with the topology of `a` and `b` where vertex positions are
+/// interpolated between the two meshes by factor `t`. Values of `t` outside
+/// the [0, 1] range extrapolate past the meshes.
+///
+/// When `channels` is set, all the f32 and Vec3 channels present in both
+/// meshes are interpolated too. Otherwise, the channels of `a` are kept.
+pub fn blend_meshes(
+    a: &HalfEdgeMesh,
+    b: &HalfEdgeMesh,
+    t: f32,
+    channels: bool,
+) -> Result<HalfEdgeMesh> {
+    let (order_a, order_b) = {
+        let conn_a = a.read_connectivity();
+        let conn_b = b.read_connectivity();
+        check_same_topology(&conn_a, &conn_b)?;
+        (ElementOrder::new(&conn_a), ElementOrder::new(&conn_b))
+    };
+
+    let out = a.clone();
+    {
+        let positions_b = b.read_positions();
+        let mut positions = out.write_positions();
+        for (v_out, v_b) in order_a.vertices.iter_cpy().zip(order_b.vertices.iter_cpy()) {
+            positions[v_out] = positions[v_out].lerp(positions_b[v_b], t);
+        }
+    }
+
+    if channels {
+        let position_name = out.channels.channel_name(out.default_channels.position);
+        let names = out
+            .channels
+            .iter_channels_dyn()
+            .filter(|(_, _, name)| Some(*name) != position_name)
+            .collect_vec();
+        for (kty, vty, name) in names {
+            if b.channels.channel_id_dyn(kty, vty, name).is_none() {
+                continue;
+            }
+            macro_rules! blend {
+                ($k:ty, $v:ty, $keys:ident) => {
+                    blend_channel::<$k, $v>(&out, b, name, &order_a.$keys, &order_b.$keys, t)?
+                };
+            }
+            match (kty, vty) {
+                (ChannelKeyType::VertexId, ChannelValueType::f32) => {
+                    blend!(VertexId, f32, vertices)
+                }
+                (ChannelKeyType::VertexId, ChannelValueType::Vec3) => {
+                    blend!(VertexId, Vec3, vertices)
+                }
+                (ChannelKeyType::FaceId, ChannelValueType::f32) => blend!(FaceId, f32, faces),
+                (ChannelKeyType::FaceId, ChannelValueType::Vec3) => blend!(FaceId, Vec3, faces),
+                (ChannelKeyType::HalfEdgeId, ChannelValueType::f32) => {
+                    blend!(HalfEdgeId, f32, halfedges)
+                }
+                (ChannelKeyType::HalfEdgeId, ChannelValueType::Vec3) => {
+                    blend!(HalfEdgeId, Vec3, halfedges)
+                }
+                // Booleans and tags can't be blended
+                (_, ChannelValueType::bool | ChannelValueType::Tag) => {}
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Returns a mesh where the vertex positions are interpolated between `a`
+    /// and `b` by factor `t`. Both meshes must have the same topology. When
+    /// `channels` is true, their f32 and vector channels are interpolated as
+    /// well.
+    #[lua(under = "Ops")]
+    pub fn blend_meshes(
+        a: &HalfEdgeMesh,
+        b: &HalfEdgeMesh,
+        t: f32,
+        channels: bool,
+    ) -> Result<HalfEdgeMesh> {
+        super::blend_meshes(a, b, t, channels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_positions(mesh: &HalfEdgeMesh) -> Vec<Vec3> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect()
+    }
+
+    #[test]
+    fn test_blend_cube_and_spherified_cube() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let sphere = cube.clone();
+        {
+            let conn = sphere.read_connectivity();
+            let mut positions = sphere.write_positions();
+            for (v, _) in conn.iter_vertices() {
+                positions[v] = positions[v].normalize() * 2.0;
+            }
+        }
+
+        let cube_positions = all_positions(&cube);
+        let sphere_positions = all_positions(&sphere);
+        for t in [0.0, 0.5, 1.0] {
+            let blended = blend_meshes(&cube, &sphere, t, false).unwrap();
+            for ((p, c), s) in all_positions(&blended)
+                .iter()
+                .zip(&cube_positions)
+                .zip(&sphere_positions)
+            {
+                assert!((*p - c.lerp(*s, t)).length() < 1e-6);
+            }
+        }
+
+        // Extrapolation beyond the second mesh
+        let blended = blend_meshes(&cube, &sphere, 2.0, false).unwrap();
+        for p in all_positions(&blended) {
+            assert!((p.length() - (4.0 - 3.0f32.sqrt() / 2.0)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_blend_channels() {
+        let mut a = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let mut b = a.clone();
+        for (mesh, value) in [(&mut a, 1.0), (&mut b, 3.0)] {
+            let ch_id = mesh.channels.ensure_channel::<FaceId, f32>("weight");
+            let conn = mesh.read_connectivity();
+            let mut ch = mesh.channels.write_channel(ch_id).unwrap();
+            for (f, _) in conn.iter_faces() {
+                ch[f] = value;
+            }
+        }
+        let check = |channels: bool, expected: f32| {
+            let blended = blend_meshes(&a, &b, 0.25, channels).unwrap();
+            let ch = blended
+                .channels
+                .read_channel_by_name::<FaceId, f32>("weight")
+                .unwrap();
+            for (f, _) in blended.read_connectivity().iter_faces() {
+                assert_eq!(ch[f], expected);
+            }
+        };
+        check(true, 1.5);
+        check(false, 1.0);
+    }
+
+    #[test]
+    fn test_blend_different_topology() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let sphere = primitives::UVSphere::build(Vec3::ZERO, 8, 8, 1.0).unwrap();
+        let err = blend_meshes(&cube, &sphere, 0.5, false).unwrap_err();
+        assert!(err.to_string().contains("has 8 vertices"));
+
+        // Same counts, but faces built in a different order
+        let reversed = {
+            let conn = cube.read_connectivity();
+            let positions = cube.read_positions();
+            let vertex_idx: HashMap<VertexId, usize> = conn
+                .iter_vertices()
+                .enumerate()
+                .map(|(i, (v, _))| (v, i))
+                .collect();
+            let mut polygons = conn
+                .iter_faces()
+                .map(|(f, _)| {
+                    conn.face_vertices(f)
+                        .iter()
+                        .map(|v| vertex_idx[v])
+                        .collect_vec()
+                })
+                .collect_vec();
+            polygons.rotate_left(1);
+            let points = conn
+                .iter_vertices()
+                .map(|(v, _)| positions[v])
+                .collect_vec();
+            HalfEdgeMesh::build_from_polygons(&points, &polygons).unwrap()
+        };
+        let err = blend_meshes(&cube, &reversed, 0.5, false).unwrap_err();
+        assert!(err.to_string().contains("halfedge"));
+    }
+}
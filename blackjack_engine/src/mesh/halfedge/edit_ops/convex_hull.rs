@@ -0,0 +1,363 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use float_ord::FloatOrd;
+
+use crate::prelude::*;
+
+/// Adjacent triangles of the hull are merged into a single face when the
+/// angle between their normals is below this tolerance, in radians.
+pub const HULL_COPLANAR_TOLERANCE: f32 = 0.1 * std::f32::consts::PI / 180.0;
+
+/// A triangle of the hull being built, with its vertices in counter-clockwise
+/// order when seen from outside.
+struct HullTriangle {
+    vertices: [usize; 3],
+    normal: Vec3,
+    offset: f32,
+    /// The points in front of this triangle, not yet inside the hull.
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl HullTriangle {
+    fn new(points: &[Vec3], vertices: [usize; 3]) -> Self {
+        let [a, b, c] = vertices.map(|v| points[v]);
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        Self {
+            vertices,
+            normal,
+            offset: normal.dot(a),
+            outside: vec![],
+            alive: true,
+        }
+    }
+
+    fn distance(&self, p: Vec3) -> f32 {
+        self.normal.dot(p) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Returns the convex hull of the vertex positions of `mesh`, as a closed mesh
+/// with its faces pointing outwards. Coplanar triangles of the hull are merged
+/// into a single polygon, so the hull of a box has 6 quads.
+///
+/// When all the points are coplanar, the result is a single polygon. Fails
+/// when the points are all collinear.
+pub fn convex_hull(mesh: &HalfEdgeMesh) -> Result<HalfEdgeMesh> {
+    let points = {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        conn.iter_vertices()
+            .map(|(v, _)| positions[v])
+            .unique_by(|p| p.to_array().map(FloatOrd))
+            .collect_vec()
+    };
+    if points.is_empty() {
+        bail!("Can't compute the convex hull of a mesh with no vertices");
+    }
+
+    let (min, max) = points.iter().fold((points[0], points[0]), |(min, max), p| {
+        (min.min(*p), max.max(*p))
+    });
+    let eps = (max - min).max_element() * 1e-5;
+    let degenerate = || anyhow!("Can't compute the convex hull of collinear points");
+
+    // --- Initial tetrahedron, from the most distant points ---
+    let farthest = |distance: &dyn Fn(Vec3) -> f32| {
+        (0..points.len())
+            .max_by_key(|i| FloatOrd(distance(points[*i])))
+            .map(|i| (i, distance(points[i])))
+            .unwrap()
+    };
+    let p0 = (0..points.len())
+        .min_by_key(|i| FloatOrd(points[*i].x))
+        .unwrap();
+    let (p1, d1) = farthest(&|p| p.distance(points[p0]));
+    if d1 <= eps {
+        return Err(degenerate());
+    }
+    let axis = (points[p1] - points[p0]).normalize();
+    let (p2, d2) = farthest(&|p| {
+        let rel = p - points[p0];
+        (rel - axis * rel.dot(axis)).length()
+    });
+    if d2 <= eps {
+        return Err(degenerate());
+    }
+    let normal = (points[p1] - points[p0])
+        .cross(points[p2] - points[p0])
+        .normalize();
+    let (p3, d3) = farthest(&|p| (p - points[p0]).dot(normal).abs());
+    if d3 <= eps {
+        return planar_hull(&points, p0, normal);
+    }
+
+    let centroid = (points[p0] + points[p1] + points[p2] + points[p3]) / 4.0;
+    let mut triangles = vec![];
+    for [a, b, c] in [[p0, p1, p2], [p0, p1, p3], [p1, p2, p3], [p2, p0, p3]] {
+        let triangle = HullTriangle::new(&points, [a, b, c]);
+        triangles.push(if triangle.distance(centroid) > 0.0 {
+            HullTriangle::new(&points, [a, c, b])
+        } else {
+            triangle
+        });
+    }
+    assign_outside(&points, &mut triangles, 0, 0..points.len(), eps);
+
+    // --- Quickhull: expand the hull towards the farthest outside point ---
+    while let Some(t) = triangles
+        .iter()
+        .position(|t| t.alive && !t.outside.is_empty())
+    {
+        let apex = triangles[t]
+            .outside
+            .iter_cpy()
+            .max_by_key(|p| FloatOrd(triangles[t].distance(points[*p])))
+            .unwrap();
+
+        let visible = (0..triangles.len())
+            .filter(|t| triangles[*t].alive && triangles[*t].distance(points[apex]) > eps)
+            .collect_vec();
+        let visible_edges: HashSet<(usize, usize)> =
+            visible.iter().flat_map(|t| triangles[*t].edges()).collect();
+        // The edges between the visible and hidden triangles. They keep their
+        // direction, so the new triangles face outwards too.
+        let horizon = visible_edges
+            .iter()
+            .filter(|(a, b)| !visible_edges.contains(&(*b, *a)))
+            .copied()
+            .collect_vec();
+
+        let mut orphans = vec![];
+        for t in visible {
+            triangles[t].alive = false;
+            orphans.append(&mut triangles[t].outside);
+        }
+        let first_new = triangles.len();
+        for (a, b) in horizon {
+            triangles.push(HullTriangle::new(&points, [a, b, apex]));
+        }
+        let orphans = orphans.into_iter().filter(|p| *p != apex);
+        assign_outside(&points, &mut triangles, first_new, orphans, eps);
+    }
+
+    let triangles = triangles.into_iter().filter(|t| t.alive).collect_vec();
+    let polygons = merge_coplanar(&triangles)?;
+    HalfEdgeMesh::build_from_polygons(&points, &polygons)
+}
+
+/// Moves each of the `candidates` to the outside set of the first triangle
+/// starting at `first` that has it in front. Points behind all of them are
+/// inside the hull, and discarded.
+fn assign_outside(
+    points: &[Vec3],
+    triangles: &mut [HullTriangle],
+    first: usize,
+    candidates: impl IntoIterator<Item = usize>,
+    eps: f32,
+) {
+    for p in candidates {
+        if let Some(t) = triangles[first..]
+            .iter_mut()
+            .find(|t| t.distance(points[p]) > eps)
+        {
+            t.outside.push(p);
+        }
+    }
+}
+
+/// Groups the triangles of a hull into regions with the same normal, within
+/// [`HULL_COPLANAR_TOLERANCE`], and returns the outline of each region.
+fn merge_coplanar(triangles: &[HullTriangle]) -> Result<Vec<Vec<usize>>> {
+    let mut edge_triangle = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for edge in triangle.edges() {
+            edge_triangle.insert(edge, t);
+        }
+    }
+
+    let cos_tolerance = HULL_COPLANAR_TOLERANCE.cos();
+    let mut region = vec![usize::MAX; triangles.len()];
+    let mut polygons = vec![];
+    for seed in 0..triangles.len() {
+        if region[seed] != usize::MAX {
+            continue;
+        }
+        // Regions grow by comparing with the normal of the first triangle,
+        // so curved surfaces made of many small triangles aren't merged.
+        let seed_normal = triangles[seed].normal;
+        let region_id = polygons.len();
+        let mut stack = vec![seed];
+        let mut members = vec![];
+        region[seed] = region_id;
+        while let Some(t) = stack.pop() {
+            members.push(t);
+            for (a, b) in triangles[t].edges() {
+                let neighbour = *edge_triangle
+                    .get(&(b, a))
+                    .ok_or_else(|| anyhow!("The convex hull is not closed. This is a bug."))?;
+                if region[neighbour] == usize::MAX
+                    && triangles[neighbour].normal.dot(seed_normal) >= cos_tolerance
+                {
+                    region[neighbour] = region_id;
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        // The outline is made of the edges not shared with the same region.
+        // All their twins were found above.
+        let next: HashMap<usize, usize> = members
+            .iter()
+            .flat_map(|t| triangles[*t].edges())
+            .filter(|(a, b)| region[edge_triangle[&(*b, *a)]] != region_id)
+            .collect();
+        let start = *next.keys().next().unwrap();
+        let mut polygon = vec![start];
+        let mut v = next[&start];
+        while v != start {
+            if polygon.len() > next.len() {
+                bail!("Could not merge the faces of the convex hull. This is a bug.");
+            }
+            polygon.push(v);
+            v = next[&v];
+        }
+        polygons.push(polygon);
+    }
+    Ok(polygons)
+}
+
+/// The convex hull of points lying on a plane through `points[origin]` with
+/// the given `normal`: a single polygon, facing along `normal`.
+fn planar_hull(points: &[Vec3], origin: usize, normal: Vec3) -> Result<HalfEdgeMesh> {
+    let (u, v) = normal.any_orthonormal_pair();
+    let project = |p: usize| {
+        let rel = points[p] - points[origin];
+        Vec2::new(rel.dot(u), rel.dot(v))
+    };
+    let mut sorted = (0..points.len()).collect_vec();
+    sorted.sort_by_key(|p| {
+        let p = project(*p);
+        (FloatOrd(p.x), FloatOrd(p.y))
+    });
+
+    // Andrew's monotone chain. Collinear points are left out.
+    let cross =
+        |o: usize, a: usize, b: usize| (project(a) - project(o)).perp_dot(project(b) - project(o));
+    let mut hull: Vec<usize> = vec![];
+    for pass in [sorted.clone(), sorted.into_iter().rev().collect_vec()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // The last point is the first one of the next chain
+        hull.pop();
+    }
+    if hull.len() < 3 {
+        bail!("Can't compute the convex hull of collinear points");
+    }
+    HalfEdgeMesh::build_from_polygons(points, &[hull])
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Returns the convex hull of the vertices of `mesh`, as a closed mesh.
+    /// Coplanar triangles of the hull are merged, so the hull of a box is
+    /// made of 6 quads. When all the vertices lie on a plane, the hull is a
+    /// single polygon.
+    #[lua(under = "Ops")]
+    pub fn convex_hull(mesh: &HalfEdgeMesh) -> Result<HalfEdgeMesh> {
+        super::convex_hull(mesh)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::random::BjkRng;
+
+    fn points_mesh(points: &[Vec3]) -> HalfEdgeMesh {
+        let mesh = HalfEdgeMesh::new();
+        {
+            let mut conn = mesh.write_connectivity();
+            let mut positions = mesh.write_positions();
+            for p in points {
+                conn.alloc_vertex(&mut positions, *p, None);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_random_point_cloud() {
+        let mut rng = BjkRng::new(7);
+        let points = (0..200)
+            .map(|_| rng.point_in_box(-Vec3::ONE, Vec3::ONE))
+            .collect_vec();
+        let hull = convex_hull(&points_mesh(&points)).unwrap();
+        assert!(analysis::mesh_stats(&hull).unwrap().is_closed);
+
+        let conn = hull.read_connectivity();
+        let positions = hull.read_positions();
+        for (face, _) in conn.iter_faces() {
+            let verts = conn.face_vertices(face);
+            let [a, b, c] = [0, 1, 2].map(|i| positions[verts[i]]);
+            let normal = (b - a).cross(c - a).normalize();
+            // All the points are inside or on the hull
+            for p in &points {
+                assert!(normal.dot(*p - a) < 1e-4);
+            }
+            // Convex: the faces around each edge bend inwards
+            for h in conn.at_face(face).halfedges().unwrap() {
+                let twin = conn.at_halfedge(h).twin().try_end().unwrap();
+                let neighbour = conn.at_halfedge(twin).face().try_end().unwrap();
+                for v in conn.face_vertices(neighbour) {
+                    assert!(normal.dot(positions[v] - a) < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_hull_merges_coplanar_faces() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let hull = convex_hull(&cube).unwrap();
+        let conn = hull.read_connectivity();
+        assert_eq!(conn.num_vertices(), 8);
+        assert_eq!(conn.num_faces(), 6);
+        assert!(conn
+            .iter_faces()
+            .all(|(f, _)| conn.face_vertices(f).len() == 4));
+        drop(conn);
+        let stats = analysis::mesh_stats(&hull).unwrap();
+        assert!((stats.volume - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_degenerate_hulls() {
+        let quad = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        let hull = convex_hull(&quad).unwrap();
+        assert_eq!(hull.read_connectivity().num_faces(), 1);
+        assert_eq!(hull.read_connectivity().num_vertices(), 4);
+
+        let line = points_mesh(&[Vec3::ZERO, Vec3::X, Vec3::X * 2.0]);
+        let err = convex_hull(&line).unwrap_err();
+        assert!(err.to_string().contains("collinear"), "{err}");
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Halfedge channels store per-corner data, like UVs, at the source vertex of
+//! each halfedge. Ops that change the topology create new corners, and the
+//! helpers in this module give them a value.
+//!
+//! The usual flow is to take the [`corners`] of the mesh before the op, give
+//! the corners that the op knows about a value with the op-specific helpers,
+//! and finally call [`fill_new_corners`] for the rest.
+
+use std::collections::BTreeSet;
+
+use crate::prelude::*;
+
+use super::chamfer_vertex;
+
+/// Returns the corners of `mesh`, that is, the halfedges that are on a face.
+/// Taken before an op, these are the corners that have a value.
+pub fn corners(mesh: &HalfEdgeMesh) -> HashSet<HalfEdgeId> {
+    mesh.read_connectivity()
+        .iter_halfedges()
+        .filter(|(_, h)| h.face.is_some())
+        .map(|(h, _)| h)
+        .collect()
+}
+
+/// Copies the value of the `src` corner to `dst`, if `src` has one.
+fn copy_known(
+    values: &mut ChannelWriteBatch<HalfEdgeId>,
+    known: &mut HashSet<HalfEdgeId>,
+    dst: HalfEdgeId,
+    src: HalfEdgeId,
+) {
+    if known.contains(&src) {
+        values.copy(dst, src);
+        known.insert(dst);
+    }
+}
+
+/// Gives a value to the corners of the side walls created when extruding. The
+/// `boundary` are the halfedges of the extruded faces returned by
+/// [`extrude_faces`](super::extrude_faces). Each wall is a stretched copy of
+/// its boundary edge, so its corners get the values of the extruded face at
+/// the same end of the edge.
+pub fn copy_extruded_corners(
+    mesh: &HalfEdgeMesh,
+    boundary: &[HalfEdgeId],
+    known: &mut HashSet<HalfEdgeId>,
+) -> Result<()> {
+    if !mesh.channels.has_channels(ChannelKeyType::HalfEdgeId) {
+        return Ok(());
+    }
+    let conn = mesh.read_connectivity();
+    let mut values = ChannelWriteBatch::new();
+    for b in boundary.iter_cpy() {
+        // The wall goes w -> v -> v' -> w', where b goes from v to w.
+        let b_next = conn.at_halfedge(b).next().try_end()?;
+        let s0 = conn.at_halfedge(b).twin().try_end()?;
+        let walls = conn.halfedge_loop(s0);
+        if walls.len() != 4 || conn.at_halfedge(s0).is_boundary()? {
+            continue;
+        }
+        copy_known(&mut values, known, walls[0], b_next);
+        copy_known(&mut values, known, walls[1], b);
+        copy_known(&mut values, known, walls[2], b);
+        copy_known(&mut values, known, walls[3], b_next);
+    }
+    values.apply(&mesh.channels);
+    Ok(())
+}
+
+/// Gives a value to the corners of the strips created when beveling. The
+/// `beveled` halfedges are the selected edges and their twins, which stay on
+/// the faces at each side of the bevel. The long edges of each strip get the
+/// values of the face they are next to.
+pub fn copy_beveled_corners(
+    mesh: &HalfEdgeMesh,
+    beveled: &BTreeSet<HalfEdgeId>,
+    known: &mut HashSet<HalfEdgeId>,
+) -> Result<()> {
+    if !mesh.channels.has_channels(ChannelKeyType::HalfEdgeId) {
+        return Ok(());
+    }
+    let conn = mesh.read_connectivity();
+    let mut values = ChannelWriteBatch::new();
+    for b in beveled.iter_cpy() {
+        if conn.at_halfedge(b).is_boundary()? {
+            continue;
+        }
+        let s0 = conn.at_halfedge(b).twin().try_end()?;
+        if conn.at_halfedge(s0).is_boundary()? {
+            continue;
+        }
+        // The long edge goes w -> v, and is followed by the rail at v.
+        let rail = conn.at_halfedge(s0).next().try_end()?;
+        copy_known(
+            &mut values,
+            known,
+            s0,
+            conn.at_halfedge(b).next().try_end()?,
+        );
+        copy_known(&mut values, known, rail, b);
+    }
+    values.apply(&mesh.channels);
+    Ok(())
+}
+
+/// Same as [`chamfer_vertex`], but the corners at the new vertices are
+/// interpolated between the corners of the faces around `v` at both ends of
+/// each edge. The corners of the new face are left for [`fill_new_corners`].
+pub fn chamfer_vertex_with_corners(
+    mesh: &HalfEdgeMesh,
+    v: VertexId,
+    interpolation_factor: f32,
+    known: &mut HashSet<HalfEdgeId>,
+) -> Result<()> {
+    let t = interpolation_factor;
+    // For each face around v: The outgoing halfedge on the face, which is also
+    // the corner at v, the corners at the far ends of both edges touching v,
+    // and the index of the other outgoing halfedge of the face.
+    let faces = {
+        let conn = mesh.read_connectivity();
+        let outgoing = conn.at_vertex(v).outgoing_halfedges()?;
+        let mut faces = vec![];
+        for h in outgoing.iter_cpy() {
+            if conn.at_halfedge(h).is_boundary()? {
+                continue;
+            }
+            let prev = conn.at_halfedge(h).previous().try_end()?;
+            let other = conn.at_halfedge(prev).twin().try_end()?;
+            let other_idx = outgoing
+                .iter()
+                .position(|o| *o == other)
+                .ok_or_else(|| anyhow!("Halfedge {other:?} should be outgoing from {v:?}"))?;
+            faces.push((h, conn.at_halfedge(h).next().try_end()?, prev, other_idx));
+        }
+        faces
+    };
+
+    let (_, new_vertices) = chamfer_vertex(
+        &mut mesh.write_connectivity(),
+        &mut mesh.write_positions(),
+        v,
+        interpolation_factor,
+    )?;
+    if !mesh.channels.has_channels(ChannelKeyType::HalfEdgeId) {
+        return Ok(());
+    }
+
+    let mut values = ChannelWriteBatch::new();
+    for (h, next, prev, other_idx) in faces {
+        // The outgoing halfedges keep their ids, and now start at the new
+        // vertex on their edge. The corner at the other new vertex of the face
+        // is on the edge that cut it.
+        let x_other = new_vertices[other_idx];
+        let h_other = {
+            let conn = mesh.read_connectivity();
+            let face = conn.at_halfedge(h).face().try_end()?;
+            conn.face_edges(face)
+                .into_iter()
+                .find(|c| conn.at_halfedge(*c).vertex().try_end().ok() == Some(x_other))
+        };
+        if let Some(h_other) = h_other {
+            if known.contains(&h) && known.contains(&prev) {
+                values.interpolate(h_other, h, prev, t);
+                known.insert(h_other);
+            }
+        }
+        if known.contains(&h) && known.contains(&next) {
+            values.interpolate(h, h, next, t);
+        }
+    }
+    values.apply(&mesh.channels);
+    Ok(())
+}
+
+/// Gives a value to every corner of `mesh` that is not `known`, copying it from
+/// a neighbouring corner around the same vertex, on one of the faces across
+/// the edges of the corner. Corners are filled from the known ones outwards.
+/// Corners with no known corner around them keep the default values.
+pub fn fill_new_corners(mesh: &HalfEdgeMesh, mut known: HashSet<HalfEdgeId>) -> Result<()> {
+    if !mesh.channels.has_channels(ChannelKeyType::HalfEdgeId) {
+        return Ok(());
+    }
+    let conn = mesh.read_connectivity();
+    let mut missing = conn
+        .iter_halfedges()
+        .filter(|(h, halfedge)| halfedge.face.is_some() && !known.contains(h))
+        .map(|(h, _)| h)
+        .collect_vec();
+    while !missing.is_empty() {
+        let mut filled = vec![];
+        let mut values = ChannelWriteBatch::new();
+        for h in missing.iter_cpy() {
+            let neighbours = [
+                conn.at_halfedge(h).twin().next().try_end(),
+                conn.at_halfedge(h).previous().twin().try_end(),
+            ];
+            if let Some(src) = neighbours.into_iter().flatten().find(|n| known.contains(n)) {
+                values.copy(h, src);
+                filled.push(h);
+            }
+        }
+        if filled.is_empty() {
+            break;
+        }
+        // The corners filled in this round are only used as sources in the
+        // next one, so their values can be set all at once.
+        values.apply(&mesh.channels);
+        known.extend(filled);
+        missing.retain(|h| !known.contains(h));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::selection::SelectionExpression;
+
+    /// A box with UVs covering the full range on each face.
+    fn uv_box() -> HalfEdgeMesh {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        super::super::set_full_range_uvs(&mut mesh).unwrap();
+        mesh
+    }
+
+    fn uv(mesh: &HalfEdgeMesh, h: HalfEdgeId) -> Vec3 {
+        mesh.channels
+            .read_channel_by_name::<HalfEdgeId, Vec3>("uv")
+            .unwrap()[h]
+    }
+
+    #[test]
+    fn test_merge_keeps_corners() {
+        let mut mesh = uv_box();
+        let other = uv_box();
+        mesh.merge_with(&other);
+
+        let conn = mesh.read_connectivity();
+        let other_conn = other.read_connectivity();
+        let merged = conn.iter_halfedges().map(|(h, _)| h).collect_vec();
+        let originals = other_conn.iter_halfedges().map(|(h, _)| h).collect_vec();
+        // The halfedges of the merged mesh come after the existing ones
+        let offset = merged.len() - originals.len();
+        for (i, h) in originals.iter_cpy().enumerate() {
+            assert_eq!(uv(&mesh, merged[offset + i]), uv(&other, h));
+        }
+    }
+
+    #[test]
+    fn test_extrude_walls() {
+        let mesh = uv_box();
+        let original_faces = mesh
+            .read_connectivity()
+            .iter_faces()
+            .map(|(f, _)| f)
+            .collect::<HashSet<_>>();
+        let top = mesh
+            .resolve_face_selection_full(&SelectionExpression::from_ids([2]))
+            .unwrap();
+
+        let mut known = corners(&mesh);
+        let boundary = super::super::extrude_faces(
+            &mut mesh.write_connectivity(),
+            &mut mesh.write_positions(),
+            &top,
+            1.0,
+        )
+        .unwrap();
+        copy_extruded_corners(&mesh, &boundary, &mut known).unwrap();
+        fill_new_corners(&mesh, known).unwrap();
+
+        let conn = mesh.read_connectivity();
+        let walls = conn
+            .iter_faces()
+            .filter(|(f, _)| !original_faces.contains(f))
+            .map(|(f, _)| f)
+            .collect_vec();
+        assert_eq!(walls.len(), 4);
+        for wall in walls {
+            let uvs = conn
+                .face_edges(wall)
+                .iter()
+                .map(|h| uv(&mesh, *h))
+                .collect_vec();
+            // Each wall spans one edge of the extruded face, so the corners
+            // at both ends of the wall match, and are corners of the face.
+            assert_eq!(uvs.len(), 4);
+            for i in 0..4 {
+                let (prev, next) = (uvs[(i + 3) % 4], uvs[(i + 1) % 4]);
+                assert!((uvs[i] == prev) != (uvs[i] == next), "{uvs:?}");
+                assert!(uvs[i].x.fract() == 0.0 && uvs[i].y.fract() == 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chamfer_interpolates_corners() {
+        let mesh = uv_box();
+        let v = mesh.read_connectivity().iter_vertices().next().unwrap().0;
+        let known = corners(&mesh);
+        let mut known_after = known.clone();
+        chamfer_vertex_with_corners(&mesh, v, 0.25, &mut known_after).unwrap();
+        fill_new_corners(&mesh, known_after).unwrap();
+
+        // The corners on the cut, both on the faces around the chamfered
+        // vertex and on the new face, are a quarter along the original edges.
+        let conn = mesh.read_connectivity();
+        let is_quarter = |x: f32| x == 0.25 || x == 0.75;
+        let quarters = conn
+            .iter_halfedges()
+            .filter(|(h, halfedge)| halfedge.face.is_some() && !known.contains(h))
+            .map(|(h, _)| uv(&mesh, h))
+            .filter(|uv| is_quarter(uv.x) || is_quarter(uv.y))
+            .count();
+        assert_eq!(quarters, 6);
+        for (h, halfedge) in conn.iter_halfedges() {
+            if halfedge.face.is_some() {
+                let uv = uv(&mesh, h);
+                assert!(uv.min_element() >= 0.0 && uv.max_element() <= 1.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use crate::prelude::*;
+use crate::progress::{report_warning, ProgressSink, Warning};
+
+/// The kind of curvature computed by [`compute_curvature`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurvatureKind {
+    /// The average of the two principal curvatures. Positive where the
+    /// surface is convex, like the outside of a sphere, and negative where
+    /// it's concave.
+    Mean,
+    /// The product of the two principal curvatures. Positive on bumps and
+    /// dents, negative on saddles, and zero on flat or cylindrical regions.
+    Gaussian,
+}
+
+/// Returns the cotangent of the angle between `a` and `b`, or zero when the
+/// angle is degenerate.
+fn cot(a: Vec3, b: Vec3) -> f32 {
+    let sin = a.cross(b).length();
+    if sin < 1e-12 {
+        0.0
+    } else {
+        a.dot(b) / sin
+    }
+}
+
+/// Returns the angle between `a` and `b`, in radians.
+fn angle(a: Vec3, b: Vec3) -> f32 {
+    let len = a.length() * b.length();
+    if len < 1e-12 {
+        return 0.0;
+    }
+    (a.dot(b) / len).clamp(-1.0, 1.0).acos()
+}
+
+/// Per-vertex sums over the triangles around each vertex.
+#[derive(Default, Clone, Copy)]
+struct VertexSums {
+    /// The sum of the angles at the vertex.
+    angle: f32,
+    /// The mixed Voronoi area of the vertex.
+    area: f32,
+    /// The cotangent Laplacian of the position, before dividing by the area.
+    laplacian: Vec3,
+    /// The area-weighted normal.
+    normal: Vec3,
+}
+
+/// Computes the curvature at every vertex of the mesh, and stores it in the
+/// f32 vertex channel named `out_channel`.
+///
+/// Curvature is estimated from the triangles around each vertex, splitting
+/// polygons into fans. The gaussian curvature is the angle defect, divided by
+/// the mixed Voronoi area of the vertex. On boundary vertices, the defect is
+/// measured against a half turn instead of a full one, so flat boundaries get
+/// zero curvature. The mean curvature is half the length of the cotangent
+/// Laplacian, along the normal. Only the part along the normal counts, which
+/// on boundaries leaves out the pull towards the inside of the mesh.
+///
+/// Vertices with no area around them, like loose vertices or those only
+/// touching collapsed faces, get zero curvature, and a warning is reported.
+pub fn compute_curvature(
+    mesh: &mut HalfEdgeMesh,
+    kind: CurvatureKind,
+    out_channel: &str,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<()> {
+    let mut curvature = Channel::<VertexId, f32>::new();
+    let mut num_degenerate = 0;
+    {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+
+        let mut sums = HashMap::<VertexId, VertexSums>::new();
+        for (f, _) in conn.iter_faces() {
+            let vertices = conn.face_vertices(f);
+            for i in 1..vertices.len().saturating_sub(1) {
+                let tri = [vertices[0], vertices[i], vertices[i + 1]];
+                let p = tri.map(|v| positions[v]);
+                let cross = (p[1] - p[0]).cross(p[2] - p[0]);
+                let tri_area = cross.length() * 0.5;
+                let angles = [0, 1, 2].map(|k| angle(p[(k + 1) % 3] - p[k], p[(k + 2) % 3] - p[k]));
+                let obtuse = angles.iter().position(|a| *a > FRAC_PI_2);
+
+                for k in 0..3 {
+                    let (j, l) = ((k + 1) % 3, (k + 2) % 3);
+                    // The angle at `k` weights the edge opposite to it
+                    let w = cot(p[j] - p[k], p[l] - p[k]);
+                    sums.entry(tri[j]).or_default().laplacian += (p[l] - p[j]) * w;
+                    sums.entry(tri[l]).or_default().laplacian += (p[j] - p[l]) * w;
+
+                    // On obtuse triangles, the Voronoi region would reach
+                    // outside of the triangle, so the area is split instead.
+                    let area = match obtuse {
+                        None => {
+                            (p[l].distance_squared(p[k]) * cot(p[k] - p[j], p[l] - p[j])
+                                + p[j].distance_squared(p[k]) * cot(p[k] - p[l], p[j] - p[l]))
+                                / 8.0
+                        }
+                        Some(o) if o == k => tri_area * 0.5,
+                        Some(_) => tri_area * 0.25,
+                    };
+                    let s = sums.entry(tri[k]).or_default();
+                    s.angle += angles[k];
+                    s.area += area;
+                    s.normal += cross;
+                }
+            }
+        }
+
+        let mut boundary = HashSet::new();
+        for (h, _) in conn.iter_halfedges() {
+            if conn[h].face.is_none() {
+                let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+                boundary.extend([src, dst]);
+            }
+        }
+
+        for (v, _) in conn.iter_vertices() {
+            let s = sums.get(&v).copied().unwrap_or_default();
+            if s.area < 1e-12 {
+                num_degenerate += 1;
+                curvature[v] = 0.0;
+                continue;
+            }
+            curvature[v] = match kind {
+                CurvatureKind::Gaussian => {
+                    let full = if boundary.contains(&v) { PI } else { TAU };
+                    (full - s.angle) / s.area
+                }
+                CurvatureKind::Mean => {
+                    let laplacian = s.laplacian / (2.0 * s.area);
+                    -0.5 * laplacian.dot(s.normal.normalize_or_zero())
+                }
+            };
+        }
+    }
+    if num_degenerate > 0 {
+        report_warning(
+            sink,
+            Warning::new(format!(
+                "{num_degenerate} vertices have no area around them, so their curvature was \
+                 set to zero"
+            )),
+        );
+    }
+    mesh.channels
+        .replace_or_create_channel(out_channel, curvature);
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Computes the curvature of every vertex of `mesh` into the f32 vertex
+    /// channel `out_channel`. The `kind` is either `"Mean"` or `"Gaussian"`.
+    #[lua(under = "Ops")]
+    pub fn compute_curvature(
+        mesh: &mut HalfEdgeMesh,
+        kind: String,
+        out_channel: String,
+    ) -> Result<()> {
+        let kind = match kind.as_str() {
+            "Mean" => CurvatureKind::Mean,
+            "Gaussian" => CurvatureKind::Gaussian,
+            _ => bail!("Invalid curvature kind '{kind}'"),
+        };
+        let sink = crate::progress::current_sink();
+        super::compute_curvature(mesh, kind, &out_channel, sink.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::progress::MockSink;
+
+    fn curvatures(mesh: &mut HalfEdgeMesh, kind: CurvatureKind) -> Vec<(Vec3, f32)> {
+        compute_curvature(mesh, kind, "curvature", None).unwrap();
+        let positions = mesh.read_positions();
+        let curvature = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("curvature")
+            .unwrap();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| (positions[v], curvature[v]))
+            .collect()
+    }
+
+    /// Returns the mean relative error of the curvature of a sphere of
+    /// radius 2, against the exact value.
+    fn sphere_error(subdivisions: u32, kind: CurvatureKind) -> f32 {
+        let radius = 2.0;
+        let expected = match kind {
+            CurvatureKind::Mean => 1.0 / radius,
+            CurvatureKind::Gaussian => 1.0 / (radius * radius),
+        };
+        let mut sphere = primitives::QuadSphere::build(Vec3::ZERO, radius, subdivisions).unwrap();
+        let values = curvatures(&mut sphere, kind);
+        values
+            .iter()
+            .map(|(_, k)| (k / expected - 1.0).abs())
+            .sum::<f32>()
+            / values.len() as f32
+    }
+
+    #[test]
+    fn test_sphere_curvature_converges() {
+        for kind in [CurvatureKind::Gaussian, CurvatureKind::Mean] {
+            let mut previous = f32::INFINITY;
+            for (subdivisions, tolerance) in [(4, 0.1), (8, 0.04), (16, 0.015)] {
+                let error = sphere_error(subdivisions, kind);
+                assert!(error < tolerance, "{kind:?} {subdivisions}: {error}");
+                assert!(error < previous, "{kind:?} {subdivisions}: {error}");
+                previous = error;
+            }
+        }
+    }
+
+    #[test]
+    fn test_flat_grid() {
+        // A 3 by 3 grid of unit squares on the XZ plane
+        let positions = (0..4)
+            .flat_map(|z| (0..4).map(move |x| Vec3::new(x as f32, 0.0, z as f32)))
+            .collect_vec();
+        let idx = |x: usize, z: usize| z * 4 + x;
+        let polygons = (0..3)
+            .flat_map(|z| {
+                (0..3)
+                    .map(move |x| vec![idx(x, z), idx(x, z + 1), idx(x + 1, z + 1), idx(x + 1, z)])
+            })
+            .collect_vec();
+        let mut grid = HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap();
+
+        for (pos, k) in curvatures(&mut grid, CurvatureKind::Mean) {
+            assert!(k.abs() < 1e-4, "{pos}: {k}");
+        }
+        for (pos, k) in curvatures(&mut grid, CurvatureKind::Gaussian) {
+            let is_corner = (pos.x == 0.0 || pos.x == 3.0) && (pos.z == 0.0 || pos.z == 3.0);
+            if !is_corner {
+                assert!(k.abs() < 1e-4, "{pos}: {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_concave_mean_curvature_is_negative() {
+        let mut sphere = primitives::QuadSphere::build(Vec3::ZERO, 1.0, 4).unwrap();
+        // Turning the faces inside out makes the sphere concave
+        let (positions, polygons) = {
+            let conn = sphere.read_connectivity();
+            let positions = sphere.read_positions();
+            let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+            let index: HashMap<VertexId, usize> =
+                vertices.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+            (
+                vertices.iter().map(|v| positions[*v]).collect_vec(),
+                conn.iter_faces()
+                    .map(|(f, _)| {
+                        conn.face_vertices(f)
+                            .iter()
+                            .rev()
+                            .map(|v| index[v])
+                            .collect_vec()
+                    })
+                    .collect_vec(),
+            )
+        };
+        for (_, k) in curvatures(&mut sphere, CurvatureKind::Mean) {
+            assert!(k > 0.0);
+        }
+        let mut inverted = HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap();
+        for (_, k) in curvatures(&mut inverted, CurvatureKind::Mean) {
+            assert!(k < 0.0);
+        }
+    }
+
+    #[test]
+    fn test_loose_vertices_warn() {
+        let mut mesh =
+            primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::splat(2.0)).unwrap();
+        mesh.merge_with(&primitives::Grid::build(1, 1, 1.0, 1.0).unwrap());
+        let sink = MockSink::new(None);
+        compute_curvature(&mut mesh, CurvatureKind::Gaussian, "curvature", Some(&sink)).unwrap();
+        let curvature = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("curvature")
+            .unwrap();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            assert!(curvature[v].is_finite());
+        }
+        assert_eq!(sink.warnings.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::*;
+use crate::progress::{report_progress, report_warning, ProgressSink, Warning};
+
+use super::collapse_edge;
+
+fn neighbors(conn: &MeshConnectivity, v: VertexId) -> Result<HashSet<VertexId>> {
+    conn.at_vertex(v)
+        .outgoing_halfedges()?
+        .iter()
+        .map(|h| Ok(conn.at_halfedge(*h).dst_vertex().try_end()?))
+        .collect()
+}
+
+fn is_boundary_vertex(conn: &MeshConnectivity, v: VertexId) -> Result<bool> {
+    for h in conn.at_vertex(v).outgoing_halfedges()? {
+        if conn.at_halfedge(h).is_boundary()? || conn.at_halfedge(h).twin().is_boundary()? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Returns whether collapsing `h` keeps the mesh manifold. This is the "link
+/// condition": The only vertices connected to both ends of the edge must be
+/// the opposite corners of the triangles at each side of the edge.
+fn can_collapse(conn: &MeshConnectivity, h: HalfEdgeId) -> Result<bool> {
+    let (v, w) = conn.at_halfedge(h).src_dst_pair()?;
+    let t = conn.at_halfedge(h).twin().try_end()?;
+    let is_boundary_edge =
+        conn.at_halfedge(h).is_boundary()? || conn.at_halfedge(t).is_boundary()?;
+    // Collapsing an inner edge between two boundaries pinches the surface.
+    if !is_boundary_edge && is_boundary_vertex(conn, v)? && is_boundary_vertex(conn, w)? {
+        return Ok(false);
+    }
+
+    let mut opposite = HashSet::new();
+    for side in [h, t] {
+        if conn.at_halfedge(side).face_or_boundary()?.is_some()
+            && conn.halfedge_loop_iter(side).count() == 3
+        {
+            opposite.insert(conn.at_halfedge(side).next().dst_vertex().try_end()?);
+        }
+    }
+
+    let common = neighbors(conn, v)?
+        .intersection(&neighbors(conn, w)?)
+        .copied()
+        .collect::<HashSet<_>>();
+    if common != opposite {
+        return Ok(false);
+    }
+    // The opposite corners lose an edge. They need at least three left.
+    for x in opposite {
+        if conn.at_vertex(x).outgoing_halfedges()?.len() <= 3 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Reduces the number of faces of the mesh, repeatedly collapsing its shortest
+/// edges until the face count is below `ratio` times the original one. Each
+/// collapsed edge is replaced by a vertex at its midpoint. Edges whose
+/// collapse would make the mesh non-manifold are kept, so the target is not
+/// always reached. When it isn't, a warning is reported to the `sink`.
+///
+/// Progress is reported to the `sink`, if any, and the operation stops early
+/// with a [`Cancelled`](crate::progress::Cancelled) error when it is
+/// cancelled. Returns the number of collapsed edges.
+pub fn decimate(
+    mesh: &mut HalfEdgeMesh,
+    ratio: f32,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<usize> {
+    if !(0.0..=1.0).contains(&ratio) {
+        bail!("The decimation ratio should be between 0 and 1, got {ratio}");
+    }
+    let mut conn = mesh.write_connectivity();
+    let mut positions = mesh.write_positions();
+
+    let initial_faces = conn.num_faces();
+    let target_faces = (initial_faces as f32 * ratio).ceil() as usize;
+    let mut collapsed = 0;
+
+    'passes: loop {
+        // Collapsing an edge moves its vertex, and changes the length of the
+        // edges around it. Those are skipped until the next pass, when edges
+        // are sorted again.
+        let edges = conn
+            .iter_halfedges()
+            .map(|(h, _)| h)
+            .filter_map(|h| {
+                let (v, w) = conn.at_halfedge(h).src_dst_pair().ok()?;
+                let t = conn.at_halfedge(h).twin().try_end().ok()?;
+                (h < t).then(|| (h, positions[v].distance_squared(positions[w])))
+            })
+            .sorted_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(h, _)| h)
+            .collect_vec();
+
+        let mut touched = HashSet::new();
+        let mut collapsed_this_pass = 0;
+        for h in edges {
+            if conn.num_faces() <= target_faces {
+                break 'passes;
+            }
+            if !conn.halfedges.contains_key(h) {
+                continue;
+            }
+            let (v, w) = conn.at_halfedge(h).src_dst_pair()?;
+            if touched.contains(&v) || touched.contains(&w) || !can_collapse(&conn, h)? {
+                continue;
+            }
+
+            let midpoint = (positions[v] + positions[w]) * 0.5;
+            let v = collapse_edge(&mut conn, h)?;
+            positions[v] = midpoint;
+            touched.insert(v);
+            touched.insert(w);
+            collapsed += 1;
+            collapsed_this_pass += 1;
+
+            let removed = initial_faces - conn.num_faces().min(initial_faces);
+            report_progress(
+                sink,
+                removed as f32 / (initial_faces - target_faces).max(1) as f32,
+            )?;
+        }
+
+        if collapsed_this_pass == 0 {
+            if conn.num_faces() > target_faces {
+                report_warning(
+                    sink,
+                    Warning::new(format!(
+                        "Stopped at {} faces instead of {target_faces}, the remaining edges \
+                         can't be collapsed without making the mesh non-manifold",
+                        conn.num_faces()
+                    )),
+                );
+            }
+            break;
+        }
+    }
+    report_progress(sink, 1.0)?;
+
+    Ok(collapsed)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Reduces the face count of the `mesh` to roughly `ratio` times the
+    /// original count by collapsing its shortest edges. Returns the number of
+    /// collapsed edges.
+    #[lua(under = "Ops")]
+    pub fn decimate(mesh: &mut HalfEdgeMesh, ratio: f32) -> Result<usize> {
+        let sink = crate::progress::current_sink();
+        super::decimate(mesh, ratio, sink.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+    use crate::progress::{Cancelled, MockSink};
+
+    fn sphere() -> HalfEdgeMesh {
+        primitives::UVSphere::build(Vec3::ZERO, 16, 16, 1.0).unwrap()
+    }
+
+    #[test]
+    fn test_decimate_sphere() {
+        let mut mesh = sphere();
+        let initial_faces = mesh.read_connectivity().num_faces();
+        let sink = MockSink::new(None);
+        let collapsed = decimate(&mut mesh, 0.5, Some(&sink)).unwrap();
+
+        assert!(collapsed > 0);
+        assert!(mesh.read_connectivity().num_faces() <= initial_faces / 2 + 1);
+        assert!(validate(&mesh).is_valid());
+        sink.assert_monotonic();
+        assert_eq!(*sink.reports.borrow().last().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_decimate_cancellation() {
+        let mut mesh = sphere();
+        let sink = MockSink::new(Some(5));
+        let err = decimate(&mut mesh, 0.1, Some(&sink)).unwrap_err();
+        assert!(err.is::<Cancelled>());
+        // The op stops as soon as it sees the cancellation
+        assert_eq!(sink.reports.borrow().len(), 5);
+    }
+
+    #[test]
+    fn test_decimate_warns_when_target_not_reached() {
+        let sink = MockSink::new(None);
+        decimate(&mut sphere(), 0.5, Some(&sink)).unwrap();
+        assert!(sink.warnings.borrow().is_empty());
+
+        // A closed mesh can't lose all of its faces
+        let mut mesh = sphere();
+        decimate(&mut mesh, 0.0, Some(&sink)).unwrap();
+        let warnings = sink.warnings.borrow();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("instead of 0"));
+        assert!(validate(&mesh).is_valid());
+    }
+}
@@ -0,0 +1,520 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::mesh::halfedge::id_remap::with_id_remap;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+use super::{dissolve_edge, remove_stale_channel_entries, validate};
+
+/// The elements removed by [`delete`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// The selected faces, and the edges and vertices left with no face.
+    Faces,
+    /// The selected faces only. Their edges and vertices are kept, leaving a
+    /// wire cage where the faces were.
+    FacesKeepBoundary,
+    /// The selected edges. The faces at both sides of each edge are merged,
+    /// or deleted when that's not possible.
+    Edges,
+    /// The selected vertices, and all the faces around them.
+    Vertices,
+}
+
+/// Removes the elements in `selection` from the mesh. The selection refers to
+/// faces, edges or vertices depending on the `mode`. The values of the
+/// removed elements are removed from the channels too.
+pub fn delete(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    mode: DeleteMode,
+) -> Result<()> {
+    match mode {
+        DeleteMode::Faces => {
+            let faces = mesh.resolve_face_selection_full(selection)?;
+            delete_faces(&mut mesh.write_connectivity(), &faces)?;
+        }
+        DeleteMode::FacesKeepBoundary => {
+            let faces = mesh.resolve_face_selection_full(selection)?;
+            delete_faces_keep_boundary(&mut mesh.write_connectivity(), &faces)?;
+        }
+        DeleteMode::Edges => {
+            let halfedges = mesh.resolve_halfedge_selection_full(selection)?;
+            delete_edges(&mut mesh.write_connectivity(), &halfedges)?;
+        }
+        DeleteMode::Vertices => {
+            let vertices = mesh.resolve_vertex_selection_full(selection)?;
+            delete_vertices(&mut mesh.write_connectivity(), &vertices)?;
+        }
+    }
+    remove_stale_channel_entries(mesh)?;
+
+    if cfg!(debug_assertions) {
+        let report = validate(mesh);
+        assert!(
+            report.is_valid(),
+            "Deleting {mode:?} left an invalid mesh: {}",
+            report.summary()
+        );
+    }
+    Ok(())
+}
+
+/// Removes the given `faces` from the mesh, leaving a hole in their place.
+/// Edges that are left with no face at either side are removed too, and so
+/// are the vertices that are left with no edges.
+pub fn delete_faces(conn: &mut MeshConnectivity, faces: &[FaceId]) -> Result<()> {
+    let mut touched_halfedges = HashSet::new();
+    let mut touched_vertices = HashSet::new();
+    for face in faces.iter_cpy() {
+        // The same face may be listed more than once
+        if !conn.faces.contains_key(face) {
+            continue;
+        }
+        for h in conn.at_face(face).halfedges()? {
+            conn[h].face = None;
+            touched_halfedges.insert(h);
+            touched_vertices.insert(conn.at_halfedge(h).vertex().try_end()?);
+        }
+        conn.remove_face(face);
+    }
+
+    // --- Remove the edges with no faces left ---
+    let mut removed = HashSet::new();
+    for h in touched_halfedges.iter_cpy() {
+        let t = conn.at_halfedge(h).twin().try_end()?;
+        if conn[t].face.is_none() {
+            removed.insert(h);
+            removed.insert(t);
+        }
+    }
+    for h in removed.iter_cpy() {
+        conn.remove_halfedge(h);
+    }
+
+    // --- Fix or remove the vertices that lost their halfedge ---
+    let mut outgoing = HashMap::<VertexId, HalfEdgeId>::new();
+    for (h, halfedge) in conn.iter_halfedges() {
+        if let Some(v) = halfedge.vertex {
+            if touched_vertices.contains(&v) {
+                outgoing.entry(v).or_insert(h);
+            }
+        }
+    }
+    for v in touched_vertices.iter_cpy() {
+        match outgoing.get(&v) {
+            Some(h) => {
+                if conn[v]
+                    .halfedge
+                    .map_or(true, |vh| !conn.halfedges.contains_key(vh))
+                {
+                    conn[v].halfedge = Some(*h);
+                }
+            }
+            None => conn.remove_vertex(v),
+        }
+    }
+
+    // --- Link the boundary halfedges around the new holes ---
+    let boundary = conn
+        .iter_halfedges()
+        .filter(|(_, halfedge)| halfedge.face.is_none())
+        .map(|(h, _)| h)
+        .collect_vec();
+    for h in boundary {
+        let t = conn.at_halfedge(h).twin().try_end()?;
+        // Edges with no face at either side (e.g. polylines) are left as is.
+        if conn[t].face.is_none()
+            || !touched_vertices.contains(&conn.at_halfedge(t).vertex().try_end()?)
+        {
+            continue;
+        }
+        // Rotate around the destination vertex of `h`, through the faces,
+        // until we find the next outgoing halfedge on the boundary.
+        let mut o = t;
+        let mut count = 0;
+        let next = loop {
+            let candidate = conn.at_halfedge(o).previous().twin().try_end()?;
+            if conn[candidate].face.is_none() {
+                break candidate;
+            }
+            o = candidate;
+            count += 1;
+            if count > MAX_LOOP_ITERATIONS {
+                bail!("Could not find the next boundary halfedge. Is the mesh malformed?");
+            }
+        };
+        conn[h].next = Some(next);
+    }
+
+    Ok(())
+}
+
+/// Removes the given `faces` from the mesh, but keeps all their edges and
+/// vertices. The halfedges of each face keep their `next` pointers, so each
+/// removed face leaves a hole of its own.
+pub fn delete_faces_keep_boundary(conn: &mut MeshConnectivity, faces: &[FaceId]) -> Result<()> {
+    for face in faces.iter_cpy() {
+        if !conn.faces.contains_key(face) {
+            continue;
+        }
+        for h in conn.at_face(face).halfedges()? {
+            conn[h].face = None;
+        }
+        conn.remove_face(face);
+    }
+    Ok(())
+}
+
+/// Removes the edges of the given `halfedges`, merging the faces at both sides
+/// of each edge into one. When the edge is on the boundary, or the merged face
+/// would visit some vertex twice, the faces next to the edge are deleted
+/// instead. Edges with no face at either side are simply removed.
+///
+/// Removing all the edges around a vertex but one leaves that edge dangling
+/// inside the merged face. Dangling edges are removed along with their tip.
+pub fn delete_edges(conn: &mut MeshConnectivity, halfedges: &[HalfEdgeId]) -> Result<()> {
+    for h in halfedges.iter_cpy() {
+        // Both halfedges of an edge may be listed, and removing an edge may
+        // remove others in the list.
+        if !conn.halfedges.contains_key(h) {
+            continue;
+        }
+        let t = conn.at_halfedge(h).twin().try_end()?;
+        match (conn[h].face, conn[t].face) {
+            (Some(f_l), Some(f_r)) if f_l != f_r => {
+                dissolve_edge(conn, h)?;
+                remove_dangling_edges(conn, f_l)?;
+                if is_degenerate(conn, f_l)? {
+                    delete_faces(conn, &[f_l])?;
+                }
+            }
+            (None, None) => remove_wire_edge(conn, h)?,
+            (f_l, f_r) => {
+                let faces = f_l.into_iter().chain(f_r).collect_vec();
+                delete_faces(conn, &faces)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the given `vertices`, along with all the faces around them and the
+/// edges and vertices left unused.
+pub fn delete_vertices(conn: &mut MeshConnectivity, vertices: &[VertexId]) -> Result<()> {
+    let mut faces = vec![];
+    for v in vertices.iter_cpy() {
+        if conn.vertices.contains_key(v) {
+            faces.extend(conn.at_vertex(v).adjacent_faces()?);
+        }
+    }
+    delete_faces(conn, &faces)?;
+
+    for v in vertices.iter_cpy() {
+        if !conn.vertices.contains_key(v) {
+            continue;
+        }
+        // Only edges with no face at either side can be left around `v`
+        for h in conn.at_vertex(v).outgoing_halfedges()? {
+            if conn.halfedges.contains_key(h) {
+                remove_wire_edge(conn, h)?;
+            }
+        }
+        if conn.vertices.contains_key(v) {
+            conn.remove_vertex(v);
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `face` has less than three sides, or visits some vertex
+/// more than once.
+fn is_degenerate(conn: &MeshConnectivity, face: FaceId) -> Result<bool> {
+    let vertices = conn.at_face(face).vertices()?;
+    Ok(vertices.len() < 3 || vertices.iter().duplicates().next().is_some())
+}
+
+/// Removes the edges of `face` that go to a vertex with no other edges and
+/// back, along with that vertex.
+fn remove_dangling_edges(conn: &mut MeshConnectivity, face: FaceId) -> Result<()> {
+    loop {
+        let halfedges = conn.at_face(face).halfedges()?;
+        if halfedges.len() <= 2 {
+            return Ok(());
+        }
+        let x = match halfedges
+            .iter_cpy()
+            .find(|h| conn[*h].next.is_some() && conn[*h].next == conn[*h].twin)
+        {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let s = conn.at_halfedge(x).twin().try_end()?;
+        let prev = conn.at_halfedge(x).previous().try_end()?;
+        let next = conn.at_halfedge(s).next().try_end()?;
+        let (v, tip) = conn.at_halfedge(x).src_dst_pair()?;
+
+        conn[prev].next = Some(next);
+        if conn[face].halfedge == Some(x) || conn[face].halfedge == Some(s) {
+            conn[face].halfedge = Some(next);
+        }
+        if conn[v].halfedge == Some(x) {
+            conn[v].halfedge = Some(next);
+        }
+        conn.remove_halfedge(x);
+        conn.remove_halfedge(s);
+        conn.remove_vertex(tip);
+    }
+}
+
+/// Removes the edge of `h`, which has no face at either side, and links the
+/// halfedges around it. Vertices left with no edges are removed.
+fn remove_wire_edge(conn: &mut MeshConnectivity, h: HalfEdgeId) -> Result<()> {
+    let t = conn.at_halfedge(h).twin().try_end()?;
+    let (v, w) = conn.at_halfedge(h).src_dst_pair()?;
+    let h_next = conn.at_halfedge(h).next().try_end()?;
+    let t_next = conn.at_halfedge(t).next().try_end()?;
+    let h_prev = conn.at_halfedge(h).previous().try_end()?;
+    let t_prev = conn.at_halfedge(t).previous().try_end()?;
+
+    // When the loop turns around at a vertex, the edge is its only one
+    if h_next == t {
+        conn.remove_vertex(w);
+    } else {
+        conn[t_prev].next = Some(h_next);
+        if conn[w].halfedge == Some(t) {
+            conn[w].halfedge = Some(h_next);
+        }
+    }
+    if t_next == h {
+        conn.remove_vertex(v);
+    } else {
+        conn[h_prev].next = Some(t_next);
+        if conn[v].halfedge == Some(h) {
+            conn[v].halfedge = Some(t_next);
+        }
+    }
+    conn.remove_halfedge(h);
+    conn.remove_halfedge(t);
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Removes the selected `faces` from the mesh, along with the edges and
+    /// vertices that are no longer used by any face.
+    #[lua(under = "Ops")]
+    pub fn delete_faces(mesh: &mut HalfEdgeMesh, faces: SelectionExpression) -> Result<()> {
+        let faces = mesh.resolve_face_selection_full(&faces)?;
+        with_id_remap(mesh, |mesh| {
+            super::delete_faces(&mut mesh.write_connectivity(), &faces)
+        })?;
+        Ok(())
+    }
+
+    /// Removes the elements in `selection` from the mesh. The `mode` is one
+    /// of:
+    /// - `"Faces"`: The selected faces, and the edges and vertices that are
+    ///   no longer used by any face. This is the default.
+    /// - `"FacesKeepBoundary"`: The selected faces only, keeping their edges
+    ///   and vertices.
+    /// - `"Edges"`: The selected edges, merging the faces at both sides.
+    /// - `"Vertices"`: The selected vertices, and the faces around them.
+    #[lua(under = "Ops")]
+    pub fn delete(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        mode: Option<String>,
+    ) -> Result<()> {
+        let mode = match mode.as_deref() {
+            None | Some("Faces") => DeleteMode::Faces,
+            Some("FacesKeepBoundary") => DeleteMode::FacesKeepBoundary,
+            Some("Edges") => DeleteMode::Edges,
+            Some("Vertices") => DeleteMode::Vertices,
+            Some(mode) => bail!("Invalid delete mode '{mode}'"),
+        };
+        with_id_remap(mesh, |mesh| super::delete(mesh, &selection, mode))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn delete_from_box(ids: &[u32]) -> HalfEdgeMesh {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let faces = mesh
+            .resolve_face_selection_full(&SelectionExpression::from_ids(ids.iter_cpy()))
+            .unwrap();
+        delete_faces(&mut mesh.write_connectivity(), &faces).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn test_delete_one_face() {
+        let mesh = delete_from_box(&[0]);
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_faces(), 5);
+        assert_eq!(conn.num_vertices(), 8);
+        // The edges around the hole are kept, as boundary edges
+        assert_eq!(conn.num_halfedges(), 24);
+        drop(conn);
+        assert!(validate(&mesh).is_valid());
+    }
+
+    #[test]
+    fn test_delete_faces_removes_loose_elements() {
+        // Deleting all faces but one leaves a single quad
+        let mesh = delete_from_box(&[0, 1, 2, 3, 4]);
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_faces(), 1);
+        assert_eq!(conn.num_vertices(), 4);
+        assert_eq!(conn.num_halfedges(), 8);
+        drop(conn);
+        assert!(validate(&mesh).is_valid());
+
+        let empty = delete_from_box(&[0, 1, 2, 3, 4, 5]);
+        let conn = empty.read_connectivity();
+        assert_eq!(conn.num_vertices(), 0);
+        assert_eq!(conn.num_halfedges(), 0);
+    }
+
+    /// A grid of 3x3 unit quads on the XY plane, with 16 vertices and 24
+    /// edges. The face at column `i` and row `j` has index `3 * j + i`.
+    fn grid() -> HalfEdgeMesh {
+        let points = (0..4)
+            .flat_map(|j| (0..4).map(move |i| Vec3::new(i as f32, j as f32, 0.0)))
+            .collect_vec();
+        let v = |i: u32, j: u32| j * 4 + i;
+        let polygons = (0..3)
+            .flat_map(|j| (0..3).map(move |i| [v(i, j), v(i + 1, j), v(i + 1, j + 1), v(i, j + 1)]))
+            .collect_vec();
+        HalfEdgeMesh::build_from_polygons(&points, &polygons).unwrap()
+    }
+
+    fn vertex_at(mesh: &HalfEdgeMesh, i: u32, j: u32) -> VertexId {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .find(|(v, _)| positions[*v] == Vec3::new(i as f32, j as f32, 0.0))
+            .unwrap()
+            .0
+    }
+
+    fn halfedge_between(mesh: &HalfEdgeMesh, a: (u32, u32), b: (u32, u32)) -> HalfEdgeId {
+        let (a, b) = (vertex_at(mesh, a.0, a.1), vertex_at(mesh, b.0, b.1));
+        mesh.read_connectivity()
+            .at_vertex(a)
+            .halfedge_to(b)
+            .try_end()
+            .unwrap()
+    }
+
+    fn assert_counts(mesh: &HalfEdgeMesh, faces: usize, vertices: usize, edges: usize) {
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_faces(), faces);
+        assert_eq!(conn.num_vertices(), vertices);
+        assert_eq!(conn.num_halfedges(), edges * 2);
+        drop(conn);
+        let report = validate(mesh);
+        assert!(report.is_valid(), "{}", report.summary());
+    }
+
+    #[test]
+    fn test_delete_faces_mode() {
+        // The edges around an inner face stay, as the boundary of the hole
+        let mesh = grid();
+        delete(
+            &mesh,
+            &SelectionExpression::from_ids([4]),
+            DeleteMode::Faces,
+        )
+        .unwrap();
+        assert_counts(&mesh, 8, 16, 24);
+
+        // Deleting a corner face removes its outer edges, and the corner
+        let mesh = grid();
+        delete(
+            &mesh,
+            &SelectionExpression::from_ids([0]),
+            DeleteMode::Faces,
+        )
+        .unwrap();
+        assert_counts(&mesh, 8, 15, 22);
+        // The channels only store values for the elements that are left
+        assert!(validate(&mesh).stale_channel_entries.is_empty());
+    }
+
+    #[test]
+    fn test_delete_faces_keep_boundary_mode() {
+        let mesh = grid();
+        let corner = SelectionExpression::from_ids([0]);
+        delete(&mesh, &corner, DeleteMode::FacesKeepBoundary).unwrap();
+        assert_counts(&mesh, 8, 16, 24);
+
+        let mesh = grid();
+        delete(
+            &mesh,
+            &SelectionExpression::All,
+            DeleteMode::FacesKeepBoundary,
+        )
+        .unwrap();
+        assert_counts(&mesh, 0, 16, 24);
+    }
+
+    #[test]
+    fn test_delete_edges_mode() {
+        // An inner edge merges the faces at both sides
+        let mesh = grid();
+        let h = halfedge_between(&mesh, (1, 0), (1, 1));
+        delete_edges(&mut mesh.write_connectivity(), &[h]).unwrap();
+        assert_counts(&mesh, 8, 16, 23);
+
+        // A boundary edge deletes the face next to it
+        let mesh = grid();
+        let h = halfedge_between(&mesh, (0, 0), (1, 0));
+        delete_edges(&mut mesh.write_connectivity(), &[h]).unwrap();
+        assert_counts(&mesh, 8, 15, 22);
+
+        // Removing all the edges around an inner vertex merges its four faces
+        // into one, and the vertex goes away with the last edge
+        let mesh = grid();
+        let edges = [(0, 1), (2, 1), (1, 0), (1, 2)]
+            .into_iter()
+            .map(|other| halfedge_between(&mesh, (1, 1), other))
+            .collect_vec();
+        delete_edges(&mut mesh.write_connectivity(), &edges).unwrap();
+        assert_counts(&mesh, 6, 15, 20);
+        let conn = mesh.read_connectivity();
+        assert!(conn
+            .iter_faces()
+            .any(|(f, _)| conn.face_edges(f).len() == 8));
+    }
+
+    #[test]
+    fn test_delete_vertices_mode() {
+        // Deleting an inner vertex removes the four faces around it. The
+        // corner of the grid is left with no faces, so it goes away too.
+        let mesh = grid();
+        let v = vertex_at(&mesh, 1, 1);
+        delete_vertices(&mut mesh.write_connectivity(), &[v]).unwrap();
+        assert_counts(&mesh, 5, 12, 16);
+
+        // The vertices of a point cloud are removed, even with no faces
+        let mesh = primitives::Grid::build(3, 3, 1.0, 1.0).unwrap();
+        delete(
+            &mesh,
+            &SelectionExpression::from_ids([0, 4]),
+            DeleteMode::Vertices,
+        )
+        .unwrap();
+        assert_counts(&mesh, 0, 7, 0);
+    }
+}
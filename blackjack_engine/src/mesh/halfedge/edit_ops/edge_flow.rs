@@ -0,0 +1,261 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::mesh::halfedge::edge_loops::{is_regular_vertex, next_in_loop};
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// A vertex of a selected loop, and its neighbors across the loop, along the
+/// perpendicular edge ring.
+struct FlowVertex {
+    v: VertexId,
+    /// The neighbors at both sides of the loop.
+    sides: [VertexId; 2],
+    /// The vertices one step further away from the loop than the `sides`, when
+    /// there are any.
+    outer: [Option<VertexId>; 2],
+}
+
+/// Returns the position of a vertex at parameter `s` between `a` and `b`,
+/// along a Catmull-Rom spline through `a_outer`, `a`, `b` and `b_outer`. Tangents
+/// are scaled by `tension`, so 0 gives a straight line between `a` and `b`.
+fn flow_position(a_outer: Vec3, a: Vec3, b: Vec3, b_outer: Vec3, s: f32, tension: f32) -> Vec3 {
+    let d_ab = a.distance(b);
+    let (d_a, d_b) = (a_outer.distance(a), b.distance(b_outer));
+    // Non-uniform tangents, so that uneven edge lengths don't overshoot
+    let m_a = tension * (b - a_outer) * d_ab / (d_a + d_ab).max(1e-12);
+    let m_b = tension * (b_outer - a) * d_ab / (d_b + d_ab).max(1e-12);
+
+    let (s2, s3) = (s * s, s * s * s);
+    (2.0 * s3 - 3.0 * s2 + 1.0) * a
+        + (s3 - 2.0 * s2 + s) * m_a
+        + (-2.0 * s3 + 3.0 * s2) * b
+        + (s3 - s2) * m_b
+}
+
+/// Finds the vertices of the selected loops that can be moved, along with
+/// their neighbors across the loop. Only vertices with four edges, where the
+/// loop goes straight through the vertex, are found. This leaves boundary
+/// vertices, and the crossings of several selected loops, where they are.
+fn flow_vertices(mesh: &HalfEdgeMesh, edges: &SelectionExpression) -> Result<Vec<FlowVertex>> {
+    let conn = mesh.read_connectivity();
+    let mut selected = HashSet::new();
+    let mut vertices = vec![];
+    for h in mesh.resolve_halfedge_selection_full(edges)? {
+        let twin = conn.at_halfedge(h).twin().try_end()?;
+        selected.insert(h);
+        selected.insert(twin);
+        let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+        for v in [src, dst] {
+            if !vertices.contains(&v) {
+                vertices.push(v);
+            }
+        }
+    }
+
+    let mut result = vec![];
+    for v in vertices {
+        if !is_regular_vertex(&conn, v)? {
+            continue;
+        }
+        // Outgoing halfedges `i` and `i + 2` are opposite each other
+        let outgoing = conn.at_vertex(v).outgoing_halfedges()?;
+        let is_selected = |i: usize| selected.contains(&outgoing[i % 4]);
+        let across = (0..2).find(|&i| {
+            !is_selected(i) && !is_selected(i + 2) && (is_selected(i + 1) || is_selected(i + 3))
+        });
+        if let Some(i) = across {
+            let (h_a, h_b) = (outgoing[i], outgoing[i + 2]);
+            let outer_of = |h: HalfEdgeId| -> Result<Option<VertexId>> {
+                Ok(match next_in_loop(&conn, h)? {
+                    Some(next) => Some(conn.at_halfedge(next).dst_vertex().try_end()?),
+                    None => None,
+                })
+            };
+            result.push(FlowVertex {
+                v,
+                sides: [
+                    conn.at_halfedge(h_a).dst_vertex().try_end()?,
+                    conn.at_halfedge(h_b).dst_vertex().try_end()?,
+                ],
+                outer: [outer_of(h_a)?, outer_of(h_b)?],
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Moves the vertices of the selected edge loops so they follow the curvature
+/// of the surface around them. Each vertex is placed on the curve through its
+/// neighbors across the loop, along the perpendicular edge ring, keeping its
+/// relative position between them. This restores the shape lost when cutting
+/// new loops through curved surfaces.
+///
+/// The `tension` scales the curvature, with 1 following the surface and 0
+/// leaving the loop straight. When several parallel loops are selected, they
+/// influence each other, so more `iterations` are needed for them to settle.
+/// Boundary vertices are not moved.
+pub fn set_edge_flow(
+    mesh: &mut HalfEdgeMesh,
+    edges: &SelectionExpression,
+    iterations: u32,
+    tension: f32,
+) -> Result<()> {
+    let flow = flow_vertices(mesh, edges)?;
+    let mut positions = mesh.write_positions();
+    for _ in 0..iterations {
+        let new_positions = flow
+            .iter()
+            .map(|f| {
+                let [a, b] = f.sides.map(|v| positions[v]);
+                // Without vertices further out, the curve is straight at that end
+                let a_outer = f.outer[0].map(|v| positions[v]).unwrap_or(a + (a - b));
+                let b_outer = f.outer[1].map(|v| positions[v]).unwrap_or(b + (b - a));
+                let ab = b - a;
+                if ab.length_squared() < 1e-12 {
+                    return positions[f.v];
+                }
+                let s = ((positions[f.v] - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+                flow_position(a_outer, a, b, b_outer, s, tension)
+            })
+            .collect_vec();
+        for (f, pos) in flow.iter().zip(new_positions) {
+            positions[f.v] = pos;
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves the vertices of the selected edge loops of `mesh` so they follow
+    /// the curvature of the surface. A `tension` of 1 follows the surface, and
+    /// 0 leaves the loops straight.
+    #[lua(under = "Ops")]
+    pub fn set_edge_flow(
+        mesh: &mut HalfEdgeMesh,
+        edges: SelectionExpression,
+        iterations: u32,
+        tension: f32,
+    ) -> Result<()> {
+        super::set_edge_flow(mesh, &edges, iterations, tension)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SEGMENTS: usize = 16;
+    const ROWS: usize = 3;
+
+    /// An open tube with a radius of 1 around the Y axis, with the result of
+    /// a plain loop cut along its length: A vertical loop of vertices at the
+    /// middle of the edges between the first two columns, which lie inside
+    /// the circle.
+    fn cut_tube() -> HalfEdgeMesh {
+        let columns = SEGMENTS + 1;
+        let mut positions = vec![];
+        for y in 0..=ROWS {
+            for i in 0..SEGMENTS {
+                let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+                positions.push(Vec3::new(angle.cos(), y as f32, -angle.sin()));
+            }
+            let row = y * columns;
+            positions.push((positions[row] + positions[row + 1]) / 2.0);
+        }
+        let idx = |i: usize, y: usize| y * columns + i;
+        let cut = |y: usize| idx(SEGMENTS, y);
+        let mut polygons = vec![];
+        for y in 0..ROWS {
+            polygons.push(vec![idx(0, y), cut(y), cut(y + 1), idx(0, y + 1)]);
+            polygons.push(vec![cut(y), idx(1, y), idx(1, y + 1), cut(y + 1)]);
+            for i in 1..SEGMENTS {
+                let j = (i + 1) % SEGMENTS;
+                polygons.push(vec![idx(i, y), idx(j, y), idx(j, y + 1), idx(i, y + 1)]);
+            }
+        }
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    fn radius(p: Vec3) -> f32 {
+        Vec2::new(p.x, p.z).length()
+    }
+
+    /// Selects the edges of the loop cut, between vertices inside the circle.
+    fn cut_edges(mesh: &HalfEdgeMesh) -> SelectionExpression {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let ids = conn
+            .iter_halfedges()
+            .enumerate()
+            .filter(|(_, (h, _))| {
+                let (src, dst) = conn.at_halfedge(*h).src_dst_pair().unwrap();
+                radius(positions[src]) < 0.99 && radius(positions[dst]) < 0.99
+            })
+            .map(|(i, _)| i as u32)
+            .collect_vec();
+        SelectionExpression::from_ids(ids)
+    }
+
+    /// Returns the positions of the vertices inside the circle, sorted by
+    /// height.
+    fn cut_vertices(mesh: &HalfEdgeMesh) -> Vec<Vec3> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .filter(|p| radius(*p) < 0.99)
+            .sorted_by(|a, b| a.y.total_cmp(&b.y))
+            .collect()
+    }
+
+    #[test]
+    fn test_edge_flow_restores_circle() {
+        let mut mesh = cut_tube();
+        assert_eq!(cut_vertices(&mesh).len(), ROWS + 1);
+        let edges = cut_edges(&mesh);
+        set_edge_flow(&mut mesh, &edges, 1, 1.0).unwrap();
+
+        // The boundary vertices of the cut stay where they are
+        let remaining = cut_vertices(&mesh);
+        assert_eq!(
+            remaining.iter().map(|p| p.y).collect_vec(),
+            vec![0.0, ROWS as f32]
+        );
+
+        // The others move onto the circle, halfway between their neighbors
+        let positions = mesh.read_positions();
+        let halfway = std::f32::consts::PI / SEGMENTS as f32;
+        let flowed = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .filter(|p| p.y > 0.0 && p.y < ROWS as f32)
+            .filter(|p| ((-p.z).atan2(p.x) - halfway).abs() < 1e-4)
+            .collect_vec();
+        assert_eq!(flowed.len(), ROWS - 1);
+        for p in flowed {
+            assert!((radius(p) - 1.0).abs() < 1e-3, "{p}");
+        }
+    }
+
+    #[test]
+    fn test_zero_tension() {
+        let mut mesh = cut_tube();
+        let before = cut_vertices(&mesh);
+        let edges = cut_edges(&mesh);
+        set_edge_flow(&mut mesh, &edges, 3, 0.0).unwrap();
+        // The loop is already straight
+        let after = cut_vertices(&mesh);
+        assert_eq!(before.len(), after.len());
+        for (a, b) in before.iter().zip(after) {
+            assert!(a.distance(b) < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+use super::{cut_face, divide_edge};
+
+/// Returns the selected halfedges, keeping only one halfedge for each pair of
+/// twins. When both halfedges of an edge are selected, the one that comes
+/// first in the selection is kept.
+fn unique_edges(mesh: &HalfEdgeMesh, edges: &SelectionExpression) -> Result<Vec<HalfEdgeId>> {
+    let conn = mesh.read_connectivity();
+    let mut seen = HashSet::new();
+    let mut result = vec![];
+    for h in mesh.resolve_halfedge_selection_full(edges)? {
+        let twin = conn.at_halfedge(h).twin().try_end()?;
+        if !seen.contains(&twin) && seen.insert(h) {
+            result.push(h);
+        }
+    }
+    Ok(result)
+}
+
+/// Inserts a new vertex on every edge in `edges`, at parameter `t` along the
+/// edge (0 is the source vertex, 1 the destination). The faces at both sides
+/// of each edge get the new vertex. Vertex channels for the new vertices, and
+/// halfedge channels for the new corners, are interpolated at `t`.
+///
+/// Returns the newly created vertices.
+pub fn split_edges(
+    mesh: &mut HalfEdgeMesh,
+    edges: &SelectionExpression,
+    t: f32,
+) -> Result<Vec<VertexId>> {
+    let mut new_vertices = vec![];
+    let mut vertex_values = ChannelWriteBatch::new();
+    let mut corner_values = ChannelWriteBatch::new();
+    for h_l in unique_edges(mesh, edges)? {
+        let (v, w, h_r, h_l_next, h_r_next) = {
+            let conn = mesh.read_connectivity();
+            let (v, w) = conn.at_halfedge(h_l).src_dst_pair()?;
+            let h_r = conn.at_halfedge(h_l).twin().try_end()?;
+            let h_l_next = conn.at_halfedge(h_l).next().try_end()?;
+            let h_r_next = conn.at_halfedge(h_r).next().try_end()?;
+            (v, w, h_r, h_l_next, h_r_next)
+        };
+
+        let x = divide_edge(
+            &mut mesh.write_connectivity(),
+            &mut mesh.write_positions(),
+            h_l,
+            t,
+        )?;
+
+        // After the split, `h_l` goes from x to w, and `h_r` goes from w to x.
+        // The new halfedges are the ones going from v to x, and x to v.
+        let (h_l_2, h_r_2) = {
+            let conn = mesh.read_connectivity();
+            let h_r_2 = conn.at_halfedge(h_r).next().try_end()?;
+            let h_l_2 = conn.at_halfedge(h_r_2).twin().try_end()?;
+            (h_l_2, h_r_2)
+        };
+
+        vertex_values.interpolate(x, v, w, t);
+        // Halfedge channels store per-corner data at the source vertex of each
+        // halfedge. The corner at v moves to h_l_2, and the corners at x are
+        // interpolated from the corners at both ends of the original edge.
+        corner_values.copy(h_l_2, h_l);
+        corner_values.interpolate(h_l, h_l_2, h_l_next, t);
+        corner_values.interpolate(h_r_2, h_r, h_r_next, 1.0 - t);
+
+        new_vertices.push(x);
+    }
+    vertex_values.apply(&mesh.channels);
+    corner_values.apply(&mesh.channels);
+    Ok(new_vertices)
+}
+
+/// Connects each of the `(v, w)` pairs in `pairs` with a new edge. The
+/// vertices in each pair must share a face, which is split in two. The face
+/// channels of the original face are copied to the new face.
+///
+/// Returns the new halfedges, going from v to w.
+pub fn connect_vertices(
+    mesh: &mut HalfEdgeMesh,
+    pairs: &[(VertexId, VertexId)],
+) -> Result<Vec<HalfEdgeId>> {
+    let mut new_halfedges = vec![];
+    let mut face_values = ChannelWriteBatch::new();
+    let mut corner_values = ChannelWriteBatch::new();
+    for (v, w) in pairs.iter_cpy() {
+        if v == w {
+            bail!("Can't connect vertex {v:?} to itself");
+        }
+        {
+            let conn = mesh.read_connectivity();
+            let shares_face = conn
+                .at_vertex(v)
+                .adjacent_faces()?
+                .iter()
+                .any(|f| conn.face_vertices(*f).contains(&w));
+            if !shares_face {
+                bail!("Can't connect vertices {v:?} and {w:?}: They don't share a face");
+            }
+        }
+
+        let h_v_w = cut_face(&mut mesh.write_connectivity(), v, w)?;
+
+        let (face, new_face, h_w_v, h_v_next, h_w_next) = {
+            let conn = mesh.read_connectivity();
+            let h_w_v = conn.at_halfedge(h_v_w).twin().try_end()?;
+            (
+                conn.at_halfedge(h_v_w).face().try_end()?,
+                conn.at_halfedge(h_w_v).face().try_end()?,
+                h_w_v,
+                conn.at_halfedge(h_w_v).next().try_end()?,
+                conn.at_halfedge(h_v_w).next().try_end()?,
+            )
+        };
+        face_values.copy(new_face, face);
+        // The new corners are copies of the existing ones at the same vertex.
+        corner_values.copy(h_v_w, h_v_next);
+        corner_values.copy(h_w_v, h_w_next);
+
+        new_halfedges.push(h_v_w);
+    }
+    face_values.apply(&mesh.channels);
+    corner_values.apply(&mesh.channels);
+    Ok(new_halfedges)
+}
+
+/// Slides the vertices of the `edges` along their adjacent faces. For a
+/// positive `t`, each vertex moves towards the neighbouring vertex on the face
+/// of the selected halfedge, for a negative `t`, it moves towards the face on
+/// the other side. A value of 1 (or -1) moves the vertices all the way.
+/// Vertices that are shared by several edges move towards the average of
+/// their targets. Only positions are modified.
+pub fn slide_edges(mesh: &mut HalfEdgeMesh, edges: &SelectionExpression, t: f32) -> Result<()> {
+    let edges = unique_edges(mesh, edges)?;
+    let conn = mesh.read_connectivity();
+    let mut targets = HashMap::<VertexId, SVec<VertexId>>::new();
+    for h in edges {
+        // Slide on the face of the halfedge, or the face of its twin when t is
+        // negative. Either way, `h` goes from `a` to `b` on that face.
+        let h = if t >= 0.0 {
+            h
+        } else {
+            conn.at_halfedge(h).twin().try_end()?
+        };
+        if conn.at_halfedge(h).face().try_end().is_err() {
+            // Boundary edges can't slide outside of the mesh.
+            continue;
+        }
+        let (a, b) = conn.at_halfedge(h).src_dst_pair()?;
+        let a_target = conn.at_halfedge(h).previous().vertex().try_end()?;
+        let b_target = conn.at_halfedge(h).next().dst_vertex().try_end()?;
+        targets.entry(a).or_default().push(a_target);
+        targets.entry(b).or_default().push(b_target);
+    }
+
+    let mut positions = mesh.write_positions();
+    let new_positions = targets
+        .iter()
+        .map(|(v, vs)| {
+            let target = vs.iter().map(|x| positions[*x]).sum::<Vec3>() / vs.len() as f32;
+            (*v, positions[*v].lerp(target, t.abs()))
+        })
+        .collect_vec();
+    for (v, pos) in new_positions {
+        positions[v] = pos;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Inserts a new vertex on each of the selected `edges`, at parameter `t`
+    /// along the edge. Vertex channels are interpolated for the new vertices.
+    #[lua(under = "Ops")]
+    pub fn split_edges(
+        mesh: &mut HalfEdgeMesh,
+        edges: SelectionExpression,
+        t: f32,
+    ) -> Result<Vec<VertexId>> {
+        super::split_edges(mesh, &edges, t)
+    }
+
+    /// Connects each vertex in the selection `a` to the vertex in the same
+    /// position in the selection `b`, by splitting the face they share with a
+    /// new edge. Both selections must have the same number of vertices.
+    #[lua(under = "Ops")]
+    pub fn connect_vertices(
+        mesh: &mut HalfEdgeMesh,
+        a: SelectionExpression,
+        b: SelectionExpression,
+    ) -> Result<Vec<HalfEdgeId>> {
+        let a = mesh.resolve_vertex_selection_full(&a)?;
+        let b = mesh.resolve_vertex_selection_full(&b)?;
+        if a.len() != b.len() {
+            bail!(
+                "Both selections must have the same number of vertices, found {} and {}",
+                a.len(),
+                b.len()
+            );
+        }
+        let pairs = a.into_iter().zip(b).collect_vec();
+        super::connect_vertices(mesh, &pairs)
+    }
+
+    /// Slides the vertices of the selected `edges` along their adjacent faces
+    /// by parameter `t`. Negative values slide towards the opposite side.
+    #[lua(under = "Ops")]
+    pub fn slide_edges(mesh: &mut HalfEdgeMesh, edges: SelectionExpression, t: f32) -> Result<()> {
+        super::slide_edges(mesh, &edges, t)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    fn quad() -> HalfEdgeMesh {
+        primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap()
+    }
+
+    #[test]
+    fn test_split_all_quad_edges() {
+        let mut mesh = quad();
+        let weight_ch = mesh.channels.ensure_channel::<VertexId, f32>("weight");
+        {
+            let conn = mesh.read_connectivity();
+            let mut weights = mesh.channels.write_channel(weight_ch).unwrap();
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                weights[v] = i as f32;
+            }
+        }
+
+        let new_vertices = split_edges(&mut mesh, &SelectionExpression::All, 0.5).unwrap();
+        assert_eq!(new_vertices.len(), 4);
+
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_vertices(), 8);
+        assert_eq!(conn.num_faces(), 1);
+        let (face, _) = conn.iter_faces().next().unwrap();
+        assert_eq!(conn.face_vertices(face).len(), 8);
+
+        // New vertices sit at the midpoint, and their channels are averaged
+        let positions = mesh.read_positions();
+        let weights = mesh.channels.read_channel(weight_ch).unwrap();
+        for x in new_vertices {
+            let (prev, next) = {
+                let h = conn.at_vertex(x).outgoing_halfedges().unwrap()[0];
+                let next = conn.at_halfedge(h).dst_vertex().end();
+                let prev = conn.at_halfedge(h).twin().next().dst_vertex().end();
+                (prev, next)
+            };
+            assert!(positions[x].abs_diff_eq((positions[prev] + positions[next]) * 0.5, 1e-5));
+            assert!((weights[x] - (weights[prev] + weights[next]) * 0.5).abs() < 1e-5);
+        }
+        drop((conn, positions, weights));
+        assert!(validate(&mesh).is_valid());
+    }
+
+    #[test]
+    fn test_connect_opposite_corners() {
+        let mut mesh = quad();
+        let material_ch = mesh.channels.ensure_channel::<FaceId, f32>("material");
+        let (v0, v2) = {
+            let conn = mesh.read_connectivity();
+            let (face, _) = conn.iter_faces().next().unwrap();
+            mesh.channels.write_channel(material_ch).unwrap()[face] = 7.0;
+            let verts = conn.face_vertices(face);
+            (verts[0], verts[2])
+        };
+
+        let new_halfedges = connect_vertices(&mut mesh, &[(v0, v2)]).unwrap();
+        assert_eq!(new_halfedges.len(), 1);
+
+        {
+            let conn = mesh.read_connectivity();
+            assert_eq!(conn.num_faces(), 2);
+            assert_eq!(conn.num_vertices(), 4);
+            let materials = mesh.channels.read_channel(material_ch).unwrap();
+            for (face, _) in conn.iter_faces() {
+                assert_eq!(conn.face_vertices(face).len(), 3);
+                assert_eq!(materials[face], 7.0);
+            }
+        }
+        assert!(validate(&mesh).is_valid());
+    }
+
+    #[test]
+    fn test_connect_requires_shared_face() {
+        let mut mesh = quad();
+        mesh.merge_with(
+            &primitives::Quad::build(Vec3::X * 5.0, Vec3::Y, Vec3::X, Vec2::ONE).unwrap(),
+        );
+        let (a, b) = {
+            let conn = mesh.read_connectivity();
+            let faces = conn.iter_faces().map(|(f, _)| f).collect_vec();
+            (
+                conn.face_vertices(faces[0])[0],
+                conn.face_vertices(faces[1])[0],
+            )
+        };
+        let err = connect_vertices(&mut mesh, &[(a, b)]).unwrap_err();
+        assert!(err.to_string().contains("don't share a face"));
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use float_ord::FloatOrd;
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// The shape of the influence curve used in proportional editing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FalloffKind {
+    /// An S-shaped curve, which blends smoothly at both ends.
+    Smooth,
+    /// Decreases linearly with the distance.
+    Linear,
+    /// A quarter circle. Stays close to full influence and drops sharply near
+    /// the radius.
+    Sphere,
+    /// Drops quickly close to the selection, then fades out slowly.
+    Sharp,
+}
+
+impl FalloffKind {
+    /// Returns the influence at `t`, the distance to the selection divided
+    /// by the radius. The influence is 1 at the selection, and goes down to
+    /// 0 at the radius and beyond.
+    pub fn weight(self, t: f32) -> f32 {
+        if t >= 1.0 {
+            return 0.0;
+        }
+        let t = t.max(0.0);
+        let x = 1.0 - t;
+        match self {
+            FalloffKind::Smooth => x * x * (3.0 - 2.0 * x),
+            FalloffKind::Linear => x,
+            FalloffKind::Sphere => (1.0 - t * t).sqrt(),
+            FalloffKind::Sharp => x * x,
+        }
+    }
+}
+
+/// How the distance to the selection is measured in proportional editing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FalloffDistance {
+    /// The straight-line distance to the closest selected vertex.
+    Euclidean,
+    /// The length of the shortest path along the edges of the mesh to a
+    /// selected vertex. Parts of the mesh that are close in space but not
+    /// connected to the selection aren't affected.
+    Topological,
+}
+
+/// Returns the distance to the closest of the `selected` vertices for every
+/// vertex within `radius` of them. The selected vertices are at distance 0.
+pub fn selection_distances(
+    mesh: &HalfEdgeMesh,
+    selected: &[VertexId],
+    radius: f32,
+    mode: FalloffDistance,
+) -> Result<HashMap<VertexId, f32>> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let mut distances: HashMap<VertexId, f32> = selected.iter().map(|v| (*v, 0.0)).collect();
+
+    match mode {
+        FalloffDistance::Euclidean => {
+            for (v, _) in conn.iter_vertices() {
+                if distances.contains_key(&v) {
+                    continue;
+                }
+                let distance = selected
+                    .iter()
+                    .map(|s| positions[*s].distance(positions[v]))
+                    .fold(f32::INFINITY, f32::min);
+                if distance <= radius {
+                    distances.insert(v, distance);
+                }
+            }
+        }
+        FalloffDistance::Topological => {
+            // Dijkstra's algorithm over the edges of the mesh, starting at
+            // all the selected vertices at once. Paths longer than the radius
+            // are not explored.
+            let mut queue = selected
+                .iter()
+                .map(|v| Reverse((FloatOrd(0.0f32), *v)))
+                .collect::<BinaryHeap<_>>();
+            while let Some(Reverse((FloatOrd(distance), v))) = queue.pop() {
+                if distance > distances[&v] {
+                    // A shorter path to `v` was found after this one was queued
+                    continue;
+                }
+                for h in conn.at_vertex(v).outgoing_halfedges()? {
+                    let w = conn.at_halfedge(h).dst_vertex().try_end()?;
+                    let new_distance = distance + positions[v].distance(positions[w]);
+                    if new_distance <= radius
+                        && distances.get(&w).map_or(true, |d| new_distance < *d)
+                    {
+                        distances.insert(w, new_distance);
+                        queue.push(Reverse((FloatOrd(new_distance), w)));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(distances)
+}
+
+/// Computes the proportional editing influence of the vertices in `selection`
+/// over the rest of the mesh. Selected vertices have a weight of 1, vertices
+/// beyond `radius` have a weight of 0.
+pub fn falloff_weights(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    radius: f32,
+    falloff: FalloffKind,
+    mode: FalloffDistance,
+) -> Result<Channel<VertexId, f32>> {
+    if radius < 0.0 {
+        bail!("The falloff radius can't be negative");
+    }
+    let selected = mesh.resolve_vertex_selection_full(selection)?;
+    let distances = selection_distances(mesh, &selected, radius, mode)?;
+
+    let mut weights = Channel::<VertexId, f32>::new();
+    for (v, distance) in distances {
+        weights[v] = if distance == 0.0 {
+            1.0
+        } else {
+            falloff.weight(distance / radius)
+        };
+    }
+    Ok(weights)
+}
+
+/// Moves the vertices in `selection` by `offset`, and the vertices around them
+/// by a fraction of it, depending on their distance to the selection.
+///
+/// When `weight_channel` is given, the computed weights are also stored in the
+/// f32 vertex channel with that name, so the same falloff can drive other ops.
+pub fn proportional_translate(
+    mesh: &mut HalfEdgeMesh,
+    selection: &SelectionExpression,
+    offset: Vec3,
+    radius: f32,
+    falloff: FalloffKind,
+    mode: FalloffDistance,
+    weight_channel: Option<&str>,
+) -> Result<()> {
+    let weights = falloff_weights(mesh, selection, radius, falloff, mode)?;
+    {
+        let mut positions = mesh.write_positions();
+        for (v, weight) in weights.iter() {
+            positions[v] += offset * *weight;
+        }
+    }
+    if let Some(name) = weight_channel {
+        mesh.channels.replace_or_create_channel(name, weights);
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves the vertices in `selection` by `offset`, and the vertices within
+    /// `radius` of them by a fraction of it. The `falloff` is one of
+    /// `"Smooth"`, `"Linear"`, `"Sphere"` or `"Sharp"`. When `mode` is
+    /// `"Topological"`, distances are measured along the edges of the mesh
+    /// instead of in a straight line. If `weight_channel` is given, the
+    /// influence on each vertex is stored in that f32 vertex channel.
+    #[lua(under = "Ops")]
+    pub fn proportional_translate(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        offset: LVec3,
+        radius: f32,
+        falloff: String,
+        mode: Option<String>,
+        weight_channel: Option<String>,
+    ) -> Result<()> {
+        let falloff = match falloff.as_str() {
+            "Smooth" => FalloffKind::Smooth,
+            "Linear" => FalloffKind::Linear,
+            "Sphere" => FalloffKind::Sphere,
+            "Sharp" => FalloffKind::Sharp,
+            _ => bail!("Invalid falloff kind '{falloff}'"),
+        };
+        let mode = match mode.as_deref() {
+            None | Some("Euclidean") => FalloffDistance::Euclidean,
+            Some("Topological") => FalloffDistance::Topological,
+            Some(mode) => bail!("Invalid distance mode '{mode}'"),
+        };
+        super::proportional_translate(
+            mesh,
+            &selection,
+            offset.0,
+            radius,
+            falloff,
+            mode,
+            weight_channel.as_deref().filter(|name| !name.is_empty()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_falloff_weights() {
+        let kinds = [
+            FalloffKind::Smooth,
+            FalloffKind::Linear,
+            FalloffKind::Sphere,
+            FalloffKind::Sharp,
+        ];
+        for kind in kinds {
+            assert_eq!(kind.weight(0.0), 1.0);
+            assert_eq!(kind.weight(1.0), 0.0);
+            assert_eq!(kind.weight(1.5), 0.0);
+        }
+        assert_eq!(FalloffKind::Smooth.weight(0.5), 0.5);
+        assert_eq!(FalloffKind::Linear.weight(0.25), 0.75);
+        assert!((FalloffKind::Sphere.weight(0.6) - 0.8).abs() < 1e-6);
+        assert_eq!(FalloffKind::Sharp.weight(0.5), 0.25);
+    }
+
+    /// Moves the corner of a unit box at the origin, and returns the offset of
+    /// each vertex.
+    fn move_corner(radius: f32, mode: FalloffDistance) -> (HalfEdgeMesh, Vec<(Vec3, Vec3)>) {
+        let mut mesh = primitives::Box::build(Vec3::splat(0.5), Vec3::ONE).unwrap();
+        let before = mesh.read_positions().clone();
+        let corner = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .position(|(v, _)| before[v] == Vec3::ZERO)
+            .unwrap();
+        proportional_translate(
+            &mut mesh,
+            &SelectionExpression::from_ids([corner as u32]),
+            Vec3::Y,
+            radius,
+            FalloffKind::Linear,
+            mode,
+            Some("weight"),
+        )
+        .unwrap();
+        let positions = mesh.read_positions();
+        let offsets = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| (before[v], positions[v] - before[v]))
+            .collect();
+        drop(positions);
+        (mesh, offsets)
+    }
+
+    #[test]
+    fn test_proportional_translate() {
+        // Euclidean: The corner, its 3 neighbors at distance 1 and the 3
+        // vertices across the faces at distance sqrt(2) are affected.
+        let (mesh, offsets) = move_corner(1.5, FalloffDistance::Euclidean);
+        for (pos, offset) in &offsets {
+            let distance = pos.length();
+            if distance > 1.5 {
+                assert_eq!(*offset, Vec3::ZERO);
+            } else {
+                let expected = FalloffKind::Linear.weight(distance / 1.5);
+                assert!((offset.y - expected).abs() < 1e-5);
+            }
+        }
+        let moved = offsets.iter().filter(|(_, o)| *o != Vec3::ZERO).count();
+        assert_eq!(moved, 7);
+        let weights = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("weight")
+            .unwrap();
+        assert_eq!(weights.iter().filter(|(_, w)| **w > 0.0).count(), moved);
+
+        // Topological: The vertices across the faces are two edges away.
+        let (_, offsets) = move_corner(1.5, FalloffDistance::Topological);
+        let moved = offsets.iter().filter(|(_, o)| *o != Vec3::ZERO).count();
+        assert_eq!(moved, 4);
+    }
+}
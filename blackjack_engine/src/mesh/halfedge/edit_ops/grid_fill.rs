@@ -0,0 +1,393 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+use super::make_quad;
+
+/// Returns the vertices of the boundary loop formed by the `boundary`
+/// halfedges, in the order of the loop. The selection may contain either
+/// halfedge of each edge, but it must form a single closed loop around a hole.
+fn boundary_loop(mesh: &HalfEdgeMesh, boundary: &SelectionExpression) -> Result<Vec<VertexId>> {
+    let conn = mesh.read_connectivity();
+    let mut selected = vec![];
+    for h in mesh.resolve_halfedge_selection_full(boundary)? {
+        let twin = conn.at_halfedge(h).twin().try_end()?;
+        let h = if conn.at_halfedge(h).is_boundary()? {
+            h
+        } else if conn.at_halfedge(twin).is_boundary()? {
+            twin
+        } else {
+            bail!("Grid fill needs a boundary loop, but the edge {h:?} is not in a boundary");
+        };
+        if !selected.contains(&h) {
+            selected.push(h);
+        }
+    }
+    let start = match selected.first() {
+        Some(start) => *start,
+        None => bail!("Grid fill needs a boundary loop, but the selection is empty"),
+    };
+
+    let mut ring = vec![];
+    let mut h = start;
+    loop {
+        let v = conn.at_halfedge(h).vertex().try_end()?;
+        if ring.contains(&v) {
+            bail!("The boundary loop passes through the vertex {v:?} more than once");
+        }
+        ring.push(v);
+        h = conn.at_halfedge(h).next().try_end()?;
+        if h == start {
+            break;
+        }
+        if !selected.contains(&h) {
+            bail!("The selected edges don't form a closed boundary loop");
+        }
+    }
+    if ring.len() != selected.len() {
+        bail!("The selected edges form more than one boundary loop");
+    }
+    Ok(ring)
+}
+
+/// Newell's method. Works for non-planar and non-convex loops.
+fn loop_normal(points: &[Vec3]) -> Vec3 {
+    points
+        .iter()
+        .circular_tuple_windows()
+        .fold(Vec3::ZERO, |n, (a, b)| n + a.cross(*b))
+}
+
+/// Do the segments `a1`-`a2` and `b1`-`b2` cross?
+fn segments_cross(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(b1, b2, a1), side(b1, b2, a2));
+    let (d3, d4) = (side(a1, a2, b1), side(a1, a2, b2));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Does the loop with the given `points` cross itself, when projected onto
+/// the plane orthogonal to `normal`?
+fn self_intersects(points: &[Vec3], normal: Vec3) -> bool {
+    let (x_axis, y_axis) = normal.normalize().any_orthonormal_pair();
+    let projected = points
+        .iter()
+        .map(|p| Vec2::new(p.dot(x_axis), p.dot(y_axis)))
+        .collect_vec();
+    let n = projected.len();
+    let segment = |i: usize| (projected[i], projected[(i + 1) % n]);
+    (0..n).any(|i| {
+        // Adjacent segments share a vertex, they can't cross
+        (i + 2..n).filter(|j| (j + 1) % n != i).any(|j| {
+            let ((a1, a2), (b1, b2)) = (segment(i), segment(j));
+            segments_cross(a1, a2, b1, b2)
+        })
+    })
+}
+
+/// Picks the number of edges along the bottom and top rails of the grid. The
+/// chosen span makes opposite sides of the grid as close in length as
+/// possible, preferring square-ish grids when several spans are equally good.
+fn best_span(points: &[Vec3]) -> u32 {
+    let n = points.len();
+    let half = n / 2;
+    let rail_length = |from: usize, edges: usize| {
+        (from..from + edges)
+            .map(|i| points[i % n].distance(points[(i + 1) % n]))
+            .sum::<f32>()
+    };
+    let perimeter = rail_length(0, n);
+    let cost = |s: usize| {
+        let t = half - s;
+        let (bottom, right) = (rail_length(0, s), rail_length(s, t));
+        let (top, left) = (rail_length(s + t, s), rail_length(2 * s + t, t));
+        (bottom - top).abs() + (left - right).abs()
+    };
+    let squareness = |s: usize| (2 * s).abs_diff(half);
+
+    let mut best = 1;
+    for s in 2..half {
+        let (c, best_c) = (cost(s), cost(best));
+        let tie = (c - best_c).abs() <= perimeter * 1e-4;
+        if (!tie && c < best_c) || (tie && squareness(s) < squareness(best)) {
+            best = s;
+        }
+    }
+    best as u32
+}
+
+/// Fills the hole surrounded by the `boundary` loop with a grid of quads. The
+/// loop must have an even number of edges. It's split into four sides: Two
+/// opposite rails of `span` edges, and the two sides joining them. When
+/// `span_hint` is `None`, the span that makes opposite sides the most similar
+/// in length is used. The `offset` moves the first corner of the grid along
+/// the loop.
+///
+/// The positions of the new vertices are interpolated from the boundary as a
+/// Coons patch, so the fill follows the shape of the loop even when it's not
+/// planar.
+pub fn grid_fill(
+    mesh: &mut HalfEdgeMesh,
+    boundary: &SelectionExpression,
+    span_hint: Option<u32>,
+    offset: i32,
+) -> Result<()> {
+    let ring = boundary_loop(mesh, boundary)?;
+    let n = ring.len();
+    if n < 4 || n % 2 != 0 {
+        bail!("Grid fill needs a loop with an even number of edges, but the loop has {n}");
+    }
+    let start = offset.rem_euclid(n as i32) as usize;
+    let ring = rotate_iter(ring.iter_cpy(), start, n).collect_vec();
+
+    let points = {
+        let positions = mesh.read_positions();
+        ring.iter().map(|v| positions[*v]).collect_vec()
+    };
+    let normal = loop_normal(&points);
+    if normal.length_squared() < 1e-12 {
+        bail!("Can't grid fill a degenerate boundary loop, with no area");
+    }
+    if self_intersects(&points, normal) {
+        bail!("Can't grid fill a boundary loop that intersects itself");
+    }
+
+    let half = n / 2;
+    let s = match span_hint {
+        Some(s) if s >= 1 && (s as usize) < half => s as usize,
+        Some(s) => bail!(
+            "The grid fill span must be between 1 and {}, but it was {s}",
+            half - 1
+        ),
+        None => best_span(&points) as usize,
+    };
+    let t = half - s;
+
+    // Grid coordinates, r in 0..=t (rows) and c in 0..=s (columns), to
+    // positions in the ring. The ring goes along the bottom row, up the last
+    // column, back along the top row and down the first column.
+    let ring_index = |r: usize, c: usize| {
+        if r == 0 {
+            Some(c)
+        } else if c == s {
+            Some(s + r)
+        } else if r == t {
+            Some(s + t + (s - c))
+        } else if c == 0 {
+            Some((2 * s + t + (t - r)) % n)
+        } else {
+            None
+        }
+    };
+
+    let boundary_point = |r: usize, c: usize| points[ring_index(r, c).unwrap()];
+    let coons = |r: usize, c: usize| {
+        let (u, v) = (c as f32 / s as f32, r as f32 / t as f32);
+        let (bottom, top) = (boundary_point(0, c), boundary_point(t, c));
+        let (left, right) = (boundary_point(r, 0), boundary_point(r, s));
+        let bilinear = (1.0 - u) * (1.0 - v) * boundary_point(0, 0)
+            + u * (1.0 - v) * boundary_point(0, s)
+            + (1.0 - u) * v * boundary_point(t, 0)
+            + u * v * boundary_point(t, s);
+        (1.0 - v) * bottom + v * top + (1.0 - u) * left + u * right - bilinear
+    };
+
+    let grid_positions = (0..=t)
+        .map(|r| (0..=s).map(|c| coons(r, c)).collect_vec())
+        .collect_vec();
+    for r in 0..t {
+        for c in 0..s {
+            let quad = [
+                grid_positions[r][c],
+                grid_positions[r][c + 1],
+                grid_positions[r + 1][c + 1],
+                grid_positions[r + 1][c],
+            ];
+            if loop_normal(&quad).dot(normal) <= 0.0 {
+                bail!(
+                    "Can't grid fill this boundary loop with a span of {s}: The grid would fold over itself"
+                );
+            }
+        }
+    }
+
+    let mut conn = mesh.write_connectivity();
+    let mut positions = mesh.write_positions();
+    let mut grid = vec![];
+    for r in 0..=t {
+        let mut row = vec![];
+        for c in 0..=s {
+            row.push(match ring_index(r, c) {
+                Some(i) => ring[i],
+                None => conn.alloc_vertex(&mut positions, grid_positions[r][c], None),
+            });
+        }
+        grid.push(row);
+    }
+    drop(positions);
+
+    for r in 0..t {
+        for c in 0..s {
+            make_quad(
+                &mut conn,
+                &[
+                    grid[r][c],
+                    grid[r][c + 1],
+                    grid[r + 1][c + 1],
+                    grid[r + 1][c],
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Fills the hole surrounded by the `boundary` edges with a grid of quads.
+    /// The `span` is the number of edges along two opposite sides of the
+    /// grid. When it's 0, the span is chosen automatically. The `offset`
+    /// moves the corners of the grid along the loop.
+    #[lua(under = "Ops")]
+    pub fn grid_fill(
+        mesh: &mut HalfEdgeMesh,
+        boundary: SelectionExpression,
+        span: u32,
+        offset: i32,
+    ) -> Result<()> {
+        let span_hint = if span == 0 { None } else { Some(span) };
+        super::grid_fill(mesh, &boundary, span_hint, offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    /// A tube of `n` segments along the Y axis, from 0 to 1, with a cap at the
+    /// bottom and a hole at the top.
+    fn tube(n: usize) -> HalfEdgeMesh {
+        let mut positions = vec![];
+        for y in [0.0, 1.0] {
+            for i in 0..n {
+                let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                positions.push(Vec3::new(angle.cos(), y, -angle.sin()));
+            }
+        }
+        let mut polygons = (0..n)
+            .map(|i| vec![i, (i + 1) % n, n + (i + 1) % n, n + i])
+            .collect_vec();
+        polygons.push((0..n).rev().collect());
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    fn boundary_edges(mesh: &HalfEdgeMesh) -> SelectionExpression {
+        let conn = mesh.read_connectivity();
+        let ids = conn
+            .iter_halfedges()
+            .enumerate()
+            .filter(|(_, (_, h))| h.face.is_none())
+            .map(|(i, _)| i as u32)
+            .collect_vec();
+        SelectionExpression::from_ids(ids)
+    }
+
+    fn assert_closed_quads(mesh: &HalfEdgeMesh, num_faces: usize) {
+        assert!(validate(mesh).is_valid());
+        let conn = mesh.read_connectivity();
+        assert!(conn.iter_halfedges().all(|(_, h)| h.face.is_some()));
+        let quads = conn
+            .iter_faces()
+            .filter(|(f, _)| conn.face_vertices(*f).len() == 4)
+            .count();
+        // All faces but the bottom cap
+        assert_eq!(quads, num_faces - 1);
+        assert_eq!(conn.num_faces(), num_faces);
+    }
+
+    #[test]
+    fn test_fill_octagon() {
+        let mut mesh = tube(8);
+        let boundary = boundary_edges(&mesh);
+        grid_fill(&mut mesh, &boundary, None, 0).unwrap();
+        // A 2x2 grid, with a single new vertex at the center
+        assert_closed_quads(&mesh, 8 + 1 + 4);
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_vertices(), 17);
+        let center = conn
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .find(|p| p.x.abs() < 1e-5 && p.z.abs() < 1e-5)
+            .unwrap();
+        assert!((center.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_fill_smooth_interior() {
+        let mut mesh = tube(16);
+        let original_vertices = mesh.read_connectivity().num_vertices();
+        let boundary = boundary_edges(&mesh);
+        grid_fill(&mut mesh, &boundary, Some(4), 1).unwrap();
+        // A 4x4 grid, with 3x3 new vertices
+        assert_closed_quads(&mesh, 16 + 1 + 16);
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        let interior = conn
+            .iter_vertices()
+            .skip(original_vertices)
+            .map(|(v, _)| positions[v])
+            .collect_vec();
+        assert_eq!(interior.len(), 9);
+        for p in &interior {
+            // On the plane of the loop, and well inside it
+            assert!((p.y - 1.0).abs() < 1e-5);
+            assert!(Vec2::new(p.x, p.z).length() < 0.9);
+        }
+        // No two vertices bunch up together
+        for (a, b) in interior.iter().tuple_combinations() {
+            assert!(a.distance(*b) > 0.2);
+        }
+    }
+
+    #[test]
+    fn test_fill_errors() {
+        let mut mesh = tube(7);
+        let boundary = boundary_edges(&mesh);
+        let err = grid_fill(&mut mesh, &boundary, None, 0).unwrap_err();
+        assert!(err.to_string().contains("even number of edges"), "{err}");
+
+        let mut mesh = tube(8);
+        let boundary = boundary_edges(&mesh);
+        let err = grid_fill(&mut mesh, &boundary, Some(4), 0).unwrap_err();
+        assert!(err.to_string().contains("between 1 and 3"), "{err}");
+        let err = grid_fill(&mut mesh, &SelectionExpression::All, None, 0).unwrap_err();
+        assert!(err.to_string().contains("not in a boundary"), "{err}");
+
+        // A single face whose outline crosses itself
+        let positions = [
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 3.0),
+            (3.0, 3.0),
+            (2.0, -1.0),
+            (0.0, 3.0),
+        ]
+        .into_iter()
+        .map(|(x, z)| Vec3::new(x, 0.0, z))
+        .collect_vec();
+        let mut mesh =
+            HalfEdgeMesh::build_from_polygons(&positions, &[[0, 1, 2, 3, 4, 5]]).unwrap();
+        let boundary = boundary_edges(&mesh);
+        let err = grid_fill(&mut mesh, &boundary, None, 0).unwrap_err();
+        assert!(err.to_string().contains("intersects itself"), "{err}");
+    }
+}
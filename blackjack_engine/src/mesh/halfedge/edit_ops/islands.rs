@@ -0,0 +1,246 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use mlua::FromLua;
+
+use super::separate::find_islands;
+use super::transform::surface_centroid;
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::*;
+use crate::random::BjkRng;
+
+/// The point around which [`transform_islands`] rotates and scales each
+/// island.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IslandPivot {
+    /// The area-weighted centroid of the surface of the island.
+    Centroid,
+    /// The center of the axis-aligned bounding box of the island.
+    BoundsCenter,
+}
+
+/// Converts from one of the strings `"Centroid"` and `"BoundsCenter"`.
+impl<'lua> FromLua<'lua> for IslandPivot {
+    fn from_lua(lua_value: mlua::Value<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match String::from_lua(lua_value, lua)?.as_str() {
+            "Centroid" => Ok(IslandPivot::Centroid),
+            "BoundsCenter" => Ok(IslandPivot::BoundsCenter),
+            other => Err(mlua::Error::FromLuaConversionError {
+                from: "string",
+                to: "IslandPivot",
+                message: Some(format!("Invalid pivot mode '{other}'")),
+            }),
+        }
+    }
+}
+
+/// Moves, rotates and scales each connected component of `mesh` by its own
+/// random transform, around the island's pivot. Each island is translated by
+/// up to `translate_jitter` and rotated by up to `rotate_jitter` XYZ euler
+/// angles, in radians, on each axis. It's scaled uniformly by a factor between
+/// the two ends of `scale_jitter`.
+///
+/// The transforms only depend on the `seed` and the order of the islands.
+/// When an `index_channel` is given, the index of the island of each face is
+/// written to that f32 face channel.
+pub fn transform_islands(
+    mesh: &mut HalfEdgeMesh,
+    translate_jitter: Vec3,
+    rotate_jitter: Vec3,
+    scale_jitter: (f32, f32),
+    seed: u32,
+    pivot: IslandPivot,
+    index_channel: Option<&str>,
+) -> Result<()> {
+    let islands = find_islands(mesh)?;
+
+    {
+        let conn = mesh.read_connectivity();
+        let mut positions = mesh.write_positions();
+        let mut rng = BjkRng::new(seed as u64);
+        for island in &islands {
+            let translate = rng.point_in_box(-translate_jitter, translate_jitter);
+            let rotate = rng.point_in_box(-rotate_jitter, rotate_jitter);
+            let scale = rng.float(scale_jitter.0, scale_jitter.1);
+
+            let bounds_center = || {
+                island
+                    .vertices
+                    .iter()
+                    .map(|v| (positions[*v], positions[*v]))
+                    .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+                    .map(|(min, max)| (min + max) * 0.5)
+                    .unwrap_or(Vec3::ZERO)
+            };
+            let center = match pivot {
+                IslandPivot::BoundsCenter => bounds_center(),
+                // Islands with no surface, like loose vertices and edges, use
+                // their bounds instead.
+                IslandPivot::Centroid => surface_centroid(&conn, &positions, &island.faces)?
+                    .unwrap_or_else(bounds_center),
+            };
+
+            let rotation = Quat::from_euler(glam::EulerRot::XYZ, rotate.x, rotate.y, rotate.z);
+            for v in island.vertices.iter_cpy() {
+                positions[v] = center + rotation * ((positions[v] - center) * scale) + translate;
+            }
+        }
+    }
+
+    if let Some(name) = index_channel {
+        let ch_id = mesh.channels.ensure_channel::<FaceId, f32>(name);
+        let mut channel = mesh.channels.write_channel(ch_id)?;
+        for (i, island) in islands.iter().enumerate() {
+            for f in island.faces.iter_cpy() {
+                channel[f] = i as f32;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves, rotates and scales each connected component of the mesh by a
+    /// random amount, around its own `pivot`, either `"Centroid"` or
+    /// `"BoundsCenter"`. Rotations are XYZ euler angles in radians, and the
+    /// uniform scale is picked between `scale_min` and `scale_max`. The index
+    /// of each face's island is written to the f32 face channel
+    /// `index_channel`, when given.
+    #[lua(under = "Ops")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn transform_islands(
+        mesh: &mut HalfEdgeMesh,
+        translate_jitter: LVec3,
+        rotate_jitter: LVec3,
+        scale_min: f32,
+        scale_max: f32,
+        seed: u32,
+        pivot: IslandPivot,
+        index_channel: Option<String>,
+    ) -> Result<()> {
+        super::transform_islands(
+            mesh,
+            translate_jitter.0,
+            rotate_jitter.0,
+            (scale_min, scale_max),
+            seed,
+            pivot,
+            index_channel.as_deref().filter(|name| !name.is_empty()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn three_cubes() -> HalfEdgeMesh {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 6.0, Vec3::ONE).unwrap());
+        mesh
+    }
+
+    /// The distances between every pair of vertices in each island.
+    fn internal_distances(mesh: &HalfEdgeMesh) -> Vec<Vec<f32>> {
+        let positions = mesh.read_positions();
+        find_islands(mesh)
+            .unwrap()
+            .iter()
+            .map(|island| {
+                island
+                    .vertices
+                    .iter()
+                    .tuple_combinations()
+                    .map(|(a, b)| positions[*a].distance(positions[*b]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_islands_move_rigidly() {
+        let mut mesh = three_cubes();
+        let before = internal_distances(&mesh);
+        let positions_before = mesh.read_positions().clone();
+        transform_islands(
+            &mut mesh,
+            Vec3::splat(2.0),
+            Vec3::splat(1.0),
+            (1.0, 1.0),
+            7,
+            IslandPivot::Centroid,
+            None,
+        )
+        .unwrap();
+
+        let after = internal_distances(&mesh);
+        for (island_before, island_after) in before.iter().zip(&after) {
+            for (a, b) in island_before.iter().zip(island_after) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+        // Each island got its own transform
+        let positions = mesh.read_positions();
+        let offsets = find_islands(&mesh)
+            .unwrap()
+            .iter()
+            .map(|island| {
+                let v = island.vertices[0];
+                positions[v] - positions_before[v]
+            })
+            .collect_vec();
+        assert_ne!(offsets[0], offsets[1]);
+        assert_ne!(offsets[1], offsets[2]);
+    }
+
+    #[test]
+    fn test_scale_around_pivot() {
+        let mut mesh = three_cubes();
+        transform_islands(
+            &mut mesh,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            (2.0, 2.0),
+            0,
+            IslandPivot::BoundsCenter,
+            None,
+        )
+        .unwrap();
+        let (min, max) = crate::mesh::halfedge::analysis::bounds(&mesh);
+        assert!(min.abs_diff_eq(Vec3::splat(-1.0), 1e-5));
+        assert!(max.abs_diff_eq(Vec3::new(7.0, 1.0, 1.0), 1e-5));
+    }
+
+    #[test]
+    fn test_island_index_channel() {
+        let mut mesh = three_cubes();
+        transform_islands(
+            &mut mesh,
+            Vec3::ONE,
+            Vec3::ZERO,
+            (0.5, 1.5),
+            3,
+            IslandPivot::Centroid,
+            Some("island_index"),
+        )
+        .unwrap();
+        let channel = mesh
+            .channels
+            .read_channel_by_name::<FaceId, f32>("island_index")
+            .unwrap();
+        let values = mesh
+            .read_connectivity()
+            .iter_faces()
+            .map(|(f, _)| channel[f] as i32)
+            .collect::<HashSet<_>>();
+        assert_eq!(values, HashSet::from([0, 1, 2]));
+    }
+}
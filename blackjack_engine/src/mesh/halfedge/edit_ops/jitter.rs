@@ -0,0 +1,193 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+use crate::random::BjkRng;
+
+/// The directions along which [`jitter`] moves the vertices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitterMode {
+    /// The components of the amount are along the X, Y and Z axes.
+    World,
+    /// The Z component of the amount is along the vertex normal, and the X
+    /// and Y components are along two directions tangent to the surface.
+    Normal,
+}
+
+/// Moves each vertex in `selection` by a random offset, between `-amount` and
+/// `amount` on each axis. When a `mask_channel` is given, the offset of each
+/// vertex is scaled by its value in that f32 vertex channel.
+///
+/// The offsets only depend on the `seed` and the order of the vertices, so
+/// changing the mask doesn't change the direction vertices move in.
+pub fn jitter(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    amount: Vec3,
+    seed: u32,
+    mask_channel: Option<&str>,
+    mode: JitterMode,
+) -> Result<()> {
+    let vertices = mesh.resolve_vertex_selection_full(selection)?;
+
+    let normals = match mode {
+        JitterMode::World => None,
+        JitterMode::Normal => Some(match mesh.read_vertex_normals() {
+            Some(normals) => normals.clone(),
+            None => generate_smooth_normals_channel(mesh)?,
+        }),
+    };
+    let mask = mask_channel
+        .map(|name| mesh.channels.read_channel_by_name::<VertexId, f32>(name))
+        .transpose()?;
+
+    let mut positions = mesh.write_positions();
+    let mut rng = BjkRng::new(seed as u64);
+    for v in vertices {
+        let offset = rng.point_in_box(-amount, amount);
+        let weight = mask.as_ref().map(|mask| mask[v]).unwrap_or(1.0);
+        let offset = match &normals {
+            None => offset,
+            Some(normals) => {
+                let n = normals[v].normalize_or_zero();
+                if n == Vec3::ZERO {
+                    // Vertices with no faces around them have no normal
+                    continue;
+                }
+                let (t, bt) = n.any_orthonormal_pair();
+                t * offset.x + bt * offset.y + n * offset.z
+            }
+        };
+        positions[v] += offset * weight;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves each vertex in `selection` by a random offset between `-amount`
+    /// and `amount` on each axis. The offset is scaled by the value of the
+    /// vertices in the f32 `mask_channel`, if given. When `mode` is
+    /// `"Normal"`, the Z component of `amount` goes along the vertex normals,
+    /// and the X and Y components along the surface. The default mode is
+    /// `"World"`.
+    #[lua(under = "Ops")]
+    pub fn jitter(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        amount: LVec3,
+        seed: u32,
+        mask_channel: Option<String>,
+        mode: Option<String>,
+    ) -> Result<()> {
+        let mode = match mode.as_deref() {
+            None | Some("World") => JitterMode::World,
+            Some("Normal") => JitterMode::Normal,
+            Some(mode) => bail!("Invalid jitter mode '{mode}'"),
+        };
+        super::jitter(
+            mesh,
+            &selection,
+            amount.0,
+            seed,
+            mask_channel.as_deref().filter(|name| !name.is_empty()),
+            mode,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_positions(mesh: &HalfEdgeMesh) -> Vec<Vec3> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect()
+    }
+
+    fn jittered_box(amount: Vec3, seed: u32, mode: JitterMode) -> (HalfEdgeMesh, Positions) {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let before = mesh.read_positions().clone();
+        jitter(&mesh, &SelectionExpression::All, amount, seed, None, mode).unwrap();
+        (mesh, before)
+    }
+
+    #[test]
+    fn test_jitter_is_deterministic() {
+        let amount = Vec3::new(0.1, 0.2, 0.3);
+        let (a, _) = jittered_box(amount, 5, JitterMode::World);
+        let (b, _) = jittered_box(amount, 5, JitterMode::World);
+        let (c, _) = jittered_box(amount, 6, JitterMode::World);
+        assert_eq!(all_positions(&a), all_positions(&b));
+        assert_ne!(all_positions(&a), all_positions(&c));
+    }
+
+    #[test]
+    fn test_jitter_respects_amount() {
+        let amount = Vec3::new(0.1, 0.0, 0.3);
+        let (mesh, before) = jittered_box(amount, 1, JitterMode::World);
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            let offset = (positions[v] - before[v]).abs();
+            assert!(offset.cmple(amount).all());
+        }
+
+        // In normal mode, the Z component bounds the offset along the normal
+        let amount = Vec3::new(0.0, 0.0, 0.25);
+        let (mesh, before) = jittered_box(amount, 1, JitterMode::Normal);
+        let normals = generate_smooth_normals_channel(
+            &primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap(),
+        )
+        .unwrap();
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            let offset = positions[v] - before[v];
+            assert!(offset.length() <= 0.25 + 1e-5);
+            assert!(offset.normalize_or_zero().cross(normals[v]).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_jitter_zero_mask() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let mask_id = mesh.channels.ensure_channel::<VertexId, f32>("mask");
+        let first = {
+            let conn = mesh.read_connectivity();
+            let mut mask = mesh.channels.write_channel(mask_id).unwrap();
+            let mut vertices = conn.iter_vertices().map(|(v, _)| v);
+            let first = vertices.next().unwrap();
+            mask[first] = 1.0;
+            // The rest of the vertices have a mask of zero
+            first
+        };
+        let before = mesh.read_positions().clone();
+        jitter(
+            &mesh,
+            &SelectionExpression::All,
+            Vec3::ONE,
+            3,
+            Some("mask"),
+            JitterMode::World,
+        )
+        .unwrap();
+
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            if v == first {
+                assert_ne!(positions[v], before[v]);
+            } else {
+                assert_eq!(positions[v], before[v]);
+            }
+        }
+    }
+}
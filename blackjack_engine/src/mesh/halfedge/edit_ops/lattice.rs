@@ -0,0 +1,249 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::mesh::halfedge::primitives::lattice_rest_points;
+use crate::prelude::*;
+
+/// How [`lattice_deform`] blends the displacements of the control points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatticeInterpolation {
+    /// Each vertex follows the 8 control points of the lattice cell it's in.
+    Trilinear,
+    /// Each vertex follows all the control points, weighted by Bernstein
+    /// polynomials. Smoother, but every control point affects the whole
+    /// lattice.
+    Bezier,
+}
+
+fn binomial(n: usize, k: usize) -> f32 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f32 / (i + 1) as f32)
+}
+
+/// Returns the weight of each control point along one axis with `n` points,
+/// at normalized coordinate `t`. Only the points with a non-zero weight are
+/// returned.
+fn axis_weights(t: f32, n: usize, interpolation: LatticeInterpolation) -> Vec<(usize, f32)> {
+    match interpolation {
+        LatticeInterpolation::Trilinear => {
+            let x = t * (n - 1) as f32;
+            let cell = (x.floor() as usize).min(n - 2);
+            let frac = x - cell as f32;
+            vec![(cell, 1.0 - frac), (cell + 1, frac)]
+        }
+        LatticeInterpolation::Bezier => {
+            let degree = n - 1;
+            (0..n)
+                .map(|i| {
+                    let w = binomial(degree, i)
+                        * t.powi(i as i32)
+                        * (1.0 - t).powi((degree - i) as i32);
+                    (i, w)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Deforms `mesh` by moving the control points of a lattice. The vertices of
+/// `lattice` are the control points, in the order produced by
+/// [`primitives::Lattice`] for the same `rest_bounds` and `resolution`. Every
+/// vertex of the mesh inside the rest bounds is moved by the interpolated
+/// displacement of the control points from their rest positions, so an
+/// unmodified lattice leaves the mesh untouched.
+///
+/// Vertices outside the rest bounds don't move, unless `clamp` is set. In that
+/// case they follow the closest point on the boundary of the lattice.
+pub fn lattice_deform(
+    mesh: &HalfEdgeMesh,
+    lattice: &HalfEdgeMesh,
+    rest_bounds: (Vec3, Vec3),
+    resolution: UVec3,
+    interpolation: LatticeInterpolation,
+    clamp: bool,
+) -> Result<()> {
+    let (min, max) = rest_bounds;
+    let size = max - min;
+    if size.cmple(Vec3::ZERO).any() {
+        bail!("The rest bounds of the lattice must have a positive size along every axis");
+    }
+    if resolution.cmplt(UVec3::splat(2)).any() {
+        bail!("A lattice needs at least two points along each axis");
+    }
+
+    let rest = lattice_rest_points(min, max, resolution);
+    let displacements = {
+        let conn = lattice.read_connectivity();
+        let positions = lattice.read_positions();
+        if conn.num_vertices() != rest.len() {
+            bail!(
+                "The lattice has {} vertices, but a resolution of {}x{}x{} needs {}",
+                conn.num_vertices(),
+                resolution.x,
+                resolution.y,
+                resolution.z,
+                rest.len()
+            );
+        }
+        conn.iter_vertices()
+            .zip(rest)
+            .map(|((v, _), rest)| positions[v] - rest)
+            .collect_vec()
+    };
+
+    let (nx, ny, nz) = (
+        resolution.x as usize,
+        resolution.y as usize,
+        resolution.z as usize,
+    );
+    let conn = mesh.read_connectivity();
+    let mut positions = mesh.write_positions();
+    for (v, _) in conn.iter_vertices() {
+        let mut t = (positions[v] - min) / size;
+        let inside = t.cmpge(Vec3::ZERO).all() && t.cmple(Vec3::ONE).all();
+        if !inside {
+            if !clamp {
+                continue;
+            }
+            t = t.clamp(Vec3::ZERO, Vec3::ONE);
+        }
+        let wx = axis_weights(t.x, nx, interpolation);
+        let wy = axis_weights(t.y, ny, interpolation);
+        let wz = axis_weights(t.z, nz, interpolation);
+        let mut displacement = Vec3::ZERO;
+        for &(k, w_k) in &wz {
+            for &(j, w_j) in &wy {
+                for &(i, w_i) in &wx {
+                    displacement += displacements[i + nx * (j + ny * k)] * (w_i * w_j * w_k);
+                }
+            }
+        }
+        positions[v] += displacement;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Deforms `mesh` with a lattice, a point cloud created by
+    /// `Primitives.lattice` with the same bounds and resolution, whose points
+    /// were then moved. The `interpolation` is either `"Trilinear"` (the
+    /// default) or `"Bezier"`. Vertices outside the bounds are moved to follow
+    /// the boundary of the lattice when `clamp` is true, and left in place
+    /// otherwise.
+    #[lua(under = "Ops")]
+    pub fn lattice_deform(
+        mesh: &mut HalfEdgeMesh,
+        lattice: &HalfEdgeMesh,
+        rest_min: LVec3,
+        rest_max: LVec3,
+        resolution: LVec3,
+        interpolation: Option<String>,
+        clamp: Option<bool>,
+    ) -> Result<()> {
+        let interpolation = match interpolation.as_deref() {
+            None | Some("Trilinear") => LatticeInterpolation::Trilinear,
+            Some("Bezier") => LatticeInterpolation::Bezier,
+            Some(interpolation) => bail!("Invalid lattice interpolation '{interpolation}'"),
+        };
+        super::lattice_deform(
+            mesh,
+            lattice,
+            (rest_min.0, rest_max.0),
+            resolution.0.as_uvec3(),
+            interpolation,
+            clamp.unwrap_or(false),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_positions(mesh: &HalfEdgeMesh) -> Vec<Vec3> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect()
+    }
+
+    /// A point cloud filling the unit cube, plus one point outside of it.
+    fn points() -> HalfEdgeMesh {
+        let mesh = primitives::Lattice::build(Vec3::ZERO, Vec3::ONE, UVec3::splat(5)).unwrap();
+        mesh.write_connectivity()
+            .alloc_vertex(&mut mesh.write_positions(), Vec3::splat(2.0), None);
+        mesh
+    }
+
+    #[test]
+    fn test_undeformed_lattice_is_noop() {
+        let resolution = UVec3::new(3, 4, 2);
+        let lattice = primitives::Lattice::build(Vec3::ZERO, Vec3::ONE, resolution).unwrap();
+        for interpolation in [
+            LatticeInterpolation::Trilinear,
+            LatticeInterpolation::Bezier,
+        ] {
+            let mesh = points();
+            let before = all_positions(&mesh);
+            let bounds = (Vec3::ZERO, Vec3::ONE);
+            lattice_deform(&mesh, &lattice, bounds, resolution, interpolation, true).unwrap();
+            for (a, b) in all_positions(&mesh).iter().zip(before) {
+                assert!((*a - b).length() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lattice_corner_moves_octant() {
+        let resolution = UVec3::splat(3);
+        let lattice = primitives::Lattice::build(Vec3::ZERO, Vec3::ONE, resolution).unwrap();
+        {
+            let (corner, _) = lattice.read_connectivity().iter_vertices().next().unwrap();
+            let mut positions = lattice.write_positions();
+            assert_eq!(positions[corner], Vec3::ZERO);
+            positions[corner] = Vec3::new(-0.5, 0.0, 0.0);
+        }
+
+        let mesh = points();
+        let before = all_positions(&mesh);
+        let bounds = (Vec3::ZERO, Vec3::ONE);
+        let interpolation = LatticeInterpolation::Trilinear;
+        lattice_deform(&mesh, &lattice, bounds, resolution, interpolation, false).unwrap();
+        for (after, before) in all_positions(&mesh).iter().zip(before) {
+            if before.cmplt(Vec3::splat(0.5)).all() {
+                // The weight of the corner is 1 at the corner, and goes down
+                // to 0 at the center of the lattice.
+                let weight = (Vec3::ONE - before * 2.0)
+                    .to_array()
+                    .iter()
+                    .product::<f32>();
+                assert!((after.x - (before.x - 0.5 * weight)).abs() < 1e-5);
+                assert!(*after != before);
+            } else {
+                assert_eq!(*after, before);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lattice_wrong_vertex_count() {
+        let lattice = primitives::Lattice::build(Vec3::ZERO, Vec3::ONE, UVec3::splat(2)).unwrap();
+        let err = lattice_deform(
+            &points(),
+            &lattice,
+            (Vec3::ZERO, Vec3::ONE),
+            UVec3::splat(3),
+            LatticeInterpolation::Trilinear,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("has 8 vertices"));
+    }
+}
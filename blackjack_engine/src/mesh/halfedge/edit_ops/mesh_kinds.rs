@@ -0,0 +1,129 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::*;
+
+/// Returns an error unless `mesh` is a curve: a set of polylines, with no
+/// faces and no vertex having more than two edges.
+pub fn check_curve(mesh: &HalfEdgeMesh) -> Result<()> {
+    let conn = mesh.read_connectivity();
+    if conn.num_faces() > 0 {
+        bail!(
+            "The mesh is not a curve, it has {} faces. Curves only have edges.",
+            conn.num_faces()
+        );
+    }
+    // Without faces, every edge has one halfedge going out of each of its
+    // vertices.
+    let mut num_edges = HashMap::<VertexId, usize>::new();
+    for (h, _) in conn.iter_halfedges() {
+        let v = conn.at_halfedge(h).vertex().try_end()?;
+        let count = num_edges.entry(v).or_default();
+        *count += 1;
+        if *count > 2 {
+            bail!(
+                "The mesh is not a curve, vertex {v:?} has more than two edges. \
+                 Curves are made of lines that don't branch."
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns an error unless `mesh` is a point cloud: a set of vertices, with
+/// no edges or faces.
+pub fn check_points(mesh: &HalfEdgeMesh) -> Result<()> {
+    let conn = mesh.read_connectivity();
+    if conn.num_halfedges() > 0 {
+        bail!(
+            "The mesh is not a point cloud, it has {} edges. Point clouds only have vertices.",
+            conn.num_halfedges() / 2
+        );
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Returns a copy of `mesh` to be used as a curve. Fails when the mesh
+    /// has faces, or lines that branch.
+    #[lua(under = "Ops")]
+    pub fn to_curve(mesh: &HalfEdgeMesh) -> Result<HalfEdgeMesh> {
+        check_curve(mesh)?;
+        Ok(mesh.clone())
+    }
+
+    /// Returns a copy of `mesh` to be used as a point cloud. Fails when the
+    /// mesh has edges or faces.
+    #[lua(under = "Ops")]
+    pub fn to_points(mesh: &HalfEdgeMesh) -> Result<HalfEdgeMesh> {
+        check_points(mesh)?;
+        Ok(mesh.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::primitives;
+    use crate::prelude::selection::SelectionExpression;
+
+    /// Three edges going out of the origin. The `next` pointers are left
+    /// unset, the checks don't look at them.
+    fn star() -> HalfEdgeMesh {
+        let mesh = HalfEdgeMesh::new();
+        let mut conn = mesh.write_connectivity();
+        let mut positions = mesh.write_positions();
+        let center = conn.alloc_vertex(&mut positions, Vec3::ZERO, None);
+        let halfedge = |vertex| HalfEdge {
+            twin: None,
+            next: None,
+            vertex: Some(vertex),
+            face: None,
+        };
+        for tip in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let tip = conn.alloc_vertex(&mut positions, tip, None);
+            let h_out = conn.alloc_halfedge(halfedge(center));
+            let h_in = conn.alloc_halfedge(halfedge(tip));
+            conn[h_out].twin = Some(h_in);
+            conn[h_in].twin = Some(h_out);
+            conn[center].halfedge = Some(h_out);
+            conn[tip].halfedge = Some(h_in);
+        }
+        drop(conn);
+        drop(positions);
+        mesh
+    }
+
+    #[test]
+    fn test_curves() {
+        let line = primitives::Line::build_straight_line(Vec3::ZERO, Vec3::X, 4).unwrap();
+        check_curve(&line).unwrap();
+        assert!(check_points(&line).is_err());
+        let circle = primitives::Circle::build_open(Vec3::ZERO, 1.0, 8).unwrap();
+        check_curve(&circle).unwrap();
+
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let err = check_curve(&cube).unwrap_err().to_string();
+        assert!(err.contains("it has 6 faces"), "{err}");
+
+        let err = check_curve(&star()).unwrap_err().to_string();
+        assert!(err.contains("more than two edges"), "{err}");
+    }
+
+    #[test]
+    fn test_points() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let points =
+            crate::mesh::halfedge::edit_ops::point_cloud(&cube, SelectionExpression::All).unwrap();
+        check_points(&points).unwrap();
+        check_curve(&points).unwrap();
+        let err = check_points(&cube).unwrap_err().to_string();
+        assert!(err.contains("it has 12 edges"), "{err}");
+    }
+}
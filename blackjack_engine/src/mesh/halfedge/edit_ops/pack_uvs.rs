@@ -0,0 +1,274 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::mesh::halfedge::analysis::{uv_islands, UvIsland};
+use crate::prelude::*;
+use crate::progress::{report_warning, ProgressSink, Warning};
+
+/// The bounding rectangle of an island in uv space, and how it's placed in
+/// the packed layout.
+struct IslandRect {
+    min: Vec2,
+    size: Vec2,
+    /// When set, the island is turned 90 degrees, swapping its width and
+    /// height.
+    rotated: bool,
+}
+
+impl IslandRect {
+    fn new(island: &UvIsland, rotate: bool) -> Self {
+        let (min, max) = island
+            .polygons
+            .iter()
+            .flat_map(|polygon| polygon.uvs.iter().copied())
+            .fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(min, max), uv| (min.min(uv), max.max(uv)),
+            );
+        let size = (max - min).max(Vec2::ZERO);
+        // Shelves pack best when the islands are wider than they are tall
+        let rotated = rotate && size.y > size.x;
+        Self { min, size, rotated }
+    }
+
+    /// The size of the island once placed in the layout, before scaling.
+    fn placed_size(&self) -> Vec2 {
+        if self.rotated {
+            Vec2::new(self.size.y, self.size.x)
+        } else {
+            self.size
+        }
+    }
+
+    /// Maps a uv of the island to its position in the packed layout, where
+    /// the island's corner is at `offset` and it is scaled by `scale`.
+    fn transform(&self, uv: Vec2, offset: Vec2, scale: f32) -> Vec2 {
+        let local = uv - self.min;
+        let local = if self.rotated {
+            // A quarter turn counter-clockwise, so the island isn't mirrored
+            Vec2::new(self.size.y - local.y, local.x)
+        } else {
+            local
+        };
+        offset + local * scale
+    }
+}
+
+/// Places the `rects`, scaled by `scale`, in shelves along the unit square,
+/// with `margin` between them and around the borders. Returns the position of
+/// the corner of each rect, or `None` when they don't fit.
+fn shelf_pack(rects: &[IslandRect], order: &[usize], scale: f32, margin: f32) -> Option<Vec<Vec2>> {
+    // Each rect takes its size plus one margin, and the layout leaves one
+    // more margin at the far borders.
+    let available = 1.0 - margin;
+    let mut positions = vec![Vec2::ZERO; rects.len()];
+    let (mut x, mut y, mut shelf_height) = (0.0f32, 0.0f32, 0.0f32);
+    for &i in order {
+        let padded = rects[i].placed_size() * scale + Vec2::splat(margin);
+        if padded.x > available {
+            return None;
+        }
+        if x + padded.x > available {
+            y += shelf_height;
+            x = 0.0;
+            shelf_height = 0.0;
+        }
+        positions[i] = Vec2::new(x, y) + Vec2::splat(margin);
+        x += padded.x;
+        shelf_height = shelf_height.max(padded.y);
+    }
+    (y + shelf_height <= available).then_some(positions)
+}
+
+/// Packs the uv islands of the mesh into the unit square, so they don't
+/// overlap. Islands are groups of faces connected by edges with the same uvs
+/// at both sides, as found by [`uv_islands`]. Each island is moved as a
+/// whole, keeping its shape, and all of them are scaled by the same amount,
+/// as much as possible while still fitting.
+///
+/// Islands are kept at least `margin` apart from each other and from the
+/// borders of the square. When `rotate` is set, islands that are taller than
+/// they are wide are turned 90 degrees, which usually packs them tighter. A
+/// warning is reported when the islands have to be scaled down to fit.
+pub fn pack_uv_islands(
+    mesh: &mut HalfEdgeMesh,
+    margin: f32,
+    rotate: bool,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<()> {
+    if !(0.0..0.5).contains(&margin) {
+        bail!("The margin must be between 0 and 0.5, but it was {margin}");
+    }
+    let islands = uv_islands(mesh)?;
+    if islands.is_empty() {
+        return Ok(());
+    }
+    let rects = islands
+        .iter()
+        .map(|island| IslandRect::new(island, rotate))
+        .collect_vec();
+    let order = (0..rects.len())
+        .sorted_by(|a, b| {
+            let (a, b) = (rects[*a].placed_size(), rects[*b].placed_size());
+            b.y.total_cmp(&a.y).then(b.x.total_cmp(&a.x))
+        })
+        .collect_vec();
+
+    // Looks for the largest scale that fits, starting from the scale where
+    // the biggest island alone would fill the square.
+    let largest = rects
+        .iter()
+        .map(|rect| rect.size.max_element())
+        .fold(1e-6, f32::max);
+    let (mut low, mut high) = (0.0, (1.0 - 2.0 * margin) / largest);
+    let mut best = match shelf_pack(&rects, &order, low, margin) {
+        Some(positions) => (low, positions),
+        None => bail!(
+            "There are too many uv islands ({}) to keep a margin of {margin} between them",
+            rects.len()
+        ),
+    };
+    for _ in 0..32 {
+        let scale = (low + high) * 0.5;
+        match shelf_pack(&rects, &order, scale, margin) {
+            Some(positions) => {
+                best = (scale, positions);
+                low = scale;
+            }
+            None => high = scale,
+        }
+    }
+    let (scale, positions) = best;
+    if scale < 1.0 {
+        report_warning(
+            sink,
+            Warning::new(format!(
+                "The uv islands didn't fit in the unit square, so they were scaled down to \
+                 {:.1}% of their size",
+                scale * 100.0
+            )),
+        );
+    }
+
+    let uvs_ch_id = mesh
+        .default_channels
+        .uvs
+        .ok_or_else(|| anyhow!("The mesh has no uv channel"))?;
+    let conn = mesh.read_connectivity();
+    let mut uvs = mesh.channels.write_channel(uvs_ch_id)?;
+    for ((island, rect), offset) in islands.iter().zip(&rects).zip(positions) {
+        for polygon in &island.polygons {
+            // The polygon lists its uvs in the order of the halfedges
+            for (h, uv) in conn.face_edges(polygon.face).iter().zip(&polygon.uvs) {
+                uvs[*h] = rect.transform(*uv, offset, scale).extend(0.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Packs the uv islands of `mesh` into the unit square without overlaps,
+    /// at least `margin` apart. When `rotate` is set, tall islands are turned
+    /// 90 degrees to pack them tighter.
+    #[lua(under = "Ops")]
+    pub fn pack_uv_islands(mesh: &mut HalfEdgeMesh, margin: f32, rotate: bool) -> Result<()> {
+        let sink = crate::progress::current_sink();
+        super::pack_uv_islands(mesh, margin, rotate, sink.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::set_full_range_uvs;
+    use crate::progress::MockSink;
+
+    /// Returns the (min, max) corners of the uv bounds of each island.
+    fn island_bounds(mesh: &HalfEdgeMesh) -> Vec<(Vec2, Vec2)> {
+        uv_islands(mesh)
+            .unwrap()
+            .iter()
+            .map(|island| {
+                let rect = IslandRect::new(island, false);
+                (rect.min, rect.min + rect.size)
+            })
+            .collect()
+    }
+
+    fn assert_packed(bounds: &[(Vec2, Vec2)], margin: f32) {
+        let eps = 1e-4;
+        for (min, max) in bounds {
+            assert!(min.min_element() >= margin - eps, "{min} {max}");
+            assert!(max.max_element() <= 1.0 - margin + eps, "{min} {max}");
+        }
+        for (i, (min_a, max_a)) in bounds.iter().enumerate() {
+            for (min_b, max_b) in &bounds[i + 1..] {
+                // Separated along at least one of the axes
+                let gap = (*min_b - *max_a).max(*min_a - *max_b);
+                assert!(
+                    gap.max_element() >= margin - eps,
+                    "Islands {min_a}-{max_a} and {min_b}-{max_b} are too close"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_cube_sides() {
+        // Like a box projection, every side covers the whole uv square
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        set_full_range_uvs(&mut cube).unwrap();
+        let sink = MockSink::new(None);
+        pack_uv_islands(&mut cube, 0.02, true, Some(&sink)).unwrap();
+
+        let bounds = island_bounds(&cube);
+        assert_eq!(bounds.len(), 6);
+        assert_packed(&bounds, 0.02);
+        // The sides stay square, and all of them have the same size
+        let size = bounds[0].1 - bounds[0].0;
+        for (min, max) in &bounds {
+            assert!((*max - *min).abs_diff_eq(size, 1e-4));
+        }
+        assert!(size.x > 0.25);
+        // Six unit squares can't fit without shrinking them
+        assert_eq!(sink.warnings.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_pack_rotates_tall_islands() {
+        let mut quad = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        set_full_range_uvs(&mut quad).unwrap();
+        {
+            let uvs_ch_id = quad.default_channels.uvs.unwrap();
+            let mut uvs = quad.channels.write_channel(uvs_ch_id).unwrap();
+            for (_, uv) in uvs.iter_mut() {
+                uv.x *= 0.2;
+            }
+        }
+
+        let mut rotated = quad.clone();
+        pack_uv_islands(&mut rotated, 0.0, true, None).unwrap();
+        let (min, max) = island_bounds(&rotated)[0];
+        assert!(((max.x - min.x) / (max.y - min.y) - 5.0).abs() < 1e-3);
+
+        pack_uv_islands(&mut quad, 0.0, false, None).unwrap();
+        let (min, max) = island_bounds(&quad)[0];
+        assert!(((max.y - min.y) / (max.x - min.x) - 5.0).abs() < 1e-3);
+        // Alone, the island fills the square along its longest side
+        assert!((max.y - min.y - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_pack_bad_margin() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        set_full_range_uvs(&mut cube).unwrap();
+        assert!(pack_uv_islands(&mut cube, 0.6, true, None).is_err());
+    }
+}
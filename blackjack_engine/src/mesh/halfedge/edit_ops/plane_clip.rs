@@ -0,0 +1,387 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Clipping meshes against a plane. The faces of a mesh are clipped into a
+//! [`ClippedMesh`], a polygon soup that remembers which elements of the
+//! original mesh each of its elements comes from. Ops can add more polygons
+//! to it before building the result, which gets the channels of the original
+//! mesh interpolated at the cut.
+
+use smallvec::smallvec;
+
+use crate::mesh::halfedge::id_remap::{ElementRemap, IdRemap};
+use crate::prelude::*;
+
+use super::{set_flat_normals, set_smooth_normals};
+
+/// A plane, given by a point on it and its unit normal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub origin: Vec3,
+    pub normal: Vec3,
+}
+
+impl Plane {
+    /// Returns the plane through `origin` perpendicular to `normal`, which
+    /// doesn't need to be normalized.
+    pub fn new(origin: Vec3, normal: Vec3) -> Result<Self> {
+        let normal = normal.normalize_or_zero();
+        if normal == Vec3::ZERO {
+            bail!("The normal of a plane can't be zero");
+        }
+        Ok(Self { origin, normal })
+    }
+
+    /// The signed distance from `p` to the plane. Points on the side the
+    /// normal points to are at a positive distance.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        (p - self.origin).dot(self.normal)
+    }
+
+    /// Returns the closest point to `p` on the plane.
+    pub fn project(&self, p: Vec3) -> Vec3 {
+        p - self.distance(p) * self.normal
+    }
+
+    /// Returns the mirror image of `p` at the other side of the plane.
+    pub fn mirror(&self, p: Vec3) -> Vec3 {
+        p - 2.0 * self.distance(p) * self.normal
+    }
+}
+
+/// A set of polygons built from the faces of a mesh. Each vertex, polygon and
+/// corner stores the elements of the original mesh it interpolates, so the
+/// channels of the mesh can be carried over to the result.
+#[derive(Debug, Clone, Default)]
+pub struct ClippedMesh {
+    pub positions: Vec<Vec3>,
+    /// For each vertex, the vertices of the original mesh it interpolates and
+    /// their weights.
+    pub vertex_sources: Vec<SVec<(VertexId, f32)>>,
+    /// The vertex indices of each polygon.
+    pub polygons: Vec<SVec<u32>>,
+    /// For each polygon, the original face it is a part of, if any.
+    pub face_sources: Vec<Option<FaceId>>,
+    /// For each polygon, the original corners each of its corners interpolate.
+    /// Corners with no sources get the default channel values.
+    pub corner_sources: Vec<SVec<SVec<(HalfEdgeId, f32)>>>,
+    /// The vertices added for each original vertex, or edge cut at a given
+    /// factor. Edges are keyed by their endpoints in ascending order.
+    added: HashMap<(VertexId, Option<VertexId>), u32>,
+}
+
+impl ClippedMesh {
+    /// Adds a vertex at `position`, interpolating the original vertices in
+    /// `sources`, and returns its index.
+    pub fn add_vertex(&mut self, position: Vec3, sources: SVec<(VertexId, f32)>) -> u32 {
+        self.positions.push(position);
+        self.vertex_sources.push(sources);
+        (self.positions.len() - 1) as u32
+    }
+
+    /// Adds a polygon. The `corners` are the sources of each of its corners,
+    /// in the same order as the `vertices`.
+    pub fn add_polygon(
+        &mut self,
+        vertices: SVec<u32>,
+        face: Option<FaceId>,
+        corners: SVec<SVec<(HalfEdgeId, f32)>>,
+    ) {
+        debug_assert_eq!(vertices.len(), corners.len());
+        self.polygons.push(vertices);
+        self.face_sources.push(face);
+        self.corner_sources.push(corners);
+    }
+
+    /// Returns the index of the vertex for the original vertex `v`, adding it
+    /// the first time.
+    fn original_vertex(&mut self, v: VertexId, position: Vec3) -> u32 {
+        if let Some(idx) = self.added.get(&(v, None)) {
+            return *idx;
+        }
+        let idx = self.add_vertex(position, smallvec![(v, 1.0)]);
+        self.added.insert((v, None), idx);
+        idx
+    }
+
+    /// Returns the index of the vertex at factor `t` between the original
+    /// vertices `a` and `b`, adding it the first time. The faces at both sides
+    /// of the edge share the same vertex.
+    fn cut_vertex(&mut self, a: VertexId, b: VertexId, t: f32, position: Vec3) -> u32 {
+        let (a, b, t) = if a < b { (a, b, t) } else { (b, a, 1.0 - t) };
+        if let Some(idx) = self.added.get(&(a, Some(b))) {
+            return *idx;
+        }
+        let idx = self.add_vertex(position, smallvec![(a, 1.0 - t), (b, t)]);
+        self.added.insert((a, Some(b)), idx);
+        idx
+    }
+
+    /// Clips the faces of `mesh` against `plane`, and adds the parts above the
+    /// plane, or below it when `above` is false. Edges crossing the plane are
+    /// cut at the intersection, shared by the faces at both sides.
+    ///
+    /// Vertices closer than `epsilon` to the plane count as being on it, and
+    /// belong to both sides. Faces with all their vertices on the plane are
+    /// kept on the side opposite to their normal, where the rest of the
+    /// surface usually is.
+    pub fn add_clipped_faces(
+        &mut self,
+        mesh: &HalfEdgeMesh,
+        plane: &Plane,
+        above: bool,
+        epsilon: f32,
+    ) -> Result<()> {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let side = if above { 1.0 } else { -1.0 };
+        for (face, _) in conn.iter_faces() {
+            let corners = conn
+                .face_edges(face)
+                .iter_cpy()
+                .map(|h| Ok((h, conn.at_halfedge(h).vertex().try_end()?)))
+                .collect::<Result<SVec<_>>>()?;
+            let distances = corners
+                .iter()
+                .map(|(_, v)| plane.distance(positions[*v]) * side)
+                .collect::<SVec<_>>();
+
+            if distances.iter().all(|d| d.abs() <= epsilon) {
+                let normal = conn.face_normal(&positions, face).unwrap_or(Vec3::ZERO);
+                if normal.dot(plane.normal) * side < 0.0 {
+                    let vertices = corners
+                        .iter()
+                        .map(|(_, v)| self.original_vertex(*v, positions[*v]))
+                        .collect();
+                    let corners = corners.iter().map(|(h, _)| smallvec![(*h, 1.0)]).collect();
+                    self.add_polygon(vertices, Some(face), corners);
+                }
+                continue;
+            }
+
+            let mut vertices = SVec::new();
+            let mut polygon_corners = SVec::new();
+            for i in 0..corners.len() {
+                let j = (i + 1) % corners.len();
+                let ((h_i, v_i), (h_j, v_j)) = (corners[i], corners[j]);
+                let (d_i, d_j) = (distances[i], distances[j]);
+                if d_i >= -epsilon {
+                    vertices.push(self.original_vertex(v_i, positions[v_i]));
+                    polygon_corners.push(smallvec![(h_i, 1.0)]);
+                }
+                let crosses =
+                    (d_i > epsilon && d_j < -epsilon) || (d_i < -epsilon && d_j > epsilon);
+                if crosses {
+                    let t = d_i / (d_i - d_j);
+                    let position = positions[v_i].lerp(positions[v_j], t);
+                    vertices.push(self.cut_vertex(v_i, v_j, t, position));
+                    polygon_corners.push(smallvec![(h_i, 1.0 - t), (h_j, t)]);
+                }
+            }
+            // Faces touching the plane from the other side leave one or two
+            // vertices on it, which are not a polygon.
+            if vertices.len() >= 3 {
+                self.add_polygon(vertices, Some(face), polygon_corners);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the faces clipped after this call use their own copies of the
+    /// vertices, instead of sharing them with the faces clipped before. This
+    /// separates the sides of the cut.
+    pub fn start_new_part(&mut self) {
+        self.added.clear();
+    }
+
+    /// Fills the holes left by the cut, adding a polygon for each loop of
+    /// boundary edges lying on `plane`. Returns the number of polygons added.
+    /// Loops inside other loops, like the two walls of a tube, are each
+    /// filled on their own.
+    pub fn add_caps(&mut self, plane: &Plane, epsilon: f32) -> usize {
+        let on_plane = |idx: u32| plane.distance(self.positions[idx as usize]).abs() <= epsilon;
+        let edges = self
+            .polygons
+            .iter()
+            .flat_map(|polygon| polygon.iter_cpy().circular_tuple_windows())
+            .collect::<HashSet<(u32, u32)>>();
+        // The next vertex along the boundary of each vertex on the plane. A
+        // vertex where several boundary loops meet can't be followed.
+        let mut next = HashMap::<u32, Option<u32>>::new();
+        let mut starts = vec![];
+        for polygon in &self.polygons {
+            for (a, b) in polygon.iter_cpy().circular_tuple_windows() {
+                if edges.contains(&(b, a)) || !on_plane(a) || !on_plane(b) {
+                    continue;
+                }
+                next.entry(a).and_modify(|n| *n = None).or_insert(Some(b));
+                starts.push(a);
+            }
+        }
+
+        let mut caps = vec![];
+        let mut visited = HashSet::new();
+        for start in starts {
+            if visited.contains(&start) {
+                continue;
+            }
+            let mut cap_loop = vec![start];
+            let mut current = start;
+            let closed = loop {
+                match next.get(&current).copied().flatten() {
+                    Some(n) if n == start => break true,
+                    Some(n) if !visited.contains(&n) && !cap_loop.contains(&n) => {
+                        cap_loop.push(n);
+                        current = n;
+                    }
+                    _ => break false,
+                }
+            };
+            visited.extend(cap_loop.iter_cpy());
+            if closed && cap_loop.len() >= 3 {
+                // The boundary goes around the hole the other way
+                cap_loop.reverse();
+                caps.push(cap_loop);
+            }
+        }
+
+        let num_caps = caps.len();
+        for cap in caps {
+            let corners = cap.iter().map(|_| SVec::new()).collect();
+            self.add_polygon(cap.into_iter().collect(), None, corners);
+        }
+        num_caps
+    }
+
+    /// Builds a mesh with the polygons, and the channels of `mesh`
+    /// interpolated at each element. The result keeps the lineage of `mesh`,
+    /// with a remap from each of its elements to the copies kept in the
+    /// result. Vertices and polygons not used by any polygon are left out.
+    pub fn build(&self, mesh: &HalfEdgeMesh) -> Result<HalfEdgeMesh> {
+        let mut result = HalfEdgeMesh::build_from_polygons(&self.positions, &self.polygons)?;
+
+        // The vertices are created in the order polygons first use them, and
+        // the halfedges of each polygon in order, before the boundary ones.
+        let mut vertex_order = vec![None; self.positions.len()];
+        let mut used_vertices = vec![];
+        for idx in self.polygons.iter().flatten() {
+            if vertex_order[*idx as usize].is_none() {
+                vertex_order[*idx as usize] = Some(used_vertices.len() as u32);
+                used_vertices.push(*idx);
+            }
+        }
+        let (new_vertices, new_faces, new_corners) = {
+            let conn = result.read_connectivity();
+            let corners = conn
+                .iter_halfedges()
+                .filter(|(_, h)| h.face.is_some())
+                .map(|(h, _)| h)
+                .collect_vec();
+            (
+                conn.iter_vertices().map(|(v, _)| v).collect_vec(),
+                conn.iter_faces().map(|(f, _)| f).collect_vec(),
+                corners,
+            )
+        };
+
+        let vertex_sources = used_vertices
+            .iter()
+            .map(|idx| self.vertex_sources[*idx as usize].clone())
+            .collect_vec();
+        let face_sources = self
+            .face_sources
+            .iter()
+            .map(|f| f.iter().map(|f| (*f, 1.0)).collect::<SVec<_>>())
+            .collect_vec();
+        let corner_sources = self.corner_sources.iter().flatten().cloned().collect_vec();
+        result
+            .channels
+            .interpolate_from(&mesh.channels, &new_vertices, &vertex_sources)?;
+        result
+            .channels
+            .interpolate_from(&mesh.channels, &new_faces, &face_sources)?;
+        result
+            .channels
+            .interpolate_from(&mesh.channels, &new_corners, &corner_sources)?;
+        {
+            // Interpolating the positions is not enough for vertices that were
+            // moved, like mirrored ones.
+            let mut positions = result.write_positions();
+            for (v, idx) in new_vertices.iter_cpy().zip(used_vertices.iter_cpy()) {
+                positions[v] = self.positions[idx as usize];
+            }
+        }
+
+        if mesh.default_channels.uvs.is_some() {
+            result.default_channels.uvs = result.channels.channel_id("uv");
+        }
+        if mesh.default_channels.vertex_normals.is_some() {
+            set_smooth_normals(&mut result)?;
+        }
+        if mesh.default_channels.face_normals.is_some() {
+            set_flat_normals(&mut result)?;
+        }
+        result.gen_config = mesh.gen_config.clone();
+
+        let remap = self.id_remap(&mesh.read_connectivity(), &vertex_order);
+        *result.lineage_mut() = mesh.lineage().clone();
+        result.lineage_mut().push_remap(remap);
+        Ok(result)
+    }
+
+    /// Returns the remap from the elements of the original mesh to the ones in
+    /// the built mesh that are exact copies of them.
+    fn id_remap(&self, conn: &MeshConnectivity, vertex_order: &[Option<u32>]) -> IdRemap {
+        fn copy_of<K: Copy>(sources: &[(K, f32)]) -> Option<K> {
+            match sources {
+                [(k, w)] if *w == 1.0 => Some(*k),
+                _ => None,
+            }
+        }
+
+        let vertex_idx = conn
+            .iter_vertices()
+            .enumerate()
+            .map(|(i, (v, _))| (v, i))
+            .collect::<HashMap<_, _>>();
+        let mut vertices = vec![SVec::new(); conn.num_vertices()];
+        for (idx, sources) in self.vertex_sources.iter().enumerate() {
+            if let (Some(v), Some(new_idx)) = (copy_of(sources), vertex_order[idx]) {
+                vertices[vertex_idx[&v]].push(new_idx);
+            }
+        }
+
+        let face_idx = conn
+            .iter_faces()
+            .enumerate()
+            .map(|(i, (f, _))| (f, i))
+            .collect::<HashMap<_, _>>();
+        let mut faces = vec![SVec::new(); conn.num_faces()];
+        for (new_idx, face) in self.face_sources.iter().enumerate() {
+            if let Some(f) = face {
+                faces[face_idx[f]].push(new_idx as u32);
+            }
+        }
+
+        let halfedge_idx = conn
+            .iter_halfedges()
+            .enumerate()
+            .map(|(i, (h, _))| (h, i))
+            .collect::<HashMap<_, _>>();
+        let mut halfedges = vec![SVec::new(); conn.num_halfedges()];
+        for (new_idx, sources) in self.corner_sources.iter().flatten().enumerate() {
+            if let Some(h) = copy_of(sources) {
+                halfedges[halfedge_idx[&h]].push(new_idx as u32);
+            }
+        }
+
+        IdRemap {
+            vertices: ElementRemap::new(vertices),
+            faces: ElementRemap::new(faces),
+            halfedges: ElementRemap::new(halfedges),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+use super::{make_quad, sort_bag_of_edges};
+
+/// Returns the rotations that carry a frame along a curve with the given
+/// `tangents`, without twisting it. The first rotation is the identity, and
+/// each of the next ones adds the smallest rotation between two consecutive
+/// tangents. This is the discrete parallel transport used by sweeps.
+pub fn parallel_transport(tangents: &[Vec3]) -> Vec<Quat> {
+    let mut rotations = Vec::with_capacity(tangents.len());
+    let mut current = Quat::IDENTITY;
+    for (i, tangent) in tangents.iter().enumerate() {
+        if i > 0 {
+            let (from, to) = (tangents[i - 1].normalize(), tangent.normalize());
+            current = (Quat::from_rotation_arc(from, to) * current).normalize();
+        }
+        rotations.push(current);
+    }
+    rotations
+}
+
+/// Returns the tangents at the `points` of a polyline. Inner points use the
+/// bisector of their two segments, so the profile is cut at a miter there.
+fn polyline_tangents(points: &[Vec3]) -> Vec<Vec3> {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let before = (i > 0).then(|| (points[i] - points[i - 1]).normalize());
+            let after = (i + 1 < n).then(|| (points[i + 1] - points[i]).normalize());
+            match (before, after) {
+                (Some(b), Some(a)) if (a + b).length_squared() > 1e-12 => (a + b).normalize(),
+                (Some(b), _) => b,
+                (None, Some(a)) => a,
+                (None, None) => Vec3::ZERO,
+            }
+        })
+        .collect()
+}
+
+/// Returns the vertices of the `rail` polyline, in order. Open rails start
+/// at the end with the oldest vertex.
+fn rail_points(rail: &HalfEdgeMesh) -> Result<Vec<Vec3>> {
+    let conn = rail.read_connectivity();
+    let positions = rail.read_positions();
+    let bag = rail.resolve_halfedge_selection_full(&SelectionExpression::All)?;
+    if bag.is_empty() {
+        bail!("The rail needs at least one segment");
+    }
+    let (mut chain, is_closed) = sort_bag_of_edges(&conn, &bag)?;
+    if is_closed {
+        bail!("The rail must be an open polyline, but it's closed");
+    }
+    if chain.first() > chain.last() {
+        chain.reverse();
+    }
+    Ok(chain.iter().map(|v| positions[*v]).collect())
+}
+
+/// Returns the vertices along the chain of `boundary` halfedges, in the order
+/// of the chain, and whether the chain is a closed loop. Every selected
+/// halfedge must be a boundary halfedge, and they must form a single chain.
+fn boundary_chain(
+    mesh: &HalfEdgeMesh,
+    boundary: &SelectionExpression,
+) -> Result<(Vec<VertexId>, bool)> {
+    let conn = mesh.read_connectivity();
+    let selected = mesh.resolve_halfedge_selection_full(boundary)?;
+    if selected.is_empty() {
+        bail!("Rail extrude needs boundary edges, but the selection is empty");
+    }
+    for h in selected.iter_cpy() {
+        if !conn.at_halfedge(h).is_boundary()? {
+            bail!("Rail extrude needs boundary halfedges, but {h:?} is not in a boundary");
+        }
+    }
+    let selected_set: HashSet<HalfEdgeId> = selected.iter_cpy().collect();
+    let mut prev = HashMap::new();
+    for h in selected.iter_cpy() {
+        prev.insert(conn.at_halfedge(h).next().try_end()?, h);
+    }
+
+    // A chain starts at a halfedge whose predecessor is not selected. When
+    // there's none, the halfedges form a closed loop.
+    let start = selected
+        .iter_cpy()
+        .find(|h| !matches!(prev.get(h), Some(p) if selected_set.contains(p)));
+    let is_closed = start.is_none();
+    let start = start.unwrap_or(selected[0]);
+
+    let mut vertices = vec![conn.at_halfedge(start).vertex().try_end()?];
+    let mut h = start;
+    let mut num_edges = 0;
+    loop {
+        num_edges += 1;
+        h = conn.at_halfedge(h).next().try_end()?;
+        if h == start || !selected_set.contains(&h) {
+            break;
+        }
+        vertices.push(conn.at_halfedge(h).vertex().try_end()?);
+    }
+    if num_edges != selected_set.len() {
+        bail!("The selected boundary edges form more than one chain");
+    }
+    if !is_closed {
+        // The destination of the last halfedge closes the chain
+        let (_, dst) = conn.at_halfedge(prev[&h]).src_dst_pair()?;
+        vertices.push(dst);
+    }
+    Ok((vertices, is_closed))
+}
+
+/// Extrudes the `boundary` edges of the mesh repeatedly, following the `rail`
+/// polyline. For each segment of the rail, the boundary is extruded by the
+/// segment vector, adding a row of quads. The boundary keeps its offset from
+/// the start of the rail, so the rail doesn't need to touch the mesh.
+///
+/// When `align` is set, the profile is also rotated to follow the turns of
+/// the rail, using parallel transport so it doesn't twist. At the inner
+/// points of the rail the profile is cut along the bisector of the two
+/// segments. Otherwise, the profile is only translated.
+///
+/// Every selected halfedge must be a boundary halfedge, and together they
+/// must form a single chain, either open or a closed loop.
+pub fn rail_extrude(
+    mesh: &mut HalfEdgeMesh,
+    boundary: &SelectionExpression,
+    rail: &HalfEdgeMesh,
+    align: bool,
+) -> Result<()> {
+    let (chain, is_closed) = boundary_chain(mesh, boundary)?;
+    let points = rail_points(rail)?;
+    let rotations = if align {
+        parallel_transport(&polyline_tangents(&points))
+    } else {
+        vec![Quat::IDENTITY; points.len()]
+    };
+
+    let profile = {
+        let positions = mesh.read_positions();
+        chain
+            .iter()
+            .map(|v| positions[*v] - points[0])
+            .collect_vec()
+    };
+
+    let mut conn = mesh.write_connectivity();
+    let mut positions = mesh.write_positions();
+    let mut current = chain;
+    for (point, rotation) in points.iter().zip(rotations).skip(1) {
+        let next = profile
+            .iter()
+            .map(|offset| conn.alloc_vertex(&mut positions, *point + rotation * *offset, None))
+            .collect_vec();
+        let pairs = (0..current.len()).branch(
+            is_closed,
+            |x| x.circular_tuple_windows(),
+            |x| x.tuple_windows(),
+        );
+        for (i, j) in pairs {
+            // The boundary halfedge goes from `i` to `j`, so the quad uses it
+            // as is, and the new boundary goes from `i` to `j` as well.
+            make_quad(&mut conn, &[current[i], current[j], next[j], next[i]])?;
+        }
+        current = next;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Extrudes the `boundary` edges of `mesh` once for each segment of the
+    /// `rail` polyline. When `align` is set, the profile turns to follow the
+    /// rail.
+    #[lua(under = "Ops")]
+    pub fn rail_extrude(
+        mesh: &mut HalfEdgeMesh,
+        boundary: SelectionExpression,
+        rail: &HalfEdgeMesh,
+        align: bool,
+    ) -> Result<()> {
+        super::rail_extrude(mesh, &boundary, rail, align)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    /// A unit square on the XY plane, and a selection with the boundary
+    /// halfedge of its top edge.
+    fn square() -> (HalfEdgeMesh, SelectionExpression) {
+        let positions = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let mesh = HalfEdgeMesh::build_from_polygons(&positions, &[[0, 1, 2, 3]]).unwrap();
+        let top = {
+            let conn = mesh.read_connectivity();
+            let mesh_positions = mesh.read_positions();
+            conn.iter_halfedges()
+                .position(|(h, data)| {
+                    let (src, dst) = conn.at_halfedge(h).src_dst_pair().unwrap();
+                    data.face.is_none()
+                        && mesh_positions[src].y == 1.0
+                        && mesh_positions[dst].y == 1.0
+                })
+                .unwrap()
+        };
+        (mesh, SelectionExpression::from_ids(vec![top as u32]))
+    }
+
+    /// Goes up from the top left corner of the square, then turns 90 degrees
+    /// towards +X.
+    fn bent_rail() -> HalfEdgeMesh {
+        let points = [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(2.0, 3.0, 0.0),
+        ];
+        primitives::Line::build(&|i| points[i as usize], 2).unwrap()
+    }
+
+    /// Returns the direction of the last edge of the profile, by looking at
+    /// the two newest vertices.
+    fn end_direction(mesh: &HalfEdgeMesh) -> Vec3 {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let last = conn
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect_vec();
+        (last[last.len() - 1] - last[last.len() - 2]).normalize()
+    }
+
+    #[test]
+    fn test_rail_extrude_aligned() {
+        let (mut mesh, top) = square();
+        rail_extrude(&mut mesh, &top, &bent_rail(), true).unwrap();
+        assert!(validate(&mesh).is_valid());
+        let conn = mesh.read_connectivity();
+        // Two new vertices and one quad per rail segment
+        assert_eq!(conn.num_vertices(), 4 + 2 * 2);
+        assert_eq!(conn.num_faces(), 1 + 2);
+        drop(conn);
+        // The edge started along X, and the rail turned from +Y to +X
+        let dir = end_direction(&mesh);
+        assert!(dir.abs_diff_eq(Vec3::NEG_Y, 1e-5), "{dir}");
+        // The profile sits at the end of the rail
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        assert!(conn
+            .iter_vertices()
+            .any(|(v, _)| positions[v].abs_diff_eq(Vec3::new(2.0, 3.0, 0.0), 1e-5)));
+    }
+
+    #[test]
+    fn test_rail_extrude_translate_only() {
+        let (mut mesh, top) = square();
+        rail_extrude(&mut mesh, &top, &bent_rail(), false).unwrap();
+        assert!(validate(&mesh).is_valid());
+        assert_eq!(mesh.read_connectivity().num_vertices(), 8);
+        let dir = end_direction(&mesh);
+        assert!(dir.abs_diff_eq(Vec3::X, 1e-5), "{dir}");
+    }
+
+    #[test]
+    fn test_rail_extrude_closed_loop() {
+        let (mut mesh, _) = square();
+        let boundary = {
+            let conn = mesh.read_connectivity();
+            let ids = conn
+                .iter_halfedges()
+                .enumerate()
+                .filter(|(_, (_, h))| h.face.is_none())
+                .map(|(i, _)| i as u32)
+                .collect_vec();
+            SelectionExpression::from_ids(ids)
+        };
+        let rail = primitives::Line::build(&|i| Vec3::Z * i as f32, 3).unwrap();
+        rail_extrude(&mut mesh, &boundary, &rail, true).unwrap();
+        assert!(validate(&mesh).is_valid());
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_vertices(), 4 * 4);
+        assert_eq!(conn.num_faces(), 1 + 4 * 3);
+    }
+
+    #[test]
+    fn test_rail_extrude_needs_boundary() {
+        let (mut mesh, _) = square();
+        let err =
+            rail_extrude(&mut mesh, &SelectionExpression::All, &bent_rail(), true).unwrap_err();
+        assert!(err.to_string().contains("not in a boundary"), "{err}");
+    }
+
+    #[test]
+    fn test_parallel_transport() {
+        let tangents = [Vec3::Y, Vec3::new(1.0, 1.0, 0.0), Vec3::X];
+        let rotations = parallel_transport(&tangents);
+        assert_eq!(rotations[0], Quat::IDENTITY);
+        for (rotation, tangent) in rotations.iter().zip(tangents) {
+            assert!((*rotation * Vec3::Y).abs_diff_eq(tangent.normalize(), 1e-5));
+            // Nothing spins around the axis of the bend
+            assert!((*rotation * Vec3::Z).abs_diff_eq(Vec3::Z, 1e-5));
+        }
+    }
+}
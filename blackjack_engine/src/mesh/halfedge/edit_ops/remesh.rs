@@ -0,0 +1,679 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use slotmap::SecondaryMap;
+
+use crate::mesh::halfedge::bvh::MeshBvh;
+use crate::prelude::*;
+use crate::progress::{report_progress, ProgressSink};
+
+/// How a vertex of the remeshed surface is allowed to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum VertexKind {
+    /// Slides on the surface.
+    Free,
+    /// On a sharp edge or the boundary. Only slides along it.
+    Feature,
+    /// Where sharp edges meet or end. Never moves.
+    Corner,
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Returns the closest point to `p` on any of the `segments`.
+fn closest_on_segments(segments: &[(Vec3, Vec3)], p: Vec3) -> Option<Vec3> {
+    segments
+        .iter()
+        .map(|(a, b)| {
+            let ab = *b - *a;
+            let t = if ab.length_squared() > 0.0 {
+                ((p - *a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            *a + ab * t
+        })
+        .min_by(|x, y| x.distance_squared(p).total_cmp(&y.distance_squared(p)))
+}
+
+/// An indexed triangle mesh where edges can be split, collapsed and flipped
+/// cheaply. Removed triangles keep their slot, and removed vertices are the
+/// ones with no triangles left.
+#[derive(Default)]
+struct TriMesh {
+    positions: Vec<Vec3>,
+    kinds: Vec<VertexKind>,
+    boundary: Vec<bool>,
+    triangles: Vec<[u32; 3]>,
+    removed: Vec<bool>,
+    /// The triangles around each vertex
+    vertex_triangles: Vec<Vec<u32>>,
+    /// The sharp and boundary edges, as sorted pairs of vertices
+    features: HashSet<(u32, u32)>,
+}
+
+impl TriMesh {
+    /// Builds the mesh from a list of triangles. Edges are sharp when the
+    /// angle between the normals of their triangles is larger than
+    /// `sharp_angle`, or when they don't have a triangle at each side.
+    fn new(positions: &[Vec3], triangles: &[[u32; 3]], sharp_angle: f32) -> Self {
+        let mut mesh = Self::default();
+        for p in positions {
+            mesh.add_vertex(*p, VertexKind::Free, false);
+        }
+        for triangle in triangles {
+            mesh.add_triangle(*triangle);
+        }
+
+        let cos_sharp = sharp_angle.cos();
+        for (a, b) in mesh.edges() {
+            let sharp = match mesh.edge_triangles(a, b)[..] {
+                [t1, t2] => {
+                    let (n1, n2) = (mesh.normal(t1), mesh.normal(t2));
+                    n1.normalize_or_zero().dot(n2.normalize_or_zero()) < cos_sharp
+                }
+                [_] => {
+                    mesh.boundary[a as usize] = true;
+                    mesh.boundary[b as usize] = true;
+                    true
+                }
+                _ => true,
+            };
+            if sharp {
+                mesh.features.insert((a, b));
+            }
+        }
+        let mut num_features = vec![0; positions.len()];
+        for (a, b) in &mesh.features {
+            num_features[*a as usize] += 1;
+            num_features[*b as usize] += 1;
+        }
+        for (kind, num) in mesh.kinds.iter_mut().zip(num_features) {
+            *kind = match num {
+                0 => VertexKind::Free,
+                2 => VertexKind::Feature,
+                _ => VertexKind::Corner,
+            };
+        }
+        mesh
+    }
+
+    /// Returns the positions of the vertices that weren't removed, and the
+    /// triangles between them.
+    fn to_triangles(&self) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let mut index = vec![0; self.positions.len()];
+        let mut positions = vec![];
+        for (v, triangles) in self.vertex_triangles.iter().enumerate() {
+            if !triangles.is_empty() {
+                index[v] = positions.len() as u32;
+                positions.push(self.positions[v]);
+            }
+        }
+        let triangles = self
+            .triangles
+            .iter()
+            .zip(&self.removed)
+            .filter(|(_, removed)| !**removed)
+            .map(|(triangle, _)| triangle.map(|v| index[v as usize]))
+            .collect();
+        (positions, triangles)
+    }
+
+    /// The segments of the sharp edges.
+    fn feature_segments(&self) -> Vec<(Vec3, Vec3)> {
+        self.features
+            .iter()
+            .map(|(a, b)| (self.position(*a), self.position(*b)))
+            .collect()
+    }
+
+    fn add_vertex(&mut self, position: Vec3, kind: VertexKind, boundary: bool) -> u32 {
+        self.positions.push(position);
+        self.kinds.push(kind);
+        self.boundary.push(boundary);
+        self.vertex_triangles.push(vec![]);
+        self.positions.len() as u32 - 1
+    }
+
+    fn add_triangle(&mut self, triangle: [u32; 3]) -> u32 {
+        let t = self.triangles.len() as u32;
+        self.triangles.push(triangle);
+        self.removed.push(false);
+        for v in triangle {
+            self.vertex_triangles[v as usize].push(t);
+        }
+        t
+    }
+
+    fn position(&self, v: u32) -> Vec3 {
+        self.positions[v as usize]
+    }
+
+    fn length(&self, a: u32, b: u32) -> f32 {
+        self.position(a).distance(self.position(b))
+    }
+
+    fn is_feature(&self, a: u32, b: u32) -> bool {
+        self.features.contains(&edge_key(a, b))
+    }
+
+    /// The unnormalized normal of a triangle, where `position` gives the
+    /// position of each of its vertices.
+    fn normal_with(&self, [a, b, c]: [u32; 3], position: impl Fn(u32) -> Vec3) -> Vec3 {
+        (position(b) - position(a)).cross(position(c) - position(a))
+    }
+
+    fn normal(&self, t: u32) -> Vec3 {
+        self.normal_with(self.triangles[t as usize], |v| self.position(v))
+    }
+
+    fn vertex_normal(&self, v: u32) -> Vec3 {
+        self.vertex_triangles[v as usize]
+            .iter()
+            .fold(Vec3::ZERO, |n, t| n + self.normal(*t))
+            .normalize_or_zero()
+    }
+
+    /// The vertices connected to `v` by an edge, sorted.
+    fn neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors = self.vertex_triangles[v as usize]
+            .iter()
+            .flat_map(|t| self.triangles[*t as usize])
+            .filter(|x| *x != v)
+            .collect_vec();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// The triangles at each side of the edge between `a` and `b`.
+    fn edge_triangles(&self, a: u32, b: u32) -> Vec<u32> {
+        self.vertex_triangles[a as usize]
+            .iter()
+            .copied()
+            .filter(|t| self.triangles[*t as usize].contains(&b))
+            .collect()
+    }
+
+    /// Returns the vertices of triangle `t`, starting with the ones of the
+    /// edge between `a` and `b` and keeping their order.
+    fn rotated(&self, t: u32, a: u32, b: u32) -> [u32; 3] {
+        let [x, y, z] = self.triangles[t as usize];
+        let in_edge = |v: u32| v == a || v == b;
+        if in_edge(x) && in_edge(y) {
+            [x, y, z]
+        } else if in_edge(y) && in_edge(z) {
+            [y, z, x]
+        } else {
+            [z, x, y]
+        }
+    }
+
+    /// All the edges of the mesh, as sorted pairs of vertices.
+    fn edges(&self) -> Vec<(u32, u32)> {
+        let mut edges = self
+            .triangles
+            .iter()
+            .zip(&self.removed)
+            .filter(|(_, removed)| !**removed)
+            .flat_map(|(&[a, b, c], _)| [edge_key(a, b), edge_key(b, c), edge_key(c, a)])
+            .collect_vec();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    /// Splits the edge between `a` and `b` at its midpoint, along with the
+    /// triangles at each side of it.
+    fn split_edge(&mut self, a: u32, b: u32) {
+        let triangles = self.edge_triangles(a, b);
+        let feature = self.is_feature(a, b);
+        let kind = if feature {
+            VertexKind::Feature
+        } else {
+            VertexKind::Free
+        };
+        let midpoint = (self.position(a) + self.position(b)) * 0.5;
+        let m = self.add_vertex(midpoint, kind, triangles.len() == 1);
+        for t in triangles {
+            let [p, q, r] = self.rotated(t, a, b);
+            self.triangles[t as usize] = [p, m, r];
+            self.vertex_triangles[q as usize].retain(|x| *x != t);
+            self.vertex_triangles[m as usize].push(t);
+            self.add_triangle([m, q, r]);
+        }
+        if feature {
+            self.features.remove(&edge_key(a, b));
+            self.features.insert(edge_key(a, m));
+            self.features.insert(edge_key(m, b));
+        }
+    }
+
+    /// Merges `gone` into `keep`, which is moved to `position`. The triangles
+    /// of the edge between them are removed.
+    fn collapse_edge(&mut self, gone: u32, keep: u32, position: Vec3) {
+        let neighbors = self.neighbors(gone);
+        for t in self.edge_triangles(gone, keep) {
+            self.removed[t as usize] = true;
+            for v in self.triangles[t as usize] {
+                self.vertex_triangles[v as usize].retain(|x| *x != t);
+            }
+        }
+        for t in std::mem::take(&mut self.vertex_triangles[gone as usize]) {
+            for v in &mut self.triangles[t as usize] {
+                if *v == gone {
+                    *v = keep;
+                }
+            }
+            self.vertex_triangles[keep as usize].push(t);
+        }
+        for x in neighbors {
+            if self.features.remove(&edge_key(gone, x)) && x != keep {
+                self.features.insert(edge_key(keep, x));
+            }
+        }
+        self.positions[keep as usize] = position;
+        self.boundary[keep as usize] |= self.boundary[gone as usize];
+    }
+
+    /// Replaces the edge between `a` and `b` with the one between the
+    /// opposite corners of its two triangles.
+    fn flip_edge(&mut self, t1: u32, t2: u32, a: u32, b: u32) {
+        let [p, q, r] = self.rotated(t1, a, b);
+        let [_, _, d] = self.rotated(t2, a, b);
+        self.triangles[t1 as usize] = [p, d, r];
+        self.triangles[t2 as usize] = [q, r, d];
+        self.vertex_triangles[p as usize].retain(|x| *x != t2);
+        self.vertex_triangles[q as usize].retain(|x| *x != t1);
+        self.vertex_triangles[r as usize].push(t2);
+        self.vertex_triangles[d as usize].push(t1);
+    }
+
+    /// Splits the edges longer than `max_length` until none is left.
+    fn split_long_edges(&mut self, max_length: f32) {
+        loop {
+            let long = self
+                .edges()
+                .into_iter()
+                .filter(|(a, b)| self.length(*a, *b) > max_length)
+                .collect_vec();
+            if long.is_empty() {
+                break;
+            }
+            // Splitting an edge never removes the other ones, so the rest of
+            // the list stays valid.
+            for (a, b) in long {
+                self.split_edge(a, b);
+            }
+        }
+    }
+
+    /// Returns the vertex to remove and the one to keep when collapsing the
+    /// edge between `a` and `b`, and where the kept one goes. Returns `None`
+    /// when the collapse would change the topology, fold a triangle over, move
+    /// a sharp edge or make an edge longer than `max_length`.
+    fn collapse_target(&self, a: u32, b: u32, max_length: f32) -> Option<(u32, u32, Vec3)> {
+        let (gone, keep) = if self.kinds[a as usize] > self.kinds[b as usize] {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        let position = match (self.kinds[gone as usize], self.kinds[keep as usize]) {
+            (VertexKind::Free, VertexKind::Free) => (self.position(a) + self.position(b)) * 0.5,
+            (VertexKind::Free, _) => self.position(keep),
+            // Feature vertices only collapse along the feature
+            (VertexKind::Feature, _) if self.is_feature(a, b) => self.position(keep),
+            _ => return None,
+        };
+
+        // The link condition: The only vertices connected to both ends of the
+        // edge are the opposite corners of its triangles.
+        let triangles = self.edge_triangles(a, b);
+        let mut opposite = triangles
+            .iter()
+            .map(|t| self.rotated(*t, a, b)[2])
+            .collect_vec();
+        opposite.sort_unstable();
+        let (neighbors_a, neighbors_b) = (self.neighbors(a), self.neighbors(b));
+        let common = neighbors_a
+            .iter()
+            .copied()
+            .filter(|x| neighbors_b.binary_search(x).is_ok())
+            .collect_vec();
+        if common != opposite || opposite.iter().any(|x| self.neighbors(*x).len() <= 3) {
+            return None;
+        }
+
+        let too_long = neighbors_a
+            .iter()
+            .chain(&neighbors_b)
+            .filter(|x| **x != a && **x != b)
+            .any(|x| self.position(*x).distance(position) > max_length);
+        if too_long {
+            return None;
+        }
+
+        let moved = |v: u32| {
+            if v == a || v == b {
+                position
+            } else {
+                self.position(v)
+            }
+        };
+        let folds = self.vertex_triangles[a as usize]
+            .iter()
+            .chain(&self.vertex_triangles[b as usize])
+            .filter(|t| !triangles.contains(t))
+            .any(|t| {
+                let triangle = self.triangles[*t as usize];
+                self.normal_with(triangle, moved).dot(self.normal(*t)) <= 0.0
+            });
+        if folds {
+            return None;
+        }
+        Some((gone, keep, position))
+    }
+
+    /// Collapses the edges shorter than `min_length`, as long as that doesn't
+    /// make any edge longer than `max_length`.
+    fn collapse_short_edges(&mut self, min_length: f32, max_length: f32) {
+        let short = self
+            .edges()
+            .into_iter()
+            .filter(|(a, b)| self.length(*a, *b) < min_length)
+            .collect_vec();
+        for (a, b) in short {
+            // Earlier collapses may have removed or stretched the edge
+            if self.edge_triangles(a, b).is_empty() || self.length(a, b) >= min_length {
+                continue;
+            }
+            if let Some((gone, keep, position)) = self.collapse_target(a, b, max_length) {
+                self.collapse_edge(gone, keep, position);
+            }
+        }
+    }
+
+    /// Flips the edges where that brings the number of edges around their
+    /// vertices closer to 6, or 4 on the boundary.
+    fn flip_edges(&mut self) {
+        let target = |mesh: &Self, v: u32| if mesh.boundary[v as usize] { 4 } else { 6 };
+        for (a, b) in self.edges() {
+            let triangles = self.edge_triangles(a, b);
+            if self.is_feature(a, b) || triangles.len() != 2 {
+                continue;
+            }
+            let (t1, t2) = (triangles[0], triangles[1]);
+            let c = self.rotated(t1, a, b)[2];
+            let d = self.rotated(t2, a, b)[2];
+            if c == d || self.neighbors(c).binary_search(&d).is_ok() {
+                continue;
+            }
+
+            let valence = |v: u32| self.neighbors(v).len() as i32;
+            let (va, vb, vc, vd) = (valence(a), valence(b), valence(c), valence(d));
+            if va <= 3 || vb <= 3 {
+                continue;
+            }
+            let deviation = |v: u32, valence: i32| (valence - target(self, v)).abs();
+            let before = deviation(a, va) + deviation(b, vb) + deviation(c, vc) + deviation(d, vd);
+            let after = deviation(a, va - 1)
+                + deviation(b, vb - 1)
+                + deviation(c, vc + 1)
+                + deviation(d, vd + 1);
+            if after >= before {
+                continue;
+            }
+
+            // The new triangles must face the same way as the old ones
+            let [p, q, r] = self.rotated(t1, a, b);
+            let normal = self.normal(t1) + self.normal(t2);
+            let position = |v| self.position(v);
+            if self.normal_with([p, d, r], position).dot(normal) <= 0.0
+                || self.normal_with([q, r, d], position).dot(normal) <= 0.0
+            {
+                continue;
+            }
+            self.flip_edge(t1, t2, a, b);
+        }
+    }
+
+    /// Moves each vertex towards the average of its neighbors, but only along
+    /// the surface, or along the feature for vertices on sharp edges.
+    fn smooth_tangentially(&mut self) {
+        let mut smoothed = self.positions.clone();
+        for v in 0..self.positions.len() as u32 {
+            let p = self.position(v);
+            let neighbors = self.neighbors(v);
+            match self.kinds[v as usize] {
+                VertexKind::Free if !neighbors.is_empty() => {
+                    let centroid = neighbors
+                        .iter()
+                        .fold(Vec3::ZERO, |sum, x| sum + self.position(*x))
+                        / neighbors.len() as f32;
+                    let normal = self.vertex_normal(v);
+                    let offset = centroid - p;
+                    smoothed[v as usize] = p + offset - normal * normal.dot(offset);
+                }
+                VertexKind::Feature => {
+                    let ends = neighbors
+                        .iter()
+                        .copied()
+                        .filter(|x| self.is_feature(v, *x))
+                        .collect_vec();
+                    if let [x, y] = ends[..] {
+                        let (px, py) = (self.position(x), self.position(y));
+                        let direction = (py - px).normalize_or_zero();
+                        let offset = (px + py) * 0.5 - p;
+                        smoothed[v as usize] = p + direction * direction.dot(offset);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.positions = smoothed;
+    }
+
+    /// Moves the vertices back onto the `surface`, if any, and the ones on
+    /// sharp edges back onto the original sharp edges.
+    fn project(&mut self, surface: Option<&MeshBvh>, features: &[(Vec3, Vec3)]) {
+        for v in 0..self.positions.len() {
+            if self.vertex_triangles[v].is_empty() {
+                continue;
+            }
+            let p = self.positions[v];
+            let projected = match self.kinds[v] {
+                VertexKind::Free => surface
+                    .and_then(|bvh| bvh.closest_point(p))
+                    .map(|hit| hit.point),
+                VertexKind::Feature => closest_on_segments(features, p),
+                VertexKind::Corner => None,
+            };
+            if let Some(projected) = projected {
+                self.positions[v] = projected;
+            }
+        }
+    }
+}
+
+/// Returns a new mesh with the surface of `mesh`, rebuilt with triangles whose
+/// edges are all about `target_edge_length` long. Each iteration splits the
+/// edges that are too long, collapses the ones that are too short, flips edges
+/// so vertices have 6 neighbors and smooths the vertices along the surface.
+///
+/// Edges where the faces meet at an angle larger than `preserve_sharp_deg`,
+/// in degrees, are kept as sharp edges: Their vertices only slide along them,
+/// and the vertices where they meet don't move. The boundary is kept the same
+/// way. When `project` is set, vertices are moved back onto the surface of
+/// the original mesh after each iteration, so curved surfaces don't shrink.
+///
+/// Only the positions are kept, the result has no other channels. Progress is
+/// reported to the `sink`, if any, after each iteration.
+pub fn isotropic_remesh(
+    mesh: &HalfEdgeMesh,
+    target_edge_length: f32,
+    iterations: u32,
+    preserve_sharp_deg: f32,
+    project: bool,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<HalfEdgeMesh> {
+    if !target_edge_length.is_finite() || target_edge_length <= 0.0 {
+        bail!("The target edge length must be positive, got {target_edge_length}");
+    }
+    let conn = mesh.read_connectivity();
+    if conn.num_faces() == 0 {
+        bail!("Can't remesh a mesh with no faces");
+    }
+
+    let positions = mesh.read_positions();
+    let mut index = SecondaryMap::<VertexId, u32>::new();
+    let mut points = vec![];
+    for (v, _) in conn.iter_vertices() {
+        index.insert(v, points.len() as u32);
+        points.push(positions[v]);
+    }
+    let mut triangles = vec![];
+    for (face, _) in conn.iter_faces() {
+        let verts = conn.face_vertices(face);
+        for i in 1..verts.len().saturating_sub(1) {
+            triangles.push([verts[0], verts[i], verts[i + 1]].map(|v| index[v]));
+        }
+    }
+
+    let mut remeshed = TriMesh::new(&points, &triangles, preserve_sharp_deg.to_radians());
+    let features = remeshed.feature_segments();
+    let surface = project.then(|| MeshBvh::build(mesh));
+    let (min_length, max_length) = (target_edge_length * 0.8, target_edge_length * 4.0 / 3.0);
+    for i in 0..iterations {
+        remeshed.split_long_edges(max_length);
+        remeshed.collapse_short_edges(min_length, max_length);
+        remeshed.flip_edges();
+        remeshed.smooth_tangentially();
+        remeshed.project(surface.as_ref(), &features);
+        report_progress(sink, (i + 1) as f32 / iterations as f32)?;
+    }
+
+    let (positions, triangles) = remeshed.to_triangles();
+    HalfEdgeMesh::build_from_polygons(&positions, &triangles)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Rebuilds the surface of `mesh` with triangles of roughly the same
+    /// size, with edges about `target_edge_length` long. Edges sharper than
+    /// `preserve_sharp_deg` degrees are kept. The optional `project` moves the
+    /// vertices back onto the original surface after each iteration, and
+    /// defaults to true.
+    #[lua(under = "Ops")]
+    pub fn isotropic_remesh(
+        mesh: &HalfEdgeMesh,
+        target_edge_length: f32,
+        iterations: u32,
+        preserve_sharp_deg: f32,
+        project: Option<bool>,
+    ) -> Result<HalfEdgeMesh> {
+        let sink = crate::progress::current_sink();
+        super::isotropic_remesh(
+            mesh,
+            target_edge_length,
+            iterations,
+            preserve_sharp_deg,
+            project.unwrap_or(true),
+            sink.as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::progress::MockSink;
+
+    /// The mean and standard deviation of the lengths of the edges.
+    fn edge_length_stats(mesh: &HalfEdgeMesh) -> (f32, f32) {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let lengths = conn
+            .iter_halfedges()
+            .map(|(h, _)| {
+                let (src, dst) = conn.at_halfedge(h).src_dst_pair().unwrap();
+                positions[src].distance(positions[dst])
+            })
+            .collect_vec();
+        let mean = lengths.iter().sum::<f32>() / lengths.len() as f32;
+        let variance =
+            lengths.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / lengths.len() as f32;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn test_remesh_sphere() {
+        let sphere = primitives::UVSphere::build(Vec3::ZERO, 16, 16, 1.0).unwrap();
+        let sink = MockSink::new(None);
+        let remeshed = isotropic_remesh(&sphere, 0.2, 5, 45.0, true, Some(&sink)).unwrap();
+        assert!(edit_ops::validate(&remeshed).is_valid());
+        sink.assert_monotonic();
+
+        let (mean, std_dev) = edge_length_stats(&remeshed);
+        assert!((mean - 0.2).abs() < 0.02, "{mean}");
+        assert!(std_dev < 0.2 * 0.2, "{std_dev}");
+
+        let stats = analysis::mesh_stats(&remeshed).unwrap();
+        assert!(stats.is_closed);
+        let euler = stats.num_vertices as i64 - stats.num_edges as i64 + stats.num_faces as i64;
+        assert_eq!(euler, 2);
+        // Projecting keeps the vertices on the original surface, which is a
+        // bit inside the unit sphere between its vertices.
+        for (_, p) in remeshed.read_positions().iter() {
+            assert!(p.length() > 0.97 && p.length() < 1.0 + 1e-4, "{p}");
+        }
+    }
+
+    #[test]
+    fn test_remesh_cube_keeps_sharp_edges() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let remeshed = isotropic_remesh(&cube, 0.1, 5, 30.0, true, None).unwrap();
+        assert!(edit_ops::validate(&remeshed).is_valid());
+
+        let positions = remeshed.read_positions();
+        // The corners don't move, and every edge of the cube is still there,
+        // split in 8 pieces close to the target length.
+        for (_, corner) in cube.read_positions().iter() {
+            assert!(positions.iter().any(|p| p.abs_diff_eq(*corner, 1e-6)));
+        }
+        let on_cube_edges = positions
+            .iter()
+            .filter(|(_, p)| {
+                p.to_array()
+                    .iter()
+                    .filter(|c| (c.abs() - 0.5).abs() < 1e-5)
+                    .count()
+                    >= 2
+            })
+            .count();
+        assert_eq!(on_cube_edges, 8 + 12 * 7);
+        drop(positions);
+
+        let stats = analysis::mesh_stats(&remeshed).unwrap();
+        assert!((stats.volume - 1.0).abs() < 1e-4, "{}", stats.volume);
+        let (mean, _) = edge_length_stats(&remeshed);
+        assert!((mean - 0.1).abs() < 0.01, "{mean}");
+    }
+
+    #[test]
+    fn test_remesh_errors() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert!(isotropic_remesh(&cube, 0.0, 1, 30.0, true, None).is_err());
+        assert!(isotropic_remesh(&HalfEdgeMesh::new(), 0.1, 1, 30.0, true, None).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::*;
+use crate::resources::{luminance, Image, ImageFilter, ImageWrap};
+
+/// Samples `image` at the UV coordinates stored in `uv_channel`, and writes
+/// the result to `out_channel`. The UV channel can be either a halfedge
+/// channel, for per-corner UVs, or a vertex channel. The output channel uses
+/// the same key type.
+///
+/// When `out_kind` is f32, the luminance of the image is written, otherwise
+/// its RGB color.
+pub fn sample_image(
+    mesh: &mut HalfEdgeMesh,
+    image: &Image,
+    uv_channel: &str,
+    out_channel: &str,
+    out_kind: ChannelValueType,
+    wrap: ImageWrap,
+    filter: ImageFilter,
+) -> Result<()> {
+    let sample = |uv: Vec3| image.sample(uv.truncate(), wrap, filter);
+
+    let conn = mesh.read_connectivity();
+    let samples = if let Some(ch_id) = mesh.channels.channel_id::<HalfEdgeId, Vec3>(uv_channel) {
+        let uvs = mesh.channels.read_channel(ch_id)?;
+        let samples = conn.iter_halfedges().map(|(h, _)| (h, sample(uvs[h])));
+        Samples::HalfEdge(samples.collect())
+    } else if let Some(ch_id) = mesh.channels.channel_id::<VertexId, Vec3>(uv_channel) {
+        let uvs = mesh.channels.read_channel(ch_id)?;
+        let samples = conn.iter_vertices().map(|(v, _)| (v, sample(uvs[v])));
+        Samples::Vertex(samples.collect())
+    } else {
+        bail!(
+            "The mesh has no UV channel named '{uv_channel}'. UVs are read from a \
+             HalfEdgeId -> Vec3 or a VertexId -> Vec3 channel."
+        )
+    };
+    drop(conn);
+
+    match samples {
+        Samples::HalfEdge(samples) => write_samples(mesh, samples, out_channel, out_kind),
+        Samples::Vertex(samples) => write_samples(mesh, samples, out_channel, out_kind),
+    }
+}
+
+enum Samples {
+    HalfEdge(Vec<(HalfEdgeId, Vec3)>),
+    Vertex(Vec<(VertexId, Vec3)>),
+}
+
+fn write_samples<K: ChannelKey>(
+    mesh: &mut HalfEdgeMesh,
+    samples: Vec<(K, Vec3)>,
+    out_channel: &str,
+    out_kind: ChannelValueType,
+) -> Result<()> {
+    match out_kind {
+        ChannelValueType::Vec3 => {
+            let mut ch = Channel::<K, Vec3>::new();
+            for (k, color) in samples {
+                ch[k] = color;
+            }
+            mesh.channels.replace_or_create_channel(out_channel, ch);
+        }
+        ChannelValueType::f32 => {
+            let mut ch = Channel::<K, f32>::new();
+            for (k, color) in samples {
+                ch[k] = luminance(color);
+            }
+            mesh.channels.replace_or_create_channel(out_channel, ch);
+        }
+        ChannelValueType::bool | ChannelValueType::Tag => {
+            bail!("Images can only be sampled into f32 or Vec3 channels")
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Samples the image at `image_path` at the UVs of each corner, or each
+    /// vertex, of the mesh, as stored in `uv_channel`, and writes the result
+    /// to `out_channel`. The `out_kind` is either `Types.F32`, to write the
+    /// luminance of the image, or `Types.VEC3`, to write its RGB color.
+    ///
+    /// The `wrap` mode, either `"Repeat"` or `"Clamp"`, decides what happens
+    /// with UVs outside the [0, 1] range. The `filter` is either `"Nearest"`
+    /// or `"Bilinear"`. Images are cached until their file is modified.
+    #[lua(under = "Ops")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_image(
+        lua: &mlua::Lua,
+        mesh: &mut HalfEdgeMesh,
+        image_path: String,
+        uv_channel: String,
+        out_channel: String,
+        out_kind: ChannelValueType,
+        wrap: String,
+        filter: String,
+    ) -> Result<()> {
+        crate::lua_engine::sandbox::ensure_filesystem_allowed(lua, "Ops.sample_image")?;
+        let wrap = match wrap.as_str() {
+            "Repeat" => ImageWrap::Repeat,
+            "Clamp" => ImageWrap::Clamp,
+            other => bail!("Invalid wrap mode '{other}'"),
+        };
+        let filter = match filter.as_str() {
+            "Nearest" => ImageFilter::Nearest,
+            "Bilinear" => ImageFilter::Bilinear,
+            other => bail!("Invalid image filter '{other}'"),
+        };
+        let cache = crate::asset_cache::lua_asset_cache(lua)?;
+        let image = Image::load_cached(&cache, image_path.as_ref())?;
+        super::sample_image(
+            mesh,
+            &image,
+            &uv_channel,
+            &out_channel,
+            out_kind,
+            wrap,
+            filter,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::resources::test::checker_png;
+
+    /// A single quad with UVs at the centers of the four pixels of a 2x2
+    /// image, shared by all the corners of each vertex.
+    fn quad_with_vertex_uvs() -> HalfEdgeMesh {
+        let mut mesh = HalfEdgeMesh::build_from_polygons(
+            &[Vec3::ZERO, Vec3::X, Vec3::new(1.0, 0.0, 1.0), Vec3::Z],
+            &[[0, 1, 2, 3]],
+        )
+        .unwrap();
+        let uvs = [(0.25, 0.25), (0.75, 0.25), (0.75, 0.75), (0.5, 0.25)];
+        let mut ch = Channel::<VertexId, Vec3>::new();
+        // Vertices are iterated in the order they appear in the polygon
+        for ((v, _), (u, w)) in mesh.read_connectivity().iter_vertices().zip(uvs) {
+            ch[v] = Vec3::new(u, w, 0.0);
+        }
+        mesh.channels.replace_or_create_channel("uv", ch);
+        mesh
+    }
+
+    fn sampled_values(filter: ImageFilter) -> Vec<f32> {
+        let image = Image::load(&checker_png("blackjack_sample_image.png")).unwrap();
+        let mut mesh = quad_with_vertex_uvs();
+        sample_image(
+            &mut mesh,
+            &image,
+            "uv",
+            "mask",
+            ChannelValueType::f32,
+            ImageWrap::Repeat,
+            filter,
+        )
+        .unwrap();
+        let mask = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("mask")
+            .unwrap();
+        let conn = mesh.read_connectivity();
+        conn.iter_vertices().map(|(v, _)| mask[v]).collect()
+    }
+
+    #[test]
+    fn test_sample_checker() {
+        let nearest = sampled_values(ImageFilter::Nearest);
+        assert_eq!(nearest, vec![1.0, 0.0, 1.0, 0.0]);
+
+        // The last UV is halfway between a white and a black pixel
+        let bilinear = sampled_values(ImageFilter::Bilinear);
+        let expected = [1.0, 0.0, 1.0, 0.5];
+        for (value, expected) in bilinear.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-5, "{value} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_sample_rgb_per_corner() {
+        let image = Image::load(&checker_png("blackjack_sample_image_rgb.png")).unwrap();
+        let mut mesh = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        edit_ops::set_full_range_uvs(&mut mesh).unwrap();
+        sample_image(
+            &mut mesh,
+            &image,
+            "uv",
+            "color",
+            ChannelValueType::Vec3,
+            ImageWrap::Clamp,
+            ImageFilter::Nearest,
+        )
+        .unwrap();
+        let colors = mesh
+            .channels
+            .read_channel_by_name::<HalfEdgeId, Vec3>("color")
+            .unwrap();
+        let conn = mesh.read_connectivity();
+        for (h, _) in conn.iter_halfedges() {
+            assert!(colors[h] == Vec3::ONE || colors[h] == Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_missing_uvs() {
+        let image = Image::load(&checker_png("blackjack_sample_image_missing.png")).unwrap();
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let err = sample_image(
+            &mut mesh,
+            &image,
+            "missing_uv",
+            "mask",
+            ChannelValueType::f32,
+            ImageWrap::Repeat,
+            ImageFilter::Nearest,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no UV channel"), "{err}");
+    }
+}
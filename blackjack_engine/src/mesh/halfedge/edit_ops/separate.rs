@@ -0,0 +1,259 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use slotmap::{Key, SecondaryMap};
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// Copies a subset of the elements of `mesh` into a new mesh, along with their
+/// channel data. Pointers to elements outside of the subset are cleared, with
+/// the exception of vertex halfedges, which are redirected to another outgoing
+/// halfedge in the subset when possible.
+fn extract_elements(
+    mesh: &HalfEdgeMesh,
+    vertices: &[VertexId],
+    faces: &[FaceId],
+    halfedges: &[HalfEdgeId],
+) -> HalfEdgeMesh {
+    let mut new_mesh = HalfEdgeMesh::new();
+    let mut vmap = SecondaryMap::<VertexId, VertexId>::new();
+    let mut fmap = SecondaryMap::<FaceId, FaceId>::new();
+    let mut hmap = SecondaryMap::<HalfEdgeId, HalfEdgeId>::new();
+
+    {
+        let conn = mesh.read_connectivity();
+        let mut new_conn = new_mesh.write_connectivity();
+
+        for v in vertices.iter_cpy() {
+            vmap.insert(v, new_conn.alloc_vertex_raw(None));
+        }
+        for f in faces.iter_cpy() {
+            fmap.insert(f, new_conn.alloc_face(None));
+        }
+        for h in halfedges.iter_cpy() {
+            hmap.insert(h, new_conn.alloc_halfedge(HalfEdge::default()));
+        }
+
+        // Any outgoing halfedge works as a vertex's halfedge, in case the
+        // original one is not part of the subset.
+        let mut fallback_outgoing = SecondaryMap::<VertexId, HalfEdgeId>::new();
+        for h in halfedges.iter_cpy() {
+            let halfedge = &conn[h];
+            let new_halfedge = HalfEdge {
+                twin: halfedge.twin.and_then(|tw| hmap.get(tw).copied()),
+                next: halfedge.next.and_then(|nxt| hmap.get(nxt).copied()),
+                vertex: halfedge.vertex.and_then(|v| vmap.get(v).copied()),
+                face: halfedge.face.and_then(|f| fmap.get(f).copied()),
+            };
+            if let Some(v) = halfedge.vertex {
+                if !fallback_outgoing.contains_key(v) {
+                    fallback_outgoing.insert(v, hmap[h]);
+                }
+            }
+            new_conn[hmap[h]] = new_halfedge;
+        }
+        for v in vertices.iter_cpy() {
+            new_conn[vmap[v]].halfedge = conn[v]
+                .halfedge
+                .and_then(|h| hmap.get(h).copied())
+                .or_else(|| fallback_outgoing.get(v).copied());
+        }
+        for f in faces.iter_cpy() {
+            new_conn[fmap[f]].halfedge = conn[f].halfedge.and_then(|h| hmap.get(h).copied());
+        }
+    }
+
+    // Only the channel values for the elements in the subset are copied over.
+    let raw_vertices: Rc<Vec<_>> = Rc::new(vertices.iter().map(|k| k.data()).collect());
+    let raw_faces: Rc<Vec<_>> = Rc::new(faces.iter().map(|k| k.data()).collect());
+    let raw_halfedges: Rc<Vec<_>> = Rc::new(halfedges.iter().map(|k| k.data()).collect());
+    let get_ids = move |kty| match kty {
+        ChannelKeyType::VertexId => Rc::clone(&raw_vertices),
+        ChannelKeyType::FaceId => Rc::clone(&raw_faces),
+        ChannelKeyType::HalfEdgeId => Rc::clone(&raw_halfedges),
+    };
+    let id_map = |kty, k| match kty {
+        ChannelKeyType::VertexId => vmap[VertexId::from(k)].data(),
+        ChannelKeyType::FaceId => fmap[FaceId::from(k)].data(),
+        ChannelKeyType::HalfEdgeId => hmap[HalfEdgeId::from(k)].data(),
+    };
+    new_mesh
+        .channels
+        .merge_with(&mesh.channels, get_ids, id_map);
+    new_mesh.gen_config = mesh.gen_config.clone();
+
+    new_mesh
+}
+
+/// The elements of one connected component of a mesh, see [`find_islands`].
+#[derive(Debug, Clone, Default)]
+pub struct MeshIsland {
+    pub vertices: Vec<VertexId>,
+    pub faces: Vec<FaceId>,
+    pub halfedges: Vec<HalfEdgeId>,
+}
+
+/// Finds the connected components of `mesh`, without splitting it. Two
+/// vertices belong to the same island when there is a path of edges between
+/// them. Isolated vertices are an island each. Islands are returned in the
+/// order of their first vertex.
+pub fn find_islands(mesh: &HalfEdgeMesh) -> Result<Vec<MeshIsland>> {
+    let conn = mesh.read_connectivity();
+
+    let mut outgoing = SecondaryMap::<VertexId, SVec<HalfEdgeId>>::new();
+    for (h, halfedge) in conn.iter_halfedges() {
+        let v = halfedge
+            .vertex
+            .ok_or_else(|| anyhow!("Halfedge {h:?} has no source vertex"))?;
+        outgoing
+            .entry(v)
+            .ok_or_else(|| anyhow!("Halfedge {h:?} points to a removed vertex"))?
+            .or_default()
+            .push(h);
+    }
+
+    let mut visited = HashSet::<VertexId>::new();
+    let mut islands = vec![];
+    for (v0, _) in conn.iter_vertices() {
+        if visited.contains(&v0) {
+            continue;
+        }
+
+        let mut island = MeshIsland::default();
+        let mut seen_faces = HashSet::<FaceId>::new();
+
+        let mut queue = VecDeque::from([v0]);
+        visited.insert(v0);
+        while let Some(v) = queue.pop_front() {
+            island.vertices.push(v);
+            for h in outgoing.get(v).into_iter().flatten().copied() {
+                island.halfedges.push(h);
+                if let Some(f) = conn[h].face {
+                    if seen_faces.insert(f) {
+                        island.faces.push(f);
+                    }
+                }
+                let dst = conn.at_halfedge(h).dst_vertex().try_end()?;
+                if visited.insert(dst) {
+                    queue.push_back(dst);
+                }
+            }
+        }
+
+        islands.push(island);
+    }
+    Ok(islands)
+}
+
+/// Splits `mesh` into one mesh per connected component. Two vertices belong to
+/// the same component when there is a path of edges between them. Isolated
+/// vertices become a single-vertex mesh each.
+pub fn separate_components(mesh: &HalfEdgeMesh) -> Result<Vec<HalfEdgeMesh>> {
+    Ok(find_islands(mesh)?
+        .into_iter()
+        .map(|island| extract_elements(mesh, &island.vertices, &island.faces, &island.halfedges))
+        .collect())
+}
+
+/// Builds a new mesh with the given `faces` of `mesh`. The edges along the
+/// border of the face set get new boundary halfedges.
+fn extract_faces(mesh: &HalfEdgeMesh, faces: &[FaceId]) -> Result<HalfEdgeMesh> {
+    let mut vertices = vec![];
+    let mut halfedges = vec![];
+    {
+        let conn = mesh.read_connectivity();
+        let mut seen_vertices = HashSet::new();
+        for f in faces.iter_cpy() {
+            for h in conn.at_face(f).halfedges()? {
+                halfedges.push(h);
+                let v = conn.at_halfedge(h).vertex().try_end()?;
+                if seen_vertices.insert(v) {
+                    vertices.push(v);
+                }
+            }
+        }
+    }
+
+    let new_mesh = extract_elements(mesh, &vertices, faces, &halfedges);
+    new_mesh.write_connectivity().add_boundary_halfedges();
+    Ok(new_mesh)
+}
+
+/// Splits `mesh` in two: A first mesh with the given selected `faces`, and a
+/// second mesh with the rest of the faces. Vertices on the border between the
+/// two parts are duplicated, so that each mesh gets its own copy.
+pub fn split_by_selection(
+    mesh: &HalfEdgeMesh,
+    faces: &SelectionExpression,
+) -> Result<(HalfEdgeMesh, HalfEdgeMesh)> {
+    let resolved = mesh.resolve_face_selection(faces)?;
+    let (selected, rest): (Vec<FaceId>, Vec<FaceId>) = mesh
+        .read_connectivity()
+        .iter_faces()
+        .map(|(f, _)| f)
+        .partition(|f| resolved.contains(*f));
+    Ok((extract_faces(mesh, &selected)?, extract_faces(mesh, &rest)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::analysis::mesh_stats;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    #[test]
+    fn test_separate_components() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 6.0, Vec3::ONE).unwrap());
+
+        let components = separate_components(&mesh).unwrap();
+        assert_eq!(components.len(), 3);
+        for component in &components {
+            let stats = mesh_stats(component).unwrap();
+            assert_eq!(stats.num_vertices, 8);
+            assert_eq!(stats.num_faces, 6);
+            assert!(stats.is_closed);
+            assert!(validate(component).is_valid());
+        }
+
+        // Positions are carried over along with the connectivity.
+        let centers = components
+            .iter()
+            .map(|c| {
+                let (min, max) = crate::mesh::halfedge::analysis::bounds(c);
+                (min + max).x * 0.5
+            })
+            .sorted_by(|a, b| a.total_cmp(b))
+            .collect_vec();
+        for (center, expected) in centers.iter().zip([0.0, 3.0, 6.0]) {
+            assert!((center - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_split_by_selection() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let sel = SelectionExpression::parse("0").unwrap();
+        let (selected, rest) = split_by_selection(&mesh, &sel).unwrap();
+
+        let selected_stats = mesh_stats(&selected).unwrap();
+        assert_eq!(selected_stats.num_faces, 1);
+        assert_eq!(selected_stats.num_vertices, 4);
+        assert_eq!(selected_stats.num_boundary_loops, 1);
+        assert!(validate(&selected).is_valid());
+
+        let rest_stats = mesh_stats(&rest).unwrap();
+        assert_eq!(rest_stats.num_faces, 5);
+        assert_eq!(rest_stats.num_vertices, 8);
+        assert_eq!(rest_stats.num_boundary_loops, 1);
+        assert!(validate(&rest).is_valid());
+    }
+}
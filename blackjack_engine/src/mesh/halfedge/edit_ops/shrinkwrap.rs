@@ -0,0 +1,226 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::mesh::halfedge::bvh::{MeshBvh, SurfaceHit};
+use crate::prelude::*;
+
+/// How [`shrinkwrap`] finds the point on the target each vertex moves to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShrinkwrapMode {
+    /// The closest point on the surface of the target.
+    NearestSurface,
+    /// The first point of the target hit by a ray from the vertex along
+    /// `direction`. Vertices whose ray misses the target don't move.
+    Project { direction: Vec3 },
+    /// The closest vertex of the target.
+    NearestVertex,
+}
+
+struct TargetVertex {
+    vertex: VertexId,
+    pos: Vec3,
+}
+
+impl RTreeObject for TargetVertex {
+    type Envelope = AABB<[f32; 3]>;
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.pos.to_array())
+    }
+}
+
+impl PointDistance for TargetVertex {
+    fn distance_2(&self, point: &[f32; 3]) -> f32 {
+        self.pos.distance_squared(Vec3::from_slice(point))
+    }
+}
+
+/// Moves the vertices of `mesh` onto the surface of `target`, then pushes
+/// them by `offset` along the normal of the target at that point.
+///
+/// The normal is interpolated from the vertex normals of the target when it
+/// has them. Otherwise, the normal of the face that was hit is used.
+pub fn shrinkwrap(
+    mesh: &HalfEdgeMesh,
+    target: &HalfEdgeMesh,
+    mode: ShrinkwrapMode,
+    offset: f32,
+) -> Result<()> {
+    let target_conn = target.read_connectivity();
+    let target_positions = target.read_positions();
+    let target_normals = target.read_vertex_normals();
+
+    let surface_normal = |hit: &SurfaceHit| -> Vec3 {
+        match &target_normals {
+            Some(normals) => hit.interpolate(|v| normals[v]).normalize_or_zero(),
+            None => target_conn
+                .face_normal(&target_positions, hit.face)
+                .unwrap_or(Vec3::ZERO),
+        }
+    };
+
+    let conn = mesh.read_connectivity();
+    let mut positions = mesh.write_positions();
+    match mode {
+        ShrinkwrapMode::NearestSurface | ShrinkwrapMode::Project { .. } => {
+            if target_conn.num_faces() == 0 {
+                bail!("The shrinkwrap target has no faces");
+            }
+            let bvh = MeshBvh::build(target);
+            for (v, _) in conn.iter_vertices() {
+                let hit = match mode {
+                    ShrinkwrapMode::Project { direction } => {
+                        match bvh.raycast(positions[v], direction) {
+                            Some(hit) => hit,
+                            None => continue,
+                        }
+                    }
+                    _ => bvh
+                        .closest_point(positions[v])
+                        .ok_or_else(|| anyhow!("The shrinkwrap target has no faces"))?,
+                };
+                positions[v] = hit.point + surface_normal(&hit) * offset;
+            }
+        }
+        ShrinkwrapMode::NearestVertex => {
+            // Vertices don't have a face to fall back to, so compute the
+            // smooth normals when the target doesn't have vertex normals.
+            let smooth_normals = match &target_normals {
+                Some(_) => None,
+                None => Some(generate_smooth_normals_channel(target)?),
+            };
+            let normals = target_normals.as_deref().or(smooth_normals.as_ref());
+            let tree = RTree::bulk_load(
+                target_conn
+                    .iter_vertices()
+                    .map(|(vertex, _)| TargetVertex {
+                        vertex,
+                        pos: target_positions[vertex],
+                    })
+                    .collect_vec(),
+            );
+            for (v, _) in conn.iter_vertices() {
+                let nearest = tree
+                    .nearest_neighbor(&positions[v].to_array())
+                    .ok_or_else(|| anyhow!("The shrinkwrap target has no vertices"))?;
+                let normal = normals.map_or(Vec3::ZERO, |n| n[nearest.vertex]);
+                positions[v] = nearest.pos + normal * offset;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves the vertices of `mesh` onto the surface of `target`, plus an
+    /// `offset` along the target's normal. The `mode` is one of
+    /// `"NearestSurface"`, `"NearestVertex"` or `"Project"`. The `"Project"`
+    /// mode casts rays along `direction`, and leaves the vertices that miss
+    /// the target untouched.
+    #[lua(under = "Ops")]
+    pub fn shrinkwrap(
+        mesh: &mut HalfEdgeMesh,
+        target: &HalfEdgeMesh,
+        mode: String,
+        offset: f32,
+        direction: Option<LVec3>,
+    ) -> Result<()> {
+        let mode = match mode.as_str() {
+            "NearestSurface" => ShrinkwrapMode::NearestSurface,
+            "NearestVertex" => ShrinkwrapMode::NearestVertex,
+            "Project" => {
+                let direction = direction
+                    .ok_or_else(|| anyhow!("The 'Project' mode needs a direction"))?
+                    .0;
+                if direction.length_squared() == 0.0 {
+                    bail!("The projection direction can't be zero");
+                }
+                ShrinkwrapMode::Project { direction }
+            }
+            _ => bail!("Invalid shrinkwrap mode '{mode}'"),
+        };
+        super::shrinkwrap(mesh, target, mode, offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn distances_to_origin(mesh: &HalfEdgeMesh) -> Vec<f32> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v].length())
+            .collect()
+    }
+
+    fn grid() -> HalfEdgeMesh {
+        let mesh = primitives::Grid::build(5, 5, 0.2, 0.2).unwrap();
+        // Center the grid above the sphere
+        {
+            let conn = mesh.read_connectivity();
+            let mut positions = mesh.write_positions();
+            for (v, _) in conn.iter_vertices() {
+                positions[v] = Vec3::new(positions[v].x - 0.4, 3.0, positions[v].y - 0.4);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_shrinkwrap_nearest_surface() {
+        let sphere = primitives::UVSphere::build(Vec3::ZERO, 32, 32, 1.0).unwrap();
+        for offset in [0.0, 0.1] {
+            let mesh = grid();
+            shrinkwrap(&mesh, &sphere, ShrinkwrapMode::NearestSurface, offset).unwrap();
+            for d in distances_to_origin(&mesh) {
+                // The sphere is made of flat faces, so points between its
+                // vertices are slightly closer to the center.
+                assert!((d - (1.0 + offset)).abs() < 0.01, "{d}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrinkwrap_project() {
+        let sphere = primitives::UVSphere::build(Vec3::ZERO, 32, 32, 1.0).unwrap();
+        let mesh = grid();
+        // Move one vertex away, so its ray misses the sphere
+        let (missed, _) = mesh.read_connectivity().iter_vertices().next().unwrap();
+        mesh.write_positions()[missed] = Vec3::new(5.0, 3.0, 0.0);
+
+        let mode = ShrinkwrapMode::Project {
+            direction: Vec3::NEG_Y,
+        };
+        shrinkwrap(&mesh, &sphere, mode, 0.0).unwrap();
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            if v == missed {
+                assert_eq!(positions[v], Vec3::new(5.0, 3.0, 0.0));
+            } else {
+                assert!((positions[v].length() - 1.0).abs() < 0.01);
+                // The ray hits the top of the sphere
+                assert!(positions[v].y > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrinkwrap_nearest_vertex() {
+        let target = primitives::Box::build(Vec3::ZERO, Vec3::splat(2.0)).unwrap();
+        let mesh = grid();
+        shrinkwrap(&mesh, &target, ShrinkwrapMode::NearestVertex, 0.0).unwrap();
+        for d in distances_to_origin(&mesh) {
+            assert!((d - 3.0f32.sqrt()).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::*;
+use crate::progress::{current_sink, report_warning, Warning};
+
+/// The deformations supported by [`simple_deform`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeformKind {
+    /// Rotates the mesh around the axis, by an angle that grows along it.
+    Twist,
+    /// Curves the axis into a circular arc.
+    Bend,
+    /// Scales the mesh away from the axis, by a factor that grows along it.
+    Taper,
+}
+
+/// Returns the unit vector perpendicular to `axis` in the direction of the
+/// world axis that's closest to being perpendicular to it. Bends curve the
+/// mesh towards this direction.
+fn bend_direction(axis: Vec3) -> Vec3 {
+    let world = [Vec3::X, Vec3::Y, Vec3::Z]
+        .into_iter()
+        .min_by(|a, b| a.dot(axis).abs().total_cmp(&b.dot(axis).abs()))
+        .unwrap();
+    (world - axis * world.dot(axis)).normalize()
+}
+
+/// Deforms the mesh along `axis`, passing through `origin`.
+///
+/// The deformation only happens in the part of the mesh between `limits`,
+/// given as fractions of the extent of the mesh along the axis, so `(0.0,
+/// 1.0)` deforms the whole mesh. The parts before the limits are unchanged,
+/// and the parts after them follow the deformation at the upper limit.
+///
+/// For twists and bends, `angle_or_factor` is the total angle in radians. For
+/// tapers, it's the growth of the scale across the limits, so a factor of -1
+/// shrinks the mesh to a point at the upper limit.
+pub fn simple_deform(
+    mesh: &HalfEdgeMesh,
+    kind: DeformKind,
+    axis: Vec3,
+    origin: Vec3,
+    angle_or_factor: f32,
+    limits: (f32, f32),
+) -> Result<()> {
+    let axis = axis
+        .try_normalize()
+        .ok_or_else(|| anyhow!("The deform axis can't be zero"))?;
+    let (lo, hi) = (limits.0.clamp(0.0, 1.0), limits.1.clamp(0.0, 1.0));
+    if lo >= hi {
+        bail!("The lower deform limit must be smaller than the upper one");
+    }
+
+    let conn = mesh.read_connectivity();
+    let mut positions = mesh.write_positions();
+    let (s_min, s_max) = conn
+        .iter_vertices()
+        .map(|(v, _)| (positions[v] - origin).dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), s| {
+            (min.min(s), max.max(s))
+        });
+    let extent = s_max - s_min;
+    if extent.is_nan() || extent <= 1e-6 {
+        report_warning(
+            current_sink().as_deref(),
+            Warning::new("The mesh has no extent along the deform axis, so it was left unchanged"),
+        );
+        return Ok(());
+    }
+    // The start and length of the deformed segment along the axis
+    let s_lo = s_min + lo * extent;
+    let length = (hi - lo) * extent;
+    let bend_dir = bend_direction(axis);
+
+    for (v, _) in conn.iter_vertices() {
+        let rel = positions[v] - origin;
+        let s = rel.dot(axis);
+        let perp = rel - axis * s;
+        // How far along the deformed segment this vertex is, from 0 to 1
+        let f = ((s - s_lo) / length).clamp(0.0, 1.0);
+        let new_rel = match kind {
+            DeformKind::Twist => {
+                let rotation = Quat::from_axis_angle(axis, angle_or_factor * f);
+                axis * s + rotation * perp
+            }
+            DeformKind::Taper => axis * s + perp * (1.0 + angle_or_factor * f),
+            DeformKind::Bend => {
+                if angle_or_factor.abs() < 1e-6 {
+                    continue;
+                }
+                // The segment is rolled around a center at a distance of
+                // `radius` towards the bend direction, so it becomes an arc
+                // of the given angle. The parts past the segment continue
+                // along the tangent at its end.
+                let radius = length / angle_or_factor;
+                let d = perp.dot(bend_dir);
+                let rest = perp - bend_dir * d;
+                let s_clamped = s_lo + f * length;
+                let phi = angle_or_factor * f;
+                let (sin, cos) = phi.sin_cos();
+                let along = s_lo + (radius - d) * sin + (s - s_clamped) * cos;
+                let across = radius - (radius - d) * cos + (s - s_clamped) * sin;
+                axis * along + bend_dir * across + rest
+            }
+        };
+        positions[v] = origin + new_rel;
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Twists, bends or tapers the mesh along `axis`, passing through
+    /// `origin`. The `kind` is one of `"Twist"`, `"Bend"` or `"Taper"`. The
+    /// `angle_or_factor` is an angle in radians for twists and bends, and the
+    /// growth of the scale for tapers. Only the part of the mesh between
+    /// `limit_min` and `limit_max`, as fractions of its extent along the axis,
+    /// is deformed.
+    #[lua(under = "Ops")]
+    pub fn simple_deform(
+        mesh: &mut HalfEdgeMesh,
+        kind: String,
+        axis: LVec3,
+        origin: LVec3,
+        angle_or_factor: f32,
+        limit_min: f32,
+        limit_max: f32,
+    ) -> Result<()> {
+        let kind = match kind.as_str() {
+            "Twist" => DeformKind::Twist,
+            "Bend" => DeformKind::Bend,
+            "Taper" => DeformKind::Taper,
+            _ => bail!("Invalid deform kind '{kind}'"),
+        };
+        super::simple_deform(
+            mesh,
+            kind,
+            axis.0,
+            origin.0,
+            angle_or_factor,
+            (limit_min, limit_max),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::{FRAC_PI_2, TAU};
+
+    use super::*;
+    use crate::progress::MockSink;
+
+    #[test]
+    fn test_twist_box() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let before = mesh.read_positions().clone();
+        simple_deform(
+            &mesh,
+            DeformKind::Twist,
+            Vec3::Y,
+            Vec3::ZERO,
+            FRAC_PI_2,
+            (0.0, 1.0),
+        )
+        .unwrap();
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            let (a, b) = (before[v], positions[v]);
+            assert!((a.y - b.y).abs() < 1e-6);
+            if a.y > 0.0 {
+                // The top face is rotated by the full angle
+                let angle = b.z.atan2(b.x) - a.z.atan2(a.x);
+                let angle = (angle + TAU) % TAU;
+                assert!((angle - (TAU - FRAC_PI_2)).abs() < 1e-5, "{angle}");
+            } else {
+                assert!((a - b).length() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bend_full_circle() {
+        let mesh = primitives::Grid::build(21, 2, 0.5, 0.1).unwrap();
+        let before = mesh.read_positions().clone();
+        simple_deform(
+            &mesh,
+            DeformKind::Bend,
+            Vec3::X,
+            Vec3::ZERO,
+            TAU,
+            (0.0, 1.0),
+        )
+        .unwrap();
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        for (start, _) in conn.iter_vertices().filter(|(v, _)| before[*v].x == 0.0) {
+            let (end, _) = conn
+                .iter_vertices()
+                .find(|(v, _)| before[*v].x == 10.0 && before[*v].y == before[start].y)
+                .unwrap();
+            assert!((positions[start] - positions[end]).length() < 1e-3);
+        }
+        // The rest of the strip was moved onto a circle of length 10
+        let middle = conn
+            .iter_vertices()
+            .find(|(v, _)| before[*v].x == 5.0 && before[*v].y == 0.0)
+            .unwrap()
+            .0;
+        assert!((positions[middle].y - 10.0 / TAU * 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_deform_flat_mesh() {
+        let mesh = primitives::Grid::build(3, 3, 1.0, 1.0).unwrap();
+        let before = mesh.read_positions().clone();
+        let sink = std::rc::Rc::new(MockSink::new(None));
+        crate::progress::with_progress_sink(sink.clone(), || {
+            simple_deform(
+                &mesh,
+                DeformKind::Taper,
+                Vec3::Z,
+                Vec3::ZERO,
+                1.0,
+                (0.0, 1.0),
+            )
+        })
+        .unwrap();
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            assert_eq!(positions[v], before[v]);
+        }
+        // The mesh has no extent along the axis, which the node warns about
+        let warnings = sink.warnings.borrow();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("no extent"));
+    }
+}
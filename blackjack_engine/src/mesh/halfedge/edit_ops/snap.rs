@@ -0,0 +1,282 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::mesh::halfedge::analysis::face_area;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// Faces with an area below this threshold are considered degenerate.
+const DEGENERATE_AREA: f32 = 1e-8;
+
+/// The plane used by [`flatten`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlattenPlane {
+    /// An axis-aligned plane, where the coordinate for the given `axis` (0 for
+    /// X, 1 for Y, 2 for Z) equals `value`.
+    Axis { axis: usize, value: f32 },
+    /// The least squares best-fit plane of the flattened vertices.
+    BestFit,
+}
+
+/// Calls `f` to move each of the given `vertices`, then returns the number of
+/// faces around those vertices that became degenerate.
+fn move_vertices(
+    mesh: &HalfEdgeMesh,
+    vertices: &[VertexId],
+    mut f: impl FnMut(Vec3) -> Vec3,
+) -> Result<usize> {
+    let conn = mesh.read_connectivity();
+    let mut positions = mesh.write_positions();
+
+    let vertex_set: HashSet<VertexId> = vertices.iter_cpy().collect();
+    let affected_faces = conn
+        .iter_faces()
+        .map(|(face, _)| face)
+        .filter(|face| {
+            conn.face_vertices(*face)
+                .iter()
+                .any(|v| vertex_set.contains(v))
+        })
+        .collect_vec();
+    let was_degenerate = affected_faces
+        .iter()
+        .map(|face| face_area(&conn, &positions, *face) < DEGENERATE_AREA)
+        .collect_vec();
+
+    for v in vertices.iter_cpy() {
+        positions[v] = f(positions[v]);
+    }
+
+    Ok(affected_faces
+        .iter()
+        .zip(was_degenerate)
+        .filter(|(face, was_degenerate)| {
+            !was_degenerate && face_area(&conn, &positions, **face) < DEGENERATE_AREA
+        })
+        .count())
+}
+
+/// Snaps the position of the vertices in `selection` to a grid with the given
+/// `grid_size`. A grid size of zero on an axis leaves that coordinate
+/// untouched.
+///
+/// Returns the number of faces that became degenerate (i.e. zero area) as a
+/// result of the snapping.
+pub fn quantize(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    grid_size: Vec3,
+) -> Result<usize> {
+    if grid_size.cmplt(Vec3::ZERO).any() {
+        bail!("The grid size can't be negative");
+    }
+    let vertices = mesh.resolve_vertex_selection_full(selection)?;
+    let snap = |x: f32, size: f32| {
+        if size > 0.0 {
+            (x / size).round() * size
+        } else {
+            x
+        }
+    };
+    move_vertices(mesh, &vertices, |p| {
+        Vec3::new(
+            snap(p.x, grid_size.x),
+            snap(p.y, grid_size.y),
+            snap(p.z, grid_size.z),
+        )
+    })
+}
+
+/// Computes the least squares best-fit plane for a set of points. Returns a
+/// point in the plane, and the plane's normal.
+///
+/// Fails when the points are all in a line, or there are fewer than three.
+pub fn best_fit_plane(points: &[Vec3]) -> Result<(Vec3, Vec3)> {
+    if points.len() < 3 {
+        bail!("At least three points are needed to fit a plane");
+    }
+    let centroid = points.iter().copied().sum::<Vec3>() / points.len() as f32;
+
+    // Covariance matrix of the points, excluding symmetries
+    let (mut xx, mut xy, mut xz, mut yy, mut yz, mut zz) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for p in points {
+        let r = *p - centroid;
+        xx += r.x * r.x;
+        xy += r.x * r.y;
+        xz += r.x * r.z;
+        yy += r.y * r.y;
+        yz += r.y * r.z;
+        zz += r.z * r.z;
+    }
+
+    // The normal is found by solving the linear system for the axis with the
+    // best conditioned determinant.
+    let det_x = yy * zz - yz * yz;
+    let det_y = xx * zz - xz * xz;
+    let det_z = xx * yy - xy * xy;
+    let det_max = det_x.max(det_y).max(det_z);
+    if det_max <= f32::EPSILON {
+        bail!("The points are collinear, there is no single best-fit plane");
+    }
+    let normal = if det_max == det_x {
+        Vec3::new(det_x, xz * yz - xy * zz, xy * yz - xz * yy)
+    } else if det_max == det_y {
+        Vec3::new(xz * yz - xy * zz, det_y, xy * xz - yz * xx)
+    } else {
+        Vec3::new(xy * yz - xz * yy, xy * xz - yz * xx, det_z)
+    };
+
+    Ok((centroid, normal.normalize()))
+}
+
+/// Projects the vertices in `selection` onto the given `plane`. Vertex
+/// connectivity is left untouched.
+///
+/// Returns the number of faces that became degenerate (i.e. zero area) as a
+/// result of the flattening.
+pub fn flatten(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    plane: FlattenPlane,
+) -> Result<usize> {
+    let vertices = mesh.resolve_vertex_selection_full(selection)?;
+    match plane {
+        FlattenPlane::Axis { axis, value } => {
+            if axis > 2 {
+                bail!("Invalid axis {axis}. Should be 0, 1 or 2.");
+            }
+            move_vertices(mesh, &vertices, |mut p| {
+                p[axis] = value;
+                p
+            })
+        }
+        FlattenPlane::BestFit => {
+            let points = {
+                let positions = mesh.read_positions();
+                vertices.iter().map(|v| positions[*v]).collect_vec()
+            };
+            let (origin, normal) = best_fit_plane(&points)?;
+            move_vertices(mesh, &vertices, |p| p - normal * (p - origin).dot(normal))
+        }
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Snaps the vertices in `selection` to a grid with the given per-axis
+    /// `grid_size`. A size of zero disables snapping on that axis. Returns the
+    /// number of faces that became degenerate.
+    #[lua(under = "Ops")]
+    pub fn quantize(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        grid_size: LVec3,
+    ) -> Result<usize> {
+        super::quantize(mesh, &selection, grid_size.0)
+    }
+
+    /// Projects the vertices in `selection` onto a plane. When `axis` is one
+    /// of `"X"`, `"Y"` or `"Z"`, the plane is the one where that coordinate
+    /// equals `value`. When `axis` is `"BestFit"`, the best-fit plane of the
+    /// selected vertices is used instead. Returns the number of faces that
+    /// became degenerate.
+    #[lua(under = "Ops")]
+    pub fn flatten(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        axis: String,
+        value: f32,
+    ) -> Result<usize> {
+        let plane = match axis.as_str() {
+            "X" => FlattenPlane::Axis { axis: 0, value },
+            "Y" => FlattenPlane::Axis { axis: 1, value },
+            "Z" => FlattenPlane::Axis { axis: 2, value },
+            "BestFit" => FlattenPlane::BestFit,
+            _ => bail!("Invalid flatten axis '{axis}'"),
+        };
+        super::flatten(mesh, &selection, plane)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let mesh = primitives::Grid::build(10, 10, 0.37, 0.37).unwrap();
+        {
+            let conn = mesh.read_connectivity();
+            let mut positions = mesh.write_positions();
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                let noise = (i as f32 * 12.9898).sin() * 0.1;
+                positions[v] += Vec3::new(noise, -noise, noise * 0.5);
+            }
+        }
+
+        let grid = Vec3::new(0.25, 0.0, 0.5);
+        let before = mesh.read_positions().clone();
+        quantize(&mesh, &SelectionExpression::All, grid).unwrap();
+
+        let positions = mesh.read_positions();
+        let is_multiple = |x: f32, size: f32| ((x / size) - (x / size).round()).abs() < 1e-4;
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            assert!(is_multiple(positions[v].x, grid.x));
+            assert!(is_multiple(positions[v].z, grid.z));
+            // A zero grid size leaves the axis untouched
+            assert_eq!(positions[v].y, before[v].y);
+        }
+    }
+
+    #[test]
+    fn test_flatten_best_fit() {
+        let mesh = primitives::Circle::build(Vec3::ZERO, 1.0, 12).unwrap();
+        // Tilt the polygon and add some noise out of the plane
+        {
+            let conn = mesh.read_connectivity();
+            let mut positions = mesh.write_positions();
+            let rot = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 1.0).normalize(), 0.6);
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                let noise = if i % 2 == 0 { 0.05 } else { -0.05 };
+                positions[v] = rot * (positions[v] + Vec3::Y * noise);
+            }
+        }
+
+        let degenerate = flatten(&mesh, &SelectionExpression::All, FlattenPlane::BestFit).unwrap();
+        assert_eq!(degenerate, 0);
+
+        let points = {
+            let positions = mesh.read_positions();
+            mesh.read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| positions[v])
+                .collect_vec()
+        };
+        let (origin, normal) = best_fit_plane(&points).unwrap();
+        for p in points {
+            assert!((p - origin).dot(normal).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_flatten_axis_degenerate() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let plane = FlattenPlane::Axis {
+            axis: 1,
+            value: 0.5,
+        };
+        let degenerate = flatten(&mesh, &SelectionExpression::All, plane).unwrap();
+        // The four side faces collapse, the top and bottom ones overlap
+        assert_eq!(degenerate, 4);
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            assert_eq!(positions[v].y, 0.5);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::*;
+
+use super::plane_clip::{ClippedMesh, Plane};
+
+/// Which side of the plane [`symmetrize`] keeps and copies over the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetrizeDirection {
+    /// The side the plane normal points to is mirrored onto the other one.
+    PositiveToNegative,
+    NegativeToPositive,
+}
+
+/// Makes `mesh` mirror-symmetric across `plane`. Everything at one side of
+/// the plane is removed, and replaced by a mirror image of the other side.
+/// Faces crossing the plane are clipped at it.
+///
+/// Vertices closer than `weld_threshold` to the plane are moved onto it, and
+/// shared by both halves. Closed meshes stay closed.
+pub fn symmetrize(
+    mesh: &HalfEdgeMesh,
+    plane: Plane,
+    direction: SymmetrizeDirection,
+    weld_threshold: f32,
+) -> Result<HalfEdgeMesh> {
+    let above = direction == SymmetrizeDirection::PositiveToNegative;
+    let mut clipped = ClippedMesh::default();
+    clipped.add_clipped_faces(mesh, &plane, above, weld_threshold)?;
+
+    // The vertex each vertex of the kept half is mirrored to
+    let mut mirrored = vec![];
+    for idx in 0..clipped.positions.len() {
+        let position = clipped.positions[idx];
+        if plane.distance(position).abs() <= weld_threshold {
+            clipped.positions[idx] = plane.project(position);
+            mirrored.push(idx as u32);
+        } else {
+            let sources = clipped.vertex_sources[idx].clone();
+            mirrored.push(clipped.add_vertex(plane.mirror(position), sources));
+        }
+    }
+
+    for p in 0..clipped.polygons.len() {
+        let polygon = &clipped.polygons[p];
+        // Faces on the plane are their own mirror image
+        if polygon.iter().all(|idx| mirrored[*idx as usize] == *idx) {
+            continue;
+        }
+        // The winding is reversed, so the mirrored faces face outwards too
+        let vertices = polygon
+            .iter()
+            .rev()
+            .map(|idx| mirrored[*idx as usize])
+            .collect();
+        let corners = clipped.corner_sources[p].iter().rev().cloned().collect();
+        clipped.add_polygon(vertices, clipped.face_sources[p], corners);
+    }
+
+    clipped.build(mesh)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Makes the mesh mirror-symmetric across the plane through `plane_origin`
+    /// with the given `plane_normal`. The `direction` is either
+    /// `"PositiveToNegative"`, the default, which keeps the side the normal
+    /// points to and mirrors it onto the other side, or
+    /// `"NegativeToPositive"`. Vertices closer than `weld_threshold` to the
+    /// plane are welded with their mirror image.
+    #[lua(under = "Ops")]
+    pub fn symmetrize(
+        mesh: &mut HalfEdgeMesh,
+        plane_origin: LVec3,
+        plane_normal: LVec3,
+        direction: Option<String>,
+        weld_threshold: f32,
+    ) -> Result<()> {
+        let direction = match direction.as_deref() {
+            None | Some("PositiveToNegative") => SymmetrizeDirection::PositiveToNegative,
+            Some("NegativeToPositive") => SymmetrizeDirection::NegativeToPositive,
+            Some(direction) => bail!("Invalid symmetrize direction '{direction}'"),
+        };
+        let plane = Plane::new(plane_origin.0, plane_normal.0)?;
+        *mesh = super::symmetrize(mesh, plane, direction, weld_threshold)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+
+    /// An asymmetric, closed blob: A sphere squashed and stretched
+    /// differently at each side of the YZ plane.
+    fn blob() -> HalfEdgeMesh {
+        let mesh = primitives::UVSphere::build(Vec3::new(0.1, 0.0, 0.0), 12, 8, 1.0).unwrap();
+        {
+            let conn = mesh.read_connectivity();
+            let mut positions = mesh.write_positions();
+            for (v, _) in conn.iter_vertices() {
+                let p = positions[v];
+                positions[v] = if p.x > 0.0 {
+                    Vec3::new(p.x * 1.5, p.y + 0.2 * p.x, p.z)
+                } else {
+                    Vec3::new(p.x * 0.7, p.y, p.z * 1.2)
+                };
+            }
+        }
+        mesh
+    }
+
+    /// Returns whether every vertex of `mesh` has a mirror image across
+    /// `plane` among its vertices.
+    fn is_mirror_symmetric(mesh: &HalfEdgeMesh, plane: &Plane) -> bool {
+        let positions = mesh.read_positions();
+        let points = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect_vec();
+        points.iter().all(|p| {
+            let image = plane.mirror(*p);
+            points.iter().any(|q| q.distance(image) < 1e-4)
+        })
+    }
+
+    #[test]
+    fn test_symmetrize_blob() {
+        let plane = Plane::new(Vec3::ZERO, Vec3::X).unwrap();
+        let mesh = blob();
+        assert!(!is_mirror_symmetric(&mesh, &plane));
+
+        for direction in [
+            SymmetrizeDirection::PositiveToNegative,
+            SymmetrizeDirection::NegativeToPositive,
+        ] {
+            let result = symmetrize(&mesh, plane, direction, 1e-4).unwrap();
+            assert!(is_mirror_symmetric(&result, &plane));
+            assert!(validate(&result).is_valid());
+            // The input was closed, and so is the result
+            let conn = result.read_connectivity();
+            assert!(conn.iter_halfedges().all(|(_, h)| h.face.is_some()));
+
+            // Only the kept side is left, mirrored
+            let positions = result.read_positions();
+            let max_x = conn
+                .iter_vertices()
+                .map(|(v, _)| positions[v].x.abs())
+                .fold(0.0, f32::max);
+            let side = match direction {
+                SymmetrizeDirection::PositiveToNegative => 1.0,
+                SymmetrizeDirection::NegativeToPositive => -1.0,
+            };
+            let input_positions = mesh.read_positions();
+            let expected = mesh
+                .read_connectivity()
+                .iter_vertices()
+                .map(|(v, _)| input_positions[v].x * side)
+                .fold(0.0, f32::max);
+            assert!((max_x - expected).abs() < 1e-4, "{max_x}");
+        }
+    }
+
+    #[test]
+    fn test_symmetrize_interpolates_channels() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        super::super::set_full_range_uvs(&mut mesh).unwrap();
+        // A plane through the middle of the box, off the vertices
+        let plane = Plane::new(Vec3::new(0.25, 0.0, 0.0), Vec3::X).unwrap();
+        let result =
+            symmetrize(&mesh, plane, SymmetrizeDirection::PositiveToNegative, 1e-4).unwrap();
+
+        let conn = result.read_connectivity();
+        // The four faces crossing the plane are clipped and mirrored, and so
+        // is the face at the kept end
+        assert_eq!(conn.num_faces(), 10);
+        assert_eq!(conn.num_vertices(), 12);
+        let uvs = result
+            .channels
+            .read_channel_by_name::<HalfEdgeId, Vec3>("uv")
+            .unwrap();
+        // The cut is a quarter of the way along the crossing edges
+        let cut_uvs = conn
+            .iter_halfedges()
+            .filter(|(h, _)| {
+                let v = conn.at_halfedge(*h).vertex().end();
+                (result.read_positions()[v].x - 0.25).abs() < 1e-5
+            })
+            .map(|(h, _)| uvs[h])
+            .collect_vec();
+        assert!(!cut_uvs.is_empty());
+        for uv in cut_uvs {
+            assert!(
+                [0.25, 0.75].contains(&uv.x) || [0.25, 0.75].contains(&uv.y),
+                "{uv}"
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use mlua::FromLua;
+
+use crate::lua_engine::lua_stdlib::LVec3;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// The point around which a transform rotates and scales the mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformPivot {
+    Origin,
+    /// The center of the axis-aligned bounding box of the transformed
+    /// vertices.
+    BoundsCenter,
+    /// The area-weighted centroid of the surface of the transformed faces.
+    Centroid,
+    Custom(Vec3),
+}
+
+/// Converts from either one of the strings `"Origin"`, `"BoundsCenter"` and
+/// `"Centroid"`, or a vector for a custom pivot.
+impl<'lua> FromLua<'lua> for TransformPivot {
+    fn from_lua(lua_value: mlua::Value<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        if let mlua::Value::String(s) = &lua_value {
+            return match s.to_str()? {
+                "Origin" => Ok(TransformPivot::Origin),
+                "BoundsCenter" => Ok(TransformPivot::BoundsCenter),
+                "Centroid" => Ok(TransformPivot::Centroid),
+                other => Err(mlua::Error::FromLuaConversionError {
+                    from: "string",
+                    to: "TransformPivot",
+                    message: Some(format!("Invalid pivot mode '{other}'")),
+                }),
+            };
+        }
+        LVec3::from_lua(lua_value, lua).map(|v| TransformPivot::Custom(v.0))
+    }
+}
+
+/// The rotation applied by a transform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformRotation {
+    /// XYZ euler angles, in radians.
+    Euler(Vec3),
+    /// A rotation of `angle` radians around `axis`.
+    AxisAngle { axis: Vec3, angle: f32 },
+}
+
+impl TransformRotation {
+    pub fn to_quat(&self) -> Result<Quat> {
+        match *self {
+            TransformRotation::Euler(e) => Ok(Quat::from_euler(glam::EulerRot::XYZ, e.x, e.y, e.z)),
+            TransformRotation::AxisAngle { axis, angle } => {
+                let axis = axis
+                    .try_normalize()
+                    .ok_or_else(|| anyhow!("The rotation axis can't be zero"))?;
+                Ok(Quat::from_axis_angle(axis, angle))
+            }
+        }
+    }
+}
+
+/// All the settings for [`transform_with_options`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformOptions {
+    pub translate: Vec3,
+    pub rotate: TransformRotation,
+    pub scale: Vec3,
+    pub pivot: TransformPivot,
+    /// When set to `(local_axis, target)`, the mesh is first rotated so that
+    /// its `local_axis` points in the `target` direction. The regular rotation
+    /// is applied after this one.
+    pub orient: Option<(Vec3, Vec3)>,
+}
+
+impl Default for TransformOptions {
+    fn default() -> Self {
+        Self {
+            translate: Vec3::ZERO,
+            rotate: TransformRotation::Euler(Vec3::ZERO),
+            scale: Vec3::ONE,
+            pivot: TransformPivot::Origin,
+            orient: None,
+        }
+    }
+}
+
+/// Computes the position of the `pivot` for the given `vertices` of `mesh`.
+pub fn compute_pivot(
+    mesh: &HalfEdgeMesh,
+    vertices: &[VertexId],
+    pivot: TransformPivot,
+) -> Result<Vec3> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let mean =
+        || vertices.iter().map(|v| positions[*v]).sum::<Vec3>() / (vertices.len().max(1) as f32);
+
+    match pivot {
+        TransformPivot::Origin => Ok(Vec3::ZERO),
+        TransformPivot::Custom(p) => Ok(p),
+        TransformPivot::BoundsCenter => Ok(vertices
+            .iter()
+            .map(|v| (positions[*v], positions[*v]))
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+            .map(|(min, max)| (min + max) * 0.5)
+            .unwrap_or(Vec3::ZERO)),
+        TransformPivot::Centroid => {
+            // Only the faces with all their vertices transformed count
+            let vertex_set: HashSet<VertexId> = vertices.iter_cpy().collect();
+            let mut faces = vec![];
+            for (f, _) in conn.iter_faces() {
+                if conn
+                    .at_face(f)
+                    .vertices()?
+                    .iter()
+                    .all(|v| vertex_set.contains(v))
+                {
+                    faces.push(f);
+                }
+            }
+            // With no surface to speak of (e.g. a point cloud), the vertex
+            // average is the best we can do.
+            Ok(surface_centroid(&conn, &positions, &faces)?.unwrap_or_else(mean))
+        }
+    }
+}
+
+/// Returns the area-weighted centroid of the given `faces`, or `None` when
+/// their area is zero.
+pub fn surface_centroid(
+    conn: &MeshConnectivity,
+    positions: &Positions,
+    faces: &[FaceId],
+) -> Result<Option<Vec3>> {
+    let mut weighted_sum = Vec3::ZERO;
+    let mut total_area = 0.0;
+    for f in faces.iter_cpy() {
+        let verts = conn.at_face(f).vertices()?;
+        if verts.len() < 3 {
+            continue;
+        }
+        let v0 = positions[verts[0]];
+        for i in 1..verts.len() - 1 {
+            let (v1, v2) = (positions[verts[i]], positions[verts[i + 1]]);
+            let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+            weighted_sum += area * (v0 + v1 + v2) / 3.0;
+            total_area += area;
+        }
+    }
+    Ok((total_area > 1e-8).then(|| weighted_sum / total_area))
+}
+
+/// Applies a transformation to the `position` channel of the vertices in the
+/// `selection`, or the whole mesh if no selection is given. Vertices are
+/// scaled, oriented and rotated around the pivot and then translated.
+pub fn transform_with_options(
+    mesh: &HalfEdgeMesh,
+    selection: Option<&SelectionExpression>,
+    opts: &TransformOptions,
+) -> Result<()> {
+    let vertices = match selection {
+        Some(sel) => mesh.resolve_vertex_selection_full(sel)?,
+        None => mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .collect(),
+    };
+    let pivot = compute_pivot(mesh, &vertices, opts.pivot)?;
+
+    let orient = match opts.orient {
+        Some((local_axis, target)) => {
+            let local_axis = local_axis
+                .try_normalize()
+                .ok_or_else(|| anyhow!("The local axis to orient can't be zero"))?;
+            let target = target
+                .try_normalize()
+                .ok_or_else(|| anyhow!("The target direction to orient to can't be zero"))?;
+            Quat::from_rotation_arc(local_axis, target)
+        }
+        None => Quat::IDENTITY,
+    };
+    let rotation = opts.rotate.to_quat()? * orient;
+
+    let mut positions = mesh.write_positions();
+    for v in vertices {
+        positions[v] = pivot + rotation * ((positions[v] - pivot) * opts.scale) + opts.translate;
+    }
+
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Like `transform`, but with more options. The `pivot` is either one of
+    /// `"Origin"`, `"BoundsCenter"` or `"Centroid"`, or a vector for a custom
+    /// pivot point, and defaults to the origin when nil. When `angle` is nil,
+    /// `rotate` contains XYZ euler angles, otherwise the mesh is rotated
+    /// `angle` radians around the `rotate` axis. When `orient_axis` is set,
+    /// the mesh is first rotated so that this local axis points towards
+    /// `orient_target`. Only the vertices in `selection` are transformed, or
+    /// all of them when `selection` is nil.
+    #[lua(under = "Ops")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn transform_with_options(
+        mesh: &mut HalfEdgeMesh,
+        selection: Option<SelectionExpression>,
+        pivot: Option<TransformPivot>,
+        translate: LVec3,
+        rotate: LVec3,
+        angle: Option<f32>,
+        scale: LVec3,
+        orient_axis: Option<LVec3>,
+        orient_target: Option<LVec3>,
+    ) -> Result<()> {
+        let orient = match (orient_axis, orient_target) {
+            (Some(axis), Some(target)) => Some((axis.0, target.0)),
+            (None, None) => None,
+            _ => bail!("Both orient_axis and orient_target must be set to orient the mesh"),
+        };
+        let opts = TransformOptions {
+            translate: translate.0,
+            rotate: match angle {
+                Some(angle) => TransformRotation::AxisAngle {
+                    axis: rotate.0,
+                    angle,
+                },
+                None => TransformRotation::Euler(rotate.0),
+            },
+            scale: scale.0,
+            pivot: pivot.unwrap_or(TransformPivot::Origin),
+            orient,
+        };
+        super::transform_with_options(mesh, selection.as_ref(), &opts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bounds_center_rotation() {
+        let center = Vec3::new(2.0, -1.0, 3.0);
+        let mesh = primitives::Box::build(center, Vec3::new(1.0, 2.0, 3.0)).unwrap();
+        let opts = TransformOptions {
+            rotate: TransformRotation::Euler(Vec3::new(0.3, 0.7, -1.1)),
+            pivot: TransformPivot::BoundsCenter,
+            ..Default::default()
+        };
+        transform_with_options(&mesh, None, &opts).unwrap();
+
+        let (min, max) = crate::mesh::halfedge::analysis::bounds(&mesh);
+        assert!(((min + max) * 0.5).abs_diff_eq(center, 1e-4));
+    }
+
+    #[test]
+    fn test_orient_to_vector() {
+        let mesh = primitives::Quad::build(Vec3::ZERO, Vec3::Y, Vec3::X, Vec2::ONE).unwrap();
+        let normal_before = {
+            let conn = mesh.read_connectivity();
+            let (f, _) = conn.iter_faces().next().unwrap();
+            conn.face_normal(&mesh.read_positions(), f).unwrap()
+        };
+
+        let target = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let opts = TransformOptions {
+            pivot: TransformPivot::Centroid,
+            orient: Some((normal_before, target)),
+            ..Default::default()
+        };
+        transform_with_options(&mesh, None, &opts).unwrap();
+
+        let conn = mesh.read_connectivity();
+        let (f, _) = conn.iter_faces().next().unwrap();
+        let normal_after = conn.face_normal(&mesh.read_positions(), f).unwrap();
+        assert!(normal_after.abs_diff_eq(target, 1e-5));
+    }
+
+    #[test]
+    fn test_selection_transform() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let before = mesh.read_positions().clone();
+        let sel = SelectionExpression::parse("0, 1").unwrap();
+        let opts = TransformOptions {
+            translate: Vec3::Y,
+            rotate: TransformRotation::AxisAngle {
+                axis: Vec3::Z,
+                angle: 1.0,
+            },
+            pivot: TransformPivot::Custom(Vec3::X),
+            ..Default::default()
+        };
+        transform_with_options(&mesh, Some(&sel), &opts).unwrap();
+
+        let selected = mesh.resolve_vertex_selection_full(&sel).unwrap();
+        let positions = mesh.read_positions();
+        for (v, _) in mesh.read_connectivity().iter_vertices() {
+            if selected.contains(&v) {
+                assert!(!positions[v].abs_diff_eq(before[v], 1e-5));
+            } else {
+                assert_eq!(positions[v], before[v]);
+            }
+        }
+    }
+}
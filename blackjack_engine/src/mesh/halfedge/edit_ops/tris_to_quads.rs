@@ -0,0 +1,304 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::f32::consts::FRAC_PI_2;
+
+use crate::mesh::halfedge::analysis::is_uv_seam;
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+
+use super::{dissolve_edge, remove_stale_channel_entries};
+
+/// A pair of triangles that can be merged into a quad by dissolving the edge
+/// between them. Lower scores give better quads.
+struct Candidate {
+    h: HalfEdgeId,
+    faces: [FaceId; 2],
+    score: f32,
+}
+
+/// Returns the angle between `a` and `b`, in radians, or NaN if one of them
+/// has zero length.
+fn angle(a: Vec3, b: Vec3) -> f32 {
+    let len = a.length() * b.length();
+    if len < 1e-12 {
+        return f32::NAN;
+    }
+    (a.dot(b) / len).clamp(-1.0, 1.0).acos()
+}
+
+/// Scores the quad with the given corners, in loop order, formed by two
+/// triangles with the given `normals`. The score adds the angle between the
+/// triangles to the worst deviation of a corner from a right angle. Returns
+/// `None` when the quad is concave, or either angle is above `max_angle`.
+fn quad_score(quad: [Vec3; 4], normals: [Vec3; 2], max_angle: f32) -> Option<f32> {
+    let planarity = angle(normals[0], normals[1]);
+    if planarity.is_nan() || planarity > max_angle {
+        return None;
+    }
+    let normal = (normals[0] + normals[1]).normalize_or_zero();
+    let mut deviation = 0.0f32;
+    for i in 0..4 {
+        let (prev, p, next) = (quad[(i + 3) % 4], quad[i], quad[(i + 1) % 4]);
+        if (p - prev).cross(next - p).dot(normal) <= 0.0 {
+            return None;
+        }
+        deviation = deviation.max((angle(prev - p, next - p) - FRAC_PI_2).abs());
+    }
+    if deviation.is_nan() || deviation > max_angle {
+        return None;
+    }
+    Some(planarity + deviation)
+}
+
+/// Finds every edge between two selected triangles whose quad would be within
+/// the thresholds, sorted from the best quad to the worst.
+fn candidates(
+    mesh: &HalfEdgeMesh,
+    selection: &SelectionExpression,
+    max_angle: f32,
+    uv_seam_aware: bool,
+) -> Result<Vec<Candidate>> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let uvs = if uv_seam_aware { mesh.read_uvs() } else { None };
+    let triangles: HashSet<FaceId> = mesh
+        .resolve_face_selection_full(selection)?
+        .into_iter()
+        .filter(|f| conn.face_edges(*f).len() == 3)
+        .collect();
+
+    let mut visited = HashSet::new();
+    let mut candidates = vec![];
+    for (h, _) in conn.iter_halfedges() {
+        let t = conn.at_halfedge(h).twin().try_end()?;
+        if !visited.insert(h) || !visited.insert(t) {
+            continue;
+        }
+        let (f_a, f_b) = match (conn[h].face, conn[t].face) {
+            (Some(f_a), Some(f_b)) if f_a != f_b => (f_a, f_b),
+            _ => continue,
+        };
+        if !triangles.contains(&f_a) || !triangles.contains(&f_b) {
+            continue;
+        }
+
+        // The triangles are (v, w, a) and (w, v, b). Dissolving the edge
+        // leaves the quad (a, v, b, w).
+        let h_next = conn.at_halfedge(h).next().try_end()?;
+        let t_next = conn.at_halfedge(t).next().try_end()?;
+        let (v, w) = conn.at_halfedge(h).src_dst_pair()?;
+        let a = conn.at_halfedge(h_next).dst_vertex().try_end()?;
+        let b = conn.at_halfedge(t_next).dst_vertex().try_end()?;
+        if a == b {
+            continue;
+        }
+
+        if let Some(uvs) = &uvs {
+            if is_uv_seam(&conn, uvs, h)? {
+                continue;
+            }
+        }
+
+        let [pv, pw, pa, pb] = [v, w, a, b].map(|x| positions[x]);
+        let normals = [
+            (pw - pv).cross(pa - pv).normalize_or_zero(),
+            (pv - pw).cross(pb - pw).normalize_or_zero(),
+        ];
+        if let Some(score) = quad_score([pa, pv, pb, pw], normals, max_angle) {
+            candidates.push(Candidate {
+                h,
+                faces: [f_a, f_b],
+                score,
+            });
+        }
+    }
+    candidates.sort_by(|x, y| x.score.total_cmp(&y.score));
+    Ok(candidates)
+}
+
+/// Merges pairs of adjacent triangles in the `selection` into quads. Pairs
+/// are merged greedily, best quads first, and each triangle is merged at most
+/// once. Triangles that aren't merged, like those where the quad would be
+/// concave, are left as they are.
+///
+/// The `max_angle_deg` limits both the angle between the two triangles and how
+/// far each corner of the quad can be from a right angle. When `uv_seam_aware`
+/// is set, triangles with different uvs at their shared edge are never merged,
+/// so uv seams are kept. Returns the number of quads created.
+pub fn tris_to_quads(
+    mesh: &mut HalfEdgeMesh,
+    selection: &SelectionExpression,
+    max_angle_deg: f32,
+    uv_seam_aware: bool,
+) -> Result<usize> {
+    let candidates = candidates(mesh, selection, max_angle_deg.to_radians(), uv_seam_aware)?;
+    let mut merged_faces = HashSet::new();
+    let mut num_quads = 0;
+    {
+        let mut conn = mesh.write_connectivity();
+        for candidate in candidates {
+            if candidate.faces.iter().any(|f| merged_faces.contains(f)) {
+                continue;
+            }
+            merged_faces.extend(candidate.faces);
+            dissolve_edge(&mut conn, candidate.h)?;
+            num_quads += 1;
+        }
+    }
+    remove_stale_channel_entries(mesh)?;
+    Ok(num_quads)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Merges pairs of adjacent triangles in the `selection` of `mesh` into
+    /// quads, best quads first. Returns the number of quads created.
+    #[lua(under = "Ops")]
+    pub fn tris_to_quads(
+        mesh: &mut HalfEdgeMesh,
+        selection: SelectionExpression,
+        max_angle_deg: f32,
+        uv_seam_aware: bool,
+    ) -> Result<usize> {
+        super::tris_to_quads(mesh, &selection, max_angle_deg, uv_seam_aware)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::validate;
+    use crate::mesh::halfedge::primitives::UVSphere;
+
+    const N: usize = 4;
+
+    /// A flat N by N grid of squares, each one split into two triangles.
+    /// Diagonals alternate, so each triangle is next to triangles of other
+    /// squares too.
+    fn triangulated_grid() -> HalfEdgeMesh {
+        let mut positions = vec![];
+        for z in 0..=N {
+            for x in 0..=N {
+                positions.push(Vec3::new(x as f32, 0.0, z as f32));
+            }
+        }
+        let idx = |x: usize, z: usize| z * (N + 1) + x;
+        let mut polygons = vec![];
+        for z in 0..N {
+            for x in 0..N {
+                let [a, b, c, d] = [idx(x, z), idx(x, z + 1), idx(x + 1, z + 1), idx(x + 1, z)];
+                if (x + z) % 2 == 0 {
+                    polygons.push(vec![a, b, c]);
+                    polygons.push(vec![a, c, d]);
+                } else {
+                    polygons.push(vec![a, b, d]);
+                    polygons.push(vec![b, c, d]);
+                }
+            }
+        }
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    /// Splits every face of `mesh` into a fan of triangles.
+    fn triangulate(mesh: &HalfEdgeMesh) -> HalfEdgeMesh {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        let index: HashMap<VertexId, usize> =
+            vertices.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let mut polygons = vec![];
+        for (f, _) in conn.iter_faces() {
+            let verts = conn.face_vertices(f);
+            for i in 1..verts.len() - 1 {
+                polygons.push(vec![
+                    index[&verts[0]],
+                    index[&verts[i]],
+                    index[&verts[i + 1]],
+                ]);
+            }
+        }
+        let positions = vertices.iter().map(|v| positions[*v]).collect_vec();
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    fn face_sizes(mesh: &HalfEdgeMesh) -> Vec<usize> {
+        let conn = mesh.read_connectivity();
+        conn.iter_faces()
+            .map(|(f, _)| conn.face_edges(f).len())
+            .collect()
+    }
+
+    /// Sets the uvs of `mesh` from the XZ position of each corner, offset by
+    /// `offset` for each face.
+    fn set_planar_uvs(mesh: &mut HalfEdgeMesh, offset: impl Fn(usize) -> f32) {
+        let mut uvs = Channel::<HalfEdgeId, Vec3>::new();
+        {
+            let conn = mesh.read_connectivity();
+            let positions = mesh.read_positions();
+            for (i, (f, _)) in conn.iter_faces().enumerate() {
+                for h in conn.face_edges(f) {
+                    let src = conn.at_halfedge(h).src_vertex().try_end().unwrap();
+                    uvs[h] = Vec3::new(positions[src].x, positions[src].z, 0.0) + offset(i);
+                }
+            }
+        }
+        let uvs_ch_id = mesh.channels.replace_or_create_channel("uv", uvs);
+        mesh.default_channels.uvs = Some(uvs_ch_id);
+    }
+
+    #[test]
+    fn test_grid_becomes_quads() {
+        let mut mesh = triangulated_grid();
+        let num_quads = tris_to_quads(&mut mesh, &SelectionExpression::All, 30.0, false).unwrap();
+        assert_eq!(num_quads, N * N);
+        assert_eq!(face_sizes(&mesh), vec![4; N * N]);
+        assert!(validate(&mesh).is_valid());
+    }
+
+    #[test]
+    fn test_sphere_is_mostly_quads() {
+        let sphere = UVSphere::build(Vec3::ZERO, 16, 12, 1.0).unwrap();
+        let mut mesh = triangulate(&sphere);
+        tris_to_quads(&mut mesh, &SelectionExpression::All, 40.0, false).unwrap();
+        let sizes = face_sizes(&mesh);
+        let quads = sizes.iter().filter(|s| **s == 4).count();
+        assert!(sizes.iter().all(|s| *s == 3 || *s == 4));
+        assert!(
+            quads as f32 / sizes.len() as f32 > 0.75,
+            "Only {quads} quads out of {} faces",
+            sizes.len()
+        );
+        assert!(validate(&mesh).is_valid());
+    }
+
+    #[test]
+    fn test_uv_seams_are_kept() {
+        let mut mesh = triangulated_grid();
+        set_planar_uvs(&mut mesh, |_| 0.0);
+        let num_quads = tris_to_quads(&mut mesh, &SelectionExpression::All, 30.0, true).unwrap();
+        assert_eq!(num_quads, N * N);
+
+        // Every triangle has its own uv island
+        let mut mesh = triangulated_grid();
+        set_planar_uvs(&mut mesh, |i| i as f32 * 10.0);
+        let num_quads = tris_to_quads(&mut mesh, &SelectionExpression::All, 30.0, true).unwrap();
+        assert_eq!(num_quads, 0);
+        let num_quads = tris_to_quads(&mut mesh, &SelectionExpression::All, 30.0, false).unwrap();
+        assert_eq!(num_quads, N * N);
+    }
+
+    #[test]
+    fn test_only_selected_faces_are_merged() {
+        let mut mesh = triangulated_grid();
+        let selection = SelectionExpression::from_ids(vec![0, 1]);
+        let num_quads = tris_to_quads(&mut mesh, &selection, 30.0, false).unwrap();
+        assert_eq!(num_quads, 1);
+        assert_eq!(face_sizes(&mesh).len(), 2 * N * N - 1);
+    }
+}
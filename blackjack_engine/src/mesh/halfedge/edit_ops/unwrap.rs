@@ -0,0 +1,894 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use glam::DVec2;
+
+use crate::prelude::selection::SelectionExpression;
+use crate::prelude::*;
+use crate::progress::{report_warning, ProgressSink, Warning};
+
+/// How [`unwrap_uvs`] flattens each island.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnwrapMethod {
+    /// Least squares conformal maps: Lays out the island so the angles of its
+    /// triangles stay as close as possible to the ones they have in 3d. This
+    /// is a sparse least squares problem, with two vertices of the island
+    /// pinned in place to fix its position, rotation and scale.
+    Lscm,
+    /// A light take on angle based flattening. The angles around each vertex
+    /// inside the island are first adjusted so they add up to a full turn,
+    /// like they do on a flat surface, while keeping the angles of each
+    /// triangle adding up to half a turn. Then the triangles are laid out with
+    /// those angles like [`UnwrapMethod::Lscm`] does. This spreads the
+    /// distortion of curved islands more evenly.
+    Abf,
+}
+
+/// The number of rounds used by [`UnwrapMethod::Abf`] to adjust the angles.
+const ABF_ROUNDS: usize = 20;
+
+/// Triangles with a smaller area than this, relative to the squared length of
+/// their first edge, are left out of the flattening.
+const DEGENERATE_AREA: f64 = 1e-10;
+
+/// Returns the normal of a polygon, with Newell's method, which also works
+/// for polygons that are not planar. Degenerate polygons give zero.
+fn polygon_normal(points: &[Vec3]) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    for (i, p) in points.iter().enumerate() {
+        normal += p.cross(points[(i + 1) % points.len()]);
+    }
+    normal.normalize_or_zero()
+}
+
+/// Whether the edge of `h` separates the corners at both of its sides: It's
+/// either one of the `cuts`, or a boundary.
+fn is_open(conn: &MeshConnectivity, cuts: &HashSet<HalfEdgeId>, h: HalfEdgeId) -> Result<bool> {
+    Ok(cuts.contains(&h)
+        || conn.at_halfedge(h).is_boundary()?
+        || conn.at_halfedge(h).twin().is_boundary()?)
+}
+
+/// Groups the faces of the mesh into islands: Faces connected through edges
+/// that are neither `cuts` nor boundaries.
+fn cut_islands(conn: &MeshConnectivity, cuts: &HashSet<HalfEdgeId>) -> Result<Vec<Vec<FaceId>>> {
+    let mut visited = HashSet::new();
+    let mut islands = vec![];
+    for (start, _) in conn.iter_faces() {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut island = vec![];
+        let mut stack = vec![start];
+        while let Some(face) = stack.pop() {
+            island.push(face);
+            for h in conn.face_edges(face) {
+                if is_open(conn, cuts, h)? {
+                    continue;
+                }
+                let neighbor = conn.at_halfedge(h).twin().face().try_end()?;
+                if visited.insert(neighbor) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        islands.push(island);
+    }
+    Ok(islands)
+}
+
+/// An edge of an island, as its two halfedges.
+#[derive(Clone, Copy)]
+struct IslandEdge {
+    h: HalfEdgeId,
+    twin: HalfEdgeId,
+    src: VertexId,
+    dst: VertexId,
+}
+
+/// Adds to the `cuts` the edges an island needs to be cut along so it becomes
+/// a topological disk, which is what can be flattened.
+///
+/// The faces of the island are connected with a spanning tree through the
+/// edges that are not cut yet, and every edge not in the tree is cut. Cutting
+/// along all of them would leave the island as a single tree of faces, which
+/// is a disk. Most of those cuts aren't needed, so the ones ending in a
+/// vertex no other cut reaches are glued back, over and over, which leaves
+/// just the cuts that join the existing seams and boundaries, and open up the
+/// handles of the island. Closed islands without any seam are first cut along
+/// a path between two far away vertices, to give them a boundary.
+fn complete_cuts(
+    conn: &MeshConnectivity,
+    faces: &[FaceId],
+    cuts: &mut HashSet<HalfEdgeId>,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    let (mut fixed, mut interior) = (vec![], vec![]);
+    for &face in faces {
+        for h in conn.face_edges(face) {
+            let twin = conn.at_halfedge(h).twin().try_end()?;
+            if !seen.insert(h.min(twin)) {
+                continue;
+            }
+            let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+            let edge = IslandEdge { h, twin, src, dst };
+            if is_open(conn, cuts, h)? {
+                fixed.push(edge);
+            } else {
+                interior.push(edge);
+            }
+        }
+    }
+
+    if fixed.is_empty() {
+        let path = far_path(&interior);
+        for &i in path.iter().rev() {
+            let edge = interior.swap_remove(i);
+            cuts.insert(edge.h);
+            cuts.insert(edge.twin);
+            fixed.push(edge);
+        }
+    }
+
+    // The faces joined by each interior edge, and a spanning tree of them
+    let mut face_neighbors = HashMap::<FaceId, Vec<(FaceId, usize)>>::new();
+    for (i, edge) in interior.iter().enumerate() {
+        let a = conn.at_halfedge(edge.h).face().try_end()?;
+        let b = conn.at_halfedge(edge.twin).face().try_end()?;
+        face_neighbors.entry(a).or_default().push((b, i));
+        face_neighbors.entry(b).or_default().push((a, i));
+    }
+    let mut in_tree = vec![false; interior.len()];
+    let mut reached = HashSet::from([faces[0]]);
+    let mut queue = VecDeque::from([faces[0]]);
+    while let Some(face) = queue.pop_front() {
+        for &(neighbor, i) in face_neighbors.get(&face).into_iter().flatten() {
+            if reached.insert(neighbor) {
+                in_tree[i] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    // The cut graph: The fixed edges, and the interior edges not in the tree.
+    // Cut edges hanging from a vertex no other cut reaches are removed.
+    let candidates = interior
+        .iter()
+        .zip(&in_tree)
+        .filter(|(_, in_tree)| !**in_tree)
+        .map(|(edge, _)| *edge)
+        .collect_vec();
+    let mut vertex_edges = HashMap::<VertexId, Vec<usize>>::new();
+    let mut degree = HashMap::<VertexId, usize>::new();
+    let graph_edges = fixed.iter().chain(&candidates).collect_vec();
+    for (i, edge) in graph_edges.iter().enumerate() {
+        for v in [edge.src, edge.dst] {
+            vertex_edges.entry(v).or_default().push(i);
+            *degree.entry(v).or_default() += 1;
+        }
+    }
+    let mut removed = vec![false; graph_edges.len()];
+    let mut dangling = degree
+        .iter()
+        .filter(|(_, degree)| **degree == 1)
+        .map(|(v, _)| *v)
+        .collect_vec();
+    while let Some(v) = dangling.pop() {
+        if degree[&v] != 1 {
+            continue;
+        }
+        let i = match vertex_edges[&v].iter().find(|i| !removed[**i]) {
+            Some(&i) => i,
+            None => continue,
+        };
+        if i < fixed.len() {
+            continue;
+        }
+        removed[i] = true;
+        for end in [graph_edges[i].src, graph_edges[i].dst] {
+            let end_degree = degree.get_mut(&end).unwrap();
+            *end_degree -= 1;
+            if *end_degree == 1 {
+                dangling.push(end);
+            }
+        }
+    }
+    for (edge, removed) in candidates.iter().zip(&removed[fixed.len()..]) {
+        if !removed {
+            cuts.insert(edge.h);
+            cuts.insert(edge.twin);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the indices into `edges` of a path between two vertices that are
+/// far apart, in number of edges. The path is sorted by index.
+fn far_path(edges: &[IslandEdge]) -> Vec<usize> {
+    let mut neighbors = HashMap::<VertexId, Vec<(VertexId, usize)>>::new();
+    for (i, edge) in edges.iter().enumerate() {
+        neighbors.entry(edge.src).or_default().push((edge.dst, i));
+        neighbors.entry(edge.dst).or_default().push((edge.src, i));
+    }
+    // Finds the vertex farthest from `start`, and the edge each vertex was
+    // reached from
+    let bfs = |start: VertexId| {
+        let mut parents = HashMap::from([(start, None)]);
+        let mut queue = VecDeque::from([start]);
+        let mut last = start;
+        while let Some(v) = queue.pop_front() {
+            last = v;
+            for &(neighbor, i) in neighbors.get(&v).into_iter().flatten() {
+                if !parents.contains_key(&neighbor) {
+                    parents.insert(neighbor, Some((v, i)));
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        (last, parents)
+    };
+    let start = match edges.first() {
+        Some(edge) => edge.src,
+        None => return vec![],
+    };
+    let (a, _) = bfs(start);
+    let (mut v, parents) = bfs(a);
+    let mut path = vec![];
+    while let Some(Some((parent, i))) = parents.get(&v) {
+        path.push(*i);
+        v = *parent;
+    }
+    path.sort_unstable();
+    path
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// The surface of an island, as a triangle mesh where the corners of its
+/// faces are welded into vertices unless they are separated by a cut.
+struct IslandMesh {
+    /// The corners of the faces of the island, as their halfedges, which is
+    /// where the uvs go.
+    corners: Vec<HalfEdgeId>,
+    /// The vertex of the island of each corner.
+    corner_vertices: Vec<usize>,
+    positions: Vec<Vec3>,
+    /// Whether each vertex is on the boundary of the island, either at a cut
+    /// or at a boundary of the mesh.
+    on_boundary: Vec<bool>,
+    /// The faces of the island, split into triangle fans.
+    triangles: Vec<[usize; 3]>,
+}
+
+impl IslandMesh {
+    fn new(
+        conn: &MeshConnectivity,
+        positions: &Positions,
+        faces: &[FaceId],
+        cuts: &HashSet<HalfEdgeId>,
+    ) -> Result<Self> {
+        let mut corners = vec![];
+        let mut corner_index = HashMap::new();
+        for &face in faces {
+            for h in conn.face_edges(face) {
+                corner_index.insert(h, corners.len());
+                corners.push(h);
+            }
+        }
+
+        // Two corners around the same vertex are the same vertex of the
+        // island when the edge between them is not open
+        let mut parents = (0..corners.len()).collect_vec();
+        for (i, &h) in corners.iter().enumerate() {
+            if is_open(conn, cuts, h)? {
+                continue;
+            }
+            let across = conn.at_halfedge(h).cycle_around_fan().try_end()?;
+            if let Some(&j) = corner_index.get(&across) {
+                let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[a] = b;
+            }
+        }
+        let mut vertex_of_root = HashMap::new();
+        let mut island = IslandMesh {
+            corners: vec![],
+            corner_vertices: vec![],
+            positions: vec![],
+            on_boundary: vec![],
+            triangles: vec![],
+        };
+        for (i, &h) in corners.iter().enumerate() {
+            let root = find_root(&mut parents, i);
+            let vertex = *vertex_of_root.entry(root).or_insert_with(|| {
+                island.positions.push(Vec3::ZERO);
+                island.on_boundary.push(false);
+                island.positions.len() - 1
+            });
+            island.positions[vertex] = positions[conn.at_halfedge(h).src_vertex().try_end()?];
+            let previous = conn.at_halfedge(h).previous().try_end()?;
+            if is_open(conn, cuts, h)? || is_open(conn, cuts, previous)? {
+                island.on_boundary[vertex] = true;
+            }
+            island.corner_vertices.push(vertex);
+        }
+        island.corners = corners;
+
+        let mut i = 0;
+        for &face in faces {
+            let len = conn.face_edges(face).len();
+            let vertices = &island.corner_vertices[i..i + len];
+            for pair in vertices[1..].windows(2) {
+                island.triangles.push([vertices[0], pair[0], pair[1]]);
+            }
+            i += len;
+        }
+        Ok(island)
+    }
+
+    fn area(&self) -> f32 {
+        self.triangles
+            .iter()
+            .map(|[a, b, c]| {
+                let (a, b, c) = (self.positions[*a], self.positions[*b], self.positions[*c]);
+                (b - a).cross(c - a).length() * 0.5
+            })
+            .sum()
+    }
+}
+
+/// Lays out a triangle in its own plane, with its first vertex at the origin
+/// and its first edge along the x axis. Returns `None` for degenerate
+/// triangles.
+fn triangle_shape(points: [Vec3; 3]) -> Option<[DVec2; 3]> {
+    let (e1, e2) = (
+        (points[1] - points[0]).as_dvec3(),
+        (points[2] - points[0]).as_dvec3(),
+    );
+    let normal = e1.cross(e2);
+    if normal.length() <= DEGENERATE_AREA * e1.length_squared() || e1.length() <= 1e-12 {
+        return None;
+    }
+    let x = e1.normalize();
+    let y = normal.normalize().cross(x);
+    Some([
+        DVec2::ZERO,
+        DVec2::new(e1.length(), 0.0),
+        DVec2::new(e2.dot(x), e2.dot(y)),
+    ])
+}
+
+/// The angle at each corner of a triangle laid out by [`triangle_shape`].
+fn shape_angles(shape: &[DVec2; 3]) -> [f64; 3] {
+    let angle = |k: usize| {
+        let (a, b, c) = (shape[k], shape[(k + 1) % 3], shape[(k + 2) % 3]);
+        (b - a).angle_between(c - a).abs()
+    };
+    [angle(0), angle(1), angle(2)]
+}
+
+/// Lays out a triangle with the given `angles`, like [`triangle_shape`], with
+/// a first edge of the given `length`.
+fn shape_from_angles(angles: [f64; 3], length: f64) -> [DVec2; 3] {
+    // The law of sines gives the length of the edge from the first vertex to
+    // the third one
+    let side = length * angles[1].sin() / angles[2].sin();
+    [
+        DVec2::ZERO,
+        DVec2::new(length, 0.0),
+        side * DVec2::new(angles[0].cos(), angles[0].sin()),
+    ]
+}
+
+/// Adjusts the angles of the shapes, for [`UnwrapMethod::Abf`].
+fn flatten_angles(island: &IslandMesh, shapes: &mut [([usize; 3], [DVec2; 3])]) {
+    let mut angles = shapes
+        .iter()
+        .map(|(_, shape)| shape_angles(shape))
+        .collect_vec();
+    for _ in 0..ABF_ROUNDS {
+        let mut sums = vec![0.0; island.positions.len()];
+        for ((vertices, _), angles) in shapes.iter().zip(&angles) {
+            for (v, angle) in vertices.iter().zip(angles) {
+                sums[*v] += angle;
+            }
+        }
+        for ((vertices, _), angles) in shapes.iter().zip(&mut angles) {
+            for (v, angle) in vertices.iter().zip(angles.iter_mut()) {
+                if !island.on_boundary[*v] && sums[*v] > 0.0 {
+                    *angle *= 2.0 * PI / sums[*v];
+                }
+            }
+            let sum: f64 = angles.iter().sum();
+            for angle in angles.iter_mut() {
+                *angle *= PI / sum;
+            }
+        }
+    }
+    for ((_, shape), angles) in shapes.iter_mut().zip(angles) {
+        if angles.iter().all(|angle| *angle > 1e-6) {
+            *shape = shape_from_angles(angles, shape[1].x);
+        }
+    }
+}
+
+/// A sparse matrix, stored as the non-zero entries of each row.
+struct SparseRows {
+    rows: Vec<Vec<(usize, f64)>>,
+    columns: usize,
+}
+
+impl SparseRows {
+    fn apply(&self, x: &[f64]) -> Vec<f64> {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(|(j, value)| value * x[*j]).sum())
+            .collect()
+    }
+
+    fn apply_transposed(&self, y: &[f64]) -> Vec<f64> {
+        let mut x = vec![0.0; self.columns];
+        for (row, y) in self.rows.iter().zip(y) {
+            for (j, value) in row {
+                x[*j] += value * y;
+            }
+        }
+        x
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Finds the `x` that minimizes `|a x - b|`, by running conjugate gradients
+/// on the normal equations, `aᵀ a x = aᵀ b`, without building `aᵀ a`.
+fn solve_least_squares(a: &SparseRows, b: &[f64]) -> Vec<f64> {
+    let max_iterations = (4 * a.columns).max(1000);
+    let mut x = vec![0.0; a.columns];
+    let mut r = b.to_vec();
+    let mut s = a.apply_transposed(&r);
+    let mut p = s.clone();
+    let mut gamma = dot(&s, &s);
+    let tolerance = gamma * 1e-24;
+    for _ in 0..max_iterations {
+        if gamma <= tolerance {
+            break;
+        }
+        let q = a.apply(&p);
+        let q_squared = dot(&q, &q);
+        if q_squared <= 0.0 {
+            break;
+        }
+        let alpha = gamma / q_squared;
+        for (x, p) in x.iter_mut().zip(&p) {
+            *x += alpha * p;
+        }
+        for (r, q) in r.iter_mut().zip(&q) {
+            *r -= alpha * q;
+        }
+        s = a.apply_transposed(&r);
+        let new_gamma = dot(&s, &s);
+        let beta = new_gamma / gamma;
+        for (p, s) in p.iter_mut().zip(&s) {
+            *p = s + beta * *p;
+        }
+        gamma = new_gamma;
+    }
+    x
+}
+
+/// Returns the index of the vertex in `among` farthest from `from`.
+fn farthest(positions: &[Vec3], among: &[usize], from: Vec3) -> usize {
+    among
+        .iter()
+        .copied()
+        .max_by(|a, b| {
+            let (a, b) = (positions[*a].distance(from), positions[*b].distance(from));
+            a.total_cmp(&b)
+        })
+        .unwrap_or(0)
+}
+
+/// Flattens the island with least squares conformal maps, from the shape of
+/// its triangles in 3d or, for [`UnwrapMethod::Abf`], from their adjusted
+/// angles. Returns the uv of each vertex of the island, or `None` when the
+/// island is degenerate and can't be flattened.
+fn flatten(island: &IslandMesh, method: UnwrapMethod) -> Option<Vec<Vec2>> {
+    let n = island.positions.len();
+    let mut shapes = island
+        .triangles
+        .iter()
+        .filter_map(|vertices| {
+            let points = vertices.map(|v| island.positions[v]);
+            triangle_shape(points).map(|shape| (*vertices, shape))
+        })
+        .collect_vec();
+    if shapes.is_empty() {
+        return None;
+    }
+    if method == UnwrapMethod::Abf {
+        flatten_angles(island, &mut shapes);
+    }
+
+    // Pins the two boundary vertices farthest apart, at the same distance
+    // they are in 3d
+    let boundary = (0..n).filter(|v| island.on_boundary[*v]).collect_vec();
+    let among = if boundary.is_empty() {
+        (0..n).collect_vec()
+    } else {
+        boundary
+    };
+    let a = farthest(&island.positions, &among, island.positions[among[0]]);
+    let b = farthest(&island.positions, &among, island.positions[a]);
+    let distance = island.positions[a].distance(island.positions[b]) as f64;
+    if distance <= 1e-9 {
+        return None;
+    }
+    // The unknowns are the u of every vertex, then the v of every vertex.
+    // Those of the pinned vertices move to the right hand side.
+    let pinned = HashMap::from([(a, 0.0), (b, distance), (n + a, 0.0), (n + b, 0.0)]);
+    let mut free_columns = vec![None; 2 * n];
+    let mut columns = 0;
+    for (unknown, column) in free_columns.iter_mut().enumerate() {
+        if !pinned.contains_key(&unknown) {
+            *column = Some(columns);
+            columns += 1;
+        }
+    }
+    let (mut rows, mut rhs) = (vec![], vec![]);
+    for (vertices, shape) in &shapes {
+        let area = (shape[1] - shape[0]).perp_dot(shape[2] - shape[0]).abs() * 0.5;
+        let weight = 1.0 / area.sqrt();
+        // The map is conformal in the triangle when the sum of the uvs,
+        // weighted by these complex numbers, is zero
+        let w = [
+            shape[2] - shape[1],
+            shape[0] - shape[2],
+            shape[1] - shape[0],
+        ];
+        for imaginary in [false, true] {
+            let (mut row, mut value) = (vec![], 0.0);
+            for (vertex, w) in vertices.iter().zip(w) {
+                let (u, v) = if imaginary { (w.y, w.x) } else { (w.x, -w.y) };
+                for (unknown, coefficient) in [(*vertex, u), (n + vertex, v)] {
+                    match free_columns[unknown] {
+                        Some(j) => row.push((j, coefficient * weight)),
+                        None => value -= coefficient * weight * pinned[&unknown],
+                    }
+                }
+            }
+            rows.push(row);
+            rhs.push(value);
+        }
+    }
+    let x = solve_least_squares(&SparseRows { rows, columns }, &rhs);
+    let value = |unknown: usize| match free_columns[unknown] {
+        Some(j) => x[j],
+        None => pinned[&unknown],
+    };
+    let uvs = (0..n)
+        .map(|v| DVec2::new(value(v), value(n + v)))
+        .collect_vec();
+
+    // A degenerate result folds the island onto a line or a point
+    let uv_area: f64 = shapes
+        .iter()
+        .map(|([a, b, c], _)| (uvs[*b] - uvs[*a]).perp_dot(uvs[*c] - uvs[*a]) * 0.5)
+        .sum();
+    let area = island.area() as f64;
+    if !uvs.iter().all(|uv| uv.is_finite()) || uv_area <= area * 1e-6 {
+        return None;
+    }
+    Some(uvs.iter().map(|uv| uv.as_vec2()).collect())
+}
+
+/// Projects the island onto the plane facing its average normal.
+fn planar_projection(island: &IslandMesh) -> Vec<Vec2> {
+    let normal = island
+        .triangles
+        .iter()
+        .map(|vertices| polygon_normal(&vertices.map(|v| island.positions[v])))
+        .sum::<Vec3>()
+        .try_normalize()
+        .unwrap_or(Vec3::Z);
+    let x = if normal.x.abs() < 0.9 {
+        Vec3::X
+    } else {
+        Vec3::Y
+    }
+    .cross(normal)
+    .normalize();
+    let y = normal.cross(x);
+    island
+        .positions
+        .iter()
+        .map(|p| Vec2::new(p.dot(x), p.dot(y)))
+        .collect()
+}
+
+/// Unwraps the mesh into a new `uv` channel. The mesh is cut into islands
+/// along the `seams`, when given, and along the edges where faces meet at an
+/// angle sharper than `auto_seam_angle_deg`, when given. Each island is then
+/// cut some more, if needed, to make it a topological disk, and flattened
+/// with the given `method`.
+///
+/// The cuts only separate the uvs at both sides of the edges, and don't
+/// change the mesh. The islands keep roughly the size they have in 3d. They
+/// are laid out next to each other without overlapping, but the layout is
+/// not meant to be final: That's what [`super::pack_uv_islands`] is for.
+/// Islands that can't be flattened, because their faces are degenerate, are
+/// projected onto a plane instead, and a warning is reported.
+pub fn unwrap_uvs(
+    mesh: &mut HalfEdgeMesh,
+    seams: Option<&SelectionExpression>,
+    auto_seam_angle_deg: Option<f32>,
+    method: UnwrapMethod,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<()> {
+    let seams = match seams {
+        Some(seams) => mesh.resolve_halfedge_selection_full(seams)?,
+        None => vec![],
+    };
+    let mut uvs = Channel::<HalfEdgeId, Vec3>::new();
+    let mut failed = 0;
+    let num_islands = {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+
+        let mut cuts = HashSet::new();
+        for h in seams {
+            cuts.insert(h);
+            cuts.insert(conn.at_halfedge(h).twin().try_end()?);
+        }
+        if let Some(angle) = auto_seam_angle_deg {
+            let max_angle = angle.to_radians();
+            let normals = conn
+                .iter_faces()
+                .map(|(face, _)| {
+                    let points = conn
+                        .face_vertices(face)
+                        .iter()
+                        .map(|v| positions[*v])
+                        .collect_vec();
+                    (face, polygon_normal(&points))
+                })
+                .collect::<HashMap<_, _>>();
+            for (h, _) in conn.iter_halfedges() {
+                let a = conn.at_halfedge(h).face_or_boundary()?;
+                let b = conn.at_halfedge(h).twin().face_or_boundary()?;
+                if let (Some(a), Some(b)) = (a, b) {
+                    if normals[&a].angle_between(normals[&b]) > max_angle {
+                        cuts.insert(h);
+                    }
+                }
+            }
+        }
+
+        let islands = cut_islands(&conn, &cuts)?;
+        let mut offset = 0.0;
+        for faces in &islands {
+            complete_cuts(&conn, faces, &mut cuts)?;
+            let island = IslandMesh::new(&conn, &positions, faces, &cuts)?;
+            let island_uvs = match flatten(&island, method) {
+                Some(island_uvs) => island_uvs,
+                None => {
+                    failed += 1;
+                    planar_projection(&island)
+                }
+            };
+
+            // Places the island to the right of the previous one
+            let (min, max) = island_uvs.iter().fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(min, max), uv| (min.min(*uv), max.max(*uv)),
+            );
+            let shift = Vec2::new(offset, 0.0) - min;
+            offset += (max.x - min.x) + (max - min).max_element().max(1e-3) * 0.1;
+            for (h, vertex) in island.corners.iter().zip(&island.corner_vertices) {
+                uvs[*h] = (island_uvs[*vertex] + shift).extend(0.0);
+            }
+        }
+        islands.len()
+    };
+
+    if failed > 0 {
+        report_warning(
+            sink,
+            Warning::new(format!(
+                "{failed} of the {num_islands} uv islands couldn't be flattened, because their \
+                 faces are degenerate, so they were projected onto a plane instead"
+            )),
+        );
+    }
+    let uvs_ch_id = mesh.channels.replace_or_create_channel("uv", uvs);
+    mesh.default_channels.uvs = Some(uvs_ch_id);
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Unwraps `mesh` into its `uv` channel. The mesh is cut along the `seams`
+    /// edges, and, when `auto_seam_angle` is given, along the edges where
+    /// faces meet at a sharper angle, in degrees. The resulting islands are
+    /// flattened with the `method`, either `"LSCM"` or `"ABF"`, and left
+    /// unpacked.
+    #[lua(under = "Ops")]
+    pub fn unwrap(
+        mesh: &mut HalfEdgeMesh,
+        seams: Option<SelectionExpression>,
+        auto_seam_angle: Option<f32>,
+        method: String,
+    ) -> Result<()> {
+        let method = match method.as_str() {
+            "LSCM" => UnwrapMethod::Lscm,
+            "ABF" => UnwrapMethod::Abf,
+            _ => bail!("Invalid unwrap method '{method}'"),
+        };
+        let sink = crate::progress::current_sink();
+        super::unwrap_uvs(
+            mesh,
+            seams.as_ref(),
+            auto_seam_angle,
+            method,
+            sink.as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::analysis::uv_islands;
+    use crate::mesh::halfedge::selection::SelectionFragment;
+    use crate::progress::MockSink;
+
+    /// An open tube of quads of radius 1 and height 2 around the y axis.
+    fn tube(segments: usize, rings: usize) -> HalfEdgeMesh {
+        let mut positions = vec![];
+        for ring in 0..=rings {
+            let y = 2.0 * ring as f32 / rings as f32;
+            for segment in 0..segments {
+                let angle = std::f32::consts::TAU * segment as f32 / segments as f32;
+                positions.push(Vec3::new(angle.cos(), y, angle.sin()));
+            }
+        }
+        let quads = (0..rings)
+            .flat_map(|ring| {
+                (0..segments).map(move |segment| {
+                    let next = (segment + 1) % segments;
+                    let (bottom, top) = (ring * segments, (ring + 1) * segments);
+                    [bottom + segment, top + segment, top + next, bottom + next]
+                })
+            })
+            .collect_vec();
+        HalfEdgeMesh::build_from_polygons(&positions, &quads).unwrap()
+    }
+
+    /// Selects the edges of the tube at the vertical line where x = 1.
+    fn tube_seam(mesh: &HalfEdgeMesh) -> SelectionExpression {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let on_seam =
+            |v: VertexId| (positions[v] - Vec3::new(1.0, positions[v].y, 0.0)).length() < 1e-4;
+        let fragments = conn
+            .iter_halfedges()
+            .enumerate()
+            .filter(|(_, (h, _))| {
+                let (src, dst) = conn.at_halfedge(*h).src_dst_pair().unwrap();
+                on_seam(src) && on_seam(dst)
+            })
+            .map(|(i, _)| SelectionFragment::Single(i as u32))
+            .collect_vec();
+        SelectionExpression::Explicit(fragments)
+    }
+
+    /// The mean difference between the angles of the corners of the faces in
+    /// 3d and in uv space, in radians.
+    fn angle_distortion(mesh: &HalfEdgeMesh) -> f32 {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let uvs = mesh.read_uvs().unwrap();
+        let mut errors = vec![];
+        for (face, _) in conn.iter_faces() {
+            let edges = conn.face_edges(face);
+            let len = edges.len();
+            for i in 0..len {
+                let [a, b, c] = [edges[(i + len - 1) % len], edges[i], edges[(i + 1) % len]];
+                let vertex = |h| positions[conn.at_halfedge(h).src_vertex().end()];
+                let angle_3d = (vertex(a) - vertex(b)).angle_between(vertex(c) - vertex(b));
+                let (uv_a, uv_b, uv_c) = (uvs[a].truncate(), uvs[b].truncate(), uvs[c].truncate());
+                let angle_uv = (uv_a - uv_b).angle_between(uv_c - uv_b).abs();
+                errors.push((angle_3d - angle_uv).abs());
+            }
+        }
+        errors.iter().sum::<f32>() / errors.len() as f32
+    }
+
+    #[test]
+    fn test_unwrap_tube_along_seam() {
+        for method in [UnwrapMethod::Lscm, UnwrapMethod::Abf] {
+            let mut mesh = tube(16, 4);
+            let seam = tube_seam(&mesh);
+            let sink = MockSink::new(None);
+            unwrap_uvs(&mut mesh, Some(&seam), None, method, Some(&sink)).unwrap();
+
+            let islands = uv_islands(&mesh).unwrap();
+            assert_eq!(islands.len(), 1, "{method:?}");
+            assert_eq!(islands[0].polygons.len(), 16 * 4);
+            let distortion = angle_distortion(&mesh);
+            assert!(distortion < 0.01, "{method:?}: {distortion}");
+            assert!(sink.warnings.borrow().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unwrap_cuts_tube_without_seams() {
+        // The tube is cut from one boundary to the other to flatten it
+        let mut mesh = tube(16, 4);
+        unwrap_uvs(&mut mesh, None, None, UnwrapMethod::Lscm, None).unwrap();
+        assert_eq!(uv_islands(&mesh).unwrap().len(), 1);
+        assert!(angle_distortion(&mesh) < 0.01);
+    }
+
+    #[test]
+    fn test_unwrap_cube_auto_seams() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        unwrap_uvs(&mut cube, None, Some(45.0), UnwrapMethod::Lscm, None).unwrap();
+        let islands = uv_islands(&cube).unwrap();
+        assert_eq!(islands.len(), 6);
+        assert!(angle_distortion(&cube) < 1e-3);
+        // The islands keep their size, and don't overlap
+        let bounds = islands
+            .iter()
+            .map(|island| {
+                let uvs = island.polygons[0].uvs.iter();
+                let min = uvs
+                    .clone()
+                    .fold(Vec2::splat(f32::INFINITY), |a, b| a.min(*b));
+                let max = uvs.fold(Vec2::splat(f32::NEG_INFINITY), |a, b| a.max(*b));
+                (min, max)
+            })
+            .sorted_by(|a, b| a.0.x.total_cmp(&b.0.x))
+            .collect_vec();
+        for (min, max) in &bounds {
+            assert!(((*max - *min).max_element() - 1.0).abs() < 1e-3);
+        }
+        for pair in bounds.windows(2) {
+            assert!(pair[0].1.x < pair[1].0.x);
+        }
+    }
+
+    #[test]
+    fn test_unwrap_closed_mesh() {
+        // Without seams, a closed mesh is cut open to give it a boundary
+        let mut sphere = primitives::UVSphere::build(Vec3::ZERO, 16, 8, 1.0).unwrap();
+        let sink = MockSink::new(None);
+        unwrap_uvs(&mut sphere, None, None, UnwrapMethod::Lscm, Some(&sink)).unwrap();
+        assert_eq!(uv_islands(&sphere).unwrap().len(), 1);
+        assert!(sink.warnings.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_unwrap_degenerate_island() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::X * 2.0];
+        let mut mesh = HalfEdgeMesh::build_from_polygons(&positions, &[[0, 1, 2]]).unwrap();
+        let sink = MockSink::new(None);
+        unwrap_uvs(&mut mesh, None, None, UnwrapMethod::Lscm, Some(&sink)).unwrap();
+        assert_eq!(sink.warnings.borrow().len(), 1);
+        assert!(mesh.read_uvs().is_some());
+    }
+}
@@ -0,0 +1,734 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::Write;
+
+use crate::prelude::*;
+
+/// A channel storing values for elements that are no longer part of the mesh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleChannelEntries {
+    pub key_type: ChannelKeyType,
+    pub value_type: ChannelValueType,
+    pub name: String,
+    pub keys: Vec<slotmap::KeyData>,
+}
+
+/// The result of checking the invariants of a [`HalfEdgeMesh`] using
+/// [`validate`]. Every field lists the ids of the elements breaking a certain
+/// invariant.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Halfedges without a twin, with a twin that doesn't exist, or with a
+    /// twin whose twin is not the halfedge itself.
+    pub bad_twins: Vec<HalfEdgeId>,
+    /// Halfedges whose `next` pointers don't form a closed cycle.
+    pub broken_cycles: Vec<HalfEdgeId>,
+    /// Halfedges in a cycle where not all halfedges point to the same face.
+    pub face_mismatches: Vec<HalfEdgeId>,
+    /// Halfedges without a source vertex, or pointing to a vertex that
+    /// doesn't exist.
+    pub orphan_halfedges: Vec<HalfEdgeId>,
+    /// Halfedges pointing to a face that doesn't exist.
+    pub dangling_faces: Vec<HalfEdgeId>,
+    /// Vertices whose halfedge pointer is not one of their outgoing
+    /// halfedges. This includes vertices without a halfedge that are the
+    /// source of some halfedge.
+    pub bad_vertex_halfedges: Vec<VertexId>,
+    /// Faces whose halfedge pointer is missing or doesn't point back to them.
+    pub bad_face_halfedges: Vec<FaceId>,
+    /// Pairs of halfedges sharing the same source and destination vertices.
+    pub duplicate_edges: Vec<(HalfEdgeId, HalfEdgeId)>,
+    /// Faces that visit the same vertex more than once, or that have less
+    /// than three sides.
+    pub degenerate_faces: Vec<FaceId>,
+    /// Channels with values for elements that were since removed from the
+    /// mesh. This does not make a mesh invalid, but wastes memory.
+    pub stale_channel_entries: Vec<StaleChannelEntries>,
+}
+
+/// Walks the `next` pointers starting at `h0`. Unlike the regular traversals,
+/// this never panics or loops forever. Returns None if the pointers don't form
+/// a closed cycle.
+fn safe_halfedge_cycle(conn: &MeshConnectivity, h0: HalfEdgeId) -> Option<SVec<HalfEdgeId>> {
+    let mut cycle = SVec::new();
+    let mut h = h0;
+    loop {
+        if cycle.len() > MAX_LOOP_ITERATIONS {
+            return None;
+        }
+        cycle.push(h);
+        h = conn.halfedge(h)?.next?;
+        if h == h0 {
+            return Some(cycle);
+        }
+    }
+}
+
+/// Checks the halfedge invariants of `mesh` and returns a report with all the
+/// elements violating them. This function never panics, even on badly
+/// malformed meshes.
+pub fn validate(mesh: &HalfEdgeMesh) -> ValidationReport {
+    let conn = mesh.read_connectivity();
+    let mut report = ValidationReport::default();
+
+    let mut edges_seen = HashMap::<(VertexId, VertexId), HalfEdgeId>::new();
+    let mut vertex_sources = HashSet::<VertexId>::new();
+
+    for (h, halfedge) in conn.halfedges.iter() {
+        match halfedge.twin.and_then(|tw| conn.halfedge(tw)) {
+            Some(twin) if twin.twin == Some(h) => {}
+            _ => report.bad_twins.push(h),
+        }
+
+        match halfedge.vertex {
+            Some(v) if conn.vertex(v).is_some() => {
+                vertex_sources.insert(v);
+            }
+            _ => report.orphan_halfedges.push(h),
+        }
+
+        if let Some(f) = halfedge.face {
+            if conn.face(f).is_none() {
+                report.dangling_faces.push(h);
+            }
+        }
+
+        match safe_halfedge_cycle(&conn, h) {
+            Some(cycle) => {
+                if cycle.iter().any(|h2| conn[*h2].face != halfedge.face) {
+                    report.face_mismatches.push(h);
+                }
+            }
+            None => report.broken_cycles.push(h),
+        }
+
+        let dst = halfedge
+            .twin
+            .and_then(|tw| conn.halfedge(tw))
+            .and_then(|tw| tw.vertex);
+        if let (Some(src), Some(dst)) = (halfedge.vertex, dst) {
+            if let Some(other) = edges_seen.insert((src, dst), h) {
+                report.duplicate_edges.push((other, h));
+            }
+        }
+    }
+
+    for (v, vertex) in conn.vertices.iter() {
+        let points_back = |h: HalfEdgeId| conn.halfedge(h).map_or(false, |h| h.vertex == Some(v));
+        match vertex.halfedge {
+            Some(h) if points_back(h) => {}
+            None if !vertex_sources.contains(&v) => {}
+            _ => report.bad_vertex_halfedges.push(v),
+        }
+    }
+
+    for (f, face) in conn.faces.iter() {
+        let h = match face.halfedge {
+            Some(h) if conn.halfedge(h).map_or(false, |h| h.face == Some(f)) => h,
+            _ => {
+                report.bad_face_halfedges.push(f);
+                continue;
+            }
+        };
+        if let Some(cycle) = safe_halfedge_cycle(&conn, h) {
+            let has_repeated = cycle
+                .iter()
+                .filter_map(|h| conn[*h].vertex)
+                .duplicates()
+                .next()
+                .is_some();
+            if cycle.len() < 3 || has_repeated {
+                report.degenerate_faces.push(f);
+            }
+        }
+    }
+
+    for (key_type, value_type, name) in mesh.channels.iter_channels_dyn() {
+        let ch = match mesh
+            .channels
+            .dyn_read_channel_by_name(key_type, value_type, name)
+        {
+            Ok(ch) => ch,
+            Err(_) => continue,
+        };
+        let keys = ch
+            .stored_keys()
+            .into_iter()
+            .filter(|k| !element_exists(&conn, key_type, *k))
+            .collect_vec();
+        if !keys.is_empty() {
+            report.stale_channel_entries.push(StaleChannelEntries {
+                key_type,
+                value_type,
+                name: name.to_string(),
+                keys,
+            });
+        }
+    }
+
+    report
+}
+
+fn element_exists(conn: &MeshConnectivity, key_type: ChannelKeyType, k: slotmap::KeyData) -> bool {
+    match key_type {
+        ChannelKeyType::VertexId => conn.vertices.contains_key(VertexId::from(k)),
+        ChannelKeyType::FaceId => conn.faces.contains_key(FaceId::from(k)),
+        ChannelKeyType::HalfEdgeId => conn.halfedges.contains_key(HalfEdgeId::from(k)),
+    }
+}
+
+/// Fixes the problems listed in `report` that can be fixed without making
+/// guesses about the intended shape of the mesh:
+/// - Halfedges without a vertex are removed, and pointers to them cleared.
+/// - Pointers to removed twins, next halfedges or faces are cleared.
+/// - Degenerate faces are removed, leaving a hole in their place.
+/// - Vertex and face halfedge pointers are redirected to a valid halfedge,
+///   and faces no halfedge points to are removed.
+/// - Stale channel entries are removed.
+///
+/// Broken cycles, mismatched twins and duplicate edges are left alone. Run
+/// [`validate`] again after repairing to see what's left.
+pub fn repair(mesh: &HalfEdgeMesh, report: &ValidationReport) -> Result<()> {
+    let mut conn = mesh.write_connectivity();
+
+    // Orphan halfedges go first, so the checks below don't see them.
+    for h in report.orphan_halfedges.iter_cpy() {
+        conn.remove_halfedge(h);
+    }
+    let halfedge_ids = conn.halfedges.keys().collect_vec();
+    for h in halfedge_ids.iter_cpy() {
+        let HalfEdge {
+            twin, next, face, ..
+        } = conn[h].clone();
+        if twin.map_or(false, |tw| conn.halfedge(tw).is_none()) {
+            conn[h].twin = None;
+        }
+        if next.map_or(false, |nxt| conn.halfedge(nxt).is_none()) {
+            conn[h].next = None;
+        }
+        if face.map_or(false, |f| conn.face(f).is_none()) {
+            conn[h].face = None;
+        }
+    }
+
+    for f in report.degenerate_faces.iter_cpy() {
+        if conn.face(f).is_none() {
+            continue;
+        }
+        for h in halfedge_ids.iter_cpy() {
+            if conn[h].face == Some(f) {
+                conn[h].face = None;
+            }
+        }
+        conn.remove_face(f);
+    }
+
+    // Redirect vertex and face pointers. Scanning the halfedges once for all
+    // the bad elements is cheaper than searching for each one of them.
+    let mut vertex_outgoing = HashMap::<VertexId, HalfEdgeId>::new();
+    let mut face_halfedge = HashMap::<FaceId, HalfEdgeId>::new();
+    for (h, halfedge) in conn.halfedges.iter() {
+        if let Some(v) = halfedge.vertex {
+            vertex_outgoing.entry(v).or_insert(h);
+        }
+        if let Some(f) = halfedge.face {
+            face_halfedge.entry(f).or_insert(h);
+        }
+    }
+    for v in report.bad_vertex_halfedges.iter_cpy() {
+        if let Some(vertex) = conn.vertex_mut(v) {
+            vertex.halfedge = vertex_outgoing.get(&v).copied();
+        }
+    }
+    let face_ids = conn.faces.keys().collect_vec();
+    for f in face_ids {
+        let points_back = conn[f]
+            .halfedge
+            .and_then(|h| conn.halfedge(h))
+            .map_or(false, |h| h.face == Some(f));
+        if !points_back {
+            match face_halfedge.get(&f) {
+                Some(h) => conn[f].halfedge = Some(*h),
+                None => conn.remove_face(f),
+            }
+        }
+    }
+
+    // Channels are cleaned last, so elements removed above are pruned too.
+    prune_channels(mesh, &conn)
+}
+
+/// Removes the channel values of elements that are no longer part of `mesh`.
+/// Ops that remove elements call this so that the channels only store values
+/// for the elements that are left.
+pub fn remove_stale_channel_entries(mesh: &HalfEdgeMesh) -> Result<()> {
+    prune_channels(mesh, &mesh.read_connectivity())
+}
+
+/// Makes every channel of `mesh` store exactly one value for each element of
+/// its key type: Elements without a value get the default value of the
+/// channel, and the values of removed elements are dropped. The graph
+/// interpreter calls this on the output meshes of every node, so ops adding
+/// elements only need to give a value to the ones that shouldn't have the
+/// default.
+pub fn sync_channels(mesh: &HalfEdgeMesh) -> Result<()> {
+    let conn = mesh.read_connectivity();
+    prune_channels(mesh, &conn)?;
+    let keys = element_keys(&conn);
+    for (key_type, value_type, name) in mesh.channels.iter_channels_dyn() {
+        mesh.channels
+            .dyn_write_channel_by_name(key_type, value_type, name)?
+            .backfill_dyn(&keys[&key_type]);
+    }
+    Ok(())
+}
+
+/// Returns whether every channel of `mesh` stores exactly one value for each
+/// element of its key type, as left by [`sync_channels`].
+pub fn channels_in_sync(mesh: &HalfEdgeMesh) -> bool {
+    let conn = mesh.read_connectivity();
+    let keys = element_keys(&conn);
+    mesh.channels
+        .iter_channels_dyn()
+        .all(|(key_type, value_type, name)| {
+            match mesh
+                .channels
+                .dyn_read_channel_by_name(key_type, value_type, name)
+            {
+                Ok(ch) => {
+                    ch.stored_len() == keys[&key_type].len()
+                        && ch
+                            .stored_keys()
+                            .into_iter()
+                            .all(|k| element_exists(&conn, key_type, k))
+                }
+                Err(_) => false,
+            }
+        })
+}
+
+fn element_keys(conn: &MeshConnectivity) -> HashMap<ChannelKeyType, Vec<slotmap::KeyData>> {
+    use slotmap::Key;
+    [
+        (
+            ChannelKeyType::VertexId,
+            conn.iter_vertices().map(|(v, _)| v.data()).collect(),
+        ),
+        (
+            ChannelKeyType::FaceId,
+            conn.iter_faces().map(|(f, _)| f.data()).collect(),
+        ),
+        (
+            ChannelKeyType::HalfEdgeId,
+            conn.iter_halfedges().map(|(h, _)| h.data()).collect(),
+        ),
+    ]
+    .into_iter()
+    .collect()
+}
+
+fn prune_channels(mesh: &HalfEdgeMesh, conn: &MeshConnectivity) -> Result<()> {
+    for (key_type, value_type, name) in mesh.channels.iter_channels_dyn() {
+        let mut ch = mesh
+            .channels
+            .dyn_write_channel_by_name(key_type, value_type, name)?;
+        for k in ch.stored_keys() {
+            if !element_exists(conn, key_type, k) {
+                ch.remove_stored(k);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    #[lua_impl]
+    impl ValidationReport {
+        /// Returns true when no invariants are broken. Stale channel entries are
+        /// not considered an error.
+        #[lua]
+        pub fn is_valid(&self) -> bool {
+            self.bad_twins.is_empty()
+                && self.broken_cycles.is_empty()
+                && self.face_mismatches.is_empty()
+                && self.orphan_halfedges.is_empty()
+                && self.dangling_faces.is_empty()
+                && self.bad_vertex_halfedges.is_empty()
+                && self.bad_face_halfedges.is_empty()
+                && self.duplicate_edges.is_empty()
+                && self.degenerate_faces.is_empty()
+        }
+
+        /// Returns a human-readable description of the problems in this report.
+        #[lua]
+        pub fn summary(&self) -> String {
+            if self.is_valid() && self.stale_channel_entries.is_empty() {
+                return "The mesh is valid".into();
+            }
+
+            let mut out = String::new();
+            macro_rules! describe {
+                ($field:ident, $msg:expr) => {
+                    if !self.$field.is_empty() {
+                        let _ = writeln!(out, "{} {}: {:?}", self.$field.len(), $msg, self.$field);
+                    }
+                };
+            }
+            describe!(bad_twins, "halfedges with a bad twin");
+            describe!(broken_cycles, "halfedges not in a closed cycle");
+            describe!(face_mismatches, "halfedges with a mismatched face");
+            describe!(orphan_halfedges, "halfedges without a vertex");
+            describe!(dangling_faces, "halfedges pointing to a removed face");
+            describe!(bad_vertex_halfedges, "vertices with a bad halfedge");
+            describe!(bad_face_halfedges, "faces with a bad halfedge");
+            describe!(duplicate_edges, "duplicate edges");
+            describe!(degenerate_faces, "degenerate faces");
+            for stale in &self.stale_channel_entries {
+                let _ = writeln!(
+                    out,
+                    "{} stale entries in channel '{}' ({:?} -> {:?})",
+                    stale.keys.len(),
+                    stale.name,
+                    stale.key_type,
+                    stale.value_type
+                );
+            }
+            out
+        }
+
+        /// Adds debug marks to `mesh` for all the elements in this report, so
+        /// they can be highlighted in the viewport.
+        #[lua]
+        pub fn add_debug_marks(&self, mesh: &HalfEdgeMesh) {
+            let mut conn = mesh.write_connectivity();
+            let mut mark_h = |h: HalfEdgeId, label: &str| {
+                if conn.halfedge(h).is_some() {
+                    conn.add_debug_halfedge(h, DebugMark::red(label));
+                }
+            };
+            for h in self.bad_twins.iter_cpy() {
+                mark_h(h, "bad twin");
+            }
+            for h in self.broken_cycles.iter_cpy() {
+                mark_h(h, "broken cycle");
+            }
+            for h in self.face_mismatches.iter_cpy() {
+                mark_h(h, "face mismatch");
+            }
+            for h in self.dangling_faces.iter_cpy() {
+                mark_h(h, "dangling face");
+            }
+            for (a, b) in self.duplicate_edges.iter_cpy() {
+                mark_h(a, "duplicate");
+                mark_h(b, "duplicate");
+            }
+            for v in self.bad_vertex_halfedges.iter_cpy() {
+                if conn.vertex(v).is_some() {
+                    conn.add_debug_vertex(v, DebugMark::red("bad halfedge"));
+                }
+            }
+            for f in self.degenerate_faces.iter_cpy() {
+                if let Some(h) = conn.face(f).and_then(|face| face.halfedge) {
+                    for h in safe_halfedge_cycle(&conn, h).unwrap_or_default() {
+                        conn.add_debug_halfedge(h, DebugMark::purple("degenerate"));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn quad_mesh() -> HalfEdgeMesh {
+        HalfEdgeMesh::build_from_polygons(
+            &[
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 1.0),
+            ],
+            &[[0, 1, 2, 3], [1, 4, 5, 2]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_valid_meshes() {
+        let report = validate(&quad_mesh());
+        assert!(report.is_valid(), "{}", report.summary());
+
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert_eq!(validate(&cube), ValidationReport::default());
+    }
+
+    #[test]
+    fn test_bad_vertex_pointer() {
+        let mesh = quad_mesh();
+        let v = {
+            let mut conn = mesh.write_connectivity();
+            let (v, _) = conn.iter_vertices().next().unwrap();
+            // Point the vertex to an incoming halfedge instead.
+            let incoming = conn.at_vertex(v).incoming_halfedges().unwrap()[0];
+            conn[v].halfedge = Some(incoming);
+            v
+        };
+
+        let report = validate(&mesh);
+        assert_eq!(report.bad_vertex_halfedges, vec![v]);
+
+        repair(&mesh, &report).unwrap();
+        assert!(validate(&mesh).is_valid());
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.at_vertex(v).halfedge().vertex().try_end().unwrap(), v);
+    }
+
+    #[test]
+    fn test_bad_twin_and_broken_cycle() {
+        let mesh = quad_mesh();
+        let (h, tw) = {
+            let mut conn = mesh.write_connectivity();
+            let (h, _) = conn.iter_halfedges().next().unwrap();
+            let tw = conn.at_halfedge(h).twin().try_end().unwrap();
+            conn[tw].twin = None;
+            conn[h].next = None;
+            (h, tw)
+        };
+
+        let report = validate(&mesh);
+        assert!(!report.is_valid());
+        assert!(report.bad_twins.contains(&h));
+        assert!(report.bad_twins.contains(&tw));
+        assert!(report.broken_cycles.contains(&h));
+    }
+
+    #[test]
+    fn test_degenerate_face() {
+        let mesh = quad_mesh();
+        let f = {
+            let mut conn = mesh.write_connectivity();
+            let (f, _) = conn.iter_faces().next().unwrap();
+            // Make two vertices of the face the same one.
+            let hs = conn.face_edges(f);
+            let v0 = conn[hs[0]].vertex;
+            conn[hs[1]].vertex = v0;
+            f
+        };
+
+        let report = validate(&mesh);
+        assert_eq!(report.degenerate_faces, vec![f]);
+
+        repair(&mesh, &report).unwrap();
+        let conn = mesh.read_connectivity();
+        assert!(conn.face(f).is_none());
+        assert_eq!(conn.num_faces(), 1);
+    }
+
+    #[test]
+    fn test_orphan_elements_and_stale_channels() {
+        let mut mesh = quad_mesh();
+        let (h, f) = {
+            let mut conn = mesh.write_connectivity();
+            let h = conn.alloc_halfedge(HalfEdge::default());
+            let f = conn.alloc_face(None);
+            (h, f)
+        };
+        let ch_id = mesh.channels.ensure_channel::<FaceId, f32>("weight");
+        mesh.channels.write_channel(ch_id).unwrap()[f] = 1.0;
+        mesh.write_connectivity().remove_face(f);
+
+        let report = validate(&mesh);
+        assert!(report.orphan_halfedges.contains(&h));
+        assert_eq!(report.stale_channel_entries.len(), 1);
+        assert_eq!(report.stale_channel_entries[0].name, "weight");
+
+        repair(&mesh, &report).unwrap();
+        let report = validate(&mesh);
+        assert!(report.is_valid(), "{}", report.summary());
+        assert!(report.stale_channel_entries.is_empty());
+    }
+
+    type Op = fn(&mut HalfEdgeMesh) -> Result<()>;
+
+    fn first_face(mesh: &HalfEdgeMesh) -> FaceId {
+        mesh.read_connectivity().iter_faces().next().unwrap().0
+    }
+
+    fn first_vertex(mesh: &HalfEdgeMesh) -> VertexId {
+        mesh.read_connectivity().iter_vertices().next().unwrap().0
+    }
+
+    /// The ops that create new elements, called the same way as from Lua.
+    fn element_creating_ops() -> Vec<(&'static str, Op)> {
+        use super::super::{corners, delete, grid_fill};
+        use crate::prelude::selection::SelectionExpression;
+        vec![
+            ("extrude", |mesh: &mut HalfEdgeMesh| {
+                let f = first_face(mesh);
+                let mut known = corners::corners(mesh);
+                let boundary = super::super::extrude_faces(
+                    &mut mesh.write_connectivity(),
+                    &mut mesh.write_positions(),
+                    &[f],
+                    0.5,
+                )?;
+                corners::copy_extruded_corners(mesh, &boundary, &mut known)?;
+                corners::fill_new_corners(mesh, known)
+            }) as Op,
+            ("bevel", |mesh: &mut HalfEdgeMesh| {
+                let h = mesh.read_connectivity().iter_halfedges().next().unwrap().0;
+                super::super::bevel_edges(
+                    &mut mesh.write_connectivity(),
+                    &mut mesh.write_positions(),
+                    &[h],
+                    0.1,
+                )
+            }) as Op,
+            ("chamfer", |mesh: &mut HalfEdgeMesh| {
+                let v = first_vertex(mesh);
+                let mut known = corners::corners(mesh);
+                corners::chamfer_vertex_with_corners(mesh, v, 0.25, &mut known)?;
+                corners::fill_new_corners(mesh, known)
+            }) as Op,
+            ("split_edges", |mesh: &mut HalfEdgeMesh| {
+                super::super::split_edges(mesh, &SelectionExpression::from_ids(0..4), 0.5)?;
+                Ok(())
+            }) as Op,
+            ("connect_vertices", |mesh: &mut HalfEdgeMesh| {
+                let f = first_face(mesh);
+                let vs = mesh.read_connectivity().face_vertices(f);
+                super::super::connect_vertices(mesh, &[(vs[0], vs[2])])?;
+                Ok(())
+            }) as Op,
+            ("delete_and_grid_fill", |mesh: &mut HalfEdgeMesh| {
+                let f = first_face(mesh);
+                let h = mesh.read_connectivity().face_edges(f)[0];
+                delete::delete_faces_keep_boundary(&mut mesh.write_connectivity(), &[f])?;
+                let ids = mesh.read_connectivity().halfedge_mapping().map_seq(&[h]);
+                grid_fill(mesh, &SelectionExpression::from_ids(ids), None, 0)
+            }) as Op,
+            ("add_vertex", |mesh: &mut HalfEdgeMesh| {
+                super::super::add_vertex(mesh, Vec3::ONE * 3.0)
+            }) as Op,
+            ("add_edge", |mesh: &mut HalfEdgeMesh| {
+                super::super::add_edge(mesh, Vec3::ONE * 3.0, Vec3::ONE * 4.0)?;
+                Ok(())
+            }) as Op,
+            ("merge", |mesh: &mut HalfEdgeMesh| {
+                mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+                Ok(())
+            }) as Op,
+        ]
+    }
+
+    #[test]
+    fn test_sync_channels_after_element_creating_ops() {
+        const DEFAULT: f32 = 0.25;
+        for (name, op) in element_creating_ops() {
+            let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+            let weight = mesh
+                .channels
+                .ensure_channel_with_default::<VertexId, f32>("weight", DEFAULT);
+            let crease = mesh
+                .channels
+                .ensure_channel_with_default::<HalfEdgeId, f32>("crease", DEFAULT);
+            let mark = mesh
+                .channels
+                .ensure_channel_with_default::<FaceId, bool>("mark", true);
+            let old_vertices = {
+                let conn = mesh.read_connectivity();
+                let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+                let halfedges = conn.iter_halfedges().map(|(h, _)| h).collect_vec();
+                let mut weights = mesh.channels.write_channel(weight).unwrap();
+                vertices.iter().for_each(|v| weights[*v] = 1.0);
+                let mut creases = mesh.channels.write_channel(crease).unwrap();
+                halfedges.iter().for_each(|h| creases[*h] = 1.0);
+                vertices
+            };
+            sync_channels(&mesh).unwrap();
+
+            op(&mut mesh).unwrap();
+            sync_channels(&mesh).unwrap();
+            assert!(channels_in_sync(&mesh), "{name}");
+
+            // Existing elements keep their values, and new ones get either the
+            // default value or a value computed from the existing ones.
+            let conn = mesh.read_connectivity();
+            let weights = mesh.channels.read_channel(weight).unwrap();
+            for (v, _) in conn.iter_vertices() {
+                let expected = if old_vertices.contains(&v) {
+                    vec![1.0]
+                } else {
+                    vec![1.0, DEFAULT]
+                };
+                assert!(expected.contains(&weights[v]), "{name}: {}", weights[v]);
+            }
+            let creases = mesh.channels.read_channel(crease).unwrap();
+            for (h, _) in conn.iter_halfedges() {
+                assert!(
+                    [1.0, DEFAULT].contains(&creases[h]),
+                    "{name}: {}",
+                    creases[h]
+                );
+            }
+            let marks = mesh.channels.read_channel(mark).unwrap();
+            assert!(conn.iter_faces().all(|(f, _)| marks[f]), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_split_edges_interpolates_new_vertices() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let weight = mesh
+            .channels
+            .ensure_channel_with_default::<VertexId, f32>("weight", 0.0);
+        {
+            let conn = mesh.read_connectivity();
+            let positions = mesh.read_positions();
+            let mut weights = mesh.channels.write_channel(weight).unwrap();
+            for (v, _) in conn.iter_vertices() {
+                weights[v] = positions[v].x;
+            }
+        }
+        let new_vertices = super::super::split_edges(
+            &mut mesh,
+            &crate::prelude::selection::SelectionExpression::All,
+            0.5,
+        )
+        .unwrap();
+        sync_channels(&mesh).unwrap();
+        let positions = mesh.read_positions();
+        let weights = mesh.channels.read_channel(weight).unwrap();
+        for v in new_vertices {
+            assert!((weights[v] - positions[v].x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_sync_channels_drops_removed_elements() {
+        let mut mesh = quad_mesh();
+        let ch_id = mesh
+            .channels
+            .replace_or_create_channel::<FaceId, f32>("weight", Channel::new_with_default(2.0));
+        let f = first_face(&mesh);
+        mesh.channels.write_channel(ch_id).unwrap()[f] = 1.0;
+        assert!(!channels_in_sync(&mesh));
+        mesh.write_connectivity().remove_face(f);
+
+        sync_channels(&mesh).unwrap();
+        assert!(channels_in_sync(&mesh));
+        let ch = mesh.channels.read_channel(ch_id).unwrap();
+        assert_eq!(ch.iter().map(|(_, v)| *v).collect_vec(), vec![2.0]);
+    }
+}
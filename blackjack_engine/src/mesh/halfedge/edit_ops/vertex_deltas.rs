@@ -0,0 +1,111 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+
+use crate::prelude::*;
+
+/// Offsets for individual vertices of a mesh, as recorded by dragging them
+/// in the viewport. Vertices are identified by their index in the mesh, the
+/// same way explicit selections refer to them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VertexDeltas(pub BTreeMap<u32, Vec3>);
+
+impl mlua::UserData for VertexDeltas {}
+
+impl VertexDeltas {
+    /// Moves the vertex at index `vertex` by `delta`, on top of any previous
+    /// offset it had.
+    pub fn add(&mut self, vertex: u32, delta: Vec3) {
+        *self.0.entry(vertex).or_insert(Vec3::ZERO) += delta;
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Moves the vertices of `mesh` by their offset in `deltas`. Upstream changes
+/// to the topology may leave some indices out of range, those are skipped and
+/// their number is returned.
+pub fn apply_vertex_deltas(mesh: &HalfEdgeMesh, deltas: &VertexDeltas) -> usize {
+    let vertices = mesh
+        .read_connectivity()
+        .iter_vertices()
+        .map(|(v, _)| v)
+        .collect_vec();
+    let mut positions = mesh.write_positions();
+    let mut skipped = 0;
+    for (idx, delta) in &deltas.0 {
+        match vertices.get(*idx as usize) {
+            Some(v) => positions[*v] += *delta,
+            None => skipped += 1,
+        }
+    }
+    skipped
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Moves the vertices of `mesh` by the offsets in `deltas`, which are
+    /// recorded by the `Manual Edit` node. Offsets for vertices the mesh
+    /// doesn't have are skipped with a warning.
+    #[lua(under = "Ops")]
+    pub fn apply_vertex_deltas(mesh: &mut HalfEdgeMesh, deltas: &VertexDeltas) -> Result<()> {
+        let skipped = super::apply_vertex_deltas(mesh, deltas);
+        if skipped > 0 {
+            crate::progress::report_warning(
+                crate::progress::current_sink().as_deref(),
+                crate::progress::Warning::new(format!(
+                    "{skipped} of {} edited vertices are missing from the input mesh",
+                    deltas.len()
+                )),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_vertex_deltas() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let before = mesh.read_positions().clone();
+        let vertices = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .collect_vec();
+
+        let mut deltas = VertexDeltas::default();
+        deltas.add(0, Vec3::X);
+        deltas.add(0, Vec3::Y);
+        deltas.add(3, Vec3::Z);
+        // The box has 8 vertices
+        deltas.add(100, Vec3::ONE);
+        assert_eq!(deltas.len(), 3);
+
+        assert_eq!(apply_vertex_deltas(&mesh, &deltas), 1);
+        let after = mesh.read_positions();
+        for (idx, v) in vertices.iter_cpy().enumerate() {
+            let expected = match idx {
+                0 => before[v] + Vec3::new(1.0, 1.0, 0.0),
+                3 => before[v] + Vec3::Z,
+                _ => before[v],
+            };
+            assert_eq!(after[v], expected);
+        }
+    }
+}
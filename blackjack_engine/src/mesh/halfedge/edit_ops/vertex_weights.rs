@@ -0,0 +1,351 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+
+use crate::mesh::halfedge::bvh::MeshBvh;
+use crate::prelude::*;
+
+/// Values for individual vertices of a mesh, as painted in the viewport.
+/// Vertices are identified by their index in the mesh, the same way explicit
+/// selections refer to them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VertexWeights(pub BTreeMap<u32, f32>);
+
+impl mlua::UserData for VertexWeights {}
+
+impl VertexWeights {
+    /// Sets the value of the vertex at index `vertex`, replacing any previous
+    /// one.
+    pub fn set(&mut self, vertex: u32, value: f32) {
+        self.0.insert(vertex, value);
+    }
+
+    pub fn get(&self, vertex: u32) -> Option<f32> {
+        self.0.get(&vertex).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Writes the painted `weights` to the f32 vertex channel named `channel`,
+/// creating it if needed. Vertices that weren't painted keep their value.
+/// Upstream changes to the topology may leave some indices out of range,
+/// those are skipped and their number is returned.
+pub fn apply_vertex_weights(
+    mesh: &mut HalfEdgeMesh,
+    channel: &str,
+    weights: &VertexWeights,
+) -> Result<usize> {
+    let vertices = mesh
+        .read_connectivity()
+        .iter_vertices()
+        .map(|(v, _)| v)
+        .collect_vec();
+    let ch_id = mesh.channels.ensure_channel::<VertexId, f32>(channel);
+    let mut values = mesh.channels.write_channel(ch_id)?;
+    let mut skipped = 0;
+    for (idx, value) in &weights.0 {
+        match vertices.get(*idx as usize) {
+            Some(v) => values[*v] = *value,
+            None => skipped += 1,
+        }
+    }
+    Ok(skipped)
+}
+
+/// How a [`Brush`] changes the values under it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushMode {
+    /// Raises values towards 1
+    Add,
+    /// Lowers values towards 0
+    Subtract,
+    /// Blends values with the average of their neighbors
+    Smooth,
+}
+
+/// A round brush that paints values on the vertices of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    /// The radius of the brush, in world units.
+    pub radius: f32,
+    /// How much each dab of the brush changes values, from 0 to 1.
+    pub strength: f32,
+    /// The fraction of the radius over which the brush fades out. A falloff
+    /// of 0 is a hard brush, and 1 fades out from the center.
+    pub falloff: f32,
+    pub mode: BrushMode,
+}
+
+/// Returns how much a brush affects a point at `distance` from its center,
+/// from 1 at the center to 0 at the `radius` and beyond. Points closer than
+/// `1.0 - falloff` times the radius get the full effect, and the effect fades
+/// out smoothly for the rest.
+pub fn brush_falloff(distance: f32, radius: f32, falloff: f32) -> f32 {
+    if radius <= 0.0 || distance >= radius {
+        return 0.0;
+    }
+    let falloff = falloff.clamp(0.0, 1.0);
+    let t = distance / radius;
+    let hard = 1.0 - falloff;
+    if t <= hard {
+        1.0
+    } else {
+        let x = (t - hard) / falloff;
+        // One minus smoothstep
+        1.0 - x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Brush {
+    /// Paints one dab of the brush centered at `center`, a point on the
+    /// surface of `mesh`, and records the new values in `weights`. Values not
+    /// painted yet start from the ones in the vertex channel named `channel`
+    /// of the mesh, or 0 when it doesn't have it. Values are kept between 0
+    /// and 1.
+    ///
+    /// The `bvh` must have been built from `mesh`. Returns the number of
+    /// vertices the dab changed.
+    pub fn dab(
+        &self,
+        mesh: &HalfEdgeMesh,
+        bvh: &MeshBvh,
+        channel: &str,
+        center: Vec3,
+        weights: &mut VertexWeights,
+    ) -> Result<usize> {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let existing = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>(channel)
+            .ok();
+        let mapping = conn.vertex_mapping();
+        let current = |v: VertexId| {
+            weights
+                .get(mapping[v])
+                .or_else(|| existing.as_ref().map(|ch| ch[v]))
+                .unwrap_or(0.0)
+        };
+
+        let mut changes = vec![];
+        for v in bvh.vertices_within(center, self.radius) {
+            let amount = brush_falloff(positions[v].distance(center), self.radius, self.falloff)
+                * self.strength.clamp(0.0, 1.0);
+            if amount <= 0.0 {
+                continue;
+            }
+            let value = current(v);
+            let new_value = match self.mode {
+                BrushMode::Add => value + amount,
+                BrushMode::Subtract => value - amount,
+                BrushMode::Smooth => {
+                    let neighbors = conn
+                        .at_vertex(v)
+                        .outgoing_halfedges()?
+                        .iter()
+                        .map(|h| conn.at_halfedge(*h).dst_vertex().try_end())
+                        .collect::<Result<SVec<_>, _>>()?;
+                    if neighbors.is_empty() {
+                        continue;
+                    }
+                    let average =
+                        neighbors.iter().map(|n| current(*n)).sum::<f32>() / neighbors.len() as f32;
+                    value + (average - value) * amount
+                }
+            }
+            .clamp(0.0, 1.0);
+            changes.push((mapping[v], value, new_value));
+        }
+
+        // Smoothing reads the values of the neighbors, so nothing is written
+        // until all the new values are known.
+        let mut changed = 0;
+        for (idx, value, new_value) in changes {
+            if new_value != value {
+                weights.set(idx, new_value);
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Writes the values in `weights`, which are painted in the `Paint
+    /// Channel` node, to the f32 vertex channel named `channel`. Values for
+    /// vertices the mesh doesn't have are skipped with a warning.
+    #[lua(under = "Ops")]
+    pub fn apply_vertex_weights(
+        mesh: &mut HalfEdgeMesh,
+        channel: String,
+        weights: &VertexWeights,
+    ) -> Result<()> {
+        if channel.is_empty() {
+            bail!("The channel to paint needs a name");
+        }
+        let skipped = super::apply_vertex_weights(mesh, &channel, weights)?;
+        if skipped > 0 {
+            crate::progress::report_warning(
+                crate::progress::current_sink().as_deref(),
+                crate::progress::Warning::new(format!(
+                    "{skipped} of {} painted vertices are missing from the input mesh",
+                    weights.len()
+                )),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_brush_falloff() {
+        // Hard brush
+        assert_eq!(brush_falloff(0.0, 2.0, 0.0), 1.0);
+        assert_eq!(brush_falloff(1.99, 2.0, 0.0), 1.0);
+        assert_eq!(brush_falloff(2.0, 2.0, 0.0), 0.0);
+        assert_eq!(brush_falloff(3.0, 2.0, 0.0), 0.0);
+
+        // Fully soft brush
+        assert_eq!(brush_falloff(0.0, 2.0, 1.0), 1.0);
+        assert!((brush_falloff(1.0, 2.0, 1.0) - 0.5).abs() < 1e-6);
+        assert!(brush_falloff(1.9, 2.0, 1.0) < 0.01);
+
+        // Half soft: full strength up to half the radius, then fading out
+        assert_eq!(brush_falloff(0.9, 2.0, 0.5), 1.0);
+        assert!((brush_falloff(1.5, 2.0, 0.5) - 0.5).abs() < 1e-6);
+        let samples = (0..=20)
+            .map(|i| brush_falloff(i as f32 * 0.1, 2.0, 0.5))
+            .collect_vec();
+        assert!(samples.iter().tuple_windows().all(|(a, b)| a >= b));
+
+        // Degenerate radius
+        assert_eq!(brush_falloff(0.0, 0.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn test_brush_dabs() {
+        let mesh = primitives::UVSphere::build(Vec3::ZERO, 16, 8, 2.0).unwrap();
+        let bvh = MeshBvh::build(&mesh);
+        let mut weights = VertexWeights::default();
+        let mut brush = Brush {
+            radius: 1.0,
+            strength: 0.5,
+            falloff: 0.0,
+            mode: BrushMode::Add,
+        };
+        let center = bvh
+            .raycast(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z)
+            .unwrap()
+            .point;
+
+        let changed = brush
+            .dab(&mesh, &bvh, "mask", center, &mut weights)
+            .unwrap();
+        assert!(changed > 0);
+        assert_eq!(changed, weights.len());
+        assert!(weights.0.values().all(|w| *w == 0.5));
+        // Values never go past 1
+        for _ in 0..3 {
+            brush
+                .dab(&mesh, &bvh, "mask", center, &mut weights)
+                .unwrap();
+        }
+        assert!(weights.0.values().all(|w| *w == 1.0));
+
+        brush.mode = BrushMode::Subtract;
+        brush.strength = 0.25;
+        brush
+            .dab(&mesh, &bvh, "mask", center, &mut weights)
+            .unwrap();
+        assert!(weights.0.values().all(|w| *w == 0.75));
+
+        // Smoothing pulls the painted values towards their unpainted
+        // neighbors, which start at 0.
+        brush.mode = BrushMode::Smooth;
+        brush.radius = 10.0;
+        brush.strength = 1.0;
+        let before = weights.clone();
+        brush
+            .dab(&mesh, &bvh, "mask", center, &mut weights)
+            .unwrap();
+        assert!(weights.len() > before.len());
+        for (idx, value) in &before.0 {
+            assert!(weights.get(*idx).unwrap() <= *value);
+        }
+    }
+
+    #[test]
+    fn test_apply_vertex_weights() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let vertices = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .collect_vec();
+
+        let mut weights = VertexWeights::default();
+        weights.set(0, 1.0);
+        weights.set(5, 0.25);
+        // The box has 8 vertices
+        weights.set(8, 0.5);
+        weights.set(100, 0.5);
+
+        assert_eq!(
+            apply_vertex_weights(&mut mesh, "mask", &weights).unwrap(),
+            2
+        );
+        {
+            let mask = mesh
+                .channels
+                .read_channel_by_name::<VertexId, f32>("mask")
+                .unwrap();
+            for (idx, v) in vertices.iter_cpy().enumerate() {
+                let expected = match idx {
+                    0 => 1.0,
+                    5 => 0.25,
+                    _ => 0.0,
+                };
+                assert_eq!(mask[v], expected);
+            }
+        }
+
+        // When the upstream mesh gains vertices, the painted ones keep their
+        // index and the new ones are left alone.
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+        assert_eq!(
+            apply_vertex_weights(&mut mesh, "mask", &weights).unwrap(),
+            1
+        );
+        let mask = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("mask")
+            .unwrap();
+        let vertices = mesh
+            .read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| v)
+            .collect_vec();
+        assert_eq!(mask[vertices[0]], 1.0);
+        assert_eq!(mask[vertices[5]], 0.25);
+        assert_eq!(mask[vertices[8]], 0.5);
+        assert_eq!(mask[vertices[9]], 0.0);
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::mesh::halfedge::bvh::MeshBvh;
+use crate::prelude::*;
+
+/// The default limit for the number of cells in the grid of [`voxel_remesh`].
+/// Each cell takes 5 bytes, so a grid at the limit takes about 80MB.
+pub const DEFAULT_MAX_VOXEL_CELLS: usize = 1 << 24;
+
+/// Each cube of the grid is split in 6 tetrahedra around its diagonal, from
+/// corner 0 to corner 7. The bits of a corner index are its X, Y and Z offsets.
+/// Neighbouring cubes split their shared faces the same way, so the surface
+/// has no cracks.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 1, 5, 7],
+    [0, 2, 3, 7],
+    [0, 2, 6, 7],
+    [0, 4, 5, 7],
+    [0, 4, 6, 7],
+];
+
+/// A signed distance field sampled at the points of a regular grid. Values are
+/// negative inside the mesh.
+struct VoxelGrid {
+    origin: Vec3,
+    voxel_size: f32,
+    dims: UVec3,
+    values: Vec<f32>,
+}
+
+impl VoxelGrid {
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        ((z * self.dims.y + y) * self.dims.x + x) as usize
+    }
+
+    fn point(&self, x: u32, y: u32, z: u32) -> Vec3 {
+        self.origin + UVec3::new(x, y, z).as_vec3() * self.voxel_size
+    }
+
+    fn point_at_index(&self, index: usize) -> Vec3 {
+        let index = index as u32;
+        let x = index % self.dims.x;
+        let y = (index / self.dims.x) % self.dims.y;
+        let z = index / (self.dims.x * self.dims.y);
+        self.point(x, y, z)
+    }
+}
+
+/// Builds the triangles of the surface, sharing the vertices along each edge of
+/// the grid.
+#[derive(Default)]
+struct SurfaceBuilder {
+    edge_vertices: HashMap<(usize, usize), u32>,
+    positions: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+}
+
+impl SurfaceBuilder {
+    /// Returns the vertex where the surface crosses the edge between the grid
+    /// points `a` and `b`.
+    fn edge_vertex(&mut self, grid: &VoxelGrid, a: usize, b: usize) -> u32 {
+        let key = (a.min(b), a.max(b));
+        let positions = &mut self.positions;
+        *self.edge_vertices.entry(key).or_insert_with(|| {
+            let (va, vb) = (grid.values[a], grid.values[b]);
+            let t = va / (va - vb);
+            let (pa, pb) = (grid.point_at_index(a), grid.point_at_index(b));
+            positions.push(pa.lerp(pb, t));
+            (positions.len() - 1) as u32
+        })
+    }
+
+    /// Adds a triangle, reversing its vertices when `flip` is set.
+    fn triangle(&mut self, [a, b, c]: [u32; 3], flip: bool) {
+        let triangle = if flip { [a, c, b] } else { [a, b, c] };
+        self.triangles.push(triangle);
+    }
+
+    /// Adds the part of the surface inside the tetrahedron with the given grid
+    /// point indices, using the marching tetrahedra algorithm.
+    fn tetrahedron(&mut self, grid: &VoxelGrid, corners: [usize; 4]) {
+        let (inside, outside): (SVec<usize>, SVec<usize>) =
+            corners.into_iter().partition(|c| grid.values[*c] < 0.0);
+
+        // Triangles must point outwards. Their orientation is decided from
+        // the signed volume of the tetrahedron formed by the grid points,
+        // because the triangles themselves can be arbitrarily thin.
+        let p = |i: usize| grid.point_at_index(i);
+        let volume = |a: usize, b: usize, c: usize, d: usize| {
+            (p(b) - p(a)).dot((p(c) - p(a)).cross(p(d) - p(a)))
+        };
+        match (inside.as_slice(), outside.as_slice()) {
+            (&[], _) | (_, &[]) => {}
+            (&[i], &[o0, o1, o2]) => {
+                let tri = [(i, o0), (i, o1), (i, o2)].map(|(a, b)| self.edge_vertex(grid, a, b));
+                self.triangle(tri, volume(i, o0, o1, o2) < 0.0);
+            }
+            (&[i0, i1, i2], &[o]) => {
+                let tri = [(i0, o), (i1, o), (i2, o)].map(|(a, b)| self.edge_vertex(grid, a, b));
+                self.triangle(tri, volume(o, i0, i1, i2) > 0.0);
+            }
+            (&[i0, i1], &[o0, o1]) => {
+                // Consecutive edges share a grid point, so they go around a quad
+                let [q0, q1, q2, q3] = [(i0, o0), (i0, o1), (i1, o1), (i1, o0)]
+                    .map(|(a, b)| self.edge_vertex(grid, a, b));
+                let flip = volume(i0, i1, o0, o1) < 0.0;
+                self.triangle([q0, q1, q2], flip);
+                self.triangle([q0, q2, q3], flip);
+            }
+            _ => unreachable!("A tetrahedron has four corners"),
+        }
+    }
+}
+
+/// Returns a new mesh with the surface of `mesh`, rebuilt from a grid of
+/// voxels of the given size. The result is closed and manifold, even when the
+/// input has self-intersections or overlapping parts, but details smaller
+/// than a voxel are lost.
+///
+/// Points are inside the mesh when its winding number around them is
+/// positive, so overlapping parts are merged together. The input should be
+/// closed, with its faces pointing outwards.
+///
+/// Fails when the grid would have more than `max_cells` cells, rather than
+/// running out of memory.
+pub fn voxel_remesh(
+    mesh: &HalfEdgeMesh,
+    voxel_size: f32,
+    max_cells: usize,
+) -> Result<HalfEdgeMesh> {
+    if voxel_size.is_nan() || voxel_size <= 0.0 {
+        bail!("The voxel size must be positive, got {voxel_size}");
+    }
+    if mesh.read_connectivity().num_faces() == 0 {
+        bail!("Can't voxel remesh a mesh with no faces");
+    }
+
+    // A voxel of padding around the mesh, so the surface never reaches the
+    // border of the grid, and is always closed.
+    let (min, max) = analysis::bounds(mesh);
+    let origin = min - Vec3::splat(voxel_size);
+    let cells = ((max - min) / voxel_size).ceil() + Vec3::splat(3.0);
+    let num_cells = cells.x as f64 * cells.y as f64 * cells.z as f64;
+    if !num_cells.is_finite() || num_cells > max_cells as f64 {
+        bail!(
+            "A voxel remesh with a voxel size of {voxel_size} needs a grid of {}x{}x{} cells, \
+             more than the limit of {max_cells}. Use a larger voxel size.",
+            cells.x,
+            cells.y,
+            cells.z
+        );
+    }
+    let dims = cells.as_uvec3();
+    let mut grid = VoxelGrid {
+        origin,
+        voxel_size,
+        dims,
+        values: vec![0.0; (dims.x * dims.y * dims.z) as usize],
+    };
+
+    let bvh = MeshBvh::build(mesh);
+    let positions = mesh.read_positions();
+
+    // --- Inside test, casting a ray along each column of the grid ---
+    let mut inside = vec![false; grid.values.len()];
+    // Rays are slightly offset from the grid points, so they don't go exactly
+    // through edges and vertices of the mesh, which are often aligned with
+    // the grid.
+    let ray_offset = Vec3::new(1.237e-4, 2.713e-4, -1.0) * voxel_size;
+    for y in 0..dims.y {
+        for x in 0..dims.x {
+            let ray_origin = grid.point(x, y, 0) + ray_offset;
+            let mut crossings = SVec::<(f32, i32)>::new();
+            for hit in bvh.raycast_all(ray_origin, Vec3::Z) {
+                let [a, b, c] = hit.vertices.map(|v| positions[v]);
+                // Entering the mesh when going against the face normal
+                let winding = if (b - a).cross(c - a).z < 0.0 { 1 } else { -1 };
+                // Triangles sharing the edge or vertex the ray goes through
+                // are only counted once.
+                let duplicate = crossings.last().map_or(false, |(t, w)| {
+                    *w == winding && (hit.distance - t).abs() < voxel_size * 1e-5
+                });
+                if !duplicate {
+                    crossings.push((hit.distance, winding));
+                }
+            }
+
+            let mut winding = 0;
+            let mut crossings = crossings.into_iter().peekable();
+            for z in 0..dims.z {
+                // The distance from the ray origin to the grid point
+                let t = voxel_size * (z + 1) as f32;
+                while let Some((_, w)) = crossings.next_if(|(t_hit, _)| *t_hit < t) {
+                    winding += w;
+                }
+                inside[grid.index(x, y, z)] = winding > 0;
+            }
+        }
+    }
+
+    // --- Signed distances, only computed near the surface ---
+    // Grid values are never zero, so the surface never goes through the grid
+    // points, which would make degenerate triangles.
+    let min_distance = voxel_size * 1e-3;
+    for z in 0..dims.z {
+        for y in 0..dims.y {
+            for x in 0..dims.x {
+                let idx = grid.index(x, y, z);
+                let near_surface = (z.saturating_sub(1)..(z + 2).min(dims.z))
+                    .cartesian_product(y.saturating_sub(1)..(y + 2).min(dims.y))
+                    .cartesian_product(x.saturating_sub(1)..(x + 2).min(dims.x))
+                    .any(|((z2, y2), x2)| inside[grid.index(x2, y2, z2)] != inside[idx]);
+                let distance = if near_surface {
+                    bvh.closest_point(grid.point(x, y, z))
+                        .map_or(voxel_size, |hit| hit.distance)
+                        .clamp(min_distance, voxel_size)
+                } else {
+                    voxel_size
+                };
+                grid.values[idx] = if inside[idx] { -distance } else { distance };
+            }
+        }
+    }
+
+    // --- Surface extraction ---
+    let mut builder = SurfaceBuilder::default();
+    for z in 0..dims.z - 1 {
+        for y in 0..dims.y - 1 {
+            for x in 0..dims.x - 1 {
+                let corner = |c: usize| {
+                    let c = c as u32;
+                    grid.index(x + (c & 1), y + ((c >> 1) & 1), z + ((c >> 2) & 1))
+                };
+                for tet in TETRAHEDRA {
+                    builder.tetrahedron(&grid, tet.map(corner));
+                }
+            }
+        }
+    }
+
+    if builder.triangles.is_empty() {
+        bail!("The voxel remesh has no inside voxels. Is the mesh closed?");
+    }
+    HalfEdgeMesh::build_from_polygons(&builder.positions, &builder.triangles)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Rebuilds the surface of `mesh` from a grid of voxels with the given
+    /// `voxel_size`. The result is always closed and manifold, which is
+    /// useful to clean up self-intersections, but details smaller than a
+    /// voxel are lost. Overlapping parts of the mesh are merged.
+    ///
+    /// The optional `max_cells` limits the size of the voxel grid, and
+    /// defaults to 16 million cells.
+    #[lua(under = "Ops")]
+    pub fn voxel_remesh(
+        mesh: &HalfEdgeMesh,
+        voxel_size: f32,
+        max_cells: Option<u32>,
+    ) -> Result<HalfEdgeMesh> {
+        super::voxel_remesh(
+            mesh,
+            voxel_size,
+            max_cells.map_or(DEFAULT_MAX_VOXEL_CELLS, |c| c as usize),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn overlapping_spheres() -> HalfEdgeMesh {
+        let mut mesh = primitives::UVSphere::build(Vec3::new(-0.5, 0.0, 0.0), 16, 12, 1.0).unwrap();
+        let other = primitives::UVSphere::build(Vec3::new(0.5, 0.0, 0.0), 16, 12, 1.0).unwrap();
+        mesh.merge_with(&other);
+        mesh
+    }
+
+    #[test]
+    fn test_remesh_overlapping_spheres() {
+        let remeshed = voxel_remesh(&overlapping_spheres(), 0.15, DEFAULT_MAX_VOXEL_CELLS).unwrap();
+        assert!(edit_ops::validate(&remeshed).is_valid());
+
+        let stats = analysis::mesh_stats(&remeshed).unwrap();
+        assert!(stats.is_closed);
+        assert_eq!(stats.num_components, 1);
+        // Genus 0: V - E + F = 2
+        let euler = stats.num_vertices as i64 - stats.num_edges as i64 + stats.num_faces as i64;
+        assert_eq!(euler, 2);
+
+        // Manifold: going around the fan of each vertex visits all its edges
+        let conn = remeshed.read_connectivity();
+        let mut outgoing = HashMap::<VertexId, usize>::new();
+        for (h, _) in conn.iter_halfedges() {
+            *outgoing
+                .entry(conn.at_halfedge(h).vertex().try_end().unwrap())
+                .or_default() += 1;
+        }
+        for (v, _) in conn.iter_vertices() {
+            assert_eq!(
+                conn.at_vertex(v).outgoing_halfedges().unwrap().len(),
+                outgoing[&v]
+            );
+        }
+
+        // The union of the two spheres, minus their overlap. The result is a
+        // bit smaller, because the spheres are polygonal.
+        let expected = 2.0 * 4.0 / 3.0 * std::f32::consts::PI - 5.0 * std::f32::consts::PI / 12.0;
+        assert!(stats.volume > 0.0);
+        assert!(
+            (stats.volume - expected).abs() / expected < 0.15,
+            "{}",
+            stats.volume
+        );
+    }
+
+    #[test]
+    fn test_remesh_cell_limit() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let err = voxel_remesh(&cube, 0.01, 1000).unwrap_err();
+        assert!(err.to_string().contains("limit"), "{err}");
+        assert!(voxel_remesh(&cube, 0.0, 1000).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::VecDeque;
+
+use crate::mesh::halfedge::conventions::reverse_winding_of;
+use crate::prelude::*;
+use crate::progress::{report_warning, ProgressSink, Warning, WarningElements};
+
+use super::{find_islands, generate_flat_normals_channel, generate_smooth_normals_channel};
+
+/// Identifies a position exactly. Faces can only disagree on their winding
+/// when they don't share their vertices, so the edges of different islands
+/// are matched by the positions of their vertices.
+type PointKey = [u32; 3];
+
+fn point_key(p: Vec3) -> PointKey {
+    // Both zeros are the same position
+    p.to_array().map(|x| if x == 0.0 { 0 } else { x.to_bits() })
+}
+
+/// A boundary edge of an island, going along the face of `halfedge`.
+struct BoundaryEdge {
+    island: usize,
+    halfedge: HalfEdgeId,
+    /// Whether the face goes along the edge from the smaller point key to the
+    /// larger one.
+    forward: bool,
+}
+
+/// Another island sharing an edge with an island.
+struct Neighbor {
+    island: usize,
+    /// Whether the two islands go along the shared edge in the same
+    /// direction, which means one of them has to be flipped.
+    disagree: bool,
+    halfedge: HalfEdgeId,
+}
+
+/// The signed volume enclosed by `faces`, as seen from
+/// `reference`. Positive for closed surfaces facing outwards.
+fn signed_volume(
+    conn: &MeshConnectivity,
+    positions: &Positions,
+    faces: &[FaceId],
+    reference: Vec3,
+) -> Result<f64> {
+    let mut volume = 0.0;
+    for face in faces.iter_cpy() {
+        let points = conn
+            .at_face(face)
+            .vertices()?
+            .iter()
+            .map(|v| (positions[*v] - reference).as_dvec3())
+            .collect_vec();
+        if let Some((first, rest)) = points.split_first() {
+            for (b, c) in rest.iter().tuple_windows() {
+                volume += first.dot(b.cross(*c)) / 6.0;
+            }
+        }
+    }
+    Ok(volume)
+}
+
+/// Makes the winding of the faces of `mesh` consistent, so all the faces of
+/// a surface point to the same side, and returns the number of flipped
+/// faces.
+///
+/// Faces only disagree with their neighbors when they don't share vertices
+/// with them, like after importing a mesh with split vertices, so the parts
+/// of the mesh are matched along the edges at the same positions. Closed
+/// surfaces are made to face `outward` or inward, and open ones keep the
+/// winding most of their faces already have. When a surface can't be wound
+/// consistently, like a Möbius strip, a warning points to one of the edges
+/// where the winding flips.
+pub fn recalculate_winding(
+    mesh: &mut HalfEdgeMesh,
+    outward: bool,
+    sink: Option<&dyn ProgressSink>,
+) -> Result<usize> {
+    let islands = find_islands(mesh)?;
+    let mut flipped_halfedges = HashSet::new();
+    let mut num_flipped = 0;
+    {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+
+        // Within an island, faces always agree, so only the islands sharing
+        // an edge are compared.
+        let mut edges = HashMap::<(PointKey, PointKey), Vec<BoundaryEdge>>::new();
+        for (i, island) in islands.iter().enumerate() {
+            for h in island.halfedges.iter_cpy() {
+                if conn.at_halfedge(h).face_or_boundary()?.is_none()
+                    || !conn.at_halfedge(h).twin().is_boundary()?
+                {
+                    continue;
+                }
+                let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+                let (a, b) = (point_key(positions[src]), point_key(positions[dst]));
+                edges
+                    .entry((a.min(b), a.max(b)))
+                    .or_default()
+                    .push(BoundaryEdge {
+                        island: i,
+                        halfedge: h,
+                        forward: a < b,
+                    });
+            }
+        }
+
+        // Edges shared by more than two faces say nothing about the winding,
+        // and neither do the ones with a single face, but both leave the
+        // surface open.
+        let mut open = vec![false; islands.len()];
+        let mut neighbors = islands.iter().map(|_| vec![]).collect_vec();
+        for shared in edges.values() {
+            match shared.as_slice() {
+                [e1, e2] => {
+                    let disagree = e1.forward == e2.forward;
+                    neighbors[e1.island].push(Neighbor {
+                        island: e2.island,
+                        disagree,
+                        halfedge: e1.halfedge,
+                    });
+                    neighbors[e2.island].push(Neighbor {
+                        island: e1.island,
+                        disagree,
+                        halfedge: e2.halfedge,
+                    });
+                }
+                shared => {
+                    for e in shared {
+                        open[e.island] = true;
+                    }
+                }
+            }
+        }
+
+        // Flood fills the islands connected through shared edges, flipping
+        // the ones that disagree with the island they were reached from.
+        let mut flip = vec![None; islands.len()];
+        let mut non_orientable = None;
+        for start in 0..islands.len() {
+            if flip[start].is_some() {
+                continue;
+            }
+            flip[start] = Some(false);
+            let mut surface = vec![start];
+            let mut queue = VecDeque::from([start]);
+            let mut orientable = true;
+            while let Some(i) = queue.pop_front() {
+                let flip_i = flip[i] == Some(true);
+                for neighbor in &neighbors[i] {
+                    let expected = flip_i != neighbor.disagree;
+                    match flip[neighbor.island] {
+                        None => {
+                            flip[neighbor.island] = Some(expected);
+                            surface.push(neighbor.island);
+                            queue.push_back(neighbor.island);
+                        }
+                        Some(existing) if existing != expected => {
+                            orientable = false;
+                            non_orientable.get_or_insert(neighbor.halfedge);
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+
+            let faces_with = |flipped: bool| {
+                surface
+                    .iter()
+                    .filter(|i| flip[**i] == Some(flipped))
+                    .map(|i| islands[*i].faces.len())
+                    .sum::<usize>()
+            };
+            let closed = orientable && !surface.iter().any(|i| open[*i]);
+            let turn_around = if closed {
+                let reference = islands[start]
+                    .vertices
+                    .first()
+                    .map(|v| positions[*v])
+                    .unwrap_or(Vec3::ZERO);
+                let mut volume = 0.0;
+                for i in surface.iter_cpy() {
+                    let sign = if flip[i] == Some(true) { -1.0 } else { 1.0 };
+                    volume +=
+                        sign * signed_volume(&conn, &positions, &islands[i].faces, reference)?;
+                }
+                volume != 0.0 && (volume > 0.0) != outward
+            } else {
+                faces_with(true) > faces_with(false)
+            };
+            for i in surface {
+                let flipped = (flip[i] == Some(true)) != turn_around;
+                flip[i] = Some(flipped);
+                if flipped {
+                    flipped_halfedges.extend(islands[i].halfedges.iter_cpy());
+                    num_flipped += islands[i].faces.len();
+                }
+            }
+        }
+
+        if let Some(h) = non_orientable {
+            let (src, dst) = conn.at_halfedge(h).src_dst_pair()?;
+            report_warning(
+                sink,
+                Warning::new(format!(
+                    "The surface can't be wound consistently, it's like a Möbius strip. The \
+                     winding flips at the edge between vertices {src:?} and {dst:?}"
+                ))
+                .with_elements(WarningElements::HalfEdges(vec![h])),
+            );
+        }
+    }
+
+    if num_flipped > 0 {
+        reverse_winding_of(mesh, &flipped_halfedges)?;
+        // The normals the mesh already had point the wrong way now
+        if let Some(ch_id) = mesh.default_channels.face_normals {
+            let normals = generate_flat_normals_channel(mesh)?;
+            *mesh.channels.write_channel(ch_id)? = normals;
+        }
+        if let Some(ch_id) = mesh.default_channels.vertex_normals {
+            let normals = generate_smooth_normals_channel(mesh)?;
+            *mesh.channels.write_channel(ch_id)? = normals;
+        }
+    }
+    Ok(num_flipped)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Makes the winding of the faces of `mesh` consistent, so all the faces
+    /// of a surface point to the same side. Closed surfaces are made to face
+    /// outwards when `outward` is set, or inwards otherwise. Parts of the
+    /// mesh that don't share their vertices are matched along the edges at
+    /// the same positions.
+    #[lua(under = "Ops")]
+    pub fn recalculate_winding(mesh: &mut HalfEdgeMesh, outward: bool) -> Result<()> {
+        let sink = crate::progress::current_sink();
+        super::recalculate_winding(mesh, outward, sink.as_deref())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::{delete_faces, validate};
+    use crate::mesh::halfedge::primitives;
+    use crate::progress::MockSink;
+
+    /// A unit cube centered at the origin, made of six separate quads. The
+    /// `flipped` ones are wound inwards.
+    fn split_cube(flipped: &[usize]) -> HalfEdgeMesh {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { -0.5 } else { 0.5 },
+                if i & 2 == 0 { -0.5 } else { 0.5 },
+                if i & 4 == 0 { -0.5 } else { 0.5 },
+            )
+        };
+        // Wound counter-clockwise when seen from outside
+        let quads = [
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        let mut positions = vec![];
+        let mut polygons = vec![];
+        for (i, quad) in quads.iter().enumerate() {
+            let mut quad = quad.map(corner);
+            if flipped.contains(&i) {
+                quad.reverse();
+            }
+            let first = positions.len() as u32;
+            positions.extend(quad);
+            polygons.push([first, first + 1, first + 2, first + 3]);
+        }
+        HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap()
+    }
+
+    /// Returns how many faces of a mesh centered at the origin point
+    /// outwards.
+    fn outward_faces(mesh: &HalfEdgeMesh) -> usize {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        conn.iter_faces()
+            .filter(|(face, _)| {
+                let normal = conn.face_normal(&positions, *face).unwrap();
+                let vertices = conn.face_vertices(*face);
+                let center =
+                    vertices.iter().map(|v| positions[*v]).sum::<Vec3>() / vertices.len() as f32;
+                normal.dot(center) > 0.0
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_cube_with_flipped_faces() {
+        let sink = MockSink::new(None);
+        let mut cube = split_cube(&[1, 2, 5]);
+        assert_eq!(outward_faces(&cube), 3);
+        assert_eq!(
+            recalculate_winding(&mut cube, true, Some(&sink)).unwrap(),
+            3
+        );
+        assert_eq!(outward_faces(&cube), 6);
+        assert!(validate(&cube).is_valid());
+        assert!(sink.warnings.borrow().is_empty());
+
+        // Already consistent
+        assert_eq!(
+            recalculate_winding(&mut cube, true, Some(&sink)).unwrap(),
+            0
+        );
+        assert_eq!(
+            recalculate_winding(&mut cube, false, Some(&sink)).unwrap(),
+            6
+        );
+        assert_eq!(outward_faces(&cube), 0);
+
+        // Regular meshes already agree, but can be turned inside out
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert_eq!(recalculate_winding(&mut cube, true, None).unwrap(), 0);
+        assert_eq!(recalculate_winding(&mut cube, false, None).unwrap(), 6);
+        assert_eq!(outward_faces(&cube), 0);
+    }
+
+    #[test]
+    fn test_open_surfaces_keep_most_faces() {
+        // Two faces of the cube are left out, so it's open
+        let mut open = split_cube(&[3]);
+        let faces = open
+            .read_connectivity()
+            .iter_faces()
+            .map(|(f, _)| f)
+            .collect_vec();
+        delete_faces(&mut open.write_connectivity(), &faces[4..]).unwrap();
+        assert_eq!(recalculate_winding(&mut open, false, None).unwrap(), 1);
+        assert_eq!(outward_faces(&open), 4);
+    }
+
+    #[test]
+    fn test_non_orientable_strip() {
+        // A Möbius strip. The last vertices are at the positions of the
+        // first ones, swapped, so they don't share the same vertices.
+        let segments = 12;
+        let mut positions = vec![];
+        for i in 0..segments {
+            let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+            let twist = angle / 2.0;
+            let center = Vec3::new(angle.cos(), 0.0, angle.sin()) * 2.0;
+            let across = Vec3::Y * twist.cos() + center.normalize() * twist.sin();
+            positions.push(center + across * 0.5);
+            positions.push(center - across * 0.5);
+        }
+        positions.push(positions[1]);
+        positions.push(positions[0]);
+        let polygons = (0..segments)
+            .map(|i| [2 * i, 2 * i + 2, 2 * i + 3, 2 * i + 1])
+            .collect_vec();
+        let mut strip = HalfEdgeMesh::build_from_polygons(&positions, &polygons).unwrap();
+
+        let sink = MockSink::new(None);
+        recalculate_winding(&mut strip, true, Some(&sink)).unwrap();
+        let warnings = sink.warnings.borrow();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("Möbius"));
+        let conn = strip.read_connectivity();
+        match &warnings[0].elements {
+            Some(WarningElements::HalfEdges(halfedges)) => {
+                // The edge is on the seam, where the strip meets itself
+                let (src, dst) = conn.at_halfedge(halfedges[0]).src_dst_pair().unwrap();
+                let seam = [positions[0], positions[1]];
+                let strip_positions = strip.read_positions();
+                assert!(seam.contains(&strip_positions[src]));
+                assert!(seam.contains(&strip_positions[dst]));
+            }
+            other => panic!("Expected an edge, got {other:?}"),
+        }
+    }
+}
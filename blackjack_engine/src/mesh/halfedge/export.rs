@@ -0,0 +1,331 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use slotmap::SecondaryMap;
+
+use crate::prelude::*;
+
+/// The halfedge f32 channel marking hard edges. Edges where either halfedge
+/// has a crease of 1 are never smoothed.
+pub const CREASE_CHANNEL: &str = "crease";
+/// The face f32 channel with an explicit smoothing group for each face. Edges
+/// between faces of different groups are hard, and a group of 0 means the
+/// face is flat.
+pub const SMOOTHING_GROUP_CHANNEL: &str = "smoothing_group";
+
+/// The vertices of a mesh as exporters write them, where each vertex is split
+/// in one copy for every region of faces around it that is shaded smooth.
+/// Vertices at hard edges get one normal on each side of the edge, like the
+/// viewport shows them.
+#[derive(Debug, Clone)]
+pub struct SplitVertices {
+    /// The position of each split vertex.
+    pub positions: Vec<Vec3>,
+    /// The normal of each split vertex.
+    pub normals: Vec<Vec3>,
+    /// The vertex of the mesh each split vertex comes from.
+    pub sources: Vec<VertexId>,
+    /// The split vertex used by each corner of a face. Corners are identified
+    /// by the halfedge of the face that starts at them.
+    pub corners: SecondaryMap<HalfEdgeId, u32>,
+    /// The faces triangulated as a fan from their first vertex, three indices
+    /// into the split vertices per triangle. Faces come in the same order as
+    /// [`HalfEdgeMesh::triangle_material_indices`].
+    pub indices: Vec<u32>,
+    /// The groups of faces joined by smooth edges, numbered from 1, or the
+    /// ones in the `smoothing_group` channel when the mesh has it. Flat faces
+    /// get no group.
+    pub smoothing_groups: SecondaryMap<FaceId, u32>,
+}
+
+/// Returns the representative of the set of `key`, in a union-find forest.
+fn find<K: slotmap::Key>(parent: &mut SecondaryMap<K, K>, mut key: K) -> K {
+    while parent[key] != key {
+        let grandparent = parent[parent[key]];
+        parent[key] = grandparent;
+        key = grandparent;
+    }
+    key
+}
+
+fn union<K: slotmap::Key>(parent: &mut SecondaryMap<K, K>, a: K, b: K) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[a] = b;
+    }
+}
+
+/// Splits the vertices of `mesh` along its hard edges, for exporters. An edge
+/// is hard when:
+///
+/// - The mesh is shaded flat, see [`ShadingMode`].
+/// - Either of its halfedges has a crease of 1 in the `crease` channel.
+/// - The faces at each side are in different groups of the
+///   `smoothing_group` channel.
+/// - Its faces meet at a sharper angle than the
+///   auto smooth angle of the mesh, see [`ShadingMode::AutoSmooth`].
+///
+/// Vertices that aren't next to any hard edge keep the normal from the mesh's
+/// vertex normals channel when it has one. The rest average the normals of
+/// the faces on each side.
+pub fn normal_split(mesh: &HalfEdgeMesh) -> Result<SplitVertices> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let creases = mesh
+        .channels
+        .read_channel_by_name::<HalfEdgeId, f32>(CREASE_CHANNEL)
+        .ok();
+    let groups = mesh
+        .channels
+        .read_channel_by_name::<FaceId, f32>(SMOOTHING_GROUP_CHANNEL)
+        .ok();
+    let smooth = mesh.gen_config.smooth_normals();
+    let cos_threshold = mesh.gen_config.auto_smooth_angle().map(f32::cos);
+
+    let face_normals = conn
+        .iter_faces()
+        .map(|(f, _)| (f, conn.face_normal(&positions, f).unwrap_or(Vec3::ZERO)))
+        .collect::<SecondaryMap<_, _>>();
+    let is_hard = |h: HalfEdgeId, twin: HalfEdgeId, a: FaceId, b: FaceId| {
+        if !smooth {
+            return true;
+        }
+        if let Some(creases) = &creases {
+            if creases[h] >= 1.0 || creases[twin] >= 1.0 {
+                return true;
+            }
+        }
+        if let Some(groups) = &groups {
+            if groups[a] != groups[b] || groups[a] == 0.0 {
+                return true;
+            }
+        }
+        match cos_threshold {
+            Some(cos_threshold) => face_normals[a].dot(face_normals[b]) < cos_threshold,
+            None => false,
+        }
+    };
+
+    // Every corner starts in its own region, and the two corners at each end
+    // of a smooth edge are joined.
+    let mut parent = SecondaryMap::<HalfEdgeId, HalfEdgeId>::new();
+    for (h, _) in conn.iter_halfedges() {
+        parent.insert(h, h);
+    }
+    // The faces joined by smooth edges, to number the smoothing groups
+    let mut face_parent = SecondaryMap::<FaceId, FaceId>::new();
+    for (f, _) in conn.iter_faces() {
+        face_parent.insert(f, f);
+    }
+    for (h, _) in conn.iter_halfedges() {
+        let halfedge = conn.at_halfedge(h);
+        let twin = halfedge.twin().try_end()?;
+        let (a, b) = match (
+            halfedge.face_or_boundary()?,
+            conn.at_halfedge(twin).face_or_boundary()?,
+        ) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+        if is_hard(h, twin, a, b) {
+            continue;
+        }
+        // `h` starts at the same vertex as the halfedge after its twin, on
+        // the other side of the edge.
+        union(&mut parent, h, conn.at_halfedge(twin).next().try_end()?);
+        union(&mut face_parent, a, b);
+    }
+
+    let vertex_normals = mesh.read_vertex_normals();
+    let mut split = SplitVertices {
+        positions: vec![],
+        normals: vec![],
+        sources: vec![],
+        corners: SecondaryMap::new(),
+        indices: vec![],
+        smoothing_groups: SecondaryMap::new(),
+    };
+    // The split vertex of each region, by its representative corner
+    let mut region_vertex = SecondaryMap::<HalfEdgeId, u32>::new();
+    // The number of regions around each vertex
+    let mut num_regions = SecondaryMap::<VertexId, u32>::new();
+    let mut group_numbers = SecondaryMap::<FaceId, u32>::new();
+
+    for (face, _) in conn.iter_faces() {
+        let edges = conn.face_edges(face);
+        for h in edges.iter_cpy() {
+            let root = find(&mut parent, h);
+            let idx = match region_vertex.get(root) {
+                Some(idx) => *idx,
+                None => {
+                    let v = conn.at_halfedge(h).vertex().try_end()?;
+                    let idx = split.positions.len() as u32;
+                    region_vertex.insert(root, idx);
+                    split.positions.push(positions[v]);
+                    split.normals.push(Vec3::ZERO);
+                    split.sources.push(v);
+                    *num_regions.entry(v).unwrap().or_default() += 1;
+                    idx
+                }
+            };
+            split.corners.insert(h, idx);
+            split.normals[idx as usize] += face_normals[face];
+        }
+
+        let corners = edges.iter().map(|h| split.corners[*h]).collect_vec();
+        for (&b, &c) in corners[1..].iter().tuple_windows() {
+            split.indices.extend([corners[0], b, c]);
+        }
+
+        if smooth {
+            let group = match &groups {
+                Some(groups) => groups[face].max(0.0) as u32,
+                None => {
+                    let root = find(&mut face_parent, face);
+                    let next_group = group_numbers.len() as u32 + 1;
+                    *group_numbers.entry(root).unwrap().or_insert(next_group)
+                }
+            };
+            if group > 0 {
+                split.smoothing_groups.insert(face, group);
+            }
+        }
+    }
+
+    for (idx, normal) in split.normals.iter_mut().enumerate() {
+        let v = split.sources[idx];
+        *normal = match &vertex_normals {
+            Some(vertex_normals) if smooth && num_regions[v] == 1 => vertex_normals[v],
+            _ => normal.normalize_or_zero(),
+        };
+    }
+
+    Ok(split)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// A smooth cube where all the edges are creased except the one between
+    /// the top face and the face looking towards X.
+    pub(crate) fn cube_with_one_smooth_edge() -> HalfEdgeMesh {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        edit_ops::set_smooth_normals(&mut cube).unwrap();
+        let ch_id = cube
+            .channels
+            .ensure_channel::<HalfEdgeId, f32>(CREASE_CHANNEL);
+        let conn = cube.read_connectivity();
+        let positions = cube.read_positions();
+        let mut creases = cube.channels.write_channel(ch_id).unwrap();
+        for (h, _) in conn.iter_halfedges() {
+            let (src, dst) = conn.at_halfedge(h).src_dst_pair().unwrap();
+            let (a, b) = (positions[src], positions[dst]);
+            let smooth_edge = a.x > 0.0 && b.x > 0.0 && a.y > 0.0 && b.y > 0.0;
+            creases[h] = if smooth_edge { 0.0 } else { 1.0 };
+        }
+        drop((conn, positions, creases));
+        cube
+    }
+
+    #[test]
+    fn test_split_smooth_cube() {
+        // Without hard edges, every vertex stays in one piece
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        edit_ops::set_smooth_normals(&mut cube).unwrap();
+        let split = normal_split(&cube).unwrap();
+        assert_eq!(split.positions.len(), 8);
+        assert_eq!(split.indices.len(), 6 * 2 * 3);
+        assert_eq!(
+            split
+                .smoothing_groups
+                .values()
+                .collect::<HashSet<_>>()
+                .len(),
+            1
+        );
+
+        // Flat shading splits every corner of every face
+        edit_ops::set_flat_normals(&mut cube).unwrap();
+        let split = normal_split(&cube).unwrap();
+        assert_eq!(split.positions.len(), 24);
+        assert!(split.smoothing_groups.is_empty());
+
+        // So does an angle threshold smaller than the angle of the faces
+        edit_ops::set_smooth_normals(&mut cube).unwrap();
+        cube.gen_config.shading = ShadingMode::AutoSmooth(80f32.to_radians());
+        assert_eq!(normal_split(&cube).unwrap().positions.len(), 24);
+        cube.gen_config.shading = ShadingMode::AutoSmooth(100f32.to_radians());
+        assert_eq!(normal_split(&cube).unwrap().positions.len(), 8);
+    }
+
+    #[test]
+    fn test_split_auto_smooth_cylinder() {
+        let mut cylinder =
+            primitives::Cone::build_truncated_cone(Vec3::ZERO, 1.0, 1.0, 2.0, 16).unwrap();
+        edit_ops::set_shading(&mut cylinder, ShadingMode::AutoSmooth(30f32.to_radians())).unwrap();
+        let split = normal_split(&cylinder).unwrap();
+
+        // The sides meet at 22.5 degrees, so they are smooth, but the caps are
+        // split from them.
+        assert_eq!(split.positions.len(), 16 * 2 * 2);
+        assert_eq!(
+            split
+                .smoothing_groups
+                .values()
+                .collect::<HashSet<_>>()
+                .len(),
+            3
+        );
+        for (position, normal) in split.positions.iter().zip(&split.normals) {
+            let radial = Vec3::new(position.x, 0.0, position.z).normalize();
+            let cap = Vec3::Y * position.y.signum();
+            assert!(
+                normal.abs_diff_eq(radial, 1e-5) || normal.abs_diff_eq(cap, 1e-5),
+                "Unexpected normal {normal} at {position}"
+            );
+        }
+        let num_caps = split.normals.iter().filter(|n| n.y.abs() > 0.5).count();
+        assert_eq!(num_caps, 16 * 2);
+
+        // A smaller angle than the one between the sides splits all faces
+        cylinder.gen_config.shading = ShadingMode::AutoSmooth(20f32.to_radians());
+        assert_eq!(
+            normal_split(&cylinder).unwrap().positions.len(),
+            16 * 4 + 16 * 2
+        );
+    }
+
+    #[test]
+    fn test_split_creased_cube() {
+        let cube = cube_with_one_smooth_edge();
+        let split = normal_split(&cube).unwrap();
+        // The two vertices of the smooth edge are shared by the faces at both
+        // sides of it, the rest are split for each face.
+        assert_eq!(split.positions.len(), 24 - 2);
+        assert_eq!(split.smoothing_groups.values().max(), Some(&5));
+
+        let shared = (0..split.positions.len())
+            .filter(|idx| split.normals[*idx].x > 0.1 && split.normals[*idx].y > 0.1)
+            .collect_vec();
+        assert_eq!(shared.len(), 2);
+        for idx in shared {
+            // Halfway between the normals of the two faces
+            let expected = Vec3::new(1.0, 1.0, 0.0).normalize();
+            assert!(split.normals[idx].abs_diff_eq(expected, 1e-5));
+        }
+
+        // Across the hard edges, the copies of a vertex have different normals
+        let corner = split
+            .positions
+            .iter()
+            .positions(|p| p.abs_diff_eq(Vec3::splat(-0.5), 1e-5))
+            .map(|idx| split.normals[idx])
+            .collect_vec();
+        assert_eq!(corner.len(), 3);
+        assert!(corner.iter().tuple_combinations().all(|(a, b)| a != b));
+    }
+}
@@ -5,6 +5,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use super::*;
+use crate::mesh::material::MaterialTable;
 
 /// The main representation to draw the halfedge's faces as triangles on the GPU
 /// This is suitable to be rendered with `wgpu::PrimitiveTopology::TriangleList`
@@ -50,114 +51,206 @@ pub struct FaceOverlayBuffers {
     pub max_id: u32,
 }
 
+/// Where the elements of some [`VertexIndexBuffers`] come from: The vertex,
+/// and for flat shading the face, of each element of `positions`. The layout
+/// only depends on the topology of the mesh, so once the vertices of the mesh
+/// move, the buffers can be updated without triangulating its faces again. See
+/// [`HalfEdgeMesh::update_triangle_buffers`].
+#[derive(Clone, Debug)]
+pub struct TriangleBufferLayout {
+    smooth: bool,
+    /// With auto smooth, vertices are split along the hard edges of the mesh,
+    /// which depend on its positions. The buffers are generated again on every
+    /// update, and `vertices` and `faces` are empty.
+    auto_smooth: bool,
+    vertices: Vec<VertexId>,
+    /// Empty for smooth shading, where elements are shared between faces.
+    faces: Vec<FaceId>,
+}
+
 impl HalfEdgeMesh {
     /// Generates the [`TriangleBuffers`] for this mesh. Suitable to be uploaded
     /// to the GPU.
     #[profiling::function]
     pub fn generate_triangle_buffers_flat(&self, force_gen: bool) -> Result<VertexIndexBuffers> {
-        let positions_ch = self.read_positions();
-        let conn = self.read_connectivity();
-
-        // This ugliness is needed because we need to either borrow the channel
-        // for the mesh or generate it here, but if we generate inside the if
-        // statement the ref owner gets dropped at the end of the scope.
-        let normal_ch: &Channel<_, _>;
-        #[allow(unused_assignments)] // Look ma, I'm smarter than clippy
-        let mut extend_lifetime = None;
-        let existing_normals_ch = self.read_face_normals();
-        if !force_gen && existing_normals_ch.is_some() {
-            normal_ch = existing_normals_ch.as_deref().unwrap();
-        } else {
-            extend_lifetime = Some(edit_ops::generate_flat_normals_channel(self)?);
-            normal_ch = extend_lifetime.as_ref().unwrap();
-        }
-
-        let mut positions = vec![];
-        let mut normals = vec![];
-
-        for (face_id, _face) in conn.faces.iter() {
-            // We try to be a bit forgiving here. We don't want to stop
-            // rendering even if we have slightly malformed meshes.
-            let normal = normal_ch[face_id];
-
-            let vertices = conn.face_vertices(face_id);
-
-            let v1 = vertices[0];
-
-            for (&v2, &v3) in vertices[1..].iter().tuple_windows() {
-                positions.push(positions_ch[v1]);
-                positions.push(positions_ch[v2]);
-                positions.push(positions_ch[v3]);
-                normals.push(normal);
-                normals.push(normal);
-                normals.push(normal);
-            }
-        }
-
-        Ok(VertexIndexBuffers {
-            indices: (0u32..positions.len() as u32).collect(),
-            positions,
-            normals,
-        })
+        Ok(self
+            .generate_triangle_buffers_with_layout(false, force_gen)?
+            .0)
     }
 
     /// If `force_gen` is true, ignores any existing vertex normals channel in
     /// the mesh and generates one from scratch instead. This is used in some
     /// viewport modes.
     pub fn generate_triangle_buffers_smooth(&self, force_gen: bool) -> Result<VertexIndexBuffers> {
-        let positions_ch = self.read_positions();
-        let conn = self.read_connectivity();
+        Ok(self
+            .generate_triangle_buffers_with_layout(true, force_gen)?
+            .0)
+    }
 
-        // @CopyPaste -- This couldn't get any worse... Are we Java now or what?
-        // This ugliness is needed because we need to either borrow the channel
-        // for the mesh or generate it here, but if we generate inside the if
-        // statement the ref owner gets dropped at the end of the scope.
-        let normal_ch: &Channel<_, _>;
-        #[allow(unused_assignments)] // Look ma, I'm smarter than clippy
-        let mut extend_lifetime = None;
-        let existing_normals_ch = self.read_vertex_normals();
-        if !force_gen && existing_normals_ch.is_some() {
-            normal_ch = existing_normals_ch.as_deref().unwrap();
-        } else {
-            extend_lifetime = Some(edit_ops::generate_smooth_normals_channel(self)?);
-            normal_ch = extend_lifetime.as_ref().unwrap();
+    /// Generates the triangle buffers for this mesh, shaded the way its
+    /// [`MeshGenerationConfig::shading`] says, and their layout. With auto
+    /// smooth, vertices are split along the hard edges of the mesh, the same
+    /// way exporters do, see [`export::normal_split`].
+    pub fn generate_shaded_triangle_buffers(
+        &self,
+    ) -> Result<(VertexIndexBuffers, TriangleBufferLayout)> {
+        match self.gen_config.shading {
+            ShadingMode::Flat => self.generate_triangle_buffers_with_layout(false, false),
+            ShadingMode::Smooth => self.generate_triangle_buffers_with_layout(true, false),
+            ShadingMode::AutoSmooth(_) => {
+                let layout = TriangleBufferLayout {
+                    smooth: true,
+                    auto_smooth: true,
+                    vertices: vec![],
+                    faces: vec![],
+                };
+                let mut buffers = VertexIndexBuffers {
+                    positions: vec![],
+                    normals: vec![],
+                    indices: vec![],
+                };
+                self.update_triangle_buffers(&mut buffers, &layout, false)?;
+                Ok((buffers, layout))
+            }
         }
+    }
 
-        let mut v_id_to_idx =
-            slotmap::SecondaryMap::<VertexId, u32>::with_capacity(conn.vertices.capacity());
-        let mut positions = vec![];
-        let mut normals = vec![];
+    /// Same as [`HalfEdgeMesh::generate_triangle_buffers_smooth`] or
+    /// [`HalfEdgeMesh::generate_triangle_buffers_flat`], depending on
+    /// `smooth`, but the layout of the buffers is returned as well.
+    pub fn generate_triangle_buffers_with_layout(
+        &self,
+        smooth: bool,
+        force_gen: bool,
+    ) -> Result<(VertexIndexBuffers, TriangleBufferLayout)> {
+        let conn = self.read_connectivity();
+        let mut layout = TriangleBufferLayout {
+            smooth,
+            auto_smooth: false,
+            vertices: vec![],
+            faces: vec![],
+        };
+        let mut indices = vec![];
 
-        conn.iter_vertices_with_channel(&positions_ch)
-            .enumerate()
-            .try_for_each::<_, Result<()>>(|(idx, (v_id, _v, pos))| {
+        if smooth {
+            let mut v_id_to_idx =
+                slotmap::SecondaryMap::<VertexId, u32>::with_capacity(conn.vertices.capacity());
+            for (idx, (v_id, _v)) in conn.iter_vertices().enumerate() {
                 v_id_to_idx.insert(v_id, idx as u32);
-                positions.push(pos);
-                normals.push(normal_ch[v_id]);
-                Ok(())
-            })?;
-
-        let mut indices = vec![];
-        for (face_id, _face) in conn.faces.iter() {
-            let vertices = conn.face_vertices(face_id);
-            let v1 = vertices[0];
-            for (&v2, &v3) in vertices[1..].iter().tuple_windows() {
-                indices.push(v_id_to_idx[v1]);
-                indices.push(v_id_to_idx[v2]);
-                indices.push(v_id_to_idx[v3]);
+                layout.vertices.push(v_id);
             }
+            for (face_id, _face) in conn.faces.iter() {
+                let vertices = conn.face_vertices(face_id);
+                let v1 = vertices[0];
+                for (&v2, &v3) in vertices[1..].iter().tuple_windows() {
+                    indices.push(v_id_to_idx[v1]);
+                    indices.push(v_id_to_idx[v2]);
+                    indices.push(v_id_to_idx[v3]);
+                }
+            }
+        } else {
+            for (face_id, _face) in conn.faces.iter() {
+                let vertices = conn.face_vertices(face_id);
+                let v1 = vertices[0];
+                for (&v2, &v3) in vertices[1..].iter().tuple_windows() {
+                    layout.vertices.extend([v1, v2, v3]);
+                    layout.faces.extend([face_id; 3]);
+                }
+            }
+            indices = (0u32..layout.vertices.len() as u32).collect();
         }
+        drop(conn);
 
-        Ok(VertexIndexBuffers {
-            positions,
-            normals,
+        let mut buffers = VertexIndexBuffers {
+            positions: vec![],
+            normals: vec![],
             indices,
-        })
+        };
+        self.update_triangle_buffers(&mut buffers, &layout, force_gen)?;
+        Ok((buffers, layout))
+    }
+
+    /// Replaces the positions and normals of `buffers`, which were generated
+    /// with the given `layout` for a mesh with the same topology as this one.
+    /// The indices are left untouched, except for auto smooth layouts, where
+    /// the buffers are generated again. If `force_gen` is true, normals are
+    /// generated from scratch even if the mesh has a normals channel.
+    pub fn update_triangle_buffers(
+        &self,
+        buffers: &mut VertexIndexBuffers,
+        layout: &TriangleBufferLayout,
+        force_gen: bool,
+    ) -> Result<()> {
+        if layout.auto_smooth {
+            let split = export::normal_split(self)?;
+            *buffers = VertexIndexBuffers {
+                positions: split.positions,
+                normals: split.normals,
+                indices: split.indices,
+            };
+            return Ok(());
+        }
+
+        let positions_ch = self.read_positions();
+        buffers.positions.clear();
+        buffers
+            .positions
+            .extend(layout.vertices.iter().map(|v| positions_ch[*v]));
+
+        buffers.normals.clear();
+        if layout.smooth {
+            let generated;
+            let existing = self.read_vertex_normals();
+            let normal_ch: &Channel<_, _> = match &existing {
+                Some(existing) if !force_gen => &**existing,
+                _ => {
+                    generated = edit_ops::generate_smooth_normals_channel(self)?;
+                    &generated
+                }
+            };
+            buffers
+                .normals
+                .extend(layout.vertices.iter().map(|v| normal_ch[*v]));
+        } else {
+            let generated;
+            let existing = self.read_face_normals();
+            let normal_ch: &Channel<_, _> = match &existing {
+                Some(existing) if !force_gen => &**existing,
+                _ => {
+                    generated = edit_ops::generate_flat_normals_channel(self)?;
+                    &generated
+                }
+            };
+            // We try to be a bit forgiving here. We don't want to stop
+            // rendering even if we have slightly malformed meshes.
+            buffers
+                .normals
+                .extend(layout.faces.iter().map(|f| normal_ch[*f]));
+        }
+        Ok(())
     }
 
-    pub fn generate_face_overlay_buffers(&self, hover: Option<u32>) -> FaceOverlayBuffers {
+    /// Generates the [`FaceOverlayBuffers`] for this mesh, where the `hover`
+    /// face and the `highlighted` ones stand out. When `materials` are given,
+    /// the other faces are tinted with the base color of their material. The
+    /// `heatmap` is the name of an f32 vertex channel to show instead, with
+    /// each triangle colored by the average value of its vertices.
+    pub fn generate_face_overlay_buffers(
+        &self,
+        hover: Option<u32>,
+        highlighted: &HashSet<FaceId>,
+        materials: Option<&MaterialTable>,
+        heatmap: Option<&str>,
+    ) -> FaceOverlayBuffers {
+        let face_materials = materials.zip(self.face_material_indices());
         let positions_ch = self.read_positions();
         let conn = self.read_connectivity();
+        // A missing channel shows as all zeros, like a freshly created one.
+        let heatmap = heatmap.map(|name| {
+            self.channels
+                .read_channel_by_name::<VertexId, f32>(name)
+                .ok()
+        });
 
         let mut positions = vec![];
         let mut colors = vec![];
@@ -169,10 +262,18 @@ impl HalfEdgeMesh {
         // but for now let's keep it simple and recompute this when needed.
         let mapping = conn.face_mapping();
 
-        for (_, (face_id, _face)) in conn.faces.iter().enumerate() {
+        for (idx, (face_id, _face)) in conn.faces.iter().enumerate() {
             let id_u32 = mapping[face_id];
             max_id = u32::max(max_id, id_u32);
 
+            let material_color = face_materials.as_ref().map(|(materials, faces)| {
+                let (_, material) = faces[idx];
+                materials
+                    .get(materials.resolve(material))
+                    .base_color
+                    .extend(0.6)
+            });
+
             let vertices = conn.face_vertices(face_id);
             let v1 = vertices[0];
             for (&v2, &v3) in vertices[1..].iter().tuple_windows() {
@@ -184,16 +285,21 @@ impl HalfEdgeMesh {
                 // id buffer, so we need a way to distinguish actual ids, and
                 // zero is an otherwise valid id.
                 let id = id_u32 + 1;
-                let color_alpha = if hover.is_some_and_(|h| *h == id) {
-                    0.5
+                let color = if hover.is_some_and_(|h| *h == id) {
+                    Vec4::new(0.2, 0.8, 0.2, 0.5)
+                } else if highlighted.contains(&face_id) {
+                    Vec4::new(0.9, 0.7, 0.1, 0.6)
+                } else if let Some(values) = &heatmap {
+                    let value = |v: VertexId| values.as_ref().map(|ch| ch[v]).unwrap_or(0.0);
+                    heat_color((value(v1) + value(v2) + value(v3)) / 3.0)
                 } else {
-                    0.0
+                    material_color.unwrap_or(Vec4::new(0.2, 0.8, 0.2, 0.0))
                 };
 
                 positions.push(v1_pos);
                 positions.push(v2_pos);
                 positions.push(v3_pos);
-                colors.push(Vec4::new(0.2, 0.8, 0.2, color_alpha));
+                colors.push(color);
                 ids.push(id_u32 + 1);
             }
         }
@@ -337,3 +443,90 @@ impl HalfEdgeMesh {
         Ok(LineBuffers { colors, positions })
     }
 }
+
+/// Maps `t`, from 0 to 1, to a color going from blue to green to red. Values
+/// outside that range are clamped.
+pub fn heat_color(t: f32) -> Vec4 {
+    let t = t.clamp(0.0, 1.0);
+    let low = Vec3::new(0.1, 0.3, 0.9);
+    let mid = Vec3::new(0.2, 0.8, 0.2);
+    let high = Vec3::new(0.9, 0.1, 0.1);
+    let color = if t < 0.5 {
+        low.lerp(mid, t * 2.0)
+    } else {
+        mid.lerp(high, t * 2.0 - 1.0)
+    };
+    color.extend(0.7)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update_triangle_buffers() {
+        for smooth in [false, true] {
+            let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+            let (mut buffers, layout) = cube
+                .generate_triangle_buffers_with_layout(smooth, true)
+                .unwrap();
+            let faces = cube.read_connectivity().num_faces();
+            assert_eq!(buffers.indices.len(), faces * 2 * 3);
+
+            let deformed = cube.clone();
+            deformed.apply_transform(Mat4::from_scale(Vec3::splat(2.0)));
+            deformed
+                .update_triangle_buffers(&mut buffers, &layout, true)
+                .unwrap();
+            let expected = if smooth {
+                deformed.generate_triangle_buffers_smooth(true).unwrap()
+            } else {
+                deformed.generate_triangle_buffers_flat(true).unwrap()
+            };
+            assert_eq!(buffers.positions, expected.positions);
+            assert_eq!(buffers.normals, expected.normals);
+            assert_eq!(buffers.indices, expected.indices);
+        }
+    }
+
+    #[test]
+    fn test_auto_smooth_buffers() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        edit_ops::set_shading(&mut cube, ShadingMode::AutoSmooth(30f32.to_radians())).unwrap();
+        let (buffers, _) = cube.generate_shaded_triangle_buffers().unwrap();
+        // All the edges of the cube are hard, so it looks flat
+        let flat = cube.generate_triangle_buffers_flat(true).unwrap();
+        assert_eq!(buffers.positions.len(), 24);
+        assert_eq!(buffers.indices.len(), flat.indices.len());
+        assert!(buffers
+            .normals
+            .iter()
+            .all(|n| flat.normals.iter().any(|f| f.abs_diff_eq(*n, 1e-5))));
+
+        cube.gen_config.shading = ShadingMode::AutoSmooth(100f32.to_radians());
+        let (buffers, _) = cube.generate_shaded_triangle_buffers().unwrap();
+        assert_eq!(buffers.positions.len(), 8);
+    }
+
+    #[test]
+    fn test_heatmap_overlay() {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        {
+            let ch_id = cube.channels.ensure_channel::<VertexId, f32>("mask");
+            let conn = cube.read_connectivity();
+            let positions = cube.read_positions();
+            let mut mask = cube.channels.write_channel(ch_id).unwrap();
+            for (v, _) in conn.iter_vertices() {
+                mask[v] = if positions[v].y > 0.0 { 1.0 } else { 0.0 };
+            }
+        }
+        let buffers = cube.generate_face_overlay_buffers(None, &HashSet::new(), None, Some("mask"));
+        // The top face is all red, the bottom one all blue
+        assert!(buffers.colors.contains(&heat_color(1.0)));
+        assert!(buffers.colors.contains(&heat_color(0.0)));
+        assert!(buffers.colors.iter().all(|c| c.w == 0.7));
+
+        let buffers = cube.generate_face_overlay_buffers(None, &HashSet::new(), None, None);
+        assert!(buffers.colors.iter().all(|c| c.w == 0.0));
+    }
+}
@@ -7,7 +7,7 @@
 use super::*;
 use crate::{
     lua_engine::{lua_stdlib::LVec3, ToLuaError},
-    sync::RefCounted,
+    sync::{BorrowedRef, InteriorMutable, MutableRef, RefCounted},
 };
 use mlua::{Function, Lua, ToLua, Value};
 
@@ -50,6 +50,10 @@ mod lua_api {
     #[lua(under = "Types")]
     const BOOL: ChannelValueType = ChannelValueType::bool;
 
+    /// The type of string tag channels associated to a mesh element.
+    #[lua(under = "Types")]
+    const TAG: ChannelValueType = ChannelValueType::Tag;
+
     #[lua_impl]
     impl HalfEdgeMesh {
         // ==== CORE ====
@@ -73,11 +77,11 @@ mod lua_api {
             lua: &'lua Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
         ) -> Result<mlua::Table<'lua>> {
             let ch_id = self
                 .channels
-                .channel_id_dyn(kty, vty, &name)
+                .channel_id_dyn(kty, vty, name)
                 .ok_or_else(|| anyhow::anyhow!("Channel '{name}' not found"))?;
             mesh_channel_to_lua_table(lua, self, kty, vty, ch_id, LuaTableKind::Sequential)
         }
@@ -93,11 +97,11 @@ mod lua_api {
             lua: &'lua Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
         ) -> Result<mlua::Table<'lua>> {
             let ch_id = self
                 .channels
-                .channel_id_dyn(kty, vty, &name)
+                .channel_id_dyn(kty, vty, name)
                 .ok_or_else(|| anyhow::anyhow!("Channel '{name}' not found"))?;
             mesh_channel_to_lua_table(lua, self, kty, vty, ch_id, LuaTableKind::Associative)
         }
@@ -111,17 +115,23 @@ mod lua_api {
         ///
         /// The shared channel can be used like a regular Lua table, using the
         /// index operator to query or set keys, using the right channel keys as
-        /// values.
+        /// values. It also has methods to work on all the elements at once:
+        /// `len`, `to_table`, `fill`, `copy_from` and `map`. These are much
+        /// faster than indexing the channel for every element. See
+        /// [`SharedChannel`] for details.
         #[lua(hidden)]
         fn get_shared_channel(
             &self,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
         ) -> Result<SharedChannel> {
-            self.channels
-                .channel_rc_dyn(kty, vty, &name)
-                .map(|x| SharedChannel(x))
+            let channel = self.channels.channel_rc_dyn(kty, vty, name)?;
+            let keys = mesh_element_keys(&self.read_connectivity(), kty);
+            Ok(SharedChannel {
+                channel,
+                keys: RefCounted::new(keys),
+            })
         }
 
         /// Sets a mesh channel with key type `kty`, value type `vty` and `name`
@@ -134,11 +144,10 @@ mod lua_api {
             lua: &Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
             table: Table,
         ) -> Result<()> {
             use slotmap::Key;
-            let name: String = name;
             let conn = self.read_connectivity();
             let keys: Box<dyn Iterator<Item = u64>> = match kty {
                 ChannelKeyType::VertexId => {
@@ -152,7 +161,7 @@ mod lua_api {
                 }
             };
             self.channels
-                .dyn_write_channel_by_name(kty, vty, &name)?
+                .dyn_write_channel_by_name(kty, vty, name)?
                 .set_from_seq_table(keys, lua, table)
         }
 
@@ -166,26 +175,34 @@ mod lua_api {
             lua: &Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
             table: Table,
         ) -> Result<()> {
-            let name: String = name;
             self.channels
-                .dyn_write_channel_by_name(kty, vty, &name)?
+                .dyn_write_channel_by_name(kty, vty, name)?
                 .set_from_assoc_table(lua, table)
         }
 
         /// Same as `HalfEdgeMesh::get_channel`, but creates the channel if one
-        /// didn't exist already.
+        /// didn't exist already. When `default` is given, a newly created
+        /// channel returns it for every element, including the ones added to
+        /// the mesh later on. An existing channel keeps its values.
         #[lua(hidden)]
         fn ensure_channel<'lua>(
             &mut self,
             lua: &'lua Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
+            default: Option<Value<'lua>>,
         ) -> Result<Table<'lua>> {
-            let id = self.channels.ensure_channel_dyn(kty, vty, &name);
+            let existed = self.channels.channel_id_dyn(kty, vty, name).is_some();
+            let id = self.channels.ensure_channel_dyn(kty, vty, name);
+            if let (false, Some(default)) = (existed, default) {
+                self.channels
+                    .dyn_write_channel(kty, vty, id)?
+                    .set_default_lua(lua, default)?;
+            }
             mesh_channel_to_lua_table(lua, self, kty, vty, id, LuaTableKind::Sequential)
         }
 
@@ -197,12 +214,41 @@ mod lua_api {
             lua: &'lua Lua,
             kty: ChannelKeyType,
             vty: ChannelValueType,
-            name: String,
+            name: ChannelName,
         ) -> Result<Table<'lua>> {
-            let id = self.channels.ensure_channel_dyn(kty, vty, &name);
+            let id = self.channels.ensure_channel_dyn(kty, vty, name);
             mesh_channel_to_lua_table(lua, self, kty, vty, id, LuaTableKind::Associative)
         }
 
+        /// Returns whether this mesh has a channel with key type `kty` and
+        /// `name`, with any value type.
+        #[lua(hidden)]
+        fn has_channel(&self, kty: ChannelKeyType, name: ChannelName) -> bool {
+            self.channels.has_channel(kty, name)
+        }
+
+        /// Returns a sequence with a table for every channel of this mesh,
+        /// with its `name`, `key_type`, `value_type` and `len`, the number of
+        /// values in the channel.
+        #[lua(hidden)]
+        fn channels(&self) -> Vec<ChannelInfo> {
+            self.channel_infos()
+        }
+
+        /// Tags the elements with key type `kty` in the `selection` with the
+        /// string `value`, in the tag channel `name`. The channel is created
+        /// when it doesn't exist, leaving the rest of the elements untagged.
+        #[lua(hidden)]
+        fn set_tag(
+            &mut self,
+            kty: ChannelKeyType,
+            name: ChannelName,
+            selection: SelectionExpression,
+            value: String,
+        ) -> Result<()> {
+            tags::set_tag(self, kty, name, &selection, &value)
+        }
+
         // ==== ITERATION ====
 
         /// Returns an iterator over the vertices of this mesh. Vertex ids are
@@ -431,22 +477,32 @@ fn mesh_channel_to_lua_table<'lua>(
     ch_id: RawChannelId,
     kind: LuaTableKind,
 ) -> anyhow::Result<mlua::Table<'lua>> {
-    use slotmap::Key;
-    let conn = mesh.read_connectivity();
-    let keys: Box<dyn Iterator<Item = u64>> = match kty {
-        ChannelKeyType::VertexId => {
-            Box::new(conn.iter_vertices().map(|(v_id, _)| v_id.data().as_ffi()))
-        }
-        ChannelKeyType::FaceId => Box::new(conn.iter_faces().map(|(f_id, _)| f_id.data().as_ffi())),
-        ChannelKeyType::HalfEdgeId => {
-            Box::new(conn.iter_halfedges().map(|(h_id, _)| h_id.data().as_ffi()))
-        }
-    };
+    let keys = mesh_element_keys(&mesh.read_connectivity(), kty);
     let ch = mesh.channels.dyn_read_channel(kty, vty, ch_id)?;
 
     match kind {
-        LuaTableKind::Sequential => Ok(ch.to_seq_table(keys, lua)),
-        LuaTableKind::Associative => Ok(ch.to_assoc_table(keys, lua)),
+        LuaTableKind::Sequential => Ok(ch.to_seq_table(Box::new(keys.into_iter()), lua)),
+        LuaTableKind::Associative => Ok(ch.to_assoc_table(Box::new(keys.into_iter()), lua)),
+    }
+}
+
+/// Returns the keys of the elements of type `kty` in the mesh, in iteration
+/// order, in the u64 format used by the dynamic channel API.
+fn mesh_element_keys(conn: &MeshConnectivity, kty: ChannelKeyType) -> Vec<u64> {
+    use slotmap::Key;
+    match kty {
+        ChannelKeyType::VertexId => conn
+            .iter_vertices()
+            .map(|(v_id, _)| v_id.data().as_ffi())
+            .collect(),
+        ChannelKeyType::FaceId => conn
+            .iter_faces()
+            .map(|(f_id, _)| f_id.data().as_ffi())
+            .collect(),
+        ChannelKeyType::HalfEdgeId => conn
+            .iter_halfedges()
+            .map(|(h_id, _)| h_id.data().as_ffi())
+            .collect(),
     }
 }
 
@@ -478,32 +534,193 @@ fn mesh_reduce<'lua>(
     Ok(acc)
 }
 
-pub struct SharedChannel(pub RefCounted<InteriorMutable<dyn DynChannel>>);
+/// A channel of a mesh, shared with Lua without copying its data.
+///
+/// The bulk methods (`len`, `to_table`, `fill`, `copy_from` and `map`) work on
+/// the elements the mesh had when the channel was obtained, in iteration
+/// order. Every access borrows the channel, and fails with an error instead of
+/// panicking when the channel is already borrowed elsewhere, e.g. when the
+/// function passed to `map` tries to access the channel being mapped.
+pub struct SharedChannel {
+    channel: RefCounted<InteriorMutable<dyn DynChannel>>,
+    keys: RefCounted<Vec<u64>>,
+}
 impl Clone for SharedChannel {
     fn clone(&self) -> Self {
-        Self(RefCounted::clone(&self.0))
+        Self {
+            channel: RefCounted::clone(&self.channel),
+            keys: RefCounted::clone(&self.keys),
+        }
+    }
+}
+
+impl SharedChannel {
+    fn borrow(&self) -> Result<BorrowedRef<dyn DynChannel>> {
+        self.channel
+            .try_borrow()
+            .map_err(|err| anyhow::anyhow!("The channel could not be borrowed: {err}"))
+    }
+
+    fn borrow_mut(&self) -> Result<MutableRef<dyn DynChannel>> {
+        self.channel
+            .try_borrow_mut()
+            .map_err(|err| anyhow::anyhow!("The channel could not be borrowed: {err}"))
     }
 }
 
 impl mlua::UserData for SharedChannel {
     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_meta_method(
-            mlua::MetaMethod::NewIndex,
-            |lua, this, (key, val): (Value, Value)| {
-                this.0.borrow_mut().set_lua(lua, key, val).map_lua_err()?;
-                Ok(())
-            },
-        );
         methods.add_meta_method(mlua::MetaMethod::Index, |lua, this, key: Value| {
-            let value = this.0.borrow().get_lua(lua, key).map_lua_err()?;
+            let value = this
+                .borrow()
+                .map_lua_err()?
+                .get_lua(lua, key)
+                .map_lua_err()?;
             Ok(value)
         });
         methods.add_meta_method(
             mlua::MetaMethod::NewIndex,
             |lua, this, (key, val): (Value, Value)| {
-                this.0.borrow_mut().set_lua(lua, key, val).map_lua_err()?;
+                this.borrow_mut()
+                    .map_lua_err()?
+                    .set_lua(lua, key, val)
+                    .map_lua_err()?;
                 Ok(())
             },
         );
+        methods.add_meta_method(mlua::MetaMethod::Len, |_, this, ()| Ok(this.keys.len()));
+        methods.add_method("len", |_, this, ()| Ok(this.keys.len()));
+        methods.add_method("to_table", |lua, this, ()| {
+            let keys = Box::new(this.keys.iter().copied());
+            Ok(this.borrow().map_lua_err()?.to_seq_table(keys, lua))
+        });
+        methods.add_method("fill", |lua, this, value: Value| {
+            this.borrow_mut()
+                .map_lua_err()?
+                .fill_lua(&this.keys, lua, value)
+                .map_lua_err()
+        });
+        methods.add_method("copy_from", |_, this, other: SharedChannel| {
+            // Copying a channel onto itself does nothing, and would fail to
+            // borrow it twice otherwise.
+            let ptr = |ch: &SharedChannel| RefCounted::as_ptr(&ch.channel) as *const u8;
+            if ptr(this) == ptr(&other) {
+                return Ok(());
+            }
+            let other_channel = other.borrow().map_lua_err()?;
+            this.borrow_mut()
+                .map_lua_err()?
+                .copy_from_dyn(&this.keys, &*other_channel, &other.keys)
+                .map_lua_err()
+        });
+        methods.add_method("map", |lua, this, f: Function| {
+            this.borrow_mut()
+                .map_lua_err()?
+                .map_lua(&this.keys, lua, &f)
+                .map_lua_err()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shared_positions(mesh: &HalfEdgeMesh) -> SharedChannel {
+        let channel = mesh
+            .channels
+            .channel_rc_dyn(ChannelKeyType::VertexId, ChannelValueType::Vec3, "position")
+            .unwrap();
+        let keys = mesh_element_keys(&mesh.read_connectivity(), ChannelKeyType::VertexId);
+        SharedChannel {
+            channel,
+            keys: RefCounted::new(keys),
+        }
+    }
+
+    fn all_positions(mesh: &HalfEdgeMesh) -> Vec<Vec3> {
+        let positions = mesh.read_positions();
+        mesh.read_connectivity()
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect()
+    }
+
+    #[test]
+    fn test_shared_channel_bulk_ops() {
+        let lua = Lua::new();
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let before = all_positions(&mesh);
+        lua.globals()
+            .set("positions", shared_positions(&mesh))
+            .unwrap();
+
+        lua.load(
+            r#"
+            assert(positions:len() == 8)
+            assert(#positions == 8)
+            positions:map(function(v, _) return vector(v.x, v.y + 1, v.z) end)
+            local t = positions:to_table()
+            assert(#t == 8)
+            assert(typeof(t[1]) == "vector")
+            "#,
+        )
+        .exec()
+        .unwrap();
+        for (a, b) in all_positions(&mesh).iter().zip(before) {
+            assert_eq!(*a, b + Vec3::Y);
+        }
+
+        lua.load("positions:fill(vector(1, 2, 3))").exec().unwrap();
+        assert!(all_positions(&mesh)
+            .iter()
+            .all(|p| *p == Vec3::new(1.0, 2.0, 3.0)));
+
+        let other = primitives::Box::build(Vec3::splat(5.0), Vec3::ONE).unwrap();
+        lua.globals()
+            .set("other", shared_positions(&other))
+            .unwrap();
+        lua.load("positions:copy_from(other)").exec().unwrap();
+        assert_eq!(all_positions(&mesh), all_positions(&other));
+
+        // Reading the channel while it's being mapped fails cleanly
+        let err = lua
+            .load("positions:map(function(v, k) return positions[k] end)")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("could not be borrowed"), "{err}");
+    }
+
+    /// Compares `map` against indexing the channel once per element. Run
+    /// with `cargo test --release -- --ignored bench_shared_channel_map
+    /// --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_shared_channel_map() {
+        let lua = Lua::new();
+        let mesh = primitives::UVSphere::build(Vec3::ZERO, 256, 256, 1.0).unwrap();
+        let keys = lua
+            .create_sequence_from(mesh.read_connectivity().iter_vertices().map(|(v, _)| v))
+            .unwrap();
+        lua.globals()
+            .set("positions", shared_positions(&mesh))
+            .unwrap();
+        lua.globals().set("keys", keys).unwrap();
+
+        let time = |code: &str| {
+            let start = std::time::Instant::now();
+            lua.load(code).exec().unwrap();
+            start.elapsed()
+        };
+        let naive = time(
+            r#"
+            for _, k in ipairs(keys) do
+                local v = positions[k]
+                positions[k] = vector(v.x, v.y + 1, v.z)
+            end
+            "#,
+        );
+        let map = time("positions:map(function(v, _) return vector(v.x, v.y + 1, v.z) end)");
+        println!("Naive loop: {naive:?}, map: {map:?}");
     }
 }
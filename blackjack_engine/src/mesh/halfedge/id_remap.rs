@@ -0,0 +1,331 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Selections refer to mesh elements by their position in the mesh's
+//! iteration order. These ids are only stable as long as ops don't remove,
+//! reorder or rebuild elements. Ops that do can report an [`IdRemap`], that
+//! describes where each element of their input ended up in their output.
+//!
+//! The remaps are accumulated in the [`IdLineage`] of a mesh, next to the
+//! nodes that produced it. This lets the graph interpreter translate a
+//! selection picked against the output of some node into the ids of a mesh
+//! further down the graph.
+//!
+//! Ops that don't report a remap are assumed to keep the ids of the existing
+//! elements of their input mesh.
+
+use std::sync::Arc;
+
+use slotmap::{SecondaryMap, SlotMap};
+
+use crate::graph::BjkNodeId;
+use crate::prelude::*;
+
+use super::selection::{SelectionExpression, SelectionKind};
+
+/// Describes how the ids of one kind of element change during an op.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElementRemap {
+    /// At position `i`, the ids of the output elements the input element `i`
+    /// became. A single id for elements that were kept, several of them for
+    /// elements that were split, and none for removed elements.
+    targets: Vec<SVec<u32>>,
+}
+
+impl ElementRemap {
+    pub fn new(targets: Vec<SVec<u32>>) -> Self {
+        Self { targets }
+    }
+
+    /// A remap where each of the `len` elements keeps its id.
+    pub fn identity(len: usize) -> Self {
+        Self {
+            targets: (0..len as u32).map(|i| smallvec::smallvec![i]).collect(),
+        }
+    }
+
+    /// Returns the output ids for input element `id`. Ids not covered by this
+    /// remap are considered removed.
+    pub fn get(&self, id: u32) -> &[u32] {
+        self.targets
+            .get(id as usize)
+            .map(|t| t.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The number of input elements covered by this remap.
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Returns the remap resulting from applying this remap, and then `other`.
+    pub fn then(&self, other: &ElementRemap) -> ElementRemap {
+        ElementRemap {
+            targets: self
+                .targets
+                .iter()
+                .map(|ts| ts.iter().flat_map(|t| other.get(*t)).copied().collect())
+                .collect(),
+        }
+    }
+}
+
+/// Describes how the ids of all the elements of a mesh change during an op.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdRemap {
+    pub vertices: ElementRemap,
+    pub faces: ElementRemap,
+    pub halfedges: ElementRemap,
+}
+
+impl IdRemap {
+    /// A remap where all the elements of `conn` keep their ids.
+    pub fn identity(conn: &MeshConnectivity) -> Self {
+        Self {
+            vertices: ElementRemap::identity(conn.num_vertices()),
+            faces: ElementRemap::identity(conn.num_faces()),
+            halfedges: ElementRemap::identity(conn.num_halfedges()),
+        }
+    }
+
+    /// Returns the remap resulting from applying this remap, and then `other`.
+    pub fn then(&self, other: &IdRemap) -> IdRemap {
+        IdRemap {
+            vertices: self.vertices.then(&other.vertices),
+            faces: self.faces.then(&other.faces),
+            halfedges: self.halfedges.then(&other.halfedges),
+        }
+    }
+
+    pub fn element_remap(&self, kind: SelectionKind) -> &ElementRemap {
+        match kind {
+            SelectionKind::Vertices => &self.vertices,
+            SelectionKind::Faces => &self.faces,
+            SelectionKind::Edges | SelectionKind::HalfEdges => &self.halfedges,
+        }
+    }
+
+    /// Translates a selection of elements of the given `kind` in the input
+    /// mesh into a selection of the elements they became in the output mesh.
+    ///
+    /// Groups and tags are kept as they are: They are stored in mesh
+    /// channels, which ops already keep up to date.
+    pub fn remap_selection(
+        &self,
+        selection: &SelectionExpression,
+        kind: SelectionKind,
+    ) -> SelectionExpression {
+        match selection {
+            SelectionExpression::All | SelectionExpression::None => selection.clone(),
+            SelectionExpression::Explicit(_) => {
+                let remap = self.element_remap(kind);
+                let mut seen = HashSet::new();
+                let ids = selection
+                    .explicit_ids()
+                    .into_iter()
+                    .flat_map(|id| remap.get(id).iter().copied())
+                    .filter(|id| seen.insert(*id))
+                    .collect_vec();
+                SelectionExpression::from_groups_and_ids(selection.groups(), ids)
+            }
+        }
+    }
+}
+
+/// The ids of all the elements in a mesh at some point in time. Taking a
+/// snapshot before an op that edits the mesh in place is enough to compute
+/// the remap for that op, as long as the op doesn't replace existing elements
+/// with new ones.
+pub struct IdSnapshot {
+    vertices: Vec<VertexId>,
+    faces: Vec<FaceId>,
+    halfedges: Vec<HalfEdgeId>,
+}
+
+impl IdSnapshot {
+    pub fn new(conn: &MeshConnectivity) -> Self {
+        Self {
+            vertices: conn.vertices.iter().map(|(v, _)| v).collect(),
+            faces: conn.faces.iter().map(|(f, _)| f).collect(),
+            halfedges: conn.halfedges.iter().map(|(h, _)| h).collect(),
+        }
+    }
+
+    /// Returns the remap from the snapshotted ids to the ids of the same
+    /// elements in `conn`. Elements that no longer exist are reported as
+    /// removed.
+    pub fn remap(&self, conn: &MeshConnectivity) -> IdRemap {
+        fn element_remap<K: slotmap::Key, V>(before: &[K], after: &SlotMap<K, V>) -> ElementRemap {
+            let mut positions = SecondaryMap::<K, u32>::new();
+            for (i, (k, _)) in after.iter().enumerate() {
+                positions.insert(k, i as u32);
+            }
+            ElementRemap::new(
+                before
+                    .iter()
+                    .map(|k| positions.get(*k).copied().into_iter().collect())
+                    .collect(),
+            )
+        }
+        IdRemap {
+            vertices: element_remap(&self.vertices, &conn.vertices),
+            faces: element_remap(&self.faces, &conn.faces),
+            halfedges: element_remap(&self.halfedges, &conn.halfedges),
+        }
+    }
+}
+
+/// Runs `f`, an op that edits `mesh` in place, and records the resulting id
+/// remap in the mesh's lineage. The remap is also returned, next to the
+/// result of the op.
+pub fn with_id_remap<T>(
+    mesh: &mut HalfEdgeMesh,
+    f: impl FnOnce(&mut HalfEdgeMesh) -> Result<T>,
+) -> Result<(T, IdRemap)> {
+    let snapshot = IdSnapshot::new(&mesh.read_connectivity());
+    let result = f(mesh)?;
+    let remap = snapshot.remap(&mesh.read_connectivity());
+    mesh.lineage_mut().push_remap(remap.clone());
+    Ok((result, remap))
+}
+
+#[derive(Debug, Clone)]
+enum LineageStep {
+    /// The mesh was returned as an output of this node.
+    Output(BjkNodeId),
+    /// An op changed the ids of the mesh.
+    Remap(Arc<IdRemap>),
+}
+
+/// The history of a mesh: Which nodes it went through, and which id remaps
+/// happened in between. Cloning a mesh clones its lineage, so meshes derived
+/// from the output of a node keep track of it.
+#[derive(Debug, Clone, Default)]
+pub struct IdLineage {
+    steps: Vec<LineageStep>,
+}
+
+impl IdLineage {
+    /// Records that the mesh was returned as an output of `node`.
+    pub fn push_output(&mut self, node: BjkNodeId) {
+        self.steps.push(LineageStep::Output(node));
+    }
+
+    /// Records that an op changed the ids of the mesh.
+    pub fn push_remap(&mut self, remap: IdRemap) {
+        self.steps.push(LineageStep::Remap(Arc::new(remap)));
+    }
+
+    /// Returns the remaps reported since the mesh was last output by `node`,
+    /// oldest first. Returns `None` when the mesh doesn't come from the output
+    /// of that node.
+    pub fn remaps_since(&self, node: BjkNodeId) -> Option<Vec<&IdRemap>> {
+        let start = self
+            .steps
+            .iter()
+            .rposition(|step| matches!(step, LineageStep::Output(n) if *n == node))?;
+        Some(
+            self.steps[start..]
+                .iter()
+                .filter_map(|step| match step {
+                    LineageStep::Remap(remap) => Some(remap.as_ref()),
+                    LineageStep::Output(_) => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Translates a selection of elements of the output of `node` into the
+    /// ids of the same elements in this mesh. Returns `None` when the mesh
+    /// doesn't come from the output of that node.
+    pub fn remap_selection(
+        &self,
+        node: BjkNodeId,
+        selection: &SelectionExpression,
+        kind: SelectionKind,
+    ) -> Option<SelectionExpression> {
+        let remaps = self.remaps_since(node)?;
+        Some(remaps.iter().fold(selection.clone(), |sel, remap| {
+            remap.remap_selection(&sel, kind)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops;
+
+    #[test]
+    fn test_compose_remaps() {
+        let a = ElementRemap::new(vec![
+            smallvec::smallvec![1],
+            smallvec::smallvec![],
+            smallvec::smallvec![0, 2],
+        ]);
+        let b = ElementRemap::new(vec![
+            smallvec::smallvec![5],
+            smallvec::smallvec![3, 4],
+            smallvec::smallvec![],
+        ]);
+        let c = a.then(&b);
+        assert_eq!(c.get(0), &[3, 4]);
+        assert!(c.get(1).is_empty());
+        assert_eq!(c.get(2), &[5]);
+        assert!(c.get(7).is_empty());
+    }
+
+    #[test]
+    fn test_remap_selection_keeps_groups() {
+        let remap = IdRemap {
+            faces: ElementRemap::new(vec![
+                smallvec::smallvec![0],
+                smallvec::smallvec![],
+                smallvec::smallvec![1, 2, 3],
+            ]),
+            ..Default::default()
+        };
+        let sel = SelectionExpression::parse("@top, 0..3").unwrap();
+        let remapped = remap.remap_selection(&sel, SelectionKind::Faces);
+        assert_eq!(remapped.unparse(), "@top, 0..4");
+    }
+
+    #[test]
+    fn test_snapshot_after_extrude() {
+        fn centroid(mesh: &HalfEdgeMesh, id: u32) -> Vec3 {
+            let conn = mesh.read_connectivity();
+            let positions = mesh.read_positions();
+            let (face, _) = conn.iter_faces().nth(id as usize).unwrap();
+            let verts = conn.face_vertices(face);
+            verts.iter().map(|v| positions[*v]).sum::<Vec3>() / verts.len() as f32
+        }
+
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let top = SelectionExpression::from_ids([2]);
+        let before = centroid(&mesh, 2);
+        let (_, remap) = with_id_remap(&mut mesh, |mesh| {
+            let faces = mesh.resolve_face_selection_full(&top)?;
+            edit_ops::extrude_faces(
+                &mut mesh.write_connectivity(),
+                &mut mesh.write_positions(),
+                &faces,
+                1.0,
+            )
+        })
+        .unwrap();
+
+        // Extrusion keeps all the original faces, the extruded one included.
+        assert_eq!(remap.faces.len(), 6);
+        assert!((0..6).all(|i| remap.faces.get(i).len() == 1));
+        let remapped = remap.remap_selection(&top, SelectionKind::Faces);
+        let id = remapped.explicit_ids()[0];
+        assert!((centroid(&mesh, id) - before).length() > 0.99);
+    }
+}
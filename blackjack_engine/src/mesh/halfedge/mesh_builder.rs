@@ -0,0 +1,503 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+
+use crate::prelude::*;
+
+/// Why a face was rejected while building a mesh. Vertices are referred to by
+/// their index in the list of positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaceErrorReason {
+    /// The face has less than three vertices.
+    TooFewVertices(usize),
+    /// The face refers to a vertex past the end of the positions.
+    IndexOutOfBounds(usize),
+    /// The vertex appears more than once in the face.
+    RepeatedVertex(usize),
+    /// The face has the same vertices as an earlier face.
+    DuplicateFace { other_face: usize },
+    /// The edge between the two vertices is already shared by two faces, so
+    /// this one would make it a fin.
+    NonManifoldEdge(usize, usize),
+    /// An earlier face already goes along the edge in the same direction,
+    /// which means the two faces are wound in opposite directions.
+    WindingConflict {
+        from: usize,
+        to: usize,
+        other_face: usize,
+    },
+    /// The faces around the vertex don't form a single fan, like the two
+    /// tips of a bowtie touching at a vertex.
+    NonManifoldVertex(usize),
+}
+
+impl std::fmt::Display for FaceErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaceErrorReason::TooFewVertices(n) => {
+                write!(f, "it has {n} vertices, but faces need at least three")
+            }
+            FaceErrorReason::IndexOutOfBounds(i) => write!(f, "vertex {i} is out of bounds"),
+            FaceErrorReason::RepeatedVertex(i) => {
+                write!(f, "vertex {i} appears more than once")
+            }
+            FaceErrorReason::DuplicateFace { other_face } => {
+                write!(f, "it has the same vertices as face {other_face}")
+            }
+            FaceErrorReason::NonManifoldEdge(a, b) => write!(
+                f,
+                "the edge between vertices {a} and {b} is already shared by two faces"
+            ),
+            FaceErrorReason::WindingConflict {
+                from,
+                to,
+                other_face,
+            } => write!(
+                f,
+                "face {other_face} already goes from vertex {from} to {to}, so the faces are \
+                 wound in opposite directions"
+            ),
+            FaceErrorReason::NonManifoldVertex(v) => {
+                write!(f, "the faces around vertex {v} don't form a single fan")
+            }
+        }
+    }
+}
+
+/// A face that couldn't be added to a mesh, with the index it was given at,
+/// and the reason why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshBuildError {
+    pub face: usize,
+    pub reason: FaceErrorReason,
+}
+
+impl std::fmt::Display for MeshBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot add face {}: {}", self.face, self.reason)
+    }
+}
+impl std::error::Error for MeshBuildError {}
+
+/// Collects faces to build a [`HalfEdgeMesh`], checking each one before it is
+/// added. Faces that would make the mesh non-manifold are rejected, and leave
+/// the builder as it was, so the mesh is never built with broken
+/// connectivity.
+///
+/// Whether the faces around a vertex form a single fan can only be known once
+/// all the faces are in, so that check happens when building.
+pub struct MeshBuilder<'a> {
+    positions: Cow<'a, [Vec3]>,
+    /// The accepted faces, with the index they were given at.
+    faces: Vec<(usize, SVec<usize>)>,
+    /// The number of faces given so far, accepted or not.
+    num_given: usize,
+    /// The face going along each directed edge.
+    directed_edges: HashMap<(usize, usize), usize>,
+    /// The number of faces around each edge, by its sorted vertices.
+    edge_faces: HashMap<(usize, usize), u8>,
+    /// The first face with each set of vertices, sorted.
+    vertex_sets: HashMap<SVec<usize>, usize>,
+}
+
+fn undirected(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+/// Returns the representative of the group of `i`, in a union-find forest.
+fn root(group: &mut [usize], mut i: usize) -> usize {
+    while group[i] != i {
+        group[i] = group[group[i]];
+        i = group[i];
+    }
+    i
+}
+
+fn sorted_vertices(polygon: &[usize]) -> SVec<usize> {
+    let mut set: SVec<usize> = polygon.iter().copied().collect();
+    set.sort_unstable();
+    set
+}
+
+impl<'a> MeshBuilder<'a> {
+    pub fn new(positions: &'a [Vec3]) -> Self {
+        Self {
+            positions: Cow::Borrowed(positions),
+            faces: vec![],
+            num_given: 0,
+            directed_edges: HashMap::new(),
+            edge_faces: HashMap::new(),
+            vertex_sets: HashMap::new(),
+        }
+    }
+
+    /// Adds more vertex positions after the existing ones, so faces can be
+    /// added as the geometry arrives. Returns the index of the first one.
+    pub fn add_positions(&mut self, positions: &[Vec3]) -> usize {
+        let first = self.positions.len();
+        self.positions.to_mut().extend_from_slice(positions);
+        first
+    }
+
+    /// The number of vertex positions faces can refer to.
+    pub fn num_positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The number of faces accepted so far.
+    pub fn num_faces(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// Adds the face with the given vertex indices. Faces are numbered in the
+    /// order they're given, counting the rejected ones. When the face can't
+    /// be added, the builder is left untouched.
+    pub fn add_face(&mut self, polygon: &[usize]) -> Result<(), MeshBuildError> {
+        let face = self.num_given;
+        self.num_given += 1;
+        let error = |reason| MeshBuildError { face, reason };
+
+        if polygon.len() < 3 {
+            return Err(error(FaceErrorReason::TooFewVertices(polygon.len())));
+        }
+        if let Some(&i) = polygon.iter().find(|i| **i >= self.positions.len()) {
+            return Err(error(FaceErrorReason::IndexOutOfBounds(i)));
+        }
+        if let Some(i) = polygon.iter().duplicates().next() {
+            return Err(error(FaceErrorReason::RepeatedVertex(*i)));
+        }
+        let vertex_set = sorted_vertices(polygon);
+        if let Some(&other_face) = self.vertex_sets.get(&vertex_set) {
+            return Err(error(FaceErrorReason::DuplicateFace { other_face }));
+        }
+        for (&a, &b) in polygon.iter().circular_tuple_windows() {
+            if self.edge_faces.get(&undirected(a, b)).copied().unwrap_or(0) >= 2 {
+                return Err(error(FaceErrorReason::NonManifoldEdge(a, b)));
+            }
+            if let Some(&other_face) = self.directed_edges.get(&(a, b)) {
+                return Err(error(FaceErrorReason::WindingConflict {
+                    from: a,
+                    to: b,
+                    other_face,
+                }));
+            }
+        }
+
+        // The face is valid, so it's committed all at once
+        for (&a, &b) in polygon.iter().circular_tuple_windows() {
+            self.directed_edges.insert((a, b), face);
+            *self.edge_faces.entry(undirected(a, b)).or_default() += 1;
+        }
+        self.vertex_sets.insert(vertex_set, face);
+        self.faces.push((face, polygon.iter().copied().collect()));
+        Ok(())
+    }
+
+    /// Returns a vertex whose faces don't form a single fan, along with the
+    /// faces that are not in the first fan around it, if there's any.
+    fn find_non_manifold_vertex(&self) -> Option<(usize, Vec<usize>)> {
+        // The faces around each vertex, in the order they were added
+        let mut vertex_faces = HashMap::<usize, Vec<usize>>::new();
+        let mut vertex_order = vec![];
+        for (slot, (_, polygon)) in self.faces.iter().enumerate() {
+            for &v in polygon {
+                let faces = vertex_faces.entry(v).or_insert_with(|| {
+                    vertex_order.push(v);
+                    vec![]
+                });
+                faces.push(slot);
+            }
+        }
+
+        for v in vertex_order {
+            let faces = &vertex_faces[&v];
+            // Groups the faces around `v` when they share an edge at `v`,
+            // keeping the first face of each group as its representative.
+            let mut group = (0..faces.len()).collect_vec();
+            let mut by_neighbor = HashMap::<usize, usize>::new();
+            for (i, &slot) in faces.iter().enumerate() {
+                let polygon = &self.faces[slot].1;
+                let n = polygon.len();
+                let pos = polygon.iter().position(|x| *x == v).unwrap();
+                for neighbor in [polygon[(pos + 1) % n], polygon[(pos + n - 1) % n]] {
+                    match by_neighbor.get(&neighbor) {
+                        Some(&j) => {
+                            let (ri, rj) = (root(&mut group, i), root(&mut group, j));
+                            group[ri.max(rj)] = ri.min(rj);
+                        }
+                        None => {
+                            by_neighbor.insert(neighbor, i);
+                        }
+                    }
+                }
+            }
+            let outside_first_fan = (0..faces.len())
+                .filter(|i| root(&mut group, *i) != 0)
+                .map(|i| faces[i])
+                .collect_vec();
+            if !outside_first_fan.is_empty() {
+                return Some((v, outside_first_fan));
+            }
+        }
+        None
+    }
+
+    /// Builds the mesh, failing if the faces around any vertex don't form a
+    /// single fan.
+    pub fn build(self) -> Result<HalfEdgeMesh, MeshBuildError> {
+        if let Some((v, faces)) = self.find_non_manifold_vertex() {
+            return Err(MeshBuildError {
+                face: faces.iter().map(|slot| self.faces[*slot].0).min().unwrap(),
+                reason: FaceErrorReason::NonManifoldVertex(v),
+            });
+        }
+        Ok(self.build_connectivity())
+    }
+
+    /// Builds the mesh, leaving out the faces around vertices that don't
+    /// form a single fan. Only the first fan around those vertices is kept.
+    /// Returns the errors for the faces that were left out.
+    pub fn build_lenient(mut self) -> (HalfEdgeMesh, Vec<MeshBuildError>) {
+        let mut skipped = vec![];
+        // Removing faces can split the fans around their other vertices, so
+        // this goes on until there are no more vertices to fix.
+        while let Some((v, slots)) = self.find_non_manifold_vertex() {
+            for slot in slots.iter().rev() {
+                let (face, _) = self.faces.remove(*slot);
+                skipped.push(MeshBuildError {
+                    face,
+                    reason: FaceErrorReason::NonManifoldVertex(v),
+                });
+            }
+        }
+        skipped.sort_by_key(|err| err.face);
+        (self.build_connectivity(), skipped)
+    }
+
+    /// Builds the halfedges for the accepted faces, which are known to make
+    /// a manifold mesh.
+    fn build_connectivity(self) -> HalfEdgeMesh {
+        let mesh = HalfEdgeMesh::new();
+        {
+            let mut conn = mesh.write_connectivity();
+            let mut positions_ch = mesh.write_positions();
+
+            // Vertices are allocated in the order they're first used, and
+            // positions that no face uses are left out.
+            let mut index_to_vertex = HashMap::<usize, VertexId>::new();
+            for (_, polygon) in &self.faces {
+                for &index in polygon {
+                    index_to_vertex.entry(index).or_insert_with(|| {
+                        conn.alloc_vertex(&mut positions_ch, self.positions[index], None)
+                    });
+                }
+            }
+
+            let mut pair_to_halfedge = HashMap::<(usize, usize), HalfEdgeId>::new();
+            for (_, polygon) in &self.faces {
+                // Cyclically ordered list of the half edge ids of this face.
+                let mut half_edges_in_face = SVec::new();
+                let face = conn.alloc_face(None);
+                for (&a, &b) in polygon.iter().circular_tuple_windows() {
+                    let h = conn.alloc_halfedge(HalfEdge::default());
+                    conn[h].face = Some(face);
+                    conn[face].halfedge = Some(h);
+
+                    let v_a = index_to_vertex[&a];
+                    conn[h].vertex = Some(v_a);
+                    conn[v_a].halfedge = Some(h);
+
+                    half_edges_in_face.push(h);
+                    pair_to_halfedge.insert((a, b), h);
+                    if let Some(&other) = pair_to_halfedge.get(&(b, a)) {
+                        conn[h].twin = Some(other);
+                        conn[other].twin = Some(h);
+                    }
+                }
+                for (&h1, &h2) in half_edges_in_face.iter().circular_tuple_windows() {
+                    conn[h1].next = Some(h2);
+                }
+            }
+
+            // Edges with a single face get a twin with no face, and the
+            // boundary halfedges are linked following each hole.
+            conn.add_boundary_halfedges();
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A row of `n` unit quads along X, with the vertices at the bottom
+    /// numbered `0..=n` and the ones at the top `n+1..=2n+1`.
+    fn strip_positions(n: usize) -> Vec<Vec3> {
+        (0..=n)
+            .map(|i| Vec3::new(i as f32, 0.0, 0.0))
+            .chain((0..=n).map(|i| Vec3::new(i as f32, 0.0, 1.0)))
+            .collect()
+    }
+
+    fn strip_quad(n: usize, i: usize) -> [usize; 4] {
+        [i, i + n + 1, i + n + 2, i + 1]
+    }
+
+    fn add_all(builder: &mut MeshBuilder, faces: &[&[usize]]) -> Vec<MeshBuildError> {
+        faces
+            .iter()
+            .filter_map(|face| builder.add_face(face).err())
+            .collect()
+    }
+
+    #[test]
+    fn test_face_checks() {
+        let positions = strip_positions(2);
+        let mut builder = MeshBuilder::new(&positions);
+        let errors = add_all(
+            &mut builder,
+            &[&[0, 3], &[0, 3, 99], &[0, 3, 0, 1], &[0, 3, 4, 1]],
+        );
+        let reasons = errors
+            .iter()
+            .map(|err| (err.face, &err.reason))
+            .collect_vec();
+        assert_eq!(
+            reasons,
+            vec![
+                (0, &FaceErrorReason::TooFewVertices(2)),
+                (1, &FaceErrorReason::IndexOutOfBounds(99)),
+                (2, &FaceErrorReason::RepeatedVertex(0)),
+            ]
+        );
+        assert_eq!(builder.num_faces(), 1);
+        let mesh = builder.build().unwrap();
+        assert_eq!(mesh.read_connectivity().num_faces(), 1);
+        // Unused positions are left out
+        assert_eq!(mesh.read_connectivity().num_vertices(), 4);
+    }
+
+    #[test]
+    fn test_duplicated_faces() {
+        let positions = strip_positions(1);
+        let mut builder = MeshBuilder::new(&positions);
+        let errors = add_all(&mut builder, &[&[0, 2, 3, 1], &[2, 3, 1, 0], &[1, 3, 2, 0]]);
+        for (err, face) in errors.iter().zip([1, 2]) {
+            assert_eq!(err.face, face);
+            assert_eq!(err.reason, FaceErrorReason::DuplicateFace { other_face: 0 });
+        }
+        assert_eq!(builder.num_faces(), 1);
+    }
+
+    #[test]
+    fn test_fin_face() {
+        // Two quads sharing an edge, and a triangle on that same edge
+        let mut positions = strip_positions(2);
+        positions.push(Vec3::new(1.0, 1.0, 0.5));
+        let tip = positions.len() - 1;
+        let mut builder = MeshBuilder::new(&positions);
+        let (a, b) = (strip_quad(2, 0), strip_quad(2, 1));
+        let errors = add_all(&mut builder, &[&a, &b, &[1, 4, tip]]);
+        assert_eq!(
+            errors,
+            vec![MeshBuildError {
+                face: 2,
+                reason: FaceErrorReason::NonManifoldEdge(1, 4),
+            }]
+        );
+        let mesh = builder.build().unwrap();
+        assert_eq!(mesh.read_connectivity().num_faces(), 2);
+    }
+
+    #[test]
+    fn test_mobius_strip() {
+        // Closing a strip of quads into a loop with a half twist means the
+        // last quad can't be wound consistently with the first one.
+        let n = 4;
+        let positions = strip_positions(n);
+        let mut builder = MeshBuilder::new(&positions);
+        let mut faces = (0..n - 1).map(|i| strip_quad(n, i)).collect_vec();
+        // The last quad connects the top of its left side with the bottom of
+        // the first quad's left side, and the other way around.
+        let last = n - 1;
+        faces.push([last, last + n + 1, 0, n + 1]);
+        let errors = add_all(
+            &mut builder,
+            &faces.iter().map(|f| f.as_slice()).collect_vec(),
+        );
+        assert_eq!(
+            errors,
+            vec![MeshBuildError {
+                face: 3,
+                reason: FaceErrorReason::WindingConflict {
+                    from: 0,
+                    to: n + 1,
+                    other_face: 0,
+                },
+            }]
+        );
+        assert_eq!(builder.num_faces(), 3);
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_bowtie_vertex() {
+        // Two triangles touching only at vertex 0
+        let positions = [
+            Vec3::ZERO,
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(-1.0, 0.0, 1.0),
+            Vec3::new(-1.0, 0.0, -1.0),
+        ];
+        let faces: [&[usize]; 2] = [&[0, 1, 2], &[0, 4, 3]];
+        let mut builder = MeshBuilder::new(&positions);
+        assert!(add_all(&mut builder, &faces).is_empty());
+        assert_eq!(
+            builder.build().unwrap_err(),
+            MeshBuildError {
+                face: 1,
+                reason: FaceErrorReason::NonManifoldVertex(0),
+            }
+        );
+
+        let mut builder = MeshBuilder::new(&positions);
+        add_all(&mut builder, &faces);
+        let (mesh, skipped) = builder.build_lenient();
+        assert_eq!(mesh.read_connectivity().num_faces(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].face, 1);
+
+        // A fan is only complete once all of its faces are in, in any order
+        let fan: [&[usize]; 3] = [&[0, 1, 2], &[0, 4, 3], &[0, 3, 1]];
+        let mut builder = MeshBuilder::new(&positions);
+        assert!(add_all(&mut builder, &fan).is_empty());
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_build_from_polygons_errors() {
+        let positions = strip_positions(1);
+        let err = HalfEdgeMesh::build_from_polygons(&positions, &[[0, 2, 3, 1], [0, 2, 3, 1]])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<MeshBuildError>(),
+            Some(&MeshBuildError {
+                face: 1,
+                reason: FaceErrorReason::DuplicateFace { other_face: 0 },
+            })
+        );
+
+        let (mesh, skipped) = HalfEdgeMesh::build_from_polygons_lenient(
+            &positions,
+            &[vec![0, 2, 3, 1], vec![0, 2], vec![0, 2, 3, 1]],
+        );
+        assert_eq!(mesh.read_connectivity().num_faces(), 1);
+        assert_eq!(skipped.iter().map(|err| err.face).collect_vec(), vec![1, 2]);
+    }
+}
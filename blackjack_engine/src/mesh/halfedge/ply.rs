@@ -0,0 +1,507 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use super::wavefront_obj::{report_skipped_faces, ImportedMesh};
+use crate::prelude::*;
+
+/// The encodings of the body of a PLY file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The types of the values stored in a PLY file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyScalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyScalar::Int8,
+            "uchar" | "uint8" => PlyScalar::UInt8,
+            "short" | "int16" => PlyScalar::Int16,
+            "ushort" | "uint16" => PlyScalar::UInt16,
+            "int" | "int32" => PlyScalar::Int32,
+            "uint" | "uint32" => PlyScalar::UInt32,
+            "float" | "float32" => PlyScalar::Float32,
+            "double" | "float64" => PlyScalar::Float64,
+            _ => bail!("Invalid PLY property type '{name}'"),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::Int8 | PlyScalar::UInt8 => 1,
+            PlyScalar::Int16 | PlyScalar::UInt16 => 2,
+            PlyScalar::Int32 | PlyScalar::UInt32 | PlyScalar::Float32 => 4,
+            PlyScalar::Float64 => 8,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PlyProperty {
+    Scalar(String, PlyScalar),
+    /// A list of values, preceded by their count.
+    List(String, PlyScalar, PlyScalar),
+}
+
+#[derive(Debug)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads the values in the body of a PLY file, one at a time.
+struct PlyBody<'a> {
+    format: PlyFormat,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PlyBody<'a> {
+    fn next(&mut self, ty: PlyScalar) -> Result<f64> {
+        if self.format == PlyFormat::Ascii {
+            let rest = &self.bytes[self.pos..];
+            let start = rest
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .ok_or_else(|| anyhow!("The PLY file ended early"))?;
+            let len = rest[start..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .unwrap_or(rest.len() - start);
+            self.pos += start + len;
+            let token = std::str::from_utf8(&rest[start..start + len])?;
+            return token
+                .parse()
+                .map_err(|err| anyhow!("Invalid PLY value '{token}'. {err}"));
+        }
+
+        let size = ty.size();
+        let mut raw = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or_else(|| anyhow!("The PLY file ended early"))?
+            .to_vec();
+        self.pos += size;
+        // Values are decoded as little endian below
+        if self.format == PlyFormat::BinaryBigEndian {
+            raw.reverse();
+        }
+        Ok(match ty {
+            PlyScalar::Int8 => raw[0] as i8 as f64,
+            PlyScalar::UInt8 => raw[0] as f64,
+            PlyScalar::Int16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            PlyScalar::UInt16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            PlyScalar::Int32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            PlyScalar::UInt32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            PlyScalar::Float32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            PlyScalar::Float64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&raw);
+                f64::from_le_bytes(bytes)
+            }
+        })
+    }
+}
+
+/// Parses the header of a PLY file. Returns the format, the elements, and
+/// the offset where the body starts.
+fn parse_header(bytes: &[u8]) -> Result<(PlyFormat, Vec<PlyElement>, usize)> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| anyhow!("The PLY file has no 'end_header' line"))?;
+    let body_start = bytes[end..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|p| end + p + 1)
+        .unwrap_or(bytes.len());
+    let header = std::str::from_utf8(&bytes[..end])?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        bail!("Not a PLY file");
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in lines {
+        let words = line.split_whitespace().collect_vec();
+        match words.as_slice() {
+            ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(PlyFormat::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(PlyFormat::BinaryBigEndian),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: vec![],
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY property '{name}' outside of an element"))?
+                .properties
+                .push(PlyProperty::List(
+                    name.to_string(),
+                    PlyScalar::parse(count_ty)?,
+                    PlyScalar::parse(item_ty)?,
+                )),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY property '{name}' outside of an element"))?
+                .properties
+                .push(PlyProperty::Scalar(name.to_string(), PlyScalar::parse(ty)?)),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => bail!("Invalid PLY header line '{line}'"),
+        }
+    }
+    let format = format.ok_or_else(|| anyhow!("The PLY file has no format line"))?;
+    Ok((format, elements, body_start))
+}
+
+impl HalfEdgeMesh {
+    /// Reads a mesh from the PLY file at `path`. See
+    /// [`HalfEdgeMesh::read_ply`].
+    pub fn from_ply(path: PathBuf) -> Result<HalfEdgeMesh> {
+        Self::read_ply(std::fs::File::open(path)?)
+    }
+
+    /// Reads a mesh in PLY format, either ascii or binary, from the given
+    /// `reader`. Only the vertex positions and the faces are read, other
+    /// properties and elements are skipped. Faces that would make the mesh
+    /// non-manifold are left out, and reported as a warning of the running
+    /// node.
+    pub fn read_ply(reader: impl Read) -> Result<HalfEdgeMesh> {
+        let imported = Self::parse_ply(reader)?;
+        report_skipped_faces(imported.format, &imported.skipped);
+        Ok(imported.mesh)
+    }
+
+    /// Same as [`HalfEdgeMesh::read_ply`], but the faces that were left out
+    /// are returned instead of reported.
+    pub fn parse_ply(mut reader: impl Read) -> Result<ImportedMesh> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let (format, elements, body_start) = parse_header(&bytes)?;
+        let mut body = PlyBody {
+            format,
+            bytes: &bytes,
+            pos: body_start,
+        };
+
+        let mut positions = vec![];
+        let mut polygons: Vec<SVec<usize>> = vec![];
+        for element in &elements {
+            for _ in 0..element.count {
+                let mut position = Vec3::ZERO;
+                for property in &element.properties {
+                    match property {
+                        PlyProperty::Scalar(name, ty) => {
+                            let value = body.next(*ty)? as f32;
+                            match (element.name.as_str(), name.as_str()) {
+                                ("vertex", "x") => position.x = value,
+                                ("vertex", "y") => position.y = value,
+                                ("vertex", "z") => position.z = value,
+                                _ => {}
+                            }
+                        }
+                        PlyProperty::List(name, count_ty, item_ty) => {
+                            let count = body.next(*count_ty)? as usize;
+                            let items = (0..count)
+                                .map(|_| body.next(*item_ty).map(|i| i as usize))
+                                .collect::<Result<SVec<usize>>>()?;
+                            if element.name == "face"
+                                && matches!(name.as_str(), "vertex_indices" | "vertex_index")
+                            {
+                                polygons.push(items);
+                            }
+                        }
+                    }
+                }
+                if element.name == "vertex" {
+                    positions.push(position);
+                }
+            }
+        }
+        let (mesh, skipped) = HalfEdgeMesh::build_from_polygons_lenient(&positions, &polygons);
+        Ok(ImportedMesh {
+            mesh,
+            format: "PLY",
+            skipped,
+        })
+    }
+
+    /// Saves this mesh as an ascii PLY file at `path`. See
+    /// [`HalfEdgeMesh::write_ply`].
+    pub fn to_ply(&self, path: impl Into<PathBuf>) -> Result<()> {
+        self.write_ply(BufWriter::new(File::create(path.into())?))
+    }
+
+    /// Writes this mesh in ascii PLY format to the given `writer`, with the
+    /// vertex positions and the faces. PLY has no strings, so the vertex and
+    /// face tag channels are written as a list of uchar property with the
+    /// name of the channel, holding the UTF-8 bytes of each tag.
+    pub fn write_ply(&self, mut writer: impl Write) -> Result<()> {
+        let conn = self.read_connectivity();
+        let positions = self.read_positions();
+        let vertex_tags = tags::tag_channels(self, ChannelKeyType::VertexId)
+            .into_iter()
+            .map(|name| {
+                Ok((
+                    name,
+                    self.channels.read_channel_by_name::<VertexId, Tag>(name)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let face_tags = tags::tag_channels(self, ChannelKeyType::FaceId)
+            .into_iter()
+            .map(|name| {
+                Ok((
+                    name,
+                    self.channels.read_channel_by_name::<FaceId, Tag>(name)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format ascii 1.0")?;
+        writeln!(
+            writer,
+            "comment Generated by Blackjack: https://github.com/setzer22/blackjack"
+        )?;
+        writeln!(writer, "element vertex {}", conn.num_vertices())?;
+        for axis in ["x", "y", "z"] {
+            writeln!(writer, "property float {axis}")?;
+        }
+        for (name, _) in &vertex_tags {
+            writeln!(writer, "property list uchar uchar {name}")?;
+        }
+        writeln!(writer, "element face {}", conn.num_faces())?;
+        writeln!(writer, "property list uchar uint vertex_indices")?;
+        for (name, _) in &face_tags {
+            writeln!(writer, "property list uchar uchar {name}")?;
+        }
+        writeln!(writer, "end_header")?;
+
+        fn write_tag(mut writer: impl Write, tag: Tag) -> Result<()> {
+            let bytes = tag.as_str().as_bytes();
+            if bytes.len() > u8::MAX as usize {
+                bail!("The tag '{tag}' is too long to be saved in a PLY file");
+            }
+            write!(writer, " {}", bytes.len())?;
+            for b in bytes {
+                write!(writer, " {b}")?;
+            }
+            Ok(())
+        }
+
+        let mut vertex_index = HashMap::new();
+        for (i, (v, _)) in conn.iter_vertices().enumerate() {
+            vertex_index.insert(v, i);
+            let p = positions[v];
+            write!(writer, "{} {} {}", p.x, p.y, p.z)?;
+            for (_, ch) in &vertex_tags {
+                write_tag(&mut writer, ch[v])?;
+            }
+            writeln!(writer)?;
+        }
+        for (f, _) in conn.iter_faces() {
+            let vertices = conn.face_vertices(f);
+            if vertices.len() > u8::MAX as usize {
+                bail!(
+                    "A face with {} vertices can't be saved in a PLY file",
+                    vertices.len()
+                );
+            }
+            write!(writer, "{}", vertices.len())?;
+            for v in &vertices {
+                write!(writer, " {}", vertex_index[v])?;
+            }
+            for (_, ch) in &face_tags {
+                write_tag(&mut writer, ch[f])?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+    use crate::lua_engine::sandbox::ensure_filesystem_allowed;
+    use crate::mesh::halfedge::conventions::{
+        convert_mesh_in_place, parse_convention, AxisConvention,
+    };
+
+    /// Reads a mesh from the PLY file at `path`, either ascii or binary.
+    /// Only vertex positions and faces are imported. The file is only parsed
+    /// again when it changes. The optional `axes` is the axis convention of
+    /// the file, see `Ops.convert_axes`.
+    #[lua(under = "HalfEdgeMesh")]
+    pub fn from_ply(lua: &mlua::Lua, path: String, axes: Option<String>) -> Result<HalfEdgeMesh> {
+        ensure_filesystem_allowed(lua, "HalfEdgeMesh.from_ply")?;
+        let axes = parse_convention(axes, AxisConvention::default())?;
+        let imported = crate::asset_cache::lua_asset_cache(lua)?
+            .get_or_load(path.as_ref(), |path| {
+                HalfEdgeMesh::parse_ply(std::fs::File::open(path)?)
+            })?;
+        let mesh = imported.to_mesh();
+        convert_mesh_in_place(&mesh, axes, AxisConvention::default())?;
+        Ok(mesh)
+    }
+
+    /// Saves this mesh as an ascii PLY file at a given `path`, with its
+    /// vertex positions, faces and tags. The path's parent folder must
+    /// exist. If there was a file at that path, it will be overwritten.
+    #[lua(under = "HalfEdgeMesh")]
+    pub fn to_ply(lua: &mlua::Lua, mesh: &HalfEdgeMesh, path: String) -> Result<()> {
+        ensure_filesystem_allowed(lua, "HalfEdgeMesh.to_ply")?;
+        mesh.to_ply(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::selection::SelectionExpression;
+
+    const ASCII_QUADS: &str = "ply
+format ascii 1.0
+comment Two quads sharing an edge
+element vertex 6
+property float x
+property float y
+property float z
+property uchar red
+element face 2
+property list uchar int vertex_indices
+end_header
+0 0 0 255
+1 0 0 255
+2 0 0 255
+0 0 1 255
+1 0 1 255
+2 0 1 255
+4 0 3 4 1
+4 1 4 5 2
+";
+
+    #[test]
+    fn test_read_ascii_ply() {
+        let mesh = HalfEdgeMesh::read_ply(ASCII_QUADS.as_bytes()).unwrap();
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        assert_eq!(conn.num_vertices(), 6);
+        assert_eq!(conn.num_faces(), 2);
+        let max_x = conn
+            .iter_vertices()
+            .map(|(v, _)| positions[v].x)
+            .fold(f32::MIN, f32::max);
+        assert_eq!(max_x, 2.0);
+    }
+
+    #[test]
+    fn test_read_binary_ply() {
+        for big_endian in [false, true] {
+            let format = if big_endian { "big" } else { "little" };
+            let mut bytes = format!(
+                "ply\nformat binary_{format}_endian 1.0\nelement vertex 3\nproperty float x\n\
+                 property float y\nproperty float z\nelement face 1\n\
+                 property list uchar uint vertex_indices\nend_header\n"
+            )
+            .into_bytes();
+            let float = |x: f32| {
+                if big_endian {
+                    x.to_be_bytes()
+                } else {
+                    x.to_le_bytes()
+                }
+            };
+            for p in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]] {
+                for x in p {
+                    bytes.extend(float(x));
+                }
+            }
+            bytes.push(3);
+            for i in [0u32, 2, 1] {
+                bytes.extend(if big_endian {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+            let mesh = HalfEdgeMesh::read_ply(bytes.as_slice()).unwrap();
+            assert_eq!(mesh.read_connectivity().num_vertices(), 3);
+            assert_eq!(mesh.read_connectivity().num_faces(), 1);
+        }
+    }
+
+    #[test]
+    fn test_read_bad_ply() {
+        let truncated = ASCII_QUADS.replace("4 1 4 5 2\n", "");
+        assert!(HalfEdgeMesh::read_ply(truncated.as_bytes()).is_err());
+        // Faces that can't be added are left out
+        let bad_index = ASCII_QUADS.replace("4 1 4 5 2", "4 1 4 5 9");
+        let mesh = HalfEdgeMesh::read_ply(bad_index.as_bytes()).unwrap();
+        assert_eq!(mesh.read_connectivity().num_faces(), 1);
+        assert!(HalfEdgeMesh::read_ply("obj\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_write_ply_with_tags() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let top = SelectionExpression::parse("0").unwrap();
+        tags::set_tag(
+            &mut mesh,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &top,
+            "roof",
+        )
+        .unwrap();
+        let mut bytes = vec![];
+        mesh.write_ply(&mut bytes).unwrap();
+        let ply = String::from_utf8(bytes).unwrap();
+
+        assert!(ply.contains(
+            "element face 6\nproperty list uchar uint vertex_indices\n\
+                              property list uchar uchar part\nend_header"
+        ));
+        // "roof" as bytes, after the four vertices of the first face
+        let faces = ply.lines().skip_while(|l| *l != "end_header").skip(9);
+        let faces = faces.collect_vec();
+        assert_eq!(faces.len(), 6);
+        assert!(faces[0].ends_with(" 4 114 111 111 102"), "{}", faces[0]);
+        assert!(faces[1].ends_with(" 0"), "{}", faces[1]);
+
+        // The reader skips the tags
+        let read = HalfEdgeMesh::read_ply(ply.as_bytes()).unwrap();
+        assert_eq!(
+            compare::compare_meshes(&mesh, &read, 1e-6, false).unwrap(),
+            None
+        );
+    }
+}
@@ -8,6 +8,12 @@ use std::f32::consts::PI;
 
 use super::*;
 
+/// Grids displaced by the pixels of a heightmap image.
+pub mod heightmap_image;
+
+/// Branching curves drawn by rewriting rules, for plants and fractals.
+pub mod l_system;
+
 pub struct Box;
 
 impl Box {
@@ -392,6 +398,43 @@ impl Grid {
     }
 }
 
+/// A point cloud with the control points of a lattice, arranged in a regular
+/// grid that fills the box between `min` and `max`. The points are ordered
+/// along X first, then Y, then Z. Used as the control points of
+/// [`edit_ops::lattice_deform`].
+pub struct Lattice;
+impl Lattice {
+    pub fn build(min: Vec3, max: Vec3, resolution: UVec3) -> Result<HalfEdgeMesh> {
+        if resolution.cmplt(UVec3::splat(2)).any() {
+            bail!("A lattice needs at least two points along each axis");
+        }
+        let mesh = HalfEdgeMesh::new();
+        let mut conn = mesh.write_connectivity();
+        let mut pos = mesh.write_positions();
+        for point in lattice_rest_points(min, max, resolution) {
+            conn.alloc_vertex(&mut pos, point, None);
+        }
+        drop(conn);
+        drop(pos);
+        Ok(mesh)
+    }
+}
+
+/// The positions of the control points of an undeformed lattice, in the order
+/// used by [`Lattice`].
+pub fn lattice_rest_points(min: Vec3, max: Vec3, resolution: UVec3) -> Vec<Vec3> {
+    let step = (max - min) / (resolution - UVec3::ONE).as_vec3();
+    let mut points = Vec::with_capacity((resolution.x * resolution.y * resolution.z) as usize);
+    for k in 0..resolution.z {
+        for j in 0..resolution.y {
+            for i in 0..resolution.x {
+                points.push(min + step * UVec3::new(i, j, k).as_vec3());
+            }
+        }
+    }
+    points
+}
+
 fn catenary(x: f32, a: f32) -> f32 {
     a * (x / a).cosh()
 }
@@ -503,6 +546,138 @@ impl Icosahedron {
     }
 }
 
+/// Builds the surface of a cube with `cells` x `cells` quads on each side.
+/// Vertices sit at the integer lattice coordinates on the surface of the cube
+/// `[0, cells]^3`, and are placed in space by `position`. Sides share the
+/// vertices along their seams, so the result is closed.
+///
+/// The `uv` channel lays out the six sides of the cube in a 3x2 grid.
+fn cube_surface(cells: u32, position: impl Fn([u32; 3]) -> Vec3) -> Result<HalfEdgeMesh> {
+    let mut lattice_vertices = HashMap::<[u32; 3], u32>::new();
+    let mut positions = Vec::<Vec3>::new();
+    let mut polygons = Vec::<[u32; 4]>::new();
+    let mut corner_uvs = Vec::<[Vec2; 4]>::new();
+
+    let sides = [0, 1, 2]
+        .into_iter()
+        .flat_map(|axis| [(axis, true), (axis, false)]);
+    for (side, (normal_axis, positive)) in sides.enumerate() {
+        // The tangent axes are ordered so quads are counter-clockwise when
+        // seen from outside the cube.
+        let (t1, t2) = ((normal_axis + 1) % 3, (normal_axis + 2) % 3);
+        let (t1, t2) = if positive { (t1, t2) } else { (t2, t1) };
+        let uv_cell = Vec2::new((side % 3) as f32, (side / 3) as f32);
+
+        for a in 0..cells {
+            for b in 0..cells {
+                let corners = [(a, b), (a + 1, b), (a + 1, b + 1), (a, b + 1)];
+                polygons.push(corners.map(|(i, j)| {
+                    let mut coord = [0; 3];
+                    coord[normal_axis] = if positive { cells } else { 0 };
+                    coord[t1] = i;
+                    coord[t2] = j;
+                    *lattice_vertices.entry(coord).or_insert_with(|| {
+                        positions.push(position(coord));
+                        positions.len() as u32 - 1
+                    })
+                }));
+                corner_uvs.push(corners.map(|(i, j)| {
+                    (uv_cell + Vec2::new(i as f32, j as f32) / cells as f32) / Vec2::new(3.0, 2.0)
+                }));
+            }
+        }
+    }
+
+    let mut mesh = HalfEdgeMesh::build_from_polygons(&positions, &polygons)?;
+
+    let mut uvs = Channel::<HalfEdgeId, Vec3>::new();
+    {
+        let conn = mesh.read_connectivity();
+        // Vertices are allocated in the order their indices first appear in
+        // the polygons, which is the order they were pushed above.
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        for (polygon, corner_uvs) in polygons.iter().zip(&corner_uvs) {
+            for i in 0..4 {
+                let src = vertices[polygon[i] as usize];
+                let dst = vertices[polygon[(i + 1) % 4] as usize];
+                let h = conn.at_vertex(src).halfedge_to(dst).try_end()?;
+                uvs[h] = corner_uvs[i].extend(0.0);
+            }
+        }
+    }
+    let uvs_ch_id = mesh.channels.replace_or_create_channel("uv", uvs);
+    mesh.default_channels.uvs = Some(uvs_ch_id);
+    Ok(mesh)
+}
+
+/// A sphere made only of quads, by projecting a subdivided cube onto it.
+/// Unlike [`UVSphere`], there are no triangle fans at the poles, so it
+/// subdivides cleanly.
+pub struct QuadSphere;
+impl QuadSphere {
+    /// Builds a quad sphere with `subdivisions` x `subdivisions` quads on each
+    /// of the six sides of the cube it's made from.
+    pub fn build(center: Vec3, radius: f32, subdivisions: u32) -> Result<HalfEdgeMesh> {
+        let cells = subdivisions.max(1);
+        cube_surface(cells, |coord| {
+            // Spacing the points by angle, rather than evenly along the
+            // sides, keeps quads close to the same size after projecting.
+            let p = Vec3::from(coord.map(|c| {
+                let t = c as f32 / cells as f32 * 2.0 - 1.0;
+                (t * PI * 0.25).tan()
+            }));
+            center + p.normalize() * radius
+        })
+    }
+}
+
+/// A box with rounded edges and corners, made of quads. The flat sides are
+/// single quads, joined by quad strips along the edges and quad patches at
+/// the corners.
+pub struct RoundedBox;
+impl RoundedBox {
+    /// Builds a rounded box with given `center` and `size`. The edges and
+    /// corners are rounded with `corner_radius`, split into a number of
+    /// `corner_segments`. The radius is clamped to half the smallest side of
+    /// the box, and a radius of 0 builds a regular box.
+    pub fn build(
+        center: Vec3,
+        size: Vec3,
+        corner_radius: f32,
+        corner_segments: u32,
+    ) -> Result<HalfEdgeMesh> {
+        let hsize = size * 0.5;
+        let radius = corner_radius.clamp(0.0, hsize.min_element());
+        let segments = if radius > 0.0 {
+            corner_segments.max(1)
+        } else {
+            0
+        };
+        let cells = 2 * segments + 1;
+
+        // Along each axis, the first and last `segments` cells belong to the
+        // rounded part, and the middle one to the flat part.
+        let axis_coord = |i: u32, hsize: f32| {
+            if i <= segments {
+                -hsize + radius * i as f32 / segments.max(1) as f32
+            } else {
+                hsize - radius * (cells - i) as f32 / segments.max(1) as f32
+            }
+        };
+        cube_surface(cells, |[x, y, z]| {
+            let p = Vec3::new(
+                axis_coord(x, hsize.x),
+                axis_coord(y, hsize.y),
+                axis_coord(z, hsize.z),
+            );
+            // Points on the rounded parts are pushed onto a sphere around the
+            // closest point of the inner box.
+            let inner = p.clamp(-hsize + radius, hsize - radius);
+            center + inner + (p - inner).normalize_or_zero() * radius
+        })
+    }
+}
+
 #[blackjack_macros::blackjack_lua_module]
 mod lua_api {
     use super::*;
@@ -577,6 +752,28 @@ mod lua_api {
         Icosahedron::build(center.0, radius)
     }
 
+    /// Creates a sphere made of quads with given `center` and `radius`, by
+    /// projecting a cube with `subdivisions` x `subdivisions` quads per side
+    /// onto it. Has no poles, so it subdivides better than a UV-sphere. The
+    /// UVs lay out the sides of the cube in a 3x2 grid.
+    #[lua(under = "Primitives")]
+    fn quad_sphere(center: LVec3, radius: f32, subdivisions: u32) -> Result<HalfEdgeMesh> {
+        QuadSphere::build(center.0, radius, subdivisions)
+    }
+
+    /// Creates a box with given `center` and `size`, with its edges and
+    /// corners rounded by `corner_radius`, split into `corner_segments`. It's
+    /// made entirely of quads. A `corner_radius` of 0 creates a regular box.
+    #[lua(under = "Primitives")]
+    fn rounded_box(
+        center: LVec3,
+        size: LVec3,
+        corner_radius: f32,
+        corner_segments: u32,
+    ) -> Result<HalfEdgeMesh> {
+        RoundedBox::build(center.0, size.0, corner_radius, corner_segments)
+    }
+
     /// Creates a polyline with `start` and `end` points split into a number of
     /// `segments`.
     #[lua(under = "Primitives")]
@@ -609,6 +806,100 @@ mod lua_api {
     fn grid(x: u32, y: u32, spacing_x: f32, spacing_y: f32) -> Result<HalfEdgeMesh> {
         Grid::build(x, y, spacing_x, spacing_y)
     }
+
+    /// Creates a grid from the heightmap image at `path`, with one vertex per
+    /// pixel, covering `size` units along the X and Z axes. Vertices are
+    /// displaced along Y by the value of their pixel times `height_scale`.
+    /// Grayscale and color images are supported, where colors are converted
+    /// to their luminance. Images with more than `resolution_cap` pixels along
+    /// a side are downsampled.
+    ///
+    /// The unscaled heights are stored in the `height` vertex channel, and
+    /// the UVs map the image onto the grid. Images are cached until their
+    /// file is modified.
+    #[lua(under = "Primitives")]
+    fn heightmap_image(
+        lua: &mlua::Lua,
+        path: String,
+        size: LVec3,
+        height_scale: f32,
+        resolution_cap: u32,
+    ) -> Result<HalfEdgeMesh> {
+        crate::lua_engine::sandbox::ensure_filesystem_allowed(lua, "Primitives.heightmap_image")?;
+        heightmap_image::HeightmapImage::build(
+            &crate::asset_cache::lua_asset_cache(lua)?,
+            path.as_ref(),
+            size.0.truncate(),
+            height_scale,
+            resolution_cap,
+        )
+    }
+
+    /// Creates the curves drawn by an L-system. The `axiom` is rewritten
+    /// `iterations` times with the `rules`, a table mapping each symbol to
+    /// its production. Symbols with several productions map to a list of
+    /// `{ production, weight }` pairs instead, and one of them is picked at
+    /// random each time, using the `seed`.
+    ///
+    /// The result is drawn by a turtle starting at the origin and heading up,
+    /// where `F` draws a segment of length `step`, `+ - & ^ \ /` turn by
+    /// `angle_deg`, and `[ ]` draw branches. Each branch is a separate
+    /// polyline, and the `depth` vertex channel stores its nesting level.
+    /// Fails when more than `max_segments` segments would be drawn, 100000 by
+    /// default.
+    #[lua(under = "Primitives")]
+    #[allow(clippy::too_many_arguments)]
+    fn l_system(
+        axiom: String,
+        rules: mlua::Table,
+        iterations: u32,
+        angle_deg: f32,
+        step: f32,
+        seed: u32,
+        max_segments: Option<u32>,
+    ) -> Result<HalfEdgeMesh> {
+        let mut parsed = l_system::LSystemRules::new();
+        for pair in rules.pairs::<String, mlua::Value>() {
+            let (symbol, productions) = pair?;
+            let mut chars = symbol.chars();
+            let symbol = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => bail!("L-system rules must rewrite a single symbol, got '{symbol}'"),
+            };
+            match productions {
+                mlua::Value::String(production) => parsed.add(symbol, production.to_str()?, 1.0)?,
+                mlua::Value::Table(options) => {
+                    for option in options.sequence_values::<mlua::Table>() {
+                        let option = option?;
+                        let weight = option.get::<_, Option<f32>>(2)?.unwrap_or(1.0);
+                        parsed.add(symbol, option.get::<_, String>(1)?, weight)?;
+                    }
+                }
+                _ => bail!(
+                    "The rule for '{symbol}' must be a string or a list of \
+                     {{ production, weight }} pairs"
+                ),
+            }
+        }
+        l_system::LSystem::build(
+            &axiom,
+            &parsed,
+            iterations,
+            angle_deg,
+            step,
+            seed,
+            max_segments.unwrap_or(l_system::DEFAULT_MAX_SEGMENTS),
+        )
+    }
+
+    /// Creates the control points of a lattice, a point cloud arranged in a
+    /// grid between `min` and `max`, with the number of points along each
+    /// axis given by `resolution`. Moving the points and passing them to
+    /// `Ops.lattice_deform` deforms other meshes.
+    #[lua(under = "Primitives")]
+    fn lattice(min: LVec3, max: LVec3, resolution: LVec3) -> Result<HalfEdgeMesh> {
+        Lattice::build(min.0, max.0, resolution.0.as_uvec3())
+    }
 }
 
 #[cfg(test)]
@@ -663,4 +954,54 @@ mod test {
     fn test_icosahedron() {
         Icosahedron::build(Vec3::ZERO, 1.).unwrap();
     }
+
+    fn euler_characteristic(mesh: &HalfEdgeMesh) -> i64 {
+        let stats = analysis::mesh_stats(mesh).unwrap();
+        stats.num_vertices as i64 - stats.num_edges as i64 + stats.num_faces as i64
+    }
+
+    #[test]
+    fn test_quad_sphere() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let sphere = QuadSphere::build(center, 2.0, 4).unwrap();
+        assert!(edit_ops::validate(&sphere).is_valid());
+        assert_eq!(euler_characteristic(&sphere), 2);
+        {
+            let conn = sphere.read_connectivity();
+            assert_eq!(conn.num_faces(), 6 * 4 * 4);
+            for (f, _) in conn.iter_faces() {
+                assert_eq!(conn.face_edges(f).len(), 4);
+            }
+        }
+        for (_, pos) in sphere.read_positions().iter() {
+            assert!((pos.distance(center) - 2.0).abs() < 1e-4);
+        }
+        assert!(sphere.read_uvs().is_some());
+    }
+
+    #[test]
+    fn test_rounded_box() {
+        let rounded = RoundedBox::build(Vec3::ZERO, Vec3::new(2.0, 1.0, 1.0), 0.25, 3).unwrap();
+        assert!(edit_ops::validate(&rounded).is_valid());
+        assert_eq!(euler_characteristic(&rounded), 2);
+        assert!(rounded.read_uvs().is_some());
+        let (min, max) = analysis::bounds(&rounded);
+        assert!(min.abs_diff_eq(Vec3::new(-1.0, -0.5, -0.5), 1e-5));
+        assert!(max.abs_diff_eq(Vec3::new(1.0, 0.5, 0.5), 1e-5));
+
+        // Without a radius, it's a regular box
+        let size = Vec3::new(2.0, 1.0, 3.0);
+        let flat = RoundedBox::build(Vec3::ZERO, size, 0.0, 3).unwrap();
+        let plain = Box::build(Vec3::ZERO, size).unwrap();
+        assert_eq!(flat.read_connectivity().num_vertices(), 8);
+        assert_eq!(flat.read_connectivity().num_faces(), 6);
+        let sorted = |mesh: &HalfEdgeMesh| {
+            mesh.read_positions()
+                .iter()
+                .map(|(_, p)| p.to_array())
+                .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+                .collect_vec()
+        };
+        assert_eq!(sorted(&flat), sorted(&plain));
+    }
 }
@@ -0,0 +1,235 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use slotmap::SecondaryMap;
+
+use crate::asset_cache::AssetCache;
+use crate::prelude::*;
+use crate::resources::{luminance, Image};
+
+/// The name of the vertex channel where [`HeightmapImage`] stores the height
+/// of each vertex, as read from the image and before scaling.
+pub const HEIGHT_CHANNEL: &str = "height";
+
+/// The pixels of a heightmap image, converted to a single height value each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightImage {
+    pub width: usize,
+    pub height: usize,
+    /// The heights, row by row. 8 and 16 bit images are mapped to the [0, 1]
+    /// range, while floating point images keep their values.
+    pub values: Vec<f32>,
+}
+
+impl HeightImage {
+    /// Converts the pixels of `image` to their luminance.
+    pub fn from_image(image: &Image) -> Self {
+        Self {
+            width: image.width,
+            height: image.height,
+            values: image.pixels.iter().map(|p| luminance(*p)).collect(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.values[y * self.width + x]
+    }
+
+    /// Returns a smaller copy of this image, with at most `max_side` pixels
+    /// along each side. Each pixel of the result is the average of a square
+    /// block of pixels in this image.
+    pub fn downsample(&self, max_side: usize) -> Self {
+        let factor = div_ceil(self.width.max(self.height), max_side.max(1));
+        if factor <= 1 {
+            return self.clone();
+        }
+        let width = div_ceil(self.width, factor);
+        let height = div_ceil(self.height, factor);
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Blocks at the right and bottom borders may be smaller
+                let xs = x * factor..((x + 1) * factor).min(self.width);
+                let ys = y * factor..((y + 1) * factor).min(self.height);
+                let count = xs.len() * ys.len();
+                let sum: f32 = ys
+                    .flat_map(|y| xs.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| self.get(x, y))
+                    .sum();
+                values.push(sum / count as f32);
+            }
+        }
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+}
+
+/// Integer division, rounding up.
+fn div_ceil(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
+
+/// A grid with one vertex per pixel of a heightmap image, displaced along the
+/// Y axis by the value of each pixel.
+pub struct HeightmapImage;
+impl HeightmapImage {
+    /// Builds the grid for the image at `path`, covering `size` units along
+    /// the X and Z axes, and centered at the origin. Images larger than
+    /// `resolution_cap` pixels along any side are downsampled first. The cap
+    /// is never lower than 2, the smallest possible grid.
+    ///
+    /// Images are read through the given `cache`.
+    pub fn build(
+        cache: &AssetCache,
+        path: &Path,
+        size: Vec2,
+        height_scale: f32,
+        resolution_cap: u32,
+    ) -> Result<HalfEdgeMesh> {
+        let image = HeightImage::from_image(&Image::load_cached(cache, path)?);
+        let resolution_cap = resolution_cap.max(2) as usize;
+        let image = if image.width.max(image.height) > resolution_cap {
+            image.downsample(resolution_cap)
+        } else {
+            image
+        };
+        Self::build_from_image(&image, size, height_scale)
+    }
+
+    /// Same as [`HeightmapImage::build`], for an already loaded `image`.
+    pub fn build_from_image(
+        image: &HeightImage,
+        size: Vec2,
+        height_scale: f32,
+    ) -> Result<HalfEdgeMesh> {
+        let (width, height) = (image.width, image.height);
+        if width < 2 || height < 2 {
+            bail!("Heightmap images need at least 2x2 pixels, got {width}x{height}");
+        }
+
+        // Image columns go along X, and rows along Z
+        let uv = |x: usize, y: usize| {
+            Vec2::new(
+                x as f32 / (width - 1) as f32,
+                y as f32 / (height - 1) as f32,
+            )
+        };
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let xz = (uv(x, y) - Vec2::splat(0.5)) * size;
+                Vec3::new(xz.x, image.get(x, y) * height_scale, xz.y)
+            })
+            .collect_vec();
+        let index = |x: usize, y: usize| y * width + x;
+        let polygons = (0..height - 1)
+            .flat_map(|y| (0..width - 1).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                // Counter-clockwise when seen from above, so faces point up
+                [
+                    index(x, y),
+                    index(x, y + 1),
+                    index(x + 1, y + 1),
+                    index(x + 1, y),
+                ]
+            })
+            .collect_vec();
+        let mut mesh = HalfEdgeMesh::build_from_polygons(&positions, &polygons)?;
+
+        // Vertices are allocated in the order their indices first appear in
+        // the polygons, and iterated in allocation order.
+        let vertex_pixels = polygons.iter().flatten().copied().unique().collect_vec();
+        let mut heights = Channel::<VertexId, f32>::new();
+        let mut pixel_uvs = SecondaryMap::<VertexId, Vec2>::new();
+        {
+            let conn = mesh.read_connectivity();
+            for ((v, _), pixel) in conn.iter_vertices().zip(&vertex_pixels) {
+                let (x, y) = (pixel % width, pixel / width);
+                heights[v] = image.get(x, y);
+                pixel_uvs.insert(v, uv(x, y));
+            }
+        }
+        let mut uvs = Channel::<HalfEdgeId, Vec3>::new();
+        {
+            let conn = mesh.read_connectivity();
+            for (h, _) in conn.iter_halfedges() {
+                let v = conn.at_halfedge(h).vertex().try_end()?;
+                uvs[h] = pixel_uvs[v].extend(0.0);
+            }
+        }
+        mesh.channels
+            .replace_or_create_channel(HEIGHT_CHANNEL, heights);
+        let uvs_ch_id = mesh.channels.replace_or_create_channel("uv", uvs);
+        mesh.default_channels.uvs = Some(uvs_ch_id);
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes a PNG with a horizontal gradient, going from black on the left
+    /// column to white on the right one, and returns its path.
+    fn gradient_png(name: &str, width: u32, height: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        image::GrayImage::from_fn(width, height, |x, _| {
+            image::Luma([(x * 255 / (width - 1)) as u8])
+        })
+        .save(&path)
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_gradient_heights() {
+        let path = gradient_png("blackjack_heightmap_gradient.png", 4, 3);
+        let mesh =
+            HeightmapImage::build(&AssetCache::default(), &path, Vec2::new(3.0, 2.0), 2.0, 256)
+                .unwrap();
+        assert_eq!(mesh.read_connectivity().num_vertices(), 12);
+        assert_eq!(mesh.read_connectivity().num_faces(), 6);
+
+        let positions = mesh.read_positions();
+        let heights = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>(HEIGHT_CHANNEL)
+            .unwrap();
+        for (v, pos) in positions.iter() {
+            // x goes from -1.5 to 1.5, so the column is x + 1.5
+            let expected = (pos.x + 1.5) / 3.0;
+            assert!((heights[v] - expected).abs() < 1e-2);
+            assert!((pos.y - expected * 2.0).abs() < 1e-2);
+        }
+
+        // Faces point up
+        let normals = edit_ops::generate_flat_normals_channel(&mesh).unwrap();
+        assert!(normals.iter().all(|(_, n)| n.y > 0.0));
+        assert!(mesh.read_uvs().is_some());
+    }
+
+    #[test]
+    fn test_downsample() {
+        let image = HeightImage {
+            width: 4,
+            height: 3,
+            values: (0..12).map(|v| v as f32).collect(),
+        };
+        let small = image.downsample(2);
+        assert_eq!((small.width, small.height), (2, 2));
+        // Average of 0, 1, 4 and 5
+        assert_eq!(small.get(0, 0), 2.5);
+        // The bottom blocks only have one row
+        assert_eq!(small.get(1, 1), 10.5);
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Lindenmayer systems: A string of symbols is rewritten a number of times,
+//! replacing each symbol by its production, and the result is drawn by a
+//! turtle walking in 3D space. The turtle understands the usual alphabet:
+//!
+//! - `F`: Moves forward, drawing a segment.
+//! - `f`: Moves forward without drawing.
+//! - `+` / `-`: Turns left / right.
+//! - `&` / `^`: Pitches down / up.
+//! - `\` / `/`: Rolls left / right.
+//! - `|`: Turns around.
+//! - `[` / `]`: Pushes / pops the state of the turtle, to draw branches.
+//!
+//! Any other symbol is only used for rewriting, and ignored when drawing.
+
+use std::f32::consts::PI;
+
+use crate::prelude::*;
+use crate::random::BjkRng;
+
+/// The name of the vertex channel where [`LSystem`] stores the bracket
+/// nesting depth of each vertex. Vertices on the trunk have a depth of 0.
+pub const DEPTH_CHANNEL: &str = "depth";
+
+/// The default cap on the number of segments an L-system can draw.
+pub const DEFAULT_MAX_SEGMENTS: u32 = 100_000;
+
+/// How many symbols, for each allowed segment, the expanded string can have.
+/// Catches rules that blow up without drawing anything, like `X -> XX`.
+const MAX_SYMBOLS_PER_SEGMENT: usize = 16;
+
+/// The rewriting rules of an L-system. Each symbol can have several
+/// productions, one of which is picked at random, proportionally to its
+/// weight, every time the symbol is rewritten. Symbols without rules are
+/// left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct LSystemRules {
+    rules: HashMap<char, Vec<(String, f32)>>,
+}
+
+impl LSystemRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a `production` for `symbol`, chosen with the given `weight` when
+    /// the symbol has more than one production.
+    pub fn add(&mut self, symbol: char, production: impl Into<String>, weight: f32) -> Result<()> {
+        if weight.is_nan() || weight <= 0.0 {
+            bail!("The weight of the rule for '{symbol}' must be positive, got {weight}");
+        }
+        self.rules
+            .entry(symbol)
+            .or_default()
+            .push((production.into(), weight));
+        Ok(())
+    }
+
+    fn production(&self, symbol: char, rng: &mut BjkRng) -> Option<&str> {
+        let productions = self.rules.get(&symbol)?;
+        if productions.len() == 1 {
+            return Some(&productions[0].0);
+        }
+        let total: f32 = productions.iter().map(|(_, w)| w).sum();
+        let mut x = rng.float(0.0, total);
+        for (production, weight) in productions {
+            if x < *weight {
+                return Some(production);
+            }
+            x -= weight;
+        }
+        // Rounding errors may skip all the productions
+        productions.last().map(|(p, _)| p.as_str())
+    }
+}
+
+/// The state of the turtle, saved by `[` and restored by `]`.
+#[derive(Clone, Copy)]
+struct Turtle {
+    position: Vec3,
+    /// Rotates the local frame of the turtle into world space. Locally, the
+    /// turtle heads along +Y, its left is -X and its up is +Z.
+    orientation: Quat,
+    depth: u32,
+    /// The polyline the turtle is drawing, if any.
+    polyline: Option<usize>,
+}
+
+pub struct LSystem;
+impl LSystem {
+    /// Rewrites the `axiom` with the `rules` for a number of `iterations`.
+    /// Random choices between productions use `rng`. Fails when the result
+    /// would draw more than `max_segments` segments.
+    pub fn expand(
+        axiom: &str,
+        rules: &LSystemRules,
+        iterations: u32,
+        rng: &mut BjkRng,
+        max_segments: u32,
+    ) -> Result<String> {
+        let max_symbols = (max_segments as usize).saturating_mul(MAX_SYMBOLS_PER_SEGMENT);
+        let mut current = axiom.to_owned();
+        for i in 0..iterations {
+            let mut next = String::with_capacity(current.len());
+            let mut segments = 0;
+            for symbol in current.chars() {
+                match rules.production(symbol, rng) {
+                    Some(production) => {
+                        next.push_str(production);
+                        segments += production.matches('F').count();
+                    }
+                    None => {
+                        next.push(symbol);
+                        segments += (symbol == 'F') as usize;
+                    }
+                }
+                if segments > max_segments as usize || next.len() > max_symbols {
+                    bail!(
+                        "The L-system grows too large after {} iterations. It would draw \
+                         more than {max_segments} segments. Lower the number of iterations \
+                         or raise the segment limit.",
+                        i + 1
+                    );
+                }
+            }
+            current = next;
+        }
+        Ok(current)
+    }
+
+    /// Expands the L-system and draws it with a turtle that starts at the
+    /// origin heading up (+Y). Each `F` draws a segment of length `step`, and
+    /// turns are `angle_deg` degrees.
+    ///
+    /// The result has one polyline per branch, disconnected from each other.
+    /// The depth of the branches is stored in the [`DEPTH_CHANNEL`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        axiom: &str,
+        rules: &LSystemRules,
+        iterations: u32,
+        angle_deg: f32,
+        step: f32,
+        seed: u32,
+        max_segments: u32,
+    ) -> Result<HalfEdgeMesh> {
+        let mut rng = BjkRng::new(seed as u64);
+        let symbols = Self::expand(axiom, rules, iterations, &mut rng, max_segments)?;
+        let polylines = Self::draw(&symbols, angle_deg.to_radians(), step)?;
+        build_polylines(&polylines)
+    }
+
+    /// Walks the turtle along the `symbols`, returning the points of each
+    /// polyline it draws, along with their depth.
+    fn draw(symbols: &str, angle: f32, step: f32) -> Result<Vec<Vec<(Vec3, u32)>>> {
+        let mut polylines = Vec::<Vec<(Vec3, u32)>>::new();
+        let mut stack = Vec::<Turtle>::new();
+        let mut turtle = Turtle {
+            position: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            depth: 0,
+            polyline: None,
+        };
+        let turn = |turtle: &mut Turtle, local_axis: Vec3, angle: f32| {
+            turtle.orientation =
+                (turtle.orientation * Quat::from_axis_angle(local_axis, angle)).normalize();
+        };
+
+        for symbol in symbols.chars() {
+            match symbol {
+                'F' => {
+                    let polyline = *turtle.polyline.get_or_insert_with(|| {
+                        polylines.push(vec![(turtle.position, turtle.depth)]);
+                        polylines.len() - 1
+                    });
+                    turtle.position += turtle.orientation * Vec3::Y * step;
+                    polylines[polyline].push((turtle.position, turtle.depth));
+                }
+                'f' => {
+                    turtle.position += turtle.orientation * Vec3::Y * step;
+                    turtle.polyline = None;
+                }
+                '+' => turn(&mut turtle, Vec3::Z, angle),
+                '-' => turn(&mut turtle, Vec3::Z, -angle),
+                '&' => turn(&mut turtle, -Vec3::X, angle),
+                '^' => turn(&mut turtle, -Vec3::X, -angle),
+                '\\' => turn(&mut turtle, Vec3::Y, angle),
+                '/' => turn(&mut turtle, Vec3::Y, -angle),
+                '|' => turn(&mut turtle, Vec3::Z, PI),
+                '[' => {
+                    stack.push(turtle);
+                    turtle.depth += 1;
+                    // Branches start a new polyline
+                    turtle.polyline = None;
+                }
+                ']' => {
+                    turtle = stack
+                        .pop()
+                        .context("Unbalanced brackets in the L-system: Found ']' without '['")?;
+                }
+                _ => {}
+            }
+        }
+        Ok(polylines)
+    }
+}
+
+/// Builds a mesh with the given `polylines`, each one made of vertices and
+/// the depth stored for it.
+fn build_polylines(polylines: &[Vec<(Vec3, u32)>]) -> Result<HalfEdgeMesh> {
+    let mut mesh = HalfEdgeMesh::new();
+    let depth_ch_id = mesh.channels.ensure_channel::<VertexId, f32>(DEPTH_CHANNEL);
+    let mut conn = mesh.write_connectivity();
+    let mut positions = mesh.write_positions();
+    let mut depths = mesh.channels.write_channel(depth_ch_id)?;
+
+    for polyline in polylines {
+        let vertices = polyline
+            .iter()
+            .map(|(pos, depth)| {
+                let v = conn.alloc_vertex(&mut positions, *pos, None);
+                depths[v] = *depth as f32;
+                v
+            })
+            .collect_vec();
+
+        // Same layout as `Line::build_with_normals`: A chain of forward
+        // halfedges and a chain of backward ones, tied into a single loop.
+        let mut forward = vec![];
+        let mut backward = vec![];
+        for (v, w) in vertices.iter_cpy().tuple_windows() {
+            let h_v_w = conn.alloc_halfedge(HalfEdge {
+                twin: None,
+                next: None,
+                vertex: Some(v),
+                face: None,
+            });
+            let h_w_v = conn.alloc_halfedge(HalfEdge {
+                twin: None,
+                next: None,
+                vertex: Some(w),
+                face: None,
+            });
+            conn[h_v_w].twin = Some(h_w_v);
+            conn[h_w_v].twin = Some(h_v_w);
+            conn[v].halfedge = Some(h_v_w);
+            conn[w].halfedge = Some(h_w_v);
+            forward.push(h_v_w);
+            backward.push(h_w_v);
+        }
+        for (h, h2) in forward.iter_cpy().tuple_windows() {
+            conn[h].next = Some(h2);
+        }
+        for (h, h2) in backward.iter_cpy().rev().tuple_windows() {
+            conn[h].next = Some(h2);
+        }
+        if let (Some(&f_first), Some(&f_last)) = (forward.first(), forward.last()) {
+            let (b_first, b_last) = (backward[0], backward[backward.len() - 1]);
+            conn[f_last].next = Some(b_last);
+            conn[b_first].next = Some(f_first);
+        }
+    }
+
+    drop(conn);
+    drop(positions);
+    drop(depths);
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn koch_rules() -> LSystemRules {
+        let mut rules = LSystemRules::new();
+        rules.add('F', "F+F-F-F+F", 1.0).unwrap();
+        rules
+    }
+
+    #[test]
+    fn test_koch_curve() {
+        let mesh = LSystem::build("F", &koch_rules(), 2, 90.0, 0.5, 0, 1000).unwrap();
+        let conn = mesh.read_connectivity();
+        // Each iteration replaces every segment by 5
+        assert_eq!(conn.num_vertices(), 26);
+        assert_eq!(conn.iter_halfedges().count(), 2 * 25);
+        assert!(edit_ops::validate(&mesh).is_valid());
+
+        // Each iteration makes the curve reach 3 times further
+        let positions = mesh.read_positions();
+        let points = conn
+            .iter_vertices()
+            .map(|(v, _)| positions[v])
+            .collect_vec();
+        assert!(points[0].abs_diff_eq(Vec3::ZERO, 1e-5));
+        assert!(points[25].abs_diff_eq(Vec3::new(0.0, 4.5, 0.0), 1e-4));
+        // The first turn goes to the left, towards -X
+        assert!(points[2].abs_diff_eq(Vec3::new(-0.5, 0.5, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut rules = LSystemRules::new();
+        rules.add('X', "F[&X]F", 1.0).unwrap();
+        let mesh = LSystem::build("X", &rules, 2, 30.0, 1.0, 0, 1000).unwrap();
+        // F[&F[&X]F]F: The innermost branch draws nothing, so there's only
+        // the trunk and one branch, with three vertices each.
+        assert_eq!(mesh.read_connectivity().num_vertices(), 6);
+        let depths = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>(DEPTH_CHANNEL)
+            .unwrap();
+        let mut depths = depths.iter().map(|(_, d)| *d).collect_vec();
+        depths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(depths, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_stochastic_rules_are_deterministic() {
+        let mut rules = LSystemRules::new();
+        rules.add('F', "F+F", 1.0).unwrap();
+        rules.add('F', "F-F", 1.0).unwrap();
+        let expand = |seed| LSystem::expand("F", &rules, 6, &mut BjkRng::new(seed), 1000).unwrap();
+        assert_eq!(expand(1), expand(1));
+        assert_ne!(expand(1), expand(2));
+        assert!(rules.add('F', "F", 0.0).is_err());
+    }
+
+    #[test]
+    fn test_growth_limit() {
+        let err = LSystem::build("F", &koch_rules(), 10, 90.0, 1.0, 0, 1000).unwrap_err();
+        assert!(err.to_string().contains("more than 1000 segments"), "{err}");
+
+        let err = LSystem::build("F]", &koch_rules(), 1, 90.0, 1.0, 0, 1000).unwrap_err();
+        assert!(err.to_string().contains("Unbalanced"), "{err}");
+    }
+}
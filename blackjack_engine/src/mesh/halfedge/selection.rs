@@ -13,7 +13,9 @@ use std::fmt::Write;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SelectionFragment {
-    Group(String),
+    Group(ChannelName),
+    /// The elements with the given tag in a tag channel.
+    Tag(ChannelName, String),
     Range(Range<u32>),
     Single(u32),
 }
@@ -25,6 +27,7 @@ pub enum SelectionExpression {
     Explicit(Vec<SelectionFragment>),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum SelectionKind {
     Vertices,
     Faces,
@@ -41,13 +44,15 @@ impl SelectionExpression {
     /// * // Select all elements
     /// 0..1 // Select a range of elements
     /// 0..5, 7..10, 13, 17, 22 // Select multiple ranges, and some single faces
+    /// tag(part, "roof") // Select the elements tagged "roof" in the `part` channel
     ///  // (empty string), selects nothing
     /// ```
     pub fn parse(input: &str) -> Result<SelectionExpression> {
+        use nom::bytes::complete::take_while;
         use nom::character::complete::{alphanumeric1, anychar};
         use nom::combinator::verify;
         use nom::multi::many0_count;
-        use nom::sequence::pair;
+        use nom::sequence::{delimited, pair};
         use nom::{
             branch::alt,
             bytes::complete::tag,
@@ -94,8 +99,28 @@ impl SelectionExpression {
             .parse(input)
         }
 
+        fn tag_value(input: &str) -> IResult<&str, &str> {
+            delimited(char('"'), take_while(|c| c != '"'), char('"')).parse(input)
+        }
+
+        fn tag_fragment(input: &str) -> IResult<&str, SelectionFragment> {
+            map(
+                tuple((
+                    tag("tag("),
+                    whitespace,
+                    identifier,
+                    separator,
+                    tag_value,
+                    whitespace,
+                    tag(")"),
+                )),
+                |(_, _, name, _, value, _, _)| SelectionFragment::Tag(name.into(), value.into()),
+            )
+            .parse(input)
+        }
+
         fn selection_fragment(input: &str) -> IResult<&str, SelectionFragment> {
-            alt((group_fragment, range, single)).parse(input)
+            alt((tag_fragment, group_fragment, range, single)).parse(input)
         }
 
         fn fragments_all(input: &str) -> IResult<&str, SelectionExpression> {
@@ -141,6 +166,107 @@ impl SelectionExpression {
         }
     }
 
+    /// Builds an explicit selection with the given `ids`, in order. Runs of
+    /// consecutive ids are merged into ranges. An empty list of ids selects
+    /// nothing.
+    pub fn from_ids(ids: impl IntoIterator<Item = u32>) -> SelectionExpression {
+        fn fragment(r: Range<u32>) -> SelectionFragment {
+            if r.end - r.start == 1 {
+                SelectionFragment::Single(r.start)
+            } else {
+                SelectionFragment::Range(r)
+            }
+        }
+
+        let mut fragments = vec![];
+        let mut run: Option<Range<u32>> = None;
+        for id in ids {
+            match &mut run {
+                Some(r) if r.end == id => r.end += 1,
+                _ => {
+                    if let Some(r) = run.replace(id..id + 1) {
+                        fragments.push(fragment(r));
+                    }
+                }
+            }
+        }
+        fragments.extend(run.map(fragment));
+
+        if fragments.is_empty() {
+            SelectionExpression::None
+        } else {
+            SelectionExpression::Explicit(fragments)
+        }
+    }
+
+    /// Returns the ids listed explicitly in this selection, as single ids or
+    /// ranges, in order. Groups and tags are not included.
+    pub fn explicit_ids(&self) -> Vec<u32> {
+        match self {
+            SelectionExpression::All | SelectionExpression::None => vec![],
+            SelectionExpression::Explicit(fragments) => fragments
+                .iter()
+                .flat_map(|fragment| match fragment {
+                    SelectionFragment::Group(_) | SelectionFragment::Tag(..) => 0..0,
+                    SelectionFragment::Range(r) => r.clone(),
+                    SelectionFragment::Single(i) => *i..*i + 1,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the groups and tags referenced by this selection.
+    pub fn groups(&self) -> Vec<SelectionFragment> {
+        match self {
+            SelectionExpression::All | SelectionExpression::None => vec![],
+            SelectionExpression::Explicit(fragments) => fragments
+                .iter()
+                .filter(|fragment| {
+                    matches!(
+                        fragment,
+                        SelectionFragment::Group(_) | SelectionFragment::Tag(..)
+                    )
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Builds a selection with the given `groups`, which may also have tags,
+    /// followed by the `ids`.
+    pub fn from_groups_and_ids(
+        groups: Vec<SelectionFragment>,
+        ids: impl IntoIterator<Item = u32>,
+    ) -> SelectionExpression {
+        let mut fragments = groups;
+        if let SelectionExpression::Explicit(id_fragments) = Self::from_ids(ids) {
+            fragments.extend(id_fragments);
+        }
+        if fragments.is_empty() {
+            SelectionExpression::None
+        } else {
+            SelectionExpression::Explicit(fragments)
+        }
+    }
+
+    /// Adds `id` to this selection, or removes it when it was already listed.
+    /// Groups and tags are kept as they are. This has no effect on a selection
+    /// of all the elements.
+    pub fn toggle(&mut self, id: u32) {
+        if *self == SelectionExpression::All {
+            return;
+        }
+        let mut ids = self.explicit_ids();
+        if ids.contains(&id) {
+            ids.retain(|x| *x != id);
+        } else {
+            ids.push(id);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        *self = Self::from_groups_and_ids(self.groups(), ids);
+    }
+
     pub fn unparse(&self) -> String {
         match self {
             SelectionExpression::All => "*".into(),
@@ -156,6 +282,9 @@ impl SelectionExpression {
                     }
                     match segment {
                         SelectionFragment::Group(name) => write!(out, "@{name}").unwrap(),
+                        SelectionFragment::Tag(name, value) => {
+                            write!(out, "tag({name}, \"{value}\")").unwrap()
+                        }
                         SelectionFragment::Range(r) => {
                             write!(out, "{}..{}", r.start, r.end).unwrap()
                         }
@@ -168,13 +297,110 @@ impl SelectionExpression {
     }
 }
 
-pub enum ResolvedSelection<Id: slotmap::Key> {
-    All,
-    None,
-    Explicit(Vec<Id>),
+/// The result of `Select.non_manifold`, converted to a Lua table.
+pub struct NonManifoldSelection {
+    pub edges: SelectionExpression,
+    pub vertices: SelectionExpression,
+}
+
+impl<'lua> mlua::ToLua<'lua> for NonManifoldSelection {
+    fn to_lua(self, lua: &'lua mlua::Lua) -> mlua::Result<mlua::Value<'lua>> {
+        let table = lua.create_table()?;
+        table.set("edges", self.edges)?;
+        table.set("vertices", self.vertices)?;
+        Ok(mlua::Value::Table(table))
+    }
+}
+
+/// A fixed-size set of small integers, stored as one bit per value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn with_capacity(len: usize) -> Self {
+        Self {
+            words: vec![0; (len + 63) / 64],
+        }
+    }
+
+    /// Adds `i` to the set, growing it when needed.
+    fn insert(&mut self, i: usize) {
+        if i / 64 >= self.words.len() {
+            self.words.resize(i / 64 + 1, 0);
+        }
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .map(|word| word & (1 << (i % 64)) != 0)
+            .unwrap_or(false)
+    }
+}
+
+/// The slot index of a slotmap key. Slot indices are dense, so they can be
+/// used to index a [`BitSet`] for all the elements of a slotmap.
+fn slot_index(id: impl slotmap::Key) -> usize {
+    (id.data().as_ffi() & 0xffff_ffff) as usize
+}
+
+/// The result of resolving a [`SelectionExpression`] against the elements of
+/// a mesh. Membership can be queried in constant time, so ops don't need to
+/// materialize a `Vec` of ids when they only check whether elements are
+/// selected. Use [`ResolvedSelection::to_vec`] to get the ids.
+#[derive(Clone, Debug)]
+pub struct ResolvedSelection<Id: slotmap::Key> {
+    /// The selected elements, by slot index. `None` when all elements are
+    /// selected.
+    slots: Option<BitSet>,
+    _phantom: std::marker::PhantomData<Id>,
+}
+
+impl<Id: slotmap::Key> ResolvedSelection<Id> {
+    pub fn all() -> Self {
+        Self {
+            slots: None,
+            _phantom: Default::default(),
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            slots: Some(BitSet::default()),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Returns whether every element is selected.
+    pub fn is_all(&self) -> bool {
+        self.slots.is_none()
+    }
+
+    /// Returns whether the element `id` is selected.
+    pub fn contains(&self, id: Id) -> bool {
+        match &self.slots {
+            Some(slots) => slots.contains(slot_index(id)),
+            None => true,
+        }
+    }
+
+    /// Returns the selected elements of `data`, in iteration order. Each
+    /// element is only returned once.
+    pub fn to_vec<V>(&self, data: &SlotMap<Id, V>) -> Vec<Id> {
+        data.iter()
+            .map(|(id, _)| id)
+            .filter(|id| self.contains(*id))
+            .collect()
+    }
 }
 
 impl HalfEdgeMesh {
+    /// Resolves the `fragments` against the elements in `data`. Requested
+    /// positions are gathered once, so the elements are only iterated a
+    /// single time regardless of the number of fragments.
     fn resolve_explicit_selection<K: ChannelKey, V>(
         &self,
         data: &SlotMap<K, V>,
@@ -182,36 +408,50 @@ impl HalfEdgeMesh {
     ) -> Result<ResolvedSelection<K>> {
         match fragments {
             SelectionExpression::Explicit(ref fragments) => {
-                let mut ids = vec![];
-
-                // TODO: Optimize this
-                for (i, (id, _)) in data.iter().enumerate() {
-                    for fragment in fragments {
-                        match fragment {
-                            SelectionFragment::Range(r) => {
-                                if r.contains(&(i as u32)) {
-                                    ids.push(id);
-                                }
-                            }
-                            SelectionFragment::Single(s) => {
-                                if *s == i as u32 {
-                                    ids.push(id);
-                                }
+                let len = data.len();
+                let mut positions = BitSet::with_capacity(len);
+                let mut groups = vec![];
+                let mut tags = vec![];
+                for fragment in fragments {
+                    match fragment {
+                        SelectionFragment::Range(r) => {
+                            let end = (r.end as usize).min(len);
+                            for i in (r.start as usize)..end {
+                                positions.insert(i);
                             }
-                            SelectionFragment::Group(group) => {
-                                let group_ch =
-                                    self.channels.read_channel_by_name::<K, bool>(group)?;
-                                if group_ch[id] {
-                                    ids.push(id);
-                                }
+                        }
+                        SelectionFragment::Single(s) => {
+                            if (*s as usize) < len {
+                                positions.insert(*s as usize);
                             }
                         }
+                        SelectionFragment::Group(group) => {
+                            groups.push(self.channels.read_channel_by_name::<K, bool>(group)?);
+                        }
+                        SelectionFragment::Tag(name, value) => {
+                            let ch = self.channels.read_channel_by_name::<K, Tag>(name)?;
+                            // A tag that was never used can't match any element
+                            tags.push((ch, Tag::find(value)));
+                        }
                     }
                 }
-                Ok(ResolvedSelection::Explicit(ids))
+
+                let mut slots = BitSet::with_capacity(len);
+                for (i, (id, _)) in data.iter().enumerate() {
+                    if positions.contains(i)
+                        || groups.iter().any(|group| group[id])
+                        || tags.iter().any(|(ch, value)| Some(ch[id]) == *value)
+                    {
+                        slots.insert(slot_index(id));
+                    }
+                }
+                Ok(ResolvedSelection {
+                    slots: Some(slots),
+                    _phantom: Default::default(),
+                })
             }
-            SelectionExpression::All => Ok(ResolvedSelection::All),
-            SelectionExpression::None => Ok(ResolvedSelection::None),
+            SelectionExpression::All => Ok(ResolvedSelection::all()),
+            SelectionExpression::None => Ok(ResolvedSelection::none()),
         }
     }
 
@@ -227,16 +467,10 @@ impl HalfEdgeMesh {
         &self,
         fragments: &SelectionExpression,
     ) -> Result<Vec<FaceId>> {
-        match self.resolve_face_selection(fragments)? {
-            ResolvedSelection::All => Ok(self
-                .read_connectivity()
-                .faces
-                .iter()
-                .map(|(a, _)| a)
-                .collect()),
-            ResolvedSelection::None => Ok(vec![]),
-            ResolvedSelection::Explicit(v) => Ok(v),
-        }
+        let conn = self.read_connectivity();
+        Ok(self
+            .resolve_explicit_selection(&conn.faces, fragments)?
+            .to_vec(&conn.faces))
     }
 
     pub fn resolve_vertex_selection(
@@ -251,16 +485,10 @@ impl HalfEdgeMesh {
         &self,
         fragments: &SelectionExpression,
     ) -> Result<Vec<VertexId>> {
-        match self.resolve_vertex_selection(fragments)? {
-            ResolvedSelection::All => Ok(self
-                .read_connectivity()
-                .vertices
-                .iter()
-                .map(|(a, _)| a)
-                .collect()),
-            ResolvedSelection::None => Ok(vec![]),
-            ResolvedSelection::Explicit(v) => Ok(v),
-        }
+        let conn = self.read_connectivity();
+        Ok(self
+            .resolve_explicit_selection(&conn.vertices, fragments)?
+            .to_vec(&conn.vertices))
     }
 
     pub fn resolve_halfedge_selection(
@@ -275,16 +503,10 @@ impl HalfEdgeMesh {
         &self,
         fragments: &SelectionExpression,
     ) -> Result<Vec<HalfEdgeId>> {
-        match self.resolve_halfedge_selection(fragments)? {
-            ResolvedSelection::All => Ok(self
-                .read_connectivity()
-                .halfedges
-                .iter()
-                .map(|(a, _)| a)
-                .collect()),
-            ResolvedSelection::None => Ok(vec![]),
-            ResolvedSelection::Explicit(v) => Ok(v),
-        }
+        let conn = self.read_connectivity();
+        Ok(self
+            .resolve_explicit_selection(&conn.halfedges, fragments)?
+            .to_vec(&conn.halfedges))
     }
 }
 
@@ -326,6 +548,34 @@ mod test {
             expl(&[Range(1..5), Range(7..10), Range(15..16), Single(18), Single(22), Single(27)]));
         assert_eq!(SelectionExpression::parse("@test, 4, 3..5, @another").unwrap(), 
             expl(&[Group("test".into()), Single(4), Range(3..5), Group("another".into())]));
+        assert_eq!(SelectionExpression::parse("tag(part, \"roof\"), 2").unwrap(),
+            expl(&[SelectionFragment::Tag("part".into(), "roof".into()), Single(2)]));
+        assert_eq!(SelectionExpression::parse("tag( part ,\"flat roof\" ), @top").unwrap(),
+            expl(&[SelectionFragment::Tag("part".into(), "flat roof".into()), Group("top".into())]));
+        assert_eq!(SelectionExpression::parse("tag(part, \"\")").unwrap(),
+            expl(&[SelectionFragment::Tag("part".into(), "".into())]));
+    }
+
+    #[test]
+    fn test_from_ids() {
+        use super::SelectionFragment::*;
+        assert_eq!(SelectionExpression::from_ids([]), SelectionExpression::None);
+        assert_eq!(
+            SelectionExpression::from_ids([3, 4, 5, 9, 1, 2]),
+            SelectionExpression::Explicit(vec![Range(3..6), Single(9), Range(1..3)])
+        );
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut sel = SelectionExpression::parse("@top, tag(part, \"roof\"), 1..3").unwrap();
+        sel.toggle(3);
+        assert_eq!(sel.unparse(), "@top, tag(part, \"roof\"), 1..4");
+        sel.toggle(2);
+        assert_eq!(sel.unparse(), "@top, tag(part, \"roof\"), 1, 3");
+        let mut all = SelectionExpression::All;
+        all.toggle(2);
+        assert_eq!(all, SelectionExpression::All);
     }
 
     #[test]
@@ -337,6 +587,162 @@ mod test {
         assert!(SelectionExpression::parse("1,2,3,a").is_err());
         assert!(SelectionExpression::parse("potato").is_err());
         assert!(SelectionExpression::parse("@1").is_err());
+        assert!(SelectionExpression::parse("tag(part, roof)").is_err());
+        assert!(SelectionExpression::parse("tag(part \"roof\")").is_err());
+        assert!(SelectionExpression::parse("tag(part, \"roof\"").is_err());
+        assert!(SelectionExpression::parse("tag(\"roof\")").is_err());
+    }
+
+    /// The resolver before it was optimized, which checks every fragment
+    /// against every element. Kept as a reference for the equivalence tests.
+    fn reference_resolve<K: ChannelKey, V>(
+        mesh: &HalfEdgeMesh,
+        data: &SlotMap<K, V>,
+        fragments: &[SelectionFragment],
+    ) -> Vec<K> {
+        let mut ids = vec![];
+        for (i, (id, _)) in data.iter().enumerate() {
+            for fragment in fragments {
+                let selected = match fragment {
+                    SelectionFragment::Range(r) => r.contains(&(i as u32)),
+                    SelectionFragment::Single(s) => *s == i as u32,
+                    SelectionFragment::Group(group) => mesh
+                        .channels
+                        .read_channel_by_name::<K, bool>(group)
+                        .unwrap()[id],
+                    SelectionFragment::Tag(name, value) => {
+                        mesh.channels.read_channel_by_name::<K, Tag>(name).unwrap()[id].as_str()
+                            == value
+                    }
+                };
+                if selected {
+                    ids.push(id);
+                }
+            }
+        }
+        // The old resolver could return an element once per matching fragment
+        ids.into_iter().unique().collect()
+    }
+
+    /// A mesh with `n` disconnected vertices, where every third vertex has
+    /// been removed, so slot indices and iteration positions differ.
+    fn sparse_vertices(n: usize) -> HalfEdgeMesh {
+        let mesh = HalfEdgeMesh::new();
+        {
+            let mut conn = mesh.write_connectivity();
+            let vertices = (0..n).map(|_| conn.alloc_vertex_raw(None)).collect_vec();
+            for v in vertices.into_iter().step_by(3) {
+                conn.vertices.remove(v);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_resolve_matches_reference() {
+        let mut mesh = sparse_vertices(100);
+        let top = mesh.channels.ensure_channel::<VertexId, bool>("top");
+        let part = mesh.channels.ensure_channel::<VertexId, Tag>("part");
+        {
+            let conn = mesh.read_connectivity();
+            let mut top = mesh.channels.write_channel(top).unwrap();
+            let mut part = mesh.channels.write_channel(part).unwrap();
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                top[v] = i % 7 == 0;
+                part[v] = Tag::new(["roof", "wall", ""][i % 3]);
+            }
+        }
+
+        for expr in [
+            "0",
+            "3, 1, 3",
+            "0..10, 5..15, 60..1000",
+            "@top",
+            "@top, 2..5, 14, 66",
+            "500, 70..20",
+            "tag(part, \"roof\")",
+            "tag(part, \"\"), @top, 3",
+            "tag(part, \"never_tagged\"), 1",
+        ] {
+            let expr = SelectionExpression::parse(expr).unwrap();
+            let fragments = match &expr {
+                SelectionExpression::Explicit(fragments) => fragments,
+                _ => unreachable!(),
+            };
+            let conn = mesh.read_connectivity();
+            let resolved = mesh.resolve_vertex_selection(&expr).unwrap();
+            let expected = reference_resolve(&mesh, &conn.vertices, fragments);
+            assert_eq!(resolved.to_vec(&conn.vertices), expected, "{expr:?}");
+            for (v, _) in conn.iter_vertices() {
+                assert_eq!(resolved.contains(v), expected.contains(&v));
+            }
+        }
+
+        let conn = mesh.read_connectivity();
+        let all = mesh
+            .resolve_vertex_selection(&SelectionExpression::All)
+            .unwrap();
+        assert!(all.is_all());
+        assert_eq!(all.to_vec(&conn.vertices).len(), conn.num_vertices());
+        let none = mesh
+            .resolve_vertex_selection(&SelectionExpression::None)
+            .unwrap();
+        assert!(conn.iter_vertices().all(|(v, _)| !none.contains(v)));
+    }
+
+    #[test]
+    fn test_resolve_full_matches_reference() {
+        let mesh = primitives::UVSphere::build(Vec3::ZERO, 8, 6, 1.0).unwrap();
+        let expr = SelectionExpression::parse("1..9, 4, 30..35, 2").unwrap();
+        let fragments = match &expr {
+            SelectionExpression::Explicit(fragments) => fragments.clone(),
+            _ => unreachable!(),
+        };
+        let conn = mesh.read_connectivity();
+        assert_eq!(
+            mesh.resolve_face_selection_full(&expr).unwrap(),
+            reference_resolve(&mesh, &conn.faces, &fragments)
+        );
+        assert_eq!(
+            mesh.resolve_vertex_selection_full(&expr).unwrap(),
+            reference_resolve(&mesh, &conn.vertices, &fragments)
+        );
+        assert_eq!(
+            mesh.resolve_halfedge_selection_full(&expr).unwrap(),
+            reference_resolve(&mesh, &conn.halfedges, &fragments)
+        );
+    }
+
+    /// Resolves a selection of 100k ids against a mesh with 1M vertices. The
+    /// reference resolver takes time proportional to the product of both, so
+    /// it is only timed on a selection 100 times smaller. Run with
+    /// `cargo test --release -- --ignored bench_resolve --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_resolve_large_explicit_selection() {
+        let mesh = sparse_vertices(1_500_000);
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_vertices(), 1_000_000);
+
+        let expr = SelectionExpression::from_ids((0..100_000).map(|i| i * 10));
+        let start = std::time::Instant::now();
+        let resolved = mesh.resolve_vertex_selection_full(&expr).unwrap();
+        println!(
+            "Resolved 100k ids against 1M vertices in {:?}",
+            start.elapsed()
+        );
+        assert_eq!(resolved.len(), 100_000);
+
+        let small = (0..1_000)
+            .map(|i| SelectionFragment::Single(i * 10))
+            .collect_vec();
+        let start = std::time::Instant::now();
+        let expected = reference_resolve(&mesh, &conn.vertices, &small);
+        println!(
+            "The reference resolver resolved 1k ids against 1M vertices in {:?}",
+            start.elapsed()
+        );
+        assert_eq!(expected.len(), 1_000);
     }
 }
 
@@ -352,6 +758,47 @@ mod lua_api {
         SelectionExpression::parse(&expr)
     }
 
+    /// Selects the edges of `mesh` with a length between `min` and `max`.
+    #[lua(under = "Select")]
+    fn by_edge_length(mesh: &HalfEdgeMesh, min: f32, max: f32) -> Result<SelectionExpression> {
+        let edges = analysis::edges_by_length(mesh, min, max)?;
+        let mapping = mesh.read_connectivity().halfedge_mapping();
+        Ok(SelectionExpression::from_ids(mapping.map_seq(&edges)))
+    }
+
+    /// Selects the faces of `mesh` with an area between `min` and `max`.
+    #[lua(under = "Select")]
+    fn by_face_area(mesh: &HalfEdgeMesh, min: f32, max: f32) -> SelectionExpression {
+        let faces = analysis::faces_by_area(mesh, min, max);
+        let mapping = mesh.read_connectivity().face_mapping();
+        SelectionExpression::from_ids(mapping.map_seq(&faces))
+    }
+
+    /// Selects the faces of `mesh` that are not planar: The ones with a
+    /// vertex more than `max_planarity_deviation_deg` degrees away from the
+    /// plane of the face, as seen from its centroid.
+    #[lua(under = "Select")]
+    fn by_face_angle(mesh: &HalfEdgeMesh, max_planarity_deviation_deg: f32) -> SelectionExpression {
+        let faces = analysis::non_planar_faces(mesh, max_planarity_deviation_deg);
+        let mapping = mesh.read_connectivity().face_mapping();
+        SelectionExpression::from_ids(mapping.map_seq(&faces))
+    }
+
+    /// Selects the non-manifold elements of `mesh`. Returns a table with an
+    /// `edges` selection, for edges with no faces or more than two, and a
+    /// `vertices` selection, for vertices where the faces around them don't
+    /// form a single fan.
+    #[lua(under = "Select")]
+    fn non_manifold(mesh: &HalfEdgeMesh) -> Result<NonManifoldSelection> {
+        let edges = analysis::non_manifold_edges(mesh)?;
+        let vertices = analysis::non_manifold_vertices(mesh)?;
+        let conn = mesh.read_connectivity();
+        Ok(NonManifoldSelection {
+            edges: SelectionExpression::from_ids(conn.halfedge_mapping().map_seq(&edges)),
+            vertices: SelectionExpression::from_ids(conn.vertex_mapping().map_seq(&vertices)),
+        })
+    }
+
     #[lua_impl]
     impl SelectionExpression {
         /// Returns a canonical string representation for this selection
@@ -0,0 +1,498 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Mesh snapshots store the expected result of a graph for regression tests,
+//! to compare against with [`compare_meshes`](super::compare::compare_meshes).
+//!
+//! The format is a compact binary encoding of the mesh in its
+//! [`CanonicalOrder`]. All numbers are little endian:
+//! - The magic bytes `BJKMESH\0`, and the version of the format as a u32.
+//! - The [`ShadingMode`] of the mesh as a u8, 0 for flat, 1 for smooth and 2
+//!   for auto smooth, followed by the auto smooth angle in radians as an f32,
+//!   or 0 when it has none. Added in version 2, older snapshots are flat.
+//! - The number of vertices as a u32, followed by their positions as three
+//!   f32 each.
+//! - The number of faces as a u32, followed by each face as its number of
+//!   vertices and their indices, all u32.
+//! - The number of channels as a u32, followed by each channel as its key
+//!   and value types as a u8 each, its name as a u32 length and UTF-8 bytes,
+//!   and its values for every element of the key type, prefixed by their
+//!   count as a u32.
+//!
+//! Loaded meshes get the same canonical order as the saved ones, which is
+//! used to put the channel values back on the right elements.
+
+use std::path::Path;
+
+use super::compare::CanonicalOrder;
+use super::*;
+
+const MAGIC: &[u8; 8] = b"BJKMESH\0";
+const VERSION: u32 = 2;
+
+/// Reads the values of a snapshot, failing when there are not enough bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            bail!("The mesh snapshot ends unexpectedly");
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    /// Reads a count, checking that there is room for that many elements of
+    /// at least `min_size` bytes each, so corrupt files don't allocate huge
+    /// buffers.
+    fn count(&mut self, min_size: usize) -> Result<usize> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_size) > self.bytes.len() {
+            bail!("The mesh snapshot ends unexpectedly");
+        }
+        Ok(count)
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) -> Result<()> {
+    let value = u32::try_from(value).map_err(|_| anyhow!("The mesh is too large to save"))?;
+    out.extend_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+fn write_vec3(out: &mut Vec<u8>, v: Vec3) {
+    for x in v.to_array() {
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+/// Channel values that can be stored in a snapshot.
+trait SnapshotValue: ChannelValue {
+    /// The size of a value in bytes.
+    const SIZE: usize;
+    fn write(self, out: &mut Vec<u8>);
+    fn read(reader: &mut Reader) -> Result<Self>;
+}
+
+impl SnapshotValue for Vec3 {
+    const SIZE: usize = 12;
+    fn write(self, out: &mut Vec<u8>) {
+        write_vec3(out, self)
+    }
+    fn read(reader: &mut Reader) -> Result<Self> {
+        reader.vec3()
+    }
+}
+
+impl SnapshotValue for f32 {
+    const SIZE: usize = 4;
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes())
+    }
+    fn read(reader: &mut Reader) -> Result<Self> {
+        reader.f32()
+    }
+}
+
+impl SnapshotValue for bool {
+    const SIZE: usize = 1;
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(self as u8)
+    }
+    fn read(reader: &mut Reader) -> Result<Self> {
+        match reader.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => bail!("Invalid boolean {other} in the mesh snapshot"),
+        }
+    }
+}
+
+/// Tags are stored as their string, with a u32 length and UTF-8 bytes.
+impl SnapshotValue for Tag {
+    const SIZE: usize = 4;
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.as_str().len() as u32).to_le_bytes());
+        out.extend_from_slice(self.as_str().as_bytes());
+    }
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let len = reader.count(1)?;
+        let value = std::str::from_utf8(reader.take(len)?)
+            .map_err(|_| anyhow!("Invalid tag in the mesh snapshot"))?;
+        Ok(Tag::new(value))
+    }
+}
+
+fn key_type_tag(kty: ChannelKeyType) -> u8 {
+    match kty {
+        ChannelKeyType::VertexId => 0,
+        ChannelKeyType::FaceId => 1,
+        ChannelKeyType::HalfEdgeId => 2,
+    }
+}
+
+fn value_type_tag(vty: ChannelValueType) -> u8 {
+    match vty {
+        ChannelValueType::Vec3 => 0,
+        ChannelValueType::f32 => 1,
+        ChannelValueType::bool => 2,
+        ChannelValueType::Tag => 3,
+    }
+}
+
+fn write_channel<K: ChannelKey, V: SnapshotValue>(
+    mesh: &HalfEdgeMesh,
+    name: ChannelName,
+    keys: &[K],
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let ch = mesh.channels.read_channel_by_name::<K, V>(name)?;
+    write_u32(out, keys.len())?;
+    for k in keys {
+        ch[*k].write(out);
+    }
+    Ok(())
+}
+
+fn read_channel<K: ChannelKey, V: SnapshotValue>(
+    mesh: &mut HalfEdgeMesh,
+    name: &str,
+    keys: &[K],
+    reader: &mut Reader,
+) -> Result<()> {
+    let count = reader.count(V::SIZE)?;
+    if count != keys.len() {
+        bail!(
+            "The channel '{name}' of the mesh snapshot has {count} values, but the mesh has {} \
+             elements",
+            keys.len()
+        );
+    }
+    let id = mesh.channels.ensure_channel::<K, V>(name);
+    let mut ch = mesh.channels.write_channel(id)?;
+    for k in keys {
+        ch[*k] = V::read(reader)?;
+    }
+    Ok(())
+}
+
+/// Encodes `mesh` and all of its channels as a snapshot.
+pub fn write_mesh_snapshot(mesh: &HalfEdgeMesh) -> Result<Vec<u8>> {
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    let (shading, angle) = match mesh.gen_config.shading {
+        ShadingMode::Flat => (0, 0.0),
+        ShadingMode::Smooth => (1, 0.0),
+        ShadingMode::AutoSmooth(angle) => (2, angle),
+    };
+    out.push(shading);
+    out.extend_from_slice(&f32::to_le_bytes(angle));
+
+    let order = {
+        let conn = mesh.read_connectivity();
+        let positions = mesh.read_positions();
+        let order = CanonicalOrder::new(&conn, &positions);
+
+        write_u32(&mut out, order.vertices.len())?;
+        for v in &order.vertices {
+            write_vec3(&mut out, positions[*v]);
+        }
+        let vertex_index: HashMap<VertexId, usize> = order
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (*v, i))
+            .collect();
+        write_u32(&mut out, order.faces.len())?;
+        for f in &order.faces {
+            let vertices = conn.face_vertices(*f);
+            write_u32(&mut out, vertices.len())?;
+            for v in vertices {
+                write_u32(&mut out, vertex_index[&v])?;
+            }
+        }
+        order
+    };
+
+    let position_name = mesh.channels.channel_name(mesh.default_channels.position);
+    let channels = mesh
+        .channels
+        .iter_channels_dyn()
+        .filter(|(_, _, name)| Some(*name) != position_name)
+        .sorted()
+        .collect_vec();
+    write_u32(&mut out, channels.len())?;
+    for (kty, vty, name) in channels {
+        out.push(key_type_tag(kty));
+        out.push(value_type_tag(vty));
+        write_u32(&mut out, name.as_str().len())?;
+        out.extend_from_slice(name.as_str().as_bytes());
+        macro_rules! write_values {
+            ($k:ty, $v:ty, $keys:ident) => {
+                write_channel::<$k, $v>(mesh, name, &order.$keys, &mut out)?
+            };
+        }
+        match (kty, vty) {
+            (ChannelKeyType::VertexId, ChannelValueType::Vec3) => {
+                write_values!(VertexId, Vec3, vertices)
+            }
+            (ChannelKeyType::VertexId, ChannelValueType::f32) => {
+                write_values!(VertexId, f32, vertices)
+            }
+            (ChannelKeyType::VertexId, ChannelValueType::bool) => {
+                write_values!(VertexId, bool, vertices)
+            }
+            (ChannelKeyType::VertexId, ChannelValueType::Tag) => {
+                write_values!(VertexId, Tag, vertices)
+            }
+            (ChannelKeyType::FaceId, ChannelValueType::Vec3) => write_values!(FaceId, Vec3, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::f32) => write_values!(FaceId, f32, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::bool) => write_values!(FaceId, bool, faces),
+            (ChannelKeyType::FaceId, ChannelValueType::Tag) => write_values!(FaceId, Tag, faces),
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::Vec3) => {
+                write_values!(HalfEdgeId, Vec3, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::f32) => {
+                write_values!(HalfEdgeId, f32, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::bool) => {
+                write_values!(HalfEdgeId, bool, halfedges)
+            }
+            (ChannelKeyType::HalfEdgeId, ChannelValueType::Tag) => {
+                write_values!(HalfEdgeId, Tag, halfedges)
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Decodes a mesh from a snapshot made by [`write_mesh_snapshot`].
+pub fn read_mesh_snapshot(bytes: &[u8]) -> Result<HalfEdgeMesh> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        bail!("This file is not a mesh snapshot");
+    }
+    let version = reader.u32()?;
+    if version == 0 || version > VERSION {
+        bail!("Unsupported mesh snapshot version {version}");
+    }
+    let shading = if version >= 2 {
+        match (reader.u8()?, reader.f32()?) {
+            (0, _) => ShadingMode::Flat,
+            (1, _) => ShadingMode::Smooth,
+            (2, angle) => ShadingMode::AutoSmooth(angle),
+            (other, _) => bail!("Invalid shading mode {other} in the mesh snapshot"),
+        }
+    } else {
+        ShadingMode::Flat
+    };
+
+    let num_vertices = reader.count(12)?;
+    let positions = (0..num_vertices)
+        .map(|_| reader.vec3())
+        .collect::<Result<Vec<_>>>()?;
+    let num_faces = reader.count(4)?;
+    let mut polygons = Vec::with_capacity(num_faces);
+    for _ in 0..num_faces {
+        let num_corners = reader.count(4)?;
+        let polygon = (0..num_corners)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>>>()?;
+        polygons.push(polygon);
+    }
+    let mut mesh = HalfEdgeMesh::build_from_polygons(&positions, &polygons)?;
+
+    // Vertices not used by any face are left out when building the mesh
+    let mut used = vec![false; num_vertices];
+    for polygon in &polygons {
+        for i in polygon {
+            used[*i as usize] = true;
+        }
+    }
+    {
+        let mut conn = mesh.write_connectivity();
+        let mut positions_ch = mesh.write_positions();
+        for (position, _) in positions.iter().zip(used).filter(|(_, used)| !used) {
+            conn.alloc_vertex(&mut positions_ch, *position, None);
+        }
+    }
+
+    let order = CanonicalOrder::new(&mesh.read_connectivity(), &mesh.read_positions());
+    let num_channels = reader.count(6)?;
+    for _ in 0..num_channels {
+        let (kty, vty) = (reader.u8()?, reader.u8()?);
+        let name_len = reader.count(1)?;
+        let name = std::str::from_utf8(reader.take(name_len)?)
+            .map_err(|_| anyhow!("Invalid channel name in the mesh snapshot"))?
+            .to_owned();
+        macro_rules! read_values {
+            ($k:ty, $v:ty, $keys:ident) => {
+                read_channel::<$k, $v>(&mut mesh, &name, &order.$keys, &mut reader)?
+            };
+        }
+        match (kty, vty) {
+            (0, 0) => read_values!(VertexId, Vec3, vertices),
+            (0, 1) => read_values!(VertexId, f32, vertices),
+            (0, 2) => read_values!(VertexId, bool, vertices),
+            (0, 3) => read_values!(VertexId, Tag, vertices),
+            (1, 0) => read_values!(FaceId, Vec3, faces),
+            (1, 1) => read_values!(FaceId, f32, faces),
+            (1, 2) => read_values!(FaceId, bool, faces),
+            (1, 3) => read_values!(FaceId, Tag, faces),
+            (2, 0) => read_values!(HalfEdgeId, Vec3, halfedges),
+            (2, 1) => read_values!(HalfEdgeId, f32, halfedges),
+            (2, 2) => read_values!(HalfEdgeId, bool, halfedges),
+            (2, 3) => read_values!(HalfEdgeId, Tag, halfedges),
+            _ => bail!("Invalid type for the channel '{name}' in the mesh snapshot"),
+        }
+    }
+    if !reader.bytes.is_empty() {
+        bail!("The mesh snapshot has unexpected data at the end");
+    }
+    mesh.gen_config.shading = shading;
+    Ok(mesh)
+}
+
+/// Saves `mesh` as a snapshot to the file at `path`.
+pub fn save_mesh_snapshot(mesh: &HalfEdgeMesh, path: &Path) -> Result<()> {
+    let bytes = write_mesh_snapshot(mesh)?;
+    std::fs::write(path, bytes)
+        .with_context(|| format!("Could not write the mesh snapshot {}", path.display()))
+}
+
+/// Loads a snapshot saved with [`save_mesh_snapshot`] from `path`.
+pub fn load_mesh_snapshot(path: &Path) -> Result<HalfEdgeMesh> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read the mesh snapshot {}", path.display()))?;
+    read_mesh_snapshot(&bytes)
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use mlua::Lua;
+
+    use super::*;
+    use crate::lua_engine::sandbox::ensure_filesystem_allowed;
+
+    /// Saves `mesh` and its channels to the file at `path`, to compare the
+    /// result of a graph against it later with `Ops.assert_mesh_equals`.
+    #[lua(under = "Ops")]
+    pub fn save_mesh_snapshot(lua: &Lua, mesh: &HalfEdgeMesh, path: String) -> Result<()> {
+        ensure_filesystem_allowed(lua, "Ops.save_mesh_snapshot")?;
+        super::save_mesh_snapshot(mesh, Path::new(&path))
+    }
+
+    /// Loads a mesh saved with `Ops.save_mesh_snapshot` from `path`.
+    #[lua(under = "Ops")]
+    pub fn load_mesh_snapshot(lua: &Lua, path: String) -> Result<HalfEdgeMesh> {
+        ensure_filesystem_allowed(lua, "Ops.load_mesh_snapshot")?;
+        super::load_mesh_snapshot(Path::new(&path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::compare::compare_meshes;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut mesh = primitives::UVSphere::build(Vec3::ZERO, 6, 4, 1.0).unwrap();
+        mesh.merge_with(&primitives::Box::build(Vec3::X * 3.0, Vec3::ONE).unwrap());
+        let normals = mesh.channels.ensure_channel::<VertexId, Vec3>("normal");
+        let weights = mesh.channels.ensure_channel::<HalfEdgeId, f32>("weight");
+        let selected = mesh.channels.ensure_channel::<FaceId, bool>("selected");
+        let parts = mesh.channels.ensure_channel::<FaceId, Tag>("part");
+        {
+            let positions = mesh.read_positions();
+            let conn = mesh.read_connectivity();
+            let mut normals = mesh.channels.write_channel(normals).unwrap();
+            for (v, _) in conn.iter_vertices() {
+                normals[v] = positions[v].normalize_or_zero();
+            }
+            let mut weights = mesh.channels.write_channel(weights).unwrap();
+            for (i, (h, _)) in conn.iter_halfedges().enumerate() {
+                weights[h] = i as f32 * 0.5;
+            }
+            let mut selected = mesh.channels.write_channel(selected).unwrap();
+            for (i, (f, _)) in conn.iter_faces().enumerate() {
+                selected[f] = i % 3 == 0;
+            }
+            let mut parts = mesh.channels.write_channel(parts).unwrap();
+            for (i, (f, _)) in conn.iter_faces().enumerate() {
+                parts[f] = Tag::new(if i % 2 == 0 { "roof" } else { "wall" });
+            }
+        }
+
+        mesh.gen_config.shading = ShadingMode::AutoSmooth(0.5);
+
+        let bytes = write_mesh_snapshot(&mesh).unwrap();
+        let loaded = read_mesh_snapshot(&bytes).unwrap();
+        assert_eq!(compare_meshes(&mesh, &loaded, 0.0, true).unwrap(), None);
+        assert_eq!(loaded.gen_config.shading, ShadingMode::AutoSmooth(0.5));
+        // Snapshots of equal meshes are identical
+        assert_eq!(write_mesh_snapshot(&loaded).unwrap(), bytes);
+
+        let path = std::env::temp_dir().join("blackjack_mesh_snapshot_test.bjkmesh");
+        save_mesh_snapshot(&mesh, &path).unwrap();
+        let loaded = load_mesh_snapshot(&path).unwrap();
+        assert_eq!(compare_meshes(&mesh, &loaded, 0.0, true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let bytes = write_mesh_snapshot(&mesh).unwrap();
+        let error = |bytes: &[u8]| read_mesh_snapshot(bytes).unwrap_err().to_string();
+        assert_eq!(error(b"not a snapshot"), "This file is not a mesh snapshot");
+        assert_eq!(
+            error(&bytes[..bytes.len() - 3]),
+            "The mesh snapshot ends unexpectedly"
+        );
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(
+            error(&extra),
+            "The mesh snapshot has unexpected data at the end"
+        );
+
+        // Version 1 snapshots have no shading, and load as flat meshes
+        let mut smooth = mesh.clone();
+        edit_ops::set_smooth_normals(&mut smooth).unwrap();
+        let bytes = write_mesh_snapshot(&smooth).unwrap();
+        let mut old = bytes[..MAGIC.len()].to_vec();
+        old.extend_from_slice(&1u32.to_le_bytes());
+        old.extend_from_slice(&bytes[MAGIC.len() + 4 + 5..]);
+        let loaded = read_mesh_snapshot(&old).unwrap();
+        assert_eq!(loaded.gen_config.shading, ShadingMode::Flat);
+        assert_eq!(compare_meshes(&smooth, &loaded, 0.0, true).unwrap(), None);
+
+        let mut future = bytes;
+        future[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(error(&future), "Unsupported mesh snapshot version 3");
+    }
+}
@@ -0,0 +1,425 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use slotmap::Key;
+
+use super::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// The value of a channel for one element, as shown in a cell of the
+/// spreadsheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellValue {
+    Vec3(Vec3),
+    F32(f32),
+    Bool(bool),
+    Tag(Tag),
+}
+
+impl std::fmt::Display for CellValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellValue::Vec3(v) => write!(f, "{:.3} {:.3} {:.3}", v.x, v.y, v.z),
+            CellValue::F32(x) => write!(f, "{x:.3}"),
+            CellValue::Bool(b) => write!(f, "{b}"),
+            CellValue::Tag(t) => write!(f, "{t}"),
+        }
+    }
+}
+
+impl CellValue {
+    /// The value with full precision, for copying it. Vectors are written
+    /// the way Lua code builds them.
+    pub fn to_exact_string(&self) -> String {
+        match self {
+            CellValue::Vec3(v) => format!("vector({}, {}, {})", v.x, v.y, v.z),
+            CellValue::F32(x) => x.to_string(),
+            CellValue::Bool(b) => b.to_string(),
+            CellValue::Tag(t) => format!("{t:?}"),
+        }
+    }
+
+    /// A total order for sorting, where vectors are compared one coordinate
+    /// at a time.
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (CellValue::Vec3(a), CellValue::Vec3(b)) => {
+                a.x.total_cmp(&b.x)
+                    .then(a.y.total_cmp(&b.y))
+                    .then(a.z.total_cmp(&b.z))
+            }
+            (CellValue::F32(a), CellValue::F32(b)) => a.total_cmp(b),
+            (CellValue::Bool(a), CellValue::Bool(b)) => a.cmp(b),
+            (CellValue::Tag(a), CellValue::Tag(b)) => a.as_str().cmp(b.as_str()),
+            // Values of a column always have the same type
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// A column of the spreadsheet, showing the values of a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpreadsheetColumn {
+    pub name: String,
+    pub value_type: ChannelValueType,
+}
+
+/// One row of the spreadsheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadsheetRow {
+    /// The index of the element in the mesh, the same one selections use.
+    pub index: u32,
+    /// The value of each column for this element.
+    pub values: Vec<CellValue>,
+}
+
+/// The rows of the spreadsheet for one kind of element of a mesh, after
+/// filtering and sorting them. Only the ids of the elements are stored, and
+/// values are read from the mesh for the rows that are requested with
+/// [`SpreadsheetView::rows`], so very large meshes can be shown a page at a
+/// time.
+///
+/// A view is only valid for the mesh it was created from.
+#[derive(Debug, Clone)]
+pub struct SpreadsheetView {
+    pub kind: ChannelKeyType,
+    /// The position comes first for vertices, followed by the rest of the
+    /// channels of this kind of element, sorted by type and name.
+    pub columns: Vec<SpreadsheetColumn>,
+    /// The element of each index, as `slotmap` FFI ids, in mesh order.
+    keys: Vec<u64>,
+    /// The index of the element shown in each row.
+    rows: Vec<u32>,
+}
+
+/// Reads the values of `column` for the elements with the given ffi `keys`.
+fn read_column<K: ChannelKey>(
+    mesh: &HalfEdgeMesh,
+    column: &SpreadsheetColumn,
+    keys: impl Iterator<Item = u64>,
+) -> Result<Vec<CellValue>> {
+    let name = column.name.as_str();
+    let key = |k| K::cast_from_ffi(k);
+    Ok(match column.value_type {
+        ChannelValueType::Vec3 => {
+            let ch = mesh.channels.read_channel_by_name::<K, Vec3>(name)?;
+            keys.map(|k| CellValue::Vec3(ch[key(k)])).collect()
+        }
+        ChannelValueType::f32 => {
+            let ch = mesh.channels.read_channel_by_name::<K, f32>(name)?;
+            keys.map(|k| CellValue::F32(ch[key(k)])).collect()
+        }
+        ChannelValueType::bool => {
+            let ch = mesh.channels.read_channel_by_name::<K, bool>(name)?;
+            keys.map(|k| CellValue::Bool(ch[key(k)])).collect()
+        }
+        ChannelValueType::Tag => {
+            let ch = mesh.channels.read_channel_by_name::<K, Tag>(name)?;
+            keys.map(|k| CellValue::Tag(ch[key(k)])).collect()
+        }
+    })
+}
+
+impl SpreadsheetView {
+    /// Creates the view for the elements of type `kind` of `mesh`, with one
+    /// row for each of them, in mesh order.
+    pub fn new(mesh: &HalfEdgeMesh, kind: ChannelKeyType) -> Self {
+        let conn = mesh.read_connectivity();
+        let keys = match kind {
+            ChannelKeyType::VertexId => conn
+                .iter_vertices()
+                .map(|(v, _)| v.data().as_ffi())
+                .collect_vec(),
+            ChannelKeyType::FaceId => conn
+                .iter_faces()
+                .map(|(f, _)| f.data().as_ffi())
+                .collect_vec(),
+            ChannelKeyType::HalfEdgeId => conn
+                .iter_halfedges()
+                .map(|(h, _)| h.data().as_ffi())
+                .collect_vec(),
+        };
+        let columns = mesh
+            .channel_infos()
+            .into_iter()
+            .filter(|info| info.key_type == kind)
+            .map(|info| SpreadsheetColumn {
+                name: info.name.as_str().to_owned(),
+                value_type: info.value_type,
+            })
+            // Stable, so the rest keep their order
+            .sorted_by_key(|column| column.name != "position")
+            .collect();
+        Self {
+            kind,
+            columns,
+            rows: (0..keys.len() as u32).collect(),
+            keys,
+        }
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The number of elements of the mesh, including the ones filtered out.
+    pub fn num_elements(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn read_column(
+        &self,
+        mesh: &HalfEdgeMesh,
+        column: &SpreadsheetColumn,
+        indices: &[u32],
+    ) -> Result<Vec<CellValue>> {
+        let keys = indices.iter().map(|i| self.keys[*i as usize]);
+        match self.kind {
+            ChannelKeyType::VertexId => read_column::<VertexId>(mesh, column, keys),
+            ChannelKeyType::FaceId => read_column::<FaceId>(mesh, column, keys),
+            ChannelKeyType::HalfEdgeId => read_column::<HalfEdgeId>(mesh, column, keys),
+        }
+    }
+
+    /// Returns the rows in `range`, with the value of every column for each
+    /// of them. The range is clamped to the number of rows.
+    pub fn rows(&self, mesh: &HalfEdgeMesh, range: Range<usize>) -> Result<Vec<SpreadsheetRow>> {
+        let end = range.end.min(self.rows.len());
+        let indices = &self.rows[range.start.min(end)..end];
+        let mut rows = indices
+            .iter()
+            .map(|index| SpreadsheetRow {
+                index: *index,
+                values: Vec::with_capacity(self.columns.len()),
+            })
+            .collect_vec();
+        for column in &self.columns {
+            for (row, value) in rows
+                .iter_mut()
+                .zip(self.read_column(mesh, column, indices)?)
+            {
+                row.values.push(value);
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Keeps only the rows of the elements in `selection`.
+    pub fn filter(&mut self, mesh: &HalfEdgeMesh, selection: &SelectionExpression) -> Result<()> {
+        let selected: HashSet<u64> = match self.kind {
+            ChannelKeyType::VertexId => mesh
+                .resolve_vertex_selection_full(selection)?
+                .into_iter()
+                .map(|v| v.data().as_ffi())
+                .collect(),
+            ChannelKeyType::FaceId => mesh
+                .resolve_face_selection_full(selection)?
+                .into_iter()
+                .map(|f| f.data().as_ffi())
+                .collect(),
+            ChannelKeyType::HalfEdgeId => mesh
+                .resolve_halfedge_selection_full(selection)?
+                .into_iter()
+                .map(|h| h.data().as_ffi())
+                .collect(),
+        };
+        let keys = &self.keys;
+        self.rows
+            .retain(|index| selected.contains(&keys[*index as usize]));
+        Ok(())
+    }
+
+    /// Sorts the rows by the values of the column at `column`. Rows with the
+    /// same value keep their order.
+    pub fn sort_by_column(
+        &mut self,
+        mesh: &HalfEdgeMesh,
+        column: usize,
+        descending: bool,
+    ) -> Result<()> {
+        let column = self
+            .columns
+            .get(column)
+            .ok_or_else(|| anyhow!("There is no column {column}"))?;
+        let values = self.read_column(mesh, column, &self.rows)?;
+        let mut order = (0..self.rows.len()).collect_vec();
+        order.sort_by(|a, b| {
+            let ordering = values[*a].total_cmp(&values[*b]);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        self.rows = order.into_iter().map(|i| self.rows[i]).collect();
+        Ok(())
+    }
+
+    /// Sorts the rows by the index of their element.
+    pub fn sort_by_index(&mut self, descending: bool) {
+        self.rows.sort_unstable();
+        if descending {
+            self.rows.reverse();
+        }
+    }
+}
+
+/// Writes the `rows` as tab separated values, with a header, so they can be
+/// pasted in other spreadsheets.
+pub fn rows_to_tsv(columns: &[SpreadsheetColumn], rows: &[SpreadsheetRow]) -> String {
+    let mut tsv = std::iter::once("index")
+        .chain(columns.iter().map(|c| c.name.as_str()))
+        .join("\t");
+    for row in rows {
+        tsv.push('\n');
+        tsv.push_str(&row.index.to_string());
+        for value in &row.values {
+            tsv.push('\t');
+            tsv.push_str(&value.to_exact_string());
+        }
+    }
+    tsv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::edit_ops::delete::{delete_faces, delete_vertices};
+
+    /// A box with a `weight` on each vertex, equal to its index.
+    fn weighted_box() -> HalfEdgeMesh {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let ch_id = mesh.channels.ensure_channel::<VertexId, f32>("weight");
+        {
+            let conn = mesh.read_connectivity();
+            let mut weights = mesh.channels.write_channel(ch_id).unwrap();
+            for (i, (v, _)) in conn.iter_vertices().enumerate() {
+                weights[v] = i as f32;
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_row_ranges() {
+        let mesh = weighted_box();
+        let view = SpreadsheetView::new(&mesh, ChannelKeyType::VertexId);
+        assert_eq!(view.num_rows(), 8);
+        assert_eq!(view.columns[0].name, "position");
+        let weight = view
+            .columns
+            .iter()
+            .position(|c| c.name == "weight")
+            .unwrap();
+
+        let rows = view.rows(&mesh, 2..5).unwrap();
+        assert_eq!(rows.iter().map(|r| r.index).collect_vec(), vec![2, 3, 4]);
+        let positions = mesh.read_positions();
+        let conn = mesh.read_connectivity();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        for row in &rows {
+            assert_eq!(row.values.len(), view.columns.len());
+            assert_eq!(
+                row.values[0],
+                CellValue::Vec3(positions[vertices[row.index as usize]])
+            );
+            assert_eq!(row.values[weight], CellValue::F32(row.index as f32));
+        }
+
+        // Ranges past the end are clamped
+        assert_eq!(view.rows(&mesh, 6..100).unwrap().len(), 2);
+        assert!(view.rows(&mesh, 50..100).unwrap().is_empty());
+
+        // Faces have their own columns
+        let faces = SpreadsheetView::new(&mesh, ChannelKeyType::FaceId);
+        assert_eq!(faces.num_rows(), 6);
+        assert!(faces.columns.iter().all(|c| c.name != "position"));
+    }
+
+    #[test]
+    fn test_rows_with_removed_elements() {
+        let mesh = weighted_box();
+        {
+            let mut conn = mesh.write_connectivity();
+            let (face, _) = conn.iter_faces().nth(2).unwrap();
+            delete_faces(&mut conn, &[face]).unwrap();
+            let (vertex, _) = conn.iter_vertices().nth(1).unwrap();
+            delete_vertices(&mut conn, &[vertex]).unwrap();
+        }
+        let conn = mesh.read_connectivity();
+        let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+        let view = SpreadsheetView::new(&mesh, ChannelKeyType::VertexId);
+        assert_eq!(view.num_rows(), vertices.len());
+        assert!(vertices.len() < 8);
+
+        // Indices skip over the removed elements, and values still belong to
+        // the right ones.
+        let weights = mesh
+            .channels
+            .read_channel_by_name::<VertexId, f32>("weight")
+            .unwrap();
+        let weight = view
+            .columns
+            .iter()
+            .position(|c| c.name == "weight")
+            .unwrap();
+        let rows = view.rows(&mesh, 0..vertices.len()).unwrap();
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row.index, i as u32);
+            assert_eq!(row.values[weight], CellValue::F32(weights[vertices[i]]));
+        }
+        // The removed vertex had weight 1
+        assert!(rows.iter().all(|r| r.values[weight] != CellValue::F32(1.0)));
+
+        let faces = SpreadsheetView::new(&mesh, ChannelKeyType::FaceId);
+        assert_eq!(faces.num_rows(), conn.num_faces());
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let mesh = weighted_box();
+        let mut view = SpreadsheetView::new(&mesh, ChannelKeyType::VertexId);
+        let weight = view
+            .columns
+            .iter()
+            .position(|c| c.name == "weight")
+            .unwrap();
+
+        view.sort_by_column(&mesh, weight, true).unwrap();
+        let indices = |view: &SpreadsheetView| {
+            view.rows(&mesh, 0..view.num_rows())
+                .unwrap()
+                .iter()
+                .map(|r| r.index)
+                .collect_vec()
+        };
+        assert_eq!(indices(&view), (0..8).rev().collect_vec());
+
+        view.filter(&mesh, &SelectionExpression::parse("1..4, 6").unwrap())
+            .unwrap();
+        assert_eq!(view.num_rows(), 4);
+        assert_eq!(view.num_elements(), 8);
+        assert_eq!(indices(&view), vec![6, 3, 2, 1]);
+        view.sort_by_index(false);
+        assert_eq!(indices(&view), vec![1, 2, 3, 6]);
+
+        assert!(view.sort_by_column(&mesh, 100, false).is_err());
+    }
+
+    #[test]
+    fn test_copy_rows() {
+        let mesh = weighted_box();
+        let view = SpreadsheetView::new(&mesh, ChannelKeyType::VertexId);
+        let tsv = rows_to_tsv(&view.columns, &view.rows(&mesh, 0..2).unwrap());
+        let lines = tsv.lines().collect_vec();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("index\tposition\t"));
+        assert!(lines[1].starts_with("0\tvector("));
+    }
+}
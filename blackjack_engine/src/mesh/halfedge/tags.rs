@@ -0,0 +1,469 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tags are short strings attached to mesh elements, like marking the faces
+//! of a building as `"roof"` or `"wall"`. They are stored in channels with
+//! [`Tag`] values, one per element, and can be selected with
+//! `tag(name, "roof")` or `Select.by_tag`.
+//!
+//! Ops keep tags the same way they keep other channels. The ops that create
+//! elements out of existing ones, like extruding or subdividing, copy the
+//! tag of the element each new one comes from, since tags can't be
+//! interpolated.
+
+use std::fmt::{Debug, Display};
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use slotmap::SlotMap;
+
+use super::id_remap::{ElementRemap, IdRemap};
+use super::selection::SelectionExpression;
+use crate::prelude::*;
+
+/// The strings of all the tags, shared by all the channels and threads. Like
+/// channel names, they are never freed: Meshes only use a handful of
+/// different tags.
+struct TagTable {
+    ids: HashMap<&'static str, u32>,
+    strings: Vec<&'static str>,
+}
+
+static TAGS: Lazy<RwLock<TagTable>> = Lazy::new(|| {
+    // The empty string is the default tag, so it always has index 0.
+    RwLock::new(TagTable {
+        ids: [("", 0)].into_iter().collect(),
+        strings: vec![""],
+    })
+});
+
+/// The value of a tag channel. Tags are interned strings stored as a small
+/// index, so tag channels are as cheap to store and copy as numbers. The
+/// default tag is the empty string, for untagged elements.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Tag(u32);
+
+impl Tag {
+    /// Returns the tag for `value`, interning it if this is the first time it
+    /// is seen.
+    pub fn new(value: &str) -> Self {
+        if let Some(tag) = Self::find(value) {
+            return tag;
+        }
+        let mut table = TAGS.write().unwrap();
+        // Another thread may have interned it after releasing the read lock
+        if let Some(id) = table.ids.get(value) {
+            return Self(*id);
+        }
+        let id = table.strings.len() as u32;
+        let value: &'static str = Box::leak(value.to_owned().into_boxed_str());
+        table.strings.push(value);
+        table.ids.insert(value, id);
+        Self(id)
+    }
+
+    /// Returns the tag for `value`, if any element was ever tagged with it.
+    /// Selections use this to look up tags without interning new strings.
+    pub fn find(value: &str) -> Option<Self> {
+        TAGS.read().unwrap().ids.get(value).map(|id| Self(*id))
+    }
+
+    /// Returns the string of this tag.
+    pub fn as_str(self) -> &'static str {
+        TAGS.read().unwrap().strings[self.0 as usize]
+    }
+
+    /// Returns whether this is the empty tag of untagged elements.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Returns the names of the tag channels of `mesh` with key type `kty`.
+pub fn tag_channels(mesh: &HalfEdgeMesh, kty: ChannelKeyType) -> Vec<ChannelName> {
+    mesh.channels
+        .iter_channels_dyn()
+        .filter(|(k, v, _)| *k == kty && *v == ChannelValueType::Tag)
+        .map(|(_, _, name)| name)
+        .collect()
+}
+
+fn set_tag_on<K: ChannelKey>(
+    mesh: &mut HalfEdgeMesh,
+    name: ChannelName,
+    elements: &[K],
+    value: Tag,
+) -> Result<()> {
+    let id = mesh.channels.ensure_channel::<K, Tag>(name);
+    let mut ch = mesh.channels.write_channel(id)?;
+    for k in elements {
+        ch[*k] = value;
+    }
+    Ok(())
+}
+
+/// Tags the elements of type `kty` in `selection` with `value`, in the tag
+/// channel called `name`. The channel is created when the mesh doesn't have
+/// it, leaving the rest of the elements untagged.
+pub fn set_tag(
+    mesh: &mut HalfEdgeMesh,
+    kty: ChannelKeyType,
+    name: ChannelName,
+    selection: &SelectionExpression,
+    value: &str,
+) -> Result<()> {
+    let value = Tag::new(value);
+    match kty {
+        ChannelKeyType::VertexId => {
+            let vertices = mesh.resolve_vertex_selection_full(selection)?;
+            set_tag_on(mesh, name, &vertices, value)
+        }
+        ChannelKeyType::FaceId => {
+            let faces = mesh.resolve_face_selection_full(selection)?;
+            set_tag_on(mesh, name, &faces, value)
+        }
+        ChannelKeyType::HalfEdgeId => {
+            let halfedges = mesh.resolve_halfedge_selection_full(selection)?;
+            set_tag_on(mesh, name, &halfedges, value)
+        }
+    }
+}
+
+/// Returns the positions, in iteration order, of the elements in `data`
+/// tagged with `value` in the tag channel `name`.
+fn tagged<K: ChannelKey, V>(
+    mesh: &HalfEdgeMesh,
+    data: &SlotMap<K, V>,
+    name: ChannelName,
+    value: &str,
+) -> Result<Vec<u32>> {
+    let ch = mesh.channels.read_channel_by_name::<K, Tag>(name)?;
+    let value = match Tag::find(value) {
+        Some(value) => value,
+        None => return Ok(vec![]),
+    };
+    Ok(data
+        .iter()
+        .enumerate()
+        .filter(|(_, (k, _))| ch[*k] == value)
+        .map(|(i, _)| i as u32)
+        .collect())
+}
+
+/// Returns a selection of the elements of type `kty` tagged with `value` in
+/// the tag channel `name`.
+pub fn select_by_tag(
+    mesh: &HalfEdgeMesh,
+    kty: ChannelKeyType,
+    name: ChannelName,
+    value: &str,
+) -> Result<SelectionExpression> {
+    let conn = mesh.read_connectivity();
+    let ids = match kty {
+        ChannelKeyType::VertexId => tagged(mesh, &conn.vertices, name, value)?,
+        ChannelKeyType::FaceId => tagged(mesh, &conn.faces, name, value)?,
+        ChannelKeyType::HalfEdgeId => tagged(mesh, &conn.halfedges, name, value)?,
+    };
+    Ok(SelectionExpression::from_ids(ids))
+}
+
+fn copy_through_remap<K: ChannelKey>(
+    src: &HalfEdgeMesh,
+    dst: &mut HalfEdgeMesh,
+    src_ids: &[K],
+    dst_ids: &[K],
+    remap: &ElementRemap,
+) -> Result<()> {
+    for name in tag_channels(src, K::key_type()) {
+        let src_ch = src.channels.read_channel_by_name::<K, Tag>(name)?;
+        let id = dst.channels.ensure_channel::<K, Tag>(name);
+        let mut dst_ch = dst.channels.write_channel(id)?;
+        for (i, k) in src_ids.iter().enumerate() {
+            for target in remap.get(i as u32) {
+                if let Some(d) = dst_ids.get(*target as usize) {
+                    dst_ch[*d] = src_ch[*k];
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gives the vertices and faces of `dst` the tags of the elements of `src`
+/// they come from, as described by `remap`. This is for ops that build a new
+/// mesh out of `src`, which don't keep its vertex and face channels.
+pub fn copy_tags_through_remap(
+    src: &HalfEdgeMesh,
+    dst: &mut HalfEdgeMesh,
+    remap: &IdRemap,
+) -> Result<()> {
+    let (src_vertices, src_faces) = {
+        let conn = src.read_connectivity();
+        (
+            conn.iter_vertices().map(|(v, _)| v).collect_vec(),
+            conn.iter_faces().map(|(f, _)| f).collect_vec(),
+        )
+    };
+    let (dst_vertices, dst_faces) = {
+        let conn = dst.read_connectivity();
+        (
+            conn.iter_vertices().map(|(v, _)| v).collect_vec(),
+            conn.iter_faces().map(|(f, _)| f).collect_vec(),
+        )
+    };
+    copy_through_remap(src, dst, &src_vertices, &dst_vertices, &remap.vertices)?;
+    copy_through_remap(src, dst, &src_faces, &dst_faces, &remap.faces)
+}
+
+/// Gives a tag to the side walls created when extruding, and to their new
+/// vertices. The `boundary` are the halfedges of the extruded faces returned
+/// by [`extrude_faces`](super::edit_ops::extrude_faces), and `old_vertices`
+/// the vertices of the mesh before the extrude. Each wall gets the tags of
+/// the extruded face next to it, and each new vertex the tags of the vertex
+/// at the other end of its wall edge.
+pub fn copy_extruded_tags(
+    mesh: &HalfEdgeMesh,
+    boundary: &[HalfEdgeId],
+    old_vertices: &HashSet<VertexId>,
+) -> Result<()> {
+    let face_tags = tag_channels(mesh, ChannelKeyType::FaceId);
+    let vertex_tags = tag_channels(mesh, ChannelKeyType::VertexId);
+    if face_tags.is_empty() && vertex_tags.is_empty() {
+        return Ok(());
+    }
+
+    // (dst, src) pairs for faces and vertices
+    let mut faces = vec![];
+    let mut vertices = vec![];
+    {
+        let conn = mesh.read_connectivity();
+        for b in boundary.iter_cpy() {
+            // The wall goes w -> v -> v' -> w', where b goes from v to w.
+            let b_next = conn.at_halfedge(b).next().try_end()?;
+            let s0 = conn.at_halfedge(b).twin().try_end()?;
+            let walls = conn.halfedge_loop(s0);
+            if walls.len() != 4 || conn.at_halfedge(s0).is_boundary()? {
+                continue;
+            }
+            faces.push((
+                conn.at_halfedge(s0).face().try_end()?,
+                conn.at_halfedge(b).face().try_end()?,
+            ));
+            // The edges from v to v' and from w to w' join a vertex of the
+            // extruded face with one at the base of the wall.
+            for (h0, h1) in [(walls[1], walls[2]), (walls[0], walls[3])] {
+                let v0 = conn.at_halfedge(h0).vertex().try_end()?;
+                let v1 = conn.at_halfedge(h1).vertex().try_end()?;
+                match (old_vertices.contains(&v0), old_vertices.contains(&v1)) {
+                    (true, false) => vertices.push((v1, v0)),
+                    (false, true) => vertices.push((v0, v1)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    for name in face_tags {
+        let mut ch = mesh.channels.write_channel_by_name::<FaceId, Tag>(name)?;
+        for (dst, src) in &faces {
+            ch[*dst] = ch[*src];
+        }
+    }
+    for name in vertex_tags {
+        let mut ch = mesh.channels.write_channel_by_name::<VertexId, Tag>(name)?;
+        for (dst, src) in &vertices {
+            ch[*dst] = ch[*src];
+        }
+    }
+    Ok(())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Selects the elements of `mesh` of type `kty`, one of `Types.VERTEX_ID`,
+    /// `Types.FACE_ID` or `Types.HALFEDGE_ID`, that are tagged with `value`
+    /// in the tag channel `name`. Fails when the mesh has no such channel.
+    #[lua(under = "Select")]
+    fn by_tag(
+        mesh: &HalfEdgeMesh,
+        kty: ChannelKeyType,
+        name: ChannelName,
+        value: String,
+    ) -> Result<SelectionExpression> {
+        select_by_tag(mesh, kty, name, &value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interning() {
+        let roof = Tag::new("test_interning_roof");
+        assert_eq!(roof.as_str(), "test_interning_roof");
+        assert_eq!(roof.to_string(), "test_interning_roof");
+        assert_eq!(format!("{roof:?}"), "\"test_interning_roof\"");
+        assert_eq!(Tag::new("test_interning_roof"), roof);
+        assert_eq!(Tag::find("test_interning_roof"), Some(roof));
+        assert_ne!(Tag::new("test_interning_wall"), roof);
+        assert_eq!(Tag::find("test_interning_never_used"), None);
+
+        assert!(Tag::default().is_empty());
+        assert_eq!(Tag::default().as_str(), "");
+        assert_eq!(Tag::new(""), Tag::default());
+        assert!(!roof.is_empty());
+    }
+
+    #[test]
+    fn test_set_and_select() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let top = SelectionExpression::parse("0, 2").unwrap();
+        set_tag(
+            &mut mesh,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &top,
+            "roof",
+        )
+        .unwrap();
+        assert_eq!(
+            select_by_tag(&mesh, ChannelKeyType::FaceId, "part".into(), "roof").unwrap(),
+            top
+        );
+        // Untagged elements have the empty tag
+        assert_eq!(
+            select_by_tag(&mesh, ChannelKeyType::FaceId, "part".into(), "")
+                .unwrap()
+                .explicit_ids(),
+            vec![1, 3, 4, 5]
+        );
+        assert_eq!(
+            select_by_tag(&mesh, ChannelKeyType::FaceId, "part".into(), "unused").unwrap(),
+            SelectionExpression::None
+        );
+        assert!(select_by_tag(&mesh, ChannelKeyType::VertexId, "part".into(), "roof").is_err());
+    }
+
+    #[test]
+    fn test_extrude_copies_tags() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let face = SelectionExpression::parse("0").unwrap();
+        set_tag(
+            &mut mesh,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &face,
+            "roof",
+        )
+        .unwrap();
+        let corners = {
+            let conn = mesh.read_connectivity();
+            let (f, _) = conn.iter_faces().next().unwrap();
+            let ids = conn.vertex_mapping().map_seq(&conn.face_vertices(f));
+            SelectionExpression::from_ids(ids.into_iter().sorted())
+        };
+        set_tag(
+            &mut mesh,
+            ChannelKeyType::VertexId,
+            "corner".into(),
+            &corners,
+            "top",
+        )
+        .unwrap();
+
+        edit_ops::lua_fns::extrude(face, 0.5, &mut mesh).unwrap();
+        let conn = mesh.read_connectivity();
+        assert_eq!(conn.num_faces(), 6 + 4);
+        assert_eq!(conn.num_vertices(), 8 + 4);
+        drop(conn);
+
+        // The extruded face and its four walls are tagged
+        let roof = select_by_tag(&mesh, ChannelKeyType::FaceId, "part".into(), "roof").unwrap();
+        assert_eq!(roof.explicit_ids().len(), 5);
+        // So are the four vertices of the face, and the four new ones
+        let top = select_by_tag(&mesh, ChannelKeyType::VertexId, "corner".into(), "top").unwrap();
+        assert_eq!(top.explicit_ids().len(), 8);
+    }
+
+    #[test]
+    fn test_subdivide_copies_tags() {
+        let mut mesh = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let face = SelectionExpression::parse("3").unwrap();
+        set_tag(
+            &mut mesh,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &face,
+            "door",
+        )
+        .unwrap();
+        set_tag(
+            &mut mesh,
+            ChannelKeyType::VertexId,
+            "corner".into(),
+            &SelectionExpression::parse("0").unwrap(),
+            "pin",
+        )
+        .unwrap();
+
+        let subdivided = edit_ops::lua_fns::subdivide(&mesh, 2, false).unwrap();
+        let door = select_by_tag(&subdivided, ChannelKeyType::FaceId, "part".into(), "door")
+            .unwrap()
+            .explicit_ids();
+        assert_eq!(door.len(), 16);
+        let pin = select_by_tag(
+            &subdivided,
+            ChannelKeyType::VertexId,
+            "corner".into(),
+            "pin",
+        )
+        .unwrap()
+        .explicit_ids();
+        assert_eq!(pin.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_keeps_tags() {
+        let mut a = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let mut b = primitives::Box::build(Vec3::X * 2.0, Vec3::ONE).unwrap();
+        set_tag(
+            &mut a,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &SelectionExpression::All,
+            "a",
+        )
+        .unwrap();
+        set_tag(
+            &mut b,
+            ChannelKeyType::FaceId,
+            "part".into(),
+            &SelectionExpression::All,
+            "b",
+        )
+        .unwrap();
+        edit_ops::lua_fns::merge(&mut a, &b).unwrap();
+        for value in ["a", "b"] {
+            let faces = select_by_tag(&a, ChannelKeyType::FaceId, "part".into(), value).unwrap();
+            assert_eq!(faces.explicit_ids().len(), 6, "{value}");
+        }
+    }
+}
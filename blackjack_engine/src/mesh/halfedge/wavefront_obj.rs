@@ -6,8 +6,9 @@
 
 use slotmap::SecondaryMap;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs::File,
-    io::{BufReader, BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
     path::PathBuf,
 };
 use wavefront_rs::obj::{
@@ -15,32 +16,228 @@ use wavefront_rs::obj::{
     entity::{Entity, FaceVertex},
 };
 
-use crate::prelude::*;
+use crate::{asset_cache::Asset, mesh::material::MaterialTable, prelude::*, sync::MaybeSync};
+
+/// The number of elements of each kind that were already written to an OBJ
+/// file. Indices in OBJ files refer to the whole file, so the faces of every
+/// object need to skip past the elements of the objects before it.
+#[derive(Default)]
+pub(crate) struct ObjIndexOffsets {
+    vertices: i32,
+    normals: i32,
+    uvs: i32,
+}
+
+/// Writes the comment at the top of the OBJ files generated by blackjack.
+pub(crate) fn write_obj_header(mut writer: impl Write) -> Result<()> {
+    obj::format_writer::FormatWriter::write(
+        &mut writer,
+        &Entity::Comment {
+            content: "Generated by Blackjack: https://github.com/setzer22/blackjack".into(),
+        },
+    );
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Saves an OBJ file at `path`, and if any materials were used, the MTL file
+/// with those `materials` next to it. The `write_obj` function writes the
+/// contents of the OBJ file, given the name of the MTL file, and returns the
+/// resolved indices of the materials it used.
+pub(crate) fn save_obj_and_mtl(
+    path: PathBuf,
+    materials: &MaterialTable,
+    write_obj: impl FnOnce(&mut dyn Write, &str) -> Result<BTreeSet<Option<u32>>>,
+) -> Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Invalid OBJ path: {path:?}"))?
+        .to_owned();
+
+    let mut writer = BufWriter::new(File::create(&path)?);
+    let used_materials = write_obj(&mut writer, &mtl_name)?;
+    writer.flush()?;
+
+    if !used_materials.is_empty() {
+        let mut writer = BufWriter::new(File::create(mtl_path)?);
+        materials.write_mtl(&mut writer, used_materials)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Same as [`save_obj_and_mtl`], but returns the contents of the OBJ and MTL
+/// files as strings. The MTL file is empty when no materials were used.
+pub(crate) fn obj_and_mtl_strings(
+    materials: &MaterialTable,
+    mtl_name: &str,
+    write_obj: impl FnOnce(&mut dyn Write, &str) -> Result<BTreeSet<Option<u32>>>,
+) -> Result<(String, String)> {
+    let mut obj = Vec::new();
+    let used_materials = write_obj(&mut obj, mtl_name)?;
+    let mut mtl = Vec::new();
+    if !used_materials.is_empty() {
+        materials.write_mtl(&mut mtl, used_materials)?;
+    }
+    Ok((String::from_utf8(obj)?, String::from_utf8(mtl)?))
+}
+
+/// Reports the faces an importer left out because the mesh couldn't be built
+/// with them, as a warning of the running node.
+pub(crate) fn report_skipped_faces(format: &str, skipped: &[MeshBuildError]) {
+    if let Some(first) = skipped.first() {
+        let sink = crate::progress::current_sink();
+        crate::progress::report_warning(
+            sink.as_deref(),
+            crate::progress::Warning::new(format!(
+                "{} faces of the {format} file were left out. {first}",
+                skipped.len()
+            )),
+        );
+    }
+}
+
+/// A mesh read from a file, with the faces that had to be left out. When the
+/// mesh comes from an [`AssetCache`](crate::asset_cache::AssetCache), the
+/// warning about those faces is shown again every time it's used.
+pub struct ImportedMesh {
+    pub mesh: HalfEdgeMesh,
+    /// The name of the file format, for the warning.
+    pub format: &'static str,
+    pub skipped: Vec<MeshBuildError>,
+}
+
+impl MaybeSync for ImportedMesh {}
+
+impl Asset for ImportedMesh {
+    fn memory_size(&self) -> usize {
+        use std::mem::size_of;
+        let conn = self.mesh.read_connectivity();
+        conn.num_vertices() * (size_of::<Vertex>() + size_of::<Vec3>())
+            + conn.num_halfedges() * size_of::<HalfEdge>()
+            + conn.num_faces() * size_of::<Face>()
+    }
+}
+
+impl ImportedMesh {
+    /// Reports the skipped faces as a warning of the running node, and
+    /// returns a copy of the mesh.
+    pub fn to_mesh(&self) -> HalfEdgeMesh {
+        report_skipped_faces(self.format, &self.skipped);
+        self.mesh.clone()
+    }
+}
 
 impl HalfEdgeMesh {
     pub fn to_wavefront_obj(&self, path: impl Into<PathBuf>) -> Result<()> {
-        let mut writer = BufWriter::new(File::create(path.into())?);
+        self.write_wavefront_obj(BufWriter::new(File::create(path.into())?))
+    }
+
+    /// Same as [`HalfEdgeMesh::to_wavefront_obj`], but returns the OBJ file
+    /// contents as a string instead of writing them to disk. Useful on
+    /// platforms without filesystem access.
+    pub fn to_wavefront_obj_string(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.write_wavefront_obj(&mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
 
+    /// Writes this mesh in Wavefront OBJ format to the given `writer`.
+    pub fn write_wavefront_obj(&self, mut writer: impl Write) -> Result<()> {
+        write_obj_header(&mut writer)?;
+        self.write_wavefront_obj_elements(
+            &mut writer,
+            Mat4::IDENTITY,
+            &mut ObjIndexOffsets::default(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Same as [`HalfEdgeMesh::to_wavefront_obj`], but faces are grouped by
+    /// their material, and the `materials` they use are saved to an MTL file
+    /// next to the OBJ file, with the same name and the `.mtl` extension.
+    pub fn to_wavefront_obj_with_materials(
+        &self,
+        path: impl Into<PathBuf>,
+        materials: &MaterialTable,
+    ) -> Result<()> {
+        save_obj_and_mtl(path.into(), materials, |writer, mtl_name| {
+            self.write_wavefront_obj_with_materials(writer, materials, mtl_name)
+        })
+    }
+
+    /// Same as [`HalfEdgeMesh::to_wavefront_obj_with_materials`], but returns
+    /// the contents of the OBJ and MTL files as strings. The OBJ file refers
+    /// to the MTL file as `mtl_name`.
+    pub fn to_wavefront_obj_strings(
+        &self,
+        materials: &MaterialTable,
+        mtl_name: &str,
+    ) -> Result<(String, String)> {
+        obj_and_mtl_strings(materials, mtl_name, |writer, mtl_name| {
+            self.write_wavefront_obj_with_materials(writer, materials, mtl_name)
+        })
+    }
+
+    /// Writes this mesh in Wavefront OBJ format to the given `writer`, with
+    /// one `usemtl` group per material. The file refers to the MTL file named
+    /// `mtl_name`. Returns the resolved indices of the materials used by the
+    /// faces of this mesh, which is empty when no materials were set on it.
+    pub fn write_wavefront_obj_with_materials(
+        &self,
+        mut writer: impl Write,
+        materials: &MaterialTable,
+        mtl_name: &str,
+    ) -> Result<BTreeSet<Option<u32>>> {
+        write_obj_header(&mut writer)?;
+        if self.face_material_indices().is_some() {
+            writeln!(writer, "mtllib {mtl_name}")?;
+        }
+        self.write_wavefront_obj_elements(
+            &mut writer,
+            Mat4::IDENTITY,
+            &mut ObjIndexOffsets::default(),
+            Some(materials),
+        )
+    }
+
+    /// Writes the vertices, normals, UVs and faces of this mesh to an OBJ
+    /// `writer`, after applying `transform` to them. The `offsets` are the
+    /// number of elements that were already written to the same file, and are
+    /// updated with the elements of this mesh.
+    ///
+    /// Normals are split along the hard edges of the mesh, see
+    /// [`normal_split`](super::export::normal_split), and faces are written in
+    /// their smoothing groups.
+    ///
+    /// When `materials` are given and the mesh has a material channel, faces
+    /// are grouped by material. Returns the resolved indices of the materials
+    /// that were used.
+    pub(crate) fn write_wavefront_obj_elements(
+        &self,
+        mut writer: impl Write,
+        transform: Mat4,
+        offsets: &mut ObjIndexOffsets,
+        materials: Option<&MaterialTable>,
+    ) -> Result<BTreeSet<Option<u32>>> {
         // We need to store the mapping between vertex ids and indices in the
         // generated OBJ
         // NOTE: OBJ Wavefront indices start at 1
         let mut imap = SecondaryMap::<VertexId, i32>::new();
 
-        obj::format_writer::FormatWriter::write(
-            &mut writer,
-            &Entity::Comment {
-                content: "Generated by Blackjack: https://github.com/setzer22/blackjack".into(),
-            },
-        );
-        writeln!(writer)?;
-
         let conn = self.read_connectivity();
 
+        let mut num_vertices = 0;
         for (idx, (v_id, _, pos)) in conn
             .iter_vertices_with_channel(&self.read_positions())
             .enumerate()
         {
             imap.insert(v_id, (idx + 1) as i32);
+            num_vertices += 1;
+            let pos = transform.transform_point3(pos);
             obj::format_writer::FormatWriter::write(
                 &mut writer,
                 &Entity::Vertex {
@@ -53,26 +250,21 @@ impl HalfEdgeMesh {
             writeln!(writer)?;
         }
 
-        let mut has_normals = false;
-        if self.gen_config.smooth_normals {
-            if let Some(v_normals_ch) = self.read_vertex_normals() {
-                has_normals = true;
-                for (v, _) in conn.iter_vertices() {
-                    let normal = v_normals_ch[v];
-                    obj::format_writer::FormatWriter::write(
-                        &mut writer,
-                        &Entity::VertexNormal {
-                            x: normal.x as f64,
-                            y: normal.y as f64,
-                            z: normal.z as f64,
-                        },
-                    );
-                    writeln!(writer)?;
-                }
-            }
-        } else {
-            // TODO has_normals = true;
-            println!("TODO: Exporting per-face normals is not yet implemented.")
+        // Vertices get a normal for each smooth region of faces around them,
+        // so hard edges look the same once imported.
+        let split = super::export::normal_split(self)?;
+        let normal_matrix = transform.inverse().transpose();
+        for normal in &split.normals {
+            let normal = normal_matrix.transform_vector3(*normal).normalize_or_zero();
+            obj::format_writer::FormatWriter::write(
+                &mut writer,
+                &Entity::VertexNormal {
+                    x: normal.x as f64,
+                    y: normal.y as f64,
+                    z: normal.z as f64,
+                },
+            );
+            writeln!(writer)?;
         }
 
         // Since UVs are stored in halfedges, we need the same mapping as `imap`
@@ -96,37 +288,93 @@ impl HalfEdgeMesh {
             }
         }
 
-        for (face_id, _) in conn.iter_faces() {
-            let vertices = conn
-                .face_vertices(face_id)
-                .iter()
-                .zip(conn.face_edges(face_id).iter())
-                .map(|(v_id, h_id)| FaceVertex {
-                    vertex: imap[*v_id] as i64,
-                    // TODO: For now we rely on emitting one normal per vertex.
-                    // Sometimes there might be less, when we implement flat
-                    // shaded normals.
-                    normal: if has_normals {
-                        Some(imap[*v_id] as i64)
-                    } else {
-                        None
-                    },
-                    texture: if has_uvs {
-                        Some(h_imap[*h_id] as i64)
-                    } else {
-                        None
-                    },
-                })
-                .collect();
-            obj::format_writer::FormatWriter::write(&mut writer, &Entity::Face { vertices });
-            writeln!(writer)?;
+        // Faces are written in groups, each one preceded by the name of its
+        // material, if any.
+        let mut used_materials = BTreeSet::new();
+        let face_groups = match (materials, self.face_material_indices()) {
+            (Some(materials), Some(face_materials)) => {
+                materials.warn_missing(face_materials.iter().map(|(_, m)| *m));
+                let mut groups = BTreeMap::<Option<u32>, Vec<FaceId>>::new();
+                for (face_id, material) in face_materials {
+                    groups
+                        .entry(materials.resolve(material))
+                        .or_default()
+                        .push(face_id);
+                }
+                used_materials.extend(groups.keys().copied());
+                groups
+                    .into_iter()
+                    .map(|(material, faces)| (Some(materials.get(material).name.clone()), faces))
+                    .collect_vec()
+            }
+            _ => vec![(None, conn.iter_faces().map(|(f, _)| f).collect_vec())],
+        };
+
+        // The smoothing group of the last face written, where 0 is off. It
+        // is always set again for the first face, since an earlier object in
+        // the same file may have left another group on.
+        let mut smoothing_group = None;
+        for (material_name, faces) in &face_groups {
+            if let Some(name) = material_name {
+                writeln!(writer, "usemtl {name}")?;
+            }
+            for &face_id in faces {
+                let group = split.smoothing_groups.get(face_id).copied().unwrap_or(0);
+                if smoothing_group != Some(group) {
+                    match group {
+                        0 => writeln!(writer, "s off")?,
+                        group => writeln!(writer, "s {group}")?,
+                    }
+                    smoothing_group = Some(group);
+                }
+                let vertices = conn
+                    .face_vertices(face_id)
+                    .iter()
+                    .zip(conn.face_edges(face_id).iter())
+                    .map(|(v_id, h_id)| FaceVertex {
+                        vertex: (imap[*v_id] + offsets.vertices) as i64,
+                        normal: Some((split.corners[*h_id] as i32 + 1 + offsets.normals) as i64),
+                        texture: if has_uvs {
+                            Some((h_imap[*h_id] + offsets.uvs) as i64)
+                        } else {
+                            None
+                        },
+                    })
+                    .collect();
+                obj::format_writer::FormatWriter::write(&mut writer, &Entity::Face { vertices });
+                writeln!(writer)?;
+            }
         }
 
-        Ok(())
+        offsets.vertices += num_vertices;
+        offsets.normals += split.normals.len() as i32;
+        offsets.uvs += h_imap.len() as i32;
+
+        Ok(used_materials)
     }
 
     pub fn from_wavefront_obj(path: PathBuf) -> Result<HalfEdgeMesh> {
-        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_wavefront_obj(BufReader::new(File::open(path)?))
+    }
+
+    /// Same as [`HalfEdgeMesh::from_wavefront_obj`], but parses the OBJ file
+    /// from an in-memory string.
+    pub fn from_wavefront_obj_str(contents: &str) -> Result<HalfEdgeMesh> {
+        Self::read_wavefront_obj(contents.as_bytes())
+    }
+
+    /// Reads a mesh in Wavefront OBJ format from the given `reader`. Faces
+    /// that would make the mesh non-manifold are left out, and reported as a
+    /// warning of the running node.
+    pub fn read_wavefront_obj(reader: impl BufRead) -> Result<HalfEdgeMesh> {
+        let imported = Self::parse_wavefront_obj(reader)?;
+        report_skipped_faces(imported.format, &imported.skipped);
+        Ok(imported.mesh)
+    }
+
+    /// Same as [`HalfEdgeMesh::read_wavefront_obj`], but the faces that were
+    /// left out are returned instead of reported.
+    pub fn parse_wavefront_obj(mut reader: impl BufRead) -> Result<ImportedMesh> {
         let mut positions = vec![];
         let mut polygons = vec![];
         obj::read_lexer::ReadLexer::read_to_end(&mut reader, |entity| match entity {
@@ -141,37 +389,98 @@ impl HalfEdgeMesh {
             }
             _ => {}
         })?;
-        halfedge::HalfEdgeMesh::build_from_polygons(&positions, &polygons)
+        let (mesh, skipped) = HalfEdgeMesh::build_from_polygons_lenient(&positions, &polygons);
+        Ok(ImportedMesh {
+            mesh,
+            format: "OBJ",
+            skipped,
+        })
     }
 }
 
 #[blackjack_macros::blackjack_lua_module]
 mod lua_api {
     use super::*;
+    use crate::asset_cache::lua_asset_cache;
+    use crate::lua_engine::sandbox::ensure_filesystem_allowed;
+    use crate::mesh::halfedge::conventions::{
+        convert_mesh_in_place, parse_convention, AxisConvention,
+    };
+    use crate::mesh::material::active_materials;
+    use crate::units::ExportFormat;
     use anyhow::Result;
+    use mlua::Lua;
 
     /// Saves this mesh as a Wavefront OBJ file at a given `path`. The path's
     /// parent folder must exist. If there was a file at that path, it will be
     /// overwritten.
+    ///
+    /// When the mesh has materials, the materials of the graph are saved
+    /// next to it, in an MTL file with the same name. The optional `scale`
+    /// multiplies every position, see `Units.export_scale`, and the optional
+    /// `axes` is the axis convention of the file, see `Ops.convert_axes`.
     #[lua(under = "HalfEdgeMesh")]
-    pub fn to_wavefront_obj(mesh: &HalfEdgeMesh, path: String) -> Result<()> {
-        mesh.to_wavefront_obj(path)
+    pub fn to_wavefront_obj(
+        lua: &Lua,
+        mesh: &HalfEdgeMesh,
+        path: String,
+        scale: Option<f32>,
+        axes: Option<String>,
+    ) -> Result<()> {
+        ensure_filesystem_allowed(lua, "HalfEdgeMesh.to_wavefront_obj")?;
+        let materials = active_materials(lua)?;
+        let scale = scale.unwrap_or(1.0);
+        let axes = parse_convention(axes, AxisConvention::export_default(ExportFormat::Obj))?;
+        if scale == 1.0 && axes == AxisConvention::default() {
+            return mesh.to_wavefront_obj_with_materials(path, &materials);
+        }
+        let converted = mesh.clone();
+        converted.apply_transform(Mat4::from_scale(Vec3::splat(scale)));
+        convert_mesh_in_place(&converted, AxisConvention::default(), axes)?;
+        converted.to_wavefront_obj_with_materials(path, &materials)
     }
 
     /// Loads a wavefront OBJ file from disk at the given `path` and returns a
-    /// `HalfEdgeMesh`.
+    /// `HalfEdgeMesh`. The optional `axes` is the axis convention of the
+    /// file, see `Ops.convert_axes`.
     ///
     /// NOTE: This currently only loads vertex positions, no normals or texture
-    /// coordinates.
+    /// coordinates. The file is only parsed again when it changes.
+    #[lua(under = "HalfEdgeMesh")]
+    pub fn from_wavefront_obj(
+        lua: &Lua,
+        path: String,
+        axes: Option<String>,
+    ) -> Result<HalfEdgeMesh> {
+        ensure_filesystem_allowed(lua, "HalfEdgeMesh.from_wavefront_obj")?;
+        let axes = parse_convention(axes, AxisConvention::default())?;
+        let imported = lua_asset_cache(lua)?.get_or_load(path.as_ref(), |path| {
+            HalfEdgeMesh::parse_wavefront_obj(BufReader::new(File::open(path)?))
+        })?;
+        let mesh = imported.to_mesh();
+        convert_mesh_in_place(&mesh, axes, AxisConvention::default())?;
+        Ok(mesh)
+    }
+
+    /// Returns the contents of a Wavefront OBJ file for this mesh as a string.
+    /// Unlike `to_wavefront_obj`, this does not require filesystem access.
     #[lua(under = "HalfEdgeMesh")]
-    pub fn from_wavefront_obj(path: String) -> Result<HalfEdgeMesh> {
-        HalfEdgeMesh::from_wavefront_obj(path.into())
+    pub fn to_wavefront_obj_string(mesh: &HalfEdgeMesh) -> Result<String> {
+        mesh.to_wavefront_obj_string()
+    }
+
+    /// Parses a `HalfEdgeMesh` from the `contents` of a Wavefront OBJ file.
+    /// Unlike `from_wavefront_obj`, this does not require filesystem access.
+    #[lua(under = "HalfEdgeMesh")]
+    pub fn from_wavefront_obj_string(contents: String) -> Result<HalfEdgeMesh> {
+        HalfEdgeMesh::from_wavefront_obj_str(&contents)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mesh::material;
 
     #[test]
     pub fn test_load_obj() {
@@ -180,4 +489,114 @@ mod tests {
             .to_wavefront_obj("/tmp/output.obj")
             .unwrap();
     }
+
+    #[test]
+    fn test_load_obj_skips_bad_faces() {
+        // Two quads sharing an edge, a fin on that edge, and a repeated quad
+        let obj = "v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 0 1\nv 1 0 1\nv 2 0 1\nv 1 1 0\n\
+                   f 1 4 5 2\nf 2 5 6 3\nf 2 5 7\nf 1 4 5 2\n";
+        let sink = std::rc::Rc::new(crate::progress::MockSink::new(None));
+        let mesh = crate::progress::with_progress_sink(sink.clone(), || {
+            HalfEdgeMesh::from_wavefront_obj_str(obj).unwrap()
+        });
+        assert_eq!(mesh.read_connectivity().num_faces(), 2);
+        let warnings = sink.warnings.borrow();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.starts_with("2 faces"));
+    }
+
+    /// Returns the material of each `usemtl` group in `obj`, and its number
+    /// of faces.
+    fn material_groups(obj: &str) -> Vec<(&str, usize)> {
+        let mut groups: Vec<(&str, usize)> = vec![];
+        for line in obj.lines() {
+            if let Some(name) = line.strip_prefix("usemtl ") {
+                groups.push((name, 0));
+            } else if line.starts_with("f ") {
+                groups.last_mut().expect("Face outside of a group").1 += 1;
+            }
+        }
+        groups
+    }
+
+    #[test]
+    pub fn test_export_materials() {
+        let cube = material::test::two_material_cube();
+        let (obj, mtl) = cube
+            .to_wavefront_obj_strings(&material::test::red_and_blue(), "cube.mtl")
+            .unwrap();
+        assert!(obj.lines().any(|l| l == "mtllib cube.mtl"));
+        assert_eq!(material_groups(&obj), vec![("red", 5), ("blue", 1)]);
+
+        let materials = mtl
+            .lines()
+            .filter_map(|l| l.strip_prefix("newmtl "))
+            .collect_vec();
+        assert_eq!(materials, vec!["red", "blue"]);
+        assert!(mtl.contains("Kd 0 0 1"));
+
+        // The file is still readable, with all the faces in it
+        let read_back = HalfEdgeMesh::from_wavefront_obj_str(&obj).unwrap();
+        assert_eq!(read_back.read_connectivity().num_faces(), 6);
+    }
+
+    #[test]
+    pub fn test_export_missing_material() {
+        let cube = material::test::two_material_cube();
+        let mut table = material::test::red_and_blue();
+        table.materials.pop();
+        let (obj, mtl) = cube.to_wavefront_obj_strings(&table, "cube.mtl").unwrap();
+        assert_eq!(material_groups(&obj), vec![("default", 1), ("red", 5)]);
+        assert!(mtl.contains("newmtl default"));
+    }
+
+    #[test]
+    pub fn test_export_without_materials() {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let (obj, mtl) = cube
+            .to_wavefront_obj_strings(&material::test::red_and_blue(), "cube.mtl")
+            .unwrap();
+        assert!(!obj.contains("mtllib"));
+        assert!(!obj.contains("usemtl"));
+        assert!(mtl.is_empty());
+    }
+
+    #[test]
+    pub fn test_export_smoothing_groups() {
+        let cube = crate::mesh::halfedge::export::test::cube_with_one_smooth_edge();
+        let obj = cube.to_wavefront_obj_string().unwrap();
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("v "), 8);
+        assert_eq!(count("vn "), 22);
+        // The top face and the one towards X are in the same group, the
+        // others are in a group of their own.
+        assert_eq!(
+            obj.lines().filter(|l| l.starts_with("s ")).unique().count(),
+            5
+        );
+
+        // Explicit groups are written as they are, and group 0 is flat
+        let mut cube = cube;
+        let ch_id = cube
+            .channels
+            .ensure_channel::<FaceId, f32>(super::super::export::SMOOTHING_GROUP_CHANNEL);
+        {
+            let faces = cube
+                .read_connectivity()
+                .iter_faces()
+                .map(|(f, _)| f)
+                .collect_vec();
+            let mut groups = cube.channels.write_channel(ch_id).unwrap();
+            for (i, f) in faces.iter_cpy().enumerate() {
+                groups[f] = (i / 3) as f32;
+            }
+        }
+        let obj = cube.to_wavefront_obj_string().unwrap();
+        let groups = obj.lines().filter(|l| l.starts_with("s ")).collect_vec();
+        assert_eq!(groups, vec!["s off", "s 1"]);
+
+        // The file can be read back
+        let read_back = HalfEdgeMesh::from_wavefront_obj_str(&obj).unwrap();
+        assert_eq!(read_back.read_connectivity().num_faces(), 6);
+    }
 }
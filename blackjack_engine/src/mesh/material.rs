@@ -0,0 +1,235 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{borrow::Cow, collections::BTreeSet, io::Write};
+
+use mlua::Lua;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::progress::{current_sink, report_warning, Warning};
+
+/// The name of the face channel storing the material index of each face. See
+/// `Ops.set_material`.
+pub const MATERIAL_CHANNEL: &str = "material";
+
+/// The key of the Lua registry where the materials of the running graph are
+/// stored, so exporters can read them.
+const MATERIALS_KEY: &str = "__blackjack_materials";
+
+/// The surface properties of a group of faces, following the
+/// metallic-roughness model used by glTF.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub name: String,
+    /// The color of the material, in linear RGB.
+    pub base_color: Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: "default".into(),
+            base_color: Vec3::splat(0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+        }
+    }
+}
+
+/// The materials of a graph. Faces refer to materials by their index in this
+/// table, which is stored in the [`MATERIAL_CHANNEL`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaterialTable {
+    pub materials: Vec<Material>,
+}
+
+impl mlua::UserData for MaterialTable {}
+
+impl MaterialTable {
+    /// Returns the index of the material for `index`, or `None` when there's
+    /// no such material, meaning the default material should be used.
+    pub fn resolve(&self, index: u32) -> Option<u32> {
+        ((index as usize) < self.materials.len()).then_some(index)
+    }
+
+    /// Returns the material a resolved index refers to. See
+    /// [`MaterialTable::resolve`].
+    pub fn get(&self, index: Option<u32>) -> Cow<Material> {
+        match index.and_then(|idx| self.materials.get(idx as usize)) {
+            Some(material) => Cow::Borrowed(material),
+            None => Cow::Owned(Material::default()),
+        }
+    }
+
+    /// Reports a warning for each of the `indices` that has no material in
+    /// this table. Those faces fall back to the default material.
+    pub fn warn_missing(&self, indices: impl IntoIterator<Item = u32>) {
+        let missing: BTreeSet<u32> = indices
+            .into_iter()
+            .filter(|idx| self.resolve(*idx).is_none())
+            .collect();
+        let sink = current_sink();
+        for idx in missing {
+            report_warning(
+                sink.as_deref(),
+                Warning::new(format!(
+                    "Material index {idx} is out of range, the graph has {} materials. \
+                     Using the default material instead.",
+                    self.materials.len()
+                )),
+            );
+        }
+    }
+
+    /// Writes the given materials in the Wavefront MTL format, the companion
+    /// file of OBJ files. The `indices` are resolved material indices, and
+    /// each one is written once.
+    pub fn write_mtl(
+        &self,
+        mut writer: impl Write,
+        indices: impl IntoIterator<Item = Option<u32>>,
+    ) -> Result<()> {
+        writeln!(
+            writer,
+            "# Generated by Blackjack: https://github.com/setzer22/blackjack"
+        )?;
+        for index in indices.into_iter().collect::<BTreeSet<_>>() {
+            let material = self.get(index);
+            let color = material.base_color;
+            writeln!(writer)?;
+            writeln!(writer, "newmtl {}", material.name)?;
+            writeln!(writer, "Kd {} {} {}", color.x, color.y, color.z)?;
+            // PBR extension of the MTL format
+            writeln!(writer, "Pm {}", material.metallic)?;
+            writeln!(writer, "Pr {}", material.roughness)?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes the `materials` of a graph available to the exporters running in
+/// `lua`. Called by the graph interpreter before running a graph.
+pub fn set_active_materials(lua: &Lua, materials: &MaterialTable) -> Result<()> {
+    lua.set_named_registry_value(MATERIALS_KEY, materials.clone())?;
+    Ok(())
+}
+
+/// Returns the materials of the graph running in `lua`. Outside of a graph,
+/// this is an empty table.
+pub fn active_materials(lua: &Lua) -> Result<MaterialTable> {
+    Ok(
+        match lua.named_registry_value::<_, Option<mlua::AnyUserData>>(MATERIALS_KEY)? {
+            Some(materials) => materials.borrow::<MaterialTable>()?.clone(),
+            None => MaterialTable::default(),
+        },
+    )
+}
+
+impl HalfEdgeMesh {
+    /// Returns the material index of every face of this mesh, in iteration
+    /// order, or `None` when no materials were set on this mesh.
+    pub fn face_material_indices(&self) -> Option<Vec<(FaceId, u32)>> {
+        let ch_id = self.channels.channel_id::<FaceId, f32>(MATERIAL_CHANNEL)?;
+        let material_ch = self.channels.read_channel(ch_id).ok()?;
+        let conn = self.read_connectivity();
+        Some(
+            conn.iter_faces()
+                .map(|(face_id, _)| (face_id, material_ch[face_id].max(0.0) as u32))
+                .collect(),
+        )
+    }
+
+    /// Returns the material index of every triangle in the buffers generated
+    /// by `generate_triangle_buffers_flat` and friends, or `None` when no
+    /// materials were set on this mesh.
+    pub fn triangle_material_indices(&self) -> Option<Vec<u32>> {
+        let face_materials = self.face_material_indices()?;
+        let conn = self.read_connectivity();
+        Some(
+            face_materials
+                .into_iter()
+                .flat_map(|(face_id, material)| {
+                    let num_triangles = conn.face_vertices(face_id).len().saturating_sub(2);
+                    std::iter::repeat(material).take(num_triangles)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::prelude::selection::{SelectionExpression, SelectionFragment};
+
+    /// A table with a red and a blue material.
+    pub(crate) fn red_and_blue() -> MaterialTable {
+        MaterialTable {
+            materials: vec![
+                Material {
+                    name: "red".into(),
+                    base_color: Vec3::new(1.0, 0.0, 0.0),
+                    ..Default::default()
+                },
+                Material {
+                    name: "blue".into(),
+                    base_color: Vec3::new(0.0, 0.0, 1.0),
+                    metallic: 1.0,
+                    roughness: 0.2,
+                },
+            ],
+        }
+    }
+
+    /// A cube with its top face using the second material, and every other
+    /// face using the first one.
+    pub(crate) fn two_material_cube() -> HalfEdgeMesh {
+        let mut cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let all = SelectionExpression::All;
+        edit_ops::set_material(&mut cube, &all, 0).unwrap();
+        let top = {
+            let conn = cube.read_connectivity();
+            let positions = cube.read_positions();
+            conn.iter_faces()
+                .find(|(f, _)| conn.face_vertices(*f).iter().all(|v| positions[*v].y > 0.0))
+                .map(|(f, _)| f)
+                .unwrap()
+        };
+        let top_idx = cube.read_connectivity().face_mapping()[top];
+        edit_ops::set_material(
+            &mut cube,
+            &SelectionExpression::Explicit(vec![SelectionFragment::Single(top_idx)]),
+            1,
+        )
+        .unwrap();
+        cube
+    }
+
+    #[test]
+    fn test_resolve() {
+        let table = red_and_blue();
+        assert_eq!(table.resolve(1), Some(1));
+        assert_eq!(table.resolve(2), None);
+        assert_eq!(table.get(table.resolve(5)).name, "default");
+    }
+
+    #[test]
+    fn test_material_indices() {
+        let cube = two_material_cube();
+        let faces = cube.face_material_indices().unwrap();
+        assert_eq!(faces.iter().filter(|(_, m)| *m == 1).count(), 1);
+        // Every quad is split in two triangles
+        let triangles = cube.triangle_material_indices().unwrap();
+        assert_eq!(triangles.len(), 12);
+        assert_eq!(triangles.iter().filter(|m| **m == 1).count(), 2);
+
+        let plain = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert!(plain.face_material_indices().is_none());
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::{prelude::*, sync::RefCounted};
+
+use super::{
+    halfedge::conventions::{self, AxisConvention},
+    halfedge::wavefront_obj::{
+        obj_and_mtl_strings, save_obj_and_mtl, write_obj_header, ObjIndexOffsets,
+    },
+    material::MaterialTable,
+};
+
+/// Exports scenes as glTF 2.0 files.
+pub mod gltf;
+
+/// A named mesh, placed in a scene with its own transform. Several objects can
+/// share the same mesh, in which case they are instances of each other and
+/// exporters only write the mesh once.
+#[derive(Debug, Clone)]
+pub struct SceneObject {
+    pub name: String,
+    pub mesh: RefCounted<HalfEdgeMesh>,
+    pub transform: Mat4,
+}
+
+impl SceneObject {
+    pub fn new(name: String, mesh: HalfEdgeMesh, transform: Mat4) -> Self {
+        Self {
+            name,
+            mesh: RefCounted::new(mesh),
+            transform,
+        }
+    }
+
+    /// Returns a new object sharing the mesh of this one, with a different
+    /// `name` and `transform`.
+    pub fn new_instance(&self, name: String, transform: Mat4) -> Self {
+        Self {
+            name,
+            mesh: RefCounted::clone(&self.mesh),
+            transform,
+        }
+    }
+}
+
+/// A collection of objects. Unlike merging meshes, a scene keeps every object
+/// separate, so they can be exported as distinct, named objects.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub objects: Vec<SceneObject>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, object: SceneObject) {
+        self.objects.push(object);
+    }
+
+    /// Returns the distinct meshes in this scene, in the order they first
+    /// appear, and for each object, the index of its mesh in that list.
+    pub fn unique_meshes(&self) -> (Vec<&RefCounted<HalfEdgeMesh>>, Vec<usize>) {
+        let mut meshes: Vec<&RefCounted<HalfEdgeMesh>> = vec![];
+        let object_meshes = self
+            .objects
+            .iter()
+            .map(|object| {
+                match meshes
+                    .iter()
+                    .position(|mesh| RefCounted::ptr_eq(mesh, &object.mesh))
+                {
+                    Some(idx) => idx,
+                    None => {
+                        meshes.push(&object.mesh);
+                        meshes.len() - 1
+                    }
+                }
+            })
+            .collect();
+        (meshes, object_meshes)
+    }
+
+    /// Returns a single mesh with all the objects of this scene, each one with
+    /// its transform applied. Used to preview the scene in the viewport.
+    pub fn to_merged_mesh(&self) -> HalfEdgeMesh {
+        let mut merged = HalfEdgeMesh::new();
+        for object in &self.objects {
+            let mesh = HalfEdgeMesh::clone(&object.mesh);
+            mesh.apply_transform(object.transform);
+            merged.merge_with(&mesh);
+        }
+        merged
+    }
+
+    /// Returns a copy of this scene scaled by `factor` around the origin, as
+    /// done by exporters to change the unit of lengths. The objects of the
+    /// copy share their meshes with this scene, only their transforms change.
+    pub fn scaled(&self, factor: f32) -> Scene {
+        let scale = Mat4::from_scale(Vec3::splat(factor));
+        Scene {
+            objects: self
+                .objects
+                .iter()
+                .map(|object| object.new_instance(object.name.clone(), scale * object.transform))
+                .collect(),
+        }
+    }
+
+    /// Returns a copy of this scene with its coordinates converted from the
+    /// `from` axis convention to the `to` one, see
+    /// [`conventions::convert_mesh_in_place`]. Objects that shared a mesh
+    /// still share the converted one.
+    pub fn converted(&self, from: AxisConvention, to: AxisConvention) -> Result<Scene> {
+        let conversion = Mat4::from_mat3(AxisConvention::conversion(from, to));
+        let (meshes, object_meshes) = self.unique_meshes();
+        let meshes = meshes
+            .into_iter()
+            .map(|mesh| {
+                let mesh = HalfEdgeMesh::clone(mesh);
+                conventions::convert_mesh_in_place(&mesh, from, to)?;
+                Ok(RefCounted::new(mesh))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The meshes are already converted, so the transforms need to take
+        // converted points back before applying the original transform.
+        let objects = self
+            .objects
+            .iter()
+            .zip(object_meshes)
+            .map(|(object, idx)| SceneObject {
+                name: object.name.clone(),
+                mesh: RefCounted::clone(&meshes[idx]),
+                transform: conversion * object.transform * conversion.inverse(),
+            })
+            .collect();
+        Ok(Scene { objects })
+    }
+
+    /// Saves this scene as a Wavefront OBJ file at the given `path`. Each
+    /// object is written as a separate `o` group with its transform applied.
+    pub fn to_wavefront_obj(&self, path: impl Into<PathBuf>) -> Result<()> {
+        self.write_wavefront_obj(BufWriter::new(File::create(path.into())?))
+    }
+
+    /// Same as [`Scene::to_wavefront_obj`], but returns the OBJ file contents
+    /// as a string instead of writing them to disk.
+    pub fn to_wavefront_obj_string(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.write_wavefront_obj(&mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Writes this scene in Wavefront OBJ format to the given `writer`.
+    pub fn write_wavefront_obj(&self, writer: impl Write) -> Result<()> {
+        self.write_obj_objects(writer, None)?;
+        Ok(())
+    }
+
+    /// Same as [`Scene::to_wavefront_obj`], but faces are grouped by their
+    /// material, and the `materials` they use are saved to an MTL file next
+    /// to the OBJ file, with the same name and the `.mtl` extension.
+    pub fn to_wavefront_obj_with_materials(
+        &self,
+        path: impl Into<PathBuf>,
+        materials: &MaterialTable,
+    ) -> Result<()> {
+        save_obj_and_mtl(path.into(), materials, |writer, mtl_name| {
+            self.write_obj_objects(writer, Some((materials, mtl_name)))
+        })
+    }
+
+    /// Same as [`Scene::to_wavefront_obj_with_materials`], but returns the
+    /// contents of the OBJ and MTL files as strings. The OBJ file refers to
+    /// the MTL file as `mtl_name`.
+    pub fn to_wavefront_obj_strings(
+        &self,
+        materials: &MaterialTable,
+        mtl_name: &str,
+    ) -> Result<(String, String)> {
+        obj_and_mtl_strings(materials, mtl_name, |writer, mtl_name| {
+            self.write_obj_objects(writer, Some((materials, mtl_name)))
+        })
+    }
+
+    /// Writes every object of this scene to an OBJ `writer`. When `materials`
+    /// and the name of their MTL file are given, faces are grouped by
+    /// material. Returns the resolved indices of the materials used.
+    fn write_obj_objects(
+        &self,
+        mut writer: impl Write,
+        materials: Option<(&MaterialTable, &str)>,
+    ) -> Result<BTreeSet<Option<u32>>> {
+        write_obj_header(&mut writer)?;
+        if let Some((_, mtl_name)) = materials {
+            if self
+                .objects
+                .iter()
+                .any(|object| object.mesh.face_material_indices().is_some())
+            {
+                writeln!(writer, "mtllib {mtl_name}")?;
+            }
+        }
+        let mut offsets = ObjIndexOffsets::default();
+        let mut used_materials = BTreeSet::new();
+        for object in &self.objects {
+            writeln!(writer, "o {}", object.name)?;
+            used_materials.extend(object.mesh.write_wavefront_obj_elements(
+                &mut writer,
+                object.transform,
+                &mut offsets,
+                materials.map(|(materials, _)| materials),
+            )?);
+        }
+        Ok(used_materials)
+    }
+}
+
+/// Builds a transform matrix from an optional Lua table, with optional
+/// `translate`, `rotate` and `scale` fields. Rotations are XYZ euler angles,
+/// like the ones in `Ops.transform`.
+fn transform_from_lua(transform: Option<mlua::Table>) -> Result<Mat4> {
+    let transform = match transform {
+        Some(transform) => transform,
+        None => return Ok(Mat4::IDENTITY),
+    };
+    let get = |key: &str, default: Vec3| -> Result<Vec3> {
+        Ok(transform
+            .get::<_, Option<crate::lua_engine::lua_stdlib::LVec3>>(key)?
+            .map(|v| v.0)
+            .unwrap_or(default))
+    };
+    let rotate = get("rotate", Vec3::ZERO)?;
+    Ok(Mat4::from_scale_rotation_translation(
+        get("scale", Vec3::ONE)?,
+        Quat::from_euler(glam::EulerRot::XYZ, rotate.x, rotate.y, rotate.z),
+        get("translate", Vec3::ZERO)?,
+    ))
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+    use crate::lua_engine::sandbox::ensure_filesystem_allowed;
+    use crate::mesh::material::active_materials;
+    use crate::units::ExportFormat;
+    use mlua::Lua;
+
+    /// Returns a new, empty scene.
+    #[lua(under = "Scene")]
+    fn new() -> Scene {
+        Scene::new()
+    }
+
+    /// Returns a new object named `name` with a copy of `mesh`. The optional
+    /// `transform` is a table with `translate`, `rotate` and `scale` vectors,
+    /// where rotations are XYZ euler angles. Missing fields take the identity.
+    #[lua(under = "Ops")]
+    pub fn make_object(
+        mesh: &HalfEdgeMesh,
+        name: String,
+        transform: Option<mlua::Table>,
+    ) -> Result<SceneObject> {
+        Ok(SceneObject::new(
+            name,
+            mesh.clone(),
+            transform_from_lua(transform)?,
+        ))
+    }
+
+    /// Adds `object` to the given `scene`.
+    #[lua(under = "Ops")]
+    pub fn scene_add(scene: &mut Scene, object: &SceneObject) {
+        scene.add(object.clone())
+    }
+
+    /// Saves this scene as a glTF file at a given `path`, with its buffers
+    /// embedded. If there was a file at that path, it will be overwritten.
+    /// The materials of the graph are written along with the meshes. glTF
+    /// files are always Y-up and right-handed, like blackjack.
+    #[lua(under = "Scene")]
+    pub fn to_gltf(lua: &Lua, scene: &Scene, path: String) -> Result<()> {
+        ensure_filesystem_allowed(lua, "Scene.to_gltf")?;
+        scene.to_gltf(path, &active_materials(lua)?)
+    }
+
+    /// Returns the contents of a glTF file for this scene as a string. Unlike
+    /// `to_gltf`, this does not require filesystem access.
+    #[lua(under = "Scene")]
+    pub fn to_gltf_string(lua: &Lua, scene: &Scene) -> Result<String> {
+        scene.to_gltf_string(&active_materials(lua)?)
+    }
+
+    /// Saves this scene as a Wavefront OBJ file at a given `path`, with one
+    /// group per object. If there was a file at that path, it will be
+    /// overwritten. When the meshes have materials, the materials of the
+    /// graph are saved next to it, in an MTL file with the same name.
+    ///
+    /// The optional `scale` multiplies every position, e.g. to write the file
+    /// in the unit of the project, see `Units.export_scale`. The optional
+    /// `axes` is the axis convention of the file, see `Ops.convert_axes`.
+    #[lua(under = "Scene")]
+    pub fn to_wavefront_obj(
+        lua: &Lua,
+        scene: &Scene,
+        path: String,
+        scale: Option<f32>,
+        axes: Option<String>,
+    ) -> Result<()> {
+        ensure_filesystem_allowed(lua, "Scene.to_wavefront_obj")?;
+        let axes =
+            conventions::parse_convention(axes, AxisConvention::export_default(ExportFormat::Obj))?;
+        scene
+            .scaled(scale.unwrap_or(1.0))
+            .converted(AxisConvention::default(), axes)?
+            .to_wavefront_obj_with_materials(path, &active_materials(lua)?)
+    }
+
+    /// Returns the contents of a Wavefront OBJ file for this scene as a
+    /// string. Unlike `to_wavefront_obj`, this does not require filesystem
+    /// access.
+    #[lua(under = "Scene")]
+    pub fn to_wavefront_obj_string(scene: &Scene) -> Result<String> {
+        scene.to_wavefront_obj_string()
+    }
+
+    #[lua_impl]
+    impl SceneObject {
+        /// Returns a new object that shares the mesh of this one, with the
+        /// given `name` and optional `transform`. Exporters only write the
+        /// shared mesh once.
+        #[lua]
+        fn instance(&self, name: String, transform: Option<mlua::Table>) -> Result<SceneObject> {
+            Ok(self.new_instance(name, transform_from_lua(transform)?))
+        }
+
+        /// Returns the name of this object.
+        #[lua]
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    #[lua_impl]
+    impl Scene {
+        /// Duplicates this scene. Meshes are shared between the copies.
+        #[lua(hidden)]
+        fn clone(&self) -> Scene {
+            self.clone()
+        }
+
+        /// Returns the number of objects in this scene.
+        #[lua]
+        fn num_objects(&self) -> usize {
+            self.objects.len()
+        }
+
+        /// Returns a single mesh with all the objects of this scene, with
+        /// their transforms applied.
+        #[lua]
+        fn to_mesh(&self) -> HalfEdgeMesh {
+            self.to_merged_mesh()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A scene with a cube, and an instance of it moved to the side.
+    pub(super) fn two_cubes() -> Scene {
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let object = SceneObject::new("cube".into(), cube, Mat4::IDENTITY);
+        let mut scene = Scene::new();
+        scene.add(object.new_instance(
+            "cube_instance".into(),
+            Mat4::from_translation(Vec3::new(3.0, 0.0, 0.0)),
+        ));
+        scene.objects.insert(0, object);
+        scene
+    }
+
+    #[test]
+    fn test_unique_meshes() {
+        let mut scene = two_cubes();
+        let other = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        scene.add(SceneObject::new("other".into(), other, Mat4::IDENTITY));
+        let (meshes, object_meshes) = scene.unique_meshes();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(object_meshes, vec![0, 0, 1]);
+    }
+
+    #[test]
+    fn test_obj_materials() {
+        let mut scene = two_cubes();
+        let cube = crate::mesh::material::test::two_material_cube();
+        scene.add(SceneObject::new("painted".into(), cube, Mat4::IDENTITY));
+        let (obj, mtl) = scene
+            .to_wavefront_obj_strings(&crate::mesh::material::test::red_and_blue(), "scene.mtl")
+            .unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("mtllib ")).count(), 1);
+        // Only the last object has materials
+        let after_painted = obj.split("o painted").nth(1).unwrap();
+        assert_eq!(obj.matches("usemtl ").count(), 2);
+        assert_eq!(after_painted.matches("usemtl ").count(), 2);
+        assert_eq!(mtl.matches("newmtl ").count(), 2);
+    }
+
+    #[test]
+    fn test_obj_objects() {
+        let obj = two_cubes().to_wavefront_obj_string().unwrap();
+        let objects = obj.lines().filter(|l| l.starts_with("o ")).collect_vec();
+        assert_eq!(objects, vec!["o cube", "o cube_instance"]);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 16);
+
+        // Faces of the second object refer to its own vertices, which come
+        // after the 8 vertices of the first one.
+        let last_face = obj.lines().filter(|l| l.starts_with("f ")).last().unwrap();
+        assert!(last_face.split_whitespace().skip(1).all(|v| v
+            .split('/')
+            .next()
+            .unwrap()
+            .parse::<i32>()
+            .unwrap()
+            > 8));
+
+        let merged = HalfEdgeMesh::from_wavefront_obj_str(&obj).unwrap();
+        let positions = merged.read_positions();
+        let max_x = positions.iter().map(|(_, p)| p.x).fold(f32::MIN, f32::max);
+        assert!((max_x - 3.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let scene = two_cubes();
+        let scaled = scene.scaled(100.0);
+        let (meshes, _) = scaled.unique_meshes();
+        assert_eq!(meshes.len(), 1);
+        assert!(RefCounted::ptr_eq(meshes[0], &scene.objects[0].mesh));
+
+        let obj = scaled.to_wavefront_obj_string().unwrap();
+        let merged = HalfEdgeMesh::from_wavefront_obj_str(&obj).unwrap();
+        let positions = merged.read_positions();
+        let max_x = positions.iter().map(|(_, p)| p.x).fold(f32::MIN, f32::max);
+        assert!((max_x - 350.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_converted() {
+        let mut scene = two_cubes();
+        let raised = scene.objects[0].new_instance(
+            "raised".into(),
+            Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+        );
+        scene.add(raised);
+        let converted = scene
+            .converted(
+                AxisConvention::YUpRightHanded,
+                AxisConvention::ZUpRightHanded,
+            )
+            .unwrap();
+        let (meshes, object_meshes) = converted.unique_meshes();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(object_meshes, vec![0, 0, 0]);
+
+        // Our Y is their Z, and the instances stay where they were
+        let merged = converted.to_merged_mesh();
+        let positions = merged.read_positions();
+        let max = |axis: fn(&Vec3) -> f32| {
+            positions
+                .iter()
+                .map(|(_, p)| axis(p))
+                .fold(f32::MIN, f32::max)
+        };
+        assert!((max(|p| p.x) - 3.5).abs() < 1e-5);
+        assert!((max(|p| p.y) - 0.5).abs() < 1e-5);
+        assert!((max(|p| p.z) - 2.5).abs() < 1e-5);
+    }
+}
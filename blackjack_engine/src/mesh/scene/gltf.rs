@@ -0,0 +1,383 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use serde_json::json;
+
+use super::Scene;
+use crate::mesh::halfedge::export::{normal_split, SplitVertices};
+use crate::{mesh::material::MaterialTable, prelude::*};
+
+// Constants from the glTF 2.0 specification
+const COMPONENT_TYPE_FLOAT: u32 = 5126;
+const COMPONENT_TYPE_UNSIGNED_INT: u32 = 5125;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const MODE_TRIANGLES: u32 = 4;
+
+/// Accumulates the binary data of a glTF file, along with the buffer views
+/// and accessors that describe it.
+#[derive(Default)]
+struct GltfBuffer {
+    data: Vec<u8>,
+    buffer_views: Vec<serde_json::Value>,
+    accessors: Vec<serde_json::Value>,
+}
+
+impl GltfBuffer {
+    /// Appends `bytes` as a new buffer view, and returns its index.
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // Both floats and u32 indices need to be aligned to 4 bytes
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.data.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    /// Appends `values` as a new accessor, and returns its index. Accessors
+    /// for positions must store their bounds, which is done when `with_bounds`
+    /// is set.
+    fn push_vec3s(&mut self, values: &[Vec3], with_bounds: bool) -> usize {
+        let bytes = values
+            .iter()
+            .flat_map(|v| v.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect_vec();
+        let view = self.push_view(&bytes, TARGET_ARRAY_BUFFER);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": COMPONENT_TYPE_FLOAT,
+            "count": values.len(),
+            "type": "VEC3",
+        });
+        if with_bounds {
+            let (min, max) = values.iter().fold(
+                (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+                |(min, max), v| (min.min(*v), max.max(*v)),
+            );
+            accessor["min"] = json!(min.to_array());
+            accessor["max"] = json!(max.to_array());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    /// Appends triangle `indices` as a new accessor, and returns its index.
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes = indices.iter().flat_map(|i| i.to_le_bytes()).collect_vec();
+        let view = self.push_view(&bytes, TARGET_ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_TYPE_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+/// The materials of a glTF file. Only the materials used by some mesh are
+/// written, in the order they're first used.
+struct GltfMaterials<'a> {
+    table: &'a MaterialTable,
+    /// The resolved indices of the materials, see [`MaterialTable::resolve`].
+    used: Vec<Option<u32>>,
+}
+
+impl<'a> GltfMaterials<'a> {
+    fn new(table: &'a MaterialTable) -> Self {
+        Self {
+            table,
+            used: vec![],
+        }
+    }
+
+    /// Returns the index of the glTF material for a resolved material index.
+    fn index_of(&mut self, material: Option<u32>) -> usize {
+        match self.used.iter().position(|m| *m == material) {
+            Some(idx) => idx,
+            None => {
+                self.used.push(material);
+                self.used.len() - 1
+            }
+        }
+    }
+
+    fn to_json(&self) -> Vec<serde_json::Value> {
+        self.used
+            .iter()
+            .map(|idx| {
+                let material = self.table.get(*idx);
+                json!({
+                    "name": material.name,
+                    "pbrMetallicRoughness": {
+                        "baseColorFactor": material.base_color.extend(1.0).to_array(),
+                        "metallicFactor": material.metallic,
+                        "roughnessFactor": material.roughness,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the glTF mesh for a halfedge `mesh`, or `None` when it has no
+/// faces, since glTF meshes need at least one triangle primitive. Meshes with
+/// materials get a primitive for each material, all of them sharing the same
+/// vertex attributes. Vertices are split along the hard edges of the mesh, so
+/// they get the same normals as in the viewport.
+fn gltf_mesh(
+    mesh: &HalfEdgeMesh,
+    name: &str,
+    buffer: &mut GltfBuffer,
+    materials: &mut GltfMaterials,
+) -> Result<Option<serde_json::Value>> {
+    let SplitVertices {
+        positions,
+        normals,
+        indices,
+        ..
+    } = normal_split(mesh)?;
+    if indices.is_empty() {
+        return Ok(None);
+    }
+
+    let attributes = json!({
+        "POSITION": buffer.push_vec3s(&positions, true),
+        "NORMAL": buffer.push_vec3s(&normals, false),
+    });
+
+    // The indices of each primitive, along with its glTF material
+    let primitive_indices = match mesh.triangle_material_indices() {
+        Some(triangle_materials) => {
+            materials
+                .table
+                .warn_missing(triangle_materials.iter().copied());
+            let mut groups = BTreeMap::<Option<u32>, Vec<u32>>::new();
+            for (triangle, material) in indices.chunks(3).zip(triangle_materials) {
+                groups
+                    .entry(materials.table.resolve(material))
+                    .or_default()
+                    .extend_from_slice(triangle);
+            }
+            groups
+                .into_iter()
+                .map(|(material, indices)| (Some(materials.index_of(material)), indices))
+                .collect_vec()
+        }
+        None => vec![(None, indices)],
+    };
+
+    let primitives = primitive_indices
+        .into_iter()
+        .map(|(material, indices)| {
+            let mut primitive = json!({
+                "attributes": attributes,
+                "indices": buffer.push_indices(&indices),
+                "mode": MODE_TRIANGLES,
+            });
+            if let Some(material) = material {
+                primitive["material"] = json!(material);
+            }
+            primitive
+        })
+        .collect_vec();
+    Ok(Some(json!({
+        "name": name,
+        "primitives": primitives,
+    })))
+}
+
+impl Scene {
+    /// Saves this scene as a glTF file at the given `path`. The binary data
+    /// is embedded in the file, so the result is a single `.gltf` file.
+    pub fn to_gltf(&self, path: impl Into<PathBuf>, materials: &MaterialTable) -> Result<()> {
+        self.write_gltf(BufWriter::new(File::create(path.into())?), materials)
+    }
+
+    /// Same as [`Scene::to_gltf`], but returns the glTF file contents as a
+    /// string instead of writing them to disk.
+    pub fn to_gltf_string(&self, materials: &MaterialTable) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.write_gltf(&mut buffer, materials)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Writes this scene in glTF format to the given `writer`. Every object
+    /// becomes a node of the glTF scene, and objects sharing the same mesh
+    /// reference a single glTF mesh. The `materials` used by the faces of the
+    /// meshes are written as PBR materials.
+    pub fn write_gltf(&self, writer: impl Write, materials: &MaterialTable) -> Result<()> {
+        let mut buffer = GltfBuffer::default();
+        let mut gltf_materials = GltfMaterials::new(materials);
+
+        let (unique_meshes, object_meshes) = self.unique_meshes();
+        let mut meshes = vec![];
+        let mut mesh_indices = vec![];
+        for (idx, mesh) in unique_meshes.iter().enumerate() {
+            // Meshes are named after the first object that uses them
+            let first_object = object_meshes
+                .iter()
+                .position(|m| *m == idx)
+                .expect("Every mesh belongs to an object");
+            let name = &self.objects[first_object].name;
+            mesh_indices.push(
+                gltf_mesh(mesh, name, &mut buffer, &mut gltf_materials)?.map(|mesh| {
+                    meshes.push(mesh);
+                    meshes.len() - 1
+                }),
+            );
+        }
+
+        let nodes = self
+            .objects
+            .iter()
+            .zip(object_meshes)
+            .map(|(object, mesh_idx)| {
+                let mut node = json!({ "name": object.name });
+                if let Some(mesh) = mesh_indices[mesh_idx] {
+                    node["mesh"] = json!(mesh);
+                }
+                if object.transform != Mat4::IDENTITY {
+                    node["matrix"] = json!(object.transform.to_cols_array());
+                }
+                node
+            })
+            .collect_vec();
+        let scene_nodes = (0..nodes.len()).collect_vec();
+
+        let mut gltf = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": "Blackjack: https://github.com/setzer22/blackjack",
+            },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "accessors": buffer.accessors,
+            "bufferViews": buffer.buffer_views,
+        });
+        if !gltf_materials.used.is_empty() {
+            gltf["materials"] = json!(gltf_materials.to_json());
+        }
+        if !buffer.data.is_empty() {
+            gltf["buffers"] = json!([{
+                "byteLength": buffer.data.len(),
+                "uri": format!(
+                    "data:application/octet-stream;base64,{}",
+                    base64::encode(&buffer.data)
+                ),
+            }]);
+        }
+
+        serde_json::to_writer(writer, &gltf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::halfedge::export::test::cube_with_one_smooth_edge;
+    use crate::mesh::{material, scene::test::two_cubes, scene::SceneObject};
+
+    #[test]
+    fn test_instances_share_mesh() {
+        let gltf: serde_json::Value =
+            serde_json::from_str(&two_cubes().to_gltf_string(&Default::default()).unwrap())
+                .unwrap();
+
+        let nodes = gltf["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0]["name"], "cube");
+        assert_eq!(nodes[1]["name"], "cube_instance");
+        assert_eq!(nodes[0]["mesh"], 0);
+        assert_eq!(nodes[1]["mesh"], 0);
+        assert_eq!(gltf["meshes"].as_array().unwrap().len(), 1);
+        assert_eq!(gltf["scenes"][0]["nodes"], json!([0, 1]));
+
+        // Only the instance is moved
+        assert!(nodes[0].get("matrix").is_none());
+        assert_eq!(nodes[1]["matrix"][12], 3.0);
+
+        let data_len = gltf["buffers"][0]["byteLength"].as_u64().unwrap() as usize;
+        let uri = gltf["buffers"][0]["uri"].as_str().unwrap();
+        let data = base64::decode(uri.split_once(',').unwrap().1).unwrap();
+        assert_eq!(data.len(), data_len);
+    }
+
+    #[test]
+    fn test_primitives_per_material() {
+        let mut scene = Scene::new();
+        let cube = material::test::two_material_cube();
+        scene.add(SceneObject::new("cube".into(), cube, Mat4::IDENTITY));
+        let gltf: serde_json::Value = serde_json::from_str(
+            &scene
+                .to_gltf_string(&material::test::red_and_blue())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let primitives = gltf["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), 2);
+        // Both primitives share the same vertices
+        assert_eq!(primitives[0]["attributes"], primitives[1]["attributes"]);
+        let index_counts = primitives
+            .iter()
+            .map(|p| gltf["accessors"][p["indices"].as_u64().unwrap() as usize]["count"].clone())
+            .collect_vec();
+        assert_eq!(index_counts, vec![json!(30), json!(6)]);
+
+        let materials = gltf["materials"].as_array().unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(
+            materials[primitives[1]["material"].as_u64().unwrap() as usize]["name"],
+            "blue"
+        );
+        assert_eq!(
+            materials[1]["pbrMetallicRoughness"]["baseColorFactor"],
+            json!([0.0, 0.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_split_hard_edges() {
+        let vertex_count = |mesh: HalfEdgeMesh| {
+            let mut scene = Scene::new();
+            scene.add(SceneObject::new("cube".into(), mesh, Mat4::IDENTITY));
+            let gltf: serde_json::Value =
+                serde_json::from_str(&scene.to_gltf_string(&Default::default()).unwrap()).unwrap();
+            let attributes = &gltf["meshes"][0]["primitives"][0]["attributes"];
+            let count = |attribute: &str| {
+                gltf["accessors"][attributes[attribute].as_u64().unwrap() as usize]["count"]
+                    .as_u64()
+                    .unwrap()
+            };
+            assert_eq!(count("POSITION"), count("NORMAL"));
+            count("POSITION")
+        };
+
+        // Flat faces don't share any vertices
+        let cube = primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        assert_eq!(vertex_count(cube), 24);
+        // Only the two vertices of the smooth edge are shared
+        assert_eq!(vertex_count(cube_with_one_smooth_edge()), 22);
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::prelude::*;
+
+/// Receives progress updates from a long-running operation, and lets the
+/// operation know when it should stop early.
+pub trait ProgressSink {
+    /// Reports the `progress` of the operation, between 0 and 1. Successive
+    /// reports for the same operation should never decrease.
+    fn report(&self, progress: f32);
+    /// Returns true when the operation should be aborted as soon as possible.
+    fn is_cancelled(&self) -> bool;
+    /// Reports a problem that didn't stop the operation. By default, the
+    /// message is printed.
+    fn warn(&self, warning: Warning) {
+        println!("[WARNING] {}", warning.message);
+    }
+}
+
+/// A problem found by an operation that didn't stop it, like a face that had
+/// to be skipped. Errors fail the whole graph, while warnings are only shown
+/// next to the node that reported them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    /// The elements of the mesh the warning is about, if any, so they can be
+    /// found in the viewport.
+    pub elements: Option<WarningElements>,
+}
+
+/// The ids of the mesh elements a [`Warning`] refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningElements {
+    Vertices(Vec<VertexId>),
+    Faces(Vec<FaceId>),
+    HalfEdges(Vec<HalfEdgeId>),
+}
+
+impl Warning {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            elements: None,
+        }
+    }
+
+    /// Returns this warning, referring to the given `elements`.
+    pub fn with_elements(self, elements: WarningElements) -> Self {
+        Self {
+            elements: Some(elements),
+            ..self
+        }
+    }
+}
+
+impl WarningElements {
+    pub fn len(&self) -> usize {
+        match self {
+            WarningElements::Vertices(ids) => ids.len(),
+            WarningElements::Faces(ids) => ids.len(),
+            WarningElements::HalfEdges(ids) => ids.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the faces of `conn` touching any of the elements. Ids that are
+    /// not in the mesh anymore are ignored.
+    pub fn touched_faces(&self, conn: &MeshConnectivity) -> HashSet<FaceId> {
+        // The faces at both sides of an edge, for halfedges in the mesh
+        let edge_faces = move |h: &HalfEdgeId| {
+            let h = conn.halfedge(*h).map(|_| *h);
+            let twin = h.and_then(|h| conn.at_halfedge(h).twin().try_end().ok());
+            [h, twin]
+                .into_iter()
+                .flatten()
+                .filter_map(move |h| conn.at_halfedge(h).face_or_boundary().ok().flatten())
+        };
+        match self {
+            WarningElements::Vertices(ids) => ids
+                .iter()
+                .filter(|v| conn.vertex_exists(**v))
+                .filter_map(|v| conn.at_vertex(*v).outgoing_halfedges().ok())
+                .flatten()
+                .flat_map(|h| edge_faces(&h))
+                .collect(),
+            WarningElements::Faces(ids) => ids
+                .iter()
+                .filter(|f| conn.face(**f).is_some())
+                .copied()
+                .collect(),
+            WarningElements::HalfEdges(ids) => ids.iter().flat_map(edge_faces).collect(),
+        }
+    }
+}
+
+/// The error returned by an operation that stopped because its
+/// [`ProgressSink`] was cancelled.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The operation was cancelled")
+    }
+}
+impl std::error::Error for Cancelled {}
+
+/// Reports `progress` to the `sink`, if any. Returns a [`Cancelled`] error
+/// when the sink was cancelled, so ops can simply use `?` on this call at the
+/// points where it is safe to stop.
+pub fn report_progress(sink: Option<&dyn ProgressSink>, progress: f32) -> Result<()> {
+    if let Some(sink) = sink {
+        sink.report(progress.clamp(0.0, 1.0));
+        if sink.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+    }
+    Ok(())
+}
+
+/// Reports a warning to the `sink`, or prints it when there is none.
+pub fn report_warning(sink: Option<&dyn ProgressSink>, warning: Warning) {
+    match sink {
+        Some(sink) => sink.warn(warning),
+        None => println!("[WARNING] {}", warning.message),
+    }
+}
+
+thread_local! {
+    /// The sink for the node currently being executed on this thread. Ops are
+    /// called from Lua, so there is no other way to pass it around.
+    static CURRENT_SINK: RefCell<Option<Rc<dyn ProgressSink>>> = RefCell::new(None);
+}
+
+/// Runs `f` with `sink` as the current progress sink for this thread. The
+/// previous sink, if any, is restored afterwards, even when `f` panics.
+pub fn with_progress_sink<T>(sink: Rc<dyn ProgressSink>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Rc<dyn ProgressSink>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_SINK.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+    let _restore = Restore(CURRENT_SINK.with(|current| current.borrow_mut().replace(sink)));
+    f()
+}
+
+/// Returns the progress sink set by the interpreter for the node currently
+/// running, if any. Used by the Lua bindings of ops that report progress.
+pub fn current_sink() -> Option<Rc<dyn ProgressSink>> {
+    CURRENT_SINK.with(|current| current.borrow().clone())
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Reports the progress of the node that is currently running. The
+    /// `value` is a number between 0 and 1. Raises an error if the execution
+    /// was cancelled, so long-running Lua ops should call this regularly.
+    #[lua(under = "Blackjack")]
+    pub fn progress(value: f32) -> Result<()> {
+        report_progress(current_sink().as_deref(), value)
+    }
+
+    /// Reports a warning for the node that is currently running. Unlike
+    /// errors, warnings don't stop the execution, and are shown next to the
+    /// node.
+    ///
+    /// The warning can optionally refer to some elements of the mesh, given
+    /// as a list of `ids` of the `key_type` (e.g. `Types.FACE_ID`), which can
+    /// then be highlighted in the viewport.
+    #[lua(under = "Blackjack")]
+    pub fn warn(
+        message: String,
+        key_type: Option<ChannelKeyType>,
+        ids: Option<mlua::Table>,
+    ) -> Result<()> {
+        let mut warning = Warning::new(message);
+        if let Some(ids) = ids {
+            let key_type =
+                key_type.ok_or_else(|| anyhow!("The key type of the warning ids is missing"))?;
+            warning = warning.with_elements(match key_type {
+                ChannelKeyType::VertexId => {
+                    WarningElements::Vertices(ids.sequence_values().collect::<mlua::Result<_>>()?)
+                }
+                ChannelKeyType::FaceId => {
+                    WarningElements::Faces(ids.sequence_values().collect::<mlua::Result<_>>()?)
+                }
+                ChannelKeyType::HalfEdgeId => {
+                    WarningElements::HalfEdges(ids.sequence_values().collect::<mlua::Result<_>>()?)
+                }
+            });
+        }
+        report_warning(current_sink().as_deref(), warning);
+        Ok(())
+    }
+}
+
+/// A sink that records every report, to be used in tests.
+#[cfg(test)]
+pub struct MockSink {
+    pub reports: RefCell<Vec<f32>>,
+    pub warnings: RefCell<Vec<Warning>>,
+    /// The sink becomes cancelled after this many reports.
+    pub cancel_after: Option<usize>,
+}
+
+#[cfg(test)]
+impl MockSink {
+    pub fn new(cancel_after: Option<usize>) -> Self {
+        Self {
+            reports: RefCell::new(vec![]),
+            warnings: RefCell::new(vec![]),
+            cancel_after,
+        }
+    }
+
+    /// Asserts the reports never decrease and stay in the [0, 1] range.
+    pub fn assert_monotonic(&self) {
+        let reports = self.reports.borrow();
+        assert!(!reports.is_empty(), "No progress was reported");
+        for (a, b) in reports.iter().tuple_windows() {
+            assert!(a <= b, "Progress went backwards: {a} -> {b}");
+        }
+        assert!(reports.iter().all(|x| (0.0..=1.0).contains(x)));
+    }
+}
+
+#[cfg(test)]
+impl ProgressSink for MockSink {
+    fn report(&self, progress: f32) {
+        self.reports.borrow_mut().push(progress);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel_after
+            .map_or(false, |n| self.reports.borrow().len() >= n)
+    }
+
+    fn warn(&self, warning: Warning) {
+        self.warnings.borrow_mut().push(warning);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_current_sink_is_scoped() {
+        assert!(current_sink().is_none());
+        let sink = Rc::new(MockSink::new(None));
+        with_progress_sink(sink.clone(), || {
+            report_progress(current_sink().as_deref(), 0.5).unwrap();
+        });
+        assert!(current_sink().is_none());
+        assert_eq!(*sink.reports.borrow(), vec![0.5]);
+    }
+
+    #[test]
+    fn test_lua_warnings_with_elements() {
+        let runtime =
+            crate::lua_engine::LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let mesh = crate::mesh::halfedge::primitives::Box::build(Vec3::ZERO, Vec3::ONE).unwrap();
+        let conn = mesh.read_connectivity();
+        let (face, _) = conn.iter_faces().next().unwrap();
+        let (vertex, _) = conn.iter_vertices().next().unwrap();
+        runtime.lua.globals().set("face", face).unwrap();
+        runtime.lua.globals().set("vertex", vertex).unwrap();
+
+        let sink = Rc::new(MockSink::new(None));
+        with_progress_sink(sink.clone(), || {
+            runtime
+                .lua
+                .load(
+                    r#"
+                    Blackjack.warn("plain")
+                    Blackjack.warn("face", Types.FACE_ID, { face })
+                    Blackjack.warn("vertex", Types.VERTEX_ID, { vertex })
+                    "#,
+                )
+                .exec()
+                .unwrap();
+        });
+        let warnings = sink.warnings.borrow();
+        assert_eq!(warnings[0], Warning::new("plain"));
+        assert_eq!(
+            warnings[1],
+            Warning::new("face").with_elements(WarningElements::Faces(vec![face]))
+        );
+        let touched = |i: usize| warnings[i].elements.as_ref().unwrap().touched_faces(&conn);
+        assert_eq!(touched(1), [face].into_iter().collect());
+        // Every corner of a box touches three faces
+        assert_eq!(touched(2).len(), 3);
+
+        // Ids without a key type are an error
+        let err = runtime
+            .lua
+            .load(r#"Blackjack.warn("face", nil, { face })"#)
+            .exec();
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_report_after_cancel_fails() {
+        let sink = MockSink::new(Some(1));
+        let err = report_progress(Some(&sink), 0.1).unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Ops that scatter, jitter or otherwise randomize geometry need random
+//! numbers that are the same on every run and on every platform, so that a
+//! graph always produces the same mesh. The generator here is a xoshiro256**,
+//! seeded through splitmix64, and only uses integer arithmetic to produce its
+//! values.
+//!
+//! Each node gets its own seed, derived from a graph-level seed and the seed
+//! stored in the node. This way, two random nodes with the same parameters
+//! don't produce correlated results, and changing the graph seed reshuffles
+//! all of them.
+
+use crate::graph::BjkNode;
+use crate::prelude::*;
+
+/// A seeded, deterministic random number generator.
+#[derive(Debug, Clone)]
+pub struct BjkRng {
+    state: [u64; 4],
+}
+
+/// One step of the splitmix64 generator. Used to expand seeds into the full
+/// xoshiro state, and to mix seeds together.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Combines two seeds into a new one. The result is well distributed even
+/// when the inputs only differ in a few bits.
+pub fn combine_seeds(a: u64, b: u64) -> u64 {
+    let mut b = b;
+    let mut a = a ^ splitmix64(&mut b);
+    splitmix64(&mut a)
+}
+
+/// Returns the seed for `node` in a graph with the given `graph_seed`. It
+/// doesn't change when other nodes are added or removed.
+pub fn node_seed(graph_seed: u64, node: &BjkNode) -> u64 {
+    combine_seeds(graph_seed, node.seed)
+}
+
+impl BjkRng {
+    pub fn new(seed: u64) -> Self {
+        let mut sm = seed;
+        Self {
+            state: [
+                splitmix64(&mut sm),
+                splitmix64(&mut sm),
+                splitmix64(&mut sm),
+                splitmix64(&mut sm),
+            ],
+        }
+    }
+
+    /// Creates a generator for one of several independent streams sharing
+    /// the same `seed`. Nodes use their node seed as the stream, so the same
+    /// user-provided seed gives different results on different nodes.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        Self::new(combine_seeds(seed, stream))
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// Returns a number in the [0, 1) range. Every possible value is a
+    /// multiple of 2^-24, so it can be represented exactly as an `f32`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    /// Returns a number in the [min, max) range.
+    pub fn float(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Returns an integer in the [min, max] range, both ends included. The
+    /// ends can be given in any order.
+    pub fn int(&mut self, min: i64, max: i64) -> i64 {
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
+        let range = (max as u64).wrapping_sub(min as u64).wrapping_add(1);
+        if range == 0 {
+            // The full range of i64
+            return self.next_u64() as i64;
+        }
+        // Reject the values that would make the modulo biased
+        let threshold = range.wrapping_neg() % range;
+        loop {
+            let x = self.next_u64();
+            if x >= threshold {
+                return (min as u64).wrapping_add(x % range) as i64;
+            }
+        }
+    }
+
+    /// Returns a random direction, uniformly distributed over the sphere.
+    pub fn vec3_unit(&mut self) -> Vec3 {
+        // Rejection sampling in the unit cube avoids trigonometric functions,
+        // whose results may differ across platforms.
+        loop {
+            let v = Vec3::new(
+                self.float(-1.0, 1.0),
+                self.float(-1.0, 1.0),
+                self.float(-1.0, 1.0),
+            );
+            let len_sq = v.length_squared();
+            if len_sq > 1e-6 && len_sq <= 1.0 {
+                return v / len_sq.sqrt();
+            }
+        }
+    }
+
+    /// Returns a point uniformly distributed inside the axis-aligned box
+    /// between `min` and `max`.
+    pub fn point_in_box(&mut self, min: Vec3, max: Vec3) -> Vec3 {
+        Vec3::new(
+            self.float(min.x, max.x),
+            self.float(min.y, max.y),
+            self.float(min.z, max.z),
+        )
+    }
+}
+
+/// Converts a seed given as a Lua number. Integer seeds are used as is, so
+/// `Rng.new(42)` in Lua matches `BjkRng::new(42)`. Other numbers, like
+/// fractional seeds coming from scalar parameters, use their bit pattern.
+pub fn seed_from_f64(seed: f64) -> u64 {
+    if seed.fract() == 0.0 && seed.abs() < 9.0e15 {
+        seed as i64 as u64
+    } else {
+        seed.to_bits()
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+    use crate::lua_engine::lua_stdlib::LVec3;
+
+    /// Creates a new random number generator. Nodes should pass their
+    /// `inputs.__node_seed` as the `stream`, so each node gets different
+    /// numbers for the same `seed`. This is what `inputs:rng(seed)` does.
+    #[lua(under = "Rng")]
+    fn new(seed: f64, stream: Option<f64>) -> BjkRng {
+        match stream {
+            Some(stream) => BjkRng::with_stream(seed_from_f64(seed), seed_from_f64(stream)),
+            None => BjkRng::new(seed_from_f64(seed)),
+        }
+    }
+
+    #[lua_impl]
+    impl BjkRng {
+        /// Returns a number in the [min, max) range.
+        #[lua]
+        pub fn float(&mut self, min: f32, max: f32) -> f32;
+
+        /// Returns an integer in the [min, max] range, both ends included.
+        #[lua]
+        pub fn int(&mut self, min: i64, max: i64) -> i64;
+
+        /// Returns a random unit vector.
+        #[lua(map = "LVec3(x)")]
+        pub fn vec3_unit(&mut self) -> Vec3;
+
+        /// Returns a random point inside the box between `min` and `max`.
+        #[lua(map = "LVec3(x)")]
+        pub fn vec3_in_box(&mut self, min: LVec3, max: LVec3) -> Vec3 {
+            self.point_in_box(min.0, max.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pinned_sequences() {
+        let mut rng = BjkRng::new(0);
+        assert_eq!(
+            [rng.next_u64(), rng.next_u64(), rng.next_u64()],
+            [
+                11091344671253066420,
+                13793997310169335082,
+                1900383378846508768
+            ]
+        );
+        let mut rng = BjkRng::new(42);
+        assert_eq!(
+            [rng.next_u64(), rng.next_u64(), rng.next_u64()],
+            [
+                1546998764402558742,
+                6990951692964543102,
+                12544586762248559009
+            ]
+        );
+        let mut rng = BjkRng::new(42);
+        assert_eq!(
+            [
+                rng.float(0.0, 1.0),
+                rng.float(0.0, 1.0),
+                rng.float(-5.0, 5.0)
+            ],
+            [0.08386296, 0.37898022, 1.8004341]
+        );
+        let mut rng = BjkRng::with_stream(7, 123);
+        assert_eq!(
+            [rng.int(1, 6), rng.int(1, 6), rng.int(1, 6), rng.int(1, 6)],
+            [3, 1, 5, 5]
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut rng = BjkRng::new(1234);
+        for _ in 0..1000 {
+            let x = rng.float(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&x));
+            let i = rng.int(10, -10);
+            assert!((-10..=10).contains(&i));
+            let v = rng.vec3_unit();
+            assert!((v.length() - 1.0).abs() < 1e-5);
+            let p = rng.point_in_box(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0));
+            assert!(p.cmpge(Vec3::ZERO).all() && p.cmplt(Vec3::new(1.0, 2.0, 3.0)).all());
+        }
+        assert_eq!(rng.int(5, 5), 5);
+        rng.int(i64::MIN, i64::MAX);
+    }
+
+    #[test]
+    fn test_seeds_are_independent() {
+        assert_ne!(BjkRng::new(1).next_u64(), BjkRng::new(2).next_u64());
+        assert_ne!(
+            BjkRng::with_stream(1, 1).next_u64(),
+            BjkRng::with_stream(1, 2).next_u64()
+        );
+        assert_ne!(combine_seeds(1, 2), combine_seeds(2, 1));
+        assert_eq!(seed_from_f64(42.0), 42);
+        assert_ne!(seed_from_f64(0.5), seed_from_f64(0.25));
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use image::ImageError;
+
+use crate::asset_cache::{Asset, AssetCache};
+use crate::prelude::*;
+use crate::sync::{MaybeSync, RefCounted};
+
+/// An image loaded from disk, with its pixels converted to linear RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// The pixels, row by row, starting from the top of the image. 8 and 16
+    /// bit images are mapped to the [0, 1] range, while floating point images
+    /// keep their values.
+    pub pixels: Vec<Vec3>,
+}
+
+/// What happens when an image is sampled outside of the [0, 1] UV range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageWrap {
+    /// The image is tiled infinitely.
+    Repeat,
+    /// The pixels at the border of the image are extended infinitely.
+    Clamp,
+}
+
+/// How the pixels around a UV coordinate are combined when sampling an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFilter {
+    /// Takes the pixel containing the UV coordinate.
+    Nearest,
+    /// Interpolates between the four closest pixel centers.
+    Bilinear,
+}
+
+/// Returns the luminance of a linear RGB `color`, using the Rec. 709
+/// coefficients.
+pub fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+impl Image {
+    /// Decodes the image at `path`. Errors tell apart missing files, formats
+    /// blackjack can't read and corrupt images.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            bail!("Image not found: {}", path.display());
+        }
+        let unsupported = || {
+            anyhow!(
+                "Unsupported image format: {}. Supported formats are PNG, and EXR when \
+                 blackjack is built with the `exr` feature.",
+                path.display()
+            )
+        };
+        let reader = image::io::Reader::open(path)?.with_guessed_format()?;
+        if reader.format().is_none() {
+            return Err(unsupported());
+        }
+        let image = reader.decode().map_err(|err| match err {
+            ImageError::Unsupported(_) => unsupported(),
+            err => anyhow!("Could not decode image {}: {err}", path.display()),
+        })?;
+
+        let rgb = image.to_rgb32f();
+        Ok(Self {
+            width: rgb.width() as usize,
+            height: rgb.height() as usize,
+            pixels: rgb.pixels().map(|p| Vec3::new(p[0], p[1], p[2])).collect(),
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Returns the color of the image at `uv`, where (0, 0) is the top-left
+    /// corner of the image and (1, 1) the bottom-right one.
+    pub fn sample(&self, uv: Vec2, wrap: ImageWrap, filter: ImageFilter) -> Vec3 {
+        let wrap_coord = |i: i64, len: usize| -> usize {
+            match wrap {
+                ImageWrap::Repeat => i.rem_euclid(len as i64) as usize,
+                ImageWrap::Clamp => i.clamp(0, len as i64 - 1) as usize,
+            }
+        };
+        let texel =
+            |x: i64, y: i64| self.get(wrap_coord(x, self.width), wrap_coord(y, self.height));
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        match filter {
+            ImageFilter::Nearest => {
+                let p = (uv * size).floor();
+                texel(p.x as i64, p.y as i64)
+            }
+            ImageFilter::Bilinear => {
+                // Pixel centers are at half-integer coordinates
+                let p = uv * size - Vec2::splat(0.5);
+                let p0 = p.floor();
+                let t = p - p0;
+                let (x, y) = (p0.x as i64, p0.y as i64);
+                let top = texel(x, y).lerp(texel(x + 1, y), t.x);
+                let bottom = texel(x, y + 1).lerp(texel(x + 1, y + 1), t.x);
+                top.lerp(bottom, t.y)
+            }
+        }
+    }
+}
+
+impl MaybeSync for Image {}
+
+impl Asset for Image {
+    fn memory_size(&self) -> usize {
+        self.pixels.len() * std::mem::size_of::<Vec3>()
+    }
+}
+
+impl Image {
+    /// Same as [`Image::load`], but the image is only decoded when `cache`
+    /// doesn't have it or the file changed.
+    pub fn load_cached(cache: &AssetCache, path: &Path) -> Result<RefCounted<Image>> {
+        cache.get_or_load(path, Image::load)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes a 2x2 checker PNG, with white pixels at the top-left and
+    /// bottom-right corners, and returns its path.
+    pub(crate) fn checker_png(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        image::GrayImage::from_fn(2, 2, |x, y| image::Luma([if x == y { 255 } else { 0 }]))
+            .save(&path)
+            .unwrap();
+        path
+    }
+
+    fn checker() -> Image {
+        Image::load(&checker_png("blackjack_resources_checker.png")).unwrap()
+    }
+
+    #[test]
+    fn test_sample_nearest() {
+        let image = checker();
+        let sample = |u, v, wrap| image.sample(Vec2::new(u, v), wrap, ImageFilter::Nearest).x;
+        assert_eq!(sample(0.25, 0.25, ImageWrap::Repeat), 1.0);
+        assert_eq!(sample(0.75, 0.25, ImageWrap::Repeat), 0.0);
+        assert_eq!(sample(0.75, 0.75, ImageWrap::Repeat), 1.0);
+        // Out of range UVs
+        assert_eq!(sample(-0.25, 0.25, ImageWrap::Repeat), 0.0);
+        assert_eq!(sample(-0.25, 0.25, ImageWrap::Clamp), 1.0);
+        assert_eq!(sample(2.25, 0.25, ImageWrap::Repeat), 1.0);
+        assert_eq!(sample(2.25, 0.25, ImageWrap::Clamp), 0.0);
+    }
+
+    #[test]
+    fn test_sample_bilinear() {
+        let image = checker();
+        let sample = |u, v, wrap| image.sample(Vec2::new(u, v), wrap, ImageFilter::Bilinear).x;
+        // Pixel centers
+        assert!((sample(0.25, 0.25, ImageWrap::Repeat) - 1.0).abs() < 1e-5);
+        assert!(sample(0.75, 0.25, ImageWrap::Repeat).abs() < 1e-5);
+        // Halfway between pixels
+        assert!((sample(0.5, 0.25, ImageWrap::Repeat) - 0.5).abs() < 1e-5);
+        assert!((sample(0.5, 0.5, ImageWrap::Repeat) - 0.5).abs() < 1e-5);
+        // At the border, repeat blends with the opposite side
+        assert!((sample(0.0, 0.25, ImageWrap::Repeat) - 0.5).abs() < 1e-5);
+        assert!((sample(0.0, 0.25, ImageWrap::Clamp) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let missing = std::env::temp_dir().join("blackjack_resources_missing.png");
+        let err = Image::load(&missing).unwrap_err().to_string();
+        assert!(err.contains("not found"), "{err}");
+
+        let unsupported = std::env::temp_dir().join("blackjack_resources.txt");
+        std::fs::write(&unsupported, "not an image").unwrap();
+        let err = Image::load(&unsupported).unwrap_err().to_string();
+        assert!(err.contains("Unsupported"), "{err}");
+
+        let corrupt = std::env::temp_dir().join("blackjack_resources_corrupt.png");
+        std::fs::write(&corrupt, "not a png").unwrap();
+        let err = Image::load(&corrupt).unwrap_err().to_string();
+        assert!(err.contains("Could not decode"), "{err}");
+    }
+
+    #[test]
+    fn test_cache() {
+        let path = checker_png("blackjack_resources_cached.png");
+        let cache = AssetCache::default();
+        let a = Image::load_cached(&cache, &path).unwrap();
+        let b = Image::load_cached(&cache, &path).unwrap();
+        assert!(RefCounted::ptr_eq(&a, &b));
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use mlua::Lua;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// The unit lengths are shown in. Lengths are always stored in meters, in
+/// meshes, parameters and files alike. The unit of a project only changes
+/// how lengths are displayed, and how much some exporters scale the meshes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum LengthUnit {
+    #[default]
+    Meters,
+    Centimeters,
+    Inches,
+    /// A unit with the given length, in meters.
+    Custom(f32),
+}
+
+impl LengthUnit {
+    /// The units that can be picked without entering a scale.
+    pub const PRESETS: [LengthUnit; 3] = [Self::Meters, Self::Centimeters, Self::Inches];
+
+    /// Returns the length of one of these units, in meters.
+    pub fn meters_per_unit(self) -> f32 {
+        match self {
+            LengthUnit::Meters => 1.0,
+            LengthUnit::Centimeters => 0.01,
+            LengthUnit::Inches => 0.0254,
+            LengthUnit::Custom(scale) => scale,
+        }
+    }
+
+    /// Converts a length in this unit to meters.
+    pub fn to_meters(self, value: f32) -> f32 {
+        value * self.meters_per_unit()
+    }
+
+    /// Converts a length in meters to this unit.
+    pub fn from_meters(self, meters: f32) -> f32 {
+        meters / self.meters_per_unit()
+    }
+
+    /// The suffix shown after lengths in this unit.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "m",
+            LengthUnit::Centimeters => "cm",
+            LengthUnit::Inches => "in",
+            LengthUnit::Custom(_) => "u",
+        }
+    }
+
+    /// The name of this unit, as shown in the settings.
+    pub fn label(self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "Meters",
+            LengthUnit::Centimeters => "Centimeters",
+            LengthUnit::Inches => "Inches",
+            LengthUnit::Custom(_) => "Custom",
+        }
+    }
+
+    /// Formats a length in meters as a number in this unit, followed by its
+    /// suffix. Trailing zeros are left out.
+    pub fn format_length(self, meters: f32) -> String {
+        let value = format!("{:.4}", self.from_meters(meters));
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+        format!("{value} {}", self.suffix())
+    }
+
+    /// Returns how much meshes are scaled when exported to `format`.
+    pub fn export_scale(self, format: ExportFormat) -> f32 {
+        match format {
+            ExportFormat::Gltf => 1.0,
+            ExportFormat::Obj => self.from_meters(1.0),
+        }
+    }
+}
+
+/// Written in the metadata of `bjk` files, e.g. `centimeters` or
+/// `custom 0.5`. Custom scales are written with as many digits as needed to
+/// read them back as the same value.
+impl Display for LengthUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LengthUnit::Meters => write!(f, "meters"),
+            LengthUnit::Centimeters => write!(f, "centimeters"),
+            LengthUnit::Inches => write!(f, "inches"),
+            LengthUnit::Custom(scale) => write!(f, "custom {scale}"),
+        }
+    }
+}
+
+impl FromStr for LengthUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(' ') {
+            Some(("custom", scale)) => {
+                let scale: f32 = scale
+                    .parse()
+                    .map_err(|err| anyhow!("Invalid unit scale '{scale}'. {err}"))?;
+                if !(scale.is_finite() && scale > 0.0) {
+                    bail!("The unit scale must be positive, but it was {scale}");
+                }
+                Ok(LengthUnit::Custom(scale))
+            }
+            _ => match s {
+                "meters" => Ok(LengthUnit::Meters),
+                "centimeters" => Ok(LengthUnit::Centimeters),
+                "inches" => Ok(LengthUnit::Inches),
+                _ => bail!("Invalid length unit '{s}'"),
+            },
+        }
+    }
+}
+
+/// The formats meshes can be exported to, which differ in the unit they
+/// expect lengths in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// glTF files are always in meters.
+    Gltf,
+    /// OBJ files have no unit, so they're written in the unit of the project.
+    Obj,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gltf" => Ok(ExportFormat::Gltf),
+            "obj" => Ok(ExportFormat::Obj),
+            _ => bail!("Invalid export format '{s}'"),
+        }
+    }
+}
+
+/// What a scalar parameter measures. Parameters that measure something are
+/// shown in the unit of the project, but stored in meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParamUnit {
+    #[default]
+    None,
+    Length,
+}
+
+impl FromStr for ParamUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "length" => Ok(ParamUnit::Length),
+            _ => bail!("Invalid parameter unit '{s}'"),
+        }
+    }
+}
+
+/// The key for the unit of the running graph in the Lua registry.
+const UNIT_KEY: &str = "__blackjack_length_unit";
+
+/// Sets the unit of the graph running in `lua`, so exporters can scale their
+/// output.
+pub fn set_active_unit(lua: &Lua, unit: LengthUnit) -> Result<()> {
+    lua.set_named_registry_value(UNIT_KEY, unit.to_string())?;
+    Ok(())
+}
+
+/// Returns the unit of the graph running in `lua`. Outside of a graph, this
+/// is meters.
+pub fn active_unit(lua: &Lua) -> Result<LengthUnit> {
+    match lua.named_registry_value::<_, Option<String>>(UNIT_KEY)? {
+        Some(unit) => unit.parse(),
+        None => Ok(LengthUnit::default()),
+    }
+}
+
+#[blackjack_macros::blackjack_lua_module]
+mod lua_api {
+    use super::*;
+
+    /// Returns how much meshes should be scaled when exported to `format`,
+    /// either `"gltf"` or `"obj"`, given the unit of the project.
+    #[lua(under = "Units")]
+    pub fn export_scale(lua: &Lua, format: String) -> Result<f32> {
+        Ok(active_unit(lua)?.export_scale(format.parse()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_length_round_trip() {
+        let units = [
+            LengthUnit::Meters,
+            LengthUnit::Centimeters,
+            LengthUnit::Inches,
+            LengthUnit::Custom(0.3048),
+        ];
+        for unit in units {
+            for meters in [0.0, 1.0, -2.5, 123.456, 1e-3] {
+                let back = unit.to_meters(unit.from_meters(meters));
+                assert!(
+                    (back - meters).abs() <= 1e-6 * meters.abs(),
+                    "{unit:?} {meters}"
+                );
+            }
+        }
+        assert!((LengthUnit::Centimeters.from_meters(1.0) - 100.0).abs() < 1e-4);
+        assert!((LengthUnit::Inches.to_meters(12.0) - 0.3048).abs() < 1e-6);
+        assert_eq!(LengthUnit::Meters.to_meters(3.0), 3.0);
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let units = [
+            LengthUnit::Meters,
+            LengthUnit::Centimeters,
+            LengthUnit::Inches,
+            LengthUnit::Custom(0.3048),
+            LengthUnit::Custom(1.0 / 3.0),
+        ];
+        for unit in units {
+            assert_eq!(unit.to_string().parse::<LengthUnit>().unwrap(), unit);
+        }
+        assert_eq!(LengthUnit::Custom(0.5).to_string(), "custom 0.5");
+        assert!("furlongs".parse::<LengthUnit>().is_err());
+        assert!("custom 0".parse::<LengthUnit>().is_err());
+        assert!("custom -1".parse::<LengthUnit>().is_err());
+        assert!("custom abc".parse::<LengthUnit>().is_err());
+    }
+
+    #[test]
+    fn test_format_length() {
+        assert_eq!(LengthUnit::Meters.format_length(10.0), "10 m");
+        assert_eq!(LengthUnit::Centimeters.format_length(0.1), "10 cm");
+        assert_eq!(LengthUnit::Meters.format_length(0.25), "0.25 m");
+        assert_eq!(LengthUnit::Inches.format_length(0.0254), "1 in");
+    }
+
+    #[test]
+    fn test_export_scale() {
+        let cm = LengthUnit::Centimeters;
+        assert_eq!(cm.export_scale(ExportFormat::Gltf), 1.0);
+        assert!((cm.export_scale(ExportFormat::Obj) - 100.0).abs() < 1e-4);
+        assert_eq!(LengthUnit::Meters.export_scale(ExportFormat::Obj), 1.0);
+    }
+
+    #[test]
+    fn test_active_unit() {
+        let lua = Lua::new();
+        assert_eq!(active_unit(&lua).unwrap(), LengthUnit::Meters);
+        set_active_unit(&lua, LengthUnit::Custom(0.25)).unwrap();
+        assert_eq!(active_unit(&lua).unwrap(), LengthUnit::Custom(0.25));
+    }
+}
@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use blackjack_engine::graph::node_migration::migrate_graph;
 use blackjack_engine::graph::serialization::SerializedBjkGraph;
 use blackjack_engine::graph::BjkGraph;
 use blackjack_engine::graph::BjkNodeId;
@@ -204,8 +205,16 @@ impl BlackjackApi {
                     return None;
                 }
             };
-            let loaded = SerializedBjkGraph::load_from_string(&contents.to_string())
-                .and_then(|x| x.into_runtime());
+            let loaded =
+                SerializedBjkGraph::load_from_string(&contents.to_string()).and_then(|mut x| {
+                    let lua_runtime = &runtime.lua_runtime;
+                    for warning in
+                        migrate_graph(&mut x, &lua_runtime.lua, &lua_runtime.node_definitions)?
+                    {
+                        godot_warn!("{}", warning.describe());
+                    }
+                    x.into_runtime()
+                });
             match loaded {
                 Ok((rt_data, _, _)) => {
                     if let Some(params) = rt_data.external_parameters {
@@ -246,6 +255,14 @@ impl BlackjackApi {
                     let new_s = new_value.try_to::<f32>().ok()?;
                     *s = new_s;
                 }
+                blackjack_engine::graph::BlackjackValue::Int(i) => {
+                    let new_i = new_value.try_to::<i32>().ok()?;
+                    *i = new_i;
+                }
+                blackjack_engine::graph::BlackjackValue::Bool(b) => {
+                    let new_b = new_value.try_to::<bool>().ok()?;
+                    *b = new_b;
+                }
                 blackjack_engine::graph::BlackjackValue::String(s) => {
                     let new_s = new_value.try_to::<String>().ok()?;
                     *s = new_s;
@@ -260,6 +277,13 @@ impl BlackjackApi {
                         *sel = None;
                     }
                 }
+                // Vertex offsets and painted values are edited in the
+                // blackjack UI only
+                blackjack_engine::graph::BlackjackValue::VertexDeltas(_)
+                | blackjack_engine::graph::BlackjackValue::VertexWeights(_) => return Some(false),
+                blackjack_engine::graph::BlackjackValue::Expression(e) => {
+                    *e = new_value.try_to::<String>().ok()?;
+                }
                 blackjack_engine::graph::BlackjackValue::None => {}
             }
             Some(true)
@@ -347,6 +371,18 @@ impl BlackjackApi {
                                 max: *max,
                             })
                         }
+                        (_, BlackjackValue::Int(i)) => params.push(GenericDef {
+                            label,
+                            addr,
+                            typ: "Int".into(),
+                            val: i.to_variant(),
+                        }),
+                        (_, BlackjackValue::Bool(b)) => params.push(GenericDef {
+                            label,
+                            addr,
+                            typ: "Bool".into(),
+                            val: b.to_variant(),
+                        }),
                         (_, BlackjackValue::String(s)) => params.push(GenericDef {
                             label,
                             addr,
@@ -383,7 +419,7 @@ impl BlackjackApi {
         Self::with_runtime(|runtime| {
             let jack = runtime.jacks.get(jack_id)?.as_ref()?;
 
-            match blackjack_engine::graph_interpreter::run_graph(
+            let result = blackjack_engine::graph_interpreter::run_graph(
                 &runtime.lua_runtime.lua,
                 &jack.graph,
                 jack.graph
@@ -393,7 +429,13 @@ impl BlackjackApi {
                 jack.params.clone(),
                 &runtime.lua_runtime.node_definitions,
                 None,
-            ) {
+            );
+            if let Ok(result) = &result {
+                for warning in &result.stats.warnings {
+                    godot_warn!("{}", warning.describe());
+                }
+            }
+            match result {
                 Ok(ProgramResult {
                     renderable: Some(RenderableThing::HalfEdgeMesh(mesh)),
                     ..
@@ -401,6 +443,14 @@ impl BlackjackApi {
                     let godot_mesh = halfedge_to_godot_mesh(&mesh, materials).unwrap();
                     Some(UpdateJackResult::Ok(godot_mesh))
                 }
+                Ok(ProgramResult {
+                    renderable: Some(RenderableThing::Scene(scene)),
+                    ..
+                }) => {
+                    let mesh = scene.to_merged_mesh();
+                    let godot_mesh = halfedge_to_godot_mesh(&mesh, materials).unwrap();
+                    Some(UpdateJackResult::Ok(godot_mesh))
+                }
                 Ok(_) => Some(UpdateJackResult::Err(
                     "This renderable type is not supported. @Heightmap".into(),
                 )),
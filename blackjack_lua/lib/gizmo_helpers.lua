@@ -80,4 +80,30 @@ GizmoHelpers.tweak_transform = function(translation_param, rotation_param, scale
     }
 end
 
+--- Handles are an alternative to the gizmo tables above. A node can define
+--- `gizmos` as a function `gizmos(inputs, out_mesh)`, receiving its inputs and
+--- the first mesh it returned, which returns a list of handles. Each handle is
+--- bound to the parameter named `param`. When a handle is dragged, the node's
+--- optional `on_gizmo_changed(inputs, handles)` function receives the dragged
+--- handles and returns a table with the parameters to update. Without it, each
+--- handle sets the parameter it's bound to.
+
+--- A handle at `position` that can be moved freely, bound to the vector
+--- parameter `param`.
+GizmoHelpers.point = function(param, position)
+    return { kind = "point", param = param, position = position }
+end
+
+--- An arrow going from `origin` to `origin + direction`. Its tip can be
+--- rotated around the origin, and sets the direction parameter `param`.
+GizmoHelpers.arrow = function(param, origin, direction)
+    return { kind = "arrow", param = param, origin = origin, direction = direction }
+end
+
+--- A handle at `origin + axis * value` that slides along `axis`, bound to the
+--- scalar parameter `param`.
+GizmoHelpers.slider = function(param, origin, axis, value)
+    return { kind = "slider", param = param, origin = origin, axis = axis, value = value }
+end
+
 return GizmoHelpers
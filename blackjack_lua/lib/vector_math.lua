@@ -6,22 +6,20 @@
 
 local VectorMath = {}
 
-VectorMath.length = function(v)
-    return math.sqrt(v.x * v.x + v.y * v.y + v.z * v.z)
-end
+-- NOTE: Most of these functions are kept for compatibility. Vectors have
+-- native methods now, e.g. `v:length()` or `v:dot(v2)`. See the `V3` table.
 
-VectorMath.normalize = function(v)
-    return v * (1.0 / VectorMath.length(v))
-end
+VectorMath.length = V3.length
+
+VectorMath.normalize = V3.normalized
 
 VectorMath.distance = function(v1, v2)
-    local d = v2 - v1
-    return math.sqrt(d.x * d.x + d.y * d.y + d.z * d.z)
+    return (v2 - v1):length()
 end
 
 VectorMath.distance_squared = function(v1, v2)
     local d = v2 - v1
-    return d.x * d.x + d.y * d.y + d.z * d.z
+    return d:dot(d)
 end
 
 VectorMath.floor = function(v)
@@ -41,13 +39,9 @@ VectorMath.from_string = function(s)
     end
 end
 
-VectorMath.dot = function(v1, v2)
-    return v1.x * v2.x + v1.y * v2.y + v1.z * v2.z
-end
+VectorMath.dot = V3.dot
 
-VectorMath.cross = function(v, v2)
-    return NativeMath.cross(v, v2)
-end
+VectorMath.cross = V3.cross
 
 VectorMath.rotate_around_axis = function(v, axis, angle)
     return NativeMath.rotate_around_axis(v, axis, angle)
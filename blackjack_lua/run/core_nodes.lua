@@ -11,10 +11,33 @@ local Gz = require("gizmo_helpers")
 local NodeLibrary = require("node_library")
 local Utils = require("utils")
 
+--- Returns a `migrate` function for nodes whose `names` parameters were
+--- scalars before version 2, and are now integers.
+local function scalars_to_ints(names)
+    return function(params, from_version)
+        if from_version < 2 then
+            for _, name in ipairs(names) do
+                if params[name] ~= nil then
+                    params[name] = math.floor(params[name] + 0.5)
+                end
+            end
+        end
+    end
+end
+
 -- Primitives: Construct new meshes based on common patterns
 local primitives = {
     MakeBox = {
         label = "Box",
+        description = [[
+            A box made of six quads, centered at the origin point. See
+            `Primitives.cube`.
+        ]],
+        input_docs = {
+            origin = "The center of the box.",
+            size = "The size of the box along each axis.",
+        },
+        example = "box",
         op = function(inputs)
             return {
                 out_mesh = Primitives.cube(inputs.origin, inputs.size),
@@ -29,9 +52,20 @@ local primitives = {
         },
         gizmos = { Gz.tweak_point("origin") },
         returns = "out_mesh",
+        channels = { produces = {} },
     },
     MakeQuad = {
         label = "Quad",
+        description = [[
+            A single quad, facing in the direction of its normal. See
+            `Primitives.quad`.
+        ]],
+        input_docs = {
+            center = "The center of the quad.",
+            normal = "The direction the quad faces.",
+            right = "The direction of the horizontal side of the quad.",
+            size = "The size of the quad. Only X and Y are used.",
+        },
         op = function(inputs)
             return {
                 out_mesh = Primitives.quad(inputs.center, inputs.normal, inputs.right, inputs.size),
@@ -51,6 +85,18 @@ local primitives = {
     },
     MakeCircle = {
         label = "Circle",
+        description = [[
+            A circle of vertices and edges, on the XZ plane. With an N-Gon fill,
+            the circle gets a single face inside. See `Primitives.circle`.
+        ]],
+        input_docs = {
+            center = "The center of the circle.",
+            radius = "The radius of the circle.",
+            num_vertices = "The number of vertices around the circle.",
+            fill = "Whether the circle is left open, or gets a face inside.",
+        },
+        version = 2,
+        migrate = scalars_to_ints({ "num_vertices" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.circle(
@@ -63,18 +109,73 @@ local primitives = {
         end,
         inputs = {
             P.v3("center", vector(0, 0, 0)),
-            P.scalar("radius", { default = 1.0, min = 0.0 }),
-            P.scalar_int("num_vertices", { default = 8, min = 3, soft_max = 32 }),
+            P.scalar("radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.int("num_vertices", 8, { min = 3, soft_max = 32 }),
             P.enum("fill", { "None", "N-Gon" }, 0),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
-        gizmos = { Gz.tweak_point("center") },
+        gizmos = function(inputs, _out_mesh)
+            return {
+                Gz.point("center", inputs.center),
+                Gz.slider("radius", inputs.center, vector(1, 0, 0), inputs.radius),
+            }
+        end,
+        returns = "out_mesh",
+    },
+    MakeCircleCurve = {
+        label = "Circle Curve",
+        description = [[
+            A closed curve of edges going around a circle, on the XZ plane.
+            Unlike the Circle node, the output is a curve, so it can go into
+            the inputs that need one. See `Primitives.circle`.
+        ]],
+        input_docs = {
+            center = "The center of the circle.",
+            radius = "The radius of the circle.",
+            num_vertices = "The number of vertices around the circle.",
+        },
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.circle(
+                    inputs.center,
+                    inputs.radius,
+                    inputs.num_vertices,
+                    false
+                ),
+            }
+        end,
+        inputs = {
+            P.v3("center", vector(0, 0, 0)),
+            P.scalar("radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.int("num_vertices", 8, { min = 3, soft_max = 32 }),
+        },
+        outputs = {
+            P.curve("out_mesh"),
+        },
+        gizmos = function(inputs, _out_mesh)
+            return {
+                Gz.point("center", inputs.center),
+                Gz.slider("radius", inputs.center, vector(1, 0, 0), inputs.radius),
+            }
+        end,
         returns = "out_mesh",
     },
     MakeUVSphere = {
         label = "UV Sphere",
+        description = [[
+            A sphere made of rings of quads, with triangle fans at the poles.
+            See `Primitives.uv_sphere`.
+        ]],
+        input_docs = {
+            center = "The center of the sphere.",
+            radius = "The radius of the sphere.",
+            segments = "The number of vertices around each ring.",
+            rings = "The number of rings from pole to pole.",
+        },
+        version = 2,
+        migrate = scalars_to_ints({ "segments", "rings" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.uv_sphere(
@@ -87,9 +188,9 @@ local primitives = {
         end,
         inputs = {
             P.v3("center", vector(0, 0, 0)),
-            P.scalar("radius", { default = 1.0, min = 0.0 }),
-            P.scalar_int("segments", { default = 12, min = 3, soft_max = 64 }),
-            P.scalar_int("rings", { default = 6, min = 3, soft_max = 64 }),
+            P.scalar("radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.int("segments", 12, { min = 3, soft_max = 64 }),
+            P.int("rings", 6, { min = 3, soft_max = 64 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -99,6 +200,16 @@ local primitives = {
     },
     MakeLine = {
         label = "Line",
+        description = [[
+            A straight line of edges between two points. See `Primitives.line`.
+        ]],
+        input_docs = {
+            start_point = "Where the line starts.",
+            end_point = "Where the line ends.",
+            segments = "The number of edges the line is made of.",
+        },
+        version = 2,
+        migrate = scalars_to_ints({ "segments" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.line(inputs.start_point, inputs.end_point, inputs.segments),
@@ -107,10 +218,10 @@ local primitives = {
         inputs = {
             P.v3("start_point", vector(0, 0, 0)),
             P.v3("end_point", vector(0.0, 1.0, 0.0)),
-            P.scalar_int("segments", { default = 1, min = 1, soft_max = 32 }),
+            P.int("segments", 1, { min = 1, soft_max = 32 }),
         },
         outputs = {
-            P.mesh("out_mesh"),
+            P.curve("out_mesh"),
         },
         gizmos = { Gz.tweak_point("start_point"), Gz.tweak_point("end_point") },
         returns = "out_mesh",
@@ -124,8 +235,8 @@ local primitives = {
             }
         end,
         inputs = {
-            P.scalar("width", { default = 100.0, min = 0.0, soft_max = 1000.0 }),
-            P.scalar("height", { default = 100.0, min = 0.0, soft_max = 1000.0 }),
+            P.scalar("width", { default = 100.0, min = 0.0, soft_max = 1000.0, unit = "length" }),
+            P.scalar("height", { default = 100.0, min = 0.0, soft_max = 1000.0, unit = "length" }),
             P.lua_str("code"),
         },
         outputs = {
@@ -189,12 +300,26 @@ local primitives = {
             P.strparam("points", "", true),
         },
         outputs = {
-            P.mesh("out_mesh"),
+            P.curve("out_mesh"),
         },
         returns = "out_mesh",
     },
     MakeCone = {
         label = "Cone",
+        description = [[
+            A cone, or a truncated cone when the top radius is not 0. The cone
+            is centered at the center point, along the Y axis. See
+            `Primitives.cone`.
+        ]],
+        input_docs = {
+            center = "The center of the cone.",
+            bottom_radius = "The radius of the base.",
+            top_radius = "The radius of the top. At 0, the cone ends in a point.",
+            height = "The height of the cone.",
+            num_vertices = "The number of vertices around the base.",
+        },
+        version = 2,
+        migrate = scalars_to_ints({ "num_vertices" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.cone(
@@ -208,10 +333,10 @@ local primitives = {
         end,
         inputs = {
             P.v3("center", vector(0, 0, 0)),
-            P.scalar("bottom_radius", { default = 1.0, min = 0.0 }),
-            P.scalar("top_radius", { default = 0.0, min = 0.0 }),
-            P.scalar("height", { default = 1.0, min = 0.0 }),
-            P.scalar_int("num_vertices", { default = 8, min = 3, soft_max = 32 }),
+            P.scalar("bottom_radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.scalar("top_radius", { default = 0.0, min = 0.0, unit = "length" }),
+            P.scalar("height", { default = 1.0, min = 0.0, unit = "length" }),
+            P.int("num_vertices", 8, { min = 3, soft_max = 32 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -221,6 +346,18 @@ local primitives = {
     },
     MakeCylinder = {
         label = "Cylinder",
+        description = [[
+            A closed cylinder along the Y axis, centered at the center point.
+            See `Primitives.cylinder`.
+        ]],
+        input_docs = {
+            center = "The center of the cylinder.",
+            radius = "The radius of the cylinder.",
+            height = "The height of the cylinder.",
+            num_vertices = "The number of vertices around the cylinder.",
+        },
+        version = 2,
+        migrate = scalars_to_ints({ "num_vertices" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.cylinder(
@@ -233,9 +370,9 @@ local primitives = {
         end,
         inputs = {
             P.v3("center", vector(0, 0, 0)),
-            P.scalar("radius", { default = 1.0, min = 0.0 }),
-            P.scalar("height", { default = 1.0, min = 0.0 }),
-            P.scalar_int("num_vertices", { default = 8, min = 3, soft_max = 32 }),
+            P.scalar("radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.scalar("height", { default = 1.0, min = 0.0, unit = "length" }),
+            P.int("num_vertices", 8, { min = 3, soft_max = 32 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -253,8 +390,37 @@ local primitives = {
         inputs = {
             P.scalar_int("x", { default = 3, min = 1, soft_max = 32 }),
             P.scalar_int("y", { default = 3, min = 1, soft_max = 32 }),
-            P.scalar("spacing_x", { default = 1.0, min = 0.0 }),
-            P.scalar("spacing_y", { default = 1.0, min = 0.0 }),
+            P.scalar("spacing_x", { default = 1.0, min = 0.0, unit = "length" }),
+            P.scalar("spacing_y", { default = 1.0, min = 0.0, unit = "length" }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+    },
+    MakeLattice = {
+        label = "Lattice",
+        doc = [[
+            Creates the control points of a lattice: A grid of points filling
+            the box between min and max, with the given number of points along
+            each axis. Move the points and connect them to a Lattice Deform
+            node to deform another mesh.
+        ]],
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.lattice(
+                    inputs.min,
+                    inputs.max,
+                    vector(inputs.res_x, inputs.res_y, inputs.res_z)
+                ),
+            }
+        end,
+        inputs = {
+            P.v3("min", vector(-1, -1, -1)),
+            P.v3("max", vector(1, 1, 1)),
+            P.scalar_int("res_x", { default = 2, min = 2, soft_max = 16 }),
+            P.scalar_int("res_y", { default = 2, min = 2, soft_max = 16 }),
+            P.scalar_int("res_z", { default = 2, min = 2, soft_max = 16 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -263,6 +429,8 @@ local primitives = {
     },
     MakeCatenary = {
         label = "Catenary",
+        version = 2,
+        migrate = scalars_to_ints({ "segments" }),
         op = function(inputs)
             return {
                 out_mesh = Primitives.catenary(
@@ -277,16 +445,24 @@ local primitives = {
             P.v3("start_point", vector(0, 0, 0)),
             P.v3("end_point", vector(1, 0, 0)),
             P.scalar("sag", { default = 1.0, min = 0.001 }),
-            P.scalar_int("segments", { default = 8, min = 1, soft_max = 32 }),
+            P.int("segments", 8, { min = 1, soft_max = 32 }),
         },
         outputs = {
-            P.mesh("out_mesh"),
+            P.curve("out_mesh"),
         },
         gizmos = { Gz.tweak_point("start_point"), Gz.tweak_point("end_point") },
         returns = "out_mesh",
     },
     MakeIcosahedron = {
         label = "Icosahedron",
+        description = [[
+            An icosahedron: The regular solid made of twenty triangles. See
+            `Primitives.icosahedron`.
+        ]],
+        input_docs = {
+            center = "The center of the icosahedron.",
+            radius = "The distance from the center to each vertex.",
+        },
         op = function(inputs)
             return {
                 out_mesh = Primitives.icosahedron(inputs.center, inputs.radius)
@@ -294,7 +470,7 @@ local primitives = {
         end,
         inputs = {
             P.v3("center", vector(0, 0, 0)),
-            P.scalar("radius", {default = 1.0, min = 0}),
+            P.scalar("radius", { default = 1.0, min = 0, unit = "length" }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -302,16 +478,253 @@ local primitives = {
         gizmos = { Gz.tweak_point("center"), },
         returns = "out_mesh",
     },
+    MakeQuadSphere = {
+        label = "Quad Sphere",
+        description = [[
+            A sphere made only of quads, by projecting a subdivided cube onto
+            it. Unlike the UV sphere, it has no poles, so it subdivides
+            cleanly. See `Primitives.quad_sphere`.
+        ]],
+        input_docs = {
+            center = "The center of the sphere.",
+            radius = "The radius of the sphere.",
+            subdivisions = "The number of times each side of the cube is divided.",
+        },
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.quad_sphere(
+                    inputs.center,
+                    inputs.radius,
+                    inputs.subdivisions
+                ),
+            }
+        end,
+        inputs = {
+            P.v3("center", vector(0, 0, 0)),
+            P.scalar("radius", { default = 1.0, min = 0.0, unit = "length" }),
+            P.scalar_int("subdivisions", { default = 4, min = 1, soft_max = 32 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        gizmos = { Gz.tweak_point("center") },
+        returns = "out_mesh",
+        channels = { produces = { { key = "halfedge", name = "uv" } } },
+    },
+    MakeRoundedBox = {
+        label = "Rounded Box",
+        description = [[
+            A box with rounded edges and corners, made only of quads. The
+            corner radius is limited to half the smallest side of the box, and
+            a radius of 0 gives a regular box. See `Primitives.rounded_box`.
+        ]],
+        input_docs = {
+            center = "The center of the box.",
+            size = "The size of the box along each axis.",
+            corner_radius = "The radius of the rounded edges and corners.",
+            corner_segments = "The number of quads along each rounded edge.",
+        },
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.rounded_box(
+                    inputs.center,
+                    inputs.size,
+                    inputs.corner_radius,
+                    inputs.corner_segments
+                ),
+            }
+        end,
+        inputs = {
+            P.v3("center", vector(0, 0, 0)),
+            P.v3("size", vector(1, 1, 1)),
+            P.scalar("corner_radius", {
+                default = 0.1,
+                min = 0.0,
+                soft_max = 1.0,
+                unit = "length",
+            }),
+            P.scalar_int("corner_segments", { default = 3, min = 1, soft_max = 16 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        gizmos = { Gz.tweak_point("center") },
+        returns = "out_mesh",
+        channels = { produces = { { key = "halfedge", name = "uv" } } },
+    },
+    MakeStreamedGrid = {
+        label = "Streamed Grid",
+        doc = [[
+            A flat grid of quads on the XZ plane, centered at the origin. The
+            grid is generated a few rows at a time by a coroutine, which is how
+            nodes can build meshes too large to make in one go. Each chunk
+            reports its progress, and gets its own instruction budget.
+        ]],
+        op = function(inputs)
+            local nx, nz = inputs.columns, inputs.rows
+            local step_x, step_z = inputs.size.x / nx, inputs.size.z / nz
+            local origin = -inputs.size / 2
+            local function row_positions(row)
+                local positions = {}
+                for i = 0, nx do
+                    table.insert(positions, origin + vector(i * step_x, 0, row * step_z))
+                end
+                return positions
+            end
+            return {
+                out_mesh = coroutine.create(function()
+                    local first_row = 0
+                    local positions = row_positions(0)
+                    while first_row < nz do
+                        local last_row = math.min(first_row + inputs.chunk_rows, nz)
+                        local faces = {}
+                        for row = first_row, last_row - 1 do
+                            for _, p in ipairs(row_positions(row + 1)) do
+                                table.insert(positions, p)
+                            end
+                            for i = 0, nx - 1 do
+                                local a = row * (nx + 1) + i
+                                local b = a + nx + 1
+                                table.insert(faces, { a, b, b + 1, a + 1 })
+                            end
+                        end
+                        coroutine.yield(positions, faces, last_row / nz)
+                        positions = {}
+                        first_row = last_row
+                    end
+                end),
+            }
+        end,
+        inputs = {
+            P.v3("size", vector(10, 0, 10)),
+            P.scalar_int("columns", { default = 100, min = 1, soft_max = 1000 }),
+            P.scalar_int("rows", { default = 100, min = 1, soft_max = 1000 }),
+            P.scalar_int("chunk_rows", { default = 10, min = 1, soft_max = 100 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+    },
+    HeightmapImport = {
+        label = "Heightmap Import",
+        doc = [[
+            Builds a grid from a heightmap image, with one vertex per pixel
+            displaced vertically by the pixel value. Large images are
+            downsampled to the resolution cap. The raw heights are stored in
+            the 'height' vertex channel.
+        ]],
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.heightmap_image(
+                    inputs.path,
+                    vector(inputs.width, inputs.depth, 0),
+                    inputs.height_scale,
+                    inputs.resolution_cap
+                ),
+            }
+        end,
+        inputs = {
+            P.file("path", "open"),
+            P.scalar("width", { default = 10.0, min = 0.0, unit = "length" }),
+            P.scalar("depth", { default = 10.0, min = 0.0, unit = "length" }),
+            P.scalar("height_scale", { default = 1.0, soft_min = 0.0, soft_max = 10.0 }),
+            P.scalar_int("resolution_cap", { default = 256, min = 2, soft_max = 1024 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = {
+            produces = {
+                { key = "vertex", name = "height" },
+                { key = "halfedge", name = "uv" },
+            },
+        },
+    },
+    LSystem = {
+        label = "L-System",
+        doc = [[
+            Draws branching curves by rewriting the axiom with the rules a
+            number of times. Rules go one per line, like 'F -> F[+F]F'. A
+            symbol with several rules picks one at random, with an optional
+            weight after a colon, like 'F -> F[-F]F : 0.5'.
+
+            F draws a segment, f moves without drawing, + and - turn, & and ^
+            pitch, \ and / roll, | turns around, and brackets draw branches.
+            The nesting level of the branches is stored in the 'depth' vertex
+            channel.
+        ]],
+        op = function(inputs)
+            local rules = {}
+            for line in inputs.rules:gmatch("[^\n]+") do
+                local symbol, production = line:match("^%s*(%S)%s*%->%s*(.-)%s*$")
+                if symbol then
+                    local weight = 1
+                    local body, w = production:match("^(.-)%s*:%s*([%d%.]+)$")
+                    if body then
+                        production, weight = body, tonumber(w)
+                    end
+                    rules[symbol] = rules[symbol] or {}
+                    table.insert(rules[symbol], { production, weight })
+                elseif line:match("%S") then
+                    error("Invalid L-system rule: '" .. line .. "'")
+                end
+            end
+            local seed = inputs:rng(inputs.seed):int(0, 4294967295)
+            return {
+                out_mesh = Primitives.l_system(
+                    inputs.axiom,
+                    rules,
+                    inputs.iterations,
+                    inputs.angle,
+                    inputs.step,
+                    seed,
+                    inputs.max_segments
+                ),
+            }
+        end,
+        inputs = {
+            P.strparam("axiom", "F", false),
+            P.strparam("rules", "F -> F[+F]F[-F]F", true),
+            P.scalar_int("iterations", { default = 3, min = 0, soft_max = 8 }),
+            P.scalar("angle", { default = 25.7, soft_min = 0.0, soft_max = 180.0 }),
+            P.scalar("step", { default = 0.1, min = 0.0, soft_max = 1.0, unit = "length" }),
+            P.scalar_int("seed", { default = 0, min = 0, soft_max = 100 }),
+            P.scalar_int("max_segments", { default = 100000, min = 1, soft_max = 1000000 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "vertex", name = "depth" } } },
+    },
 }
 
 -- Edit ops: Nodes to edit existing meshes
 local edit_ops = {
     BevelEdges = {
         label = "Bevel Edges",
+        description = [[
+            Bevels the selected edges. With more than one segment, the bevel
+            follows a profile curve. A shape of 0.5 gives a flat profile,
+            around 0.7 gives a round one, and lower values a concave one.
+            See `Ops.bevel`.
+        ]],
+        input_docs = {
+            in_mesh = "The mesh to bevel.",
+            edges = "The edges to bevel.",
+            amount = "The width of the bevel.",
+            segments = "The number of faces across the bevel.",
+            shape = "The profile of the bevel, from concave to flat and round.",
+        },
+        example = "bevel_edges",
         inputs = {
             P.mesh("in_mesh"),
             P.selection("edges"),
-            P.scalar("amount", { default = 0.0, min = 0.0, soft_max = 1.0 }),
+            P.scalar("amount", { default = 0.0, min = 0.0, soft_max = 1.0, unit = "length" }),
+            P.scalar_int("segments", { default = 1, min = 1, soft_max = 16 }),
+            P.scalar("shape", { default = 0.5, min = 0.0, max = 1.0 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -319,16 +732,25 @@ local edit_ops = {
         returns = "out_mesh",
         op = function(inputs)
             local out_mesh = inputs.in_mesh:clone()
-            Ops.bevel(inputs.edges, inputs.amount, out_mesh)
+            Ops.bevel(inputs.edges, inputs.amount, out_mesh, inputs.segments, inputs.shape)
             return { out_mesh = out_mesh }
         end,
     },
     ChamferVertices = {
         label = "Chamfer Vertices",
+        description = [[
+            Cuts off the corners at the selected vertices, replacing each vertex
+            with a face. See `Ops.chamfer`.
+        ]],
+        input_docs = {
+            in_mesh = "The mesh to chamfer.",
+            vertices = "The vertices to chamfer.",
+            amount = "How far from each vertex the cut is made, along its edges.",
+        },
         inputs = {
             P.mesh("in_mesh"),
             P.selection("vertices"),
-            P.scalar("amount", { default = 0.0, min = 0.0, soft_max = 1.0 }),
+            P.scalar("amount", { default = 0.0, min = 0.0, soft_max = 1.0, unit = "length" }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -342,18 +764,80 @@ local edit_ops = {
     },
     ExtrudeFaces = {
         label = "Extrude Faces",
+        description = [[
+            Extrudes the selected faces along their averaged normals. With
+            an even offset, the extruded faces stay at the same distance
+            from the original ones where they meet at an angle. The maximum
+            factor limits how much farther the vertices on sharp corners
+            can move. See `Ops.extrude`.
+        ]],
+        input_docs = {
+            in_mesh = "The mesh to extrude.",
+            faces = "The faces to extrude.",
+            amount = "How far the faces move. Negative values extrude inwards.",
+            even_offset = "Keeps the extruded faces at the same distance from the original ones.",
+            max_factor = "How many times the amount the vertices can move with an even offset.",
+        },
+        example = "extrude_faces",
         inputs = {
             P.mesh("in_mesh"),
             P.selection("faces"),
-            P.scalar("amount", { default = 0.0 }),
+            P.scalar("amount", { default = 0.0, unit = "length" }),
+            P.bool("even_offset", false),
+            P.scalar("max_factor", { default = 3.0, min = 1.0, soft_max = 10.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        -- Version 2 added the even offset, which is off for older nodes
+        version = 2,
+        migrate = function(_params, _from_version) end,
+        op = function(inputs)
+            local out_mesh = inputs.in_mesh:clone()
+            Ops.extrude(
+                inputs.faces,
+                inputs.amount,
+                out_mesh,
+                inputs.even_offset,
+                inputs.max_factor
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    -- Keeps its original op name, so graphs saved before the delete modes
+    -- were added still load
+    DeleteFaces = {
+        label = "Delete",
+        description = [[
+            Deletes the selected faces, edges or vertices, along with the faces
+            that use them. Deleting faces with their boundary kept leaves the
+            edges around the hole in place. See `Ops.delete`.
+        ]],
+        input_docs = {
+            in_mesh = "The mesh to delete from.",
+            selection = "The faces, edges or vertices to delete.",
+            mode = "What the selection refers to, and what is deleted.",
+        },
+        inputs = {
+            P.mesh("in_mesh"),
+            P.selection("selection"),
+            P.enum("mode", { "Faces", "FacesKeepBoundary", "Edges", "Vertices" }, 0),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
+        version = 2,
+        migrate = function(params, from_version)
+            if from_version < 2 then
+                params.selection = params.faces
+                params.faces = nil
+            end
+        end,
         op = function(inputs)
             local out_mesh = inputs.in_mesh:clone()
-            Ops.extrude(inputs.faces, inputs.amount, out_mesh)
+            Ops.delete(out_mesh, inputs.selection, inputs.mode)
             return { out_mesh = out_mesh }
         end,
     },
@@ -413,6 +897,14 @@ local edit_ops = {
     },
     MergeMeshes = {
         label = "Merge Meshes",
+        description = [[
+            Combines two meshes into one, without connecting them. See
+            `Ops.merge`.
+        ]],
+        input_docs = {
+            mesh_a = "The first mesh, whose shading the result keeps.",
+            mesh_b = "The mesh added to the first one.",
+        },
         inputs = {
             P.mesh("mesh_a"),
             P.mesh("mesh_b"),
@@ -429,6 +921,16 @@ local edit_ops = {
     },
     Subdivide = {
         label = "Subdivide",
+        description = [[
+            Subdivides every face of the mesh. The linear technique splits the
+            faces without moving any vertex, while Catmull-Clark smooths the
+            mesh as well. See `Ops.subdivide`.
+        ]],
+        input_docs = {
+            mesh = "The mesh to subdivide.",
+            technique = "How the new faces are placed.",
+            iterations = "How many times the mesh is subdivided.",
+        },
         inputs = {
             P.mesh("mesh"),
             P.enum("technique", { "linear", "catmull-clark" }, 0),
@@ -438,6 +940,7 @@ local edit_ops = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
+        cost = { base = 4, exponent = "iterations" },
         op = function(inputs)
             if inputs.iterations < 1 then
                 return { out_mesh = inputs.mesh:clone() }
@@ -452,103 +955,141 @@ local edit_ops = {
             end
         end,
     },
-    SubdivideEdge = {
-        label = "Divide Edges",
+    Decimate = {
+        label = "Decimate",
+        doc = [[
+            Reduces the number of faces of the mesh by collapsing its shortest
+            edges. The ratio is the fraction of faces to keep.
+        ]],
         inputs = {
-            P.mesh("in_mesh"),
-            P.selection("edges"),
-            P.scalar("interp", { default = 0.5, soft_min = 0.0, soft_max = 1.0 }),
-            P.scalar_int("divisions", { default = 1, min = 1, soft_max = 32 }),
+            P.mesh("mesh"),
+            P.scalar("ratio", { default = 0.5, min = 0.0, max = 1.0 }),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.in_mesh:clone()
-            Ops.divide_edges(out_mesh, inputs.edges, inputs.interp, inputs.divisions)
+            local out_mesh = inputs.mesh:clone()
+            Ops.decimate(out_mesh, inputs.ratio)
             return { out_mesh = out_mesh }
         end,
     },
-    CutFace = {
-        label = "Cut Face",
+    TrisToQuads = {
+        label = "Tris to Quads",
+        doc = [[
+            Merges pairs of adjacent triangles into quads, best quads first.
+            The max angle limits both how far the two triangles can be from
+            flat, and how far each corner of the quad can be from a right
+            angle. Respecting uv seams keeps triangles with different uvs at
+            their shared edge apart.
+        ]],
         inputs = {
-            P.mesh("in_mesh"),
-            P.selection("a"),
-            P.selection("b"),
+            P.mesh("mesh"),
+            P.selection("faces"),
+            P.scalar("max_angle", { default = 40.0, min = 0.0, max = 90.0 }),
+            P.enum("uv_seams", { "Respect", "Ignore" }, 0),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.in_mesh:clone()
-            Ops.cut_face(out_mesh, inputs.a, inputs.b)
+            local out_mesh = inputs.mesh:clone()
+            Ops.tris_to_quads(out_mesh, inputs.faces, inputs.max_angle, inputs.uv_seams == "Respect")
             return { out_mesh = out_mesh }
         end,
     },
-    SetNormals = {
-        label = "Set Normals",
+    VoxelRemesh = {
+        label = "Voxel Remesh",
+        doc = [[
+            Rebuilds the surface of the mesh from a grid of voxels. The result
+            is always closed and manifold, which cleans up self-intersections
+            and overlapping parts, but details smaller than a voxel are lost.
+        ]],
         inputs = {
             P.mesh("mesh"),
-            P.enum("normals", { "smooth", "flat" }, 0),
+            P.scalar("voxel_size", { default = 0.1, min = 0.001, soft_max = 1.0, unit = "length" }),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.mesh:clone()
-            if inputs.normals == "smooth" then
-                Ops.set_smooth_normals(out_mesh)
-            else
-                Ops.set_flat_normals(out_mesh)
-            end
-            return { out_mesh = out_mesh }
+            return { out_mesh = Ops.voxel_remesh(inputs.mesh, inputs.voxel_size) }
         end,
     },
-    Transform = {
-        label = "Transform",
+    Remesh = {
+        label = "Remesh",
+        doc = [[
+            Rebuilds the surface of the mesh with triangles of roughly the same
+            size. Edges where the faces meet at a sharper angle than the given
+            one are kept, and so is the boundary. Projecting moves the new
+            vertices back onto the original surface, so curved parts don't
+            shrink.
+        ]],
         inputs = {
             P.mesh("mesh"),
-            P.v3("translate", vector(0, 0, 0)),
-            P.v3("rotate", vector(0, 0, 0)),
-            P.v3("scale", vector(1, 1, 1)),
+            P.scalar("edge_length", { default = 0.1, min = 0.001, soft_max = 1.0, unit = "length" }),
+            P.int("iterations", 5, { min = 0, soft_max = 20 }),
+            P.scalar("sharp_angle", { default = 30.0, min = 0.0, max = 180.0 }),
+            P.bool("project", true),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.mesh:clone()
-            Ops.transform(out_mesh, inputs.translate, inputs.rotate, inputs.scale)
             return {
-                out_mesh = out_mesh,
+                out_mesh = Ops.isotropic_remesh(
+                    inputs.mesh,
+                    inputs.edge_length,
+                    inputs.iterations,
+                    inputs.sharp_angle,
+                    inputs.project
+                ),
             }
         end,
-        gizmos = { Gz.tweak_transform("translate", "rotate", "scale") },
     },
-    VertexAttribTransfer = {
-        label = "Vertex Attribute Transfer",
+    ConvexHull = {
+        label = "Convex Hull",
+        doc = [[
+            Returns the convex hull of the vertices of the mesh, the smallest
+            convex shape containing all of them. Useful as a simple collision
+            shape. Coplanar faces of the hull are merged into polygons.
+        ]],
         inputs = {
-            P.mesh("src_mesh"),
-            P.mesh("dst_mesh"),
-            P.strparam("channel", "", false),
+            P.mesh("mesh"),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.dst_mesh:clone()
-            Ops.vertex_attribute_transfer(inputs.src_mesh, out_mesh, Types.VEC3, inputs.channel)
-            return { out_mesh = out_mesh }
+            return { out_mesh = Ops.convex_hull(inputs.mesh) }
         end,
     },
-    SetFullRangeUVs = {
-        label = "Set Full Range UVs",
+    Symmetrize = {
+        label = "Symmetrize",
+        doc = [[
+            Makes the mesh mirror-symmetric across a plane. The side of the
+            plane given by the direction is kept, and a mirrored copy of it
+            replaces the other side. Faces crossing the plane are cut at it.
+
+            Vertices closer to the plane than the weld threshold are moved
+            onto it, and joined with their mirror image.
+        ]],
         inputs = {
             P.mesh("mesh"),
+            P.v3("plane_origin", vector(0, 0, 0)),
+            P.v3("plane_normal", vector(1, 0, 0)),
+            P.enum("direction", { "Positive to negative", "Negative to positive" }, 0),
+            P.scalar("weld_threshold", {
+                default = 0.001,
+                min = 0.0,
+                soft_max = 0.1,
+                unit = "length",
+            }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -556,34 +1097,68 @@ local edit_ops = {
         returns = "out_mesh",
         op = function(inputs)
             local out_mesh = inputs.mesh:clone()
-            Ops.set_full_range_uvs(out_mesh)
+            local directions = {
+                ["Positive to negative"] = "PositiveToNegative",
+                ["Negative to positive"] = "NegativeToPositive",
+            }
+            Ops.symmetrize(
+                out_mesh,
+                inputs.plane_origin,
+                inputs.plane_normal,
+                directions[inputs.direction],
+                inputs.weld_threshold
+            )
             return { out_mesh = out_mesh }
         end,
     },
-    SetMaterial = {
-        label = "Set Material",
+    Bisect = {
+        label = "Bisect",
+        doc = [[
+            Slices the mesh with a plane, splitting the faces crossing it.
+            Either both sides are kept, or only the one above or below the
+            plane, where above is the side the normal points to.
+
+            With cap, each loop of the cut is filled with a face. When both
+            sides are kept, capping also separates them into two parts.
+        ]],
         inputs = {
             P.mesh("mesh"),
-            P.selection("faces"),
-            P.scalar_int("material_index", { default = 0, min = 0 }),
+            P.v3("plane_origin", vector(0, 0, 0)),
+            P.v3("plane_normal", vector(0, 1, 0)),
+            P.enum("keep", { "Both", "Above", "Below" }, 0),
+            P.enum("cap", { "No", "Yes" }, 0),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = inputs.mesh:clone()
-            Ops.set_material(out_mesh, inputs.faces, inputs.material_index)
-            return { out_mesh = out_mesh }
+            return {
+                out_mesh = Ops.bisect(
+                    inputs.mesh,
+                    inputs.plane_origin,
+                    inputs.plane_normal,
+                    inputs.keep,
+                    inputs.cap == "Yes"
+                ),
+            }
         end,
     },
-    MakeGroup = {
-        label = "Group",
+    GridFill = {
+        label = "Grid Fill",
+        doc = [[
+            Fills a hole with a grid of quads. The boundary must be a single
+            loop with an even number of edges.
+
+            The span is the number of edges along two opposite sides of the
+            grid, with 0 picking the span that makes opposite sides the most
+            similar. The offset moves the corners of the grid along the loop.
+        ]],
         inputs = {
             P.mesh("mesh"),
-            P.enum("type", { "Vertex", "Face", "Halfedge" }, 0),
-            P.strparam("name", ""),
-            P.selection("selection"),
+            P.selection("boundary"),
+            P.int("span", 0, { min = 0, soft_max = 32 }),
+            P.int("offset", 0, { soft_min = -16, soft_max = 16 }),
         },
         outputs = {
             P.mesh("out_mesh"),
@@ -591,12 +1166,921 @@ local edit_ops = {
         returns = "out_mesh",
         op = function(inputs)
             local out_mesh = inputs.mesh:clone()
-            local typ = Utils.parse_ch_key(inputs.type)
-            Ops.make_group(out_mesh, typ, inputs.selection, inputs.name)
+            Ops.grid_fill(out_mesh, inputs.boundary, inputs.span, inputs.offset)
             return { out_mesh = out_mesh }
         end,
     },
-    EditChannels = {
+    SetEdgeFlow = {
+        label = "Set Edge Flow",
+        doc = [[
+            Moves the vertices of the selected edge loops so they follow the
+            curvature of the surface around them, like after cutting new loops
+            through a curved surface. A tension of 1 follows the surface, and 0
+            leaves the loops straight. Boundary vertices are not moved.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("edges"),
+            P.int("iterations", 1, { min = 1, soft_max = 10 }),
+            P.scalar("tension", { default = 1.0, soft_min = 0.0, soft_max = 2.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.set_edge_flow(out_mesh, inputs.edges, inputs.iterations, inputs.tension)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    ManualEdit = {
+        label = "Manual Edit",
+        doc = [[
+            Moves individual vertices by hand. Offsets are recorded by dragging
+            the vertices in the viewport's edit mode. Offsets for vertices that
+            are no longer in the input mesh are skipped.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.vertex_deltas("deltas"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.apply_vertex_deltas(out_mesh, inputs.deltas)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    PaintChannel = {
+        label = "Paint Channel",
+        doc = [[
+            Writes hand-painted values to a vertex channel, to use as a mask in
+            later nodes. Values are painted with the brush of the viewport's
+            paint mode, and go from 0 to 1. Vertices that weren't painted keep
+            the value they had in the input mesh, and painted values for
+            vertices that are no longer in the input mesh are skipped.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.strparam("channel", "mask", false),
+            P.vertex_weights("weights"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "vertex", param = "channel" } } },
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            Ops.apply_vertex_weights(out_mesh, inputs.channel, inputs.weights)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SubdivideEdge = {
+        label = "Divide Edges",
+        inputs = {
+            P.mesh("in_mesh"),
+            P.selection("edges"),
+            P.scalar("interp", { default = 0.5, soft_min = 0.0, soft_max = 1.0 }),
+            P.scalar_int("divisions", { default = 1, min = 1, soft_max = 32 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.in_mesh:clone()
+            Ops.divide_edges(out_mesh, inputs.edges, inputs.interp, inputs.divisions)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    CutFace = {
+        label = "Cut Face",
+        inputs = {
+            P.mesh("in_mesh"),
+            P.selection("a"),
+            P.selection("b"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.in_mesh:clone()
+            Ops.cut_face(out_mesh, inputs.a, inputs.b)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SetNormals = {
+        label = "Set Shading",
+        doc = [[
+            Sets whether the mesh is shaded smooth or flat. With auto smooth,
+            the viewport and exported files get hard edges where faces meet at
+            a sharper angle than the auto smooth angle, and at the edges with a
+            crease of 1.
+
+            The shading is kept by the mesh. When meshes are merged, the result
+            is shaded like the first one, unless it had no faces.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("normals", { "smooth", "flat", "auto smooth" }, 0),
+            P.scalar("auto_smooth_angle", { default = 30.0, min = 0.0, max = 180.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.set_shading(out_mesh, inputs.normals, inputs.auto_smooth_angle)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    Transform = {
+        label = "Transform",
+        description = [[
+            Translates, rotates and scales the selected vertices of the mesh.
+
+            Rotation and scale are applied around the pivot, which can be the
+            origin, the center of the bounding box, the centroid of the surface
+            or a custom point. The rotation is given either as XYZ euler angles
+            (in radians), or as an axis and an angle (in degrees).
+
+            When "orient" is enabled, the mesh is first rotated so that its
+            local axis points in the target direction.
+            See `Ops.transform_with_options`.
+        ]],
+        input_docs = {
+            mesh = "The mesh to transform.",
+            selection = "The vertices to transform.",
+            translate = "How far the vertices move.",
+            rotation_mode = "How the rotation is given.",
+            rotate = "The euler angles, or the axis of the rotation.",
+            angle = "The angle of the rotation around the axis, in degrees.",
+            scale = "The scale along each axis.",
+            pivot = "The point that stays in place when rotating and scaling.",
+            pivot_point = "The pivot, when it's custom.",
+            orient = "Whether to orient the mesh first.",
+            orient_axis = "The local axis of the mesh to orient.",
+            orient_target = "The direction the axis is rotated to.",
+        },
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("selection", "*"),
+            P.v3("translate", vector(0, 0, 0)),
+            P.enum("rotation_mode", { "Euler", "Axis angle" }, 0),
+            P.v3("rotate", vector(0, 0, 0)),
+            P.scalar("angle", { default = 0.0, soft_min = -180.0, soft_max = 180.0 }),
+            P.v3("scale", vector(1, 1, 1)),
+            P.enum("pivot", { "Origin", "Bounds center", "Centroid", "Custom" }, 0),
+            P.v3("pivot_point", vector(0, 0, 0)),
+            P.enum("orient", { "No", "Yes" }, 0),
+            P.v3("orient_axis", vector(0, 1, 0)),
+            P.v3("orient_target", vector(0, 1, 0)),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        deform_only = true,
+        -- Version 2 added the selection, pivot, rotation mode and orient
+        -- inputs. Their defaults transform like older nodes did.
+        version = 2,
+        migrate = function(_params, _from_version) end,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local pivot = inputs.pivot_point
+            -- Graphs that weren't migrated have none of the newer inputs
+            if inputs.pivot == nil or inputs.pivot == "Origin" then
+                pivot = "Origin"
+            elseif inputs.pivot == "Bounds center" then
+                pivot = "BoundsCenter"
+            elseif inputs.pivot == "Centroid" then
+                pivot = "Centroid"
+            end
+            local angle = nil
+            if inputs.rotation_mode == "Axis angle" then
+                angle = math.rad(inputs.angle)
+            end
+            local orient_axis, orient_target = nil, nil
+            if inputs.orient == "Yes" then
+                orient_axis, orient_target = inputs.orient_axis, inputs.orient_target
+            end
+            Ops.transform_with_options(
+                out_mesh,
+                inputs.selection,
+                pivot,
+                inputs.translate,
+                inputs.rotate,
+                angle,
+                inputs.scale,
+                orient_axis,
+                orient_target
+            )
+            return {
+                out_mesh = out_mesh,
+            }
+        end,
+        gizmos = { Gz.tweak_transform("translate", "rotate", "scale") },
+    },
+    SplitEdges = {
+        label = "Split edges",
+        doc = [[
+            Inserts a new vertex on each of the selected edges. The factor sets
+            the position of the vertex along the edge, where 0.5 is the
+            midpoint.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("edges"),
+            P.scalar("factor", { default = 0.5, min = 0.0, max = 1.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.split_edges(out_mesh, inputs.edges, inputs.factor)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    ConnectVertices = {
+        label = "Connect vertices",
+        doc = [[
+            Connects each vertex in the first selection with the vertex at the
+            same position in the second selection, splitting the face they
+            share in two. Both selections must have the same length.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("a"),
+            P.selection("b"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.connect_vertices(out_mesh, inputs.a, inputs.b)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SlideEdges = {
+        label = "Slide edges",
+        doc = [[
+            Slides the selected edges along their adjacent faces. Negative
+            factors slide the edges towards the opposite side.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("edges"),
+            P.scalar("factor", { default = 0.0, min = -1.0, max = 1.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.slide_edges(out_mesh, inputs.edges, inputs.factor)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    Quantize = {
+        label = "Quantize",
+        doc = [[
+            Snaps the selected vertices to a grid. Each component of the grid
+            size sets the spacing on that axis, a value of zero disables
+            snapping on that axis.
+
+            Snapping vertices may leave some faces with zero area. The number
+            of such faces is returned in the "degenerate_faces" output.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("selection", "*"),
+            P.v3("grid_size", vector(0.1, 0.1, 0.1)),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+            P.scalar("degenerate_faces"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local degenerate = Ops.quantize(out_mesh, inputs.selection, inputs.grid_size)
+            return { out_mesh = out_mesh, degenerate_faces = degenerate }
+        end,
+    },
+    Flatten = {
+        label = "Flatten",
+        doc = [[
+            Projects the selected vertices onto a plane. The plane is either
+            axis-aligned, where the coordinate on the chosen axis is set to the
+            given value, or the best-fit plane of the selected vertices.
+
+            Flattening vertices may leave some faces with zero area. The number
+            of such faces is returned in the "degenerate_faces" output.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("selection", "*"),
+            P.enum("plane", { "X", "Y", "Z", "Best fit" }, 1),
+            P.scalar("value", { default = 0.0, soft_min = -10.0, soft_max = 10.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+            P.scalar("degenerate_faces"),
+        },
+        returns = "out_mesh",
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local axis = inputs.plane
+            if axis == "Best fit" then
+                axis = "BestFit"
+            end
+            local degenerate = Ops.flatten(out_mesh, inputs.selection, axis, inputs.value)
+            return { out_mesh = out_mesh, degenerate_faces = degenerate }
+        end,
+    },
+    Jitter = {
+        label = "Jitter",
+        doc = [[
+            Moves each selected vertex by a random offset, up to the given
+            amount on each axis. In "Normal" mode, the Z component of the
+            amount goes along the vertex normals, and the X and Y components
+            along the surface.
+
+            When a mask channel is given, the offset of each vertex is scaled
+            by its value in that vertex channel.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("selection", "*"),
+            P.v3("amount", vector(0.1, 0.1, 0.1)),
+            P.scalar_int("seed", { default = 0, min = 0, soft_max = 100 }),
+            P.enum("mode", { "World", "Normal" }, 0),
+            P.strparam("mask_channel", "", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { requires = { { key = "vertex", param = "mask_channel" } } },
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local seed = inputs:rng(inputs.seed):int(0, 4294967295)
+            Ops.jitter(out_mesh, inputs.selection, inputs.amount, seed, inputs.mask_channel, inputs.mode)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    TransformIslands = {
+        label = "Transform Islands",
+        doc = [[
+            Moves, rotates and scales each connected component of the mesh by
+            its own random transform, around the centroid or the center of the
+            bounds of that island. Rotations are XYZ euler angles, in radians.
+            Each island is scaled uniformly, by a factor between the minimum
+            and maximum scale.
+
+            The index of the island of each face is written to a face channel,
+            so later nodes can use it as a mask.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.v3("translate_jitter", vector(0, 0, 0)),
+            P.v3("rotate_jitter", vector(0, 0, 0)),
+            P.scalar("scale_min", { default = 1.0, min = 0.0, soft_max = 2.0 }),
+            P.scalar("scale_max", { default = 1.0, min = 0.0, soft_max = 2.0 }),
+            P.scalar_int("seed", { default = 0, min = 0, soft_max = 100 }),
+            P.enum("pivot", { "Centroid", "Bounds center" }, 0),
+            P.strparam("index_channel", "island_index", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "face", param = "index_channel" } } },
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local seed = inputs:rng(inputs.seed):int(0, 4294967295)
+            local pivot = "Centroid"
+            if inputs.pivot == "Bounds center" then
+                pivot = "BoundsCenter"
+            end
+            Ops.transform_islands(
+                out_mesh,
+                inputs.translate_jitter,
+                inputs.rotate_jitter,
+                inputs.scale_min,
+                inputs.scale_max,
+                seed,
+                pivot,
+                inputs.index_channel
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SampleImage = {
+        label = "Sample Image",
+        doc = [[
+            Samples an image at the UVs of the mesh, and writes the result to
+            a channel. The UV channel can be either per corner (halfedge) or
+            per vertex, and the output channel uses the same kind of element.
+
+            "Luminance" writes an f32 channel, useful as a mask for other
+            nodes, while "Color" writes the RGB color in a Vec3 channel.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.file("image_path", "open"),
+            P.strparam("uv_channel", "uv", false),
+            P.strparam("out_channel", "mask", false),
+            P.enum("out_kind", { "Luminance", "Color" }, 0),
+            P.enum("wrap", { "Repeat", "Clamp" }, 0),
+            P.enum("filter", { "Bilinear", "Nearest" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = {
+            requires = { { param = "uv_channel" } },
+            produces = { { param = "out_channel" } },
+        },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local out_kind = Types.F32
+            if inputs.out_kind == "Color" then
+                out_kind = Types.VEC3
+            end
+            Ops.sample_image(
+                out_mesh,
+                inputs.image_path,
+                inputs.uv_channel,
+                inputs.out_channel,
+                out_kind,
+                inputs.wrap,
+                inputs.filter
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    BakeAO = {
+        label = "Bake AO",
+        doc = [[
+            Bakes ambient occlusion into an f32 vertex channel: The fraction of
+            the rays cast from each vertex that don't hit the mesh. Exposed
+            vertices get 1, and vertices in creases and cavities get less.
+            Only hits closer than the max distance count.
+
+            More samples give smoother results, but take longer to bake.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.scalar_int("samples", { default = 64, min = 1, soft_max = 1024 }),
+            P.scalar("max_distance", {
+                default = 1.0,
+                min = 0.0,
+                soft_max = 10.0,
+                unit = "length",
+            }),
+            P.strparam("out_channel", "ao", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "vertex", param = "out_channel" } } },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.bake_ao(out_mesh, inputs.samples, inputs.max_distance, inputs.out_channel)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    Curvature = {
+        label = "Curvature",
+        doc = [[
+            Computes the curvature at each vertex into an f32 vertex channel.
+
+            The mean curvature is positive where the surface is convex and
+            negative where it's concave, which is useful to find edges and
+            creases. The gaussian curvature is positive on bumps and dents,
+            negative on saddles, and zero on flat and cylindrical regions.
+
+            Curvature is measured in inverse units of length, so it gets
+            larger as the mesh is scaled down.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("kind", { "Mean", "Gaussian" }, 0),
+            P.strparam("out_channel", "curvature", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "vertex", param = "out_channel" } } },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.compute_curvature(out_mesh, inputs.kind, inputs.out_channel)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    ProportionalMove = {
+        label = "Proportional Move",
+        doc = [[
+            Moves the selected vertices by the given offset, and the vertices
+            around them by a fraction of it. The fraction goes down with the
+            distance to the selection, following the falloff curve, and is
+            zero beyond the radius.
+
+            In "Topological" mode, distances are measured along the edges of
+            the mesh, so nearby parts that aren't connected to the selection
+            stay in place. When a weight channel name is given, the influence
+            on each vertex is stored in that vertex channel.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("selection"),
+            P.v3("offset", vector(0, 1, 0)),
+            P.scalar("radius", { default = 1.0, min = 0.0, soft_max = 10.0, unit = "length" }),
+            P.enum("falloff", { "Smooth", "Linear", "Sphere", "Sharp" }, 0),
+            P.enum("mode", { "Euclidean", "Topological" }, 0),
+            P.strparam("weight_channel", "", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "vertex", param = "weight_channel" } } },
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            Ops.proportional_translate(
+                out_mesh,
+                inputs.selection,
+                inputs.offset,
+                inputs.radius,
+                inputs.falloff,
+                inputs.mode,
+                inputs.weight_channel
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    Blend = {
+        label = "Blend",
+        doc = [[
+            Interpolates between two meshes with the same topology, like two
+            variations of a model built by the same nodes. A factor of 0 gives
+            the first mesh, and 1 gives the second one. Values outside that
+            range extrapolate.
+
+            Vertex positions are interpolated, along with any numeric channels
+            present in both meshes.
+        ]],
+        inputs = {
+            P.mesh("a"),
+            P.mesh("b"),
+            P.scalar("t", { default = 0.5, soft_min = 0.0, soft_max = 1.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            return { out_mesh = Ops.blend_meshes(inputs.a, inputs.b, inputs.t, true) }
+        end,
+    },
+    Shrinkwrap = {
+        label = "Shrinkwrap",
+        doc = [[
+            Moves the vertices of the mesh onto the surface of the target.
+            Each vertex goes to the closest point on the target's surface, to
+            the closest vertex of the target, or to the point hit by a ray cast
+            along the projection direction. In "Project" mode, the vertices
+            whose ray misses the target stay in place.
+
+            The offset pushes the vertices away from the target's surface,
+            along its normal.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.mesh("target"),
+            P.enum("mode", { "Nearest surface", "Project", "Nearest vertex" }, 0),
+            P.v3("direction", vector(0, -1, 0)),
+            P.scalar("offset", { default = 0.0, soft_min = -1.0, soft_max = 1.0, unit = "length" }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local modes = {
+                ["Nearest surface"] = "NearestSurface",
+                ["Project"] = "Project",
+                ["Nearest vertex"] = "NearestVertex",
+            }
+            Ops.shrinkwrap(out_mesh, inputs.target, modes[inputs.mode], inputs.offset, inputs.direction)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    LatticeDeform = {
+        label = "Lattice Deform",
+        doc = [[
+            Deforms the mesh by moving the control points of a lattice. The
+            lattice must come from a Lattice node with the same bounds and
+            resolution as set here, and its points can then be moved by other
+            nodes. Each vertex inside the bounds follows the displacement of
+            the control points around it.
+
+            Trilinear interpolation only uses the points of the lattice cell
+            around each vertex. Bezier interpolation is smoother, but every
+            control point affects the whole lattice. Vertices outside the
+            bounds stay in place, or follow the boundary of the lattice when
+            "outside" is set to "Clamp".
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.mesh("lattice"),
+            P.v3("min", vector(-1, -1, -1)),
+            P.v3("max", vector(1, 1, 1)),
+            P.scalar_int("res_x", { default = 2, min = 2, soft_max = 16 }),
+            P.scalar_int("res_y", { default = 2, min = 2, soft_max = 16 }),
+            P.scalar_int("res_z", { default = 2, min = 2, soft_max = 16 }),
+            P.enum("interpolation", { "Trilinear", "Bezier" }, 0),
+            P.enum("outside", { "Unaffected", "Clamp" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            Ops.lattice_deform(
+                out_mesh,
+                inputs.lattice,
+                inputs.min,
+                inputs.max,
+                vector(inputs.res_x, inputs.res_y, inputs.res_z),
+                inputs.interpolation,
+                inputs.outside == "Clamp"
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SimpleDeform = {
+        label = "Simple Deform",
+        doc = [[
+            Twists, bends or tapers the mesh along an axis passing through the
+            origin point.
+
+            Twist rotates the mesh around the axis, and Bend curves the axis
+            into an arc, both by the given angle (in degrees). Taper scales the
+            mesh away from the axis, growing by the given factor along it.
+
+            Only the part of the mesh between the lower and upper limits, as
+            fractions of its length along the axis, is deformed. The parts
+            past the upper limit follow the deformation at the limit.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("kind", { "Twist", "Bend", "Taper" }, 0),
+            P.v3("axis", vector(0, 1, 0)),
+            P.v3("origin", vector(0, 0, 0)),
+            P.scalar("angle", { default = 45.0, soft_min = -360.0, soft_max = 360.0 }),
+            P.scalar("factor", { default = 0.5, soft_min = -1.0, soft_max = 1.0 }),
+            P.scalar("lower_limit", { default = 0.0, min = 0.0, max = 1.0 }),
+            P.scalar("upper_limit", { default = 1.0, min = 0.0, max = 1.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        deform_only = true,
+        op = function(inputs)
+            local out_mesh = inputs.mesh
+            local amount = inputs.factor
+            if inputs.kind ~= "Taper" then
+                amount = math.rad(inputs.angle)
+            end
+            Ops.simple_deform(
+                out_mesh,
+                inputs.kind,
+                inputs.axis,
+                inputs.origin,
+                amount,
+                inputs.lower_limit,
+                inputs.upper_limit
+            )
+            return { out_mesh = out_mesh }
+        end,
+    },
+    VertexAttribTransfer = {
+        label = "Vertex Attribute Transfer",
+        inputs = {
+            P.mesh("src_mesh"),
+            P.mesh("dst_mesh"),
+            P.strparam("channel", "", false),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.dst_mesh:clone()
+            Ops.vertex_attribute_transfer(inputs.src_mesh, out_mesh, Types.VEC3, inputs.channel)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SetFullRangeUVs = {
+        label = "Set Full Range UVs",
+        inputs = {
+            P.mesh("mesh"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "halfedge", name = "uv" } } },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.set_full_range_uvs(out_mesh)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    PackUVs = {
+        label = "Pack UVs",
+        doc = [[
+            Moves the uv islands of the mesh so they don't overlap, packing
+            them into the unit square. All the islands are scaled by the same
+            amount, keeping the margin between them. Rotating turns tall
+            islands sideways, which usually packs them tighter.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.scalar("margin", { default = 0.01, min = 0.0, soft_max = 0.1 }),
+            P.enum("rotate", { "No", "Yes" }, 1),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { requires = { { key = "halfedge", name = "uv" } } },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.pack_uv_islands(out_mesh, inputs.margin, inputs.rotate == "Yes")
+            return { out_mesh = out_mesh }
+        end,
+    },
+    Unwrap = {
+        label = "Unwrap",
+        description = [[
+            Unwraps the mesh into uv islands, cutting it along the seams, and
+            along the edges where faces meet at a sharper angle than the seam
+            angle when automatic seams are on. Each island is cut some more if
+            needed to lay it flat, and flattened keeping the angles of its
+            faces. ABF spreads the distortion of curved islands more evenly
+            than LSCM. The islands are left next to each other at their size
+            in 3d, so they usually go through Pack UVs next. See `Ops.unwrap`.
+        ]],
+        input_docs = {
+            mesh = "The mesh to unwrap.",
+            seams = "The edges to cut the uvs along.",
+            auto_seams = "Whether to also cut along sharp edges.",
+            seam_angle = "The angle between faces, in degrees, above which edges are cut.",
+            method = "How to flatten the islands.",
+        },
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("seams"),
+            P.enum("auto_seams", { "Off", "On" }, 1),
+            P.scalar("seam_angle", { default = 60.0, min = 0.0, max = 180.0 }),
+            P.enum("method", { "LSCM", "ABF" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        channels = { produces = { { key = "halfedge", name = "uv" } } },
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local angle = nil
+            if inputs.auto_seams == "On" then
+                angle = inputs.seam_angle
+            end
+            Ops.unwrap(out_mesh, inputs.seams, angle, inputs.method)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    RecalculateNormals = {
+        label = "Recalculate Normals/Winding",
+        description = [[
+            Flips the faces that are wound the other way from their neighbors,
+            like the ones of imported meshes or boolean results that shade
+            black in patches. Parts of the mesh that don't share vertices are
+            matched along the edges at the same positions. Closed surfaces are
+            made to face outwards or inwards, and open ones keep the winding
+            most of their faces have. Surfaces like a Möbius strip can't be
+            fixed, and show a warning at an edge where the winding flips. See
+            `Ops.recalculate_winding`.
+        ]],
+        input_docs = {
+            mesh = "The mesh to fix the winding of.",
+            direction = "Where the faces of closed surfaces should point to.",
+        },
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("direction", { "Outward", "Inward" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.recalculate_winding(out_mesh, inputs.direction == "Outward")
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SetMaterial = {
+        label = "Set Material",
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("faces"),
+            P.scalar_int("material_index", { default = 0, min = 0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.set_material(out_mesh, inputs.faces, inputs.material_index)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    MakeGroup = {
+        label = "Group",
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("type", { "Vertex", "Face", "Halfedge" }, 0),
+            P.strparam("name", ""),
+            P.selection("selection"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local typ = Utils.parse_ch_key(inputs.type)
+            Ops.make_group(out_mesh, typ, inputs.selection, inputs.name)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SetTag = {
+        label = "Tag",
+        doc = [[
+            Tags the selected elements with a string, in the tag channel with
+            the given name. Tagged elements can be selected later on with
+            `tag(name, "value")`, and extruding or subdividing copies the tags
+            to the new elements.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("type", { "Vertex", "Face", "Halfedge" }, 1),
+            P.strparam("name", "tag"),
+            P.selection("selection"),
+            P.strparam("value", ""),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local typ = Utils.parse_ch_key(inputs.type)
+            out_mesh:set_tag(typ, inputs.name, inputs.selection, inputs.value)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    EditChannels = {
         label = "Edit Channels",
         inputs = {
             P.mesh("mesh"),
@@ -659,7 +2143,7 @@ local edit_ops = {
             return { out_mesh = Ops.copy_to_points(inputs.points, inputs.mesh) }
         end,
         inputs = {
-            P.mesh("points"),
+            P.points("points"),
             P.mesh("mesh"),
         },
         outputs = {
@@ -679,7 +2163,7 @@ local edit_ops = {
             }
         end,
         inputs = {
-            P.mesh("backbone"),
+            P.curve("backbone"),
             P.mesh("cross_section"),
             P.scalar_int("flip", { default = 0.0, min = 0.0, soft_max = 4.0 }),
         },
@@ -688,6 +2172,33 @@ local edit_ops = {
         },
         returns = "out_mesh",
     },
+    RailExtrude = {
+        label = "Rail Extrude",
+        doc = [[
+            Extrudes the selected boundary edges once for each segment of the
+            rail polyline, so the profile follows the rail, like a gutter or a
+            molding along a path. The rail is placed relative to the profile,
+            so it doesn't need to touch the mesh.
+
+            When align is enabled, the profile also turns to follow the rail,
+            without twisting. Otherwise it's only moved along it.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("boundary"),
+            P.curve("rail"),
+            P.enum("align", { "No", "Yes" }, 1),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            Ops.rail_extrude(out_mesh, inputs.boundary, inputs.rail, inputs.align == "Yes")
+            return { out_mesh = out_mesh }
+        end,
+    },
     ResampleCurve = {
         label = "Resample Curve",
         op = function(inputs)
@@ -702,14 +2213,14 @@ local edit_ops = {
             }
         end,
         inputs = {
-            P.mesh("curve"),
+            P.curve("curve"),
             P.enum("density_mode", { "Uniform", "Curvature" }, 0),
             P.scalar("density", { default = 1.0, min = 0.05, soft_max = 10.0 }),
             P.scalar("tension", { default = 0.0, min = 0.0, max = 1.0 }),
             P.scalar("alpha", { default = 0.5, min = 0.0, max = 1.0 }),
         },
         outputs = {
-            P.mesh("out_mesh"),
+            P.curve("out_mesh"),
         },
         returns = "out_mesh",
     },
@@ -723,19 +2234,60 @@ local edit_ops = {
             P.selection("points"),
         },
         outputs = {
-            P.mesh("out_mesh"),
+            P.points("out_mesh"),
         },
         returns = "out_mesh",
     },
+    ToCurve = {
+        label = "To Curve",
+        description = [[
+            Lets a mesh go into the inputs that need a curve. Fails when the
+            mesh has faces, or lines that branch. See `Ops.to_curve`.
+        ]],
+        input_docs = {
+            mesh = "The mesh made of lines to use as a curve.",
+        },
+        inputs = {
+            P.mesh("mesh"),
+        },
+        outputs = {
+            P.curve("out_curve"),
+        },
+        returns = "out_curve",
+        op = function(inputs)
+            return { out_curve = Ops.to_curve(inputs.mesh) }
+        end,
+    },
+    ToPoints = {
+        label = "To Points",
+        description = [[
+            Lets a mesh go into the inputs that need a point cloud. Fails when
+            the mesh has edges or faces, use a Point Cloud node to keep only
+            the vertices of a mesh. See `Ops.to_points`.
+        ]],
+        input_docs = {
+            mesh = "The mesh made of vertices to use as a point cloud.",
+        },
+        inputs = {
+            P.mesh("mesh"),
+        },
+        outputs = {
+            P.points("out_points"),
+        },
+        returns = "out_points",
+        op = function(inputs)
+            return { out_points = Ops.to_points(inputs.mesh) }
+        end,
+    },
     -- TODO: This should be a more generic randomize channel
     RandomizeSize = {
         label = "Randomize Size",
         op = function(inputs)
             local mesh = inputs.mesh:clone()
             local size_ch = mesh:ensure_channel(Types.VERTEX_ID, Types.F32, "size")
-            math.randomseed(inputs.seed)
+            local rng = inputs:rng(inputs.seed)
             for i = 0, #size_ch do
-                size_ch[i] = math.random() * inputs.scale
+                size_ch[i] = rng:float(0, inputs.scale)
             end
             mesh:set_channel(Types.VERTEX_ID, Types.F32, "size", size_ch)
             return { out_mesh = mesh }
@@ -892,34 +2444,236 @@ local math_nodes = {
     },
 }
 
+-- Scene: Nodes to arrange several meshes as separate, named objects
+local scene = {
+    AddObject = {
+        label = "Add object",
+        doc = [[
+            Adds the mesh to the scene as a new object, placed with the given
+            transform. Rotations are XYZ euler angles, in radians. When the
+            scene input is not connected, a new scene is created.
+
+            With more than one instance, copies of the object are added, each
+            one moved by the offset from the previous one. Instances share
+            their mesh, so exporters only write it once.
+        ]],
+        inputs = {
+            P.scene("scene"),
+            P.mesh("mesh"),
+            P.strparam("name", "object"),
+            P.v3("translate", vector(0, 0, 0)),
+            P.v3("rotate", vector(0, 0, 0)),
+            P.v3("scale", vector(1, 1, 1)),
+            P.scalar_int("instances", { default = 1, min = 1, soft_max = 32 }),
+            P.v3("offset", vector(1, 0, 0)),
+        },
+        outputs = {
+            P.scene("out_scene"),
+        },
+        returns = "out_scene",
+        op = function(inputs)
+            local out_scene
+            if inputs.scene then
+                out_scene = inputs.scene:clone()
+            else
+                out_scene = Scene.new()
+            end
+            local object = Ops.make_object(inputs.mesh, inputs.name, {
+                translate = inputs.translate,
+                rotate = inputs.rotate,
+                scale = inputs.scale,
+            })
+            Ops.scene_add(out_scene, object)
+            for i = 1, inputs.instances - 1 do
+                local instance = object:instance(inputs.name .. "." .. i, {
+                    translate = inputs.translate + inputs.offset * i,
+                    rotate = inputs.rotate,
+                    scale = inputs.scale,
+                })
+                Ops.scene_add(out_scene, instance)
+            end
+            return { out_scene = out_scene }
+        end,
+    },
+    SceneToMesh = {
+        label = "Scene to mesh",
+        doc = [[
+            Merges all the objects in the scene into a single mesh, with their
+            transforms applied.
+        ]],
+        inputs = {
+            P.scene("scene"),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            return { out_mesh = inputs.scene:to_mesh() }
+        end,
+    },
+}
+
+--- Returns the file an export node should write to. Relative paths are
+--- relative to the folder of the graph, once it has been saved, and the
+--- `extension` is added to file names that don't have one.
+local function export_path(path, extension)
+    if not Path.is_absolute(path) then
+        local saved, dir = pcall(Path.project_dir)
+        if saved then
+            path = Path.join(dir, path)
+        end
+    end
+    if not Path.filename(path):find(".", 1, true) then
+        path = Path.with_extension(path, extension)
+    end
+    return path
+end
+
+--- Returns the scale an OBJ export node should apply, from its `units`
+--- parameter. OBJ files have no unit, so they are written in the unit of the
+--- project unless meters are asked for.
+local function obj_export_scale(units)
+    if units == "Meters" then
+        return 1.0
+    end
+    return Units.export_scale("obj")
+end
+
+-- The axis conventions import and export nodes can convert from and to, as
+-- understood by `Ops.convert_axes`. The first one is the one blackjack uses.
+local axis_conventions = {
+    ["Y-up right-handed"] = "YUpRightHanded",
+    ["Z-up right-handed"] = "ZUpRightHanded",
+    ["Y-up left-handed"] = "YUpLeftHanded",
+}
+local axis_convention_labels = { "Y-up right-handed", "Z-up right-handed", "Y-up left-handed" }
+
 -- Export: Nodes to export the generated meshes outside of blacjack
 local export = {
     ExportObj = {
         label = "Export OBJ",
+        doc = [[
+            Exports the mesh as a Wavefront OBJ file. OBJ files have no unit,
+            so lengths are written in the unit of the project, unless the
+            units are set to meters. The axes are the up axis and handedness
+            the file is written in, Z-up for tools like Blender.
+        ]],
         inputs = {
             P.mesh("mesh"),
             P.file("path"),
+            P.enum("units", { "Project", "Meters" }, 0),
+            P.enum("axes", axis_convention_labels, 0),
+        },
+        outputs = {},
+        executable = true,
+        op = function(inputs)
+            HalfEdgeMesh.to_wavefront_obj(
+                inputs.mesh,
+                export_path(inputs.path, "obj"),
+                obj_export_scale(inputs.units),
+                axis_conventions[inputs.axes]
+            )
+        end,
+    },
+    ExportSceneObj = {
+        label = "Export scene OBJ",
+        doc = [[
+            Exports the scene as a Wavefront OBJ file, with one group per
+            object. Lengths are written in the unit of the project, unless
+            the units are set to meters, and positions are converted to the
+            given axes.
+        ]],
+        inputs = {
+            P.scene("scene"),
+            P.file("path"),
+            P.enum("units", { "Project", "Meters" }, 0),
+            P.enum("axes", axis_convention_labels, 0),
+        },
+        outputs = {},
+        executable = true,
+        op = function(inputs)
+            Scene.to_wavefront_obj(
+                inputs.scene,
+                export_path(inputs.path, "obj"),
+                obj_export_scale(inputs.units),
+                axis_conventions[inputs.axes]
+            )
+        end,
+    },
+    ExportGltf = {
+        label = "Export glTF",
+        doc = [[
+            Exports the scene as a glTF file. Each object becomes a node, and
+            instances of an object reference the same mesh. glTF files are
+            always in meters and Y-up right-handed, whatever the unit of the
+            project.
+        ]],
+        inputs = {
+            P.scene("scene"),
+            P.file("path"),
         },
         outputs = {},
         executable = true,
         op = function(inputs)
-            HalfEdgeMesh.to_wavefront_obj(inputs.mesh, inputs.path)
+            Scene.to_gltf(inputs.scene, export_path(inputs.path, "gltf"))
         end,
     },
     ImportObj = {
         label = "Import OBJ",
+        doc = [[
+            Reads a mesh from a Wavefront OBJ file. The axes are the up axis
+            and handedness the file was written in, and the mesh is converted
+            from them.
+        ]],
         inputs = {
             P.file("path", "open"),
+            P.enum("axes", axis_convention_labels, 0),
         },
         outputs = {
             P.mesh("out_mesh"),
         },
         returns = "out_mesh",
         op = function(inputs)
-            local out_mesh = HalfEdgeMesh.from_wavefront_obj(inputs.path)
+            local axes = axis_conventions[inputs.axes]
+            local out_mesh = HalfEdgeMesh.from_wavefront_obj(inputs.path, axes)
             return { out_mesh = out_mesh }
         end,
     },
+    ImportPly = {
+        label = "Import PLY",
+        doc = [[
+            Reads a mesh from a PLY file, either ascii or binary. Only the
+            vertex positions and the faces are imported. The file is only
+            parsed again when it changes. The axes are the up axis and
+            handedness the file was written in.
+        ]],
+        inputs = {
+            P.file("path", "open"),
+            P.enum("axes", axis_convention_labels, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local axes = axis_conventions[inputs.axes]
+            return { out_mesh = HalfEdgeMesh.from_ply(inputs.path, axes) }
+        end,
+    },
+}
+
+-- The measurements of the Probe node, as understood by `Ops.probe`
+local probe_modes = {
+    "BoundsMin",
+    "BoundsMax",
+    "BoundsSize",
+    "Centroid",
+    "VertexPosition",
+    "FaceCenter",
+    "ChannelValue",
+    "SurfaceArea",
+    "VertexCount",
 }
 
 -- Miscelaneous nodes
@@ -967,10 +2721,340 @@ local misc = {
             }
         end,
     },
+    MeshStats = {
+        label = "Mesh Stats",
+        doc = [[
+            Computes some statistics about the mesh. The triangles are the
+            ones the faces would have once triangulated. The volume is only
+            meaningful for closed meshes, which have no boundary loops.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+        },
+        outputs = {
+            P.int("vertices"),
+            P.int("edges"),
+            P.int("faces"),
+            P.int("triangles"),
+            P.int("boundary_loops"),
+            P.int("components"),
+            P.bool("is_closed"),
+            P.scalar("area"),
+            P.scalar("volume"),
+            P.v3("bounds_min"),
+            P.v3("bounds_max"),
+            P.strparam("summary"),
+        },
+        -- Version 2 added the triangle, boundary loop and component counts,
+        -- and whether the mesh is closed. Version 3 made all the counts
+        -- integers, which can still be connected to scalar inputs.
+        version = 3,
+        migrate = function(_params, _from_version) end,
+        op = function(inputs)
+            local stats = Ops.mesh_stats(inputs.mesh)
+            return {
+                vertices = stats.num_vertices,
+                edges = stats.num_edges,
+                faces = stats.num_faces,
+                triangles = stats.num_triangles,
+                boundary_loops = stats.num_boundary_loops,
+                components = stats.num_components,
+                is_closed = stats.is_closed,
+                area = stats.surface_area,
+                volume = stats.volume,
+                bounds_min = stats.bounds_min,
+                bounds_max = stats.bounds_max,
+                summary = stats.summary,
+            }
+        end,
+    },
+    Probe = {
+        label = "Probe",
+        doc = [[
+            Measures the mesh, so other parameters can depend on it, like
+            extruding by a fraction of its height. Depending on the mode, the
+            measurement is a vector or a number. Vectors are split into x, y
+            and z, and their length goes to the scalar output. Numbers go to
+            the scalar output, and to all three components of the vector.
+
+            The id is the index of the vertex, face or channel element, for
+            the modes that need one. The channel key and name pick the channel
+            for the ChannelValue mode.
+
+            An invalid id or a missing channel marks the node with an error,
+            and its outputs are zero, but the rest of the graph still runs.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("mode", probe_modes, 2),
+            P.int("id", 0, { min = 0, soft_max = 100 }),
+            P.enum("channel_key", { "Vertex", "Face", "Halfedge" }, 0),
+            P.strparam("channel_name", "", false),
+        },
+        outputs = {
+            P.v3("vector"),
+            P.scalar("x"),
+            P.scalar("y"),
+            P.scalar("z"),
+            P.scalar("scalar"),
+        },
+        soft_errors = true,
+        op = function(inputs)
+            local value = Ops.probe(
+                inputs.mesh,
+                inputs.mode,
+                inputs.id,
+                Utils.parse_ch_key(inputs.channel_key),
+                inputs.channel_name
+            )
+            local vec, scalar
+            if type(value) == "number" then
+                vec = vector(value, value, value)
+                scalar = value
+            else
+                vec = value
+                scalar = value:length()
+            end
+            return { vector = vec, x = vec.x, y = vec.y, z = vec.z, scalar = scalar }
+        end,
+    },
+    BoundingBox = {
+        label = "Bounding Box",
+        doc = [[
+            Builds a box around the mesh. Axis aligned boxes follow the world
+            axes. Oriented boxes are rotated to follow the directions the mesh
+            is most spread along, which fits elongated shapes more tightly.
+
+            The center, size and rotation of the box are also available as
+            outputs. The rotation is in XYZ euler angles, in radians.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("mode", { "Axis aligned", "Oriented" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+            P.v3("center"),
+            P.v3("size"),
+            P.v3("rotation"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local oriented = inputs.mode == "Oriented"
+            local bbox = Ops.measure_bounding_box(inputs.mesh, oriented)
+            return {
+                out_mesh = Ops.bounding_box(inputs.mesh, oriented),
+                center = bbox.center,
+                size = bbox.size,
+                rotation = bbox.rotation,
+            }
+        end,
+    },
+    BoundingSphere = {
+        label = "Bounding Sphere",
+        doc = [[
+            Builds a sphere containing all the vertices of the mesh. The sphere
+            is close to the smallest one, but can be slightly larger.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.int("segments", 16, { min = 3, soft_max = 64 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+            P.v3("center"),
+            P.scalar("radius"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local sphere = Ops.measure_bounding_sphere(inputs.mesh)
+            return {
+                out_mesh = Ops.bounding_sphere(inputs.mesh, inputs.segments),
+                center = sphere.center,
+                radius = sphere.radius,
+            }
+        end,
+    },
+    ChannelInfo = {
+        label = "Channel Info",
+        doc = [[
+            Lists the channels of the mesh, with their key type, value type
+            and number of values. Useful to check which channels reach a node.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+        },
+        outputs = {
+            P.strparam("summary"),
+            P.int("count"),
+        },
+        op = function(inputs)
+            local lines = {}
+            for _, info in ipairs(inputs.mesh:channels()) do
+                table.insert(
+                    lines,
+                    string.format(
+                        "%s: %s -> %s (%d values)",
+                        info.name,
+                        tostring(info.key_type),
+                        tostring(info.value_type),
+                        info.len
+                    )
+                )
+            end
+            return {
+                summary = table.concat(lines, "\n"),
+                count = #lines,
+            }
+        end,
+    },
+    ValidateMesh = {
+        label = "Validate mesh",
+        doc = [[
+            Checks the mesh for broken connectivity, like halfedges without a
+            twin or faces with repeated vertices, and highlights the offending
+            elements in the viewport. When the mode is set to "Repair", also
+            fixes the problems that can be safely fixed.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.enum("mode", { "Highlight", "Repair" }, 0),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local out_mesh = inputs.mesh:clone()
+            local report = Ops.validate(out_mesh)
+            if inputs.mode == "Repair" then
+                Ops.repair(out_mesh, report)
+                report = Ops.validate(out_mesh)
+            end
+            report:add_debug_marks(out_mesh)
+            return { out_mesh = out_mesh }
+        end,
+    },
+    SelectComponent = {
+        label = "Select component",
+        doc = [[
+            Separates the mesh into its connected components, that is, groups
+            of elements linked by edges, and outputs the one at the given index.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.scalar_int("index", { default = 0, min = 0, soft_max = 16 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            local components = Ops.separate_components(inputs.mesh)
+            local component = components[inputs.index + 1]
+            if component == nil then
+                error("Component index " .. inputs.index .. " is out of range. The mesh has "
+                    .. #components .. " components.")
+            end
+            return { out_mesh = component }
+        end,
+    },
+    SplitBySelection = {
+        label = "Split by selection",
+        doc = [[
+            Splits the mesh in two. The selected faces go to the first output,
+            and the rest of the faces go to the second output.
+        ]],
+        inputs = {
+            P.mesh("mesh"),
+            P.selection("faces"),
+        },
+        outputs = {
+            P.mesh("selected"),
+            P.mesh("rest"),
+        },
+        returns = "selected",
+        op = function(inputs)
+            local selected, rest = Ops.split_by_selection(inputs.mesh, inputs.faces)
+            return { selected = selected, rest = rest }
+        end,
+    },
+    Cache = {
+        label = "Cache",
+        doc = [[
+            Stores the mesh in a file next to the graph when frozen, and
+            returns it from there without running the nodes before this one.
+            The graph must be saved first, and the name picks the file, so
+            each cache in a graph needs its own. The node warns when the
+            nodes before it changed since it was frozen. Refresh it, or
+            unfreeze it, to run them again.
+        ]],
+        cache = true,
+        inputs = {
+            P.mesh("mesh"),
+            P.bool("frozen", false),
+            P.strparam("name", "cache"),
+            P.strparam("fingerprint", ""),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            if not inputs.frozen then
+                return { out_mesh = inputs.mesh }
+            end
+            if inputs.__cache_file == nil then
+                error("The graph must be saved, and the cache needs a valid name")
+            end
+            -- The nodes before a cache that stored its mesh don't run
+            if inputs.mesh == nil then
+                return { out_mesh = inputs.__load_cache() }
+            end
+            inputs.__store_cache(inputs.mesh)
+            return { out_mesh = inputs.mesh }
+        end,
+    },
 }
 
+-- Reroutes: Pass their input through unchanged. The node editor inserts them
+-- on wires, to route the wires around other nodes. There is one for each type
+-- that can be connected, and they are not listed in the node finder.
+local reroutes = {}
+for type_name, param in pairs({
+    Vector = function(name)
+        return P.v3(name, vector(0, 0, 0))
+    end,
+    Scalar = function(name)
+        return P.scalar(name, { default = 0.0 })
+    end,
+    Int = P.int,
+    Bool = P.bool,
+    Selection = P.selection,
+    Mesh = P.mesh,
+    Curve = P.curve,
+    Points = P.points,
+    String = function(name)
+        return P.strparam(name, "")
+    end,
+    HeightMap = P.heightmap,
+    Scene = P.scene,
+}) do
+    reroutes["Reroute" .. type_name] = {
+        label = "Reroute",
+        hidden = true,
+        inputs = { param("in") },
+        outputs = { param("out") },
+        op = function(inputs)
+            return { out = inputs["in"] }
+        end,
+    }
+end
+
 NodeLibrary:addNodes(primitives)
 NodeLibrary:addNodes(edit_ops)
 NodeLibrary:addNodes(math_nodes)
+NodeLibrary:addNodes(scene)
 NodeLibrary:addNodes(export)
 NodeLibrary:addNodes(misc)
+NodeLibrary:addNodes(reroutes)
@@ -4,11 +4,11 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote, ToTokens};
-use syn::{parse_quote, Attribute, ReturnType, Signature, Type};
+use syn::{ext::IdentExt, parse_quote, Attribute, ReturnType, Signature, Type};
 
 /// The mini-language inside #[lua] annotations
 mod fn_attr;
@@ -50,6 +50,8 @@ struct LuaFnDef {
 struct LuaConst {
     register_const_fn_item: TokenStream,
     register_const_fn_ident: Ident,
+    /// The global table the constant is registered under.
+    table: String,
     lua_docstr: LuaDocstring,
 }
 
@@ -80,6 +82,9 @@ fn generate_lua_fn_documentation(
                 syn::FnArg::Receiver(_) => {
                     writeln!(docstr, "-- @param self The current object")?;
                 }
+                // The Lua context is not passed from Lua
+                syn::FnArg::Typed(tpd)
+                    if matches!(&*tpd.ty, Type::Reference(r) if is_mlua_type(&r.elem, "Lua")) => {}
                 syn::FnArg::Typed(tpd) => {
                     let name = tpd.pat.to_token_stream().to_string();
                     let typ = tpd.ty.to_token_stream().to_string();
@@ -110,6 +115,7 @@ fn generate_lua_fn_documentation(
 fn generate_lua_const_documentation(
     item_const: &syn::ItemConst,
     attrs: &FunctionAttributes,
+    under_table: String,
 ) -> LuaDocstring {
     use std::fmt::Write;
     let doc = (|| -> Result<String, Box<dyn std::error::Error>> {
@@ -132,13 +138,7 @@ fn generate_lua_const_documentation(
     .unwrap();
 
     LuaDocstring {
-        def_kind: LuaFnDefKind::GlobalConstant {
-            table: attrs
-                .lua_attr
-                .under
-                .clone()
-                .unwrap_or_else(|| "Default".into()),
-        },
+        def_kind: LuaFnDefKind::GlobalConstant { table: under_table },
         doc,
     }
 }
@@ -172,6 +172,9 @@ fn lua_fn_sanity_checks(item_fn: &GlobalFnOrMethod) -> syn::Result<()> {
 
 enum LuaFnArgKind {
     Owned,
+    /// An owned integer, taken from Lua as a number and coerced. See
+    /// `coerce_integer_arg` in the engine.
+    Integer,
     Ref,
     RefMut,
     SelfRef,
@@ -191,13 +194,57 @@ struct LuaFnReturn {
 }
 
 struct LuaFnSignature {
+    /// The name of the function as seen from Lua, e.g. `Ops.extrude`, used
+    /// in error messages.
+    lua_name: String,
     inputs: Vec<LuaFnArg>,
     output: LuaFnReturn,
 }
 
+/// The integer types whose owned arguments get coerced from Lua numbers.
+const INTEGER_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize",
+];
+
+fn is_integer_type(typ: &Type) -> bool {
+    match typ {
+        Type::Path(p) if p.qself.is_none() => p
+            .path
+            .get_ident()
+            .map_or(false, |ident| INTEGER_TYPES.iter().any(|t| ident == t)),
+        _ => false,
+    }
+}
+
+/// The mlua types exported functions can return without any conversion.
+const LUA_PASS_THROUGH_TYPES: &[&str] = &["Table", "Value", "MultiValue"];
+
+/// Returns whether `typ` is the mlua type called `name`, either written with
+/// its path, like `mlua::Table`, or imported, like `Table`.
+fn is_mlua_type(typ: &Type, name: &str) -> bool {
+    match typ {
+        Type::Path(p) if p.qself.is_none() => {
+            let segments = p
+                .path
+                .segments
+                .iter()
+                .map(|seg| seg.ident.to_string())
+                .collect::<Vec<_>>();
+            match segments.as_slice() {
+                [ident] => ident == name,
+                [module, ident] => module == "mlua" && ident == name,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 fn analyze_lua_fn_args(
     item_fn: &GlobalFnOrMethod,
     fn_def_kind: &LuaFnDefKind,
+    lua_fn_name: &str,
+    attrs: &FunctionAttributes,
 ) -> syn::Result<LuaFnSignature> {
     let mut lua_fn_args = vec![];
 
@@ -239,7 +286,7 @@ fn analyze_lua_fn_args(
                 };
                 match &*t.ty {
                     Type::Reference(inner) => {
-                        if inner.elem.to_token_stream().to_string() == "Lua" {
+                        if is_mlua_type(&inner.elem, "Lua") {
                             lua_fn_args.push(LuaFnArg {
                                 kind: LuaFnArgKind::LuaRef,
                                 typ: *inner.elem.clone(),
@@ -259,7 +306,11 @@ fn analyze_lua_fn_args(
                     }
                     t => {
                         lua_fn_args.push(LuaFnArg {
-                            kind: LuaFnArgKind::Owned,
+                            kind: if is_integer_type(t) && !attrs.lua_attr.strict {
+                                LuaFnArgKind::Integer
+                            } else {
+                                LuaFnArgKind::Owned
+                            },
                             typ: t.clone(),
                             name: arg_name.ident,
                         });
@@ -269,15 +320,35 @@ fn analyze_lua_fn_args(
         }
     }
 
+    // Lua values are returned as they are. Their lifetime is always the one
+    // of the `lua` context, whatever name the function uses for it.
+    let ret_type = |t: &Type| match LUA_PASS_THROUGH_TYPES
+        .iter()
+        .find(|name| is_mlua_type(t, name))
+    {
+        Some(name) => {
+            let ident = format_ident!("{name}");
+            quote! { mlua::#ident<'lua> }
+        }
+        None => quote! { #t },
+    };
     let (ret_typ, ret_is_result) = match &item_fn.sig.output {
         ReturnType::Default => (quote! { () }, false),
         ReturnType::Type(_, t) => match unwrap_result(t) {
-            Some(inner) => (quote! { #inner }, true),
-            None => (quote! { #t }, false),
+            Some(inner) => (ret_type(inner), true),
+            None => (ret_type(t), false),
         },
     };
 
+    let lua_name = match fn_def_kind {
+        LuaFnDefKind::Method { class } => format!("{class}:{lua_fn_name}"),
+        LuaFnDefKind::Global { table } | LuaFnDefKind::GlobalConstant { table } => {
+            format!("{table}.{lua_fn_name}")
+        }
+    };
+
     Ok(LuaFnSignature {
+        lua_name,
         inputs: lua_fn_args,
         output: LuaFnReturn {
             inner_type: ret_typ,
@@ -310,7 +381,7 @@ fn analyze_lua_global_fn(
         table: under_table.clone(),
     };
 
-    let signature = analyze_lua_fn_args(item_fn, &fn_def_kind)?;
+    let signature = analyze_lua_fn_args(item_fn, &fn_def_kind, &original_fn_name, attrs)?;
     let fn_sig_args_code = signature.code_for_fn_signature();
     let fn_borrows_code = signature.code_for_fn_borrows();
     let fn_invoke_args_code = signature.code_for_fn_invoke_args();
@@ -327,17 +398,13 @@ fn analyze_lua_global_fn(
         kind: fn_def_kind,
         register_fn_item: quote! {
             pub fn #register_fn_ident(lua: &mlua::Lua) -> mlua::Result<()> {
-                fn __inner(lua: &mlua::Lua, #fn_sig_args_code) -> mlua::Result<#ret_typ_code> {
+                fn __inner<'lua>(lua: &'lua mlua::Lua, #fn_sig_args_code) -> mlua::Result<#ret_typ_code> {
                     #(#fn_borrows_code)*
                     #call_fn_and_map_result_code
                 }
 
-                // Ensure the table exists before accessing
-                if !lua.globals().contains_key(#under_table)? {
-                    lua.globals().set(#under_table, lua.create_table()?)?;
-                }
-                let table = lua.globals().get::<_, mlua::Table>(#under_table).unwrap();
-
+                // The table is created by `__blackjack_register_lua_fns`
+                let table = lua.globals().get::<_, mlua::Table>(#under_table)?;
 
                 table.set(
                     #original_fn_name,
@@ -386,7 +453,7 @@ fn analyze_lua_method_fn(
     let original_fn_ident = &item_fn.sig.ident;
     let class_ident = format_ident!("{class_name}");
 
-    let signature = analyze_lua_fn_args(item_fn, &fn_def_kind)?;
+    let signature = analyze_lua_fn_args(item_fn, &fn_def_kind, &original_fn_name, attrs)?;
     let fn_sig_args_code = signature.code_for_fn_signature();
     let fn_borrows_code = signature.code_for_fn_borrows();
     let fn_invoke_args_code = signature.code_for_fn_invoke_args();
@@ -438,25 +505,17 @@ fn analyze_lua_method_fn(
 /// analysis for it and returns the collected metadata.
 fn analyze_lua_const(
     item_const: &mut syn::ItemConst,
+    under_table: String,
     attributes: &FunctionAttributes,
 ) -> syn::Result<LuaConst> {
     let register_const_fn_ident =
         format_ident!("__blackjack_export_const_{}_to_lua", item_const.ident);
     let original_const_ident = &item_const.ident;
-    let under_table = attributes
-        .lua_attr
-        .under
-        .clone()
-        .unwrap_or_else(|| "Default".into());
     let register_const_fn_item = quote! {
         #[allow(non_snake_case)]
         pub fn #register_const_fn_ident(lua: &mlua::Lua) -> mlua::Result<()> {
-            // Ensure the table exists before accessing
-            if !lua.globals().contains_key(#under_table)? {
-                lua.globals().set(#under_table, lua.create_table()?)?;
-            }
-            let table = lua.globals().get::<_, mlua::Table>(#under_table).unwrap();
-
+            // The table is created by `__blackjack_register_lua_fns`
+            let table = lua.globals().get::<_, mlua::Table>(#under_table)?;
 
             table.set(
                 stringify!(#original_const_ident),
@@ -469,7 +528,8 @@ fn analyze_lua_const(
     Ok(LuaConst {
         register_const_fn_item,
         register_const_fn_ident,
-        lua_docstr: generate_lua_const_documentation(item_const, attributes),
+        table: under_table.clone(),
+        lua_docstr: generate_lua_const_documentation(item_const, attributes, under_table),
     })
 }
 
@@ -526,6 +586,54 @@ fn path_ident_is<'a>(attr: &'a Attribute, name: &str) -> Option<&'a Attribute> {
     }
 }
 
+/// Converts a snake_case identifier, like a module name, to PascalCase.
+fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the name of the global Lua table where the function or constant
+/// named `item_ident` gets registered. That is its own `under`, or else the
+/// module's `default_under`, or else the name of the module in PascalCase.
+/// Errors at `item_ident` when that's not a valid name for a table.
+fn resolve_under_table(
+    attrs: &FunctionAttributes,
+    module_attr: &LuaModuleAttr,
+    module_ident: &Ident,
+    item_ident: &Ident,
+) -> syn::Result<String> {
+    let table = attrs
+        .lua_attr
+        .under
+        .clone()
+        .or_else(|| module_attr.default_under.clone())
+        .unwrap_or_else(|| to_pascal_case(&module_ident.unraw().to_string()));
+
+    let is_valid = table
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_valid {
+        Ok(table)
+    } else {
+        Err(syn::Error::new(
+            item_ident.span(),
+            format!(
+                "Can't export '{item_ident}' to Lua: '{table}' is not a valid table name. \
+                 Set one with #[lua(under = \"...\")]"
+            ),
+        ))
+    }
+}
+
 /// Collects the #[lua] attribute in a function and any other relevant metadata.
 /// Also strips out any annotations that rustc cannot interpret.
 fn collect_function_attributes(attrs: &mut Vec<Attribute>) -> Option<FunctionAttributes> {
@@ -578,8 +686,11 @@ fn collect_lua_impl_attrs(attrs: &mut Vec<Attribute>) -> bool {
 }
 
 pub(crate) fn blackjack_lua_module2(
+    attr: TokenStream,
     mut module: syn::ItemMod,
 ) -> Result<TokenStream, Box<dyn std::error::Error>> {
+    let module_attr: LuaModuleAttr = syn::parse2(attr)?;
+
     // Any new function definitions that will be exported to Lua at the end of
     // the module are stored here.
     let mut fn_defs = vec![];
@@ -593,12 +704,16 @@ pub(crate) fn blackjack_lua_module2(
                 syn::Item::Fn(item_fn) => {
                     let function_attributes = collect_function_attributes(&mut item_fn.attrs);
                     if let Some(lua_attr) = function_attributes {
-                        if let Some(under) = lua_attr.lua_attr.under.as_ref().cloned() {
-                            let item_fn = GlobalFnOrMethod {
-                                sig: &mut item_fn.sig,
-                            };
-                            fn_defs.push(analyze_lua_global_fn(&item_fn, under, &lua_attr)?);
-                        }
+                        let under = resolve_under_table(
+                            &lua_attr,
+                            &module_attr,
+                            &module.ident,
+                            &item_fn.sig.ident,
+                        )?;
+                        let item_fn = GlobalFnOrMethod {
+                            sig: &mut item_fn.sig,
+                        };
+                        fn_defs.push(analyze_lua_global_fn(&item_fn, under, &lua_attr)?);
                     }
                 }
                 syn::Item::Impl(item_impl) => {
@@ -638,7 +753,13 @@ pub(crate) fn blackjack_lua_module2(
                 }
                 syn::Item::Const(item_const) => {
                     if let Some(attributes) = collect_function_attributes(&mut item_const.attrs) {
-                        const_defs.push(analyze_lua_const(item_const, &attributes)?);
+                        let under = resolve_under_table(
+                            &attributes,
+                            &module_attr,
+                            &module.ident,
+                            &item_const.ident,
+                        )?;
+                        const_defs.push(analyze_lua_const(item_const, under, &attributes)?);
                     }
                 }
                 _ => { /* Ignore */ }
@@ -649,6 +770,24 @@ pub(crate) fn blackjack_lua_module2(
     }
 
     let register_global_fn_calls_code = {
+        // All the tables are created up front, before registering anything
+        let tables = fn_defs
+            .iter()
+            .filter_map(|f| match &f.kind {
+                LuaFnDefKind::Global { table } => Some(table),
+                _ => None,
+            })
+            .chain(const_defs.iter().map(|c| &c.table))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|table| {
+                quote! {
+                    if !lua.globals().contains_key(#table)? {
+                        lua.globals().set(#table, lua.create_table()?)?;
+                    }
+                }
+            });
+
         let calls = fn_defs
             .iter()
             .filter(|f| matches!(f.kind, LuaFnDefKind::Global { .. }))
@@ -671,6 +810,7 @@ pub(crate) fn blackjack_lua_module2(
 
         quote! {
             pub fn __blackjack_register_lua_fns(lua: &mlua::Lua) -> mlua::Result<()> {
+                #(#tables)*
                 #(#calls)*
                 #(#const_calls)*
                 Ok(())
@@ -788,9 +928,10 @@ impl LuaFnSignature {
     /// fn foo(a: &HalfEdgeMesh, b: u32, c: &mut PerlinNoise) { }
     /// ```
     ///
-    /// Would generate two lines of code:
+    /// Would generate three lines of code:
     /// ```ignore
     /// let a = a.borrow::<HalfEdgeMesh>()?;
+    /// let b = coerce_integer_arg::<u32>(b, "Ops.foo", "b")?;
     /// let mut c = c.borrow_mut::<PerlinNoise>()?;
     /// ```
     fn code_for_fn_borrows(&self) -> impl Iterator<Item = TokenStream> + '_ {
@@ -798,6 +939,15 @@ impl LuaFnSignature {
             let name = &arg.name;
             let typ = &arg.typ;
             match arg.kind {
+                LuaFnArgKind::Integer => {
+                    let lua_name = &self.lua_name;
+                    let arg_name = name.to_string();
+                    Some(quote! {
+                        let #name = blackjack_engine::lua_engine::lua_stdlib::lua_coercion::coerce_integer_arg::<#typ>(
+                            #name, #lua_name, #arg_name
+                        )?;
+                    })
+                }
                 LuaFnArgKind::Ref => Some(quote! {
                     let #name = #name.borrow::<#typ>()?;
                 }),
@@ -814,8 +964,8 @@ impl LuaFnSignature {
 
     /// Returns generated code to specify the fn signature of a function when
     /// exposing it to Lua, wrapping it as a tuple of arguments. Any references
-    /// are mapped to AnyUserData. This mapping is then undone by shadowing
-    /// those variables in `code_for_fn_borrows`.
+    /// are mapped to AnyUserData, and integers to Lua numbers. This mapping is
+    /// then undone by shadowing those variables in `code_for_fn_borrows`.
     ///
     /// For methods, the self argument is ommitted.
     ///
@@ -826,12 +976,13 @@ impl LuaFnSignature {
     ///
     /// Would generate
     /// ```ignore
-    /// (a, b, c): (AnyUserData, u32, AnyUserData)
+    /// (a, b, c): (AnyUserData<'lua>, mlua::Number, AnyUserData<'lua>)
     /// ```
     fn code_for_fn_signature(&self) -> TokenStream {
         let types = self.inputs.iter().filter_map(|arg| match &arg.kind {
             LuaFnArgKind::Owned => Some(arg.typ.to_token_stream()),
-            LuaFnArgKind::Ref | LuaFnArgKind::RefMut => Some(quote! { mlua::AnyUserData }),
+            LuaFnArgKind::Integer => Some(quote! { mlua::Number }),
+            LuaFnArgKind::Ref | LuaFnArgKind::RefMut => Some(quote! { mlua::AnyUserData<'lua> }),
             LuaFnArgKind::SelfRef | LuaFnArgKind::SelfRefMut | LuaFnArgKind::LuaRef => {
                 // We can safely ignore self values here, because when they
                 // occur, they don't go inside the tuple. Same for the lua
@@ -841,7 +992,10 @@ impl LuaFnSignature {
             }
         });
         let names = self.inputs.iter().filter_map(|arg| match &arg.kind {
-            LuaFnArgKind::Owned | LuaFnArgKind::Ref | LuaFnArgKind::RefMut => Some(&arg.name),
+            LuaFnArgKind::Owned
+            | LuaFnArgKind::Integer
+            | LuaFnArgKind::Ref
+            | LuaFnArgKind::RefMut => Some(&arg.name),
             LuaFnArgKind::SelfRef | LuaFnArgKind::SelfRefMut | LuaFnArgKind::LuaRef => None,
         });
 
@@ -864,7 +1018,7 @@ impl LuaFnSignature {
         self.inputs
             .iter()
             .filter_map(|LuaFnArg { kind, name, .. }| match kind {
-                LuaFnArgKind::Owned => Some(quote! { #name }),
+                LuaFnArgKind::Owned | LuaFnArgKind::Integer => Some(quote! { #name }),
                 LuaFnArgKind::Ref => Some(quote! { &#name}),
                 LuaFnArgKind::RefMut => Some(quote! { &mut #name }),
                 LuaFnArgKind::LuaRef => Some(quote! { lua }),
@@ -931,6 +1085,153 @@ mod test {
             }
         };
         let module = syn::parse2(input).unwrap();
-        write_and_fmt("/tmp/test.rs", blackjack_lua_module2(module).unwrap()).unwrap();
+        write_and_fmt(
+            "/tmp/test.rs",
+            blackjack_lua_module2(quote! {}, module).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_integer_coercion() {
+        let expand = |input: TokenStream| {
+            let module = syn::parse2(input).unwrap();
+            blackjack_lua_module2(quote! {}, module)
+                .unwrap()
+                .to_string()
+        };
+
+        let coerced = expand(quote! {
+            mod lua_fns {
+                #[lua(under = "Ops")]
+                fn repeat(mesh: &HalfEdgeMesh, times: u32, offset: Option<u32>) {}
+            }
+        });
+        assert!(coerced.contains(
+            "(mesh , times , offset) : (mlua :: AnyUserData < 'lua > , mlua :: Number , Option < u32 >)"
+        ));
+        assert!(
+            coerced.contains("coerce_integer_arg :: < u32 > (times , \"Ops.repeat\" , \"times\")")
+        );
+
+        let strict = expand(quote! {
+            mod lua_fns {
+                #[lua(under = "Ops", strict)]
+                fn repeat(times: u32) {}
+            }
+        });
+        assert!(strict.contains("(times) : (u32)"));
+        assert!(!strict.contains("coerce_integer_arg"));
+    }
+
+    #[test]
+    fn test_under_tables() {
+        let expand = |attr: TokenStream, input: TokenStream| {
+            let module = syn::parse2(input).unwrap();
+            blackjack_lua_module2(attr, module).map(|code| code.to_string())
+        };
+        let creates_table =
+            |code: &str, table: &str| code.contains(&format!("contains_key (\"{table}\")"));
+        let registers_in =
+            |code: &str, table: &str| code.contains(&format!("mlua :: Table > (\"{table}\")"));
+
+        // Functions inherit the module default, unless they set their own
+        let code = expand(
+            quote! { default_under = "Ops" },
+            quote! {
+                mod lua_api {
+                    #[lua]
+                    fn extrude(mesh: &HalfEdgeMesh) {}
+                    #[lua(under = "Primitives")]
+                    fn cube() {}
+                    #[lua]
+                    const MAX_ITERATIONS: u32 = 10;
+                }
+            },
+        )
+        .unwrap();
+        assert!(creates_table(&code, "Ops"));
+        assert!(creates_table(&code, "Primitives"));
+        assert!(registers_in(&code, "Ops"));
+        assert!(registers_in(&code, "Primitives"));
+        assert!(!creates_table(&code, "LuaApi"));
+        // Each table is only created once
+        assert_eq!(code.matches("contains_key (\"Ops\")").count(), 1);
+
+        // Without a default, the table is named after the module
+        let code = expand(
+            quote! {},
+            quote! {
+                mod mesh_utils {
+                    #[lua]
+                    fn extrude(mesh: &HalfEdgeMesh) {}
+                }
+            },
+        )
+        .unwrap();
+        assert!(creates_table(&code, "MeshUtils"));
+        assert!(registers_in(&code, "MeshUtils"));
+
+        let err = expand(
+            quote! {},
+            quote! {
+                mod lua_api {
+                    #[lua(under = "Ops.Mesh")]
+                    fn extrude(mesh: &HalfEdgeMesh) {}
+                }
+            },
+        )
+        .unwrap_err();
+        assert!(err.downcast::<syn::Error>().is_ok());
+        assert!(expand(quote! { under = "Ops" }, quote! { mod lua_api {} }).is_err());
+    }
+
+    #[test]
+    fn test_lua_context_and_tables() {
+        let expand = |input: TokenStream| {
+            let module = syn::parse2(input).unwrap();
+            blackjack_lua_module2(quote! {}, module)
+                .unwrap()
+                .to_string()
+        };
+
+        for lua_type in [quote! { mlua::Lua }, quote! { Lua }] {
+            let code = expand(quote! {
+                mod lua_api {
+                    /// Returns the number of vertices of each mesh, by name
+                    #[lua(under = "Stats")]
+                    fn vertex_counts<'a>(
+                        lua: &'a #lua_type,
+                        meshes: Vec<String>,
+                        mesh: &HalfEdgeMesh,
+                    ) -> Result<mlua::Table<'a>> {
+                        let table = lua.create_table()?;
+                        for name in meshes {
+                            table.set(name, mesh.read_connectivity().num_vertices())?;
+                        }
+                        Ok(table)
+                    }
+                }
+            });
+            // The Lua context is not a Lua argument, but it's passed along
+            assert!(
+                code.contains("(meshes , mesh) : (Vec < String > , mlua :: AnyUserData < 'lua >)")
+            );
+            assert!(code.contains("vertex_counts (lua , meshes , & mesh)"));
+            // The table is returned as it is
+            assert!(code.contains("-> mlua :: Result < mlua :: Table < 'lua > >"));
+            assert!(code.contains("function vertex_counts(meshes, mesh)"));
+        }
+
+        let code = expand(quote! {
+            mod lua_api {
+                #[lua(under = "Stats")]
+                fn summary(lua: &Lua) -> MultiValue {
+                    MultiValue::new()
+                }
+            }
+        });
+        assert!(code.contains("-> mlua :: Result < mlua :: MultiValue < 'lua > >"));
+        assert!(code.contains("summary (lua)"));
     }
 }
@@ -19,6 +19,16 @@ pub struct LuaFnAttr {
     pub map_this: Option<Expr>,
     pub map_result: Option<Expr>,
     pub hidden_fn: bool,
+    /// Integer arguments are taken as-is from Lua, without coercing them from
+    /// a number.
+    pub strict: bool,
+}
+
+/// The arguments of the `#[blackjack_lua_module(...)]` annotation itself.
+#[derive(Default, Debug)]
+pub struct LuaModuleAttr {
+    /// The table for global functions and constants that don't set `under`.
+    pub default_under: Option<String>,
 }
 
 #[derive(Default, Debug)]
@@ -65,6 +75,8 @@ impl Parse for LuaFnAttr {
                 )?);
             } else if key == "hidden" {
                 lua_attr.hidden_fn = true;
+            } else if key == "strict" {
+                lua_attr.strict = true;
             } else {
                 panic!("Unexpected annotation '{key}'");
             }
@@ -73,3 +85,33 @@ impl Parse for LuaFnAttr {
         Ok(lua_attr)
     }
 }
+
+impl Parse for LuaModuleAttr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(LuaModuleAttr::default());
+        }
+        let properties = input.comma_separated_fn(|input| {
+            let lhs: Ident = input.parse()?;
+            input.expect_token::<Token![=]>()?;
+            let rhs: Expr = input.parse()?;
+            Ok((lhs, rhs))
+        })?;
+
+        let mut module_attr = LuaModuleAttr::default();
+
+        for (key, val) in properties.iter() {
+            if key == "default_under" {
+                module_attr.default_under =
+                    Some(val.assume_string_literal("Value for 'default_under' must be a string")?);
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!("Unexpected module annotation '{key}'"),
+                ));
+            }
+        }
+
+        Ok(module_attr)
+    }
+}
@@ -17,6 +17,17 @@ pub fn project_point(view_proj: &Mat4, viewport_rect: Rect, point: Vec3) -> Pos2
     egui::pos2(projected.x, projected.y)
 }
 
+/// The inverse of [`project_point`]. Returns the point in world space that is
+/// drawn at `pos` on the screen, at the given normalized `depth`. Depth goes
+/// from 1 at the near plane towards 0 at infinity.
+pub fn unproject_point(view_proj: &Mat4, viewport_rect: Rect, pos: Pos2, depth: f32) -> Vec3 {
+    let offset = pos - viewport_rect.left_top();
+    let x = offset.x / viewport_rect.width();
+    let y = offset.y / viewport_rect.height();
+    let ndc = Vec3::new(x * 2.0 - 1.0, 1.0 - y * 2.0, depth);
+    view_proj.inverse().project_point3(ndc)
+}
+
 pub fn draw_gui_overlays(
     view_proj: &Mat4,
     viewport_rect: egui::Rect,
@@ -108,5 +119,9 @@ pub fn draw_gui_overlays(
         RenderableThing::HeightMap(_) => {
             // TODO @Heightmap
         }
+        RenderableThing::Scene(_) => {
+            // Scenes are shown as a single merged mesh, whose ids don't match
+            // the ones in the objects, so there's nothing to overlay.
+        }
     }
 }
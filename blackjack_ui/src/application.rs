@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::{
@@ -11,17 +12,33 @@ use crate::{
     prelude::*,
     rendergraph::{
         face_routine::FaceRoutine, grid_routine::GridRoutine, id_picking_routine::IdPickingRoutine,
-        point_cloud_routine::PointCloudRoutine, wireframe_routine::WireframeRoutine,
+        point_cloud_routine::PointCloudRoutine, thumbnail_routine::ThumbnailRoutine,
+        wireframe_routine::WireframeRoutine,
     },
 };
-use blackjack_engine::lua_engine::LuaRuntime;
+use blackjack_engine::asset_cache::{AssetCache, AssetCacheConfig};
+use blackjack_engine::graph::serialization::{BjkFileFormat, SerializedBjkGraph};
+use blackjack_engine::graph_interpreter::dry_run::Severity;
+use blackjack_engine::graph_worker::GraphWorker;
+use blackjack_engine::lua_engine::graph_libraries::GraphLibraries;
+use blackjack_engine::lua_engine::{LuaRuntime, LuaRuntimeConfig};
 use egui_wgpu::renderer::{RenderPass, ScreenDescriptor};
 use winit::window::Window;
 
 use self::{
-    app_viewport::AppViewport, application_context::ApplicationContext,
-    gizmo_ui::UiNodeGizmoStates, graph_editor::GraphEditor, inspector::InspectorTabs,
-    root_ui::AppRootAction, viewport_3d::Viewport3d,
+    app_viewport::AppViewport,
+    application_context::ApplicationContext,
+    documents::{Document, DocumentTabs, TabAction},
+    file_browser::FileBrowser,
+    file_dialogs::{remember_dialog_dir, UnsavedChangesGuard},
+    gizmo_ui::UiNodeGizmoStates,
+    graph_editor::GraphEditor,
+    inspector::InspectorTabs,
+    node_examples::ExampleTab,
+    root_ui::AppRootAction,
+    templates::{NewGraph, TemplatePicker, TemplateRegistry},
+    trust_settings::TrustSettings,
+    viewport_3d::Viewport3d,
 };
 
 pub struct RootViewport {
@@ -37,8 +54,37 @@ pub struct RootViewport {
     offscreen_viewports: HashMap<OffscreenViewport, AppViewport>,
     inspector_tabs: InspectorTabs,
     diagnostics_open: bool,
+    materials_open: bool,
+    project_settings_open: bool,
+    validation_open: bool,
+    warnings_open: bool,
+    /// The problems found the last time the graph was validated, with the
+    /// label of the node they were found in.
+    validation_log: Vec<(String, Severity, String)>,
     lua_runtime: LuaRuntime,
     mouse_captured_by_split: bool,
+    trust_settings: TrustSettings,
+    /// The graph file that was last loaded or saved, if any.
+    open_file: Option<PathBuf>,
+    /// The format graph files are saved in.
+    save_format: BjkFileFormat,
+    /// When set, saved graphs embed the node libraries they use that don't
+    /// ship with blackjack, so they can be opened where those aren't installed.
+    embed_node_libraries: bool,
+    /// A file to save once the thumbnail for it is rendered.
+    pending_save: Option<PendingSave>,
+    file_browser: FileBrowser,
+    templates: TemplateRegistry,
+    template_picker: TemplatePicker,
+    /// Asks to save the changes to the graph before another one is opened.
+    unsaved_changes: UnsavedChangesGuard,
+    /// The example graph shown in the editor instead of the open graph, which
+    /// it keeps until it's closed.
+    example_tab: Option<ExampleTab>,
+    /// The node libraries that came with the open graph.
+    graph_libraries: GraphLibraries,
+    /// The other graphs open in tabs.
+    tabs: DocumentTabs,
 }
 
 /// The application context is state that is global to an instance of blackjack.
@@ -48,6 +94,12 @@ pub mod application_context;
 /// The gizmo logic specific to blackjack_ui
 pub mod gizmo_ui;
 
+/// Editing the vertices of the displayed mesh by hand in the 3d viewport
+pub mod edit_mode;
+
+/// Painting the values of vertex channels in the 3d viewport
+pub mod paint_mode;
+
 /// The graph editor viewport. Shows an inner egui instance with zooming /
 /// panning functionality.
 pub mod graph_editor;
@@ -73,10 +125,49 @@ pub mod viewport_split;
 /// The properties and spreadsheet inspector code
 pub mod inspector;
 
+/// A 2d view of the uv layout of the displayed mesh
+pub mod uv_view;
+
 /// An egui widget to display a text editor with source code and syntax
 /// highlighting support
 pub mod code_viewer;
 
+/// Remembers which graph files are trusted to run outside the Lua sandbox
+pub mod trust_settings;
+
+/// A panel to browse graph files by their thumbnails
+pub mod file_browser;
+
+/// A window to edit the materials of the graph
+pub mod materials_ui;
+
+/// A window to edit the settings saved with the graph, like its units
+pub mod project_settings_ui;
+
+/// Native file dialogs, and asking to save changes before opening a graph
+pub mod file_dialogs;
+
+/// Spawning import nodes for the files dropped on the window
+pub mod file_drop;
+
+/// Starting new graphs from the bundled templates, or from the ones saved by
+/// the user
+pub mod templates;
+
+/// The example graphs opened from the docs of a node, without closing the
+/// open graph
+pub mod node_examples;
+
+/// Several graphs open at once, in tabs
+pub mod documents;
+
+/// What a pending save writes, once the thumbnail is rendered.
+enum PendingSave {
+    Graph(PathBuf),
+    /// A user template, which doesn't change the file of the open graph.
+    Template(PathBuf),
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 enum OffscreenViewport {
     GraphEditor,
@@ -118,6 +209,14 @@ impl RootViewport {
         // TODO: Hardcoded node libraries path. Read from cmd line?
         let mut lua_runtime = LuaRuntime::initialize_with_std("./blackjack_lua/".into())
             .unwrap_or_else(|err| panic!("Init lua should not fail. {err}"));
+        // Both runtimes share the imported assets
+        let asset_cache = AssetCache::new(AssetCacheConfig {
+            budget_bytes: CLI_ARGS.asset_cache_mb * 1024 * 1024,
+            hash_contents: CLI_ARGS.hash_assets,
+        });
+        lua_runtime
+            .set_asset_cache(asset_cache.clone())
+            .expect("Setting the asset cache should not fail");
         if !CLI_ARGS.disable_lua_watcher {
             lua_runtime
                 .start_file_watcher()
@@ -125,6 +224,16 @@ impl RootViewport {
         }
 
         let gizmo_state = UiNodeGizmoStates::init();
+        let mut app_context = ApplicationContext::new(
+            gizmo_state.share(),
+            GraphWorker::spawn(move || {
+                let mut runtime = LuaRuntime::initialize_with_std("./blackjack_lua/".into())?;
+                runtime.set_asset_cache(asset_cache.clone())?;
+                Ok(runtime)
+            }),
+        );
+        app_context.memory_budget =
+            (!CLI_ARGS.allow_large).then_some(CLI_ARGS.memory_budget_mb * 1024 * 1024);
         RootViewport {
             egui_winit_state,
             egui_context,
@@ -134,7 +243,7 @@ impl RootViewport {
                 pixels_per_point: scale_factor as f32,
             },
             renderpass: RenderPass::new(&renderer.device, screen_format, 1),
-            app_context: ApplicationContext::new(gizmo_state.share()),
+            app_context,
             graph_editor: GraphEditor::new(
                 renderer,
                 screen_format,
@@ -146,8 +255,25 @@ impl RootViewport {
             offscreen_viewports,
             inspector_tabs: InspectorTabs::new(),
             diagnostics_open: false,
+            materials_open: false,
+            project_settings_open: false,
+            validation_open: false,
+            warnings_open: false,
+            validation_log: Vec::new(),
             lua_runtime,
             mouse_captured_by_split: false,
+            trust_settings: TrustSettings::load(),
+            open_file: None,
+            save_format: BjkFileFormat::default(),
+            embed_node_libraries: false,
+            pending_save: None,
+            file_browser: FileBrowser::default(),
+            templates: TemplateRegistry::load(),
+            template_picker: TemplatePicker::default(),
+            unsaved_changes: UnsavedChangesGuard::default(),
+            example_tab: None,
+            graph_libraries: GraphLibraries::default(),
+            tabs: DocumentTabs::default(),
         }
     }
 
@@ -204,6 +330,9 @@ impl RootViewport {
         if let Some(load) = &CLI_ARGS.load {
             self.handle_root_action(AppRootAction::Load(std::path::PathBuf::from(load)))
                 .expect("Error loading scene from CLI arg.");
+        } else {
+            // New users get to pick something to start with
+            self.template_picker.open = true;
         }
     }
 
@@ -221,6 +350,10 @@ impl RootViewport {
                     // interactively develop gizmos, otherwise the init function
                     // is not run again after reloading.
                     self.app_context.node_gizmo_states.reset_for_hot_reload();
+
+                    // The graph worker has its own copy of the Lua runtime,
+                    // which needs to be reloaded as well.
+                    self.app_context.graph_worker.reload_runtime();
                 }
                 Ok(false) => { /* Do nothing */ }
                 Err(err) => {
@@ -234,6 +367,7 @@ impl RootViewport {
             self.screen_descriptor.pixels_per_point,
             self.offscreen_viewports[&OffscreenViewport::GraphEditor].rect,
         );
+        self.viewport_3d.settings.lighting = self.graph_editor.custom_state.viewport_lighting;
         self.viewport_3d.update(
             self.screen_descriptor.pixels_per_point,
             self.offscreen_viewports[&OffscreenViewport::Viewport3d].rect,
@@ -246,6 +380,15 @@ impl RootViewport {
         if let Some(menubar_action) = self.top_menubar() {
             actions.push(menubar_action);
         }
+        if let Some(status_bar_action) = self.status_bar() {
+            actions.push(status_bar_action);
+        }
+        actions.extend(self.document_tabs_ui());
+        if let Some(tab) = &self.example_tab {
+            if tab.banner_ui(&self.egui_context) {
+                actions.push(AppRootAction::CloseExample);
+            }
+        }
 
         egui::CentralPanel::default().show(&self.egui_context.clone(), |ui| {
             let mut split_tree = self.app_context.split_tree.clone();
@@ -262,15 +405,69 @@ impl RootViewport {
         });
 
         self.diagnostics_ui();
+        self.materials_ui();
+        self.project_settings_ui();
+        self.validation_ui();
+        self.warnings_ui();
+        if let Some(path) = self.file_browser.show(&self.egui_context) {
+            remember_dialog_dir(&mut self.graph_editor.custom_state, &path);
+            actions.extend(self.request_open(path));
+        }
+        // Graphs dropped on the window are opened here. Other files are
+        // imported by the graph editor.
+        let dropped_graph = self
+            .egui_context
+            .input()
+            .raw
+            .dropped_files
+            .iter()
+            .filter_map(|file| file.path.clone())
+            .find(|path| file_drop::is_graph_file(path));
+        if let Some(path) = dropped_graph {
+            actions.extend(self.request_open(path));
+        }
+        // The snapshot is only taken while the picker is open, it's costly
+        let unsaved_changes = self.template_picker.open
+            && self
+                .unsaved_changes
+                .has_unsaved_changes(&self.graph_snapshot());
+        if let Some(new_graph) =
+            self.template_picker
+                .show(&self.egui_context, &self.templates, unsaved_changes)
+        {
+            actions.push(AppRootAction::New(new_graph));
+        }
+        if let Some(name) = self.template_picker.show_save_prompt(&self.egui_context) {
+            actions.push(AppRootAction::SaveTemplate(name));
+        }
+        if let Some(choice) =
+            file_dialogs::unsaved_changes_prompt(&self.egui_context, &self.unsaved_changes)
+        {
+            let custom_state = &self.graph_editor.custom_state;
+            let open_file = self.open_file.clone();
+            actions.extend(self.unsaved_changes.resolve(choice, || {
+                open_file.or_else(|| {
+                    file_dialogs::file_dialog(custom_state)
+                        .set_file_name("Untitled.bjk")
+                        .add_filter("Blackjack Model", &["bjk"])
+                        .save_file()
+                })
+            }));
+        }
 
+        let viewport_clicked = self.viewport_3d.take_clicked();
         actions.extend(self.app_context.update(
             &self.egui_context,
             &mut self.graph_editor.editor_state,
             &mut self.graph_editor.custom_state,
             render_ctx,
             &self.viewport_3d.settings,
+            viewport_clicked,
             &self.lua_runtime,
         ));
+        if let Some(name) = self.graph_editor.custom_state.open_example.take() {
+            actions.push(AppRootAction::OpenExample(name));
+        }
 
         for action in actions {
             // TODO: Don't panic, report error to user in modal dialog
@@ -279,28 +476,348 @@ impl RootViewport {
         }
     }
 
+    /// Shows the tab bar, and switches tabs with Ctrl+Tab. Tabs don't change
+    /// while the user is asked about unsaved changes.
+    fn document_tabs_ui(&mut self) -> Option<AppRootAction> {
+        let shown_title = match &self.example_tab {
+            Some(tab) => format!("📖 {}", tab.name),
+            None => documents::tab_title(self.open_file.as_deref()),
+        };
+        let tab_action = self.tabs.tab_bar_ui(&self.egui_context, &shown_title);
+        if self.unsaved_changes.is_pending() {
+            return None;
+        }
+        let cycle = {
+            let input = self.egui_context.input();
+            (input.modifiers.ctrl && input.key_pressed(egui::Key::Tab))
+                .then(|| self.tabs.cycle(input.modifiers.shift))
+        };
+        if let Some(index) = cycle {
+            return Some(AppRootAction::SelectTab(index));
+        }
+        match tab_action? {
+            TabAction::Select(index) => Some(AppRootAction::SelectTab(index)),
+            TabAction::Close(index) => {
+                // Only the active tab can ask about its unsaved changes
+                if let Err(err) = self.handle_root_action(AppRootAction::SelectTab(index)) {
+                    println!("Error switching tabs: {err:?}");
+                    return None;
+                }
+                self.request_close_tab()
+            }
+            TabAction::New => Some(AppRootAction::NewTab),
+        }
+    }
+
     pub fn handle_root_action(&mut self, action: AppRootAction) -> Result<()> {
         match action {
+            AppRootAction::Save(_) | AppRootAction::SaveTemplate(_)
+                if self.example_tab.is_some() =>
+            {
+                println!("Examples can't be saved. Close the example to save the open graph.");
+                self.unsaved_changes.on_save_failed();
+            }
             AppRootAction::Save(path) => {
-                serialization::save(
-                    &self.graph_editor.editor_state,
-                    &self.graph_editor.custom_state,
-                    path,
-                )?;
+                // The file is saved after the next frame, which captures the
+                // 3d viewport for its thumbnail.
+                self.pending_save = Some(PendingSave::Graph(path));
+            }
+            AppRootAction::SaveTemplate(name) => {
+                let path = self.templates.user_template_path(&name)?;
+                self.pending_save = Some(PendingSave::Template(path));
             }
             AppRootAction::Load(path) => {
-                let (editor_state, custom_state) = serialization::load(
-                    path,
+                self.close_example();
+                // The sandbox needs to be enabled before the new graph runs.
+                let config = self.trust_settings.runtime_config_for(&path);
+                self.set_runtime_config(config)?;
+                let serialized = SerializedBjkGraph::load_from_file(&path)?;
+                // Its nodes may come from the libraries that come with it.
+                self.load_graph_libraries(GraphLibraries::of_graph(&serialized, !config.sandboxed));
+                let (editor_state, custom_state) = serialization::load_serialized(
+                    serialized,
+                    Some(path.clone()),
+                    &self.lua_runtime.lua,
                     &self.graph_editor.custom_state.node_definitions,
                     &self.graph_editor.custom_state.gizmo_states,
                 )?;
-                self.graph_editor.editor_state = editor_state;
-                self.graph_editor.custom_state = custom_state;
+                self.replace_graph(editor_state, custom_state, Some(path));
+            }
+            AppRootAction::New(NewGraph::Empty) => {
+                self.close_example();
+                let custom_state = graph::CustomGraphState::new(
+                    self.graph_editor.custom_state.node_definitions.share(),
+                    self.graph_editor.custom_state.gizmo_states.share(),
+                );
+                let editor_state =
+                    graph::GraphEditorState::new(1.0 / self.screen_descriptor.pixels_per_point);
+                self.set_runtime_config(LuaRuntimeConfig::default())?;
+                self.load_graph_libraries(GraphLibraries::default());
+                self.replace_graph(editor_state, custom_state, None);
+            }
+            AppRootAction::New(NewGraph::FromTemplate(template)) => {
+                self.close_example();
+                // Bundled templates are trusted, the user's ones are trusted
+                // like any other file.
+                let config = match &template.source {
+                    templates::TemplateSource::Bundled(_) => LuaRuntimeConfig::default(),
+                    templates::TemplateSource::User(path) => {
+                        self.trust_settings.runtime_config_for(path)
+                    }
+                };
+                self.set_runtime_config(config)?;
+                self.load_graph_libraries(GraphLibraries::default());
+                // The copy has no path, so saving it asks where to.
+                let (editor_state, custom_state) = serialization::load_from_bytes(
+                    &template.bytes()?,
+                    &self.lua_runtime.lua,
+                    &self.graph_editor.custom_state.node_definitions,
+                    &self.graph_editor.custom_state.gizmo_states,
+                )?;
+                self.replace_graph(editor_state, custom_state, None);
+            }
+            AppRootAction::SetFileTrusted(trusted) => {
+                if let Some(path) = self.open_file.clone() {
+                    self.trust_settings.set_trusted(&path, trusted)?;
+                    let config = self.trust_settings.runtime_config_for(&path);
+                    self.set_runtime_config(config)?;
+                }
+            }
+            AppRootAction::OpenExample(name) => {
+                let example = node_examples::load_example(
+                    &name,
+                    &self.lua_runtime.lua,
+                    &self.graph_editor.custom_state.node_definitions,
+                    &self.graph_editor.custom_state.gizmo_states,
+                )?;
+                // Only one example is open at a time
+                self.close_example();
+                self.example_tab = Some(ExampleTab::open(
+                    name,
+                    example,
+                    &mut self.graph_editor.editor_state,
+                    &mut self.graph_editor.custom_state,
+                    &mut self.open_file,
+                ));
+                self.forget_node_ids();
+            }
+            AppRootAction::CloseExample => self.close_example(),
+            AppRootAction::NewTab => {
+                self.close_example();
+                let mut document = self.new_document();
+                self.swap_document(&mut document)?;
+                self.tabs.push(document);
+                self.template_picker.open = true;
+            }
+            AppRootAction::OpenInNewTab(path) => {
+                self.handle_root_action(AppRootAction::NewTab)?;
+                self.template_picker.open = false;
+                self.handle_root_action(AppRootAction::Load(path))?;
+            }
+            AppRootAction::SelectTab(index) => {
+                self.close_example();
+                let mut tabs = std::mem::take(&mut self.tabs);
+                let selected = tabs.select(index, |document| self.swap_document(document));
+                self.tabs = tabs;
+                selected?;
             }
+            AppRootAction::CloseTab => {
+                self.close_example();
+                let mut tabs = std::mem::take(&mut self.tabs);
+                let closed = tabs.close_active(|document| self.swap_document(document));
+                self.tabs = tabs;
+                match closed? {
+                    Some(closed) => self.app_context.close_results(closed.results),
+                    // The last tab is left with an empty graph
+                    None => {
+                        self.handle_root_action(AppRootAction::New(NewGraph::Empty))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a tab with an empty graph.
+    fn new_document(&self) -> Document {
+        Document::new(
+            graph::GraphEditorState::new(1.0 / self.screen_descriptor.pixels_per_point),
+            graph::CustomGraphState::new(
+                self.graph_editor.custom_state.node_definitions.share(),
+                self.graph_editor.custom_state.gizmo_states.share(),
+            ),
+            self.app_context.new_results(),
+        )
+    }
+
+    /// Shows the graph of the `document` of another tab, and keeps the one
+    /// shown until now in its place. The Lua runtimes get the config and the
+    /// node libraries of the graph that is shown next.
+    fn swap_document(&mut self, document: &mut Document) -> Result<()> {
+        let editor = &mut self.graph_editor;
+        std::mem::swap(&mut editor.editor_state, &mut document.editor_state);
+        std::mem::swap(&mut editor.custom_state, &mut document.custom_state);
+        // The folder of the last file dialog is shared by all the tabs
+        std::mem::swap(
+            &mut editor.custom_state.last_dialog_dir,
+            &mut document.custom_state.last_dialog_dir,
+        );
+        std::mem::swap(&mut editor.last_edit, &mut document.last_edit);
+        std::mem::swap(&mut self.open_file, &mut document.open_file);
+        std::mem::swap(&mut self.unsaved_changes, &mut document.unsaved_changes);
+        self.app_context
+            .node_gizmo_states
+            .swap(&document.gizmo_states);
+        self.viewport_3d.swap_camera(&mut document.camera);
+        self.app_context.swap_results(&mut document.results);
+        // Node ids from the other graph may exist in this one
+        self.graph_editor.layout_targets.clear();
+        self.graph_editor.wires = Default::default();
+
+        let config = std::mem::replace(&mut document.runtime_config, *self.lua_runtime.config());
+        self.set_runtime_config(config)?;
+        let libraries = std::mem::take(&mut self.graph_libraries);
+        let shown_libraries = std::mem::replace(&mut document.libraries, libraries);
+        self.load_graph_libraries(shown_libraries);
+        Ok(())
+    }
+
+    /// Closes the active tab, unless its graph has unsaved changes. Then the
+    /// user is asked what to do with them first.
+    pub fn request_close_tab(&mut self) -> Option<AppRootAction> {
+        let snapshot = self.graph_snapshot();
+        self.unsaved_changes
+            .request_close(snapshot)
+            .then_some(AppRootAction::CloseTab)
+    }
+
+    /// Puts the open graph back in the editor, if an example replaced it.
+    fn close_example(&mut self) {
+        if let Some(tab) = self.example_tab.take() {
+            tab.close(
+                &mut self.graph_editor.editor_state,
+                &mut self.graph_editor.custom_state,
+                &mut self.open_file,
+            );
+            self.forget_node_ids();
+        }
+    }
+
+    /// Clears the state of the graph editor that refers to the nodes of the
+    /// graph it had before. Node ids from that graph may exist in the new one.
+    fn forget_node_ids(&mut self) {
+        self.graph_editor.layout_targets.clear();
+        self.graph_editor.last_edit = None;
+        self.graph_editor.wires = Default::default();
+    }
+
+    /// Replaces the open graph with a new one. The `open_file` is the file
+    /// it was loaded from, if any.
+    fn replace_graph(
+        &mut self,
+        editor_state: graph::GraphEditorState,
+        custom_state: graph::CustomGraphState,
+        open_file: Option<PathBuf>,
+    ) {
+        let last_dialog_dir = self.graph_editor.custom_state.last_dialog_dir.take();
+        self.graph_editor.editor_state = editor_state;
+        self.graph_editor.custom_state = custom_state;
+        self.graph_editor.custom_state.last_dialog_dir = last_dialog_dir;
+        // Node ids from the previous graph may exist in the new one
+        self.graph_editor.layout_targets.clear();
+        self.open_file = open_file;
+        self.unsaved_changes.mark_saved(self.graph_snapshot());
+    }
+
+    /// Returns a snapshot of the graph, see [`file_dialogs::graph_snapshot`].
+    /// While an example is open, this is the graph it replaced.
+    fn graph_snapshot(&self) -> Option<String> {
+        let (editor_state, custom_state) = match &self.example_tab {
+            Some(tab) => tab.stashed_graph(),
+            None => (
+                &self.graph_editor.editor_state,
+                &self.graph_editor.custom_state,
+            ),
+        };
+        file_dialogs::graph_snapshot(editor_state, custom_state)
+    }
+
+    /// Opens the graph at `path`, unless the open graph has unsaved changes.
+    /// Then the user is asked what to do with them first.
+    pub fn request_open(&mut self, path: PathBuf) -> Option<AppRootAction> {
+        let snapshot = self.graph_snapshot();
+        self.unsaved_changes
+            .request_open(path, snapshot)
+            .map(AppRootAction::Load)
+    }
+
+    /// Saves the graph to `path`, with an optional PNG preview. Returns what
+    /// was waiting for the graph to be saved, if anything: Opening another
+    /// file, or closing the tab.
+    fn save_file(
+        &mut self,
+        path: PathBuf,
+        thumbnail_png: Option<Vec<u8>>,
+    ) -> Result<Option<AppRootAction>> {
+        serialization::save(
+            &mut self.graph_editor.editor_state,
+            &self.graph_editor.custom_state,
+            &path,
+            self.save_format,
+            thumbnail_png,
+            self.embed_node_libraries.then_some(&self.lua_runtime),
+        )?;
+        // Files saved from a trusted graph are trusted as well.
+        if !self.lua_runtime.config().sandboxed {
+            self.trust_settings.set_trusted(&path, true)?;
         }
+        self.graph_editor.custom_state.file_path = Some(path.clone());
+        self.open_file = Some(path);
+        let snapshot = self.graph_snapshot();
+        let next = match self.unsaved_changes.on_saved(snapshot) {
+            Some(next) => Some(AppRootAction::Load(next)),
+            None => self
+                .unsaved_changes
+                .take_close_after_save()
+                .then_some(AppRootAction::CloseTab),
+        };
+        Ok(next)
+    }
+
+    /// Applies `config` to all the Lua runtimes that run the graph.
+    fn set_runtime_config(&mut self, config: LuaRuntimeConfig) -> Result<()> {
+        self.lua_runtime.set_config(config)?;
+        self.app_context.graph_worker.set_runtime_config(config);
         Ok(())
     }
 
+    /// Loads the node libraries that come with the open graph into all the
+    /// Lua runtimes that run it, replacing the ones of the previous graph. A
+    /// library that fails to load is reported, and its nodes are missing.
+    fn load_graph_libraries(&mut self, libraries: GraphLibraries) {
+        match self.lua_runtime.load_graph_libraries(libraries.clone()) {
+            Ok(report) => {
+                for (op_name, provenance) in &report.loaded {
+                    println!("Loaded the node {op_name} from the {provenance}");
+                }
+                for (op_name, provenance) in &report.skipped {
+                    println!(
+                        "[WARNING] The {provenance} defines {op_name} again, \
+                         which keeps its existing definition"
+                    );
+                }
+                if report.sandboxed && !report.loaded.is_empty() {
+                    println!("The node libraries of the graph run in the sandbox");
+                }
+            }
+            Err(err) => {
+                println!("[WARNING] Could not load the node libraries of the graph: {err:?}")
+            }
+        }
+        self.app_context.set_graph_libraries(libraries.clone());
+        self.graph_libraries = libraries;
+    }
+
     pub fn render(&mut self, render_ctx: &mut RenderContext) -> egui::PlatformOutput {
         let RenderContext {
             ref base_graph,
@@ -311,9 +828,17 @@ impl RootViewport {
             ref point_cloud_routine,
             ref face_routine,
             ref mut id_picking_routine,
+            ref mut thumbnail_routine,
             ..
         } = render_ctx;
 
+        if self.pending_save.is_some() {
+            thumbnail_routine.request(
+                &render_ctx.renderer.device,
+                self.viewport_3d.get_resolution(),
+            );
+        }
+
         // TODO: Maybe this is not the best place to do this. Do it in `update` instead?
         id_picking_routine.set_cursor_pos(
             self.egui_context
@@ -325,7 +850,12 @@ impl RootViewport {
         );
 
         let frame = rend3::util::output::OutputFrame::Surface {
-            surface: Arc::clone(&render_ctx.surface),
+            surface: Arc::clone(
+                render_ctx
+                    .surface
+                    .as_ref()
+                    .expect("The application renders to a window"),
+            ),
         };
         let (cmd_bufs, ready) = render_ctx.renderer.ready();
         let mut graph = rend3::graph::RenderGraph::new();
@@ -341,6 +871,7 @@ impl RootViewport {
                 point_cloud: point_cloud_routine,
                 face: face_routine,
                 id_picking: id_picking_routine,
+                thumbnail: thumbnail_routine,
             },
         );
 
@@ -359,6 +890,42 @@ impl RootViewport {
         let id = id_picking_routine.id_under_mouse(&render_ctx.renderer.device);
         self.app_context.on_id_hovered(id);
 
+        if let Some(pending) = self.pending_save.take() {
+            let thumbnail = thumbnail_routine
+                .take_png(&render_ctx.renderer.device)
+                .unwrap_or_else(|err| {
+                    println!("[WARNING] Could not capture the thumbnail: {err}");
+                    None
+                });
+            // TODO: Report errors to the user in a modal dialog
+            match pending {
+                PendingSave::Graph(path) => match self.save_file(path, thumbnail) {
+                    Ok(Some(next)) => {
+                        if let Err(err) = self.handle_root_action(next) {
+                            println!("Error after saving the file: {err:?}");
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        println!("Error saving file: {err:?}");
+                        self.unsaved_changes.on_save_failed();
+                    }
+                },
+                PendingSave::Template(path) => {
+                    if let Err(err) = serialization::save_template(
+                        &self.graph_editor.editor_state,
+                        &self.graph_editor.custom_state,
+                        &path,
+                        thumbnail,
+                    ) {
+                        println!("Error saving template: {err:?}");
+                    }
+                    self.templates.reload();
+                    self.template_picker.clear_thumbnails();
+                }
+            }
+        }
+
         platform_output
     }
 
@@ -381,4 +948,5 @@ pub struct ViewportRoutines<'a> {
     pub point_cloud: &'a PointCloudRoutine,
     pub face: &'a FaceRoutine,
     pub id_picking: &'a IdPickingRoutine,
+    pub thumbnail: &'a ThumbnailRoutine,
 }
@@ -8,17 +8,34 @@ use crate::graph::graph_interop::{self, NodeMapping};
 use crate::prelude::*;
 use anyhow::Error;
 
-use blackjack_engine::graph::BjkGraph;
-use blackjack_engine::graph_interpreter::ExternalParameterValues;
-use blackjack_engine::prelude::ChannelKeyType;
+use std::time::{Duration, Instant};
+
+use blackjack_engine::graph::{BjkGraph, BlackjackValue};
+use blackjack_engine::graph_interpreter::memory_budget::format_bytes;
+use blackjack_engine::graph_interpreter::{
+    ExecutionCancelled, ExternalParameterValues, MemoryBudgetExceeded, NodePanicked,
+};
+use blackjack_engine::graph_worker::{ExecutionRequest, GraphHandle, GraphWorker};
+use blackjack_engine::lua_engine::graph_libraries::GraphLibraries;
+use blackjack_engine::lua_engine::ProgramResult;
+use blackjack_engine::mesh::halfedge::analysis::MeshStats;
+use blackjack_engine::mesh::material::MaterialTable;
+use blackjack_engine::prelude::selection::{SelectionExpression, SelectionKind};
+use blackjack_engine::prelude::{ChannelKeyType, FaceId, HalfEdgeMesh, ShadingMode};
+use blackjack_engine::progress::WarningElements;
 use blackjack_engine::{
     lua_engine::{LuaRuntime, RenderableThing},
-    prelude::{FaceOverlayBuffers, LineBuffers, PointBuffers, VertexIndexBuffers},
+    prelude::{
+        FaceOverlayBuffers, LineBuffers, PointBuffers, TriangleBufferLayout, VertexIndexBuffers,
+    },
 };
 use egui::epaint::RectShape;
 use egui::{Rounding, Shape};
+use egui_node_graph::NodeId;
 
+use super::edit_mode::{self, EditMode};
 use super::gizmo_ui::UiNodeGizmoStates;
+use super::paint_mode::{self, PaintMode};
 use super::{
     root_ui::AppRootAction,
     viewport_3d::{EdgeDrawMode, FaceDrawMode, Viewport3dSettings},
@@ -31,12 +48,81 @@ pub struct MeshViewportSelection {
     pub primitive_type: ChannelKeyType,
 }
 
+/// The triangle buffers of the mesh in the viewport, kept between frames so
+/// they are only generated when the mesh changes.
+pub struct BaseMeshBuffers {
+    /// The shading of the buffers, and whether their normals were generated
+    /// instead of taken from the mesh.
+    shading: (ShadingMode, bool),
+    /// The topology fingerprint of the mesh the buffers were generated for.
+    fingerprint: u64,
+    buffers: VertexIndexBuffers,
+    layout: TriangleBufferLayout,
+    /// Set when the vertices of the mesh moved since the buffers were last
+    /// updated, but its topology stayed the same.
+    positions_changed: bool,
+}
+
+/// The result of running the graph with the bypass group toggled, shown in
+/// place of the main result while the compare key is held.
+struct AlternativeResult {
+    renderable_thing: Option<RenderableThing>,
+    mesh_stats: Option<MeshStats>,
+    scene_mesh: Option<HalfEdgeMesh>,
+    base_mesh_buffers: Option<BaseMeshBuffers>,
+}
+
+/// Holding this key shows the alternative result in the viewport.
+const COMPARE_KEY: egui::Key = egui::Key::F;
+
+/// Executions that take longer than this show a progress indicator in the UI.
+const SLOW_EXECUTION_THRESHOLD: Duration = Duration::from_millis(300);
+
+/// Book-keeping for the request currently running in the graph worker. The
+/// results can only be mapped back to the UI graph using the same mapping
+/// that was used to build the request.
+struct InFlightExecution {
+    request_id: u64,
+    mapping: NodeMapping,
+    params: ExternalParameterValues,
+    submitted: Instant,
+}
+
+/// The results of running the graph of a tab that is not shown, while the
+/// context shows the ones of another tab. See
+/// [`ApplicationContext::swap_results`].
+pub struct ExecutionResults {
+    graph: GraphHandle,
+    renderable_thing: Option<RenderableThing>,
+    mesh_stats: Option<MeshStats>,
+    scene_mesh: Option<HalfEdgeMesh>,
+    base_mesh_buffers: Option<BaseMeshBuffers>,
+    edit_mode: EditMode,
+    last_run_error: Option<Error>,
+    execution_paused: bool,
+    large_allocation: Option<MemoryBudgetExceeded>,
+    allowed_bytes: usize,
+    alternative: Option<AlternativeResult>,
+    showing_alternative: bool,
+}
+
 pub struct ApplicationContext {
     /// The 'renderable thing' is at the center of the application, it is
     /// typically a kind of mesh.
     /// - The graph generates a program that produces it.
     /// - The 3d viewport renders it.
     pub renderable_thing: Option<RenderableThing>,
+    /// Incremented every time the `renderable_thing` is replaced, so the
+    /// views built from it know when to update.
+    pub renderable_generation: u64,
+    /// Statistics about the `renderable_thing`, when it is a mesh. Shown in
+    /// the status bar.
+    pub mesh_stats: Option<MeshStats>,
+    /// When the `renderable_thing` is a scene, all of its objects merged into
+    /// a single mesh. This is what the viewport renders for scenes.
+    pub scene_mesh: Option<HalfEdgeMesh>,
+    /// The buffers the viewport draws the faces of the mesh with.
+    base_mesh_buffers: Option<BaseMeshBuffers>,
     /// If the current `renderable_thing` is a HalfEdgeMesh and there is
     /// currently a request to select a group of primitives in the viewport,
     /// this stores the data for the selection.
@@ -44,19 +130,75 @@ pub struct ApplicationContext {
     /// The currently active gizmos. Gizmos are returned by nodes to represent
     /// visual objects that can be used to manipulate its parameters.
     pub node_gizmo_states: UiNodeGizmoStates,
+    /// The vertices selected in the viewport's edit mode, and the offsets
+    /// dragged on them that still need to be recorded in the graph.
+    pub edit_mode: EditMode,
+    /// The brush of the viewport's paint mode, and the dabs painted with it
+    /// that still need to be recorded in the graph.
+    pub paint_mode: PaintMode,
     /// The tree of splits at the center of application. Splits recursively
     /// partition the state either horizontally or vertically. This separation
     /// is dynamic, very similar to Blender's UI model
     pub split_tree: SplitTree,
+    /// Runs the active node in a background thread, so the UI never blocks
+    /// while a graph is executing.
+    pub graph_worker: GraphWorker,
+    /// The graph of the `graph_worker` the requests are submitted for. Each
+    /// tab has its own.
+    graph: GraphHandle,
+    /// The execution currently running in the `graph_worker`, if any.
+    in_flight: Option<InFlightExecution>,
+    /// The error produced by the last finished execution, if any. Kept around
+    /// so it can be displayed until the next execution finishes.
+    last_run_error: Option<Error>,
+    /// When the user cancels an execution, we stop running the active node
+    /// until they choose to resume it.
+    execution_paused: bool,
+    /// The node that stopped the last execution because it would go over the
+    /// memory budget. The execution stays paused until the user confirms it
+    /// can run.
+    large_allocation: Option<MemoryBudgetExceeded>,
+    /// The memory the user confirmed the meshes can take, on top of the
+    /// `memory_budget`.
+    allowed_bytes: usize,
+    /// The memory the meshes of an execution can take, in bytes. Nodes
+    /// predicted to go over it wait for the user to confirm they can run.
+    /// When `None`, every node runs.
+    pub memory_budget: Option<usize>,
+    /// The estimated memory taken by the meshes of the last successful
+    /// execution, in bytes.
+    pub run_mesh_bytes: usize,
+    /// The result of the last execution with the bypass group toggled, if
+    /// there is a bypass group. While `showing_alternative` is set, this
+    /// holds the main result instead.
+    alternative: Option<AlternativeResult>,
+    showing_alternative: bool,
 }
 
 impl ApplicationContext {
-    pub fn new(gizmo_states: UiNodeGizmoStates) -> ApplicationContext {
+    pub fn new(gizmo_states: UiNodeGizmoStates, graph_worker: GraphWorker) -> ApplicationContext {
         ApplicationContext {
             renderable_thing: None,
+            renderable_generation: 0,
+            mesh_stats: None,
+            scene_mesh: None,
+            base_mesh_buffers: None,
             current_selection: None,
             node_gizmo_states: gizmo_states,
+            edit_mode: EditMode::default(),
+            paint_mode: PaintMode::default(),
             split_tree: SplitTree::default_tree(),
+            graph: graph_worker.open_graph(),
+            graph_worker,
+            in_flight: None,
+            last_run_error: None,
+            execution_paused: false,
+            large_allocation: None,
+            allowed_bytes: 0,
+            memory_budget: None,
+            run_mesh_bytes: 0,
+            alternative: None,
+            showing_alternative: false,
         }
     }
 
@@ -77,15 +219,53 @@ impl ApplicationContext {
         custom_state: &mut graph::CustomGraphState,
         render_ctx: &mut RenderContext,
         viewport_settings: &Viewport3dSettings,
+        viewport_clicked: bool,
         lua_runtime: &LuaRuntime,
     ) -> Vec<AppRootAction> {
         // TODO: Instead of clearing all objects, make the app context own the
         // objects it's drawing and clear those instead.
         render_ctx.clear_objects();
 
-        if let Err(err) = self.run_active_node(editor_state, custom_state, lua_runtime) {
-            self.paint_errors(egui_ctx, err);
+        if std::mem::take(&mut custom_state.cancel_requested) {
+            self.cancel_execution();
+        }
+        if let Err(err) = self.run_active_node(editor_state, custom_state) {
+            self.paint_errors(egui_ctx, &err);
         };
+        custom_state.node_progress = self.running_node_progress();
+        let compare_held =
+            !egui_ctx.wants_keyboard_input() && egui_ctx.input().key_down(COMPARE_KEY);
+        self.show_alternative(compare_held);
+        self.alternative_status_ui(egui_ctx, custom_state);
+        if let Some(err) = &self.last_run_error {
+            self.paint_errors(egui_ctx, err);
+        }
+        self.execution_status_ui(egui_ctx);
+        self.update_picking(editor_state, custom_state, viewport_clicked);
+        if let Some(delta) = self.edit_mode.take_pending_delta() {
+            if let Err(err) = edit_mode::record_vertex_deltas(
+                editor_state,
+                custom_state,
+                &self.edit_mode.selected,
+                delta,
+            ) {
+                self.paint_errors(egui_ctx, &err);
+            }
+        }
+        let dabs = self.paint_mode.take_pending_dabs();
+        if let Some(RenderableThing::HalfEdgeMesh(mesh)) = &self.renderable_thing {
+            if !dabs.is_empty() {
+                if let Err(err) = paint_mode::record_dabs(
+                    editor_state,
+                    custom_state,
+                    &self.paint_mode,
+                    mesh,
+                    &dabs,
+                ) {
+                    self.paint_errors(egui_ctx, &err);
+                }
+            }
+        }
 
         if let Err(err) = self.run_side_effects(editor_state, custom_state, lua_runtime) {
             eprintln!(
@@ -93,8 +273,13 @@ impl ApplicationContext {
                 err.backtrace()
             );
         }
-        if let Err(err) = self.build_and_render_mesh(render_ctx, viewport_settings) {
-            self.paint_errors(egui_ctx, err);
+        if let Err(err) = self.build_and_render_mesh(
+            render_ctx,
+            viewport_settings,
+            &custom_state.materials,
+            custom_state.highlighted_warning.as_ref(),
+        ) {
+            self.paint_errors(egui_ctx, &err);
         }
 
         Vec::new()
@@ -104,86 +289,39 @@ impl ApplicationContext {
         &mut self,
         render_ctx: &mut RenderContext,
         viewport_settings: &Viewport3dSettings,
+        materials: &MaterialTable,
+        highlighted_warning: Option<&WarningElements>,
     ) -> Result<()> {
+        let materials = viewport_settings.show_materials.then_some(materials);
+        let highlighted = |mesh: &HalfEdgeMesh| {
+            highlighted_warning
+                .map(|elements| elements.touched_faces(&mesh.read_connectivity()))
+                .unwrap_or_default()
+        };
         match self.renderable_thing.as_mut() {
             Some(RenderableThing::HalfEdgeMesh(mesh)) => {
-                // Base mesh
-                {
-                    if let Some(VertexIndexBuffers {
-                        positions,
-                        normals,
-                        indices,
-                    }) = match viewport_settings.face_mode {
-                        FaceDrawMode::Real => {
-                            if mesh.gen_config.smooth_normals {
-                                Some(mesh.generate_triangle_buffers_smooth(false)?)
-                            } else {
-                                Some(mesh.generate_triangle_buffers_flat(false)?)
-                            }
-                        }
-                        FaceDrawMode::Flat => Some(mesh.generate_triangle_buffers_flat(true)?),
-                        FaceDrawMode::Smooth => Some(mesh.generate_triangle_buffers_smooth(true)?),
-                        FaceDrawMode::NoDraw => None,
-                    } {
-                        if !positions.is_empty() {
-                            render_ctx.face_routine.add_base_mesh(
-                                &render_ctx.renderer,
-                                &positions,
-                                &normals,
-                                &indices,
-                            );
-                        }
-                    }
-                }
-
-                // Face overlays and ids
-                {
-                    let FaceOverlayBuffers {
-                        positions,
-                        colors,
-                        ids,
-                        max_id,
-                    } = mesh.generate_face_overlay_buffers(
-                        self.current_selection.as_ref().and_then(|x| x.hovered),
-                    );
-                    if !positions.is_empty() {
-                        render_ctx.face_routine.add_overlay_mesh(
-                            &render_ctx.renderer,
-                            &positions,
-                            &colors,
-                            &ids,
-                            max_id,
-                        );
-                    }
-                }
-
-                // Edges
-                {
-                    if let Some(LineBuffers { positions, colors }) =
-                        match viewport_settings.edge_mode {
-                            EdgeDrawMode::HalfEdge => Some(mesh.generate_halfedge_arrow_buffers()?),
-                            EdgeDrawMode::FullEdge => Some(mesh.generate_line_buffers()?),
-                            EdgeDrawMode::NoDraw => None,
-                        }
-                    {
-                        if !positions.is_empty() {
-                            render_ctx.wireframe_routine.add_wireframe(
-                                &render_ctx.renderer.device,
-                                &positions,
-                                &colors,
-                            )
-                        }
-                    }
-                }
-
-                // Vertices
-                {
-                    let PointBuffers { positions } = mesh.generate_point_buffers();
-                    if !positions.is_empty() {
-                        render_ctx
-                            .point_cloud_routine
-                            .add_point_cloud(&render_ctx.renderer.device, &positions);
-                    }
+                let hovered = self.current_selection.as_ref().and_then(|x| x.hovered);
+                render_halfedge_mesh(
+                    render_ctx,
+                    viewport_settings,
+                    mesh,
+                    hovered,
+                    &highlighted(mesh),
+                    materials,
+                    &mut self.base_mesh_buffers,
+                )?;
+            }
+            Some(RenderableThing::Scene(_)) => {
+                if let Some(mesh) = &self.scene_mesh {
+                    render_halfedge_mesh(
+                        render_ctx,
+                        viewport_settings,
+                        mesh,
+                        None,
+                        &highlighted(mesh),
+                        materials,
+                        &mut self.base_mesh_buffers,
+                    )?;
                 }
             }
             Some(RenderableThing::HeightMap(heightmap)) => {
@@ -207,7 +345,7 @@ impl ApplicationContext {
         Ok(())
     }
 
-    pub fn paint_errors(&mut self, egui_ctx: &egui::Context, err: Error) {
+    pub fn paint_errors(&self, egui_ctx: &egui::Context, err: &Error) {
         let painter = egui_ctx.debug_painter();
         let width = egui_ctx.available_rect().width();
         let bg_shape = painter.add(Shape::Noop);
@@ -239,56 +377,361 @@ impl ApplicationContext {
         Ok((bjk_graph, mapping, params))
     }
 
-    // Returns the compiled lua code
+    /// Shows a small indicator while the graph worker is taking a long time,
+    /// letting the user cancel the execution.
+    fn execution_status_ui(&mut self, egui_ctx: &egui::Context) {
+        let is_slow = self
+            .in_flight
+            .as_ref()
+            .map_or(false, |x| x.submitted.elapsed() > SLOW_EXECUTION_THRESHOLD);
+        if !is_slow && !self.execution_paused {
+            return;
+        }
+
+        egui::Area::new("execution_status")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .show(egui_ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        if let Some(exceeded) = self.large_allocation.clone() {
+                            ui.label(format!(
+                                "{} will allocate ~{}, continue?",
+                                exceeded.op_name,
+                                format_bytes(exceeded.predicted_bytes)
+                            ));
+                            if ui.button("Continue").clicked() {
+                                self.allowed_bytes = exceeded.predicted_bytes;
+                                self.large_allocation = None;
+                                self.execution_paused = false;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.large_allocation = None;
+                            }
+                        } else if self.execution_paused {
+                            ui.label("Graph execution paused");
+                            if ui.button("Resume").clicked() {
+                                self.execution_paused = false;
+                            }
+                        } else {
+                            ui.label("Running graph...");
+                            if ui.button("Cancel").clicked() {
+                                self.cancel_execution();
+                            }
+                        }
+                    });
+                });
+            });
+    }
+
+    /// Shows which variant of the bypass group is in the viewport while the
+    /// alternative result is displayed.
+    fn alternative_status_ui(
+        &self,
+        egui_ctx: &egui::Context,
+        custom_state: &graph::CustomGraphState,
+    ) {
+        let muted = match &custom_state.bypass_group {
+            Some(group) if self.showing_alternative => !group.muted,
+            _ => return,
+        };
+        egui::Area::new("alternative_status")
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 30.0))
+            .show(egui_ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(if muted {
+                        "Showing the graph with the group bypassed"
+                    } else {
+                        "Showing the graph with the group enabled"
+                    });
+                });
+            });
+    }
+
+    /// Swaps the main result with the alternative one in the viewport, when
+    /// `show` differs from what is being displayed.
+    fn show_alternative(&mut self, show: bool) {
+        if show == self.showing_alternative {
+            return;
+        }
+        if let Some(alternative) = &mut self.alternative {
+            std::mem::swap(
+                &mut self.renderable_thing,
+                &mut alternative.renderable_thing,
+            );
+            std::mem::swap(&mut self.mesh_stats, &mut alternative.mesh_stats);
+            std::mem::swap(&mut self.scene_mesh, &mut alternative.scene_mesh);
+            std::mem::swap(
+                &mut self.base_mesh_buffers,
+                &mut alternative.base_mesh_buffers,
+            );
+            self.renderable_generation += 1;
+            self.showing_alternative = show;
+        }
+    }
+
+    /// Aborts the running execution, and pauses the active node until the
+    /// user resumes it.
+    fn cancel_execution(&mut self) {
+        self.graph_worker.cancel_graph(self.graph);
+        self.execution_paused = true;
+    }
+
+    /// Returns empty results for a new tab, with its own graph in the
+    /// `graph_worker`.
+    pub fn new_results(&self) -> ExecutionResults {
+        ExecutionResults {
+            graph: self.graph_worker.open_graph(),
+            renderable_thing: None,
+            mesh_stats: None,
+            scene_mesh: None,
+            base_mesh_buffers: None,
+            edit_mode: EditMode::default(),
+            last_run_error: None,
+            execution_paused: false,
+            large_allocation: None,
+            allowed_bytes: 0,
+            alternative: None,
+            showing_alternative: false,
+        }
+    }
+
+    /// Shows the `results` of another tab, and keeps the ones shown until
+    /// now in their place. The execution in flight is cancelled, the graph
+    /// runs again when its tab is shown.
+    pub fn swap_results(&mut self, results: &mut ExecutionResults) {
+        if self.in_flight.take().is_some() {
+            self.graph_worker.cancel_graph(self.graph);
+        }
+        std::mem::swap(&mut self.graph, &mut results.graph);
+        std::mem::swap(&mut self.renderable_thing, &mut results.renderable_thing);
+        std::mem::swap(&mut self.mesh_stats, &mut results.mesh_stats);
+        std::mem::swap(&mut self.scene_mesh, &mut results.scene_mesh);
+        std::mem::swap(&mut self.base_mesh_buffers, &mut results.base_mesh_buffers);
+        std::mem::swap(&mut self.edit_mode, &mut results.edit_mode);
+        std::mem::swap(&mut self.last_run_error, &mut results.last_run_error);
+        std::mem::swap(&mut self.execution_paused, &mut results.execution_paused);
+        std::mem::swap(&mut self.large_allocation, &mut results.large_allocation);
+        std::mem::swap(&mut self.allowed_bytes, &mut results.allowed_bytes);
+        std::mem::swap(&mut self.alternative, &mut results.alternative);
+        std::mem::swap(
+            &mut self.showing_alternative,
+            &mut results.showing_alternative,
+        );
+        self.renderable_generation += 1;
+        self.current_selection = None;
+    }
+
+    /// Drops the `results` of a closed tab, along with its graph in the
+    /// `graph_worker`.
+    pub fn close_results(&self, results: ExecutionResults) {
+        self.graph_worker.close_graph(results.graph);
+    }
+
+    /// Sets the node libraries of the shown graph in the `graph_worker`.
+    pub fn set_graph_libraries(&self, libraries: GraphLibraries) {
+        self.graph_worker
+            .set_graph_libraries_for(self.graph, libraries);
+    }
+
+    /// Returns the UI node running in a slow execution, and its progress.
+    fn running_node_progress(&self) -> Option<(NodeId, f32)> {
+        let in_flight = self
+            .in_flight
+            .as_ref()
+            .filter(|x| x.submitted.elapsed() > SLOW_EXECUTION_THRESHOLD)?;
+        let (bjk_node, progress) = self.graph_worker.progress()?;
+        Some((in_flight.mapping[bjk_node], progress))
+    }
+
+    /// Picks up the result of the last execution of the active node, if it
+    /// has finished, and submits a new one to the graph worker. Only one
+    /// execution is in flight at a time, so rapid changes to the graph while
+    /// the worker is busy are coalesced into the next execution.
     pub fn run_active_node(
         &mut self,
         editor_state: &mut graph::GraphEditorState,
         custom_state: &mut graph::CustomGraphState,
-        lua_runtime: &LuaRuntime,
     ) -> Result<()> {
-        if let Some(active) = custom_state.active_node {
-            let (bjk_graph, mapping, params) =
-                self.generate_bjk_graph(&editor_state.graph, custom_state)?;
-            let gizmos = self.node_gizmo_states.to_bjk_data(&mapping);
-            let program_result = blackjack_engine::graph_interpreter::run_graph(
-                &lua_runtime.lua,
-                &bjk_graph,
-                mapping[active],
-                params,
-                &lua_runtime.node_definitions,
-                Some(gizmos),
-            )?;
-
-            self.renderable_thing = program_result.renderable;
-            if let Some(updated_gizmos) = program_result.updated_gizmos {
-                self.node_gizmo_states
-                    .update_gizmos(updated_gizmos, &mapping)?;
+        while let Some(response) = self.graph_worker.try_recv() {
+            match self.in_flight.take() {
+                Some(in_flight)
+                    if in_flight.request_id == response.request_id
+                        && response.graph == self.graph =>
+                {
+                    // New results always replace the main ones
+                    let showing_alternative = self.showing_alternative;
+                    self.show_alternative(false);
+                    self.alternative = match response.alternative {
+                        Some(Ok(alternative)) => {
+                            let scene_mesh = merged_scene_mesh(&alternative.renderable);
+                            Some(AlternativeResult {
+                                renderable_thing: alternative.renderable,
+                                mesh_stats: alternative.mesh_stats,
+                                scene_mesh,
+                                base_mesh_buffers: None,
+                            })
+                        }
+                        Some(Err(err)) => {
+                            println!("Error: Could not run the alternative graph: {err}");
+                            None
+                        }
+                        None => None,
+                    };
+                    match response.result {
+                        Ok(program_result) => {
+                            self.last_run_error = None;
+                            custom_state.crashed_node = None;
+                            self.apply_program_result(
+                                editor_state,
+                                custom_state,
+                                program_result,
+                                in_flight,
+                            )?;
+                        }
+                        Err(err) if err.is::<ExecutionCancelled>() => {}
+                        Err(err) => {
+                            custom_state.crashed_node =
+                                err.downcast_ref::<NodePanicked>().map(|panicked| {
+                                    (
+                                        in_flight.mapping[panicked.node_id],
+                                        panicked.message.clone(),
+                                    )
+                                });
+                            if let Some(exceeded) = err.downcast_ref::<MemoryBudgetExceeded>() {
+                                self.large_allocation = Some(exceeded.clone());
+                                self.execution_paused = true;
+                            }
+                            self.last_run_error = Some(err);
+                        }
+                    }
+                    self.show_alternative(showing_alternative);
+                }
+                // A response for an execution we no longer care about.
+                other => self.in_flight = other,
             }
+        }
 
-            // TODO: This is debug code used by viewport picking. Currently disabled.
-            /* if let Some(RenderableThing::HalfEdgeMesh(_)) = &self.renderable_thing {
-                if self.current_selection.is_none() {
-                    self.current_selection = Some(MeshViewportSelection {
-                        hovered: None,
-                        selected: HashSet::new(),
-                        primitive_type: ChannelKeyType::FaceId,
-                    });
-                }
-            } */
-
-            // Running gizmos returns a set of updated values, we need to
-            // refresh the UI graph values with those here.
-            graph_interop::set_parameters_from_external_values(
-                &mut editor_state.graph,
-                program_result.updated_values,
-                mapping,
-            )?;
+        if let Some(active) = custom_state.active_node {
+            if self.in_flight.is_none() && !self.execution_paused {
+                let (bjk_graph, mapping, params) =
+                    self.generate_bjk_graph(&editor_state.graph, custom_state)?;
+                let gizmos = self.node_gizmo_states.to_bjk_data(&mapping);
+                let (muted, compare_group) = match &custom_state.bypass_group {
+                    Some(group) => {
+                        let nodes = group
+                            .nodes
+                            .iter()
+                            .filter_map(|node_id| mapping.get(*node_id))
+                            .collect::<HashSet<_>>();
+                        let muted = if group.muted {
+                            nodes.clone()
+                        } else {
+                            HashSet::new()
+                        };
+                        (muted, Some(nodes))
+                    }
+                    None => (HashSet::new(), None),
+                };
+                let request_id = self.graph_worker.submit_to(
+                    self.graph,
+                    ExecutionRequest {
+                        graph: bjk_graph,
+                        target_node: mapping[active],
+                        params: params.clone(),
+                        gizmos: Some(gizmos),
+                        muted,
+                        compare_group,
+                        memory_budget: self
+                            .memory_budget
+                            .map(|budget| budget.max(self.allowed_bytes)),
+                    },
+                );
+                self.in_flight = Some(InFlightExecution {
+                    request_id,
+                    mapping,
+                    params,
+                    submitted: Instant::now(),
+                });
+            }
         } else {
+            if self.in_flight.take().is_some() {
+                self.graph_worker.cancel_graph(self.graph);
+            }
             self.renderable_thing = None;
+            self.renderable_generation += 1;
+            self.mesh_stats = None;
+            self.scene_mesh = None;
+            self.base_mesh_buffers = None;
+            self.last_run_error = None;
+            self.alternative = None;
+            self.showing_alternative = false;
         }
         Ok(())
     }
 
+    fn apply_program_result(
+        &mut self,
+        editor_state: &mut graph::GraphEditorState,
+        custom_state: &mut graph::CustomGraphState,
+        program_result: ProgramResult,
+        in_flight: InFlightExecution,
+    ) -> Result<()> {
+        let InFlightExecution {
+            mapping, params, ..
+        } = in_flight;
+
+        self.run_mesh_bytes = program_result.stats.mesh_bytes;
+        custom_state.node_warnings.clear();
+        for warning in &program_result.stats.warnings {
+            custom_state
+                .node_warnings
+                .entry(mapping[warning.node_id])
+                .or_default()
+                .push(warning.warning.clone());
+        }
+        custom_state.node_errors = program_result
+            .stats
+            .errors
+            .iter()
+            .map(|error| (mapping[error.node_id], error.warning.message.clone()))
+            .collect();
+
+        // When only the vertices moved, the triangulation of the previous mesh
+        // is reused and the buffers just get the new positions.
+        self.base_mesh_buffers = match (self.base_mesh_buffers.take(), &program_result.renderable) {
+            (Some(mut cached), Some(RenderableThing::HalfEdgeMesh(mesh)))
+                if program_result.positions_only
+                    && mesh.read_connectivity().topology_fingerprint() == cached.fingerprint =>
+            {
+                cached.positions_changed = true;
+                Some(cached)
+            }
+            _ => None,
+        };
+        self.renderable_thing = program_result.renderable;
+        self.renderable_generation += 1;
+        self.scene_mesh = merged_scene_mesh(&self.renderable_thing);
+        self.mesh_stats = program_result.mesh_stats;
+        if let Some(updated_gizmos) = program_result.updated_gizmos {
+            self.node_gizmo_states
+                .update_gizmos(updated_gizmos, &mapping)?;
+        }
+
+        // Running gizmos returns a set of updated values, we need to
+        // refresh the UI graph values with those here. The user may have
+        // edited the graph while the execution was running, so we only
+        // write back the values that the execution actually changed.
+        let mut updated_values = program_result.updated_values;
+        updated_values
+            .0
+            .retain(|param, value| params.0.get(param) != Some(value));
+        graph_interop::set_parameters_from_external_values(
+            &mut editor_state.graph,
+            updated_values,
+            mapping,
+        )
+    }
+
     pub fn run_side_effects(
         &mut self,
         editor_state: &mut graph::GraphEditorState,
@@ -312,9 +755,206 @@ impl ApplicationContext {
         Ok(())
     }
 
+    /// While a selection parameter is being picked, toggles the face under
+    /// the cursor in that parameter every time the viewport is clicked.
+    fn update_picking(
+        &mut self,
+        editor_state: &mut graph::GraphEditorState,
+        custom_state: &mut graph::CustomGraphState,
+        viewport_clicked: bool,
+    ) {
+        let input_id = custom_state.picking.as_ref().and_then(|(node_id, name)| {
+            editor_state
+                .graph
+                .nodes
+                .get(*node_id)
+                .and_then(|node| node.get_input(name).ok())
+        });
+        let input_id = match (input_id, &self.renderable_thing) {
+            (Some(input_id), Some(RenderableThing::HalfEdgeMesh(_))) => input_id,
+            _ => {
+                // The parameter may be gone, e.g. if its node was deleted.
+                if input_id.is_none() {
+                    custom_state.picking = None;
+                }
+                self.current_selection = None;
+                return;
+            }
+        };
+        let selection = self
+            .current_selection
+            .get_or_insert_with(|| MeshViewportSelection {
+                hovered: None,
+                selected: HashSet::new(),
+                primitive_type: ChannelKeyType::FaceId,
+            });
+
+        // Face ids in the overlay are offset by one, zero means no face.
+        let face = match selection.hovered {
+            Some(id) if viewport_clicked && id > 0 => id - 1,
+            _ => return,
+        };
+        if let (BlackjackValue::Selection(text, sel), Some((node_id, name))) = (
+            &mut editor_state.graph.inputs[input_id].value.0,
+            &custom_state.picking,
+        ) {
+            let mut new_sel = sel.clone().unwrap_or(SelectionExpression::None);
+            new_sel.toggle(face);
+            *text = new_sel.unparse();
+            *sel = Some(new_sel);
+
+            // The ids refer to the mesh that is being displayed. Ids picked
+            // on the output of the node that owns the parameter are used as
+            // they are.
+            match custom_state.active_node {
+                Some(active) if active != *node_id => {
+                    custom_state.picked_selections.insert(
+                        (*node_id, name.clone()),
+                        graph::PickedFrom {
+                            node: active,
+                            kind: SelectionKind::Faces,
+                        },
+                    );
+                }
+                _ => {
+                    custom_state
+                        .picked_selections
+                        .remove(&(*node_id, name.clone()));
+                }
+            }
+        }
+    }
+
     pub fn on_id_hovered(&mut self, id: Option<u32>) {
         if let Some(selection) = &mut self.current_selection {
             selection.hovered = id;
         }
     }
 }
+
+/// Returns the mesh that the viewport renders for a scene, all of its objects
+/// merged together.
+fn merged_scene_mesh(renderable: &Option<RenderableThing>) -> Option<HalfEdgeMesh> {
+    match renderable {
+        Some(RenderableThing::Scene(scene)) => Some(scene.to_merged_mesh()),
+        _ => None,
+    }
+}
+
+/// Adds the buffers to draw a halfedge `mesh` to the viewport: Its faces,
+/// edges and vertices, depending on the `viewport_settings`. The `hovered`
+/// face, if any, and the `highlighted` ones are drawn on top, and faces are
+/// tinted with the color of their material when `materials` are given.
+///
+/// The triangle buffers of the faces are kept in `base_buffers`, and only
+/// generated again when there are none, or they use a different shading.
+pub fn render_halfedge_mesh(
+    render_ctx: &mut RenderContext,
+    viewport_settings: &Viewport3dSettings,
+    mesh: &HalfEdgeMesh,
+    hovered: Option<u32>,
+    highlighted: &HashSet<FaceId>,
+    materials: Option<&MaterialTable>,
+    base_buffers: &mut Option<BaseMeshBuffers>,
+) -> Result<()> {
+    // Base mesh
+    {
+        // The shading to use, and whether to generate normals. By default,
+        // the mesh is shown with its own shading.
+        let shading = match viewport_settings.face_mode {
+            FaceDrawMode::Real => Some((mesh.gen_config.shading, false)),
+            FaceDrawMode::Flat => Some((ShadingMode::Flat, true)),
+            FaceDrawMode::Smooth => Some((ShadingMode::Smooth, true)),
+            FaceDrawMode::NoDraw => None,
+        };
+        if let Some((shading, force_gen)) = shading {
+            let up_to_date =
+                matches!(base_buffers, Some(cached) if cached.shading == (shading, force_gen));
+            if !up_to_date {
+                let (buffers, layout) = if force_gen {
+                    let smooth = shading != ShadingMode::Flat;
+                    mesh.generate_triangle_buffers_with_layout(smooth, force_gen)?
+                } else {
+                    mesh.generate_shaded_triangle_buffers()?
+                };
+                *base_buffers = Some(BaseMeshBuffers {
+                    shading: (shading, force_gen),
+                    fingerprint: mesh.read_connectivity().topology_fingerprint(),
+                    buffers,
+                    layout,
+                    positions_changed: false,
+                });
+            }
+            let cached = base_buffers.as_mut().expect("Just generated");
+            if cached.positions_changed {
+                mesh.update_triangle_buffers(&mut cached.buffers, &cached.layout, force_gen)?;
+                cached.positions_changed = false;
+            }
+            let VertexIndexBuffers {
+                positions,
+                normals,
+                indices,
+            } = &cached.buffers;
+            if !positions.is_empty() {
+                render_ctx.face_routine.add_base_mesh(
+                    &render_ctx.renderer,
+                    positions,
+                    normals,
+                    indices,
+                );
+            }
+        }
+    }
+
+    // Face overlays and ids
+    {
+        let FaceOverlayBuffers {
+            positions,
+            colors,
+            ids,
+            max_id,
+        } = mesh.generate_face_overlay_buffers(
+            hovered,
+            highlighted,
+            materials,
+            viewport_settings.heatmap.as_deref(),
+        );
+        if !positions.is_empty() {
+            render_ctx.face_routine.add_overlay_mesh(
+                &render_ctx.renderer,
+                &positions,
+                &colors,
+                &ids,
+                max_id,
+            );
+        }
+    }
+
+    // Edges
+    {
+        if let Some(LineBuffers { positions, colors }) = match viewport_settings.edge_mode {
+            EdgeDrawMode::HalfEdge => Some(mesh.generate_halfedge_arrow_buffers()?),
+            EdgeDrawMode::FullEdge => Some(mesh.generate_line_buffers()?),
+            EdgeDrawMode::NoDraw => None,
+        } {
+            if !positions.is_empty() {
+                render_ctx.wireframe_routine.add_wireframe(
+                    &render_ctx.renderer.device,
+                    &positions,
+                    &colors,
+                )
+            }
+        }
+    }
+
+    // Vertices
+    {
+        let PointBuffers { positions } = mesh.generate_point_buffers();
+        if !positions.is_empty() {
+            render_ctx
+                .point_cloud_routine
+                .add_point_cloud(&render_ctx.renderer.device, &positions);
+        }
+    }
+    Ok(())
+}
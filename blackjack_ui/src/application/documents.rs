@@ -0,0 +1,319 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::lua_engine::graph_libraries::GraphLibraries;
+use blackjack_engine::lua_engine::LuaRuntimeConfig;
+
+use super::application_context::ExecutionResults;
+use super::file_dialogs::UnsavedChangesGuard;
+use super::gizmo_ui::UiNodeGizmoStates;
+use super::graph_editor::UndoableEdit;
+use super::viewport_3d::OrbitCamera;
+use crate::graph::node_graph::{CustomGraphState, GraphEditorState};
+use crate::prelude::*;
+
+/// Everything that belongs to one of the graphs open in tabs. The editor,
+/// the viewport and the application context show the graph of the active
+/// tab, and the other tabs keep theirs in one of these. The Lua runtime, the
+/// node definitions, the asset cache and the settings are shared by all the
+/// tabs.
+pub struct Document {
+    pub editor_state: GraphEditorState,
+    pub custom_state: CustomGraphState,
+    pub last_edit: Option<UndoableEdit>,
+    pub open_file: Option<PathBuf>,
+    pub unsaved_changes: UnsavedChangesGuard,
+    /// The gizmo states of the nodes, which are only valid for their graph.
+    pub gizmo_states: UiNodeGizmoStates,
+    pub camera: OrbitCamera,
+    pub results: ExecutionResults,
+    pub libraries: GraphLibraries,
+    pub runtime_config: LuaRuntimeConfig,
+}
+
+impl Document {
+    /// A tab for the given graph, which has no file and hasn't run yet.
+    pub fn new(
+        editor_state: GraphEditorState,
+        custom_state: CustomGraphState,
+        results: ExecutionResults,
+    ) -> Self {
+        Self {
+            editor_state,
+            custom_state,
+            last_edit: None,
+            open_file: None,
+            unsaved_changes: UnsavedChangesGuard::default(),
+            gizmo_states: UiNodeGizmoStates::init(),
+            camera: OrbitCamera::default(),
+            results,
+            libraries: GraphLibraries::default(),
+            runtime_config: LuaRuntimeConfig::default(),
+        }
+    }
+}
+
+/// Returns the title of the tab of a graph loaded from `open_file`.
+pub fn tab_title(open_file: Option<&Path>) -> String {
+    match open_file.and_then(|path| path.file_name()) {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => "Untitled".into(),
+    }
+}
+
+/// What the user did in the tab bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabAction {
+    Select(usize),
+    Close(usize),
+    New,
+}
+
+/// The tabs of the graphs open in the editor, in the order they are shown.
+/// The active tab has no [`Document`], its graph is the one in the editor.
+pub struct DocumentTabs {
+    tabs: Vec<Option<Document>>,
+    active: usize,
+}
+
+impl Default for DocumentTabs {
+    fn default() -> Self {
+        Self {
+            tabs: vec![None],
+            active: 0,
+        }
+    }
+}
+
+impl DocumentTabs {
+    /// Returns the tab after the active one, or the one before it when going
+    /// `backwards`. The tabs wrap around.
+    pub fn cycle(&self, backwards: bool) -> usize {
+        let len = self.tabs.len();
+        if backwards {
+            (self.active + len - 1) % len
+        } else {
+            (self.active + 1) % len
+        }
+    }
+
+    /// Adds a new tab after the others and makes it active. The `previous`
+    /// document is the one the editor showed until now, which stays in the
+    /// tab that was active.
+    pub fn push(&mut self, previous: Document) {
+        self.tabs[self.active] = Some(previous);
+        self.tabs.push(None);
+        self.active = self.tabs.len() - 1;
+    }
+
+    /// Makes the tab at `index` active. The `swap` function exchanges its
+    /// document with the one the editor shows. Returns whether the active
+    /// tab changed.
+    pub fn select(
+        &mut self,
+        index: usize,
+        swap: impl FnOnce(&mut Document) -> Result<()>,
+    ) -> Result<bool> {
+        if index == self.active || index >= self.tabs.len() {
+            return Ok(false);
+        }
+        let mut document = self.tabs[index]
+            .take()
+            .expect("Only the active tab has no document");
+        let swapped = swap(&mut document);
+        // The shown document is the one of the selected tab, even when the
+        // swap could not be completed.
+        self.tabs[self.active] = Some(document);
+        self.active = index;
+        swapped.map(|()| true)
+    }
+
+    /// Removes the active tab, and makes the one before it active, or the
+    /// one after it for the first tab. The `swap` function exchanges the
+    /// document of that tab with the one the editor shows. Returns the
+    /// document of the closed tab, or `None` when it's the only tab, which
+    /// can't be closed.
+    pub fn close_active(
+        &mut self,
+        swap: impl FnOnce(&mut Document) -> Result<()>,
+    ) -> Result<Option<Document>> {
+        if self.tabs.len() == 1 {
+            return Ok(None);
+        }
+        let closed = self.active;
+        let shown = if closed == 0 { 1 } else { closed - 1 };
+        let mut document = self.tabs[shown]
+            .take()
+            .expect("Only the active tab has no document");
+        let swapped = swap(&mut document);
+        self.tabs.remove(closed);
+        self.active = if shown > closed { shown - 1 } else { shown };
+        swapped.map(|()| Some(document))
+    }
+
+    /// Shows the tab bar, with the `shown_title` for the active tab. Returns
+    /// what the user did with it, if anything.
+    pub fn tab_bar_ui(&self, ctx: &egui::Context, shown_title: &str) -> Option<TabAction> {
+        let mut action = None;
+        egui::TopBottomPanel::top("document_tabs").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (index, document) in self.tabs.iter().enumerate() {
+                    let title = match document {
+                        Some(document) => tab_title(document.open_file.as_deref()),
+                        None => shown_title.to_string(),
+                    };
+                    let tab = ui.selectable_label(index == self.active, title);
+                    if tab.clicked() {
+                        action = Some(TabAction::Select(index));
+                    }
+                    if let Some(path) = document.as_ref().and_then(|d| d.open_file.as_ref()) {
+                        tab.on_hover_text(path.display().to_string());
+                    }
+                    if ui.small_button("✖").on_hover_text("Close tab").clicked() {
+                        action = Some(TabAction::Close(index));
+                    }
+                    ui.separator();
+                }
+                if ui
+                    .small_button("+")
+                    .on_hover_text("New tab (Ctrl+Tab cycles the tabs)")
+                    .clicked()
+                {
+                    action = Some(TabAction::New);
+                }
+            });
+        });
+        action
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use blackjack_engine::graph_worker::GraphWorker;
+    use blackjack_engine::lua_engine::LuaRuntime;
+
+    use super::*;
+    use crate::application::application_context::ApplicationContext;
+    use crate::application::file_dialogs::graph_snapshot;
+    use crate::application::node_examples::load_example;
+    use crate::application::serialization;
+
+    fn test_runtime() -> LuaRuntime {
+        LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap()
+    }
+
+    #[test]
+    fn test_tabs_keep_their_documents() {
+        let runtime = test_runtime();
+        let gizmo_states = UiNodeGizmoStates::init();
+        let app_context = ApplicationContext::new(
+            gizmo_states.share(),
+            GraphWorker::spawn(|| LuaRuntime::initialize_with_std("../blackjack_lua".into())),
+        );
+        let document = |seed: u64| {
+            let mut custom_state =
+                CustomGraphState::new(runtime.node_definitions.share(), gizmo_states.share());
+            custom_state.graph_seed = seed;
+            Document::new(
+                GraphEditorState::new(1.0),
+                custom_state,
+                app_context.new_results(),
+            )
+        };
+        // Stands for the document in the editor
+        let mut shown = document(0);
+        let seed_of = |tabs: &mut DocumentTabs, shown: &mut Document, index: usize| {
+            tabs.select(index, |document| {
+                std::mem::swap(shown, document);
+                Ok(())
+            })
+            .unwrap();
+            shown.custom_state.graph_seed
+        };
+
+        let mut tabs = DocumentTabs::default();
+        for seed in [1, 2] {
+            let mut new = document(seed);
+            std::mem::swap(&mut shown, &mut new);
+            tabs.push(new);
+        }
+        assert_eq!((tabs.tabs.len(), tabs.active), (3, 2));
+        assert_eq!(shown.custom_state.graph_seed, 2);
+        assert_eq!(tabs.cycle(false), 0);
+        assert_eq!(tabs.cycle(true), 1);
+
+        assert_eq!(seed_of(&mut tabs, &mut shown, 0), 0);
+        assert_eq!(seed_of(&mut tabs, &mut shown, 2), 2);
+        assert_eq!(seed_of(&mut tabs, &mut shown, 1), 1);
+        // Selecting the active tab doesn't swap anything
+        assert!(!tabs.select(1, |_| panic!("Should not swap")).unwrap());
+
+        // Closing the middle tab shows the one before it
+        let swap = |document: &mut Document| -> Result<()> {
+            std::mem::swap(&mut shown, document);
+            Ok(())
+        };
+        let closed = tabs.close_active(swap).unwrap().unwrap();
+        assert_eq!(closed.custom_state.graph_seed, 1);
+        assert_eq!(shown.custom_state.graph_seed, 0);
+        assert_eq!((tabs.tabs.len(), tabs.active), (2, 0));
+        assert_eq!(seed_of(&mut tabs, &mut shown, 1), 2);
+        assert_eq!(seed_of(&mut tabs, &mut shown, 0), 0);
+
+        // Closing the first tab shows the next one
+        let swap = |document: &mut Document| -> Result<()> {
+            std::mem::swap(&mut shown, document);
+            Ok(())
+        };
+        let closed = tabs.close_active(swap).unwrap().unwrap();
+        assert_eq!(closed.custom_state.graph_seed, 0);
+        assert_eq!(shown.custom_state.graph_seed, 2);
+        assert_eq!((tabs.tabs.len(), tabs.active), (1, 0));
+        // The last tab stays
+        assert!(tabs
+            .close_active(|_| panic!("Should not swap"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_paste_nodes_copied_from_another_tab() {
+        let runtime = test_runtime();
+        let defs = &runtime.node_definitions;
+        let gizmo_states = UiNodeGizmoStates::init();
+        let (source_editor, source_custom) =
+            load_example("extrude_faces", &runtime.lua, defs, &gizmo_states).unwrap();
+        let mut editor_state = GraphEditorState::new(1.0);
+        let mut custom_state = CustomGraphState::new(defs.share(), gizmo_states.share());
+
+        // Copying and pasting goes through the clipboard, like between two
+        // instances of blackjack
+        let nodes = source_editor.graph.nodes.keys().collect_vec();
+        let copied = serialization::to_clipboard(&source_editor, &source_custom, &nodes).unwrap();
+        let snippet = serialization::parse_clipboard_snippet(&copied).unwrap();
+        serialization::from_clipboard(
+            &mut editor_state,
+            &mut custom_state,
+            snippet,
+            egui::Pos2::ZERO,
+        )
+        .unwrap();
+
+        let op_names = |editor_state: &GraphEditorState| {
+            editor_state
+                .graph
+                .nodes
+                .iter()
+                .map(|(_, node)| node.user_data.op_name.clone())
+                .sorted()
+                .collect_vec()
+        };
+        assert_eq!(op_names(&editor_state), op_names(&source_editor));
+        assert!(graph_snapshot(&editor_state, &custom_state).is_some());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::graph::BlackjackValue;
+use blackjack_engine::prelude::HalfEdgeMesh;
+use egui_node_graph::{NodeId, NodeTemplateTrait};
+
+use crate::app_window::gui_overlay::project_point;
+use crate::prelude::*;
+
+use super::viewport_3d::Viewport3d;
+
+/// The op name of the node that stores the edits made in the viewport.
+pub const MANUAL_EDIT_OP: &str = "ManualEdit";
+
+/// Vertices further than this from the cursor, in points, can't be picked.
+const PICK_RADIUS: f32 = 12.0;
+
+/// State of the viewport's edit mode. In edit mode, clicking the viewport
+/// selects vertices of the displayed mesh, which can then be dragged around
+/// with a gizmo. The offsets are stored in a `Manual Edit` node.
+#[derive(Default)]
+pub struct EditMode {
+    pub enabled: bool,
+    /// The selected vertices, by their index in the displayed mesh.
+    pub selected: Vec<u32>,
+    /// Where the gizmo is drawn. This can't be derived from the selected
+    /// vertices while dragging, because the mesh only moves after the graph
+    /// runs again.
+    gizmo_position: Option<Vec3>,
+    /// The offset dragged with the gizmo that hasn't been written to the
+    /// graph yet.
+    pending_delta: Vec3,
+}
+
+impl EditMode {
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.selected.clear();
+        self.gizmo_position = None;
+    }
+
+    /// Selects the vertex under `cursor`, or clears the selection if there's
+    /// none. With `additive`, the vertex is toggled instead, keeping the rest
+    /// of the selection.
+    pub fn click(
+        &mut self,
+        mesh: &HalfEdgeMesh,
+        view_proj: &Mat4,
+        viewport_rect: egui::Rect,
+        cursor: egui::Pos2,
+        additive: bool,
+    ) {
+        let picked = pick_vertex(mesh, view_proj, viewport_rect, cursor);
+        match (picked, additive) {
+            (Some(v), true) => {
+                if let Some(pos) = self.selected.iter().position(|x| *x == v) {
+                    self.selected.remove(pos);
+                } else {
+                    self.selected.push(v);
+                }
+            }
+            (Some(v), false) => self.selected = vec![v],
+            (None, true) => {}
+            (None, false) => self.selected.clear(),
+        }
+        self.gizmo_position = None;
+    }
+
+    /// Highlights the selected vertices in the viewport.
+    pub fn draw_selection(
+        &self,
+        painter: &egui::Painter,
+        mesh: &HalfEdgeMesh,
+        view_proj: &Mat4,
+        viewport_rect: egui::Rect,
+    ) {
+        let positions = selected_positions(mesh, &self.selected);
+        for pos in positions {
+            painter.circle_filled(
+                project_point(view_proj, viewport_rect, pos),
+                4.0,
+                egui::Color32::GOLD,
+            );
+        }
+    }
+
+    /// Draws a translation gizmo on the selected vertices. Returns whether
+    /// the gizmo is being dragged.
+    pub fn gizmo_ui(
+        &mut self,
+        viewport: &Viewport3d,
+        ui: &mut egui::Ui,
+        mesh: &HalfEdgeMesh,
+    ) -> bool {
+        let positions = selected_positions(mesh, &self.selected);
+        if positions.is_empty() {
+            self.gizmo_position = None;
+            return false;
+        }
+        let position = *self.gizmo_position.get_or_insert_with(|| {
+            positions.iter().fold(Vec3::ZERO, |acc, p| acc + *p) / positions.len() as f32
+        });
+
+        let gizmo = egui_gizmo::Gizmo::new("edit_mode_gizmo")
+            .view_matrix(viewport.view_matrix().to_cols_array_2d())
+            .projection_matrix(viewport.projection_matrix().to_cols_array_2d())
+            .model_matrix(Mat4::from_translation(position).to_cols_array_2d())
+            .viewport(viewport.viewport_rect())
+            .mode(egui_gizmo::GizmoMode::Translate);
+        if let Some(response) = gizmo.interact(ui) {
+            let new_position = Mat4::from_cols_array_2d(&response.transform)
+                .w_axis
+                .truncate();
+            self.pending_delta += new_position - position;
+            self.gizmo_position = Some(new_position);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the offset dragged since the last call, if any.
+    pub fn take_pending_delta(&mut self) -> Option<Vec3> {
+        let delta = std::mem::replace(&mut self.pending_delta, Vec3::ZERO);
+        (delta != Vec3::ZERO).then_some(delta)
+    }
+}
+
+/// Returns the positions of the vertices at the given indices. Indices not in
+/// the mesh are ignored.
+fn selected_positions(mesh: &HalfEdgeMesh, selected: &[u32]) -> Vec<Vec3> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    let vertices = conn.iter_vertices().map(|(v, _)| v).collect_vec();
+    selected
+        .iter()
+        .filter_map(|idx| vertices.get(*idx as usize))
+        .map(|v| positions[*v])
+        .collect()
+}
+
+/// Returns the index of the vertex closest to `cursor` on the screen. When
+/// several vertices overlap, the one closest to the camera wins.
+fn pick_vertex(
+    mesh: &HalfEdgeMesh,
+    view_proj: &Mat4,
+    viewport_rect: egui::Rect,
+    cursor: egui::Pos2,
+) -> Option<u32> {
+    let conn = mesh.read_connectivity();
+    let positions = mesh.read_positions();
+    conn.iter_vertices()
+        .enumerate()
+        .filter_map(|(idx, (v, _))| {
+            let depth = view_proj.project_point3(positions[v]).z;
+            if !(0.0..=1.0).contains(&depth) {
+                // Behind the camera, or past the far plane
+                return None;
+            }
+            let distance = project_point(view_proj, viewport_rect, positions[v]).distance(cursor);
+            (distance < PICK_RADIUS).then_some((idx as u32, distance, depth))
+        })
+        .min_by(|(_, d1, z1), (_, d2, z2)| {
+            // Distances are rounded to whole points, so that vertices that
+            // look the same on screen are sorted by depth.
+            d1.round().total_cmp(&d2.round()).then(z1.total_cmp(z2))
+        })
+        .map(|(idx, _, _)| idx)
+}
+
+/// Adds `delta` to the offset of each of the `vertices` in the `Manual Edit`
+/// node. When the active node is not one already, a new one is appended after
+/// it and becomes the active node.
+pub fn record_vertex_deltas(
+    editor_state: &mut graph::GraphEditorState,
+    custom_state: &mut graph::CustomGraphState,
+    vertices: &[u32],
+    delta: Vec3,
+) -> Result<()> {
+    let active = custom_state
+        .active_node
+        .ok_or_else(|| anyhow!("There is no active node to edit"))?;
+    let edit_node = if editor_state.graph[active].user_data.op_name == MANUAL_EDIT_OP {
+        active
+    } else {
+        let edit_node = append_edit_node(editor_state, custom_state, active, MANUAL_EDIT_OP)?;
+        custom_state.active_node = Some(edit_node);
+        edit_node
+    };
+
+    let input_id = editor_state.graph[edit_node].get_input("deltas")?;
+    match &mut editor_state.graph.inputs[input_id].value.0 {
+        BlackjackValue::VertexDeltas(deltas) => {
+            for v in vertices {
+                deltas.add(*v, delta);
+            }
+            Ok(())
+        }
+        _ => bail!("The 'deltas' parameter of the Manual Edit node has the wrong type"),
+    }
+}
+
+/// Creates a node with the given `op_name` next to `node`, taking its mesh as
+/// input. Used for the nodes that store changes made in the viewport, which
+/// take their input mesh from a `mesh` parameter.
+pub fn append_edit_node(
+    editor_state: &mut graph::GraphEditorState,
+    custom_state: &mut graph::CustomGraphState,
+    node: NodeId,
+    op_name: &str,
+) -> Result<NodeId> {
+    let returns = custom_state
+        .node_definitions
+        .node_def(&editor_state.graph[node].user_data.op_name)
+        .and_then(|def| def.returns.clone());
+    let output_id = returns
+        .and_then(|name| editor_state.graph[node].get_output(&name).ok())
+        .filter(|output| editor_state.graph.outputs[*output].typ.0.is_mesh())
+        .ok_or_else(|| anyhow!("Only nodes that return a mesh can be edited in the viewport"))?;
+    if custom_state.node_definitions.node_def(op_name).is_none() {
+        bail!("The {op_name} node definition is missing");
+    }
+
+    let template = graph::NodeOpName(op_name.into());
+    let label = template.node_graph_label(custom_state);
+    let user_data = template.user_data(custom_state);
+    let edit_node = editor_state
+        .graph
+        .add_node(label, user_data, |graph, node_id| {
+            template.build_node(graph, custom_state, node_id)
+        });
+    let position = editor_state
+        .node_positions
+        .get(node)
+        .copied()
+        .unwrap_or(egui::Pos2::ZERO);
+    editor_state
+        .node_positions
+        .insert(edit_node, position + egui::vec2(250.0, 0.0));
+    editor_state.node_order.push(edit_node);
+
+    let input_id = editor_state.graph[edit_node].get_input("mesh")?;
+    editor_state.graph.add_connection(output_id, input_id);
+    Ok(edit_node)
+}
@@ -0,0 +1,160 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::graph::serialization::BjkMetadata;
+
+use crate::prelude::*;
+
+/// The size of the thumbnails in the grid, in points.
+pub const THUMBNAIL_SIZE: f32 = 128.0;
+
+struct FileEntry {
+    path: PathBuf,
+    name: String,
+    /// The thumbnail stored in the file, if it has one.
+    thumbnail: Option<egui::TextureHandle>,
+}
+
+/// A window listing the `.bjk` files in a folder as a grid of thumbnails.
+/// Only the metadata header of each file is read, so folders with many files
+/// open quickly.
+#[derive(Default)]
+pub struct FileBrowser {
+    pub open: bool,
+    folder: Option<PathBuf>,
+    entries: Vec<FileEntry>,
+}
+
+impl FileBrowser {
+    /// Opens the browser, showing the files in `folder` unless a folder was
+    /// already chosen.
+    pub fn open_at(&mut self, ctx: &egui::Context, folder: &Path) {
+        self.open = true;
+        if self.folder.is_none() {
+            self.set_folder(ctx, folder.to_owned());
+        }
+    }
+
+    fn set_folder(&mut self, ctx: &egui::Context, folder: PathBuf) {
+        let mut paths = std::fs::read_dir(&folder)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| path.extension().map(|ext| ext == "bjk").unwrap_or(false))
+                    .collect_vec()
+            })
+            .unwrap_or_else(|err| {
+                println!("Could not read folder {}: {err}", folder.display());
+                vec![]
+            });
+        paths.sort();
+
+        self.entries = paths
+            .into_iter()
+            .map(|path| {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let thumbnail = load_thumbnail(ctx, &path).unwrap_or_else(|err| {
+                    println!("Could not load thumbnail of {}: {err}", path.display());
+                    None
+                });
+                FileEntry {
+                    path,
+                    name,
+                    thumbnail,
+                }
+            })
+            .collect();
+        self.folder = Some(folder);
+    }
+
+    /// Draws the browser, and returns the path of the file to open when the
+    /// user picks one.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<PathBuf> {
+        let mut picked = None;
+        let mut new_folder = None;
+        let mut open = self.open;
+        egui::Window::new("Open")
+            .open(&mut open)
+            .default_size(egui::vec2(640.0, 480.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Choose folder…").clicked() {
+                        let mut dialog = rfd::FileDialog::new();
+                        if let Some(folder) = &self.folder {
+                            dialog = dialog.set_directory(folder);
+                        }
+                        new_folder = dialog.pick_folder();
+                    }
+                    if ui.button("Refresh").clicked() {
+                        new_folder = self.folder.clone();
+                    }
+                    if ui.button("Open file…").clicked() {
+                        picked = rfd::FileDialog::new()
+                            .add_filter("Blackjack Model", &["bjk"])
+                            .pick_file();
+                    }
+                    if let Some(folder) = &self.folder {
+                        ui.label(folder.display().to_string());
+                    }
+                });
+                ui.separator();
+
+                if self.entries.is_empty() {
+                    ui.label("There are no .bjk files in this folder");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for entry in &self.entries {
+                            ui.vertical(|ui| {
+                                ui.set_width(THUMBNAIL_SIZE);
+                                let size = egui::vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+                                let response = match &entry.thumbnail {
+                                    Some(texture) => {
+                                        ui.add(egui::ImageButton::new(texture.id(), size))
+                                    }
+                                    None => ui.add_sized(size, egui::Button::new("No preview")),
+                                };
+                                if response
+                                    .on_hover_text(entry.path.display().to_string())
+                                    .clicked()
+                                {
+                                    picked = Some(entry.path.clone());
+                                }
+                                ui.label(entry.name.as_str());
+                            });
+                        }
+                    });
+                });
+            });
+
+        if let Some(folder) = new_folder {
+            self.set_folder(ctx, folder);
+        }
+        self.open = open && picked.is_none();
+        picked
+    }
+}
+
+/// Reads the thumbnail in the metadata of the file at `path`.
+fn load_thumbnail(ctx: &egui::Context, path: &Path) -> Result<Option<egui::TextureHandle>> {
+    match BjkMetadata::load_from_file(path)?.thumbnail_png {
+        Some(png) => Ok(Some(png_texture(ctx, path.display().to_string(), &png)?)),
+        None => Ok(None),
+    }
+}
+
+/// Loads a thumbnail, stored as a PNG image, into a texture called `name`.
+pub fn png_texture(ctx: &egui::Context, name: String, png: &[u8]) -> Result<egui::TextureHandle> {
+    let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)?.to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+    Ok(ctx.load_texture(name, image, egui::TextureFilter::Linear))
+}
@@ -0,0 +1,339 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::lua_engine::lua_stdlib::lua_path::ProjectContext;
+
+use super::root_ui::AppRootAction;
+use super::serialization;
+use crate::prelude::graph::{CustomGraphState, GraphEditorState};
+use crate::prelude::*;
+
+/// Returns the folder file dialogs start at: the folder of the last file
+/// picked in a dialog, or else the folder of the open graph.
+pub fn dialog_directory(last_used: Option<&Path>, graph_file: Option<&Path>) -> Option<PathBuf> {
+    last_used
+        .map(|dir| dir.to_owned())
+        .or_else(|| graph_file?.parent().map(|dir| dir.to_owned()))
+        .filter(|dir| !dir.as_os_str().is_empty())
+}
+
+/// Returns a native file dialog, starting at the [`dialog_directory`] of the
+/// graph.
+pub fn file_dialog(custom_state: &CustomGraphState) -> rfd::FileDialog {
+    let dialog = rfd::FileDialog::new();
+    match dialog_directory(
+        custom_state.last_dialog_dir.as_deref(),
+        custom_state.file_path.as_deref(),
+    ) {
+        Some(dir) => dialog.set_directory(dir),
+        None => dialog,
+    }
+}
+
+/// Remembers the folder of a file picked in a dialog, so the next dialog
+/// starts there.
+pub fn remember_dialog_dir(custom_state: &mut CustomGraphState, picked: &Path) {
+    if let Some(dir) = picked.parent() {
+        custom_state.last_dialog_dir = Some(dir.to_owned());
+    }
+}
+
+/// Returns how a file path parameter stores `path`. Files inside the folder
+/// of the graph are stored relative to it, as they would be when saving.
+pub fn path_param_value(custom_state: &CustomGraphState, path: PathBuf) -> String {
+    let project = ProjectContext::for_graph_file(custom_state.file_path.as_deref());
+    let path = path
+        .into_os_string()
+        .into_string()
+        .unwrap_or_else(|err| format!("INVALID PATH: {err:?}"));
+    match project.make_relative(&path) {
+        Some(relative) if project.contains(&path) => relative,
+        _ => path,
+    }
+}
+
+/// Returns a snapshot of everything saved in the graph file, to tell whether
+/// the graph changed since it was saved. Empty graphs have no snapshot.
+pub fn graph_snapshot(
+    editor_state: &GraphEditorState,
+    custom_state: &CustomGraphState,
+) -> Option<String> {
+    let nodes = editor_state.graph.nodes.keys().collect_vec();
+    if nodes.is_empty() {
+        return None;
+    }
+    let snippet = serialization::to_clipboard(editor_state, custom_state, &nodes)
+        .unwrap_or_else(|err| format!("Invalid graph: {err}"));
+    Some(format!(
+        "{snippet}\n{}\n{}\n{:?}",
+        custom_state.graph_seed, custom_state.units, custom_state.materials
+    ))
+}
+
+/// What to do with the unsaved changes of the graph before opening another
+/// one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnsavedChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+/// Keeps the user from losing unsaved changes when opening another graph, or
+/// when closing the tab of the graph. Opening a file while the graph differs
+/// from the last saved or loaded snapshot leaves it pending, until the user
+/// chooses what to do. Closing the tab works the same.
+#[derive(Default)]
+pub struct UnsavedChangesGuard {
+    /// The graph as it was last saved or loaded. `None` for an empty graph.
+    saved: Option<String>,
+    /// The file waiting for the user to choose what to do with the changes.
+    pub pending_open: Option<PathBuf>,
+    /// Set while closing the tab waits for the user to choose what to do
+    /// with the changes.
+    pub pending_close: bool,
+    /// The file to open once the graph is saved.
+    open_after_save: Option<PathBuf>,
+    /// Whether to close the tab once the graph is saved.
+    close_after_save: bool,
+}
+
+impl UnsavedChangesGuard {
+    /// Records the `snapshot` of the graph that was just saved or loaded.
+    pub fn mark_saved(&mut self, snapshot: Option<String>) {
+        self.saved = snapshot;
+    }
+
+    pub fn has_unsaved_changes(&self, current: &Option<String>) -> bool {
+        self.saved != *current
+    }
+
+    /// Returns whether the user has yet to choose what to do with the
+    /// changes.
+    pub fn is_pending(&self) -> bool {
+        self.pending_open.is_some() || self.pending_close
+    }
+
+    /// Asks to open `path`, with the graph being at `current`. Returns the
+    /// path when it can be opened right away, otherwise it's left pending.
+    pub fn request_open(&mut self, path: PathBuf, current: Option<String>) -> Option<PathBuf> {
+        if self.has_unsaved_changes(&current) {
+            self.pending_open = Some(path);
+            None
+        } else {
+            Some(path)
+        }
+    }
+
+    /// Asks to close the tab, with the graph being at `current`. Returns
+    /// whether it can be closed right away, otherwise it's left pending.
+    pub fn request_close(&mut self, current: Option<String>) -> bool {
+        self.pending_close = self.has_unsaved_changes(&current);
+        !self.pending_close
+    }
+
+    /// Applies the user's `choice` for the pending file, or for closing the
+    /// tab. Saving asks for the path to save to with `save_path`, and the
+    /// file is opened or the tab closed after the save is done, see
+    /// [`Self::on_saved`]. When no path is given, the choice stays pending.
+    pub fn resolve(
+        &mut self,
+        choice: UnsavedChoice,
+        save_path: impl FnOnce() -> Option<PathBuf>,
+    ) -> Option<AppRootAction> {
+        match choice {
+            UnsavedChoice::Cancel => {
+                self.pending_open = None;
+                self.pending_close = false;
+                None
+            }
+            UnsavedChoice::Discard => {
+                if std::mem::take(&mut self.pending_close) {
+                    Some(AppRootAction::CloseTab)
+                } else {
+                    self.pending_open.take().map(AppRootAction::Load)
+                }
+            }
+            UnsavedChoice::Save => {
+                if !self.is_pending() {
+                    return None;
+                }
+                let save_to = save_path()?;
+                self.open_after_save = self.pending_open.take();
+                self.close_after_save = std::mem::take(&mut self.pending_close);
+                Some(AppRootAction::Save(save_to))
+            }
+        }
+    }
+
+    /// Records that the graph was saved as `snapshot`, and returns the file
+    /// that was waiting for it, if any.
+    pub fn on_saved(&mut self, snapshot: Option<String>) -> Option<PathBuf> {
+        self.mark_saved(snapshot);
+        self.open_after_save.take()
+    }
+
+    /// Returns whether the tab was waiting for the graph to be saved to
+    /// close, after [`Self::on_saved`].
+    pub fn take_close_after_save(&mut self) -> bool {
+        std::mem::take(&mut self.close_after_save)
+    }
+
+    /// Forgets the file waiting for a save that failed, so the changes are
+    /// not lost by opening it anyway. The tab stays open too.
+    pub fn on_save_failed(&mut self) {
+        self.open_after_save = None;
+        self.close_after_save = false;
+    }
+}
+
+/// Draws the prompt for a file pending in the `guard`, or for closing the
+/// tab, and returns the choice made this frame, if any.
+pub fn unsaved_changes_prompt(
+    ctx: &egui::Context,
+    guard: &UnsavedChangesGuard,
+) -> Option<UnsavedChoice> {
+    let question = match &guard.pending_open {
+        Some(pending) => format!("Save them before opening {}?", pending.display()),
+        None if guard.pending_close => "Save them before closing the tab?".to_string(),
+        None => return None,
+    };
+    let mut choice = None;
+    egui::Window::new("Unsaved changes")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.label(format!("The graph has unsaved changes. {question}"));
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    choice = Some(UnsavedChoice::Save);
+                }
+                if ui.button("Discard").clicked() {
+                    choice = Some(UnsavedChoice::Discard);
+                }
+                if ui.button("Cancel").clicked() {
+                    choice = Some(UnsavedChoice::Cancel);
+                }
+            });
+        });
+    choice
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot(s: &str) -> Option<String> {
+        Some(s.into())
+    }
+
+    #[test]
+    fn test_dialog_directory() {
+        let last = Path::new("/home/me/textures");
+        let graph = Path::new("/home/me/jacks/sword.bjk");
+        assert_eq!(
+            dialog_directory(Some(last), Some(graph)),
+            Some(last.to_owned())
+        );
+        assert_eq!(
+            dialog_directory(None, Some(graph)),
+            Some(PathBuf::from("/home/me/jacks"))
+        );
+        assert_eq!(dialog_directory(None, None), None);
+        // Graphs saved to the working directory have no folder to start at
+        assert_eq!(dialog_directory(None, Some(Path::new("sword.bjk"))), None);
+    }
+
+    #[test]
+    fn test_open_without_changes() {
+        let mut guard = UnsavedChangesGuard::default();
+        // A new, empty graph has nothing to lose
+        assert_eq!(
+            guard.request_open("a.bjk".into(), None),
+            Some(PathBuf::from("a.bjk"))
+        );
+        guard.mark_saved(snapshot("a"));
+        assert_eq!(
+            guard.request_open("b.bjk".into(), snapshot("a")),
+            Some(PathBuf::from("b.bjk"))
+        );
+        assert!(guard.pending_open.is_none());
+    }
+
+    #[test]
+    fn test_discard_and_cancel() {
+        let mut guard = UnsavedChangesGuard::default();
+        guard.mark_saved(snapshot("a"));
+        assert_eq!(guard.request_open("b.bjk".into(), snapshot("a2")), None);
+        assert_eq!(guard.pending_open, Some(PathBuf::from("b.bjk")));
+
+        assert!(guard.resolve(UnsavedChoice::Cancel, || None).is_none());
+        assert!(guard.pending_open.is_none());
+
+        guard.request_open("b.bjk".into(), snapshot("a2"));
+        let action = guard.resolve(UnsavedChoice::Discard, || panic!("Should not save"));
+        assert!(matches!(action, Some(AppRootAction::Load(path)) if path == Path::new("b.bjk")));
+        assert!(guard.pending_open.is_none());
+    }
+
+    #[test]
+    fn test_save_then_open() {
+        let mut guard = UnsavedChangesGuard::default();
+        guard.request_open("b.bjk".into(), snapshot("new"));
+
+        // Cancelling the save dialog keeps asking
+        assert!(guard.resolve(UnsavedChoice::Save, || None).is_none());
+        assert!(guard.pending_open.is_some());
+
+        let action = guard.resolve(UnsavedChoice::Save, || Some("a.bjk".into()));
+        assert!(matches!(action, Some(AppRootAction::Save(path)) if path == Path::new("a.bjk")));
+        assert!(guard.pending_open.is_none());
+        assert_eq!(
+            guard.on_saved(snapshot("new")),
+            Some(PathBuf::from("b.bjk"))
+        );
+        assert!(!guard.has_unsaved_changes(&snapshot("new")));
+        // Later saves don't open the file again
+        assert_eq!(guard.on_saved(snapshot("new")), None);
+
+        // Nothing is opened after a failed save
+        guard.request_open("c.bjk".into(), snapshot("newer"));
+        guard.resolve(UnsavedChoice::Save, || Some("a.bjk".into()));
+        guard.on_save_failed();
+        assert_eq!(guard.on_saved(snapshot("newer")), None);
+    }
+
+    #[test]
+    fn test_close_tab() {
+        let mut guard = UnsavedChangesGuard::default();
+        guard.mark_saved(snapshot("a"));
+        assert!(guard.request_close(snapshot("a")));
+        assert!(!guard.is_pending());
+
+        assert!(!guard.request_close(snapshot("a2")));
+        assert!(guard.is_pending());
+        let action = guard.resolve(UnsavedChoice::Discard, || panic!("Should not save"));
+        assert!(matches!(action, Some(AppRootAction::CloseTab)));
+        assert!(!guard.is_pending());
+
+        // The tab closes once saved, but not after a failed save
+        guard.request_close(snapshot("a2"));
+        let action = guard.resolve(UnsavedChoice::Save, || Some("a.bjk".into()));
+        assert!(matches!(action, Some(AppRootAction::Save(_))));
+        assert_eq!(guard.on_saved(snapshot("a2")), None);
+        assert!(guard.take_close_after_save());
+        assert!(!guard.take_close_after_save());
+
+        guard.request_close(snapshot("a3"));
+        guard.resolve(UnsavedChoice::Save, || Some("a.bjk".into()));
+        guard.on_save_failed();
+        guard.on_saved(snapshot("a3"));
+        assert!(!guard.take_close_after_save());
+    }
+}
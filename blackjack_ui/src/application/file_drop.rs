@@ -0,0 +1,200 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::graph::BlackjackValue;
+use egui_node_graph::{NodeId, NodeTemplateTrait};
+
+use super::file_dialogs::path_param_value;
+use super::graph_editor::estimated_node_size;
+use crate::prelude::graph::{CustomGraphState, GraphEditorState, NodeOpName, ValueTypeUi};
+use crate::prelude::*;
+
+/// A node that imports files, and the parameter it takes their path in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileImporter {
+    pub op_name: String,
+    pub path_param: String,
+}
+
+/// The nodes spawned for files dropped on the window, by the extension of
+/// the files. Extensions are matched regardless of their case.
+pub struct ImporterRegistry {
+    importers: HashMap<String, FileImporter>,
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            importers: HashMap::default(),
+        };
+        registry.register("obj", "ImportObj", "path");
+        registry.register("ply", "ImportPly", "path");
+        registry.register("png", "HeightmapImport", "path");
+        registry
+    }
+}
+
+impl ImporterRegistry {
+    /// Makes files ending in `extension` spawn an `op_name` node, with their
+    /// path in the `path_param` parameter. Replaces the previous importer for
+    /// the extension, if any.
+    pub fn register(&mut self, extension: &str, op_name: &str, path_param: &str) {
+        self.importers.insert(
+            extension.to_lowercase(),
+            FileImporter {
+                op_name: op_name.into(),
+                path_param: path_param.into(),
+            },
+        );
+    }
+
+    /// Returns the importer for the file at `path`, if there is one for its
+    /// extension.
+    pub fn importer_for(&self, path: &Path) -> Option<&FileImporter> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.importers.get(&extension)
+    }
+}
+
+/// Returns whether the file at `path` is a graph, to be opened instead of
+/// imported.
+pub fn is_graph_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("bjk"))
+        .unwrap_or(false)
+}
+
+/// Returns the positions of nodes with the given `heights`, placed in a
+/// column from `top`, with `spacing` between them.
+pub fn column_positions(top: egui::Pos2, heights: &[f32], spacing: f32) -> Vec<egui::Pos2> {
+    let mut y = top.y;
+    heights
+        .iter()
+        .map(|height| {
+            let pos = egui::pos2(top.x, y);
+            y += height + spacing;
+            pos
+        })
+        .collect()
+}
+
+/// Adds an import node for each of the `paths` there is an importer for,
+/// with the path filled in. The nodes are laid out in a column from `pos`,
+/// in graph space, and become selected. Returns the paths that couldn't be
+/// imported.
+pub fn spawn_import_nodes(
+    editor_state: &mut GraphEditorState,
+    custom_state: &mut CustomGraphState,
+    registry: &ImporterRegistry,
+    paths: &[PathBuf],
+    pos: egui::Pos2,
+    spacing: f32,
+) -> Vec<PathBuf> {
+    let mut skipped = vec![];
+    let mut spawned: Vec<NodeId> = vec![];
+    for path in paths {
+        let importer = match registry.importer_for(path).filter(|imp| {
+            custom_state
+                .node_definitions
+                .node_def(&imp.op_name)
+                .is_some()
+        }) {
+            Some(importer) => importer,
+            None => {
+                skipped.push(path.clone());
+                continue;
+            }
+        };
+        let template = NodeOpName(importer.op_name.clone());
+        let label = template.node_graph_label(custom_state);
+        let user_data = template.user_data(custom_state);
+        let node = editor_state
+            .graph
+            .add_node(label, user_data, |graph, node_id| {
+                template.build_node(graph, custom_state, node_id)
+            });
+        match editor_state.graph[node].get_input(&importer.path_param) {
+            Ok(input) => {
+                let value = path_param_value(custom_state, path.clone());
+                editor_state.graph[input].value = ValueTypeUi(BlackjackValue::String(value));
+            }
+            Err(err) => println!(
+                "Error: The {} node has no '{}' parameter: {err}",
+                importer.op_name, importer.path_param
+            ),
+        }
+        editor_state.node_order.push(node);
+        spawned.push(node);
+    }
+
+    let heights = spawned
+        .iter()
+        .map(|node| estimated_node_size(&editor_state.graph[*node]).y)
+        .collect_vec();
+    for (node, pos) in spawned.iter().zip(column_positions(pos, &heights, spacing)) {
+        editor_state.node_positions.insert(*node, pos);
+    }
+    if !spawned.is_empty() {
+        editor_state.selected_nodes = spawned;
+    }
+    skipped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_importer_registry() {
+        let mut registry = ImporterRegistry::default();
+        let op_name = |path: &str| {
+            registry
+                .importer_for(Path::new(path))
+                .map(|imp| imp.op_name.clone())
+        };
+        assert_eq!(op_name("/models/sword.obj").as_deref(), Some("ImportObj"));
+        assert_eq!(op_name("scan.PLY").as_deref(), Some("ImportPly"));
+        assert_eq!(op_name("terrain.png").as_deref(), Some("HeightmapImport"));
+        assert_eq!(op_name("notes.txt"), None);
+        assert_eq!(op_name("no_extension"), None);
+
+        registry.register("STL", "ImportStl", "file");
+        let importer = registry.importer_for(Path::new("part.stl")).unwrap();
+        assert_eq!(importer.op_name, "ImportStl");
+        assert_eq!(importer.path_param, "file");
+        // Registering again replaces the importer
+        registry.register("obj", "ImportObjWithMaterials", "path");
+        assert_eq!(
+            registry.importer_for(Path::new("a.obj")).unwrap().op_name,
+            "ImportObjWithMaterials"
+        );
+    }
+
+    #[test]
+    fn test_graph_files() {
+        assert!(is_graph_file(Path::new("/jacks/sword.bjk")));
+        assert!(is_graph_file(Path::new("SWORD.BJK")));
+        assert!(!is_graph_file(Path::new("sword.obj")));
+        assert!(!is_graph_file(Path::new("bjk")));
+    }
+
+    #[test]
+    fn test_column_positions() {
+        let positions = column_positions(egui::pos2(10.0, 20.0), &[100.0, 50.0, 80.0], 5.0);
+        assert_eq!(
+            positions,
+            vec![
+                egui::pos2(10.0, 20.0),
+                egui::pos2(10.0, 125.0),
+                egui::pos2(10.0, 180.0),
+            ]
+        );
+        assert!(column_positions(egui::Pos2::ZERO, &[], 5.0).is_empty());
+    }
+}
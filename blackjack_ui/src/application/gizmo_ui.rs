@@ -9,7 +9,7 @@ use std::{cell::RefCell, hash::Hash, rc::Rc};
 use anyhow::Result;
 use blackjack_commons::utils::OptionExt;
 use blackjack_engine::{
-    gizmos::{BlackjackGizmo, TransformGizmoMode},
+    gizmos::{BlackjackGizmo, HandleGizmo, TransformGizmoMode},
     graph::BjkNodeId,
     graph_interpreter::GizmoState,
 };
@@ -18,7 +18,10 @@ use egui_node_graph::{Node, NodeId};
 use glam::Mat4;
 use slotmap::SecondaryMap;
 
-use crate::{graph::graph_interop::NodeMapping, prelude::graph::NodeData};
+use crate::{
+    app_window::gui_overlay::project_point, graph::graph_interop::NodeMapping,
+    prelude::graph::NodeData,
+};
 
 use super::viewport_3d::Viewport3d;
 
@@ -96,24 +99,12 @@ pub fn draw_gizmo_ui_viewport(
                 });
             }
 
-            let mut visuals = GizmoVisuals::default();
-            visuals.gizmo_size *= 0.8;
-            if !has_focus {
-                visuals.gizmo_size *= 0.8;
-                visuals.stroke_width *= 0.8;
-                visuals.inactive_alpha *= 0.6;
-                visuals.highlight_alpha *= 0.6;
-            } else {
-                visuals.inactive_alpha *= 1.2;
-                visuals.highlight_alpha *= 1.2;
-            }
-
             let gizmo = egui_gizmo::Gizmo::new(unique_id)
                 .view_matrix(viewport.view_matrix().to_cols_array_2d())
                 .projection_matrix(viewport.projection_matrix().to_cols_array_2d())
                 .model_matrix(transform_gizmo.matrix().to_cols_array_2d())
                 .viewport(viewport.viewport_rect())
-                .visuals(visuals)
+                .visuals(gizmo_visuals(has_focus))
                 .mode(match transform_gizmo.gizmo_mode {
                     TransformGizmoMode::Translate => egui_gizmo::GizmoMode::Translate,
                     TransformGizmoMode::Rotate => egui_gizmo::GizmoMode::Rotate,
@@ -126,12 +117,68 @@ pub fn draw_gizmo_ui_viewport(
                 transform_gizmo.set_from_matrix(updated_matrix);
             }
         }
+        BlackjackGizmo::Handles(handles) => {
+            if has_focus {
+                ui.allocate_ui_at_rect(viewport.viewport_rect().shrink(10.0), gizmo_label);
+            }
+
+            let view_proj = viewport.projection_matrix() * viewport.view_matrix();
+            let viewport_rect = viewport.viewport_rect();
+            let painter = ui.painter_at(viewport_rect);
+            for (i, handle) in handles.iter_mut().enumerate() {
+                let position = handle.handle_position();
+                // Arrows and sliders show the line they move along
+                if let HandleGizmo::Arrow { origin, .. } | HandleGizmo::Slider { origin, .. } =
+                    handle
+                {
+                    painter.line_segment(
+                        [
+                            project_point(&view_proj, viewport_rect, *origin),
+                            project_point(&view_proj, viewport_rect, position),
+                        ],
+                        egui::Stroke::new(2.0, egui::Color32::GOLD),
+                    );
+                }
+
+                let gizmo = egui_gizmo::Gizmo::new((&unique_id, i))
+                    .view_matrix(viewport.view_matrix().to_cols_array_2d())
+                    .projection_matrix(viewport.projection_matrix().to_cols_array_2d())
+                    .model_matrix(Mat4::from_translation(position).to_cols_array_2d())
+                    .viewport(viewport_rect)
+                    .visuals(gizmo_visuals(has_focus))
+                    .mode(egui_gizmo::GizmoMode::Translate);
+                if let Some(response) = gizmo.interact(ui) {
+                    responses.push(GizmoViewportResponse::CaptureMouse);
+                    responses.push(GizmoViewportResponse::GizmoIsInteracted);
+                    let new_position = Mat4::from_cols_array_2d(&response.transform)
+                        .w_axis
+                        .truncate();
+                    handle.drag_to(new_position);
+                }
+            }
+        }
         BlackjackGizmo::None => {}
     }
 
     Ok(responses)
 }
 
+/// Gizmos of the focused node are drawn larger and more opaque than the rest.
+fn gizmo_visuals(has_focus: bool) -> GizmoVisuals {
+    let mut visuals = GizmoVisuals::default();
+    visuals.gizmo_size *= 0.8;
+    if !has_focus {
+        visuals.gizmo_size *= 0.8;
+        visuals.stroke_width *= 0.8;
+        visuals.inactive_alpha *= 0.6;
+        visuals.highlight_alpha *= 0.6;
+    } else {
+        visuals.inactive_alpha *= 1.2;
+        visuals.highlight_alpha *= 1.2;
+    }
+    visuals
+}
+
 impl UiNodeGizmoStates {
     pub fn init() -> Self {
         Self {
@@ -145,6 +192,18 @@ impl UiNodeGizmoStates {
         }
     }
 
+    /// Exchanges the gizmo states with the ones in `other`, for all the
+    /// places sharing each. Used when the editor shows the graph of another
+    /// tab, whose node ids mean other nodes.
+    pub fn swap(&self, other: &UiNodeGizmoStates) {
+        if !Rc::ptr_eq(&self.inner, &other.inner) {
+            std::mem::swap(
+                &mut *self.inner.borrow_mut(),
+                &mut *other.inner.borrow_mut(),
+            );
+        }
+    }
+
     /// Returns a map suitable to be sent to blackjack_engine's run_node function
     pub fn to_bjk_data(&self, mapping: &NodeMapping) -> SecondaryMap<BjkNodeId, GizmoState> {
         let mut result = SecondaryMap::new();
@@ -6,6 +6,11 @@
 
 use crate::{
     app_window::input::viewport_relative_position,
+    graph::{
+        connections::{ConnectionSplice, WireEditorState},
+        graph_layout::{self, LayoutSettings},
+        parameter_edits::{ParameterEdit, ParameterEditMode},
+    },
     prelude::{
         graph::{data_type_to_input_param_kind, default_shown_inline, DataTypeUi, ValueTypeUi},
         *,
@@ -14,9 +19,10 @@ use crate::{
 use blackjack_engine::graph::{
     serialization::SerializedBjkSnippet, BlackjackValue, DataType, NodeDefinitions,
 };
+use egui_node_graph::NodeId;
 use egui_wgpu::renderer::{RenderPass, ScreenDescriptor};
 
-use super::{blackjack_theme, gizmo_ui::UiNodeGizmoStates};
+use super::{blackjack_theme, file_drop::ImporterRegistry, gizmo_ui::UiNodeGizmoStates};
 
 pub struct GraphEditor {
     pub editor_state: graph::GraphEditorState,
@@ -38,6 +44,46 @@ pub struct GraphEditor {
     pub pending_paste_operation: Option<SerializedBjkSnippet>,
     /// Allows ignoring the potentially unsafe paste confirmation dialog.
     pub skip_pending_paste_check: bool,
+    /// The spacing used when laying out the graph automatically.
+    pub layout_settings: LayoutSettings,
+    /// Where the nodes moved by the last automatic layout are going. Nodes
+    /// are animated towards these positions over a few frames.
+    pub layout_targets: HashMap<NodeId, egui::Pos2>,
+    /// The wires that are hovered or selected, and the wire being edited.
+    pub wires: WireEditorState,
+    /// How edits of a parameter apply to the other selected nodes.
+    pub parameter_edit_mode: ParameterEditMode,
+    /// The last edit, which can be undone with Ctrl+Z.
+    pub last_edit: Option<UndoableEdit>,
+    /// The nodes spawned for the files dropped on the graph.
+    pub importers: ImporterRegistry,
+}
+
+/// The edits of the graph that can be undone. Only the last one is kept.
+pub enum UndoableEdit {
+    Splice(ConnectionSplice),
+    Parameters(ParameterEdit),
+}
+
+impl UndoableEdit {
+    pub fn undo(&self, graph: &mut graph::Graph) {
+        match self {
+            UndoableEdit::Splice(splice) => splice.undo(graph),
+            UndoableEdit::Parameters(edit) => edit.undo(graph),
+        }
+    }
+
+    /// Stores an edit of some parameters as the last edit. Consecutive edits
+    /// of the same parameters, like the ones of every frame while dragging a
+    /// value, are undone at once.
+    pub fn record_parameter_edit(last_edit: &mut Option<UndoableEdit>, edit: ParameterEdit) {
+        if let Some(UndoableEdit::Parameters(last)) = last_edit {
+            if last.same_parameters(&edit) {
+                return;
+            }
+        }
+        *last_edit = Some(UndoableEdit::Parameters(edit));
+    }
 }
 
 pub fn blackjack_graph_theme() -> egui::Visuals {
@@ -79,9 +125,21 @@ impl GraphEditor {
             previous_clipboard_contents: String::new(),
             pending_paste_operation: None,
             skip_pending_paste_check: false,
+            layout_settings: LayoutSettings::default(),
+            layout_targets: HashMap::new(),
+            wires: WireEditorState::default(),
+            parameter_edit_mode: ParameterEditMode::default(),
+            last_edit: None,
+            importers: ImporterRegistry::default(),
         }
     }
 
+    /// Lays out the selected nodes, or all of them if none is selected. See
+    /// [`layout_targets`].
+    pub fn layout_nodes(&mut self) {
+        self.layout_targets = layout_targets(&self.editor_state, &self.layout_settings);
+    }
+
     pub fn zoom_level(&self) -> f32 {
         self.editor_state.pan_zoom.zoom
     }
@@ -534,3 +592,81 @@ impl GraphEditor {
         Ok(())
     }
 }
+
+/// Computes a layered layout for the selected nodes of the graph, or for all
+/// of them if none is selected. Returns the new position of each node. The
+/// layout keeps the top left corner of the nodes where it was.
+pub fn layout_targets(
+    editor_state: &graph::GraphEditorState,
+    settings: &LayoutSettings,
+) -> HashMap<NodeId, egui::Pos2> {
+    let graph = &editor_state.graph;
+    let position = |node_id: NodeId| {
+        editor_state
+            .node_positions
+            .get(node_id)
+            .copied()
+            .unwrap_or(egui::Pos2::ZERO)
+    };
+    let nodes = if editor_state.selected_nodes.is_empty() {
+        graph.nodes.keys().collect_vec()
+    } else {
+        editor_state.selected_nodes.clone()
+    };
+    // Nodes are sorted by their current position, so that the layout keeps
+    // their order when there's no reason to change it.
+    let nodes = nodes
+        .into_iter()
+        .sorted_by(|a, b| {
+            let (a, b) = (position(*a), position(*b));
+            a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+        })
+        .collect_vec();
+    if nodes.is_empty() {
+        return HashMap::new();
+    }
+    let index: HashMap<NodeId, usize> = nodes.iter_cpy().enumerate().map(|(i, n)| (n, i)).collect();
+
+    let sizes = nodes
+        .iter()
+        .map(|node_id| estimated_node_size(&graph[*node_id]))
+        .collect_vec();
+    let edges = nodes
+        .iter()
+        .flat_map(|node_id| {
+            graph[*node_id].inputs.iter().filter_map(|(_, input_id)| {
+                let src = graph.get_output(graph.connection(*input_id)?).node;
+                Some((*index.get(&src)?, index[node_id]))
+            })
+        })
+        .unique()
+        .collect_vec();
+
+    let layout = graph_layout::layered_layout(&sizes, &edges, settings);
+    let origin = nodes
+        .iter()
+        .map(|node_id| position(*node_id))
+        .reduce(|a, b| a.min(b))
+        .unwrap_or(egui::Pos2::ZERO);
+    nodes
+        .into_iter()
+        .zip(layout)
+        .map(|(node_id, pos)| (node_id, origin + egui::vec2(pos.x, pos.y)))
+        .collect()
+}
+
+/// The estimated width of a node, and the height of its title and of each
+/// parameter. See [`estimated_node_size`].
+pub const NODE_WIDTH: f32 = 200.0;
+pub const NODE_HEADER_HEIGHT: f32 = 70.0;
+pub const NODE_PARAM_HEIGHT: f32 = 30.0;
+
+/// egui_node_graph doesn't tell the size of the nodes it draws, so it is
+/// estimated from their number of parameters.
+pub fn estimated_node_size(node: &graph::Node<graph::NodeData>) -> Vec2 {
+    let num_params = node.inputs.len() + node.outputs.len();
+    Vec2::new(
+        NODE_WIDTH,
+        NODE_HEADER_HEIGHT + NODE_PARAM_HEIGHT * num_params as f32,
+    )
+}
@@ -4,13 +4,21 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::application::graph_editor::UndoableEdit;
+use crate::application::uv_view::{self, UvView};
+use crate::graph::parameter_edits::{self, ParameterEditMode};
 use crate::prelude::{
     graph::{CustomGraphState, Graph},
     *,
 };
+use blackjack_engine::graph::BlackjackValue;
 use blackjack_engine::{
     lua_engine::RenderableThing,
-    prelude::{selection::SelectionExpression, ChannelKeyType, ChannelValueType, HalfEdgeMesh},
+    prelude::{
+        selection::SelectionExpression,
+        spreadsheet::{rows_to_tsv, SpreadsheetView},
+        ChannelKeyType, ChannelValueType, HalfEdgeMesh,
+    },
 };
 use egui::*;
 use egui_node_graph::{InputId, NodeId, WidgetValueTrait};
@@ -20,6 +28,7 @@ use slotmap::Key;
 pub enum InspectorTab {
     Properties,
     Spreadsheet,
+    Uvs,
     Debug,
 }
 
@@ -27,6 +36,7 @@ pub struct InspectorTabs {
     current_view: InspectorTab,
     properties: PropertiesTab,
     spreadsheet: SpreadsheetTab,
+    uvs: UvView,
     debug: DebugTab,
 }
 
@@ -37,9 +47,8 @@ impl InspectorTabs {
             properties: PropertiesTab {
                 new_promoted_popup: None,
             },
-            spreadsheet: SpreadsheetTab {
-                current_view: SpreadsheetViews::Vertices,
-            },
+            spreadsheet: SpreadsheetTab::default(),
+            uvs: UvView::default(),
             debug: DebugTab {
                 mesh_element: ChannelKeyType::VertexId,
                 v_query: "".into(),
@@ -72,8 +81,41 @@ pub enum SpreadsheetViews {
     Faces,
 }
 
+/// What a [`SpreadsheetView`] was built for, to know when it's outdated.
+#[derive(Clone, PartialEq)]
+struct SpreadsheetViewKey {
+    renderable_generation: u64,
+    view: SpreadsheetViews,
+    filter: String,
+    sort_column: Option<usize>,
+    sort_descending: bool,
+}
+
 pub struct SpreadsheetTab {
     pub current_view: SpreadsheetViews,
+    /// A selection expression, only the rows of the elements in it are shown.
+    pub filter: String,
+    /// The column rows are sorted by, or `None` to sort them by index.
+    pub sort_column: Option<usize>,
+    pub sort_descending: bool,
+    /// The rows for the current settings. Rebuilding the view reads every
+    /// element of the mesh, so it's only done when something changes.
+    cached_view: Option<(SpreadsheetViewKey, Result<SpreadsheetView, String>)>,
+    /// The rows that were on screen in the last frame, to copy them.
+    visible_rows: std::ops::Range<usize>,
+}
+
+impl Default for SpreadsheetTab {
+    fn default() -> Self {
+        Self {
+            current_view: SpreadsheetViews::Vertices,
+            filter: String::new(),
+            sort_column: None,
+            sort_descending: false,
+            cached_view: None,
+            visible_rows: 0..0,
+        }
+    }
 }
 
 pub struct DebugTab {
@@ -88,8 +130,11 @@ impl InspectorTabs {
         &mut self,
         ui: &mut Ui,
         renderable_thing: Option<&RenderableThing>,
+        renderable_generation: u64,
         editor_state: &mut graph::GraphEditorState,
         custom_state: &mut graph::CustomGraphState,
+        edit_mode: &mut ParameterEditMode,
+        last_edit: &mut Option<UndoableEdit>,
     ) {
         match renderable_thing {
             Some(RenderableThing::HalfEdgeMesh(mesh)) => {
@@ -104,19 +149,42 @@ impl InspectorTabs {
                         InspectorTab::Spreadsheet,
                         "Spreadsheet",
                     );
+                    ui.selectable_value(&mut self.current_view, InspectorTab::Uvs, "UVs");
                     ui.selectable_value(&mut self.current_view, InspectorTab::Debug, "Debug");
                 });
                 ui.separator();
 
                 match self.current_view {
-                    InspectorTab::Properties => self.properties.ui(ui, editor_state, custom_state),
-                    InspectorTab::Spreadsheet => self.spreadsheet.ui(ui, Some(mesh)),
+                    InspectorTab::Properties => {
+                        self.properties
+                            .ui(ui, editor_state, custom_state, edit_mode, last_edit)
+                    }
+                    InspectorTab::Spreadsheet => {
+                        self.spreadsheet.ui(ui, mesh, renderable_generation)
+                    }
+                    InspectorTab::Uvs => {
+                        let highlighted: HashSet<_> =
+                            uv_view::active_selection(&editor_state.graph, custom_state)
+                                .and_then(|selection| {
+                                    mesh.resolve_face_selection_full(&selection).ok()
+                                })
+                                .map(|faces| faces.into_iter().collect())
+                                .unwrap_or_default();
+                        self.uvs.ui(ui, mesh, &highlighted)
+                    }
                     InspectorTab::Debug => self.debug.ui(ui, Some(mesh)),
                 }
             }
             Some(RenderableThing::HeightMap(_)) => {
                 // TODO: @Heightmap
             }
+            Some(RenderableThing::Scene(scene)) => {
+                ui.label(format!("Scene with {} objects", scene.objects.len()));
+                ui.separator();
+                for object in &scene.objects {
+                    ui.label(&object.name);
+                }
+            }
             None => { /**/ }
         }
     }
@@ -169,38 +237,69 @@ impl PropertiesTab {
         ui: &mut Ui,
         editor_state: &mut graph::GraphEditorState,
         custom_state: &mut CustomGraphState,
+        edit_mode: &mut ParameterEditMode,
+        last_edit: &mut Option<UndoableEdit>,
     ) {
         self.maybe_show_new_promoted_modal(ui.ctx(), editor_state, custom_state);
 
         let graph = &mut editor_state.graph;
-        if !editor_state.selected_nodes.is_empty() {
+        let selected = &editor_state.selected_nodes;
+        if !selected.is_empty() {
+            // With several nodes selected, editing a parameter edits the one
+            // with the same name in all of them.
+            if selected.len() > 1 {
+                ui.horizontal(|ui| {
+                    ui.label("Edit all selected");
+                    ui.selectable_value(edit_mode, ParameterEditMode::Absolute, "Absolute")
+                        .on_hover_text("Set the edited parameter to the same value");
+                    ui.selectable_value(edit_mode, ParameterEditMode::Relative, "Relative")
+                        .on_hover_text("Change the edited parameter by the same amount");
+                });
+                ui.separator();
+            }
+            let mut edited = None;
             Frame::default()
                 .inner_margin(egui::vec2(5.0, 0.0))
                 .show(ui, |ui| {
                     ScrollArea::both().show(ui, |ui| {
-                        for node in editor_state.selected_nodes.iter_cpy().sorted() {
+                        for node in selected.iter_cpy().sorted() {
                             let mut ch_ui = ui.child_ui_with_id_source(
                                 ui.available_rect_before_wrap(),
                                 *ui.layout(),
                                 node,
                             );
-                            self.draw_params_for_node(&mut ch_ui, graph, custom_state, node);
+                            edited = edited.or(self.draw_params_for_node(
+                                &mut ch_ui,
+                                graph,
+                                custom_state,
+                                node,
+                            ));
                             ui.allocate_rect(ch_ui.min_rect(), Sense::hover());
                         }
                     });
                 });
+            if let Some((source, previous)) = edited {
+                if let Some(edit) = parameter_edits::fan_out_parameter_edit(
+                    graph, selected, source, &previous, *edit_mode,
+                ) {
+                    UndoableEdit::record_parameter_edit(last_edit, edit);
+                }
+            }
         } else {
             ui.label("No node selected.");
         }
     }
 
+    /// Draws the widgets of the parameters of a node. Returns the parameter
+    /// that was edited, if any, along with its previous value.
     fn draw_params_for_node(
         &mut self,
         ui: &mut egui::Ui,
         graph: &mut Graph,
         custom_state: &mut CustomGraphState,
         node_id: NodeId,
-    ) {
+    ) -> Option<(InputId, BlackjackValue)> {
+        let mut edited = None;
         let node = &graph[node_id];
         let inputs = node.inputs.clone();
         ui.label(format!("{} ({:?})", node.label, node.id.data()));
@@ -241,6 +340,7 @@ impl PropertiesTab {
                         }
 
                         let mut value = std::mem::take(&mut graph[param].value);
+                        let previous = value.0.clone();
                         value.value_widget(
                             &param_name,
                             node_id,
@@ -248,16 +348,83 @@ impl PropertiesTab {
                             custom_state,
                             &graph[node_id].user_data,
                         );
+                        if value.0 != previous {
+                            edited = Some((param, previous));
+                        }
                         graph[param].value = value;
                     });
                 }
             }
         });
         ui.separator();
+        edited
     }
 }
 impl SpreadsheetTab {
-    fn ui(&mut self, ui: &mut Ui, mesh: Option<&HalfEdgeMesh>) {
+    fn view_key(&self, renderable_generation: u64) -> SpreadsheetViewKey {
+        SpreadsheetViewKey {
+            renderable_generation,
+            view: self.current_view,
+            filter: self.filter.clone(),
+            sort_column: self.sort_column,
+            sort_descending: self.sort_descending,
+        }
+    }
+
+    fn build_view(&self, mesh: &HalfEdgeMesh) -> Result<SpreadsheetView> {
+        let kind = match self.current_view {
+            SpreadsheetViews::Vertices => ChannelKeyType::VertexId,
+            SpreadsheetViews::Halfedges => ChannelKeyType::HalfEdgeId,
+            SpreadsheetViews::Faces => ChannelKeyType::FaceId,
+        };
+        let mut view = SpreadsheetView::new(mesh, kind);
+        if !self.filter.trim().is_empty() {
+            view.filter(mesh, &SelectionExpression::parse(&self.filter)?)?;
+        }
+        match self.sort_column {
+            Some(column) => view.sort_by_column(mesh, column, self.sort_descending)?,
+            None => view.sort_by_index(self.sort_descending),
+        }
+        Ok(view)
+    }
+
+    /// Clicking a column header sorts by it, and clicking it again reverses
+    /// the order.
+    fn toggle_sort(&mut self, column: Option<usize>) {
+        if self.sort_column == column {
+            self.sort_descending = !self.sort_descending;
+        } else {
+            self.sort_column = column;
+            self.sort_descending = false;
+        }
+    }
+
+    fn header_button(&mut self, ui: &mut Ui, column: Option<usize>, name: &str, width: f32) {
+        let arrow = match (self.sort_column == column, self.sort_descending) {
+            (true, false) => " ⏶",
+            (true, true) => " ⏷",
+            (false, _) => "",
+        };
+        let text = RichText::new(format!("{name}{arrow}")).strong();
+        if ui
+            .add_sized([width, 18.0], Button::new(text).frame(false))
+            .on_hover_text("Click to sort")
+            .clicked()
+        {
+            self.toggle_sort(column);
+        }
+    }
+
+    fn column_width(value_type: ChannelValueType) -> f32 {
+        match value_type {
+            ChannelValueType::Vec3 => 180.0,
+            ChannelValueType::f32 => 80.0,
+            ChannelValueType::bool => 50.0,
+            ChannelValueType::Tag => 100.0,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui, mesh: &HalfEdgeMesh, renderable_generation: u64) {
         ui.horizontal(|ui| {
             ui.selectable_value(
                 &mut self.current_view,
@@ -270,67 +437,109 @@ impl SpreadsheetTab {
                 SpreadsheetViews::Halfedges,
                 "Half edges",
             );
+            ui.separator();
+            ui.label("Filter");
+            ui.add(TextEdit::singleline(&mut self.filter).desired_width(120.0))
+                .on_hover_text(
+                    "A selection expression, like '0..10, 42' or '@group'. \
+                     Only the selected elements are shown.",
+                );
         });
 
-        if let Some(mesh) = mesh {
-            let channel_introspect = mesh.channels.introspect(mesh.gen_introspect_fn());
-
-            let scroll_area = ScrollArea::both().auto_shrink([false, false]);
-            scroll_area.show(ui, |ui| {
-                let mut columns = vec![];
-                let kt = match self.current_view {
-                    SpreadsheetViews::Vertices => ChannelKeyType::VertexId,
-                    SpreadsheetViews::Halfedges => ChannelKeyType::HalfEdgeId,
-                    SpreadsheetViews::Faces => ChannelKeyType::FaceId,
-                };
-                for vt in [
-                    ChannelValueType::Vec3,
-                    ChannelValueType::f32,
-                    ChannelValueType::bool,
-                ] {
-                    if let Some(ch) = channel_introspect.get(&(kt, vt)) {
-                        for (ch_name, ch_contents) in ch.iter() {
-                            columns.push((ch_name, ch_contents));
-                        }
-                    }
+        if let Some((cached_key, _)) = &self.cached_view {
+            // Columns depend on the kind of element
+            if cached_key.view != self.current_view {
+                self.sort_column = None;
+            }
+        }
+        let key = self.view_key(renderable_generation);
+        if self.cached_view.as_ref().map(|(k, _)| k) != Some(&key) {
+            let view = self.build_view(mesh).map_err(|err| err.to_string());
+            self.cached_view = Some((key, view));
+        }
+        // Taken out while drawing, so the table can update the rest of the
+        // state without copying the view.
+        if let Some((key, view)) = self.cached_view.take() {
+            match &view {
+                Ok(view) => self.table_ui(ui, mesh, view),
+                Err(err) => {
+                    ui.label(RichText::new(err).color(Color32::RED));
                 }
+            }
+            self.cached_view = Some((key, view));
+        }
+    }
 
-                Grid::new("vertex-spreadsheet")
-                    .striped(true)
-                    .num_columns(columns.len())
-                    .show(ui, |ui| {
-                        ui.label(" ");
-                        for c in &columns {
-                            ui.label(c.0);
-                        }
-                        ui.end_row();
+    fn table_ui(&mut self, ui: &mut Ui, mesh: &HalfEdgeMesh, view: &SpreadsheetView) {
+        const INDEX_WIDTH: f32 = 60.0;
 
-                        if !columns.is_empty() {
-                            for i in 0..columns[0].1.len() {
-                                ui.label(i.to_string());
-                                for c in &columns {
-                                    ui.monospace(c.1[i].clone() + " |");
-                                }
-                                ui.end_row();
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} of {} rows",
+                view.num_rows(),
+                view.num_elements()
+            ));
+            if ui
+                .button("Copy visible rows")
+                .on_hover_text("Copies the rows on screen as tab separated values")
+                .clicked()
+            {
+                match view.rows(mesh, self.visible_rows.clone()) {
+                    Ok(rows) => ui.output().copied_text = rows_to_tsv(&view.columns, &rows),
+                    Err(err) => println!("Could not copy rows: {err}"),
+                }
+            }
+        });
+        ui.separator();
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + 4.0;
+        ScrollArea::horizontal()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    self.header_button(ui, None, "index", INDEX_WIDTH);
+                    for (i, column) in view.columns.iter().enumerate() {
+                        let width = Self::column_width(column.value_type);
+                        self.header_button(ui, Some(i), &column.name, width);
+                    }
+                });
+
+                // Only the rows on screen are read from the mesh, so the
+                // spreadsheet stays responsive for any number of elements.
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show_rows(ui, row_height, view.num_rows(), |ui, range| {
+                        self.visible_rows = range.clone();
+                        let rows = match view.rows(mesh, range) {
+                            Ok(rows) => rows,
+                            Err(err) => {
+                                ui.label(RichText::new(err.to_string()).color(Color32::RED));
+                                return;
                             }
-                        } else {
-                            let count = match self.current_view {
-                                SpreadsheetViews::Vertices => {
-                                    mesh.read_connectivity().num_vertices()
-                                }
-                                SpreadsheetViews::Halfedges => {
-                                    mesh.read_connectivity().num_halfedges()
+                        };
+                        for row in rows {
+                            ui.horizontal(|ui| {
+                                ui.add_sized(
+                                    [INDEX_WIDTH, row_height],
+                                    Label::new(RichText::new(row.index.to_string()).monospace()),
+                                );
+                                for (column, value) in view.columns.iter().zip(&row.values) {
+                                    let width = Self::column_width(column.value_type);
+                                    let cell =
+                                        Label::new(RichText::new(value.to_string()).monospace())
+                                            .sense(Sense::click());
+                                    if ui
+                                        .add_sized([width, row_height], cell)
+                                        .on_hover_text("Click to copy")
+                                        .clicked()
+                                    {
+                                        ui.output().copied_text = value.to_exact_string();
+                                    }
                                 }
-                                SpreadsheetViews::Faces => mesh.read_connectivity().num_faces(),
-                            };
-                            for i in 0..count {
-                                ui.label(i.to_string());
-                                ui.end_row();
-                            }
+                            });
                         }
-                    })
+                    });
             });
-        }
     }
 }
 impl DebugTab {
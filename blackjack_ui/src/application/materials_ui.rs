@@ -0,0 +1,43 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::mesh::material::{Material, MaterialTable};
+
+use crate::prelude::*;
+
+/// Draws an editor for the `materials` of a graph. Faces refer to materials by
+/// their index, so the index is shown next to each one.
+pub fn material_table_ui(ui: &mut egui::Ui, materials: &mut MaterialTable) {
+    let mut removed = None;
+    egui::Grid::new("material_table")
+        .striped(true)
+        .show(ui, |ui| {
+            for (idx, material) in materials.materials.iter_mut().enumerate() {
+                ui.label(idx.to_string());
+                ui.add(egui::TextEdit::singleline(&mut material.name).desired_width(100.0));
+                let mut color = material.base_color.to_array();
+                if ui.color_edit_button_rgb(&mut color).changed() {
+                    material.base_color = Vec3::from(color);
+                }
+                ui.add(egui::Slider::new(&mut material.metallic, 0.0..=1.0).text("Metallic"));
+                ui.add(egui::Slider::new(&mut material.roughness, 0.0..=1.0).text("Roughness"));
+                if ui.button("🗑").on_hover_text("Remove").clicked() {
+                    removed = Some(idx);
+                }
+                ui.end_row();
+            }
+        });
+    if let Some(idx) = removed {
+        materials.materials.remove(idx);
+    }
+
+    if ui.button("Add material").clicked() {
+        materials.materials.push(Material {
+            name: format!("material_{}", materials.materials.len()),
+            ..Default::default()
+        });
+    }
+}
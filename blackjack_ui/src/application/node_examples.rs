@@ -0,0 +1,199 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::PathBuf;
+
+use blackjack_engine::graph::NodeDefinitions;
+
+use super::gizmo_ui::UiNodeGizmoStates;
+use super::serialization;
+use crate::graph::node_graph::{CustomGraphState, GraphEditorState};
+use crate::prelude::*;
+
+/// The example graphs that node definitions can point to with their
+/// `example` field, embedded in the binary.
+const NODE_EXAMPLES: &[(&str, &[u8])] = &[
+    ("box", include_bytes!("../../../examples/box.bjk")),
+    (
+        "extrude_faces",
+        include_bytes!("../../../examples/nodes/extrude_faces.bjk"),
+    ),
+    (
+        "bevel_edges",
+        include_bytes!("../../../examples/nodes/bevel_edges.bjk"),
+    ),
+];
+
+/// Loads the bundled example graph called `name`. Like templates, it has no
+/// file.
+pub fn load_example(
+    name: &str,
+    lua: &mlua::Lua,
+    node_definitions: &NodeDefinitions,
+    gizmo_states: &UiNodeGizmoStates,
+) -> Result<(GraphEditorState, CustomGraphState)> {
+    let (_, bytes) = NODE_EXAMPLES
+        .iter()
+        .find(|(example, _)| *example == name)
+        .ok_or_else(|| anyhow!("There is no example graph called '{name}'"))?;
+    serialization::load_from_bytes(bytes, lua, node_definitions, gizmo_states)
+        .with_context(|| format!("Could not load the example graph '{name}'"))
+}
+
+/// An example graph shown in place of the graph being edited. The graph
+/// being edited is kept aside, untouched, and comes back when the example is
+/// closed. Only the graph in the editor can be edited, and changes to the
+/// example are lost when it's closed.
+pub struct ExampleTab {
+    pub name: String,
+    editor_state: GraphEditorState,
+    custom_state: CustomGraphState,
+    open_file: Option<PathBuf>,
+}
+
+impl ExampleTab {
+    /// Puts the `example` graph in the editor, in place of the graph in
+    /// `editor_state` and `custom_state`, which was loaded from `open_file`.
+    pub fn open(
+        name: String,
+        example: (GraphEditorState, CustomGraphState),
+        editor_state: &mut GraphEditorState,
+        custom_state: &mut CustomGraphState,
+        open_file: &mut Option<PathBuf>,
+    ) -> Self {
+        let (example_editor_state, mut example_custom_state) = example;
+        // The docs the example was opened from stay open
+        example_custom_state.docs_node = custom_state.docs_node.clone();
+        example_custom_state.last_dialog_dir = custom_state.last_dialog_dir.clone();
+        Self {
+            name,
+            editor_state: std::mem::replace(editor_state, example_editor_state),
+            custom_state: std::mem::replace(custom_state, example_custom_state),
+            open_file: open_file.take(),
+        }
+    }
+
+    /// Puts the graph that was being edited back in the editor, dropping the
+    /// example.
+    pub fn close(
+        self,
+        editor_state: &mut GraphEditorState,
+        custom_state: &mut CustomGraphState,
+        open_file: &mut Option<PathBuf>,
+    ) {
+        let last_dialog_dir = custom_state.last_dialog_dir.take();
+        *editor_state = self.editor_state;
+        *custom_state = self.custom_state;
+        custom_state.last_dialog_dir = last_dialog_dir;
+        *open_file = self.open_file;
+    }
+
+    /// The graph that was being edited when the example was opened.
+    pub fn stashed_graph(&self) -> (&GraphEditorState, &CustomGraphState) {
+        (&self.editor_state, &self.custom_state)
+    }
+
+    /// Shows which example is open, above the editor. Returns whether the
+    /// user asked to close it.
+    pub fn banner_ui(&self, ctx: &egui::Context) -> bool {
+        let mut close = false;
+        egui::TopBottomPanel::top("example_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("📖 Example: {}", self.name)).strong());
+                ui.label(egui::RichText::new("Changes to the example are not saved").weak());
+                let back_to = match self.open_file.as_ref().and_then(|path| path.file_name()) {
+                    Some(file_name) => format!("✖ Back to {}", file_name.to_string_lossy()),
+                    None => "✖ Close example".to_string(),
+                };
+                close = ui.button(back_to).clicked();
+            });
+        });
+        close
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use blackjack_engine::graph::BlackjackValue;
+    use blackjack_engine::lua_engine::LuaRuntime;
+
+    use super::*;
+    use crate::application::file_dialogs::graph_snapshot;
+    use crate::graph::node_graph::ValueTypeUi;
+
+    #[test]
+    fn test_node_examples_exist() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &runtime.node_definitions;
+        let mut referenced = HashSet::new();
+        for op_name in defs.node_names() {
+            let example = defs.node_def(&op_name).unwrap().docs.example.clone();
+            if let Some(example) = example {
+                let (editor_state, _) =
+                    load_example(&example, &runtime.lua, defs, &UiNodeGizmoStates::init())
+                        .unwrap_or_else(|err| panic!("The example of {op_name}: {err:?}"));
+                let uses_node = editor_state
+                    .graph
+                    .nodes
+                    .iter()
+                    .any(|(_, node)| node.user_data.op_name == op_name);
+                assert!(uses_node, "The example of {op_name} doesn't use it");
+                referenced.insert(example);
+            }
+        }
+        // Every bundled example belongs to a node
+        assert_eq!(referenced.len(), NODE_EXAMPLES.len());
+    }
+
+    #[test]
+    fn test_example_keeps_the_open_graph() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &runtime.node_definitions;
+        let gizmo_states = UiNodeGizmoStates::init();
+        let load = |name: &str| load_example(name, &runtime.lua, defs, &gizmo_states).unwrap();
+
+        let (mut editor_state, mut custom_state) = load("box");
+        custom_state.graph_seed = 42;
+        let mut open_file = Some(PathBuf::from("/projects/tree.bjk"));
+        let original = graph_snapshot(&editor_state, &custom_state);
+
+        let tab = ExampleTab::open(
+            "extrude_faces".into(),
+            load("extrude_faces"),
+            &mut editor_state,
+            &mut custom_state,
+            &mut open_file,
+        );
+        assert_eq!(open_file, None);
+        assert_ne!(graph_snapshot(&editor_state, &custom_state), original);
+        let (stashed_editor_state, stashed_custom_state) = tab.stashed_graph();
+        assert_eq!(
+            graph_snapshot(stashed_editor_state, stashed_custom_state),
+            original
+        );
+
+        // Edit the example
+        let (extrude, _) = editor_state
+            .graph
+            .nodes
+            .iter()
+            .find(|(_, node)| node.user_data.op_name == "ExtrudeFaces")
+            .unwrap();
+        let amount = editor_state.graph[extrude].get_input("amount").unwrap();
+        editor_state.graph[amount].value = ValueTypeUi(BlackjackValue::Scalar(2.0));
+        custom_state.graph_seed = 7;
+
+        tab.close(&mut editor_state, &mut custom_state, &mut open_file);
+        assert_eq!(graph_snapshot(&editor_state, &custom_state), original);
+        assert_eq!(custom_state.graph_seed, 42);
+        assert_eq!(open_file, Some(PathBuf::from("/projects/tree.bjk")));
+        assert!(!editor_state
+            .graph
+            .nodes
+            .iter()
+            .any(|(_, node)| node.user_data.op_name == "ExtrudeFaces"));
+    }
+}
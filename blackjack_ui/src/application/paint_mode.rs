@@ -0,0 +1,242 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::graph::BlackjackValue;
+use blackjack_engine::mesh::halfedge::bvh::MeshBvh;
+use blackjack_engine::mesh::halfedge::channels::{ChannelKeyType, ChannelValueType};
+use blackjack_engine::mesh::halfedge::edit_ops::vertex_weights::{Brush, BrushMode};
+use blackjack_engine::prelude::HalfEdgeMesh;
+
+use crate::app_window::gui_overlay::unproject_point;
+use crate::prelude::*;
+
+use super::edit_mode::append_edit_node;
+use super::viewport_3d::Viewport3dSettings;
+
+/// The op name of the node that stores the values painted in the viewport.
+pub const PAINT_CHANNEL_OP: &str = "PaintChannel";
+
+/// State of the viewport's paint mode. In paint mode, dragging over the
+/// displayed mesh paints the values of an f32 vertex channel with a round
+/// brush. The values are stored in a `Paint Channel` node.
+pub struct PaintMode {
+    pub enabled: bool,
+    /// The name of the vertex channel being painted.
+    pub channel: String,
+    /// The radius of the brush on the screen, in points.
+    pub screen_radius: f32,
+    pub strength: f32,
+    pub falloff: f32,
+    pub brush_mode: BrushMode,
+    /// The hierarchy of the displayed mesh, built when a stroke starts.
+    /// Painting only changes a channel, so it stays valid for the stroke.
+    bvh: Option<MeshBvh>,
+    stroke_active: bool,
+    /// The center and world space radius of the dabs painted since the last
+    /// call to [`PaintMode::take_pending_dabs`].
+    pending_dabs: Vec<(Vec3, f32)>,
+    /// The heatmap shown before painting, restored when painting stops.
+    heatmap_before: Option<Option<String>>,
+}
+
+impl Default for PaintMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            channel: "mask".into(),
+            screen_radius: 40.0,
+            strength: 0.2,
+            falloff: 0.5,
+            brush_mode: BrushMode::Add,
+            bvh: None,
+            stroke_active: false,
+            pending_dabs: vec![],
+            heatmap_before: None,
+        }
+    }
+}
+
+impl PaintMode {
+    /// Turns paint mode on or off. While painting, the viewport shows the
+    /// painted channel as a heatmap.
+    pub fn toggle(&mut self, settings: &mut Viewport3dSettings) {
+        self.enabled = !self.enabled;
+        self.bvh = None;
+        self.stroke_active = false;
+        if self.enabled {
+            self.heatmap_before = Some(settings.heatmap.clone());
+            settings.heatmap = Some(self.channel.clone());
+        } else if let Some(heatmap) = self.heatmap_before.take() {
+            settings.heatmap = heatmap;
+        }
+    }
+
+    /// Shows the settings of the brush, and the channel being painted.
+    pub fn toolbar_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        mesh: Option<&HalfEdgeMesh>,
+        settings: &mut Viewport3dSettings,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Channel:");
+            egui::ComboBox::from_id_source("paint_channel")
+                .selected_text(self.channel.clone())
+                .show_ui(ui, |ui| {
+                    for name in mesh.map(f32_vertex_channels).unwrap_or_default() {
+                        let label = name.clone();
+                        ui.selectable_value(&mut self.channel, name, label);
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.channel).desired_width(80.0))
+                .on_hover_text("Type a new name to create a channel");
+            settings.heatmap = Some(self.channel.clone());
+
+            ui.separator();
+            ui.selectable_value(&mut self.brush_mode, BrushMode::Add, "Add");
+            ui.selectable_value(&mut self.brush_mode, BrushMode::Subtract, "Subtract");
+            ui.selectable_value(&mut self.brush_mode, BrushMode::Smooth, "Smooth");
+
+            ui.separator();
+            ui.add(egui::Slider::new(&mut self.screen_radius, 2.0..=200.0).text("Radius"));
+            ui.add(egui::Slider::new(&mut self.strength, 0.0..=1.0).text("Strength"));
+            ui.add(egui::Slider::new(&mut self.falloff, 0.0..=1.0).text("Falloff"));
+        });
+    }
+
+    /// Draws the brush under the cursor, and paints while the primary button
+    /// is held over the mesh. Returns whether the mouse is being used to
+    /// paint, so the camera doesn't move.
+    pub fn viewport_ui(
+        &mut self,
+        ui: &egui::Ui,
+        mesh: &HalfEdgeMesh,
+        view_proj: &Mat4,
+        viewport_rect: egui::Rect,
+    ) -> bool {
+        let pointer = ui.input().pointer.clone();
+        if !pointer.primary_down() {
+            self.stroke_active = false;
+        }
+        let cursor = match pointer.hover_pos() {
+            Some(cursor) if viewport_rect.contains(cursor) => cursor,
+            _ => return self.stroke_active,
+        };
+        ui.ctx().debug_painter().circle_stroke(
+            cursor,
+            self.screen_radius,
+            egui::Stroke::new(1.5, egui::Color32::WHITE),
+        );
+
+        // Strokes only start by pressing over the mesh. Dragging from
+        // anywhere else moves the camera.
+        let pressed = pointer.primary_pressed();
+        if pressed {
+            self.bvh = Some(MeshBvh::build(mesh));
+        }
+        if !pressed && !self.stroke_active {
+            return false;
+        }
+        let bvh = self.bvh.as_ref().expect("Built when the stroke started");
+
+        let near = unproject_point(view_proj, viewport_rect, cursor, 1.0);
+        let far = unproject_point(view_proj, viewport_rect, cursor, 0.5);
+        if let Some(hit) = bvh.raycast(near, far - near) {
+            // The brush covers the same area on the screen regardless of the
+            // distance to the camera, so its world radius is measured at the
+            // depth of the hit.
+            let depth = view_proj.project_point3(hit.point).z;
+            let edge = unproject_point(
+                view_proj,
+                viewport_rect,
+                cursor + egui::vec2(self.screen_radius, 0.0),
+                depth,
+            );
+            self.pending_dabs
+                .push((hit.point, edge.distance(hit.point)));
+            self.stroke_active = true;
+        }
+        self.stroke_active
+    }
+
+    /// Returns the dabs painted since the last call.
+    pub fn take_pending_dabs(&mut self) -> Vec<(Vec3, f32)> {
+        std::mem::take(&mut self.pending_dabs)
+    }
+}
+
+/// Returns the names of the f32 vertex channels of `mesh`, the ones that can
+/// be painted or shown as a heatmap.
+pub fn f32_vertex_channels(mesh: &HalfEdgeMesh) -> Vec<String> {
+    mesh.channel_infos()
+        .into_iter()
+        .filter(|info| {
+            info.key_type == ChannelKeyType::VertexId && info.value_type == ChannelValueType::f32
+        })
+        .map(|info| info.name.as_str().to_owned())
+        .collect()
+}
+
+/// Paints the `dabs` on `mesh`, the displayed mesh, and stores the new values
+/// in a `Paint Channel` node. When the active node is not one painting the
+/// same channel, a new one is appended after it and becomes the active node.
+pub fn record_dabs(
+    editor_state: &mut graph::GraphEditorState,
+    custom_state: &mut graph::CustomGraphState,
+    paint_mode: &PaintMode,
+    mesh: &HalfEdgeMesh,
+    dabs: &[(Vec3, f32)],
+) -> Result<()> {
+    let bvh = paint_mode
+        .bvh
+        .as_ref()
+        .ok_or_else(|| anyhow!("Painting needs a stroke to be started first"))?;
+    if paint_mode.channel.is_empty() {
+        bail!("The channel to paint needs a name");
+    }
+    let active = custom_state
+        .active_node
+        .ok_or_else(|| anyhow!("There is no active node to paint"))?;
+
+    let painted_channel = |node| -> Option<String> {
+        let node = &editor_state.graph[node];
+        if node.user_data.op_name != PAINT_CHANNEL_OP {
+            return None;
+        }
+        let input_id = node.get_input("channel").ok()?;
+        match &editor_state.graph.inputs[input_id].value.0 {
+            BlackjackValue::String(name) => Some(name.clone()),
+            _ => None,
+        }
+    };
+    let paint_node = if painted_channel(active).as_ref() == Some(&paint_mode.channel) {
+        active
+    } else {
+        let paint_node = append_edit_node(editor_state, custom_state, active, PAINT_CHANNEL_OP)?;
+        let input_id = editor_state.graph[paint_node].get_input("channel")?;
+        editor_state.graph.inputs[input_id].value.0 =
+            BlackjackValue::String(paint_mode.channel.clone());
+        custom_state.active_node = Some(paint_node);
+        paint_node
+    };
+
+    let input_id = editor_state.graph[paint_node].get_input("weights")?;
+    match &mut editor_state.graph.inputs[input_id].value.0 {
+        BlackjackValue::VertexWeights(weights) => {
+            for (center, radius) in dabs {
+                let brush = Brush {
+                    radius: *radius,
+                    strength: paint_mode.strength,
+                    falloff: paint_mode.falloff,
+                    mode: paint_mode.brush_mode,
+                };
+                brush.dab(mesh, bvh, &paint_mode.channel, *center, weights)?;
+            }
+            Ok(())
+        }
+        _ => bail!("The 'weights' parameter of the Paint Channel node has the wrong type"),
+    }
+}
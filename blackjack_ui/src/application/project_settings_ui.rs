@@ -0,0 +1,45 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::units::LengthUnit;
+
+use crate::prelude::*;
+
+/// Draws an editor for the settings saved with the graph. For now, the only
+/// setting is the unit lengths are shown in.
+pub fn project_settings_ui(ui: &mut egui::Ui, units: &mut LengthUnit) {
+    egui::Grid::new("project_settings").show(ui, |ui| {
+        ui.label("Units").on_hover_text(
+            "The unit lengths are shown in. Lengths are always stored in meters, so changing \
+             the unit doesn't change the meshes. OBJ files are exported in this unit, and glTF \
+             files in meters.",
+        );
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("length_unit")
+                .selected_text(units.label())
+                .show_ui(ui, |ui| {
+                    for preset in LengthUnit::PRESETS {
+                        ui.selectable_value(units, preset, preset.label());
+                    }
+                    let is_custom = matches!(units, LengthUnit::Custom(_));
+                    if ui.selectable_label(is_custom, "Custom").clicked() && !is_custom {
+                        // Starts from the current unit, so nothing jumps
+                        *units = LengthUnit::Custom(units.meters_per_unit());
+                    }
+                });
+            if let LengthUnit::Custom(scale) = units {
+                ui.add(
+                    egui::DragValue::new(scale)
+                        .speed(0.001)
+                        .clamp_range(1e-6..=1e6)
+                        .suffix(" m"),
+                )
+                .on_hover_text("The length of one unit, in meters.");
+            }
+        });
+        ui.end_row();
+    });
+}
@@ -4,57 +4,409 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use super::file_dialogs::{self, remember_dialog_dir};
+use super::templates::NewGraph;
 use super::*;
+use blackjack_engine::graph_interpreter::dry_run::dry_run;
+use blackjack_engine::graph_interpreter::memory_budget::format_bytes;
+use blackjack_engine::random::combine_seeds;
 use std::path::PathBuf;
 
 pub enum AppRootAction {
     Save(PathBuf),
     Load(PathBuf),
+    /// Replaces the open graph with a new one, which has no file yet.
+    New(NewGraph),
+    /// Saves the open graph as a user template with the given name.
+    SaveTemplate(String),
+    /// Trusts or distrusts the open file, running its Lua code inside the
+    /// sandbox when it's not trusted.
+    SetFileTrusted(bool),
+    /// Shows the bundled example graph with the given name in place of the
+    /// open graph, see [`super::node_examples`].
+    OpenExample(String),
+    /// Puts the open graph back in place of the example.
+    CloseExample,
+    /// Opens a new tab with an empty graph, see [`super::documents`].
+    NewTab,
+    /// Opens the graph at the given path in a new tab.
+    OpenInNewTab(PathBuf),
+    /// Shows the graph of the tab at the given index.
+    SelectTab(usize),
+    /// Closes the active tab, without asking about unsaved changes.
+    CloseTab,
+}
+
+/// Returns a new graph seed, different from `seed`. Seeds are kept small, so
+/// they are easy to read and type back.
+fn reroll_seed(seed: u64) -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos() as u64);
+    let new_seed = combine_seeds(seed, nanos) % 1_000_000;
+    if new_seed == seed {
+        (new_seed + 1) % 1_000_000
+    } else {
+        new_seed
+    }
 }
 
 impl RootViewport {
     pub fn top_menubar(&mut self) -> Option<AppRootAction> {
         let mut action = None;
+        let mut open_request = None;
+        let mut close_tab_request = false;
         egui::TopBottomPanel::top("top_menubar").show(&self.egui_context, |ui| {
             // When set, will load a new editor state at the end of this function
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
-                    ui.add_enabled_ui(false, |ui| ui.button("New"));
+                    if ui.button("New…").clicked() {
+                        self.template_picker.open = true;
+                        ui.close_menu();
+                    }
                     if ui.button("Open…").clicked() {
-                        let file_location = rfd::FileDialog::new()
+                        let picked = file_dialogs::file_dialog(&self.graph_editor.custom_state)
                             .add_filter("Blackjack Model", &["bjk"])
                             .pick_file();
-                        if let Some(path) = file_location {
-                            action = Some(AppRootAction::Load(path))
+                        if let Some(path) = picked {
+                            remember_dialog_dir(&mut self.graph_editor.custom_state, &path);
+                            open_request = Some(path);
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button("Open in new tab…").clicked() {
+                        let picked = file_dialogs::file_dialog(&self.graph_editor.custom_state)
+                            .add_filter("Blackjack Model", &["bjk"])
+                            .pick_file();
+                        if let Some(path) = picked {
+                            remember_dialog_dir(&mut self.graph_editor.custom_state, &path);
+                            action = Some(AppRootAction::OpenInNewTab(path));
                         }
+                        ui.close_menu();
+                    }
+                    if ui.button("Browse…").clicked() {
+                        let folder = self
+                            .open_file
+                            .as_ref()
+                            .and_then(|path| path.parent())
+                            .map(|folder| folder.to_owned())
+                            .or_else(|| std::env::current_dir().ok())
+                            .unwrap_or_default();
+                        self.file_browser.open_at(&self.egui_context, &folder);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("New tab").clicked() {
+                        action = Some(AppRootAction::NewTab);
+                        ui.close_menu();
+                    }
+                    if ui.button("Close tab").clicked() {
+                        close_tab_request = true;
+                        ui.close_menu();
                     }
                     ui.separator();
                     if ui.button("Save As…").clicked() {
-                        let file_location = rfd::FileDialog::new()
-                            .set_file_name("Untitled.bjk")
-                            .add_filter("Blackjack Model", &["bjk"])
-                            .save_file();
+                        let file_location =
+                            file_dialogs::file_dialog(&self.graph_editor.custom_state)
+                                .set_file_name("Untitled.bjk")
+                                .add_filter("Blackjack Model", &["bjk"])
+                                .save_file();
                         if let Some(path) = file_location {
+                            remember_dialog_dir(&mut self.graph_editor.custom_state, &path);
                             action = Some(AppRootAction::Save(path))
                         }
                     }
+                    if ui
+                        .button("Save as template…")
+                        .on_hover_text("Saves the graph to start new graphs from, in File → New.")
+                        .clicked()
+                    {
+                        self.template_picker.save_prompt_open = true;
+                        ui.close_menu();
+                    }
+                    let mut canonical = self.save_format == BjkFileFormat::Canonical;
+                    if ui
+                        .checkbox(&mut canonical, "Save as canonical text")
+                        .on_hover_text(
+                            "Saves files in a stable format that only changes where the \
+                             graph changes, to review and merge them under version control.",
+                        )
+                        .changed()
+                    {
+                        self.save_format = if canonical {
+                            BjkFileFormat::Canonical
+                        } else {
+                            BjkFileFormat::Ron
+                        };
+                    }
+                    ui.checkbox(&mut self.embed_node_libraries, "Embed node libraries")
+                        .on_hover_text(
+                            "Saves the node libraries the graph uses, other than the bundled \
+                             ones, in its file. Others can then open it without installing \
+                             them. They run in the sandbox unless the file is trusted.",
+                        );
                     ui.separator();
                     ui.add_enabled_ui(false, |ui| ui.button("Quit"));
                 });
+                ui.menu_button("Graph", |ui| {
+                    if ui
+                        .button("Validate")
+                        .on_hover_text(
+                            "Checks the graph for unconnected inputs, mismatched types and \
+                             missing channels, without running it.",
+                        )
+                        .clicked()
+                    {
+                        self.validate_graph();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui
+                        .button("Clean up layout (Ctrl+L)")
+                        .on_hover_text(
+                            "Arranges the selected nodes, or all of them if none is selected, \
+                             in columns from left to right following their wires.",
+                        )
+                        .clicked()
+                    {
+                        self.graph_editor.layout_nodes();
+                        ui.close_menu();
+                    }
+                    let settings = &mut self.graph_editor.layout_settings;
+                    ui.horizontal(|ui| {
+                        ui.label("Spacing:");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.horizontal_spacing)
+                                .clamp_range(0.0..=500.0)
+                                .prefix("x "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut settings.vertical_spacing)
+                                .clamp_range(0.0..=500.0)
+                                .prefix("y "),
+                        );
+                    });
+                });
                 ui.menu_button("Window", |ui| {
                     ui.checkbox(&mut self.diagnostics_open, "Diagnostics");
+                    ui.checkbox(&mut self.materials_open, "Materials");
+                    ui.checkbox(&mut self.project_settings_open, "Project settings");
+                    ui.checkbox(&mut self.validation_open, "Validation log");
+                    ui.checkbox(&mut self.warnings_open, "Warnings");
                 });
+                ui.separator();
+                let seed = &mut self.graph_editor.custom_state.graph_seed;
+                ui.label("Seed:");
+                ui.add(egui::DragValue::new(seed)).on_hover_text(
+                    "The seed of the graph. Each node derives its own random numbers from it.",
+                );
+                if ui
+                    .button("🎲")
+                    .on_hover_text("Picks a new seed, re-rolling all the random nodes.")
+                    .clicked()
+                {
+                    *seed = reroll_seed(*seed);
+                }
             });
         });
 
+        if let Some(path) = open_request {
+            action = self.request_open(path);
+        }
+        if close_tab_request {
+            action = self.request_close_tab();
+        }
+        action
+    }
+
+    pub fn status_bar(&mut self) -> Option<AppRootAction> {
+        let mut action = None;
+        egui::TopBottomPanel::bottom("status_bar").show(&self.egui_context, |ui| {
+            ui.horizontal(|ui| {
+                match &self.app_context.mesh_stats {
+                    Some(stats) => ui.label(stats.summary()),
+                    None => ui.label(""),
+                };
+                if self.open_file.is_some() {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        let mut trusted = !self.lua_runtime.config().sandboxed;
+                        if ui
+                            .checkbox(&mut trusted, "Trust this file")
+                            .on_hover_text(
+                                "Untrusted files run their Lua code in a sandbox, without \
+                                 access to the filesystem and with limited resources.",
+                            )
+                            .changed()
+                        {
+                            action = Some(AppRootAction::SetFileTrusted(trusted));
+                        }
+                        if !trusted {
+                            ui.label("🔒 Sandboxed");
+                        }
+                    });
+                }
+            });
+        });
         action
     }
 
     pub fn diagnostics_ui(&mut self) {
+        let app_context = &mut self.app_context;
         egui::Window::new("Diagnostics")
             .open(&mut self.diagnostics_open)
             .show(&self.egui_context, |ui| {
                 ui.label(format!("HiDPI Scale: {}", ui.ctx().pixels_per_point()));
+                ui.label(format!(
+                    "Mesh memory of the last run: {}",
+                    format_bytes(app_context.run_mesh_bytes)
+                ));
+                ui.horizontal(|ui| {
+                    let budget = &mut app_context.memory_budget;
+                    let mut limited = budget.is_some();
+                    if ui
+                        .checkbox(&mut limited, "Memory budget")
+                        .on_hover_text(
+                            "Before running a node predicted to make the meshes take more \
+                             memory than this, the graph pauses and asks to continue.",
+                        )
+                        .changed()
+                    {
+                        *budget = limited.then_some(CLI_ARGS.memory_budget_mb * 1024 * 1024);
+                    }
+                    if let Some(bytes) = budget {
+                        let mut megabytes = *bytes / (1024 * 1024);
+                        let drag = egui::DragValue::new(&mut megabytes)
+                            .clamp_range(1..=1024 * 1024)
+                            .suffix(" MB");
+                        if ui.add(drag).changed() {
+                            *bytes = megabytes * 1024 * 1024;
+                        }
+                    }
+                });
+            });
+    }
+
+    /// Runs a dry run of the graph, and shows the problems it finds on their
+    /// nodes and in the validation log.
+    pub fn validate_graph(&mut self) {
+        let graph = &self.graph_editor.editor_state.graph;
+        let custom_state = &mut self.graph_editor.custom_state;
+        custom_state.dry_run_problems.clear();
+        self.validation_log.clear();
+        self.validation_open = true;
+
+        let (bjk_graph, mapping, params) =
+            match self.app_context.generate_bjk_graph(graph, custom_state) {
+                Ok(generated) => generated,
+                Err(err) => {
+                    self.validation_log.push((
+                        "Graph".into(),
+                        Severity::Error,
+                        format!("The graph could not be built: {err}"),
+                    ));
+                    return;
+                }
+            };
+        let report = dry_run(&bjk_graph, &custom_state.node_definitions, &params);
+        for problem in report.problems {
+            let node_id = mapping[problem.node];
+            self.validation_log.push((
+                graph[node_id].label.clone(),
+                problem.severity,
+                problem.message.clone(),
+            ));
+            custom_state
+                .dry_run_problems
+                .entry(node_id)
+                .or_default()
+                .push((problem.severity, problem.message));
+        }
+    }
+
+    pub fn validation_ui(&mut self) {
+        let log = &self.validation_log;
+        egui::Window::new("Validation log")
+            .open(&mut self.validation_open)
+            .show(&self.egui_context, |ui| {
+                if log.is_empty() {
+                    ui.label("No problems found");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (node, severity, message) in log {
+                        let (icon, color) = match severity {
+                            Severity::Error => ("⛔", egui::Color32::LIGHT_RED),
+                            Severity::Warning => ("⚠", egui::Color32::GOLD),
+                        };
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label(egui::RichText::new(icon).color(color));
+                            ui.strong(node.as_str());
+                            ui.label(message.as_str());
+                        });
+                    }
+                });
+            });
+    }
+
+    /// Lists the warnings reported by the nodes in the last execution. Picking
+    /// a warning that refers to mesh elements highlights them in the viewport.
+    pub fn warnings_ui(&mut self) {
+        let graph = &self.graph_editor.editor_state.graph;
+        let custom_state = &mut self.graph_editor.custom_state;
+        egui::Window::new("Warnings")
+            .open(&mut self.warnings_open)
+            .show(&self.egui_context, |ui| {
+                if custom_state.node_warnings.is_empty() {
+                    ui.label("The last run had no warnings");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let highlighted = &mut custom_state.highlighted_warning;
+                    for (node_id, warnings) in custom_state
+                        .node_warnings
+                        .iter()
+                        .filter(|(node_id, _)| graph.nodes.contains_key(**node_id))
+                        .sorted_by_key(|(node_id, _)| graph[**node_id].label.as_str())
+                    {
+                        for warning in warnings {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label(egui::RichText::new("⚠").color(egui::Color32::GOLD));
+                                ui.strong(graph[*node_id].label.as_str());
+                                ui.label(warning.message.as_str());
+                                if let Some(elements) = &warning.elements {
+                                    let is_highlighted = highlighted.as_ref() == Some(elements);
+                                    if ui
+                                        .selectable_label(
+                                            is_highlighted,
+                                            format!("🔍 {}", elements.len()),
+                                        )
+                                        .on_hover_text("Highlights the elements in the viewport")
+                                        .clicked()
+                                    {
+                                        *highlighted = (!is_highlighted).then(|| elements.clone());
+                                    }
+                                }
+                            });
+                        }
+                    }
+                });
+            });
+    }
+
+    pub fn materials_ui(&mut self) {
+        egui::Window::new("Materials")
+            .open(&mut self.materials_open)
+            .show(&self.egui_context, |ui| {
+                materials_ui::material_table_ui(ui, &mut self.graph_editor.custom_state.materials);
+            });
+    }
+
+    pub fn project_settings_ui(&mut self) {
+        egui::Window::new("Project settings")
+            .open(&mut self.project_settings_open)
+            .show(&self.egui_context, |ui| {
+                project_settings_ui::project_settings_ui(
+                    ui,
+                    &mut self.graph_editor.custom_state.units,
+                );
             });
     }
 
@@ -70,8 +422,10 @@ impl RootViewport {
                         .get_mut(&OffscreenViewport::Viewport3d)
                         .unwrap(),
                     payload.app_context.renderable_thing.as_ref(),
-                    &payload.graph_editor,
+                    &mut payload.graph_editor,
                     &mut payload.app_context.node_gizmo_states,
+                    &mut payload.app_context.edit_mode,
+                    &mut payload.app_context.paint_mode,
                 ) {
                     // TODO: Do something better for error reporting
                     println!("Error in viewport: {err}")
@@ -87,8 +441,11 @@ impl RootViewport {
             "inspector" => payload.inspector_tabs.ui(
                 ui,
                 payload.app_context.renderable_thing.as_ref(),
+                payload.app_context.renderable_generation,
                 &mut payload.graph_editor.editor_state,
                 &mut payload.graph_editor.custom_state,
+                &mut payload.graph_editor.parameter_edit_mode,
+                &mut payload.graph_editor.last_edit,
             ),
             _ => panic!("Invalid split name {name}"),
         }
@@ -4,26 +4,122 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{graph::graph_interop, prelude::graph::*, prelude::*};
+use crate::{
+    graph::graph_interop::{self, NodeMapping},
+    prelude::graph::*,
+    prelude::*,
+};
 use std::path::{Path, PathBuf};
 
 use blackjack_engine::graph::{
-    serialization::{RuntimeData, SerializedBjkGraph, SerializedBjkSnippet, SerializedUiData},
-    DependencyKind, NodeDefinitions,
+    node_migration::migrate_graph,
+    project_paths::rebase_file_paths,
+    serialization::{
+        BjkFileFormat, RuntimeData, SerializedBjkGraph, SerializedBjkSnippet, SerializedUiData,
+    },
+    BjkGraph, DependencyKind, NodeDefinitions,
 };
+use blackjack_engine::graph_interpreter::ExternalParameterValues;
+use blackjack_engine::lua_engine::graph_libraries::embed_node_libraries;
+use blackjack_engine::lua_engine::lua_stdlib::lua_path::ProjectContext;
+use blackjack_engine::lua_engine::LuaRuntime;
 use egui_node_graph::PanZoom;
 
 use super::gizmo_ui::UiNodeGizmoStates;
+use super::viewport_3d::LightingSettings;
+
+/// Returns the project of a graph saved at `file`, if any, with an absolute
+/// folder so paths can be made relative to it.
+fn absolute_project(file: Option<&Path>) -> Result<ProjectContext> {
+    let file = file
+        .map(|file| std::env::current_dir().map(|dir| dir.join(file)))
+        .transpose()?;
+    Ok(ProjectContext::for_graph_file(file.as_deref()))
+}
 
+/// Saves the graph to `path`. File parameters inside the folder of `path` are
+/// stored relative to it, and the parameters in the editor are updated to
+/// match.
+///
+/// When `embed_libraries_from` is set, the file embeds the sources of the
+/// node libraries of that runtime the graph uses, other than the bundled ones.
 pub fn save(
+    editor_state: &mut GraphEditorState,
+    custom_state: &CustomGraphState,
+    path: impl AsRef<Path>,
+    format: BjkFileFormat,
+    thumbnail_png: Option<Vec<u8>>,
+    embed_libraries_from: Option<&LuaRuntime>,
+) -> Result<()> {
+    let (bjk_graph, mapping) =
+        graph_interop::ui_graph_to_blackjack_graph(&editor_state.graph, custom_state)?;
+    let mut external_param_values =
+        graph_interop::extract_graph_params(&editor_state.graph, &bjk_graph, &mapping)?;
+    rebase_file_paths(
+        &bjk_graph,
+        &mut external_param_values,
+        &custom_state.node_definitions,
+        &absolute_project(custom_state.file_path.as_deref())?,
+        &absolute_project(Some(path.as_ref()))?,
+    );
+    graph_interop::set_parameters_from_external_values(
+        &mut editor_state.graph,
+        external_param_values.clone(),
+        mapping.clone(),
+    )?;
+    let node_libraries = embed_libraries_from
+        .map(|runtime| embed_node_libraries(runtime, &bjk_graph))
+        .transpose()?
+        .unwrap_or_default();
+    let mut serialized = serialize_graph(
+        editor_state,
+        custom_state,
+        bjk_graph,
+        &mapping,
+        external_param_values,
+    )?;
+    serialized.thumbnail_png = thumbnail_png;
+    serialized.node_libraries = node_libraries;
+    serialized.write_to_file_with_format(path, format)?;
+
+    Ok(())
+}
+
+/// Saves the graph to `path` as a template. Unlike [`save`], the parameters
+/// are stored as they are: templates have no folder of their own for paths
+/// to be relative to, and the open graph is left untouched.
+pub fn save_template(
     editor_state: &GraphEditorState,
     custom_state: &CustomGraphState,
     path: impl AsRef<Path>,
+    thumbnail_png: Option<Vec<u8>>,
 ) -> Result<()> {
     let (bjk_graph, mapping) =
         graph_interop::ui_graph_to_blackjack_graph(&editor_state.graph, custom_state)?;
     let external_param_values =
         graph_interop::extract_graph_params(&editor_state.graph, &bjk_graph, &mapping)?;
+    let mut serialized = serialize_graph(
+        editor_state,
+        custom_state,
+        bjk_graph,
+        &mapping,
+        external_param_values,
+    )?;
+    serialized.thumbnail_png = thumbnail_png;
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    serialized.write_to_file(path)
+}
+
+/// Serializes the graph along with its layout in the editor.
+fn serialize_graph(
+    editor_state: &GraphEditorState,
+    custom_state: &CustomGraphState,
+    bjk_graph: BjkGraph,
+    mapping: &NodeMapping,
+    external_param_values: ExternalParameterValues,
+) -> Result<SerializedBjkGraph> {
     let (mut serialized, id_map) =
         blackjack_engine::graph::serialization::SerializedBjkGraph::from_runtime(RuntimeData {
             graph: bjk_graph,
@@ -61,28 +157,45 @@ pub fn save(
         locked_gizmo_nodes,
         pan: Vec2::new(pan.x, pan.y),
         zoom: editor_state.pan_zoom.zoom,
+        viewport_lighting: Some(custom_state.viewport_lighting.into()),
     });
+    Ok(serialized)
+}
 
-    serialized.write_to_file(path)?;
-
-    Ok(())
+/// Loads a graph from the contents of a `bjk` file, like a template. The
+/// graph has no file path, so saving it asks where to.
+pub fn load_from_bytes(
+    bytes: &[u8],
+    lua: &mlua::Lua,
+    node_definitions: &NodeDefinitions,
+    gizmo_states: &UiNodeGizmoStates,
+) -> Result<(GraphEditorState, CustomGraphState)> {
+    let serialized = SerializedBjkGraph::load_from_bytes(bytes)?;
+    load_serialized(serialized, None, lua, node_definitions, gizmo_states)
 }
 
-pub fn load(
-    path: PathBuf,
+/// Loads a graph read from a `bjk` file. The node libraries that come with
+/// it need to be loaded before, see [`SerializedBjkGraph::node_libraries`].
+pub fn load_serialized(
+    mut serialized: SerializedBjkGraph,
+    path: Option<PathBuf>,
+    lua: &mlua::Lua,
     node_definitions: &NodeDefinitions,
     gizmo_states: &UiNodeGizmoStates,
 ) -> Result<(GraphEditorState, CustomGraphState)> {
-    let serialized = SerializedBjkGraph::load_from_file(&path)?;
+    let version_warnings = migrate_graph(&mut serialized, lua, node_definitions)?;
     let (runtime, ui_data, id_idx_mappings) = serialized.into_runtime()?;
 
-    if ui_data.is_none() {
-        bail!(
-            "The file at {} doesn't have UI information. Cannot load.",
-            path.to_string_lossy()
-        )
-    }
-    let ui_data = ui_data.unwrap();
+    let ui_data = match ui_data {
+        Some(ui_data) => ui_data,
+        None => match &path {
+            Some(path) => bail!(
+                "The file at {} doesn't have UI information. Cannot load.",
+                path.to_string_lossy()
+            ),
+            None => bail!("The graph doesn't have UI information. Cannot load."),
+        },
+    };
 
     let (graph, mapping) = graph_interop::blackjack_graph_to_ui_graph(
         &runtime.graph,
@@ -102,6 +215,18 @@ pub fn load(
 
     let active_node = runtime.graph.default_node.map(|x| mapping[x]);
 
+    let node_version_warnings = version_warnings
+        .into_iter()
+        .map(|warning| {
+            println!(
+                "[WARNING] Node {}: {}",
+                warning.node_idx,
+                warning.describe()
+            );
+            (idx_to_node_id(warning.node_idx), warning)
+        })
+        .collect();
+
     // Restore locked gizmo state
     gizmo_states.restore_locked_nodes(ui_data.locked_gizmo_nodes.iter_cpy().map(idx_to_node_id));
     if let Some(n) = active_node {
@@ -124,6 +249,10 @@ pub fn load(
             }
         }
     }
+    let picked_selections =
+        graph_interop::picked_selections_from_bjk_nodes(&runtime.graph.nodes, &mapping)
+            .into_iter()
+            .collect();
 
     let editor_state = GraphEditorState {
         graph,
@@ -145,6 +274,31 @@ pub fn load(
         node_definitions: node_definitions.share(),
         gizmo_states: gizmo_states.share(),
         promoted_params,
+        node_progress: None,
+        cancel_requested: false,
+        picking: None,
+        picked_selections,
+        graph_seed: runtime.graph.seed,
+        materials: runtime.graph.materials.clone(),
+        units: runtime.graph.units,
+        viewport_lighting: ui_data
+            .viewport_lighting
+            .map(LightingSettings::from)
+            .unwrap_or_default(),
+        file_path: path,
+        last_dialog_dir: None,
+        node_version_warnings,
+        dry_run_problems: HashMap::default(),
+        node_warnings: HashMap::default(),
+        node_errors: HashMap::default(),
+        crashed_node: None,
+        highlighted_warning: None,
+        node_presets: load_node_presets(),
+        preset_name: String::new(),
+        preset_warnings: HashMap::default(),
+        bypass_group: None,
+        docs_node: None,
+        open_example: None,
     };
 
     Ok((editor_state, custom_state))
@@ -219,6 +373,42 @@ pub fn from_clipboard(
         node_definitions: _,
         promoted_params: _,
         gizmo_states: _,
+        node_progress: _,
+        cancel_requested: _,
+        picking: _,
+        // Picked selections are kept when the node they were picked from is
+        // pasted along with them.
+        picked_selections: _,
+        // Pasted nodes use the seed of the graph they're pasted into
+        graph_seed: _,
+        // Same for the materials
+        materials: _,
+        // And the units
+        units: _,
+        // Lighting is a setting of the viewport, not of the pasted nodes
+        viewport_lighting: _,
+        // And the file they're saved to
+        file_path: _,
+        // Dialogs are not affected by pasting
+        last_dialog_dir: _,
+        // Pasted nodes are saved with the current version of their definition
+        node_version_warnings: _,
+        // Problems are found again the next time the graph is validated
+        dry_run_problems: _,
+        // And warnings are reported the next time the graph runs
+        node_warnings: _,
+        node_errors: _,
+        crashed_node: _,
+        highlighted_warning: _,
+        // Presets belong to the user, not to the graph
+        node_presets: _,
+        preset_name: _,
+        preset_warnings: _,
+        // Bypassing is a way to look at the graph, not part of it
+        bypass_group: _,
+        // And so is reading the docs of its nodes
+        docs_node: _,
+        open_example: _,
     } = custom_state;
     let GraphEditorState {
         // This is updated by `append_snippet_to_existing_ui_graph`
@@ -246,6 +436,13 @@ pub fn from_clipboard(
         &custom_state.node_definitions,
     );
 
+    custom_state
+        .picked_selections
+        .extend(graph_interop::picked_selections_from_bjk_nodes(
+            &rt_data.snippet.nodes,
+            &node_mapping,
+        ));
+
     editor_state.selected_nodes.clear();
     if let Some(positions) = relative_node_positions {
         for (idx, position) in positions.iter().enumerate() {
@@ -0,0 +1,406 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::graph::serialization::BjkMetadata;
+
+use super::file_browser::{png_texture, THUMBNAIL_SIZE};
+use super::file_drop::is_graph_file;
+use super::trust_settings::settings_folder;
+use crate::prelude::*;
+
+/// The templates that come with blackjack, embedded in the binary.
+const BUNDLED_TEMPLATES: &[(&str, &[u8])] = &[
+    (
+        "Box modeling",
+        include_bytes!("../../../templates/box_modeling.bjk"),
+    ),
+    ("Terrain", include_bytes!("../../../templates/terrain.bjk")),
+    ("Scatter", include_bytes!("../../../templates/scatter.bjk")),
+];
+
+/// Where the contents of a template come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateSource {
+    Bundled(&'static [u8]),
+    /// A file in the user's templates folder.
+    User(PathBuf),
+}
+
+/// A graph to start new graphs from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphTemplate {
+    pub name: String,
+    pub source: TemplateSource,
+}
+
+impl GraphTemplate {
+    /// Returns the contents of the template, as a `bjk` file.
+    pub fn bytes(&self) -> Result<Cow<'static, [u8]>> {
+        match &self.source {
+            TemplateSource::Bundled(bytes) => Ok(Cow::Borrowed(*bytes)),
+            TemplateSource::User(path) => std::fs::read(path)
+                .map(Cow::Owned)
+                .with_context(|| format!("Could not read template {}", path.display())),
+        }
+    }
+}
+
+pub fn bundled_templates() -> Vec<GraphTemplate> {
+    BUNDLED_TEMPLATES
+        .iter()
+        .map(|(name, bytes)| GraphTemplate {
+            name: name.to_string(),
+            source: TemplateSource::Bundled(*bytes),
+        })
+        .collect()
+}
+
+/// Returns the folder where the user's templates are saved.
+pub fn user_templates_folder() -> Option<PathBuf> {
+    settings_folder().map(|folder| folder.join("templates"))
+}
+
+/// Returns the templates saved in `folder`, named after their files. A
+/// missing folder simply has no templates.
+pub fn user_templates(folder: &Path) -> Vec<GraphTemplate> {
+    let mut paths = match std::fs::read_dir(folder) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| is_graph_file(path))
+            .collect_vec(),
+        Err(_) => vec![],
+    };
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| GraphTemplate {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            source: TemplateSource::User(path),
+        })
+        .collect()
+}
+
+/// Merges the `bundled` and `user` templates into a single list. Names are
+/// compared regardless of their case:
+///
+/// - Bundled templates come first, in their order.
+/// - A user template with the name of a bundled one replaces it, in place.
+/// - Other user templates follow, sorted by name. When several of them have
+///   the same name, the first one is kept.
+pub fn merge_templates(
+    bundled: Vec<GraphTemplate>,
+    mut user: Vec<GraphTemplate>,
+) -> Vec<GraphTemplate> {
+    let key = |template: &GraphTemplate| template.name.to_lowercase();
+    user.sort_by_key(key);
+
+    let mut merged = bundled;
+    let num_bundled = merged.len();
+    for template in user {
+        match merged.iter().position(|other| key(other) == key(&template)) {
+            Some(i) if i < num_bundled => merged[i] = template,
+            Some(_) => {}
+            None => merged.push(template),
+        }
+    }
+    merged
+}
+
+/// Returns the file in `folder` where a user template called `name` is
+/// saved. Characters that can't be part of a file name are replaced.
+pub fn user_template_path(folder: &Path, name: &str) -> Result<PathBuf> {
+    let file_name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if file_name.is_empty() {
+        bail!("Templates need a name");
+    }
+    Ok(folder.join(format!("{file_name}.bjk")))
+}
+
+/// The templates new graphs can start from: the bundled ones, merged with the
+/// ones in the user's templates folder.
+pub struct TemplateRegistry {
+    user_folder: Option<PathBuf>,
+    templates: Vec<GraphTemplate>,
+}
+
+impl TemplateRegistry {
+    pub fn load() -> Self {
+        let mut registry = Self {
+            user_folder: user_templates_folder(),
+            templates: vec![],
+        };
+        registry.reload();
+        registry
+    }
+
+    /// Lists the templates again, picking up the ones saved since.
+    pub fn reload(&mut self) {
+        let user = self
+            .user_folder
+            .as_deref()
+            .map(user_templates)
+            .unwrap_or_default();
+        self.templates = merge_templates(bundled_templates(), user);
+    }
+
+    pub fn templates(&self) -> &[GraphTemplate] {
+        &self.templates
+    }
+
+    /// Returns the file to save a user template called `name` to.
+    pub fn user_template_path(&self, name: &str) -> Result<PathBuf> {
+        let folder = self
+            .user_folder
+            .as_ref()
+            .ok_or_else(|| anyhow!("Could not find a folder to store the templates"))?;
+        user_template_path(folder, name)
+    }
+}
+
+/// What to start a new graph from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NewGraph {
+    Empty,
+    FromTemplate(GraphTemplate),
+}
+
+/// A window showing the templates as a grid of thumbnails, shown at startup
+/// and when creating a new graph. Also asks for the name of the templates
+/// saved from the open graph.
+#[derive(Default)]
+pub struct TemplatePicker {
+    pub open: bool,
+    pub save_prompt_open: bool,
+    save_name: String,
+    /// The thumbnails of the templates, by name. Loaded the first time they
+    /// are shown.
+    thumbnails: HashMap<String, Option<egui::TextureHandle>>,
+}
+
+impl TemplatePicker {
+    /// Forgets the loaded thumbnails, after the templates changed.
+    pub fn clear_thumbnails(&mut self) {
+        self.thumbnails.clear();
+    }
+
+    fn thumbnail(
+        &mut self,
+        ctx: &egui::Context,
+        template: &GraphTemplate,
+    ) -> Option<egui::TextureHandle> {
+        self.thumbnails
+            .entry(template.name.clone())
+            .or_insert_with(|| {
+                let png = template
+                    .bytes()
+                    .and_then(|bytes| BjkMetadata::from_reader(&bytes[..]))
+                    .map(|metadata| metadata.thumbnail_png);
+                match png {
+                    Ok(Some(png)) => png_texture(ctx, format!("template {}", template.name), &png)
+                        .map_err(|err| {
+                            println!("Could not load thumbnail of {}: {err}", template.name)
+                        })
+                        .ok(),
+                    Ok(None) => None,
+                    Err(err) => {
+                        println!("Could not read template {}: {err}", template.name);
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    /// Draws the picker, and returns what to start the new graph from when
+    /// the user picks something. The user is warned that starting a new
+    /// graph loses the `unsaved_changes`.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        registry: &TemplateRegistry,
+        unsaved_changes: bool,
+    ) -> Option<NewGraph> {
+        if !self.open {
+            return None;
+        }
+        let thumbnails = registry
+            .templates()
+            .iter()
+            .map(|template| self.thumbnail(ctx, template))
+            .collect_vec();
+
+        let mut picked = None;
+        let mut open = self.open;
+        egui::Window::new("New graph")
+            .open(&mut open)
+            .default_size(egui::vec2(640.0, 480.0))
+            .show(ctx, |ui| {
+                if unsaved_changes {
+                    ui.label("The open graph has unsaved changes, which will be lost.");
+                    ui.separator();
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        let size = egui::vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+                        ui.vertical(|ui| {
+                            ui.set_width(THUMBNAIL_SIZE);
+                            if ui.add_sized(size, egui::Button::new("+")).clicked() {
+                                picked = Some(NewGraph::Empty);
+                            }
+                            ui.label("Empty graph");
+                        });
+                        for (template, thumbnail) in registry.templates().iter().zip(&thumbnails) {
+                            ui.vertical(|ui| {
+                                ui.set_width(THUMBNAIL_SIZE);
+                                let response = match thumbnail {
+                                    Some(texture) => {
+                                        ui.add(egui::ImageButton::new(texture.id(), size))
+                                    }
+                                    None => ui.add_sized(size, egui::Button::new("No preview")),
+                                };
+                                if response.clicked() {
+                                    picked = Some(NewGraph::FromTemplate(template.clone()));
+                                }
+                                ui.label(template.name.as_str());
+                            });
+                        }
+                    });
+                });
+            });
+        self.open = open && picked.is_none();
+        picked
+    }
+
+    /// Draws the prompt for the name of a template to save the open graph
+    /// as, and returns the name once the user confirms it.
+    pub fn show_save_prompt(&mut self, ctx: &egui::Context) -> Option<String> {
+        let mut name = None;
+        let mut open = self.save_prompt_open;
+        egui::Window::new("Save as template")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.save_name);
+                });
+                let valid = !self.save_name.trim().is_empty();
+                if ui.add_enabled(valid, egui::Button::new("Save")).clicked() {
+                    name = Some(self.save_name.trim().to_string());
+                }
+            });
+        self.save_prompt_open = open && name.is_none();
+        if name.is_some() {
+            self.save_name.clear();
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use blackjack_engine::graph::serialization::SerializedBjkGraph;
+
+    use super::*;
+
+    fn user(name: &str) -> GraphTemplate {
+        GraphTemplate {
+            name: name.into(),
+            source: TemplateSource::User(format!("/templates/{name}.bjk").into()),
+        }
+    }
+
+    fn names(templates: &[GraphTemplate]) -> Vec<&str> {
+        templates.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_bundled_templates_load() {
+        let templates = bundled_templates();
+        assert!(!templates.is_empty());
+        for template in &templates {
+            let bytes = template.bytes().unwrap();
+            let graph = SerializedBjkGraph::load_from_bytes(&bytes).unwrap();
+            assert!(graph.ui_data.is_some(), "{} has no layout", template.name);
+            assert_eq!(graph.file_path, None);
+        }
+        let unique: HashSet<&str> = names(&templates).into_iter().collect();
+        assert_eq!(unique.len(), templates.len());
+    }
+
+    #[test]
+    fn test_merge_templates() {
+        let merged = merge_templates(
+            bundled_templates(),
+            vec![
+                user("rocks"),
+                user("TERRAIN"),
+                user("Arches"),
+                user("Rocks"),
+            ],
+        );
+        assert_eq!(
+            names(&merged),
+            vec!["Box modeling", "TERRAIN", "Scatter", "Arches", "rocks"]
+        );
+        // The user's template replaces the bundled one
+        assert_eq!(merged[1], user("TERRAIN"));
+        // Without user templates, the bundled ones are left as they are
+        assert_eq!(
+            merge_templates(bundled_templates(), vec![]),
+            bundled_templates()
+        );
+    }
+
+    #[test]
+    fn test_user_templates() {
+        let folder = std::env::temp_dir().join("blackjack_user_templates_test");
+        let _ = std::fs::remove_dir_all(&folder);
+        assert!(user_templates(&folder).is_empty());
+
+        std::fs::create_dir_all(&folder).unwrap();
+        for file in ["Rocks.bjk", "arches.BJK", "notes.txt"] {
+            std::fs::write(folder.join(file), "").unwrap();
+        }
+        let templates = user_templates(&folder);
+        assert_eq!(names(&templates), vec!["Rocks", "arches"]);
+        assert_eq!(
+            templates[0].source,
+            TemplateSource::User(folder.join("Rocks.bjk"))
+        );
+    }
+
+    #[test]
+    fn test_user_template_path() {
+        let folder = Path::new("/config/templates");
+        assert_eq!(
+            user_template_path(folder, " Low poly tree ").unwrap(),
+            folder.join("Low poly tree.bjk")
+        );
+        assert_eq!(
+            user_template_path(folder, "a/b: c?").unwrap(),
+            folder.join("a_b_ c_.bjk")
+        );
+        assert!(user_template_path(folder, "  ").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use blackjack_engine::lua_engine::LuaRuntimeConfig;
+
+use crate::prelude::*;
+
+/// Graphs inside these folders, relative to the working directory, come with
+/// blackjack and are always trusted.
+const TRUSTED_FOLDERS: &[&str] = &["./examples"];
+
+/// Remembers which graph files the user trusts to run their Lua code outside
+/// of the sandbox. The list is persisted in the user's config folder, as a
+/// plain text file with one path per line.
+pub struct TrustSettings {
+    settings_file: Option<PathBuf>,
+    trusted_files: BTreeSet<PathBuf>,
+}
+
+impl TrustSettings {
+    /// Loads the trusted files from the user's config folder. Missing or
+    /// unreadable settings simply mean no file is trusted yet.
+    pub fn load() -> Self {
+        let settings_file = settings_folder().map(|folder| folder.join("trusted_files.txt"));
+        let trusted_files = settings_file
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            settings_file,
+            trusted_files,
+        }
+    }
+
+    /// Returns whether the graph at `path` can run without the sandbox.
+    pub fn is_trusted(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.trusted_files.contains(&path)
+            || TRUSTED_FOLDERS
+                .iter()
+                .any(|folder| path.starts_with(normalize(Path::new(folder))))
+    }
+
+    /// Marks the graph at `path` as trusted or untrusted, and saves the
+    /// settings.
+    pub fn set_trusted(&mut self, path: &Path, trusted: bool) -> Result<()> {
+        let path = normalize(path);
+        let changed = if trusted {
+            self.trusted_files.insert(path)
+        } else {
+            self.trusted_files.remove(&path)
+        };
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the Lua runtime config to use for the graph at `path`.
+    pub fn runtime_config_for(&self, path: &Path) -> LuaRuntimeConfig {
+        if self.is_trusted(path) {
+            LuaRuntimeConfig::default()
+        } else {
+            LuaRuntimeConfig::sandboxed()
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let settings_file = self
+            .settings_file
+            .as_ref()
+            .ok_or_else(|| anyhow!("Could not find a folder to store the settings"))?;
+        if let Some(parent) = settings_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = self
+            .trusted_files
+            .iter()
+            .filter_map(|path| path.to_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(settings_file, contents)
+            .with_context(|| format!("Could not save settings to {}", settings_file.display()))
+    }
+}
+
+/// Returns the folder where blackjack stores its settings for this user.
+pub fn settings_folder() -> Option<PathBuf> {
+    if let Some(config) = std::env::var_os("XDG_CONFIG_HOME") {
+        Some(PathBuf::from(config).join("blackjack"))
+    } else if let Some(app_data) = std::env::var_os("APPDATA") {
+        Some(PathBuf::from(app_data).join("blackjack"))
+    } else {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("blackjack"))
+    }
+}
+
+/// Paths are compared in their canonical form when the file exists, so the
+/// same file opened through different paths is recognized.
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
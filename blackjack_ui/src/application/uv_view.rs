@@ -0,0 +1,161 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::prelude::*;
+use blackjack_engine::graph::BlackjackValue;
+use blackjack_engine::mesh::halfedge::analysis::{uv_islands, UvIsland};
+use blackjack_engine::prelude::{selection::SelectionExpression, FaceId, HalfEdgeMesh};
+use egui::*;
+
+/// A read-only 2d view of the uv layout of the displayed mesh.
+pub struct UvView {
+    /// Offset of the center of the uv square from the center of the view, in
+    /// screen points.
+    pan: egui::Vec2,
+    zoom: f32,
+    /// Colors the faces by how much their uvs are stretched, instead of only
+    /// drawing their outlines.
+    show_stretch: bool,
+}
+
+impl Default for UvView {
+    fn default() -> Self {
+        Self {
+            pan: egui::Vec2::ZERO,
+            zoom: 1.0,
+            show_stretch: false,
+        }
+    }
+}
+
+/// Returns the value of the first selection parameter of the active node, so
+/// the faces it matches can be highlighted.
+pub fn active_selection(
+    graph: &graph::Graph,
+    custom_state: &graph::CustomGraphState,
+) -> Option<SelectionExpression> {
+    let node = graph.nodes.get(custom_state.active_node?)?;
+    node.inputs
+        .iter()
+        .find_map(|(_, input)| match &graph.inputs.get(*input)?.value.0 {
+            BlackjackValue::Selection(_, Some(selection)) => Some(selection.clone()),
+            _ => None,
+        })
+}
+
+/// Maps the ratio between a stretch and the typical stretch of the mesh to a
+/// color: Green when they match, going to red for edges that are longer in 3d
+/// than their uvs suggest, and to blue for shorter ones.
+fn heat_color(relative_stretch: f32) -> Color32 {
+    let t = relative_stretch.log2().clamp(-1.0, 1.0);
+    let neutral = Rgba::from_rgb(0.2, 0.8, 0.2);
+    let target = if t >= 0.0 {
+        Rgba::from_rgb(0.9, 0.1, 0.1)
+    } else {
+        Rgba::from_rgb(0.1, 0.3, 0.9)
+    };
+    let t = t.abs();
+    let color = neutral * (1.0 - t) + target * t;
+    Color32::from(color).linear_multiply(0.7)
+}
+
+/// Returns the median stretch of the faces in the `islands`, ignoring faces
+/// collapsed in uv space.
+fn median_stretch(islands: &[UvIsland]) -> f32 {
+    let stretches = islands
+        .iter()
+        .flat_map(|island| &island.polygons)
+        .map(|polygon| polygon.mean_stretch())
+        .filter(|s| s.is_finite())
+        .sorted_by(f32::total_cmp)
+        .collect_vec();
+    stretches.get(stretches.len() / 2).copied().unwrap_or(1.0)
+}
+
+impl UvView {
+    pub fn ui(&mut self, ui: &mut Ui, mesh: &HalfEdgeMesh, highlighted: &HashSet<FaceId>) {
+        let islands = match uv_islands(mesh) {
+            Ok(islands) => islands,
+            Err(err) => {
+                ui.label(err.to_string());
+                return;
+            }
+        };
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_stretch, "Stretch")
+                .on_hover_text(
+                    "Colors the faces by the ratio between their size in 3d and in uv space, \
+                     compared to the rest of the mesh. Red faces are stretched, blue ones \
+                     are compressed.",
+                );
+            if ui.button("Reset view").clicked() {
+                *self = Self {
+                    show_stretch: self.show_stretch,
+                    ..Default::default()
+                };
+            }
+            ui.label(format!("{} islands", islands.len()));
+        });
+
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
+        let rect = response.rect;
+        self.pan += response.drag_delta();
+        if let Some(cursor) = response.hover_pos() {
+            let scroll = ui.input().scroll_delta.y;
+            if scroll != 0.0 {
+                // Zoom around the cursor, keeping the uv under it in place
+                let factor = (scroll * 0.005).exp();
+                let new_zoom = (self.zoom * factor).clamp(0.05, 100.0);
+                let factor = new_zoom / self.zoom;
+                let offset = cursor - rect.center();
+                self.pan = offset - (offset - self.pan) * factor;
+                self.zoom = new_zoom;
+            }
+        }
+
+        let scale = rect.width().min(rect.height()) * 0.9 * self.zoom;
+        let origin = rect.center() + self.pan;
+        // The v axis points up
+        let to_screen = |uv: glam::Vec2| origin + vec2(uv.x - 0.5, 0.5 - uv.y) * scale;
+
+        painter.rect_filled(rect, 0.0, Color32::from_gray(25));
+        const CHECKER_CELLS: usize = 8;
+        let cell = 1.0 / CHECKER_CELLS as f32;
+        for i in 0..CHECKER_CELLS {
+            for j in 0..CHECKER_CELLS {
+                let min = glam::Vec2::new(i as f32, j as f32) * cell;
+                let color = if (i + j) % 2 == 0 {
+                    Color32::from_gray(60)
+                } else {
+                    Color32::from_gray(45)
+                };
+                painter.rect_filled(
+                    Rect::from_two_pos(to_screen(min), to_screen(min + glam::Vec2::splat(cell))),
+                    0.0,
+                    color,
+                );
+            }
+        }
+
+        let median = median_stretch(&islands);
+        let stroke = Stroke::new(1.0, Color32::from_gray(200));
+        for polygon in islands.iter().flat_map(|island| &island.polygons) {
+            let points = polygon.uvs.iter().map(|uv| to_screen(*uv)).collect_vec();
+            let fill = if highlighted.contains(&polygon.face) {
+                Some(Color32::from_rgba_unmultiplied(230, 180, 25, 150))
+            } else if self.show_stretch {
+                Some(heat_color(polygon.mean_stretch() / median))
+            } else {
+                None
+            };
+            match fill {
+                Some(fill) => painter.add(Shape::convex_polygon(points, fill, stroke)),
+                None => painter.add(Shape::closed_line(points, stroke)),
+            };
+        }
+    }
+}
@@ -4,15 +4,20 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use blackjack_engine::graph::serialization::SerializedViewportLighting;
 use blackjack_engine::lua_engine::RenderableThing;
+use blackjack_engine::units::LengthUnit;
 use winit::event::MouseButton;
 
 use crate::app_window::input::InputSystem;
+use crate::rendergraph::grid_routine;
 use crate::{prelude::*, rendergraph};
 
 use super::app_viewport::AppViewport;
+use super::edit_mode::EditMode;
 use super::gizmo_ui::{self, GizmoViewportResponse, UiNodeGizmoStates};
 use super::graph_editor::GraphEditor;
+use super::paint_mode::{self, PaintMode};
 
 /// A generic lerper
 mod lerp;
@@ -55,12 +60,162 @@ pub enum TextOverlayMode {
     DevDebug,
 }
 
+/// The plane the ground grid is drawn on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridPlane {
+    XZ,
+    XY,
+    YZ,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridSettings {
+    pub visible: bool,
+    pub plane: GridPlane,
+    /// Draws circles around the origin and lines going out of it, instead of
+    /// a square grid.
+    pub polar: bool,
+    pub color: [f32; 3],
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            plane: GridPlane::XZ,
+            polar: false,
+            color: [0.2, 0.2, 0.2],
+        }
+    }
+}
+
+/// How the faces of the mesh are lit, on top of their matcap. Angles are in
+/// degrees, and the lights move along with the camera. The default has no
+/// lights and a full ambient, which leaves only the matcap shading.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightingSettings {
+    /// The angle of the key light around the view direction. At 0, the light
+    /// comes from the camera, and positive angles move it to the right.
+    pub key_azimuth: f32,
+    /// The angle of the key light above the camera.
+    pub key_elevation: f32,
+    pub key_intensity: f32,
+    pub ambient: f32,
+    /// Adds a fill light opposite to the key light and a rim light behind the
+    /// mesh, with their intensities relative to the key light.
+    pub three_point: bool,
+    /// Darkens the ambient light in creases and corners, using screen space
+    /// ambient occlusion.
+    pub ssao: bool,
+    /// How far away surfaces occlude each other, in world units.
+    pub ssao_radius: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            key_azimuth: -45.0,
+            key_elevation: 35.0,
+            key_intensity: 0.0,
+            ambient: 1.0,
+            three_point: false,
+            ssao: false,
+            ssao_radius: 0.5,
+        }
+    }
+}
+
+impl LightingSettings {
+    /// A single light from the top left, on top of a dimmer ambient.
+    pub fn key_light() -> Self {
+        Self {
+            key_intensity: 0.8,
+            ambient: 0.35,
+            ..Default::default()
+        }
+    }
+
+    /// Key, fill and rim lights, with ambient occlusion.
+    pub fn studio() -> Self {
+        Self {
+            key_intensity: 0.8,
+            ambient: 0.25,
+            three_point: true,
+            ssao: true,
+            ..Default::default()
+        }
+    }
+
+    /// The direction towards the key, fill and rim lights in view space, and
+    /// their intensities. Lights that aren't used have an intensity of 0.
+    pub fn lights(&self) -> [(Vec3, f32); 3] {
+        // The view space of the camera looks along +Z, with Y up.
+        let direction = |azimuth: f32, elevation: f32| {
+            let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+            Vec3::new(
+                azimuth.sin() * elevation.cos(),
+                elevation.sin(),
+                -azimuth.cos() * elevation.cos(),
+            )
+        };
+        let key = (
+            direction(self.key_azimuth, self.key_elevation),
+            self.key_intensity,
+        );
+        let (fill, rim) = if self.three_point {
+            (self.key_intensity * 0.4, self.key_intensity * 0.6)
+        } else {
+            (0.0, 0.0)
+        };
+        [
+            key,
+            (direction(-self.key_azimuth, 10.0), fill),
+            (direction(180.0 - self.key_azimuth, 45.0), rim),
+        ]
+    }
+}
+
+impl From<SerializedViewportLighting> for LightingSettings {
+    fn from(lighting: SerializedViewportLighting) -> Self {
+        Self {
+            key_azimuth: lighting.key_azimuth,
+            key_elevation: lighting.key_elevation,
+            key_intensity: lighting.key_intensity,
+            ambient: lighting.ambient,
+            three_point: lighting.three_point,
+            ssao: lighting.ssao,
+            ssao_radius: lighting.ssao_radius,
+        }
+    }
+}
+
+impl From<LightingSettings> for SerializedViewportLighting {
+    fn from(lighting: LightingSettings) -> Self {
+        Self {
+            key_azimuth: lighting.key_azimuth,
+            key_elevation: lighting.key_elevation,
+            key_intensity: lighting.key_intensity,
+            ambient: lighting.ambient,
+            three_point: lighting.three_point,
+            ssao: lighting.ssao,
+            ssao_radius: lighting.ssao_radius,
+        }
+    }
+}
+
 pub struct Viewport3dSettings {
     pub render_vertices: bool,
     pub matcap: usize,
     pub edge_mode: EdgeDrawMode,
     pub face_mode: FaceDrawMode,
     pub overlay_mode: TextOverlayMode,
+    /// When set, faces are tinted with the base color of their material.
+    pub show_materials: bool,
+    /// The name of an f32 vertex channel to show as a heatmap over the faces.
+    pub heatmap: Option<String>,
+    pub grid: GridSettings,
+    /// Copied from the graph every frame, which stores it with the project.
+    pub lighting: LightingSettings,
 }
 
 pub struct Viewport3d {
@@ -75,9 +230,13 @@ pub struct Viewport3d {
     // True when a mouse drag does not belong to the camera. Such as when
     // dragging a gizmo.
     mouse_captured: bool,
+    // True when the viewport was clicked during the last frame, without
+    // dragging the camera or a gizmo.
+    clicked: bool,
 }
 
-struct OrbitCamera {
+/// Where the viewport looks from. Each tab keeps its own.
+pub struct OrbitCamera {
     yaw: Lerp<f32>,
     pitch: Lerp<f32>,
     distance: Lerp<f32>,
@@ -122,14 +281,24 @@ impl Viewport3d {
                 overlay_mode: TextOverlayMode::NoDraw,
                 render_vertices: true,
                 matcap: 0,
+                show_materials: false,
+                heatmap: None,
+                grid: GridSettings::default(),
+                lighting: LightingSettings::default(),
             },
             view_proj_matrix: Mat4::default(),
             view_matrix: Mat4::default(),
             projection_matrix: Mat4::default(),
             mouse_captured: false,
+            clicked: false,
         }
     }
 
+    /// Looks from `camera`, and keeps the camera used until now in its place.
+    pub fn swap_camera(&mut self, camera: &mut OrbitCamera) {
+        std::mem::swap(&mut self.camera, camera);
+    }
+
     pub fn on_winit_event(
         &mut self,
         parent_scale: f32,
@@ -211,7 +380,7 @@ impl Viewport3d {
             .set_aspect_ratio(self.viewport_rect.width() / self.viewport_rect.height());
     }
 
-    fn ambient_light() -> Vec4 {
+    pub fn ambient_light() -> Vec4 {
         Vec4::splat(0.25)
     }
 
@@ -246,17 +415,50 @@ impl Viewport3d {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn show_ui(
         &mut self,
         ui: &mut egui::Ui,
         offscreen_viewport: &mut AppViewport,
         renderable_thing: Option<&RenderableThing>,
-        graph_editor: &GraphEditor,
+        graph_editor: &mut GraphEditor,
         node_gizmo_states: &mut UiNodeGizmoStates,
+        edit_mode: &mut EditMode,
+        paint_mode: &mut PaintMode,
     ) -> Result<()> {
+        let displayed_mesh = match renderable_thing {
+            Some(RenderableThing::HalfEdgeMesh(mesh)) => Some(mesh),
+            _ => None,
+        };
         ui.vertical(|ui| {
             ui.horizontal(|ui| {
-                mesh_visuals_popup(ui, |ui| {
+                if ui
+                    .selectable_label(edit_mode.enabled, "✏ Edit mode")
+                    .on_hover_text(
+                        "Click vertices to select them, shift-click to add to the \
+                         selection, and drag them with the gizmo",
+                    )
+                    .clicked()
+                {
+                    edit_mode.toggle();
+                    if paint_mode.enabled {
+                        paint_mode.toggle(&mut self.settings);
+                    }
+                }
+                if ui
+                    .selectable_label(paint_mode.enabled, "🖌 Paint mode")
+                    .on_hover_text(
+                        "Drag over the mesh to paint the values of a vertex channel, \
+                         to use as a mask",
+                    )
+                    .clicked()
+                {
+                    paint_mode.toggle(&mut self.settings);
+                    if edit_mode.enabled {
+                        edit_mode.toggle();
+                    }
+                }
+                settings_popup(ui, "settings_popup", "Mesh Visuals", |ui| {
                     ui.horizontal(|ui| {
                         ui.label("Edges:");
                         ui.selectable_value(
@@ -305,6 +507,28 @@ impl Viewport3d {
                         );
                     });
 
+                    ui.horizontal(|ui| {
+                        ui.label("Materials:");
+                        ui.checkbox(&mut self.settings.show_materials, "");
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Heatmap:");
+                        let heatmap = &mut self.settings.heatmap;
+                        egui::ComboBox::from_id_source("heatmap_channel")
+                            .selected_text(heatmap.as_deref().unwrap_or("None"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(heatmap, None, "None");
+                                for name in displayed_mesh
+                                    .map(paint_mode::f32_vertex_channels)
+                                    .unwrap_or_default()
+                                {
+                                    let label = name.clone();
+                                    ui.selectable_value(heatmap, Some(name), label);
+                                }
+                            });
+                    });
+
                     ui.horizontal(|ui| {
                         ui.label("Matcap:");
                         if ui.button("<").clicked() {
@@ -352,10 +576,39 @@ impl Viewport3d {
                             "Debug",
                         );
                     });
+
+                    ui.horizontal(|ui| {
+                        let grid = &mut self.settings.grid;
+                        ui.label("Grid:");
+                        ui.checkbox(&mut grid.visible, "");
+                        ui.selectable_value(&mut grid.plane, GridPlane::XZ, "XZ");
+                        ui.selectable_value(&mut grid.plane, GridPlane::XY, "XY");
+                        ui.selectable_value(&mut grid.plane, GridPlane::YZ, "YZ");
+                        ui.checkbox(&mut grid.polar, "Polar");
+                        ui.color_edit_button_rgb(&mut grid.color);
+                    });
+                });
+                settings_popup(ui, "lighting_popup", "Lighting", |ui| {
+                    let lighting = &mut graph_editor.custom_state.viewport_lighting;
+                    lighting_ui(ui, lighting);
+                    self.settings.lighting = *lighting;
                 });
             });
+            if paint_mode.enabled {
+                paint_mode.toolbar_ui(ui, displayed_mesh, &mut self.settings);
+            }
             offscreen_viewport.show(ui, ui.available_size());
         });
+        if self.settings.grid.visible {
+            self.draw_grid_spacing(ui, offscreen_viewport.rect, graph_editor.custom_state.units);
+        }
+        self.clicked = !self.mouse_captured && {
+            let pointer = &ui.input().pointer;
+            pointer.primary_clicked()
+                && pointer
+                    .interact_pos()
+                    .map_or(false, |pos| offscreen_viewport.rect.contains(pos))
+        };
         if let Some(renderable_thing) = renderable_thing {
             crate::app_window::gui_overlay::draw_gui_overlays(
                 &self.view_proj_matrix,
@@ -366,6 +619,38 @@ impl Viewport3d {
             );
 
             self.mouse_captured = false;
+            if let (true, RenderableThing::HalfEdgeMesh(mesh)) =
+                (edit_mode.enabled, renderable_thing)
+            {
+                let cursor = ui.input().pointer.interact_pos();
+                if let (true, Some(cursor)) = (self.clicked, cursor) {
+                    let additive = ui.input().modifiers.shift;
+                    edit_mode.click(
+                        mesh,
+                        &self.view_proj_matrix,
+                        offscreen_viewport.rect,
+                        cursor,
+                        additive,
+                    );
+                }
+                edit_mode.draw_selection(
+                    &ui.ctx().debug_painter(),
+                    mesh,
+                    &self.view_proj_matrix,
+                    offscreen_viewport.rect,
+                );
+                if edit_mode.gizmo_ui(self, ui, mesh) {
+                    self.mouse_captured = true;
+                }
+            }
+            if let (true, RenderableThing::HalfEdgeMesh(mesh)) =
+                (paint_mode.enabled, renderable_thing)
+            {
+                if paint_mode.viewport_ui(ui, mesh, &self.view_proj_matrix, offscreen_viewport.rect)
+                {
+                    self.mouse_captured = true;
+                }
+            }
             node_gizmo_states.iterate_gizmos_for_drawing(
                 |node_id, gizmo_idx, gizmo, has_focus| {
                     let node = &graph_editor.editor_state.graph[node_id];
@@ -396,6 +681,22 @@ impl Viewport3d {
         Ok(())
     }
 
+    /// Shows the spacing between the major lines of the grid in the bottom
+    /// right corner of the viewport, in the unit of the project. The grid
+    /// itself is laid out in meters.
+    fn draw_grid_spacing(&self, ui: &egui::Ui, viewport_rect: egui::Rect, units: LengthUnit) {
+        let camera_position = self.view_matrix.inverse().transform_point3(Vec3::ZERO);
+        let levels =
+            grid_routine::GridLevels::new(self.settings.grid.plane.distance_to(camera_position));
+        ui.painter().text(
+            viewport_rect.right_bottom() - egui::vec2(8.0, 8.0),
+            egui::Align2::RIGHT_BOTTOM,
+            format!("Grid: {}", units.format_length(levels.major_spacing)),
+            egui::FontId::monospace(12.0),
+            egui::Color32::GRAY,
+        );
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
         self.view_matrix
     }
@@ -407,18 +708,55 @@ impl Viewport3d {
     pub fn viewport_rect(&self) -> egui::Rect {
         self.viewport_rect
     }
+
+    /// Returns whether the viewport was clicked since the last call.
+    pub fn take_clicked(&mut self) -> bool {
+        std::mem::take(&mut self.clicked)
+    }
 }
 
-/// Draws the "Mesh Visuals" popup.
+/// The controls of the "Lighting" popup.
+fn lighting_ui(ui: &mut egui::Ui, lighting: &mut LightingSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Preset:");
+        if ui.button("Matcap").clicked() {
+            *lighting = LightingSettings::default();
+        }
+        if ui.button("Key light").clicked() {
+            *lighting = LightingSettings::key_light();
+        }
+        if ui.button("Studio").clicked() {
+            *lighting = LightingSettings::studio();
+        }
+    });
+    ui.add(egui::Slider::new(&mut lighting.key_azimuth, -180.0..=180.0).text("Key azimuth"));
+    ui.add(egui::Slider::new(&mut lighting.key_elevation, -90.0..=90.0).text("Key elevation"));
+    ui.add(egui::Slider::new(&mut lighting.key_intensity, 0.0..=2.0).text("Key intensity"));
+    ui.add(egui::Slider::new(&mut lighting.ambient, 0.0..=1.0).text("Ambient"));
+    ui.checkbox(&mut lighting.three_point, "Fill and rim lights");
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut lighting.ssao, "Ambient occlusion");
+        ui.add_enabled(
+            lighting.ssao,
+            egui::Slider::new(&mut lighting.ssao_radius, 0.05..=2.0).text("Radius"),
+        );
+    });
+}
+
+/// Draws a button with the given `label` that opens a popup with the
+/// `contents`, like the "Mesh Visuals" one.
 /// This code was adapted from egui's Color Picker widget
-pub fn mesh_visuals_popup(
+pub fn settings_popup(
     ui: &mut egui::Ui,
+    id: &str,
+    label: &str,
     contents: impl FnOnce(&mut egui::Ui),
 ) -> egui::Response {
-    let popup_id = egui::Id::new("settings_popup");
-    let mut button_response = ui.button("Mesh Visuals");
+    let popup_id = egui::Id::new(id);
+    let mut button_response = ui.button(label);
     if ui.style().explanation_tooltips {
-        button_response = button_response.on_hover_text("Click to edit mesh visuals");
+        button_response =
+            button_response.on_hover_text(format!("Click to edit the {}", label.to_lowercase()));
     }
 
     if button_response.clicked() {
@@ -446,8 +784,77 @@ pub fn mesh_visuals_popup(
     button_response
 }
 
+impl GridPlane {
+    /// The normal of the plane, and the two axes along it. Lines along the
+    /// first axis are drawn in its color when they go through the origin, and
+    /// the same goes for the second one.
+    pub fn axes(&self) -> (Vec3, Vec3, Vec3) {
+        match self {
+            GridPlane::XZ => (Vec3::Y, Vec3::X, Vec3::Z),
+            GridPlane::XY => (Vec3::Z, Vec3::X, Vec3::Y),
+            GridPlane::YZ => (Vec3::X, Vec3::Z, Vec3::Y),
+        }
+    }
+
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        self.axes().0.dot(point).abs()
+    }
+}
+
 impl Default for Viewport3d {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_lighting_is_matcap_only() {
+        let lighting = LightingSettings::default();
+        assert_eq!(lighting.ambient, 1.0);
+        assert!(lighting
+            .lights()
+            .iter()
+            .all(|(_, intensity)| *intensity == 0.0));
+    }
+
+    #[test]
+    fn test_light_directions() {
+        let lighting = LightingSettings {
+            key_azimuth: 0.0,
+            key_elevation: 0.0,
+            three_point: true,
+            ..LightingSettings::key_light()
+        };
+        let [(key, _), (fill, fill_intensity), (rim, rim_intensity)] = lighting.lights();
+        // The camera looks along +Z, so a light behind it points towards -Z
+        assert!(key.abs_diff_eq(-Vec3::Z, 1e-5));
+        assert!(rim.z > 0.0 && rim.y > 0.0);
+        assert!(fill.z < 0.0);
+        assert!(fill_intensity > 0.0 && fill_intensity < lighting.key_intensity);
+        assert!(rim_intensity > 0.0);
+
+        // Positive azimuths move the key light right, and the fill light left
+        let lighting = LightingSettings {
+            key_azimuth: 45.0,
+            three_point: true,
+            ..LightingSettings::key_light()
+        };
+        let [(key, _), (fill, _), _] = lighting.lights();
+        assert!(key.x > 0.0 && key.y > 0.0);
+        assert!(fill.x < 0.0);
+        for (dir, _) in lighting.lights() {
+            assert!((dir.length() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_lighting_serialization_roundtrip() {
+        let studio = LightingSettings::studio();
+        let serialized = SerializedViewportLighting::from(studio);
+        assert_eq!(LightingSettings::from(serialized), studio);
+    }
+}
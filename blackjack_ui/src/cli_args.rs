@@ -4,12 +4,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use once_cell::sync::Lazy;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Loads the given `.bjk` file
     pub load: Option<String>,
 
@@ -22,6 +26,44 @@ pub struct Args {
     /// and the Lua code will be loaded once at startup.
     #[arg(long)]
     pub disable_lua_watcher: bool,
+
+    /// The memory imported meshes and images can use while they're cached
+    /// between graph runs, in megabytes.
+    #[arg(long, default_value_t = 512)]
+    pub asset_cache_mb: usize,
+
+    /// Recognize cached imported files by a hash of their contents, instead
+    /// of their modification time.
+    #[arg(long)]
+    pub hash_assets: bool,
+
+    /// The memory the meshes of a graph run can take, in megabytes. Before
+    /// running a node predicted to go over it, the editor asks to continue.
+    /// This can also be changed in the diagnostics window.
+    #[arg(long, default_value_t = 4096)]
+    pub memory_budget_mb: usize,
+
+    /// Run the nodes predicted to go over the memory budget without asking.
+    #[arg(long)]
+    pub allow_large: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compares two `.bjk` files and prints the nodes, parameters and
+    /// connections that differ between them.
+    Diff {
+        /// The original file
+        a: String,
+        /// The modified file
+        b: String,
+        /// Print the differences as JSON instead of a human-readable report
+        #[arg(long)]
+        json: bool,
+        /// Don't report nodes that only changed their position
+        #[arg(long)]
+        ignore_layout: bool,
+    },
 }
 
 /// CLI args are stored in a lazy static variable so they're accessible from
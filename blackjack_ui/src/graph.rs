@@ -9,3 +9,15 @@ pub mod node_graph;
 
 /// Functions to convert graphs from `egui_node_graph` into blacjkack graphs.
 pub mod graph_interop;
+
+/// Automatic layout of the nodes in a graph
+pub mod graph_layout;
+
+/// Editing connections from their wires, and splicing nodes into them
+pub mod connections;
+
+/// Editing the same parameter of several nodes at once
+pub mod parameter_edits;
+
+/// The documentation of nodes, in the node finder and in the docs window
+pub mod node_docs_ui;
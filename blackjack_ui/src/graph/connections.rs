@@ -0,0 +1,658 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::graph::DataType;
+use egui_node_graph::{
+    AnyParameterId, DataTypeTrait, InputId, NodeFinder, NodeId, NodeTemplateTrait, OutputId,
+};
+
+use crate::application::graph_editor::{
+    estimated_node_size, NODE_HEADER_HEIGHT, NODE_PARAM_HEIGHT, NODE_WIDTH,
+};
+use crate::prelude::graph::{CustomGraphState, Graph, GraphEditorState, NodeOpName};
+use crate::prelude::*;
+
+/// A node inserted in the middle of a connection, which now goes through the
+/// node. Keeps what's needed to undo it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionSplice {
+    /// The ends of the original connection.
+    pub output: OutputId,
+    pub input: InputId,
+    /// The parameters of the inserted node connected to those ends.
+    pub node_input: InputId,
+    pub node_output: OutputId,
+    /// What the input of the inserted node was connected to before.
+    previous_node_input: Option<OutputId>,
+}
+
+/// Inserts `node` in the connection going into `input`: The output at the
+/// other end of the connection goes into the first input of `node` that
+/// accepts it, and the first compatible output of `node` goes into `input`.
+/// The graph is only changed when the whole splice can be done.
+pub fn splice_connection(
+    graph: &mut Graph,
+    input: InputId,
+    node: NodeId,
+) -> Result<ConnectionSplice> {
+    let output = graph
+        .connection(input)
+        .ok_or_else(|| anyhow!("There is no connection to insert the node in"))?;
+    if graph.get_output(output).node == node || graph[input].node == node {
+        bail!("A node can't be inserted in its own connections");
+    }
+    let wire_type = graph.get_output(output).typ.0;
+    let input_type = graph[input].typ.0;
+    let node_input = graph[node]
+        .inputs
+        .iter()
+        .map(|(_, id)| *id)
+        .find(|id| graph[*id].typ.0.accepts(wire_type))
+        .ok_or_else(|| anyhow!("The node has no input that accepts {wire_type:?} values"))?;
+    let node_output = graph[node]
+        .outputs
+        .iter()
+        .map(|(_, id)| *id)
+        .find(|id| input_type.accepts(graph.get_output(*id).typ.0))
+        .ok_or_else(|| anyhow!("The node has no output that goes into {input_type:?} inputs"))?;
+
+    let previous_node_input = graph.remove_connection(node_input);
+    graph.add_connection(output, node_input);
+    graph.add_connection(node_output, input);
+    Ok(ConnectionSplice {
+        output,
+        input,
+        node_input,
+        node_output,
+        previous_node_input,
+    })
+}
+
+impl ConnectionSplice {
+    /// Puts the connections back as they were before the splice. The inserted
+    /// node stays in the graph. Parameters deleted since the splice are
+    /// skipped.
+    pub fn undo(&self, graph: &mut Graph) {
+        let has_input = |graph: &Graph, id: InputId| graph.inputs.contains_key(id);
+        let has_output = |graph: &Graph, id: OutputId| graph.outputs.contains_key(id);
+        if has_input(graph, self.node_input) {
+            graph.remove_connection(self.node_input);
+            if let Some(previous) = self.previous_node_input {
+                if has_output(graph, previous) {
+                    graph.add_connection(previous, self.node_input);
+                }
+            }
+        }
+        if has_input(graph, self.input) && has_output(graph, self.output) {
+            graph.add_connection(self.output, self.input);
+        }
+    }
+}
+
+/// Returns the connection going into `input`, and all the connections going
+/// into the nodes upstream from it, each one once.
+pub fn upstream_connections(graph: &Graph, input: InputId) -> Vec<InputId> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![input];
+    let mut result = vec![];
+    while let Some(input) = pending.pop() {
+        if !seen.insert(input) {
+            continue;
+        }
+        if let Some(output) = graph.connection(input) {
+            result.push(input);
+            let node = graph.get_output(output).node;
+            pending.extend(graph[node].inputs.iter().map(|(_, id)| *id));
+        }
+    }
+    result
+}
+
+/// The name of the reroute node for wires of `data_type`, defined in the core
+/// node library.
+pub fn reroute_op_name(data_type: DataType) -> Option<&'static str> {
+    Some(match data_type {
+        DataType::Vector => "RerouteVector",
+        DataType::Scalar => "RerouteScalar",
+        DataType::Int => "RerouteInt",
+        DataType::Bool => "RerouteBool",
+        DataType::Selection => "RerouteSelection",
+        DataType::Mesh => "RerouteMesh",
+        DataType::Curve => "RerouteCurve",
+        DataType::Points => "ReroutePoints",
+        DataType::String => "RerouteString",
+        DataType::HeightMap => "RerouteHeightMap",
+        DataType::Scene => "RerouteScene",
+        // These can't be connected
+        DataType::VertexDeltas | DataType::VertexWeights => return None,
+    })
+}
+
+/// The state of the wire overlay of the node editor.
+#[derive(Default)]
+pub struct WireEditorState {
+    /// The wire under the mouse, identified by the input it goes into.
+    pub hovered: Option<InputId>,
+    /// The wire that was clicked. Pressing delete removes its connection.
+    pub selected: Option<InputId>,
+    /// The wire whose context menu is open, and where the menu is.
+    pub menu: Option<(InputId, egui::Pos2)>,
+    /// When the node finder was opened from the context menu of a wire, the
+    /// node created with it is inserted in that wire.
+    pub pending_splice: Option<InputId>,
+    /// The output a connection is being dragged from, as of the last frame.
+    dragged_output: Option<OutputId>,
+}
+
+impl WireEditorState {
+    /// The type of the wire a node is about to be inserted in, if any. The
+    /// node finder only lists nodes that can be inserted there.
+    pub fn pending_splice_type(&self, graph: &Graph) -> Option<DataType> {
+        let output = graph.connection(self.pending_splice?)?;
+        Some(graph.get_output(output).typ.0)
+    }
+
+    /// Inserts `node` in the pending wire, if there is one.
+    pub fn on_node_created(&mut self, graph: &mut Graph, node: NodeId) -> Option<ConnectionSplice> {
+        let input = self.pending_splice.take()?;
+        splice_connection(graph, input, node)
+            .map_err(|err| println!("Error: Could not insert the node in the wire: {err}"))
+            .ok()
+    }
+}
+
+/// How close the cursor needs to be to a wire to hover it, in points.
+const WIRE_HOVER_DISTANCE: f32 = 6.0;
+
+/// The number of segments used to test whether the cursor is over a wire.
+const WIRE_SEGMENTS: usize = 24;
+
+/// How close to an input a connection needs to be dropped to go into it, in
+/// points.
+const PORT_DROP_DISTANCE: f32 = 12.0;
+
+/// A connection, as drawn in the editor.
+struct Wire {
+    input: InputId,
+    /// The control points of the cubic bezier of the wire.
+    points: [egui::Pos2; 4],
+    color: egui::Color32,
+}
+
+impl Wire {
+    /// The same curve egui_node_graph draws for connections.
+    fn new(input: InputId, src: egui::Pos2, dst: egui::Pos2, color: egui::Color32) -> Self {
+        let control_scale = ((dst.x - src.x) / 2.0).max(30.0);
+        let points = [
+            src,
+            src + egui::vec2(control_scale, 0.0),
+            dst - egui::vec2(control_scale, 0.0),
+            dst,
+        ];
+        Self {
+            input,
+            points,
+            color,
+        }
+    }
+
+    fn point_at(&self, t: f32) -> egui::Pos2 {
+        let [p0, p1, p2, p3] = self.points.map(|p| p.to_vec2());
+        let s = 1.0 - t;
+        (p0 * s * s * s + p1 * 3.0 * s * s * t + p2 * 3.0 * s * t * t + p3 * t * t * t).to_pos2()
+    }
+
+    fn distance_to(&self, pos: egui::Pos2) -> f32 {
+        (0..WIRE_SEGMENTS)
+            .map(|i| {
+                let a = self.point_at(i as f32 / WIRE_SEGMENTS as f32);
+                let b = self.point_at((i + 1) as f32 / WIRE_SEGMENTS as f32);
+                let ab = b - a;
+                let t = ((pos - a).dot(ab) / ab.length_sq().max(1e-6)).clamp(0.0, 1.0);
+                pos.distance(a + ab * t)
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    fn draw(&self, painter: &egui::Painter, width: f32, color: egui::Color32) {
+        painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+            self.points,
+            false,
+            egui::Color32::TRANSPARENT,
+            egui::Stroke::new(width, color),
+        ));
+    }
+}
+
+/// Makes a color closer to white.
+fn highlight(color: egui::Color32) -> egui::Color32 {
+    let mix = |c: u8| c + (255 - c) / 2;
+    egui::Color32::from_rgb(mix(color.r()), mix(color.g()), mix(color.b()))
+}
+
+/// Returns the position of the ports of a node, relative to its top left
+/// corner. Like node sizes, they are estimated: Inputs go on the left, above
+/// the outputs, which go on the right.
+fn estimated_port_offset(index: usize, is_output: bool) -> egui::Vec2 {
+    egui::vec2(
+        if is_output { NODE_WIDTH } else { 0.0 },
+        NODE_HEADER_HEIGHT + NODE_PARAM_HEIGHT * (index as f32 + 0.5),
+    )
+}
+
+/// Returns the input a connection from `output` dropped at `cursor` goes into,
+/// if any. Only inputs of a different type that accept the output are looked
+/// at: egui_node_graph connects ports of the same type by itself, but not a
+/// curve going into a mesh input, for instance.
+fn lenient_drop_target(
+    editor_state: &GraphEditorState,
+    output: OutputId,
+    cursor: egui::Pos2,
+    origin: egui::Vec2,
+) -> Option<InputId> {
+    let graph = &editor_state.graph;
+    let output_type = graph.get_output(output).typ.0;
+    let src_node = graph.get_output(output).node;
+    editor_state
+        .node_positions
+        .iter()
+        .filter(|(node, _)| *node != src_node && graph.nodes.contains_key(*node))
+        .flat_map(|(node, pos)| {
+            graph[node]
+                .inputs
+                .iter()
+                .enumerate()
+                .map(move |(index, (_, input))| {
+                    let port = *pos + origin + estimated_port_offset(index, false);
+                    (*input, port.distance(cursor))
+                })
+        })
+        .filter(|(input, distance)| {
+            let input_type = graph[*input].typ.0;
+            *distance < PORT_DROP_DISTANCE
+                && input_type != output_type
+                && input_type.accepts(output_type)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(input, _)| input)
+}
+
+/// Returns the wires of all the connections in the graph, in screen space.
+fn wires(
+    editor_state: &GraphEditorState,
+    custom_state: &mut CustomGraphState,
+    origin: egui::Vec2,
+) -> Vec<Wire> {
+    let graph = &editor_state.graph;
+    let node_pos = |node: NodeId| {
+        editor_state
+            .node_positions
+            .get(node)
+            .map(|pos| *pos + origin)
+    };
+    graph
+        .connections
+        .iter()
+        .filter_map(|(input, output)| {
+            let dst_node = graph[input].node;
+            let src_node = graph.get_output(*output).node;
+            let input_index = graph[dst_node]
+                .inputs
+                .iter()
+                .position(|(_, i)| *i == input)?;
+            let output_index = graph[src_node]
+                .outputs
+                .iter()
+                .position(|(_, o)| o == output)?;
+            let src = node_pos(src_node)?
+                + estimated_port_offset(graph[src_node].inputs.len() + output_index, true);
+            let dst = node_pos(dst_node)? + estimated_port_offset(input_index, false);
+            let color = graph.get_output(*output).typ.data_type_color(custom_state);
+            Some(Wire::new(input, src, dst, color))
+        })
+        .collect()
+}
+
+/// Creates a reroute node for the wire going into `input`, at `pos` in screen
+/// space, and inserts it in the wire.
+fn insert_reroute(
+    editor_state: &mut GraphEditorState,
+    custom_state: &mut CustomGraphState,
+    input: InputId,
+    pos: egui::Pos2,
+    origin: egui::Vec2,
+) -> Result<ConnectionSplice> {
+    let graph = &editor_state.graph;
+    let output = graph
+        .connection(input)
+        .ok_or_else(|| anyhow!("The wire is not connected"))?;
+    let data_type = graph.get_output(output).typ.0;
+    let op_name = reroute_op_name(data_type)
+        .filter(|op_name| custom_state.node_definitions.node_def(op_name).is_some())
+        .ok_or_else(|| anyhow!("There is no reroute node for {data_type:?} wires"))?;
+
+    let template = NodeOpName(op_name.into());
+    let label = template.node_graph_label(custom_state);
+    let user_data = template.user_data(custom_state);
+    let node = editor_state
+        .graph
+        .add_node(label, user_data, |graph, node_id| {
+            template.build_node(graph, custom_state, node_id)
+        });
+    // Puts the input port of the reroute under the cursor
+    let offset = estimated_port_offset(0, false);
+    editor_state
+        .node_positions
+        .insert(node, pos - origin - offset);
+    editor_state.node_order.push(node);
+    splice_connection(&mut editor_state.graph, input, node)
+}
+
+/// Draws the hovered and selected wires on top of the graph, and handles
+/// selecting, deleting and inserting nodes in wires. Must be called after
+/// egui_node_graph draws the graph editor in the same `ui`. Returns the splice
+/// done this frame, if any.
+pub fn wire_editor_ui(
+    ui: &mut egui::Ui,
+    editor_state: &mut GraphEditorState,
+    custom_state: &mut CustomGraphState,
+    state: &mut WireEditorState,
+) -> Option<ConnectionSplice> {
+    let origin = editor_state.pan_zoom.pan + ui.max_rect().min.to_vec2();
+    let cursor = ui.input().pointer.hover_pos();
+
+    // The connection in progress is gone on the frame it's dropped
+    let dragged_output = match editor_state.connection_in_progress {
+        Some((_, AnyParameterId::Output(output))) => Some(output),
+        _ => None,
+    };
+    if let (Some(output), None, Some(cursor)) = (state.dragged_output, dragged_output, cursor) {
+        if editor_state.graph.outputs.contains_key(output) {
+            if let Some(input) = lenient_drop_target(editor_state, output, cursor, origin) {
+                editor_state.graph.add_connection(output, input);
+            }
+        }
+    }
+    state.dragged_output = dragged_output;
+
+    let wires = wires(editor_state, custom_state, origin);
+    let graph = &editor_state.graph;
+
+    // Wires don't get hovered through nodes
+    let over_node = cursor.map_or(false, |cursor| {
+        editor_state.node_positions.iter().any(|(node, pos)| {
+            if !graph.nodes.contains_key(node) {
+                return false;
+            }
+            let size = estimated_node_size(&graph[node]);
+            egui::Rect::from_min_size(*pos + origin, egui::vec2(size.x, size.y)).contains(cursor)
+        })
+    });
+    state.hovered = cursor
+        .filter(|_| !over_node && editor_state.connection_in_progress.is_none())
+        .and_then(|cursor| {
+            wires
+                .iter()
+                .map(|wire| (wire.input, wire.distance_to(cursor)))
+                .filter(|(_, distance)| *distance < WIRE_HOVER_DISTANCE)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(input, _)| input)
+        });
+    if state
+        .selected
+        .map_or(false, |i| !graph.connections.contains_key(i))
+    {
+        state.selected = None;
+    }
+
+    // The hovered wire lights up along with everything upstream of it
+    let painter = ui.painter();
+    let highlighted = state
+        .hovered
+        .map(|input| upstream_connections(graph, input))
+        .unwrap_or_default();
+    for wire in &wires {
+        if state.selected == Some(wire.input) {
+            wire.draw(painter, 8.0, egui::Color32::WHITE);
+            wire.draw(painter, 5.0, wire.color);
+        } else if highlighted.contains(&wire.input) {
+            wire.draw(painter, 6.0, highlight(wire.color));
+        }
+    }
+
+    let (primary_clicked, secondary_clicked, delete) = {
+        let input = ui.input();
+        (
+            input.pointer.primary_clicked(),
+            input.pointer.button_clicked(egui::PointerButton::Secondary),
+            input.key_pressed(egui::Key::Delete),
+        )
+    };
+    if primary_clicked && state.menu.is_none() {
+        state.selected = state.hovered;
+    }
+    if let (true, Some(input)) = (secondary_clicked, state.hovered) {
+        state.menu = cursor.map(|cursor| (input, cursor));
+        // Right clicking on the background opens the node finder otherwise
+        editor_state.node_finder = None;
+    }
+    if let (true, Some(input)) = (delete, state.selected.take()) {
+        editor_state.graph.remove_connection(input);
+    }
+    // The finder was closed without creating a node
+    if editor_state.node_finder.is_none() {
+        state.pending_splice = None;
+    }
+
+    let mut splice = None;
+    if let Some((input, pos)) = state.menu {
+        let mut close = false;
+        let response = egui::Area::new("wire_context_menu")
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    if ui.button("Insert reroute here").clicked() {
+                        match insert_reroute(editor_state, custom_state, input, pos, origin) {
+                            Ok(new_splice) => splice = Some(new_splice),
+                            Err(err) => println!("Error: Could not insert a reroute: {err}"),
+                        }
+                        close = true;
+                    }
+                    if ui.button("Insert node...").clicked() {
+                        editor_state.node_finder = Some(NodeFinder::new_at(pos));
+                        state.pending_splice = Some(input);
+                        close = true;
+                    }
+                })
+            })
+            .response;
+        if close
+            || ui.input().key_pressed(egui::Key::Escape)
+            || (response.clicked_elsewhere() && !secondary_clicked)
+        {
+            state.menu = None;
+        }
+    }
+    splice
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::graph::{DataTypeUi, NodeData, ValueTypeUi};
+    use egui_node_graph::InputParamKind;
+
+    /// Adds a node with one input and one output of each of the given types.
+    fn add_node(graph: &mut Graph, inputs: &[DataType], outputs: &[DataType]) -> NodeId {
+        let node_data = NodeData {
+            op_name: "Test".into(),
+            seed: 0,
+        };
+        graph.add_node("Test".into(), node_data, |graph, node_id| {
+            for (i, data_type) in inputs.iter().enumerate() {
+                graph.add_input_param(
+                    node_id,
+                    format!("in_{i}"),
+                    DataTypeUi(*data_type),
+                    ValueTypeUi::default(),
+                    InputParamKind::ConnectionOnly,
+                    true,
+                );
+            }
+            for (i, data_type) in outputs.iter().enumerate() {
+                graph.add_output_param(node_id, format!("out_{i}"), DataTypeUi(*data_type));
+            }
+        })
+    }
+
+    fn input(graph: &Graph, node: NodeId, index: usize) -> InputId {
+        graph[node].inputs[index].1
+    }
+
+    fn output(graph: &Graph, node: NodeId, index: usize) -> OutputId {
+        graph[node].outputs[index].1
+    }
+
+    /// A mesh going from a source node into a sink node.
+    fn connected_pair(graph: &mut Graph) -> (NodeId, NodeId) {
+        let src = add_node(graph, &[], &[DataType::Mesh]);
+        let dst = add_node(graph, &[DataType::Mesh], &[]);
+        let (src_output, dst_input) = (output(graph, src, 0), input(graph, dst, 0));
+        graph.add_connection(src_output, dst_input);
+        (src, dst)
+    }
+
+    #[test]
+    fn test_splice_connection() {
+        let mut graph = Graph::new();
+        let (src, dst) = connected_pair(&mut graph);
+        // The first parameters don't match, so the node is connected through
+        // its second ones.
+        let node = add_node(
+            &mut graph,
+            &[DataType::Scalar, DataType::Mesh],
+            &[DataType::Vector, DataType::Mesh],
+        );
+        let dst_input = input(&graph, dst, 0);
+        let splice = splice_connection(&mut graph, dst_input, node).unwrap();
+        assert_eq!(
+            graph.connection(input(&graph, node, 1)),
+            Some(output(&graph, src, 0))
+        );
+        assert_eq!(
+            graph.connection(input(&graph, dst, 0)),
+            Some(output(&graph, node, 1))
+        );
+        assert_eq!(graph.connections.len(), 2);
+
+        splice.undo(&mut graph);
+        assert_eq!(
+            graph.connection(input(&graph, dst, 0)),
+            Some(output(&graph, src, 0))
+        );
+        assert_eq!(graph.connections.len(), 1);
+    }
+
+    #[test]
+    fn test_splice_undo_restores_previous_connections() {
+        let mut graph = Graph::new();
+        let (_, dst) = connected_pair(&mut graph);
+        let other = add_node(&mut graph, &[], &[DataType::Mesh]);
+        let node = add_node(&mut graph, &[DataType::Mesh], &[DataType::Mesh]);
+        graph.add_connection(output(&graph, other, 0), input(&graph, node, 0));
+
+        let dst_input = input(&graph, dst, 0);
+        let splice = splice_connection(&mut graph, dst_input, node).unwrap();
+        splice.undo(&mut graph);
+        assert_eq!(
+            graph.connection(input(&graph, node, 0)),
+            Some(output(&graph, other, 0))
+        );
+    }
+
+    #[test]
+    fn test_splice_is_atomic() {
+        let mut graph = Graph::new();
+        let (_, dst) = connected_pair(&mut graph);
+        let before = graph.connections.clone();
+
+        // Accepts meshes, but outputs something else
+        let node = add_node(&mut graph, &[DataType::Mesh], &[DataType::Scalar]);
+        let dst_input = input(&graph, dst, 0);
+        assert!(splice_connection(&mut graph, dst_input, node).is_err());
+        assert_eq!(graph.connections, before);
+
+        // Not connected
+        let node = add_node(&mut graph, &[DataType::Mesh], &[DataType::Mesh]);
+        let node_input = input(&graph, node, 0);
+        assert!(splice_connection(&mut graph, node_input, dst).is_err());
+        assert_eq!(graph.connections, before);
+    }
+
+    #[test]
+    fn test_splice_converts_ints() {
+        let mut graph = Graph::new();
+        let src = add_node(&mut graph, &[], &[DataType::Int]);
+        let dst = add_node(&mut graph, &[DataType::Scalar], &[]);
+        graph.add_connection(output(&graph, src, 0), input(&graph, dst, 0));
+        // Integers go into scalar inputs, and scalars into the scalar input
+        let node = add_node(&mut graph, &[DataType::Scalar], &[DataType::Scalar]);
+        let dst_input = input(&graph, dst, 0);
+        assert!(splice_connection(&mut graph, dst_input, node).is_ok());
+    }
+
+    #[test]
+    fn test_lenient_drop_target() {
+        let mut editor_state = GraphEditorState::new(1.0);
+        let graph = &mut editor_state.graph;
+        let curve = add_node(graph, &[], &[DataType::Curve]);
+        let mesh = add_node(graph, &[], &[DataType::Mesh]);
+        let sink = add_node(graph, &[DataType::Curve, DataType::Mesh], &[]);
+        let (curve_output, mesh_output) = (output(graph, curve, 0), output(graph, mesh, 0));
+        let mesh_input = input(graph, sink, 1);
+        for (node, x) in [(curve, 0.0), (mesh, 0.0), (sink, 400.0)] {
+            editor_state.node_positions.insert(node, egui::pos2(x, 0.0));
+        }
+        let port = |index| egui::pos2(400.0, 0.0) + estimated_port_offset(index, false);
+        let target =
+            |output, cursor| lenient_drop_target(&editor_state, output, cursor, egui::Vec2::ZERO);
+
+        // Curves go into mesh inputs, dropped close enough
+        assert_eq!(target(curve_output, port(1)), Some(mesh_input));
+        assert_eq!(
+            target(curve_output, port(1) + egui::vec2(5.0, 5.0)),
+            Some(mesh_input)
+        );
+        assert_eq!(target(curve_output, port(1) + egui::vec2(0.0, 30.0)), None);
+        // egui_node_graph already connects the ports of the same type
+        assert_eq!(target(curve_output, port(0)), None);
+        // Meshes need a conversion to go into curve inputs
+        assert_eq!(target(mesh_output, port(0)), None);
+    }
+
+    #[test]
+    fn test_upstream_connections() {
+        let mut graph = Graph::new();
+        let (src, dst) = connected_pair(&mut graph);
+        let node = add_node(&mut graph, &[DataType::Mesh], &[DataType::Mesh]);
+        let dst_input = input(&graph, dst, 0);
+        splice_connection(&mut graph, dst_input, node).unwrap();
+        let unrelated = add_node(&mut graph, &[DataType::Mesh], &[]);
+        graph.add_connection(output(&graph, src, 0), input(&graph, unrelated, 0));
+
+        let upstream = upstream_connections(&graph, input(&graph, dst, 0));
+        assert_eq!(
+            upstream,
+            vec![input(&graph, dst, 0), input(&graph, node, 0)]
+        );
+        let unrelated_input = input(&graph, unrelated, 0);
+        assert_eq!(
+            upstream_connections(&graph, unrelated_input),
+            vec![unrelated_input]
+        );
+    }
+}
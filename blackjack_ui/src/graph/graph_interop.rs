@@ -7,14 +7,15 @@
 use std::ops::Index;
 
 use super::node_graph::{
-    data_type_to_input_param_kind, default_shown_inline, CustomGraphState, DataTypeUi, Graph,
-    NodeData, ValueTypeUi,
+    data_type_to_input_param_kind, default_shown_inline, unused_node_seed, CustomGraphState,
+    DataTypeUi, Graph, NodeData, PickedFrom, ValueTypeUi,
 };
 
 use crate::prelude::*;
 use blackjack_engine::{
     graph::{
         BjkGraph, BjkNode, BjkNodeId, BjkSnippet, BlackjackValue, DependencyKind, NodeDefinitions,
+        PickedSelection,
     },
     graph_interpreter::{ExternalParameter, ExternalParameterValues},
 };
@@ -34,6 +35,10 @@ impl NodeMapping {
         self.0.insert(node_id, bjk_node_id);
         self.1.insert(bjk_node_id, node_id);
     }
+    /// Returns the engine node for `node_id`, if it is in the mapping.
+    pub fn get(&self, node_id: NodeId) -> Option<BjkNodeId> {
+        self.0.get(node_id).copied()
+    }
 }
 impl Index<NodeId> for NodeMapping {
     type Output = BjkNodeId;
@@ -65,6 +70,13 @@ pub fn ui_graph_to_blackjack_graph(
 
         let bjk_id = bjk_graph.add_node(node.user_data.op_name.clone(), node_def.returns.clone());
         mapping.insert(node_id, bjk_id);
+        bjk_graph.nodes[bjk_id].seed = node.user_data.seed;
+        // Nodes that couldn't be migrated keep the version they were loaded
+        // with, so they are reported again the next time.
+        bjk_graph.nodes[bjk_id].version = match custom_state.node_version_warnings.get(&node_id) {
+            Some(warning) => warning.saved_version,
+            None => node_def.version,
+        };
 
         for (input_name, input_id) in &node.inputs {
             bjk_graph.add_input(
@@ -91,7 +103,33 @@ pub fn ui_graph_to_blackjack_graph(
         bjk_graph.add_connection(output_node_id, output_name, input_node_id, input_name)?;
     }
 
+    // Picked selections may outlive their parameter, e.g. when node
+    // definitions are reloaded. Those are ignored.
+    for ((node_id, input_name), picked) in &custom_state.picked_selections {
+        let has_input = graph.nodes.get(*node_id).map_or(false, |node| {
+            node.inputs.iter().any(|(name, _)| name == input_name)
+        });
+        if let (true, Some(bjk_id), Some(picked_node)) = (
+            has_input,
+            mapping.0.get(*node_id),
+            mapping.0.get(picked.node),
+        ) {
+            bjk_graph.set_picked_from(
+                *bjk_id,
+                input_name,
+                Some(PickedSelection {
+                    node: *picked_node,
+                    kind: picked.kind,
+                }),
+            )?;
+        }
+    }
+
     bjk_graph.default_node = custom_state.active_node.map(|x| mapping[x]);
+    bjk_graph.seed = custom_state.graph_seed;
+    bjk_graph.materials = custom_state.materials.clone();
+    bjk_graph.units = custom_state.units;
+    bjk_graph.file_path = custom_state.file_path.clone();
 
     Ok((bjk_graph, mapping))
 }
@@ -111,6 +149,7 @@ pub fn add_ui_node_from_bjk_node(
         },
         NodeData {
             op_name: bjk_node.op_name.clone(),
+            seed: bjk_node.seed,
         },
         |_, _| { /* Params added later */ },
     );
@@ -208,6 +247,11 @@ pub fn blackjack_graph_to_ui_graph(
     let BjkGraph {
         nodes: bjk_nodes,
         default_node: _,
+        // Restored along with the rest of the custom state
+        seed: _,
+        materials: _,
+        units: _,
+        file_path: _,
     } = bjk_graph;
 
     // Fill in the nodes in a first pass
@@ -256,9 +300,13 @@ pub fn append_snippet_to_existing_ui_graph(
     // functions separate to accomodate for potential future differences. All
     // the common bits have already been refactored into functions.
 
-    // Add new nodes in a first pass
+    // Add new nodes in a first pass. Pasted nodes get new seeds, so they
+    // don't produce the same random values as the nodes they were copied
+    // from.
     for (bjk_node_id, bjk_node) in &snippet.nodes {
         add_ui_node_from_bjk_node(graph, bjk_node_id, bjk_node, &mut mapping, node_definitions);
+        let new_id = mapping[bjk_node_id];
+        graph[new_id].user_data.seed = unused_node_seed(graph);
     }
 
     // Then, define inputs / outputs in a second pass.
@@ -281,6 +329,34 @@ pub fn append_snippet_to_existing_ui_graph(
     mapping
 }
 
+/// Returns the picked selections of the given blackjack `nodes`, keyed by the
+/// ui node and parameter name. Selections picked against nodes that are not
+/// in the mapping are skipped.
+pub fn picked_selections_from_bjk_nodes<'a>(
+    nodes: impl IntoIterator<Item = (BjkNodeId, &'a BjkNode)>,
+    mapping: &NodeMapping,
+) -> Vec<((NodeId, String), PickedFrom)> {
+    let mut picked_selections = vec![];
+    for (bjk_node_id, bjk_node) in nodes {
+        for bjk_input in &bjk_node.inputs {
+            if let Some(picked) = &bjk_input.picked_from {
+                if let (Some(node_id), Some(picked_node)) =
+                    (mapping.1.get(bjk_node_id), mapping.1.get(picked.node))
+                {
+                    picked_selections.push((
+                        (*node_id, bjk_input.name.clone()),
+                        PickedFrom {
+                            node: *picked_node,
+                            kind: picked.kind,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    picked_selections
+}
+
 pub fn extract_graph_params(
     graph: &Graph,
     bjk_graph: &BjkGraph,
@@ -0,0 +1,272 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A layered, left-to-right layout for node graphs, in the style of
+//! Sugiyama's method:
+//!
+//! - Nodes are assigned a rank by their longest path from the roots of the
+//!   graph. Each rank is a column of the layout.
+//! - Nodes are reordered within their rank to reduce the number of crossing
+//!   wires, moving each node to the median position of its neighbours.
+//! - Columns are placed next to each other, with nodes stacked vertically.
+//!
+//! The layout works on plain node sizes and edges, so it doesn't depend on
+//! egui.
+
+use crate::prelude::*;
+
+/// How many times the nodes are reordered, alternating downstream and
+/// upstream passes.
+const ORDERING_SWEEPS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutSettings {
+    /// The space between two columns of nodes.
+    pub horizontal_spacing: f32,
+    /// The space between two nodes in the same column.
+    pub vertical_spacing: f32,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            horizontal_spacing: 80.0,
+            vertical_spacing: 30.0,
+        }
+    }
+}
+
+/// Computes a layout for nodes of the given `sizes`, where each edge `(a, b)`
+/// is a wire going from the output of node `a` to an input of node `b`.
+/// Returns the top left corner of each node, with the layout starting at the
+/// origin.
+///
+/// Nodes keep their relative order in `sizes` when there's nothing better to
+/// do, so callers should sort them by their current position.
+pub fn layered_layout(
+    sizes: &[Vec2],
+    edges: &[(usize, usize)],
+    settings: &LayoutSettings,
+) -> Vec<Vec2> {
+    let ranks = compute_ranks(sizes.len(), edges);
+    let mut layers = vec![Vec::new(); ranks.iter().max().map_or(0, |r| r + 1)];
+    for (node, rank) in ranks.iter_cpy().enumerate() {
+        layers[rank].push(node);
+    }
+    reduce_crossings(&mut layers, sizes.len(), edges);
+
+    let mut positions = vec![Vec2::ZERO; sizes.len()];
+    let column_heights = layers
+        .iter()
+        .map(|layer| {
+            layer.iter().map(|n| sizes[*n].y).sum::<f32>()
+                + settings.vertical_spacing * layer.len().saturating_sub(1) as f32
+        })
+        .collect_vec();
+    let max_height = column_heights.iter_cpy().fold(0.0, f32::max);
+
+    let mut x = 0.0;
+    for (layer, height) in layers.iter().zip(column_heights) {
+        // Columns are centered vertically
+        let mut y = (max_height - height) * 0.5;
+        let mut width: f32 = 0.0;
+        for node in layer.iter_cpy() {
+            positions[node] = Vec2::new(x, y);
+            y += sizes[node].y + settings.vertical_spacing;
+            width = width.max(sizes[node].x);
+        }
+        x += width + settings.horizontal_spacing;
+    }
+    positions
+}
+
+/// Returns the rank of each node: The length of the longest path reaching it
+/// from a node with no incoming edges. Edges closing a cycle are ignored.
+fn compute_ranks(num_nodes: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut in_degree = vec![0; num_nodes];
+    for (_, dst) in edges {
+        in_degree[*dst] += 1;
+    }
+    let mut ranks = vec![0; num_nodes];
+    let mut done = vec![false; num_nodes];
+    let mut ready = (0..num_nodes).filter(|n| in_degree[*n] == 0).collect_vec();
+    loop {
+        while let Some(node) = ready.pop() {
+            done[node] = true;
+            for (_, dst) in edges.iter().filter(|(src, _)| *src == node) {
+                if done[*dst] {
+                    // An edge closing a cycle
+                    continue;
+                }
+                ranks[*dst] = ranks[*dst].max(ranks[node] + 1);
+                in_degree[*dst] -= 1;
+                if in_degree[*dst] == 0 {
+                    ready.push(*dst);
+                }
+            }
+        }
+        // Only nodes in a cycle are left. Break it at the first one.
+        match (0..num_nodes).find(|n| !done[*n]) {
+            Some(node) => {
+                in_degree[node] = 0;
+                ready.push(node);
+            }
+            None => break,
+        }
+    }
+    ranks
+}
+
+/// Reorders the nodes in each layer using the median heuristic. Each pass
+/// sorts the nodes of a layer by the median position of their neighbours in
+/// the previous layers, going downstream, or in the next ones going upstream.
+/// With an even number of neighbours, the two middle positions are averaged.
+fn reduce_crossings(layers: &mut [Vec<usize>], num_nodes: usize, edges: &[(usize, usize)]) {
+    // The position of each node in its layer, normalized to [0, 1] so that
+    // positions in layers of different sizes can be compared.
+    let mut position = vec![0.0; num_nodes];
+    let update_positions = |layer: &[usize], position: &mut Vec<f32>| {
+        for (idx, node) in layer.iter_cpy().enumerate() {
+            position[node] = (idx as f32 + 0.5) / layer.len() as f32;
+        }
+    };
+    for layer in layers.iter() {
+        update_positions(layer, &mut position);
+    }
+
+    for sweep in 0..ORDERING_SWEEPS {
+        let downstream = sweep % 2 == 0;
+        let layer_indices = if downstream {
+            (1..layers.len()).collect_vec()
+        } else {
+            (0..layers.len().saturating_sub(1)).rev().collect_vec()
+        };
+        for layer_idx in layer_indices {
+            let layer = &mut layers[layer_idx];
+            let medians = layer
+                .iter_cpy()
+                .map(|node| {
+                    let mut neighbours = edges
+                        .iter()
+                        .filter_map(|(src, dst)| match downstream {
+                            true if *dst == node => Some(position[*src]),
+                            false if *src == node => Some(position[*dst]),
+                            _ => None,
+                        })
+                        .collect_vec();
+                    neighbours.sort_by(f32::total_cmp);
+                    let mid = neighbours.len() / 2;
+                    let median = match neighbours.len() {
+                        // Nodes without neighbours on this side stay where
+                        // they are
+                        0 => position[node],
+                        n if n % 2 == 0 => (neighbours[mid - 1] + neighbours[mid]) * 0.5,
+                        _ => neighbours[mid],
+                    };
+                    (node, median)
+                })
+                .collect::<HashMap<_, _>>();
+            // The sort is stable, so ties keep their current order
+            layer.sort_by(|a, b| medians[a].total_cmp(&medians[b]));
+            update_positions(layer, &mut position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counts the pairs of edges between adjacent layers that cross.
+    fn count_crossings(positions: &[Vec2], edges: &[(usize, usize)]) -> usize {
+        edges
+            .iter()
+            .tuple_combinations()
+            .filter(|((a1, b1), (a2, b2))| {
+                let same_columns =
+                    positions[*a1].x == positions[*a2].x && positions[*b1].x == positions[*b2].x;
+                let up = positions[*a1].y - positions[*a2].y;
+                let down = positions[*b1].y - positions[*b2].y;
+                same_columns && up * down < 0.0
+            })
+            .count()
+    }
+
+    /// A small procedural model: Two primitives merged, with a few operations
+    /// on each branch, and a parameter node feeding both branches.
+    fn fixture() -> (Vec<Vec2>, Vec<(usize, usize)>) {
+        let sizes = vec![
+            Vec2::new(200.0, 120.0), // 0: Box
+            Vec2::new(180.0, 80.0),  // 1: Bevel
+            Vec2::new(220.0, 160.0), // 2: Cylinder
+            Vec2::new(200.0, 100.0), // 3: Extrude
+            Vec2::new(150.0, 60.0),  // 4: Merge
+            Vec2::new(160.0, 90.0),  // 5: Make vector
+            Vec2::new(200.0, 140.0), // 6: Export
+            Vec2::new(100.0, 50.0),  // 7: Unconnected comment
+        ];
+        let edges = vec![(0, 1), (2, 3), (1, 4), (3, 4), (5, 3), (5, 0), (4, 6)];
+        (sizes, edges)
+    }
+
+    #[test]
+    fn test_no_overlaps() {
+        let (sizes, edges) = fixture();
+        let settings = LayoutSettings::default();
+        let positions = layered_layout(&sizes, &edges, &settings);
+        for (a, b) in (0..sizes.len()).tuple_combinations() {
+            let (min_a, max_a) = (positions[a], positions[a] + sizes[a]);
+            let (min_b, max_b) = (positions[b], positions[b] + sizes[b]);
+            let overlaps =
+                min_a.x < max_b.x && min_b.x < max_a.x && min_a.y < max_b.y && min_b.y < max_a.y;
+            assert!(!overlaps, "Nodes {a} and {b} overlap");
+        }
+        // Layouts start at the origin
+        let min = positions
+            .iter()
+            .fold(Vec2::splat(f32::MAX), |acc, p| acc.min(*p));
+        assert_eq!(min, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_ranks_go_left_to_right() {
+        let (sizes, edges) = fixture();
+        let settings = LayoutSettings::default();
+        let positions = layered_layout(&sizes, &edges, &settings);
+        for (src, dst) in &edges {
+            assert!(
+                positions[*src].x + sizes[*src].x + settings.horizontal_spacing
+                    <= positions[*dst].x,
+                "Edge {src} -> {dst} goes backwards"
+            );
+        }
+        // Longest path ranks: the box comes after the vector it depends on
+        assert_eq!(
+            compute_ranks(sizes.len(), &edges),
+            vec![1, 2, 0, 1, 3, 0, 4, 0]
+        );
+    }
+
+    #[test]
+    fn test_reduce_crossings() {
+        // Two chains whose second nodes are given in the wrong order
+        let sizes = vec![Vec2::new(100.0, 50.0); 4];
+        let edges = vec![(0, 3), (1, 2)];
+        let positions = layered_layout(&sizes, &edges, &LayoutSettings::default());
+        assert_eq!(count_crossings(&positions, &edges), 0);
+
+        let (sizes, edges) = fixture();
+        let positions = layered_layout(&sizes, &edges, &LayoutSettings::default());
+        assert_eq!(count_crossings(&positions, &edges), 0);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let sizes = vec![Vec2::new(100.0, 50.0); 3];
+        let positions = layered_layout(&sizes, &[(0, 1), (1, 2), (2, 1)], &Default::default());
+        assert_eq!(positions.len(), 3);
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::graph::node_docs::NodeDocs;
+use blackjack_engine::graph::{InputDefinition, NodeDefinitions};
+use egui::RichText;
+use egui_node_graph::DataTypeTrait;
+
+use crate::graph::node_graph::{CustomGraphState, DataTypeUi, GraphEditorState};
+use crate::prelude::*;
+
+/// The width of the description shown next to the node finder.
+const FINDER_PREVIEW_WIDTH: f32 = 260.0;
+
+/// Returns the op name of the node the finder creates when pressing enter:
+/// The first one of `names` whose label contains the `query`, regardless of
+/// case. This is the same search the finder does.
+pub fn finder_first_match<'a>(
+    node_definitions: &NodeDefinitions,
+    names: &'a [String],
+    query: &str,
+) -> Option<&'a str> {
+    let query = query.to_lowercase();
+    names
+        .iter()
+        .find(|name| {
+            matches!(
+                node_definitions.node_def(name),
+                Some(def) if def.label.to_lowercase().contains(&query)
+            )
+        })
+        .map(String::as_str)
+}
+
+/// Shows the summary of the first match of the node finder next to it, while
+/// it's open. The finder doesn't tell which entry is hovered, so the summary
+/// is the one of the node that pressing enter would create.
+pub fn finder_preview_ui(
+    ctx: &egui::Context,
+    editor_state: &GraphEditorState,
+    custom_state: &CustomGraphState,
+    finder_node_names: &[String],
+) {
+    let finder = match &editor_state.node_finder {
+        Some(finder) => finder,
+        None => return,
+    };
+    let (anchor, query) = match finder.position {
+        Some(position) => (position, &finder.query),
+        None => return,
+    };
+    let node_definitions = &custom_state.node_definitions;
+    let node_def = finder_first_match(node_definitions, finder_node_names, query)
+        .and_then(|op_name| node_definitions.node_def(op_name));
+    let node_def = match node_def {
+        Some(node_def) => node_def,
+        None => return,
+    };
+    let summary = match node_def.docs.summary() {
+        Some(summary) => summary,
+        None => return,
+    };
+
+    // To the left of the finder, which grows to the right and downwards
+    let pos = egui::pos2((anchor.x - FINDER_PREVIEW_WIDTH - 16.0).max(0.0), anchor.y);
+    egui::Area::new("node_finder_preview")
+        .order(egui::Order::Foreground)
+        .fixed_pos(pos)
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_max_width(FINDER_PREVIEW_WIDTH);
+                ui.label(RichText::new(&node_def.label).strong());
+                ui.label(summary);
+                ui.label(RichText::new("Enter creates this node").weak());
+            });
+        });
+}
+
+/// The window with the full documentation of the node definition in
+/// [`CustomGraphState::docs_node`]: Its description, its inputs, and the Lua
+/// API functions it mentions. Clicking "Open example" sets
+/// [`CustomGraphState::open_example`].
+pub fn docs_window_ui(ctx: &egui::Context, custom_state: &mut CustomGraphState) {
+    let op_name = match &custom_state.docs_node {
+        Some(op_name) => op_name.clone(),
+        None => return,
+    };
+    let mut open = true;
+    let mut open_example = None;
+    egui::Window::new("Node docs")
+        .open(&mut open)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            let node_def = match custom_state.node_definitions.node_def(&op_name) {
+                Some(node_def) => node_def,
+                None => {
+                    ui.label(format!("There is no node definition for '{op_name}'"));
+                    return;
+                }
+            };
+            ui.heading(&node_def.label);
+            ui.label(RichText::new(&node_def.op_name).monospace().weak());
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                docs_ui(ui, &node_def.docs, &node_def.inputs);
+            });
+            if let Some(example) = &node_def.docs.example {
+                ui.separator();
+                if ui
+                    .button("📖 Open example")
+                    .on_hover_text("Opens a small graph using this node, without closing this one")
+                    .clicked()
+                {
+                    open_example = Some(example.clone());
+                }
+            }
+        });
+    if !open {
+        custom_state.docs_node = None;
+    }
+    if open_example.is_some() {
+        custom_state.open_example = open_example;
+    }
+}
+
+fn docs_ui(ui: &mut egui::Ui, docs: &NodeDocs, inputs: &[InputDefinition]) {
+    match &docs.description {
+        Some(description) => {
+            for paragraph in description.split("\n\n") {
+                ui.label(paragraph);
+            }
+        }
+        None => {
+            ui.label(RichText::new("This node has no description").weak());
+        }
+    }
+
+    if !inputs.is_empty() {
+        ui.separator();
+        ui.label(RichText::new("Inputs").strong());
+        egui::Grid::new("node_docs_inputs")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for input in inputs {
+                    ui.label(RichText::new(&input.name).monospace());
+                    ui.label(RichText::new(DataTypeUi(input.data_type).name()).weak());
+                    ui.label(docs.input_doc(&input.name).unwrap_or("—"));
+                    ui.end_row();
+                }
+            });
+    }
+
+    let api = docs.referenced_api();
+    if !api.is_empty() {
+        ui.separator();
+        ui.label(RichText::new("Lua API").strong());
+        for function in api {
+            let params = function
+                .params
+                .iter()
+                .map(|(name, typ)| format!("{name}: {typ}"))
+                .join(", ");
+            ui.label(RichText::new(format!("{}({params})", function.name)).monospace());
+            ui.indent(&function.name, |ui| ui.label(&function.description));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blackjack_engine::lua_engine::LuaRuntime;
+
+    #[test]
+    fn test_finder_first_match() {
+        let runtime = LuaRuntime::initialize_with_std("../blackjack_lua".into()).unwrap();
+        let defs = &runtime.node_definitions;
+        let names = defs.finder_node_names(None);
+        let label = |op_name: &str| defs.node_def(op_name).unwrap().label.clone();
+
+        let extrude = finder_first_match(defs, &names, "xtrude fa").unwrap();
+        assert_eq!(label(extrude), "Extrude Faces");
+        // Like the finder, the first match comes first in its list
+        let first = finder_first_match(defs, &names, "").unwrap();
+        assert_eq!(first, names[0]);
+        assert_eq!(
+            finder_first_match(defs, &names, "no node has this label"),
+            None
+        );
+        // The core edit ops have a summary to preview
+        assert!(defs.node_def(extrude).unwrap().docs.summary().is_some());
+    }
+}
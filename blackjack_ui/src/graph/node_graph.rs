@@ -5,16 +5,30 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::borrow::Cow;
+use std::path::PathBuf;
 
 use crate::application::gizmo_ui::UiNodeGizmoStates;
-use crate::application::graph_editor::GraphEditor;
+use crate::application::graph_editor::{self, GraphEditor, UndoableEdit};
 use crate::application::serialization;
+use crate::application::trust_settings::settings_folder;
+use crate::application::viewport_3d::LightingSettings;
+use crate::application::{file_dialogs, file_drop};
 use crate::custom_widgets::smart_dragvalue::SmartDragValue;
+use crate::graph::{connections, node_docs_ui, parameter_edits};
 use crate::{application::code_viewer::code_edit_ui, prelude::*};
+use blackjack_engine::graph::expressions;
+use blackjack_engine::graph::node_migration::NodeVersionWarning;
+use blackjack_engine::graph::node_presets::{NodePreset, NodePresetLibrary};
 use blackjack_engine::graph::serialization::SerializedBjkSnippet;
+use blackjack_engine::graph_interpreter::dry_run::Severity;
+use blackjack_engine::graph_interpreter::frozen_cache;
+use blackjack_engine::lua_engine::lua_stdlib::lua_path::{self, ProjectContext};
+use blackjack_engine::mesh::material::MaterialTable;
+use blackjack_engine::progress::{Warning, WarningElements};
+use blackjack_engine::units::{LengthUnit, ParamUnit};
 use blackjack_engine::{
     graph::{BlackjackValue, DataType, FilePathMode, InputValueConfig, NodeDefinitions},
-    prelude::selection::SelectionExpression,
+    prelude::selection::{SelectionExpression, SelectionKind},
 };
 use egui::RichText;
 use egui_node_graph::{
@@ -36,13 +50,25 @@ pub type GraphEditorState = egui_node_graph::GraphEditorState<
 >;
 
 /// Blackjack-specific node responses (graph side-effects)
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CustomNodeResponse {
     SetActiveNode(NodeId),
     ClearActiveNode,
     RunNodeSideEffect(NodeId),
+    /// Makes a frozen cache node run the nodes upstream of it again, and
+    /// store their result.
+    RefreshCache(NodeId),
     LockGizmos(NodeId),
     UnlockGizmos(NodeId),
+    CancelExecution,
+    /// Stores the current parameters of the node as a preset with this name
+    SavePreset(NodeId, String),
+    ApplyPreset(NodeId, String),
+    DeletePreset(NodeId, String),
+    /// Sets the preset applied to new nodes of the same type, or clears it
+    SetDefaultPreset(NodeId, Option<String>),
+    /// Opens the docs window for the node definition of the node
+    ShowNodeDocs(NodeId),
 }
 
 /// Blackjack-specific global graph state
@@ -60,6 +86,96 @@ pub struct CustomGraphState {
     pub promoted_params: HashMap<InputId, String>,
 
     pub gizmo_states: UiNodeGizmoStates,
+
+    /// The node that is currently running in a slow graph execution, and the
+    /// progress it reported. Used to draw a progress bar on that node.
+    pub node_progress: Option<(NodeId, f32)>,
+    /// Set by the UI when the user wants to abort the running execution.
+    pub cancel_requested: bool,
+
+    /// The selection parameter that is currently being edited by clicking on
+    /// the viewport, if any.
+    pub picking: Option<(NodeId, String)>,
+    /// For selection parameters that were picked in the viewport, the node
+    /// whose output was displayed while picking. This lets the engine keep
+    /// the selection pointing to the same elements when the graph upstream
+    /// of the parameter changes.
+    pub picked_selections: HashMap<(NodeId, String), PickedFrom>,
+
+    /// The graph-level seed, from which the seed of each node is derived.
+    pub graph_seed: u64,
+
+    /// The materials of the graph, which faces refer to by index. Edited in
+    /// the materials window.
+    pub materials: MaterialTable,
+
+    /// The unit lengths are shown in. Edited in the project settings window.
+    pub units: LengthUnit,
+    /// How the viewport lights the mesh. Edited in the lighting popup of the
+    /// viewport, and saved with the graph so each project keeps its own.
+    pub viewport_lighting: LightingSettings,
+
+    /// The file the graph was loaded from or last saved to. Exporters resolve
+    /// relative paths against its folder.
+    pub file_path: Option<PathBuf>,
+    /// The folder of the last file picked in a file dialog, where the next
+    /// dialogs start. Kept when another graph is opened.
+    pub last_dialog_dir: Option<PathBuf>,
+
+    /// Nodes that were loaded with a different version of their node
+    /// definition, and couldn't be migrated.
+    pub node_version_warnings: HashMap<NodeId, NodeVersionWarning>,
+
+    /// The problems found in each node the last time the graph was validated.
+    pub dry_run_problems: HashMap<NodeId, Vec<(Severity, String)>>,
+
+    /// The warnings reported by each node during the last execution.
+    pub node_warnings: HashMap<NodeId, Vec<Warning>>,
+    /// The errors of the nodes that failed with a soft error during the last
+    /// execution. Their outputs were replaced by default values.
+    pub node_errors: HashMap<NodeId, String>,
+    /// The node whose op crashed during the last execution, along with the
+    /// panic message. The execution stopped at that node.
+    pub crashed_node: Option<(NodeId, String)>,
+    /// The elements of the warning picked in the warnings window, which are
+    /// highlighted in the viewport.
+    pub highlighted_warning: Option<WarningElements>,
+
+    /// The user's node presets. These are not stored in the graph, but in
+    /// the user's config folder.
+    pub node_presets: NodePresetLibrary,
+    /// The name typed in the "Save preset" field of the presets menu.
+    pub preset_name: String,
+    /// Parameters of the last preset applied to a node that the node doesn't
+    /// have anymore.
+    pub preset_warnings: HashMap<NodeId, String>,
+
+    /// The nodes that can be bypassed together to compare the result of the
+    /// graph with and without them.
+    pub bypass_group: Option<BypassGroup>,
+
+    /// The op name of the node definition shown in the docs window, if open.
+    pub docs_node: Option<String>,
+    /// Set by the docs window to open the bundled example graph with this
+    /// name, which the root viewport does at the end of the frame.
+    pub open_example: Option<String>,
+}
+
+/// A group of nodes that are bypassed as a whole. Bypassed nodes pass their
+/// first input through to the output of the same type. While the group
+/// exists, each run also produces the result with the group toggled, which
+/// the viewport shows while the compare key is held.
+#[derive(Clone, Debug, Default)]
+pub struct BypassGroup {
+    pub nodes: HashSet<NodeId>,
+    pub muted: bool,
+}
+
+/// Where the ids of a selection parameter picked in the viewport come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PickedFrom {
+    pub node: NodeId,
+    pub kind: SelectionKind,
 }
 
 impl CustomGraphState {
@@ -70,21 +186,149 @@ impl CustomGraphState {
             active_node: None,
             promoted_params: HashMap::default(),
             gizmo_states,
+            node_progress: None,
+            cancel_requested: false,
+            picking: None,
+            picked_selections: HashMap::default(),
+            graph_seed: 0,
+            materials: MaterialTable::default(),
+            units: LengthUnit::default(),
+            viewport_lighting: LightingSettings::default(),
+            file_path: None,
+            last_dialog_dir: None,
+            node_version_warnings: HashMap::default(),
+            dry_run_problems: HashMap::default(),
+            node_warnings: HashMap::default(),
+            node_errors: HashMap::default(),
+            crashed_node: None,
+            highlighted_warning: None,
+            node_presets: load_node_presets(),
+            preset_name: String::new(),
+            preset_warnings: HashMap::default(),
+            bypass_group: None,
+            docs_node: None,
+            open_example: None,
         }
     }
 }
 
+/// The file where the user's node presets are stored.
+fn node_presets_file() -> Option<PathBuf> {
+    settings_folder().map(|folder| folder.join("node_presets.json"))
+}
+
+/// Loads the user's node presets. A missing or invalid file is reported and
+/// treated as having no presets.
+pub fn load_node_presets() -> NodePresetLibrary {
+    node_presets_file()
+        .map(NodePresetLibrary::load_from_file)
+        .transpose()
+        .unwrap_or_else(|err| {
+            println!("[WARNING] {err:#}");
+            None
+        })
+        .unwrap_or_default()
+}
+
+fn save_node_presets(presets: &NodePresetLibrary) -> Result<()> {
+    let file = node_presets_file()
+        .ok_or_else(|| anyhow!("Could not find a folder to store the node presets"))?;
+    presets.save_to_file(file)
+}
+
+/// Sets the parameters of `node_id` to the values in its preset named `name`.
+/// Parameters of the preset that the node doesn't have are reported in the
+/// node.
+fn apply_node_preset(
+    graph: &mut Graph,
+    custom_state: &mut CustomGraphState,
+    node_id: NodeId,
+    name: &str,
+) -> Result<()> {
+    let op_name = &graph[node_id].user_data.op_name;
+    let node_def = custom_state
+        .node_definitions
+        .node_def(op_name)
+        .ok_or_else(|| anyhow!("There is no node definition for '{op_name}'"))?;
+    let preset = custom_state
+        .node_presets
+        .get(op_name, name)
+        .ok_or_else(|| anyhow!("There is no preset named '{name}' for '{op_name}' nodes"))?;
+    let application = preset.apply(&node_def)?;
+    for (input, value) in application.values {
+        let input_id = graph[node_id].get_input(&input)?;
+        graph.inputs[input_id].value = ValueTypeUi(value);
+    }
+    match application.warning() {
+        Some(warning) => custom_state.preset_warnings.insert(node_id, warning),
+        None => custom_state.preset_warnings.remove(&node_id),
+    };
+    Ok(())
+}
+
+/// Updates the node presets in response to `response`, and saves them.
+fn handle_preset_response(
+    graph: &mut Graph,
+    custom_state: &mut CustomGraphState,
+    response: CustomNodeResponse,
+) -> Result<()> {
+    let presets_changed = match response {
+        CustomNodeResponse::SavePreset(node_id, name) => {
+            let node = &graph[node_id];
+            let node_def = custom_state
+                .node_definitions
+                .node_def(&node.user_data.op_name)
+                .ok_or_else(|| anyhow!("There is no node definition for this node"))?;
+            let values = node
+                .inputs
+                .iter()
+                .map(|(input, id)| (input.as_str(), &graph.inputs[*id].value.0));
+            let preset = NodePreset::from_values(name, &node_def, values);
+            custom_state.node_presets.insert(preset);
+            true
+        }
+        CustomNodeResponse::ApplyPreset(node_id, name) => {
+            apply_node_preset(graph, custom_state, node_id, &name)?;
+            false
+        }
+        CustomNodeResponse::DeletePreset(node_id, name) => {
+            let op_name = &graph[node_id].user_data.op_name;
+            custom_state.node_presets.remove(op_name, &name);
+            true
+        }
+        CustomNodeResponse::SetDefaultPreset(node_id, name) => {
+            let op_name = &graph[node_id].user_data.op_name;
+            custom_state
+                .node_presets
+                .set_default(op_name, name.as_deref())?;
+            true
+        }
+        _ => false,
+    };
+    if presets_changed {
+        save_node_presets(&custom_state.node_presets)?;
+    }
+    Ok(())
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct DataTypeUi(pub DataType); // Prevents orphan rules
 impl DataTypeTrait<CustomGraphState> for DataTypeUi {
     fn data_type_color(&self, _user_state: &mut CustomGraphState) -> egui::Color32 {
         match self.0 {
             DataType::Mesh => color_from_hex("#b43e3e").unwrap(),
+            DataType::Curve => color_from_hex("#e07a3f").unwrap(),
+            DataType::Points => color_from_hex("#d64f8a").unwrap(),
             DataType::HeightMap => color_from_hex("#33673b").unwrap(),
+            DataType::Scene => color_from_hex("#8c5fbf").unwrap(),
             DataType::Vector => color_from_hex("#1A535C").unwrap(),
             DataType::Scalar => color_from_hex("#4ecdc4").unwrap(),
+            DataType::Int => color_from_hex("#2a9d8f").unwrap(),
+            DataType::Bool => color_from_hex("#ff9f1c").unwrap(),
             DataType::Selection => color_from_hex("#f7fff7").unwrap(),
             DataType::String => color_from_hex("#ffe66d").unwrap(),
+            DataType::VertexDeltas => color_from_hex("#ff6b6b").unwrap(),
+            DataType::VertexWeights => color_from_hex("#ff8fab").unwrap(),
         }
     }
 
@@ -92,10 +336,17 @@ impl DataTypeTrait<CustomGraphState> for DataTypeUi {
         Cow::Borrowed(match self.0 {
             DataType::Vector => "vector",
             DataType::Scalar => "scalar",
+            DataType::Int => "int",
+            DataType::Bool => "bool",
             DataType::Selection => "selection",
             DataType::Mesh => "mesh",
+            DataType::Curve => "curve",
+            DataType::Points => "points",
             DataType::HeightMap => "heightmap",
+            DataType::Scene => "scene",
             DataType::String => "string",
+            DataType::VertexDeltas => "vertex deltas",
+            DataType::VertexWeights => "vertex weights",
         })
     }
 }
@@ -106,6 +357,18 @@ impl UserResponseTrait for CustomNodeResponse {}
 #[derive(Clone)]
 pub struct NodeData {
     pub op_name: String,
+    /// See [`blackjack_engine::graph::BjkNode::seed`].
+    pub seed: u64,
+}
+
+/// Returns a node seed that none of the nodes in the `graph` use.
+pub fn unused_node_seed(graph: &Graph) -> u64 {
+    graph
+        .nodes
+        .values()
+        .map(|node| node.user_data.seed.saturating_add(1))
+        .max()
+        .unwrap_or(0)
 }
 impl NodeDataTrait for NodeData {
     type Response = CustomNodeResponse;
@@ -133,6 +396,61 @@ impl NodeDataTrait for NodeData {
         }
         let node_def = node_def.unwrap();
 
+        if let Some(warning) = user_state.node_version_warnings.get(&node_id) {
+            ui.label(RichText::new("⚠ Outdated node").color(egui::Color32::GOLD))
+                .on_hover_text(warning.describe());
+        }
+
+        if let Some(problems) = user_state.dry_run_problems.get(&node_id) {
+            let (label, color) = if problems.iter().any(|(s, _)| *s == Severity::Error) {
+                ("⛔ Invalid node", egui::Color32::LIGHT_RED)
+            } else {
+                ("⚠ Check node", egui::Color32::GOLD)
+            };
+            let messages: Vec<&str> = problems.iter().map(|(_, m)| m.as_str()).collect();
+            ui.label(RichText::new(label).color(color))
+                .on_hover_text(messages.join("\n"));
+        }
+
+        if let Some(warning) = user_state.preset_warnings.get(&node_id) {
+            ui.label(RichText::new("⚠ Preset").color(egui::Color32::GOLD))
+                .on_hover_text(warning);
+        }
+
+        if let Some(warnings) = user_state.node_warnings.get(&node_id) {
+            let label = match warnings.len() {
+                1 => "⚠ 1 warning".to_string(),
+                n => format!("⚠ {n} warnings"),
+            };
+            let messages = warnings.iter().map(|w| w.message.as_str()).collect_vec();
+            ui.label(RichText::new(label).color(egui::Color32::GOLD))
+                .on_hover_text(messages.join("\n"));
+        }
+
+        if let Some(error) = user_state.node_errors.get(&node_id) {
+            ui.label(RichText::new("⛔ Failed, using defaults").color(egui::Color32::LIGHT_RED))
+                .on_hover_text(error);
+        }
+
+        if let Some((_, message)) = user_state.crashed_node.as_ref().filter(|c| c.0 == node_id) {
+            ui.label(RichText::new("⛔ Crashed").color(egui::Color32::RED))
+                .on_hover_text(message);
+        }
+
+        if let Some(group) = user_state
+            .bypass_group
+            .as_ref()
+            .filter(|group| group.nodes.contains(&node_id))
+        {
+            let label = if group.muted {
+                "⏸ Bypassed"
+            } else {
+                "⏵ Bypass group"
+            };
+            ui.label(RichText::new(label).color(egui::Color32::LIGHT_BLUE))
+                .on_hover_text("Ctrl+M toggles the group, and holding F shows the other variant");
+        }
+
         let mut responses = Vec::new();
         ui.horizontal(|ui| {
             // Show 'Enable' button for nodes that output a mesh
@@ -179,12 +497,141 @@ impl NodeDataTrait for NodeData {
                         node_id,
                     )));
                 }
+                if node_def.cache
+                    && ui
+                        .button("⟳ Refresh")
+                        .on_hover_text("Runs the nodes before the cache again")
+                        .clicked()
+                {
+                    responses.push(NodeResponse::User(CustomNodeResponse::RefreshCache(
+                        node_id,
+                    )));
+                }
+                ui.menu_button("🔖", |ui| {
+                    presets_menu_ui(
+                        ui,
+                        node_id,
+                        &node_def.op_name,
+                        &user_state.node_presets,
+                        &mut user_state.preset_name,
+                        &mut responses,
+                    )
+                })
+                .response
+                .on_hover_text("Presets");
+                if ui.button("?").on_hover_text("Documentation").clicked() {
+                    responses.push(NodeResponse::User(CustomNodeResponse::ShowNodeDocs(
+                        node_id,
+                    )));
+                }
             });
         });
+        if let Some((_, progress)) = user_state.node_progress.filter(|(n, _)| *n == node_id) {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(120.0)
+                        .show_percentage(),
+                );
+                if ui.button("✖ Cancel").clicked() {
+                    responses.push(NodeResponse::User(CustomNodeResponse::CancelExecution));
+                }
+            });
+        }
         responses
     }
 }
 
+/// How far nodes move towards their layout position on each frame, as a
+/// fraction of the remaining distance.
+const LAYOUT_ANIMATION_SPEED: f32 = 0.25;
+
+/// Moves the nodes towards their position in the last automatic layout.
+fn animate_layout(
+    editor_state: &mut GraphEditorState,
+    layout_targets: &mut HashMap<NodeId, egui::Pos2>,
+    ctx: &egui::Context,
+) {
+    if layout_targets.is_empty() {
+        return;
+    }
+    layout_targets.retain(|node_id, target| {
+        match editor_state.node_positions.get_mut(*node_id) {
+            Some(pos) => {
+                *pos += (*target - *pos) * LAYOUT_ANIMATION_SPEED;
+                if pos.distance(*target) < 1.0 {
+                    *pos = *target;
+                    false
+                } else {
+                    true
+                }
+            }
+            // The node was deleted
+            None => false,
+        }
+    });
+    ctx.request_repaint();
+}
+
+/// The contents of the presets menu of a node: The presets for its node type,
+/// and a field to save its current parameters as a new one.
+fn presets_menu_ui(
+    ui: &mut egui::Ui,
+    node_id: NodeId,
+    op_name: &str,
+    presets: &NodePresetLibrary,
+    preset_name: &mut String,
+    responses: &mut Vec<NodeResponse<CustomNodeResponse, NodeData>>,
+) {
+    let default_preset = presets.default_for(op_name).map(|p| p.name.clone());
+    for preset in presets.presets_for(op_name) {
+        let is_default = default_preset.as_ref() == Some(&preset.name);
+        ui.horizontal(|ui| {
+            if ui.button(&preset.name).clicked() {
+                responses.push(NodeResponse::User(CustomNodeResponse::ApplyPreset(
+                    node_id,
+                    preset.name.clone(),
+                )));
+                ui.close_menu();
+            }
+            if ui
+                .selectable_label(is_default, "★")
+                .on_hover_text("Set as default for new nodes")
+                .clicked()
+            {
+                responses.push(NodeResponse::User(CustomNodeResponse::SetDefaultPreset(
+                    node_id,
+                    (!is_default).then(|| preset.name.clone()),
+                )));
+            }
+            if ui.button("🗑").on_hover_text("Delete preset").clicked() {
+                responses.push(NodeResponse::User(CustomNodeResponse::DeletePreset(
+                    node_id,
+                    preset.name.clone(),
+                )));
+            }
+        });
+    }
+    if presets.presets_for(op_name).next().is_some() {
+        ui.separator();
+    }
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(preset_name);
+        let name = preset_name.trim();
+        if ui
+            .add_enabled(!name.is_empty(), egui::Button::new("Save preset"))
+            .clicked()
+        {
+            responses.push(NodeResponse::User(CustomNodeResponse::SavePreset(
+                node_id,
+                name.to_owned(),
+            )));
+            preset_name.clear();
+            ui.close_menu();
+        }
+    });
+}
+
 /// Blackjack's custom draw node graph function. It defers to egui_node_graph to
 /// draw the graph itself, then interprets any responses it got and applies the
 /// required side effects.
@@ -197,23 +644,83 @@ pub fn draw_node_graph(graph_editor: &mut GraphEditor) {
         previous_clipboard_contents,
         pending_paste_operation,
         skip_pending_paste_check,
+        layout_settings,
+        layout_targets,
+        wires,
+        parameter_edit_mode,
+        last_edit,
+        importers,
         ..
     } = graph_editor;
     egui::CentralPanel::default().show(ctx, |ui| {
+        animate_layout(editor_state, layout_targets, ui.ctx());
+
+        // Graph files dropped on the window are opened by the root viewport
+        let dropped = ui
+            .input()
+            .raw
+            .dropped_files
+            .iter()
+            .filter_map(|file| file.path.clone())
+            .filter(|path| !file_drop::is_graph_file(path))
+            .collect_vec();
+        if !dropped.is_empty() {
+            let rect = ui.max_rect();
+            let cursor = ui
+                .input()
+                .pointer
+                .hover_pos()
+                .filter(|pos| rect.contains(*pos))
+                .unwrap_or_else(|| rect.center());
+            let origin = editor_state.pan_zoom.pan + rect.min.to_vec2();
+            let skipped = file_drop::spawn_import_nodes(
+                editor_state,
+                custom_state,
+                importers,
+                &dropped,
+                cursor - origin,
+                layout_settings.vertical_spacing,
+            );
+            for path in skipped {
+                println!("There is no node to import {}", path.display());
+            }
+        }
+
         // We clone the old graph here, so we can get a hold of the old state
         // before the graph is mutated. This is useful on some operations.
         let old_graph = editor_state.graph.clone();
 
-        let responses = editor_state.draw_graph_editor(
-            ui,
-            NodeOpNames(custom_state.node_definitions.node_names()),
-            custom_state,
-        );
+        // When inserting a node in a wire, only the nodes that fit are listed
+        let finder_node_names = custom_state
+            .node_definitions
+            .finder_node_names(wires.pending_splice_type(&editor_state.graph));
+        node_docs_ui::finder_preview_ui(ui.ctx(), editor_state, custom_state, &finder_node_names);
+        let responses =
+            editor_state.draw_graph_editor(ui, NodeOpNames(finder_node_names), custom_state);
 
         // Store whether the mouse is in the node finder. This helps prevent
         // scroll wheel events.
         *mouse_over_node_finder = responses.cursor_in_finder;
 
+        // Alt-dragging a widget of one of the selected nodes edits the same
+        // parameter in all of them.
+        if ui.input().modifiers.alt && editor_state.selected_nodes.len() > 1 {
+            let selected = &editor_state.selected_nodes;
+            if let Some((source, previous)) =
+                parameter_edits::find_edited_parameter(&old_graph, &editor_state.graph, selected)
+            {
+                if let Some(edit) = parameter_edits::fan_out_parameter_edit(
+                    &mut editor_state.graph,
+                    selected,
+                    source,
+                    &previous,
+                    *parameter_edit_mode,
+                ) {
+                    UndoableEdit::record_parameter_edit(last_edit, edit);
+                }
+            }
+        }
+
         for response in responses.node_responses {
             match response {
                 NodeResponse::DeleteNodeFull { node_id, .. } => {
@@ -238,6 +745,32 @@ pub fn draw_node_graph(graph_editor: &mut GraphEditor) {
                         custom_state.run_side_effect = None;
                     }
                     custom_state.gizmo_states.node_deleted(node_id);
+                    custom_state
+                        .picked_selections
+                        .retain(|(n, _), picked| *n != node_id && picked.node != node_id);
+                    if matches!(&custom_state.picking, Some((n, _)) if *n == node_id) {
+                        custom_state.picking = None;
+                    }
+                    if let Some(group) = &mut custom_state.bypass_group {
+                        group.nodes.remove(&node_id);
+                        if group.nodes.is_empty() {
+                            custom_state.bypass_group = None;
+                        }
+                    }
+                }
+                NodeResponse::CreatedNode(node_id) => {
+                    let op_name = &editor_state.graph[node_id].user_data.op_name;
+                    if let Some(preset) = custom_state.node_presets.default_for(op_name) {
+                        let name = preset.name.clone();
+                        if let Err(err) =
+                            apply_node_preset(&mut editor_state.graph, custom_state, node_id, &name)
+                        {
+                            println!("Error: Could not apply the default preset: {err}");
+                        }
+                    }
+                    if let Some(splice) = wires.on_node_created(&mut editor_state.graph, node_id) {
+                        *last_edit = Some(UndoableEdit::Splice(splice));
+                    }
                 }
                 NodeResponse::User(response) => match response {
                     graph::CustomNodeResponse::SetActiveNode(n) => {
@@ -258,6 +791,15 @@ pub fn draw_node_graph(graph_editor: &mut GraphEditor) {
                     graph::CustomNodeResponse::RunNodeSideEffect(n) => {
                         custom_state.run_side_effect = Some(n)
                     }
+                    CustomNodeResponse::RefreshCache(n) => {
+                        // Without a fingerprint, the cache stores its mesh again
+                        if let Ok(input_id) =
+                            editor_state.graph[n].get_input(frozen_cache::FINGERPRINT_PARAM)
+                        {
+                            editor_state.graph[input_id].value =
+                                ValueTypeUi(BlackjackValue::String(String::new()));
+                        }
+                    }
                     CustomNodeResponse::LockGizmos(n) => {
                         custom_state.gizmo_states.lock_gizmos_for(n);
                     }
@@ -266,11 +808,59 @@ pub fn draw_node_graph(graph_editor: &mut GraphEditor) {
                             .gizmo_states
                             .unlock_gizmos_for(n, custom_state.active_node);
                     }
+                    CustomNodeResponse::CancelExecution => {
+                        custom_state.cancel_requested = true;
+                    }
+                    CustomNodeResponse::ShowNodeDocs(n) => {
+                        custom_state.docs_node =
+                            Some(editor_state.graph[n].user_data.op_name.clone());
+                    }
+                    response @ (CustomNodeResponse::SavePreset(..)
+                    | CustomNodeResponse::ApplyPreset(..)
+                    | CustomNodeResponse::DeletePreset(..)
+                    | CustomNodeResponse::SetDefaultPreset(..)) => {
+                        if let Err(err) =
+                            handle_preset_response(&mut editor_state.graph, custom_state, response)
+                        {
+                            println!("Error: Could not update the node presets: {err}");
+                        }
+                    }
                 },
                 _ => {}
             }
         }
 
+        node_docs_ui::docs_window_ui(ui.ctx(), custom_state);
+
+        if let Some(splice) = connections::wire_editor_ui(ui, editor_state, custom_state, wires) {
+            *last_edit = Some(UndoableEdit::Splice(splice));
+        }
+
+        if ui.input().key_pressed(egui::Key::Z) && ui.input().modifiers.ctrl {
+            if let Some(edit) = last_edit.take() {
+                edit.undo(&mut editor_state.graph);
+            }
+        }
+
+        if ui.input().key_pressed(egui::Key::L) && ui.input().modifiers.ctrl {
+            *layout_targets = graph_editor::layout_targets(editor_state, layout_settings);
+        }
+
+        // Ctrl+B makes a bypass group of the selected nodes, or removes the
+        // group when nothing is selected. Ctrl+M toggles it.
+        if ui.input().key_pressed(egui::Key::B) && ui.input().modifiers.ctrl {
+            custom_state.bypass_group =
+                (!editor_state.selected_nodes.is_empty()).then(|| BypassGroup {
+                    nodes: editor_state.selected_nodes.iter().copied().collect(),
+                    muted: false,
+                });
+        }
+        if ui.input().key_pressed(egui::Key::M) && ui.input().modifiers.ctrl {
+            if let Some(group) = &mut custom_state.bypass_group {
+                group.muted = !group.muted;
+            }
+        }
+
         if ui.input().key_released(egui::Key::C)
             && ui.input().modifiers.ctrl
             && !editor_state.selected_nodes.is_empty()
@@ -367,10 +957,17 @@ pub fn data_type_to_input_param_kind(data_type: DataType) -> InputParamKind {
     match data_type {
         DataType::Vector => InputParamKind::ConnectionOrConstant,
         DataType::Scalar => InputParamKind::ConnectionOrConstant,
+        DataType::Int => InputParamKind::ConnectionOrConstant,
+        DataType::Bool => InputParamKind::ConnectionOrConstant,
         DataType::Selection => InputParamKind::ConnectionOrConstant,
         DataType::Mesh => InputParamKind::ConnectionOnly,
+        DataType::Curve => InputParamKind::ConnectionOnly,
+        DataType::Points => InputParamKind::ConnectionOnly,
         DataType::HeightMap => InputParamKind::ConnectionOnly,
+        DataType::Scene => InputParamKind::ConnectionOnly,
         DataType::String => InputParamKind::ConnectionOrConstant,
+        DataType::VertexDeltas => InputParamKind::ConstantOnly,
+        DataType::VertexWeights => InputParamKind::ConstantOnly,
     }
 }
 
@@ -381,7 +978,7 @@ pub fn default_shown_inline() -> bool {
 }
 
 #[derive(Clone, Debug)]
-pub struct NodeOpName(String);
+pub struct NodeOpName(pub String);
 impl NodeTemplateTrait for NodeOpName {
     type NodeData = NodeData;
     type DataType = DataTypeUi;
@@ -409,8 +1006,10 @@ impl NodeTemplateTrait for NodeOpName {
             "This method is only called when creating a new node.\
              Definitions can't be outdated at this point.",
         );
+        // The seed is picked in `build_node`, which has access to the graph.
         NodeData {
             op_name: node_def.op_name.clone(),
+            seed: 0,
         }
     }
 
@@ -424,6 +1023,7 @@ impl NodeTemplateTrait for NodeOpName {
             "This method is only called when creating a new node.\
              Definitions can't be outdated at this point.",
         );
+        graph[node_id].user_data.seed = unused_node_seed(graph);
         for input in &node_def.inputs {
             let input_param_kind = data_type_to_input_param_kind(input.data_type);
 
@@ -460,7 +1060,7 @@ impl WidgetValueTrait for ValueTypeUi {
     fn value_widget(
         &mut self,
         param_name: &str,
-        _node_id: NodeId,
+        node_id: NodeId,
         ui: &mut egui::Ui,
         user_state: &mut CustomGraphState,
         node_data: &NodeData,
@@ -484,6 +1084,28 @@ impl WidgetValueTrait for ValueTypeUi {
         }
         let input_def = input_def.unwrap();
 
+        // The fingerprint of cache nodes is written by the engine
+        if matches!(node_def.as_deref(), Some(def) if def.cache)
+            && param_name == frozen_cache::FINGERPRINT_PARAM
+        {
+            match &self.0 {
+                BlackjackValue::String(fingerprint) if !fingerprint.is_empty() => {
+                    ui.label(format!("Cached ({fingerprint})"))
+                }
+                _ => ui.label("Not cached"),
+            };
+            return Vec::new();
+        }
+
+        if let BlackjackValue::Expression(src) = &mut self.0 {
+            if expression_ui(ui, param_name, src) {
+                self.0 = literal_for_expression(src, input_def.data_type)
+                    .unwrap_or_else(|| input_def.default_value());
+            }
+            return Vec::new();
+        }
+
+        let mut use_expression = false;
         match (&mut self.0, &input_def.config) {
             (BlackjackValue::Vector(vector), InputValueConfig::Vector { .. }) => {
                 ui.label(param_name);
@@ -506,6 +1128,7 @@ impl WidgetValueTrait for ValueTypeUi {
                             .speed(1.0)
                             .decimals(5),
                     );
+                    use_expression = expression_toggle(ui);
                 });
             }
             (
@@ -516,6 +1139,7 @@ impl WidgetValueTrait for ValueTypeUi {
                     soft_min,
                     soft_max,
                     num_decimals,
+                    unit,
                     ..
                 },
             ) => {
@@ -530,23 +1154,63 @@ impl WidgetValueTrait for ValueTypeUi {
                 } else {
                     FLOAT_DRAG_LABELS
                 };
-                let mut drag_value = SmartDragValue::new(value, drag_speeds, drag_labels)
+                // Lengths are edited in the unit of the project, but they are
+                // always stored in meters.
+                let length_unit = (*unit == ParamUnit::Length).then_some(user_state.units);
+                let to_displayed = |v: f32| length_unit.map_or(v, |u| u.from_meters(v));
+                let mut displayed = to_displayed(*value);
+                let mut drag_value = SmartDragValue::new(&mut displayed, drag_speeds, drag_labels)
                     .speed(1.0)
                     .clamp_range_hard(
-                        min.unwrap_or(f32::NEG_INFINITY)..=max.unwrap_or(f32::INFINITY),
+                        min.map_or(f32::NEG_INFINITY, to_displayed)
+                            ..=max.map_or(f32::INFINITY, to_displayed),
                     )
                     .clamp_range_soft(
-                        soft_min.unwrap_or(f32::NEG_INFINITY)..=soft_max.unwrap_or(f32::INFINITY),
+                        soft_min.map_or(f32::NEG_INFINITY, to_displayed)
+                            ..=soft_max.map_or(f32::INFINITY, to_displayed),
                     )
                     .decimals(num_decimals.unwrap_or(5) as usize);
                 if is_int {
                     drag_value = drag_value.default_range_index(2);
                 }
+                if let Some(length_unit) = length_unit {
+                    drag_value = drag_value.suffix(format!(" {}", length_unit.suffix()));
+                }
 
                 ui.horizontal(|ui| {
                     ui.label(param_name);
-                    ui.add(drag_value)
+                    ui.add(drag_value);
+                    use_expression = expression_toggle(ui);
                 });
+                if displayed != to_displayed(*value) {
+                    *value = length_unit.map_or(displayed, |u| u.to_meters(displayed));
+                }
+            }
+            (
+                BlackjackValue::Int(value),
+                InputValueConfig::Int {
+                    min,
+                    max,
+                    soft_min,
+                    soft_max,
+                    ..
+                },
+            ) => {
+                let drag_value = SmartDragValue::new(value, INT_DRAG_SPEEDS, INT_DRAG_LABELS)
+                    .clamp_range_hard(min.unwrap_or(i32::MIN)..=max.unwrap_or(i32::MAX))
+                    .clamp_range_soft(
+                        soft_min.map_or(f64::NEG_INFINITY, f64::from)
+                            ..=soft_max.map_or(f64::INFINITY, f64::from),
+                    )
+                    .default_range_index(2);
+                ui.horizontal(|ui| {
+                    ui.label(param_name);
+                    ui.add(drag_value);
+                    use_expression = expression_toggle(ui);
+                });
+            }
+            (BlackjackValue::Bool(value), InputValueConfig::Bool { .. }) => {
+                ui.checkbox(value, param_name);
             }
             (BlackjackValue::String(string), InputValueConfig::Enum { values, .. }) => {
                 egui::ComboBox::from_label(param_name)
@@ -558,23 +1222,44 @@ impl WidgetValueTrait for ValueTypeUi {
                     });
             }
             (BlackjackValue::String(path), InputValueConfig::FilePath { file_path_mode, .. }) => {
+                let project = ProjectContext::for_graph_file(user_state.file_path.as_deref());
                 ui.label(param_name);
                 ui.horizontal(|ui| {
                     if ui.button("Select").clicked() {
+                        let dialog = file_dialogs::file_dialog(user_state);
                         let new_path = match file_path_mode {
-                            FilePathMode::Open => rfd::FileDialog::new().pick_file(),
-                            FilePathMode::Save => rfd::FileDialog::new().save_file(),
+                            FilePathMode::Open => dialog.pick_file(),
+                            FilePathMode::Save => dialog.save_file(),
                         };
 
                         if let Some(new_path) = new_path {
-                            *path = new_path
-                                .into_os_string()
-                                .into_string()
-                                .unwrap_or_else(|err| format!("INVALID PATH: {err:?}"))
+                            file_dialogs::remember_dialog_dir(user_state, &new_path);
+                            *path = file_dialogs::path_param_value(user_state, new_path);
                         }
                     }
+
+                    let (toggle_label, toggled) = if lua_path::is_project_relative(path) {
+                        ("Make absolute", project.resolve(path).ok())
+                    } else {
+                        ("Make relative", project.make_relative(path))
+                    };
+                    let toggle = ui
+                        .add_enabled(toggled.is_some(), egui::Button::new(toggle_label))
+                        .on_disabled_hover_text(
+                            "Paths can only be relative to the folder of a saved graph, \
+                             on the same drive",
+                        );
+                    if let (true, Some(toggled)) = (toggle.clicked(), toggled) {
+                        *path = toggled;
+                    }
+
                     if !path.is_empty() {
-                        ui.label(path.clone());
+                        let label = ui.label(path.clone());
+                        if let Ok(resolved) = project.resolve(path) {
+                            if resolved != *path {
+                                label.on_hover_text(resolved);
+                            }
+                        }
                     } else {
                         ui.label("No file selected");
                     }
@@ -601,9 +1286,45 @@ impl WidgetValueTrait for ValueTypeUi {
                 //ui.add(egui::TextEdit::multiline(text).text_style(egui::TextStyle::Monospace).desired_width(f32::INFINITY));
             }
             (BlackjackValue::Selection(text, selection), InputValueConfig::Selection { .. }) => {
-                if ui.text_edit_singleline(text).changed() {
-                    *selection = SelectionExpression::parse(text).ok();
-                }
+                let key = (node_id, param_name.to_owned());
+                ui.horizontal(|ui| {
+                    if ui.text_edit_singleline(text).changed() {
+                        *selection = SelectionExpression::parse(text).ok();
+                        // Ids typed by hand refer to the mesh this node gets
+                        // as input, not to the one where they were picked.
+                        user_state.picked_selections.remove(&key);
+                    }
+                    let is_picking = user_state.picking.as_ref() == Some(&key);
+                    if ui
+                        .selectable_label(is_picking, "🖱")
+                        .on_hover_text("Pick faces by clicking on the viewport")
+                        .clicked()
+                    {
+                        user_state.picking = if is_picking { None } else { Some(key) };
+                    }
+                });
+            }
+            (BlackjackValue::VertexDeltas(deltas), InputValueConfig::None) => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{param_name}: {} edited vertices", deltas.len()));
+                    if ui
+                        .add_enabled(!deltas.is_empty(), egui::Button::new("Clear"))
+                        .clicked()
+                    {
+                        deltas.0.clear();
+                    }
+                });
+            }
+            (BlackjackValue::VertexWeights(weights), InputValueConfig::None) => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{param_name}: {} painted vertices", weights.len()));
+                    if ui
+                        .add_enabled(!weights.is_empty(), egui::Button::new("Clear"))
+                        .clicked()
+                    {
+                        weights.0.clear();
+                    }
+                });
             }
             (BlackjackValue::None, InputValueConfig::None) => {
                 ui.label(param_name);
@@ -613,6 +1334,72 @@ impl WidgetValueTrait for ValueTypeUi {
             }
         }
 
+        if use_expression {
+            self.0 = BlackjackValue::Expression(expression_for_literal(&self.0));
+        }
+
         Vec::new()
     }
 }
+
+/// The button that switches a number or vector parameter to an expression.
+/// Returns whether it was clicked.
+fn expression_toggle(ui: &mut egui::Ui) -> bool {
+    ui.small_button("=")
+        .on_hover_text("Compute this value with an expression")
+        .clicked()
+}
+
+/// Draws the text field of an expression parameter. Expressions that don't
+/// parse are underlined in red, and the error is shown on hover. Returns
+/// whether the parameter should go back to a literal value.
+fn expression_ui(ui: &mut egui::Ui, param_name: &str, src: &mut String) -> bool {
+    const EXPRESSION_BG: egui::Color32 = egui::Color32::from_rgb(0x1d, 0x2b, 0x3a);
+    let mut back_to_literal = false;
+    ui.horizontal(|ui| {
+        ui.label(param_name);
+        let response = ui
+            .scope(|ui| {
+                ui.visuals_mut().extreme_bg_color = EXPRESSION_BG;
+                ui.add(
+                    egui::TextEdit::singleline(src)
+                        .font(egui::TextStyle::Monospace)
+                        .hint_text("expression"),
+                )
+            })
+            .inner;
+        if let Err(err) = expressions::parse(src) {
+            let rect = response.rect;
+            ui.painter().line_segment(
+                [rect.left_bottom(), rect.right_bottom()],
+                egui::Stroke::new(2.0, egui::Color32::RED),
+            );
+            response.on_hover_text(err.to_string());
+        }
+        back_to_literal = ui
+            .small_button("✖")
+            .on_hover_text("Use a fixed value instead")
+            .clicked();
+    });
+    back_to_literal
+}
+
+/// Returns an expression with the same value as the literal `value`.
+fn expression_for_literal(value: &BlackjackValue) -> String {
+    match value {
+        BlackjackValue::Vector(v) => format!("vec({}, {}, {})", v.x, v.y, v.z),
+        BlackjackValue::Scalar(x) => x.to_string(),
+        BlackjackValue::Int(i) => i.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Returns the value of `src` when it doesn't depend on other parameters, to
+/// keep it when going back to a literal value.
+fn literal_for_expression(src: &str, data_type: DataType) -> Option<BlackjackValue> {
+    let value = expressions::parse(src)
+        .ok()?
+        .eval(&mut |name| Err(anyhow!("'{name}' is not a constant")))
+        .ok()?;
+    expressions::expr_value_to_blackjack(value, data_type).ok()
+}
@@ -0,0 +1,411 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::graph::BlackjackValue;
+use egui_node_graph::{InputId, NodeId};
+
+use crate::prelude::graph::Graph;
+use crate::prelude::*;
+
+/// How the edit of a parameter is applied to the other selected nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParameterEditMode {
+    /// The other parameters are set to the same value.
+    #[default]
+    Absolute,
+    /// The other parameters change by the same amount. Only numbers and
+    /// vectors can change by an amount, other values are set like in
+    /// `Absolute` mode.
+    Relative,
+}
+
+/// An edit of the same parameter in several nodes at once. Keeps the previous
+/// values of all the edited parameters, so it can be undone.
+#[derive(Clone, Debug)]
+pub struct ParameterEdit {
+    previous: Vec<(InputId, BlackjackValue)>,
+}
+
+impl ParameterEdit {
+    /// Returns whether both edits changed the same parameters.
+    pub fn same_parameters(&self, other: &ParameterEdit) -> bool {
+        let inputs = |edit: &ParameterEdit| edit.previous.iter().map(|(i, _)| *i).collect_vec();
+        inputs(self) == inputs(other)
+    }
+
+    /// Sets the edited parameters back to their previous values. Parameters
+    /// deleted since the edit are skipped.
+    pub fn undo(&self, graph: &mut Graph) {
+        for (input, value) in &self.previous {
+            if let Some(param) = graph.inputs.get_mut(*input) {
+                param.value.0 = value.clone();
+            }
+        }
+    }
+}
+
+/// Returns `target` changed by the same amount as `previous` changed into
+/// `new`, or `None` when these are not numbers or vectors of the same type.
+fn offset_value(
+    target: &BlackjackValue,
+    previous: &BlackjackValue,
+    new: &BlackjackValue,
+) -> Option<BlackjackValue> {
+    use BlackjackValue as V;
+    Some(match (target, previous, new) {
+        (V::Scalar(t), V::Scalar(a), V::Scalar(b)) => V::Scalar(t + (b - a)),
+        (V::Int(t), V::Int(a), V::Int(b)) => V::Int(t.saturating_add(b.saturating_sub(*a))),
+        (V::Vector(t), V::Vector(a), V::Vector(b)) => V::Vector(*t + (*b - *a)),
+        _ => return None,
+    })
+}
+
+/// Applies the edit of the `source` parameter, whose value was `previous`
+/// before the edit, to the parameters of the other `nodes` with the same name
+/// and data type. Parameters that are connected are left as they are. Returns
+/// the edit, including the source parameter, or `None` when there was no
+/// other parameter to edit.
+pub fn fan_out_parameter_edit(
+    graph: &mut Graph,
+    nodes: &[NodeId],
+    source: InputId,
+    previous: &BlackjackValue,
+    mode: ParameterEditMode,
+) -> Option<ParameterEdit> {
+    let source_node = graph[source].node;
+    let name = graph[source_node]
+        .inputs
+        .iter()
+        .find(|(_, id)| *id == source)
+        .map(|(name, _)| name.clone())?;
+    let data_type = graph[source].typ;
+    let new = graph[source].value.0.clone();
+    let by_amount =
+        mode == ParameterEditMode::Relative && offset_value(previous, previous, &new).is_some();
+
+    let targets = nodes
+        .iter()
+        .filter(|node| **node != source_node && graph.nodes.contains_key(**node))
+        .filter_map(|node| {
+            graph[*node]
+                .inputs
+                .iter()
+                .find(|(input_name, _)| *input_name == name)
+                .map(|(_, id)| *id)
+        })
+        .filter(|input| graph[*input].typ == data_type && graph.connection(*input).is_none())
+        .collect_vec();
+
+    let mut edit = ParameterEdit {
+        previous: vec![(source, previous.clone())],
+    };
+    for input in targets {
+        let value = &mut graph[input].value.0;
+        let edited = if by_amount {
+            offset_value(value, previous, &new)
+        } else {
+            Some(new.clone())
+        };
+        // Values that can't change by an amount, like expressions, are kept
+        if let Some(edited) = edited {
+            edit.previous
+                .push((input, std::mem::replace(value, edited)));
+        }
+    }
+    (edit.previous.len() > 1).then_some(edit)
+}
+
+/// Compares the parameters of `nodes` before and after drawing the graph, and
+/// returns the first one that changed, along with its previous value.
+pub fn find_edited_parameter(
+    old_graph: &Graph,
+    graph: &Graph,
+    nodes: &[NodeId],
+) -> Option<(InputId, BlackjackValue)> {
+    nodes
+        .iter()
+        .filter(|node| graph.nodes.contains_key(**node))
+        .flat_map(|node| graph[*node].inputs.iter().map(|(_, id)| *id))
+        .find_map(|input| {
+            let old = &old_graph.inputs.get(input)?.value.0;
+            (*old != graph[input].value.0).then(|| (input, old.clone()))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::graph::{DataTypeUi, NodeData, ValueTypeUi};
+    use blackjack_engine::graph::DataType;
+    use egui_node_graph::InputParamKind;
+
+    /// Adds a node with the given parameters, and returns its inputs.
+    fn add_node(
+        graph: &mut Graph,
+        op_name: &str,
+        params: &[(&str, BlackjackValue)],
+    ) -> Vec<InputId> {
+        let node_data = NodeData {
+            op_name: op_name.into(),
+            seed: 0,
+        };
+        let node = graph.add_node(op_name.into(), node_data, |graph, node_id| {
+            for (name, value) in params {
+                let data_type = match value {
+                    BlackjackValue::Vector(_) => DataType::Vector,
+                    BlackjackValue::Scalar(_) => DataType::Scalar,
+                    BlackjackValue::Int(_) => DataType::Int,
+                    BlackjackValue::Bool(_) => DataType::Bool,
+                    _ => DataType::String,
+                };
+                graph.add_input_param(
+                    node_id,
+                    name.to_string(),
+                    DataTypeUi(data_type),
+                    ValueTypeUi(value.clone()),
+                    InputParamKind::ConnectionOrConstant,
+                    true,
+                );
+            }
+        });
+        graph[node].inputs.iter().map(|(_, id)| *id).collect()
+    }
+
+    fn node_of(graph: &Graph, input: InputId) -> NodeId {
+        graph[input].node
+    }
+
+    fn value(graph: &Graph, input: InputId) -> BlackjackValue {
+        graph[input].value.0.clone()
+    }
+
+    /// Sets the value of `input`, like its widget would, and returns the
+    /// previous one.
+    fn edit(graph: &mut Graph, input: InputId, value: BlackjackValue) -> BlackjackValue {
+        std::mem::replace(&mut graph[input].value.0, value)
+    }
+
+    #[test]
+    fn test_absolute_edit() {
+        let mut graph = Graph::new();
+        let a = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(1.0))],
+        );
+        let b = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(2.0))],
+        );
+        let unselected = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(3.0))],
+        );
+        let nodes = [node_of(&graph, a[0]), node_of(&graph, b[0])];
+
+        let previous = edit(&mut graph, a[0], BlackjackValue::Scalar(5.0));
+        let result = fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            a[0],
+            &previous,
+            ParameterEditMode::Absolute,
+        )
+        .unwrap();
+        assert_eq!(value(&graph, b[0]), BlackjackValue::Scalar(5.0));
+        assert_eq!(value(&graph, unselected[0]), BlackjackValue::Scalar(3.0));
+
+        // A single undo restores every node
+        result.undo(&mut graph);
+        assert_eq!(value(&graph, a[0]), BlackjackValue::Scalar(1.0));
+        assert_eq!(value(&graph, b[0]), BlackjackValue::Scalar(2.0));
+    }
+
+    #[test]
+    fn test_relative_edit() {
+        let mut graph = Graph::new();
+        let a = add_node(
+            &mut graph,
+            "Translate",
+            &[("amount", BlackjackValue::Vector(Vec3::ZERO))],
+        );
+        let b = add_node(
+            &mut graph,
+            "Translate",
+            &[("amount", BlackjackValue::Vector(Vec3::ONE))],
+        );
+        let nodes = [node_of(&graph, a[0]), node_of(&graph, b[0])];
+
+        let previous = edit(&mut graph, a[0], BlackjackValue::Vector(Vec3::X));
+        fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            a[0],
+            &previous,
+            ParameterEditMode::Relative,
+        )
+        .unwrap();
+        assert_eq!(
+            value(&graph, b[0]),
+            BlackjackValue::Vector(Vec3::new(2.0, 1.0, 1.0))
+        );
+
+        // Values that can't change by an amount are set
+        let a = add_node(&mut graph, "Set", &[("flag", BlackjackValue::Bool(false))]);
+        let b = add_node(&mut graph, "Set", &[("flag", BlackjackValue::Bool(false))]);
+        let nodes = [node_of(&graph, a[0]), node_of(&graph, b[0])];
+        let previous = edit(&mut graph, a[0], BlackjackValue::Bool(true));
+        fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            a[0],
+            &previous,
+            ParameterEditMode::Relative,
+        )
+        .unwrap();
+        assert_eq!(value(&graph, b[0]), BlackjackValue::Bool(true));
+    }
+
+    #[test]
+    fn test_mixed_selection() {
+        let mut graph = Graph::new();
+        let extrude = add_node(
+            &mut graph,
+            "Extrude",
+            &[
+                ("faces", BlackjackValue::String("*".into())),
+                ("amount", BlackjackValue::Scalar(1.0)),
+            ],
+        );
+        // Same name and type, in a node of another type
+        let bevel = add_node(
+            &mut graph,
+            "Bevel",
+            &[("amount", BlackjackValue::Scalar(0.5))],
+        );
+        // Same name, other type
+        let subdivide = add_node(
+            &mut graph,
+            "Subdivide",
+            &[("amount", BlackjackValue::Int(2))],
+        );
+        // No parameter with that name
+        let make_box = add_node(
+            &mut graph,
+            "MakeBox",
+            &[("size", BlackjackValue::Scalar(1.0))],
+        );
+        let nodes = [extrude[0], bevel[0], subdivide[0], make_box[0]].map(|i| node_of(&graph, i));
+
+        let previous = edit(&mut graph, extrude[1], BlackjackValue::Scalar(1.5));
+        let result = fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            extrude[1],
+            &previous,
+            ParameterEditMode::Relative,
+        )
+        .unwrap();
+        assert_eq!(value(&graph, bevel[0]), BlackjackValue::Scalar(1.0));
+        assert_eq!(value(&graph, subdivide[0]), BlackjackValue::Int(2));
+        assert_eq!(value(&graph, make_box[0]), BlackjackValue::Scalar(1.0));
+        assert_eq!(
+            value(&graph, extrude[0]),
+            BlackjackValue::String("*".into())
+        );
+        assert_eq!(
+            result.previous,
+            vec![
+                (extrude[1], BlackjackValue::Scalar(1.0)),
+                (bevel[0], BlackjackValue::Scalar(0.5)),
+            ]
+        );
+
+        // Nothing else to edit
+        let previous = edit(&mut graph, subdivide[0], BlackjackValue::Int(3));
+        assert!(fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            subdivide[0],
+            &previous,
+            ParameterEditMode::Absolute,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_connected_and_expression_parameters_are_kept() {
+        let mut graph = Graph::new();
+        let a = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(1.0))],
+        );
+        let b = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(1.0))],
+        );
+        let c = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Expression("2 * 3".into()))],
+        );
+        let source = graph.add_node(
+            "Source".into(),
+            NodeData {
+                op_name: "Source".into(),
+                seed: 0,
+            },
+            |graph, node_id| {
+                graph.add_output_param(node_id, "out".into(), DataTypeUi(DataType::Scalar));
+            },
+        );
+        let output = graph[source].outputs[0].1;
+        graph.add_connection(output, b[0]);
+        let nodes = [a[0], b[0], c[0]].map(|i| node_of(&graph, i));
+
+        let previous = edit(&mut graph, a[0], BlackjackValue::Scalar(2.0));
+        let result = fan_out_parameter_edit(
+            &mut graph,
+            &nodes,
+            a[0],
+            &previous,
+            ParameterEditMode::Relative,
+        );
+        assert!(result.is_none());
+        assert_eq!(value(&graph, b[0]), BlackjackValue::Scalar(1.0));
+        assert_eq!(
+            value(&graph, c[0]),
+            BlackjackValue::Expression("2 * 3".into())
+        );
+    }
+
+    #[test]
+    fn test_find_edited_parameter() {
+        let mut graph = Graph::new();
+        let a = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(1.0))],
+        );
+        let b = add_node(
+            &mut graph,
+            "Extrude",
+            &[("amount", BlackjackValue::Scalar(1.0))],
+        );
+        let nodes = [node_of(&graph, a[0]), node_of(&graph, b[0])];
+        let old_graph = graph.clone();
+        assert_eq!(find_edited_parameter(&old_graph, &graph, &nodes), None);
+        edit(&mut graph, b[0], BlackjackValue::Scalar(4.0));
+        assert_eq!(
+            find_edited_parameter(&old_graph, &graph, &nodes),
+            Some((b[0], BlackjackValue::Scalar(1.0)))
+        );
+    }
+}
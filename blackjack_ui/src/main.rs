@@ -34,6 +34,26 @@ pub mod custom_widgets;
 /// Command line argument parsing.
 pub mod cli_args;
 
+/// Prints the differences between the `.bjk` files at paths `a` and `b`.
+fn print_graph_diff(a: &str, b: &str, json: bool, ignore_layout: bool) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use blackjack_engine::graph::{diff::graph_diff, serialization::SerializedBjkGraph};
+
+    let load = |path: &str| {
+        SerializedBjkGraph::load_from_file(path).with_context(|| format!("Could not load {path}"))
+    };
+    let mut diff = graph_diff(&load(a)?, &load(b)?);
+    if ignore_layout {
+        diff = diff.without_layout();
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff.to_report());
+    }
+    Ok(())
+}
+
 fn main() {
     #[cfg(feature = "tracy")]
     let _client = profiling::tracy_client::Client::start();
@@ -41,6 +61,20 @@ fn main() {
     // Various setup calls
     env_logger::init();
 
+    if let Some(cli_args::Command::Diff {
+        a,
+        b,
+        json,
+        ignore_layout,
+    }) = &cli_args::CLI_ARGS.command
+    {
+        if let Err(err) = print_graph_diff(a, b, *json, *ignore_layout) {
+            eprintln!("Error: {err:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Handle luadoc flag
     if let Some(ldoc_path) = &cli_args::CLI_ARGS.generate_ldoc {
         use blackjack_engine::lua_engine::lua_stdlib::lua_documentation;
@@ -11,7 +11,7 @@ use crate::{
     rendergraph::{
         face_routine::FaceRoutine, grid_routine::GridRoutine, id_picking_routine::IdPickingRoutine,
         point_cloud_routine::PointCloudRoutine, shader_manager::ShaderManager,
-        wireframe_routine::WireframeRoutine,
+        thumbnail_routine::ThumbnailRoutine, wireframe_routine::WireframeRoutine,
     },
 };
 
@@ -39,7 +39,9 @@ pub struct RenderContext {
     pub face_routine: FaceRoutine,
     pub point_cloud_routine: PointCloudRoutine,
     pub id_picking_routine: IdPickingRoutine,
-    pub surface: Arc<Surface>,
+    pub thumbnail_routine: ThumbnailRoutine,
+    /// The surface of the window, when rendering to one.
+    pub surface: Option<Arc<Surface>>,
     pub adapter: Arc<Adapter>,
     pub texture_format: TextureFormat,
     pub shader_manager: ShaderManager,
@@ -71,12 +73,42 @@ impl RenderContext {
             get_present_mode(&surface, &adapter),
         );
 
-        let renderer = r3::Renderer::new(
+        Self::from_iad(
             iad,
-            r3::Handedness::Left,
-            Some(window_size.width as f32 / window_size.height as f32),
+            Some(surface),
+            format,
+            window_size.width as f32 / window_size.height as f32,
         )
-        .unwrap();
+    }
+
+    /// Creates a render context without a window, which can only render to
+    /// offscreen targets. Returns `None` when there is no graphics adapter
+    /// available, e.g. in CI machines.
+    pub fn new_headless() -> Option<Self> {
+        let iad = pollster::block_on(rend3::create_iad(
+            None,
+            None,
+            Some(rend3::RendererProfile::CpuDriven),
+            None,
+        ))
+        .ok()?;
+        // The same format used by the viewport output
+        Some(Self::from_iad(
+            iad,
+            None,
+            TextureFormat::Bgra8UnormSrgb,
+            1.0,
+        ))
+    }
+
+    fn from_iad(
+        iad: rend3::InstanceAdapterDevice,
+        surface: Option<Arc<Surface>>,
+        format: TextureFormat,
+        aspect_ratio: f32,
+    ) -> Self {
+        let adapter = iad.adapter.clone();
+        let renderer = r3::Renderer::new(iad, r3::Handedness::Left, Some(aspect_ratio)).unwrap();
 
         let mut spp = rend3::ShaderPreProcessor::new();
         rend3_routine::builtin_shaders(&mut spp);
@@ -107,6 +139,7 @@ impl RenderContext {
             point_cloud_routine,
             face_routine,
             id_picking_routine,
+            thumbnail_routine: ThumbnailRoutine::new(),
             surface,
             adapter,
             texture_format: format,
@@ -171,12 +204,14 @@ impl RenderContext {
     }
 
     pub fn on_resize(&mut self, width: u32, height: u32) {
-        rend3::configure_surface(
-            &self.surface,
-            &self.renderer.device,
-            self.texture_format,
-            glam::uvec2(width, height),
-            get_present_mode(&self.surface, &self.adapter),
-        );
+        if let Some(surface) = &self.surface {
+            rend3::configure_surface(
+                surface,
+                &self.renderer.device,
+                self.texture_format,
+                glam::uvec2(width, height),
+                get_present_mode(surface, &self.adapter),
+            );
+        }
     }
 }
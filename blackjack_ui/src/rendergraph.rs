@@ -26,12 +26,25 @@ pub mod point_cloud_routine;
 /// A render routine to draw meshes
 pub mod face_routine;
 
+/// Screen-space ambient occlusion for the faces of the meshes
+pub mod ssao_routine;
+
 /// A routine to implement object picking, by reading the id_map buffer.
 pub mod id_picking_routine;
 
+/// A routine to capture the 3d viewport as the thumbnail of saved files.
+pub mod thumbnail_routine;
+
 /// Shader manager struct which sets up loading with a basic preprocessor
 pub mod shader_manager;
 
+/// Rendering the 3d viewport without a window
+pub mod offscreen;
+
+/// Comparing the output of the viewport routines against golden images
+#[cfg(test)]
+mod golden_tests;
+
 /// Adds the necessary nodes to render the 3d viewport of the app. The viewport
 /// is rendered into a render target, and its handle is returned.
 #[allow(clippy::too_many_arguments)]
@@ -82,12 +95,14 @@ pub fn blackjack_viewport_rendergraph<'node>(
     }
     use crate::application::viewport_3d::FaceDrawMode::*;
     if matches!(settings.face_mode, Flat | Smooth | Real) {
-        routines.face.add_to_graph(graph, &state, id_map, settings);
+        routines
+            .face
+            .add_to_graph(graph, &state, id_map, resolution, settings);
     }
 
     routines.id_picking.add_to_graph(graph, resolution, id_map);
 
-    routines.grid.add_to_graph(graph, &state);
+    routines.grid.add_to_graph(graph, &state, settings.grid);
 
     // Make the reference to the surface
     let output = graph.add_render_target(r3::RenderTargetDescriptor {
@@ -95,9 +110,13 @@ pub fn blackjack_viewport_rendergraph<'node>(
         resolution,
         samples,
         format: r3::TextureFormat::Bgra8UnormSrgb,
-        usage: r3::TextureUsages::RENDER_ATTACHMENT | r3::TextureUsages::TEXTURE_BINDING,
+        usage: r3::TextureUsages::RENDER_ATTACHMENT
+            | r3::TextureUsages::TEXTURE_BINDING
+            | r3::TextureUsages::COPY_SRC,
     });
     state.tonemapping(graph, routines.tonemapping, output);
 
+    routines.thumbnail.add_to_graph(graph, resolution, output);
+
     output
 }
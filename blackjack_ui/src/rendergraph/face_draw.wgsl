@@ -16,6 +16,35 @@ var<storage> positions: Vec3Array;
 var<storage> normals: Vec3Array;
 @group(1) @binding(2)
 var matcap: texture_2d<f32>;
+@group(1) @binding(3)
+var ambient_occlusion: texture_2d<f32>;
+
+struct Lighting {
+    // The direction towards each light in view space, and its intensity
+    lights: array<vec4<f32>, 3>,
+    // The ambient intensity, then 1 when the ambient occlusion is used
+    ambient: vec4<f32>,
+};
+
+@group(1) @binding(4)
+var<uniform> lighting: Lighting;
+
+/// The ambient occlusion at the pixel, averaged over the 4x4 block around it
+/// to remove the noise of the occlusion pass.
+fn ambient_occlusion_at(pixel: vec2<i32>) -> f32 {
+    if (lighting.ambient.y == 0.0) {
+        return 1.0;
+    }
+    let size = textureDimensions(ambient_occlusion);
+    var total = 0.0;
+    for (var x = -2; x < 2; x = x + 1) {
+        for (var y = -2; y < 2; y = y + 1) {
+            let p = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+            total = total + textureLoad(ambient_occlusion, p, 0).x;
+        }
+    }
+    return total / 16.0;
+}
 
 @vertex
 fn vs_main(
@@ -34,10 +63,19 @@ fn vs_main(
 fn fs_main(input: VertexOutput) -> FragmentOutput {
     var out : FragmentOutput;
 
-    let muv = (uniforms.view * vec4<f32>(normalize(input.normal), 0.0)).xy;
-    let muv = muv * 0.5 + vec2<f32>(0.5, 0.5);
+    let view_normal = normalize((uniforms.view * vec4<f32>(normalize(input.normal), 0.0)).xyz);
+    let muv = view_normal.xy * 0.5 + vec2<f32>(0.5, 0.5);
+
+    let color = textureSample(matcap, primary_sampler, vec2<f32>(muv.x, 1.0 - muv.y));
 
-    out.color = textureSample(matcap, primary_sampler, vec2<f32>(muv.x, 1.0 - muv.y));
+    // With the default settings there are no lights and the ambient is 1, so
+    // faces are shaded by the matcap alone.
+    var light = lighting.ambient.x * ambient_occlusion_at(vec2<i32>(input.clip_position.xy));
+    for (var i = 0; i < 3; i = i + 1) {
+        let l = lighting.lights[i];
+        light = light + l.w * max(dot(view_normal, l.xyz), 0.0);
+    }
+    out.color = vec4<f32>(color.rgb * light, color.a);
 
     return out;
 }
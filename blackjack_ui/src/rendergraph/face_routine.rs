@@ -6,8 +6,11 @@
 
 use std::sync::Arc;
 
-use crate::{application::viewport_3d::Viewport3dSettings, prelude::r3};
-use glam::{Vec3, Vec4};
+use crate::{
+    application::viewport_3d::{LightingSettings, Viewport3dSettings},
+    prelude::r3,
+};
+use glam::{UVec2, Vec3, Vec4};
 
 use rend3::{
     managers::TextureManager,
@@ -21,6 +24,7 @@ use wgpu::{
 
 use super::{
     shader_manager::ShaderManager,
+    ssao_routine::SsaoRoutine,
     viewport_3d_routine::{DrawType, RoutineLayout, Viewport3dRoutine},
 };
 
@@ -32,16 +36,48 @@ pub const NUM_MATCAPS: usize = 6;
 /// vertex pulling and instance ids to simulate indices, this buffer structure
 /// uses a real index buffer. This simplifies things like smooth normals
 pub struct MeshFacesLayout {
-    indices: Buffer,
-    positions: Buffer,
-    normals: Buffer,
+    pub(super) indices: Buffer,
+    pub(super) positions: Buffer,
+    pub(super) normals: Buffer,
     matcaps: Arc<Vec<TextureHandle>>,
-    num_indices: usize,
+    /// The [`LightingUniform`], shared by all the meshes.
+    lighting: Arc<Buffer>,
+    pub(super) num_indices: usize,
+}
+
+/// The lights of the viewport, in view space. Each light is a direction
+/// towards it and an intensity in `w`. The ambient intensity is stored in the
+/// `x` of `ambient`, and its `y` is 1 when the ambient occlusion target
+/// should be used.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    lights: [[f32; 4]; 3],
+    ambient: [f32; 4],
+}
+
+impl LightingUniform {
+    fn new(lighting: &LightingSettings) -> Self {
+        Self {
+            lights: lighting
+                .lights()
+                .map(|(dir, intensity)| dir.extend(intensity).to_array()),
+            ambient: [
+                lighting.ambient,
+                if lighting.ssao { 1.0 } else { 0.0 },
+                0.0,
+                0.0,
+            ],
+        }
+    }
 }
 
 const BASE_MESH_NUM_BUFFERS: usize = 2;
 const BASE_MESH_NUM_TEXTURES: usize = 1;
-impl RoutineLayout<BASE_MESH_NUM_BUFFERS, BASE_MESH_NUM_TEXTURES> for MeshFacesLayout {
+const BASE_MESH_NUM_UNIFORMS: usize = 1;
+impl RoutineLayout<BASE_MESH_NUM_BUFFERS, BASE_MESH_NUM_TEXTURES, BASE_MESH_NUM_UNIFORMS>
+    for MeshFacesLayout
+{
     type Settings = Viewport3dSettings;
 
     fn get_wgpu_buffers(&self, _settings: &Viewport3dSettings) -> [&Buffer; BASE_MESH_NUM_BUFFERS] {
@@ -56,8 +92,13 @@ impl RoutineLayout<BASE_MESH_NUM_BUFFERS, BASE_MESH_NUM_TEXTURES> for MeshFacesL
         [texture_manager.get_view(self.matcaps[settings.matcap % NUM_MATCAPS].get_raw())]
     }
 
-    fn get_wgpu_uniforms(&self, _settings: &Self::Settings) -> [&Buffer; 0] {
-        []
+    /// The ambient occlusion, see [`SsaoRoutine`].
+    fn num_input_targets() -> usize {
+        1
+    }
+
+    fn get_wgpu_uniforms(&self, _settings: &Self::Settings) -> [&Buffer; BASE_MESH_NUM_UNIFORMS] {
+        [&self.lighting]
     }
 
     fn get_draw_type(&self, _settings: &Self::Settings) -> DrawType<'_> {
@@ -117,10 +158,16 @@ impl RoutineLayout<OVERLAY_NUM_BUFFERS, 0, OVERLAY_NUM_UNIFORMS> for FaceOverlay
 
 pub struct FaceRoutine {
     matcaps: Arc<Vec<TextureHandle>>,
-    base_mesh_routine:
-        Viewport3dRoutine<MeshFacesLayout, BASE_MESH_NUM_BUFFERS, BASE_MESH_NUM_TEXTURES>,
+    lighting: Arc<Buffer>,
+    base_mesh_routine: Viewport3dRoutine<
+        MeshFacesLayout,
+        BASE_MESH_NUM_BUFFERS,
+        BASE_MESH_NUM_TEXTURES,
+        BASE_MESH_NUM_UNIFORMS,
+    >,
     face_overlay_routine:
         Viewport3dRoutine<FaceOverlayLayout, OVERLAY_NUM_BUFFERS, 0, OVERLAY_NUM_UNIFORMS>,
+    ssao: SsaoRoutine,
 }
 
 impl FaceRoutine {
@@ -158,8 +205,15 @@ impl FaceRoutine {
         load_matcap!("34352A_718184_50605E_6E6761");
         load_matcap!("2E763A_78A0B7_B3D1CF_14F209");
 
+        let lighting = renderer.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("viewport lighting"),
+            contents: bytemuck::bytes_of(&LightingUniform::new(&LightingSettings::default())),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
         Self {
             matcaps: Arc::new(matcaps),
+            lighting: Arc::new(lighting),
             base_mesh_routine: Viewport3dRoutine::new(
                 "base mesh",
                 &renderer.device,
@@ -176,6 +230,7 @@ impl FaceRoutine {
                 PrimitiveTopology::TriangleList,
                 FrontFace::Cw,
             ),
+            ssao: SsaoRoutine::new(&renderer.device, base, shader_manager),
         }
     }
 
@@ -211,6 +266,7 @@ impl FaceRoutine {
             normals,
             indices,
             matcaps: self.matcaps.clone(),
+            lighting: self.lighting.clone(),
             num_indices,
         });
     }
@@ -263,16 +319,48 @@ impl FaceRoutine {
         self.face_overlay_routine.clear();
     }
 
+    /// Writes the lighting settings to the uniform buffer shared by the
+    /// meshes, before they are drawn.
+    fn upload_lighting<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        lighting: LightingSettings,
+    ) {
+        let mut builder = graph.add_node("base mesh: upload lighting");
+        let pt_handle = builder.passthrough_ref(self);
+        // Nothing reads an output of this node, so it would be culled.
+        builder.add_external_output();
+        builder.build(
+            move |pt, renderer, _encoder_or_pass, _temps, _ready, _graph_data| {
+                let this = pt.get(pt_handle);
+                renderer.queue.write_buffer(
+                    &this.lighting,
+                    0,
+                    bytemuck::bytes_of(&LightingUniform::new(&lighting)),
+                );
+            },
+        );
+    }
+
     pub fn add_to_graph<'node>(
         &'node self,
         graph: &mut r3::RenderGraph<'node>,
         state: &BaseRenderGraphIntermediateState,
         id_map: r3::RenderTargetHandle,
+        resolution: UVec2,
         settings: &'node Viewport3dSettings,
     ) {
+        self.upload_lighting(graph, settings.lighting);
+        let ambient_occlusion = self.ssao.add_to_graph(
+            graph,
+            state,
+            &self.base_mesh_routine.layouts,
+            resolution,
+            settings.lighting,
+        );
         self.base_mesh_routine
-            .add_to_graph(graph, state, settings, &[]);
+            .add_to_graph(graph, state, settings, &[], &[ambient_occlusion]);
         self.face_overlay_routine
-            .add_to_graph(graph, state, &(), &[id_map]);
+            .add_to_graph(graph, state, &(), &[id_map], &[]);
     }
 }
@@ -0,0 +1,307 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Renders a fixture mesh through the viewport routines, and compares the
+//! results against the golden images in `test/goldens`. The tests are skipped
+//! when there is no graphics adapter. Running them with the
+//! `BLACKJACK_UPDATE_GOLDENS` environment variable set stores the current
+//! results as the new goldens instead.
+
+use std::path::Path;
+
+use blackjack_engine::prelude::{primitives, HalfEdgeMesh};
+
+use crate::application::application_context::render_halfedge_mesh;
+use crate::application::viewport_3d::{
+    EdgeDrawMode, FaceDrawMode, GridPlane, GridSettings, LightingSettings, TextOverlayMode,
+    Viewport3dSettings,
+};
+use crate::prelude::*;
+
+use super::offscreen::render_viewport_offscreen;
+
+const GOLDENS_DIR: &str = "../test/goldens";
+const UPDATE_GOLDENS_VAR: &str = "BLACKJACK_UPDATE_GOLDENS";
+
+/// The width and height of the rendered images
+const RESOLUTION: u32 = 128;
+
+/// Pixels are equal when no channel differs by more than this. Leaves room
+/// for differences in blending and filtering between drivers.
+const CHANNEL_TOLERANCE: u8 = 24;
+
+/// The fraction of pixels that can differ before an image no longer matches
+/// its golden.
+const MAX_DIFFERENT_PIXELS: f32 = 0.01;
+
+fn settings(
+    face_mode: FaceDrawMode,
+    edge_mode: EdgeDrawMode,
+    vertices: bool,
+) -> Viewport3dSettings {
+    Viewport3dSettings {
+        render_vertices: vertices,
+        matcap: 0,
+        edge_mode,
+        face_mode,
+        overlay_mode: TextOverlayMode::NoDraw,
+        show_materials: false,
+        heatmap: None,
+        grid: GridSettings::default(),
+        lighting: LightingSettings::default(),
+    }
+}
+
+/// A box and a sphere next to it. Back faces are culled, so faces with the
+/// wrong winding leave holes in the box.
+fn fixture_mesh() -> HalfEdgeMesh {
+    let mut mesh = primitives::Box::build(Vec3::new(-0.8, 0.0, 0.0), Vec3::ONE).unwrap();
+    let sphere = primitives::UVSphere::build(Vec3::new(0.8, 0.0, 0.0), 12, 8, 0.6).unwrap();
+    mesh.merge_with(&sphere);
+    mesh
+}
+
+/// Returns a headless render context with the camera looking at the origin
+/// from `distance` units away, or `None` when there is no adapter to render
+/// with.
+fn render_context(distance: f32) -> Option<RenderContext> {
+    let mut render_ctx = match RenderContext::new_headless() {
+        Some(render_ctx) => render_ctx,
+        None => {
+            println!("[WARNING] No graphics adapter available. Skipping render test.");
+            return None;
+        }
+    };
+    // The same light and camera transform as the app
+    render_ctx.add_light(r3::DirectionalLight {
+        color: Vec3::ONE,
+        intensity: 10.0,
+        direction: Vec3::new(-1.0, -4.0, 2.0),
+        distance: 400.0,
+    });
+    let view = Mat4::from_translation(Vec3::Z * distance)
+        * Mat4::from_rotation_x(-25f32.to_radians())
+        * Mat4::from_rotation_y(-30f32.to_radians());
+    render_ctx.set_camera(view, 60.0);
+    Some(render_ctx)
+}
+
+/// Renders the fixture mesh with the given `settings`, from a fixed camera.
+/// Returns `None` when there is no adapter to render with.
+fn render_fixture(settings: &Viewport3dSettings, hovered: Option<u32>) -> Option<image::RgbaImage> {
+    let mut render_ctx = render_context(5.0)?;
+    render_halfedge_mesh(
+        &mut render_ctx,
+        settings,
+        &fixture_mesh(),
+        hovered,
+        &HashSet::new(),
+        None,
+        &mut None,
+    )
+    .unwrap();
+    Some(render_viewport_offscreen(&mut render_ctx, settings, UVec2::splat(RESOLUTION)).unwrap())
+}
+
+/// Returns whether the `actual` pixel at `(x, y)` is close to the golden one.
+/// Pixels can also match one of the golden neighbors, so edges rasterized
+/// one pixel off don't count as differences.
+fn pixel_matches(golden: &image::RgbaImage, actual: &image::RgbaImage, x: u32, y: u32) -> bool {
+    let (w, h) = golden.dimensions();
+    let pixel = actual.get_pixel(x, y);
+    (x.saturating_sub(1)..(x + 2).min(w))
+        .cartesian_product(y.saturating_sub(1)..(y + 2).min(h))
+        .any(|(gx, gy)| {
+            let golden = golden.get_pixel(gx, gy);
+            (0..4).all(|c| golden[c].abs_diff(pixel[c]) <= CHANNEL_TOLERANCE)
+        })
+}
+
+fn assert_matches_golden(name: &str, actual: &image::RgbaImage) {
+    let path = Path::new(GOLDENS_DIR).join(format!("{name}.png"));
+    if std::env::var_os(UPDATE_GOLDENS_VAR).is_some() {
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let golden = match image::open(&path) {
+        Ok(golden) => golden.to_rgba8(),
+        Err(err) => panic!(
+            "Could not read the golden image {}: {err}. Run the tests with {UPDATE_GOLDENS_VAR}=1 to create it.",
+            path.display()
+        ),
+    };
+    assert_eq!(golden.dimensions(), actual.dimensions());
+    let (w, h) = golden.dimensions();
+    let different = (0..w)
+        .cartesian_product(0..h)
+        .filter(|(x, y)| !pixel_matches(&golden, actual, *x, *y))
+        .count();
+    if different as f32 > (w * h) as f32 * MAX_DIFFERENT_PIXELS {
+        let actual_path = path.with_extension("actual.png");
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{different} pixels differ from the golden image {}. The rendered image was saved to {}",
+            path.display(),
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn test_render_flat_faces() {
+    let settings = settings(FaceDrawMode::Flat, EdgeDrawMode::NoDraw, false);
+    if let Some(image) = render_fixture(&settings, None) {
+        assert_matches_golden("flat_faces", &image);
+    }
+}
+
+#[test]
+fn test_render_smooth_faces() {
+    let settings = settings(FaceDrawMode::Smooth, EdgeDrawMode::NoDraw, false);
+    if let Some(image) = render_fixture(&settings, None) {
+        assert_matches_golden("smooth_faces", &image);
+    }
+}
+
+#[test]
+fn test_render_wireframe() {
+    let settings = settings(FaceDrawMode::NoDraw, EdgeDrawMode::FullEdge, false);
+    if let Some(image) = render_fixture(&settings, None) {
+        assert_matches_golden("wireframe", &image);
+    }
+}
+
+#[test]
+fn test_render_halfedge_arrows() {
+    let settings = settings(FaceDrawMode::NoDraw, EdgeDrawMode::HalfEdge, false);
+    if let Some(image) = render_fixture(&settings, None) {
+        assert_matches_golden("halfedge_arrows", &image);
+    }
+}
+
+#[test]
+fn test_render_points() {
+    let settings = settings(FaceDrawMode::NoDraw, EdgeDrawMode::NoDraw, true);
+    if let Some(image) = render_fixture(&settings, None) {
+        assert_matches_golden("points", &image);
+    }
+}
+
+/// The face overlay pass, which highlights the hovered face and writes the
+/// ids used for picking.
+#[test]
+fn test_render_face_overlay() {
+    let settings = settings(FaceDrawMode::Flat, EdgeDrawMode::FullEdge, true);
+    if let Some(image) = render_fixture(&settings, Some(0)) {
+        assert_matches_golden("face_overlay", &image);
+    }
+}
+
+/// Renders the faces of the fixture mesh lit with the given `lighting`.
+fn render_lit_fixture(lighting: LightingSettings) -> Option<image::RgbaImage> {
+    let mut settings = settings(FaceDrawMode::Smooth, EdgeDrawMode::NoDraw, false);
+    settings.lighting = lighting;
+    render_fixture(&settings, None)
+}
+
+#[test]
+fn test_render_lighting_presets() {
+    let ssao_only = LightingSettings {
+        ssao: true,
+        ..Default::default()
+    };
+    for (lighting, name) in [
+        (LightingSettings::key_light(), "lighting_key"),
+        (LightingSettings::studio(), "lighting_studio"),
+        (ssao_only, "lighting_ssao"),
+    ] {
+        if let Some(image) = render_lit_fixture(lighting) {
+            assert_matches_golden(name, &image);
+        }
+    }
+}
+
+/// Ambient occlusion only ever darkens the faces.
+#[test]
+fn test_ssao_only_darkens() {
+    let ssao = LightingSettings {
+        ssao: true,
+        ..Default::default()
+    };
+    let (with, without) = match (
+        render_lit_fixture(ssao),
+        render_lit_fixture(LightingSettings::default()),
+    ) {
+        (Some(with), Some(without)) => (with, without),
+        _ => return,
+    };
+    assert!(with
+        .pixels()
+        .zip(without.pixels())
+        .all(|(a, b)| (0..3).all(|c| a[c] <= b[c].saturating_add(CHANNEL_TOLERANCE))));
+    assert_ne!(with, without);
+}
+
+/// Renders only the grid, from `distance` units away.
+fn render_grid(grid: GridSettings, distance: f32) -> Option<image::RgbaImage> {
+    let mut settings = settings(FaceDrawMode::NoDraw, EdgeDrawMode::NoDraw, false);
+    settings.grid = grid;
+    let mut render_ctx = render_context(distance)?;
+    Some(render_viewport_offscreen(&mut render_ctx, &settings, UVec2::splat(RESOLUTION)).unwrap())
+}
+
+#[test]
+fn test_render_grid() {
+    if let Some(image) = render_grid(GridSettings::default(), 5.0) {
+        assert_matches_golden("grid", &image);
+    }
+}
+
+/// Further away, the grid switches to a larger spacing.
+#[test]
+fn test_render_grid_far() {
+    if let Some(image) = render_grid(GridSettings::default(), 200.0) {
+        assert_matches_golden("grid_far", &image);
+    }
+}
+
+#[test]
+fn test_render_grid_polar() {
+    let grid = GridSettings {
+        polar: true,
+        ..Default::default()
+    };
+    if let Some(image) = render_grid(grid, 5.0) {
+        assert_matches_golden("grid_polar", &image);
+    }
+}
+
+#[test]
+fn test_render_grid_planes() {
+    for (plane, name) in [(GridPlane::XY, "grid_xy"), (GridPlane::YZ, "grid_yz")] {
+        let grid = GridSettings {
+            plane,
+            color: [0.5, 0.5, 0.1],
+            ..Default::default()
+        };
+        if let Some(image) = render_grid(grid, 5.0) {
+            assert_matches_golden(name, &image);
+        }
+    }
+}
+
+/// Hidden grids aren't drawn at all.
+#[test]
+fn test_render_hidden_grid() {
+    let grid = GridSettings {
+        visible: false,
+        ..Default::default()
+    };
+    if let Some(image) = render_grid(grid, 5.0) {
+        assert!(image.pixels().all(|p| p == image.get_pixel(0, 0)));
+    }
+}
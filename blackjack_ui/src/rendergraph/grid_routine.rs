@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::application::viewport_3d::GridSettings;
 use crate::prelude::*;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
@@ -22,6 +23,64 @@ pub struct GridRoutineUniform {
     pub proj: [[f32; 4]; 4],
     pub inv_view: [[f32; 4]; 4],
     pub inv_proj: [[f32; 4]; 4],
+    /// The color of the grid lines, with an alpha of 1.
+    pub color: [f32; 4],
+    pub normal: [f32; 4],
+    pub u_axis: [f32; 4],
+    pub v_axis: [f32; 4],
+    pub u_axis_color: [f32; 4],
+    pub v_axis_color: [f32; 4],
+    /// The position of the camera, with its distance to the grid plane in the
+    /// last component.
+    pub camera: [f32; 4],
+    /// The spacing between the minor and the major lines, how much the minor
+    /// lines are faded out, and 1 for a polar grid or 0 otherwise.
+    pub levels: [f32; 4],
+}
+
+/// The smallest and largest spacing between grid lines, as powers of ten.
+const MIN_LEVEL: i32 = -1;
+const MAX_LEVEL: i32 = 2;
+
+/// The spacing between grid lines is 1 when the camera is at this distance.
+const UNIT_SPACING_DISTANCE: f32 = 10.0;
+
+/// The two levels of grid lines drawn at some camera distance. Lines are drawn
+/// every `minor_spacing` and every `major_spacing` units, ten times further
+/// apart. As the camera moves away, the minor lines fade out until they are
+/// gone and the major lines become the new minor ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridLevels {
+    pub minor_spacing: f32,
+    pub major_spacing: f32,
+    /// How much the minor lines are faded out, from 0 to 1.
+    pub fade: f32,
+}
+
+impl GridLevels {
+    pub fn new(camera_distance: f32) -> Self {
+        let level = (camera_distance.max(1e-6) / UNIT_SPACING_DISTANCE)
+            .log10()
+            .clamp(MIN_LEVEL as f32, MAX_LEVEL as f32);
+        // The largest spacing is only used for major lines, with the minor
+        // ones faded out completely.
+        let (minor_level, fade) = if level >= MAX_LEVEL as f32 {
+            (MAX_LEVEL - 1, 1.0)
+        } else {
+            (level.floor() as i32, level - level.floor())
+        };
+        let minor_spacing = 10f32.powi(minor_level);
+        Self {
+            minor_spacing,
+            major_spacing: minor_spacing * 10.0,
+            fade,
+        }
+    }
+}
+
+/// The conventional color of an axis: Red for X, green for Y and blue for Z.
+fn axis_color(axis: Vec3) -> [f32; 4] {
+    (axis * 0.8 + Vec3::splat(0.15)).extend(1.0).to_array()
 }
 
 impl GridRoutine {
@@ -149,6 +208,7 @@ impl GridRoutine {
         &'node self,
         graph: &mut r3::RenderGraph<'node>,
         grid_uniform_bg: r3::DataHandle<BindGroup>,
+        settings: GridSettings,
     ) {
         use wgpu::*;
         let mut builder = graph.add_node("build grid uniforms");
@@ -159,11 +219,28 @@ impl GridRoutine {
                 let this = pt.get(pt_handle);
 
                 let camera_manager = graph_data.camera_manager;
+                let camera_position = camera_manager.view().inverse().transform_point3(Vec3::ZERO);
+                let camera_distance = settings.plane.distance_to(camera_position);
+                let levels = GridLevels::new(camera_distance);
+                let (normal, u_axis, v_axis) = settings.plane.axes();
                 let cam_data = GridRoutineUniform {
                     view: camera_manager.view().to_cols_array_2d(),
                     proj: camera_manager.proj().to_cols_array_2d(),
                     inv_view: camera_manager.view().inverse().to_cols_array_2d(),
                     inv_proj: camera_manager.proj().inverse().to_cols_array_2d(),
+                    color: Vec3::from(settings.color).extend(1.0).to_array(),
+                    normal: normal.extend(0.0).to_array(),
+                    u_axis: u_axis.extend(0.0).to_array(),
+                    v_axis: v_axis.extend(0.0).to_array(),
+                    u_axis_color: axis_color(u_axis),
+                    v_axis_color: axis_color(v_axis),
+                    camera: camera_position.extend(camera_distance).to_array(),
+                    levels: [
+                        levels.minor_spacing,
+                        levels.major_spacing,
+                        levels.fade,
+                        if settings.polar { 1.0 } else { 0.0 },
+                    ],
                 };
 
                 let buffer = renderer.device.create_buffer_init(&BufferInitDescriptor {
@@ -190,9 +267,13 @@ impl GridRoutine {
         &'node self,
         graph: &mut r3::RenderGraph<'node>,
         state: &r3::BaseRenderGraphIntermediateState,
+        settings: GridSettings,
     ) {
+        if !settings.visible {
+            return;
+        }
         let grid_uniform_bg = graph.add_data::<BindGroup>();
-        self.create_bind_groups(graph, grid_uniform_bg);
+        self.create_bind_groups(graph, grid_uniform_bg, settings);
         self.grid_pass(
             graph,
             state.color,
@@ -202,3 +283,40 @@ impl GridRoutine {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grid_levels() {
+        let levels = GridLevels::new(UNIT_SPACING_DISTANCE);
+        assert_eq!(levels.minor_spacing, 1.0);
+        assert_eq!(levels.major_spacing, 10.0);
+        assert_eq!(levels.fade, 0.0);
+
+        // Halfway to the next level, in logarithmic scale
+        let levels = GridLevels::new(UNIT_SPACING_DISTANCE * 10f32.sqrt());
+        assert_eq!(levels.minor_spacing, 1.0);
+        assert!((levels.fade - 0.5).abs() < 1e-4);
+
+        // The spacing stays within bounds
+        let levels = GridLevels::new(0.0);
+        assert_eq!(levels.minor_spacing, 0.1);
+        assert_eq!(levels.fade, 0.0);
+        let levels = GridLevels::new(1e6);
+        assert_eq!(levels.major_spacing, 100.0);
+        assert_eq!(levels.fade, 1.0);
+    }
+
+    #[test]
+    fn test_grid_levels_are_continuous() {
+        // The major lines of one level are the minor lines of the next one,
+        // so they must look the same at the point where the levels change.
+        let before = GridLevels::new(UNIT_SPACING_DISTANCE * 0.9999);
+        let after = GridLevels::new(UNIT_SPACING_DISTANCE);
+        assert!((before.major_spacing - after.minor_spacing).abs() < 1e-4);
+        assert!(before.fade > 0.999);
+        assert_eq!(after.fade, 0.0);
+    }
+}
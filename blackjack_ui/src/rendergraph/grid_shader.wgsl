@@ -16,6 +16,16 @@ struct GridRoutineUniform {
     proj: mat4x4<f32>,
     inv_view: mat4x4<f32>,
     inv_proj: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: vec4<f32>,
+    u_axis: vec4<f32>,
+    v_axis: vec4<f32>,
+    u_axis_color: vec4<f32>,
+    v_axis_color: vec4<f32>,
+    // xyz: Camera position, w: Distance from the camera to the grid plane
+    camera: vec4<f32>,
+    // x: Minor spacing, y: Major spacing, z: Minor fade, w: 1 if polar
+    levels: vec4<f32>,
 };
 
 @group(0) @binding(0)
@@ -54,24 +64,54 @@ fn vs_main(
 
 // Fragment shader
 
-fn grid(frag_pos_3d: vec3<f32>, scale: f32) -> vec4<f32> {
-    let coord = frag_pos_3d.xz * scale; // use the scale variable to set the distance between the lines
+let TAU: f32 = 6.28318530718;
+
+// The number of lines going out of the origin in the polar grid
+let POLAR_SPOKES: f32 = 24.0;
+
+// Returns how much a point at `coord` is covered by the lines drawn at every
+// integer value, from 0 to 1. Lines are about one pixel wide.
+fn line_coverage(coord: f32) -> f32 {
     let derivative = fwidth(coord);
-    let grid = abs(fract(coord - 0.5) - 0.5) / derivative;
-    let grid_line = min(grid.x, grid.y);
-    let minimumz = min(derivative.y, 1.0);
-    let minimumx = min(derivative.x, 1.0);
-    var color = vec4<f32>(0.2, 0.2, 0.2, 1.0 - min(grid_line, 1.0));
+    let distance = abs(fract(coord - 0.5) - 0.5) / max(derivative, 1e-6);
+    // Lines become noise when they are closer than a few pixels
+    let too_dense = clamp(derivative * 2.0 - 0.5, 0.0, 1.0);
+    return (1.0 - min(distance, 1.0)) * (1.0 - too_dense);
+}
+
+// Returns how much a point at `coord` is covered by the axis line at 0. Axis
+// lines are a bit wider than the others.
+fn axis_coverage(coord: f32) -> f32 {
+    let distance = abs(coord) / max(fwidth(coord), 1e-6);
+    return 1.0 - min(distance / 1.5, 1.0);
+}
+
+// The coverage of the square or polar grid lines at `uv`, every `spacing` units.
+fn grid_coverage(uv: vec2<f32>, spacing: f32) -> f32 {
+    if (matrices.levels.w > 0.5) {
+        let rings = line_coverage(length(uv) / spacing);
+        let angle = atan2(uv.y, uv.x) / TAU * POLAR_SPOKES;
+        // Spokes closer to the origin than a ring would be too dense
+        let spokes = line_coverage(angle) * f32(length(uv) > spacing);
+        return max(rings, spokes);
+    } else {
+        return max(line_coverage(uv.x / spacing), line_coverage(uv.y / spacing));
+    }
+}
 
-    let threshold = 1.0 / scale;
+fn grid(uv: vec2<f32>) -> vec4<f32> {
+    let minor = grid_coverage(uv, matrices.levels.x) * (1.0 - matrices.levels.z);
+    let major = grid_coverage(uv, matrices.levels.y);
+    var color = vec4<f32>(matrices.color.rgb, max(minor, major));
 
-    // z axis
-    if (frag_pos_3d.x > -threshold * minimumx && frag_pos_3d.x < threshold * minimumx) {
-        color.z = 1.0;
+    // The line along the u axis is where v is 0, and vice versa
+    let u_axis = axis_coverage(uv.y);
+    let v_axis = axis_coverage(uv.x);
+    if (u_axis > 0.0) {
+        color = vec4<f32>(matrices.u_axis_color.rgb, max(color.a, u_axis));
     }
-    // x axis
-    if (frag_pos_3d.z > -threshold * minimumz && frag_pos_3d.z < threshold * minimumz) {
-        color.x = 1.0;
+    if (v_axis > 0.0) {
+        color = vec4<f32>(matrices.v_axis_color.rgb, max(color.a, v_axis));
     }
     return color;
 }
@@ -81,25 +121,27 @@ fn compute_depth(frag_pos_3d: vec3<f32>) -> f32 {
     return (clip_space_pos.z / clip_space_pos.w);
 }
 
-fn fading(frag_pos_3d: vec3<f32>, depth: f32) -> f32 {
-    let znear = 0.001;
-    // If you're using far plane at infinity as described here, then linearized depth is simply znear / depth.
-    // From: https://www.reddit.com/r/GraphicsProgramming/comments/f9zwin/linearising_reverse_depth_buffer/
-    let linear_depth = znear / depth;
-    return max(0.0, 2.5 - linear_depth);
+// Fades the grid out far away from the camera, relative to the distance
+// between the camera and the grid. The grid looks the same at any zoom level.
+fn fading(frag_pos_3d: vec3<f32>) -> f32 {
+    let distance = length(frag_pos_3d - matrices.camera.xyz);
+    let fade_distance = max(matrices.camera.w, 0.01) * 4.0;
+    return clamp(2.0 - distance / fade_distance, 0.0, 1.0);
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> FragmentOutput {
-    let t = -in.near_point.y / (in.far_point.y - in.near_point.y);
+    let normal = matrices.normal.xyz;
+    let t = -dot(in.near_point, normal) / dot(in.far_point - in.near_point, normal);
     let frag_pos_3d = in.near_point + t * (in.far_point - in.near_point);
+    let uv = vec2<f32>(dot(frag_pos_3d, matrices.u_axis.xyz), dot(frag_pos_3d, matrices.v_axis.xyz));
 
     let depth = compute_depth(frag_pos_3d);
 
     var out: FragmentOutput;
-    out.color = grid(frag_pos_3d, 2.0) * f32(t < 0.0);
+    out.color = grid(uv) * f32(t < 0.0);
     out.depth = depth;
-    out.color.a = out.color.a * fading(frag_pos_3d, depth);
+    out.color.a = out.color.a * fading(frag_pos_3d);
 
     return out;
 }
@@ -0,0 +1,92 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::Arc;
+
+use crate::application::viewport_3d::{Viewport3d, Viewport3dSettings};
+use crate::application::ViewportRoutines;
+use crate::prelude::*;
+
+/// Renders the 3d viewport into an image of the given `resolution`, without
+/// a window or the UI. The buffers of the meshes to draw must have been added
+/// to the routines of `render_ctx`, and its camera set.
+pub fn render_viewport_offscreen(
+    render_ctx: &mut RenderContext,
+    settings: &Viewport3dSettings,
+    resolution: UVec2,
+) -> Result<image::RgbaImage> {
+    render_ctx
+        .renderer
+        .set_aspect_ratio(resolution.x as f32 / resolution.y as f32);
+
+    let RenderContext {
+        ref renderer,
+        ref base_graph,
+        ref pbr_routine,
+        ref tonemapping_routine,
+        ref grid_routine,
+        ref wireframe_routine,
+        ref point_cloud_routine,
+        ref face_routine,
+        ref id_picking_routine,
+        ref mut thumbnail_routine,
+        texture_format,
+        ..
+    } = *render_ctx;
+    thumbnail_routine.request(&renderer.device, resolution);
+
+    // Nothing gets presented, but rend3 needs a frame to execute the graph
+    let frame_texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen frame"),
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: texture_format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    let frame = rend3::util::output::OutputFrame::View(Arc::new(
+        frame_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+    ));
+
+    let (cmd_bufs, ready) = renderer.ready();
+    let mut graph = r3::RenderGraph::new();
+    super::blackjack_viewport_rendergraph(
+        &mut graph,
+        &ready,
+        ViewportRoutines {
+            base_graph,
+            pbr: pbr_routine,
+            tonemapping: tonemapping_routine,
+            grid: grid_routine,
+            wireframe: wireframe_routine,
+            point_cloud: point_cloud_routine,
+            face: face_routine,
+            id_picking: id_picking_routine,
+            thumbnail: thumbnail_routine,
+        },
+        resolution,
+        r3::SampleCount::One,
+        Viewport3d::ambient_light(),
+        settings,
+    );
+
+    renderer
+        .device
+        .push_error_scope(wgpu::ErrorFilter::Validation);
+    graph.execute(renderer, frame, cmd_bufs, &ready);
+    if let Some(error) = pollster::block_on(renderer.device.pop_error_scope()) {
+        bail!("Error validating WebGPU: {error}.");
+    }
+
+    thumbnail_routine
+        .take_image(&renderer.device)
+        .ok_or_else(|| anyhow!("The viewport was not captured"))
+}
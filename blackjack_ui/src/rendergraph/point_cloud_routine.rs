@@ -91,6 +91,6 @@ impl PointCloudRoutine {
         graph: &mut r3::RenderGraph<'node>,
         state: &BaseRenderGraphIntermediateState,
     ) {
-        self.inner.add_to_graph(graph, state, &(), &[]);
+        self.inner.add_to_graph(graph, state, &(), &[], &[]);
     }
 }
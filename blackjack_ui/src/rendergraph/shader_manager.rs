@@ -162,6 +162,37 @@ impl ShaderManager {
             ]
         );
 
+        // The ambient occlusion passes only draw to offscreen buffers, see
+        // `ssao_routine`. First the view space normals and positions of the
+        // faces, then the occlusion computed from them.
+        def_shader!(
+            "ssao_geometry_draw",
+            "ssao_geometry_draw.wgsl",
+            custom,
+            vec![
+                ShaderColorTarget::Offscreen(ColorTargetState {
+                    format: super::ssao_routine::NORMALS_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                ShaderColorTarget::Offscreen(ColorTargetState {
+                    format: super::ssao_routine::POSITIONS_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ]
+        );
+        def_shader!(
+            "ssao",
+            "ssao.wgsl",
+            custom,
+            vec![ShaderColorTarget::Offscreen(ColorTargetState {
+                format: super::ssao_routine::OCCLUSION_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })]
+        );
+
         Self { shaders }
     }
 
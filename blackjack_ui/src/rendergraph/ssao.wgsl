@@ -0,0 +1,100 @@
+#include <utils.wgsl>
+
+struct SsaoUniform {
+    proj: mat4x4<f32>,
+    // The radius and strength of the occlusion, then the resolution in pixels
+    params: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var normals: texture_2d<f32>;
+@group(0) @binding(1)
+var positions: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> ssao: SsaoUniform;
+
+let NUM_SAMPLES: i32 = 16;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+struct FragmentOutput {
+    @location(0) occlusion: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    // A single triangle that covers the whole screen
+    let uv = vec2<f32>(f32((vertex_idx << 1u) & 2u), f32(vertex_idx & 2u));
+    var output : VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+/// A random number in [0, 1) for the i-th use at the pixel. The numbers repeat
+/// every 4x4 pixels, so the face shader can blur the noise away by averaging
+/// blocks of that size.
+fn noise(pixel: vec2<i32>, i: i32) -> f32 {
+    let tile = vec2<u32>(pixel % vec2<i32>(4, 4));
+    return float_construct(hash_v3(vec3<u32>(tile, u32(i))));
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> FragmentOutput {
+    var out : FragmentOutput;
+    let pixel = vec2<i32>(input.clip_position.xy);
+    let position = textureLoad(positions, pixel, 0);
+
+    // Pixels without faces are not occluded
+    if (position.w == 0.0) {
+        out.occlusion = vec4<f32>(1.0);
+        return out;
+    }
+
+    let normal = normalize(textureLoad(normals, pixel, 0).xyz);
+    let radius = ssao.params.x;
+    let resolution = ssao.params.zw;
+
+    // A basis around the normal, rotated randomly at each pixel of the tile
+    let random_dir = vec3<f32>(noise(pixel, 0) * 2.0 - 1.0, noise(pixel, 1) * 2.0 - 1.0, 0.0);
+    var tangent = random_dir - normal * dot(random_dir, normal);
+    if (length(tangent) < 0.001) {
+        tangent = cross(normal, vec3<f32>(1.0, 0.0, 0.0));
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occlusion = 0.0;
+    for (var i = 0; i < NUM_SAMPLES; i = i + 1) {
+        // Points in the hemisphere above the surface, more of them close to
+        // the center.
+        let dir = vec3<f32>(
+            noise(pixel, 3 * i + 2) * 2.0 - 1.0,
+            noise(pixel, 3 * i + 3) * 2.0 - 1.0,
+            noise(pixel, 3 * i + 4) + 0.05
+        );
+        var scale = f32(i + 1) / f32(NUM_SAMPLES);
+        scale = mix(0.1, 1.0, scale * scale);
+        let sample_pos = position.xyz + tbn * normalize(dir) * scale * radius;
+
+        let clip = ssao.proj * vec4<f32>(sample_pos, 1.0);
+        let ndc = clip.xy / clip.w;
+        let sample_pixel = vec2<i32>((vec2<f32>(ndc.x, -ndc.y) * 0.5 + 0.5) * resolution);
+        if (all(sample_pixel >= vec2<i32>(0, 0)) && all(sample_pixel < vec2<i32>(resolution))) {
+            let scene = textureLoad(positions, sample_pixel, 0);
+            // The camera looks along +Z, so a visible surface in front of the
+            // sample point has a smaller z. Surfaces much further away than
+            // the radius don't occlude the pixel.
+            let in_range = smoothstep(0.0, 1.0, radius / max(abs(position.z - scene.z), 0.0001));
+            if (scene.w > 0.0 && scene.z < sample_pos.z - 0.02 * radius) {
+                occlusion = occlusion + in_range;
+            }
+        }
+    }
+
+    let ambient = 1.0 - ssao.params.y * occlusion / f32(NUM_SAMPLES);
+    out.occlusion = vec4<f32>(clamp(ambient, 0.0, 1.0));
+    return out;
+}
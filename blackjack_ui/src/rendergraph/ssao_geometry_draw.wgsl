@@ -0,0 +1,41 @@
+#include <utils.wgsl>
+#include <rend3_uniforms.wgsl>
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) view_position: vec3<f32>,
+    @location(1) view_normal: vec3<f32>,
+};
+
+struct FragmentOutput {
+    @location(0) normal: vec4<f32>,
+    @location(1) position: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<storage> positions: Vec3Array;
+@group(1) @binding(1)
+var<storage> normals: Vec3Array;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_idx: u32,
+) -> VertexOutput {
+    let position = unpack_v3(positions.inner[vertex_idx]);
+    let normal = unpack_v3(normals.inner[vertex_idx]);
+
+    var output : VertexOutput;
+    output.clip_position = uniforms.view_proj * vec4<f32>(position, 1.0);
+    output.view_position = (uniforms.view * vec4<f32>(position, 1.0)).xyz;
+    output.view_normal = (uniforms.view * vec4<f32>(normal, 0.0)).xyz;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> FragmentOutput {
+    var out : FragmentOutput;
+    out.normal = vec4<f32>(normalize(input.view_normal), 1.0);
+    // The `w` tells the occlusion pass there is a face at this pixel
+    out.position = vec4<f32>(input.view_position, 1.0);
+    return out;
+}
@@ -0,0 +1,350 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The faces of the base meshes are first drawn into two offscreen targets,
+//! with their view space normals and positions. A fullscreen pass then
+//! estimates how occluded each pixel is by looking at the positions around
+//! it. The face shader reads the result to darken its ambient light.
+
+use crate::application::viewport_3d::LightingSettings;
+use crate::prelude::r3;
+use glam::UVec2;
+use rend3::{
+    graph::DataHandle,
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+};
+use rend3_routine::base::{BaseRenderGraph, BaseRenderGraphIntermediateState};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    *,
+};
+
+use super::{common, face_routine::MeshFacesLayout, shader_manager::ShaderManager};
+
+/// The formats of the targets the passes draw to.
+pub const NORMALS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const POSITIONS_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+pub const OCCLUSION_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// How much a fully occluded pixel is darkened.
+const STRENGTH: f32 = 1.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    proj: [[f32; 4]; 4],
+    /// The radius and strength of the occlusion, then the resolution in
+    /// pixels.
+    params: [f32; 4],
+}
+
+pub struct SsaoRoutine {
+    geometry_bgl: BindGroupLayout,
+    geometry_pipeline: RenderPipeline,
+    occlusion_bgl: BindGroupLayout,
+    occlusion_pipeline: RenderPipeline,
+}
+
+impl SsaoRoutine {
+    pub fn new(device: &Device, base: &BaseRenderGraph, shader_manager: &ShaderManager) -> Self {
+        let geometry_bgl = {
+            let mut builder = BindGroupLayoutBuilder::new();
+            // The positions and normals of the mesh
+            for _ in 0..2 {
+                builder.append(
+                    ShaderStages::VERTEX_FRAGMENT,
+                    BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    None,
+                );
+            }
+            builder.build(device, Some("ssao geometry bgl"))
+        };
+        let shader = shader_manager.get("ssao_geometry_draw");
+        let geometry_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("ssao geometry render pipeline"),
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&base.interfaces.forward_uniform_bgl, &geometry_bgl],
+                push_constant_ranges: &[],
+            })),
+            vertex: shader.to_vertex_state(&[]),
+            primitive: common::primitive_state(PrimitiveTopology::TriangleList, FrontFace::Cw),
+            depth_stencil: Some(common::depth_stencil(true)),
+            multisample: MultisampleState::default(),
+            fragment: Some(shader.get_fragment_state()),
+            multiview: None,
+        });
+
+        let occlusion_bgl = {
+            let mut builder = BindGroupLayoutBuilder::new();
+            // The normals and positions targets
+            for _ in 0..2 {
+                builder.append(
+                    ShaderStages::FRAGMENT,
+                    BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    None,
+                );
+            }
+            builder.append(
+                ShaderStages::FRAGMENT,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                None,
+            );
+            builder.build(device, Some("ssao bgl"))
+        };
+        let shader = shader_manager.get("ssao");
+        let occlusion_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("ssao render pipeline"),
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&occlusion_bgl],
+                push_constant_ranges: &[],
+            })),
+            vertex: shader.to_vertex_state(&[]),
+            // A single triangle covering the whole target
+            primitive: PrimitiveState {
+                cull_mode: None,
+                ..common::primitive_state(PrimitiveTopology::TriangleList, FrontFace::Ccw)
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(shader.get_fragment_state()),
+            multiview: None,
+        });
+
+        Self {
+            geometry_bgl,
+            geometry_pipeline,
+            occlusion_bgl,
+            occlusion_pipeline,
+        }
+    }
+
+    fn create_geometry_bind_groups<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        meshes: &'node [MeshFacesLayout],
+        out_bgs: DataHandle<Vec<BindGroup>>,
+    ) {
+        let mut builder = graph.add_node("ssao: create geometry bind groups");
+        let pt_handle = builder.passthrough_ref(self);
+        let out_bgs = builder.add_data_output(out_bgs);
+
+        builder.build(
+            move |pt, renderer, _encoder_or_pass, _temps, _ready, graph_data| {
+                let this = pt.get(pt_handle);
+                let bind_groups = meshes
+                    .iter()
+                    .map(|mesh| {
+                        let mut builder = BindGroupBuilder::new();
+                        builder.append_buffer(&mesh.positions);
+                        builder.append_buffer(&mesh.normals);
+                        builder.build(&renderer.device, None, &this.geometry_bgl)
+                    })
+                    .collect();
+                graph_data.set_data(out_bgs, Some(bind_groups));
+            },
+        );
+    }
+
+    fn geometry_pass<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        state: &BaseRenderGraphIntermediateState,
+        meshes: &'node [MeshFacesLayout],
+        in_bgs: DataHandle<Vec<BindGroup>>,
+        [normals, positions, depth]: [r3::RenderTargetHandle; 3],
+    ) {
+        let mut builder = graph.add_node("ssao: draw geometry");
+        let normals = builder.add_render_target_output(normals);
+        let positions = builder.add_render_target_output(positions);
+        let depth = builder.add_render_target_output(depth);
+        let in_bgs = builder.add_data_input(in_bgs);
+        let forward_uniform_bg = builder.add_data_input(state.forward_uniform_bg);
+        let pt_handle = builder.passthrough_ref(self);
+
+        let rpass_handle = builder.add_renderpass(r3::RenderPassTargets {
+            targets: vec![
+                r3::RenderPassTarget {
+                    color: normals,
+                    clear: Color::TRANSPARENT,
+                    resolve: None,
+                },
+                // Pixels without faces are left with a `w` of 0
+                r3::RenderPassTarget {
+                    color: positions,
+                    clear: Color::TRANSPARENT,
+                    resolve: None,
+                },
+            ],
+            depth_stencil: Some(r3::RenderPassDepthTarget {
+                target: r3::DepthHandle::RenderTarget(depth),
+                depth_clear: Some(0.0),
+                stencil_clear: None,
+            }),
+        });
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, temps, _ready, graph_data| {
+                let this = pt.get(pt_handle);
+                let pass = encoder_or_pass.get_rpass(rpass_handle);
+                let in_bgs = graph_data.get_data(temps, in_bgs).unwrap();
+                let forward_uniform_bg = graph_data.get_data(temps, forward_uniform_bg).unwrap();
+
+                pass.set_pipeline(&this.geometry_pipeline);
+                pass.set_bind_group(0, forward_uniform_bg, &[]);
+                for (mesh, bg) in meshes.iter().zip(in_bgs.iter()) {
+                    pass.set_bind_group(1, bg, &[]);
+                    pass.set_index_buffer(mesh.indices.slice(..), IndexFormat::Uint32);
+                    pass.draw_indexed(0..mesh.num_indices as u32, 0, 0..1);
+                }
+            },
+        );
+    }
+
+    fn create_occlusion_bind_group<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        [normals, positions]: [r3::RenderTargetHandle; 2],
+        lighting: LightingSettings,
+        resolution: UVec2,
+        out_bg: DataHandle<BindGroup>,
+    ) {
+        let mut builder = graph.add_node("ssao: create bind group");
+        let normals = builder.add_render_target_input(normals);
+        let positions = builder.add_render_target_input(positions);
+        let out_bg = builder.add_data_output(out_bg);
+        let pt_handle = builder.passthrough_ref(self);
+
+        builder.build(
+            move |pt, renderer, _encoder_or_pass, _temps, _ready, graph_data| {
+                let this = pt.get(pt_handle);
+                let uniform = SsaoUniform {
+                    proj: graph_data.camera_manager.proj().to_cols_array_2d(),
+                    params: [
+                        lighting.ssao_radius,
+                        STRENGTH,
+                        resolution.x as f32,
+                        resolution.y as f32,
+                    ],
+                };
+                let buffer = renderer.device.create_buffer_init(&BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::bytes_of(&uniform),
+                    usage: BufferUsages::UNIFORM,
+                });
+
+                let mut builder = BindGroupBuilder::new();
+                builder.append_texture_view(graph_data.get_render_target(normals));
+                builder.append_texture_view(graph_data.get_render_target(positions));
+                builder.append_buffer(&buffer);
+                let bind_group =
+                    builder.build(&renderer.device, Some("ssao bg"), &this.occlusion_bgl);
+                graph_data.set_data(out_bg, Some(bind_group));
+            },
+        );
+    }
+
+    fn occlusion_pass<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        occlusion: r3::RenderTargetHandle,
+        in_bg: Option<DataHandle<BindGroup>>,
+    ) {
+        let mut builder = graph.add_node("ssao: occlusion");
+        let occlusion = builder.add_render_target_output(occlusion);
+        let in_bg = in_bg.map(|in_bg| builder.add_data_input(in_bg));
+        let pt_handle = builder.passthrough_ref(self);
+
+        // Without a bind group, the pass only clears the target, so nothing
+        // is occluded.
+        let rpass_handle = builder.add_renderpass(r3::RenderPassTargets {
+            targets: vec![r3::RenderPassTarget {
+                color: occlusion,
+                clear: Color::WHITE,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, temps, _ready, graph_data| {
+                let this = pt.get(pt_handle);
+                let pass = encoder_or_pass.get_rpass(rpass_handle);
+                if let Some(in_bg) = in_bg {
+                    let bind_group = graph_data.get_data(temps, in_bg).unwrap();
+                    pass.set_pipeline(&this.occlusion_pipeline);
+                    pass.set_bind_group(0, bind_group, &[]);
+                    pass.draw(0..3, 0..1);
+                }
+            },
+        );
+    }
+
+    /// Adds the passes computing the ambient occlusion of the `meshes`, and
+    /// returns the target they write it to. Unoccluded pixels are white. When
+    /// ambient occlusion is disabled in the `lighting`, the target is only
+    /// cleared.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        state: &BaseRenderGraphIntermediateState,
+        meshes: &'node [MeshFacesLayout],
+        resolution: UVec2,
+        lighting: LightingSettings,
+    ) -> r3::RenderTargetHandle {
+        let target = |graph: &mut r3::RenderGraph<'node>, label: &'static str, format| {
+            graph.add_render_target(r3::RenderTargetDescriptor {
+                label: Some(label.into()),
+                resolution,
+                samples: r3::SampleCount::One,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            })
+        };
+        let occlusion = target(graph, "ambient occlusion", OCCLUSION_FORMAT);
+        if !lighting.ssao {
+            self.occlusion_pass(graph, occlusion, None);
+            return occlusion;
+        }
+        let normals = target(graph, "ssao normals", NORMALS_FORMAT);
+        let positions = target(graph, "ssao positions", POSITIONS_FORMAT);
+        let depth = target(graph, "ssao depth", TextureFormat::Depth32Float);
+
+        let geometry_bgs = graph.add_data();
+        self.create_geometry_bind_groups(graph, meshes, geometry_bgs);
+        self.geometry_pass(
+            graph,
+            state,
+            meshes,
+            geometry_bgs,
+            [normals, positions, depth],
+        );
+
+        let occlusion_bg = graph.add_data();
+        self.create_occlusion_bind_group(
+            graph,
+            [normals, positions],
+            lighting,
+            resolution,
+            occlusion_bg,
+        );
+        self.occlusion_pass(graph, occlusion, Some(occlusion_bg));
+        occlusion
+    }
+}
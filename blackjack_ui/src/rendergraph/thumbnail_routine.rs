@@ -0,0 +1,164 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::num::NonZeroU32;
+
+use crate::prelude::*;
+
+/// A pending capture of the 3d viewport.
+struct Capture {
+    /// Receives the pixels of the viewport output, row by row.
+    buffer: wgpu::Buffer,
+    /// The resolution of the viewport this capture was requested for.
+    resolution: UVec2,
+    /// The size of a row in `buffer`, padded to the alignment required by
+    /// `copy_texture_to_buffer`.
+    padded_bytes_per_row: u32,
+}
+
+/// A routine to capture the output of the 3d viewport, which is used as the
+/// thumbnail preview of saved files, and to check the viewport rendering in
+/// tests.
+#[derive(Default)]
+pub struct ThumbnailRoutine {
+    capture: Option<Capture>,
+}
+
+impl ThumbnailRoutine {
+    /// The width and height of the thumbnails, in pixels.
+    pub const SIZE: u32 = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests a capture of the next frame of the 3d viewport, which is
+    /// rendered with the given `resolution`.
+    pub fn request(&mut self, device: &wgpu::Device, resolution: UVec2) {
+        if resolution.x == 0 || resolution.y == 0 {
+            self.capture = None;
+            return;
+        }
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (resolution.x * 4 + align - 1) / align * align;
+        self.capture = Some(Capture {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Thumbnail Output Buffer"),
+                size: padded_bytes_per_row as u64 * resolution.y as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            resolution,
+            padded_bytes_per_row,
+        });
+    }
+
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut r3::RenderGraph<'node>,
+        resolution: UVec2,
+        output: r3::RenderTargetHandle,
+    ) {
+        match &self.capture {
+            Some(capture) if capture.resolution == resolution => {}
+            _ => return,
+        }
+
+        let mut builder = graph.add_node("Thumbnail: Copy texture");
+        let output = builder.add_render_target_input(output);
+        let this_pt = builder.passthrough_ref(self);
+
+        // Make sure this node won't get pruned
+        builder.add_external_output();
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, _temps, _ready, graph_data| {
+                let this = pt.get(this_pt);
+                let capture = this.capture.as_ref().expect("Checked above");
+                let commands = encoder_or_pass.get_encoder();
+                let tex = graph_data.get_render_target_texture(output);
+
+                commands.copy_texture_to_buffer(
+                    wgpu::ImageCopyTexture {
+                        texture: tex,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyBuffer {
+                        buffer: &capture.buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: NonZeroU32::new(capture.padded_bytes_per_row),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::Extent3d {
+                        width: resolution.x,
+                        height: resolution.y,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            },
+        );
+    }
+
+    /// Returns the requested capture as an image, with the resolution of the
+    /// viewport. Returns `None` when nothing was captured, e.g. because the
+    /// viewport was hidden. Must be called after the render graph with this
+    /// routine was executed.
+    pub fn take_image(&mut self, device: &wgpu::Device) -> Option<image::RgbaImage> {
+        let capture = self.capture.take()?;
+
+        let buffer_slice = capture.buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(err) = result {
+                panic!("Error when mapping buffer: {err}");
+            }
+        });
+        device.poll(wgpu::Maintain::Wait);
+        let mapped = buffer_slice.get_mapped_range();
+
+        // The viewport is rendered as BGRA
+        let UVec2 { x: w, y: h } = capture.resolution;
+        let mut image = image::RgbaImage::new(w, h);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let offset = (y * capture.padded_bytes_per_row + x * 4) as usize;
+            let [b, g, r, a]: [u8; 4] = mapped[offset..offset + 4].try_into().unwrap();
+            *pixel = image::Rgba([r, g, b, a]);
+        }
+        drop(mapped);
+        capture.buffer.unmap();
+        Some(image)
+    }
+
+    /// Returns the requested capture as a square PNG image of `Self::SIZE`
+    /// pixels, cropped from the center of the viewport. Returns `None` when
+    /// nothing was captured, see [`Self::take_image`].
+    pub fn take_png(&mut self, device: &wgpu::Device) -> Result<Option<Vec<u8>>> {
+        let image = match self.take_image(device) {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+
+        let (w, h) = image.dimensions();
+        let side = w.min(h);
+        let cropped = image::imageops::crop_imm(&image, (w - side) / 2, (h - side) / 2, side, side)
+            .to_image();
+        let thumbnail = image::imageops::resize(
+            &cropped,
+            Self::SIZE,
+            Self::SIZE,
+            image::imageops::FilterType::Triangle,
+        );
+        let mut png = vec![];
+        image::DynamicImage::ImageRgba8(thumbnail).write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        Ok(Some(png))
+    }
+}
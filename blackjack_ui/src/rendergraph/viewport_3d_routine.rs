@@ -36,8 +36,9 @@ pub enum DrawType<'a> {
 ///
 /// Will generate a layout with given storage buffers, textures and uniform
 /// buffers. Any of the three could be left as empty and will be generated in
-/// the following order: (storages, textures, uniforms). All bindings will be
-/// added to bind group 1, since bind group 0 is already used by rend3.
+/// the following order: (storages, textures, input targets, uniforms). All
+/// bindings will be added to bind group 1, since bind group 0 is already used
+/// by rend3.
 pub trait RoutineLayout<
     const NUM_BUFFERS: usize = 0,
     const NUM_TEXTURES: usize = 0,
@@ -74,6 +75,13 @@ pub trait RoutineLayout<
     fn num_uniforms() -> usize {
         NUM_UNIFORMS
     }
+
+    /// The number of render targets, written by earlier passes of the graph,
+    /// that are bound as textures after the `NUM_TEXTURES` ones. Their
+    /// handles are passed to [`Viewport3dRoutine::add_to_graph`].
+    fn num_input_targets() -> usize {
+        0
+    }
 }
 
 pub struct Viewport3dRoutine<
@@ -128,6 +136,17 @@ impl<
                     None,
                 );
             }
+            for _ in 0..Layout::num_input_targets() {
+                builder.append(
+                    ShaderStages::FRAGMENT,
+                    BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    None,
+                );
+            }
             for _ in 0..Layout::num_uniforms() {
                 builder.append(
                     ShaderStages::VERTEX_FRAGMENT,
@@ -178,10 +197,15 @@ impl<
         graph: &mut r3::RenderGraph<'node>,
         out_bgs: DataHandle<Vec<BindGroup>>,
         settings: &'node Layout::Settings,
+        input_targets: &[r3::RenderTargetHandle],
     ) {
         let mut builder = graph.add_node(format!("{}: create bind groups", self.name));
         let pt_handle = builder.passthrough_ref(self);
         let out_bgs = builder.add_data_output(out_bgs);
+        let input_targets = input_targets
+            .iter()
+            .map(|target| builder.add_render_target_input(*target))
+            .collect::<Vec<_>>();
 
         builder.build(
             move |pt, renderer, _encoder_or_pass, _temps, _ready, graph_data| {
@@ -201,6 +225,10 @@ impl<
                                 {
                                     builder.append_texture_view(texture);
                                 }
+                                for target in &input_targets {
+                                    builder
+                                        .append_texture_view(graph_data.get_render_target(*target));
+                                }
                                 for uniform in buffer.get_wgpu_uniforms(settings) {
                                     builder.append_buffer(uniform);
                                 }
@@ -304,9 +332,15 @@ impl<
         state: &BaseRenderGraphIntermediateState,
         settings: &'node Layout::Settings,
         offscreen_targets: &[r3::RenderTargetHandle],
+        input_targets: &[r3::RenderTargetHandle],
     ) {
+        assert_eq!(
+            input_targets.len(),
+            Layout::num_input_targets(),
+            "One render target handle is needed for each input target of the layout"
+        );
         let bgs = graph.add_data();
-        self.create_bind_groups(graph, bgs, settings);
+        self.create_bind_groups(graph, bgs, settings, input_targets);
         self.draw(graph, state, bgs, settings, offscreen_targets);
     }
 }
@@ -106,6 +106,6 @@ impl WireframeRoutine {
         graph: &mut r3::RenderGraph<'node>,
         state: &BaseRenderGraphIntermediateState,
     ) {
-        self.inner.add_to_graph(graph, state, &(), &[]);
+        self.inner.add_to_graph(graph, state, &(), &[], &[]);
     }
 }
@@ -0,0 +1,67 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use blackjack_engine::lua_engine::lua_stdlib::{LuaFileIo, LuaSourceFile};
+
+/// The scripts under $BLACKJACK_LUA/run, embedded at compile time.
+const RUN_FILES: &[(&str, &str)] = &[(
+    "run/core_nodes.lua",
+    include_str!("../../blackjack_lua/run/core_nodes.lua"),
+)];
+
+/// The libraries under $BLACKJACK_LUA/lib, embedded at compile time. Keys are
+/// the names used when calling `require` from Lua.
+const LIB_FILES: &[(&str, &str)] = &[
+    (
+        "gizmo_helpers",
+        include_str!("../../blackjack_lua/lib/gizmo_helpers.lua"),
+    ),
+    (
+        "priority_queue",
+        include_str!("../../blackjack_lua/lib/priority_queue.lua"),
+    ),
+    (
+        "table_helpers",
+        include_str!("../../blackjack_lua/lib/table_helpers.lua"),
+    ),
+    (
+        "vector_math",
+        include_str!("../../blackjack_lua/lib/vector_math.lua"),
+    ),
+];
+
+/// A [`LuaFileIo`] that serves the blackjack Lua library from sources embedded
+/// in the binary. The browser has no filesystem to load them from.
+pub struct EmbeddedLuaIo;
+
+fn find_file(files: &[(&str, &str)], name: &str) -> anyhow::Result<LuaSourceFile> {
+    files
+        .iter()
+        .find(|(file_name, _)| *file_name == name)
+        .map(|(file_name, contents)| LuaSourceFile {
+            contents: contents.to_string(),
+            name: file_name.to_string(),
+        })
+        .ok_or_else(|| anyhow::anyhow!("No embedded Lua file named {name}"))
+}
+
+impl LuaFileIo for EmbeddedLuaIo {
+    fn base_folder(&self) -> &str {
+        "embedded"
+    }
+
+    fn find_run_files(&self) -> Box<dyn Iterator<Item = String>> {
+        Box::new(RUN_FILES.iter().map(|(name, _)| name.to_string()))
+    }
+
+    fn load_file_absolute(&self, path: &str) -> anyhow::Result<LuaSourceFile> {
+        find_file(RUN_FILES, path)
+    }
+
+    fn load_file_require(&self, path: &str) -> anyhow::Result<LuaSourceFile> {
+        find_file(LIB_FILES, path.trim_end_matches(".lua"))
+    }
+}
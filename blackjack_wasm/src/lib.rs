@@ -0,0 +1,273 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A WebAssembly integration for blackjack, exposing a small JS-friendly API
+//! via `wasm-bindgen` to load `.bjk` graphs, tweak their parameters and get
+//! the resulting mesh as typed arrays, ready to upload to WebGL.
+//!
+//! Build with `wasm-pack build blackjack_wasm`. Note that Luau is a C++
+//! library, so the C/C++ compiler used by the `cc` crate (`CC`/`CXX`
+//! variables) must be a clang with wasm32 support and a wasm sysroot (e.g.
+//! the one shipped by wasi-sdk).
+//!
+//! The blackjack engine is compiled without its `hot_reload` and `parallel`
+//! features. File-based nodes like Import / Export OBJ are still available,
+//! but will return an error when executed since there is no filesystem.
+
+use blackjack_engine::graph::node_migration::migrate_graph;
+use blackjack_engine::graph::serialization::{IdMappings, SerializedBjkGraph};
+use blackjack_engine::graph::{BjkGraph, BlackjackValue, DependencyKind};
+use blackjack_engine::graph_interpreter::{run_graph, ExternalParameter, ExternalParameterValues};
+use blackjack_engine::lua_engine::{LuaRuntime, ProgramResult, RenderableThing};
+use blackjack_engine::mesh::halfedge::HalfEdgeMesh;
+use blackjack_engine::prelude::selection::SelectionExpression;
+use blackjack_engine::prelude::*;
+use wasm_bindgen::prelude::*;
+
+use crate::embedded_lua_io::EmbeddedLuaIo;
+
+mod embedded_lua_io;
+
+fn js_err(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{err:?}"))
+}
+
+struct LoadedGraph {
+    graph: BjkGraph,
+    params: ExternalParameterValues,
+    mappings: IdMappings,
+}
+
+/// The mesh produced by running a graph, as flat triangle buffers.
+#[wasm_bindgen]
+pub struct MeshBuffers {
+    positions: Vec<f32>,
+    normals: Vec<f32>,
+    indices: Vec<u32>,
+    shading: ShadingMode,
+}
+
+#[wasm_bindgen]
+impl MeshBuffers {
+    /// Vertex positions, three floats per vertex.
+    pub fn positions(&self) -> js_sys::Float32Array {
+        js_sys::Float32Array::from(&self.positions[..])
+    }
+
+    /// Vertex normals, three floats per vertex.
+    pub fn normals(&self) -> js_sys::Float32Array {
+        js_sys::Float32Array::from(&self.normals[..])
+    }
+
+    /// Triangle indices, three per triangle, pointing to vertices in the
+    /// `positions` and `normals` buffers.
+    pub fn indices(&self) -> js_sys::Uint32Array {
+        js_sys::Uint32Array::from(&self.indices[..])
+    }
+
+    /// How the mesh is shaded: `"flat"`, `"smooth"` or `"auto smooth"`. The
+    /// normals already follow it.
+    pub fn shading(&self) -> String {
+        self.shading.name().to_string()
+    }
+
+    /// The auto smooth angle of the mesh in degrees, when it has one.
+    pub fn auto_smooth_angle(&self) -> Option<f32> {
+        match self.shading {
+            ShadingMode::AutoSmooth(angle) => Some(angle.to_degrees()),
+            ShadingMode::Flat | ShadingMode::Smooth => None,
+        }
+    }
+}
+
+impl MeshBuffers {
+    fn from_mesh(mesh: &HalfEdgeMesh) -> Result<Self> {
+        let (buffers, _) = mesh.generate_shaded_triangle_buffers()?;
+        let flatten = |v: &[Vec3]| -> Vec<f32> { v.iter().flat_map(|x| x.to_array()).collect() };
+        Ok(Self {
+            positions: flatten(&buffers.positions),
+            normals: flatten(&buffers.normals),
+            indices: buffers.indices,
+            shading: mesh.gen_config.shading,
+        })
+    }
+}
+
+/// The main entry point for JS code. Owns a Lua runtime with the core node
+/// library and, optionally, a loaded graph.
+#[wasm_bindgen]
+pub struct BlackjackWasm {
+    lua_runtime: LuaRuntime,
+    loaded: Option<LoadedGraph>,
+}
+
+#[wasm_bindgen]
+impl BlackjackWasm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<BlackjackWasm, JsError> {
+        Ok(Self {
+            lua_runtime: LuaRuntime::initialize_custom(EmbeddedLuaIo).map_err(js_err)?,
+            loaded: None,
+        })
+    }
+
+    /// Loads the contents of a `.bjk` file. Replaces any previously loaded
+    /// graph.
+    pub fn load_graph(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        let contents = std::str::from_utf8(bytes).map_err(|err| js_err(err.into()))?;
+        let lua_runtime = &self.lua_runtime;
+        // Nodes that can't be migrated run as they were saved
+        let (rt_data, _, mappings) = SerializedBjkGraph::load_from_string(contents)
+            .and_then(|mut x| {
+                migrate_graph(&mut x, &lua_runtime.lua, &lua_runtime.node_definitions)?;
+                x.into_runtime()
+            })
+            .map_err(js_err)?;
+        self.loaded = Some(LoadedGraph {
+            graph: rt_data.graph,
+            params: rt_data.external_parameters.unwrap_or_default(),
+            mappings,
+        });
+        Ok(())
+    }
+
+    /// Sets the parameter `param_name` of the node at position `node_index`
+    /// in the `.bjk` file. Vector parameters accept an array (or typed array)
+    /// of three numbers, scalars accept a number and strings or selections
+    /// accept a string.
+    pub fn set_param(
+        &mut self,
+        node_index: usize,
+        param_name: String,
+        value: JsValue,
+    ) -> Result<(), JsError> {
+        let loaded = self.loaded_graph_mut()?;
+        let node_id = loaded.mappings.get_id(node_index).map_err(js_err)?;
+        let param = ExternalParameter::new(node_id, param_name);
+        Self::set_param_value(&mut loaded.params, &param, value).map_err(js_err)
+    }
+
+    /// Sets the value of a parameter that was promoted with the given `name`
+    /// in the graph editor. Accepts the same values as `set_param`.
+    pub fn set_promoted_param(&mut self, name: String, value: JsValue) -> Result<(), JsError> {
+        let loaded = self.loaded_graph_mut()?;
+        let param = loaded
+            .graph
+            .nodes
+            .iter()
+            .find_map(|(node_id, node)| {
+                node.inputs.iter().find_map(|input| match &input.kind {
+                    DependencyKind::External {
+                        promoted: Some(promoted),
+                    } if *promoted == name => {
+                        Some(ExternalParameter::new(node_id, input.name.clone()))
+                    }
+                    _ => None,
+                })
+            })
+            .ok_or_else(|| JsError::new(&format!("No promoted parameter named {name}")))?;
+        Self::set_param_value(&mut loaded.params, &param, value).map_err(js_err)
+    }
+
+    /// Runs the loaded graph, starting at its default node, and returns the
+    /// resulting mesh.
+    pub fn run(&self) -> Result<MeshBuffers, JsError> {
+        let loaded = self
+            .loaded
+            .as_ref()
+            .ok_or_else(|| JsError::new("No graph loaded"))?;
+        let target_node = loaded
+            .graph
+            .default_node
+            .ok_or_else(|| JsError::new("Default node not set for this graph."))?;
+        let result = run_graph(
+            &self.lua_runtime.lua,
+            &loaded.graph,
+            target_node,
+            loaded.params.clone(),
+            &self.lua_runtime.node_definitions,
+            None,
+        )
+        .map_err(js_err)?;
+        match result {
+            ProgramResult {
+                renderable: Some(RenderableThing::HalfEdgeMesh(mesh)),
+                ..
+            } => MeshBuffers::from_mesh(&mesh).map_err(js_err),
+            ProgramResult {
+                renderable: Some(RenderableThing::Scene(scene)),
+                ..
+            } => MeshBuffers::from_mesh(&scene.to_merged_mesh()).map_err(js_err),
+            _ => Err(JsError::new(
+                "This renderable type is not supported. @Heightmap",
+            )),
+        }
+    }
+}
+
+impl BlackjackWasm {
+    fn loaded_graph_mut(&mut self) -> Result<&mut LoadedGraph, JsError> {
+        self.loaded
+            .as_mut()
+            .ok_or_else(|| JsError::new("No graph loaded"))
+    }
+
+    fn set_param_value(
+        params: &mut ExternalParameterValues,
+        param: &ExternalParameter,
+        value: JsValue,
+    ) -> Result<()> {
+        let current = params
+            .0
+            .get_mut(param)
+            .ok_or_else(|| anyhow!("No parameter named {}", param.param_name))?;
+        match current {
+            BlackjackValue::Vector(v) => {
+                let arr = js_sys::Float32Array::new(&value).to_vec();
+                if arr.len() != 3 {
+                    bail!("Expected an array of three numbers for a vector parameter");
+                }
+                *v = Vec3::new(arr[0], arr[1], arr[2]);
+            }
+            BlackjackValue::Scalar(s) => {
+                *s = value.as_f64().ok_or_else(|| anyhow!("Expected a number"))? as f32;
+            }
+            BlackjackValue::Int(i) => {
+                *i = value.as_f64().ok_or_else(|| anyhow!("Expected a number"))? as i32;
+            }
+            BlackjackValue::Bool(b) => {
+                *b = value
+                    .as_bool()
+                    .ok_or_else(|| anyhow!("Expected a boolean"))?;
+            }
+            BlackjackValue::String(s) => {
+                *s = value
+                    .as_string()
+                    .ok_or_else(|| anyhow!("Expected a string"))?;
+            }
+            BlackjackValue::Selection(text, sel) => {
+                let new_s = value
+                    .as_string()
+                    .ok_or_else(|| anyhow!("Expected a string"))?;
+                *sel = SelectionExpression::parse(&new_s).ok();
+                *text = new_s;
+            }
+            BlackjackValue::VertexDeltas(_) => {
+                bail!("Vertex offsets can only be edited in the blackjack UI")
+            }
+            BlackjackValue::VertexWeights(_) => {
+                bail!("Painted vertex values can only be edited in the blackjack UI")
+            }
+            BlackjackValue::Expression(e) => {
+                *e = value
+                    .as_string()
+                    .ok_or_else(|| anyhow!("Expected an expression"))?;
+            }
+            BlackjackValue::None => {}
+        }
+        Ok(())
+    }
+}
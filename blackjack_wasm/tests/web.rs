@@ -0,0 +1,37 @@
+// Copyright (C) 2023 setzer22 and contributors
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Run with `wasm-pack test --node blackjack_wasm`
+
+#![cfg(target_arch = "wasm32")]
+
+use blackjack_wasm::BlackjackWasm;
+use wasm_bindgen::{JsError, JsValue};
+use wasm_bindgen_test::*;
+
+const BOX_GRAPH: &[u8] = include_bytes!("../../examples/box.bjk");
+
+fn ok<T>(result: Result<T, JsError>) -> T {
+    result.map_err(JsValue::from).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn test_run_box_graph() {
+    let mut bjk = ok(BlackjackWasm::new());
+    ok(bjk.load_graph(BOX_GRAPH));
+
+    let buffers = ok(bjk.run());
+    // A box has 6 quads, which get triangulated into 12 triangles.
+    assert_eq!(buffers.indices().length(), 36);
+    assert_eq!(buffers.positions().length() % 3, 0);
+    assert_eq!(buffers.positions().length(), buffers.normals().length());
+
+    // Scaling the box should not change the topology
+    let size = js_sys::Array::of3(&2.0.into(), &2.0.into(), &2.0.into());
+    ok(bjk.set_param(0, "size".into(), JsValue::from(size)));
+    let buffers = ok(bjk.run());
+    assert_eq!(buffers.indices().length(), 36);
+}
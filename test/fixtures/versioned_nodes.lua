@@ -0,0 +1,45 @@
+-- Copyright (C) 2023 setzer22 and contributors
+--
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+-- Node definitions for the tests of graphs saved with older node versions.
+
+local P = require("params")
+local NodeLibrary = require("node_library")
+
+local function versioned_box(migrate)
+    return {
+        label = "Versioned Box",
+        -- Version 1 had a single "size" parameter, which was split into
+        -- "width" and "height" in version 2.
+        version = 2,
+        inputs = {
+            P.scalar("width", { default = 1.0, min = 0.0 }),
+            P.scalar("height", { default = 1.0, min = 0.0 }),
+        },
+        outputs = {
+            P.mesh("out_mesh"),
+        },
+        returns = "out_mesh",
+        op = function(inputs)
+            return {
+                out_mesh = Primitives.cube(vector(0, 0, 0), vector(inputs.width, inputs.height, 1)),
+            }
+        end,
+        migrate = migrate,
+    }
+end
+
+NodeLibrary:addNodes({
+    VersionedBox = versioned_box(function(params, from_version)
+        if from_version < 2 then
+            params.width = params.size
+            params.height = params.size
+            params.size = nil
+        end
+        return params
+    end),
+    UnmigratedBox = versioned_box(nil),
+})
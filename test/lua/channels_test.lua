@@ -0,0 +1,88 @@
+-- Tests for the channel introspection methods of meshes. Run by the Lua test
+-- harness in blackjack_engine, see `lua_test_harness.rs`.
+
+local function unit_cube()
+    return Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+end
+
+local function find_channel(mesh, name)
+    for _, info in ipairs(mesh:channels()) do
+        if info.name == name then
+            return info
+        end
+    end
+    return nil
+end
+
+test("list_channels", function()
+    local cube = unit_cube()
+    cube:ensure_channel(Types.FACE_ID, Types.BOOL, "top")
+    local position = find_channel(cube, "position")
+    assert(position ~= nil)
+    assert_eq(position.key_type, Types.VERTEX_ID)
+    assert_eq(position.value_type, Types.VEC3)
+    assert_eq(position.len, 8)
+    local top = find_channel(cube, "top")
+    assert_eq(top.key_type, Types.FACE_ID)
+    assert_eq(top.value_type, Types.BOOL)
+    assert_eq(top.len, 6)
+    assert_eq(tostring(top.key_type), "FaceId")
+end)
+
+test("has_channel", function()
+    local cube = unit_cube()
+    assert(cube:has_channel(Types.VERTEX_ID, "position"))
+    assert(not cube:has_channel(Types.FACE_ID, "position"))
+    assert(not cube:has_channel(Types.VERTEX_ID, "weight"))
+    cube:ensure_channel(Types.VERTEX_ID, Types.F32, "weight")
+    assert(cube:has_channel(Types.VERTEX_ID, "weight"))
+end)
+
+test("ensure_channel_with_default", function()
+    local cube = unit_cube()
+    local weights = cube:ensure_channel(Types.VERTEX_ID, Types.F32, "weight", 0.5)
+    assert_eq(#weights, 8)
+    for _, w in ipairs(weights) do
+        assert_eq(w, 0.5)
+    end
+
+    -- Vertices added later get the default too
+    cube:add_vertex(vector(2, 2, 2))
+    weights = cube:get_channel(Types.VERTEX_ID, Types.F32, "weight")
+    assert_eq(#weights, 9)
+    assert_eq(weights[9], 0.5)
+
+    -- An existing channel keeps its default
+    weights = cube:ensure_channel(Types.VERTEX_ID, Types.F32, "weight", 1.0)
+    assert_eq(weights[1], 0.5)
+end)
+
+test("channel_info_node", function()
+    local channel_info = require("node_library").nodes.ChannelInfo
+    local cube = unit_cube()
+    local before = channel_info.op({ mesh = cube }).count
+    cube:ensure_channel(Types.HALFEDGE_ID, Types.F32, "crease")
+    local outputs = channel_info.op({ mesh = cube })
+    assert_eq(outputs.count, before + 1)
+    assert(string.find(outputs.summary, "crease: HalfEdgeId -> f32 (24 values)", 1, true))
+end)
+
+test("tags", function()
+    local cube = unit_cube()
+    cube:set_tag(Types.FACE_ID, "part", SelectionExpression.new("0, 2"), "roof")
+    local parts = cube:get_channel(Types.FACE_ID, Types.TAG, "part")
+    assert_eq(#parts, 6)
+    assert_eq(parts[1], "roof")
+    assert_eq(parts[2], "")
+    assert_eq(parts[3], "roof")
+
+    local roof = Select.by_tag(cube, Types.FACE_ID, "part", "roof")
+    assert_eq(roof:unparse(), "0, 2")
+    local by_syntax = SelectionExpression.new('tag(part, "roof")')
+    assert_eq(#cube:resolve_face_selection_full(by_syntax), 2)
+
+    -- Tags can also be written as strings in the channel tables
+    parts[2] = "wall"
+    cube:set_channel(Types.FACE_ID, Types.TAG, "part", parts)
+    assert_eq(Select.by_tag(cube, Types.FACE_ID, "part", "wall"):unparse(), "1")
+end)
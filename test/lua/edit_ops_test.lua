@@ -0,0 +1,120 @@
+-- Tests for some of the functions in the Ops table. Run by the Lua test
+-- harness in blackjack_engine, see `lua_test_harness.rs`.
+
+local function unit_cube()
+    return Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+end
+
+test("extrude_one_face", function()
+    local cube = unit_cube()
+    Ops.extrude(SelectionExpression.new("0"), 0.5, cube)
+    expect_mesh_counts(cube, 12, 20, 10)
+end)
+
+test("catmull_clark_subdivide", function()
+    local subdivided = Ops.subdivide(unit_cube(), 1, true)
+    expect_mesh_counts(subdivided, 26, 48, 24)
+end)
+
+test("simple_deform_invalid_kind", function()
+    assert_error(function()
+        Ops.simple_deform(unit_cube(), "Squash", vector(0, 1, 0), vector(0, 0, 0), 1, 0, 1)
+    end, "Invalid deform kind")
+end)
+
+test("simple_deform_taper_keeps_counts", function()
+    local cube = unit_cube()
+    Ops.simple_deform(cube, "Taper", vector(0, 1, 0), vector(0, 0, 0), -0.5, 0, 1)
+    expect_mesh_counts(cube, 8, 12, 6)
+end)
+
+test("lattice_deform_undeformed_is_noop", function()
+    local cube = unit_cube()
+    local before = cube:get_assoc_channel(Types.VERTEX_ID, Types.VEC3, "position")
+    local lattice = Primitives.lattice(vector(-1, -1, -1), vector(1, 1, 1), vector(3, 3, 3))
+    Ops.lattice_deform(cube, lattice, vector(-1, -1, -1), vector(1, 1, 1), vector(3, 3, 3))
+    local after = cube:get_assoc_channel(Types.VERTEX_ID, Types.VEC3, "position")
+    for id, pos in pairs(before) do
+        assert_close(after[id], pos)
+    end
+end)
+
+test("select_non_manifold_closed_cube", function()
+    local selection = Select.non_manifold(unit_cube())
+    assert_eq(selection.edges:unparse(), "")
+    assert_eq(selection.vertices:unparse(), "")
+end)
+
+test("select_by_face_area", function()
+    -- All the faces of the unit cube have an area of 1
+    assert_eq(Select.by_face_area(unit_cube(), 0.5, 1.5):unparse(), "0..6")
+    assert_eq(Select.by_face_area(unit_cube(), 2, 3):unparse(), "")
+end)
+
+test("assert_mesh_equals", function()
+    Ops.assert_mesh_equals(unit_cube(), unit_cube(), 0, true)
+    local larger = Primitives.cube(vector(0, 0, 0), vector(1.5, 1, 1))
+    Ops.assert_mesh_equals(unit_cube(), larger, 0.5, false)
+    assert_error(function()
+        Ops.assert_mesh_equals(unit_cube(), larger, 0.1, false)
+    end, "The meshes are not equal")
+end)
+
+test("set_shading", function()
+    local cube = unit_cube()
+    assert_eq(Ops.get_shading(cube), "flat")
+    Ops.set_shading(cube, "auto smooth", 45)
+    local mode, angle = Ops.get_shading(cube)
+    assert_eq(mode, "auto smooth")
+    assert_close(angle, 45)
+
+    -- Merging into an empty mesh keeps the shading of the merged one
+    local merged = HalfEdgeMesh.new()
+    Ops.merge(merged, cube)
+    Ops.merge(merged, unit_cube())
+    assert_eq(Ops.get_shading(merged), "auto smooth")
+
+    assert_error(function()
+        Ops.set_shading(cube, "shiny")
+    end, "Unknown shading mode")
+end)
+
+test("unwrap", function()
+    local cube = unit_cube()
+    Ops.unwrap(cube, nil, 45, "ABF")
+    assert(cube:has_channel(Types.HALFEDGE_ID, "uv"))
+    assert_error(function()
+        Ops.unwrap(unit_cube(), nil, nil, "Cylindrical")
+    end, "Invalid unwrap method")
+end)
+
+test("to_curve_and_to_points", function()
+    local line = Primitives.line(vector(0, 0, 0), vector(1, 0, 0), 4)
+    expect_mesh_counts(Ops.to_curve(line), 5, 4, 0)
+    assert_error(function()
+        Ops.to_curve(unit_cube())
+    end, "The mesh is not a curve")
+
+    local points = unit_cube():point_cloud(SelectionExpression.new("*"))
+    expect_mesh_counts(Ops.to_points(points), 8, 0, 0)
+    assert_error(function()
+        Ops.to_points(line)
+    end, "The mesh is not a point cloud")
+end)
+
+test("recalculate_winding", function()
+    local cube = unit_cube()
+    Ops.recalculate_winding(cube, false)
+    assert_error(function()
+        Ops.assert_mesh_equals(cube, unit_cube(), 0, false)
+    end, "The meshes are not equal")
+    Ops.recalculate_winding(cube, true)
+    Ops.assert_mesh_equals(cube, unit_cube(), 0, false)
+end)
+
+test("transform_with_options_default_pivot", function()
+    local cube = unit_cube()
+    local zero = vector(0, 0, 0)
+    Ops.transform_with_options(cube, nil, nil, vector(0, 1, 0), zero, nil, vector(1, 1, 1), nil, nil)
+    Ops.assert_mesh_equals(cube, Primitives.cube(vector(0, 1, 0), vector(1, 1, 1)), 1e-5, false)
+end)
@@ -0,0 +1,58 @@
+-- Tests for the functions in the Primitives table. Run by the Lua test
+-- harness in blackjack_engine, see `lua_test_harness.rs`.
+
+test("cube", function()
+    local cube = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+    expect_mesh_counts(cube, 8, 12, 6)
+end)
+
+test("uv_sphere", function()
+    local sphere = Primitives.uv_sphere(vector(0, 0, 0), 1, 8, 4)
+    expect_mesh_counts(sphere, 26, 56, 32)
+end)
+
+test("icosahedron", function()
+    local ico = Primitives.icosahedron(vector(0, 0, 0), 1)
+    expect_mesh_counts(ico, 12, 30, 20)
+    local positions = ico:get_channel(Types.VERTEX_ID, Types.VEC3, "position")
+    for _, pos in ipairs(positions) do
+        assert_close(pos:length(), 1, 1e-4)
+    end
+end)
+
+test("grid", function()
+    local grid = Primitives.grid(3, 4, 1, 1)
+    expect_mesh_counts(grid, 12, 0, 0)
+end)
+
+test("lattice", function()
+    local lattice = Primitives.lattice(vector(0, 0, 0), vector(1, 1, 1), vector(2, 3, 4))
+    expect_mesh_counts(lattice, 24, 0, 0)
+end)
+
+test("lattice_needs_two_points_per_axis", function()
+    assert_error(function()
+        Primitives.lattice(vector(0, 0, 0), vector(1, 1, 1), vector(1, 3, 4))
+    end)
+end)
+
+test("quad_sphere", function()
+    local sphere = Primitives.quad_sphere(vector(0, 0, 0), 1, 2)
+    expect_mesh_counts(sphere, 26, 48, 24)
+end)
+
+test("rounded_box_without_radius", function()
+    local box = Primitives.rounded_box(vector(0, 0, 0), vector(1, 1, 1), 0, 4)
+    expect_mesh_counts(box, 8, 12, 6)
+end)
+
+test("l_system_koch_curve", function()
+    local curve = Primitives.l_system("F", { F = "F+F-F-F+F" }, 2, 90, 1, 0)
+    expect_mesh_counts(curve, 26, 25, 0)
+end)
+
+test("l_system_too_large", function()
+    assert_error(function()
+        Primitives.l_system("F", { F = "FF" }, 20, 90, 1, 0, 1000)
+    end, "too large")
+end)
@@ -0,0 +1,38 @@
+-- Tests for scenes and the functions in the Scene table. Run by the Lua test
+-- harness in blackjack_engine, see `lua_test_harness.rs`.
+
+test("make_scene", function()
+    local cube = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+    local scene = Scene.new()
+    local object = Ops.make_object(cube, "cube", { translate = vector(0, 2, 0) })
+    Ops.scene_add(scene, object)
+    Ops.scene_add(scene, object:instance("cube_instance"))
+    assert_eq(scene:num_objects(), 2)
+    assert_eq(object:name(), "cube")
+    expect_mesh_counts(scene:to_mesh(), 16, 24, 12)
+end)
+
+test("scene_clone", function()
+    local cube = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1))
+    local scene = Scene.new()
+    local copy = scene:clone()
+    Ops.scene_add(copy, Ops.make_object(cube, "cube"))
+    assert_eq(scene:num_objects(), 0)
+    assert_eq(copy:num_objects(), 1)
+end)
+
+test("add_object_instances", function()
+    local add_object = require("node_library").nodes.AddObject
+    local outputs = add_object.op({
+        mesh = Primitives.cube(vector(0, 0, 0), vector(1, 1, 1)),
+        name = "cube",
+        translate = vector(0, 0, 0),
+        rotate = vector(0, 0, 0),
+        scale = vector(1, 1, 1),
+        instances = 3,
+        offset = vector(2, 0, 0),
+    })
+    assert_eq(outputs.out_scene:num_objects(), 3)
+    local obj = Scene.to_wavefront_obj_string(outputs.out_scene)
+    assert(string.find(obj, "o cube.2", 1, true))
+end)